@@ -0,0 +1,39 @@
+//! Generate the gRPC service of the `grpc` feature.
+//!
+//! Messages are Rust types deriving `prost::Message` (see `src/grpc.rs`),
+//! so the service is described here rather than compiled from
+//! `proto/capture.proto`, which needs no `protoc` at build time.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+#[cfg(feature = "grpc")]
+fn grpc() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{input}"))
+            .output_type(format!("crate::grpc::{output}"))
+            .codec_path("tonic::codec::ProstCodec")
+            .server_streaming()
+            .build()
+    };
+    let service = Service::builder()
+        .name("Capture")
+        .package("netkit.capture")
+        .method(method(
+            "subscribe",
+            "Subscribe",
+            "SubscribeRequest",
+            "CaptureRecord",
+        ))
+        .method(method("flows", "Flows", "FlowsRequest", "FlowSummary"))
+        .build();
+    Builder::new().compile(&[service]);
+}
@@ -140,10 +140,10 @@ fn info(file_path: PathBuf, args: &Flags) -> anyhow::Result<()> {
 
     println!("Elapsed: {:?}", elapsed);
 
-    if args.dump.is_some() {
+    if let Some(dump) = args.dump {
         let start = std::time::Instant::now();
 
-        match args.dump.unwrap() {
+        match dump {
             DumpFormat::Csv => {
                 let dump_path = file_path.with_extension("csv");
 
@@ -0,0 +1,451 @@
+//! Bounded channels between pipeline stages.
+//!
+//! When analysis can't keep up with capture, an unbounded queue between them
+//! grows until memory runs out. A [`channel`] holds at most a given number of
+//! items, and its [`OverflowPolicy`] chooses what happens when it is full:
+//! block the sender, losing nothing but stalling capture, or drop packets and
+//! count them.
+//!
+//! ```
+//! use std::thread;
+//!
+//! use netkit_capture::channel::{channel, ChannelConfig, OverflowPolicy};
+//!
+//! let config = ChannelConfig::new().capacity(2).policy(OverflowPolicy::DropNewest);
+//! let (tx, rx) = channel(config);
+//! for i in 0..4 {
+//!     tx.send(i).unwrap();
+//! }
+//! drop(tx);
+//!
+//! let worker = thread::spawn(move || rx.iter().sum::<i32>());
+//! assert_eq!(worker.join().unwrap(), 1);
+//! ```
+//!
+//! Both ends can be cloned, e.g. to fan packets out to several workers; the
+//! channel is closed once every sender or every receiver is dropped.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::shutdown::{ShutdownToken, SIGNAL_POLL_INTERVAL};
+
+/// Default number of items a channel holds.
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// What to do with an item sent to a full channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Wait until there is room; nothing is lost.
+    #[default]
+    Block,
+    /// Drop the item being sent, keeping the oldest ones.
+    DropNewest,
+    /// Drop the oldest queued item to make room, keeping the freshest ones.
+    DropOldest,
+}
+
+/// Configuration of a [`channel`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct ChannelConfig {
+    /// Maximum number of queued items.
+    pub capacity: usize,
+    /// Behavior when full.
+    pub policy: OverflowPolicy,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            policy: OverflowPolicy::default(),
+        }
+    }
+}
+
+impl ChannelConfig {
+    /// Create the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of queued items, at least 1.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Set the behavior when full.
+    pub fn policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// Counters of a channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Number of items accepted by the channel.
+    pub sent: u64,
+    /// Number of items taken out of the channel.
+    pub received: u64,
+    /// Number of items dropped because the channel was full.
+    pub dropped: u64,
+    /// Number of times a sender waited for room.
+    pub blocked: u64,
+    /// Number of queued items.
+    pub len: usize,
+    /// Largest number of items queued at once.
+    pub high_watermark: usize,
+}
+
+/// Error sending to a channel without receivers; holds the item.
+#[derive(Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("sending on a closed channel")]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+/// Error trying to send to a channel without waiting; holds the item.
+#[derive(Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum TrySendError<T> {
+    /// The channel is full and its policy is to block.
+    #[error("sending on a full channel")]
+    Full(T),
+    /// The channel has no receivers.
+    #[error("sending on a closed channel")]
+    Closed(T),
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State<T> {
+    queue: VecDeque<T>,
+    stats: ChannelStats,
+    senders: usize,
+    receivers: usize,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    config: ChannelConfig,
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, state: &mut State<T>, item: T) {
+        state.queue.push_back(item);
+        state.stats.sent += 1;
+        state.stats.high_watermark = state.stats.high_watermark.max(state.queue.len());
+        self.not_empty.notify_one();
+    }
+
+    fn pop(&self, state: &mut State<T>) -> Option<T> {
+        let item = state.queue.pop_front()?;
+        state.stats.received += 1;
+        self.not_full.notify_one();
+        Some(item)
+    }
+}
+
+/// Create a bounded channel.
+pub fn channel<T>(config: ChannelConfig) -> (Sender<T>, Receiver<T>) {
+    let config = config.capacity(config.capacity);
+    let shared = Arc::new(Shared {
+        config,
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(config.capacity.min(DEFAULT_CAPACITY)),
+            stats: ChannelStats::default(),
+            senders: 1,
+            receivers: 1,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Sending end of a [`channel`].
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send an item, applying the overflow policy if the channel is full.
+    ///
+    /// Fails only if every receiver is gone; a dropped item is not an error.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        self.send_with(item, true).map_err(|e| match e {
+            TrySendError::Full(item) | TrySendError::Closed(item) => SendError(item),
+        })
+    }
+
+    /// Send an item without waiting for room.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        self.send_with(item, false)
+    }
+
+    fn send_with(&self, item: T, wait: bool) -> Result<(), TrySendError<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        if state.receivers == 0 {
+            return Err(TrySendError::Closed(item));
+        }
+        if state.queue.len() >= shared.config.capacity {
+            match shared.config.policy {
+                OverflowPolicy::Block if !wait => return Err(TrySendError::Full(item)),
+                OverflowPolicy::Block => {
+                    state.stats.blocked += 1;
+                    while state.queue.len() >= shared.config.capacity {
+                        state = shared
+                            .not_full
+                            .wait(state)
+                            .unwrap_or_else(|e| e.into_inner());
+                        if state.receivers == 0 {
+                            return Err(TrySendError::Closed(item));
+                        }
+                    }
+                }
+                OverflowPolicy::DropNewest => {
+                    state.stats.dropped += 1;
+                    return Ok(());
+                }
+                OverflowPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    state.stats.dropped += 1;
+                }
+            }
+        }
+        shared.push(&mut state, item);
+        Ok(())
+    }
+
+    /// Get the configuration of the channel.
+    pub fn config(&self) -> ChannelConfig {
+        self.shared.config
+    }
+
+    /// Get the counters of the channel.
+    pub fn stats(&self) -> ChannelStats {
+        stats(&self.shared)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+/// Receiving end of a [`channel`].
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Wait for an item; `None` once the channel is empty and every sender
+    /// is gone.
+    pub fn recv(&self) -> Option<T> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if let Some(item) = shared.pop(&mut state) {
+                return Some(item);
+            }
+            if state.senders == 0 {
+                return None;
+            }
+            state = shared
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Wait at most `timeout` for an item.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        let shared = &*self.shared;
+        let deadline = Instant::now() + timeout;
+        let mut state = shared.lock();
+        loop {
+            if let Some(item) = shared.pop(&mut state) {
+                return Some(item);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if state.senders == 0 || remaining.is_zero() {
+                return None;
+            }
+            state = shared
+                .not_empty
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Wait for an item until the token is cancelled; `None` once the
+    /// channel is closed, or cancelled and drained.
+    ///
+    /// Items queued before the cancellation are still returned, so a
+    /// worker stopping on `None` loses nothing accepted by the channel.
+    pub fn recv_until(&self, token: &ShutdownToken) -> Option<T> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if let Some(item) = shared.pop(&mut state) {
+                return Some(item);
+            }
+            if state.senders == 0 || token.is_cancelled() {
+                return None;
+            }
+            state = shared
+                .not_empty
+                .wait_timeout(state, SIGNAL_POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Take an item if one is queued.
+    pub fn try_recv(&self) -> Option<T> {
+        let shared = &*self.shared;
+        shared.pop(&mut shared.lock())
+    }
+
+    /// Iterate over the items until the channel is closed.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv())
+    }
+
+    /// Get the configuration of the channel.
+    pub fn config(&self) -> ChannelConfig {
+        self.shared.config
+    }
+
+    /// Get the counters of the channel.
+    pub fn stats(&self) -> ChannelStats {
+        stats(&self.shared)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            self.shared.not_full.notify_all();
+        }
+    }
+}
+
+fn stats<T>(shared: &Shared<T>) -> ChannelStats {
+    let state = shared.lock();
+    ChannelStats {
+        len: state.queue.len(),
+        ..state.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn channel_overflow() {
+        let config = ChannelConfig::new().capacity(2);
+
+        let (tx, rx) = channel(config.policy(OverflowPolicy::DropOldest));
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(
+            (rx.try_recv(), rx.try_recv(), rx.try_recv()),
+            (Some(3), Some(4), None)
+        );
+        let stats = rx.stats();
+        assert_eq!((stats.sent, stats.received, stats.dropped), (5, 2, 3));
+        assert_eq!(stats.high_watermark, 2);
+
+        let (tx, rx) = channel(config);
+        tx.send(0).unwrap();
+        tx.send(1).unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        let producer = thread::spawn(move || {
+            for i in 2..100 {
+                tx.send(i).unwrap();
+            }
+            tx.stats()
+        });
+        let received: Vec<_> = rx.iter().collect();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+        let stats = producer.join().unwrap();
+        assert_eq!(stats.dropped, 0);
+        assert!(stats.high_watermark <= 2);
+
+        let (tx, rx) = channel::<u8>(config);
+        let token = ShutdownToken::new();
+        tx.send(1).unwrap();
+        token.cancel();
+        assert_eq!(
+            (rx.recv_until(&token), rx.recv_until(&token)),
+            (Some(1), None)
+        );
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+}
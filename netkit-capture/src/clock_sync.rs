@@ -0,0 +1,325 @@
+//! Clock synchronization of two captures.
+//!
+//! Capture hosts are rarely perfectly synchronized: their clocks are offset
+//! by up to seconds without NTP, and drift apart by tens of parts per
+//! million even with it. [`ClockSync`] estimates the offset and drift
+//! between the clocks of two captures of the same traffic from the packets
+//! seen in both, matched by a fingerprint of their payload, which survives
+//! NAT and other rewriting of the headers. The resulting [`ClockCorrection`]
+//! maps timestamps of one capture to the clock of the other, e.g. in
+//! [`CaptureCompare::clock`](crate::compare::CaptureCompare::clock).
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::clock_sync::ClockSync;
+//! # use netkit_packet::prelude::*;
+//! # let frame = |i: u8| {
+//! #     let udp = udp!(payload: [i; 32]);
+//! #     let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//! #     eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone()
+//! # };
+//!
+//! let a: Vec<_> = (0..10).map(|i| (Duration::from_secs(i as u64), frame(i))).collect();
+//! // The clock of B is 1.5 seconds ahead
+//! let b = a.iter().map(|(ts, frame)| (*ts + Duration::from_millis(1500), frame.clone()));
+//!
+//! let correction = ClockSync::new().estimate(a.clone(), b).unwrap();
+//! assert_eq!(correction.offset, 1_500_000_000);
+//! assert_eq!(correction.to_a(Duration::from_millis(2500)), Duration::from_secs(1));
+//! ```
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    io::Read,
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use crate::file::pcap::PcapReader;
+
+/// Default maximum offset between the clocks of the captures.
+pub const DEFAULT_MAX_OFFSET: Duration = Duration::from_secs(10);
+
+/// Default minimum number of matched packets of an estimate.
+pub const DEFAULT_MIN_MATCHES: usize = 3;
+
+/// Correction of the clock of a capture B to the clock of a capture A
+///
+/// A packet seen at `t` by A is seen at
+/// `t + offset + drift_ppm * 1e-6 * (t - epoch)` by B.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClockCorrection {
+    /// Offset of the clock of B at `epoch`, in nanoseconds.
+    pub offset: i64,
+    /// Drift of the clock of B, in parts per million.
+    pub drift_ppm: f64,
+    /// Time of A at which the offset is measured.
+    pub epoch: Duration,
+    /// Number of packets matched in both captures.
+    pub matches: usize,
+}
+
+impl ClockCorrection {
+    /// Create a correction of a constant offset, in nanoseconds.
+    pub fn new(offset: i64) -> Self {
+        Self {
+            offset,
+            ..Self::default()
+        }
+    }
+
+    /// Map a timestamp of A to the clock of B.
+    pub fn to_b(&self, ts: Duration) -> Duration {
+        let rel = (nanos(ts) - nanos(self.epoch)) as f64;
+        let rel = rel * (1.0 + self.drift_ppm * 1e-6) + self.offset as f64;
+        from_nanos(nanos(self.epoch) + rel.round() as i128)
+    }
+
+    /// Map a timestamp of B to the clock of A.
+    pub fn to_a(&self, ts: Duration) -> Duration {
+        let rel = (nanos(ts) - nanos(self.epoch) - self.offset as i128) as f64;
+        let rel = rel / (1.0 + self.drift_ppm * 1e-6);
+        from_nanos(nanos(self.epoch) + rel.round() as i128)
+    }
+}
+
+/// Clock synchronization estimator
+///
+/// Packets are fingerprinted by their IP protocol and transport payload;
+/// packets without payload, and fingerprints seen more than once in a
+/// capture (e.g. retransmissions), are ambiguous and left out. The offset
+/// and drift are fitted by least squares to the matched packets.
+#[derive(Clone, Debug)]
+pub struct ClockSync {
+    max_offset: Duration,
+    min_matches: usize,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self {
+            max_offset: DEFAULT_MAX_OFFSET,
+            min_matches: DEFAULT_MIN_MATCHES,
+        }
+    }
+}
+
+impl ClockSync {
+    /// Create a new estimator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum offset between the clocks of the captures, past
+    /// which packets with the same fingerprint are not matched.
+    pub fn max_offset(&mut self, max_offset: Duration) -> &mut Self {
+        self.max_offset = max_offset;
+        self
+    }
+
+    /// Set the minimum number of matched packets of an estimate.
+    pub fn min_matches(&mut self, min_matches: usize) -> &mut Self {
+        self.min_matches = min_matches;
+        self
+    }
+
+    /// Estimate the correction of the clock of B to the clock of A from
+    /// their Ethernet frames.
+    ///
+    /// Returns `None` if fewer packets than the minimum are matched.
+    pub fn estimate<A, B>(&self, a: A, b: B) -> Option<ClockCorrection>
+    where
+        A: IntoIterator<Item = (Duration, Vec<u8>)>,
+        B: IntoIterator<Item = (Duration, Vec<u8>)>,
+    {
+        let a = fingerprints(a);
+        let b = fingerprints(b);
+        let mut pairs: Vec<_> = a
+            .iter()
+            .filter_map(|(hash, ts_a)| Some((nanos((*ts_a)?), nanos((*b.get(hash)?)?))))
+            .filter(|(ts_a, ts_b)| ts_a.abs_diff(*ts_b) <= self.max_offset.as_nanos())
+            .collect();
+        if pairs.len() < self.min_matches.max(1) {
+            return None;
+        }
+        pairs.sort_unstable();
+
+        // Fit the offset of B against the time of A, relative to the first
+        // match to keep the values small enough for floats.
+        let epoch = pairs[0].0;
+        let n = pairs.len() as f64;
+        let xy: Vec<_> = pairs
+            .iter()
+            .map(|(ts_a, ts_b)| ((ts_a - epoch) as f64, (ts_b - ts_a) as f64))
+            .collect();
+        let mean_x = xy.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = xy.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let var = xy.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+        let cov = xy
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let slope = if var > 0.0 { cov / var } else { 0.0 };
+
+        Some(ClockCorrection {
+            offset: (mean_y - slope * mean_x).round() as i64,
+            drift_ppm: slope * 1e6,
+            epoch: from_nanos(epoch),
+            matches: pairs.len(),
+        })
+    }
+
+    /// Estimate the correction of the clock of the capture in `b` to the
+    /// clock of the capture in `a`.
+    pub fn estimate_pcap<A, B>(
+        &self,
+        a: &mut PcapReader<A>,
+        b: &mut PcapReader<B>,
+    ) -> Option<ClockCorrection>
+    where
+        A: Read,
+        B: Read,
+    {
+        let a = std::iter::from_fn(|| {
+            let (header, data) = a.next_packet()?;
+            Some((a.timestamp(&header), data))
+        });
+        let b = std::iter::from_fn(|| {
+            let (header, data) = b.next_packet()?;
+            Some((b.timestamp(&header), data))
+        });
+
+        self.estimate(a, b)
+    }
+}
+
+/// Get the timestamps of the fingerprints of a capture, `None` for
+/// fingerprints seen more than once.
+fn fingerprints<I>(packets: I) -> HashMap<u64, Option<Duration>>
+where
+    I: IntoIterator<Item = (Duration, Vec<u8>)>,
+{
+    let mut fingerprints = HashMap::new();
+    for (ts, frame) in packets {
+        let Some(hash) = fingerprint(&frame) else {
+            continue;
+        };
+        fingerprints
+            .entry(hash)
+            .and_modify(|ts| *ts = None)
+            .or_insert(Some(ts));
+    }
+    fingerprints
+}
+
+/// Hash the IP protocol and transport payload of a frame. Returns `None`
+/// for frames without payload.
+fn fingerprint(frame: &[u8]) -> Option<u64> {
+    let eth = Eth::new(frame).ok()?;
+    if let Some(ipv4) = eth.ipv4() {
+        let protocol = ipv4.protocol().get();
+        if let Some(tcp) = ipv4.tcp() {
+            return hash_payload(protocol, tcp.payload());
+        }
+        if let Some(udp) = ipv4.udp() {
+            return hash_payload(protocol, udp.payload());
+        }
+        return hash_payload(protocol, ipv4.payload());
+    }
+
+    let ipv6 = eth.ipv6()?;
+    let (protocol, upper) = ipv6.upper_layer()?;
+    if let Some(tcp) = ipv6.tcp() {
+        return hash_payload(protocol, tcp.payload());
+    }
+    if let Some(udp) = ipv6.udp() {
+        return hash_payload(protocol, udp.payload());
+    }
+    hash_payload(protocol, upper)
+}
+
+fn hash_payload(protocol: IpProtocol, payload: &[u8]) -> Option<u64> {
+    if payload.is_empty() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    u8::from(protocol).hash(&mut hasher);
+    payload.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+fn nanos(ts: Duration) -> i128 {
+    ts.as_nanos() as i128
+}
+
+/// Convert nanoseconds to a timestamp, saturating at zero.
+fn from_nanos(nanos: i128) -> Duration {
+    let nanos = nanos.max(0) as u128;
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::gen::craft::udp_datagram;
+
+    use super::*;
+
+    fn frame(src: Ipv4Addr, id: u16) -> Vec<u8> {
+        udp_datagram(
+            [0; 6],
+            [0; 6],
+            (src, 5000).into(),
+            (Ipv4Addr::UNSPECIFIED, 53).into(),
+            id.to_be_bytes(),
+        )
+        .inner()
+        .clone()
+    }
+
+    #[test]
+    fn clock_sync_estimate() {
+        let inside = Ipv4Addr::new(192, 168, 1, 10);
+        let outside = Ipv4Addr::new(203, 0, 113, 1);
+        let secs = Duration::from_secs;
+
+        // B is 2 s behind at the start and gains 100 ppm, behind a NAT
+        let a: Vec<_> = (0..100u16)
+            .map(|i| (secs(1000 + i as u64), frame(inside, i)))
+            .collect();
+        let mut b: Vec<_> = (0..100u16)
+            .map(|i| {
+                let ts = secs(998 + i as u64) + Duration::from_micros(100 * i as u64);
+                (ts, frame(outside, i))
+            })
+            .collect();
+        // A retransmission, and packets seen by B only
+        b.push((secs(1050), frame(outside, 50)));
+        b.push((secs(1200), frame(outside, 1000)));
+
+        let correction = ClockSync::new().estimate(a.clone(), b).unwrap();
+        assert_eq!(correction.matches, 99);
+        assert_eq!(correction.epoch, secs(1000));
+        assert_eq!(correction.offset, -2_000_000_000);
+        assert!((correction.drift_ppm - 100.0).abs() < 1e-6);
+        let ts = secs(1097) + Duration::from_micros(9900);
+        assert_eq!(correction.to_a(ts), secs(1099));
+        assert_eq!(correction.to_b(secs(1099)), ts);
+
+        // Too far apart
+        let b: Vec<_> = a
+            .iter()
+            .map(|(ts, frame)| (*ts + secs(60), frame.clone()))
+            .collect();
+        assert_eq!(ClockSync::new().estimate(a.clone(), b.clone()), None);
+        let correction = ClockSync::new().max_offset(secs(120)).estimate(a, b);
+        assert_eq!(correction.map(|c| c.offset), Some(60_000_000_000));
+    }
+}
@@ -0,0 +1,497 @@
+//! Comparison of captures against golden captures.
+//!
+//! Software emitting traffic can be tested by capturing its output and
+//! comparing it packet by packet with a reviewed (golden) capture. Fields
+//! that legitimately differ between runs, such as timestamps, Ipv4
+//! identifications or ephemeral ports, can be ignored.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::compare::{CaptureCompare, IgnoreField, MismatchKind};
+//!
+//! let expected = vec![(Duration::ZERO, vec![1, 2, 3])];
+//! let actual = vec![(Duration::from_secs(5), vec![1, 2, 4])];
+//!
+//! let mismatches = CaptureCompare::new()
+//!     .ignore(IgnoreField::Timestamp)
+//!     .compare(actual, expected);
+//! assert_eq!(mismatches.len(), 1);
+//! assert_eq!(mismatches[0].index, 0);
+//! assert!(matches!(mismatches[0].kind, MismatchKind::Bytes { offset: 2, .. }));
+//! ```
+
+use std::{
+    collections::HashSet,
+    io::Read,
+    ops::{Range, RangeInclusive},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use crate::{clock_sync::ClockCorrection, file::pcap::PcapReader};
+
+/// Default range of ephemeral ports (IANA dynamic ports).
+pub const DEFAULT_EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+/// A field ignored when comparing packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IgnoreField {
+    /// Packet timestamps.
+    Timestamp,
+
+    /// Ipv4 identification, and therefore the Ipv4 header checksum.
+    Ipv4Id,
+
+    /// TCP and UDP ports in the ephemeral range, and therefore the transport
+    /// checksum.
+    EphemeralPorts,
+
+    /// TCP sequence and acknowledgment numbers, and therefore the transport
+    /// checksum.
+    TcpSeq,
+
+    /// Ipv4 header and transport checksums.
+    Checksums,
+}
+
+/// A difference between a packet and its golden counterpart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Index of the packet in the captures.
+    pub index: usize,
+    /// What differs.
+    pub kind: MismatchKind,
+}
+
+/// Kind of [`Mismatch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// The golden packet is missing from the actual capture.
+    Missing,
+
+    /// The actual capture has a packet beyond the golden capture.
+    Extra,
+
+    /// The timestamps relative to the first packet differ by more than the
+    /// tolerance.
+    Timestamp {
+        /// Actual relative timestamp.
+        actual: Duration,
+        /// Expected relative timestamp.
+        expected: Duration,
+    },
+
+    /// The packet lengths differ.
+    Length {
+        /// Actual length.
+        actual: usize,
+        /// Expected length.
+        expected: usize,
+    },
+
+    /// The packet bytes differ.
+    Bytes {
+        /// Offset of the first differing byte.
+        offset: usize,
+        /// Name of the field holding that byte, e.g. `ipv4.ttl`.
+        field: &'static str,
+    },
+}
+
+impl core::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "packet {}: ", self.index)?;
+        match &self.kind {
+            MismatchKind::Missing => write!(f, "missing"),
+            MismatchKind::Extra => write!(f, "unexpected"),
+            MismatchKind::Timestamp { actual, expected } => {
+                write!(f, "timestamp {actual:?}, expected {expected:?}")
+            }
+            MismatchKind::Length { actual, expected } => {
+                write!(f, "length {actual}, expected {expected}")
+            }
+            MismatchKind::Bytes { offset, field } => {
+                write!(f, "{field} differs at offset {offset}")
+            }
+        }
+    }
+}
+
+/// Capture comparator
+///
+/// Packets are compared in order. Timestamps are compared relative to the
+/// first packet of each capture, so captures taken at different times can
+/// match, unless a [clock correction](Self::clock) relates the captures.
+#[derive(Clone, Debug)]
+pub struct CaptureCompare {
+    ignore: HashSet<IgnoreField>,
+    ephemeral_ports: RangeInclusive<u16>,
+    ts_tolerance: Duration,
+    clock: Option<ClockCorrection>,
+}
+
+impl Default for CaptureCompare {
+    fn default() -> Self {
+        Self {
+            ignore: HashSet::new(),
+            ephemeral_ports: DEFAULT_EPHEMERAL_PORTS,
+            ts_tolerance: Duration::ZERO,
+            clock: None,
+        }
+    }
+}
+
+impl CaptureCompare {
+    /// Create a new comparator ignoring nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignore a field.
+    pub fn ignore(&mut self, field: IgnoreField) -> &mut Self {
+        self.ignore.insert(field);
+        self
+    }
+
+    /// Set the range of ports considered ephemeral.
+    pub fn ephemeral_ports(&mut self, ports: RangeInclusive<u16>) -> &mut Self {
+        self.ephemeral_ports = ports;
+        self
+    }
+
+    /// Set the tolerated difference of relative timestamps.
+    pub fn ts_tolerance(&mut self, tolerance: Duration) -> &mut Self {
+        self.ts_tolerance = tolerance;
+        self
+    }
+
+    /// Set the correction of the clock of the actual capture to the clock of
+    /// the expected one, e.g. estimated by
+    /// [`ClockSync`](crate::clock_sync::ClockSync) for two captures of the
+    /// same traffic.
+    ///
+    /// Timestamps are then compared as they are, after correcting the actual
+    /// ones.
+    pub fn clock(&mut self, correction: ClockCorrection) -> &mut Self {
+        self.clock = Some(correction);
+        self
+    }
+
+    /// Compare captured Ethernet frames with golden ones.
+    ///
+    /// Returns the mismatches, at most one per packet, in packet order.
+    pub fn compare<A, E>(&self, actual: A, expected: E) -> Vec<Mismatch>
+    where
+        A: IntoIterator<Item = (Duration, Vec<u8>)>,
+        E: IntoIterator<Item = (Duration, Vec<u8>)>,
+    {
+        let mut actual = actual.into_iter();
+        let mut expected = expected.into_iter();
+        let mut starts = None;
+        let mut mismatches = Vec::new();
+
+        for index in 0.. {
+            let kind = match (actual.next(), expected.next()) {
+                (None, None) => break,
+                (None, Some(_)) => Some(MismatchKind::Missing),
+                (Some(_), None) => Some(MismatchKind::Extra),
+                (Some((actual_ts, actual)), Some((expected_ts, expected))) => {
+                    let (actual_ts, (actual_start, expected_start)) = match self.clock {
+                        Some(clock) => (clock.to_a(actual_ts), (Duration::ZERO, Duration::ZERO)),
+                        None => (actual_ts, *starts.get_or_insert((actual_ts, expected_ts))),
+                    };
+                    self.compare_ts(
+                        actual_ts.saturating_sub(actual_start),
+                        expected_ts.saturating_sub(expected_start),
+                    )
+                    .or_else(|| self.compare_packet(actual, expected))
+                }
+            };
+
+            if let Some(kind) = kind {
+                mismatches.push(Mismatch { index, kind });
+            }
+        }
+
+        mismatches
+    }
+
+    /// Compare a captured pcap file with a golden one.
+    pub fn compare_pcap<A, E>(
+        &self,
+        actual: &mut PcapReader<A>,
+        expected: &mut PcapReader<E>,
+    ) -> Vec<Mismatch>
+    where
+        A: Read,
+        E: Read,
+    {
+        let actual = std::iter::from_fn(|| {
+            let (header, data) = actual.next_packet()?;
+            Some((actual.timestamp(&header), data))
+        });
+        let expected = std::iter::from_fn(|| {
+            let (header, data) = expected.next_packet()?;
+            Some((expected.timestamp(&header), data))
+        });
+
+        self.compare(actual, expected)
+    }
+
+    fn compare_ts(&self, actual: Duration, expected: Duration) -> Option<MismatchKind> {
+        if self.ignore.contains(&IgnoreField::Timestamp)
+            || actual.abs_diff(expected) <= self.ts_tolerance
+        {
+            return None;
+        }
+
+        Some(MismatchKind::Timestamp { actual, expected })
+    }
+
+    fn compare_packet(&self, mut actual: Vec<u8>, mut expected: Vec<u8>) -> Option<MismatchKind> {
+        if actual.len() != expected.len() {
+            return Some(MismatchKind::Length {
+                actual: actual.len(),
+                expected: expected.len(),
+            });
+        }
+
+        self.normalize(&mut actual);
+        self.normalize(&mut expected);
+
+        let offset = actual.iter().zip(&expected).position(|(a, e)| a != e)?;
+        Some(MismatchKind::Bytes {
+            offset,
+            field: field_at(&expected, offset),
+        })
+    }
+
+    /// Zero the ignored fields of a frame.
+    fn normalize(&self, frame: &mut [u8]) {
+        let Ok(mut eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(mut ipv4) = eth.ipv4_mut() else {
+            return;
+        };
+
+        let ignores = |field| self.ignore.contains(&field);
+        let mut transport_changed = false;
+
+        if ignores(IgnoreField::Ipv4Id) || ignores(IgnoreField::Checksums) {
+            ipv4.checksum_mut().set(0);
+        }
+        if ignores(IgnoreField::Ipv4Id) {
+            ipv4.identification_mut().set(0);
+        }
+
+        let protocol = ipv4.protocol().get();
+        let payload = ipv4.payload_mut();
+        let (ports, checksum) = match protocol {
+            IpProtocol::Tcp if payload.len() >= 20 => (0..4, 16..18),
+            IpProtocol::Udp if payload.len() >= 8 => (0..4, 6..8),
+            _ => return,
+        };
+
+        if ignores(IgnoreField::EphemeralPorts) {
+            for port in payload[ports].chunks_exact_mut(2) {
+                if self
+                    .ephemeral_ports
+                    .contains(&u16::from_be_bytes([port[0], port[1]]))
+                {
+                    port.fill(0);
+                    transport_changed = true;
+                }
+            }
+        }
+        if ignores(IgnoreField::TcpSeq) && protocol == IpProtocol::Tcp {
+            payload[4..12].fill(0);
+            transport_changed = true;
+        }
+        if transport_changed || ignores(IgnoreField::Checksums) {
+            payload[checksum].fill(0);
+        }
+    }
+}
+
+/// Get the name of the field holding the byte at `offset` of a frame.
+fn field_at(frame: &[u8], offset: usize) -> &'static str {
+    const ETH: &[(Range<usize>, &str)] =
+        &[(0..6, "eth.dst"), (6..12, "eth.src"), (12..14, "eth.type")];
+    const IPV4: &[(Range<usize>, &str)] = &[
+        (0..1, "ipv4.version"),
+        (1..2, "ipv4.dscp"),
+        (2..4, "ipv4.total_length"),
+        (4..6, "ipv4.identification"),
+        (6..8, "ipv4.flags"),
+        (8..9, "ipv4.ttl"),
+        (9..10, "ipv4.protocol"),
+        (10..12, "ipv4.checksum"),
+        (12..16, "ipv4.src"),
+        (16..20, "ipv4.dst"),
+    ];
+    const TCP: &[(Range<usize>, &str)] = &[
+        (0..2, "tcp.src_port"),
+        (2..4, "tcp.dst_port"),
+        (4..8, "tcp.seq_num"),
+        (8..12, "tcp.ack_num"),
+        (12..13, "tcp.data_offset"),
+        (13..14, "tcp.flags"),
+        (14..16, "tcp.window_size"),
+        (16..18, "tcp.checksum"),
+        (18..20, "tcp.urgent_pointer"),
+    ];
+    const UDP: &[(Range<usize>, &str)] = &[
+        (0..2, "udp.src_port"),
+        (2..4, "udp.dst_port"),
+        (4..6, "udp.length"),
+        (6..8, "udp.checksum"),
+    ];
+
+    let lookup = |fields: &[(Range<usize>, &'static str)], offset: usize| {
+        fields
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map(|(_, name)| *name)
+    };
+
+    if let Some(name) = lookup(ETH, offset) {
+        return name;
+    }
+    let Ok(eth) = Eth::new(frame) else {
+        return "eth.payload";
+    };
+    let Some(ipv4) = eth.ipv4() else {
+        return "eth.payload";
+    };
+
+    let offset = offset - 14;
+    let header_len = ipv4.ihl().get() as usize * 4;
+    if offset < header_len {
+        return lookup(IPV4, offset).unwrap_or("ipv4.options");
+    }
+
+    let offset = offset - header_len;
+    if let Some(tcp) = ipv4.tcp() {
+        if offset < tcp.data_offset().get() as usize * 4 {
+            return lookup(TCP, offset).unwrap_or("tcp.options");
+        }
+        return "tcp.payload";
+    }
+    if ipv4.udp().is_some() {
+        return lookup(UDP, offset).unwrap_or("udp.payload");
+    }
+    "ipv4.payload"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn frame(src_port: u16, id: u16, ttl: u8) -> Vec<u8> {
+        let tcp = tcp!(src_port: src_port, dst_port: 80u16, payload: b"hello");
+        let mut ipv4 = ipv4!(
+            identification: id,
+            ttl: ttl,
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::Tcp,
+            payload: tcp.inner(),
+        );
+        ipv4.update_transport_checksum();
+        let checksum = internet_checksum(&ipv4.inner()[..20]);
+        ipv4.checksum_mut().set(checksum);
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    fn at(ms: u64, frame: Vec<u8>) -> (Duration, Vec<u8>) {
+        (Duration::from_millis(ms), frame)
+    }
+
+    #[test]
+    fn compare_ignore() {
+        let expected = vec![at(0, frame(50000, 1, 64)), at(10, frame(50000, 2, 64))];
+        let actual = vec![at(100, frame(50123, 7, 64)), at(110, frame(50123, 8, 64))];
+
+        let mismatches = CaptureCompare::new().compare(actual.clone(), expected.clone());
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(
+            mismatches[0].kind,
+            MismatchKind::Bytes {
+                offset: 19,
+                field: "ipv4.identification",
+            }
+        );
+
+        let mismatches = CaptureCompare::new()
+            .ignore(IgnoreField::Ipv4Id)
+            .ignore(IgnoreField::EphemeralPorts)
+            .compare(actual, expected);
+        assert!(mismatches.is_empty());
+    }
+
+    #[test]
+    fn compare_mismatches() {
+        let expected = vec![
+            at(0, frame(50000, 1, 64)),
+            at(10, frame(50000, 1, 64)),
+            at(20, frame(50000, 1, 64)),
+        ];
+        let actual = vec![at(0, frame(50000, 1, 63)), at(30, frame(50000, 1, 64))];
+
+        let mismatches = CaptureCompare::new()
+            .ts_tolerance(Duration::from_millis(5))
+            .compare(actual, expected);
+        assert_eq!(
+            mismatches,
+            [
+                Mismatch {
+                    index: 0,
+                    kind: MismatchKind::Bytes {
+                        offset: 22,
+                        field: "ipv4.ttl",
+                    },
+                },
+                Mismatch {
+                    index: 1,
+                    kind: MismatchKind::Timestamp {
+                        actual: Duration::from_millis(30),
+                        expected: Duration::from_millis(10),
+                    },
+                },
+                Mismatch {
+                    index: 2,
+                    kind: MismatchKind::Missing,
+                },
+            ]
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "packet 0: ipv4.ttl differs at offset 22"
+        );
+
+        // The clock of the actual capture is one second ahead
+        let expected = vec![at(5, frame(50000, 1, 64)), at(10, frame(50000, 1, 64))];
+        let actual = vec![at(1005, frame(50000, 1, 64)), at(1012, frame(50000, 1, 64))];
+        let mismatches = CaptureCompare::new()
+            .clock(ClockCorrection::new(1_000_000_000))
+            .compare(actual, expected);
+        assert_eq!(
+            mismatches,
+            [Mismatch {
+                index: 1,
+                kind: MismatchKind::Timestamp {
+                    actual: Duration::from_millis(12),
+                    expected: Duration::from_millis(10),
+                },
+            }]
+        );
+    }
+}
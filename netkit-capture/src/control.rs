@@ -0,0 +1,279 @@
+//! Control of running capture sessions.
+//!
+//! Interactive tools refine what they look at while capturing. A
+//! [`SessionControl`] is shared between the capture loop and the user
+//! interface: the interface pauses and resumes delivery or swaps the packet
+//! filter, and the loop asks a [`SessionGate`] whether to deliver each packet
+//! it reads. The socket keeps being drained while paused, so its buffers
+//! don't overflow and nothing has to be re-opened.
+//!
+//! ```
+//! use netkit_capture::control::SessionControl;
+//!
+//! let control = SessionControl::new();
+//! let mut gate = control.gate();
+//!
+//! // From the user interface:
+//! control.set_filter("len > 100", |data: &[u8]| data.len() > 100);
+//!
+//! // In the capture loop, for every packet read:
+//! assert!(!gate.admit(&[0; 60]));
+//! assert!(gate.admit(&[0; 1500]));
+//!
+//! control.pause();
+//! assert!(!gate.admit(&[0; 1500]));
+//! assert_eq!(control.stats().paused, 1);
+//! ```
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Predicate deciding whether a packet is delivered.
+type Predicate = dyn Fn(&[u8]) -> bool + Send + Sync;
+
+/// Packet filter of a session.
+#[derive(Clone)]
+pub struct PacketFilter {
+    expression: String,
+    predicate: Arc<Predicate>,
+}
+
+impl PacketFilter {
+    /// Create a filter from its expression, for display, and the predicate
+    /// implementing it.
+    pub fn new(
+        expression: impl Into<String>,
+        predicate: impl Fn(&[u8]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            expression: expression.into(),
+            predicate: Arc::new(predicate),
+        }
+    }
+
+    /// Get the expression of the filter.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Whether a packet passes the filter.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        (self.predicate)(data)
+    }
+}
+
+impl fmt::Debug for PacketFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PacketFilter")
+            .field(&self.expression)
+            .finish()
+    }
+}
+
+/// Counters of a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// Packets delivered.
+    pub delivered: u64,
+    /// Packets discarded by the filter.
+    pub filtered: u64,
+    /// Packets discarded while paused.
+    pub paused: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    paused: AtomicBool,
+    generation: AtomicU64,
+    filter: Mutex<Option<PacketFilter>>,
+    lock: Mutex<()>,
+    resume: Condvar,
+    delivered: AtomicU64,
+    filtered: AtomicU64,
+    discarded: AtomicU64,
+}
+
+/// Handle controlling a capture session.
+///
+/// Cloning a handle gives another handle to the same session.
+#[derive(Clone, Debug, Default)]
+pub struct SessionControl {
+    shared: Arc<Shared>,
+}
+
+impl SessionControl {
+    /// Create a running session without filter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a gate for a capture loop.
+    pub fn gate(&self) -> SessionGate {
+        SessionGate {
+            shared: self.shared.clone(),
+            generation: u64::MAX,
+            filter: None,
+        }
+    }
+
+    /// Pause delivery; packets read meanwhile are discarded.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resume delivery, waking up the loops waiting for it.
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::SeqCst);
+        let _lock = self.shared.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.shared.resume.notify_all();
+    }
+
+    /// Whether delivery is paused.
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+
+    /// Wait at most `timeout` for delivery to resume, returning whether it
+    /// runs.
+    pub fn wait_resumed(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut lock = self.shared.lock.lock().unwrap_or_else(|e| e.into_inner());
+        while self.is_paused() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            lock = self
+                .shared
+                .resume
+                .wait_timeout(lock, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        true
+    }
+
+    /// Replace the filter; every gate applies it from its next packet on.
+    pub fn set_filter(
+        &self,
+        expression: impl Into<String>,
+        predicate: impl Fn(&[u8]) -> bool + Send + Sync + 'static,
+    ) {
+        self.swap_filter(Some(PacketFilter::new(expression, predicate)));
+    }
+
+    /// Remove the filter.
+    pub fn clear_filter(&self) {
+        self.swap_filter(None);
+    }
+
+    /// Replace the filter, returning the previous one.
+    pub fn swap_filter(&self, filter: Option<PacketFilter>) -> Option<PacketFilter> {
+        let mut current = self.shared.filter.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = std::mem::replace(&mut *current, filter);
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        previous
+    }
+
+    /// Get the current filter.
+    pub fn filter(&self) -> Option<PacketFilter> {
+        self.shared
+            .filter
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Get the counters of the session.
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            delivered: self.shared.delivered.load(Ordering::Relaxed),
+            filtered: self.shared.filtered.load(Ordering::Relaxed),
+            paused: self.shared.discarded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Per-loop view of a session.
+///
+/// The gate caches the filter and only takes the lock after it changed, so
+/// admitting a packet is lock-free in the steady state.
+#[derive(Debug)]
+pub struct SessionGate {
+    shared: Arc<Shared>,
+    generation: u64,
+    filter: Option<PacketFilter>,
+}
+
+impl SessionGate {
+    /// Whether to deliver a packet just read.
+    pub fn admit(&mut self, data: &[u8]) -> bool {
+        if self.shared.paused.load(Ordering::SeqCst) {
+            self.shared.discarded.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let generation = self.shared.generation.load(Ordering::SeqCst);
+        if generation != self.generation {
+            let filter = self.shared.filter.lock().unwrap_or_else(|e| e.into_inner());
+            self.filter = filter.clone();
+            self.generation = self.shared.generation.load(Ordering::SeqCst);
+        }
+
+        if self.filter.as_ref().is_some_and(|f| !f.matches(data)) {
+            self.shared.filtered.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.shared.delivered.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn session_pause_and_filter() {
+        let control = SessionControl::new();
+        let mut gate = control.gate();
+        assert!(gate.admit(&[0; 60]));
+
+        control.set_filter("udp", |data: &[u8]| data.get(23) == Some(&17));
+        let mut udp = [0; 60];
+        udp[23] = 17;
+        assert!(!gate.admit(&[0; 60]));
+        assert!(gate.admit(&udp));
+        assert_eq!(control.filter().unwrap().expression(), "udp");
+
+        control.pause();
+        assert!(!gate.admit(&udp));
+        assert!(!control.wait_resumed(Duration::from_millis(1)));
+        let resumer = {
+            let control = control.clone();
+            thread::spawn(move || control.resume())
+        };
+        assert!(control.wait_resumed(Duration::from_secs(10)));
+        resumer.join().unwrap();
+
+        let previous = control.swap_filter(None).unwrap();
+        assert_eq!(previous.expression(), "udp");
+        assert!(gate.admit(&[0; 60]));
+        assert_eq!(
+            control.stats(),
+            SessionStats {
+                delivered: 3,
+                filtered: 1,
+                paused: 1,
+            }
+        );
+    }
+}
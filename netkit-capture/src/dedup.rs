@@ -0,0 +1,220 @@
+//! Deduplication of packets seen twice by the capture.
+//!
+//! Capturing on the Linux "any" pseudo-interface or on a loopback interface
+//! records many packets twice: a loopback packet is seen once outgoing and
+//! once incoming, and a forwarded packet once on each interface it crosses.
+//! Counting both copies doubles the statistics of these flows.
+//!
+//! A [`Deduplicator`] fingerprints the network layer of each packet, leaving
+//! out the fields rewritten on the way (TTL / hop limit and the IPv4 header
+//! checksum), and drops a packet whose fingerprint was seen within a short
+//! window. With SLL2 captures the interface index and packet type tell
+//! copies apart from genuine duplicates: a packet is only dropped if its
+//! last copy was seen on another interface or in the other direction.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::dedup::Deduplicator;
+//! use netkit_packet::prelude::*;
+//!
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp!(dst_port: 53u16).inner());
+//!
+//! let mut dedup = Deduplicator::new();
+//! assert!(dedup.check(Duration::ZERO, LinkType::Raw, ipv4.inner()));
+//! assert!(!dedup.check(Duration::from_micros(5), LinkType::Raw, ipv4.inner()));
+//! assert_eq!(dedup.duplicates(), 1);
+//! ```
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use netkit_packet::{layer::link, prelude::*};
+
+/// Default time within which a copy of a packet is considered a duplicate.
+pub const DEFAULT_WINDOW: Duration = Duration::from_millis(5);
+
+/// SLL2 packet type of packets sent by the host.
+pub const PACKET_OUTGOING: u8 = 4;
+
+/// Where a packet was captured, from the SLL2 header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Origin {
+    /// Interface index.
+    pub interface: u32,
+    /// Whether the packet was sent by the host.
+    pub outgoing: bool,
+}
+
+impl Origin {
+    /// Get the origin of a packet, if its link type records it.
+    pub fn of(link_type: LinkType, data: &[u8]) -> Option<Self> {
+        if link_type != LinkType::Sll2 {
+            return None;
+        }
+        let sll2 = Sll2::new(data).ok()?;
+        Some(Self {
+            interface: sll2.interface_index().get(),
+            outgoing: sll2.packet_type().get() == PACKET_OUTGOING,
+        })
+    }
+}
+
+/// Packet deduplicator
+#[derive(Debug)]
+pub struct Deduplicator {
+    window: Duration,
+    recent: HashMap<u64, (Duration, Option<Origin>)>,
+    order: VecDeque<(Duration, u64)>,
+    seen: u64,
+    duplicates: u64,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            recent: HashMap::new(),
+            order: VecDeque::new(),
+            seen: 0,
+            duplicates: 0,
+        }
+    }
+}
+
+impl Deduplicator {
+    /// Create a new deduplicator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the time within which a copy of a packet is a duplicate.
+    pub fn window(&mut self, window: Duration) -> &mut Self {
+        self.window = window;
+        self
+    }
+
+    /// Check a packet, returning whether to keep it.
+    ///
+    /// Packets must be checked in timestamp order. Packets without a network
+    /// layer are always kept.
+    pub fn check(&mut self, ts: Duration, link_type: LinkType, data: &[u8]) -> bool {
+        self.seen += 1;
+        self.expire(ts);
+
+        let Some(fingerprint) = fingerprint(link_type, data) else {
+            return true;
+        };
+        let origin = Origin::of(link_type, data);
+
+        if let Some((_, previous)) = self.recent.get_mut(&fingerprint) {
+            let copy = match (*previous, origin) {
+                (Some(previous), Some(origin)) => previous != origin,
+                _ => true,
+            };
+            if copy {
+                // Compare the next copy with the last one seen.
+                *previous = origin;
+                self.duplicates += 1;
+                return false;
+            }
+        }
+
+        self.recent.insert(fingerprint, (ts, origin));
+        self.order.push_back((ts, fingerprint));
+        true
+    }
+
+    /// Get the number of packets checked.
+    #[inline]
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Get the number of packets dropped as duplicates.
+    #[inline]
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    fn expire(&mut self, now: Duration) {
+        while let Some(&(ts, fingerprint)) = self.order.front() {
+            if now.saturating_sub(ts) <= self.window {
+                break;
+            }
+            self.order.pop_front();
+            // The entry may have been refreshed by a later packet.
+            if self.recent.get(&fingerprint).is_some_and(|(t, _)| *t == ts) {
+                self.recent.remove(&fingerprint);
+            }
+        }
+    }
+}
+
+/// Fingerprint the network layer of a packet, ignoring the fields rewritten
+/// by routers.
+fn fingerprint(link_type: LinkType, data: &[u8]) -> Option<u64> {
+    let (eth_type, network) = link::network(link_type, data)?;
+    let mut hasher = DefaultHasher::new();
+    match eth_type {
+        EthType::Ipv4 if network.len() >= 20 => {
+            // TTL at 8, checksum at 10..12
+            hasher.write(&network[..8]);
+            hasher.write_u8(network[9]);
+            hasher.write(&network[12..]);
+        }
+        EthType::Ipv6 if network.len() >= 40 => {
+            // Hop limit at 7
+            hasher.write(&network[..7]);
+            hasher.write(&network[8..]);
+        }
+        _ => {
+            eth_type.hash(&mut hasher);
+            hasher.write(network);
+        }
+    }
+    Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sll2(interface: u32, packet_type: u8, ttl: u8) -> Vec<u8> {
+        let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+        let ipv4 = ipv4!(ttl: ttl, protocol: IpProtocol::Udp, payload: udp.inner());
+        sll2!(
+            protocol: EthType::Ipv4,
+            interface_index: interface,
+            packet_type: packet_type,
+            payload: ipv4.inner(),
+        )
+        .inner()
+        .clone()
+    }
+
+    #[test]
+    fn dedup_sll2_copies() {
+        let ms = Duration::from_millis;
+        let mut dedup = Deduplicator::new();
+
+        // Loopback: outgoing, then incoming on the same interface
+        assert!(dedup.check(ms(0), LinkType::Sll2, &sll2(1, PACKET_OUTGOING, 64)));
+        assert!(!dedup.check(ms(0), LinkType::Sll2, &sll2(1, 0, 64)));
+
+        // Same packet on the same interface and direction: not a copy
+        assert!(dedup.check(ms(1), LinkType::Sll2, &sll2(1, 0, 64)));
+
+        // Forwarded to another interface, with a decremented TTL
+        assert!(!dedup.check(ms(2), LinkType::Sll2, &sll2(2, PACKET_OUTGOING, 63)));
+
+        // Outside the window
+        assert!(dedup.check(ms(20), LinkType::Sll2, &sll2(3, 0, 64)));
+
+        assert_eq!(dedup.seen(), 5);
+        assert_eq!(dedup.duplicates(), 2);
+    }
+}
@@ -1 +1,8 @@
+pub mod canonical;
+pub mod durable;
+pub mod index;
 pub mod pcap;
+pub mod pcapng;
+pub mod preview;
+#[cfg(feature = "remote")]
+pub mod remote;
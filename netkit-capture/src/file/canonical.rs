@@ -0,0 +1,168 @@
+//! Canonical pcap files, byte-identical for identical captures.
+//!
+//! Two captures of the same packets may differ byte for byte: the byte
+//! order and timestamp resolution of the file, the snaplen and time zone
+//! recorded in its header, and the order in which packets of several
+//! interfaces were merged. A [`CanonicalPcapWriter`] buffers the packets
+//! and writes them in a fixed form, so that such captures produce the same
+//! file, which can be stored by its hash or compared with `cmp`:
+//!
+//! - little-endian, with nanosecond timestamps, version 2.4, a zero time
+//!   zone and sigfigs, and a snaplen of [`DEFAULT_SNAPLEN`];
+//! - packets sorted by timestamp, then by original length and data, so the
+//!   order of simultaneous packets is fixed as well.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::file::canonical::CanonicalPcapWriter;
+//!
+//! let write = |packets: &[(u64, &[u8])]| {
+//!     let mut writer = CanonicalPcapWriter::new(Vec::new());
+//!     for &(ts, data) in packets {
+//!         writer.write_packet(Duration::from_secs(ts), data);
+//!     }
+//!     writer.finish().unwrap()
+//! };
+//! assert_eq!(write(&[(2, b"b"), (1, b"a")]), write(&[(1, b"a"), (2, b"b")]));
+//! ```
+//!
+//! Packets are held in memory until [`finish`](CanonicalPcapWriter::finish).
+//!
+//! [`DEFAULT_SNAPLEN`]: super::pcap::DEFAULT_SNAPLEN
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use super::pcap::{PcapReader, PcapWriterBuilder, LINKTYPE_ETHERNET};
+
+/// Writer of canonical pcap files.
+#[derive(Debug)]
+pub struct CanonicalPcapWriter<W: Write> {
+    writer: W,
+    network: u32,
+    packets: Vec<(Duration, u32, Vec<u8>)>,
+}
+
+impl<W: Write> CanonicalPcapWriter<W> {
+    /// Create a writer of Ethernet frames.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            network: LINKTYPE_ETHERNET,
+            packets: Vec::new(),
+        }
+    }
+
+    /// Set the link type, Ethernet by default.
+    pub fn network(&mut self, network: u32) -> &mut Self {
+        self.network = network;
+        self
+    }
+
+    /// Get the number of packets buffered.
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Whether no packet has been written.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Buffer a packet captured at `ts` (since the epoch).
+    pub fn write_packet(&mut self, ts: Duration, data: &[u8]) {
+        self.write_captured(ts, data, data.len() as u32);
+    }
+
+    /// Buffer a packet whose original length was `orig_len`.
+    pub fn write_captured(&mut self, ts: Duration, data: &[u8], orig_len: u32) {
+        self.packets.push((ts, orig_len, data.to_vec()));
+    }
+
+    /// Sort and write the packets, and get the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.packets.sort_unstable();
+        let mut writer = PcapWriterBuilder::new()
+            .network(self.network)
+            .nanosecond(true)
+            .build(self.writer)?;
+        for (ts, orig_len, data) in &self.packets {
+            writer.write_captured(*ts, data, *orig_len)?;
+        }
+        writer.into_inner()
+    }
+}
+
+/// Rewrite a pcap file in canonical form, keeping its link type.
+pub fn canonicalize<R: Read, W: Write>(reader: R, writer: W) -> io::Result<W> {
+    let mut reader = PcapReader::new(reader);
+    let mut canonical = CanonicalPcapWriter::new(writer);
+    canonical.network(reader.header.network);
+    while let Some((header, data)) = reader.next_packet() {
+        canonical.write_captured(reader.timestamp(&header), &data, header.orig_len);
+    }
+    canonical.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::file::pcap::{Trim, DEFAULT_SNAPLEN};
+
+    #[test]
+    fn canonical_pcap() {
+        let packets: [(Duration, &[u8]); 3] = [
+            (Duration::new(1, 500_000), b"first"),
+            (Duration::new(2, 0), b"second"),
+            (Duration::new(2, 0), b"also second"),
+        ];
+
+        // Microsecond, big-endian, with an odd snaplen and time zone
+        let mut big_endian = Vec::new();
+        for field in [0xa1b2c3d4u32, 0x0002_0004, 3600, 0, 64, LINKTYPE_ETHERNET] {
+            big_endian.extend_from_slice(&field.to_be_bytes());
+        }
+        for (ts, data) in packets.iter().rev() {
+            for field in [ts.as_secs() as u32, ts.subsec_micros()] {
+                big_endian.extend_from_slice(&field.to_be_bytes());
+            }
+            for _ in 0..2 {
+                big_endian.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            }
+            big_endian.extend_from_slice(data);
+        }
+
+        // Nanosecond, little-endian, in another order
+        let mut writer = PcapWriterBuilder::new()
+            .nanosecond(true)
+            .trim(Trim::Snaplen(128))
+            .build(Vec::new())
+            .unwrap();
+        for i in [1, 0, 2] {
+            writer.write_packet(packets[i].0, packets[i].1).unwrap();
+        }
+        let little_endian = writer.into_inner().unwrap();
+
+        let canonical = canonicalize(Cursor::new(big_endian), Vec::new()).unwrap();
+        assert_eq!(
+            canonical,
+            canonicalize(Cursor::new(little_endian), Vec::new()).unwrap()
+        );
+        assert_eq!(
+            canonical,
+            canonicalize(Cursor::new(canonical.clone()), Vec::new()).unwrap()
+        );
+
+        let reader = PcapReader::new(Cursor::new(canonical));
+        assert!(reader.is_nanosecond() && !reader.big_endian);
+        assert_eq!(reader.header.snaplen, DEFAULT_SNAPLEN);
+        assert_eq!(reader.header.thiszone, 0);
+        let data: Vec<_> = reader.map(|(_, data)| data).collect();
+        assert_eq!(data, [&b"first"[..], b"second", b"also second"]);
+    }
+}
@@ -0,0 +1,252 @@
+//! Crash-safe capture files.
+//!
+//! A capture service killed mid-write leaves whatever its buffers held
+//! unwritten, and a reader of a file still being written may see a torn
+//! record. A [`FlushPolicy`] bounds how much a writer buffers, a
+//! [`DurableFile`] can make every flush reach the disk, and an atomic
+//! [`DurableFile`] is written under a temporary name and only renamed to
+//! its final path by [`finish`](DurableFile::finish), so the final path
+//! holds either nothing or a complete file.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Suffix of the temporary file of an atomic [`DurableFile`].
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// When a writer flushes its buffered records.
+///
+/// By default, records are flushed only when the buffer is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[must_use]
+pub struct FlushPolicy {
+    /// Flush after this many packets.
+    pub packets: Option<u64>,
+    /// Flush when a packet is written this long after the last flush.
+    pub interval: Option<Duration>,
+}
+
+impl FlushPolicy {
+    /// Create a policy flushing only when the buffer is full.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flush after every packet.
+    pub fn every_packet() -> Self {
+        Self::new().packets(1)
+    }
+
+    /// Flush after `packets` packets.
+    pub fn packets(mut self, packets: u64) -> Self {
+        self.packets = Some(packets);
+        self
+    }
+
+    /// Flush when a packet is written `interval` after the last flush.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+}
+
+/// State of a [`FlushPolicy`] in a writer.
+#[derive(Debug)]
+pub(crate) struct Flusher {
+    policy: FlushPolicy,
+    pending: u64,
+    last: Instant,
+}
+
+impl Flusher {
+    pub(crate) fn new(policy: FlushPolicy) -> Self {
+        Self {
+            policy,
+            pending: 0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Count a written packet, and tell whether to flush.
+    pub(crate) fn wrote(&mut self) -> bool {
+        self.pending += 1;
+        let due = self.policy.packets.is_some_and(|n| self.pending >= n)
+            || self
+                .policy
+                .interval
+                .is_some_and(|interval| self.last.elapsed() >= interval);
+        if due {
+            self.flushed();
+        }
+        due
+    }
+
+    pub(crate) fn flushed(&mut self) {
+        self.pending = 0;
+        self.last = Instant::now();
+    }
+}
+
+/// Writer whose written data can be made durable.
+pub trait SyncWrite: Write {
+    /// Wait until the written data is on stable storage.
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl SyncWrite for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+impl SyncWrite for Vec<u8> {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Capture file with fsync and atomic finalize options.
+#[derive(Debug)]
+pub struct DurableFile {
+    file: File,
+    path: PathBuf,
+    partial: Option<PathBuf>,
+    fsync: bool,
+}
+
+impl DurableFile {
+    /// Create or truncate the file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            partial: None,
+            fsync: false,
+        })
+    }
+
+    /// Create a file written at `path` with [`PARTIAL_SUFFIX`] appended,
+    /// and renamed to `path` by [`finish`](Self::finish).
+    ///
+    /// The temporary file is removed if dropped unfinished.
+    pub fn create_atomic(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut partial = path.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+        Ok(Self {
+            file: File::create(&partial)?,
+            path,
+            partial: Some(partial),
+            fsync: false,
+        })
+    }
+
+    /// Set whether every flush also syncs the data to disk.
+    pub fn fsync(&mut self, fsync: bool) -> &mut Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Get the final path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the path written to until finished, if atomic.
+    pub fn partial_path(&self) -> Option<&Path> {
+        self.partial.as_deref()
+    }
+
+    /// Sync the file to disk and, if atomic, rename it to its final path.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        if let Some(partial) = self.partial.take() {
+            std::fs::rename(partial, &self.path)?;
+            // Persist the rename itself.
+            #[cfg(unix)]
+            if let Some(dir) = self.path.parent() {
+                let dir = if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir
+                };
+                File::open(dir)?.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for DurableFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.fsync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+impl SyncWrite for DurableFile {
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+impl Drop for DurableFile {
+    fn drop(&mut self) {
+        if let Some(partial) = &self.partial {
+            let _ = std::fs::remove_file(partial);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::pcap::{PcapReader, PcapWriterBuilder};
+
+    #[test]
+    fn durable_atomic_finalize() {
+        let path = std::env::temp_dir().join(format!("netkit-durable-{}.pcap", std::process::id()));
+        let mut file = DurableFile::create_atomic(&path).unwrap();
+        file.fsync(true);
+        let partial = file.partial_path().unwrap().to_path_buf();
+
+        let mut writer = PcapWriterBuilder::new()
+            .flush_policy(FlushPolicy::new().packets(2))
+            .build(file)
+            .unwrap();
+        writer.write_packet(Duration::ZERO, &[0; 60]).unwrap();
+        assert_eq!(std::fs::metadata(&partial).unwrap().len(), 0);
+        writer.write_packet(Duration::ZERO, &[0; 60]).unwrap();
+        assert_eq!(
+            std::fs::metadata(&partial).unwrap().len(),
+            24 + 2 * (16 + 60)
+        );
+        writer.write_packet(Duration::ZERO, &[0; 60]).unwrap();
+        writer.sync().unwrap();
+        assert!(!path.exists());
+
+        writer.finish().unwrap();
+        assert!(!partial.exists());
+        let reader = PcapReader::new(File::open(&path).unwrap());
+        assert_eq!(reader.count(), 3);
+        std::fs::remove_file(&path).unwrap();
+
+        // Abandoned files never reach their final path.
+        let file = DurableFile::create_atomic(&path).unwrap();
+        drop(PcapWriterBuilder::new().build(file).unwrap());
+        assert!(!path.exists() && !partial.exists());
+    }
+}
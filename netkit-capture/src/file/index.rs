@@ -0,0 +1,649 @@
+//! Random access index for pcap files.
+//!
+//! Scanning a large capture to find packet boundaries is expensive. A
+//! [`PcapIndex`] records the offset, header and flow of every packet so it can
+//! be persisted to a sidecar file and reloaded by later analyses. Indexing can
+//! be stopped after a number of packets and resumed later from where it left.
+//! The index keeps a [`CaptureFingerprint`] of its capture, so a stale or
+//! swapped sidecar is [`verify`](PcapIndex::verify)ed before its offsets are
+//! used.
+//! [`filter`]s select packets and [`query`]s aggregate them from the index
+//! alone.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::Hasher,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+};
+
+use netkit_packet::{
+    flow::{fast::FastPath, siphash::SipHasher24},
+    prelude::*,
+};
+
+use super::pcap::{PacketHeader, PcapReader, PCAP_HEADER_LENGTH};
+
+pub mod filter;
+pub mod query;
+
+/// Magic bytes at the start of an index file.
+pub const INDEX_MAGIC: [u8; 4] = *b"NKIX";
+
+/// Current version of the index file format.
+pub const INDEX_VERSION: u16 = 2;
+
+/// Number of leading capture bytes hashed into a [`CaptureFingerprint`].
+pub const FINGERPRINT_LENGTH: usize = 4096;
+
+/// Extension appended to the capture path for sidecar index files.
+pub const INDEX_EXTENSION: &str = "nkidx";
+
+/// Error type for index files.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum IndexError {
+    /// I/O error while reading or writing the index.
+    #[error("Index I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The file does not start with the index magic.
+    #[error("Invalid index magic: {0:?}")]
+    InvalidMagic([u8; 4]),
+
+    /// The index was written by an unsupported format version.
+    #[error("Unsupported index version: {0}")]
+    UnsupportedVersion(u16),
+
+    /// The flow tag of an entry is unknown.
+    #[error("Invalid flow tag: {0}")]
+    InvalidFlowTag(u8),
+
+    /// The index was built from another capture, or the capture was
+    /// rewritten or truncated since.
+    #[error("Index does not match the capture")]
+    Mismatch,
+}
+
+/// Fingerprint of the capture an index was built from.
+///
+/// It hashes the leading bytes of the capture, which are kept as packets are
+/// appended, so an index resumed on a growing capture still matches it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureFingerprint {
+    /// Number of bytes hashed, at most [`FINGERPRINT_LENGTH`].
+    pub len: u64,
+    /// SipHash-2-4 of the bytes, with zero keys.
+    pub hash: u64,
+}
+
+impl CaptureFingerprint {
+    /// Fingerprint the first `len` bytes of the capture read by `reader`, or
+    /// all of it if shorter.
+    ///
+    /// The reader must be moved with [`PcapReader::seek_to`] afterwards.
+    pub fn read<R: Read + Seek>(reader: &mut PcapReader<R>, len: usize) -> io::Result<Self> {
+        let mut buf = vec![0; len];
+        let len = reader.read_prefix(&mut buf)?;
+
+        let mut hasher = SipHasher24::new_with_keys(0, 0);
+        hasher.write(&buf[..len]);
+        Ok(Self {
+            len: len as u64,
+            hash: hasher.finish(),
+        })
+    }
+}
+
+/// A single indexed packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Byte offset of the packet record in the capture file.
+    pub offset: u64,
+    /// The packet record header.
+    pub header: PacketHeader,
+    /// The flow the packet belongs to, if it could be dissected.
+    pub flow: Option<FlowKey>,
+}
+
+/// Summary of a flow across the indexed packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowSummary {
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of bytes on the wire.
+    pub bytes: u64,
+    /// Ordinal of the first packet of the flow.
+    pub first: usize,
+    /// Ordinal of the last packet of the flow.
+    pub last: usize,
+}
+
+/// Random access index of a pcap file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapIndex {
+    entries: Vec<IndexEntry>,
+    next_offset: u64,
+    complete: bool,
+    fingerprint: Option<CaptureFingerprint>,
+}
+
+impl Default for PcapIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PcapIndex {
+    /// Create a new empty index.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_offset: PCAP_HEADER_LENGTH,
+            complete: false,
+            fingerprint: None,
+        }
+    }
+
+    /// Build the index of the whole capture.
+    pub fn build<R: Read + Seek>(reader: &mut PcapReader<R>) -> std::io::Result<Self> {
+        Self::build_with(reader, FastPath::default())
+    }
+
+    /// Build the index of the whole capture, extracting flows with `path`.
+    pub fn build_with<R: Read + Seek>(
+        reader: &mut PcapReader<R>,
+        path: FastPath,
+    ) -> std::io::Result<Self> {
+        let mut index = Self::new();
+        index.resume_with(reader, None, path)?;
+        Ok(index)
+    }
+
+    /// Continue indexing from where the index stopped.
+    ///
+    /// At most `limit` packets are indexed if given. Returns the number of
+    /// newly indexed packets. The index is marked complete once the end of the
+    /// capture is reached; a truncated or corrupt record stops indexing
+    /// without completing it, so it is retried by the next resume.
+    ///
+    /// Fails with [`IndexError::Mismatch`] as an [`io::ErrorKind::InvalidData`]
+    /// error if the index does not match the capture.
+    pub fn resume<R: Read + Seek>(
+        &mut self,
+        reader: &mut PcapReader<R>,
+        limit: Option<usize>,
+    ) -> std::io::Result<usize> {
+        self.resume_with(reader, limit, FastPath::default())
+    }
+
+    /// Continue indexing, extracting flows with `path`.
+    ///
+    /// [`FastPath::Raw`] reads flows straight from the raw bytes without
+    /// constructing layers, which dominates the cost of indexing.
+    pub fn resume_with<R: Read + Seek>(
+        &mut self,
+        reader: &mut PcapReader<R>,
+        limit: Option<usize>,
+        path: FastPath,
+    ) -> std::io::Result<usize> {
+        match self.fingerprint {
+            Some(_) => {
+                if !self.matches(reader)? {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        IndexError::Mismatch,
+                    ));
+                }
+            }
+            None => self.fingerprint = Some(CaptureFingerprint::read(reader, FINGERPRINT_LENGTH)?),
+        }
+
+        if self.complete {
+            return Ok(0);
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("pcap_index", offset = self.next_offset).entered();
+
+        reader.seek_to(self.next_offset)?;
+
+        let link_type = reader.link_type();
+        let mut count = 0;
+        while limit.is_none_or(|limit| count < limit) {
+            let offset = reader.offset();
+            let Some((header, data)) = reader.next_packet() else {
+                self.complete = reader.file_len()? == self.next_offset;
+                break;
+            };
+
+            let flow = path.flow_key(link_type, &data);
+
+            self.entries.push(IndexEntry {
+                offset,
+                header,
+                flow,
+            });
+            self.next_offset = reader.offset();
+            count += 1;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(packets = count, complete = self.complete, "indexed packets");
+
+        Ok(count)
+    }
+
+    /// Check that the index was built from the capture read by `reader`.
+    ///
+    /// The capture must start with the bytes fingerprinted when indexing
+    /// began, and be long enough for the indexed packets. The position of the
+    /// reader is kept.
+    pub fn verify<R: Read + Seek>(&self, reader: &mut PcapReader<R>) -> Result<(), IndexError> {
+        match self.matches(reader)? {
+            true => Ok(()),
+            false => Err(IndexError::Mismatch),
+        }
+    }
+
+    fn matches<R: Read + Seek>(&self, reader: &mut PcapReader<R>) -> io::Result<bool> {
+        let Some(fingerprint) = self.fingerprint else {
+            return Ok(true);
+        };
+
+        let offset = reader.offset();
+        let len = reader.file_len()?;
+        let actual = CaptureFingerprint::read(reader, fingerprint.len as usize)?;
+        reader.seek_to(offset)?;
+        Ok(actual == fingerprint && len >= self.next_offset)
+    }
+
+    /// Get the fingerprint of the capture, taken when indexing began.
+    #[inline]
+    pub fn fingerprint(&self) -> Option<CaptureFingerprint> {
+        self.fingerprint
+    }
+
+    /// Whether the whole capture has been indexed.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Get the offset where indexing will resume.
+    #[inline]
+    pub fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    /// Get the indexed packets.
+    #[inline]
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Get the entry of the packet with the given ordinal.
+    #[inline]
+    pub fn get(&self, ordinal: usize) -> Option<&IndexEntry> {
+        self.entries.get(ordinal)
+    }
+
+    /// Get the number of indexed packets.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no packet is indexed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Summarize the indexed packets per flow.
+    pub fn flows(&self) -> HashMap<FlowKey, FlowSummary> {
+        let mut flows: HashMap<FlowKey, FlowSummary> = HashMap::new();
+        for (ordinal, entry) in self.entries.iter().enumerate() {
+            let Some(flow) = entry.flow else {
+                continue;
+            };
+
+            let summary = flows.entry(flow).or_insert(FlowSummary {
+                packets: 0,
+                bytes: 0,
+                first: ordinal,
+                last: ordinal,
+            });
+            summary.packets += 1;
+            summary.bytes += entry.header.orig_len as u64;
+            summary.last = ordinal;
+        }
+        flows
+    }
+
+    /// Get the sidecar index path of a capture file.
+    ///
+    /// `trace.pcap` is indexed in `trace.pcap.nkidx`.
+    pub fn sidecar_path(capture: impl AsRef<Path>) -> PathBuf {
+        let mut path = capture.as_ref().as_os_str().to_owned();
+        path.push(".");
+        path.push(INDEX_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Write the index to the given writer.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(&INDEX_MAGIC)?;
+        writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+        writer.write_all(&[self.complete as u8, 0])?;
+        writer.write_all(&self.next_offset.to_le_bytes())?;
+        // A capture is never empty, so a zero length marks no fingerprint.
+        let fingerprint = self
+            .fingerprint
+            .unwrap_or(CaptureFingerprint { len: 0, hash: 0 });
+        writer.write_all(&fingerprint.len.to_le_bytes())?;
+        writer.write_all(&fingerprint.hash.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+
+        for entry in &self.entries {
+            writer.write_all(&entry.offset.to_le_bytes())?;
+            writer.write_all(&entry.header.ts_sec.to_le_bytes())?;
+            writer.write_all(&entry.header.ts_usec.to_le_bytes())?;
+            writer.write_all(&entry.header.incl_len.to_le_bytes())?;
+            writer.write_all(&entry.header.orig_len.to_le_bytes())?;
+
+            let Some(flow) = entry.flow else {
+                writer.write_all(&[0])?;
+                continue;
+            };
+
+            match (flow.src, flow.dst) {
+                (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                    writer.write_all(&[4])?;
+                    writer.write_all(&src.octets())?;
+                    writer.write_all(&dst.octets())?;
+                }
+                (src, dst) => {
+                    writer.write_all(&[6])?;
+                    writer.write_all(&to_ipv6(src).octets())?;
+                    writer.write_all(&to_ipv6(dst).octets())?;
+                }
+            }
+            writer.write_all(&flow.src_port.to_le_bytes())?;
+            writer.write_all(&flow.dst_port.to_le_bytes())?;
+            writer.write_all(&[flow.protocol.into()])?;
+        }
+
+        writer.flush()
+    }
+
+    /// Read an index from the given reader.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, IndexError> {
+        let magic: [u8; 4] = read_array(&mut reader)?;
+        if magic != INDEX_MAGIC {
+            return Err(IndexError::InvalidMagic(magic));
+        }
+
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != INDEX_VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+
+        let [complete, _] = read_array(&mut reader)?;
+        let next_offset = u64::from_le_bytes(read_array(&mut reader)?);
+        let fingerprint = CaptureFingerprint {
+            len: u64::from_le_bytes(read_array(&mut reader)?),
+            hash: u64::from_le_bytes(read_array(&mut reader)?),
+        };
+        let count = u64::from_le_bytes(read_array(&mut reader)?);
+
+        let mut entries = Vec::with_capacity(count.min(1 << 20) as usize);
+        for _ in 0..count {
+            let offset = u64::from_le_bytes(read_array(&mut reader)?);
+            let header = PacketHeader {
+                ts_sec: u32::from_le_bytes(read_array(&mut reader)?),
+                ts_usec: u32::from_le_bytes(read_array(&mut reader)?),
+                incl_len: u32::from_le_bytes(read_array(&mut reader)?),
+                orig_len: u32::from_le_bytes(read_array(&mut reader)?),
+            };
+
+            let [tag] = read_array(&mut reader)?;
+            let addrs: Option<(IpAddr, IpAddr)> = match tag {
+                0 => None,
+                4 => Some((
+                    Ipv4Addr::from(read_array::<_, 4>(&mut reader)?).into(),
+                    Ipv4Addr::from(read_array::<_, 4>(&mut reader)?).into(),
+                )),
+                6 => Some((
+                    from_ipv6(Ipv6Addr::from(read_array::<_, 16>(&mut reader)?)),
+                    from_ipv6(Ipv6Addr::from(read_array::<_, 16>(&mut reader)?)),
+                )),
+                tag => return Err(IndexError::InvalidFlowTag(tag)),
+            };
+
+            let flow = match addrs {
+                Some((src, dst)) => {
+                    let src_port = u16::from_le_bytes(read_array(&mut reader)?);
+                    let dst_port = u16::from_le_bytes(read_array(&mut reader)?);
+                    let [protocol] = read_array(&mut reader)?;
+                    Some(FlowKey::new(
+                        src,
+                        dst,
+                        src_port,
+                        dst_port,
+                        IpProtocol::from(protocol),
+                    ))
+                }
+                None => None,
+            };
+
+            entries.push(IndexEntry {
+                offset,
+                header,
+                flow,
+            });
+        }
+
+        Ok(Self {
+            entries,
+            next_offset,
+            complete: complete != 0,
+            fingerprint: (fingerprint.len != 0).then_some(fingerprint),
+        })
+    }
+
+    /// Save the index to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Load the index from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IndexError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+// Mixed-family flows never occur in practice, but keep them lossless by
+// storing both addresses in the IPv6 form.
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+        IpAddr::V6(addr) => addr,
+    }
+}
+
+fn from_ipv6(addr: Ipv6Addr) -> IpAddr {
+    match addr.to_ipv4_mapped() {
+        Some(addr) => addr.into(),
+        None => addr.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use netkit_packet::gen::craft::udp_datagram;
+
+    use super::*;
+    use crate::file::pcap::LINKTYPE_ETHERNET;
+
+    fn pcap(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&65535u32.to_le_bytes());
+        data.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+
+        for (i, packet) in packets.iter().enumerate() {
+            data.extend_from_slice(&(i as u32).to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            data.extend_from_slice(packet);
+        }
+        data
+    }
+
+    fn udp_frame(src_port: u16) -> Vec<u8> {
+        udp_datagram(
+            [0; 6],
+            [0; 6],
+            (Ipv4Addr::new(10, 0, 0, 1), src_port).into(),
+            (Ipv4Addr::new(10, 0, 0, 2), 53).into(),
+            [1, 2, 3],
+        )
+        .inner()
+        .clone()
+    }
+
+    #[test]
+    fn index_build_and_seek() {
+        let frames = vec![udp_frame(1000), udp_frame(1001), udp_frame(1000)];
+        let mut reader = PcapReader::new(Cursor::new(pcap(&frames)));
+
+        let index = PcapIndex::build(&mut reader).unwrap();
+        assert!(index.is_complete());
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get(0).unwrap().offset, PCAP_HEADER_LENGTH);
+
+        let entry = index.get(1).unwrap();
+        assert_eq!(entry.flow.unwrap().src_port, 1001);
+        let (header, data) = reader.packet_at(entry.offset).unwrap().unwrap();
+        assert_eq!(header, entry.header);
+        assert_eq!(data, frames[1]);
+
+        let flows = index.flows();
+        assert_eq!(flows.len(), 2);
+        let summary = flows[&index.get(0).unwrap().flow.unwrap()];
+        assert_eq!(summary.packets, 2);
+        assert_eq!((summary.first, summary.last), (0, 2));
+    }
+
+    #[test]
+    fn index_resume_and_roundtrip() {
+        let frames = vec![udp_frame(1000), udp_frame(1001), udp_frame(1002)];
+        let data = pcap(&frames);
+
+        let mut reader = PcapReader::new(Cursor::new(data.clone()));
+        let mut partial = PcapIndex::new();
+        assert_eq!(partial.resume(&mut reader, Some(2)).unwrap(), 2);
+        assert!(!partial.is_complete());
+
+        let mut file = Vec::new();
+        partial.write_to(&mut file).unwrap();
+        let mut resumed = PcapIndex::read_from(file.as_slice()).unwrap();
+        assert_eq!(resumed, partial);
+
+        let mut reader = PcapReader::new(Cursor::new(data.clone()));
+        assert_eq!(resumed.resume(&mut reader, None).unwrap(), 1);
+        assert!(resumed.is_complete());
+
+        let mut reader = PcapReader::new(Cursor::new(data.clone()));
+        assert_eq!(resumed, PcapIndex::build(&mut reader).unwrap());
+
+        let mut reader = PcapReader::new(Cursor::new(data));
+        assert_eq!(
+            resumed,
+            PcapIndex::build_with(&mut reader, FastPath::Raw).unwrap()
+        );
+    }
+
+    #[test]
+    fn index_fingerprint() {
+        let frames = vec![udp_frame(1000), udp_frame(1001), udp_frame(1002)];
+        let data = pcap(&frames);
+
+        let mut reader = PcapReader::new(Cursor::new(data.clone()));
+        let mut index = PcapIndex::new();
+        index.resume(&mut reader, Some(2)).unwrap();
+        let fingerprint = index.fingerprint().unwrap();
+        assert_eq!(fingerprint.len, data.len() as u64);
+        index.verify(&mut reader).unwrap();
+
+        let mut file = Vec::new();
+        index.write_to(&mut file).unwrap();
+        let loaded = PcapIndex::read_from(file.as_slice()).unwrap();
+        assert_eq!(loaded.fingerprint(), Some(fingerprint));
+
+        // Another capture with the same layout.
+        let other = pcap(&[udp_frame(2000), udp_frame(2001), udp_frame(2002)]);
+        let mut reader = PcapReader::new(Cursor::new(other));
+        assert!(matches!(
+            loaded.verify(&mut reader),
+            Err(IndexError::Mismatch)
+        ));
+        let error = loaded.clone().resume(&mut reader, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // The capture grew after its fingerprinted prefix.
+        let mut grown = data.clone();
+        grown.extend_from_slice(&pcap(&[udp_frame(1003)])[PCAP_HEADER_LENGTH as usize..]);
+        let mut reader = PcapReader::new(Cursor::new(grown));
+        let mut resumed = loaded.clone();
+        assert_eq!(resumed.resume(&mut reader, None).unwrap(), 2);
+        assert!(resumed.is_complete());
+    }
+
+    #[test]
+    fn index_truncated_tail() {
+        let frames = vec![udp_frame(1000), udp_frame(1001)];
+        let data = pcap(&frames);
+
+        let mut reader = PcapReader::new(Cursor::new(data[..data.len() - 4].to_vec()));
+        let mut index = PcapIndex::new();
+        assert_eq!(index.resume(&mut reader, None).unwrap(), 1);
+        assert!(!index.is_complete());
+
+        // Once the record is complete, indexing picks it up.
+        let mut reader = PcapReader::new(Cursor::new(data));
+        assert_eq!(index.resume(&mut reader, None).unwrap(), 1);
+        assert!(index.is_complete());
+    }
+
+    #[test]
+    fn index_invalid_magic() {
+        assert!(matches!(
+            PcapIndex::read_from(&b"NOPE\x01\x00"[..]),
+            Err(IndexError::InvalidMagic(_))
+        ));
+    }
+
+    #[test]
+    fn index_sidecar_path() {
+        assert_eq!(
+            PcapIndex::sidecar_path("/tmp/trace.pcap"),
+            PathBuf::from("/tmp/trace.pcap.nkidx")
+        );
+    }
+}
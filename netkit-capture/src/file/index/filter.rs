@@ -0,0 +1,217 @@
+//! Filters evaluated over a [`PcapIndex`] without reading packets.
+//!
+//! An [`IndexFilter`] is a subset of the pcap filter syntax restricted to
+//! what an index records: the flow and the length of each packet. Running
+//! it over an index gives the ordinals and offsets of the matching packets
+//! without touching the capture, so a huge capture can be queried
+//! iteratively once indexed:
+//!
+//! ```
+//! use netkit_capture::file::index::{filter::IndexFilter, PcapIndex};
+//!
+//! let index = PcapIndex::new();
+//! let filter: IndexFilter = "udp and dst port 53 and not src net 10.0.0.0/8".parse().unwrap();
+//! for (ordinal, entry) in index.matching(&filter) {
+//!     println!("packet {ordinal} at offset {}", entry.offset);
+//! }
+//! ```
+//!
+//! The syntax is that of [`crate::filter`]; `ip` and `ip6` match packets of
+//! IPv4 and IPv6 flows.
+
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use netkit_packet::prelude::*;
+
+use super::{IndexEntry, PcapIndex};
+pub use crate::filter::FilterError;
+use crate::filter::{parse, Cmp, Dir, Expr};
+
+/// Filter over the entries of a [`PcapIndex`].
+///
+/// Packets whose flow is unknown only match length primitives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexFilter {
+    expression: String,
+    expr: Expr,
+}
+
+impl IndexFilter {
+    /// Get the expression of the filter.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Whether an entry matches the filter.
+    pub fn matches(&self, entry: &IndexEntry) -> bool {
+        eval(&self.expr, entry)
+    }
+}
+
+impl fmt::Display for IndexFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl FromStr for IndexFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            expression: s.trim().to_string(),
+            expr: parse(s)?,
+        })
+    }
+}
+
+impl PcapIndex {
+    /// Get the entries matching a filter, with their ordinals.
+    pub fn matching<'a>(
+        &'a self,
+        filter: &'a IndexFilter,
+    ) -> impl Iterator<Item = (usize, &'a IndexEntry)> + 'a {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| filter.matches(entry))
+    }
+
+    /// Get the ordinals of the packets matching a filter.
+    pub fn filter(&self, filter: &IndexFilter) -> Vec<usize> {
+        self.matching(filter).map(|(ordinal, _)| ordinal).collect()
+    }
+}
+
+fn eval(expr: &Expr, entry: &IndexEntry) -> bool {
+    let flow = entry.flow.as_ref();
+    match expr {
+        Expr::And(a, b) => eval(a, entry) && eval(b, entry),
+        Expr::Or(a, b) => eval(a, entry) || eval(b, entry),
+        Expr::Not(a) => !eval(a, entry),
+        Expr::Net(dir, net, len) => flow.is_some_and(|flow| {
+            let (src, dst) = (in_net(flow.src, *net, *len), in_net(flow.dst, *net, *len));
+            match dir {
+                Dir::Src => src,
+                Dir::Dst => dst,
+                Dir::Any => src || dst,
+            }
+        }),
+        Expr::Ports(dir, low, high) => flow.is_some_and(|flow| {
+            let range = *low..=*high;
+            let (src, dst) = (
+                range.contains(&flow.src_port),
+                range.contains(&flow.dst_port),
+            );
+            has_ports(flow.protocol)
+                && match dir {
+                    Dir::Src => src,
+                    Dir::Dst => dst,
+                    Dir::Any => src || dst,
+                }
+        }),
+        Expr::Ipv4 => flow.is_some_and(|flow| flow.src.is_ipv4()),
+        Expr::Ipv6 => flow.is_some_and(|flow| flow.src.is_ipv6()),
+        Expr::Proto(protocol) => flow.is_some_and(|flow| flow.protocol == *protocol),
+        Expr::Len(cmp, n) => {
+            let len = entry.header.orig_len;
+            match cmp {
+                Cmp::Lt => len < *n,
+                Cmp::Le => len <= *n,
+                Cmp::Gt => len > *n,
+                Cmp::Ge => len >= *n,
+                Cmp::Eq => len == *n,
+                Cmp::Ne => len != *n,
+            }
+        }
+    }
+}
+
+fn has_ports(protocol: IpProtocol) -> bool {
+    matches!(
+        protocol,
+        IpProtocol::Tcp | IpProtocol::Udp | IpProtocol::Sctp
+    )
+}
+
+fn in_net(addr: IpAddr, net: IpAddr, len: u8) -> bool {
+    match (addr, net) {
+        (IpAddr::V4(addr), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - len as u32).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::file::pcap::PacketHeader;
+
+    fn entry(len: u32, flow: Option<FlowKey>) -> IndexEntry {
+        IndexEntry {
+            offset: 24,
+            header: PacketHeader {
+                ts_sec: 0,
+                ts_usec: 0,
+                incl_len: len,
+                orig_len: len,
+            },
+            flow,
+        }
+    }
+
+    #[test]
+    fn index_filter() {
+        let flow = |src: [u8; 4], src_port: u16, dst_port: u16, protocol: IpProtocol| {
+            Some(FlowKey::new(
+                IpAddr::from(Ipv4Addr::from(src)),
+                IpAddr::from(Ipv4Addr::new(192, 0, 2, 53)),
+                src_port,
+                dst_port,
+                protocol,
+            ))
+        };
+        let mut index = PcapIndex::new();
+        index.entries = vec![
+            entry(80, flow([10, 0, 0, 1], 40000, 53, IpProtocol::Udp)),
+            entry(1500, flow([10, 0, 0, 2], 40001, 443, IpProtocol::Tcp)),
+            entry(98, flow([172, 16, 0, 1], 0, 0, IpProtocol::Icmp)),
+            entry(60, None),
+            entry(90, flow([172, 16, 0, 1], 5353, 53, IpProtocol::Udp)),
+        ];
+
+        let run = |expression: &str| index.filter(&expression.parse().unwrap());
+        assert_eq!(run("udp and dst port 53"), [0, 4]);
+        assert_eq!(run("udp && !src net 10.0.0.0/8"), [4]);
+        assert_eq!(run("src host 10.0.0.2 or icmp"), [1, 2]);
+        assert_eq!(run("portrange 400-500 or len<=60"), [1, 3]);
+        assert_eq!(run("not (tcp or udp) and greater 90"), [2]);
+        assert_eq!(run("ip proto 6"), [1]);
+        assert_eq!(run("ip and less 80"), [0]);
+        assert!(run("ip6").is_empty());
+
+        let filter: IndexFilter = " dst port 53 ".parse().unwrap();
+        assert_eq!(filter.to_string(), "dst port 53");
+        for (expression, error) in [
+            ("udp and", FilterError::UnexpectedEnd),
+            ("(udp", FilterError::UnexpectedEnd),
+            ("udp udp", FilterError::UnexpectedToken("udp".into())),
+            ("src tcp", FilterError::UnexpectedToken("tcp".into())),
+            (
+                "net 10.0.0.0/33",
+                FilterError::InvalidAddress("10.0.0.0/33".into()),
+            ),
+            ("port 70000", FilterError::InvalidNumber("70000".into())),
+        ] {
+            assert_eq!(expression.parse::<IndexFilter>(), Err(error));
+        }
+    }
+}
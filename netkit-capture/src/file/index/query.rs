@@ -0,0 +1,298 @@
+//! Aggregation queries over a [`PcapIndex`].
+//!
+//! A [`Query`] groups the indexed packets, optionally [filtered](super::filter),
+//! by [`Dimension`]s and computes [`Aggregate`]s per group, from the index
+//! alone. Basic rollups of a capture then need no dataframe library, e.g.
+//! the bytes per destination port and minute:
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::file::index::{
+//!     query::{Aggregate, Dimension},
+//!     PcapIndex,
+//! };
+//!
+//! let index = PcapIndex::new();
+//! let rows = index
+//!     .query()
+//!     .group_by(Dimension::Window(Duration::from_secs(60)))
+//!     .group_by(Dimension::DstPort)
+//!     .aggregate(Aggregate::Bytes)
+//!     .run();
+//! for row in rows {
+//!     println!("{:?}: {} bytes", row.keys, row.values[0]);
+//! }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::{filter::IndexFilter, IndexEntry, PcapIndex};
+
+/// Property packets are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    /// Time window of the given length, keyed by its start.
+    Window(Duration),
+    /// Source address.
+    Src,
+    /// Destination address.
+    Dst,
+    /// Source port.
+    SrcPort,
+    /// Destination port.
+    DstPort,
+    /// IP protocol number.
+    Protocol,
+}
+
+/// Value computed over the packets of a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Number of packets.
+    Packets,
+    /// Bytes on the wire.
+    Bytes,
+    /// Bytes captured.
+    CapturedBytes,
+    /// Number of distinct flows, in either direction.
+    Flows,
+    /// Length on the wire of the shortest packet.
+    MinLength,
+    /// Length on the wire of the longest packet.
+    MaxLength,
+}
+
+/// Key of a group along a dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    /// The packet has no flow to take the key from.
+    Unknown,
+    /// Start of a time window, since the epoch.
+    Time(Duration),
+    /// An address.
+    Addr(IpAddr),
+    /// A port.
+    Port(u16),
+    /// An IP protocol number.
+    Protocol(u8),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unknown => f.write_str("-"),
+            Value::Time(ts) => write!(f, "{}.{:06}", ts.as_secs(), ts.subsec_micros()),
+            Value::Addr(addr) => write!(f, "{addr}"),
+            Value::Port(port) => write!(f, "{port}"),
+            Value::Protocol(protocol) => write!(f, "{}", IpProtocol::from(*protocol)),
+        }
+    }
+}
+
+/// Group of packets in the result of a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// Keys, in the order of the dimensions.
+    pub keys: Vec<Value>,
+    /// Values, in the order of the aggregates.
+    pub values: Vec<u64>,
+}
+
+/// Aggregation query over an index.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct Query<'a> {
+    index: &'a PcapIndex,
+    filter: Option<&'a IndexFilter>,
+    dimensions: Vec<Dimension>,
+    aggregates: Vec<Aggregate>,
+}
+
+/// Running aggregates of a group.
+#[derive(Debug, Default)]
+struct Group {
+    packets: u64,
+    bytes: u64,
+    captured: u64,
+    flows: HashSet<FlowKey>,
+    min: u64,
+    max: u64,
+}
+
+impl PcapIndex {
+    /// Start a query over the indexed packets.
+    pub fn query(&self) -> Query<'_> {
+        Query {
+            index: self,
+            filter: None,
+            dimensions: Vec::new(),
+            aggregates: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Query<'a> {
+    /// Only consider the packets matching a filter.
+    pub fn filter(mut self, filter: &'a IndexFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Group by a dimension, after the previous ones.
+    pub fn group_by(mut self, dimension: Dimension) -> Self {
+        self.dimensions.push(dimension);
+        self
+    }
+
+    /// Compute an aggregate per group, after the previous ones.
+    pub fn aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregates.push(aggregate);
+        self
+    }
+
+    /// Run the query, giving one row per group, ordered by keys.
+    ///
+    /// Without dimensions, all packets form a single group, present even if
+    /// no packet matches.
+    pub fn run(&self) -> Vec<Row> {
+        let mut groups: BTreeMap<Vec<Value>, Group> = BTreeMap::new();
+        if self.dimensions.is_empty() {
+            groups.insert(Vec::new(), Group::default());
+        }
+        let entries = self.index.entries.iter();
+        for entry in entries.filter(|e| self.filter.is_none_or(|f| f.matches(e))) {
+            let keys = self.dimensions.iter().map(|d| key(*d, entry)).collect();
+            let group = groups.entry(keys).or_default();
+            let len = entry.header.orig_len as u64;
+            group.min = if group.packets == 0 {
+                len
+            } else {
+                group.min.min(len)
+            };
+            group.max = group.max.max(len);
+            group.packets += 1;
+            group.bytes += len;
+            group.captured += entry.header.incl_len as u64;
+            if let Some(flow) = entry.flow {
+                group.flows.insert(flow.canonical());
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(keys, group)| Row {
+                keys,
+                values: self
+                    .aggregates
+                    .iter()
+                    .map(|aggregate| match aggregate {
+                        Aggregate::Packets => group.packets,
+                        Aggregate::Bytes => group.bytes,
+                        Aggregate::CapturedBytes => group.captured,
+                        Aggregate::Flows => group.flows.len() as u64,
+                        Aggregate::MinLength => group.min,
+                        Aggregate::MaxLength => group.max,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Get the key of an entry along a dimension.
+fn key(dimension: Dimension, entry: &IndexEntry) -> Value {
+    let flow = entry.flow.as_ref();
+    let value = match dimension {
+        Dimension::Window(length) => {
+            let ts = Duration::from_secs(entry.header.ts_sec as u64)
+                + Duration::from_micros(entry.header.ts_usec as u64);
+            let length = length.as_nanos().max(1);
+            let start = ts.as_nanos() / length * length;
+            return Value::Time(Duration::from_nanos(start as u64));
+        }
+        Dimension::Src => flow.map(|f| Value::Addr(f.src)),
+        Dimension::Dst => flow.map(|f| Value::Addr(f.dst)),
+        Dimension::SrcPort => flow.map(|f| Value::Port(f.src_port)),
+        Dimension::DstPort => flow.map(|f| Value::Port(f.dst_port)),
+        Dimension::Protocol => flow.map(|f| Value::Protocol(f.protocol.into())),
+    };
+    value.unwrap_or(Value::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::file::pcap::PacketHeader;
+
+    #[test]
+    fn index_query() {
+        let entry = |ts_sec: u32, len: u32, dst_port: u16| IndexEntry {
+            offset: 24,
+            header: PacketHeader {
+                ts_sec,
+                ts_usec: 0,
+                incl_len: len.min(100),
+                orig_len: len,
+            },
+            flow: Some(FlowKey::new(
+                IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::from(Ipv4Addr::new(192, 0, 2, 1)),
+                40000 + ts_sec as u16,
+                dst_port,
+                IpProtocol::Tcp,
+            )),
+        };
+        let mut index = PcapIndex::new();
+        index.entries = vec![
+            entry(0, 1500, 443),
+            entry(30, 500, 443),
+            entry(45, 100, 80),
+            entry(70, 1000, 443),
+            IndexEntry {
+                flow: None,
+                ..entry(80, 60, 0)
+            },
+        ];
+
+        let rows = index
+            .query()
+            .group_by(Dimension::Window(Duration::from_secs(60)))
+            .group_by(Dimension::DstPort)
+            .aggregate(Aggregate::Bytes)
+            .aggregate(Aggregate::Flows)
+            .run();
+        let minute = |m: u64| Value::Time(Duration::from_secs(m * 60));
+        let rows: Vec<_> = rows.into_iter().map(|r| (r.keys, r.values)).collect();
+        assert_eq!(
+            rows,
+            [
+                (vec![minute(0), Value::Port(80)], vec![100, 1]),
+                (vec![minute(0), Value::Port(443)], vec![2000, 2]),
+                (vec![minute(1), Value::Unknown], vec![60, 0]),
+                (vec![minute(1), Value::Port(443)], vec![1000, 1]),
+            ]
+        );
+
+        let filter = "port 443".parse().unwrap();
+        let totals = index
+            .query()
+            .filter(&filter)
+            .aggregate(Aggregate::Packets)
+            .aggregate(Aggregate::CapturedBytes)
+            .aggregate(Aggregate::MinLength)
+            .aggregate(Aggregate::MaxLength)
+            .run();
+        assert_eq!(totals[0].values, [3, 300, 500, 1500]);
+        assert_eq!(Value::Protocol(6).to_string(), "Tcp");
+    }
+}
@@ -1,4 +1,15 @@
-use std::io::{BufReader, Read};
+use std::{
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+use netkit_packet::{layer::link, prelude::*};
+
+use super::durable::{DurableFile, FlushPolicy, Flusher, SyncWrite};
+use crate::{
+    pool::{Packet, PacketPool},
+    sanitize::TimestampSanitizer,
+};
 
 // use deku::prelude::*;
 
@@ -9,25 +20,40 @@ pub struct PcapReader<R: Read> {
     pub big_endian: bool,
 
     reader: BufReader<R>,
+
+    offset: u64,
+
+    sanitizer: Option<TimestampSanitizer>,
 }
 
 impl<R: Read> PcapReader<R> {
+    /// Create a reader, panicking if the file does not start with a pcap
+    /// header.
     pub fn new(reader: R) -> Self {
+        Self::try_new(reader).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Create a reader, failing if the file does not start with a pcap
+    /// header, e.g. for files of untrusted origin.
+    pub fn try_new(reader: R) -> io::Result<Self> {
         let mut reader = BufReader::new(reader);
 
         let mut magic_number: [u8; 4] = [0; 4];
-        reader.read_exact(&mut magic_number).unwrap();
+        reader.read_exact(&mut magic_number)?;
 
         let big_endian = if magic_number[0] == 0xa1 {
             true
         } else if magic_number[3] == 0xa1 {
             false
         } else {
-            panic!("Invalid magic number: {:?}", magic_number);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid magic number: {:?}", magic_number),
+            ));
         };
 
         let mut buffer: [u8; 20] = [0; 20];
-        reader.read_exact(&mut buffer).unwrap();
+        reader.read_exact(&mut buffer)?;
 
         let header = if big_endian {
             PcapHeader {
@@ -51,18 +77,162 @@ impl<R: Read> PcapReader<R> {
             }
         };
 
-        Self {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            link_type = header.network,
+            snaplen = header.snaplen,
+            big_endian,
+            "opened pcap"
+        );
+
+        Ok(Self {
             header,
             big_endian,
             reader,
-        }
+            offset: PCAP_HEADER_LENGTH,
+            sanitizer: None,
+        })
+    }
+
+    /// Get the link type of the packets.
+    pub fn link_type(&self) -> LinkType {
+        LinkType::from(self.header.network)
+    }
+
+    /// Whether the packet timestamps have nanosecond resolution.
+    pub fn is_nanosecond(&self) -> bool {
+        self.header.magic_number == PCAP_MAGIC_NANOSECOND
+    }
+
+    /// Get the timestamp of a packet read from this file since the epoch.
+    pub fn timestamp(&self, header: &PacketHeader) -> std::time::Duration {
+        let subsec_nanos = if self.is_nanosecond() {
+            header.ts_usec
+        } else {
+            header.ts_usec.saturating_mul(1_000)
+        };
+        std::time::Duration::new(header.ts_sec as u64, subsec_nanos)
+    }
+
+    /// Sanitize the timestamps of the packets read, see [`sanitize`].
+    ///
+    /// [`sanitize`]: crate::sanitize
+    pub fn sanitize_timestamps(&mut self, sanitizer: TimestampSanitizer) -> &mut Self {
+        self.sanitizer = Some(sanitizer);
+        self
+    }
+
+    /// Get the timestamp sanitizer, with its counters.
+    pub fn timestamp_sanitizer(&self) -> Option<&TimestampSanitizer> {
+        self.sanitizer.as_ref()
+    }
+
+    /// Get the byte offset of the next packet record in the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Get the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
     }
 
     pub fn next_packet(&mut self) -> Option<(PacketHeader, Vec<u8>)> {
+        let mut data = Vec::new();
+        let header = self.next_packet_into(&mut data)?;
+        Some((header, data))
+    }
+
+    /// Read the next packet into a pooled buffer.
+    ///
+    /// The packet can be handed to worker threads without copying, and its
+    /// buffer is reused once all handles are dropped.
+    pub fn next_pooled(&mut self, pool: &PacketPool) -> Option<(PacketHeader, Packet)> {
+        let mut buf = pool.get();
+        let header = self.next_packet_into(&mut buf)?;
+        Some((header, buf.freeze()))
+    }
+
+    /// Read the next packet into `data`, replacing its content.
+    pub fn next_packet_into(&mut self, data: &mut Vec<u8>) -> Option<PacketHeader> {
+        let header = self.next_header()?;
+        self.read_data(&header, data)?;
+        Some(header)
+    }
+
+    /// Skip the next packet, only reading its header.
+    ///
+    /// The data is not copied out, which makes skipping cheaper than reading
+    /// when sampling a large file.
+    pub fn skip_packet(&mut self) -> Option<PacketHeader> {
+        let header = self.next_header()?;
+        self.skip_data(&header)?;
+        Some(header)
+    }
+
+    /// Read the data of the record whose header was just read.
+    pub(crate) fn read_data(&mut self, header: &PacketHeader, data: &mut Vec<u8>) -> Option<()> {
+        // Read incl_len bytes
+        data.clear();
+        data.resize(header.incl_len as usize, 0);
+        if let Err(_e) = self.reader.read_exact(data) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(offset = self.offset, error = %_e, "truncated pcap record");
+            return None;
+        }
+
+        self.offset += PACKET_HEADER_LENGTH + header.incl_len as u64;
+        Some(())
+    }
+
+    /// Skip the data of the record whose header was just read.
+    pub(crate) fn skip_data(&mut self, header: &PacketHeader) -> Option<()> {
+        let len = header.incl_len as u64;
+        let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink()).ok()?;
+        if skipped < len {
+            return None;
+        }
+
+        self.offset += PACKET_HEADER_LENGTH + len;
+        Some(())
+    }
+
+    /// Read the next packet record header, with its timestamp sanitized.
+    ///
+    /// It must be followed by reading or skipping the data of the record.
+    /// Records dropped by the sanitizer are skipped.
+    pub(crate) fn next_header(&mut self) -> Option<PacketHeader> {
+        loop {
+            let mut header = self.read_header()?;
+            let ts = self.timestamp(&header);
+            let nanosecond = self.is_nanosecond();
+            let Some(sanitizer) = &mut self.sanitizer else {
+                return Some(header);
+            };
+            match sanitizer.sanitize(ts) {
+                Some(ts) => {
+                    header.ts_sec = u32::try_from(ts.as_secs()).unwrap_or(u32::MAX);
+                    header.ts_usec = if nanosecond {
+                        ts.subsec_nanos()
+                    } else {
+                        ts.subsec_micros()
+                    };
+                    return Some(header);
+                }
+                None => self.skip_data(&header)?,
+            }
+        }
+    }
+
+    fn read_header(&mut self) -> Option<PacketHeader> {
         let mut buffer: [u8; 16] = [0; 16];
         match self.reader.read_exact(&mut buffer) {
             Ok(_) => (),
-            Err(_) => return None,
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(offset = self.offset, error = %_e, "end of pcap");
+                return None;
+            }
         }
 
         let header = if self.big_endian {
@@ -81,11 +251,64 @@ impl<R: Read> PcapReader<R> {
             }
         };
 
-        // Read incl_len bytes
-        let mut data = vec![0; header.incl_len as usize];
-        self.reader.read_exact(&mut data).unwrap();
+        // Records larger than the snaplen come from a corrupt file, and must
+        // not size the buffer they are read into.
+        let max_len = self
+            .header
+            .snaplen
+            .clamp(DEFAULT_SNAPLEN, MAX_RECORD_LENGTH);
+        if header.incl_len > max_len {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                offset = self.offset,
+                incl_len = header.incl_len,
+                max_len,
+                "oversized pcap record"
+            );
+            return None;
+        }
 
-        Some((header, data))
+        Some(header)
+    }
+}
+
+impl<R: Read + Seek> PcapReader<R> {
+    /// Move the reader to the packet record starting at `offset`.
+    ///
+    /// The offset must point at a record boundary, e.g. one taken from
+    /// [`PcapReader::offset`] or a [`PcapIndex`](super::index::PcapIndex).
+    pub fn seek_to(&mut self, offset: u64) -> std::io::Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        Ok(())
+    }
+
+    /// Read the packet record starting at `offset`.
+    pub fn packet_at(&mut self, offset: u64) -> std::io::Result<Option<(PacketHeader, Vec<u8>)>> {
+        self.seek_to(offset)?;
+        Ok(self.next_packet())
+    }
+
+    /// Get the length of the file.
+    ///
+    /// The reader must be moved with [`PcapReader::seek_to`] afterwards.
+    pub(crate) fn file_len(&mut self) -> std::io::Result<u64> {
+        self.reader.seek(SeekFrom::End(0))
+    }
+
+    /// Read up to `buf.len()` raw bytes from the start of the file.
+    ///
+    /// The reader must be moved with [`PcapReader::seek_to`] afterwards.
+    pub(crate) fn read_prefix(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut len = 0;
+        while len < buf.len() {
+            match self.reader.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        Ok(len)
     }
 }
 
@@ -97,6 +320,33 @@ impl<R: Read> Iterator for PcapReader<R> {
     }
 }
 
+/// Magic number of pcap files with microsecond timestamps.
+pub const PCAP_MAGIC_MICROSECOND: u32 = 0xa1b2c3d4;
+
+/// Magic number of pcap files with nanosecond timestamps.
+pub const PCAP_MAGIC_NANOSECOND: u32 = 0xa1b23c4d;
+
+/// Link type of Ethernet frames.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+/// Default snapshot length of written files.
+///
+/// This is the libpcap maximum, which leaves room for super-jumbo frames and
+/// offloaded segments of up to 64 KiB of IP payload.
+pub const DEFAULT_SNAPLEN: u32 = 262144;
+
+/// Largest record length accepted by readers, whatever the snaplen.
+///
+/// Records up to the snaplen of the file, or [`DEFAULT_SNAPLEN`] if that is
+/// smaller, are read; a record beyond it ends the file.
+pub const MAX_RECORD_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// Length of the pcap global header.
+pub const PCAP_HEADER_LENGTH: u64 = 24;
+
+/// Length of a pcap packet record header.
+pub const PACKET_HEADER_LENGTH: u64 = 16;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PcapHeader {
     pub magic_number: u32,
@@ -115,3 +365,414 @@ pub struct PacketHeader {
     pub incl_len: u32,
     pub orig_len: u32,
 }
+
+/// How packets are trimmed when written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Trim {
+    /// Keep whole packets.
+    #[default]
+    None,
+
+    /// Keep at most the given number of bytes of every packet.
+    Snaplen(u32),
+
+    /// Keep only the protocol headers (link layer with its VLAN tags, IPv4
+    /// or IPv6 with its extension headers, and TCP, UDP, UDP-Lite, SCTP or
+    /// ICMP), dropping the application payload. Non-first fragments keep
+    /// their IP header only.
+    ///
+    /// Packets of unsupported link types, and of network protocols other
+    /// than IP, are kept whole.
+    Headers,
+}
+
+impl Trim {
+    /// Get the number of bytes of a packet to keep.
+    pub fn len(&self, network: u32, data: &[u8]) -> usize {
+        match *self {
+            Trim::None => data.len(),
+            Trim::Snaplen(snaplen) => data.len().min(snaplen as usize),
+            Trim::Headers => headers_len(LinkType::from(network), data),
+        }
+    }
+}
+
+/// Get the length of the link, IP and transport headers of a packet.
+fn headers_len(link_type: LinkType, data: &[u8]) -> usize {
+    let Some((mut eth_type, mut payload)) = link::network(link_type, data) else {
+        return data.len();
+    };
+    while matches!(eth_type, EthType::Vlan | EthType::ServiceVlan) {
+        let Ok(tag) = Vlan::new(payload) else {
+            break;
+        };
+        eth_type = tag.eth_type().get();
+        payload = &payload[Vlan::<&[u8]>::FIELD_PAYLOAD];
+    }
+    let link_len = data.len() - payload.len();
+
+    let network_len = match eth_type {
+        EthType::Ipv4 => Ipv4::new(payload).ok().map(|ipv4| {
+            let ipv4_len = ipv4.ihl().get() as usize * 4;
+            if ipv4.fragment_offset().get() != 0 {
+                return ipv4_len;
+            }
+            let protocol = ipv4.protocol().get();
+            ipv4_len + transport_len(protocol, payload.get(ipv4_len..).unwrap_or_default())
+        }),
+        EthType::Ipv6 => Ipv6::new(payload)
+            .ok()
+            .map(|ipv6| match ipv6.upper_layer() {
+                Some((protocol, upper)) => {
+                    let ipv6_len = upper.as_ptr() as usize - payload.as_ptr() as usize;
+                    ipv6_len + transport_len(protocol, upper)
+                }
+                None => Ipv6::<&[u8]>::FIELD_PAYLOAD.start,
+            }),
+        _ => None,
+    };
+    match network_len {
+        Some(network_len) => (link_len + network_len).min(data.len()),
+        None => data.len(),
+    }
+}
+
+/// Get the length of the transport header at the start of `data`.
+fn transport_len(protocol: IpProtocol, data: &[u8]) -> usize {
+    match protocol {
+        IpProtocol::Tcp => Tcp::new(data).map_or(0, |tcp| tcp.data_offset().get() as usize * 4),
+        IpProtocol::Udp | IpProtocol::UdpLite | IpProtocol::Icmp | IpProtocol::Ipv6Icmp => 8,
+        IpProtocol::Sctp => 12,
+        _ => 0,
+    }
+}
+
+/// Builder of [`PcapWriter`].
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct PcapWriterBuilder {
+    network: Option<u32>,
+    nanosecond: bool,
+    trim: Trim,
+    flush_policy: FlushPolicy,
+}
+
+impl PcapWriterBuilder {
+    /// Create a new builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the link type, Ethernet by default.
+    pub fn network(&mut self, network: u32) -> &mut Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Set whether timestamps are written with nanosecond resolution.
+    pub fn nanosecond(&mut self, nanosecond: bool) -> &mut Self {
+        self.nanosecond = nanosecond;
+        self
+    }
+
+    /// Set how packets are trimmed.
+    pub fn trim(&mut self, trim: Trim) -> &mut Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Set when buffered records are flushed.
+    pub fn flush_policy(&mut self, flush_policy: FlushPolicy) -> &mut Self {
+        self.flush_policy = flush_policy;
+        self
+    }
+
+    /// Create the writer and write the global header.
+    pub fn build<W: Write>(&self, writer: W) -> io::Result<PcapWriter<W>> {
+        let header = PcapHeader {
+            magic_number: if self.nanosecond {
+                PCAP_MAGIC_NANOSECOND
+            } else {
+                PCAP_MAGIC_MICROSECOND
+            },
+            version_major: 2,
+            version_minor: 4,
+            thiszone: 0,
+            sigfigs: 0,
+            snaplen: match self.trim {
+                Trim::Snaplen(snaplen) => snaplen.min(DEFAULT_SNAPLEN),
+                Trim::None | Trim::Headers => DEFAULT_SNAPLEN,
+            },
+            network: self.network.unwrap_or(LINKTYPE_ETHERNET),
+        };
+
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(&header.magic_number.to_le_bytes())?;
+        writer.write_all(&header.version_major.to_le_bytes())?;
+        writer.write_all(&header.version_minor.to_le_bytes())?;
+        writer.write_all(&header.thiszone.to_le_bytes())?;
+        writer.write_all(&header.sigfigs.to_le_bytes())?;
+        writer.write_all(&header.snaplen.to_le_bytes())?;
+        writer.write_all(&header.network.to_le_bytes())?;
+
+        Ok(PcapWriter {
+            header,
+            trim: self.trim,
+            writer,
+            flusher: Flusher::new(self.flush_policy),
+        })
+    }
+}
+
+/// Pcap file writer
+///
+/// Files are written in little-endian byte order.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    header: PcapHeader,
+    trim: Trim,
+    writer: BufWriter<W>,
+    flusher: Flusher,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Create a writer of untrimmed Ethernet frames with microsecond
+    /// timestamps.
+    pub fn new(writer: W) -> io::Result<Self> {
+        PcapWriterBuilder::new().build(writer)
+    }
+
+    /// Get the global header written.
+    pub fn header(&self) -> &PcapHeader {
+        &self.header
+    }
+
+    /// Write a packet captured at `ts` (since the epoch).
+    pub fn write_packet(&mut self, ts: Duration, data: &[u8]) -> io::Result<()> {
+        self.write_captured(ts, data, data.len() as u32)
+    }
+
+    /// Write a packet whose original length was `orig_len`.
+    ///
+    /// Use this to copy packets already truncated at capture.
+    pub fn write_captured(&mut self, ts: Duration, data: &[u8], orig_len: u32) -> io::Result<()> {
+        let incl_len = self.trim.len(self.header.network, data);
+        let subsec = if self.header.magic_number == PCAP_MAGIC_NANOSECOND {
+            ts.subsec_nanos()
+        } else {
+            ts.subsec_micros()
+        };
+
+        self.writer
+            .write_all(&(ts.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&subsec.to_le_bytes())?;
+        self.writer.write_all(&(incl_len as u32).to_le_bytes())?;
+        self.writer.write_all(&orig_len.to_le_bytes())?;
+        self.writer.write_all(&data[..incl_len])?;
+        self.flush_due()
+    }
+
+    /// Flush buffered records to the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flusher.flushed();
+        self.writer.flush()
+    }
+
+    /// Flush and get the underlying writer.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer.into_inner().map_err(|e| e.into_error())
+    }
+
+    fn flush_due(&mut self) -> io::Result<()> {
+        if self.flusher.wrote() {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: SyncWrite> PcapWriter<W> {
+    /// Flush buffered records and wait until they are on stable storage,
+    /// e.g. to checkpoint a long-running capture.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.writer.get_mut().sync()
+    }
+}
+
+impl PcapWriter<DurableFile> {
+    /// Flush, sync and finalize the file, see [`DurableFile::finish`].
+    pub fn finish(self) -> io::Result<()> {
+        self.into_inner()?.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, net::Ipv4Addr};
+
+    use netkit_packet::gen::craft::udp_datagram;
+
+    use super::*;
+
+    fn frame() -> Vec<u8> {
+        udp_datagram(
+            [0; 6],
+            [0; 6],
+            (Ipv4Addr::new(10, 0, 0, 1), 1000).into(),
+            (Ipv4Addr::new(10, 0, 0, 2), 53).into(),
+            [0; 100],
+        )
+        .inner()
+        .clone()
+    }
+
+    fn roundtrip(trim: Trim) -> Vec<(PacketHeader, Vec<u8>)> {
+        let mut writer = PcapWriterBuilder::new()
+            .trim(trim)
+            .build(Vec::new())
+            .unwrap();
+        writer
+            .write_packet(Duration::new(1, 500_000), &frame())
+            .unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let mut reader = PcapReader::new(Cursor::new(data));
+        let packets: Vec<_> = reader.by_ref().collect();
+        assert_eq!(reader.timestamp(&packets[0].0), Duration::new(1, 500_000));
+        packets
+    }
+
+    #[test]
+    fn pcap_writer_trim() {
+        let frame = frame();
+
+        let packets = roundtrip(Trim::None);
+        assert_eq!(packets[0].1, frame);
+
+        let packets = roundtrip(Trim::Snaplen(64));
+        assert_eq!(packets[0].0.incl_len, 64);
+        assert_eq!(packets[0].0.orig_len, frame.len() as u32);
+        assert_eq!(packets[0].1, frame[..64]);
+
+        let packets = roundtrip(Trim::Headers);
+        assert_eq!(packets[0].1, frame[..14 + 20 + 8]);
+
+        let tcp = tcp!(src_port: 443u16, dst_port: 1000u16, payload: [0; 100]);
+        let ipv6 = ipv6!(next_header: IpProtocol::Tcp, payload: tcp.inner());
+        let tag = vlan!(vid: 10u16, eth_type: EthType::Ipv6, payload: ipv6.inner());
+        let tagged = eth!(eth_type: EthType::Vlan, payload: tag.inner());
+        let len = Trim::Headers.len(LINKTYPE_ETHERNET, tagged.inner());
+        assert_eq!(len, 14 + 4 + 40 + 20);
+        let arp = eth!(eth_type: EthType::Arp, payload: [0; 28]);
+        assert_eq!(Trim::Headers.len(LINKTYPE_ETHERNET, arp.inner()), 42);
+
+        let error = PcapReader::try_new(Cursor::new(frame)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(PcapReader::try_new(Cursor::new([0xd4, 0xc3, 0xb2, 0xa1])).is_err());
+    }
+
+    #[test]
+    fn pcap_corrupt_records() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        writer.write_packet(Duration::ZERO, &frame()).unwrap();
+        writer.write_packet(Duration::ZERO, &frame()).unwrap();
+        let data = writer.into_inner().unwrap();
+        let second = PCAP_HEADER_LENGTH as usize + 16 + frame().len();
+
+        // A truncated last record ends the file.
+        let truncated = &data[..data.len() - 10];
+        assert_eq!(PcapReader::new(Cursor::new(truncated)).count(), 1);
+
+        // So does a record longer than any snaplen, without allocating it.
+        let mut oversized = data.clone();
+        oversized[second + 8..second + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = PcapReader::new(Cursor::new(&oversized));
+        assert!(reader.next_packet().is_some());
+        assert!(reader.next_packet().is_none());
+        let mut reader = PcapReader::new(Cursor::new(&oversized));
+        assert!(reader.skip_packet().is_some());
+        assert!(reader.skip_packet().is_none());
+    }
+
+    #[test]
+    fn pcap_jumbo_frames() {
+        // A 9K jumbo frame and a 64K super-jumbo (offloaded) frame.
+        let frames: Vec<Vec<u8>> = [8972, 65507]
+            .into_iter()
+            .map(|len| {
+                let udp = udp!(payload: vec![0x5A; len]);
+                let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+                eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                    .inner()
+                    .clone()
+            })
+            .collect();
+
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for frame in &frames {
+            writer.write_packet(Duration::ZERO, frame).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let mut reader = PcapReader::new(Cursor::new(data));
+        assert_eq!(reader.header.snaplen, DEFAULT_SNAPLEN);
+        let packets: Vec<_> = reader.by_ref().collect();
+        assert_eq!(packets.len(), 2);
+        for ((header, data), frame) in packets.iter().zip(&frames) {
+            assert_eq!(header.incl_len as usize, frame.len());
+            assert_eq!(data, frame);
+
+            let eth = Eth::new(data).unwrap();
+            let ipv4 = eth.ipv4().unwrap();
+            assert_eq!(ipv4.total_length().get() as usize, data.len() - 14);
+            assert_eq!(ipv4.udp().unwrap().payload().len(), data.len() - 42);
+        }
+        assert_eq!(packets[1].1.len(), 14 + 65535);
+    }
+
+    #[test]
+    fn pcap_pooled_read() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for _ in 0..3 {
+            writer.write_packet(Duration::ZERO, &frame()).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let pool = PacketPool::new(2048);
+        let mut reader = PcapReader::new(Cursor::new(data));
+        while let Some((header, packet)) = reader.next_pooled(&pool) {
+            assert_eq!(header.incl_len as usize, packet.len());
+            assert_eq!(&*packet, frame().as_slice());
+        }
+        assert_eq!(pool.allocated(), 1);
+    }
+
+    #[test]
+    fn pcap_sanitize_timestamps() {
+        use crate::sanitize::{Anomaly, TimestampPolicy};
+
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for ts in [1_000_000, 0, 990_000, 990_001] {
+            writer
+                .write_packet(Duration::from_millis(ts), &frame())
+                .unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let mut sanitizer = TimestampSanitizer::new();
+        sanitizer.policy(Anomaly::Zero, TimestampPolicy::Drop);
+        let mut reader = PcapReader::new(Cursor::new(data));
+        reader.sanitize_timestamps(sanitizer);
+        let ts: Vec<_> = reader
+            .by_ref()
+            .map(|(header, _)| header)
+            .collect::<Vec<_>>()
+            .iter()
+            .map(|header| reader.timestamp(header).as_millis())
+            .collect();
+        assert_eq!(ts, [1_000_000, 1_000_000, 1_000_001]);
+        let stats = reader.timestamp_sanitizer().unwrap().stats();
+        assert_eq!((stats.dropped, stats.corrected), (1, 1));
+    }
+}
@@ -0,0 +1,803 @@
+//! pcapng files.
+//!
+//! Besides packets, pcapng files hold blocks which pcap files have no room
+//! for. Two of them are exposed here:
+//!
+//! - Decryption Secrets Blocks, which embed key material such as a TLS key
+//!   log (the `SSLKEYLOGFILE` format), so that a capture can be decrypted
+//!   without shipping the keys separately,
+//! - custom blocks, which carry vendor data identified by a Private
+//!   Enterprise Number.
+//!
+//! The [`TimestampSource`] of a packet is recorded in a custom option of its
+//! Enhanced Packet Block, under [`PEN`]; other tools ignore it.
+//!
+//! Only Ethernet-style Enhanced and Simple Packet Blocks are read as
+//! packets; other blocks are handed out as [`PcapngBlock::Other`].
+
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    time::Duration,
+};
+
+use crate::{
+    sanitize::TimestampSanitizer,
+    timestamp::{Timestamp, TimestampSource},
+};
+
+use super::{
+    durable::{DurableFile, FlushPolicy, Flusher, SyncWrite},
+    pcap::{DEFAULT_SNAPLEN, LINKTYPE_ETHERNET},
+};
+
+/// Block type of Section Header Blocks.
+pub const BLOCK_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+
+/// Block type of Interface Description Blocks.
+pub const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+
+/// Block type of Simple Packet Blocks.
+pub const BLOCK_SIMPLE_PACKET: u32 = 0x0000_0003;
+
+/// Block type of Enhanced Packet Blocks.
+pub const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+
+/// Block type of Decryption Secrets Blocks.
+pub const BLOCK_DECRYPTION_SECRETS: u32 = 0x0000_000a;
+
+/// Block type of custom blocks which may be copied to new files.
+pub const BLOCK_CUSTOM: u32 = 0x0000_0bad;
+
+/// Block type of custom blocks which must not be copied to new files.
+pub const BLOCK_CUSTOM_NO_COPY: u32 = 0x4000_0bad;
+
+/// Largest block length accepted by readers.
+///
+/// Block lengths come from the file, and must not size the buffer a block is
+/// read into unchecked; a longer block is reported as invalid.
+pub const MAX_BLOCK_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// Byte-order magic of Section Header Blocks.
+pub const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+/// Secrets type of TLS key logs (`TLSK`).
+pub const SECRETS_TLS_KEY_LOG: u32 = 0x544c_534b;
+
+/// Secrets type of WireGuard key logs (`WGKL`).
+pub const SECRETS_WIREGUARD_KEY_LOG: u32 = 0x5747_4b4c;
+
+/// Option code ending the options of a block.
+const OPTION_END: u16 = 0;
+
+/// Option code of comments.
+const OPTION_COMMENT: u16 = 1;
+
+/// Option code of the timestamp resolution of an interface.
+const OPTION_IF_TSRESOL: u16 = 9;
+
+/// Option code of binary custom options which may be copied.
+const OPTION_CUSTOM_BINARY: u16 = 2989;
+
+/// Private Enterprise Number of the custom options written by this crate.
+///
+/// This is the number reserved for documentation by RFC 5612, until one is
+/// assigned.
+pub const PEN: u32 = 32473;
+
+/// Type of the custom option holding the timestamp source of a packet.
+const CUSTOM_TIMESTAMP_SOURCE: u8 = 1;
+
+/// An interface of a section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcapngInterface {
+    /// Link type.
+    pub link_type: u16,
+    /// Snapshot length, 0 if unlimited.
+    pub snaplen: u32,
+    /// Number of timestamp units per second.
+    pub ts_units: u64,
+}
+
+/// A packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapngPacket {
+    /// Index of the interface in its section.
+    pub interface: u32,
+    /// Timestamp since the epoch, zero for Simple Packet Blocks.
+    pub ts: Duration,
+    /// Where the timestamp was taken, unknown unless recorded by
+    /// [`PcapngWriter::write_timestamped_packet`].
+    pub ts_source: TimestampSource,
+    /// Original length.
+    pub orig_len: u32,
+    /// Captured data.
+    pub data: Vec<u8>,
+    /// Comments of Enhanced Packet Blocks, e.g. provenance records.
+    pub comments: Vec<String>,
+}
+
+/// Key material of a Decryption Secrets Block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptionSecrets {
+    /// Secrets type, e.g. [`SECRETS_TLS_KEY_LOG`].
+    pub secrets_type: u32,
+    /// Secrets data.
+    pub data: Vec<u8>,
+}
+
+impl DecryptionSecrets {
+    /// Create a TLS key log block, in the `SSLKEYLOGFILE` format.
+    pub fn tls_key_log(key_log: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secrets_type: SECRETS_TLS_KEY_LOG,
+            data: key_log.into(),
+        }
+    }
+
+    /// Get the TLS key log, if these are TLS secrets.
+    pub fn key_log(&self) -> Option<&[u8]> {
+        (self.secrets_type == SECRETS_TLS_KEY_LOG).then_some(&self.data[..])
+    }
+}
+
+/// A custom block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomBlock {
+    /// Private Enterprise Number of the vendor defining the block.
+    pub pen: u32,
+    /// Whether the block may be copied to new files.
+    pub copy: bool,
+    /// Custom data.
+    pub data: Vec<u8>,
+}
+
+/// A block of a pcapng file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PcapngBlock {
+    /// Start of a new section, whose interfaces replace the previous ones.
+    Section,
+    /// A new interface.
+    Interface(PcapngInterface),
+    /// A packet.
+    Packet(PcapngPacket),
+    /// Decryption secrets.
+    DecryptionSecrets(DecryptionSecrets),
+    /// A custom block.
+    Custom(CustomBlock),
+    /// A block of another type, with its body.
+    Other {
+        /// Block type.
+        block_type: u32,
+        /// Block body, between the length fields.
+        body: Vec<u8>,
+    },
+}
+
+/// pcapng file reader.
+#[derive(Debug)]
+pub struct PcapngReader<R: Read> {
+    reader: BufReader<R>,
+    big_endian: bool,
+    interfaces: Vec<PcapngInterface>,
+    secrets: Vec<DecryptionSecrets>,
+    custom: Vec<CustomBlock>,
+    peeked: Option<u32>,
+    sanitizer: Option<TimestampSanitizer>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl<R: Read> PcapngReader<R> {
+    /// Create a reader, checking that the file starts with a section.
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut reader = Self {
+            reader: BufReader::new(reader),
+            big_endian: false,
+            interfaces: Vec::new(),
+            secrets: Vec::new(),
+            custom: Vec::new(),
+            peeked: None,
+            sanitizer: None,
+        };
+        let mut block_type = [0; 4];
+        reader.reader.read_exact(&mut block_type)?;
+        if u32::from_le_bytes(block_type) != BLOCK_SECTION_HEADER {
+            return Err(invalid("not a pcapng file"));
+        }
+        reader.peeked = Some(BLOCK_SECTION_HEADER);
+        Ok(reader)
+    }
+
+    /// Get the interfaces of the current section.
+    pub fn interfaces(&self) -> &[PcapngInterface] {
+        &self.interfaces
+    }
+
+    /// Get the decryption secrets read so far.
+    pub fn secrets(&self) -> &[DecryptionSecrets] {
+        &self.secrets
+    }
+
+    /// Get the TLS key logs read so far, concatenated.
+    pub fn key_log(&self) -> Vec<u8> {
+        self.secrets
+            .iter()
+            .filter_map(DecryptionSecrets::key_log)
+            .flat_map(|log| {
+                let newline = (!log.ends_with(b"\n")).then_some(b'\n');
+                log.iter().copied().chain(newline)
+            })
+            .collect()
+    }
+
+    /// Get the custom blocks read so far.
+    pub fn custom_blocks(&self) -> &[CustomBlock] {
+        &self.custom
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes[..4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = bytes[..2].try_into().unwrap();
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    /// Read the next block.
+    ///
+    /// Decryption secrets and custom blocks are also kept by the reader.
+    pub fn next_block(&mut self) -> io::Result<Option<PcapngBlock>> {
+        let mut raw_type = [0; 4];
+        let block_type = match self.peeked.take() {
+            Some(block_type) => block_type,
+            None => match self.reader.read_exact(&mut raw_type) {
+                Ok(()) => u32::from_le_bytes(raw_type),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            },
+        };
+
+        let mut raw_len = [0; 4];
+        self.reader.read_exact(&mut raw_len)?;
+        if block_type == BLOCK_SECTION_HEADER {
+            // The byte order of a section is given by its header.
+            let mut magic = [0; 4];
+            self.reader.read_exact(&mut magic)?;
+            self.big_endian = match u32::from_le_bytes(magic) {
+                BYTE_ORDER_MAGIC => false,
+                magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err(invalid("invalid pcapng byte-order magic")),
+            };
+            let len = self.u32(&raw_len) as usize;
+            if len < 28 || !len.is_multiple_of(4) || len > MAX_BLOCK_LENGTH as usize {
+                return Err(invalid("invalid pcapng section header length"));
+            }
+            let mut rest = vec![0; len - 12];
+            self.reader.read_exact(&mut rest)?;
+            self.interfaces.clear();
+            #[cfg(feature = "tracing")]
+            tracing::debug!(big_endian = self.big_endian, "opened pcapng section");
+            return Ok(Some(PcapngBlock::Section));
+        }
+
+        let block_type = if self.big_endian {
+            u32::from_be_bytes(raw_type)
+        } else {
+            block_type
+        };
+        let len = self.u32(&raw_len) as usize;
+        if len < 12 || !len.is_multiple_of(4) || len > MAX_BLOCK_LENGTH as usize {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(block_type, len, "invalid pcapng block length");
+            return Err(invalid("invalid pcapng block length"));
+        }
+        let mut body = vec![0; len - 8];
+        self.reader.read_exact(&mut body)?;
+        body.truncate(len - 12);
+
+        let block = match block_type {
+            BLOCK_INTERFACE_DESCRIPTION if body.len() >= 8 => {
+                let interface = PcapngInterface {
+                    link_type: self.u16(&body[0..]),
+                    snaplen: self.u32(&body[4..]),
+                    ts_units: self.ts_units(&body[8..]),
+                };
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    link_type = interface.link_type,
+                    snaplen = interface.snaplen,
+                    "pcapng interface"
+                );
+                self.interfaces.push(interface);
+                PcapngBlock::Interface(interface)
+            }
+            BLOCK_ENHANCED_PACKET if body.len() >= 20 => {
+                let interface = self.u32(&body[0..]);
+                let ts = (self.u32(&body[4..]) as u64) << 32 | self.u32(&body[8..]) as u64;
+                let cap_len = self.u32(&body[12..]) as usize;
+                let orig_len = self.u32(&body[16..]);
+                let data = body
+                    .get(20..20 + cap_len)
+                    .ok_or_else(|| invalid("invalid pcapng packet length"))?;
+                let options = body
+                    .get(20 + cap_len.next_multiple_of(4)..)
+                    .unwrap_or_default();
+                let mut comments = Vec::new();
+                let mut ts_source = TimestampSource::Unknown;
+                for (code, value) in self.options(options) {
+                    match (code, value) {
+                        (OPTION_COMMENT, _) => {
+                            comments.push(String::from_utf8_lossy(value).into_owned());
+                        }
+                        (OPTION_CUSTOM_BINARY, [pen @ .., CUSTOM_TIMESTAMP_SOURCE, source])
+                            if pen.len() == 4 && self.u32(pen) == PEN =>
+                        {
+                            ts_source = TimestampSource::from_code(*source);
+                        }
+                        _ => {}
+                    }
+                }
+                let units = self
+                    .interfaces
+                    .get(interface as usize)
+                    .map_or(1_000_000, |i| i.ts_units);
+                PcapngBlock::Packet(PcapngPacket {
+                    interface,
+                    ts: Duration::from_secs(ts / units)
+                        + Duration::from_nanos(
+                            ((ts % units) as u128 * 1_000_000_000 / units as u128) as u64,
+                        ),
+                    ts_source,
+                    orig_len,
+                    data: data.to_vec(),
+                    comments,
+                })
+            }
+            BLOCK_SIMPLE_PACKET if body.len() >= 4 => {
+                let orig_len = self.u32(&body);
+                let cap_len = (orig_len as usize).min(body.len() - 4);
+                PcapngBlock::Packet(PcapngPacket {
+                    interface: 0,
+                    ts: Duration::ZERO,
+                    ts_source: TimestampSource::Unknown,
+                    orig_len,
+                    data: body[4..4 + cap_len].to_vec(),
+                    comments: Vec::new(),
+                })
+            }
+            BLOCK_DECRYPTION_SECRETS if body.len() >= 8 => {
+                let secrets_type = self.u32(&body);
+                let len = self.u32(&body[4..]) as usize;
+                let data = body
+                    .get(8..8 + len)
+                    .ok_or_else(|| invalid("invalid pcapng secrets length"))?;
+                let secrets = DecryptionSecrets {
+                    secrets_type,
+                    data: data.to_vec(),
+                };
+                self.secrets.push(secrets.clone());
+                PcapngBlock::DecryptionSecrets(secrets)
+            }
+            // The custom data length is not recorded, so it keeps the padding
+            // and options.
+            BLOCK_CUSTOM | BLOCK_CUSTOM_NO_COPY if body.len() >= 4 => {
+                let custom = CustomBlock {
+                    pen: self.u32(&body),
+                    copy: block_type == BLOCK_CUSTOM,
+                    data: body[4..].to_vec(),
+                };
+                self.custom.push(custom.clone());
+                PcapngBlock::Custom(custom)
+            }
+            block_type => PcapngBlock::Other { block_type, body },
+        };
+        Ok(Some(block))
+    }
+
+    /// Split the options of a block into codes and values.
+    fn options<'a>(&self, mut options: &'a [u8]) -> Vec<(u16, &'a [u8])> {
+        let mut parsed = Vec::new();
+        while options.len() >= 4 {
+            let code = self.u16(options);
+            let len = self.u16(&options[2..]) as usize;
+            let Some(value) = options.get(4..4 + len) else {
+                break;
+            };
+            if code == OPTION_END {
+                break;
+            }
+            parsed.push((code, value));
+            options = options
+                .get(4 + len.next_multiple_of(4)..)
+                .unwrap_or_default();
+        }
+        parsed
+    }
+
+    /// Get the timestamp units per second from interface options.
+    fn ts_units(&self, options: &[u8]) -> u64 {
+        for (code, value) in self.options(options) {
+            if code == OPTION_IF_TSRESOL && value.len() == 1 {
+                let resolution = value[0];
+                let exponent = (resolution & 0x7f) as u32;
+                return if resolution & 0x80 == 0 {
+                    10u64.checked_pow(exponent).unwrap_or(1_000_000)
+                } else {
+                    2u64.checked_pow(exponent).unwrap_or(1_000_000)
+                };
+            }
+        }
+        1_000_000
+    }
+
+    /// Sanitize the timestamps of the packets read with
+    /// [`next_packet`](Self::next_packet), see [`sanitize`].
+    ///
+    /// [`sanitize`]: crate::sanitize
+    pub fn sanitize_timestamps(&mut self, sanitizer: TimestampSanitizer) -> &mut Self {
+        self.sanitizer = Some(sanitizer);
+        self
+    }
+
+    /// Get the timestamp sanitizer, with its counters.
+    pub fn timestamp_sanitizer(&self) -> Option<&TimestampSanitizer> {
+        self.sanitizer.as_ref()
+    }
+
+    /// Read the next packet, skipping (and keeping) other blocks.
+    ///
+    /// Packets dropped by the timestamp sanitizer are skipped.
+    pub fn next_packet(&mut self) -> io::Result<Option<PcapngPacket>> {
+        while let Some(block) = self.next_block()? {
+            if let PcapngBlock::Packet(mut packet) = block {
+                let Some(sanitizer) = &mut self.sanitizer else {
+                    return Ok(Some(packet));
+                };
+                if let Some(ts) = sanitizer.sanitize(packet.ts) {
+                    packet.ts = ts;
+                    return Ok(Some(packet));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Builder of [`PcapngWriter`].
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct PcapngWriterBuilder {
+    link_type: Option<u16>,
+    secrets: Vec<DecryptionSecrets>,
+    flush_policy: FlushPolicy,
+}
+
+impl PcapngWriterBuilder {
+    /// Create a new builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the link type of the interface, Ethernet by default.
+    pub fn link_type(&mut self, link_type: u16) -> &mut Self {
+        self.link_type = Some(link_type);
+        self
+    }
+
+    /// Embed a TLS key log (the content of an `SSLKEYLOGFILE`) before the
+    /// packets.
+    pub fn key_log(&mut self, key_log: impl Into<Vec<u8>>) -> &mut Self {
+        self.secrets.push(DecryptionSecrets::tls_key_log(key_log));
+        self
+    }
+
+    /// Embed decryption secrets before the packets.
+    pub fn secrets(&mut self, secrets: DecryptionSecrets) -> &mut Self {
+        self.secrets.push(secrets);
+        self
+    }
+
+    /// Set when buffered packets are flushed.
+    pub fn flush_policy(&mut self, flush_policy: FlushPolicy) -> &mut Self {
+        self.flush_policy = flush_policy;
+        self
+    }
+
+    /// Create the writer and write the section header, the interface
+    /// description and the secrets.
+    pub fn build<W: Write>(&self, writer: W) -> io::Result<PcapngWriter<W>> {
+        let mut writer = PcapngWriter {
+            writer: BufWriter::new(writer),
+            flusher: Flusher::new(self.flush_policy),
+        };
+
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        // Unknown section length
+        shb.extend_from_slice(&u64::MAX.to_le_bytes());
+        writer.write_block(BLOCK_SECTION_HEADER, &shb)?;
+
+        // Nanosecond timestamps
+        let mut idb = Vec::new();
+        idb.extend_from_slice(
+            &self
+                .link_type
+                .unwrap_or(LINKTYPE_ETHERNET as u16)
+                .to_le_bytes(),
+        );
+        idb.extend_from_slice(&0u16.to_le_bytes());
+        idb.extend_from_slice(&DEFAULT_SNAPLEN.to_le_bytes());
+        idb.extend_from_slice(&OPTION_IF_TSRESOL.to_le_bytes());
+        idb.extend_from_slice(&1u16.to_le_bytes());
+        idb.extend_from_slice(&[9, 0, 0, 0]);
+        idb.extend_from_slice(&[0; 4]);
+        writer.write_block(BLOCK_INTERFACE_DESCRIPTION, &idb)?;
+
+        for secrets in &self.secrets {
+            writer.write_secrets(secrets)?;
+        }
+        Ok(writer)
+    }
+}
+
+/// pcapng file writer
+///
+/// Files are written in little-endian byte order, with one section and one
+/// interface with nanosecond timestamps.
+#[derive(Debug)]
+pub struct PcapngWriter<W: Write> {
+    writer: BufWriter<W>,
+    flusher: Flusher,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Create a writer of Ethernet frames.
+    pub fn new(writer: W) -> io::Result<Self> {
+        PcapngWriterBuilder::new().build(writer)
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let padding = body.len().next_multiple_of(4) - body.len();
+        let len = (12 + body.len() + padding) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&[0; 3][..padding])?;
+        self.writer.write_all(&len.to_le_bytes())
+    }
+
+    /// Write a packet captured at `ts` (since the epoch).
+    pub fn write_packet(&mut self, ts: Duration, data: &[u8]) -> io::Result<()> {
+        self.write_packet_with_comments(ts, data, &[])
+    }
+
+    /// Write a packet with comments, e.g. the provenance record of a frame
+    /// rewritten by transforms.
+    pub fn write_packet_with_comments(
+        &mut self,
+        ts: Duration,
+        data: &[u8],
+        comments: &[&str],
+    ) -> io::Result<()> {
+        self.write_enhanced_packet(ts, TimestampSource::Unknown, data, comments)
+    }
+
+    /// Write a packet with the source of its timestamp, which is read back
+    /// in [`PcapngPacket::ts_source`].
+    pub fn write_timestamped_packet(&mut self, ts: Timestamp, data: &[u8]) -> io::Result<()> {
+        self.write_enhanced_packet(ts.time, ts.source, data, &[])
+    }
+
+    fn write_enhanced_packet(
+        &mut self,
+        ts: Duration,
+        ts_source: TimestampSource,
+        data: &[u8],
+        comments: &[&str],
+    ) -> io::Result<()> {
+        let ts = ts.as_nanos() as u64;
+        let mut body = Vec::with_capacity(20 + data.len());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        let mut options = Vec::new();
+        for comment in comments {
+            options.push((OPTION_COMMENT, comment.as_bytes().to_vec()));
+        }
+        if ts_source != TimestampSource::Unknown {
+            let mut value = PEN.to_le_bytes().to_vec();
+            value.extend_from_slice(&[CUSTOM_TIMESTAMP_SOURCE, ts_source.code()]);
+            options.push((OPTION_CUSTOM_BINARY, value));
+        }
+        if !options.is_empty() {
+            body.resize(body.len().next_multiple_of(4), 0);
+            for (code, value) in options {
+                body.extend_from_slice(&code.to_le_bytes());
+                body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                body.extend_from_slice(&value);
+                body.resize(body.len().next_multiple_of(4), 0);
+            }
+            body.extend_from_slice(&[0; 4]);
+        }
+        self.write_block(BLOCK_ENHANCED_PACKET, &body)?;
+        self.flush_due()
+    }
+
+    /// Write decryption secrets, which apply to the packets after them.
+    pub fn write_secrets(&mut self, secrets: &DecryptionSecrets) -> io::Result<()> {
+        let mut body = Vec::with_capacity(8 + secrets.data.len());
+        body.extend_from_slice(&secrets.secrets_type.to_le_bytes());
+        body.extend_from_slice(&(secrets.data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&secrets.data);
+        self.write_block(BLOCK_DECRYPTION_SECRETS, &body)
+    }
+
+    /// Write a custom block.
+    pub fn write_custom(&mut self, custom: &CustomBlock) -> io::Result<()> {
+        let mut body = Vec::with_capacity(4 + custom.data.len());
+        body.extend_from_slice(&custom.pen.to_le_bytes());
+        body.extend_from_slice(&custom.data);
+        let block_type = if custom.copy {
+            BLOCK_CUSTOM
+        } else {
+            BLOCK_CUSTOM_NO_COPY
+        };
+        self.write_block(block_type, &body)
+    }
+
+    /// Flush buffered blocks to the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flusher.flushed();
+        self.writer.flush()
+    }
+
+    /// Flush and get the underlying writer.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer.into_inner().map_err(|e| e.into_error())
+    }
+
+    fn flush_due(&mut self) -> io::Result<()> {
+        if self.flusher.wrote() {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: SyncWrite> PcapngWriter<W> {
+    /// Flush buffered blocks and wait until they are on stable storage,
+    /// e.g. to checkpoint a long-running capture.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.writer.get_mut().sync()
+    }
+}
+
+impl PcapngWriter<DurableFile> {
+    /// Flush, sync and finalize the file, see [`DurableFile::finish`].
+    pub fn finish(self) -> io::Result<()> {
+        self.into_inner()?.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn pcapng_secrets_and_custom_blocks() {
+        let key_log = b"CLIENT_RANDOM 0011 aabb\n";
+        let mut writer = PcapngWriterBuilder::new()
+            .key_log(&key_log[..])
+            .build(Vec::new())
+            .unwrap();
+        writer
+            .write_custom(&CustomBlock {
+                pen: 32473,
+                copy: true,
+                data: b"note".to_vec(),
+            })
+            .unwrap();
+        writer
+            .write_packet(Duration::new(1, 500), &[0xab; 61])
+            .unwrap();
+        writer
+            .write_packet_with_comments(Duration::new(2, 0), &[0xcd; 62], &["a", "masked"])
+            .unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let mut reader = PcapngReader::new(Cursor::new(data)).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.ts, Duration::new(1, 500));
+        assert_eq!(packet.data, [0xab; 61]);
+        assert_eq!(reader.interfaces()[0].ts_units, 1_000_000_000);
+        assert_eq!(reader.key_log(), key_log);
+        assert_eq!(reader.custom_blocks()[0].pen, 32473);
+        assert_eq!(reader.custom_blocks()[0].data, b"note");
+        assert!(packet.comments.is_empty());
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.data, [0xcd; 62]);
+        assert_eq!(packet.comments, ["a", "masked"]);
+        assert_eq!(reader.next_packet().unwrap(), None);
+
+        assert!(PcapngReader::new(Cursor::new([0xd4, 0xc3, 0xb2, 0xa1])).is_err());
+    }
+
+    #[test]
+    fn pcapng_timestamp_source() {
+        let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+        writer.write_packet(Duration::new(1, 0), &[1; 60]).unwrap();
+        for source in [
+            TimestampSource::Interpolated,
+            TimestampSource::Userspace,
+            TimestampSource::Software,
+            TimestampSource::Hardware,
+        ] {
+            let ts = Timestamp::new(Duration::new(2, 7), source);
+            writer.write_timestamped_packet(ts, &[2; 61]).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let mut reader = PcapngReader::new(Cursor::new(data)).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.ts_source, TimestampSource::Unknown);
+        let mut sources = Vec::new();
+        while let Some(packet) = reader.next_packet().unwrap() {
+            assert_eq!(packet.ts, Duration::new(2, 7));
+            assert_eq!(packet.data, [2; 61]);
+            assert!(packet.comments.is_empty());
+            sources.push(packet.ts_source);
+        }
+        assert_eq!(
+            sources,
+            [
+                TimestampSource::Interpolated,
+                TimestampSource::Userspace,
+                TimestampSource::Software,
+                TimestampSource::Hardware,
+            ]
+        );
+    }
+
+    #[test]
+    fn pcapng_oversized_block() {
+        let data = PcapngWriter::new(Vec::new()).unwrap().into_inner().unwrap();
+        for (block_type, len) in [
+            (BLOCK_CUSTOM, 0xffff_fffc),
+            (BLOCK_SECTION_HEADER, MAX_BLOCK_LENGTH + 4),
+        ] {
+            let mut file = data.clone();
+            file.extend_from_slice(&block_type.to_le_bytes());
+            file.extend_from_slice(&len.to_le_bytes());
+            file.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+            let mut reader = PcapngReader::new(Cursor::new(file)).unwrap();
+            let e = loop {
+                match reader.next_block() {
+                    Ok(Some(_)) => continue,
+                    Ok(None) => panic!("oversized block accepted"),
+                    Err(e) => break e,
+                }
+            };
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
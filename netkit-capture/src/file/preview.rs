@@ -0,0 +1,188 @@
+//! Sampled previews of large captures.
+//!
+//! Interactive tools want to show something of a capture well before it is
+//! fully read or indexed. A [`SampledReader`] reads a pcap file keeping only
+//! a sample of its packets, and skips the data of the others without
+//! copying it, for a fast approximate first view:
+//!
+//! - [`PreviewSampling::OneInN`] keeps one packet out of every N;
+//! - [`PreviewSampling::Interval`] keeps the first packet of every interval
+//!   of capture time, so that bursts do not crowd out the quiet periods.
+//!
+//! ```
+//! use std::{io::Cursor, time::Duration};
+//!
+//! use netkit_capture::file::{
+//!     pcap::{PcapReader, PcapWriter},
+//!     preview::{PreviewSampling, SampledReader},
+//! };
+//!
+//! let mut writer = PcapWriter::new(Vec::new()).unwrap();
+//! for i in 0..100 {
+//!     writer.write_packet(Duration::from_millis(i), &[i as u8]).unwrap();
+//! }
+//! let file = writer.into_inner().unwrap();
+//!
+//! let reader = PcapReader::new(Cursor::new(file));
+//! let mut preview = SampledReader::new(reader, PreviewSampling::OneInN(10));
+//! assert_eq!(preview.by_ref().count(), 10);
+//! assert_eq!(preview.seen(), 100);
+//! ```
+
+use std::{io::Read, time::Duration};
+
+use super::pcap::{PacketHeader, PcapReader};
+
+/// Which packets a [`SampledReader`] keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewSampling {
+    /// Keep every N-th packet, starting with the first one; 0 keeps all.
+    OneInN(u64),
+    /// Keep the first packet of every interval of capture time, counted from
+    /// the first packet.
+    Interval(Duration),
+}
+
+/// Reader of a sample of the packets of a pcap file.
+#[derive(Debug)]
+pub struct SampledReader<R: Read> {
+    reader: PcapReader<R>,
+    sampling: PreviewSampling,
+    seen: u64,
+    sampled: u64,
+    start: Option<Duration>,
+    last_slot: Option<u128>,
+}
+
+impl<R: Read> SampledReader<R> {
+    /// Sample the packets of a reader.
+    pub fn new(reader: PcapReader<R>, sampling: PreviewSampling) -> Self {
+        Self {
+            reader,
+            sampling,
+            seen: 0,
+            sampled: 0,
+            start: None,
+            last_slot: None,
+        }
+    }
+
+    /// Get the number of packets read or skipped so far.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Get the number of packets kept so far.
+    pub fn sampled(&self) -> u64 {
+        self.sampled
+    }
+
+    /// Get the number of packets seen per packet kept, to scale up the
+    /// statistics of the sample.
+    pub fn scale(&self) -> f64 {
+        self.seen as f64 / self.sampled.max(1) as f64
+    }
+
+    /// Get the underlying reader, e.g. for its header.
+    pub fn get_ref(&self) -> &PcapReader<R> {
+        &self.reader
+    }
+
+    /// Get the underlying reader back.
+    pub fn into_inner(self) -> PcapReader<R> {
+        self.reader
+    }
+
+    /// Read the next packet of the sample.
+    pub fn next_packet(&mut self) -> Option<(PacketHeader, Vec<u8>)> {
+        match self.sampling {
+            PreviewSampling::OneInN(n) => {
+                let n = n.max(1);
+                while !self.seen.is_multiple_of(n) {
+                    self.reader.skip_packet()?;
+                    self.seen += 1;
+                }
+                let packet = self.reader.next_packet()?;
+                self.seen += 1;
+                self.sampled += 1;
+                Some(packet)
+            }
+            PreviewSampling::Interval(interval) => {
+                let interval = interval.as_nanos().max(1);
+                let mut data = Vec::new();
+                loop {
+                    // Only read the data of the packets kept.
+                    let header = self.reader.next_header()?;
+                    let ts = self.reader.timestamp(&header);
+                    let start = *self.start.get_or_insert(ts);
+                    let slot = ts.saturating_sub(start).as_nanos() / interval;
+                    self.seen += 1;
+                    if self.last_slot.is_some_and(|last| slot <= last) {
+                        self.reader.skip_data(&header)?;
+                        continue;
+                    }
+                    self.reader.read_data(&header, &mut data)?;
+                    self.last_slot = Some(slot);
+                    self.sampled += 1;
+                    return Some((header, data));
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for SampledReader<R> {
+    type Item = (PacketHeader, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::file::pcap::PcapWriter;
+
+    #[test]
+    fn sampled_preview() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        // Ten packets 100 ms apart, then a burst of ten 1 ms apart
+        for i in 0..10u8 {
+            let ts = Duration::from_millis(100 * i as u64);
+            writer.write_packet(ts, &[i; 4]).unwrap();
+        }
+        for i in 10..20u8 {
+            let ts = Duration::from_millis(1000 + i as u64);
+            writer.write_packet(ts, &[i; 4]).unwrap();
+        }
+        let file = writer.into_inner().unwrap();
+        let sample = |sampling| {
+            let mut reader =
+                SampledReader::new(PcapReader::new(Cursor::new(file.clone())), sampling);
+            let kept: Vec<_> = reader.by_ref().map(|(_, data)| data[0]).collect();
+            (kept, reader.seen())
+        };
+
+        assert_eq!(sample(PreviewSampling::OneInN(7)), (vec![0, 7, 14], 20));
+        assert_eq!(
+            sample(PreviewSampling::OneInN(0)).0,
+            (0..20).collect::<Vec<_>>()
+        );
+        // The burst only gets one packet in its interval.
+        let (kept, seen) = sample(PreviewSampling::Interval(Duration::from_millis(250)));
+        assert_eq!((kept, seen), (vec![0, 3, 5, 8, 10], 20));
+
+        let mut reader = SampledReader::new(
+            PcapReader::new(Cursor::new(file)),
+            PreviewSampling::Interval(Duration::from_millis(500)),
+        );
+        let (header, data) = reader.next_packet().unwrap();
+        assert_eq!((header.incl_len, data), (4, vec![0; 4]));
+        assert_eq!(reader.by_ref().count(), 2);
+        assert_eq!(reader.scale(), 20.0 / 3.0);
+        assert_eq!(reader.into_inner().offset(), 24 + 20 * 20);
+    }
+}
@@ -0,0 +1,431 @@
+//! Read-only remote capture files.
+//!
+//! Archived captures are often multi-GB objects on an HTTP server or in an
+//! S3 bucket. A [`RemoteFile`] reads them with HTTP range requests, one
+//! block at a time, and implements [`Read`] and [`Seek`], so a
+//! [`PcapReader`](super::pcap::PcapReader) or
+//! [`PcapngReader`](super::pcapng::PcapngReader) streams it like a local
+//! file, and with a [`PcapIndex`](super::index::PcapIndex) only the blocks
+//! holding the wanted packets are fetched:
+//!
+//! ```no_run
+//! use netkit_capture::file::{index::PcapIndex, pcap::PcapReader, remote::RemoteFile};
+//!
+//! let file = RemoteFile::open("http://archive.local/captures/day1.pcap").unwrap();
+//! let mut reader = PcapReader::new(file);
+//! let index = PcapIndex::load("day1.pcap.nkidx").unwrap();
+//! index.verify(&mut reader).unwrap();
+//! let entry = index.get(1_000_000).unwrap();
+//! let packet = reader.packet_at(entry.offset).unwrap();
+//! ```
+//!
+//! S3 objects are read through presigned URLs, or with
+//! [`s3`](RemoteFile::s3) from public buckets and S3-compatible servers.
+//!
+//! The client speaks HTTP/1.1. Plain HTTP needs no dependencies; HTTPS,
+//! which S3 and presigned URLs require, needs the `remote-tls` feature,
+//! verifying servers against the Mozilla root certificates with rustls.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+use crate::http::{self, Client};
+
+/// Default number of bytes fetched per request.
+pub const DEFAULT_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Default timeout of connections, reads and writes.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default number of retries of a failed request.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Remote file read with HTTP range requests
+#[derive(Debug)]
+pub struct RemoteFile {
+    client: Client,
+    target: String,
+    headers: Vec<(String, String)>,
+    block_size: usize,
+    max_retries: u32,
+    len: u64,
+    pos: u64,
+    block: Vec<u8>,
+    block_start: u64,
+    requests: u64,
+}
+
+impl RemoteFile {
+    /// Open the file at an `http://` or `https://` URL, fetching its length.
+    pub fn open(url: &str) -> io::Result<Self> {
+        let mut file = Self::builder(url)?;
+        file.connect()?;
+        Ok(file)
+    }
+
+    /// Open the object `key` of `bucket` on an S3-compatible `endpoint`
+    /// (e.g. `s3.eu-west-1.amazonaws.com` or `http://127.0.0.1:9000`), with
+    /// path-style addressing.
+    ///
+    /// Endpoints without a scheme are reached over HTTPS.
+    pub fn s3(endpoint: &str, bucket: &str, key: &str) -> io::Result<Self> {
+        let key: String = key
+            .split('/')
+            .map(percent_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        let scheme = if endpoint.contains("://") {
+            ""
+        } else {
+            "https://"
+        };
+        Self::open(&format!("{scheme}{endpoint}/{bucket}/{key}"))
+    }
+
+    /// Create an unopened file at an `http://` or `https://` URL, to be
+    /// configured and [`connect`](Self::connect)ed.
+    ///
+    /// Other schemes, and HTTPS without the `remote-tls` feature, are
+    /// [`Unsupported`](io::ErrorKind::Unsupported); URLs without a scheme
+    /// are invalid.
+    pub fn builder(url: &str) -> io::Result<Self> {
+        let (mut client, target) = Client::from_url(url)?;
+        client.timeout(DEFAULT_TIMEOUT);
+        Ok(Self {
+            client,
+            target,
+            headers: Vec::new(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_retries: DEFAULT_MAX_RETRIES,
+            len: 0,
+            pos: 0,
+            block: Vec::new(),
+            block_start: 0,
+            requests: 0,
+        })
+    }
+
+    /// Add a header to every request, e.g. `Authorization`.
+    pub fn header(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the number of bytes fetched per request.
+    pub fn block_size(&mut self, block_size: usize) -> &mut Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Set the timeout of connections, reads and writes.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.client.timeout(timeout);
+        self
+    }
+
+    /// Set the number of retries of a failed request.
+    pub fn max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Trust a DER-encoded root certificate besides the Mozilla ones, e.g.
+    /// the private CA of an S3-compatible server.
+    #[cfg(feature = "remote-tls")]
+    pub fn root_certificate(&mut self, der: impl Into<Vec<u8>>) -> io::Result<&mut Self> {
+        self.client.root_certificate(der)?;
+        Ok(self)
+    }
+
+    /// Fetch the length of the file.
+    ///
+    /// The first byte is requested rather than the headers, since presigned
+    /// S3 URLs are only valid for GET requests.
+    pub fn connect(&mut self) -> io::Result<()> {
+        let (len, block) = self.fetch(0, 0)?;
+        self.len = len;
+        self.block = block;
+        self.block_start = 0;
+        Ok(())
+    }
+
+    /// Get the length of the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of requests sent.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Request bytes `start..=end`, returning the file length and the bytes.
+    fn fetch(&mut self, start: u64, end: u64) -> io::Result<(u64, Vec<u8>)> {
+        http::retry(self.max_retries, Duration::ZERO, || {
+            self.requests += 1;
+            self.get(start, end)
+        })
+    }
+
+    fn get(&self, start: u64, end: u64) -> io::Result<(u64, Vec<u8>)> {
+        let range = format!("bytes={start}-{end}");
+        let mut request = self
+            .client
+            .request("GET", &self.target)
+            .header("Range", &range);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send()?;
+
+        match response.status() {
+            206 => {}
+            // The whole (empty) file when the range is unsatisfiable.
+            416 if start == 0 => return Ok((0, Vec::new())),
+            200 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "server does not support range requests",
+                ))
+            }
+            _ => return Err(response.error("server")),
+        }
+        // bytes 0-1023/4096
+        let total = response
+            .header("content-range")
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, total)| total.parse().ok())
+            .ok_or_else(|| invalid_data("missing Content-Range"))?;
+        let data = response.body((end - start + 1) as usize)?;
+        Ok((total, data))
+    }
+}
+
+impl Read for RemoteFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.pos < self.block_start || self.pos >= block_end {
+            let end = (self.pos + self.block_size as u64).min(self.len) - 1;
+            let (_, data) = self.fetch(self.pos, end)?;
+            if data.is_empty() {
+                return Err(invalid_data("empty range response"));
+            }
+            self.block = data;
+            self.block_start = self.pos;
+        }
+
+        let offset = (self.pos - self.block_start) as usize;
+        let len = buf.len().min(self.block.len() - offset);
+        buf[..len].copy_from_slice(&self.block[offset..offset + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for RemoteFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::Arc,
+    };
+
+    use super::*;
+    use crate::file::{
+        index::PcapIndex,
+        pcap::{PcapReader, PcapWriter},
+    };
+
+    /// Serve range requests of `data` until the listener is dropped.
+    fn serve(data: Arc<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                respond(&mut stream.unwrap(), &data).unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Answer one range request of `data`.
+    fn respond(stream: &mut (impl Read + Write), data: &[u8]) -> io::Result<()> {
+        let mut reader = BufReader::new(&mut *stream);
+        let mut range = None;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            if let Some(value) = line.strip_prefix("Range: bytes=") {
+                let (start, end) = value.trim().split_once('-').unwrap();
+                range = Some((
+                    start.parse::<usize>().unwrap(),
+                    end.parse::<usize>().unwrap(),
+                ));
+            }
+            line.clear();
+        }
+        let (start, end) = range.unwrap();
+        let end = end.min(data.len() - 1);
+        let body = &data[start..=end];
+        let header = format!(
+            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+             Content-Range: bytes {start}-{end}/{}\r\n\r\n",
+            body.len(),
+            data.len(),
+        );
+        stream.write_all(header.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()
+    }
+
+    #[test]
+    fn remote_range_reads() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for i in 0..100u8 {
+            writer
+                .write_packet(Duration::from_secs(i as u64), &[i; 200])
+                .unwrap();
+        }
+        let data = Arc::new(writer.into_inner().unwrap());
+        let addr = serve(data.clone());
+
+        let mut file = RemoteFile::builder(&format!("http://{addr}/captures/day1.pcap")).unwrap();
+        file.block_size(1000).connect().unwrap();
+        assert_eq!(file.len(), data.len() as u64);
+
+        let mut reader = PcapReader::new(file);
+        let index = PcapIndex::build(&mut reader).unwrap();
+        assert_eq!(index.len(), 100);
+        let requests = reader.get_ref().requests();
+
+        let entry = index.get(42).unwrap();
+        let (_, packet) = reader.packet_at(entry.offset).unwrap().unwrap();
+        assert_eq!(packet, [42; 200]);
+        assert_eq!(reader.get_ref().requests(), requests + 1);
+
+        let scheme_error = |url| RemoteFile::builder(url).unwrap_err().kind();
+        #[cfg(not(feature = "remote-tls"))]
+        assert_eq!(
+            scheme_error("https://example.com/a.pcap"),
+            io::ErrorKind::Unsupported
+        );
+        #[cfg(feature = "remote-tls")]
+        assert!(RemoteFile::builder("https://example.com/a.pcap").is_ok());
+        assert_eq!(
+            scheme_error("ftp://example.com/a.pcap"),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            scheme_error("example.com/a.pcap"),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(RemoteFile::builder("HTTP://example.com/a.pcap").is_ok());
+    }
+
+    #[test]
+    fn remote_oversized_content_length() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let header = "HTTP/1.1 206 Partial Content\r\nContent-Length: 1152921504606846976\r\n\
+                          Content-Range: bytes 0-0/4096\r\n\r\n";
+            stream.write_all(header.as_bytes()).unwrap();
+        });
+
+        let mut file = RemoteFile::builder(&format!("http://{addr}/a.pcap")).unwrap();
+        let error = file.max_retries(0).connect().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "remote-tls")]
+    #[test]
+    fn remote_tls_reads() {
+        use rustls::pki_types::PrivateKeyDer;
+
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = certified.cert.der().clone();
+        let key = PrivateKeyDer::Pkcs8(certified.signing_key.serialize_der().into());
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+        let config = Arc::new(config);
+
+        let data: Arc<Vec<u8>> = Arc::new((0..5000u32).map(|i| i as u8).collect());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served = data.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let connection = rustls::ServerConnection::new(config.clone()).unwrap();
+                let mut stream = rustls::StreamOwned::new(connection, stream.unwrap());
+                // Handshakes with untrusting clients fail.
+                let _ = respond(&mut stream, &served);
+            }
+        });
+
+        let url = format!("https://localhost:{port}/a.pcap");
+        let mut file = RemoteFile::builder(&url).unwrap();
+        let error = file.max_retries(0).connect().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut file = RemoteFile::builder(&url).unwrap();
+        file.root_certificate(cert.to_vec())
+            .unwrap()
+            .block_size(1000)
+            .connect()
+            .unwrap();
+        assert_eq!(file.len(), 5000);
+        let mut read = Vec::new();
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read, *data);
+    }
+}
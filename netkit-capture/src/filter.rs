@@ -0,0 +1,264 @@
+//! Filter expressions in the pcap filter syntax.
+//!
+//! The same expressions select packets in several places: over a
+//! [`PcapIndex`](crate::file::index::PcapIndex) with an
+//! [`IndexFilter`](crate::file::index::filter::IndexFilter), in the kernel
+//! once [compiled](compile_cbpf) to classic BPF, and in the driver once
+//! [compiled](compile_xdp) to an XDP program. They are a subset of
+//! the syntax of tcpdump, whose primitives are:
+//!
+//! | Primitive                         | Matches packets                      |
+//! |-----------------------------------|--------------------------------------|
+//! | `[src\|dst] host ADDR`            | from or to an address                |
+//! | `[src\|dst] net ADDR/LEN`         | from or to a prefix                  |
+//! | `[src\|dst] port N`               | from or to a port                    |
+//! | `[src\|dst] portrange N-M`        | from or to a port in a range         |
+//! | `ip`, `ip6`                       | of IPv4 or IPv6                      |
+//! | `tcp`, `udp`, `icmp`, `icmp6`, `sctp`, `proto N` | of a transport protocol |
+//! | `len OP N`, `greater N`, `less N` | by length on the wire                |
+//!
+//! They combine with `and` (`&&`), `or` (`||`), `not` (`!`) and
+//! parentheses; `OP` is one of `<`, `<=`, `>`, `>=`, `=`, `==`, `!=`.
+
+use std::{net::IpAddr, str::FromStr};
+
+use netkit_packet::prelude::*;
+
+pub mod cbpf;
+pub use cbpf::{compile_cbpf, SockFilter};
+pub mod ebpf;
+pub use ebpf::compile_xdp;
+
+/// Error type for parsing and compiling filters.
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FilterError {
+    /// The expression ended early.
+    #[error("Unexpected end of filter")]
+    UnexpectedEnd,
+
+    /// A token is not valid at its position.
+    #[error("Unexpected token in filter: {0:?}")]
+    UnexpectedToken(String),
+
+    /// An address or prefix is invalid.
+    #[error("Invalid address in filter: {0:?}")]
+    InvalidAddress(String),
+
+    /// A number, port or range is invalid.
+    #[error("Invalid number in filter: {0:?}")]
+    InvalidNumber(String),
+
+    /// The compiled program exceeds the instructions a kernel accepts.
+    #[error("Filter too long: {0} instructions")]
+    TooLong(usize),
+}
+
+/// Direction of an address or port primitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dir {
+    Src,
+    Dst,
+    Any,
+}
+
+/// Comparison of a length primitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// Parsed filter expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Net(Dir, IpAddr, u8),
+    Ports(Dir, u16, u16),
+    Ipv4,
+    Ipv6,
+    Proto(IpProtocol),
+    Len(Cmp, u32),
+}
+
+/// Parse a filter expression.
+pub(crate) fn parse(s: &str) -> Result<Expr, FilterError> {
+    let tokens = tokenize(s);
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    if let Some(token) = parser.peek() {
+        return Err(FilterError::UnexpectedToken(token.to_string()));
+    }
+    Ok(expr)
+}
+
+/// Split an expression into words, parentheses and operators.
+fn tokenize(s: &str) -> Vec<String> {
+    const SPECIAL: &str = "()!&|<>=";
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if SPECIAL.contains(c) {
+            chars.next();
+            let mut token = c.to_string();
+            if let Some(&next) = chars.peek() {
+                let pair = matches!((c, next), ('&', '&') | ('|', '|') | (_, '='));
+                if pair && c != '(' && c != ')' {
+                    token.push(next);
+                    chars.next();
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || SPECIAL.contains(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&str, FilterError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or(FilterError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while matches!(self.peek(), Some("or" | "||")) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.not()?;
+        while matches!(self.peek(), Some("and" | "&&")) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, FilterError> {
+        if matches!(self.peek(), Some("not" | "!")) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some("(") {
+            self.pos += 1;
+            let expr = self.or()?;
+            return match self.next()? {
+                ")" => Ok(expr),
+                token => Err(FilterError::UnexpectedToken(token.to_string())),
+            };
+        }
+        self.primitive()
+    }
+
+    fn primitive(&mut self) -> Result<Expr, FilterError> {
+        let dir = match self.peek() {
+            Some("src") => Dir::Src,
+            Some("dst") => Dir::Dst,
+            _ => Dir::Any,
+        };
+        if dir != Dir::Any {
+            self.pos += 1;
+        }
+
+        let keyword = self.next()?.to_string();
+        match (keyword.as_str(), dir) {
+            ("host", _) => {
+                let addr = self.next()?;
+                let addr: IpAddr = addr
+                    .parse()
+                    .map_err(|_| FilterError::InvalidAddress(addr.to_string()))?;
+                let len = if addr.is_ipv4() { 32 } else { 128 };
+                Ok(Expr::Net(dir, addr, len))
+            }
+            ("net", _) => {
+                let net = self.next()?;
+                let invalid = || FilterError::InvalidAddress(net.to_string());
+                let (addr, len) = net.split_once('/').ok_or_else(invalid)?;
+                let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+                let len: u8 = len.parse().map_err(|_| invalid())?;
+                if len > if addr.is_ipv4() { 32 } else { 128 } {
+                    return Err(invalid());
+                }
+                Ok(Expr::Net(dir, addr, len))
+            }
+            ("port", _) => {
+                let port = number(self.next()?)?;
+                Ok(Expr::Ports(dir, port, port))
+            }
+            ("portrange", _) => {
+                let range = self.next()?;
+                let (low, high) = range
+                    .split_once('-')
+                    .ok_or_else(|| FilterError::InvalidNumber(range.to_string()))?;
+                Ok(Expr::Ports(dir, number(low)?, number(high)?))
+            }
+            ("ip", Dir::Any) if self.peek() == Some("proto") => {
+                self.pos += 1;
+                Ok(Expr::Proto(IpProtocol::from(number::<u8>(self.next()?)?)))
+            }
+            ("proto", Dir::Any) => Ok(Expr::Proto(IpProtocol::from(number::<u8>(self.next()?)?))),
+            ("ip", Dir::Any) => Ok(Expr::Ipv4),
+            ("ip6", Dir::Any) => Ok(Expr::Ipv6),
+            ("tcp", Dir::Any) => Ok(Expr::Proto(IpProtocol::Tcp)),
+            ("udp", Dir::Any) => Ok(Expr::Proto(IpProtocol::Udp)),
+            ("icmp", Dir::Any) => Ok(Expr::Proto(IpProtocol::Icmp)),
+            ("icmp6", Dir::Any) => Ok(Expr::Proto(IpProtocol::Ipv6Icmp)),
+            ("sctp", Dir::Any) => Ok(Expr::Proto(IpProtocol::Sctp)),
+            ("greater", Dir::Any) => Ok(Expr::Len(Cmp::Ge, number(self.next()?)?)),
+            ("less", Dir::Any) => Ok(Expr::Len(Cmp::Le, number(self.next()?)?)),
+            ("len", Dir::Any) => {
+                let cmp = match self.next()? {
+                    "<" => Cmp::Lt,
+                    "<=" => Cmp::Le,
+                    ">" => Cmp::Gt,
+                    ">=" => Cmp::Ge,
+                    "=" | "==" => Cmp::Eq,
+                    "!=" => Cmp::Ne,
+                    token => return Err(FilterError::UnexpectedToken(token.to_string())),
+                };
+                Ok(Expr::Len(cmp, number(self.next()?)?))
+            }
+            _ => Err(FilterError::UnexpectedToken(keyword)),
+        }
+    }
+}
+
+fn number<T: FromStr>(s: &str) -> Result<T, FilterError> {
+    s.parse()
+        .map_err(|_| FilterError::InvalidNumber(s.to_string()))
+}
@@ -0,0 +1,525 @@
+//! Compiler of filter expressions to classic BPF.
+//!
+//! [`compile_cbpf`] turns an expression into a program over Ethernet
+//! frames, independent of any capture, so it can be attached to a socket
+//! opened by the application (`SO_ATTACH_FILTER`) or loaded by an XDP or
+//! tc program that runs classic BPF:
+//!
+//! ```
+//! use netkit_capture::filter::compile_cbpf;
+//!
+//! let program = compile_cbpf("udp and dst port 53").unwrap();
+//! // The format of `tcpdump -dd`
+//! for insn in &program {
+//!     println!("{insn},");
+//! }
+//! ```
+//!
+//! Accepted packets are kept whole, up to [`SNAPLEN`] bytes. Like libpcap,
+//! the program does not look past VLAN tags or IPv6 extension headers, and
+//! only reads the ports of first IPv4 fragments.
+
+use std::{fmt, net::IpAddr};
+
+use netkit_packet::prelude::*;
+
+use super::{parse, Cmp, Dir, Expr, FilterError};
+
+/// Bytes of accepted packets, as in libpcap.
+pub const SNAPLEN: u32 = 262_144;
+
+/// Most instructions a Linux kernel accepts in a program.
+pub const MAX_INSNS: usize = 4096;
+
+/// Instruction of a classic BPF program, laid out as `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SockFilter {
+    /// Operation.
+    pub code: u16,
+    /// Offset of the next instruction if a jump is taken.
+    pub jt: u8,
+    /// Offset of the next instruction if a jump is not taken.
+    pub jf: u8,
+    /// Operand.
+    pub k: u32,
+}
+
+/// Format the instruction as `tcpdump -dd` does.
+impl fmt::Display for SockFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{ {:#04x}, {}, {}, {:#010x} }}",
+            self.code, self.jt, self.jf, self.k
+        )
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl From<SockFilter> for libc::sock_filter {
+    fn from(insn: SockFilter) -> Self {
+        libc::sock_filter {
+            code: insn.code,
+            jt: insn.jt,
+            jf: insn.jf,
+            k: insn.k,
+        }
+    }
+}
+
+// Operations, from `linux/filter.h`.
+const LD_W_ABS: u16 = 0x20;
+const LD_H_ABS: u16 = 0x28;
+const LD_B_ABS: u16 = 0x30;
+const LD_W_LEN: u16 = 0x80;
+const LD_H_IND: u16 = 0x48;
+const LDX_B_MSH: u16 = 0xb1;
+const ALU_AND_K: u16 = 0x54;
+const JMP_JA: u16 = 0x05;
+const JMP_JEQ_K: u16 = 0x15;
+const JMP_JGT_K: u16 = 0x25;
+const JMP_JGE_K: u16 = 0x35;
+const JMP_JSET_K: u16 = 0x45;
+const RET_K: u16 = 0x06;
+
+// Offsets in Ethernet frames.
+const ETH_TYPE: u32 = 12;
+const NETWORK: u32 = 14;
+
+/// Compile a filter expression to a classic BPF program over Ethernet
+/// frames.
+pub fn compile_cbpf(expression: &str) -> Result<Vec<SockFilter>, FilterError> {
+    let expr = parse(expression)?;
+    let mut compiler = Compiler::default();
+    let (accept, reject) = (compiler.label(), compiler.label());
+    compiler.expr(&expr, accept, reject);
+    compiler.place(accept);
+    compiler.ret(SNAPLEN);
+    compiler.place(reject);
+    compiler.ret(0);
+
+    let program = compiler.assemble();
+    if program.len() > MAX_INSNS {
+        return Err(FilterError::TooLong(program.len()));
+    }
+    Ok(program)
+}
+
+type Label = usize;
+
+/// Instruction with symbolic jump targets.
+#[derive(Debug, Clone, Copy)]
+enum Insn {
+    Stmt(u16, u32),
+    Jump(u16, u32, Label, Label),
+    Ja(Label),
+    Place(Label),
+}
+
+#[derive(Debug, Default)]
+struct Compiler {
+    insns: Vec<Insn>,
+    labels: usize,
+}
+
+impl Compiler {
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn place(&mut self, label: Label) {
+        self.insns.push(Insn::Place(label));
+    }
+
+    fn stmt(&mut self, code: u16, k: u32) {
+        self.insns.push(Insn::Stmt(code, k));
+    }
+
+    fn jump(&mut self, code: u16, k: u32, jt: Label, jf: Label) {
+        self.insns.push(Insn::Jump(code, k, jt, jf));
+    }
+
+    fn ret(&mut self, k: u32) {
+        self.stmt(RET_K, k);
+    }
+
+    /// Jump to `t` if the expression holds, to `f` otherwise.
+    fn expr(&mut self, expr: &Expr, t: Label, f: Label) {
+        match expr {
+            Expr::And(a, b) => {
+                let next = self.label();
+                self.expr(a, next, f);
+                self.place(next);
+                self.expr(b, t, f);
+            }
+            Expr::Or(a, b) => {
+                let next = self.label();
+                self.expr(a, t, next);
+                self.place(next);
+                self.expr(b, t, f);
+            }
+            Expr::Not(a) => self.expr(a, f, t),
+            Expr::Ipv4 => self.eth_type(EthType::Ipv4, t, f),
+            Expr::Ipv6 => self.eth_type(EthType::Ipv6, t, f),
+            Expr::Proto(protocol) => {
+                let protocol = u8::from(*protocol) as u32;
+                let (v4, v6) = (self.label(), self.label());
+                self.families(v4, v6, f);
+                self.place(v4);
+                self.stmt(LD_B_ABS, NETWORK + 9);
+                self.jump(JMP_JEQ_K, protocol, t, f);
+                self.place(v6);
+                self.stmt(LD_B_ABS, NETWORK + 6);
+                self.jump(JMP_JEQ_K, protocol, t, f);
+            }
+            Expr::Net(dir, net, len) => self.net(*dir, *net, *len, t, f),
+            Expr::Ports(dir, low, high) => self.ports(*dir, *low, *high, t, f),
+            Expr::Len(cmp, n) => {
+                self.stmt(LD_W_LEN, 0);
+                let (code, t, f) = match cmp {
+                    Cmp::Lt => (JMP_JGE_K, f, t),
+                    Cmp::Le => (JMP_JGT_K, f, t),
+                    Cmp::Gt => (JMP_JGT_K, t, f),
+                    Cmp::Ge => (JMP_JGE_K, t, f),
+                    Cmp::Eq => (JMP_JEQ_K, t, f),
+                    Cmp::Ne => (JMP_JEQ_K, f, t),
+                };
+                self.jump(code, *n, t, f);
+            }
+        }
+    }
+
+    fn eth_type(&mut self, eth_type: EthType, t: Label, f: Label) {
+        self.stmt(LD_H_ABS, ETH_TYPE);
+        self.jump(JMP_JEQ_K, u16::from(eth_type) as u32, t, f);
+    }
+
+    /// Jump to `v4` for IPv4, to `v6` for IPv6, to `f` otherwise.
+    fn families(&mut self, v4: Label, v6: Label, f: Label) {
+        let not_v4 = self.label();
+        self.eth_type(EthType::Ipv4, v4, not_v4);
+        self.place(not_v4);
+        self.jump(JMP_JEQ_K, u16::from(EthType::Ipv6) as u32, v6, f);
+    }
+
+    /// Jump to `t` if the addresses in `dir` are in a prefix.
+    fn net(&mut self, dir: Dir, net: IpAddr, len: u8, t: Label, f: Label) {
+        let (eth_type, src, dst, words) = match net {
+            IpAddr::V4(net) => (EthType::Ipv4, NETWORK + 12, NETWORK + 16, vec![net.into()]),
+            IpAddr::V6(net) => {
+                let words = net
+                    .octets()
+                    .chunks(4)
+                    .map(|w| u32::from_be_bytes(w.try_into().unwrap()))
+                    .collect();
+                (EthType::Ipv6, NETWORK + 8, NETWORK + 24, words)
+            }
+        };
+        let family = self.label();
+        self.eth_type(eth_type, family, f);
+        self.place(family);
+
+        let offsets = match dir {
+            Dir::Src => vec![src],
+            Dir::Dst => vec![dst],
+            Dir::Any => vec![src, dst],
+        };
+        let last = offsets.len() - 1;
+        for (i, offset) in offsets.into_iter().enumerate() {
+            let miss = if i == last { f } else { self.label() };
+            for (j, word) in words.iter().enumerate() {
+                let bits = (len as u32).saturating_sub(32 * j as u32).min(32);
+                if bits == 0 {
+                    continue;
+                }
+                let mask = u32::MAX << (32 - bits);
+                self.stmt(LD_W_ABS, offset + 4 * j as u32);
+                if mask != u32::MAX {
+                    self.stmt(ALU_AND_K, mask);
+                }
+                let hit = self.label();
+                self.jump(JMP_JEQ_K, word & mask, hit, miss);
+                self.place(hit);
+            }
+            self.insns.push(Insn::Ja(t));
+            if i != last {
+                self.place(miss);
+            }
+        }
+    }
+
+    /// Jump to `t` if the ports in `dir` are in a range.
+    fn ports(&mut self, dir: Dir, low: u16, high: u16, t: Label, f: Label) {
+        let (v4, v6) = (self.label(), self.label());
+        self.families(v4, v6, f);
+
+        // IPv4: first fragments only, ports after the options.
+        self.place(v4);
+        self.protocols(NETWORK + 9, f);
+        let first = self.label();
+        self.stmt(LD_H_ABS, NETWORK + 6);
+        self.jump(JMP_JSET_K, 0x1fff, f, first);
+        self.place(first);
+        self.stmt(LDX_B_MSH, NETWORK);
+        self.range(dir, (low, high), (LD_H_IND, NETWORK), t, f);
+
+        // IPv6: ports right after the fixed header.
+        self.place(v6);
+        self.protocols(NETWORK + 6, f);
+        self.range(dir, (low, high), (LD_H_ABS, NETWORK + 40), t, f);
+    }
+
+    /// Fall through if the protocol at `offset` has ports, jump to `f`
+    /// otherwise.
+    fn protocols(&mut self, offset: u32, f: Label) {
+        let ok = self.label();
+        self.stmt(LD_B_ABS, offset);
+        for protocol in [IpProtocol::Tcp, IpProtocol::Udp] {
+            let next = self.label();
+            self.jump(JMP_JEQ_K, u8::from(protocol) as u32, ok, next);
+            self.place(next);
+        }
+        self.jump(JMP_JEQ_K, u8::from(IpProtocol::Sctp) as u32, ok, f);
+        self.place(ok);
+    }
+
+    /// Jump to `t` if a port, loaded with an operation at an offset, is in
+    /// a range.
+    fn range(
+        &mut self,
+        dir: Dir,
+        (low, high): (u16, u16),
+        (load, offset): (u16, u32),
+        t: Label,
+        f: Label,
+    ) {
+        let offsets = match dir {
+            Dir::Src => vec![offset],
+            Dir::Dst => vec![offset + 2],
+            Dir::Any => vec![offset, offset + 2],
+        };
+        let last = offsets.len() - 1;
+        for (i, offset) in offsets.into_iter().enumerate() {
+            let miss = if i == last { f } else { self.label() };
+            self.stmt(load, offset);
+            if low == high {
+                self.jump(JMP_JEQ_K, low as u32, t, miss);
+            } else {
+                let above = self.label();
+                self.jump(JMP_JGE_K, low as u32, above, miss);
+                self.place(above);
+                self.jump(JMP_JGT_K, high as u32, miss, t);
+            }
+            if i != last {
+                self.place(miss);
+            }
+        }
+    }
+
+    /// Resolve the labels, relaying conditional jumps too far for their
+    /// 8-bit offsets through unconditional ones.
+    fn assemble(mut self) -> Vec<SockFilter> {
+        loop {
+            let (insns, labels) = self.layout();
+            let distance = |at: usize, label: Label| labels[label] - insns[at] - 1;
+            let far = self
+                .insns
+                .iter()
+                .enumerate()
+                .find_map(|(i, insn)| match *insn {
+                    Insn::Jump(code, k, jt, jf) => {
+                        let (far_t, far_f) = (distance(i, jt) > 255, distance(i, jf) > 255);
+                        (far_t || far_f).then_some((i, code, k, (jt, far_t), (jf, far_f)))
+                    }
+                    _ => None,
+                });
+            let Some((i, code, k, jt, jf)) = far else {
+                break;
+            };
+
+            let mut relays = Vec::new();
+            let [jt, jf] = [jt, jf].map(|(target, far)| {
+                if !far {
+                    return target;
+                }
+                let relay = self.label();
+                relays.extend([Insn::Place(relay), Insn::Ja(target)]);
+                relay
+            });
+            self.insns[i] = Insn::Jump(code, k, jt, jf);
+            self.insns.splice(i + 1..i + 1, relays);
+        }
+
+        let (insns, labels) = self.layout();
+        let distance = |at: usize, label: Label| labels[label] - insns[at] - 1;
+        self.insns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, insn)| match *insn {
+                Insn::Stmt(code, k) => Some(SockFilter {
+                    code,
+                    jt: 0,
+                    jf: 0,
+                    k,
+                }),
+                Insn::Jump(code, k, jt, jf) => Some(SockFilter {
+                    code,
+                    jt: distance(i, jt) as u8,
+                    jf: distance(i, jf) as u8,
+                    k,
+                }),
+                Insn::Ja(target) => Some(SockFilter {
+                    code: JMP_JA,
+                    jt: 0,
+                    jf: 0,
+                    k: distance(i, target) as u32,
+                }),
+                Insn::Place(_) => None,
+            })
+            .collect()
+    }
+
+    /// Get the positions in the program of the instructions and labels.
+    fn layout(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut insns = Vec::with_capacity(self.insns.len());
+        let mut labels = vec![0; self.labels];
+        let mut at = 0;
+        for insn in &self.insns {
+            insns.push(at);
+            match insn {
+                Insn::Place(label) => labels[*label] = at,
+                _ => at += 1,
+            }
+        }
+        (insns, labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    /// Run a program over a frame as the kernel does, giving the bytes kept.
+    fn run(program: &[SockFilter], frame: &[u8]) -> u32 {
+        let load = |offset: u32, size: usize| {
+            let bytes = frame.get(offset as usize..offset as usize + size)?;
+            Some(bytes.iter().fold(0, |value, &b| value << 8 | b as u32))
+        };
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0);
+        loop {
+            let insn = program[pc];
+            pc += 1;
+            let jump = |taken: bool| if taken { insn.jt } else { insn.jf } as usize;
+            match insn.code {
+                LD_W_ABS | LD_H_ABS | LD_B_ABS | LD_H_IND => {
+                    let size = match insn.code {
+                        LD_W_ABS => 4,
+                        LD_B_ABS => 1,
+                        _ => 2,
+                    };
+                    let offset = insn.k + if insn.code == LD_H_IND { x } else { 0 };
+                    let Some(value) = load(offset, size) else {
+                        return 0;
+                    };
+                    a = value;
+                }
+                LD_W_LEN => a = frame.len() as u32,
+                LDX_B_MSH => x = 4 * (load(insn.k, 1).unwrap_or(0) & 0xf),
+                ALU_AND_K => a &= insn.k,
+                JMP_JA => pc += insn.k as usize,
+                JMP_JEQ_K => pc += jump(a == insn.k),
+                JMP_JGT_K => pc += jump(a > insn.k),
+                JMP_JGE_K => pc += jump(a >= insn.k),
+                JMP_JSET_K => pc += jump(a & insn.k != 0),
+                RET_K => return insn.k,
+                code => panic!("unexpected operation {code:#x}"),
+            }
+        }
+    }
+
+    fn frames() -> Vec<Vec<u8>> {
+        let v4 = |src: [u8; 4], protocol: IpProtocol, payload: &[u8], fragment_offset: u16| {
+            let ipv4 = ipv4!(
+                src: Ipv4Addr::from(src),
+                dst: Ipv4Addr::new(192, 0, 2, 53),
+                protocol: protocol,
+                fragment_offset: fragment_offset,
+                payload: payload
+            );
+            eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                .inner()
+                .clone()
+        };
+        let udp = udp!(src_port: 40000u16, dst_port: 53u16, payload: [1, 2, 3]);
+        let tcp = tcp!(src_port: 40001u16, dst_port: 443u16, payload: &[0; 1000]);
+        let udp6 = udp!(src_port: 5353u16, dst_port: 53u16);
+        let ipv6 = ipv6!(
+            src: "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+            dst: "2001:db8::53".parse::<Ipv6Addr>().unwrap(),
+            next_header: IpProtocol::Udp,
+            payload: udp6.inner()
+        );
+        vec![
+            v4([10, 0, 0, 1], IpProtocol::Udp, udp.inner(), 0),
+            v4([10, 0, 0, 2], IpProtocol::Tcp, tcp.inner(), 0),
+            v4(
+                [172, 16, 0, 1],
+                IpProtocol::Icmp,
+                &[8, 0, 0, 0, 0, 0, 0, 0],
+                0,
+            ),
+            eth!(eth_type: EthType::Ipv6, payload: ipv6.inner())
+                .inner()
+                .clone(),
+            eth!(eth_type: EthType::Arp, payload: [0; 28])
+                .inner()
+                .clone(),
+            // Second fragment, whose payload reads as port 53
+            v4([10, 0, 0, 1], IpProtocol::Udp, udp.inner(), 185),
+        ]
+    }
+
+    #[test]
+    fn compile_and_run_cbpf() {
+        let frames = frames();
+        let matching = |expression: &str| {
+            let program = compile_cbpf(expression).unwrap();
+            let kept: Vec<_> = frames.iter().map(|f| run(&program, f)).collect();
+            assert!(kept.iter().all(|&k| k == 0 || k == SNAPLEN));
+            (0..frames.len())
+                .filter(|&i| kept[i] != 0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching("udp and dst port 53"), [0, 3]);
+        assert_eq!(matching("udp"), [0, 3, 5]);
+        assert_eq!(matching("src net 10.0.0.0/8"), [0, 1, 5]);
+        assert_eq!(matching("host 2001:db8::53"), [3]);
+        assert_eq!(matching("net 2001:db8::/32 and src port 5353"), [3]);
+        assert_eq!(matching("portrange 400-500 or icmp"), [1, 2]);
+        assert_eq!(matching("not ip and not ip6"), [4]);
+        assert_eq!(matching("ip proto 1 || greater 1000"), [1, 2]);
+        assert_eq!(matching("len != 45 && !ip6 && less 100"), [2, 4]);
+
+        // Far jumps are relayed
+        let ports: Vec<_> = (1..=60).map(|port| format!("port {port}")).collect();
+        let expression = format!("({}) and host 192.0.2.53", ports.join(" or "));
+        let program = compile_cbpf(&expression).unwrap();
+        assert!(program.len() > 255);
+        assert_eq!(matching(&expression), [0]);
+        assert_eq!(
+            program[0].to_string(),
+            "{ 0x28, 0, 0, 0x0000000c }".to_string()
+        );
+
+        let ports: Vec<_> = (1..=300).map(|port| format!("port {port}")).collect();
+        assert!(matches!(
+            compile_cbpf(&ports.join(" or ")),
+            Err(FilterError::TooLong(_))
+        ));
+        assert_eq!(compile_cbpf("udp and"), Err(FilterError::UnexpectedEnd));
+    }
+}
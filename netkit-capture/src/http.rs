@@ -0,0 +1,575 @@
+//! HTTP/1.1 client shared by the remote files of this crate and the HTTP
+//! exporters and alert sinks of `netkit`.
+//!
+//! A [`Client`] sends one request per connection and reads response bodies
+//! framed by `Content-Length`, the chunked transfer coding or the end of the
+//! connection, up to a limit given by the caller. Plain HTTP needs no
+//! dependencies; HTTPS needs the `remote-tls` feature, verifying servers
+//! against the Mozilla root certificates with rustls.
+//!
+//! This is not a general-purpose client, and not part of the public API.
+
+#[cfg(feature = "remote-tls")]
+use std::sync::{Arc, OnceLock};
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Default timeout of connections, reads and writes.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest status line and headers accepted from a server.
+pub const MAX_HEAD_LENGTH: u64 = 64 * 1024;
+
+/// Longest body of an error response kept in the error message.
+const MAX_ERROR_BODY_LENGTH: usize = 4096;
+
+/// HTTP client of one server
+#[derive(Debug)]
+pub struct Client {
+    host: String,
+    https: bool,
+    timeout: Duration,
+    #[cfg(feature = "remote-tls")]
+    roots: rustls::RootCertStore,
+    #[cfg(feature = "remote-tls")]
+    tls: OnceLock<Arc<rustls::ClientConfig>>,
+}
+
+impl Client {
+    /// Create a client of the server at `host` (e.g. `127.0.0.1:8080`),
+    /// over plain HTTP.
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            https: false,
+            timeout: DEFAULT_TIMEOUT,
+            #[cfg(feature = "remote-tls")]
+            roots: webpki_roots::TLS_SERVER_ROOTS.iter().cloned().collect(),
+            #[cfg(feature = "remote-tls")]
+            tls: OnceLock::new(),
+        }
+    }
+
+    /// Create a client of the server of an `http://` or `https://` URL,
+    /// returning it with the request target of the URL.
+    ///
+    /// Other schemes, and HTTPS without the `remote-tls` feature, are
+    /// [`Unsupported`](io::ErrorKind::Unsupported); URLs without a scheme or
+    /// host are invalid.
+    pub fn from_url(url: &str) -> io::Result<(Self, String)> {
+        let (scheme, rest) = url.split_once("://").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("missing URL scheme, expected http:// or https://: {url}"),
+            )
+        })?;
+        let https = scheme.eq_ignore_ascii_case("https");
+        if https && cfg!(not(feature = "remote-tls")) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("HTTPS needs the remote-tls feature: {url}"),
+            ));
+        }
+        if !https && !scheme.eq_ignore_ascii_case("http") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported URL scheme {scheme}, expected http:// or https://: {url}"),
+            ));
+        }
+        let (host, target) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "missing host"));
+        }
+        let mut client = Self::new(host);
+        client.https = https;
+        Ok((client, target.to_string()))
+    }
+
+    /// Set the timeout of connections, reads and writes.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Trust a DER-encoded root certificate besides the Mozilla ones.
+    #[cfg(feature = "remote-tls")]
+    pub fn root_certificate(&mut self, der: impl Into<Vec<u8>>) -> io::Result<&mut Self> {
+        self.roots
+            .add(der.into().into())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.tls = OnceLock::new();
+        Ok(self)
+    }
+
+    /// Start a request of `target` (e.g. `/hooks/netkit`).
+    pub fn request<'a>(&'a self, method: &'a str, target: &'a str) -> Request<'a> {
+        Request {
+            client: self,
+            method,
+            target,
+            headers: String::new(),
+            body: None,
+        }
+    }
+
+    /// Connect to the server.
+    fn connect(&self) -> io::Result<Box<dyn Stream>> {
+        let addr = if self.host.contains(':') {
+            self.host.clone()
+        } else if self.https {
+            format!("{}:443", self.host)
+        } else {
+            format!("{}:80", self.host)
+        };
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        match self.https {
+            #[cfg(feature = "remote-tls")]
+            true => Ok(Box::new(self.tls_stream(stream)?)),
+            _ => Ok(Box::new(stream)),
+        }
+    }
+
+    /// Start a TLS session over `stream`, verifying the server certificate
+    /// against the host name.
+    #[cfg(feature = "remote-tls")]
+    fn tls_stream(
+        &self,
+        stream: TcpStream,
+    ) -> io::Result<rustls::StreamOwned<rustls::ClientConnection, TcpStream>> {
+        let config = match self.tls.get() {
+            Some(config) => config.clone(),
+            None => {
+                let provider = Arc::new(rustls::crypto::ring::default_provider());
+                let config = rustls::ClientConfig::builder_with_provider(provider)
+                    .with_safe_default_protocol_versions()
+                    .map_err(io::Error::other)?
+                    .with_root_certificates(self.roots.clone())
+                    .with_no_client_auth();
+                self.tls.get_or_init(|| Arc::new(config)).clone()
+            }
+        };
+        let name = match self.host.rsplit_once(':') {
+            Some((name, port)) if port.parse::<u16>().is_ok() => name,
+            _ => &self.host,
+        };
+        let name = rustls::pki_types::ServerName::try_from(name.trim_matches(['[', ']']))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .to_owned();
+        let connection = rustls::ClientConnection::new(config, name).map_err(io::Error::other)?;
+        Ok(rustls::StreamOwned::new(connection, stream))
+    }
+}
+
+/// HTTP request, sent with [`send`](Self::send)
+#[must_use]
+pub struct Request<'a> {
+    client: &'a Client,
+    method: &'a str,
+    target: &'a str,
+    headers: String,
+    body: Option<&'a [u8]>,
+}
+
+impl<'a> Request<'a> {
+    /// Add a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let _ = write!(self.headers, "{name}: {value}\r\n");
+        self
+    }
+
+    /// Set the body, sent with its `Content-Length`.
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Send the request on a new connection and read the head of the
+    /// response.
+    pub fn send(self) -> io::Result<Response> {
+        let mut stream = self.client.connect()?;
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n{}",
+            self.method, self.target, self.client.host, self.headers,
+        );
+        if let Some(body) = self.body {
+            let _ = write!(head, "Content-Length: {}\r\n", body.len());
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        stream.write_all(head.as_bytes())?;
+        if let Some(body) = self.body {
+            stream.write_all(body)?;
+        }
+        stream.flush()?;
+        Response::read(BufReader::new(stream), self.method == "HEAD")
+    }
+}
+
+/// HTTP response whose body is not read yet
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    reader: BufReader<Box<dyn Stream>>,
+    bodiless: bool,
+}
+
+impl Response {
+    /// Read the status line and headers.
+    fn read(mut reader: BufReader<Box<dyn Stream>>, head_request: bool) -> io::Result<Self> {
+        let mut head = (&mut reader).take(MAX_HEAD_LENGTH);
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+        let status: u16 = line
+            .strip_prefix("HTTP/1.")
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| invalid_data("invalid HTTP status line"))?;
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if head.read_line(&mut line)? == 0 {
+                let message = if head.limit() == 0 {
+                    "HTTP response head too long"
+                } else {
+                    "truncated HTTP response head"
+                };
+                return Err(invalid_data(message));
+            }
+            if line == "\r\n" || line == "\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        Ok(Self {
+            status,
+            headers,
+            reader,
+            bodiless: head_request || status < 200 || status == 204 || status == 304,
+        })
+    }
+
+    /// Get the status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Get the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Read the body, failing with [`InvalidData`](io::ErrorKind::InvalidData)
+    /// before reading or allocating more than `limit` bytes, and with
+    /// [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) if it is truncated.
+    pub fn body(self, limit: usize) -> io::Result<Vec<u8>> {
+        if self.bodiless {
+            return Ok(Vec::new());
+        }
+        let chunked = self
+            .header("transfer-encoding")
+            .is_some_and(|coding| coding.to_ascii_lowercase().ends_with("chunked"));
+        if chunked {
+            return self.chunked_body(limit);
+        }
+
+        match self.header("content-length") {
+            Some(length) => {
+                let length: u64 = length
+                    .parse()
+                    .map_err(|_| invalid_data("invalid Content-Length"))?;
+                // The length comes from the server, so it is only trusted up
+                // to the limit before allocating.
+                if length > limit as u64 {
+                    return Err(invalid_data("HTTP body exceeds the limit"));
+                }
+                let mut body = Vec::with_capacity(length as usize);
+                self.reader.take(length).read_to_end(&mut body)?;
+                if body.len() as u64 != length {
+                    return Err(truncated());
+                }
+                Ok(body)
+            }
+            None => {
+                let mut body = Vec::new();
+                self.reader.take(limit as u64 + 1).read_to_end(&mut body)?;
+                if body.len() > limit {
+                    return Err(invalid_data("HTTP body exceeds the limit"));
+                }
+                Ok(body)
+            }
+        }
+    }
+
+    fn chunked_body(mut self, limit: usize) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            (&mut self.reader).take(1024).read_line(&mut line)?;
+            if !line.ends_with('\n') {
+                return Err(truncated());
+            }
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size =
+                usize::from_str_radix(size, 16).map_err(|_| invalid_data("invalid chunk size"))?;
+            if size == 0 {
+                break;
+            }
+            if size > limit - body.len() {
+                return Err(invalid_data("HTTP body exceeds the limit"));
+            }
+            let start = body.len();
+            (&mut self.reader)
+                .take(size as u64)
+                .read_to_end(&mut body)?;
+            if body.len() - start != size {
+                return Err(truncated());
+            }
+            let mut crlf = [0; 2];
+            self.reader.read_exact(&mut crlf)?;
+            if crlf != *b"\r\n" {
+                return Err(invalid_data("invalid chunk"));
+            }
+        }
+        // Skip the trailers.
+        let mut trailers = (&mut self.reader).take(MAX_HEAD_LENGTH);
+        loop {
+            line.clear();
+            if trailers.read_line(&mut line)? == 0 {
+                return Err(truncated());
+            }
+            if line == "\r\n" || line == "\n" {
+                return Ok(body);
+            }
+        }
+    }
+
+    /// Turn an unexpected status into an error of `peer` (e.g. `webhook`),
+    /// holding the start of the body, which usually explains it.
+    ///
+    /// Server errors may succeed if retried (see [`is_transient`]), other
+    /// statuses are [`InvalidInput`](io::ErrorKind::InvalidInput).
+    pub fn error(self, peer: &str) -> io::Error {
+        let status = self.status;
+        let kind = if status >= 500 {
+            io::ErrorKind::Other
+        } else {
+            io::ErrorKind::InvalidInput
+        };
+        let body = self.body(MAX_ERROR_BODY_LENGTH).unwrap_or_default();
+        let body = String::from_utf8_lossy(&body);
+        let body = body.trim();
+        if body.is_empty() {
+            io::Error::new(kind, format!("{peer} returned {status}"))
+        } else {
+            io::Error::new(kind, format!("{peer} returned {status}: {body}"))
+        }
+    }
+}
+
+/// Whether a failed request may succeed if retried: connection failures and
+/// server errors, but not rejected requests.
+pub fn is_transient(e: &io::Error) -> bool {
+    e.kind() != io::ErrorKind::InvalidInput
+}
+
+/// Run `request`, retrying [transient](is_transient) failures up to
+/// `max_retries` times, after `backoff` doubled at every retry.
+pub fn retry<T>(
+    max_retries: u32,
+    backoff: Duration,
+    mut request: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut backoff = backoff;
+    let mut retries = 0;
+    loop {
+        match request() {
+            Ok(response) => return Ok(response),
+            Err(e) if retries < max_retries && is_transient(&e) => {
+                retries += 1;
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Connection to a server, plain or over TLS.
+trait Stream: Read + Write {}
+
+impl<S: Read + Write> Stream for S {}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated HTTP response")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Answer one request per response, returning the requests.
+    fn serve(responses: Vec<&'static [u8]>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut length = 0;
+                while reader.read_line(&mut request).unwrap() > 2 {
+                    if let Some(value) = request
+                        .lines()
+                        .last()
+                        .unwrap()
+                        .strip_prefix("Content-Length: ")
+                    {
+                        length = value.parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(std::str::from_utf8(&body).unwrap());
+                // Clients stop reading oversized heads.
+                let _ = stream.write_all(response);
+                requests.push(request);
+            }
+            requests
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn http_bodies() {
+        let (addr, server) = serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3;ext=1\r\nhel\r\n2\r\nlo\r\n0\r\nTrailer: x\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\n\r\nhello",
+            b"HTTP/1.1 204 No Content\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nhello",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4294967296\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffff\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+            b"HTTP/1.1 200 OK\r\n\r\nhello, world",
+        ]);
+        let client = Client::new(addr);
+        let get = || client.request("GET", "/a").send().unwrap();
+        for _ in 0..3 {
+            assert_eq!(get().body(64).unwrap(), b"hello");
+        }
+        assert_eq!(get().body(64).unwrap(), b"");
+        assert_eq!(
+            get().body(64).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                get().body(64).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+        assert_eq!(
+            get().body(64).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            get().body(5).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests[0],
+            format!(
+                "GET /a HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                client.host
+            )
+        );
+    }
+
+    #[test]
+    fn http_errors_and_retries() {
+        let head = [b'a'; MAX_HEAD_LENGTH as usize];
+        let head: &'static [u8] = Vec::leak([b"HTTP/1.1 200 OK\r\nX: ".as_slice(), &head].concat());
+        let (addr, server) = serve(vec![
+            b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 13\r\n\r\nUnknown table",
+            b"SSH-2.0-OpenSSH_9.6\r\n",
+            head,
+        ]);
+        let client = Client::new(addr);
+        let post = || {
+            let response = client
+                .request("POST", "/hooks")
+                .header("Content-Type", "application/json")
+                .body(b"{}")
+                .send()?;
+            match response.status() {
+                200..=299 => Ok(response.status()),
+                _ => Err(response.error("webhook")),
+            }
+        };
+
+        assert_eq!(retry(3, Duration::from_millis(1), post).unwrap(), 201);
+        let e = retry(3, Duration::from_millis(1), post).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(e.to_string(), "webhook returned 404: Unknown table");
+        for _ in 0..2 {
+            assert_eq!(post().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 5);
+        assert!(requests[1].starts_with("POST /hooks HTTP/1.1\r\n"));
+        assert!(requests[1].ends_with(
+            "Content-Type: application/json\r\nContent-Length: 2\r\n\
+             Connection: close\r\n\r\n{}"
+        ));
+
+        let scheme_error = |url| Client::from_url(url).unwrap_err().kind();
+        #[cfg(not(feature = "remote-tls"))]
+        assert_eq!(
+            scheme_error("https://example.com/a"),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            scheme_error("ftp://example.com/a"),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(scheme_error("example.com/a"), io::ErrorKind::InvalidInput);
+        assert_eq!(scheme_error("http:///a"), io::ErrorKind::InvalidInput);
+        let (client, target) = Client::from_url("HTTP://example.com").unwrap();
+        assert_eq!(
+            (client.host.as_str(), target.as_str()),
+            ("example.com", "/")
+        );
+    }
+}
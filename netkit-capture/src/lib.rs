@@ -1 +1,19 @@
+pub mod channel;
+pub mod clock_sync;
+pub mod compare;
+pub mod control;
+pub mod dedup;
 pub mod file;
+pub mod filter;
+#[doc(hidden)]
+pub mod http;
+pub mod mirror;
+pub mod numa;
+pub mod pool;
+pub mod privilege;
+pub mod reorder;
+pub mod sanitize;
+pub mod shutdown;
+pub mod stats;
+pub mod timestamp;
+pub mod tuning;
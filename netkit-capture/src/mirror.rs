@@ -0,0 +1,468 @@
+//! Kernel mirroring of interface traffic.
+//!
+//! Capturing on a production interface directly competes with its traffic
+//! and sees only what reaches the host stack. A [`Mirror`] instead has the
+//! kernel copy the frames of an interface to another one, e.g. an `ifb` or
+//! `veth` device the capture socket listens on, with a `u32` filter
+//! matching every frame and a `mirred` action on its `clsact` qdisc: what
+//!
+//! ```sh
+//! tc qdisc add dev eth0 clsact
+//! tc filter add dev eth0 ingress prio 1 u32 match u32 0 0 action mirred egress mirror dev ifb0 continue
+//! tc filter add dev eth0 egress prio 1 u32 match u32 0 0 action mirred egress mirror dev ifb0 continue
+//! ```
+//!
+//! does by hand. The returned [`ActiveMirror`] removes the filters, and the
+//! qdisc if it added it, when stopped or dropped. Unlike `matchall`, `u32`
+//! is in every kernel.
+//!
+//! ```no_run
+//! use netkit_capture::mirror::{Mirror, MirrorDirection};
+//!
+//! let mirror = Mirror::new("eth0", "ifb0")
+//!     .direction(MirrorDirection::Ingress)
+//!     .start()
+//!     .unwrap();
+//! // Capture on ifb0...
+//! mirror.stop().unwrap();
+//! ```
+//!
+//! Mirroring needs `CAP_NET_ADMIN`. The filters pass frames on to the next
+//! filters of the interface, so they do not change its behavior.
+
+use std::io;
+
+#[cfg(target_os = "linux")]
+use crate::privilege::{self, Capability};
+
+/// Default priority of the mirroring filters, first of the interface.
+pub const DEFAULT_PRIORITY: u16 = 1;
+
+/// Direction of the traffic mirrored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MirrorDirection {
+    /// Frames received by the interface.
+    Ingress,
+    /// Frames sent by the interface.
+    Egress,
+    /// Frames received and sent by the interface.
+    #[default]
+    Both,
+}
+
+impl MirrorDirection {
+    /// Whether the direction includes received frames.
+    pub fn ingress(&self) -> bool {
+        matches!(self, MirrorDirection::Ingress | MirrorDirection::Both)
+    }
+
+    /// Whether the direction includes sent frames.
+    pub fn egress(&self) -> bool {
+        matches!(self, MirrorDirection::Egress | MirrorDirection::Both)
+    }
+}
+
+/// Mirroring of an interface to another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[must_use]
+pub struct Mirror {
+    source: String,
+    target: String,
+    direction: MirrorDirection,
+    priority: u16,
+}
+
+impl Mirror {
+    /// Mirror the frames of interface `source` to interface `target`, sent
+    /// out of `target`.
+    pub fn new(source: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            target: target.into(),
+            direction: MirrorDirection::default(),
+            priority: DEFAULT_PRIORITY,
+        }
+    }
+
+    /// Set the direction of the frames mirrored.
+    pub fn direction(mut self, direction: MirrorDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Set the priority of the filters, to place them among the existing
+    /// filters of `source`; lower is earlier.
+    pub fn priority(mut self, priority: u16) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Start mirroring.
+    ///
+    /// The `clsact` qdisc of `source` is added if it has none. Everything
+    /// added is removed if a step fails.
+    #[cfg(target_os = "linux")]
+    pub fn start(&self) -> io::Result<ActiveMirror> {
+        let explain = |err| privilege::explain(err, Capability::NetAdmin, "mirroring traffic");
+        let source = linux::index(&self.source)?;
+        let target = linux::index(&self.target)?;
+        let mut mirror = ActiveMirror {
+            netlink: linux::Netlink::open()?,
+            ifindex: source,
+            priority: self.priority,
+            qdisc: false,
+            filters: Vec::new(),
+        };
+
+        let add = linux::add_clsact(source);
+        match mirror.netlink.request(add) {
+            Ok(()) => mirror.qdisc = true,
+            Err(err) if err.raw_os_error() == Some(libc::EEXIST) => {}
+            Err(err) => return Err(explain(err)),
+        }
+        let parents = [
+            (self.direction.ingress(), linux::PARENT_INGRESS),
+            (self.direction.egress(), linux::PARENT_EGRESS),
+        ];
+        for (_, parent) in parents.into_iter().filter(|(on, _)| *on) {
+            let add = linux::add_mirror(source, parent, self.priority, target);
+            mirror.netlink.request(add).map_err(explain)?;
+            mirror.filters.push(parent);
+        }
+        Ok(mirror)
+    }
+
+    /// Start mirroring.
+    #[cfg(not(target_os = "linux"))]
+    pub fn start(&self) -> io::Result<ActiveMirror> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "mirroring is only supported on Linux",
+        ))
+    }
+}
+
+/// Mirroring in place, undone when dropped.
+#[derive(Debug)]
+pub struct ActiveMirror {
+    #[cfg(target_os = "linux")]
+    netlink: linux::Netlink,
+    #[cfg(target_os = "linux")]
+    ifindex: u32,
+    #[cfg(target_os = "linux")]
+    priority: u16,
+    #[cfg(target_os = "linux")]
+    qdisc: bool,
+    #[cfg(target_os = "linux")]
+    filters: Vec<u32>,
+}
+
+impl ActiveMirror {
+    /// Stop mirroring, returning the first error removing the filters or
+    /// the qdisc.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.teardown()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn teardown(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for parent in std::mem::take(&mut self.filters) {
+            let delete = linux::delete_filter(self.ifindex, parent, self.priority);
+            result = result.and(self.netlink.request(delete));
+        }
+        if std::mem::take(&mut self.qdisc) {
+            let delete = linux::delete_clsact(self.ifindex);
+            result = result.and(self.netlink.request(delete));
+        }
+        result
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn teardown(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ActiveMirror {
+    fn drop(&mut self) {
+        let _ = self.teardown();
+    }
+}
+
+#[cfg(any(target_os = "linux", test))]
+mod linux {
+    #[cfg(target_os = "linux")]
+    use std::{
+        ffi::CString,
+        io,
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    };
+
+    // Values of linux/rtnetlink.h and linux/pkt_sched.h, not exported by
+    // every libc version.
+    const RTM_NEWQDISC: u16 = 36;
+    const RTM_DELQDISC: u16 = 37;
+    const RTM_NEWTFILTER: u16 = 44;
+    const RTM_DELTFILTER: u16 = 45;
+    const NLM_F_REQUEST: u16 = 0x1;
+    const NLM_F_ACK: u16 = 0x4;
+    const NLM_F_EXCL: u16 = 0x200;
+    const NLM_F_CREATE: u16 = 0x400;
+    #[cfg(target_os = "linux")]
+    const NLMSG_ERROR: u16 = 0x2;
+    const TCA_KIND: u16 = 1;
+    const TCA_OPTIONS: u16 = 2;
+    const TCA_U32_SEL: u16 = 5;
+    const TCA_U32_ACT: u16 = 7;
+    const TC_U32_TERMINAL: u8 = 1;
+    const TCA_ACT_KIND: u16 = 1;
+    const TCA_ACT_OPTIONS: u16 = 2;
+    const TCA_MIRRED_PARMS: u16 = 2;
+    const TCA_EGRESS_MIRROR: i32 = 2;
+    const TC_ACT_UNSPEC: i32 = -1;
+    const ETH_P_ALL: u16 = 0x0003;
+
+    const CLSACT_HANDLE: u32 = 0xffff_0000;
+    pub(super) const PARENT_INGRESS: u32 = 0xffff_fff2;
+    pub(super) const PARENT_EGRESS: u32 = 0xffff_fff3;
+    const TC_H_CLSACT: u32 = 0xffff_fff1;
+
+    /// Length of a netlink header.
+    const HEADER: usize = 16;
+
+    /// Netlink message with a `tcmsg` header and attributes.
+    pub(super) struct Message {
+        buf: Vec<u8>,
+        nests: Vec<usize>,
+    }
+
+    impl Message {
+        fn new(kind: u16, flags: u16, ifindex: u32, handle: u32, parent: u32, info: u32) -> Self {
+            let mut buf = vec![0; HEADER];
+            buf[4..6].copy_from_slice(&kind.to_ne_bytes());
+            buf[6..8].copy_from_slice(&(NLM_F_REQUEST | NLM_F_ACK | flags).to_ne_bytes());
+            // family and padding
+            buf.extend_from_slice(&[0; 4]);
+            for field in [ifindex, handle, parent, info] {
+                buf.extend_from_slice(&field.to_ne_bytes());
+            }
+            Self {
+                buf,
+                nests: Vec::new(),
+            }
+        }
+
+        fn attr(&mut self, kind: u16, data: &[u8]) -> &mut Self {
+            self.buf
+                .extend_from_slice(&(4 + data.len() as u16).to_ne_bytes());
+            self.buf.extend_from_slice(&kind.to_ne_bytes());
+            self.buf.extend_from_slice(data);
+            self.buf.resize(self.buf.len().next_multiple_of(4), 0);
+            self
+        }
+
+        fn begin(&mut self, kind: u16) -> &mut Self {
+            self.nests.push(self.buf.len());
+            self.attr(kind, &[])
+        }
+
+        fn end(&mut self) -> &mut Self {
+            let start = self.nests.pop().expect("unbalanced nested attribute");
+            let len = (self.buf.len() - start) as u16;
+            self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+            self
+        }
+
+        /// Get the bytes of the message with a sequence number.
+        pub(super) fn finish(&self, seq: u32) -> Vec<u8> {
+            let mut buf = self.buf.clone();
+            let len = buf.len() as u32;
+            buf[0..4].copy_from_slice(&len.to_ne_bytes());
+            buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+            buf
+        }
+    }
+
+    /// Request adding the `clsact` qdisc to an interface.
+    pub(super) fn add_clsact(ifindex: u32) -> Message {
+        let flags = NLM_F_CREATE | NLM_F_EXCL;
+        let mut message = Message::new(RTM_NEWQDISC, flags, ifindex, CLSACT_HANDLE, TC_H_CLSACT, 0);
+        message.attr(TCA_KIND, b"clsact\0");
+        message
+    }
+
+    /// Request removing the `clsact` qdisc of an interface.
+    pub(super) fn delete_clsact(ifindex: u32) -> Message {
+        Message::new(RTM_DELQDISC, 0, ifindex, CLSACT_HANDLE, TC_H_CLSACT, 0)
+    }
+
+    /// Request adding a filter mirroring all frames under `parent` to
+    /// interface `target`.
+    pub(super) fn add_mirror(ifindex: u32, parent: u32, priority: u16, target: u32) -> Message {
+        let flags = NLM_F_CREATE | NLM_F_EXCL;
+        let info = filter_info(priority);
+        let mut message = Message::new(RTM_NEWTFILTER, flags, ifindex, 0, parent, info);
+
+        // struct tc_mirred: index, capab, action, refcnt, bindcnt, eaction,
+        // ifindex
+        let mut parms = Vec::with_capacity(28);
+        for field in [0, 0, TC_ACT_UNSPEC, 0, 0, TCA_EGRESS_MIRROR] {
+            parms.extend_from_slice(&field.to_ne_bytes());
+        }
+        parms.extend_from_slice(&target.to_ne_bytes());
+        // struct tc_u32_sel with a single key, matching anything
+        let mut sel = vec![0; 32];
+        sel[0] = TC_U32_TERMINAL;
+        sel[2] = 1;
+
+        message
+            .attr(TCA_KIND, b"u32\0")
+            .begin(TCA_OPTIONS)
+            .attr(TCA_U32_SEL, &sel)
+            .begin(TCA_U32_ACT)
+            .begin(1)
+            .attr(TCA_ACT_KIND, b"mirred\0")
+            .begin(TCA_ACT_OPTIONS)
+            .attr(TCA_MIRRED_PARMS, &parms)
+            .end()
+            .end()
+            .end()
+            .end();
+        message
+    }
+
+    /// Request removing the filters of a priority under `parent`.
+    pub(super) fn delete_filter(ifindex: u32, parent: u32, priority: u16) -> Message {
+        Message::new(RTM_DELTFILTER, 0, ifindex, 0, parent, filter_info(priority))
+    }
+
+    /// Priority and protocol of a filter, as in `tcm_info`.
+    fn filter_info(priority: u16) -> u32 {
+        (priority as u32) << 16 | ETH_P_ALL.to_be() as u32
+    }
+
+    /// Get the index of an interface.
+    #[cfg(target_os = "linux")]
+    pub(super) fn index(name: &str) -> io::Result<u32> {
+        let c_name = CString::new(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+        // SAFETY: `c_name` is a valid C string.
+        match unsafe { libc::if_nametoindex(c_name.as_ptr()) } {
+            0 => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no such interface: {name}"),
+            )),
+            index => Ok(index),
+        }
+    }
+
+    /// Route netlink socket.
+    #[cfg(target_os = "linux")]
+    #[derive(Debug)]
+    pub(super) struct Netlink {
+        fd: OwnedFd,
+        seq: u32,
+    }
+
+    #[cfg(target_os = "linux")]
+    impl Netlink {
+        pub(super) fn open() -> io::Result<Self> {
+            // SAFETY: socket has no preconditions.
+            let fd = unsafe {
+                libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                    libc::NETLINK_ROUTE,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` is a new descriptor owned by nothing else.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            Ok(Self { fd, seq: 0 })
+        }
+
+        /// Send a request and wait for its acknowledgement.
+        pub(super) fn request(&mut self, message: Message) -> io::Result<()> {
+            self.seq = self.seq.wrapping_add(1);
+            let buf = message.finish(self.seq);
+            // SAFETY: `buf` is valid for reads of its length.
+            let sent = unsafe {
+                libc::send(
+                    self.fd.as_raw_fd(),
+                    buf.as_ptr() as *const libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if sent < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut buf = vec![0u8; 8192];
+            loop {
+                // SAFETY: `buf` is valid for writes of its length.
+                let len = unsafe {
+                    libc::recv(
+                        self.fd.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                        0,
+                    )
+                };
+                if len < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let mut rest = &buf[..len as usize];
+                while rest.len() >= HEADER {
+                    let u32_at =
+                        |at: usize| u32::from_ne_bytes(rest[at..at + 4].try_into().unwrap());
+                    let size = (u32_at(0) as usize).clamp(HEADER, rest.len());
+                    let kind = u16::from_ne_bytes([rest[4], rest[5]]);
+                    if kind == NLMSG_ERROR && u32_at(8) == self.seq && size >= HEADER + 4 {
+                        return match u32_at(HEADER) as i32 {
+                            0 => Ok(()),
+                            err => Err(io::Error::from_raw_os_error(-err)),
+                        };
+                    }
+                    rest = &rest[size.next_multiple_of(4).min(rest.len())..];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirror_messages() {
+        let message = linux::add_mirror(2, linux::PARENT_INGRESS, DEFAULT_PRIORITY, 7).finish(9);
+        let u16_at = |at: usize| u16::from_ne_bytes([message[at], message[at + 1]]);
+        let u32_at = |at: usize| u32::from_ne_bytes(message[at..at + 4].try_into().unwrap());
+
+        // Header, then tcmsg
+        assert_eq!(u32_at(0) as usize, message.len());
+        assert_eq!((u16_at(4), u16_at(6), u32_at(8)), (44, 0x605, 9));
+        assert_eq!(u32_at(20), 2);
+        assert_eq!(u32_at(28), 0xffff_fff2);
+        assert_eq!(u32_at(32), 1 << 16 | 0x0003u16.to_be() as u32);
+
+        // Kind, then options nesting the mirred parameters
+        assert_eq!((u16_at(36), u16_at(38)), (8, 1));
+        assert_eq!(&message[40..44], b"u32\0");
+        assert_eq!(u16_at(44) as usize, message.len() - 44);
+        assert_eq!((u16_at(50), message[52], message[54]), (5, 1, 1));
+        // Continue, mirror out of the target
+        let end = message.len();
+        assert_eq!(u32_at(end - 20) as i32, -1);
+        assert_eq!((u32_at(end - 8), u32_at(end - 4)), (2, 7));
+
+        assert_eq!(linux::delete_clsact(2).finish(1).len(), 36);
+        assert!(MirrorDirection::Both.ingress() && MirrorDirection::Both.egress());
+        assert!(!MirrorDirection::Ingress.egress());
+    }
+}
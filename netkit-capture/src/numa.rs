@@ -0,0 +1,299 @@
+//! NUMA-aware worker placement.
+//!
+//! On multi-socket machines, a worker reading packets allocated on another
+//! node pays for every cache miss with a trip across the interconnect, which
+//! quickly limits throughput. Keeping each worker on a fixed node and giving
+//! it buffers from a pool local to that node avoids this traffic.
+//!
+//! [`Topology::detect`] reads the nodes from sysfs, [`Topology::place`]
+//! spreads workers over them, and [`NumaPools`] keeps a [`PacketPool`] per
+//! node. Linux allocates memory on the node of the thread first touching
+//! it, so [`NumaPools::preallocate`] faults the buffers in from a thread
+//! pinned to each node.
+//!
+//! ```no_run
+//! use std::thread;
+//!
+//! use netkit_capture::numa::{pin_current_thread, NumaPools, Topology};
+//!
+//! let topology = Topology::detect();
+//! let pools = NumaPools::new(&topology, 2048);
+//! pools.preallocate(&topology, 1024);
+//!
+//! let handles: Vec<_> = topology
+//!     .place(4)
+//!     .into_iter()
+//!     .map(|placement| {
+//!         let pool = pools.pool(placement.node).clone();
+//!         thread::spawn(move || {
+//!             pin_current_thread(&placement.cpus).unwrap();
+//!             let buf = pool.get();
+//!             // ... fill and analyze packets
+//!         })
+//!     })
+//!     .collect();
+//! ```
+
+use std::{fs, io, path::Path, thread};
+
+use crate::pool::PacketPool;
+
+/// Directory where Linux exposes the NUMA nodes.
+pub const SYSFS_NODE_PATH: &str = "/sys/devices/system/node";
+
+/// A NUMA node and its CPUs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumaNode {
+    /// Node number.
+    pub id: usize,
+    /// CPUs of the node.
+    pub cpus: Vec<usize>,
+}
+
+/// NUMA topology of the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topology {
+    nodes: Vec<NumaNode>,
+}
+
+impl Topology {
+    /// Create a topology from its nodes.
+    ///
+    /// Nodes without CPUs are dropped.
+    pub fn new(nodes: impl IntoIterator<Item = NumaNode>) -> Self {
+        let mut nodes: Vec<_> = nodes.into_iter().filter(|n| !n.cpus.is_empty()).collect();
+        nodes.sort_by_key(|n| n.id);
+        Self { nodes }
+    }
+
+    /// Create a topology with a single node holding all CPUs.
+    pub fn uniform() -> Self {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new([NumaNode {
+            id: 0,
+            cpus: (0..cpus).collect(),
+        }])
+    }
+
+    /// Detect the topology of the machine.
+    ///
+    /// Falls back to [`Topology::uniform`] where NUMA information is not
+    /// available.
+    pub fn detect() -> Self {
+        Self::read_from(SYSFS_NODE_PATH)
+            .ok()
+            .filter(|t| !t.nodes.is_empty())
+            .unwrap_or_else(Self::uniform)
+    }
+
+    /// Read the topology from a sysfs node directory.
+    pub fn read_from(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut nodes = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(id) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("node"))
+                .and_then(|id| id.parse().ok())
+            else {
+                continue;
+            };
+            let cpulist = fs::read_to_string(entry.path().join("cpulist"))?;
+            let cpus = parse_cpulist(&cpulist)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid cpulist"))?;
+            nodes.push(NumaNode { id, cpus });
+        }
+        Ok(Self::new(nodes))
+    }
+
+    /// Get the nodes.
+    #[inline]
+    pub fn nodes(&self) -> &[NumaNode] {
+        &self.nodes
+    }
+
+    /// Get the node with the given number.
+    pub fn node(&self, id: usize) -> Option<&NumaNode> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    /// Place `workers` workers on the nodes.
+    ///
+    /// Workers are spread round-robin over the nodes, and over the CPUs of
+    /// each node, so that they share CPUs only once every CPU has a worker.
+    pub fn place(&self, workers: usize) -> Vec<WorkerPlacement> {
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+        (0..workers)
+            .map(|worker| {
+                let node = &self.nodes[worker % self.nodes.len()];
+                let slot = worker / self.nodes.len();
+                WorkerPlacement {
+                    worker,
+                    node: node.id,
+                    cpus: vec![node.cpus[slot % node.cpus.len()]],
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where a worker runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkerPlacement {
+    /// Worker number.
+    pub worker: usize,
+    /// Node of the worker.
+    pub node: usize,
+    /// CPUs the worker is pinned to.
+    pub cpus: Vec<usize>,
+}
+
+/// Packet pools local to each NUMA node.
+#[derive(Clone, Debug)]
+pub struct NumaPools {
+    pools: Vec<(usize, PacketPool)>,
+}
+
+impl NumaPools {
+    /// Create a pool per node of buffers with the given initial capacity.
+    pub fn new(topology: &Topology, capacity: usize) -> Self {
+        Self {
+            pools: topology
+                .nodes()
+                .iter()
+                .map(|node| (node.id, PacketPool::new(capacity)))
+                .collect(),
+        }
+    }
+
+    /// Get the pool of a node.
+    ///
+    /// Unknown nodes get the pool of the first node.
+    pub fn pool(&self, node: usize) -> &PacketPool {
+        self.pools
+            .iter()
+            .find(|(id, _)| *id == node)
+            .map_or(&self.pools[0].1, |(_, pool)| pool)
+    }
+
+    /// Allocate `count` buffers per node, touching them from a thread pinned
+    /// to the node so their pages are node-local.
+    pub fn preallocate(&self, topology: &Topology, count: usize) {
+        thread::scope(|scope| {
+            for node in topology.nodes() {
+                let pool = self.pool(node.id);
+                scope.spawn(move || {
+                    // Without pinning, buffers are still allocated, only not
+                    // necessarily on the node.
+                    let _ = pin_current_thread(&node.cpus);
+                    let bufs: Vec<_> = (0..count)
+                        .map(|_| {
+                            let mut buf = pool.get();
+                            let capacity = buf.capacity();
+                            buf.resize(capacity, 0);
+                            buf
+                        })
+                        .collect();
+                    drop(bufs);
+                });
+            }
+        });
+    }
+}
+
+/// Pin the calling thread to the given CPUs.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: `cpu_set_t` is a plain bit set, valid when zeroed, and the
+    // CPU numbers are checked against its size before `CPU_SET`.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        let max = 8 * std::mem::size_of::<libc::cpu_set_t>();
+        for &cpu in cpus {
+            if cpu >= max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("CPU {cpu} out of range"),
+                ));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Pin the calling thread to the given CPUs.
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread affinity is only supported on Linux",
+    ))
+}
+
+/// Parse a Linux CPU list such as `0-3,8,10-11`.
+pub fn parse_cpulist(list: &str) -> Option<Vec<usize>> {
+    let list = list.trim();
+    let mut cpus = Vec::new();
+    if list.is_empty() {
+        return Some(cpus);
+    }
+    for range in list.split(',') {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+                if start > end {
+                    return None;
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numa_cpulist_and_placement() {
+        assert_eq!(parse_cpulist("0-3,8\n"), Some(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpulist(""), Some(vec![]));
+        assert_eq!(parse_cpulist("3-1"), None);
+
+        let topology = Topology::new([
+            NumaNode {
+                id: 1,
+                cpus: vec![2, 3],
+            },
+            NumaNode {
+                id: 0,
+                cpus: vec![0, 1],
+            },
+            NumaNode {
+                id: 2,
+                cpus: vec![],
+            },
+        ]);
+        assert_eq!(topology.nodes().len(), 2);
+
+        let placement = topology.place(5);
+        let nodes: Vec<_> = placement.iter().map(|p| p.node).collect();
+        let cpus: Vec<_> = placement.iter().map(|p| p.cpus[0]).collect();
+        assert_eq!(nodes, [0, 1, 0, 1, 0]);
+        assert_eq!(cpus, [0, 2, 1, 3, 0]);
+
+        let pools = NumaPools::new(&topology, 64);
+        pools.preallocate(&topology, 2);
+        assert_eq!(pools.pool(1).allocated(), 2);
+        assert_eq!(pools.pool(1).available(), 2);
+    }
+}
@@ -0,0 +1,259 @@
+//! Pool of reusable packet buffers.
+//!
+//! At millions of packets per second, allocating a buffer per packet and
+//! freeing it once analyzed keeps the allocator busy. A [`PacketPool`] hands
+//! out buffers that return to the pool when their last handle is dropped, so
+//! steady-state capture allocates nothing.
+//!
+//! A reader fills a [`PacketMut`], then [`freezes`](PacketMut::freeze) it
+//! into a [`Packet`]: a cheap, reference-counted handle that can be cloned
+//! and sent to worker threads without copying the data.
+//!
+//! ```
+//! use netkit_capture::pool::PacketPool;
+//!
+//! let pool = PacketPool::new(1514);
+//!
+//! let mut buf = pool.get();
+//! buf.extend_from_slice(&[1, 2, 3]);
+//! let packet = buf.freeze();
+//! let shared = packet.clone();
+//! assert_eq!(&*shared, &[1, 2, 3]);
+//!
+//! drop((packet, shared));
+//! assert_eq!(pool.available(), 1);
+//! ```
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Default number of free buffers kept by a pool.
+pub const DEFAULT_MAX_FREE: usize = 4096;
+
+#[derive(Debug)]
+struct Shared {
+    free: Mutex<Vec<Vec<u8>>>,
+    capacity: usize,
+    max_free: usize,
+    allocated: AtomicUsize,
+}
+
+impl Shared {
+    fn put(&self, mut buf: Vec<u8>) {
+        buf.clear();
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_free {
+            free.push(buf);
+        }
+    }
+}
+
+/// Pool of reusable packet buffers.
+///
+/// Cloning a pool gives another handle to the same buffers.
+#[derive(Clone, Debug)]
+pub struct PacketPool {
+    shared: Arc<Shared>,
+}
+
+impl PacketPool {
+    /// Create a pool of buffers with the given initial capacity.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_max_free(capacity, DEFAULT_MAX_FREE)
+    }
+
+    /// Create a pool keeping at most `max_free` free buffers; buffers
+    /// released beyond that are deallocated.
+    pub fn with_max_free(capacity: usize, max_free: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                free: Mutex::new(Vec::new()),
+                capacity,
+                max_free,
+                allocated: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Allocate `count` buffers up front.
+    pub fn preallocate(&self, count: usize) {
+        for _ in 0..count {
+            self.shared.allocated.fetch_add(1, Ordering::Relaxed);
+            self.shared.put(Vec::with_capacity(self.shared.capacity));
+        }
+    }
+
+    /// Get an empty buffer, reusing a free one if any.
+    pub fn get(&self) -> PacketMut {
+        let buf = self.shared.free.lock().unwrap().pop();
+        let buf = buf.unwrap_or_else(|| {
+            self.shared.allocated.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(self.shared.capacity)
+        });
+
+        PacketMut {
+            buf,
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Get a buffer holding a copy of `data`.
+    pub fn copy_from_slice(&self, data: &[u8]) -> Packet {
+        let mut buf = self.get();
+        buf.extend_from_slice(data);
+        buf.freeze()
+    }
+
+    /// Get the number of free buffers.
+    pub fn available(&self) -> usize {
+        self.shared.free.lock().unwrap().len()
+    }
+
+    /// Get the number of buffers allocated by the pool so far.
+    pub fn allocated(&self) -> usize {
+        self.shared.allocated.load(Ordering::Relaxed)
+    }
+}
+
+/// Writable pooled buffer, returned to its pool when dropped.
+#[derive(Debug)]
+pub struct PacketMut {
+    buf: Vec<u8>,
+    shared: Arc<Shared>,
+}
+
+impl PacketMut {
+    /// Convert the buffer into a shared, read-only packet.
+    pub fn freeze(mut self) -> Packet {
+        let buf = std::mem::take(&mut self.buf);
+        Packet {
+            inner: Arc::new(Frozen {
+                buf,
+                shared: self.shared.clone(),
+            }),
+        }
+    }
+}
+
+impl Deref for PacketMut {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PacketMut {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PacketMut {
+    fn drop(&mut self) {
+        // Frozen buffers were taken, leaving an unallocated vector.
+        if self.buf.capacity() != 0 {
+            self.shared.put(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Frozen {
+    buf: Vec<u8>,
+    shared: Arc<Shared>,
+}
+
+impl Drop for Frozen {
+    fn drop(&mut self) {
+        self.shared.put(std::mem::take(&mut self.buf));
+    }
+}
+
+/// Shared, read-only pooled packet.
+///
+/// Cloning a packet only increments a reference count; the buffer returns
+/// to its pool when the last clone is dropped.
+#[derive(Clone, Debug)]
+pub struct Packet {
+    inner: Arc<Frozen>,
+}
+
+impl Packet {
+    /// Get the number of handles to this packet.
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+}
+
+impl Deref for Packet {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner.buf
+    }
+}
+
+impl AsRef<[u8]> for Packet {
+    fn as_ref(&self) -> &[u8] {
+        &self.inner.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn pool_reuse() {
+        let pool = PacketPool::with_max_free(64, 2);
+
+        let ptr = {
+            let packet = pool.copy_from_slice(&[1; 32]);
+            packet.as_ptr()
+        };
+        assert_eq!(pool.available(), 1);
+
+        // The buffer is reused, empty.
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(pool.allocated(), 1);
+        drop(buf);
+
+        // At most 2 free buffers are kept.
+        let bufs: Vec<_> = (0..4).map(|_| pool.get()).collect();
+        assert_eq!(pool.allocated(), 4);
+        drop(bufs);
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    fn pool_shared_across_threads() {
+        let pool = PacketPool::new(64);
+        pool.preallocate(8);
+
+        let packets: Vec<_> = (0..8u8).map(|i| pool.copy_from_slice(&[i; 16])).collect();
+        let clones: Vec<_> = packets.to_vec();
+        assert_eq!(packets[3].ref_count(), 2);
+        let handles: Vec<_> = clones
+            .into_iter()
+            .map(|packet| thread::spawn(move || packet.iter().map(|b| *b as u32).sum::<u32>()))
+            .collect();
+
+        let sums: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(sums, (0..8).map(|i| i * 16).collect::<Vec<_>>());
+        assert_eq!(pool.available(), 0);
+
+        drop(packets);
+        assert_eq!(pool.available(), 8);
+        assert_eq!(pool.allocated(), 8);
+    }
+}
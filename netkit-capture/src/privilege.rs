@@ -0,0 +1,176 @@
+//! Privileges of capture and injection.
+//!
+//! Raw sockets need `CAP_NET_RAW`, and some socket options `CAP_NET_ADMIN`.
+//! Without them the kernel answers with a bare `EPERM`, which tells users
+//! nothing about what to do. Tools check up front with [`require`], or turn
+//! the error into one naming the missing capability with [`explain`], and
+//! may fall back to an unprivileged way with [`with_fallback`]:
+//!
+//! ```
+//! use std::io;
+//!
+//! use netkit_capture::privilege::{self, Capability};
+//!
+//! let err = io::Error::from(io::ErrorKind::PermissionDenied);
+//! let err = privilege::explain(err, Capability::NetRaw, "opening a raw socket");
+//! assert!(err.to_string().contains("CAP_NET_RAW"));
+//! ```
+
+use std::{fmt, io};
+
+/// Linux capability needed by network tools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Use raw and packet sockets.
+    NetRaw,
+    /// Configure interfaces and privileged socket options.
+    NetAdmin,
+}
+
+impl Capability {
+    /// Get the bit of the capability in capability sets.
+    pub fn bit(&self) -> u32 {
+        match self {
+            Capability::NetAdmin => 12,
+            Capability::NetRaw => 13,
+        }
+    }
+
+    /// Get the name of the capability, as given to `setcap`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::NetAdmin => "cap_net_admin",
+            Capability::NetRaw => "cap_net_raw",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::NetAdmin => write!(f, "CAP_NET_ADMIN"),
+            Capability::NetRaw => write!(f, "CAP_NET_RAW"),
+        }
+    }
+}
+
+/// Error type for missing privileges.
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[error(
+    "{operation} needs {capability}: run as root, or grant it with \
+     `sudo setcap {}=eip <program>`",
+    capability.name()
+)]
+pub struct PrivilegeError {
+    /// The missing capability.
+    pub capability: Capability,
+    /// What was attempted.
+    pub operation: String,
+}
+
+impl From<PrivilegeError> for io::Error {
+    fn from(err: PrivilegeError) -> Self {
+        io::Error::new(io::ErrorKind::PermissionDenied, err)
+    }
+}
+
+/// Whether the process has a capability in its effective set.
+///
+/// Outside Linux, this is whether the process runs as root.
+#[cfg(target_os = "linux")]
+pub fn has_capability(capability: Capability) -> io::Result<bool> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    let effective = effective_capabilities(&status).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "no CapEff in /proc/self/status")
+    })?;
+    Ok(effective & (1 << capability.bit()) != 0)
+}
+
+/// Whether the process has a capability in its effective set.
+///
+/// Outside Linux, this is whether the process runs as root.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn has_capability(_capability: Capability) -> io::Result<bool> {
+    // SAFETY: geteuid has no preconditions.
+    Ok(unsafe { libc::geteuid() } == 0)
+}
+
+/// Parse the effective capability set from `/proc/<pid>/status`.
+#[cfg(any(target_os = "linux", test))]
+fn effective_capabilities(status: &str) -> Option<u64> {
+    let line = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(line.trim(), 16).ok()
+}
+
+/// Check that the process has a capability before attempting `operation`.
+///
+/// A capability which cannot be checked is assumed present, leaving the
+/// kernel to refuse the operation.
+#[cfg(unix)]
+pub fn require(capability: Capability, operation: &str) -> Result<(), PrivilegeError> {
+    match has_capability(capability) {
+        Ok(false) => Err(PrivilegeError {
+            capability,
+            operation: operation.into(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Explain a permission error of `operation` by the missing capability.
+///
+/// Other errors are returned unchanged.
+pub fn explain(err: io::Error, capability: Capability, operation: &str) -> io::Error {
+    if err.kind() != io::ErrorKind::PermissionDenied {
+        return err;
+    }
+    PrivilegeError {
+        capability,
+        operation: operation.into(),
+    }
+    .into()
+}
+
+/// Run a privileged operation, or `fallback` if it is not permitted.
+///
+/// E.g. a prober opens a raw socket, or an unprivileged ICMP datagram
+/// socket without `CAP_NET_RAW`.
+pub fn with_fallback<T>(
+    privileged: impl FnOnce() -> io::Result<T>,
+    fallback: impl FnOnce() -> io::Result<T>,
+) -> io::Result<T> {
+    match privileged() {
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => fallback(),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn privilege_errors() {
+        let status = "Name:\tcat\nCapInh:\t0000000000000000\nCapEff:\t0000000000003000\n";
+        let effective = effective_capabilities(status).unwrap();
+        assert_ne!(effective & (1 << Capability::NetRaw.bit()), 0);
+        assert_ne!(effective & (1 << Capability::NetAdmin.bit()), 0);
+        assert_eq!(effective_capabilities("Name:\tcat\n"), None);
+        #[cfg(unix)]
+        has_capability(Capability::NetRaw).unwrap();
+
+        let denied = || io::Error::from_raw_os_error(libc::EPERM);
+        let err = explain(denied(), Capability::NetAdmin, "setting a busy poll budget");
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            err.to_string(),
+            "setting a busy poll budget needs CAP_NET_ADMIN: run as root, or grant it \
+             with `sudo setcap cap_net_admin=eip <program>`"
+        );
+        let other = explain(io::ErrorKind::NotFound.into(), Capability::NetRaw, "x");
+        assert_eq!(other.kind(), io::ErrorKind::NotFound);
+
+        assert_eq!(with_fallback(|| Err(denied()), || Ok(1)).unwrap(), 1);
+        assert!(with_fallback(|| Err::<u8, _>(io::ErrorKind::NotFound.into()), || Ok(1)).is_err());
+    }
+}
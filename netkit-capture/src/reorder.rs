@@ -0,0 +1,231 @@
+//! Reordering of slightly out-of-order packet streams.
+//!
+//! Multi-queue (fanout) captures merge packets from several queues, so
+//! packets may arrive slightly out of timestamp order. A [`ReorderBuffer`]
+//! holds packets for a time horizon and releases them in timestamp order,
+//! which is required by analyses that assume sequential input.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::reorder::ReorderBuffer;
+//!
+//! let mut buffer = ReorderBuffer::new(Duration::from_millis(10));
+//! buffer.push(Duration::from_millis(5), "b");
+//! buffer.push(Duration::from_millis(3), "a");
+//! assert_eq!(buffer.pop(), None);
+//!
+//! buffer.push(Duration::from_millis(20), "c");
+//! assert_eq!(buffer.pop(), Some((Duration::from_millis(3), "a")));
+//! assert_eq!(buffer.pop(), Some((Duration::from_millis(5), "b")));
+//! assert_eq!(buffer.pop(), None);
+//!
+//! assert_eq!(buffer.flush().count(), 1);
+//! ```
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    time::Duration,
+};
+
+use netkit_packet::utils::memory::MemoryAccount;
+
+/// A packet held by the buffer.
+#[derive(Debug)]
+struct Entry<P> {
+    ts: Duration,
+    order: u64,
+    packet: P,
+}
+
+impl<P> PartialEq for Entry<P> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.ts, self.order) == (other.ts, other.order)
+    }
+}
+
+impl<P> Eq for Entry<P> {}
+
+impl<P> PartialOrd for Entry<P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P> Ord for Entry<P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.ts, self.order).cmp(&(other.ts, other.order))
+    }
+}
+
+/// Reorder buffer
+///
+/// Packets are released once the newest timestamp seen is at least the
+/// horizon ahead of them. Packets with equal timestamps keep their arrival
+/// order. A packet arriving later than the horizon allows is still released
+/// in order with the remaining packets, but may follow packets already
+/// released with a greater timestamp; such packets are counted by
+/// [`late`](ReorderBuffer::late).
+///
+/// A buffer given a [`MemoryAccount`] charges the size of its held packets
+/// to it, heap data of the packets excluded, and releases packets before
+/// the horizon while the budget is exceeded.
+#[derive(Debug)]
+pub struct ReorderBuffer<P> {
+    horizon: Duration,
+    heap: BinaryHeap<Reverse<Entry<P>>>,
+    newest: Duration,
+    released: Option<Duration>,
+    order: u64,
+    late: u64,
+    memory: Option<MemoryAccount>,
+}
+
+impl<P> ReorderBuffer<P> {
+    /// Bytes charged per held packet.
+    const ENTRY_SIZE: usize = std::mem::size_of::<Entry<P>>();
+
+    /// Create a new reorder buffer with the given time horizon.
+    pub fn new(horizon: Duration) -> Self {
+        Self {
+            horizon,
+            heap: BinaryHeap::new(),
+            newest: Duration::ZERO,
+            released: None,
+            order: 0,
+            late: 0,
+            memory: None,
+        }
+    }
+
+    /// Charge the memory of the held packets to an account.
+    pub fn memory(&mut self, mut account: MemoryAccount) -> &mut Self {
+        account.set(self.heap.len() * Self::ENTRY_SIZE);
+        self.memory = Some(account);
+        self
+    }
+
+    /// Get the time horizon.
+    pub fn horizon(&self) -> Duration {
+        self.horizon
+    }
+
+    /// Get the number of packets held.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Whether no packet is held.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Get the number of packets that arrived behind an already released
+    /// packet.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    /// Add a packet.
+    pub fn push(&mut self, ts: Duration, packet: P) {
+        if self.released.is_some_and(|released| ts < released) {
+            self.late += 1;
+        }
+
+        self.newest = self.newest.max(ts);
+        self.heap.push(Reverse(Entry {
+            ts,
+            order: self.order,
+            packet,
+        }));
+        self.order += 1;
+        if let Some(account) = &mut self.memory {
+            account.reserve(Self::ENTRY_SIZE);
+        }
+    }
+
+    /// Release the oldest packet if it is out of the horizon, or if the
+    /// memory budget is exceeded.
+    pub fn pop(&mut self) -> Option<(Duration, P)> {
+        let Reverse(oldest) = self.heap.peek()?;
+        let due = self.newest.saturating_sub(oldest.ts) >= self.horizon;
+        let over = self.memory.as_ref().is_some_and(|a| a.is_over());
+        if !due && !over {
+            return None;
+        }
+
+        self.pop_oldest(!due)
+    }
+
+    /// Release all held packets in timestamp order.
+    ///
+    /// Call this at the end of the stream.
+    pub fn flush(&mut self) -> impl Iterator<Item = (Duration, P)> + '_ {
+        std::iter::from_fn(|| self.pop_oldest(false))
+    }
+
+    /// Release the oldest packet, counting an eviction if `evict`.
+    fn pop_oldest(&mut self, evict: bool) -> Option<(Duration, P)> {
+        let Reverse(entry) = self.heap.pop()?;
+        match &mut self.memory {
+            Some(account) if evict => account.evict(Self::ENTRY_SIZE),
+            Some(account) => account.release(Self::ENTRY_SIZE),
+            None => (),
+        }
+        self.released = Some(entry.ts);
+        Some((entry.ts, entry.packet))
+    }
+}
+
+impl<P> Iterator for ReorderBuffer<P> {
+    type Item = (Duration, P);
+
+    /// Release packets out of the horizon, see [`ReorderBuffer::pop`].
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::utils::memory::{MemoryBudget, Subsystem};
+
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn reorder_buffer() {
+        let mut buffer = ReorderBuffer::new(ms(10));
+        for (ts, packet) in [(0, 0), (4, 2), (2, 1), (4, 3), (9, 4), (15, 5)] {
+            buffer.push(ms(ts), packet);
+        }
+
+        let released: Vec<_> = buffer.by_ref().map(|(_, p)| p).collect();
+        assert_eq!(released, [0, 1, 2, 3]);
+        assert_eq!(buffer.len(), 2);
+
+        buffer.push(ms(1), 6);
+        assert_eq!(buffer.late(), 1);
+
+        let rest: Vec<_> = buffer.flush().map(|(_, p)| p).collect();
+        assert_eq!(rest, [6, 4, 5]);
+        assert!(buffer.is_empty());
+
+        // Packets are released early while the budget is exceeded
+        let budget = MemoryBudget::new(2 * std::mem::size_of::<Entry<u8>>());
+        let mut buffer = ReorderBuffer::new(ms(10));
+        buffer.memory(budget.account(Subsystem::Reorder));
+        for (ts, packet) in [(2, 1), (0, 0), (4, 2)] {
+            buffer.push(ms(ts), packet);
+        }
+        assert_eq!(buffer.pop(), Some((ms(0), 0)));
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(budget.usage().get(Subsystem::Reorder).evictions, 1);
+        assert_eq!(buffer.flush().count(), 2);
+        assert_eq!(budget.used(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+//! Sanitization of corrupt packet timestamps.
+//!
+//! Buggy capture appliances stamp packets with zero, with dates far in the
+//! future, or with a clock that steps backwards, and time-based analyses
+//! then report absurd durations and rates. A [`TimestampSanitizer`] detects
+//! these [`Anomaly`]s in a stream of timestamps and applies a
+//! [`TimestampPolicy`] to each kind, counting what it saw and did. Readers
+//! apply one with `sanitize_timestamps`, e.g.
+//! [`PcapReader::sanitize_timestamps`](crate::file::pcap::PcapReader::sanitize_timestamps).
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::sanitize::TimestampSanitizer;
+//! use netkit_packet::utils::clock::ManualClock;
+//!
+//! let clock = ManualClock::new(Duration::from_secs(2_000_000_000));
+//! let mut sanitizer = TimestampSanitizer::new();
+//! let secs = Duration::from_secs;
+//!
+//! assert_eq!(sanitizer.sanitize_with(&clock, secs(1_000)), Some(secs(1_000)));
+//! // A zero timestamp is clamped to the last valid one
+//! assert_eq!(sanitizer.sanitize_with(&clock, Duration::ZERO), Some(secs(1_000)));
+//! // The clock steps 500 s back: later timestamps are shifted to follow on
+//! assert_eq!(sanitizer.sanitize_with(&clock, secs(500)), Some(secs(1_000)));
+//! assert_eq!(sanitizer.sanitize_with(&clock, secs(501)), Some(secs(1_001)));
+//! assert_eq!(sanitizer.stats().corrected, 2);
+//! ```
+
+use std::time::Duration;
+
+use netkit_packet::utils::clock::{Clock, SystemClock};
+
+/// Default time after the current time past which a timestamp is corrupt.
+pub const DEFAULT_MAX_FUTURE: Duration = Duration::from_secs(86400);
+
+/// Default time a timestamp may go back before it is a backward jump.
+pub const DEFAULT_MAX_BACKWARD: Duration = Duration::from_secs(1);
+
+/// Kind of corrupt timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Anomaly {
+    /// The timestamp is within the first second of the epoch.
+    Zero,
+    /// The timestamp is further ahead of the current time than allowed.
+    Future,
+    /// The timestamp is further behind the last valid one than allowed.
+    Backward,
+}
+
+/// What to do with a corrupt timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimestampPolicy {
+    /// Keep the timestamp, only counting it.
+    Keep,
+    /// Drop the packet.
+    Drop,
+    /// Replace the timestamp with the last valid one.
+    Clamp,
+    /// Replace the timestamp with the last valid one and shift the later
+    /// timestamps by the same offset, undoing a step of the clock.
+    Rebase,
+}
+
+/// Counters of a [`TimestampSanitizer`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SanitizerStats {
+    /// Number of timestamps checked.
+    pub checked: u64,
+    /// Number of zero timestamps.
+    pub zero: u64,
+    /// Number of far-future timestamps.
+    pub future: u64,
+    /// Number of backward jumps.
+    pub backward: u64,
+    /// Number of timestamps replaced.
+    pub corrected: u64,
+    /// Number of packets dropped.
+    pub dropped: u64,
+}
+
+/// Timestamp sanitizer
+///
+/// Timestamps are checked after the offsets of earlier [`Rebase`]s are
+/// applied, against the current time and the last valid timestamp. By default zero and
+/// far-future timestamps are clamped, and backward jumps rebased. A
+/// corrupt timestamp with no valid one before it to clamp or rebase to is
+/// kept.
+///
+/// [`Rebase`]: TimestampPolicy::Rebase
+#[derive(Clone, Debug)]
+pub struct TimestampSanitizer {
+    max_future: Duration,
+    max_backward: Duration,
+    zero: TimestampPolicy,
+    future: TimestampPolicy,
+    backward: TimestampPolicy,
+    /// Last valid timestamp, in nanoseconds.
+    last: Option<i128>,
+    /// Offset added to the timestamps, in nanoseconds.
+    offset: i128,
+    stats: SanitizerStats,
+}
+
+impl Default for TimestampSanitizer {
+    fn default() -> Self {
+        Self {
+            max_future: DEFAULT_MAX_FUTURE,
+            max_backward: DEFAULT_MAX_BACKWARD,
+            zero: TimestampPolicy::Clamp,
+            future: TimestampPolicy::Clamp,
+            backward: TimestampPolicy::Rebase,
+            last: None,
+            offset: 0,
+            stats: SanitizerStats::default(),
+        }
+    }
+}
+
+impl TimestampSanitizer {
+    /// Create a sanitizer with the default policies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the policy of an anomaly.
+    pub fn policy(&mut self, anomaly: Anomaly, policy: TimestampPolicy) -> &mut Self {
+        match anomaly {
+            Anomaly::Zero => self.zero = policy,
+            Anomaly::Future => self.future = policy,
+            Anomaly::Backward => self.backward = policy,
+        }
+        self
+    }
+
+    /// Set the time after the current time past which a timestamp is
+    /// corrupt.
+    pub fn max_future(&mut self, max_future: Duration) -> &mut Self {
+        self.max_future = max_future;
+        self
+    }
+
+    /// Set the time a timestamp may go back before it is a backward jump,
+    /// e.g. to allow for the reordering of multi-queue captures.
+    pub fn max_backward(&mut self, max_backward: Duration) -> &mut Self {
+        self.max_backward = max_backward;
+        self
+    }
+
+    /// Get the counters.
+    pub fn stats(&self) -> SanitizerStats {
+        self.stats
+    }
+
+    /// Sanitize a timestamp, checking for future ones against the system
+    /// clock. Returns `None` if the packet is to be dropped.
+    pub fn sanitize(&mut self, ts: Duration) -> Option<Duration> {
+        self.sanitize_with(&SystemClock, ts)
+    }
+
+    /// Sanitize a timestamp, checking for future ones against `clock`.
+    /// Returns `None` if the packet is to be dropped.
+    pub fn sanitize_with(&mut self, clock: &impl Clock, ts: Duration) -> Option<Duration> {
+        self.stats.checked += 1;
+        let shifted = ts.as_nanos() as i128 + self.offset;
+        let anomaly = if ts < Duration::from_secs(1) {
+            Some(Anomaly::Zero)
+        } else if shifted > clock.now().saturating_add(self.max_future).as_nanos() as i128 {
+            Some(Anomaly::Future)
+        } else if self
+            .last
+            .is_some_and(|last| shifted + (self.max_backward.as_nanos() as i128) < last)
+        {
+            Some(Anomaly::Backward)
+        } else {
+            None
+        };
+        let Some(anomaly) = anomaly else {
+            self.last = Some(self.last.map_or(shifted, |last| last.max(shifted)));
+            return Some(from_nanos(shifted));
+        };
+
+        let policy = match anomaly {
+            Anomaly::Zero => {
+                self.stats.zero += 1;
+                self.zero
+            }
+            Anomaly::Future => {
+                self.stats.future += 1;
+                self.future
+            }
+            Anomaly::Backward => {
+                self.stats.backward += 1;
+                self.backward
+            }
+        };
+        match (policy, self.last) {
+            (TimestampPolicy::Drop, _) => {
+                self.stats.dropped += 1;
+                None
+            }
+            (TimestampPolicy::Keep, _) | (_, None) => Some(from_nanos(shifted)),
+            (TimestampPolicy::Clamp, Some(last)) => {
+                self.stats.corrected += 1;
+                Some(from_nanos(last))
+            }
+            (TimestampPolicy::Rebase, Some(last)) => {
+                self.stats.corrected += 1;
+                self.offset += last - shifted;
+                Some(from_nanos(last))
+            }
+        }
+    }
+}
+
+/// Convert nanoseconds to a timestamp, saturating at zero.
+fn from_nanos(nanos: i128) -> Duration {
+    let nanos = nanos.max(0) as u128;
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::utils::clock::ManualClock;
+
+    use super::*;
+
+    #[test]
+    fn sanitize_policies() {
+        let clock = ManualClock::new(Duration::from_secs(10_000));
+        let secs = Duration::from_secs;
+
+        let mut sanitizer = TimestampSanitizer::new();
+        sanitizer.policy(Anomaly::Backward, TimestampPolicy::Clamp);
+        // Nothing to clamp to yet
+        assert_eq!(
+            sanitizer.sanitize_with(&clock, Duration::ZERO),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(100)), Some(secs(100)));
+        assert_eq!(
+            sanitizer.sanitize_with(&clock, secs(200_000)),
+            Some(secs(100))
+        );
+        // Within the allowed reordering
+        let ts = Duration::from_millis(99_500);
+        assert_eq!(sanitizer.sanitize_with(&clock, ts), Some(ts));
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(50)), Some(secs(100)));
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(101)), Some(secs(101)));
+        assert_eq!(
+            sanitizer.stats(),
+            SanitizerStats {
+                checked: 6,
+                zero: 1,
+                future: 1,
+                backward: 1,
+                corrected: 2,
+                dropped: 0,
+            }
+        );
+
+        let mut sanitizer = TimestampSanitizer::new();
+        sanitizer
+            .policy(Anomaly::Future, TimestampPolicy::Rebase)
+            .policy(Anomaly::Zero, TimestampPolicy::Drop)
+            .max_future(secs(10));
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(100)), Some(secs(100)));
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(0)), None);
+        // The clock steps ahead, then back, and the timeline stays continuous
+        assert_eq!(
+            sanitizer.sanitize_with(&clock, secs(13_700)),
+            Some(secs(100))
+        );
+        assert_eq!(
+            sanitizer.sanitize_with(&clock, secs(13_705)),
+            Some(secs(105))
+        );
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(110)), Some(secs(105)));
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(111)), Some(secs(106)));
+        assert_eq!(sanitizer.stats().dropped, 1);
+        assert_eq!(sanitizer.stats().backward, 1);
+
+        let mut sanitizer = TimestampSanitizer::new();
+        sanitizer.policy(Anomaly::Backward, TimestampPolicy::Keep);
+        sanitizer.sanitize_with(&clock, secs(100));
+        assert_eq!(sanitizer.sanitize_with(&clock, secs(10)), Some(secs(10)));
+        assert_eq!(sanitizer.stats().backward, 1);
+    }
+}
@@ -0,0 +1,248 @@
+//! Graceful shutdown.
+//!
+//! A [`ShutdownToken`] is shared by the threads of a capture service. Once
+//! cancelled, by [`cancel`](ShutdownToken::cancel) or by SIGINT/SIGTERM for a
+//! token [watching signals](ShutdownToken::with_signals), capture loops stop
+//! reading, workers drain what is queued (see
+//! [`Receiver::recv_until`](crate::channel::Receiver::recv_until)), and
+//! writers flush their files:
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufWriter, time::Duration};
+//!
+//! use netkit_capture::{file::pcap::PcapWriter, shutdown::ShutdownToken};
+//!
+//! let token = ShutdownToken::with_signals().unwrap();
+//! let mut writer = PcapWriter::new(BufWriter::new(File::create("out.pcap").unwrap())).unwrap();
+//! while !token.is_cancelled() {
+//!     // Read a packet, with a timeout so the token is checked regularly
+//!     # let (ts, data) = (Duration::ZERO, [0u8; 60]);
+//!     writer.write_packet(ts, &data).unwrap();
+//! }
+//! // The partial capture stays readable.
+//! writer.flush().unwrap();
+//! ```
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Interval at which waits check for signals.
+pub const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Set by the signal handler.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the SIGHUP handler.
+static HUNG_UP: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    signals: bool,
+    lock: Mutex<()>,
+    cancel: Condvar,
+}
+
+/// Token telling the threads of a service to shut down.
+///
+/// Cloning a token gives another handle to the same state.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownToken {
+    shared: Arc<Shared>,
+}
+
+impl ShutdownToken {
+    /// Create a token cancelled only by [`cancel`](Self::cancel).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a token also cancelled by SIGINT and SIGTERM.
+    ///
+    /// This replaces the handlers of these signals for the whole process. A
+    /// second signal, once the token is cancelled, exits the process as
+    /// usual, so a stuck shutdown can still be interrupted.
+    pub fn with_signals() -> io::Result<Self> {
+        install_handlers()?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                signals: true,
+                ..Shared::default()
+            }),
+        })
+    }
+
+    /// Cancel the token, waking up the threads waiting on it.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::SeqCst);
+        let _lock = self.shared.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.shared.cancel.notify_all();
+    }
+
+    /// Whether the token is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        if self.shared.cancelled.load(Ordering::SeqCst) {
+            return true;
+        }
+        if self.shared.signals && SIGNALLED.load(Ordering::SeqCst) {
+            self.shared.cancelled.store(true, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+
+    /// Wait at most `timeout` for the token to be cancelled, returning
+    /// whether it is.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut lock = self.shared.lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if self.is_cancelled() {
+                return true;
+            }
+            let mut remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            if self.shared.signals {
+                remaining = remaining.min(SIGNAL_POLL_INTERVAL);
+            }
+            lock = self
+                .shared
+                .cancel
+                .wait_timeout(lock, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Wait for the token to be cancelled.
+    pub fn wait(&self) {
+        while !self.wait_timeout(Duration::from_secs(3600)) {}
+    }
+}
+
+/// SIGHUP, conventionally asking a daemon to reload its configuration.
+///
+/// Handles share the same flag, so a SIGHUP is taken by one of them.
+#[derive(Clone, Debug)]
+pub struct HangupSignal {
+    _private: (),
+}
+
+impl HangupSignal {
+    /// Handle SIGHUP, instead of being terminated by it.
+    ///
+    /// This replaces the handler of SIGHUP for the whole process.
+    pub fn install() -> io::Result<Self> {
+        install_hangup_handler()?;
+        Ok(Self { _private: () })
+    }
+
+    /// Whether SIGHUP was received since the last call.
+    pub fn take(&self) -> bool {
+        HUNG_UP.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    if SIGNALLED.swap(true, Ordering::SeqCst) {
+        // Second signal: fall back to the default behavior.
+        // SAFETY: `signal` and `raise` are async-signal-safe.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn install_handlers() -> io::Result<()> {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only touches an atomic and calls
+        // async-signal-safe functions.
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+extern "C" fn on_hangup(_signal: libc::c_int) {
+    HUNG_UP.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn install_hangup_handler() -> io::Result<()> {
+    let handler = on_hangup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches an atomic.
+    if unsafe { libc::signal(libc::SIGHUP, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn install_handlers() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "signal handling is only supported on Unix",
+    ))
+}
+
+#[cfg(not(unix))]
+fn install_hangup_handler() -> io::Result<()> {
+    install_handlers()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn shutdown_token() {
+        let token = ShutdownToken::new();
+        assert!(!token.wait_timeout(Duration::from_millis(1)));
+
+        let worker = {
+            let token = token.clone();
+            thread::spawn(move || token.wait())
+        };
+        token.cancel();
+        worker.join().unwrap();
+        assert!(token.is_cancelled());
+
+        // Signals only cancel the tokens watching them.
+        SIGNALLED.store(true, Ordering::SeqCst);
+        assert!(!ShutdownToken::new().is_cancelled());
+        let token = ShutdownToken {
+            shared: Arc::new(Shared {
+                signals: true,
+                ..Shared::default()
+            }),
+        };
+        assert!(token.wait_timeout(Duration::from_secs(1)));
+        SIGNALLED.store(false, Ordering::SeqCst);
+
+        #[cfg(unix)]
+        {
+            let hangup = HangupSignal::install().unwrap();
+            assert!(!hangup.take());
+            // SAFETY: SIGHUP is handled.
+            unsafe { libc::raise(libc::SIGHUP) };
+            assert!(hangup.take());
+            assert!(!hangup.take());
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Per-worker capture counters.
+//!
+//! A capture box losing packets needs to know where: the kernel ring
+//! overflowing because a worker is too slow, a [`channel`](crate::channel)
+//! between stages dropping on overflow, or a filter discarding on purpose.
+//! Each worker counts into its own [`WorkerCounters`], without contention,
+//! and [`CaptureStats`] reads them all for operators:
+//!
+//! ```
+//! use netkit_capture::stats::{CaptureStats, DropSource};
+//!
+//! let stats = CaptureStats::new(2);
+//! stats.worker(0).record(1500);
+//! stats.worker(1).record(60);
+//! stats.worker(1).add_drops(DropSource::Kernel, 3);
+//!
+//! let total = stats.total();
+//! assert_eq!((total.packets, total.bytes, total.drops()), (2, 1560, 3));
+//! ```
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Where packets were dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DropSource {
+    /// Dropped by the kernel or the NIC before the worker read them, e.g.
+    /// a full ring.
+    Kernel,
+    /// Dropped by a full queue between pipeline stages.
+    Queue,
+    /// Discarded by a filter.
+    Filter,
+}
+
+/// Counters of one capture worker.
+#[derive(Debug, Default)]
+pub struct WorkerCounters {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    kernel_drops: AtomicU64,
+    queue_drops: AtomicU64,
+    filter_drops: AtomicU64,
+}
+
+impl WorkerCounters {
+    /// Count a packet read by the worker.
+    #[inline]
+    pub fn record(&self, bytes: usize) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count dropped packets.
+    #[inline]
+    pub fn add_drops(&self, source: DropSource, count: u64) {
+        self.drops(source).fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn drops(&self, source: DropSource) -> &AtomicU64 {
+        match source {
+            DropSource::Kernel => &self.kernel_drops,
+            DropSource::Queue => &self.queue_drops,
+            DropSource::Filter => &self.filter_drops,
+        }
+    }
+
+    /// Read the counters.
+    pub fn snapshot(&self) -> WorkerSnapshot {
+        WorkerSnapshot {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            kernel_drops: self.kernel_drops.load(Ordering::Relaxed),
+            queue_drops: self.queue_drops.load(Ordering::Relaxed),
+            filter_drops: self.filter_drops.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters of a worker, or of all workers, at one point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorkerSnapshot {
+    /// Packets read.
+    pub packets: u64,
+    /// Bytes read.
+    pub bytes: u64,
+    /// Packets dropped by the kernel or the NIC.
+    pub kernel_drops: u64,
+    /// Packets dropped by full queues.
+    pub queue_drops: u64,
+    /// Packets discarded by filters.
+    pub filter_drops: u64,
+}
+
+impl WorkerSnapshot {
+    /// Get the number of packets dropped for any reason.
+    pub fn drops(&self) -> u64 {
+        self.kernel_drops + self.queue_drops + self.filter_drops
+    }
+
+    /// Get the share of packets lost before reaching the worker or its
+    /// consumers, filters excluded.
+    pub fn loss_rate(&self) -> f64 {
+        let lost = self.kernel_drops + self.queue_drops;
+        match self.packets + self.kernel_drops {
+            0 => 0.0,
+            seen => lost as f64 / seen as f64,
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.packets += other.packets;
+        self.bytes += other.bytes;
+        self.kernel_drops += other.kernel_drops;
+        self.queue_drops += other.queue_drops;
+        self.filter_drops += other.filter_drops;
+    }
+}
+
+/// Counters of all the workers of a capture.
+#[derive(Clone, Debug)]
+pub struct CaptureStats {
+    workers: Vec<Arc<WorkerCounters>>,
+}
+
+impl CaptureStats {
+    /// Create the counters of `workers` workers.
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: (0..workers).map(|_| Arc::default()).collect(),
+        }
+    }
+
+    /// Get the number of workers.
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Get the counters of a worker, to be moved to its thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the worker does not exist.
+    pub fn worker(&self, worker: usize) -> Arc<WorkerCounters> {
+        self.workers[worker].clone()
+    }
+
+    /// Read the counters of every worker.
+    pub fn snapshot(&self) -> Vec<WorkerSnapshot> {
+        self.workers.iter().map(|w| w.snapshot()).collect()
+    }
+
+    /// Read the counters summed over the workers.
+    pub fn total(&self) -> WorkerSnapshot {
+        let mut total = WorkerSnapshot::default();
+        for worker in &self.workers {
+            total.add(&worker.snapshot());
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn capture_stats_per_worker() {
+        let stats = CaptureStats::new(4);
+        let handles: Vec<_> = (0..stats.workers())
+            .map(|i| {
+                let counters = stats.worker(i);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        counters.record(100 * (i + 1));
+                    }
+                    counters.add_drops(DropSource::Queue, i as u64);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        stats.worker(3).add_drops(DropSource::Kernel, 1000);
+        stats.worker(0).add_drops(DropSource::Filter, 5);
+
+        let workers = stats.snapshot();
+        assert_eq!(workers[2].bytes, 300_000);
+        assert_eq!(workers[3].loss_rate(), 1003.0 / 2000.0);
+        let total = stats.total();
+        assert_eq!(total.packets, 4000);
+        assert_eq!(
+            (total.kernel_drops, total.queue_drops, total.filter_drops),
+            (1000, 6, 5)
+        );
+        assert_eq!(total.drops(), 1011);
+    }
+}
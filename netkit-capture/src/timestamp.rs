@@ -0,0 +1,319 @@
+//! Packet timestamps and where they come from.
+//!
+//! A timestamp taken by the NIC when the frame arrives is accurate to tens
+//! of nanoseconds, one taken by the kernel to a few microseconds, and one
+//! taken in userspace after the packet is read may be off by the scheduling
+//! latency. Carrying the [`TimestampSource`] along with the time lets
+//! latency analyses tell whether microsecond differences are meaningful.
+//! pcapng files keep it with each packet, see
+//! [`PcapngWriter::write_timestamped_packet`](crate::file::pcapng::PcapngWriter::write_timestamped_packet).
+//!
+//! On Linux, receive timestamps are requested with the `SO_TIMESTAMPING`
+//! socket option and delivered in a `SCM_TIMESTAMPING` control message,
+//! decoded by [`Timestamp::from_scm_timestamping`]. There is no live capture
+//! backend in this crate yet; the decoding is kept independent of the
+//! socket so any backend can use it.
+//!
+//! Ring-buffer backends such as `TPACKET_V3` only timestamp the first and
+//! last packet of each block. A [`TimestampMode`] chooses how the packets in
+//! between are stamped: with a block timestamp, interpolated along the
+//! bytes received (see [`Timestamp::interpolate`]), or with the time they
+//! are read, from any [`Clock`].
+//!
+//! On Linux, a [`PhcClock`] reads the PTP hardware clock of a NIC, the one
+//! its hardware timestamps come from.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_capture::timestamp::{Timestamp, TimestampSource};
+//!
+//! // Software timestamp only, hardware timestamp zero
+//! let mut cmsg = [0u8; 48];
+//! cmsg[0..8].copy_from_slice(&5i64.to_ne_bytes());
+//! cmsg[8..16].copy_from_slice(&250i64.to_ne_bytes());
+//!
+//! let ts = Timestamp::from_scm_timestamping(&cmsg).unwrap();
+//! assert_eq!(ts.source, TimestampSource::Software);
+//! assert_eq!(ts.time, Duration::new(5, 250));
+//! ```
+
+use std::{fmt, time::Duration};
+
+use netkit_packet::utils::clock::{Clock, SystemClock};
+
+/// Flags of the `SO_TIMESTAMPING` socket option (`linux/net_tstamp.h`).
+pub mod flags {
+    /// Request hardware receive timestamps.
+    pub const SOF_TIMESTAMPING_RX_HARDWARE: u32 = 1 << 2;
+    /// Request software receive timestamps.
+    pub const SOF_TIMESTAMPING_RX_SOFTWARE: u32 = 1 << 3;
+    /// Report software timestamps.
+    pub const SOF_TIMESTAMPING_SOFTWARE: u32 = 1 << 4;
+    /// Report hardware timestamps in the NIC clock.
+    pub const SOF_TIMESTAMPING_RAW_HARDWARE: u32 = 1 << 6;
+
+    /// Flags requesting and reporting hardware and software receive
+    /// timestamps, the hardware one being preferred when both are present.
+    pub const RX_ALL: u32 = SOF_TIMESTAMPING_RX_HARDWARE
+        | SOF_TIMESTAMPING_RX_SOFTWARE
+        | SOF_TIMESTAMPING_SOFTWARE
+        | SOF_TIMESTAMPING_RAW_HARDWARE;
+}
+
+/// Where a timestamp was taken, from the least to the most accurate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TimestampSource {
+    /// Unknown, e.g. read from a capture file.
+    #[default]
+    Unknown,
+    /// Interpolated between the timestamps of other packets.
+    Interpolated,
+    /// Taken in userspace after the packet was received.
+    Userspace,
+    /// Taken by the kernel when the packet was received.
+    Software,
+    /// Taken by the NIC when the frame arrived.
+    Hardware,
+}
+
+impl TimestampSource {
+    /// Get the code of the source, as recorded in capture files.
+    pub fn code(self) -> u8 {
+        match self {
+            TimestampSource::Unknown => 0,
+            TimestampSource::Interpolated => 1,
+            TimestampSource::Userspace => 2,
+            TimestampSource::Software => 3,
+            TimestampSource::Hardware => 4,
+        }
+    }
+
+    /// Get the source of a code, unknown codes giving
+    /// [`TimestampSource::Unknown`].
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => TimestampSource::Interpolated,
+            2 => TimestampSource::Userspace,
+            3 => TimestampSource::Software,
+            4 => TimestampSource::Hardware,
+            _ => TimestampSource::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for TimestampSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampSource::Unknown => write!(f, "unknown"),
+            TimestampSource::Interpolated => write!(f, "interpolated"),
+            TimestampSource::Userspace => write!(f, "userspace"),
+            TimestampSource::Software => write!(f, "software"),
+            TimestampSource::Hardware => write!(f, "hardware"),
+        }
+    }
+}
+
+/// A packet timestamp with its source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Timestamp {
+    /// Time since the Unix epoch.
+    pub time: Duration,
+    /// Where the timestamp was taken.
+    pub source: TimestampSource,
+}
+
+impl Timestamp {
+    /// Size of a `SCM_TIMESTAMPING` payload with 64-bit `timespec`s.
+    pub const SCM_TIMESTAMPING_LEN: usize = 48;
+
+    /// Create a new timestamp.
+    pub fn new(time: Duration, source: TimestampSource) -> Self {
+        Self { time, source }
+    }
+
+    /// Decode the payload of a `SCM_TIMESTAMPING` control message.
+    ///
+    /// The payload holds three native-endian 64-bit `timespec`s: the software
+    /// timestamp, a deprecated one and the raw hardware timestamp. The
+    /// hardware timestamp is preferred; zero timestamps are absent. Returns
+    /// `None` if the payload is too short or holds no timestamp.
+    pub fn from_scm_timestamping(data: &[u8]) -> Option<Self> {
+        let data = data.get(..Self::SCM_TIMESTAMPING_LEN)?;
+        let timespec = |i: usize| {
+            let field = |offset: usize| {
+                let bytes = data[offset..offset + 8].try_into().unwrap();
+                i64::from_ne_bytes(bytes)
+            };
+            let (sec, nsec) = (field(16 * i), field(16 * i + 8));
+            (sec > 0 || nsec > 0).then(|| Duration::new(sec as u64, nsec as u32))
+        };
+
+        timespec(2)
+            .map(|time| Self::new(time, TimestampSource::Hardware))
+            .or_else(|| timespec(0).map(|time| Self::new(time, TimestampSource::Software)))
+    }
+
+    /// Stamp the packets of a block from the timestamps of its first and
+    /// last packets, given the packet lengths.
+    ///
+    /// Packets are assumed to arrive at a constant bit rate within the
+    /// block, so each is placed in proportion to the bytes received before
+    /// it. The first and last packets keep their timestamps; the others are
+    /// [`Interpolated`](TimestampSource::Interpolated).
+    pub fn interpolate(first: Timestamp, last: Timestamp, lengths: &[usize]) -> Vec<Timestamp> {
+        let span = last.time.saturating_sub(first.time);
+        // Bytes before the last packet, which arrives at `last`.
+        let total: u128 = lengths
+            .iter()
+            .take(lengths.len().saturating_sub(1))
+            .map(|&len| len as u128)
+            .sum();
+
+        let mut before = 0u128;
+        let mut stamps = Vec::with_capacity(lengths.len());
+        for (i, &len) in lengths.iter().enumerate() {
+            let stamp = if i == 0 {
+                first
+            } else if i == lengths.len() - 1 {
+                last
+            } else {
+                let offset = span.as_nanos() * before / total.max(1);
+                Timestamp::new(
+                    first.time + Duration::from_nanos(offset as u64),
+                    TimestampSource::Interpolated,
+                )
+            };
+            stamps.push(stamp);
+            before += len as u128;
+        }
+        stamps
+    }
+}
+
+/// How packets of a block timestamped as a whole are stamped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TimestampMode {
+    /// Every packet gets the timestamp of the block's first packet.
+    Block,
+    /// Packets are interpolated between the first and last timestamps.
+    #[default]
+    Interpolate,
+    /// Packets are stamped when read, in userspace.
+    Userspace,
+}
+
+impl TimestampMode {
+    /// Stamp the packets of a block, given the timestamps of its first and
+    /// last packets and the packet lengths.
+    pub fn stamp(&self, first: Timestamp, last: Timestamp, lengths: &[usize]) -> Vec<Timestamp> {
+        self.stamp_with(&SystemClock, first, last, lengths)
+    }
+
+    /// Stamp the packets of a block, reading the time from `clock` in
+    /// [`TimestampMode::Userspace`].
+    pub fn stamp_with(
+        &self,
+        clock: &impl Clock,
+        first: Timestamp,
+        last: Timestamp,
+        lengths: &[usize],
+    ) -> Vec<Timestamp> {
+        match self {
+            TimestampMode::Block => vec![first; lengths.len()],
+            TimestampMode::Interpolate => Timestamp::interpolate(first, last, lengths),
+            TimestampMode::Userspace => {
+                vec![Timestamp::new(clock.now(), TimestampSource::Userspace); lengths.len()]
+            }
+        }
+    }
+}
+
+/// PTP hardware clock of a NIC, e.g. `/dev/ptp0`.
+///
+/// The clock usually runs on TAI when synchronized by PTP, 37 seconds ahead
+/// of the system clock.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct PhcClock {
+    device: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl PhcClock {
+    /// Open the clock device at `path`.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let device = std::fs::File::open(path)?;
+        Ok(Self { device })
+    }
+
+    /// Read the clock.
+    pub fn read(&self) -> std::io::Result<Duration> {
+        use std::os::fd::AsRawFd;
+
+        // FD_TO_CLOCKID of the kernel's dynamic POSIX clocks.
+        let clock_id = ((!self.device.as_raw_fd()) << 3) | 3;
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` is valid for writes of its size.
+        if unsafe { libc::clock_gettime(clock_id as libc::clockid_t, &mut ts) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Clock for PhcClock {
+    /// Read the clock, or zero if it cannot be read, e.g. because the NIC
+    /// was removed.
+    fn now(&self) -> Duration {
+        self.read().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_scm_timestamping() {
+        let mut cmsg = [0u8; 48];
+        assert_eq!(Timestamp::from_scm_timestamping(&cmsg), None);
+        assert_eq!(Timestamp::from_scm_timestamping(&cmsg[..40]), None);
+
+        cmsg[0..8].copy_from_slice(&10i64.to_ne_bytes());
+        cmsg[32..40].copy_from_slice(&10i64.to_ne_bytes());
+        cmsg[40..48].copy_from_slice(&123i64.to_ne_bytes());
+        assert_eq!(
+            Timestamp::from_scm_timestamping(&cmsg),
+            Some(Timestamp::new(
+                Duration::new(10, 123),
+                TimestampSource::Hardware
+            ))
+        );
+        assert!(TimestampSource::Hardware > TimestampSource::Software);
+    }
+
+    #[test]
+    fn timestamp_interpolate() {
+        let first = Timestamp::new(Duration::from_micros(100), TimestampSource::Software);
+        let last = Timestamp::new(Duration::from_micros(200), TimestampSource::Software);
+        let stamps = Timestamp::interpolate(first, last, &[100, 300, 100, 60]);
+        let times: Vec<_> = stamps.iter().map(|ts| ts.time.as_micros()).collect();
+        assert_eq!(times, [100, 120, 180, 200]);
+        assert_eq!(stamps[0].source, TimestampSource::Software);
+        assert_eq!(stamps[1].source, TimestampSource::Interpolated);
+        assert_eq!(Timestamp::interpolate(first, last, &[60]), [first]);
+
+        let block = TimestampMode::Block.stamp(first, last, &[60, 60]);
+        assert_eq!(block, [first, first]);
+        let read = TimestampMode::Userspace.stamp(first, last, &[60]);
+        assert_eq!(read[0].source, TimestampSource::Userspace);
+        let clock = netkit_packet::utils::clock::ManualClock::new(Duration::from_secs(7));
+        let read = TimestampMode::Userspace.stamp_with(&clock, first, last, &[60]);
+        assert_eq!(read[0].time, Duration::from_secs(7));
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * C bindings of the netkit dissector and flow table.
+ *
+ * Objects are opaque pointers freed by their *_free function, and the
+ * strings they hand out are NUL-terminated and live as long as the object.
+ * Timestamps are nanoseconds since the epoch. Functions accept NULL objects
+ * and then return 0, NULL or false.
+ *
+ *     nk_packet *packet = nk_packet_parse(1, frame, frame_len);
+ *     for (size_t i = 0; i < nk_packet_layer_count(packet); i++) {
+ *         nk_field field;
+ *         for (size_t j = 0; nk_packet_field(packet, i, j, &field); j++)
+ *             printf("%s.%s = %s\n", field.layer, field.name, field.value);
+ *     }
+ *     nk_packet_free(packet);
+ */
+
+#ifndef NETKIT_H
+#define NETKIT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Address families of flow keys. */
+#define NK_FAMILY_IPV4 4
+#define NK_FAMILY_IPV6 6
+
+/* Dissected packet. */
+typedef struct NkPacket nk_packet;
+
+/* Field of a dissected packet. */
+typedef struct nk_field {
+    const char *layer; /* e.g. "Ipv4" */
+    const char *name;  /* e.g. "src" */
+    const char *value; /* for display */
+} nk_field;
+
+/* Callback visiting fields; a nonzero return stops the iteration. */
+typedef int (*nk_field_callback)(const nk_field *field, void *user);
+
+/* Dissect a packet of a link type (1 for Ethernet); NULL if data is NULL.
+ * Packets of unsupported link types have no layers. */
+nk_packet *nk_packet_parse(uint32_t link_type, const uint8_t *data, size_t len);
+void nk_packet_free(nk_packet *packet);
+
+/* Layers, outermost first. */
+size_t nk_packet_layer_count(const nk_packet *packet);
+const char *nk_packet_layer_name(const nk_packet *packet, size_t layer);
+
+/* Fields of a layer. */
+size_t nk_packet_field_count(const nk_packet *packet, size_t layer);
+bool nk_packet_field(const nk_packet *packet, size_t layer, size_t index, nk_field *out);
+
+/* Visit the fields of every layer; returns the number visited. */
+size_t nk_packet_foreach_field(const nk_packet *packet, nk_field_callback callback, void *user);
+
+/* 5-tuple of a flow; IPv4 addresses take the first 4 bytes. */
+typedef struct nk_flow_key {
+    uint8_t family;
+    uint8_t protocol;
+    uint16_t src_port;
+    uint16_t dst_port;
+    uint8_t src[16];
+    uint8_t dst[16];
+} nk_flow_key;
+
+/* Counters of a flow. */
+typedef struct nk_flow_stats {
+    uint64_t packets;
+    uint64_t bytes;
+    uint64_t first_ns;
+    uint64_t last_ns;
+} nk_flow_stats;
+
+/* Callback visiting flows; a nonzero return stops the iteration. */
+typedef int (*nk_flow_callback)(const nk_flow_key *key, const nk_flow_stats *stats, void *user);
+
+/* Table of per-flow counters. */
+typedef struct NkFlowTable nk_flow_table;
+
+/* Both directions of a conversation are one flow, keyed by its lower
+ * endpoint, if bidirectional. */
+nk_flow_table *nk_flow_table_new(bool bidirectional);
+void nk_flow_table_free(nk_flow_table *table);
+
+/* Count a packet; returns whether it belongs to an IP flow, whose key is
+ * then written to key unless NULL. */
+bool nk_flow_table_add(nk_flow_table *table, uint64_t ts_ns, uint32_t link_type,
+                       const uint8_t *data, size_t len, nk_flow_key *key);
+size_t nk_flow_table_len(const nk_flow_table *table);
+bool nk_flow_table_get(const nk_flow_table *table, const nk_flow_key *key, nk_flow_stats *stats);
+bool nk_flow_table_remove(nk_flow_table *table, const nk_flow_key *key, nk_flow_stats *stats);
+
+/* Remove the flows idle since before before_ns; returns their number. */
+size_t nk_flow_table_expire(nk_flow_table *table, uint64_t before_ns);
+
+/* Visit the flows in no particular order; the callback must not modify
+ * the table. Returns the number visited. */
+size_t nk_flow_table_foreach(const nk_flow_table *table, nk_flow_callback callback, void *user);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NETKIT_H */
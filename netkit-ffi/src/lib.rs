@@ -0,0 +1,589 @@
+//! C bindings of the dissector and the flow table.
+//!
+//! The declarations are in `include/netkit.h`. Objects are opaque pointers
+//! freed by their `*_free` function, and the strings they hand out are
+//! NUL-terminated and live as long as the object. Timestamps are
+//! nanoseconds since the epoch.
+
+use std::{
+    ffi::{c_char, c_int, c_void, CString},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ptr, slice,
+    sync::OnceLock,
+};
+
+use netkit_packet::{
+    dissect::DissectorRegistry,
+    flow::{table::FlowTable, FlowKey},
+    layer::{
+        eth::Eth,
+        ip::IpProtocol,
+        link::{self, LinkType},
+    },
+};
+
+/// Address family of IPv4 flow keys.
+pub const NK_FAMILY_IPV4: u8 = 4;
+
+/// Address family of IPv6 flow keys.
+pub const NK_FAMILY_IPV6: u8 = 6;
+
+fn registry() -> &'static DissectorRegistry {
+    static REGISTRY: OnceLock<DissectorRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(DissectorRegistry::with_builtins)
+}
+
+/// Convert to a C string, dropping any NUL byte.
+fn c_string(value: impl Into<Vec<u8>>) -> CString {
+    let mut bytes = value.into();
+    bytes.retain(|&b| b != 0);
+    CString::new(bytes).unwrap_or_default()
+}
+
+/// Borrow `len` bytes at `data`, `None` if `data` is NULL.
+///
+/// # Safety
+///
+/// `data` must be NULL or point to `len` readable bytes.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    (!data.is_null()).then(|| slice::from_raw_parts(data, len))
+}
+
+struct NkLayer {
+    name: CString,
+    fields: Vec<(CString, CString)>,
+}
+
+/// Dissected packet.
+pub struct NkPacket {
+    layers: Vec<NkLayer>,
+}
+
+impl NkPacket {
+    fn field(&self, layer: usize, index: usize) -> Option<NkField> {
+        let layer = self.layers.get(layer)?;
+        let (name, value) = layer.fields.get(index)?;
+        Some(NkField {
+            layer: layer.name.as_ptr(),
+            name: name.as_ptr(),
+            value: value.as_ptr(),
+        })
+    }
+}
+
+/// Field of a dissected packet.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct NkField {
+    /// Name of the layer, e.g. `Ipv4`.
+    pub layer: *const c_char,
+    /// Name of the field, e.g. `src`.
+    pub name: *const c_char,
+    /// Value of the field, for display.
+    pub value: *const c_char,
+}
+
+/// Callback visiting the fields of a packet; a nonzero return stops the
+/// iteration.
+pub type NkFieldCallback = extern "C" fn(field: *const NkField, user: *mut c_void) -> c_int;
+
+/// Dissect a packet of the given link type, e.g. 1 for Ethernet.
+///
+/// Returns NULL if `data` is NULL. Packets of unsupported link types have no
+/// layers.
+///
+/// # Safety
+///
+/// `data` must be NULL or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_parse(
+    link_type: u32,
+    data: *const u8,
+    len: usize,
+) -> *mut NkPacket {
+    let Some(data) = bytes(data, len) else {
+        return ptr::null_mut();
+    };
+    let layers = match link::to_eth(LinkType::from(link_type), data) {
+        Some(frame) => registry()
+            .dissect_eth(&frame)
+            .layers
+            .iter()
+            .map(|layer| NkLayer {
+                name: c_string(layer.name()),
+                fields: layer
+                    .fields()
+                    .into_iter()
+                    .map(|(name, value)| (c_string(name), c_string(value)))
+                    .collect(),
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    Box::into_raw(Box::new(NkPacket { layers }))
+}
+
+/// Free a packet.
+///
+/// # Safety
+///
+/// `packet` must be NULL or returned by [`nk_packet_parse`] and not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_free(packet: *mut NkPacket) {
+    if !packet.is_null() {
+        drop(Box::from_raw(packet));
+    }
+}
+
+/// Get the number of layers of a packet, outermost first.
+///
+/// # Safety
+///
+/// `packet` must be NULL or a live packet.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_layer_count(packet: *const NkPacket) -> usize {
+    packet.as_ref().map_or(0, |packet| packet.layers.len())
+}
+
+/// Get the name of a layer, NULL if out of range.
+///
+/// # Safety
+///
+/// `packet` must be NULL or a live packet.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_layer_name(
+    packet: *const NkPacket,
+    layer: usize,
+) -> *const c_char {
+    packet
+        .as_ref()
+        .and_then(|packet| packet.layers.get(layer))
+        .map_or(ptr::null(), |layer| layer.name.as_ptr())
+}
+
+/// Get the number of fields of a layer.
+///
+/// # Safety
+///
+/// `packet` must be NULL or a live packet.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_field_count(packet: *const NkPacket, layer: usize) -> usize {
+    packet
+        .as_ref()
+        .and_then(|packet| packet.layers.get(layer))
+        .map_or(0, |layer| layer.fields.len())
+}
+
+/// Get a field of a layer into `out`, returning whether it exists.
+///
+/// # Safety
+///
+/// `packet` must be NULL or a live packet, and `out` must be NULL or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_field(
+    packet: *const NkPacket,
+    layer: usize,
+    index: usize,
+    out: *mut NkField,
+) -> bool {
+    let Some(field) = packet.as_ref().and_then(|p| p.field(layer, index)) else {
+        return false;
+    };
+    if let Some(out) = out.as_mut() {
+        *out = field;
+    }
+    true
+}
+
+/// Visit the fields of every layer in order, returning the number of fields
+/// visited.
+///
+/// # Safety
+///
+/// `packet` must be NULL or a live packet. `user` is passed as is to the
+/// callback.
+#[no_mangle]
+pub unsafe extern "C" fn nk_packet_foreach_field(
+    packet: *const NkPacket,
+    callback: Option<NkFieldCallback>,
+    user: *mut c_void,
+) -> usize {
+    let (Some(packet), Some(callback)) = (packet.as_ref(), callback) else {
+        return 0;
+    };
+    let mut visited = 0;
+    for layer in 0..packet.layers.len() {
+        for index in 0..packet.layers[layer].fields.len() {
+            let field = packet.field(layer, index).expect("field in range");
+            visited += 1;
+            if callback(&field, user) != 0 {
+                return visited;
+            }
+        }
+    }
+    visited
+}
+
+/// 5-tuple of a flow.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NkFlowKey {
+    /// [`NK_FAMILY_IPV4`] or [`NK_FAMILY_IPV6`].
+    pub family: u8,
+    /// IP protocol.
+    pub protocol: u8,
+    /// Source port, 0 without ports.
+    pub src_port: u16,
+    /// Destination port, 0 without ports.
+    pub dst_port: u16,
+    /// Source address; IPv4 addresses take the first 4 bytes.
+    pub src: [u8; 16],
+    /// Destination address; IPv4 addresses take the first 4 bytes.
+    pub dst: [u8; 16],
+}
+
+impl From<&FlowKey> for NkFlowKey {
+    fn from(key: &FlowKey) -> Self {
+        let address = |ip: IpAddr| {
+            let mut bytes = [0; 16];
+            match ip {
+                IpAddr::V4(ip) => bytes[..4].copy_from_slice(&ip.octets()),
+                IpAddr::V6(ip) => bytes = ip.octets(),
+            }
+            bytes
+        };
+        Self {
+            family: if key.src.is_ipv4() {
+                NK_FAMILY_IPV4
+            } else {
+                NK_FAMILY_IPV6
+            },
+            protocol: key.protocol.into(),
+            src_port: key.src_port,
+            dst_port: key.dst_port,
+            src: address(key.src),
+            dst: address(key.dst),
+        }
+    }
+}
+
+impl NkFlowKey {
+    fn to_flow_key(self) -> Option<FlowKey> {
+        let address = |bytes: [u8; 16]| -> Option<IpAddr> {
+            match self.family {
+                NK_FAMILY_IPV4 => {
+                    Some(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).into())
+                }
+                NK_FAMILY_IPV6 => Some(Ipv6Addr::from(bytes).into()),
+                _ => None,
+            }
+        };
+        Some(FlowKey::new(
+            address(self.src)?,
+            address(self.dst)?,
+            self.src_port,
+            self.dst_port,
+            IpProtocol::from(self.protocol),
+        ))
+    }
+}
+
+/// Counters of a flow.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NkFlowStats {
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of bytes, link-layer headers included.
+    pub bytes: u64,
+    /// Timestamp of the first packet.
+    pub first_ns: u64,
+    /// Timestamp of the last packet.
+    pub last_ns: u64,
+}
+
+/// Callback visiting the flows of a table; a nonzero return stops the
+/// iteration.
+pub type NkFlowCallback =
+    extern "C" fn(key: *const NkFlowKey, stats: *const NkFlowStats, user: *mut c_void) -> c_int;
+
+/// Table of per-flow counters.
+pub struct NkFlowTable {
+    table: FlowTable<NkFlowStats>,
+    bidirectional: bool,
+}
+
+/// Create a flow table, counting both directions of a conversation as one
+/// flow (keyed by its lower endpoint) if `bidirectional`.
+#[no_mangle]
+pub extern "C" fn nk_flow_table_new(bidirectional: bool) -> *mut NkFlowTable {
+    Box::into_raw(Box::new(NkFlowTable {
+        table: FlowTable::new(),
+        bidirectional,
+    }))
+}
+
+/// Free a flow table.
+///
+/// # Safety
+///
+/// `table` must be NULL or returned by [`nk_flow_table_new`] and not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_free(table: *mut NkFlowTable) {
+    if !table.is_null() {
+        drop(Box::from_raw(table));
+    }
+}
+
+/// Count a packet of the given link type, returning whether it belongs to
+/// an IP flow, whose key is then written to `key` unless NULL.
+///
+/// # Safety
+///
+/// `table` must be NULL or a live table, `data` must be NULL or point to
+/// `len` readable bytes, and `key` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_add(
+    table: *mut NkFlowTable,
+    ts_ns: u64,
+    link_type: u32,
+    data: *const u8,
+    len: usize,
+    key: *mut NkFlowKey,
+) -> bool {
+    let (Some(table), Some(data)) = (table.as_mut(), bytes(data, len)) else {
+        return false;
+    };
+    let Some(frame) = link::to_eth(LinkType::from(link_type), data) else {
+        return false;
+    };
+    let Some(mut flow) = Eth::new(&frame[..])
+        .ok()
+        .and_then(|eth| FlowKey::from_eth(&eth))
+    else {
+        return false;
+    };
+    if table.bidirectional {
+        flow = flow.canonical();
+    }
+
+    let stats = table.table.entry(flow).or_insert(NkFlowStats {
+        first_ns: ts_ns,
+        last_ns: ts_ns,
+        ..Default::default()
+    });
+    stats.packets += 1;
+    stats.bytes += len as u64;
+    stats.first_ns = stats.first_ns.min(ts_ns);
+    stats.last_ns = stats.last_ns.max(ts_ns);
+    if let Some(key) = key.as_mut() {
+        *key = NkFlowKey::from(&flow);
+    }
+    true
+}
+
+/// Get the number of flows of a table.
+///
+/// # Safety
+///
+/// `table` must be NULL or a live table.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_len(table: *const NkFlowTable) -> usize {
+    table.as_ref().map_or(0, |table| table.table.len())
+}
+
+/// Get the counters of a flow into `stats`, returning whether it exists.
+///
+/// # Safety
+///
+/// `table` and `key` must be NULL or valid, and `stats` must be NULL or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_get(
+    table: *const NkFlowTable,
+    key: *const NkFlowKey,
+    stats: *mut NkFlowStats,
+) -> bool {
+    let (Some(table), Some(key)) = (table.as_ref(), key.as_ref()) else {
+        return false;
+    };
+    let Some(found) = key.to_flow_key().and_then(|key| table.table.get(&key)) else {
+        return false;
+    };
+    if let Some(stats) = stats.as_mut() {
+        *stats = *found;
+    }
+    true
+}
+
+/// Remove a flow, writing its counters to `stats` unless NULL, and
+/// returning whether it existed.
+///
+/// # Safety
+///
+/// `table` and `key` must be NULL or valid, and `stats` must be NULL or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_remove(
+    table: *mut NkFlowTable,
+    key: *const NkFlowKey,
+    stats: *mut NkFlowStats,
+) -> bool {
+    let (Some(table), Some(key)) = (table.as_mut(), key.as_ref()) else {
+        return false;
+    };
+    let Some(removed) = key.to_flow_key().and_then(|key| table.table.remove(&key)) else {
+        return false;
+    };
+    if let Some(stats) = stats.as_mut() {
+        *stats = removed;
+    }
+    true
+}
+
+/// Remove the flows idle since before `before_ns`, returning their number.
+///
+/// # Safety
+///
+/// `table` must be NULL or a live table.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_expire(table: *mut NkFlowTable, before_ns: u64) -> usize {
+    let Some(table) = table.as_mut() else {
+        return 0;
+    };
+    let len = table.table.len();
+    table.table.retain(|_, stats| stats.last_ns >= before_ns);
+    len - table.table.len()
+}
+
+/// Visit the flows of a table in no particular order, returning the number
+/// of flows visited.
+///
+/// # Safety
+///
+/// `table` must be NULL or a live table, not modified by the callback.
+/// `user` is passed as is to the callback.
+#[no_mangle]
+pub unsafe extern "C" fn nk_flow_table_foreach(
+    table: *const NkFlowTable,
+    callback: Option<NkFlowCallback>,
+    user: *mut c_void,
+) -> usize {
+    let (Some(table), Some(callback)) = (table.as_ref(), callback) else {
+        return 0;
+    };
+    let mut visited = 0;
+    for (key, stats) in table.table.iter() {
+        visited += 1;
+        if callback(&NkFlowKey::from(key), stats, user) != 0 {
+            break;
+        }
+    }
+    visited
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use netkit_packet::prelude::*;
+
+    use super::*;
+
+    extern "C" fn collect(field: *const NkField, user: *mut c_void) -> c_int {
+        let (field, fields) = unsafe { (&*field, &mut *(user as *mut Vec<String>)) };
+        let text = |s| unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        fields.push(format!(
+            "{}.{}={}",
+            text(field.layer),
+            text(field.name),
+            text(field.value)
+        ));
+        0
+    }
+
+    #[test]
+    fn ffi_packet_and_flows() {
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: [0; 8]);
+        let ipv4 = ipv4!(
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone();
+
+        unsafe {
+            let packet = nk_packet_parse(1, frame.as_ptr(), frame.len());
+            assert_eq!(nk_packet_layer_count(packet), 3);
+            let name = CStr::from_ptr(nk_packet_layer_name(packet, 1));
+            assert_eq!(name.to_str(), Ok("Ipv4"));
+            assert!(nk_packet_layer_name(packet, 3).is_null());
+
+            let mut fields = Vec::<String>::new();
+            let user = &mut fields as *mut _ as *mut c_void;
+            let visited = nk_packet_foreach_field(packet, Some(collect), user);
+            assert_eq!(visited, fields.len());
+            assert!(fields.contains(&"Ipv4.src=10.0.0.1".to_string()));
+            assert!(fields.contains(&"Udp.dst_port=53".to_string()));
+            assert!(!nk_packet_field(packet, 0, 99, ptr::null_mut()));
+            nk_packet_free(packet);
+            assert!(nk_packet_parse(1, ptr::null(), 0).is_null());
+
+            let table = nk_flow_table_new(true);
+            let mut key = NkFlowKey::default();
+            assert!(nk_flow_table_add(
+                table,
+                1_000,
+                1,
+                frame.as_ptr(),
+                frame.len(),
+                &mut key
+            ));
+            assert!(nk_flow_table_add(
+                table,
+                3_000,
+                1,
+                frame.as_ptr(),
+                frame.len(),
+                &mut key
+            ));
+            assert!(!nk_flow_table_add(
+                table,
+                0,
+                1,
+                frame.as_ptr(),
+                10,
+                ptr::null_mut()
+            ));
+            assert_eq!(nk_flow_table_len(table), 1);
+            assert_eq!(
+                (key.family, key.protocol, key.src_port, key.dst_port),
+                (NK_FAMILY_IPV4, 17, 1000, 53)
+            );
+            assert_eq!(key.src[..4], [10, 0, 0, 1]);
+
+            let mut stats = NkFlowStats::default();
+            assert!(nk_flow_table_get(table, &key, &mut stats));
+            let bytes = 2 * frame.len() as u64;
+            assert_eq!(
+                stats,
+                NkFlowStats {
+                    packets: 2,
+                    bytes,
+                    first_ns: 1_000,
+                    last_ns: 3_000
+                }
+            );
+            assert_eq!(nk_flow_table_expire(table, 2_000), 0);
+            assert_eq!(nk_flow_table_expire(table, 4_000), 1);
+            assert!(!nk_flow_table_remove(table, &key, ptr::null_mut()));
+            nk_flow_table_free(table);
+        }
+    }
+}
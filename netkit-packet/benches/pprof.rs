@@ -1,7 +1,16 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use pprof::criterion::{Output, PProfProfiler};
 
-use netkit_packet::prelude::*;
+use std::{net::Ipv4Addr, time::Duration};
+
+use netkit_packet::{
+    flow::{
+        fast::FastPath,
+        store::{FlowStore, StoreLayout},
+    },
+    layer::link::LinkType,
+    prelude::*,
+};
 
 const DATA: [u8; 46] = [
     0x01, 0x02, 0x03, 0x04, 0x05, 0x06, // dst mac
@@ -27,7 +36,7 @@ fn read_eth(c: &mut Criterion) {
     c.bench_function("read_eth_mac_addr", |b| {
         b.iter_batched(
             || &DATA[0..6],
-            |data| EthAddr::from_slice(data),
+            EthAddr::from_slice,
             criterion::BatchSize::SmallInput,
         )
     });
@@ -41,25 +50,52 @@ fn read_eth(c: &mut Criterion) {
     });
 
     c.bench_function("read_eth_no_payload", |b| {
+        b.iter_batched(|| &DATA[0..14], Eth::new, criterion::BatchSize::SmallInput)
+    });
+
+    c.bench_function("read_eth_ip_no_payload", |b| {
+        b.iter_batched(|| &DATA[0..34], Eth::new, criterion::BatchSize::SmallInput)
+    });
+}
+
+fn flow_key(c: &mut Criterion) {
+    c.bench_function("flow_key_layers", |b| {
         b.iter_batched(
-            || &DATA[0..14],
-            |data| Eth::new(data),
+            || &DATA[..],
+            |data| FastPath::Layers.flow_key(LinkType::Ethernet, data),
             criterion::BatchSize::SmallInput,
         )
     });
 
-    c.bench_function("read_eth_ip_no_payload", |b| {
+    c.bench_function("flow_key_raw", |b| {
         b.iter_batched(
-            || &DATA[0..34],
-            |data| Eth::new(data),
+            || &DATA[..],
+            |data| FastPath::Raw.flow_key(LinkType::Ethernet, data),
             criterion::BatchSize::SmallInput,
         )
     });
 }
 
+fn flow_store(c: &mut Criterion) {
+    const FLOWS: u32 = 1_000_000;
+
+    for layout in [StoreLayout::Map, StoreLayout::Soa] {
+        let mut store = FlowStore::new(layout);
+        for i in 0..FLOWS {
+            let src = Ipv4Addr::from(i);
+            let key = FlowKey::new(src, src, 1234, 80, IpProtocol::Tcp);
+            store.record(key, Duration::from_micros(i as u64), 64 + (i % 1400) as u64);
+        }
+
+        c.bench_function(&format!("flow_store_scan_{layout:?}"), |b| {
+            b.iter(|| store.total_bytes())
+        });
+    }
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
-    targets = read_eth,
+    targets = read_eth, flow_key, flow_store,
 }
 criterion_main!(benches);
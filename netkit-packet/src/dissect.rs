@@ -0,0 +1,1751 @@
+//! Runtime-extensible dissection.
+//!
+//! The layer types are statically typed, which is what most code wants, but
+//! a generic dissector (e.g. to print every layer of a frame) needs to walk
+//! protocols it does not know at compile time. A [`DissectorRegistry`] holds
+//! [`DissectorPlugin`]s, each recognizing one protocol from the hint left by
+//! the layer below and parsing it into a boxed [`Layer`]. Downstream crates
+//! add protocols by registering their own plugins.
+//!
+//! ```
+//! use netkit_packet::dissect::DissectorRegistry;
+//! # use netkit_packet::prelude::*;
+//!
+//! let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//! let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+//!
+//! let registry = DissectorRegistry::with_builtins();
+//! let dissected = registry.dissect_eth(eth.inner());
+//! let names: Vec<_> = dissected.layers.iter().map(|l| l.name()).collect();
+//! assert_eq!(names, ["Eth", "Ipv4", "Udp"]);
+//! ```
+
+use std::collections::BTreeMap;
+
+use decode_as::DecodeKey;
+
+use crate::prelude::*;
+
+pub mod decode_as;
+pub mod packet;
+pub mod wireshark;
+
+/// Default maximum number of layers dissected in a frame.
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Error type for dissection.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum DissectError {
+    /// A plugin matched the data but failed to parse it.
+    #[error("Malformed {plugin} layer: {reason}")]
+    Malformed {
+        /// Name of the plugin.
+        plugin: &'static str,
+        /// Reason of the failure.
+        reason: String,
+    },
+}
+
+/// Dynamically typed layer.
+pub trait Layer: Send + Sync {
+    /// Get the name of the protocol.
+    fn name(&self) -> &'static str;
+
+    /// Get the length of the header, i.e. the offset of the payload.
+    fn header_len(&self) -> usize;
+
+    /// Get the names and values of the header fields, for display.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+}
+
+/// Hint of the layer below about the next layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NextHint {
+    /// The next layer is identified by an Eth type.
+    EthType(EthType),
+
+    /// The next layer is identified by an IP protocol.
+    IpProtocol(IpProtocol),
+
+    /// The next layer is the payload of a UDP datagram.
+    Udp {
+        /// Source port.
+        src_port: u16,
+        /// Destination port.
+        dst_port: u16,
+    },
+
+    /// The next layer is the payload of a TCP segment.
+    Tcp {
+        /// Source port.
+        src_port: u16,
+        /// Destination port.
+        dst_port: u16,
+    },
+
+    /// The next layer is identified by a plugin-defined name.
+    Named(&'static str),
+
+    /// The next layer is unknown; plugins may recognize it from the data.
+    Unknown,
+
+    /// There is no next layer.
+    End,
+}
+
+impl NextHint {
+    /// Whether either port of a UDP or TCP hint is `port`.
+    pub fn has_port(&self, port: u16) -> bool {
+        match *self {
+            NextHint::Udp { src_port, dst_port } | NextHint::Tcp { src_port, dst_port } => {
+                src_port == port || dst_port == port
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Result of a plugin parsing a layer.
+pub struct Dissection {
+    /// The parsed layer.
+    pub layer: Box<dyn Layer>,
+    /// Hint about the layer following it.
+    pub next: NextHint,
+}
+
+/// Dissector of a protocol, registered at runtime.
+pub trait DissectorPlugin: Send + Sync {
+    /// Get the name of the plugin, unique in a registry.
+    fn name(&self) -> &'static str;
+
+    /// Whether the plugin handles the data, given the hint of the layer
+    /// below.
+    fn matches(&self, hint: &NextHint, data: &[u8]) -> bool;
+
+    /// Parse the layer at the start of the data.
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError>;
+}
+
+/// Layers dissected from a frame.
+#[derive(Default)]
+pub struct Dissected {
+    /// Layers, outermost first.
+    pub layers: Vec<Box<dyn Layer>>,
+    /// Number of bytes covered by the layers' headers.
+    pub offset: usize,
+    /// Error that stopped the dissection, if any.
+    pub error: Option<DissectError>,
+    /// Hint no plugin matched, if that stopped the dissection.
+    pub unrecognized: Option<NextHint>,
+}
+
+impl Dissected {
+    /// Get the first layer of the given name.
+    pub fn layer(&self, name: &str) -> Option<&dyn Layer> {
+        self.layers
+            .iter()
+            .find(|l| l.name() == name)
+            .map(|l| l.as_ref())
+    }
+
+    /// Get the values of a field in all layers.
+    ///
+    /// The field is named either by its Wireshark display filter name (e.g.
+    /// `ip.src`, `tcp.port`, see [`wireshark`]) or as `Layer.field` (e.g.
+    /// `Ipv4.src`).
+    pub fn field(&self, name: &str) -> Vec<String> {
+        let (layer, fields) = match wireshark::lookup(name) {
+            Some(field) => (field.layer, field.fields),
+            None => match name.split_once('.') {
+                Some((layer, field)) => (layer, &[field][..]),
+                None => return Vec::new(),
+            },
+        };
+
+        self.layers
+            .iter()
+            .filter(|l| l.name() == layer)
+            .flat_map(|l| l.fields())
+            .filter(|(name, _)| fields.contains(name))
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+/// Registry of dissector plugins.
+///
+/// When several plugins match, the last registered one wins, so plugins can
+/// override the built-in dissectors. ["Decode As"](decode_as) overrides
+/// take precedence over both.
+pub struct DissectorRegistry {
+    plugins: Vec<Box<dyn DissectorPlugin>>,
+    decode_as: BTreeMap<DecodeKey, String>,
+    max_depth: usize,
+}
+
+impl Default for DissectorRegistry {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            decode_as: BTreeMap::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+impl DissectorRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with a dissector for each layer of
+    /// [`layer`](crate::layer).
+    ///
+    /// Ethernet, BSD loopback (Null) and SLL2 frames are dissected from
+    /// `NextHint::Named("Eth")`, `"Null"` and `"Sll2"` respectively. RTP and
+    /// WireGuard are recognized from UDP payloads on any port, but only when
+    /// no dissector matches the ports.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(RtpDissector)
+            .register(WireGuardDissector)
+            .register(EthDissector)
+            .register(NullDissector)
+            .register(Sll2Dissector)
+            .register(VlanDissector)
+            .register(LlcDissector)
+            .register(Ipv4Dissector)
+            .register(Ipv6Dissector)
+            .register(IcmpDissector)
+            .register(AhDissector)
+            .register(EspDissector)
+            .register(GreDissector)
+            .register(UdpDissector)
+            .register(UdpLiteDissector)
+            .register(TcpDissector)
+            .register(DccpDissector)
+            .register(DnsDissector)
+            .register(QuicDissector)
+            .register(L2tpDissector)
+            .register(PppDissector)
+            .register(OpenVpnDissector)
+            .register(PtpDissector)
+            .register(EapolDissector)
+            .register(EapDissector)
+            .register(GooseDissector)
+            .register(SvDissector);
+        registry
+    }
+
+    /// Set the maximum number of layers dissected in a frame.
+    pub fn max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Register a plugin, replacing any plugin of the same name.
+    pub fn register(&mut self, plugin: impl DissectorPlugin + 'static) -> &mut Self {
+        self.register_boxed(Box::new(plugin))
+    }
+
+    /// Register a boxed plugin, replacing any plugin of the same name.
+    ///
+    /// This is convenient to register plugins chosen at runtime, e.g. from
+    /// a list of optional dissectors.
+    pub fn register_boxed(&mut self, plugin: Box<dyn DissectorPlugin>) -> &mut Self {
+        self.unregister(plugin.name());
+        self.plugins.push(plugin);
+        self
+    }
+
+    /// Remove the plugin of the given name, returning whether it existed.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let len = self.plugins.len();
+        self.plugins.retain(|p| p.name() != name);
+        self.plugins.len() != len
+    }
+
+    /// Get the names of the registered plugins, in registration order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.plugins.iter().map(|p| p.name()).collect()
+    }
+
+    /// Dissect what a key names with the plugin of the given name, whatever
+    /// the plugins match.
+    ///
+    /// Overrides naming a plugin which is not registered are ignored.
+    pub fn decode_as(&mut self, key: DecodeKey, plugin: impl Into<String>) -> &mut Self {
+        self.decode_as.insert(key, plugin.into());
+        self
+    }
+
+    /// Remove the override of a key, returning the plugin it named.
+    pub fn remove_decode_as(&mut self, key: &DecodeKey) -> Option<String> {
+        self.decode_as.remove(key)
+    }
+
+    /// Get the "Decode As" overrides.
+    pub fn decode_as_table(&self) -> &BTreeMap<DecodeKey, String> {
+        &self.decode_as
+    }
+
+    /// Find the plugin handling the data.
+    pub fn find(&self, hint: &NextHint, data: &[u8]) -> Option<&dyn DissectorPlugin> {
+        let overridden = DecodeKey::for_hint(hint)
+            .iter()
+            .filter_map(|key| self.decode_as.get(key))
+            .find_map(|name| self.plugins.iter().find(|p| p.name() == name));
+        if let Some(plugin) = overridden {
+            return Some(plugin.as_ref());
+        }
+
+        self.plugins
+            .iter()
+            .rev()
+            .find(|p| p.matches(hint, data))
+            .map(|p| p.as_ref())
+    }
+
+    /// Dissect as many layers as possible, starting with the given hint.
+    ///
+    /// Dissection stops at the end of the data, when no plugin matches, when
+    /// a plugin fails (see [`Dissected::error`]) or at the maximum depth.
+    pub fn dissect(&self, mut hint: NextHint, data: &[u8]) -> Dissected {
+        let mut dissected = Dissected::default();
+        while hint != NextHint::End && dissected.layers.len() < self.max_depth {
+            let rest = &data[dissected.offset..];
+            if rest.is_empty() {
+                break;
+            }
+            let Some(plugin) = self.find(&hint, rest) else {
+                dissected.unrecognized = Some(hint);
+                break;
+            };
+
+            match plugin.parse(rest) {
+                Ok(Dissection { layer, next }) => {
+                    dissected.offset += layer.header_len().min(rest.len());
+                    dissected.layers.push(layer);
+                    hint = next;
+                }
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(%error, offset = dissected.offset, "dissection failed");
+                    dissected.error = Some(error);
+                    break;
+                }
+            }
+        }
+
+        dissected
+    }
+
+    /// Dissect an Ethernet frame.
+    pub fn dissect_eth(&self, frame: &[u8]) -> Dissected {
+        self.dissect(NextHint::Named(EthDissector.name()), frame)
+    }
+}
+
+fn malformed(plugin: &'static str, error: impl ToString) -> DissectError {
+    DissectError::Malformed {
+        plugin,
+        reason: error.to_string(),
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Eth<T> {
+    fn name(&self) -> &'static str {
+        "Eth"
+    }
+
+    fn header_len(&self) -> usize {
+        Self::FIELD_PAYLOAD.start
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("dst", self.dst().get().to_string()),
+            ("src", self.src().get().to_string()),
+            ("eth_type", self.eth_type().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Vlan<T> {
+    fn name(&self) -> &'static str {
+        "Vlan"
+    }
+
+    fn header_len(&self) -> usize {
+        Self::FIELD_PAYLOAD.start
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("pcp", self.pcp().get().to_string()),
+            ("dei", self.dei().get().to_string()),
+            ("vid", self.vid().get().to_string()),
+            ("eth_type", self.eth_type().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Ipv4<T> {
+    fn name(&self) -> &'static str {
+        "Ipv4"
+    }
+
+    fn header_len(&self) -> usize {
+        self.ihl().get() as usize * 4
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("src", self.src().get().to_string()),
+            ("dst", self.dst().get().to_string()),
+            ("ttl", self.ttl().get().to_string()),
+            ("protocol", self.protocol().get().to_string()),
+            ("total_length", self.total_length().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Udp<T> {
+    fn name(&self) -> &'static str {
+        "Udp"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::udp::MIN_HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("src_port", self.src_port().get().to_string()),
+            ("dst_port", self.dst_port().get().to_string()),
+            ("length", self.length().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Tcp<T> {
+    fn name(&self) -> &'static str {
+        "Tcp"
+    }
+
+    fn header_len(&self) -> usize {
+        self.data_offset().get() as usize * 4
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("src_port", self.src_port().get().to_string()),
+            ("dst_port", self.dst_port().get().to_string()),
+            ("seq_num", self.seq_num().get().to_string()),
+            ("ack_num", self.ack_num().get().to_string()),
+            ("flags", format!("{:?}", self.flags().get())),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Dns<T> {
+    fn name(&self) -> &'static str {
+        "Dns"
+    }
+
+    /// The whole message, which is the last layer.
+    fn header_len(&self) -> usize {
+        self.inner().as_ref().len()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("id", self.id().get().to_string()),
+            ("qr", self.qr().get().to_string()),
+            ("opcode", self.opcode().get().to_string()),
+            ("rcode", self.rcode().get().to_string()),
+            ("qdcount", self.qdcount().get().to_string()),
+            ("ancount", self.ancount().get().to_string()),
+        ];
+        for question in self.questions() {
+            let qname = question.qname().to_string();
+            fields.push(("qname", qname.trim_end_matches('.').to_string()));
+            fields.push(("qtype", question.qtype().get().to_string()));
+            fields.push(("qclass", question.qclass().get().to_string()));
+        }
+        fields
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Ipv6<T> {
+    fn name(&self) -> &'static str {
+        "Ipv6"
+    }
+
+    /// The fixed header and the extension headers.
+    fn header_len(&self) -> usize {
+        match self.upper_layer() {
+            Some((_, data)) => self.inner().as_ref().len() - data.len(),
+            None => Self::HEADER_LENGTH,
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("src", self.src().get().to_string()),
+            ("dst", self.dst().get().to_string()),
+            ("hop_limit", self.hop_limit().get().to_string()),
+            ("next_header", self.next_header().get().to_string()),
+            ("payload_length", self.payload_length().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Icmp<T> {
+    fn name(&self) -> &'static str {
+        "Icmp"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::icmp::MIN_HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("type", self.icmp_type().get().to_string()),
+            ("code", self.code().get().to_string()),
+            ("identifier", self.identifier().get().to_string()),
+            ("sequence", self.sequence().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Ah<T> {
+    fn name(&self) -> &'static str {
+        "Ah"
+    }
+
+    fn header_len(&self) -> usize {
+        Ah::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("next_header", self.next_header().get().to_string()),
+            ("spi", self.spi().get().to_string()),
+            ("seq_num", self.seq_num().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Esp<T> {
+    fn name(&self) -> &'static str {
+        "Esp"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::ipsec::esp::MIN_HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("spi", self.spi().get().to_string()),
+            ("seq_num", self.seq_num().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Gre<T> {
+    fn name(&self) -> &'static str {
+        "Gre"
+    }
+
+    fn header_len(&self) -> usize {
+        Gre::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("version", self.version().get().to_string()),
+            ("protocol_type", self.protocol_type().get().to_string()),
+        ];
+        if let Some(key) = self.key() {
+            fields.push(("key", key.get().to_string()));
+        }
+        fields
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Dccp<T> {
+    fn name(&self) -> &'static str {
+        "Dccp"
+    }
+
+    fn header_len(&self) -> usize {
+        Dccp::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("src_port", self.src_port().get().to_string()),
+            ("dst_port", self.dst_port().get().to_string()),
+            ("type", self.packet_type().get().to_string()),
+            ("seq_num", self.seq_num().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for UdpLite<T> {
+    fn name(&self) -> &'static str {
+        "UdpLite"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::udplite::MIN_HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("src_port", self.src_port().get().to_string()),
+            ("dst_port", self.dst_port().get().to_string()),
+            ("coverage", self.coverage().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Llc<T> {
+    fn name(&self) -> &'static str {
+        "Llc"
+    }
+
+    fn header_len(&self) -> usize {
+        Llc::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("dsap", self.dsap().get().to_string()),
+            ("ssap", self.ssap().get().to_string()),
+            ("control", self.control().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Eapol<T> {
+    fn name(&self) -> &'static str {
+        "Eapol"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::eapol::HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("version", self.version().get().to_string()),
+            ("type", self.packet_type().get().to_string()),
+            ("body_length", self.body_length().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Eap<T> {
+    fn name(&self) -> &'static str {
+        "Eap"
+    }
+
+    /// The whole packet, which is the last layer.
+    fn header_len(&self) -> usize {
+        self.inner().as_ref().len()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("code", self.code().get().to_string()),
+            ("identifier", self.identifier().get().to_string()),
+        ];
+        if let Some(method) = self.method() {
+            fields.push(("method", method.to_string()));
+        }
+        fields
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Goose<T> {
+    fn name(&self) -> &'static str {
+        "Goose"
+    }
+
+    /// The whole frame, which is the last layer.
+    fn header_len(&self) -> usize {
+        self.inner().as_ref().len()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("appid", self.appid().get().to_string()),
+            ("length", self.length().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Sv<T> {
+    fn name(&self) -> &'static str {
+        "Sv"
+    }
+
+    /// The whole frame, which is the last layer.
+    fn header_len(&self) -> usize {
+        self.inner().as_ref().len()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("appid", self.appid().get().to_string()),
+            ("length", self.length().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Ptp<T> {
+    fn name(&self) -> &'static str {
+        "Ptp"
+    }
+
+    /// The whole message, which is the last layer.
+    fn header_len(&self) -> usize {
+        self.inner().as_ref().len()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("message_type", self.message_type().get().to_string()),
+            ("domain_number", self.domain_number().get().to_string()),
+            ("sequence_id", self.sequence_id().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for L2tp<T> {
+    fn name(&self) -> &'static str {
+        "L2tp"
+    }
+
+    fn header_len(&self) -> usize {
+        L2tp::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("control", self.control().get().to_string()),
+            ("tunnel_id", self.tunnel_id().get().to_string()),
+            ("session_id", self.session_id().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Ppp<T> {
+    fn name(&self) -> &'static str {
+        "Ppp"
+    }
+
+    fn header_len(&self) -> usize {
+        Ppp::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![("protocol", self.protocol().to_string())]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Null<T> {
+    fn name(&self) -> &'static str {
+        "Null"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::null::MIN_HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![("family", self.family().to_string())]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Sll2<T> {
+    fn name(&self) -> &'static str {
+        "Sll2"
+    }
+
+    fn header_len(&self) -> usize {
+        crate::layer::sll2::MIN_HEADER_LENGTH
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("protocol", self.protocol().get().to_string()),
+            ("interface_index", self.interface_index().get().to_string()),
+            ("packet_type", self.packet_type().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for OpenVpn<T> {
+    fn name(&self) -> &'static str {
+        "OpenVpn"
+    }
+
+    fn header_len(&self) -> usize {
+        OpenVpn::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("opcode", self.opcode().get().to_string()),
+            ("key_id", self.key_id().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Quic<T> {
+    fn name(&self) -> &'static str {
+        "Quic"
+    }
+
+    fn header_len(&self) -> usize {
+        Quic::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("long_header", self.is_long_header().to_string())];
+        if let Some(version) = self.version() {
+            fields.push(("version", version.get().to_string()));
+        }
+        fields
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for Rtp<T> {
+    fn name(&self) -> &'static str {
+        "Rtp"
+    }
+
+    fn header_len(&self) -> usize {
+        Rtp::header_len(self)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("payload_type", self.payload_type().get().to_string()),
+            ("sequence", self.sequence().get().to_string()),
+            ("timestamp", self.timestamp().get().to_string()),
+            ("ssrc", self.ssrc().get().to_string()),
+        ]
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> Layer for WireGuard<T> {
+    fn name(&self) -> &'static str {
+        "WireGuard"
+    }
+
+    /// The whole message, which is the last layer.
+    fn header_len(&self) -> usize {
+        self.inner().as_ref().len()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("type", self.message_type().get().to_string())];
+        if let Some(index) = self.receiver_index() {
+            fields.push(("receiver_index", index.get().to_string()));
+        }
+        fields
+    }
+}
+
+struct EthDissector;
+
+impl DissectorPlugin for EthDissector {
+    fn name(&self) -> &'static str {
+        "Eth"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::Named(self.name())
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let eth = Eth::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::EthType(eth.eth_type().get());
+        Ok(Dissection {
+            layer: Box::new(eth),
+            next,
+        })
+    }
+}
+
+struct VlanDissector;
+
+impl DissectorPlugin for VlanDissector {
+    fn name(&self) -> &'static str {
+        "Vlan"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        matches!(
+            hint,
+            NextHint::EthType(EthType::Vlan | EthType::ServiceVlan)
+        )
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let vlan = Vlan::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::EthType(vlan.eth_type().get());
+        Ok(Dissection {
+            layer: Box::new(vlan),
+            next,
+        })
+    }
+}
+
+struct Ipv4Dissector;
+
+impl DissectorPlugin for Ipv4Dissector {
+    fn name(&self) -> &'static str {
+        "Ipv4"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        matches!(
+            hint,
+            NextHint::EthType(EthType::Ipv4) | NextHint::IpProtocol(IpProtocol::Ipv4)
+        )
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let ipv4 = Ipv4::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::IpProtocol(ipv4.protocol().get());
+        Ok(Dissection {
+            layer: Box::new(ipv4),
+            next,
+        })
+    }
+}
+
+struct UdpDissector;
+
+impl DissectorPlugin for UdpDissector {
+    fn name(&self) -> &'static str {
+        "Udp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Udp)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let udp = Udp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::Udp {
+            src_port: udp.src_port().get(),
+            dst_port: udp.dst_port().get(),
+        };
+        Ok(Dissection {
+            layer: Box::new(udp),
+            next,
+        })
+    }
+}
+
+struct TcpDissector;
+
+impl DissectorPlugin for TcpDissector {
+    fn name(&self) -> &'static str {
+        "Tcp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Tcp)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let tcp = Tcp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::Tcp {
+            src_port: tcp.src_port().get(),
+            dst_port: tcp.dst_port().get(),
+        };
+        Ok(Dissection {
+            layer: Box::new(tcp),
+            next,
+        })
+    }
+}
+
+struct DnsDissector;
+
+impl DissectorPlugin for DnsDissector {
+    fn name(&self) -> &'static str {
+        "Dns"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        hint.has_port(crate::layer::dns::DEFAULT_PORT)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let dns = Dns::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(dns),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct Ipv6Dissector;
+
+impl DissectorPlugin for Ipv6Dissector {
+    fn name(&self) -> &'static str {
+        "Ipv6"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        matches!(
+            hint,
+            NextHint::EthType(EthType::Ipv6) | NextHint::IpProtocol(IpProtocol::Ipv6)
+        )
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let ipv6 = Ipv6::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        // Fragments other than the first do not start with a header.
+        let next = match ipv6.upper_layer() {
+            Some((protocol, _)) => NextHint::IpProtocol(protocol),
+            None => NextHint::End,
+        };
+        Ok(Dissection {
+            layer: Box::new(ipv6),
+            next,
+        })
+    }
+}
+
+struct IcmpDissector;
+
+impl DissectorPlugin for IcmpDissector {
+    fn name(&self) -> &'static str {
+        "Icmp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Icmp)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let icmp = Icmp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(icmp),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct AhDissector;
+
+impl DissectorPlugin for AhDissector {
+    fn name(&self) -> &'static str {
+        "Ah"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Ah)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let ah = Ah::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::IpProtocol(ah.next_header().get());
+        Ok(Dissection {
+            layer: Box::new(ah),
+            next,
+        })
+    }
+}
+
+struct EspDissector;
+
+impl DissectorPlugin for EspDissector {
+    fn name(&self) -> &'static str {
+        "Esp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Esp)
+    }
+
+    /// The payload is encrypted, so ESP is the last layer.
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let esp = Esp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(esp),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct GreDissector;
+
+impl DissectorPlugin for GreDissector {
+    fn name(&self) -> &'static str {
+        "Gre"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Gre)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let gre = Gre::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::EthType(gre.protocol_type().get());
+        Ok(Dissection {
+            layer: Box::new(gre),
+            next,
+        })
+    }
+}
+
+struct DccpDissector;
+
+impl DissectorPlugin for DccpDissector {
+    fn name(&self) -> &'static str {
+        "Dccp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::Dccp)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let dccp = Dccp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(dccp),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct UdpLiteDissector;
+
+impl DissectorPlugin for UdpLiteDissector {
+    fn name(&self) -> &'static str {
+        "UdpLite"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::IpProtocol(IpProtocol::UdpLite)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let udplite = UdpLite::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(udplite),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct LlcDissector;
+
+impl DissectorPlugin for LlcDissector {
+    fn name(&self) -> &'static str {
+        "Llc"
+    }
+
+    /// IEEE 802.3 frames hold the payload length in the Eth type field.
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        match *hint {
+            NextHint::EthType(eth_type) => u16::from(eth_type) <= crate::layer::eth::MAX_LENGTH,
+            _ => false,
+        }
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let llc = Llc::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = match llc.protocol() {
+            LlcProtocol::Ethernet(eth_type) => NextHint::EthType(eth_type),
+            _ => NextHint::End,
+        };
+        Ok(Dissection {
+            layer: Box::new(llc),
+            next,
+        })
+    }
+}
+
+struct EapolDissector;
+
+impl DissectorPlugin for EapolDissector {
+    fn name(&self) -> &'static str {
+        "Eapol"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::EthType(EthType::Eapol)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let eapol = Eapol::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = match eapol.packet_type().get() {
+            EapolType::EapPacket => NextHint::Named(EapDissector.name()),
+            _ => NextHint::End,
+        };
+        Ok(Dissection {
+            layer: Box::new(eapol),
+            next,
+        })
+    }
+}
+
+struct EapDissector;
+
+impl DissectorPlugin for EapDissector {
+    fn name(&self) -> &'static str {
+        "Eap"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::Named(self.name())
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let eap = Eap::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(eap),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct GooseDissector;
+
+impl DissectorPlugin for GooseDissector {
+    fn name(&self) -> &'static str {
+        "Goose"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::EthType(EthType::Goose)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let goose = Goose::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(goose),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct SvDissector;
+
+impl DissectorPlugin for SvDissector {
+    fn name(&self) -> &'static str {
+        "Sv"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::EthType(EthType::Sv)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let sv = Sv::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(sv),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct PtpDissector;
+
+impl DissectorPlugin for PtpDissector {
+    fn name(&self) -> &'static str {
+        "Ptp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        use crate::layer::ptp::{EVENT_PORT, GENERAL_PORT};
+        *hint == NextHint::EthType(EthType::Ptp)
+            || (matches!(hint, NextHint::Udp { .. })
+                && (hint.has_port(EVENT_PORT) || hint.has_port(GENERAL_PORT)))
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let ptp = Ptp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(ptp),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct L2tpDissector;
+
+impl DissectorPlugin for L2tpDissector {
+    fn name(&self) -> &'static str {
+        "L2tp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        matches!(hint, NextHint::Udp { .. }) && hint.has_port(crate::layer::l2tp::DEFAULT_PORT)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let l2tp = L2tp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        // Control messages carry AVPs, data messages carry PPP frames.
+        let next = if l2tp.control().get() {
+            NextHint::End
+        } else {
+            NextHint::Named(PppDissector.name())
+        };
+        Ok(Dissection {
+            layer: Box::new(l2tp),
+            next,
+        })
+    }
+}
+
+struct PppDissector;
+
+impl DissectorPlugin for PppDissector {
+    fn name(&self) -> &'static str {
+        "Ppp"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::Named(self.name())
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let ppp = Ppp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = match ppp.protocol() {
+            PppProtocol::Ipv4 => NextHint::EthType(EthType::Ipv4),
+            PppProtocol::Ipv6 => NextHint::EthType(EthType::Ipv6),
+            _ => NextHint::End,
+        };
+        Ok(Dissection {
+            layer: Box::new(ppp),
+            next,
+        })
+    }
+}
+
+struct NullDissector;
+
+impl DissectorPlugin for NullDissector {
+    fn name(&self) -> &'static str {
+        "Null"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::Named(self.name())
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let null = Null::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::EthType(null.eth_type());
+        Ok(Dissection {
+            layer: Box::new(null),
+            next,
+        })
+    }
+}
+
+struct Sll2Dissector;
+
+impl DissectorPlugin for Sll2Dissector {
+    fn name(&self) -> &'static str {
+        "Sll2"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        *hint == NextHint::Named(self.name())
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let sll2 = Sll2::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        let next = NextHint::EthType(sll2.protocol().get());
+        Ok(Dissection {
+            layer: Box::new(sll2),
+            next,
+        })
+    }
+}
+
+struct OpenVpnDissector;
+
+impl DissectorPlugin for OpenVpnDissector {
+    fn name(&self) -> &'static str {
+        "OpenVpn"
+    }
+
+    /// Only OpenVPN over UDP: over TCP, packets are prefixed by their
+    /// length and may span segments.
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        matches!(hint, NextHint::Udp { .. }) && hint.has_port(crate::layer::openvpn::DEFAULT_PORT)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let openvpn = OpenVpn::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(openvpn),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct QuicDissector;
+
+impl DissectorPlugin for QuicDissector {
+    fn name(&self) -> &'static str {
+        "Quic"
+    }
+
+    fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+        matches!(hint, NextHint::Udp { .. }) && hint.has_port(crate::layer::quic::DEFAULT_PORT)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let quic = Quic::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(quic),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct RtpDissector;
+
+impl DissectorPlugin for RtpDissector {
+    fn name(&self) -> &'static str {
+        "Rtp"
+    }
+
+    /// RTP runs on negotiated ports, so it is recognized from the header
+    /// alone.
+    fn matches(&self, hint: &NextHint, data: &[u8]) -> bool {
+        matches!(hint, NextHint::Udp { .. }) && Rtp::new(data).is_ok()
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let rtp = Rtp::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(rtp),
+            next: NextHint::End,
+        })
+    }
+}
+
+struct WireGuardDissector;
+
+impl DissectorPlugin for WireGuardDissector {
+    fn name(&self) -> &'static str {
+        "WireGuard"
+    }
+
+    /// WireGuard runs on configured ports, so it is recognized from the
+    /// message structure alone.
+    fn matches(&self, hint: &NextHint, data: &[u8]) -> bool {
+        matches!(hint, NextHint::Udp { .. }) && WireGuard::new(data).is_ok()
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+        let wireguard = WireGuard::new(data.to_vec()).map_err(|e| malformed(self.name(), e))?;
+        Ok(Dissection {
+            layer: Box::new(wireguard),
+            next: NextHint::End,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A toy protocol on UDP port 9999: a magic byte and a 1-byte length.
+    struct Toy;
+
+    struct ToyLayer(u8);
+
+    impl Layer for ToyLayer {
+        fn name(&self) -> &'static str {
+            "Toy"
+        }
+
+        fn header_len(&self) -> usize {
+            2
+        }
+
+        fn fields(&self) -> Vec<(&'static str, String)> {
+            vec![("length", self.0.to_string())]
+        }
+    }
+
+    impl DissectorPlugin for Toy {
+        fn name(&self) -> &'static str {
+            "Toy"
+        }
+
+        fn matches(&self, hint: &NextHint, _data: &[u8]) -> bool {
+            hint.has_port(9999)
+        }
+
+        fn parse(&self, data: &[u8]) -> Result<Dissection, DissectError> {
+            match data {
+                [0x7E, len, ..] => Ok(Dissection {
+                    layer: Box::new(ToyLayer(*len)),
+                    next: NextHint::End,
+                }),
+                _ => Err(malformed(self.name(), "bad magic")),
+            }
+        }
+    }
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let udp = udp!(src_port: 40000u16, dst_port: 9999u16, payload: payload);
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let vlan = vlan!(vid: 7u16, eth_type: EthType::Ipv4, payload: ipv4.inner());
+        eth!(eth_type: EthType::Vlan, payload: vlan.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn dissect_plugin() {
+        let mut registry = DissectorRegistry::with_builtins();
+        let frame = frame(&[0x7E, 3, 1, 2, 3]);
+
+        let dissected = registry.dissect_eth(&frame);
+        let names: Vec<_> = dissected.layers.iter().map(|l| l.name()).collect();
+        assert_eq!(names, ["Eth", "Vlan", "Ipv4", "Udp"]);
+        assert_eq!(dissected.offset, 14 + 4 + 20 + 8);
+        assert_eq!(
+            dissected.unrecognized,
+            Some(NextHint::Udp {
+                src_port: 40000,
+                dst_port: 9999
+            })
+        );
+
+        registry.register(Toy);
+        assert_eq!(registry.names().last(), Some(&"Toy"));
+        let dissected = registry.dissect_eth(&frame);
+        assert_eq!(dissected.layers.len(), 5);
+        assert_eq!(dissected.layers[4].fields(), [("length", "3".into())]);
+        assert_eq!(dissected.layers[1].fields()[2], ("vid", "7".into()));
+        assert!(dissected.error.is_none());
+        assert!(dissected.unrecognized.is_none());
+
+        let dissected = registry.dissect_eth(&self::frame(&[0, 0]));
+        assert_eq!(dissected.layers.len(), 4);
+        assert_eq!(
+            dissected.error,
+            Some(DissectError::Malformed {
+                plugin: "Toy",
+                reason: "bad magic".into()
+            })
+        );
+
+        assert_eq!(dissected.field("udp.dstport"), ["9999"]);
+        assert_eq!(dissected.field("Udp.dst_port"), ["9999"]);
+        assert_eq!(dissected.field("udp.port"), ["40000", "9999"]);
+        assert!(dissected.field("tcp.port").is_empty());
+
+        assert!(registry.unregister("Toy"));
+        assert!(!registry.unregister("Toy"));
+        assert_eq!(registry.dissect_eth(&frame).layers.len(), 4);
+    }
+
+    #[test]
+    fn dissect_wireshark_fields() {
+        let dns = crate::dns!(
+            id: 0x0102u16,
+            questions: crate::dns_question!(qname: "www.example.com", qtype: "A", qclass: "IN"),
+        );
+        let udp = udp!(src_port: 40000u16, dst_port: 53u16, payload: dns.inner());
+        let ipv4 = ipv4!(
+            src: core::net::Ipv4Addr::new(10, 0, 0, 1),
+            dst: core::net::Ipv4Addr::new(10, 0, 0, 53),
+            protocol: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+
+        let dissected = DissectorRegistry::with_builtins().dissect_eth(eth.inner());
+        assert_eq!(
+            dissected.layer("Dns").unwrap().header_len(),
+            dns.inner().len()
+        );
+        assert_eq!(dissected.field("dns.qry.name"), ["www.example.com"]);
+        assert_eq!(dissected.field("dns.qry.type"), ["A"]);
+        assert_eq!(dissected.field("dns.id"), ["258"]);
+        assert_eq!(dissected.field("ip.addr"), ["10.0.0.1", "10.0.0.53"]);
+        assert_eq!(dissected.field("ip.src"), dissected.field("Ipv4.src"));
+        assert!(dissected.field("ip").is_empty());
+    }
+
+    #[test]
+    fn dissect_builtins() {
+        let registry = DissectorRegistry::with_builtins();
+        let eth = |eth_type: EthType, payload: &[u8]| {
+            eth!(eth_type: eth_type, payload: payload).inner().clone()
+        };
+        let ipv4 = |protocol: IpProtocol, payload: &[u8]| {
+            ipv4!(protocol: protocol, payload: payload).inner().clone()
+        };
+        let udp = |dst_port: u16, payload: &[u8]| {
+            udp!(src_port: 40000u16, dst_port: dst_port, payload: payload)
+                .inner()
+                .clone()
+        };
+
+        let dns = crate::dns!(id: 1u16);
+        let ppp = ppp!(
+            protocol: PppProtocol::Ipv4,
+            payload: ipv4(IpProtocol::Udp, &udp(53, dns.inner())),
+        );
+        let l2tp = l2tp!(tunnel_id: 1u16, session_id: 2u16, payload: ppp.inner());
+        let ah = ah!(next_header: IpProtocol::Udp, payload: udp(1701, l2tp.inner()));
+        let gre = gre!(
+            protocol_type: EthType::Ipv4,
+            payload: ipv4(IpProtocol::UdpLite, crate::udplite!(src_port: 1u16, dst_port: 2u16).inner()),
+        );
+        let tcp = tcp!(src_port: 40000u16, dst_port: 80u16);
+        let ipv6 = ipv6!(next_header: IpProtocol::Tcp, payload: tcp.inner());
+        let vlan = vlan!(vid: 7u16, eth_type: EthType::Ipv6, payload: ipv6.inner());
+        let dccp = crate::dccp!(src_port: 1u16, dst_port: 2u16, packet_type: DccpType::Data);
+        let dccp = ipv6!(next_header: IpProtocol::Dccp, payload: dccp.inner());
+        let llc = llc!(
+            dsap: crate::layer::llc::SAP_STP,
+            ssap: crate::layer::llc::SAP_STP,
+            control: crate::layer::llc::CONTROL_UI,
+        );
+        let eap = eap!(code: EapCode::Request, identifier: 1u8, method: EapMethod::Identity);
+        let eapol = eapol!(packet_type: EapolType::EapPacket, body: eap.inner());
+        let openvpn = openvpn!(
+            opcode: OpenVpnOpcode::ControlHardResetClientV2,
+            session_id: 1u64,
+        );
+        let wireguard = wireguard!(
+            message_type: WireGuardMessageType::TransportData,
+            receiver_index: 1u32,
+        );
+        let mut null = crate::layer::null::AF_INET.to_le_bytes().to_vec();
+        null.extend(ipv4(IpProtocol::Esp, esp!(spi: 1u32).inner()));
+        let sll2 = sll2!(protocol: EthType::Ipv6, payload: dccp.inner());
+
+        let frames = [
+            (
+                EthDissector.name(),
+                eth(EthType::Ipv4, &ipv4(IpProtocol::Ah, ah.inner())),
+            ),
+            (
+                EthDissector.name(),
+                eth(EthType::Ipv4, &ipv4(IpProtocol::Gre, gre.inner())),
+            ),
+            (
+                EthDissector.name(),
+                eth(
+                    EthType::Ipv4,
+                    &ipv4(
+                        IpProtocol::Icmp,
+                        icmp!(icmp_type: IcmpType::EchoRequest).inner(),
+                    ),
+                ),
+            ),
+            (EthDissector.name(), eth(EthType::Vlan, vlan.inner())),
+            (
+                EthDissector.name(),
+                eth(EthType::from(llc.inner().len() as u16), llc.inner()),
+            ),
+            (EthDissector.name(), eth(EthType::Eapol, eapol.inner())),
+            (
+                EthDissector.name(),
+                eth(EthType::Goose, goose!(appid: 1u16).inner()),
+            ),
+            (
+                EthDissector.name(),
+                eth(EthType::Sv, crate::sv!(appid: 1u16).inner()),
+            ),
+            (
+                EthDissector.name(),
+                eth(
+                    EthType::Ptp,
+                    ptp!(message_type: PtpMessageType::Sync).inner(),
+                ),
+            ),
+            (
+                EthDissector.name(),
+                eth(
+                    EthType::Ipv4,
+                    &ipv4(IpProtocol::Udp, &udp(443, quic!(version: 1u32).inner())),
+                ),
+            ),
+            (
+                EthDissector.name(),
+                eth(
+                    EthType::Ipv4,
+                    &ipv4(IpProtocol::Udp, &udp(1194, openvpn.inner())),
+                ),
+            ),
+            (
+                EthDissector.name(),
+                eth(
+                    EthType::Ipv4,
+                    &ipv4(
+                        IpProtocol::Udp,
+                        &udp(5004, rtp!(payload_type: 96u8).inner()),
+                    ),
+                ),
+            ),
+            (
+                EthDissector.name(),
+                eth(
+                    EthType::Ipv4,
+                    &ipv4(IpProtocol::Udp, &udp(51820, wireguard.inner())),
+                ),
+            ),
+            (NullDissector.name(), null),
+            (Sll2Dissector.name(), sll2.inner().clone()),
+        ];
+
+        let mut seen = std::collections::BTreeSet::new();
+        for (link, frame) in &frames {
+            let dissected = registry.dissect(NextHint::Named(link), frame);
+            assert!(dissected.error.is_none(), "{:?}", dissected.error);
+            assert!(
+                dissected.unrecognized.is_none(),
+                "{:?}",
+                dissected.unrecognized
+            );
+            seen.extend(dissected.layers.iter().map(|l| l.name()));
+        }
+        for name in registry.names() {
+            assert!(seen.contains(name), "{name} not dissected");
+        }
+
+        let dissected = registry.dissect_eth(&frames[0].1);
+        let names: Vec<_> = dissected.layers.iter().map(|l| l.name()).collect();
+        assert_eq!(
+            names,
+            ["Eth", "Ipv4", "Ah", "Udp", "L2tp", "Ppp", "Ipv4", "Udp", "Dns"]
+        );
+        let dissected = registry.dissect_eth(&frames[3].1);
+        assert_eq!(dissected.offset, 14 + 4 + 40 + 20);
+        assert_eq!(dissected.field("Ipv6.next_header"), ["Tcp"]);
+    }
+}
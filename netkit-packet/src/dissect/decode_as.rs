@@ -0,0 +1,155 @@
+//! "Decode As" overrides.
+//!
+//! Dissectors recognize protocols by their well-known ports and numbers, but
+//! nonstandard ports are everywhere. A [`DecodeKey`] names a port, Eth type
+//! or IP protocol; mapping it to a dissector with
+//! [`DissectorRegistry::decode_as`](super::DissectorRegistry::decode_as)
+//! makes the registry use that dissector for it, like Wireshark's "Decode
+//! As":
+//!
+//! ```
+//! use netkit_packet::dissect::{decode_as::DecodeKey, DissectorRegistry};
+//! # use netkit_packet::prelude::*;
+//!
+//! let dns = [0u8; 12];
+//! let udp = udp!(src_port: 40000u16, dst_port: 8053u16, payload: dns);
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//! let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+//!
+//! let mut registry = DissectorRegistry::with_builtins();
+//! registry.decode_as("udp.port:8053".parse().unwrap(), "Dns");
+//! assert!(registry.dissect_eth(eth.inner()).layer("Dns").is_some());
+//! ```
+
+use core::{fmt, str::FromStr};
+
+use super::NextHint;
+
+/// Error type for parsing a [`DecodeKey`].
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[error("Invalid decode-as key {0:?}: expected e.g. udp.port:8053 or eth.type:0x88b5")]
+pub struct DecodeKeyError(pub String);
+
+/// What a "Decode As" override applies to.
+///
+/// Written as in Wireshark's decode-as table, e.g. `udp.port:8053`,
+/// `tcp.port:8443`, `eth.type:0x88b5` or `ip.proto:253`.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DecodeKey {
+    /// Either port of a UDP datagram.
+    UdpPort(u16),
+    /// Either port of a TCP segment.
+    TcpPort(u16),
+    /// An Eth type.
+    EthType(u16),
+    /// An IP protocol.
+    IpProtocol(u8),
+}
+
+impl DecodeKey {
+    /// Get the keys matching a hint, most specific first.
+    ///
+    /// The destination port comes before the source port, as servers
+    /// usually listen on the destination port of requests.
+    pub fn for_hint(hint: &NextHint) -> Vec<DecodeKey> {
+        match *hint {
+            NextHint::Udp { src_port, dst_port } => {
+                vec![DecodeKey::UdpPort(dst_port), DecodeKey::UdpPort(src_port)]
+            }
+            NextHint::Tcp { src_port, dst_port } => {
+                vec![DecodeKey::TcpPort(dst_port), DecodeKey::TcpPort(src_port)]
+            }
+            NextHint::EthType(eth_type) => vec![DecodeKey::EthType(eth_type.into())],
+            NextHint::IpProtocol(protocol) => vec![DecodeKey::IpProtocol(protocol.into())],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeKey::UdpPort(port) => write!(f, "udp.port:{port}"),
+            DecodeKey::TcpPort(port) => write!(f, "tcp.port:{port}"),
+            DecodeKey::EthType(eth_type) => write!(f, "eth.type:{eth_type:#06x}"),
+            DecodeKey::IpProtocol(protocol) => write!(f, "ip.proto:{protocol}"),
+        }
+    }
+}
+
+impl FromStr for DecodeKey {
+    type Err = DecodeKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || DecodeKeyError(s.to_string());
+        let (field, value) = s.split_once(':').ok_or_else(error)?;
+        let value = value.trim();
+        let number = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+
+        match field.trim() {
+            "udp.port" => value.parse().map(DecodeKey::UdpPort),
+            "tcp.port" => value.parse().map(DecodeKey::TcpPort),
+            "eth.type" => number(value).map(DecodeKey::EthType),
+            "ip.proto" => value.parse().map(DecodeKey::IpProtocol),
+            _ => return Err(error()),
+        }
+        .map_err(|_| error())
+    }
+}
+
+impl TryFrom<String> for DecodeKey {
+    type Error = DecodeKeyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DecodeKey> for String {
+    fn from(key: DecodeKey) -> Self {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn decode_key() {
+        for (s, key) in [
+            ("udp.port:8053", DecodeKey::UdpPort(8053)),
+            ("tcp.port:8443", DecodeKey::TcpPort(8443)),
+            ("eth.type:0x88b5", DecodeKey::EthType(0x88B5)),
+            ("ip.proto:253", DecodeKey::IpProtocol(253)),
+        ] {
+            assert_eq!(s.parse::<DecodeKey>(), Ok(key));
+            assert_eq!(key.to_string(), s);
+        }
+        assert_eq!("eth.type:2048".parse(), Ok(DecodeKey::EthType(0x0800)));
+        assert!("udp.port:70000".parse::<DecodeKey>().is_err());
+        assert!("sctp.port:80".parse::<DecodeKey>().is_err());
+
+        let hint = NextHint::Tcp {
+            src_port: 40000,
+            dst_port: 8443,
+        };
+        assert_eq!(
+            DecodeKey::for_hint(&hint),
+            [DecodeKey::TcpPort(8443), DecodeKey::TcpPort(40000)]
+        );
+        assert_eq!(
+            DecodeKey::for_hint(&NextHint::EthType(EthType::Ipv4)),
+            [DecodeKey::EthType(0x0800)]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Packets of any link type, dissected in one call.
+//!
+//! [`Packet::parse_from`] dispatches on the link type of the capture
+//! (Ethernet, SLL2, BSD loopback or raw IP, see [`link::network`]), dissects
+//! the layers with the built-in dissectors, and reports what looks wrong as
+//! [`ExpertEvent`]s, like Wireshark's expert info:
+//!
+//! ```
+//! use netkit_packet::dissect::packet::{Packet, Severity};
+//! use netkit_packet::prelude::*;
+//!
+//! let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//!
+//! let packet = Packet::parse_from(LinkType::Raw, ipv4.inner());
+//! let names: Vec<_> = packet.layers().iter().map(|l| l.name()).collect();
+//! assert_eq!(names, ["Ipv4", "Udp"]);
+//! // The builder leaves the header checksum zero.
+//! assert_eq!(packet.max_severity(), Some(Severity::Warning));
+//! ```
+
+use std::sync::OnceLock;
+
+use super::{Dissected, DissectorRegistry, Layer, NextHint};
+use crate::{
+    layer::link,
+    prelude::*,
+    utils::checksum::{internet_checksum, partial_checksum},
+};
+
+/// Severity of an expert event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Unusual but valid, e.g. padding after the IP packet.
+    Note,
+    /// Likely a problem, e.g. a bad checksum.
+    Warning,
+    /// The packet could not be dissected further.
+    Error,
+}
+
+/// Something noteworthy found while dissecting a packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpertEvent {
+    /// Severity of the event.
+    pub severity: Severity,
+    /// Name of the layer concerned, or `Link` for the link-layer header.
+    pub layer: &'static str,
+    /// Offset of the layer in the packet.
+    pub offset: usize,
+    /// Description of the event.
+    pub message: String,
+}
+
+/// Dissected packet
+pub struct Packet {
+    link_type: LinkType,
+    dissected: Dissected,
+    offsets: Vec<usize>,
+    events: Vec<ExpertEvent>,
+}
+
+impl Packet {
+    /// Dissect a packet with the built-in dissectors.
+    pub fn parse_from(link_type: LinkType, data: &[u8]) -> Self {
+        static BUILTINS: OnceLock<DissectorRegistry> = OnceLock::new();
+        Self::parse_with(
+            BUILTINS.get_or_init(DissectorRegistry::with_builtins),
+            link_type,
+            data,
+        )
+    }
+
+    /// Dissect a packet with the dissectors of a registry.
+    pub fn parse_with(registry: &DissectorRegistry, link_type: LinkType, data: &[u8]) -> Self {
+        let mut packet = Self {
+            link_type,
+            dissected: Dissected::default(),
+            offsets: Vec::new(),
+            events: Vec::new(),
+        };
+
+        let start = if link_type == LinkType::Ethernet {
+            packet.dissected = registry.dissect_eth(data);
+            0
+        } else if let Some((eth_type, payload)) = link::network(link_type, data) {
+            packet.dissected = registry.dissect(NextHint::EthType(eth_type), payload);
+            payload.as_ptr() as usize - data.as_ptr() as usize
+        } else {
+            packet.event(
+                Severity::Error,
+                "Link",
+                0,
+                format!("Unsupported or malformed {link_type} header"),
+            );
+            return packet;
+        };
+
+        let mut offset = start;
+        for layer in &packet.dissected.layers {
+            packet.offsets.push(offset);
+            offset = (offset + layer.header_len()).min(data.len());
+        }
+        packet.dissected.offset = offset;
+
+        if let Some(error) = packet.dissected.error.clone() {
+            let super::DissectError::Malformed { plugin, reason } = error;
+            packet.event(Severity::Error, plugin, offset, reason);
+        }
+        packet.check(data);
+        packet
+    }
+
+    /// Get the link type of the packet.
+    pub fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    /// Get the layers, outermost first.
+    ///
+    /// The link-layer header of non-Ethernet packets is not a layer.
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.dissected.layers
+    }
+
+    /// Get the offsets of the layers in the packet.
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+    /// Get the dissection, e.g. to look up fields.
+    pub fn dissected(&self) -> &Dissected {
+        &self.dissected
+    }
+
+    /// Get the expert events, in the order they were found.
+    pub fn events(&self) -> &[ExpertEvent] {
+        &self.events
+    }
+
+    /// Get the highest severity of the events, if any.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.events.iter().map(|e| e.severity).max()
+    }
+
+    fn event(&mut self, severity: Severity, layer: &'static str, offset: usize, message: String) {
+        self.events.push(ExpertEvent {
+            severity,
+            layer,
+            offset,
+            message,
+        });
+    }
+
+    /// Check the lengths and checksums of the IPv4, UDP and TCP layers.
+    fn check(&mut self, data: &[u8]) {
+        let layers: Vec<_> = self
+            .dissected
+            .layers
+            .iter()
+            .zip(&self.offsets)
+            .map(|(layer, offset)| (layer.name(), *offset))
+            .collect();
+
+        for (name, offset) in layers {
+            if name != "Ipv4" {
+                continue;
+            }
+            let Ok(ipv4) = Ipv4::new(&data[offset..]) else {
+                continue;
+            };
+
+            let header_len = (ipv4.ihl().get() as usize * 4).min(ipv4.inner().len());
+            if internet_checksum(&ipv4.inner()[..header_len]) != 0 {
+                self.event(
+                    Severity::Warning,
+                    "Ipv4",
+                    offset,
+                    format!("Bad header checksum {:#06x}", ipv4.checksum().get()),
+                );
+            }
+
+            let total_length = ipv4.total_length().get() as usize;
+            let available = ipv4.inner().len();
+            if total_length > available {
+                self.event(
+                    Severity::Warning,
+                    "Ipv4",
+                    offset,
+                    format!("Truncated: total length {total_length}, {available} bytes captured"),
+                );
+                continue;
+            } else if total_length < available {
+                self.event(
+                    Severity::Note,
+                    "Ipv4",
+                    offset,
+                    format!("{} bytes of padding", available - total_length),
+                );
+            }
+
+            let transport = match ipv4.protocol().get() {
+                IpProtocol::Udp => ipv4.udp().map(|udp| ("Udp", udp.checksum().get())),
+                IpProtocol::Tcp => ipv4.tcp().map(|tcp| ("Tcp", tcp.checksum().get())),
+                _ => None,
+            };
+            let (Some((layer, checksum)), Some(expected)) = (transport, ipv4.transport_checksum())
+            else {
+                continue;
+            };
+            if (layer == "Udp" && checksum == 0) || checksum == expected {
+                continue;
+            }
+            // Captured on a host offloading checksums to its NIC.
+            let partial = partial_checksum(
+                ipv4.src().get().into(),
+                ipv4.dst().get().into(),
+                ipv4.protocol().get(),
+                total_length - header_len,
+            );
+            if checksum == partial {
+                self.event(
+                    Severity::Note,
+                    layer,
+                    offset + header_len,
+                    "Partial checksum, likely offloaded".to_string(),
+                );
+            } else {
+                self.event(
+                    Severity::Warning,
+                    layer,
+                    offset + header_len,
+                    format!("Bad checksum {checksum:#06x}, expected {expected:#06x}"),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::layer::tcp::TcpBuilder;
+
+    #[test]
+    fn packet_parse_from() {
+        let udp = udp!(src_port: 1234u16, dst_port: 9999u16, payload: [0; 4]);
+        let mut ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let checksum = internet_checksum(&ipv4.inner()[..20]);
+        ipv4.checksum_mut().set(checksum);
+        ipv4.update_transport_checksum();
+        let sll2 = sll2!(protocol: EthType::Ipv4, payload: ipv4.inner());
+
+        let packet = Packet::parse_from(LinkType::Sll2, sll2.inner());
+        let names: Vec<_> = packet.layers().iter().map(|l| l.name()).collect();
+        assert_eq!(names, ["Ipv4", "Udp"]);
+        assert_eq!(packet.offsets(), [20, 40]);
+        assert!(packet.events().is_empty());
+
+        // Corrupted UDP checksum and Ethernet padding.
+        let mut ipv4 = ipv4.inner().clone();
+        ipv4[26] ^= 0xFF;
+        let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4).inner().clone();
+        frame.extend_from_slice(&[0; 6]);
+        let packet = Packet::parse_from(LinkType::Ethernet, &frame);
+        let events: Vec<_> = packet
+            .events()
+            .iter()
+            .map(|e| (e.severity, e.layer, e.offset))
+            .collect();
+        assert_eq!(
+            events,
+            [(Severity::Note, "Ipv4", 14), (Severity::Warning, "Udp", 34)]
+        );
+
+        let tcp = TcpBuilder::new()
+            .pseudo_header((Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED))
+            .partial_checksum(true)
+            .build();
+        let ipv4 = ipv4!(protocol: IpProtocol::Tcp, checksum: 0xBAFFu16, payload: tcp.inner());
+        let packet = Packet::parse_from(LinkType::Raw, ipv4.inner());
+        assert_eq!(packet.events()[1].severity, Severity::Note);
+        assert_eq!(packet.events()[1].layer, "Tcp");
+
+        let packet = Packet::parse_from(LinkType::Unknown(12345), &frame);
+        assert!(packet.layers().is_empty());
+        assert_eq!(packet.max_severity(), Some(Severity::Error));
+    }
+}
@@ -0,0 +1,109 @@
+//! Wireshark display filter field names.
+//!
+//! Users migrating from tshark-based scripts know fields by their Wireshark
+//! display filter names (`ip.src`, `tcp.port`, `dns.qry.name`). This module
+//! maps those names onto the fields reported by [`Layer::fields`], so that
+//! [`Dissected::field`] accepts both spellings.
+//!
+//! Some Wireshark names cover several netkit fields: `ip.addr` matches both
+//! the source and the destination address, like in display filters.
+//!
+//! TCP sequence numbers are mapped to `tcp.seq_raw` and `tcp.ack_raw` only,
+//! since Wireshark's `tcp.seq` and `tcp.ack` are relative to the first
+//! segment of the flow.
+//!
+//! [`Layer::fields`]: super::Layer::fields
+//! [`Dissected::field`]: super::Dissected::field
+
+/// Wireshark field and the netkit fields it covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WiresharkField {
+    /// Wireshark display filter name.
+    pub name: &'static str,
+    /// Name of the netkit layer.
+    pub layer: &'static str,
+    /// Names of the netkit fields.
+    pub fields: &'static [&'static str],
+}
+
+macro_rules! wireshark_fields {
+    ($($name: literal => $layer: literal [$($field: literal),+]),* $(,)?) => {
+        &[$(WiresharkField {
+            name: $name,
+            layer: $layer,
+            fields: &[$($field),+],
+        }),*]
+    };
+}
+
+/// Known Wireshark fields.
+pub const WIRESHARK_FIELDS: &[WiresharkField] = wireshark_fields![
+    "eth.dst" => "Eth" ["dst"],
+    "eth.src" => "Eth" ["src"],
+    "eth.addr" => "Eth" ["dst", "src"],
+    "eth.type" => "Eth" ["eth_type"],
+    "vlan.priority" => "Vlan" ["pcp"],
+    "vlan.dei" => "Vlan" ["dei"],
+    "vlan.id" => "Vlan" ["vid"],
+    "vlan.etype" => "Vlan" ["eth_type"],
+    "ip.src" => "Ipv4" ["src"],
+    "ip.dst" => "Ipv4" ["dst"],
+    "ip.addr" => "Ipv4" ["src", "dst"],
+    "ip.ttl" => "Ipv4" ["ttl"],
+    "ip.proto" => "Ipv4" ["protocol"],
+    "ip.len" => "Ipv4" ["total_length"],
+    "udp.srcport" => "Udp" ["src_port"],
+    "udp.dstport" => "Udp" ["dst_port"],
+    "udp.port" => "Udp" ["src_port", "dst_port"],
+    "udp.length" => "Udp" ["length"],
+    "tcp.srcport" => "Tcp" ["src_port"],
+    "tcp.dstport" => "Tcp" ["dst_port"],
+    "tcp.port" => "Tcp" ["src_port", "dst_port"],
+    "tcp.seq_raw" => "Tcp" ["seq_num"],
+    "tcp.ack_raw" => "Tcp" ["ack_num"],
+    "tcp.flags" => "Tcp" ["flags"],
+    "dns.id" => "Dns" ["id"],
+    "dns.flags.response" => "Dns" ["qr"],
+    "dns.flags.opcode" => "Dns" ["opcode"],
+    "dns.flags.rcode" => "Dns" ["rcode"],
+    "dns.count.queries" => "Dns" ["qdcount"],
+    "dns.count.answers" => "Dns" ["ancount"],
+    "dns.qry.name" => "Dns" ["qname"],
+    "dns.qry.type" => "Dns" ["qtype"],
+    "dns.qry.class" => "Dns" ["qclass"],
+];
+
+/// Look up a Wireshark field by its display filter name.
+pub fn lookup(name: &str) -> Option<&'static WiresharkField> {
+    WIRESHARK_FIELDS.iter().find(|f| f.name == name)
+}
+
+/// Get the Wireshark name of a netkit field.
+///
+/// Only exact equivalents are returned, not names covering several fields
+/// like `ip.addr`.
+pub fn wireshark_name(layer: &str, field: &str) -> Option<&'static str> {
+    WIRESHARK_FIELDS
+        .iter()
+        .find(|f| f.layer == layer && f.fields == [field])
+        .map(|f| f.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wireshark_names() {
+        assert_eq!(lookup("tcp.port").unwrap().fields, ["src_port", "dst_port"]);
+        assert_eq!(lookup("tcp.seq"), None);
+        assert_eq!(wireshark_name("Ipv4", "src"), Some("ip.src"));
+        assert_eq!(wireshark_name("Dns", "qname"), Some("dns.qry.name"));
+        assert_eq!(wireshark_name("Ipv4", "checksum"), None);
+
+        // Names are unique.
+        for (i, field) in WIRESHARK_FIELDS.iter().enumerate() {
+            assert!(WIRESHARK_FIELDS[..i].iter().all(|f| f.name != field.name));
+        }
+    }
+}
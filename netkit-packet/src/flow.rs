@@ -0,0 +1,273 @@
+//! Flow identification.
+
+use core::net::IpAddr;
+
+use crate::prelude::*;
+
+pub mod fast;
+pub mod keying;
+pub mod siphash;
+pub mod store;
+pub mod table;
+
+/// Extra fields refining a flow key.
+///
+/// Set by a [`FlowKeying`](keying::FlowKeying) for the fields it includes;
+/// unset fields do not split flows.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, rename_all = "kebab-case")
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FlowScope {
+    /// VLAN identifier of the outermost tag.
+    pub vlan: Option<u16>,
+    /// IPv6 flow label.
+    pub flow_label: Option<u32>,
+    /// DSCP of the IP header.
+    pub dscp: Option<u8>,
+    /// Tunnel identifier, e.g. the GRE key.
+    pub tunnel_id: Option<u32>,
+}
+
+impl FlowScope {
+    /// Whether no field is set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl core::fmt::Display for FlowScope {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut sep = "";
+        let mut field = |f: &mut core::fmt::Formatter<'_>, name, value: Option<u32>| {
+            if let Some(value) = value {
+                write!(f, "{sep}{name}={value}")?;
+                sep = " ";
+            }
+            Ok(())
+        };
+        field(f, "vlan", self.vlan.map(u32::from))?;
+        field(f, "flow-label", self.flow_label)?;
+        field(f, "dscp", self.dscp.map(u32::from))?;
+        field(f, "tunnel", self.tunnel_id)
+    }
+}
+
+/// Flow key
+///
+/// The classic 5-tuple identifying a transport conversation. Packets without
+/// ports (e.g. ICMP) use port `0`. The [scope](FlowScope) optionally refines
+/// it, e.g. to tell apart the same conversation in two VLANs.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    /// Source IP address.
+    pub src: IpAddr,
+    /// Destination IP address.
+    pub dst: IpAddr,
+    /// Source port.
+    pub src_port: u16,
+    /// Destination port.
+    pub dst_port: u16,
+    /// IP protocol.
+    pub protocol: IpProtocol,
+    /// Extra fields, empty unless included by a [`FlowKeying`](keying::FlowKeying).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "FlowScope::is_empty")
+    )]
+    pub scope: FlowScope,
+}
+
+impl FlowKey {
+    /// Create a new flow key.
+    pub fn new(
+        src: impl Into<IpAddr>,
+        dst: impl Into<IpAddr>,
+        src_port: u16,
+        dst_port: u16,
+        protocol: IpProtocol,
+    ) -> Self {
+        Self {
+            src: src.into(),
+            dst: dst.into(),
+            src_port,
+            dst_port,
+            protocol,
+            scope: FlowScope::default(),
+        }
+    }
+
+    /// Set the scope of the key.
+    pub fn with_scope(mut self, scope: FlowScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Extract the flow key from an Ipv4 layer.
+    pub fn from_ipv4<T: AsRef<[u8]>>(ipv4: &Ipv4<T>) -> Self {
+        let protocol = ipv4.protocol().get();
+        let (src_port, dst_port) = if let Some(tcp) = ipv4.tcp() {
+            (tcp.src_port().get(), tcp.dst_port().get())
+        } else if let Some(udp) = ipv4.udp() {
+            (udp.src_port().get(), udp.dst_port().get())
+        } else if let Some(udplite) = ipv4.udplite() {
+            (udplite.src_port().get(), udplite.dst_port().get())
+        } else if let Some(dccp) = ipv4.dccp() {
+            (dccp.src_port().get(), dccp.dst_port().get())
+        } else {
+            (0, 0)
+        };
+
+        Self::new(
+            ipv4.src().get(),
+            ipv4.dst().get(),
+            src_port,
+            dst_port,
+            protocol,
+        )
+    }
+
+    /// Extract the flow key from an Ipv6 layer.
+    ///
+    /// The protocol is the upper-layer protocol behind the extension headers.
+    pub fn from_ipv6<T: AsRef<[u8]>>(ipv6: &Ipv6<T>) -> Self {
+        let protocol = ipv6
+            .upper_layer()
+            .map_or(ipv6.next_header().get(), |(protocol, _)| protocol);
+        let (src_port, dst_port) = if let Some(tcp) = ipv6.tcp() {
+            (tcp.src_port().get(), tcp.dst_port().get())
+        } else if let Some(udp) = ipv6.udp() {
+            (udp.src_port().get(), udp.dst_port().get())
+        } else if let Some(udplite) = ipv6.udplite() {
+            (udplite.src_port().get(), udplite.dst_port().get())
+        } else if let Some(dccp) = ipv6.dccp() {
+            (dccp.src_port().get(), dccp.dst_port().get())
+        } else {
+            (0, 0)
+        };
+
+        Self::new(
+            ipv6.src().get(),
+            ipv6.dst().get(),
+            src_port,
+            dst_port,
+            protocol,
+        )
+    }
+
+    /// Extract the flow key from an Eth layer.
+    ///
+    /// Returns `None` if the frame does not carry a supported IP layer.
+    pub fn from_eth<T: AsRef<[u8]>>(eth: &Eth<T>) -> Option<Self> {
+        if let Some(ipv4) = eth.ipv4() {
+            return Some(Self::from_ipv4(&ipv4));
+        }
+        eth.ipv6().map(|ipv6| Self::from_ipv6(&ipv6))
+    }
+
+    /// Get the key of the opposite direction.
+    pub fn reversed(&self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            protocol: self.protocol,
+            scope: self.scope,
+        }
+    }
+
+    /// Whether the key is in canonical order (the lower endpoint is the source).
+    pub fn is_canonical(&self) -> bool {
+        (self.src, self.src_port) <= (self.dst, self.dst_port)
+    }
+
+    /// Get the direction-independent key of this flow.
+    ///
+    /// Both directions of a conversation map to the same canonical key.
+    pub fn canonical(&self) -> Self {
+        if self.is_canonical() {
+            *self
+        } else {
+            self.reversed()
+        }
+    }
+}
+
+impl core::fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} {}:{} -> {}:{}",
+            self.protocol, self.src, self.src_port, self.dst, self.dst_port
+        )?;
+        if !self.scope.is_empty() {
+            write!(f, " [{}]", self.scope)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::layer::{ip::Ipv4Builder, udp::UdpBuilder};
+
+    #[test]
+    fn flow_key_from_eth() {
+        let udp = UdpBuilder::new().src_port(1234u16).dst_port(53u16).build();
+        let ipv4 = Ipv4Builder::new()
+            .src(Ipv4Addr::new(10, 0, 0, 2))
+            .dst(Ipv4Addr::new(10, 0, 0, 1))
+            .protocol(IpProtocol::Udp)
+            .payload(udp.inner())
+            .build();
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+
+        let key = FlowKey::from_eth(&eth).unwrap();
+        assert_eq!(key.src, Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(key.dst, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(key.src_port, 1234);
+        assert_eq!(key.dst_port, 53);
+        assert_eq!(key.protocol, IpProtocol::Udp);
+        assert_eq!(key.to_string(), "Udp 10.0.0.2:1234 -> 10.0.0.1:53");
+    }
+
+    #[test]
+    fn flow_key_from_ipv6() {
+        let udp = UdpBuilder::new().src_port(1234u16).dst_port(53u16).build();
+        let ipv6 = ipv6!(
+            src: Ipv6Addr::LOCALHOST,
+            next_header: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        let eth = eth!(eth_type: EthType::Ipv6, payload: ipv6.inner());
+
+        let key = FlowKey::from_eth(&eth).unwrap();
+        assert_eq!(key.src, Ipv6Addr::LOCALHOST);
+        assert_eq!(key.dst, Ipv6Addr::UNSPECIFIED);
+        assert_eq!(key.dst_port, 53);
+        assert_eq!(key.protocol, IpProtocol::Udp);
+    }
+
+    #[test]
+    fn flow_key_canonical() {
+        let key = FlowKey::new(
+            Ipv4Addr::new(10, 0, 0, 2),
+            Ipv4Addr::new(10, 0, 0, 1),
+            1234,
+            53,
+            IpProtocol::Udp,
+        );
+
+        assert!(!key.is_canonical());
+        assert!(key.reversed().is_canonical());
+        assert_eq!(key.canonical(), key.reversed().canonical());
+        assert_eq!(key.reversed().reversed(), key);
+    }
+}
@@ -0,0 +1,254 @@
+//! Fast 5-tuple extraction.
+//!
+//! Workloads touching every packet of a capture only for its flow, such as
+//! indexing, spend most of their time validating and constructing layers
+//! they never use. [`extract`] reads the 5-tuple and the TCP flags straight
+//! from the raw bytes with fixed offsets and bounds checks only.
+//!
+//! The fast path understands Ethernet with up to two VLAN tags, the other
+//! link types of [`link::network`], IPv4 and IPv6 with extension headers.
+//! Non-first fragments carry no transport header, so their ports are `0`.
+
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::{
+    layer::link::{self, LinkType},
+    prelude::*,
+};
+
+/// Flow fields extracted by the fast path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FastTuple {
+    /// Flow key of the packet.
+    pub key: FlowKey,
+    /// TCP flag bits, `0` for other protocols.
+    pub tcp_flags: u8,
+    /// Whether the packet is an IP fragment.
+    pub fragment: bool,
+}
+
+/// How flows are extracted from packets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FastPath {
+    /// Construct and validate the layers, as the rest of the crate does.
+    #[default]
+    Layers,
+    /// Read the fields from the raw bytes with [`extract`].
+    Raw,
+}
+
+impl FastPath {
+    /// Get the flow key of a packet of any supported link type.
+    pub fn flow_key(self, link_type: LinkType, data: &[u8]) -> Option<FlowKey> {
+        match self {
+            FastPath::Layers => match link::network(link_type, data)? {
+                (EthType::Ipv4, payload) => Some(FlowKey::from_ipv4(&Ipv4::new(payload).ok()?)),
+                (EthType::Ipv6, payload) => Some(FlowKey::from_ipv6(&Ipv6::new(payload).ok()?)),
+                _ => None,
+            },
+            FastPath::Raw => extract(link_type, data).map(|tuple| tuple.key),
+        }
+    }
+}
+
+const ETH_TYPE_IPV4: u16 = 0x0800;
+const ETH_TYPE_IPV6: u16 = 0x86DD;
+const ETH_TYPE_VLAN: u16 = 0x8100;
+const ETH_TYPE_QINQ: u16 = 0x88A8;
+
+/// Extract the flow fields of a packet of any supported link type.
+pub fn extract(link_type: LinkType, data: &[u8]) -> Option<FastTuple> {
+    if link_type == LinkType::Ethernet {
+        return extract_eth(data);
+    }
+    let (_, payload) = link::network(link_type, data)?;
+    extract_ip(payload)
+}
+
+/// Extract the flow fields of an Ethernet frame.
+pub fn extract_eth(frame: &[u8]) -> Option<FastTuple> {
+    let mut offset = 12;
+    let mut eth_type = read_u16(frame, offset)?;
+    for _ in 0..2 {
+        if eth_type != ETH_TYPE_VLAN && eth_type != ETH_TYPE_QINQ {
+            break;
+        }
+        offset += 4;
+        eth_type = read_u16(frame, offset)?;
+    }
+
+    match eth_type {
+        ETH_TYPE_IPV4 | ETH_TYPE_IPV6 => extract_ip(frame.get(offset + 2..)?),
+        _ => None,
+    }
+}
+
+/// Extract the flow fields of an IPv4 or IPv6 packet.
+pub fn extract_ip(packet: &[u8]) -> Option<FastTuple> {
+    match packet.first()? >> 4 {
+        4 => extract_ipv4(packet),
+        6 => extract_ipv6(packet),
+        _ => None,
+    }
+}
+
+fn extract_ipv4(packet: &[u8]) -> Option<FastTuple> {
+    let ihl = (packet.first()? & 0x0F) as usize * 4;
+    if ihl < 20 || packet.len() < ihl {
+        return None;
+    }
+
+    let fragment_field = read_u16(packet, 6)?;
+    let offset = fragment_field & 0x1FFF;
+    let fragment = offset != 0 || fragment_field & 0x2000 != 0;
+    let src: [u8; 4] = packet[12..16].try_into().ok()?;
+    let dst: [u8; 4] = packet[16..20].try_into().ok()?;
+    let transport = if offset == 0 { &packet[ihl..] } else { &[] };
+
+    Some(tuple(
+        IpAddr::V4(Ipv4Addr::from(src)),
+        IpAddr::V4(Ipv4Addr::from(dst)),
+        IpProtocol::from(packet[9]),
+        transport,
+        fragment,
+    ))
+}
+
+fn extract_ipv6(packet: &[u8]) -> Option<FastTuple> {
+    if packet.len() < 40 {
+        return None;
+    }
+    let src: [u8; 16] = packet[8..24].try_into().ok()?;
+    let dst: [u8; 16] = packet[24..40].try_into().ok()?;
+
+    let mut protocol = IpProtocol::from(packet[6]);
+    let mut data = &packet[40..];
+    let mut fragment = false;
+    loop {
+        let len = match protocol {
+            IpProtocol::Hopopt | IpProtocol::Ipv6Route | IpProtocol::Ipv6Opts => {
+                (*data.get(1)? as usize + 1) * 8
+            }
+            IpProtocol::Ipv6Frag => {
+                fragment = true;
+                if read_u16(data, 2)? >> 3 != 0 {
+                    data = &[];
+                    break;
+                }
+                8
+            }
+            _ => break,
+        };
+        protocol = IpProtocol::from(*data.first()?);
+        data = data.get(len..)?;
+    }
+
+    Some(tuple(
+        IpAddr::V6(Ipv6Addr::from(src)),
+        IpAddr::V6(Ipv6Addr::from(dst)),
+        protocol,
+        data,
+        fragment,
+    ))
+}
+
+fn tuple(
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: IpProtocol,
+    transport: &[u8],
+    fragment: bool,
+) -> FastTuple {
+    let header_len = match protocol {
+        IpProtocol::Tcp => 20,
+        IpProtocol::Udp | IpProtocol::UdpLite => 8,
+        IpProtocol::Dccp => 12,
+        _ => usize::MAX,
+    };
+    let (src_port, dst_port, tcp_flags) = if transport.len() >= header_len {
+        let flags = if protocol == IpProtocol::Tcp {
+            transport[13]
+        } else {
+            0
+        };
+        (
+            u16::from_be_bytes([transport[0], transport[1]]),
+            u16::from_be_bytes([transport[2], transport[3]]),
+            flags,
+        )
+    } else {
+        (0, 0, 0)
+    };
+
+    FastTuple {
+        key: FlowKey::new(src, dst, src_port, dst_port, protocol),
+        tcp_flags,
+        fragment,
+    }
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv4Addr;
+
+    use super::*;
+    use crate::layer::tcp::TcpFlags;
+
+    #[test]
+    fn fast_path_matches_layers() {
+        let tcp = tcp!(src_port: 40000u16, dst_port: 443u16, flags: TcpFlags::SYN);
+        let ipv4 = ipv4!(
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::Tcp,
+            payload: tcp.inner(),
+        );
+        let udp = udp!(src_port: 5353u16, dst_port: 53u16);
+        let ipv6 = ipv6!(
+            src: "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+            dst: "2001:db8::2".parse::<Ipv6Addr>().unwrap(),
+            next_header: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        let frames = [
+            eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()),
+            eth!(eth_type: EthType::Ipv6, payload: ipv6.inner()),
+        ];
+
+        for frame in &frames {
+            let layers = FastPath::Layers.flow_key(LinkType::Ethernet, frame.inner());
+            let raw = FastPath::Raw.flow_key(LinkType::Ethernet, frame.inner());
+            assert!(layers.is_some());
+            assert_eq!(layers, raw);
+        }
+
+        let tuple = extract_eth(frames[0].inner()).unwrap();
+        assert_eq!(tuple.tcp_flags, TcpFlags::SYN.bits());
+        assert!(!tuple.fragment);
+        assert_eq!(extract(LinkType::Raw, ipv4.inner()), Some(tuple));
+
+        // VLAN tagged frame
+        let mut tagged = frames[0].inner()[..12].to_vec();
+        tagged.extend([0x81, 0x00, 0x00, 0x0A]);
+        tagged.extend(&frames[0].inner()[12..]);
+        assert_eq!(extract_eth(&tagged), Some(tuple));
+    }
+
+    #[test]
+    fn fast_path_fragments() {
+        let udp = udp!(src_port: 1u16, dst_port: 2u16, payload: [0; 16]);
+        let mut ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        ipv4.fragment_offset_mut().set(3);
+
+        let tuple = extract_ip(ipv4.inner()).unwrap();
+        assert!(tuple.fragment);
+        assert_eq!((tuple.key.src_port, tuple.key.dst_port), (0, 0));
+        assert_eq!(extract_ip(&ipv4.inner()[..19]), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Configurable flow granularity.
+//!
+//! The 5-tuple is not always the right flow: the same addresses may be
+//! reused in several VLANs or tunnels, and QoS analyses want each DSCP class
+//! on its own. A [`FlowKeying`] chooses which extra fields go into the
+//! [scope](super::FlowScope) of the keys it extracts:
+//!
+//! ```
+//! use netkit_packet::flow::keying::FlowKeying;
+//! # use netkit_packet::prelude::*;
+//!
+//! let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+//! let ipv4 = ipv4!(dscp: 46u8, protocol: IpProtocol::Udp, payload: udp.inner());
+//! let eth = eth!(eth_type: EthType::Ipv4, vlan: 10u16, payload: ipv4.inner());
+//!
+//! let key = FlowKeying::new().vlan(true).dscp(true).key_eth(&eth).unwrap();
+//! assert_eq!(key.scope.vlan, Some(10));
+//! assert_eq!(key.scope.dscp, Some(46));
+//! assert_eq!(key.to_string(), "Udp 0.0.0.0:1234 -> 0.0.0.0:53 [vlan=10 dscp=46]");
+//! ```
+
+use super::{FlowKey, FlowScope};
+use crate::prelude::*;
+
+/// Fields included in flow keys besides the 5-tuple.
+///
+/// Nothing is included by default, giving plain 5-tuple keys.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, rename_all = "kebab-case")
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct FlowKeying {
+    /// Include the VLAN identifier of the outermost tag.
+    pub vlan: bool,
+    /// Include the IPv6 flow label.
+    pub flow_label: bool,
+    /// Include the DSCP (the IPv6 traffic class without ECN).
+    pub dscp: bool,
+    /// Key GRE-encapsulated IPv4 packets by the inner packet, scoped by the
+    /// GRE key (0 if absent).
+    pub tunnel_id: bool,
+}
+
+impl FlowKeying {
+    /// Create the default keying, i.e. plain 5-tuples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the VLAN identifier is included.
+    pub fn vlan(mut self, vlan: bool) -> Self {
+        self.vlan = vlan;
+        self
+    }
+
+    /// Set whether the IPv6 flow label is included.
+    pub fn flow_label(mut self, flow_label: bool) -> Self {
+        self.flow_label = flow_label;
+        self
+    }
+
+    /// Set whether the DSCP is included.
+    pub fn dscp(mut self, dscp: bool) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    /// Set whether GRE tunnels are keyed by their inner packets and key.
+    pub fn tunnel_id(mut self, tunnel_id: bool) -> Self {
+        self.tunnel_id = tunnel_id;
+        self
+    }
+
+    /// Extract the flow key of an Eth layer, behind any VLAN tags.
+    ///
+    /// Returns `None` if the frame does not carry a supported IP layer.
+    pub fn key_eth<T: AsRef<[u8]>>(&self, eth: &Eth<T>) -> Option<FlowKey> {
+        let vlan = eth.vlan_ids().first().copied();
+        let (eth_type, mut payload) = (eth.eth_type().get(), eth.payload());
+        let mut inner = eth_type;
+        while matches!(inner, EthType::Vlan | EthType::ServiceVlan) {
+            let tag = Vlan::new(payload).ok()?;
+            inner = tag.eth_type().get();
+            payload = &payload[Vlan::<&[u8]>::FIELD_PAYLOAD];
+        }
+
+        let mut key = match inner {
+            EthType::Ipv4 => self.key_ipv4(&Ipv4::new(payload).ok()?),
+            EthType::Ipv6 => self.key_ipv6(&Ipv6::new(payload).ok()?),
+            _ => return None,
+        };
+        if self.vlan {
+            key.scope.vlan = vlan;
+        }
+        Some(key)
+    }
+
+    /// Extract the flow key of an Ipv4 layer.
+    pub fn key_ipv4<T: AsRef<[u8]>>(&self, ipv4: &Ipv4<T>) -> FlowKey {
+        if self.tunnel_id {
+            if let Some(gre) = ipv4.gre() {
+                if let Some(inner) = gre.ipv4() {
+                    let mut key = self.key_ipv4(&inner);
+                    key.scope.tunnel_id = Some(gre.key().map_or(0, |key| key.get()));
+                    return key;
+                }
+            }
+        }
+
+        let scope = FlowScope {
+            dscp: self.dscp.then(|| ipv4.dscp().get()),
+            ..FlowScope::default()
+        };
+        FlowKey::from_ipv4(ipv4).with_scope(scope)
+    }
+
+    /// Extract the flow key of an Ipv6 layer.
+    pub fn key_ipv6<T: AsRef<[u8]>>(&self, ipv6: &Ipv6<T>) -> FlowKey {
+        let scope = FlowScope {
+            flow_label: self.flow_label.then(|| ipv6.flow_label().get()),
+            dscp: self.dscp.then(|| ipv6.traffic_class().get() >> 2),
+            ..FlowScope::default()
+        };
+        FlowKey::from_ipv6(ipv6).with_scope(scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn flow_keying() {
+        let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+        let ipv6 = ipv6!(
+            traffic_class: 0xB8u8,
+            flow_label: 0x12345u32,
+            src: Ipv6Addr::LOCALHOST,
+            next_header: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        let eth = eth!(eth_type: EthType::Ipv6, payload: ipv6.inner());
+
+        // Plain 5-tuple by default.
+        assert_eq!(FlowKeying::new().key_eth(&eth), FlowKey::from_eth(&eth));
+        let key = FlowKeying::new()
+            .flow_label(true)
+            .dscp(true)
+            .key_eth(&eth)
+            .unwrap();
+        assert_eq!(key.scope.flow_label, Some(0x12345));
+        assert_eq!(key.scope.dscp, Some(46));
+        assert_eq!(key.reversed().scope, key.scope);
+
+        // GRE tunnels are keyed by the inner packet and their key.
+        let inner = ipv4!(
+            src: Ipv4Addr::new(192, 168, 0, 1),
+            protocol: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        let gre = gre!(protocol_type: EthType::Ipv4, key: 7u32, payload: inner.inner());
+        let outer = ipv4!(protocol: IpProtocol::Gre, payload: gre.inner());
+
+        let key = FlowKeying::new().key_ipv4(&outer);
+        assert_eq!(key.protocol, IpProtocol::Gre);
+        let key = FlowKeying::new().tunnel_id(true).key_ipv4(&outer);
+        assert_eq!(key.src, Ipv4Addr::new(192, 168, 0, 1));
+        assert_eq!((key.dst_port, key.scope.tunnel_id), (53, Some(7)));
+    }
+}
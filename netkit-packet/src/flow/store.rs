@@ -0,0 +1,264 @@
+//! Per-flow counters with a selectable memory layout.
+//!
+//! Reporting over a large capture first updates millions of flows one
+//! packet at a time, then scans all of them to aggregate. A hash map of
+//! structs is fast for the updates but makes the scan walk the whole table,
+//! loading keys and every counter even to sum a single one.
+//!
+//! A [`FlowStore`] with [`StoreLayout::Soa`] keeps the counters in one
+//! array per field (struct of arrays) behind an index from flow key to
+//! position, so a scan over one counter reads contiguous memory. Updates pay
+//! one more indirection. The `flow_store` benchmark compares both layouts.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_packet::flow::store::{FlowStore, StoreLayout};
+//! # use netkit_packet::prelude::*;
+//! # use core::net::Ipv4Addr;
+//!
+//! let key = FlowKey::new(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, 1, 2, IpProtocol::Udp);
+//!
+//! let mut store = FlowStore::new(StoreLayout::Soa);
+//! store.record(key, Duration::from_secs(1), 100);
+//! store.record(key, Duration::from_secs(2), 50);
+//! assert_eq!(store.total_bytes(), 150);
+//! assert_eq!(store.get(&key).unwrap().packets, 2);
+//! ```
+
+use core::time::Duration;
+
+use super::{
+    table::{FlowTable, FlowTableConfig},
+    FlowKey,
+};
+
+/// Counters of a flow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FlowCounters {
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of bytes.
+    pub bytes: u64,
+    /// Timestamp of the first packet.
+    pub first_seen: Duration,
+    /// Timestamp of the last packet.
+    pub last_seen: Duration,
+}
+
+impl FlowCounters {
+    fn record(&mut self, ts: Duration, bytes: u64) {
+        if self.packets == 0 {
+            self.first_seen = ts;
+        }
+        self.packets += 1;
+        self.bytes += bytes;
+        self.last_seen = ts;
+    }
+}
+
+/// Memory layout of a flow store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StoreLayout {
+    /// A hash map from flow key to counters, fastest to update.
+    #[default]
+    Map,
+
+    /// One array per counter, fastest to scan.
+    Soa,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Soa {
+    index: FlowTable<usize>,
+    keys: Vec<FlowKey>,
+    packets: Vec<u64>,
+    bytes: Vec<u64>,
+    first_seen: Vec<Duration>,
+    last_seen: Vec<Duration>,
+}
+
+impl Soa {
+    fn get(&self, i: usize) -> FlowCounters {
+        FlowCounters {
+            packets: self.packets[i],
+            bytes: self.bytes[i],
+            first_seen: self.first_seen[i],
+            last_seen: self.last_seen[i],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Inner {
+    Map(FlowTable<FlowCounters>),
+    Soa(Soa),
+}
+
+/// Store of per-flow counters.
+#[derive(Clone, Debug)]
+pub struct FlowStore {
+    inner: Inner,
+}
+
+impl Default for FlowStore {
+    fn default() -> Self {
+        Self::new(StoreLayout::default())
+    }
+}
+
+impl FlowStore {
+    /// Create an empty store with the given layout.
+    pub fn new(layout: StoreLayout) -> Self {
+        Self::with_config(layout, FlowTableConfig::default())
+    }
+
+    /// Create an empty store with the given layout and flow table
+    /// configuration.
+    pub fn with_config(layout: StoreLayout, config: FlowTableConfig) -> Self {
+        let inner = match layout {
+            StoreLayout::Map => Inner::Map(FlowTable::with_config(config)),
+            StoreLayout::Soa => Inner::Soa(Soa {
+                index: FlowTable::with_config(config),
+                ..Default::default()
+            }),
+        };
+        Self { inner }
+    }
+
+    /// Get the layout of the store.
+    pub fn layout(&self) -> StoreLayout {
+        match self.inner {
+            Inner::Map(_) => StoreLayout::Map,
+            Inner::Soa(_) => StoreLayout::Soa,
+        }
+    }
+
+    /// Record a packet of a flow.
+    pub fn record(&mut self, key: FlowKey, ts: Duration, bytes: u64) {
+        match &mut self.inner {
+            Inner::Map(flows) => flows.entry(key).or_default().record(ts, bytes),
+            Inner::Soa(soa) => {
+                let next = soa.keys.len();
+                let i = *soa.index.entry(key).or_insert(next);
+                if i == next {
+                    soa.keys.push(key);
+                    soa.packets.push(0);
+                    soa.bytes.push(0);
+                    soa.first_seen.push(ts);
+                    soa.last_seen.push(ts);
+                }
+                soa.packets[i] += 1;
+                soa.bytes[i] += bytes;
+                soa.last_seen[i] = ts;
+            }
+        }
+    }
+
+    /// Get the counters of a flow.
+    pub fn get(&self, key: &FlowKey) -> Option<FlowCounters> {
+        match &self.inner {
+            Inner::Map(flows) => flows.get(key).copied(),
+            Inner::Soa(soa) => soa.index.get(key).map(|i| soa.get(*i)),
+        }
+    }
+
+    /// Get the number of flows.
+    pub fn len(&self) -> usize {
+        match &self.inner {
+            Inner::Map(flows) => flows.len(),
+            Inner::Soa(soa) => soa.keys.len(),
+        }
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the total number of packets of all flows.
+    pub fn total_packets(&self) -> u64 {
+        match &self.inner {
+            Inner::Map(flows) => flows.iter().map(|(_, c)| c.packets).sum(),
+            Inner::Soa(soa) => soa.packets.iter().sum(),
+        }
+    }
+
+    /// Get the total number of bytes of all flows.
+    pub fn total_bytes(&self) -> u64 {
+        match &self.inner {
+            Inner::Map(flows) => flows.iter().map(|(_, c)| c.bytes).sum(),
+            Inner::Soa(soa) => soa.bytes.iter().sum(),
+        }
+    }
+
+    /// Get the number of flows with a packet at or after `ts`.
+    pub fn active_since(&self, ts: Duration) -> usize {
+        match &self.inner {
+            Inner::Map(flows) => flows.iter().filter(|(_, c)| c.last_seen >= ts).count(),
+            Inner::Soa(soa) => soa.last_seen.iter().filter(|t| **t >= ts).count(),
+        }
+    }
+
+    /// Iterate over the flows.
+    ///
+    /// Flows are in insertion order with [`StoreLayout::Soa`], and in
+    /// arbitrary order with [`StoreLayout::Map`].
+    pub fn iter(&self) -> impl Iterator<Item = (FlowKey, FlowCounters)> + '_ {
+        let (map, soa) = match &self.inner {
+            Inner::Map(flows) => (Some(flows.iter().map(|(k, c)| (*k, *c))), None),
+            Inner::Soa(soa) => (
+                None,
+                Some(soa.keys.iter().enumerate().map(|(i, k)| (*k, soa.get(i)))),
+            ),
+        };
+        map.into_iter().flatten().chain(soa.into_iter().flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv4Addr;
+
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn flow_store_layouts_agree() {
+        let key = |port: u16| {
+            FlowKey::new(
+                Ipv4Addr::new(10, 0, 0, 1),
+                Ipv4Addr::new(10, 0, 0, 2),
+                port,
+                80,
+                IpProtocol::Tcp,
+            )
+        };
+
+        let mut map = FlowStore::default();
+        let mut soa = FlowStore::new(StoreLayout::Soa);
+        for i in 0..100u64 {
+            let ts = Duration::from_millis(i);
+            map.record(key(i as u16 % 7), ts, i);
+            soa.record(key(i as u16 % 7), ts, i);
+        }
+
+        assert_eq!(soa.layout(), StoreLayout::Soa);
+        assert_eq!((map.len(), soa.len()), (7, 7));
+        assert_eq!(map.total_bytes(), soa.total_bytes());
+        assert_eq!(map.total_packets(), 100);
+        assert_eq!(soa.total_packets(), 100);
+        assert_eq!(
+            map.active_since(Duration::from_millis(95)),
+            soa.active_since(Duration::from_millis(95))
+        );
+
+        let mut flows: Vec<_> = map.iter().collect();
+        flows.sort_by_key(|(k, _)| k.src_port);
+        assert_eq!(flows, soa.iter().collect::<Vec<_>>());
+        assert_eq!(
+            soa.get(&key(3)).unwrap().first_seen,
+            Duration::from_millis(3)
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Flow tables.
+//!
+//! A [`FlowTable`] maps flow keys to per-flow state. Flow keys of live
+//! traffic are chosen by whoever sends it, so with a predictable hash an
+//! attacker can craft many flows hashing to the same bucket and turn every
+//! lookup into a linear scan. Flow tables hash keys with SipHash under a
+//! secret random key by default; a fixed key can be configured where hashes
+//! must be reproducible.
+//!
+//! ```
+//! use netkit_packet::flow::table::{FlowTable, FlowTableConfig, HashKey};
+//! # use netkit_packet::prelude::*;
+//! # use core::net::Ipv4Addr;
+//!
+//! let key = FlowKey::new(Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST, 1, 2, IpProtocol::Udp);
+//!
+//! let mut table = FlowTable::with_config(FlowTableConfig::new().hash_key(HashKey::Fixed(1, 2)));
+//! *table.entry(key).or_insert(0) += 1;
+//! assert_eq!(table.get(&key), Some(&1));
+//! ```
+
+use core::hash::BuildHasher;
+use std::collections::{hash_map, HashMap};
+
+use super::{keying::FlowKeying, siphash::SipHasher13, FlowKey};
+use crate::utils::{memory::MemoryAccount, rng};
+
+/// Key of the flow table hash.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    /// A random key drawn when the table is created, reproducible from the
+    /// seed of an entered [`RngContext`](crate::utils::rng::RngContext).
+    #[default]
+    Random,
+
+    /// A fixed key, making hashes reproducible across tables and runs.
+    ///
+    /// Only use a fixed key for trusted traffic, or keep it secret.
+    Fixed(u64, u64),
+}
+
+/// Configuration of a flow table.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, rename_all = "kebab-case")
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct FlowTableConfig {
+    /// Key of the hash.
+    pub hash_key: HashKey,
+    /// Number of flows to reserve room for.
+    pub capacity: usize,
+    /// Fields included in the keys of the flows.
+    pub keying: FlowKeying,
+}
+
+impl FlowTableConfig {
+    /// Create the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the key of the hash.
+    pub fn hash_key(mut self, hash_key: HashKey) -> Self {
+        self.hash_key = hash_key;
+        self
+    }
+
+    /// Set the number of flows to reserve room for.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the fields included in the keys of the flows.
+    pub fn keying(mut self, keying: FlowKeying) -> Self {
+        self.keying = keying;
+        self
+    }
+}
+
+/// Builder of keyed SipHash-1-3 hashers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[must_use]
+pub struct FlowHashBuilder {
+    k0: u64,
+    k1: u64,
+}
+
+impl FlowHashBuilder {
+    /// Create a builder for the given key.
+    pub fn new(hash_key: HashKey) -> Self {
+        let (k0, k1) = match hash_key {
+            HashKey::Random => (rng::random(), rng::random()),
+            HashKey::Fixed(k0, k1) => (k0, k1),
+        };
+        Self { k0, k1 }
+    }
+}
+
+impl Default for FlowHashBuilder {
+    fn default() -> Self {
+        Self::new(HashKey::Random)
+    }
+}
+
+impl BuildHasher for FlowHashBuilder {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+/// Table of per-flow state.
+///
+/// A table given a [`MemoryAccount`] charges the size of its keys and
+/// values to it, heap data of the values excluded, and its users evict
+/// flows with [`evict_by`](FlowTable::evict_by) when the budget is exceeded.
+#[derive(Clone, Debug)]
+pub struct FlowTable<V> {
+    flows: HashMap<FlowKey, V, FlowHashBuilder>,
+    keying: FlowKeying,
+    memory: Option<MemoryAccount>,
+}
+
+impl<V> Default for FlowTable<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> FlowTable<V> {
+    /// Bytes charged per flow.
+    const ENTRY_SIZE: usize = core::mem::size_of::<(FlowKey, V)>();
+
+    /// Create an empty table with a random hash key.
+    pub fn new() -> Self {
+        Self::with_config(FlowTableConfig::default())
+    }
+
+    /// Create an empty table with the given configuration.
+    pub fn with_config(config: FlowTableConfig) -> Self {
+        Self {
+            flows: HashMap::with_capacity_and_hasher(
+                config.capacity,
+                FlowHashBuilder::new(config.hash_key),
+            ),
+            keying: config.keying,
+            memory: None,
+        }
+    }
+
+    /// Charge the memory of the flows to an account.
+    pub fn memory(&mut self, account: MemoryAccount) -> &mut Self {
+        self.memory = Some(account);
+        self.account(self.flows.len());
+        self
+    }
+
+    /// Get the account charged, if any.
+    pub fn memory_account(&self) -> Option<&MemoryAccount> {
+        self.memory.as_ref()
+    }
+
+    /// Whether the memory budget of the table is exceeded.
+    pub fn is_over_budget(&self) -> bool {
+        self.memory.as_ref().is_some_and(MemoryAccount::is_over)
+    }
+
+    /// Evict flows in increasing order of `order` while the memory budget is
+    /// exceeded, returning the number evicted.
+    ///
+    /// Flows are evicted down to the
+    /// [low watermark](crate::utils::memory::MemoryBudget::low_watermark) of
+    /// the budget, or until the table is empty.
+    pub fn evict_by<K: Ord>(&mut self, mut order: impl FnMut(&FlowKey, &V) -> K) -> usize {
+        let Some(account) = self.memory.as_mut().filter(|a| a.is_over()) else {
+            return 0;
+        };
+        let mut flows: Vec<_> = self.flows.iter().map(|(k, v)| (order(k, v), *k)).collect();
+        flows.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let budget = account.budget().clone();
+        let mut evicted = 0;
+        for (_, key) in flows {
+            if budget.used() <= budget.low_watermark() {
+                break;
+            }
+            self.flows.remove(&key);
+            account.evict(Self::ENTRY_SIZE);
+            evicted += 1;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            evicted,
+            flows = self.flows.len(),
+            "evicted flows over budget"
+        );
+        evicted
+    }
+
+    /// Log the table each time inserting `key` grows it to a power of two.
+    #[cfg(feature = "tracing")]
+    fn trace_growth(&self, key: &FlowKey) {
+        let len = self.flows.len() + 1;
+        if len.is_power_of_two() && !self.flows.contains_key(key) {
+            tracing::debug!(flows = len, "flow table grew");
+        }
+    }
+
+    /// Charge `len` flows to the account.
+    fn account(&mut self, len: usize) {
+        if let Some(account) = &mut self.memory {
+            account.set(len * Self::ENTRY_SIZE);
+        }
+    }
+
+    /// Get the fields included in the keys of the flows, for the users of
+    /// the table to extract keys with.
+    pub fn keying(&self) -> &FlowKeying {
+        &self.keying
+    }
+
+    /// Get the hash of a flow key in this table.
+    pub fn hash(&self, key: &FlowKey) -> u64 {
+        self.flows.hasher().hash_one(key)
+    }
+
+    /// Get the state of a flow.
+    #[inline]
+    pub fn get(&self, key: &FlowKey) -> Option<&V> {
+        self.flows.get(key)
+    }
+
+    /// Get the mutable state of a flow.
+    #[inline]
+    pub fn get_mut(&mut self, key: &FlowKey) -> Option<&mut V> {
+        self.flows.get_mut(key)
+    }
+
+    /// Whether the table holds a flow.
+    #[inline]
+    pub fn contains_key(&self, key: &FlowKey) -> bool {
+        self.flows.contains_key(key)
+    }
+
+    /// Get the entry of a flow for in-place manipulation.
+    #[inline]
+    pub fn entry(&mut self, key: FlowKey) -> hash_map::Entry<'_, FlowKey, V> {
+        #[cfg(feature = "tracing")]
+        self.trace_growth(&key);
+        if self.memory.is_some() {
+            // Charged as inserted; corrected by the next change otherwise.
+            let len = self.flows.len() + usize::from(!self.flows.contains_key(&key));
+            self.account(len);
+        }
+        self.flows.entry(key)
+    }
+
+    /// Insert the state of a flow, returning the previous one.
+    #[inline]
+    pub fn insert(&mut self, key: FlowKey, value: V) -> Option<V> {
+        #[cfg(feature = "tracing")]
+        self.trace_growth(&key);
+        let previous = self.flows.insert(key, value);
+        self.account(self.flows.len());
+        previous
+    }
+
+    /// Remove a flow, returning its state.
+    #[inline]
+    pub fn remove(&mut self, key: &FlowKey) -> Option<V> {
+        let value = self.flows.remove(key);
+        self.account(self.flows.len());
+        value
+    }
+
+    /// Keep only the flows for which `f` returns `true`.
+    pub fn retain(&mut self, f: impl FnMut(&FlowKey, &mut V) -> bool) {
+        self.flows.retain(f);
+        self.account(self.flows.len());
+    }
+
+    /// Remove all flows.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.flows.clear();
+        self.account(0);
+    }
+
+    /// Get the number of flows.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Whether the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Iterate over the flows, in arbitrary order.
+    #[inline]
+    pub fn iter(&self) -> hash_map::Iter<'_, FlowKey, V> {
+        self.flows.iter()
+    }
+
+    /// Iterate mutably over the flows, in arbitrary order.
+    #[inline]
+    pub fn iter_mut(&mut self) -> hash_map::IterMut<'_, FlowKey, V> {
+        self.flows.iter_mut()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a FlowTable<V> {
+    type Item = (&'a FlowKey, &'a V);
+    type IntoIter = hash_map::Iter<'a, FlowKey, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{
+        prelude::*,
+        utils::memory::{MemoryBudget, Subsystem},
+    };
+
+    #[test]
+    fn flow_table_hash_key() {
+        let key = FlowKey::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            1234,
+            80,
+            IpProtocol::Tcp,
+        );
+        let fixed = FlowTableConfig::new().hash_key(HashKey::Fixed(7, 11));
+
+        let a = FlowTable::<()>::with_config(fixed);
+        let b = FlowTable::<()>::with_config(fixed.capacity(16));
+        assert_eq!(a.hash(&key), b.hash(&key));
+
+        // Random keys differ between tables.
+        let hashes: Vec<_> = (0..4).map(|_| FlowTable::<()>::new().hash(&key)).collect();
+        assert!(hashes.iter().any(|h| *h != hashes[0]));
+
+        let mut table = FlowTable::new();
+        table.insert(key, 1);
+        *table.entry(key.reversed()).or_insert(0) += 2;
+        assert_eq!(table.len(), 2);
+        table.retain(|_, v| *v > 1);
+        assert_eq!(table.iter().next(), Some((&key.reversed(), &2)));
+    }
+
+    #[test]
+    fn flow_table_eviction() {
+        let entry = core::mem::size_of::<(FlowKey, u16)>();
+        let budget = MemoryBudget::new(10 * entry);
+        let mut table = FlowTable::new();
+        table.memory(budget.account(Subsystem::FlowTable));
+        for port in 0..11u16 {
+            let key = FlowKey::new(
+                Ipv4Addr::LOCALHOST,
+                Ipv4Addr::LOCALHOST,
+                port,
+                80,
+                IpProtocol::Udp,
+            );
+            *table.entry(key).or_default() = port;
+        }
+        assert_eq!(budget.used(), 11 * entry);
+
+        // Evicted down to 7/8 of the budget, lowest values first
+        assert_eq!(table.evict_by(|_, port| *port), 3);
+        assert_eq!(table.evict_by(|_, port| *port), 0);
+        assert!(table.iter().all(|(_, port)| *port >= 3));
+        let usage = budget.usage();
+        assert_eq!(usage.get(Subsystem::FlowTable).evictions, 3);
+
+        table.clear();
+        assert_eq!(budget.used(), 0);
+    }
+}
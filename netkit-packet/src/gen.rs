@@ -0,0 +1,7 @@
+//! Packet generators.
+
+pub mod craft;
+pub mod fuzz;
+pub mod mutate;
+pub mod rate;
+pub mod shape;
@@ -0,0 +1,220 @@
+//! Quick packet crafting from socket addresses.
+//!
+//! These constructors cover the common case of a well-formed frame between
+//! two endpoints: the IPv4 or IPv6 layer is picked from the address family,
+//! and lengths and checksums are filled in.
+//!
+//! ```
+//! use netkit_packet::gen::craft::udp_datagram;
+//! use netkit_packet::prelude::*;
+//!
+//! let frame = udp_datagram(
+//!     [0x02, 0, 0, 0, 0, 1],
+//!     [0x02, 0, 0, 0, 0, 2],
+//!     "[2001:db8::1]:5353".parse().unwrap(),
+//!     "[2001:db8::2]:53".parse().unwrap(),
+//!     b"query",
+//! );
+//!
+//! let ipv6 = frame.ipv6().unwrap();
+//! let udp = ipv6.udp().unwrap();
+//! assert_eq!(udp.dst_port().get(), 53);
+//! assert_eq!(udp.payload(), b"query");
+//! assert!(udp.verify_checksum(ipv6.src().get().into(), ipv6.dst().get().into()).is_ok());
+//! ```
+//!
+//! Mixed address families are crafted as IPv6, with the IPv4 address
+//! mapped (`::ffff:a.b.c.d`).
+
+use core::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use crate::layer::eth::EthBuilder;
+use crate::layer::tcp::{TcpBuilder, TcpFlags};
+use crate::prelude::*;
+use crate::utils::checksum::internet_checksum;
+
+/// Build an Ethernet frame carrying an IP packet between two addresses.
+///
+/// The IPv4 header checksum is computed. The checksum of a TCP or UDP
+/// payload is left as is.
+///
+/// # Panics
+///
+/// Panics if the payload does not fit in an IP packet.
+pub fn ip_packet(
+    src_mac: impl Into<EthAddr>,
+    dst_mac: impl Into<EthAddr>,
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: IpProtocol,
+    payload: impl AsRef<[u8]>,
+) -> Eth<Vec<u8>> {
+    let payload = payload.as_ref();
+    let mut eth = EthBuilder::new();
+    eth.src(src_mac).dst(dst_mac);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            assert!(
+                payload.len() <= u16::MAX as usize - 20,
+                "payload too large for an IPv4 packet"
+            );
+            let mut ipv4 = ipv4!(src: src, dst: dst, protocol: protocol, payload: payload);
+            let checksum = internet_checksum(&ipv4.inner()[..20]);
+            ipv4.checksum_mut().set(checksum);
+            eth.eth_type(EthType::Ipv4).payload(ipv4.inner());
+        }
+        (src, dst) => {
+            assert!(
+                payload.len() <= u16::MAX as usize,
+                "payload too large for an IPv6 packet"
+            );
+            let ipv6 = ipv6!(
+                src: to_ipv6(src),
+                dst: to_ipv6(dst),
+                next_header: protocol,
+                payload: payload,
+            );
+            eth.eth_type(EthType::Ipv6).payload(ipv6.inner());
+        }
+    }
+    eth.build()
+}
+
+/// Build an Ethernet frame carrying a UDP datagram between two sockets.
+///
+/// # Panics
+///
+/// Panics if the payload does not fit in an IP packet.
+pub fn udp_datagram(
+    src_mac: impl Into<EthAddr>,
+    dst_mac: impl Into<EthAddr>,
+    src: SocketAddr,
+    dst: SocketAddr,
+    payload: impl AsRef<[u8]>,
+) -> Eth<Vec<u8>> {
+    let payload = payload.as_ref();
+    assert!(
+        payload.len() <= u16::MAX as usize - 8,
+        "payload too large for a UDP datagram"
+    );
+    let udp = udp!(
+        src_port: src.port(),
+        dst_port: dst.port(),
+        pseudo_header: (src.ip(), dst.ip()),
+        payload: payload,
+    );
+    ip_packet(
+        src_mac,
+        dst_mac,
+        src.ip(),
+        dst.ip(),
+        IpProtocol::Udp,
+        udp.inner(),
+    )
+}
+
+/// Build an Ethernet frame carrying a TCP segment between two sockets.
+///
+/// The sequence and acknowledgment numbers are zero; see
+/// [`tcp_segment_with`] to set other fields.
+///
+/// # Panics
+///
+/// Panics if the payload does not fit in an IP packet.
+pub fn tcp_segment(
+    src_mac: impl Into<EthAddr>,
+    dst_mac: impl Into<EthAddr>,
+    src: SocketAddr,
+    dst: SocketAddr,
+    flags: TcpFlags,
+    payload: impl AsRef<[u8]>,
+) -> Eth<Vec<u8>> {
+    let mut tcp = TcpBuilder::new();
+    tcp.flags(flags).payload(payload);
+    tcp_segment_with(src_mac, dst_mac, src, dst, &tcp)
+}
+
+/// Build an Ethernet frame carrying a TCP segment between two sockets,
+/// with the other fields taken from a builder.
+///
+/// The ports of the sockets replace those of the builder, and the checksum
+/// is computed unless set explicitly (or left partial, see
+/// [`TcpBuilder::partial_checksum`]).
+///
+/// # Panics
+///
+/// Panics if the segment does not fit in an IP packet.
+pub fn tcp_segment_with(
+    src_mac: impl Into<EthAddr>,
+    dst_mac: impl Into<EthAddr>,
+    src: SocketAddr,
+    dst: SocketAddr,
+    tcp: &TcpBuilder,
+) -> Eth<Vec<u8>> {
+    let tcp = tcp
+        .clone()
+        .src_port(src.port())
+        .dst_port(dst.port())
+        .pseudo_header((src.ip(), dst.ip()))
+        .build();
+    ip_packet(
+        src_mac,
+        dst_mac,
+        src.ip(),
+        dst.ip(),
+        IpProtocol::Tcp,
+        tcp.inner(),
+    )
+}
+
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+        IpAddr::V6(addr) => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_frames() {
+        let (a, b) = ([0x02, 0, 0, 0, 0, 1], [0x02, 0, 0, 0, 0, 2]);
+
+        let frame = tcp_segment(
+            a,
+            b,
+            "10.0.0.1:40000".parse().unwrap(),
+            "10.0.0.2:80".parse().unwrap(),
+            TcpFlags::SYN,
+            b"",
+        );
+        assert_eq!(frame.src().get(), a.into());
+        let ipv4 = frame.ipv4().unwrap();
+        assert_eq!(internet_checksum(&ipv4.inner()[..20]), 0);
+        assert_eq!(ipv4.total_length().get(), 40);
+        let tcp = ipv4.tcp().unwrap();
+        assert_eq!((tcp.src_port().get(), tcp.dst_port().get()), (40000, 80));
+        assert_eq!(ipv4.transport_checksum(), Some(tcp.checksum().get()));
+
+        let frame = udp_datagram(
+            a,
+            b,
+            "[2001:db8::1]:5353".parse().unwrap(),
+            "10.0.0.2:53".parse().unwrap(),
+            b"query",
+        );
+        let ipv6 = frame.ipv6().unwrap();
+        assert_eq!(
+            ipv6.dst().get(),
+            "::ffff:10.0.0.2".parse::<Ipv6Addr>().unwrap()
+        );
+        assert_eq!(ipv6.payload_length().get(), 8 + 5);
+        let udp = ipv6.udp().unwrap();
+        assert_eq!(udp.length().get(), 8 + 5);
+        assert!(udp
+            .verify_checksum(ipv6.src().get().into(), ipv6.dst().get().into())
+            .is_ok());
+    }
+}
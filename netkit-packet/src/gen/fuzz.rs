@@ -0,0 +1,334 @@
+//! Randomized packet generator for fuzzing.
+//!
+//! A [`Fuzzer`] produces Ethernet/IPv4/TCP or UDP frames with random but
+//! valid header fields, or malformed variants of them mutated at a chosen
+//! layer. All randomness comes from a seedable RNG, so a failing input can be
+//! reproduced from its seed.
+//!
+//! ```
+//! use netkit_packet::gen::fuzz::{FuzzLayer, Fuzzer, Mutation};
+//! use netkit_packet::prelude::*;
+//!
+//! let mut fuzzer = Fuzzer::new(42);
+//!
+//! let frame = fuzzer.frame();
+//! assert!(Eth::new(frame.data()).unwrap().ipv4().is_some());
+//!
+//! let malformed = fuzzer.malformed(FuzzLayer::Ipv4, Mutation::Truncate);
+//! assert!(Eth::new(malformed.data()).unwrap().ipv4().is_none());
+//! ```
+
+use core::{net::Ipv4Addr, ops::Range};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::mutate::{FieldMutate, FrameField};
+use crate::{
+    layer::{ip::Ipv4Builder, tcp::TcpBuilder, udp::UdpBuilder},
+    prelude::*,
+};
+
+/// Default maximum payload length of generated packets.
+pub const DEFAULT_MAX_PAYLOAD: usize = 256;
+
+/// Layer of a generated frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FuzzLayer {
+    /// Ethernet header.
+    Eth,
+    /// IPv4 header.
+    Ipv4,
+    /// TCP or UDP header.
+    Transport,
+    /// Application payload.
+    Payload,
+}
+
+/// Mutation applied to a layer of a generated frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    /// Flip the given number of random bits.
+    BitFlips(usize),
+    /// Cut the frame at a random position inside the layer.
+    Truncate,
+    /// Overwrite the whole layer with random bytes.
+    Garbage,
+}
+
+/// A generated frame with the position of its layers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzFrame {
+    data: Vec<u8>,
+    ipv4: usize,
+    transport: usize,
+    payload: usize,
+}
+
+impl FuzzFrame {
+    /// Get the raw frame.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the raw frame, consuming self.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Get the byte range of a layer in the original (unmutated) frame.
+    ///
+    /// The range is clamped to the frame length, so it may be empty after a
+    /// truncation.
+    pub fn range(&self, layer: FuzzLayer) -> Range<usize> {
+        let range = match layer {
+            FuzzLayer::Eth => 0..self.ipv4,
+            FuzzLayer::Ipv4 => self.ipv4..self.transport,
+            FuzzLayer::Transport => self.transport..self.payload,
+            FuzzLayer::Payload => self.payload..usize::MAX,
+        };
+        range.start.min(self.data.len())..range.end.min(self.data.len())
+    }
+}
+
+/// Randomized packet generator.
+#[derive(Clone, Debug)]
+pub struct Fuzzer<R = StdRng> {
+    rng: R,
+    max_payload: usize,
+}
+
+impl Fuzzer<StdRng> {
+    /// Create a new fuzzer seeded with `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self::from_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: Rng> Fuzzer<R> {
+    /// Create a new fuzzer from an existing RNG.
+    pub fn from_rng(rng: R) -> Self {
+        Self {
+            rng,
+            max_payload: DEFAULT_MAX_PAYLOAD,
+        }
+    }
+
+    /// Set the maximum payload length.
+    pub fn max_payload(&mut self, max_payload: usize) -> &mut Self {
+        self.max_payload = max_payload;
+        self
+    }
+
+    /// Get the underlying RNG.
+    pub fn rng(&mut self) -> &mut R {
+        &mut self.rng
+    }
+
+    /// Generate a random payload.
+    pub fn payload(&mut self) -> Vec<u8> {
+        let len = self.rng.gen_range(0..=self.max_payload);
+        let mut payload = vec![0; len];
+        self.rng.fill(payload.as_mut_slice());
+        payload
+    }
+
+    /// Generate a UDP datagram with random ports and payload.
+    ///
+    /// The checksum is zero, i.e. not used.
+    pub fn udp(&mut self) -> Udp<Vec<u8>> {
+        UdpBuilder::new()
+            .src_port(self.rng.gen::<u16>())
+            .dst_port(self.rng.gen::<u16>())
+            .payload(self.payload())
+            .build()
+    }
+
+    /// Generate a TCP segment with random ports, numbers, flags and payload.
+    ///
+    /// The checksum is left zero.
+    pub fn tcp(&mut self) -> Tcp<Vec<u8>> {
+        TcpBuilder::new()
+            .src_port(self.rng.gen::<u16>())
+            .dst_port(self.rng.gen::<u16>())
+            .seq_num(self.rng.gen::<u32>())
+            .ack_num(self.rng.gen::<u32>())
+            .flags(self.rng.gen::<u8>())
+            .window_size(self.rng.gen::<u16>())
+            .payload(self.payload())
+            .build()
+    }
+
+    /// Generate an IPv4 packet carrying a random TCP or UDP layer.
+    ///
+    /// The header checksum is valid.
+    pub fn ipv4(&mut self) -> Ipv4<Vec<u8>> {
+        let (protocol, payload) = if self.rng.gen() {
+            (IpProtocol::Tcp, self.tcp().inner().clone())
+        } else {
+            (IpProtocol::Udp, self.udp().inner().clone())
+        };
+
+        let mut ipv4 = Ipv4Builder::new()
+            .dscp(self.rng.gen_range(0..64u8))
+            .ecn(self.rng.gen_range(0..4u8))
+            .identification(self.rng.gen::<u16>())
+            .flags(if self.rng.gen() { 0b010u8 } else { 0 })
+            .ttl(self.rng.gen_range(1..=255u8))
+            .protocol(protocol)
+            .src(Ipv4Addr::from(self.rng.gen::<u32>()))
+            .dst(Ipv4Addr::from(self.rng.gen::<u32>()))
+            .payload(payload)
+            .build();
+
+        let checksum = internet_checksum(&ipv4.inner()[..20]);
+        ipv4.checksum_mut().set(checksum);
+        ipv4
+    }
+
+    /// Generate an Ethernet frame with random unicast addresses.
+    pub fn eth(&mut self) -> Eth<Vec<u8>> {
+        let ipv4 = self.ipv4();
+        self.frame_eth(ipv4.inner())
+    }
+
+    /// Generate a valid Ethernet/IPv4/TCP or UDP frame.
+    pub fn frame(&mut self) -> FuzzFrame {
+        let ipv4 = self.ipv4();
+        let transport = 14 + ipv4.ihl().get() as usize * 4;
+        let payload = transport
+            + match ipv4.tcp() {
+                Some(tcp) => tcp.data_offset().get() as usize * 4,
+                None => 8,
+            };
+
+        FuzzFrame {
+            data: self.frame_eth(ipv4.inner()).inner().clone(),
+            ipv4: 14,
+            transport,
+            payload,
+        }
+    }
+
+    /// Generate a frame and apply a mutation to one of its layers.
+    ///
+    /// If the layer is empty (e.g. a packet without payload), the frame is
+    /// returned unmodified.
+    pub fn malformed(&mut self, layer: FuzzLayer, mutation: Mutation) -> FuzzFrame {
+        let mut frame = self.frame();
+        self.mutate(&mut frame, layer, mutation);
+        frame
+    }
+
+    /// Generate a frame and set one of its header fields, chosen at random,
+    /// to a different random value.
+    pub fn field_mutated(&mut self) -> (FuzzFrame, FrameField) {
+        let mut frame = self.frame();
+        let field = frame
+            .data
+            .mutate_field_random(&mut self.rng)
+            .expect("generated frames have header fields");
+        (frame, field)
+    }
+
+    /// Apply a mutation to a layer of a frame.
+    pub fn mutate(&mut self, frame: &mut FuzzFrame, layer: FuzzLayer, mutation: Mutation) {
+        let range = frame.range(layer);
+        if range.is_empty() {
+            return;
+        }
+
+        match mutation {
+            Mutation::BitFlips(count) => {
+                for _ in 0..count {
+                    let byte = self.rng.gen_range(range.clone());
+                    frame.data[byte] ^= 1 << self.rng.gen_range(0..8);
+                }
+            }
+            Mutation::Truncate => {
+                let len = self.rng.gen_range(range);
+                frame.data.truncate(len);
+            }
+            Mutation::Garbage => self.rng.fill(&mut frame.data[range]),
+        }
+    }
+
+    fn frame_eth(&mut self, payload: &[u8]) -> Eth<Vec<u8>> {
+        eth!(
+            src: self.unicast_addr(),
+            dst: self.unicast_addr(),
+            eth_type: EthType::Ipv4,
+            payload: payload,
+        )
+    }
+
+    fn unicast_addr(&mut self) -> [u8; 6] {
+        let mut addr: [u8; 6] = self.rng.gen();
+        addr[0] &= 0xFE;
+        addr
+    }
+}
+
+impl<R: Rng> Iterator for Fuzzer<R> {
+    type Item = Vec<u8>;
+
+    /// Generate an endless stream of valid frames.
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.frame().into_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzz_reproducible() {
+        let a: Vec<_> = Fuzzer::new(7).take(8).collect();
+        let b: Vec<_> = Fuzzer::new(7).take(8).collect();
+        let c: Vec<_> = Fuzzer::new(8).take(8).collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn fuzz_valid() {
+        let mut fuzzer = Fuzzer::new(1);
+        fuzzer.max_payload(64);
+
+        for _ in 0..64 {
+            let frame = fuzzer.frame();
+            let eth = Eth::new(frame.data()).unwrap();
+            let ipv4 = eth.ipv4().unwrap();
+
+            assert_eq!(
+                internet_checksum(&frame.data()[frame.range(FuzzLayer::Ipv4)]),
+                0
+            );
+            assert!(ipv4.tcp().is_some() || ipv4.udp().is_some());
+            assert!(ipv4.payload().len() - (frame.range(FuzzLayer::Transport).len()) <= 64);
+        }
+    }
+
+    #[test]
+    fn fuzz_malformed() {
+        let mut fuzzer = Fuzzer::new(2);
+
+        let frame = fuzzer.malformed(FuzzLayer::Transport, Mutation::Truncate);
+        assert!(frame.range(FuzzLayer::Payload).is_empty());
+        assert_eq!(frame.range(FuzzLayer::Ipv4).len(), 20);
+
+        let valid = Fuzzer::new(3).frame();
+        let flipped = Fuzzer::new(3).malformed(FuzzLayer::Ipv4, Mutation::BitFlips(1));
+        let diff: Vec<_> = valid
+            .data()
+            .iter()
+            .zip(flipped.data())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(diff.len(), 1);
+        assert!(valid.range(FuzzLayer::Ipv4).contains(&diff[0]));
+    }
+}
@@ -0,0 +1,305 @@
+//! Field-level mutation for negative testing.
+//!
+//! [`frame_fields`] locates the header fields of an Ethernet frame down to
+//! the bit, from the field ranges of the layers (e.g.
+//! [`Ipv4::FIELD_TTL`]). [`FieldMutate`] builds on it to corrupt a chosen or
+//! random field, and [`truncate_at`] cuts a frame at a layer boundary, so
+//! parsers can be tested against inputs that are wrong in one precise way:
+//!
+//! ```
+//! use netkit_packet::gen::mutate::{truncate_at, FieldMutate};
+//! use netkit_packet::prelude::*;
+//! use rand::{rngs::StdRng, SeedableRng};
+//!
+//! let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//! let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone();
+//! let mut rng = StdRng::seed_from_u64(1);
+//!
+//! frame.randomize_field("Ipv4", "version", &mut rng).unwrap();
+//! let eth = Eth::new(&frame[..]).unwrap();
+//! assert_ne!(eth.ipv4().unwrap().version().get(), 4);
+//!
+//! truncate_at(&mut frame, "Udp");
+//! assert_eq!(frame.len(), 14 + 20);
+//! ```
+
+use core::ops::Range;
+
+use rand::Rng;
+
+use crate::prelude::*;
+
+/// A header field located in a frame.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FrameField {
+    /// Name of the layer, as in [`Layer::name`](crate::dissect::Layer::name).
+    pub layer: &'static str,
+    /// Name of the field.
+    pub name: &'static str,
+    /// Bits of the field in the frame, most significant bit first.
+    pub bits: Range<usize>,
+}
+
+impl FrameField {
+    /// Get the bytes of the frame holding the field.
+    pub fn bytes(&self) -> Range<usize> {
+        self.bits.start / 8..self.bits.end.div_ceil(8)
+    }
+}
+
+const fn bytes(range: Range<usize>) -> Range<usize> {
+    range.start * 8..range.end * 8
+}
+
+type FieldTable = &'static [(&'static str, Range<usize>)];
+
+const ETH_FIELDS: FieldTable = &[
+    ("dst", bytes(Eth::<&[u8]>::FIELD_DST)),
+    ("src", bytes(Eth::<&[u8]>::FIELD_SRC)),
+    ("eth_type", bytes(Eth::<&[u8]>::FIELD_ETH_TYPE)),
+];
+
+const IPV4_FIELDS: FieldTable = &[
+    ("version", 0..4),
+    ("ihl", 4..8),
+    ("dscp", 8..14),
+    ("ecn", 14..16),
+    ("total_length", bytes(Ipv4::<&[u8]>::FIELD_TOTAL_LENGTH)),
+    ("identification", bytes(Ipv4::<&[u8]>::FIELD_IDENTIFICATION)),
+    ("flags", 48..51),
+    ("fragment_offset", 51..64),
+    ("ttl", bytes(Ipv4::<&[u8]>::FIELD_TTL)),
+    ("protocol", bytes(Ipv4::<&[u8]>::FIELD_PROTOCOL)),
+    ("checksum", bytes(Ipv4::<&[u8]>::FIELD_CHECKSUM)),
+    ("src", bytes(Ipv4::<&[u8]>::FIELD_SRC)),
+    ("dst", bytes(Ipv4::<&[u8]>::FIELD_DST)),
+];
+
+const IPV6_FIELDS: FieldTable = &[
+    ("version", 0..4),
+    ("traffic_class", 4..12),
+    ("flow_label", 12..32),
+    ("payload_length", bytes(Ipv6::<&[u8]>::FIELD_PAYLOAD_LENGTH)),
+    ("next_header", bytes(Ipv6::<&[u8]>::FIELD_NEXT_HEADER)),
+    ("hop_limit", bytes(Ipv6::<&[u8]>::FIELD_HOP_LIMIT)),
+    ("src", bytes(Ipv6::<&[u8]>::FIELD_SRC)),
+    ("dst", bytes(Ipv6::<&[u8]>::FIELD_DST)),
+];
+
+const TCP_FIELDS: FieldTable = &[
+    ("src_port", bytes(Tcp::<&[u8]>::FIELD_SRC_PORT)),
+    ("dst_port", bytes(Tcp::<&[u8]>::FIELD_DST_PORT)),
+    ("seq_num", bytes(Tcp::<&[u8]>::FIELD_SEQ_NUM)),
+    ("ack_num", bytes(Tcp::<&[u8]>::FIELD_ACK_NUM)),
+    ("data_offset", 96..100),
+    ("flags", bytes(Tcp::<&[u8]>::FIELD_FLAGS)),
+    ("window_size", bytes(Tcp::<&[u8]>::FIELD_WINDOW_SIZE)),
+    ("checksum", bytes(Tcp::<&[u8]>::FIELD_CHECKSUM)),
+    ("urgent_pointer", bytes(Tcp::<&[u8]>::FIELD_URGENT_POINTER)),
+];
+
+const UDP_FIELDS: FieldTable = &[
+    ("src_port", bytes(Udp::<&[u8]>::FIELD_SRC_PORT)),
+    ("dst_port", bytes(Udp::<&[u8]>::FIELD_DST_PORT)),
+    ("length", bytes(Udp::<&[u8]>::FIELD_LENGTH)),
+    ("checksum", bytes(Udp::<&[u8]>::FIELD_CHECKSUM)),
+];
+
+/// Get the layers of an Ethernet frame with their offsets, outermost first.
+///
+/// Ethernet, IPv4 or IPv6 (behind VLAN tags), and TCP or UDP are located.
+pub fn frame_layers(frame: &[u8]) -> Vec<(&'static str, usize)> {
+    let offset = |inner: &[u8]| inner.as_ptr() as usize - frame.as_ptr() as usize;
+
+    let Ok(eth) = Eth::new(frame) else {
+        return Vec::new();
+    };
+    let mut layers = vec![("Eth", 0)];
+    if let Some(ipv4) = eth.ipv4() {
+        layers.push(("Ipv4", offset(ipv4.inner())));
+        if let Some(tcp) = ipv4.tcp() {
+            layers.push(("Tcp", offset(tcp.inner())));
+        } else if let Some(udp) = ipv4.udp() {
+            layers.push(("Udp", offset(udp.inner())));
+        }
+    } else if let Some(ipv6) = eth.ipv6() {
+        layers.push(("Ipv6", offset(ipv6.inner())));
+        if let Some(tcp) = ipv6.tcp() {
+            layers.push(("Tcp", offset(tcp.inner())));
+        } else if let Some(udp) = ipv6.udp() {
+            layers.push(("Udp", offset(udp.inner())));
+        }
+    }
+    layers
+}
+
+/// Locate the header fields of an Ethernet frame, outermost first.
+pub fn frame_fields(frame: &[u8]) -> Vec<FrameField> {
+    frame_layers(frame)
+        .into_iter()
+        .flat_map(|(layer, offset)| {
+            let table = match layer {
+                "Eth" => ETH_FIELDS,
+                "Ipv4" => IPV4_FIELDS,
+                "Ipv6" => IPV6_FIELDS,
+                "Tcp" => TCP_FIELDS,
+                _ => UDP_FIELDS,
+            };
+            table.iter().map(move |(name, bits)| FrameField {
+                layer,
+                name,
+                bits: offset * 8 + bits.start..offset * 8 + bits.end,
+            })
+        })
+        .collect()
+}
+
+/// Locate a header field of an Ethernet frame.
+pub fn find_field(frame: &[u8], layer: &str, name: &str) -> Option<FrameField> {
+    frame_fields(frame)
+        .into_iter()
+        .find(|f| f.layer == layer && f.name == name)
+}
+
+/// Cut a frame at the start of a layer, returning whether the layer was
+/// found.
+pub fn truncate_at(frame: &mut Vec<u8>, layer: &str) -> bool {
+    match frame_layers(frame).into_iter().find(|(l, _)| *l == layer) {
+        Some((_, offset)) => {
+            frame.truncate(offset);
+            true
+        }
+        None => false,
+    }
+}
+
+fn flip_bit(frame: &mut [u8], bit: usize) {
+    frame[bit / 8] ^= 0x80 >> (bit % 8);
+}
+
+/// Corruption of the header fields of a frame.
+///
+/// Mutations only touch the bits of the field; lengths and checksums are
+/// not fixed up, so the frame is wrong in exactly one field.
+///
+/// This trait is sealed; it is implemented for byte slices.
+pub trait FieldMutate: crate::sealed::Sealed {
+    /// Set a random field to a different random value, returning it.
+    fn mutate_field_random(&mut self, rng: &mut impl Rng) -> Option<FrameField>;
+
+    /// Set a field to a different random value.
+    fn randomize_field(
+        &mut self,
+        layer: &str,
+        name: &str,
+        rng: &mut impl Rng,
+    ) -> Option<FrameField>;
+
+    /// Flip `count` random bits of a field, possibly the same bit twice.
+    fn flip_field_bits(
+        &mut self,
+        layer: &str,
+        name: &str,
+        count: usize,
+        rng: &mut impl Rng,
+    ) -> Option<FrameField>;
+}
+
+impl crate::sealed::Sealed for [u8] {}
+
+impl FieldMutate for [u8] {
+    fn mutate_field_random(&mut self, rng: &mut impl Rng) -> Option<FrameField> {
+        let fields = frame_fields(self);
+        if fields.is_empty() {
+            return None;
+        }
+        let field = fields[rng.gen_range(0..fields.len())].clone();
+        randomize(self, &field, rng);
+        Some(field)
+    }
+
+    fn randomize_field(
+        &mut self,
+        layer: &str,
+        name: &str,
+        rng: &mut impl Rng,
+    ) -> Option<FrameField> {
+        let field = find_field(self, layer, name)?;
+        randomize(self, &field, rng);
+        Some(field)
+    }
+
+    fn flip_field_bits(
+        &mut self,
+        layer: &str,
+        name: &str,
+        count: usize,
+        rng: &mut impl Rng,
+    ) -> Option<FrameField> {
+        let field = find_field(self, layer, name)?;
+        for _ in 0..count {
+            flip_bit(self, rng.gen_range(field.bits.clone()));
+        }
+        Some(field)
+    }
+}
+
+fn randomize(frame: &mut [u8], field: &FrameField, rng: &mut impl Rng) {
+    let mut changed = false;
+    for bit in field.bits.clone() {
+        if rng.gen() {
+            flip_bit(frame, bit);
+            changed = true;
+        }
+    }
+    if !changed {
+        flip_bit(frame, rng.gen_range(field.bits.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::layer::tcp::TcpFlags;
+
+    #[test]
+    fn field_mutation() {
+        let tcp = tcp!(src_port: 1234u16, dst_port: 80u16, flags: TcpFlags::SYN);
+        let ipv4 = ipv4!(protocol: IpProtocol::Tcp, ttl: 64u8, payload: tcp.inner());
+        let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone();
+
+        let fields = frame_fields(&frame);
+        assert_eq!(fields.len(), 3 + 13 + 9);
+        let ihl = find_field(&frame, "Ipv4", "ihl").unwrap();
+        assert_eq!((ihl.bits.clone(), ihl.bytes()), (116..120, 14..15));
+        let offset = find_field(&frame, "Tcp", "data_offset").unwrap();
+        assert_eq!(offset.bits, (34 + 12) * 8..(34 + 12) * 8 + 4);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut mutated = frame.clone();
+        mutated.flip_field_bits("Ipv4", "ttl", 1, &mut rng).unwrap();
+        let ttl = Eth::new(&mutated[..]).unwrap().ipv4().unwrap().ttl().get();
+        assert_eq!((64 ^ ttl).count_ones(), 1);
+
+        // Only the bytes of the mutated field change.
+        for _ in 0..32 {
+            let mut mutated = frame.clone();
+            let field = mutated.mutate_field_random(&mut rng).unwrap();
+            let changed: Vec<_> = (0..frame.len())
+                .filter(|&i| frame[i] != mutated[i])
+                .collect();
+            assert!(!changed.is_empty());
+            assert!(changed.iter().all(|i| field.bytes().contains(i)));
+        }
+
+        let mut truncated = frame.clone();
+        assert!(truncate_at(&mut truncated, "Tcp"));
+        assert_eq!(truncated.len(), 34);
+        assert!(!truncate_at(&mut truncated, "Udp"));
+    }
+}
@@ -0,0 +1,300 @@
+//! Token-bucket rate limiting for active tooling.
+//!
+//! Generators and scanners should pace what they send, both overall and per
+//! target, so that a tool built on netkit does not flood a network or a host
+//! by accident. A [`RateLimiter`] combines a global [`TokenBucket`] with one
+//! bucket per destination address. Its default limits are conservative;
+//! they are raised explicitly, or removed with [`RateLimiter::unlimited`].
+//!
+//! ```
+//! use std::{net::Ipv4Addr, time::Instant};
+//!
+//! use netkit_packet::gen::rate::RateLimiter;
+//!
+//! let mut limiter = RateLimiter::new();
+//! limiter.per_target(10.0, 2.0);
+//!
+//! let now = Instant::now();
+//! let target = Ipv4Addr::new(192, 0, 2, 1).into();
+//! assert!(limiter.check(target, now).is_ok());
+//! assert!(limiter.check(target, now).is_ok());
+//! // The burst is spent: wait 100 ms for the next token.
+//! assert!(limiter.check(target, now).is_err());
+//! ```
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::utils::clock::Clock;
+
+/// Default global rate, in packets per second.
+pub const DEFAULT_GLOBAL_RATE: f64 = 1000.0;
+
+/// Default global burst, in packets.
+pub const DEFAULT_GLOBAL_BURST: f64 = 100.0;
+
+/// Default rate per target, in packets per second.
+pub const DEFAULT_TARGET_RATE: f64 = 100.0;
+
+/// Default burst per target, in packets.
+pub const DEFAULT_TARGET_BURST: f64 = 10.0;
+
+/// Default number of target buckets kept before idle ones are pruned.
+pub const DEFAULT_MAX_TARGETS: usize = 65536;
+
+/// Token bucket.
+///
+/// Tokens accumulate at `rate` per second up to `burst`; every packet takes
+/// one token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Create a full bucket.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: None,
+        }
+    }
+
+    /// Get the rate, in tokens per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Get the burst, in tokens.
+    pub fn burst(&self) -> f64 {
+        self.burst
+    }
+
+    /// Get the tokens available at `now`.
+    pub fn available(&self, now: Instant) -> f64 {
+        let elapsed = self
+            .last
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst)
+    }
+
+    /// Check whether `n` tokens are available at `now`, without taking them.
+    ///
+    /// Returns the time to wait for them otherwise.
+    pub fn check_n(&self, n: f64, now: Instant) -> Result<(), Duration> {
+        let missing = n - self.available(now);
+        if missing <= 0.0 {
+            return Ok(());
+        }
+        if self.rate <= 0.0 || n > self.burst {
+            return Err(Duration::MAX);
+        }
+
+        Err(Duration::from_secs_f64(missing / self.rate))
+    }
+
+    /// Take `n` tokens at `now` if available.
+    ///
+    /// Returns the time to wait for them otherwise.
+    pub fn take_n(&mut self, n: f64, now: Instant) -> Result<(), Duration> {
+        self.check_n(n, now)?;
+        self.tokens = self.available(now) - n;
+        self.last = Some(now);
+        Ok(())
+    }
+
+    /// Take one token at `now` if available.
+    pub fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.take_n(1.0, now)
+    }
+}
+
+/// Rate limiter keyed by destination.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    global: Option<TokenBucket>,
+    target: Option<(f64, f64)>,
+    targets: HashMap<IpAddr, TokenBucket>,
+    max_targets: usize,
+    origin: Instant,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            global: Some(TokenBucket::new(DEFAULT_GLOBAL_RATE, DEFAULT_GLOBAL_BURST)),
+            target: Some((DEFAULT_TARGET_RATE, DEFAULT_TARGET_BURST)),
+            targets: HashMap::new(),
+            max_targets: DEFAULT_MAX_TARGETS,
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Create a rate limiter with the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a rate limiter without any limit.
+    pub fn unlimited() -> Self {
+        Self {
+            global: None,
+            target: None,
+            ..Self::default()
+        }
+    }
+
+    /// Set the global limit, in packets per second and burst packets.
+    pub fn global(&mut self, rate: f64, burst: f64) -> &mut Self {
+        self.global = Some(TokenBucket::new(rate, burst));
+        self
+    }
+
+    /// Remove the global limit.
+    pub fn no_global(&mut self) -> &mut Self {
+        self.global = None;
+        self
+    }
+
+    /// Set the limit per target, in packets per second and burst packets.
+    pub fn per_target(&mut self, rate: f64, burst: f64) -> &mut Self {
+        self.target = Some((rate, burst));
+        self.targets.clear();
+        self
+    }
+
+    /// Remove the limit per target.
+    pub fn no_per_target(&mut self) -> &mut Self {
+        self.target = None;
+        self.targets.clear();
+        self
+    }
+
+    /// Set the number of target buckets kept before idle ones are pruned.
+    pub fn max_targets(&mut self, max_targets: usize) -> &mut Self {
+        self.max_targets = max_targets;
+        self
+    }
+
+    /// Get the number of tracked targets.
+    pub fn targets(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Take a token for a packet to `dst` at `now` if both the global and
+    /// the target limits allow it.
+    ///
+    /// Returns the time to wait otherwise; no token is taken then.
+    pub fn check(&mut self, dst: IpAddr, now: Instant) -> Result<(), Duration> {
+        let global_wait = match &self.global {
+            Some(global) => global.check_n(1.0, now).err(),
+            None => None,
+        };
+
+        let target_wait = match self.target {
+            Some((rate, burst)) => {
+                if self.targets.len() >= self.max_targets && !self.targets.contains_key(&dst) {
+                    self.prune(now);
+                }
+                let bucket = self
+                    .targets
+                    .entry(dst)
+                    .or_insert_with(|| TokenBucket::new(rate, burst));
+                bucket.check_n(1.0, now).err()
+            }
+            None => None,
+        };
+
+        if let Some(wait) = global_wait.max(target_wait) {
+            return Err(wait);
+        }
+
+        if let Some(global) = &mut self.global {
+            let _ = global.take(now);
+        }
+        if let Some(bucket) = self.targets.get_mut(&dst) {
+            let _ = bucket.take(now);
+        }
+        Ok(())
+    }
+
+    /// Block until a packet to `dst` is allowed, then take its token.
+    pub fn wait(&mut self, dst: IpAddr) {
+        while let Err(wait) = self.check(dst, Instant::now()) {
+            std::thread::sleep(wait.min(Duration::from_secs(1)));
+        }
+    }
+
+    /// Block on `clock` until a packet to `dst` is allowed, then take its
+    /// token.
+    ///
+    /// The times of `clock` are mapped to instants from a fixed origin, so
+    /// a limiter waiting on a clock should not also be checked with
+    /// [`Instant::now`].
+    pub fn wait_on(&mut self, dst: IpAddr, clock: &impl Clock) {
+        while let Err(wait) = self.check(dst, self.origin + clock.now()) {
+            clock.sleep(wait.min(Duration::from_secs(1)));
+        }
+    }
+
+    /// Drop the buckets of targets that are full again, i.e. idle.
+    pub fn prune(&mut self, now: Instant) {
+        self.targets
+            .retain(|_, bucket| bucket.available(now) < bucket.burst());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::utils::clock::ManualClock;
+
+    #[test]
+    fn rate_limiter_buckets() {
+        let a = IpAddr::from(Ipv4Addr::new(192, 0, 2, 1));
+        let b = IpAddr::from(Ipv4Addr::new(192, 0, 2, 2));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let mut limiter = RateLimiter::new();
+        limiter.global(20.0, 3.0).per_target(10.0, 2.0);
+
+        assert_eq!(limiter.check(a, at(0)), Ok(()));
+        assert_eq!(limiter.check(a, at(0)), Ok(()));
+        // Target a is limited, b is not yet.
+        assert_eq!(limiter.check(a, at(0)), Err(Duration::from_millis(100)));
+        assert_eq!(limiter.check(b, at(0)), Ok(()));
+        // The global burst is spent.
+        assert_eq!(limiter.check(b, at(0)), Err(Duration::from_millis(50)));
+        assert_eq!(limiter.check(b, at(50)), Ok(()));
+        assert!(limiter.check(a, at(50)).is_err());
+        assert_eq!(limiter.check(a, at(100)), Ok(()));
+
+        // Idle targets are pruned.
+        limiter.prune(at(1000));
+        assert_eq!(limiter.targets(), 0);
+
+        let mut unlimited = RateLimiter::unlimited();
+        assert!((0..10_000).all(|_| unlimited.check(a, start).is_ok()));
+
+        // Waiting on a manual clock advances it instead of sleeping.
+        let clock = ManualClock::new(Duration::from_secs(1_000));
+        let mut paced = RateLimiter::new();
+        paced.per_target(10.0, 1.0);
+        paced.wait_on(a, &clock);
+        paced.wait_on(a, &clock);
+        assert_eq!(clock.now(), Duration::from_millis(1_000_100));
+    }
+}
@@ -0,0 +1,200 @@
+//! Inter-packet gap shaping.
+//!
+//! Load tests need realistic timing as much as realistic packets. A
+//! [`Shaper`] spaces the packets of a generator or a replay after a
+//! [`GapModel`]: at a constant rate, with Poisson arrivals, in ON/OFF bursts,
+//! or following the recorded timestamps sped up by a factor. Random gaps are
+//! drawn with [`rng::random`], so they are reproducible inside an
+//! [`RngContext`](crate::utils::rng::RngContext).
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_packet::gen::shape::{GapModel, Shaper};
+//!
+//! let mut shaper = Shaper::new(GapModel::Replay { speedup: 2.0 });
+//! assert_eq!(shaper.gap(Duration::from_secs(10)), Duration::ZERO);
+//! assert_eq!(shaper.gap(Duration::from_secs(11)), Duration::from_millis(500));
+//! assert_eq!(shaper.offset(), Duration::from_millis(500));
+//! ```
+
+use std::time::Duration;
+
+use crate::utils::{clock::Clock, rng};
+
+/// Model of the gaps between packets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GapModel {
+    /// Packets evenly spaced at `rate` packets per second.
+    Constant {
+        /// Packets per second.
+        rate: f64,
+    },
+    /// Packets arriving as a Poisson process of `rate` packets per second,
+    /// i.e. with exponentially distributed gaps.
+    Poisson {
+        /// Mean packets per second.
+        rate: f64,
+    },
+    /// Bursts at `rate` packets per second lasting `on`, separated by `off`
+    /// of silence.
+    OnOff {
+        /// Packets per second within a burst.
+        rate: f64,
+        /// Length of a burst.
+        on: Duration,
+        /// Silence between bursts.
+        off: Duration,
+    },
+    /// Gaps of the recorded timestamps divided by `speedup`.
+    ///
+    /// A speedup which is not positive and finite sends as fast as possible.
+    Replay {
+        /// Factor by which the recording is sped up.
+        speedup: f64,
+    },
+}
+
+/// Scheduler of packet send times after a [`GapModel`].
+#[derive(Clone, Debug)]
+pub struct Shaper {
+    model: GapModel,
+    count: u64,
+    offset: Duration,
+    last_ts: Option<Duration>,
+    burst: Duration,
+    start: Option<Duration>,
+}
+
+impl Shaper {
+    /// Create a shaper of a model.
+    pub fn new(model: GapModel) -> Self {
+        Self {
+            model,
+            count: 0,
+            offset: Duration::ZERO,
+            last_ts: None,
+            burst: Duration::ZERO,
+            start: None,
+        }
+    }
+
+    /// Get the model.
+    pub fn model(&self) -> GapModel {
+        self.model
+    }
+
+    /// Get the number of packets scheduled.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Get the send time of the last packet scheduled, relative to the
+    /// first.
+    pub fn offset(&self) -> Duration {
+        self.offset
+    }
+
+    /// Schedule the next packet, returning the gap since the previous one.
+    ///
+    /// `ts` is the recorded timestamp of the packet, only used by
+    /// [`GapModel::Replay`]; the first packet has no gap.
+    pub fn gap(&mut self, ts: Duration) -> Duration {
+        let first = self.count == 0;
+        self.count += 1;
+        let gap = match self.model {
+            GapModel::Replay { speedup } => {
+                let recorded = self
+                    .last_ts
+                    .map_or(Duration::ZERO, |last| ts.saturating_sub(last));
+                self.last_ts = Some(ts);
+                if speedup.is_finite() && speedup > 0.0 {
+                    recorded.div_f64(speedup)
+                } else {
+                    Duration::ZERO
+                }
+            }
+            _ if first => Duration::ZERO,
+            GapModel::Constant { rate } => interval(rate),
+            GapModel::Poisson { rate } => {
+                // Inverse transform of a uniform draw in (0, 1].
+                let uniform = 1.0 - rng::random::<f64>();
+                interval(rate).mul_f64(-uniform.ln())
+            }
+            GapModel::OnOff { rate, on, off } => {
+                let gap = interval(rate);
+                if self.burst + gap > on {
+                    self.burst = Duration::ZERO;
+                    gap + off
+                } else {
+                    self.burst += gap;
+                    gap
+                }
+            }
+        };
+        self.offset += gap;
+        gap
+    }
+
+    /// Schedule the next packet and sleep on `clock` until it is due.
+    ///
+    /// The schedule starts at the first call, and does not drift when the
+    /// sender is late: missed gaps are caught up by sending at once.
+    pub fn wait(&mut self, ts: Duration, clock: &impl Clock) {
+        self.gap(ts);
+        let start = *self.start.get_or_insert_with(|| clock.now());
+        let due = start + self.offset;
+        let now = clock.now();
+        if due > now {
+            clock.sleep(due - now);
+        }
+    }
+}
+
+/// Gap between packets at `rate` packets per second, zero if unlimited.
+fn interval(rate: f64) -> Duration {
+    if rate.is_finite() && rate > 0.0 {
+        Duration::from_secs_f64(1.0 / rate)
+    } else {
+        Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{clock::ManualClock, rng::RngContext};
+
+    #[test]
+    fn shaper_models() {
+        let ms = Duration::from_millis;
+
+        let clock = ManualClock::new(Duration::from_secs(100));
+        let mut constant = Shaper::new(GapModel::Constant { rate: 100.0 });
+        for _ in 0..5 {
+            constant.wait(Duration::ZERO, &clock);
+        }
+        assert_eq!(clock.now(), Duration::from_secs(100) + ms(40));
+
+        let mut bursts = Shaper::new(GapModel::OnOff {
+            rate: 1000.0,
+            on: ms(2),
+            off: ms(10),
+        });
+        let gaps: Vec<_> = (0..5).map(|_| bursts.gap(Duration::ZERO)).collect();
+        assert_eq!(gaps, [ms(0), ms(1), ms(1), ms(11), ms(1)]);
+
+        let mut replay = Shaper::new(GapModel::Replay { speedup: 0.0 });
+        replay.gap(ms(5));
+        assert_eq!(replay.gap(ms(900)), Duration::ZERO);
+
+        let mut poisson = Shaper::new(GapModel::Poisson { rate: 1000.0 });
+        RngContext::new(3).enter(|| {
+            for _ in 0..10_000 {
+                poisson.gap(Duration::ZERO);
+            }
+        });
+        let mean = poisson.offset().as_secs_f64() / 9_999.0;
+        assert!((mean - 0.001).abs() < 0.0001, "{mean}");
+    }
+}
@@ -0,0 +1,14 @@
+//! Interoperability with other packet crates.
+//!
+//! Projects moving to netkit step by step keep code written against other
+//! packet crates for a while. The adapters here convert between their
+//! packet types and netkit layers without byte-level glue, each behind the
+//! feature of the same name:
+//!
+//! - `pnet`: views of `pnet_packet`, borrowing the same bytes;
+//! - `etherparse`: headers and sliced packets of `etherparse`.
+
+#[cfg(feature = "etherparse")]
+pub mod etherparse;
+#[cfg(feature = "pnet")]
+pub mod pnet;
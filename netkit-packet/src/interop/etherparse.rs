@@ -0,0 +1,73 @@
+//! Conversions between netkit layers and `etherparse` headers.
+//!
+//! Layers convert into the owned `etherparse` headers, and headers with
+//! their payload back into layers. Whole frames can also be sliced by
+//! `etherparse`:
+//!
+//! ```
+//! use etherparse::{Ipv4Header, UdpHeader};
+//! use netkit_packet::{interop::etherparse as interop, prelude::*};
+//!
+//! let udp = udp!(src_port: 40000u16, dst_port: 53u16, payload: b"query");
+//! let header = UdpHeader::try_from(&udp).unwrap();
+//! assert_eq!(header.destination_port, 53);
+//!
+//! let udp = interop::to_udp(&header, b"query").unwrap();
+//! assert_eq!(udp.payload(), b"query");
+//!
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//! let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+//! let sliced = interop::sliced(&eth).unwrap();
+//! assert!(matches!(sliced.transport, Some(etherparse::TransportSlice::Udp(_))));
+//! assert_eq!(Ipv4Header::try_from(&ipv4).unwrap().total_len as usize, ipv4.inner().len());
+//! ```
+
+use ::etherparse::{
+    err, Ethernet2Header, Ipv4Header, Ipv6Header, SlicedPacket, TcpHeader, UdpHeader,
+};
+
+use crate::prelude::*;
+
+/// Implement the conversions between a layer and an `etherparse` header.
+macro_rules! impl_etherparse {
+    ($layer:ident, $error:ty, $header:ident, $header_error:ty, $to:ident) => {
+        impl<T: AsRef<[u8]>> TryFrom<&$layer<T>> for $header {
+            type Error = $header_error;
+
+            /// Parse the header of the layer with `etherparse`.
+            fn try_from(layer: &$layer<T>) -> Result<Self, Self::Error> {
+                $header::from_slice(layer.inner().as_ref()).map(|(header, _)| header)
+            }
+        }
+
+        #[doc = concat!("Assemble a [`", stringify!($layer), "`] layer from an `etherparse` header and its payload.")]
+        pub fn $to(header: &$header, payload: &[u8]) -> Result<$layer<Vec<u8>>, $error> {
+            let mut data = header.to_bytes().to_vec();
+            data.extend_from_slice(payload);
+            $layer::new(data)
+        }
+    };
+}
+
+impl_etherparse!(Eth, EthError, Ethernet2Header, err::LenError, to_eth);
+impl_etherparse!(
+    Ipv4,
+    Ipv4Error,
+    Ipv4Header,
+    err::ipv4::HeaderSliceError,
+    to_ipv4
+);
+impl_etherparse!(
+    Ipv6,
+    Ipv6Error,
+    Ipv6Header,
+    err::ipv6::HeaderSliceError,
+    to_ipv6
+);
+impl_etherparse!(Tcp, TcpError, TcpHeader, err::tcp::HeaderSliceError, to_tcp);
+impl_etherparse!(Udp, UdpError, UdpHeader, err::LenError, to_udp);
+
+/// Slice an Ethernet frame with `etherparse`, down to its transport layer.
+pub fn sliced<T: AsRef<[u8]>>(eth: &Eth<T>) -> Result<SlicedPacket<'_>, err::packet::SliceError> {
+    SlicedPacket::from_ethernet(eth.inner().as_ref())
+}
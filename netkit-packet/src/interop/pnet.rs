@@ -0,0 +1,110 @@
+//! Conversions between netkit layers and `pnet_packet` views.
+//!
+//! Both sides are views over the same bytes, so conversions borrow the data
+//! of the converted layer rather than copying it:
+//!
+//! ```
+//! use netkit_packet::prelude::*;
+//! use pnet_packet::{ethernet::EthernetPacket, Packet};
+//!
+//! let udp = udp!(src_port: 40000u16, dst_port: 53u16, payload: b"query");
+//! let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+//! let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+//!
+//! let packet = EthernetPacket::try_from(&eth).unwrap();
+//! assert_eq!(packet.payload(), ipv4.inner().as_slice());
+//!
+//! let eth = Eth::try_from(&packet).unwrap();
+//! assert_eq!(eth.ipv4().unwrap().udp().unwrap().dst_port().get(), 53);
+//! ```
+//!
+//! Mutable `pnet_packet` views convert through their `to_immutable`.
+
+use pnet_packet::{
+    ethernet::EthernetPacket, ipv4::Ipv4Packet, ipv6::Ipv6Packet, tcp::TcpPacket, udp::UdpPacket,
+    Packet,
+};
+
+use crate::prelude::*;
+
+/// Implement the conversions between a layer and a `pnet_packet` view.
+macro_rules! impl_pnet {
+    ($layer:ident, $error:ty, $packet:ident) => {
+        impl<'a, T: AsRef<[u8]>> TryFrom<&'a $layer<T>> for $packet<'a> {
+            type Error = LayerError;
+
+            /// View the layer as a `pnet_packet` packet.
+            ///
+            /// Fails if the layer is shorter than the fixed header of the
+            /// packet, i.e. was created unchecked.
+            fn try_from(layer: &'a $layer<T>) -> Result<Self, Self::Error> {
+                let data = layer.inner().as_ref();
+                $packet::new(data).ok_or_else(|| {
+                    LayerError::min_length(
+                        stringify!($packet),
+                        "header",
+                        0,
+                        $packet::minimum_packet_size(),
+                        data.len(),
+                    )
+                })
+            }
+        }
+
+        impl<'a> TryFrom<&'a $packet<'_>> for $layer<&'a [u8]> {
+            type Error = $error;
+
+            /// View the `pnet_packet` packet as a layer, validating it.
+            fn try_from(packet: &'a $packet<'_>) -> Result<Self, Self::Error> {
+                $layer::new(packet.packet())
+            }
+        }
+    };
+}
+
+impl_pnet!(Eth, EthError, EthernetPacket);
+impl_pnet!(Ipv4, Ipv4Error, Ipv4Packet);
+impl_pnet!(Ipv6, Ipv6Error, Ipv6Packet);
+impl_pnet!(Tcp, TcpError, TcpPacket);
+impl_pnet!(Udp, UdpError, UdpPacket);
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use pnet_packet::ip::IpNextHeaderProtocols;
+
+    use super::*;
+
+    #[test]
+    fn pnet_interop() {
+        let tcp = tcp!(src_port: 40000u16, dst_port: 443u16, seq_num: 7u32, payload: b"hello");
+        let ipv4 = ipv4!(
+            protocol: IpProtocol::Tcp,
+            src: Ipv4Addr::new(192, 0, 2, 1),
+            dst: Ipv4Addr::new(192, 0, 2, 2),
+            payload: tcp.inner()
+        );
+
+        let packet = Ipv4Packet::try_from(&ipv4).unwrap();
+        assert_eq!(packet.get_source(), Ipv4Addr::new(192, 0, 2, 1));
+        assert_eq!(packet.get_next_level_protocol(), IpNextHeaderProtocols::Tcp);
+        let segment = TcpPacket::new(packet.payload()).unwrap();
+        assert_eq!(
+            (segment.get_sequence(), segment.payload()),
+            (7, &b"hello"[..])
+        );
+
+        let layer = Tcp::try_from(&segment).unwrap();
+        assert_eq!(layer.dst_port().get(), 443);
+        assert_eq!(*Ipv4::try_from(&packet).unwrap().inner(), &ipv4.inner()[..]);
+
+        // Only the length of the data is read.
+        let short = unsafe { Udp::new_unchecked(&[0u8; 4][..]) };
+        let error = UdpPacket::try_from(&short).unwrap_err();
+        assert_eq!(
+            error.constraint,
+            Constraint::MinLength { min: 8, actual: 4 }
+        );
+    }
+}
@@ -1,18 +1,83 @@
 //! The implementation of various network layers.
 
+pub mod assemble;
+pub mod dccp;
 pub mod dns;
+pub mod eapol;
+pub mod error;
 pub mod eth;
+pub mod gre;
+pub mod icmp;
+pub mod industrial;
 pub mod ip;
+pub mod ipsec;
+pub mod l2tp;
+pub mod link;
+pub mod llc;
+pub mod netflow;
+pub mod null;
+pub mod openvpn;
+pub mod ppp;
+pub mod ptp;
+pub mod quic;
+pub mod rtp;
+pub mod sflow;
+pub mod sll2;
 pub mod tcp;
 pub mod udp;
+pub mod udplite;
+pub mod vlan;
+pub mod wireguard;
 
 /// prelude module for layer.
 pub mod prelude {
+    pub use super::dccp::{Dccp, DccpError, DccpType};
+
+    pub use super::dns::{Dns, DnsError};
+
+    pub use super::eapol::{Eap, EapCode, EapError, EapMethod, Eapol, EapolError, EapolType};
+
+    pub use super::error::{Constraint, LayerError};
+
     pub use super::eth::{Eth, EthAddr, EthAddrError, EthError, EthType};
 
-    pub use super::ip::{IpProtocol, Ipv4, Ipv4Error};
+    pub use super::gre::{Gre, GreError};
+
+    pub use super::icmp::{Icmp, IcmpError, IcmpType};
+
+    pub use super::industrial::{Goose, GooseError, GoosePdu, Sv, SvAsdu, SvError};
+
+    pub use super::ip::{IpProtocol, Ipv4, Ipv4Error, Ipv6, Ipv6Error};
+
+    pub use super::ipsec::{Ah, AhError, Esp, EspDecryptor, EspError, EspPlaintext, EspSaTable};
+
+    pub use super::l2tp::{L2tp, L2tpError};
+
+    pub use super::link::LinkType;
+
+    pub use super::llc::{Llc, LlcError, LlcProtocol};
+
+    pub use super::null::{Null, NullError};
+
+    pub use super::openvpn::{OpenVpn, OpenVpnError, OpenVpnOpcode};
+
+    pub use super::ppp::{Ppp, PppError, PppProtocol};
+
+    pub use super::ptp::{ClockIdentity, Ptp, PtpError, PtpMessageType, PtpTimestamp};
+
+    pub use super::quic::{Quic, QuicError};
+
+    pub use super::rtp::{Rtp, RtpError};
+
+    pub use super::sll2::{Sll2, Sll2Error};
 
     pub use super::udp::{Udp, UdpError};
 
+    pub use super::udplite::{UdpLite, UdpLiteError};
+
     pub use super::tcp::{Tcp, TcpError};
+
+    pub use super::vlan::{Vlan, VlanError, VlanTag};
+
+    pub use super::wireguard::{WireGuard, WireGuardError, WireGuardMessageType};
 }
@@ -0,0 +1,121 @@
+//! Assembly of nested layers into a caller buffer.
+//!
+//! Building layers one at a time copies the payload at every nesting level:
+//! into the Udp layer, then the Ipv4 layer, then the Eth frame. [`assemble`]
+//! instead takes the builders of all the layers and a borrowed payload, and
+//! writes the frame into a caller buffer with a single copy of the payload.
+//!
+//! ```
+//! use netkit_packet::layer::{
+//!     assemble::assemble, eth::EthBuilder, ip::Ipv4Builder, udp::UdpBuilder,
+//! };
+//! use netkit_packet::prelude::*;
+//!
+//! let payload = vec![0xAB; 1400];
+//! let mut eth = EthBuilder::new();
+//! eth.eth_type(EthType::Ipv4);
+//! let mut ipv4 = Ipv4Builder::new();
+//! ipv4.protocol(IpProtocol::Udp);
+//! let mut udp = UdpBuilder::new();
+//! udp.src_port(1234u16).dst_port(5678u16);
+//!
+//! let mut buf = Vec::new();
+//! let frame = assemble(&[&eth, &ipv4, &udp], &payload, &mut buf);
+//! let eth = Eth::new(&frame[..]).unwrap();
+//! assert_eq!(eth.ipv4().unwrap().total_length().get(), 20 + 8 + 1400);
+//! assert_eq!(eth.ipv4().unwrap().udp().unwrap().payload(), payload);
+//! ```
+
+/// A layer builder writing its header in front of a payload in place.
+///
+/// Payloads set on the builder itself are not used.
+pub trait Assemble {
+    /// Get the length of the header, i.e. everything before the payload.
+    fn header_len(&self) -> usize;
+
+    /// Write the header at the start of `segment`.
+    ///
+    /// `segment` holds the header, zeroed, followed by the payload already
+    /// in place, so lengths and checksums can be computed from it.
+    fn write_header(&self, segment: &mut [u8]);
+}
+
+/// Assemble nested layers, outermost first, around a payload.
+///
+/// The frame is appended to `buf`, which is grown at most once, and
+/// returned. Headers are written innermost first, so the checksum of a
+/// layer covers the final bytes of the layers it carries.
+pub fn assemble<'a>(
+    layers: &[&dyn Assemble],
+    payload: &[u8],
+    buf: &'a mut Vec<u8>,
+) -> &'a mut [u8] {
+    let headers: usize = layers.iter().map(|l| l.header_len()).sum();
+    let start = buf.len();
+    buf.reserve(headers + payload.len());
+    buf.resize(start + headers, 0);
+    buf.extend_from_slice(payload);
+
+    let frame = &mut buf[start..];
+    let mut offsets = Vec::with_capacity(layers.len());
+    let mut offset = 0;
+    for layer in layers {
+        offsets.push(offset);
+        offset += layer.header_len();
+    }
+    for (layer, offset) in layers.iter().zip(offsets).rev() {
+        layer.write_header(&mut frame[offset..]);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{
+        layer::{eth::EthBuilder, ip::Ipv4Builder, tcp::TcpBuilder, udp::UdpBuilder},
+        prelude::*,
+    };
+
+    #[test]
+    fn assemble_layers() {
+        let payload = [1, 2, 3, 4, 5];
+        let (src, dst) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+
+        let mut eth = EthBuilder::new();
+        eth.src([2, 0, 0, 0, 0, 1])
+            .eth_type(EthType::Ipv4)
+            .vlan(10u16)
+            .vlan(20u16);
+        let mut ipv4 = Ipv4Builder::new();
+        ipv4.src(src)
+            .dst(dst)
+            .protocol(IpProtocol::Udp)
+            .options([1, 1, 1, 1]);
+        let mut udp = UdpBuilder::new();
+        udp.src_port(53u16).pseudo_header((src, dst));
+
+        // Same frame as building the layers one by one.
+        let nested = eth
+            .clone()
+            .payload(
+                ipv4.clone()
+                    .payload(udp.clone().payload(payload).build().inner())
+                    .build()
+                    .inner(),
+            )
+            .build();
+        let mut buf = vec![0xFF];
+        let frame = assemble(&[&eth, &ipv4, &udp], &payload, &mut buf).to_vec();
+        assert_eq!(&frame, nested.inner());
+        assert_eq!(buf.len(), 1 + frame.len());
+
+        let mut tcp = TcpBuilder::new();
+        tcp.seq_num(7u32).options([1, 1, 1, 1]);
+        let nested = tcp.clone().payload(payload).build();
+        let mut buf = Vec::new();
+        assert_eq!(assemble(&[&tcp], &payload, &mut buf), nested.inner());
+    }
+}
@@ -0,0 +1,700 @@
+//! Datagram Congestion Control Protocol (DCCP) layer.
+//!
+//! DCCP ([RFC 4340]) carries unreliable datagrams over congestion
+//! controlled connections. Every packet has a sequence number, 48 bits long
+//! with the extended sequence numbers bit set and 24 bits otherwise, most
+//! types acknowledge one, and connections are opened for a service code.
+//!
+//! [RFC 4340]: https://datatracker.ietf.org/doc/html/rfc4340
+
+use core::net::IpAddr;
+
+use crate::{
+    field_spec,
+    layer::{assemble::Assemble, udp::PortSpec},
+    prelude::*,
+};
+
+pub mod packet_type;
+pub use packet_type::DccpType;
+
+/// Error type for Dccp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum DccpError {
+    /// Invalid Dccp length.
+    #[error("Invalid Dccp length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Reserved Dccp packet type.
+    #[error("Invalid Dccp packet type: {0}")]
+    InvalidType(u8),
+
+    /// Invalid Dccp checksum.
+    #[error("Invalid Dccp checksum")]
+    InvalidChecksum,
+}
+
+field_spec!(DataOffsetSpec, u8, u8);
+field_spec!(CcValSpec, u8, u8, 0xF0, 4);
+field_spec!(CsCovSpec, u8, u8, 0x0F);
+field_spec!(ChecksumSpec, u16, u16);
+field_spec!(TypeSpec, DccpType, u8, 0x1E, 1);
+field_spec!(ExtendedSpec, bool, u8, 0x01);
+
+/// Minimum length of a Dccp header, with short sequence numbers.
+pub const MIN_HEADER_LENGTH: usize = 12;
+
+/// Datagram Congestion Control Protocol (DCCP) layer.
+///
+/// ```text
+///   0                   1                   2                   3
+///   0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///  |          Source Port          |           Dest Port           |
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///  |  Data Offset  | CCVal | CsCov |           Checksum            |
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///  | Res | Type  |X|   Reserved    |  Sequence Number (high bits)  .
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///  .                  Sequence Number (low bits)                   |
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// Without the extended bit `X`, the reserved byte and the high bits are
+/// replaced by a 24-bit sequence number. The acknowledgement subheader and
+/// the service code follow, depending on the type, then the options up to
+/// the data offset.
+pub struct Dccp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Dccp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the source port: 0..2
+    pub const FIELD_SRC_PORT: core::ops::Range<usize> = 0..2;
+    /// Field range of the destination port: 2..4
+    pub const FIELD_DST_PORT: core::ops::Range<usize> = 2..4;
+    /// Field range of the data offset: 4..5
+    pub const FIELD_DATA_OFFSET: core::ops::Range<usize> = 4..5;
+    /// Field range of the CCVal: 5..6 (4bits)
+    pub const FIELD_CCVAL: core::ops::Range<usize> = 5..6;
+    /// Field range of the checksum coverage: 5..6 (4bits)
+    pub const FIELD_CSCOV: core::ops::Range<usize> = 5..6;
+    /// Field range of the checksum: 6..8
+    pub const FIELD_CHECKSUM: core::ops::Range<usize> = 6..8;
+    /// Field range of the packet type: 8..9 (4bits)
+    pub const FIELD_TYPE: core::ops::Range<usize> = 8..9;
+    /// Field range of the extended sequence numbers bit: 8..9 (1bit)
+    pub const FIELD_EXTENDED: core::ops::Range<usize> = 8..9;
+
+    /// Create a new Dccp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Dccp packet.
+    ///
+    /// The data must be at least as long as the header required by the
+    /// type and the extended bit, and the data offset must be set
+    /// correctly. Otherwise, the following methods may panic when accessing
+    /// the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Dccp layer.
+    pub fn validate(&self) -> Result<(), DccpError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("Dccp", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+
+        if let DccpType::Unknown(packet_type) = self.packet_type().get() {
+            return Err(DccpError::InvalidType(packet_type));
+        }
+
+        let fixed_len = self.fixed_len();
+        if len < fixed_len {
+            return Err(LayerError::min_length("Dccp", "header", 0, fixed_len, len).into());
+        }
+
+        let offset = Self::FIELD_DATA_OFFSET.start;
+        let header_len = self.header_len();
+        if header_len < fixed_len {
+            return Err(LayerError::min_value(
+                "Dccp",
+                "data_offset",
+                offset,
+                fixed_len,
+                header_len,
+            )
+            .into());
+        }
+        if header_len > len {
+            return Err(
+                LayerError::max_value("Dccp", "data_offset", offset, len, header_len).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Dccp layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, DccpError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the length of the header, options included, from the data
+    /// offset.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        self.data_offset().get() as usize * 4
+    }
+
+    /// Get the length of the generic header, with the sequence number.
+    #[inline]
+    fn generic_len(&self) -> usize {
+        if self.extended().get() {
+            16
+        } else {
+            12
+        }
+    }
+
+    /// Get the length of the acknowledgement subheader, if any.
+    #[inline]
+    fn ack_len(&self) -> usize {
+        match (self.packet_type().get().has_ack(), self.extended().get()) {
+            (false, _) => 0,
+            (true, true) => 8,
+            (true, false) => 4,
+        }
+    }
+
+    /// Get the length of the header up to the options.
+    #[inline]
+    fn fixed_len(&self) -> usize {
+        let service_len = match self.packet_type().get() {
+            DccpType::Request | DccpType::Response => 4,
+            _ => 0,
+        };
+        self.generic_len() + self.ack_len() + service_len
+    }
+
+    /// Get the accessor of the source port.
+    #[inline]
+    pub fn src_port(&self) -> &Field<PortSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SRC_PORT])
+    }
+
+    /// Get the accessor of the destination port.
+    #[inline]
+    pub fn dst_port(&self) -> &Field<PortSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DST_PORT])
+    }
+
+    /// Get the accessor of the data offset, in 32-bit words.
+    #[inline]
+    pub fn data_offset(&self) -> &Field<DataOffsetSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DATA_OFFSET])
+    }
+
+    /// Get the accessor of the CCVal, used by the congestion control.
+    #[inline]
+    pub fn ccval(&self) -> &Field<CcValSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CCVAL])
+    }
+
+    /// Get the accessor of the checksum coverage.
+    ///
+    /// Zero covers the whole packet, `n` the header and `n - 1` words of the
+    /// payload.
+    #[inline]
+    pub fn cscov(&self) -> &Field<CsCovSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CSCOV])
+    }
+
+    /// Get the accessor of the checksum.
+    #[inline]
+    pub fn checksum(&self) -> &Field<ChecksumSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CHECKSUM])
+    }
+
+    /// Get the accessor of the packet type.
+    #[inline]
+    pub fn packet_type(&self) -> &Field<TypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_TYPE])
+    }
+
+    /// Get the accessor of the extended sequence numbers bit.
+    #[inline]
+    pub fn extended(&self) -> &Field<ExtendedSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_EXTENDED])
+    }
+
+    /// Get the sequence number, 48 or 24 bits long.
+    #[inline]
+    pub fn seq_num(&self) -> u64 {
+        let data = self.data.as_ref();
+        if self.extended().get() {
+            read_uint(&data[10..16])
+        } else {
+            read_uint(&data[9..12])
+        }
+    }
+
+    /// Get the acknowledgement number, if the type carries one.
+    #[inline]
+    pub fn ack_num(&self) -> Option<u64> {
+        let start = self.generic_len();
+        let data = self.data.as_ref();
+        match self.ack_len() {
+            0 => None,
+            8 => Some(read_uint(&data[start + 2..start + 8])),
+            _ => Some(read_uint(&data[start + 1..start + 4])),
+        }
+    }
+
+    /// Get the service code of Request and Response packets.
+    #[inline]
+    pub fn service_code(&self) -> Option<u32> {
+        match self.packet_type().get() {
+            DccpType::Request | DccpType::Response => {
+                let start = self.generic_len() + self.ack_len();
+                Some(read_uint(&self.data.as_ref()[start..start + 4]) as u32)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the options.
+    #[inline]
+    pub fn options(&self) -> &[u8] {
+        &self.data.as_ref()[self.fixed_len()..self.header_len()]
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+
+    /// Get the number of bytes covered by the checksum, header included.
+    #[inline]
+    pub fn covered_len(&self) -> usize {
+        let len = self.data.as_ref().len();
+        match self.cscov().get() as usize {
+            0 => len,
+            cscov => (self.header_len() + (cscov - 1) * 4).min(len),
+        }
+    }
+
+    /// Compute the checksum over the pseudo header of the given addresses.
+    ///
+    /// The pseudo header holds the length of the whole packet, while only
+    /// the covered bytes are summed, ignoring the current value of the
+    /// checksum field.
+    pub fn compute_checksum(&self, src: IpAddr, dst: IpAddr) -> u16 {
+        let data = self.data.as_ref();
+        let covered = &data[..self.covered_len()];
+
+        Checksum::new()
+            .add_pseudo_header(src, dst, IpProtocol::Dccp, data.len())
+            .add_bytes(&covered[..Self::FIELD_CHECKSUM.start])
+            .add_bytes(&covered[Self::FIELD_CHECKSUM.end..])
+            .finish()
+    }
+
+    /// Verify the checksum against the pseudo header of the given addresses.
+    pub fn verify_checksum(&self, src: IpAddr, dst: IpAddr) -> Result<(), DccpError> {
+        if self.checksum().get() == self.compute_checksum(src, dst) {
+            Ok(())
+        } else {
+            Err(DccpError::InvalidChecksum)
+        }
+    }
+}
+
+impl<T> Dccp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the source port.
+    #[inline]
+    pub fn src_port_mut(&mut self) -> &mut Field<PortSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SRC_PORT])
+    }
+
+    /// Get the mutable accessor of the destination port.
+    #[inline]
+    pub fn dst_port_mut(&mut self) -> &mut Field<PortSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DST_PORT])
+    }
+
+    /// Get the mutable accessor of the data offset.
+    #[inline]
+    pub fn data_offset_mut(&mut self) -> &mut Field<DataOffsetSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DATA_OFFSET])
+    }
+
+    /// Get the mutable accessor of the CCVal.
+    #[inline]
+    pub fn ccval_mut(&mut self) -> &mut Field<CcValSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CCVAL])
+    }
+
+    /// Get the mutable accessor of the checksum coverage.
+    #[inline]
+    pub fn cscov_mut(&mut self) -> &mut Field<CsCovSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CSCOV])
+    }
+
+    /// Get the mutable accessor of the checksum.
+    #[inline]
+    pub fn checksum_mut(&mut self) -> &mut Field<ChecksumSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CHECKSUM])
+    }
+
+    /// Get the mutable accessor of the packet type.
+    #[inline]
+    pub fn packet_type_mut(&mut self) -> &mut Field<TypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_TYPE])
+    }
+
+    /// Get the mutable accessor of the extended sequence numbers bit.
+    #[inline]
+    pub fn extended_mut(&mut self) -> &mut Field<ExtendedSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_EXTENDED])
+    }
+
+    /// Set the sequence number, truncated to 48 or 24 bits.
+    #[inline]
+    pub fn set_seq_num(&mut self, seq_num: u64) {
+        let range = if self.extended().get() { 10..16 } else { 9..12 };
+        write_uint(&mut self.data.as_mut()[range], seq_num);
+    }
+
+    /// Set the acknowledgement number, truncated to 48 or 24 bits, if the
+    /// type carries one.
+    #[inline]
+    pub fn set_ack_num(&mut self, ack_num: u64) {
+        let start = self.generic_len();
+        let range = match self.ack_len() {
+            0 => return,
+            8 => start + 2..start + 8,
+            _ => start + 1..start + 4,
+        };
+        write_uint(&mut self.data.as_mut()[range], ack_num);
+    }
+
+    /// Set the service code of Request and Response packets.
+    #[inline]
+    pub fn set_service_code(&mut self, service_code: u32) {
+        if let DccpType::Request | DccpType::Response = self.packet_type().get() {
+            let start = self.generic_len() + self.ack_len();
+            write_uint(
+                &mut self.data.as_mut()[start..start + 4],
+                service_code.into(),
+            );
+        }
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[header_len..]
+    }
+}
+
+layer_impl!(Dccp);
+
+/// Read a big-endian unsigned integer of up to 8 bytes.
+fn read_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+/// Write the low bytes of a big-endian unsigned integer.
+fn write_uint(bytes: &mut [u8], n: u64) {
+    let len = bytes.len();
+    bytes.copy_from_slice(&n.to_be_bytes()[8 - len..]);
+}
+
+/// Builder for [`Dccp`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct DccpBuilder {
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    packet_type: Option<DccpType>,
+    short_seq: bool,
+    seq_num: Option<u64>,
+    ack_num: Option<u64>,
+    service_code: Option<u32>,
+    ccval: Option<u8>,
+    cscov: Option<u8>,
+    checksum: Option<u16>,
+    pseudo_header: Option<(IpAddr, IpAddr)>,
+    options: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl DccpBuilder {
+    /// Create a new Dccp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the source port.
+    pub fn src_port(&mut self, src_port: impl Into<u16>) -> &mut Self {
+        self.src_port = Some(src_port.into());
+        self
+    }
+
+    /// Set the destination port.
+    pub fn dst_port(&mut self, dst_port: impl Into<u16>) -> &mut Self {
+        self.dst_port = Some(dst_port.into());
+        self
+    }
+
+    /// Set the packet type, Data by default.
+    pub fn packet_type(&mut self, packet_type: impl Into<DccpType>) -> &mut Self {
+        self.packet_type = Some(packet_type.into());
+        self
+    }
+
+    /// Set whether 24-bit sequence numbers are used instead of 48-bit ones.
+    pub fn short_seq(&mut self, short_seq: bool) -> &mut Self {
+        self.short_seq = short_seq;
+        self
+    }
+
+    /// Set the sequence number.
+    pub fn seq_num(&mut self, seq_num: impl Into<u64>) -> &mut Self {
+        self.seq_num = Some(seq_num.into());
+        self
+    }
+
+    /// Set the acknowledgement number, ignored unless the type carries one.
+    pub fn ack_num(&mut self, ack_num: impl Into<u64>) -> &mut Self {
+        self.ack_num = Some(ack_num.into());
+        self
+    }
+
+    /// Set the service code, ignored unless a Request or Response is built.
+    pub fn service_code(&mut self, service_code: impl Into<u32>) -> &mut Self {
+        self.service_code = Some(service_code.into());
+        self
+    }
+
+    /// Set the CCVal.
+    pub fn ccval(&mut self, ccval: impl Into<u8>) -> &mut Self {
+        self.ccval = Some(ccval.into());
+        self
+    }
+
+    /// Set the checksum coverage, zero (the whole packet) by default.
+    pub fn cscov(&mut self, cscov: impl Into<u8>) -> &mut Self {
+        self.cscov = Some(cscov.into());
+        self
+    }
+
+    /// Set the checksum.
+    pub fn checksum(&mut self, checksum: impl Into<u16>) -> &mut Self {
+        self.checksum = Some(checksum.into());
+        self
+    }
+
+    /// Set the source and destination addresses of the pseudo header, so
+    /// that the checksum is computed.
+    pub fn pseudo_header<S, D>(&mut self, (src, dst): (S, D)) -> &mut Self
+    where
+        S: Into<IpAddr>,
+        D: Into<IpAddr>,
+    {
+        self.pseudo_header = Some((src.into(), dst.into()));
+        self
+    }
+
+    /// Set the options, padded to a multiple of 4 bytes.
+    pub fn options<T: AsRef<[u8]>>(&mut self, options: T) -> &mut Self {
+        self.options.extend_from_slice(options.as_ref());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build a Dccp layer.
+    ///
+    /// Unless set explicitly, the checksum is computed when the pseudo
+    /// header is set, and left zero otherwise.
+    pub fn build(&self) -> Dccp<Vec<u8>> {
+        let header_len = self.header_len();
+        let mut data = vec![0; header_len + self.payload.len()];
+        data[header_len..].copy_from_slice(self.payload.as_ref());
+        self.write_header(&mut data);
+
+        unsafe { Dccp::new_unchecked(data) }
+    }
+}
+
+impl Assemble for DccpBuilder {
+    fn header_len(&self) -> usize {
+        let packet_type = self.packet_type.unwrap_or(DccpType::Data);
+        let generic_len = if self.short_seq { 12 } else { 16 };
+        let ack_len = match (packet_type.has_ack(), self.short_seq) {
+            (false, _) => 0,
+            (true, true) => 4,
+            (true, false) => 8,
+        };
+        let service_len = match packet_type {
+            DccpType::Request | DccpType::Response => 4,
+            _ => 0,
+        };
+        (generic_len + ack_len + service_len + self.options.len()).next_multiple_of(4)
+    }
+
+    fn write_header(&self, segment: &mut [u8]) {
+        let header_len = self.header_len();
+        let mut dccp = unsafe { Dccp::new_unchecked(segment) };
+
+        dccp.src_port_mut().set(self.src_port.unwrap_or_default());
+        dccp.dst_port_mut().set(self.dst_port.unwrap_or_default());
+        dccp.data_offset_mut().set((header_len / 4) as u8);
+        dccp.ccval_mut().set(self.ccval.unwrap_or_default());
+        dccp.cscov_mut().set(self.cscov.unwrap_or_default());
+        dccp.packet_type_mut()
+            .set(self.packet_type.unwrap_or(DccpType::Data));
+        dccp.extended_mut().set(!self.short_seq);
+        dccp.set_seq_num(self.seq_num.unwrap_or_default());
+        dccp.set_ack_num(self.ack_num.unwrap_or_default());
+        dccp.set_service_code(self.service_code.unwrap_or_default());
+        let fixed_len = dccp.fixed_len();
+        dccp.inner_mut()[fixed_len..fixed_len + self.options.len()].copy_from_slice(&self.options);
+
+        let checksum = match (self.checksum, self.pseudo_header) {
+            (Some(checksum), _) => checksum,
+            (None, Some((src, dst))) => dccp.compute_checksum(src, dst),
+            (None, None) => 0,
+        };
+        dccp.checksum_mut().set(checksum);
+    }
+}
+
+/// Create a new Dccp layer with the given fields.
+#[macro_export]
+macro_rules! dccp {
+    ($($field : ident : $value : expr),* $(,)? ) => {
+        $crate::layer::dccp::DccpBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::{IpAddr, Ipv6Addr};
+
+    use crate::prelude::*;
+
+    #[test]
+    fn dccp_headers() {
+        // A Request with extended sequence numbers and a Reset with short
+        // ones, as captured.
+        let request: [u8; 20] = [
+            0x9c, 0x40, 0x13, 0x89, // ports 40000 -> 5001
+            0x05, 0x00, 0x00, 0x00, // data offset 5, checksum
+            0x01, 0x00, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78, // Request, X, seq
+            0x00, 0x00, 0x00, 0x2a, // service code 42
+        ];
+        let dccp = Dccp::new(&request[..]).unwrap();
+        assert_eq!(dccp.packet_type().get(), DccpType::Request);
+        assert!(dccp.extended().get());
+        assert_eq!(dccp.seq_num(), 0x1234_5678);
+        assert_eq!((dccp.ack_num(), dccp.service_code()), (None, Some(42)));
+        assert!(dccp.payload().is_empty());
+
+        let ack: [u8; 16] = [
+            0x13, 0x89, 0x9c, 0x40, 0x04, 0x00, 0x00, 0x00, //
+            0x06, 0x00, 0x01, 0x02, 0x00, 0x00, 0x01, 0x03, // Ack, seq, ack
+        ];
+        let dccp = Dccp::new(&ack[..]).unwrap();
+        assert_eq!(dccp.packet_type().get(), DccpType::Ack);
+        assert_eq!((dccp.seq_num(), dccp.ack_num()), (0x0102, Some(0x0103)));
+
+        let (src, dst) = (
+            IpAddr::from(Ipv6Addr::LOCALHOST),
+            IpAddr::from(Ipv6Addr::LOCALHOST),
+        );
+        let mut built = dccp!(
+            src_port: 5001u16,
+            dst_port: 40000u16,
+            packet_type: DccpType::Response,
+            seq_num: 0xAAAA_BBBB_CCCCu64,
+            ack_num: 0x1234_5678u64,
+            service_code: 42u32,
+            options: [0x20, 0x01],
+            cscov: 1u8,
+            pseudo_header: (src, dst),
+            payload: b"data",
+        );
+        let dccp = Dccp::new(built.inner()).unwrap();
+        assert_eq!(dccp.header_len(), 32);
+        assert_eq!(dccp.seq_num(), 0xAAAA_BBBB_CCCC);
+        assert_eq!(dccp.ack_num(), Some(0x1234_5678));
+        assert_eq!(dccp.service_code(), Some(42));
+        assert_eq!(dccp.options(), [0x20, 0x01, 0, 0]);
+        assert_eq!(dccp.payload(), b"data");
+        assert_eq!(dccp.verify_checksum(src, dst), Ok(()));
+        // The payload is not covered
+        built.payload_mut()[0] = b'D';
+        assert_eq!(built.verify_checksum(src, dst), Ok(()));
+
+        let mut truncated = request;
+        truncated[8] = 0x03; // Response, which needs an acknowledgement
+        assert_eq!(
+            Dccp::new(&truncated[..]).err(),
+            Some(DccpError::InvalidLength(LayerError::min_length(
+                "Dccp", "header", 0, 28, 20
+            )))
+        );
+        truncated[8] = 0x15; // Reserved type 10
+        assert_eq!(
+            Dccp::new(&truncated[..]).err(),
+            Some(DccpError::InvalidType(10))
+        );
+
+        let ipv6 = ipv6!(
+            next_header: IpProtocol::Dccp,
+            payload: built.inner(),
+        );
+        let key = FlowKey::from_ipv6(&ipv6);
+        assert_eq!((key.src_port, key.dst_port), (5001, 40000));
+        assert_eq!(ipv6.dccp().unwrap().service_code(), Some(42));
+    }
+}
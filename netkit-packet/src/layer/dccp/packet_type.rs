@@ -0,0 +1,100 @@
+//! DCCP Packet Type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// DCCP Packet Type
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum DccpType {
+    /// Initiate a connection, from the client
+    Request = 0,
+
+    /// Respond to a Request, from the server
+    Response = 1,
+
+    /// Application data
+    Data = 2,
+
+    /// Acknowledgement
+    Ack = 3,
+
+    /// Application data with an acknowledgement
+    DataAck = 4,
+
+    /// Ask the client to close, from the server
+    CloseReq = 5,
+
+    /// Close the connection
+    Close = 6,
+
+    /// Terminate the connection
+    Reset = 7,
+
+    /// Resynchronize sequence numbers
+    Sync = 8,
+
+    /// Acknowledge a Sync
+    SyncAck = 9,
+
+    /// Unknown, reserved
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl DccpType {
+    /// Whether packets of this type carry an acknowledgement number.
+    pub fn has_ack(&self) -> bool {
+        !matches!(
+            self,
+            DccpType::Request | DccpType::Data | DccpType::Unknown(_)
+        )
+    }
+}
+
+impl_target!(frominto, DccpType, u8);
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn packet_type_num() {
+        test_enum_num!(
+            DccpType : u8,
+            Request => 0,
+            Response => 1,
+            Data => 2,
+            Ack => 3,
+            DataAck => 4,
+            CloseReq => 5,
+            Close => 6,
+            Reset => 7,
+            Sync => 8,
+            SyncAck => 9,
+        );
+        test_enum_str!(DccpType, DataAck => "DataAck", CloseReq => "CloseReq");
+    }
+}
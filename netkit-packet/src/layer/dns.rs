@@ -23,12 +23,17 @@ pub use rrtype::DnsRrType;
 pub mod class;
 pub use class::DnsClass;
 
+pub mod record;
+use record::NameCompressor;
+pub use record::{DnsRData, DnsRecord};
+
 /// Error type for Dns layer
 #[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum DnsError {
     /// Invalid Dns length
-    #[error("Invalid Dns length: Length {0} is less than 12")]
-    InvalidLength(usize),
+    #[error("Invalid Dns length: {0}")]
+    InvalidLength(#[from] LayerError),
 }
 
 field_spec!(IdSpec, u16, u16);
@@ -45,6 +50,12 @@ field_spec!(CountSpec, u16, u16);
 /// Minimum length of a Dns header
 pub const MIN_HEADER_LENGTH: usize = 12;
 
+/// Default port of DNS, over both UDP and TCP
+pub const DEFAULT_PORT: u16 = 53;
+
+/// Maximum size of a DNS message over UDP without EDNS (RFC 1035)
+pub const MAX_UDP_SIZE: usize = 512;
+
 /// Domain Name System (DNS) layer
 pub struct Dns<T>
 where
@@ -97,7 +108,9 @@ where
     /// Validate the DNS layer
     pub fn validate(&self) -> Result<(), DnsError> {
         if self.data.as_ref().len() < 12 {
-            return Err(DnsError::InvalidLength(self.data.as_ref().len()));
+            return Err(
+                LayerError::min_length("Dns", "header", 0, 12, self.data.as_ref().len()).into(),
+            );
         }
 
         // TODO: validate count and rr, etc.
@@ -198,9 +211,19 @@ where
     }
 
     /// Get the iterator of the questions
-    pub fn questions(&self) -> DnsQuestionIter<T> {
+    pub fn questions(&self) -> DnsQuestionIter<'_, T> {
         DnsQuestionIter::from(self)
     }
+
+    /// Get the resource records as an iterator
+    ///
+    /// Records of the answer, authority and additional sections are yielded
+    /// in order, see [`ancount`](Self::ancount) and friends for the section
+    /// sizes. Iteration stops at the first malformed record.
+    #[inline]
+    pub fn records(&self) -> DnsRecordIter<'_> {
+        DnsRecordIter::from(self)
+    }
 }
 
 impl<T> Dns<T>
@@ -338,8 +361,61 @@ where
     }
 }
 
+/// Iterator over the resource records of a [`Dns`] message
+pub struct DnsRecordIter<'a> {
+    message: &'a [u8],
+    offset: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, T> From<&'a Dns<T>> for DnsRecordIter<'a>
+where
+    T: AsRef<[u8]>,
+{
+    fn from(dns: &'a Dns<T>) -> Self {
+        let message = dns.inner().as_ref();
+        let mut offset = Some(MIN_HEADER_LENGTH);
+        for _ in 0..dns.qdcount().get() {
+            offset = offset
+                .and_then(|offset| record::read_name(message, offset))
+                .map(|(_, offset)| offset + 4);
+        }
+        let remaining = [dns.ancount(), dns.nscount(), dns.arcount()]
+            .iter()
+            .map(|count| count.get() as usize)
+            .sum();
+
+        Self {
+            message,
+            offset,
+            remaining,
+        }
+    }
+}
+
+impl Iterator for DnsRecordIter<'_> {
+    type Item = DnsRecord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (record, next) = DnsRecord::parse(self.message, self.offset?)?;
+        self.offset = Some(next);
+        self.remaining -= 1;
+        Some(record)
+    }
+}
+
 /// Builder for [`Dns`]
+///
+/// Names of the questions and records are compressed unless disabled with
+/// [`compress`](Self::compress). With a [`max_size`](Self::max_size), whole
+/// RRsets that do not fit are left out: dropping answer or authority records
+/// sets the TC bit, while additional records are dropped silently (RFC 2181
+/// section 9).
 #[derive(Clone, Debug, Default)]
+#[must_use]
 pub struct DnsBuilder {
     id: Option<u16>,
     qr: Option<bool>,
@@ -355,6 +431,12 @@ pub struct DnsBuilder {
     nscount: Option<u16>,
     arcount: Option<u16>,
     questions: Vec<DnsQuestion<Vec<u8>>>,
+    answers: Vec<DnsRecord>,
+    authorities: Vec<DnsRecord>,
+    additionals: Vec<DnsRecord>,
+    compress: Option<bool>,
+    canonical_order: Option<bool>,
+    max_size: Option<usize>,
 }
 
 impl DnsBuilder {
@@ -447,29 +529,112 @@ impl DnsBuilder {
         self
     }
 
+    /// Add an answer record
+    pub fn answers(&mut self, answer: impl Into<DnsRecord>) -> &mut Self {
+        self.answers.push(answer.into());
+        self
+    }
+
+    /// Add an authority record
+    pub fn authorities(&mut self, authority: impl Into<DnsRecord>) -> &mut Self {
+        self.authorities.push(authority.into());
+        self
+    }
+
+    /// Add an additional record
+    pub fn additionals(&mut self, additional: impl Into<DnsRecord>) -> &mut Self {
+        self.additionals.push(additional.into());
+        self
+    }
+
+    /// Set whether names are compressed, defaults to true
+    pub fn compress(&mut self, compress: impl Into<bool>) -> &mut Self {
+        self.compress = Some(compress.into());
+        self
+    }
+
+    /// Set whether the records of each section are sorted in canonical
+    /// order and deduplicated, defaults to false
+    ///
+    /// This makes the output independent of the insertion order, see
+    /// [`DnsRecord::canonical_cmp`].
+    pub fn canonical_order(&mut self, canonical_order: impl Into<bool>) -> &mut Self {
+        self.canonical_order = Some(canonical_order.into());
+        self
+    }
+
+    /// Set the size budget of the message, e.g. [`MAX_UDP_SIZE`]
+    pub fn max_size(&mut self, max_size: impl Into<usize>) -> &mut Self {
+        self.max_size = Some(max_size.into());
+        self
+    }
+
     /// Build the Dns layer
     pub fn build(&self) -> Dns<Vec<u8>> {
         let mut dns = unsafe { Dns::new_unchecked(vec![0; 12]) };
+        let mut names = NameCompressor::new(self.compress.unwrap_or(true));
+
+        let qdcount = self.qdcount.unwrap_or(self.questions.len() as u16);
+        for question in self.questions.iter().take(qdcount as usize) {
+            let qname = question.qname();
+            if qname.labels().any(|label| label.is_compressed()) {
+                dns.inner_mut().extend_from_slice(question.inner());
+                continue;
+            }
+            names.write(dns.inner_mut(), &qname.to_string(), true);
+            let fixed = &question.inner()[qname.inner().len()..];
+            dns.inner_mut().extend_from_slice(fixed);
+        }
+
+        let max_size = self.max_size.unwrap_or(usize::MAX);
+        let mut counts = [0u16; 3];
+        let mut truncated = None;
+        let sections = [&self.answers, &self.authorities, &self.additionals];
+        for (i, records) in sections.into_iter().enumerate() {
+            let mut records = records.iter().collect::<Vec<_>>();
+            if self.canonical_order.unwrap_or(false) {
+                records.sort_by(|a, b| a.canonical_cmp(b));
+                records.dedup_by(|a, b| a.canonical_cmp(b).is_eq());
+            }
+
+            let mut rrset = (dns.inner().len(), 0);
+            for (j, record) in records.iter().enumerate() {
+                if j == 0 || !record.same_rrset(records[j - 1]) {
+                    rrset = (dns.inner().len(), counts[i]);
+                }
+                record.write(dns.inner_mut(), &mut names);
+                counts[i] += 1;
+
+                if dns.inner().len() > max_size {
+                    let (len, count) = rrset;
+                    dns.inner_mut().truncate(len);
+                    names.truncate(len);
+                    counts[i] = count;
+                    truncated = Some(i);
+                    break;
+                }
+            }
+            if truncated.is_some() {
+                break;
+            }
+        }
 
         dns.id_mut().set(self.id.unwrap_or(0));
         dns.qr_mut().set(self.qr.unwrap_or(false));
         dns.opcode_mut()
             .set(self.opcode.unwrap_or(DnsOpCode::Query));
         dns.aa_mut().set(self.aa.unwrap_or(false));
-        dns.tc_mut().set(self.tc.unwrap_or(false));
+        // Dropping additional records alone does not truncate the message
+        let tc = matches!(truncated, Some(0 | 1));
+        dns.tc_mut().set(self.tc.unwrap_or(tc));
         dns.rd_mut().set(self.rd.unwrap_or(false));
         dns.ra_mut().set(self.ra.unwrap_or(false));
         dns.z_mut().set(self.z.unwrap_or(0));
         dns.rcode_mut().set(self.rcode.unwrap_or(DnsRCode::NoError));
-        dns.ancount_mut().set(self.ancount.unwrap_or(0));
-        dns.nscount_mut().set(self.nscount.unwrap_or(0));
-        dns.arcount_mut().set(self.arcount.unwrap_or(0));
-
-        let qdcount = self.qdcount.unwrap_or(self.questions.len() as u16);
         dns.qdcount_mut().set(qdcount);
-        for question in self.questions.iter().take(qdcount as usize) {
-            dns.inner_mut().extend_from_slice(question.inner());
-        }
+        dns.ancount_mut().set(self.ancount.unwrap_or(counts[0]));
+        dns.nscount_mut().set(self.nscount.unwrap_or(counts[1]));
+        dns.arcount_mut().set(self.arcount.unwrap_or(counts[2]));
 
         dns
     }
@@ -492,6 +657,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn dns_new_unchecked() {
         let data: [u8; 29] = [
             0x01, 0x02, // id
@@ -562,4 +728,97 @@ mod tests {
             ]
         )
     }
+
+    fn response() -> DnsBuilder {
+        let mut builder = DnsBuilder::new();
+        builder
+            .id(0x0102u16)
+            .qr(true)
+            .questions(dns_question!(qname: "www.example.com", qtype: "A", qclass: "IN"))
+            .answers(DnsRecord::new(
+                "www.example.com",
+                60,
+                DnsRData::Cname("web.example.com".into()),
+            ));
+        for i in 1..=4 {
+            builder.answers(DnsRecord::new(
+                "web.example.com",
+                60,
+                DnsRData::A([192, 0, 2, i].into()),
+            ));
+        }
+        builder.authorities(DnsRecord::new(
+            "example.com",
+            3600,
+            DnsRData::Ns("ns1.example.com".into()),
+        ));
+        builder.additionals(DnsRecord::new(
+            "ns1.example.com",
+            3600,
+            DnsRData::A([198, 51, 100, 1].into()),
+        ));
+        builder
+    }
+
+    #[test]
+    fn dns_build_compression() {
+        let dns = response().build();
+        let data = dns.inner();
+
+        // The answer owner points to the question name
+        assert_eq!(&data[33..35], &[0xC0, 0x0C]);
+        // The CNAME target shares the "example.com" suffix of the question
+        assert_eq!(&data[45..51], b"\x03web\xc0\x10");
+        assert_eq!(dns.ancount().get(), 5);
+        assert_eq!(dns.nscount().get(), 1);
+        assert_eq!(dns.arcount().get(), 1);
+        assert!(!dns.tc().get());
+
+        let records = dns.records().collect::<Vec<_>>();
+        assert_eq!(records.len(), 7);
+        assert_eq!(records[0].rdata, DnsRData::Cname("web.example.com".into()));
+        assert_eq!(records[4].name, "web.example.com");
+        assert_eq!(records[6].rdata, DnsRData::A([198, 51, 100, 1].into()));
+
+        let uncompressed = response().compress(false).build();
+        assert!(uncompressed.inner().len() > data.len());
+        assert_eq!(uncompressed.records().collect::<Vec<_>>(), records);
+    }
+
+    #[test]
+    fn dns_build_canonical_order() {
+        let a = DnsRecord::new("b.example.com", 60, DnsRData::A([192, 0, 2, 2].into()));
+        let b = DnsRecord::new("a.example.com", 60, DnsRData::A([192, 0, 2, 1].into()));
+        let c = DnsRecord::new("A.example.com", 60, DnsRData::A([192, 0, 2, 1].into()));
+
+        let one = dns!(canonical_order: true, answers: a.clone(), answers: b.clone());
+        let two = dns!(
+            canonical_order: true,
+            answers: b.clone(),
+            answers: c,
+            answers: a.clone()
+        );
+        assert_eq!(one.inner(), two.inner());
+        assert_eq!(two.records().collect::<Vec<_>>(), vec![b, a]);
+    }
+
+    #[test]
+    fn dns_build_truncation() {
+        let full = response().build();
+
+        // Dropping the additional record does not set TC
+        let dns = response().max_size(full.inner().len() - 1).build();
+        assert!(!dns.tc().get());
+        assert_eq!(dns.arcount().get(), 0);
+        assert_eq!(dns.nscount().get(), 1);
+
+        // The A RRset is dropped as a whole
+        let dns = response().max_size(80usize).build();
+        assert!(dns.tc().get());
+        assert_eq!(dns.ancount().get(), 1);
+        assert_eq!(dns.nscount().get(), 0);
+        assert_eq!(dns.arcount().get(), 0);
+        assert_eq!(dns.records().count(), 1);
+        assert!(dns.inner().len() <= 80);
+    }
 }
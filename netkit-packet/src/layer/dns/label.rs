@@ -121,10 +121,9 @@ where
         }
     }
 
-    /// Convert the label to a str
+    /// Convert the label to a str, if it is normal and valid UTF-8
     pub fn as_str(&self) -> Option<&str> {
-        self.label()
-            .map(|l| unsafe { std::str::from_utf8_unchecked(l) })
+        self.label().and_then(|l| std::str::from_utf8(l).ok())
     }
 }
 
@@ -38,7 +38,7 @@ where
 
     /// Get the labels as an iterator
     #[inline]
-    pub fn labels(&self) -> DnsNameLabelIter<T> {
+    pub fn labels(&self) -> DnsNameLabelIter<'_, T> {
         DnsNameLabelIter::from(self)
     }
 }
@@ -62,12 +62,12 @@ where
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for label in self.labels() {
             // write!(f, "{}.", label.as_str().unwrap())?;
-            if label.is_normal() {
-                if label.len().unwrap().get() > 0 {
-                    write!(f, "{}.", label.as_str().unwrap())?;
+            if let Some(label) = label.label() {
+                if !label.is_empty() {
+                    write!(f, "{}.", String::from_utf8_lossy(label))?;
                 }
-            } else {
-                write!(f, "PTR({})", label.offset().unwrap().get())?;
+            } else if let Some(offset) = label.offset() {
+                write!(f, "PTR({})", offset.get())?;
             }
         }
         Ok(())
@@ -81,7 +81,7 @@ where
     fn eq(&self, other: &str) -> bool {
         let labels = self.to_string();
 
-        labels == other || labels[..labels.len() - 1] == *other
+        labels == other || labels.strip_suffix('.') == Some(other)
     }
 }
 
@@ -116,16 +116,19 @@ where
     type Item = DnsLabel<&'a [u8]>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.offset >= self.name.data.as_ref().len() {
-            return None;
-        }
-
-        let len = self.name.data.as_ref()[self.offset] as usize;
-        let label = unsafe {
-            DnsLabel::new_unchecked(&self.name.data.as_ref()[self.offset..self.offset + len + 1])
+        let data = self.name.data.as_ref();
+        let first = *data.get(self.offset)?;
+        // The root label and pointers end the name, and reserved label types
+        // or labels running past the data end the iteration.
+        let (len, last) = match first & 0xC0 {
+            0x00 => (first as usize + 1, first == 0),
+            0xC0 => (2, true),
+            _ => return None,
         };
-        self.offset += len + 1;
-        Some(label)
+
+        let label = data.get(self.offset..self.offset + len)?;
+        self.offset = if last { data.len() } else { self.offset + len };
+        Some(unsafe { DnsLabel::new_unchecked(label) })
     }
 }
 
@@ -145,6 +148,25 @@ mod tests {
         assert_eq!(labels[3], "");
     }
 
+    #[test]
+    fn dns_name_malformed_labels() {
+        // A label running past the data, a pointer and a reserved type.
+        let name = unsafe { DnsName::new_unchecked(b"\x03www\x3fabc") };
+        assert_eq!(name.labels().count(), 1);
+        assert_eq!(name.to_string(), "www.");
+
+        let name = unsafe { DnsName::new_unchecked(b"\x03www\xc0\x0c\x03com\x00") };
+        assert_eq!(name.labels().count(), 2);
+        assert_eq!(name.to_string(), "www.PTR(12)");
+
+        let name = unsafe { DnsName::new_unchecked(b"\x40\x00") };
+        assert_eq!(name.labels().count(), 0);
+        assert_eq!(name, "");
+
+        let name = unsafe { DnsName::new_unchecked(b"\x02\xff\xfe\x00") };
+        assert_eq!(name.to_string(), "\u{fffd}\u{fffd}.");
+    }
+
     #[test]
     fn dns_name_from_str() {
         let name = DnsName::from("www.google.com");
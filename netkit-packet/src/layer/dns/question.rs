@@ -7,10 +7,19 @@ use super::{class::DnsClass, rrtype::DnsRrType, DnsName};
 
 /// Error type of DnsQuestion
 #[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum DnsQuestionError {
     /// No root label found
     #[error("No root label found")]
     NoRootLabelFound,
+
+    /// A label of the name has a reserved type
+    #[error("Invalid label type: {0:#04x}")]
+    InvalidLabelType(u8),
+
+    /// The data ends before the qtype and qclass
+    #[error("Truncated question")]
+    Truncated,
 }
 
 /// DnsQuestion
@@ -42,12 +51,24 @@ where
 {
     /// Create a new DnsQuestion from the given data
     pub fn new(data: T) -> Result<DnsQuestion<T>, DnsQuestionError> {
-        // Find the length of the name by finding the first null byte
-        let name_len = data
-            .as_ref()
-            .iter()
-            .position(|&x| x == 0)
-            .ok_or(DnsQuestionError::NoRootLabelFound)?;
+        // Find the last byte of the name, the root label or a pointer
+        let bytes = data.as_ref();
+        let mut offset = 0;
+        let name_len = loop {
+            let len = *bytes
+                .get(offset)
+                .ok_or(DnsQuestionError::NoRootLabelFound)?;
+            match len & 0xC0 {
+                0x00 if len == 0 => break offset,
+                0x00 => offset += 1 + len as usize,
+                0xC0 => break offset + 1,
+                _ => return Err(DnsQuestionError::InvalidLabelType(len)),
+            }
+        };
+
+        if bytes.len() < name_len + 5 {
+            return Err(DnsQuestionError::Truncated);
+        }
 
         Ok(DnsQuestion { data, name_len })
     }
@@ -61,7 +82,7 @@ where
     /// Get the length of the DnsQuestion
     #[inline]
     pub const fn len(&self) -> usize {
-        self.name_len + 4
+        self.name_len + 5
     }
 
     /// Unimplemented: Make clippy happy :)
@@ -121,6 +142,7 @@ where
 
 /// Builder for DnsQuestion
 #[derive(Clone, Debug, Default)]
+#[must_use]
 pub struct DnsQuestionBuilder {
     qname: Option<String>,
     qtype: Option<DnsRrType>,
@@ -212,6 +234,27 @@ mod tests {
         assert_eq!(question.qclass().get(), DnsClass::Internet);
     }
 
+    #[test]
+    fn dns_question_malformed() {
+        let question = DnsQuestion::new(b"\xc0\x0c\x00\x01\x00\x01").unwrap();
+        assert_eq!(question.len(), 6);
+        assert_eq!(question.qname().to_string(), "PTR(12)");
+        assert_eq!(question.qtype().get(), DnsRrType::A);
+
+        assert_eq!(
+            DnsQuestion::new(b"\x03www\x00\x00\x01\x00").err(),
+            Some(DnsQuestionError::Truncated)
+        );
+        assert_eq!(
+            DnsQuestion::new(b"\x3fwww\x00\x00\x01\x00\x01").err(),
+            Some(DnsQuestionError::NoRootLabelFound)
+        );
+        assert_eq!(
+            DnsQuestion::new(b"\x80\x00\x00\x01\x00\x01").err(),
+            Some(DnsQuestionError::InvalidLabelType(0x80))
+        );
+    }
+
     #[test]
     fn dns_question_macro() {
         let question = dns_question!(
@@ -0,0 +1,536 @@
+//! Dns Resource Record
+//!
+//! Owned resource records used when building and parsing whole DNS messages.
+//! Names are kept as dotted strings without the trailing root dot, e.g.
+//! `www.example.com`; the root name is the empty string.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::{DnsClass, DnsRrType};
+
+/// Maximum number of compression pointers followed while reading a name
+const MAX_POINTERS: usize = 64;
+
+/// Data of a resource record
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DnsRData {
+    /// IPv4 address
+    A(Ipv4Addr),
+    /// IPv6 address
+    Aaaa(Ipv6Addr),
+    /// Authoritative name server
+    Ns(String),
+    /// Canonical name of an alias
+    Cname(String),
+    /// Domain name pointer
+    Ptr(String),
+    /// Mail exchange
+    Mx {
+        /// Preference, lower is preferred
+        preference: u16,
+        /// Mail exchange host
+        exchange: String,
+    },
+    /// Start of authority
+    Soa {
+        /// Primary name server
+        mname: String,
+        /// Responsible mailbox
+        rname: String,
+        /// Serial number of the zone
+        serial: u32,
+        /// Refresh interval in seconds
+        refresh: u32,
+        /// Retry interval in seconds
+        retry: u32,
+        /// Expire limit in seconds
+        expire: u32,
+        /// Minimum (negative caching) TTL in seconds
+        minimum: u32,
+    },
+    /// Service location
+    Srv {
+        /// Priority, lower is preferred
+        priority: u16,
+        /// Weight among equal priorities
+        weight: u16,
+        /// Service port
+        port: u16,
+        /// Target host
+        target: String,
+    },
+    /// Text strings, each at most 255 bytes
+    Txt(Vec<Vec<u8>>),
+    /// Any other type, kept as raw bytes
+    Other(DnsRrType, Vec<u8>),
+}
+
+impl DnsRData {
+    /// Get the record type of the data
+    pub fn rr_type(&self) -> DnsRrType {
+        match self {
+            DnsRData::A(_) => DnsRrType::A,
+            DnsRData::Aaaa(_) => DnsRrType::AAAA,
+            DnsRData::Ns(_) => DnsRrType::NS,
+            DnsRData::Cname(_) => DnsRrType::CNAME,
+            DnsRData::Ptr(_) => DnsRrType::PTR,
+            DnsRData::Mx { .. } => DnsRrType::MX,
+            DnsRData::Soa { .. } => DnsRrType::SOA,
+            DnsRData::Srv { .. } => DnsRrType::SRV,
+            DnsRData::Txt(_) => DnsRrType::TXT,
+            DnsRData::Other(rr_type, _) => *rr_type,
+        }
+    }
+
+    /// Create TXT data from a string, split into 255 byte chunks
+    pub fn txt(text: &str) -> Self {
+        let chunks = text.as_bytes().chunks(255).map(<[u8]>::to_vec).collect();
+        DnsRData::Txt(chunks)
+    }
+
+    /// Parse the data of a record of type `rr_type`
+    ///
+    /// `rdata` is the range of the data within `message`, which is needed to
+    /// follow compression pointers. Types not known here are kept raw.
+    pub fn parse(
+        message: &[u8],
+        rr_type: DnsRrType,
+        rdata: core::ops::Range<usize>,
+    ) -> Option<Self> {
+        let data = message.get(rdata.clone())?;
+        let name_at = |offset: usize| read_name(message, rdata.start + offset);
+        let u16_at = |offset: usize| {
+            Some(u16::from_be_bytes(
+                data.get(offset..offset + 2)?.try_into().ok()?,
+            ))
+        };
+        let u32_at = |offset: usize| {
+            Some(u32::from_be_bytes(
+                data.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+
+        let parsed = match rr_type {
+            DnsRrType::A => DnsRData::A(<[u8; 4]>::try_from(data).ok()?.into()),
+            DnsRrType::AAAA => DnsRData::Aaaa(<[u8; 16]>::try_from(data).ok()?.into()),
+            DnsRrType::NS => DnsRData::Ns(name_at(0)?.0),
+            DnsRrType::CNAME => DnsRData::Cname(name_at(0)?.0),
+            DnsRrType::PTR => DnsRData::Ptr(name_at(0)?.0),
+            DnsRrType::MX => DnsRData::Mx {
+                preference: u16_at(0)?,
+                exchange: name_at(2)?.0,
+            },
+            DnsRrType::SOA => {
+                let (mname, next) = name_at(0)?;
+                let (rname, next) = read_name(message, next)?;
+                let offset = next.checked_sub(rdata.start)?;
+                DnsRData::Soa {
+                    mname,
+                    rname,
+                    serial: u32_at(offset)?,
+                    refresh: u32_at(offset + 4)?,
+                    retry: u32_at(offset + 8)?,
+                    expire: u32_at(offset + 12)?,
+                    minimum: u32_at(offset + 16)?,
+                }
+            }
+            DnsRrType::SRV => DnsRData::Srv {
+                priority: u16_at(0)?,
+                weight: u16_at(2)?,
+                port: u16_at(4)?,
+                target: name_at(6)?.0,
+            },
+            DnsRrType::TXT => {
+                let mut strings = Vec::new();
+                let mut rest = data;
+                while let Some((&len, tail)) = rest.split_first() {
+                    strings.push(tail.get(..len as usize)?.to_vec());
+                    rest = &tail[len as usize..];
+                }
+                DnsRData::Txt(strings)
+            }
+            rr_type => DnsRData::Other(rr_type, data.to_vec()),
+        };
+        Some(parsed)
+    }
+
+    /// Write the data, compressing names where RFC 1035 allows it
+    ///
+    /// Only the names of the types defined in RFC 1035 (NS, CNAME, PTR, MX
+    /// and SOA) are compressed, see RFC 3597 section 4.
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, names: &mut NameCompressor) {
+        match self {
+            DnsRData::A(addr) => buf.extend_from_slice(&addr.octets()),
+            DnsRData::Aaaa(addr) => buf.extend_from_slice(&addr.octets()),
+            DnsRData::Ns(name) | DnsRData::Cname(name) | DnsRData::Ptr(name) => {
+                names.write(buf, name, true)
+            }
+            DnsRData::Mx {
+                preference,
+                exchange,
+            } => {
+                buf.extend_from_slice(&preference.to_be_bytes());
+                names.write(buf, exchange, true);
+            }
+            DnsRData::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                names.write(buf, mname, true);
+                names.write(buf, rname, true);
+                for value in [serial, refresh, retry, expire, minimum] {
+                    buf.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            DnsRData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                for value in [priority, weight, port] {
+                    buf.extend_from_slice(&value.to_be_bytes());
+                }
+                names.write(buf, target, false);
+            }
+            DnsRData::Txt(strings) => {
+                for string in strings {
+                    let string = &string[..string.len().min(255)];
+                    buf.push(string.len() as u8);
+                    buf.extend_from_slice(string);
+                }
+            }
+            DnsRData::Other(_, data) => buf.extend_from_slice(data),
+        }
+    }
+
+    /// Get the data in canonical form (RFC 4034 section 6.2)
+    ///
+    /// Embedded names are uncompressed and lowercased.
+    pub fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, &mut NameCompressor::canonical());
+        buf
+    }
+}
+
+/// Dns Resource Record
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DnsRecord {
+    /// Owner name
+    pub name: String,
+    /// Class
+    pub class: DnsClass,
+    /// Time to live in seconds
+    pub ttl: u32,
+    /// Record data
+    pub rdata: DnsRData,
+}
+
+impl DnsRecord {
+    /// Create a new Internet class record
+    pub fn new(name: impl Into<String>, ttl: u32, rdata: DnsRData) -> Self {
+        Self {
+            name: name.into(),
+            class: DnsClass::Internet,
+            ttl,
+            rdata,
+        }
+    }
+
+    /// Get the record type
+    #[inline]
+    pub fn rr_type(&self) -> DnsRrType {
+        self.rdata.rr_type()
+    }
+
+    /// Parse a record starting at `offset` of a whole DNS message
+    ///
+    /// Returns the record and the offset of the next one.
+    pub fn parse(message: &[u8], offset: usize) -> Option<(Self, usize)> {
+        let (name, offset) = read_name(message, offset)?;
+        let fixed = message.get(offset..offset + 10)?;
+        let rr_type = DnsRrType::from(u16::from_be_bytes([fixed[0], fixed[1]]));
+        let class = DnsClass::from(u16::from_be_bytes([fixed[2], fixed[3]]));
+        let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+
+        let start = offset + 10;
+        let end = start + rdlength;
+        if end > message.len() {
+            return None;
+        }
+        let rdata = DnsRData::parse(message, rr_type, start..end)?;
+
+        Some((
+            Self {
+                name,
+                class,
+                ttl,
+                rdata,
+            },
+            end,
+        ))
+    }
+
+    /// Write the record at the end of `buf`, which holds the message so far
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, names: &mut NameCompressor) {
+        names.write(buf, &self.name, true);
+        buf.extend_from_slice(&u16::from(self.rr_type()).to_be_bytes());
+        buf.extend_from_slice(&u16::from(self.class).to_be_bytes());
+        buf.extend_from_slice(&self.ttl.to_be_bytes());
+
+        let rdlength = buf.len();
+        buf.extend_from_slice(&[0, 0]);
+        self.rdata.write(buf, names);
+        let len = (buf.len() - rdlength - 2) as u16;
+        buf[rdlength..rdlength + 2].copy_from_slice(&len.to_be_bytes());
+    }
+
+    /// Get the record in canonical form (RFC 4034 section 6.2)
+    ///
+    /// The owner name and the embedded names are uncompressed and lowercased.
+    pub fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, &mut NameCompressor::canonical());
+        buf
+    }
+
+    /// Compare two records in canonical order
+    ///
+    /// Records are ordered by owner name (RFC 4034 section 6.1), type, class
+    /// and canonical data (RFC 4034 section 6.3). The TTL is ignored.
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        canonical_name_cmp(&self.name, &other.name)
+            .then_with(|| u16::from(self.rr_type()).cmp(&u16::from(other.rr_type())))
+            .then_with(|| u16::from(self.class).cmp(&u16::from(other.class)))
+            .then_with(|| {
+                self.rdata
+                    .to_canonical_bytes()
+                    .cmp(&other.rdata.to_canonical_bytes())
+            })
+    }
+
+    /// Whether two records belong to the same RRset
+    #[inline]
+    pub fn same_rrset(&self, other: &Self) -> bool {
+        self.name
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(other.name.trim_end_matches('.'))
+            && self.rr_type() == other.rr_type()
+            && self.class == other.class
+    }
+}
+
+/// Split a dotted name into its labels, ignoring the trailing root dot
+fn labels(name: &str) -> impl DoubleEndedIterator<Item = &str> {
+    name.split('.').filter(|label| !label.is_empty())
+}
+
+/// Compare two names in canonical DNS order (RFC 4034 section 6.1)
+pub fn canonical_name_cmp(a: &str, b: &str) -> Ordering {
+    let a = labels(a).rev().map(|label| label.to_ascii_lowercase());
+    let b = labels(b).rev().map(|label| label.to_ascii_lowercase());
+    a.map(String::into_bytes).cmp(b.map(String::into_bytes))
+}
+
+/// Name writer applying message compression (RFC 1035 section 4.1.4)
+#[derive(Clone, Debug, Default)]
+pub(crate) struct NameCompressor {
+    /// Offsets of the name suffixes already written, keyed by lowercase name
+    offsets: HashMap<String, u16>,
+    /// Whether names may be replaced by pointers
+    compress: bool,
+    /// Whether names are lowercased
+    lowercase: bool,
+}
+
+impl NameCompressor {
+    /// Create a compressor, which only records offsets when disabled
+    pub(crate) fn new(compress: bool) -> Self {
+        Self {
+            compress,
+            ..Default::default()
+        }
+    }
+
+    /// Create a writer for canonical form: no compression, lowercase names
+    fn canonical() -> Self {
+        Self {
+            lowercase: true,
+            ..Default::default()
+        }
+    }
+
+    /// Write `name` at the end of `buf`, which holds the message so far
+    ///
+    /// `compressible` is false for names that must be written in full, such
+    /// as the SRV target.
+    pub(crate) fn write(&mut self, buf: &mut Vec<u8>, name: &str, compressible: bool) {
+        let labels = labels(name).collect::<Vec<_>>();
+        for i in 0..labels.len() {
+            let suffix = labels[i..].join(".").to_ascii_lowercase();
+            if self.compress && compressible {
+                if let Some(offset) = self.offsets.get(&suffix) {
+                    buf.extend_from_slice(&(0xC000 | offset).to_be_bytes());
+                    return;
+                }
+            }
+            if compressible && buf.len() < 0x4000 {
+                self.offsets.entry(suffix).or_insert(buf.len() as u16);
+            }
+
+            let label = &labels[i].as_bytes()[..labels[i].len().min(63)];
+            buf.push(label.len() as u8);
+            if self.lowercase {
+                buf.extend(label.iter().map(u8::to_ascii_lowercase));
+            } else {
+                buf.extend_from_slice(label);
+            }
+        }
+        buf.push(0);
+    }
+
+    /// Forget the names written at or after `len`, used when rolling back
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.offsets.retain(|_, offset| (*offset as usize) < len);
+    }
+}
+
+/// Read a possibly compressed name at `offset` of a whole DNS message
+///
+/// Returns the dotted name and the offset right after it.
+pub fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end = None;
+    let mut pointers = 0;
+
+    loop {
+        let len = *message.get(offset)?;
+        match len & 0xC0 {
+            0x00 if len == 0 => break,
+            0x00 => {
+                let label = message.get(offset + 1..offset + 1 + len as usize)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                offset += 1 + len as usize;
+            }
+            0xC0 => {
+                let pointer = u16::from_be_bytes([len, *message.get(offset + 1)?]) & 0x3FFF;
+                end.get_or_insert(offset + 2);
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                offset = pointer as usize;
+            }
+            _ => return None,
+        }
+    }
+
+    Some((name, end.unwrap_or(offset + 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dns_record_round_trip() {
+        let records = [
+            DnsRecord::new("example.com", 300, DnsRData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            DnsRecord::new("www.example.com", 60, DnsRData::Cname("example.com".into())),
+            DnsRecord::new(
+                "example.com",
+                3600,
+                DnsRData::Mx {
+                    preference: 10,
+                    exchange: "mail.example.com".into(),
+                },
+            ),
+            DnsRecord::new(
+                "example.com",
+                3600,
+                DnsRData::Soa {
+                    mname: "ns1.example.com".into(),
+                    rname: "hostmaster.example.com".into(),
+                    serial: 2024010101,
+                    refresh: 7200,
+                    retry: 3600,
+                    expire: 1209600,
+                    minimum: 300,
+                },
+            ),
+            DnsRecord::new(
+                "_sip._udp.example.com",
+                60,
+                DnsRData::Srv {
+                    priority: 1,
+                    weight: 5,
+                    port: 5060,
+                    target: "sip.example.com".into(),
+                },
+            ),
+            DnsRecord::new("example.com", 60, DnsRData::txt("v=spf1 -all")),
+        ];
+
+        let mut buf = vec![0; 12];
+        let mut names = NameCompressor::new(true);
+        for record in &records {
+            record.write(&mut buf, &mut names);
+        }
+
+        let mut offset = 12;
+        for record in &records {
+            let (parsed, next) = DnsRecord::parse(&buf, offset).unwrap();
+            assert_eq!(&parsed, record);
+            offset = next;
+        }
+        assert_eq!(offset, buf.len());
+    }
+
+    #[test]
+    fn dns_record_canonical() {
+        let record = DnsRecord::new(
+            "WWW.Example.COM.",
+            60,
+            DnsRData::Cname("Example.com".into()),
+        );
+        assert_eq!(
+            record.to_canonical_bytes(),
+            b"\x03www\x07example\x03com\x00\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x0d\x07example\x03com\x00"
+        );
+
+        assert_eq!(
+            canonical_name_cmp("example.com", "a.example.com"),
+            Ordering::Less
+        );
+        assert_eq!(
+            canonical_name_cmp("Z.example.com", "a.example.com"),
+            Ordering::Greater
+        );
+        assert_eq!(
+            canonical_name_cmp("example.com.", "EXAMPLE.com"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn dns_read_name_loop() {
+        assert_eq!(read_name(b"\xc0\x00", 0), None);
+        assert_eq!(read_name(b"\x01a\x00", 0), Some(("a".into(), 3)));
+        assert_eq!(
+            read_name(b"\x01a\x00\x01b\xc0\x00", 3),
+            Some(("b.a".into(), 7))
+        );
+    }
+}
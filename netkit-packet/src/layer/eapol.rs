@@ -0,0 +1,303 @@
+//! EAP over LAN (EAPOL, IEEE 802.1X) layer.
+
+use crate::{field_spec, prelude::*};
+
+pub mod eap;
+pub use eap::{Eap, EapBuilder, EapError};
+
+pub mod eap_code;
+pub use eap_code::EapCode;
+
+pub mod eap_method;
+pub use eap_method::EapMethod;
+
+pub mod eapol_type;
+pub use eapol_type::EapolType;
+
+/// Error type for Eapol layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EapolError {
+    /// Invalid Eapol length.
+    #[error("Invalid Eapol length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(VersionSpec, u8, u8);
+field_spec!(PacketTypeSpec, EapolType, u8);
+field_spec!(BodyLengthSpec, u16, u16);
+
+/// Length of an Eapol header.
+pub const HEADER_LENGTH: usize = 4;
+
+/// Default protocol version of built frames (IEEE 802.1X-2004).
+pub const DEFAULT_VERSION: u8 = 2;
+
+/// EAP over LAN (EAPOL) layer.
+///
+/// See IEEE 802.1X-2020, clause 11.3. Eapol frames are carried directly in
+/// Ethernet with Eth type [`EthType::Eapol`].
+///
+/// ```text
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |    Version    |  Packet Type  |       Packet Body Length      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Packet Body ...
+/// +-+-+-+-+-+-+-+-+-
+/// ```
+pub struct Eapol<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Eapol<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the protocol version: 0..1
+    pub const FIELD_VERSION: core::ops::Range<usize> = 0..1;
+    /// Field range of the packet type: 1..2
+    pub const FIELD_PACKET_TYPE: core::ops::Range<usize> = 1..2;
+    /// Field range of the packet body length: 2..4
+    pub const FIELD_BODY_LENGTH: core::ops::Range<usize> = 2..4;
+
+    /// Create a new Eapol layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Eapol frame.
+    ///
+    /// The data must hold the header and the packet body. Otherwise, the
+    /// following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Eapol layer.
+    ///
+    /// Trailing bytes after the packet body, e.g. Ethernet padding, are
+    /// allowed.
+    pub fn validate(&self) -> Result<(), EapolError> {
+        let len = self.data.as_ref().len();
+        if len < HEADER_LENGTH {
+            return Err(LayerError::min_length("Eapol", "header", 0, HEADER_LENGTH, len).into());
+        }
+
+        let required = HEADER_LENGTH + self.body_length().get() as usize;
+        if len < required {
+            return Err(
+                LayerError::min_length("Eapol", "body", HEADER_LENGTH, required, len).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Eapol layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, EapolError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the protocol version.
+    #[inline]
+    pub fn version(&self) -> &Field<VersionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION])
+    }
+
+    /// Get the accessor of the packet type.
+    #[inline]
+    pub fn packet_type(&self) -> &Field<PacketTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PACKET_TYPE])
+    }
+
+    /// Get the accessor of the packet body length.
+    #[inline]
+    pub fn body_length(&self) -> &Field<BodyLengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_BODY_LENGTH])
+    }
+
+    /// Get the packet body, excluding any trailing padding.
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        let end = HEADER_LENGTH + self.body_length().get() as usize;
+        &self.data.as_ref()[HEADER_LENGTH..end]
+    }
+
+    /// Get the EAP packet if the packet type is EAP Packet.
+    pub fn eap(&self) -> Option<Eap<&[u8]>> {
+        if self.packet_type().get() == EapolType::EapPacket {
+            Eap::new(self.body()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Eapol<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the protocol version.
+    #[inline]
+    pub fn version_mut(&mut self) -> &mut Field<VersionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION])
+    }
+
+    /// Get the mutable accessor of the packet type.
+    #[inline]
+    pub fn packet_type_mut(&mut self) -> &mut Field<PacketTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PACKET_TYPE])
+    }
+
+    /// Get the mutable accessor of the packet body length.
+    #[inline]
+    pub fn body_length_mut(&mut self) -> &mut Field<BodyLengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_BODY_LENGTH])
+    }
+
+    /// Get the mutable packet body.
+    #[inline]
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        let end = HEADER_LENGTH + self.body_length().get() as usize;
+        &mut self.data.as_mut()[HEADER_LENGTH..end]
+    }
+}
+
+layer_impl!(Eapol);
+
+/// Builder for [`Eapol`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct EapolBuilder {
+    version: Option<u8>,
+    packet_type: Option<EapolType>,
+    body: Vec<u8>,
+}
+
+impl EapolBuilder {
+    /// Create a new Eapol builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the protocol version.
+    pub fn version(&mut self, version: impl Into<u8>) -> &mut Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the packet type.
+    pub fn packet_type(&mut self, packet_type: impl Into<EapolType>) -> &mut Self {
+        self.packet_type = Some(packet_type.into());
+        self
+    }
+
+    /// Set the packet body.
+    pub fn body<T: AsRef<[u8]>>(&mut self, body: T) -> &mut Self {
+        self.body.extend_from_slice(body.as_ref());
+        self
+    }
+
+    /// Build the Eapol layer.
+    ///
+    /// Unless set explicitly, the version is [`DEFAULT_VERSION`] and the
+    /// packet type is EAP Packet.
+    pub fn build(&self) -> Eapol<Vec<u8>> {
+        let len = HEADER_LENGTH + self.body.len();
+        let mut eapol = unsafe { Eapol::new_unchecked(vec![0; len]) };
+
+        eapol
+            .version_mut()
+            .set(self.version.unwrap_or(DEFAULT_VERSION));
+        eapol
+            .packet_type_mut()
+            .set(self.packet_type.unwrap_or(EapolType::EapPacket));
+        eapol.body_length_mut().set(self.body.len() as u16);
+        eapol.body_mut().copy_from_slice(&self.body);
+
+        eapol
+    }
+}
+
+/// Create an Eapol layer with the given fields.
+#[macro_export]
+macro_rules! eapol {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::eapol::EapolBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eapol_eap_failure() {
+        // EAPOL v2 EAP Packet: EAP Failure, identifier 7, plus Ethernet padding.
+        let data = [0x02, 0x00, 0x00, 0x04, 0x04, 0x07, 0x00, 0x04, 0x00, 0x00];
+        let eapol = Eapol::new(&data[..]).unwrap();
+
+        assert_eq!(eapol.version().get(), 2);
+        assert_eq!(eapol.packet_type().get(), EapolType::EapPacket);
+        assert_eq!(eapol.body_length().get(), 4);
+        assert_eq!(eapol.body().len(), 4);
+
+        let eap = eapol.eap().unwrap();
+        assert_eq!(eap.code().get(), EapCode::Failure);
+        assert_eq!(eap.identifier().get(), 7);
+        assert_eq!(eap.method(), None);
+
+        assert_eq!(
+            Eapol::new(&data[..6]).err(),
+            Some(EapolError::InvalidLength(LayerError::min_length(
+                "Eapol", "body", 4, 8, 6
+            )))
+        );
+    }
+
+    #[test]
+    fn eapol_over_eth() {
+        let eap = eap!(
+            code: EapCode::Response,
+            identifier: 1u8,
+            method: EapMethod::Identity,
+            type_data: b"alice",
+        );
+        let eapol = eapol!(body: eap.inner());
+        let eth = eth!(eth_type: EthType::Eapol, payload: eapol.inner());
+
+        let eapol = eth.eapol().unwrap();
+        assert_eq!(eapol.body_length().get(), 10);
+
+        let eap = eapol.eap().unwrap();
+        assert_eq!(eap.code().get(), EapCode::Response);
+        assert_eq!(eap.method(), Some(EapMethod::Identity));
+        assert_eq!(eap.type_data(), b"alice");
+
+        let start = eapol!(packet_type: EapolType::Start);
+        assert_eq!(start.inner(), &[0x02, 0x01, 0x00, 0x00]);
+        assert!(start.eap().is_none());
+    }
+}
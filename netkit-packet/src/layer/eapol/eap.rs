@@ -0,0 +1,258 @@
+//! Extensible Authentication Protocol (EAP) packet.
+
+use super::{EapCode, EapMethod};
+use crate::{field_spec, prelude::*};
+
+/// Error type for Eap layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EapError {
+    /// Invalid Eap length.
+    #[error("Invalid Eap length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(CodeSpec, EapCode, u8);
+field_spec!(IdentifierSpec, u8, u8);
+field_spec!(LengthSpec, u16, u16);
+
+/// Length of an Eap header.
+pub const HEADER_LENGTH: usize = 4;
+
+/// Extensible Authentication Protocol (EAP) packet.
+///
+/// See [RFC 3748](https://datatracker.ietf.org/doc/html/rfc3748). Only
+/// Request and Response packets carry a method type.
+///
+/// ```text
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Code      |  Identifier   |            Length             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |  Type-Data ...
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-
+/// ```
+pub struct Eap<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Eap<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the code: 0..1
+    pub const FIELD_CODE: core::ops::Range<usize> = 0..1;
+    /// Field range of the identifier: 1..2
+    pub const FIELD_IDENTIFIER: core::ops::Range<usize> = 1..2;
+    /// Field range of the length: 2..4
+    pub const FIELD_LENGTH: core::ops::Range<usize> = 2..4;
+
+    /// Create a new Eap layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Eap packet.
+    ///
+    /// The data must be at least as long as the length field. Otherwise, the
+    /// following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Eap layer.
+    pub fn validate(&self) -> Result<(), EapError> {
+        let len = self.data.as_ref().len();
+        if len < HEADER_LENGTH {
+            return Err(LayerError::min_length("Eap", "header", 0, HEADER_LENGTH, len).into());
+        }
+
+        let length = self.length().get() as usize;
+        if length < HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Eap",
+                "length",
+                Self::FIELD_LENGTH.start,
+                HEADER_LENGTH,
+                length,
+            )
+            .into());
+        }
+        if len < length {
+            return Err(LayerError::min_length("Eap", "data", HEADER_LENGTH, length, len).into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Eap layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, EapError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the code.
+    #[inline]
+    pub fn code(&self) -> &Field<CodeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CODE])
+    }
+
+    /// Get the accessor of the identifier.
+    #[inline]
+    pub fn identifier(&self) -> &Field<IdentifierSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_IDENTIFIER])
+    }
+
+    /// Get the accessor of the length.
+    #[inline]
+    pub fn length(&self) -> &Field<LengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_LENGTH])
+    }
+
+    /// Whether the packet is a Request or a Response, carrying a method type.
+    #[inline]
+    pub fn has_method(&self) -> bool {
+        matches!(self.code().get(), EapCode::Request | EapCode::Response)
+            && self.length().get() as usize > HEADER_LENGTH
+    }
+
+    /// Get the method type of a Request or Response.
+    #[inline]
+    pub fn method(&self) -> Option<EapMethod> {
+        self.has_method()
+            .then(|| EapMethod::from(self.data.as_ref()[HEADER_LENGTH]))
+    }
+
+    /// Get the data following the header, and the method type if present.
+    #[inline]
+    pub fn type_data(&self) -> &[u8] {
+        let start = HEADER_LENGTH + self.has_method() as usize;
+        &self.data.as_ref()[start..self.length().get() as usize]
+    }
+}
+
+impl<T> Eap<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the code.
+    #[inline]
+    pub fn code_mut(&mut self) -> &mut Field<CodeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CODE])
+    }
+
+    /// Get the mutable accessor of the identifier.
+    #[inline]
+    pub fn identifier_mut(&mut self) -> &mut Field<IdentifierSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_IDENTIFIER])
+    }
+
+    /// Get the mutable accessor of the length.
+    #[inline]
+    pub fn length_mut(&mut self) -> &mut Field<LengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_LENGTH])
+    }
+
+    /// Get the mutable type data.
+    #[inline]
+    pub fn type_data_mut(&mut self) -> &mut [u8] {
+        let start = HEADER_LENGTH + self.has_method() as usize;
+        let end = self.length().get() as usize;
+        &mut self.data.as_mut()[start..end]
+    }
+}
+
+layer_impl!(Eap);
+
+/// Builder for [`Eap`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct EapBuilder {
+    code: Option<EapCode>,
+    identifier: Option<u8>,
+    method: Option<EapMethod>,
+    type_data: Vec<u8>,
+}
+
+impl EapBuilder {
+    /// Create a new Eap builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the code.
+    pub fn code(&mut self, code: impl Into<EapCode>) -> &mut Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Set the identifier.
+    pub fn identifier(&mut self, identifier: impl Into<u8>) -> &mut Self {
+        self.identifier = Some(identifier.into());
+        self
+    }
+
+    /// Set the method type.
+    ///
+    /// It is only written for Request and Response packets.
+    pub fn method(&mut self, method: impl Into<EapMethod>) -> &mut Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    /// Set the type data.
+    pub fn type_data<T: AsRef<[u8]>>(&mut self, type_data: T) -> &mut Self {
+        self.type_data.extend_from_slice(type_data.as_ref());
+        self
+    }
+
+    /// Build the Eap layer.
+    ///
+    /// Unless set explicitly, the code is Request and the method type is
+    /// Identity.
+    pub fn build(&self) -> Eap<Vec<u8>> {
+        let code = self.code.unwrap_or(EapCode::Request);
+        let method = matches!(code, EapCode::Request | EapCode::Response)
+            .then(|| self.method.unwrap_or(EapMethod::Identity));
+
+        let mut data = Vec::with_capacity(HEADER_LENGTH + 1 + self.type_data.len());
+        data.push(code.into());
+        data.push(self.identifier.unwrap_or_default());
+        data.extend_from_slice(&[0, 0]);
+        data.extend(method.map(u8::from));
+        data.extend_from_slice(&self.type_data);
+
+        let mut eap = unsafe { Eap::new_unchecked(data) };
+        let length = eap.inner().len() as u16;
+        eap.length_mut().set(length);
+        eap
+    }
+}
+
+/// Create an Eap layer with the given fields.
+#[macro_export]
+macro_rules! eap {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::eapol::EapBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
@@ -0,0 +1,87 @@
+//! EAP Code
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// EAP Code
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum EapCode {
+    /// Request
+    Request = 1,
+
+    /// Response
+    Response = 2,
+
+    /// Success
+    Success = 3,
+
+    /// Failure
+    Failure = 4,
+
+    /// Initiate
+    Initiate = 5,
+
+    /// Finish
+    Finish = 6,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl_target!(frominto, EapCode, u8);
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+
+    #[test]
+    fn eap_code_str() {
+        test_enum_str!(
+            EapCode,
+            Request => "Request",
+            Response => "Response",
+            Success => "Success",
+            Failure => "Failure",
+            Initiate => "Initiate",
+            Finish => "Finish",
+        );
+    }
+
+    #[test]
+    fn eap_code_num() {
+        test_enum_num!(
+            EapCode: u8,
+            Request => 1,
+            Response => 2,
+            Success => 3,
+            Failure => 4,
+            Initiate => 5,
+            Finish => 6,
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! EAP Method Type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// EAP Method Type
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum EapMethod {
+    /// Identity
+    Identity = 1,
+
+    /// Notification
+    Notification = 2,
+
+    /// Legacy Nak
+    Nak = 3,
+
+    /// MD5-Challenge
+    Md5Challenge = 4,
+
+    /// One-Time Password
+    Otp = 5,
+
+    /// Generic Token Card
+    Gtc = 6,
+
+    /// EAP-TLS
+    Tls = 13,
+
+    /// Cisco LEAP
+    Leap = 17,
+
+    /// EAP-SIM
+    Sim = 18,
+
+    /// EAP-TTLS
+    Ttls = 21,
+
+    /// EAP-AKA
+    Aka = 23,
+
+    /// PEAP
+    Peap = 25,
+
+    /// EAP-MSCHAP-V2
+    MsChapV2 = 26,
+
+    /// EAP-FAST
+    Fast = 43,
+
+    /// EAP-AKA'
+    AkaPrime = 50,
+
+    /// TEAP
+    Teap = 55,
+
+    /// Expanded Type
+    Expanded = 254,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl_target!(frominto, EapMethod, u8);
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+
+    #[test]
+    fn eap_method_str() {
+        test_enum_str!(
+            EapMethod,
+            Identity => "Identity",
+            Notification => "Notification",
+            Nak => "Nak",
+            Md5Challenge => "Md5Challenge",
+            Otp => "Otp",
+            Gtc => "Gtc",
+            Tls => "Tls",
+            Leap => "Leap",
+            Sim => "Sim",
+            Ttls => "Ttls",
+            Aka => "Aka",
+            Peap => "Peap",
+            MsChapV2 => "MsChapV2",
+            Fast => "Fast",
+            AkaPrime => "AkaPrime",
+            Teap => "Teap",
+            Expanded => "Expanded",
+        );
+    }
+
+    #[test]
+    fn eap_method_num() {
+        test_enum_num!(
+            EapMethod: u8,
+            Identity => 1,
+            Notification => 2,
+            Nak => 3,
+            Md5Challenge => 4,
+            Otp => 5,
+            Gtc => 6,
+            Tls => 13,
+            Leap => 17,
+            Sim => 18,
+            Ttls => 21,
+            Aka => 23,
+            Peap => 25,
+            MsChapV2 => 26,
+            Fast => 43,
+            AkaPrime => 50,
+            Teap => 55,
+            Expanded => 254,
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! EAPOL Packet Type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// EAPOL Packet Type
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum EapolType {
+    /// EAP Packet
+    EapPacket = 0,
+
+    /// EAPOL-Start
+    Start = 1,
+
+    /// EAPOL-Logoff
+    Logoff = 2,
+
+    /// EAPOL-Key
+    Key = 3,
+
+    /// EAPOL-Encapsulated-ASF-Alert
+    EncapsulatedAsfAlert = 4,
+
+    /// EAPOL-MKA (MACsec Key Agreement)
+    Mka = 5,
+
+    /// EAPOL-Announcement (Generic)
+    Announcement = 6,
+
+    /// EAPOL-Announcement (Specific)
+    AnnouncementSpecific = 7,
+
+    /// EAPOL-Announcement-Req
+    AnnouncementReq = 8,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl_target!(frominto, EapolType, u8);
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+
+    #[test]
+    fn eapol_type_str() {
+        test_enum_str!(
+            EapolType,
+            EapPacket => "EapPacket",
+            Start => "Start",
+            Logoff => "Logoff",
+            Key => "Key",
+            EncapsulatedAsfAlert => "EncapsulatedAsfAlert",
+            Mka => "Mka",
+            Announcement => "Announcement",
+            AnnouncementSpecific => "AnnouncementSpecific",
+            AnnouncementReq => "AnnouncementReq",
+        );
+    }
+
+    #[test]
+    fn eapol_type_num() {
+        test_enum_num!(
+            EapolType: u8,
+            EapPacket => 0,
+            Start => 1,
+            Logoff => 2,
+            Key => 3,
+            EncapsulatedAsfAlert => 4,
+            Mka => 5,
+            Announcement => 6,
+            AnnouncementSpecific => 7,
+            AnnouncementReq => 8,
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Parse failures of layers, located in the data.
+//!
+//! Every layer fails to parse with its own error type, e.g. [`Ipv4Error`],
+//! whose length variant wraps a [`LayerError`]. The latter names the layer,
+//! the field that failed and its byte offset in the layer, and the
+//! constraint it broke, so a corpus of malformed packets can be triaged
+//! without re-reading each one:
+//!
+//! ```
+//! use netkit_packet::prelude::*;
+//!
+//! let Err(Ipv4Error::InvalidLength(err)) = Ipv4::new(&[0x45u8; 8][..]) else {
+//!     unreachable!()
+//! };
+//! assert_eq!((err.layer, err.field, err.offset), ("Ipv4", "header", 0));
+//! assert_eq!(err.constraint, Constraint::MinLength { min: 20, actual: 8 });
+//! assert_eq!(err.to_string(), "header at offset 0: length 8 is less than 20");
+//! ```
+//!
+//! [`Ipv4Error`]: super::ip::Ipv4Error
+
+use core::fmt;
+
+/// Constraint a field failed to satisfy.
+///
+/// Lengths of the layer count its bytes up to the end of the field, so a
+/// truncated layer reads e.g. as a header needing 20 bytes of which 8 are
+/// present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Constraint {
+    /// The layer is too short for the field.
+    MinLength {
+        /// Smallest valid length.
+        min: usize,
+        /// Actual length.
+        actual: usize,
+    },
+    /// The layer must end with the field.
+    Length {
+        /// Valid length.
+        expected: usize,
+        /// Actual length.
+        actual: usize,
+    },
+    /// The field holds a length below its smallest valid value.
+    MinValue {
+        /// Smallest valid value.
+        min: usize,
+        /// Actual value.
+        actual: usize,
+    },
+    /// The field holds a length above its largest valid value.
+    MaxValue {
+        /// Largest valid value.
+        max: usize,
+        /// Actual value.
+        actual: usize,
+    },
+    /// The field must be a multiple of `of` bytes long.
+    Multiple {
+        /// Granularity of the length.
+        of: usize,
+        /// Actual length of the field.
+        actual: usize,
+    },
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::MinLength { min, actual } => {
+                write!(f, "length {actual} is less than {min}")
+            }
+            Constraint::Length { expected, actual } => {
+                write!(f, "length {actual} is not {expected}")
+            }
+            Constraint::MinValue { min, actual } => {
+                write!(f, "value {actual} is less than {min}")
+            }
+            Constraint::MaxValue { max, actual } => {
+                write!(f, "value {actual} is greater than {max}")
+            }
+            Constraint::Multiple { of, actual } => {
+                write!(f, "length {actual} is not a multiple of {of}")
+            }
+        }
+    }
+}
+
+/// Parse failure of a layer.
+#[derive(Debug, thiserror::Error, Clone, Copy, PartialEq, Eq)]
+#[error("{field} at offset {offset}: {constraint}")]
+pub struct LayerError {
+    /// Name of the layer.
+    pub layer: &'static str,
+    /// Name of the field that failed, e.g. `header` if the data is too
+    /// short for the header.
+    pub field: &'static str,
+    /// Offset of the field from the start of the layer.
+    pub offset: usize,
+    /// Constraint the field failed to satisfy.
+    pub constraint: Constraint,
+}
+
+impl LayerError {
+    /// A layer of `actual` bytes, too short for a field ending at `min`.
+    pub fn min_length(
+        layer: &'static str,
+        field: &'static str,
+        offset: usize,
+        min: usize,
+        actual: usize,
+    ) -> Self {
+        Self {
+            layer,
+            field,
+            offset,
+            constraint: Constraint::MinLength { min, actual },
+        }
+    }
+
+    /// A layer of `actual` bytes, which should end with a field ending at
+    /// `expected`.
+    pub fn length(
+        layer: &'static str,
+        field: &'static str,
+        offset: usize,
+        expected: usize,
+        actual: usize,
+    ) -> Self {
+        Self {
+            layer,
+            field,
+            offset,
+            constraint: Constraint::Length { expected, actual },
+        }
+    }
+
+    /// A field holding a length of `actual`, below `min`.
+    pub fn min_value(
+        layer: &'static str,
+        field: &'static str,
+        offset: usize,
+        min: usize,
+        actual: usize,
+    ) -> Self {
+        Self {
+            layer,
+            field,
+            offset,
+            constraint: Constraint::MinValue { min, actual },
+        }
+    }
+
+    /// A field holding a length of `actual`, above `max`.
+    pub fn max_value(
+        layer: &'static str,
+        field: &'static str,
+        offset: usize,
+        max: usize,
+        actual: usize,
+    ) -> Self {
+        Self {
+            layer,
+            field,
+            offset,
+            constraint: Constraint::MaxValue { max, actual },
+        }
+    }
+}
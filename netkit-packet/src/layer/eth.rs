@@ -1,6 +1,10 @@
 //! Ethernet layer.
 
-use crate::{field_spec, prelude::*};
+use crate::{
+    field_spec,
+    layer::{assemble::Assemble, vlan},
+    prelude::*,
+};
 
 pub mod eth_addr;
 pub use eth_addr::*;
@@ -10,10 +14,11 @@ pub use eth_type::*;
 
 /// Error type for Eth layer.
 #[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum EthError {
     /// Invalid Eth length.
-    #[error("Invalid Eth length: Length {0} is less than minimum 14")]
-    InvalidLength(usize),
+    #[error("Invalid Eth length: {0}")]
+    InvalidLength(#[from] LayerError),
 }
 
 field_spec!(EthAddrSpec, EthAddr, [u8; 6]);
@@ -22,7 +27,14 @@ field_spec!(EthTypeSpec, EthType, u16);
 /// Minimum length of an Eth header.
 pub const MIN_HEADER_LENGTH: usize = 14;
 
+/// Largest value of the Eth type field that is an IEEE 802.3 payload length.
+pub const MAX_LENGTH: u16 = 1500;
+
 /// Ethernet layer.
+///
+/// Both Ethernet II and IEEE 802.3 frames are handled. In 802.3 frames the
+/// Eth type field holds the payload length (see [`length`](Eth::length)) and
+/// an [`Llc`] header follows.
 pub struct Eth<T>
 where
     T: AsRef<[u8]>,
@@ -59,7 +71,14 @@ where
     /// Validate the Eth layer.
     pub fn validate(&self) -> Result<(), EthError> {
         if self.data.as_ref().len() < MIN_HEADER_LENGTH {
-            return Err(EthError::InvalidLength(self.data.as_ref().len()));
+            return Err(LayerError::min_length(
+                "Eth",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
         }
 
         Ok(())
@@ -103,6 +122,42 @@ where
         &self.data.as_ref()[Self::FIELD_PAYLOAD]
     }
 
+    /// Get the payload length of an IEEE 802.3 frame.
+    ///
+    /// Returns `None` for Ethernet II frames, whose Eth type field holds an
+    /// Eth type.
+    #[inline]
+    pub fn length(&self) -> Option<u16> {
+        let value: u16 = self.eth_type().get().into();
+        (value <= MAX_LENGTH).then_some(value)
+    }
+
+    /// Get the Llc layer of an IEEE 802.3 frame.
+    ///
+    /// The Llc layer excludes the padding after the payload length.
+    pub fn llc(&self) -> Option<Llc<&[u8]>> {
+        let length = self.length()? as usize;
+        let payload = self.payload();
+        Llc::new(&payload[..length.min(payload.len())]).ok()
+    }
+
+    /// Get the Eth type of the network layer and the network layer.
+    ///
+    /// For IEEE 802.3 frames this is the protocol encapsulated by LLC/SNAP
+    /// (RFC 1042), if any.
+    pub fn network(&self) -> Option<(EthType, &[u8])> {
+        if self.length().is_none() {
+            return Some((self.eth_type().get(), self.payload()));
+        }
+
+        let llc = self.llc()?;
+        let LlcProtocol::Ethernet(eth_type) = llc.protocol() else {
+            return None;
+        };
+        let payload = self.payload();
+        Some((eth_type, &payload[llc.header_len()..llc.inner().len()]))
+    }
+
     /// Get the IPv4 layer if the Eth type is IPv4.
     pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
         if self.eth_type().get() == EthType::Ipv4 {
@@ -111,6 +166,75 @@ where
             None
         }
     }
+
+    /// Get the IPv6 layer if the Eth type is IPv6.
+    pub fn ipv6(&self) -> Option<Ipv6<&[u8]>> {
+        if self.eth_type().get() == EthType::Ipv6 {
+            Ipv6::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the Eapol layer if the Eth type is Eapol.
+    pub fn eapol(&self) -> Option<Eapol<&[u8]>> {
+        if self.eth_type().get() == EthType::Eapol {
+            Eapol::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the Goose layer if the Eth type is Goose.
+    pub fn goose(&self) -> Option<Goose<&[u8]>> {
+        if self.eth_type().get() == EthType::Goose {
+            Goose::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the Sv layer if the Eth type is Sv.
+    pub fn sv(&self) -> Option<Sv<&[u8]>> {
+        if self.eth_type().get() == EthType::Sv {
+            Sv::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the Ptp layer if the Eth type is Ptp.
+    pub fn ptp(&self) -> Option<Ptp<&[u8]>> {
+        if self.eth_type().get() == EthType::Ptp {
+            Ptp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the VLAN tag if the Eth type is a VLAN tag.
+    pub fn vlan(&self) -> Option<Vlan<&[u8]>> {
+        match self.eth_type().get() {
+            EthType::Vlan | EthType::ServiceVlan => Vlan::new(self.payload()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the VLAN identifiers of all tags, outermost first.
+    pub fn vlan_ids(&self) -> Vec<u16> {
+        let mut ids = Vec::new();
+        let mut eth_type = self.eth_type().get();
+        let mut payload = self.payload();
+        while matches!(eth_type, EthType::Vlan | EthType::ServiceVlan) {
+            let Ok(tag) = Vlan::new(payload) else {
+                break;
+            };
+            ids.push(tag.vid().get());
+            eth_type = tag.eth_type().get();
+            payload = &payload[Vlan::<&[u8]>::FIELD_PAYLOAD];
+        }
+        ids
+    }
 }
 
 impl<T> Eth<T>
@@ -157,6 +281,39 @@ where
     }
 }
 
+impl Eth<Vec<u8>> {
+    /// Insert a VLAN tag as the outermost tag.
+    ///
+    /// The tag carries the previous Eth type of the frame, which becomes the
+    /// tag protocol identifier.
+    pub fn push_vlan(&mut self, tag: VlanTag) {
+        let eth_type = self.eth_type().get();
+        let bytes = tag.to_bytes(eth_type);
+        let at = Self::FIELD_PAYLOAD.start;
+        self.data.splice(at..at, bytes);
+        self.eth_type_mut().set(tag.tpid);
+    }
+
+    /// Remove the outermost VLAN tag, returning it.
+    ///
+    /// The Eth type of the frame becomes the one carried by the tag.
+    pub fn pop_vlan(&mut self) -> Option<VlanTag> {
+        let vlan = self.vlan()?;
+        let tag = VlanTag {
+            tpid: self.eth_type().get(),
+            pcp: vlan.pcp().get(),
+            dei: vlan.dei().get(),
+            vid: vlan.vid().get(),
+        };
+        let eth_type = vlan.eth_type().get();
+
+        let at = Self::FIELD_PAYLOAD.start;
+        self.data.drain(at..at + vlan::MIN_HEADER_LENGTH);
+        self.eth_type_mut().set(eth_type);
+        Some(tag)
+    }
+}
+
 layer_impl!(Eth);
 
 impl<T> core::fmt::Debug for Eth<T>
@@ -167,8 +324,11 @@ where
         let mut f = f.debug_struct("Eth");
 
         f.field("dst", &format_args!("{}", self.dst().get()))
-            .field("src", &format_args!("{}", self.src().get()))
-            .field("eth_type", &self.eth_type().get());
+            .field("src", &format_args!("{}", self.src().get()));
+        match self.length() {
+            Some(length) => f.field("length", &length),
+            None => f.field("eth_type", &self.eth_type().get()),
+        };
 
         // TODO: Print payload
 
@@ -178,10 +338,12 @@ where
 
 /// Builder for [`Eth`].
 #[derive(Clone, Debug, Default)]
+#[must_use]
 pub struct EthBuilder {
     src: Option<EthAddr>,
     dst: Option<EthAddr>,
     eth_type: Option<EthType>,
+    vlans: Vec<VlanTag>,
     payload: Vec<u8>,
 }
 
@@ -209,6 +371,16 @@ impl EthBuilder {
         self
     }
 
+    /// Add a VLAN tag, inside the tags already added.
+    ///
+    /// The Eth type of the frame announces the outermost tag, each tag
+    /// announces the next one, and the innermost tag carries the Eth type
+    /// set with [`eth_type`](Self::eth_type).
+    pub fn vlan(&mut self, tag: impl Into<VlanTag>) -> &mut Self {
+        self.vlans.push(tag.into());
+        self
+    }
+
     /// Set the payload.
     pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
         self.payload.extend_from_slice(payload.as_ref());
@@ -217,16 +389,35 @@ impl EthBuilder {
 
     /// Build the Eth layer.
     pub fn build(&self) -> Eth<Vec<u8>> {
-        let len = MIN_HEADER_LENGTH + self.payload.len();
+        let mut data = vec![0; self.header_len()];
+        data.extend_from_slice(&self.payload);
+        self.write_header(&mut data);
+
+        unsafe { Eth::new_unchecked(data) }
+    }
+}
 
-        let mut eth = unsafe { Eth::new_unchecked(vec![0; len]) };
+impl Assemble for EthBuilder {
+    fn header_len(&self) -> usize {
+        MIN_HEADER_LENGTH + self.vlans.len() * vlan::MIN_HEADER_LENGTH
+    }
+
+    fn write_header(&self, segment: &mut [u8]) {
+        let eth_type = self.eth_type.unwrap_or_default();
+        let mut eth = unsafe { Eth::new_unchecked(&mut *segment) };
 
         eth.src_mut().set(self.src.unwrap_or_default());
         eth.dst_mut().set(self.dst.unwrap_or_default());
-        eth.eth_type_mut().set(self.eth_type.unwrap_or_default());
-        eth.payload_mut().copy_from_slice(self.payload.as_ref());
-
-        eth
+        eth.eth_type_mut()
+            .set(self.vlans.first().map_or(eth_type, |tag| tag.tpid));
+
+        // Each tag announces the next one, the innermost the Eth type.
+        let mut at = MIN_HEADER_LENGTH;
+        for (i, tag) in self.vlans.iter().enumerate() {
+            let next = self.vlans.get(i + 1).map_or(eth_type, |tag| tag.tpid);
+            segment[at..at + vlan::MIN_HEADER_LENGTH].copy_from_slice(&tag.to_bytes(next));
+            at += vlan::MIN_HEADER_LENGTH;
+        }
     }
 }
 
@@ -370,4 +561,66 @@ mod tests {
             "Eth { dst: 01:23:45:67:89:AB, src: CD:EF:01:23:45:67, eth_type: Ipv4 }"
         );
     }
+
+    #[test]
+    fn eth_llc() {
+        use crate::layer::llc::*;
+
+        let bpdu = llc!(dsap: SAP_STP, ssap: SAP_STP, payload: [0u8; 35]);
+        let mut payload = bpdu.inner().clone();
+        payload.resize(46, 0); // padding
+        let eth = eth!(
+            dst: [0x01, 0x80, 0xC2, 0x00, 0x00, 0x00],
+            eth_type: 38u16,
+            payload: payload,
+        );
+
+        assert_eq!(eth.length(), Some(38));
+        assert!(format!("{eth:?}").ends_with("length: 38 }"));
+        let llc = eth.llc().unwrap();
+        assert_eq!(llc.protocol(), LlcProtocol::Stp);
+        assert_eq!(llc.payload().len(), 35);
+        assert!(eth.network().is_none());
+
+        let ipv4 = ipv4!(ttl: 3u8);
+        let snap = llc!(oui: OUI_ETHERNET, pid: EthType::Ipv4, payload: ipv4.inner());
+        let eth = eth!(eth_type: snap.inner().len() as u16, payload: snap.inner());
+        let (eth_type, payload) = eth.network().unwrap();
+        assert_eq!(eth_type, EthType::Ipv4);
+        assert_eq!(payload, ipv4.inner().as_slice());
+
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+        assert_eq!(eth.length(), None);
+        assert!(eth.llc().is_none());
+        assert_eq!(eth.network().unwrap().0, EthType::Ipv4);
+    }
+
+    #[test]
+    fn eth_vlan_push_pop() {
+        let ipv4 = ipv4!(ttl: 3u8);
+        let tagged = eth!(
+            eth_type: EthType::Ipv4,
+            vlan: VlanTag::service(100),
+            vlan: VlanTag::new(10).with_pcp(5),
+            payload: ipv4.inner(),
+        );
+        assert_eq!(tagged.eth_type().get(), EthType::ServiceVlan);
+        assert_eq!(tagged.vlan_ids(), [100, 10]);
+        let outer = tagged.vlan().unwrap();
+        let inner = Vlan::new(outer.payload()).unwrap();
+        assert_eq!(inner.pcp().get(), 5);
+        assert_eq!(inner.eth_type().get(), EthType::Ipv4);
+        assert_eq!(inner.payload(), ipv4.inner().as_slice());
+
+        let mut eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+        eth.push_vlan(VlanTag::new(10).with_pcp(5));
+        eth.push_vlan(VlanTag::service(100));
+        assert_eq!(eth.inner(), tagged.inner());
+
+        assert_eq!(eth.pop_vlan(), Some(VlanTag::service(100)));
+        assert_eq!(eth.pop_vlan(), Some(VlanTag::new(10).with_pcp(5)));
+        assert_eq!(eth.pop_vlan(), None);
+        assert_eq!(eth.eth_type().get(), EthType::Ipv4);
+        assert_eq!(eth.payload(), ipv4.inner().as_slice());
+    }
 }
@@ -6,6 +6,7 @@ use crate::impl_target;
 
 /// Error type for `EthAddr`
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum EthAddrError {
     /// Invalid length
     #[error("Invalide EthAddr length: Length must be 6, got {0}")]
@@ -17,13 +18,16 @@ pub enum EthAddrError {
 }
 
 /// Ethernet MAC address
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct EthAddr {
     octets: [u8; 6],
 }
 
 impl EthAddr {
+    /// The broadcast address `FF:FF:FF:FF:FF:FF`
+    pub const BROADCAST: EthAddr = EthAddr::new(0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF);
+
     /// Create a new `EthAddr` from octets
     pub const fn new(a: u8, b: u8, c: u8, d: u8, e: u8, f: u8) -> Self {
         Self {
@@ -43,6 +47,16 @@ impl EthAddr {
         octets.copy_from_slice(slice);
         Self { octets }
     }
+
+    /// Whether this is the broadcast address
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    /// Whether this is a multicast (group) address, broadcast included
+    pub fn is_multicast(&self) -> bool {
+        self.octets[0] & 0x01 != 0
+    }
 }
 
 impl Display for EthAddr {
@@ -35,12 +35,27 @@ pub enum EthType {
     /// Frame Relay ARP
     FrameRelayArp = 0x0808,
 
+    /// EAP over LAN (IEEE 802.1X)
+    Eapol = 0x888E,
+
     /// Customer VLAN Tag Type
     Vlan = 0x8100,
 
     /// Internet Protocol version 6 (IPv6)
     Ipv6 = 0x86DD,
 
+    /// Service VLAN Tag Type (QinQ)
+    ServiceVlan = 0x88A8,
+
+    /// IEC 61850 GOOSE
+    Goose = 0x88B8,
+
+    /// IEC 61850 Sampled Values
+    Sv = 0x88BA,
+
+    /// Precision Time Protocol (IEEE 1588)
+    Ptp = 0x88F7,
+
     /// Represents any other EthType
     #[num_enum(catch_all)]
     Reserved(u16),
@@ -69,8 +84,13 @@ mod tests {
             Ipv4 => "Ipv4",
             Arp => "Arp",
             FrameRelayArp => "FrameRelayArp",
+            Eapol => "Eapol",
             Vlan => "Vlan",
             Ipv6 => "Ipv6",
+            ServiceVlan => "ServiceVlan",
+            Goose => "Goose",
+            Sv => "Sv",
+            Ptp => "Ptp",
         );
     }
 
@@ -81,8 +101,13 @@ mod tests {
             Ipv4 => 0x0800,
             Arp => 0x0806,
             FrameRelayArp => 0x0808,
+            Eapol => 0x888E,
             Vlan => 0x8100,
             Ipv6 => 0x86DD,
+            ServiceVlan => 0x88A8,
+            Goose => 0x88B8,
+            Sv => 0x88BA,
+            Ptp => 0x88F7,
         );
     }
 }
@@ -0,0 +1,445 @@
+//! Generic Routing Encapsulation (GRE) layer.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Gre layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum GreError {
+    /// Invalid Gre length.
+    #[error("Invalid Gre length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(ChecksumPresentSpec, bool, u8, 0x80, 7);
+field_spec!(KeyPresentSpec, bool, u8, 0x20, 5);
+field_spec!(SeqPresentSpec, bool, u8, 0x10, 4);
+field_spec!(VersionSpec, u8, u8, 0x07);
+field_spec!(ProtocolTypeSpec, EthType, u16);
+field_spec!(ChecksumSpec, u16, u16);
+field_spec!(KeySpec, u32, u32);
+field_spec!(SeqNumSpec, u32, u32);
+
+/// Minimum length of a Gre header.
+pub const MIN_HEADER_LENGTH: usize = 4;
+
+/// Generic Routing Encapsulation (GRE) layer.
+///
+/// See [RFC 2784](https://datatracker.ietf.org/doc/html/rfc2784) and
+/// [RFC 2890](https://datatracker.ietf.org/doc/html/rfc2890).
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// | C|  | K| S|    Reserved0       |    Version   |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                                 Protocol Type |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |               Checksum (optional) + Reserved1 |
+/// |                              Key (optional)   |
+/// |                  Sequence Number (optional)   |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct Gre<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Gre<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the checksum present bit: 0..1 (1bit)
+    pub const FIELD_CHECKSUM_PRESENT: core::ops::Range<usize> = 0..1;
+    /// Field range of the key present bit: 0..1 (1bit)
+    pub const FIELD_KEY_PRESENT: core::ops::Range<usize> = 0..1;
+    /// Field range of the sequence number present bit: 0..1 (1bit)
+    pub const FIELD_SEQ_PRESENT: core::ops::Range<usize> = 0..1;
+    /// Field range of the version: 1..2 (3bits)
+    pub const FIELD_VERSION: core::ops::Range<usize> = 1..2;
+    /// Field range of the protocol type: 2..4
+    pub const FIELD_PROTOCOL_TYPE: core::ops::Range<usize> = 2..4;
+
+    /// Create a new Gre layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Gre packet.
+    ///
+    /// The data must be at least as long as the header indicated by the
+    /// present bits. Otherwise, the following methods may panic when accessing
+    /// the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Gre layer.
+    pub fn validate(&self) -> Result<(), GreError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("Gre", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+
+        if len < self.header_len() {
+            return Err(LayerError::min_length(
+                "Gre",
+                "optional fields",
+                MIN_HEADER_LENGTH,
+                self.header_len(),
+                len,
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Gre layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, GreError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the length of the header including the optional fields.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        MIN_HEADER_LENGTH
+            + 4 * (self.checksum_present().get() as usize
+                + self.key_present().get() as usize
+                + self.seq_present().get() as usize)
+    }
+
+    /// Get the accessor of the checksum present bit.
+    #[inline]
+    pub fn checksum_present(&self) -> &Field<ChecksumPresentSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CHECKSUM_PRESENT])
+    }
+
+    /// Get the accessor of the key present bit.
+    #[inline]
+    pub fn key_present(&self) -> &Field<KeyPresentSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_KEY_PRESENT])
+    }
+
+    /// Get the accessor of the sequence number present bit.
+    #[inline]
+    pub fn seq_present(&self) -> &Field<SeqPresentSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SEQ_PRESENT])
+    }
+
+    /// Get the accessor of the version.
+    #[inline]
+    pub fn version(&self) -> &Field<VersionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION])
+    }
+
+    /// Get the accessor of the protocol type.
+    #[inline]
+    pub fn protocol_type(&self) -> &Field<ProtocolTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PROTOCOL_TYPE])
+    }
+
+    /// Get the accessor of the checksum if present.
+    #[inline]
+    pub fn checksum(&self) -> Option<&Field<ChecksumSpec>> {
+        self.checksum_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[4..6]))
+    }
+
+    /// Get the accessor of the key if present.
+    #[inline]
+    pub fn key(&self) -> Option<&Field<KeySpec>> {
+        let offset = self.key_offset();
+        self.key_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[offset..offset + 4]))
+    }
+
+    /// Get the accessor of the sequence number if present.
+    #[inline]
+    pub fn seq_num(&self) -> Option<&Field<SeqNumSpec>> {
+        let offset = self.seq_num_offset();
+        self.seq_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[offset..offset + 4]))
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+
+    /// Get the encapsulated IPv4 layer if the protocol type is IPv4.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.protocol_type().get() == EthType::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn key_offset(&self) -> usize {
+        MIN_HEADER_LENGTH + 4 * self.checksum_present().get() as usize
+    }
+
+    #[inline]
+    fn seq_num_offset(&self) -> usize {
+        self.key_offset() + 4 * self.key_present().get() as usize
+    }
+}
+
+impl<T> Gre<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the checksum present bit.
+    #[inline]
+    pub fn checksum_present_mut(&mut self) -> &mut Field<ChecksumPresentSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CHECKSUM_PRESENT])
+    }
+
+    /// Get the mutable accessor of the key present bit.
+    #[inline]
+    pub fn key_present_mut(&mut self) -> &mut Field<KeyPresentSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_KEY_PRESENT])
+    }
+
+    /// Get the mutable accessor of the sequence number present bit.
+    #[inline]
+    pub fn seq_present_mut(&mut self) -> &mut Field<SeqPresentSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SEQ_PRESENT])
+    }
+
+    /// Get the mutable accessor of the version.
+    #[inline]
+    pub fn version_mut(&mut self) -> &mut Field<VersionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION])
+    }
+
+    /// Get the mutable accessor of the protocol type.
+    #[inline]
+    pub fn protocol_type_mut(&mut self) -> &mut Field<ProtocolTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PROTOCOL_TYPE])
+    }
+
+    /// Get the mutable accessor of the checksum if present.
+    #[inline]
+    pub fn checksum_mut(&mut self) -> Option<&mut Field<ChecksumSpec>> {
+        self.checksum_present()
+            .get()
+            .then(|| cast_from_bytes_mut(&mut self.data.as_mut()[4..6]))
+    }
+
+    /// Get the mutable accessor of the key if present.
+    #[inline]
+    pub fn key_mut(&mut self) -> Option<&mut Field<KeySpec>> {
+        let offset = self.key_offset();
+        self.key_present()
+            .get()
+            .then(|| cast_from_bytes_mut(&mut self.data.as_mut()[offset..offset + 4]))
+    }
+
+    /// Get the mutable accessor of the sequence number if present.
+    #[inline]
+    pub fn seq_num_mut(&mut self) -> Option<&mut Field<SeqNumSpec>> {
+        let offset = self.seq_num_offset();
+        self.seq_present()
+            .get()
+            .then(|| cast_from_bytes_mut(&mut self.data.as_mut()[offset..offset + 4]))
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let range = self.header_len()..;
+        &mut self.data.as_mut()[range]
+    }
+}
+
+layer_impl!(Gre);
+
+/// Builder for [`Gre`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct GreBuilder {
+    version: Option<u8>,
+    protocol_type: Option<EthType>,
+    checksum: Option<u16>,
+    key: Option<u32>,
+    seq_num: Option<u32>,
+    payload: Vec<u8>,
+}
+
+impl GreBuilder {
+    /// Create a new Gre builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the version.
+    pub fn version(&mut self, version: impl Into<u8>) -> &mut Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the protocol type.
+    pub fn protocol_type(&mut self, protocol_type: impl Into<EthType>) -> &mut Self {
+        self.protocol_type = Some(protocol_type.into());
+        self
+    }
+
+    /// Set the checksum, which also sets the checksum present bit.
+    pub fn checksum(&mut self, checksum: impl Into<u16>) -> &mut Self {
+        self.checksum = Some(checksum.into());
+        self
+    }
+
+    /// Set the key, which also sets the key present bit.
+    pub fn key(&mut self, key: impl Into<u32>) -> &mut Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Set the sequence number, which also sets the sequence present bit.
+    pub fn seq_num(&mut self, seq_num: impl Into<u32>) -> &mut Self {
+        self.seq_num = Some(seq_num.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Gre layer.
+    pub fn build(&self) -> Gre<Vec<u8>> {
+        let header_len = MIN_HEADER_LENGTH
+            + 4 * (self.checksum.is_some() as usize
+                + self.key.is_some() as usize
+                + self.seq_num.is_some() as usize);
+
+        let mut gre = unsafe { Gre::new_unchecked(vec![0; header_len + self.payload.len()]) };
+
+        gre.checksum_present_mut().set(self.checksum.is_some());
+        gre.key_present_mut().set(self.key.is_some());
+        gre.seq_present_mut().set(self.seq_num.is_some());
+        gre.version_mut().set(self.version.unwrap_or(0));
+        gre.protocol_type_mut()
+            .set(self.protocol_type.unwrap_or(EthType::Ipv4));
+        if let (Some(field), Some(checksum)) = (gre.checksum_mut(), self.checksum) {
+            field.set(checksum);
+        }
+        if let (Some(field), Some(key)) = (gre.key_mut(), self.key) {
+            field.set(key);
+        }
+        if let (Some(field), Some(seq_num)) = (gre.seq_num_mut(), self.seq_num) {
+            field.set(seq_num);
+        }
+        gre.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        gre
+    }
+}
+
+/// Create a Gre layer with the given fields.
+///
+/// # Example
+///
+/// ```
+/// # use netkit_packet::prelude::*;
+/// let gre = gre!(
+///     protocol_type: EthType::Ipv4,
+///     key: 42u32,
+///     payload: [0x01, 0x02],
+/// );
+///
+/// assert_eq!(gre.header_len(), 8);
+/// assert_eq!(gre.key().unwrap().get(), 42);
+/// assert!(gre.seq_num().is_none());
+/// assert_eq!(gre.payload(), [0x01, 0x02]);
+/// ```
+#[macro_export]
+macro_rules! gre {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::gre::GreBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn gre_new() {
+        let data: [u8; 14] = [
+            0x30, // key and sequence present
+            0x00, // version 0
+            0x08, 0x00, // protocol type ipv4
+            0x00, 0x00, 0x00, 0x2a, // key 42
+            0x00, 0x00, 0x00, 0x07, // sequence number 7
+            0x01, 0x02, // payload
+        ];
+
+        let gre = Gre::new(data).unwrap();
+
+        assert!(gre.checksum().is_none());
+        assert_eq!(gre.version().get(), 0);
+        assert_eq!(gre.protocol_type().get(), EthType::Ipv4);
+        assert_eq!(gre.key().unwrap().get(), 42);
+        assert_eq!(gre.seq_num().unwrap().get(), 7);
+        assert_eq!(gre.payload(), &[0x01, 0x02]);
+
+        assert_eq!(
+            Gre::new(&data[..8]).err(),
+            Some(GreError::InvalidLength(LayerError::min_length(
+                "Gre",
+                "optional fields",
+                4,
+                12,
+                8
+            )))
+        );
+    }
+
+    #[test]
+    fn gre_macro() {
+        let gre = gre!(
+            checksum: 0xFFFFu16,
+            seq_num: 1u32,
+            payload: [0x01, 0x02, 0x03, 0x04],
+        );
+
+        assert_eq!(
+            gre.inner(),
+            &[
+                0x90, 0x00, 0x08, 0x00, // flags, version, protocol type
+                0xFF, 0xFF, 0x00, 0x00, // checksum, reserved
+                0x00, 0x00, 0x00, 0x01, // sequence number
+                0x01, 0x02, 0x03, 0x04, // payload
+            ]
+        );
+    }
+}
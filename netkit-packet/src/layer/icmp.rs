@@ -0,0 +1,368 @@
+//! Internet Control Message Protocol (ICMP) layer.
+
+use crate::{field_spec, prelude::*};
+
+pub mod icmp_type;
+pub use icmp_type::IcmpType;
+
+/// Error type for Icmp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum IcmpError {
+    /// Invalid Icmp length.
+    #[error("Invalid Icmp length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(TypeSpec, IcmpType, u8);
+field_spec!(CodeSpec, u8, u8);
+field_spec!(ChecksumSpec, u16, u16);
+field_spec!(IdentifierSpec, u16, u16);
+field_spec!(SequenceSpec, u16, u16);
+
+/// Minimum length of an Icmp message.
+pub const MIN_HEADER_LENGTH: usize = 8;
+
+/// Code of Time Exceeded messages: TTL exceeded in transit.
+pub const CODE_TTL_EXCEEDED: u8 = 0;
+
+/// Code of Destination Unreachable messages: port unreachable.
+pub const CODE_PORT_UNREACHABLE: u8 = 3;
+
+/// Code of Destination Unreachable messages: fragmentation needed and DF set.
+pub const CODE_FRAGMENTATION_NEEDED: u8 = 4;
+
+/// Internet Control Message Protocol (ICMP) layer.
+///
+/// See [RFC 792](https://datatracker.ietf.org/doc/html/rfc792).
+///
+/// ```text
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |     Code      |          Checksum             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |           Identifier          |        Sequence Number        |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Data ...
+/// +-+-+-+-+-
+/// ```
+///
+/// The identifier and sequence number are only meaningful for echo
+/// messages; error messages carry the start of the offending datagram as
+/// data, see [`Icmp::original`].
+pub struct Icmp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Icmp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the type: 0..1
+    pub const FIELD_TYPE: core::ops::Range<usize> = 0..1;
+    /// Field range of the code: 1..2
+    pub const FIELD_CODE: core::ops::Range<usize> = 1..2;
+    /// Field range of the checksum: 2..4
+    pub const FIELD_CHECKSUM: core::ops::Range<usize> = 2..4;
+    /// Field range of the identifier: 4..6
+    pub const FIELD_IDENTIFIER: core::ops::Range<usize> = 4..6;
+    /// Field range of the sequence number: 6..8
+    pub const FIELD_SEQUENCE: core::ops::Range<usize> = 6..8;
+    /// Field range of the payload: 8..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 8..;
+
+    /// Create a new Icmp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Icmp message.
+    ///
+    /// The length of the data must be at least 8 bytes. Otherwise, the
+    /// following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Icmp layer.
+    pub fn validate(&self) -> Result<(), IcmpError> {
+        if self.data.as_ref().len() < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "Icmp",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Icmp layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, IcmpError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the type.
+    #[inline]
+    pub fn icmp_type(&self) -> &Field<TypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_TYPE])
+    }
+
+    /// Get the accessor of the code.
+    #[inline]
+    pub fn code(&self) -> &Field<CodeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CODE])
+    }
+
+    /// Get the accessor of the checksum.
+    #[inline]
+    pub fn checksum(&self) -> &Field<ChecksumSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CHECKSUM])
+    }
+
+    /// Get the accessor of the identifier.
+    #[inline]
+    pub fn identifier(&self) -> &Field<IdentifierSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_IDENTIFIER])
+    }
+
+    /// Get the accessor of the sequence number.
+    #[inline]
+    pub fn sequence(&self) -> &Field<SequenceSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SEQUENCE])
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Get the next-hop MTU of a Fragmentation Needed message (RFC 1191).
+    ///
+    /// Routers predating RFC 1191 report zero.
+    pub fn next_hop_mtu(&self) -> Option<u16> {
+        (self.icmp_type().get() == IcmpType::DestinationUnreachable
+            && self.code().get() == CODE_FRAGMENTATION_NEEDED)
+            .then(|| self.sequence().get())
+    }
+
+    /// Get the quoted header of the offending datagram of an error message.
+    ///
+    /// Only the IP header and the first 8 bytes of its payload are usually
+    /// quoted, enough for the ports of TCP and UDP.
+    pub fn original(&self) -> Option<Ipv4<&[u8]>> {
+        if !self.icmp_type().get().is_error() {
+            return None;
+        }
+
+        let original = Ipv4::new(self.payload()).ok()?;
+        (original.ihl().get() as usize * 4 <= self.payload().len()).then_some(original)
+    }
+}
+
+impl<T> Icmp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the type.
+    #[inline]
+    pub fn icmp_type_mut(&mut self) -> &mut Field<TypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_TYPE])
+    }
+
+    /// Get the mutable accessor of the code.
+    #[inline]
+    pub fn code_mut(&mut self) -> &mut Field<CodeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CODE])
+    }
+
+    /// Get the mutable accessor of the checksum.
+    #[inline]
+    pub fn checksum_mut(&mut self) -> &mut Field<ChecksumSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CHECKSUM])
+    }
+
+    /// Get the mutable accessor of the identifier.
+    #[inline]
+    pub fn identifier_mut(&mut self) -> &mut Field<IdentifierSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_IDENTIFIER])
+    }
+
+    /// Get the mutable accessor of the sequence number.
+    #[inline]
+    pub fn sequence_mut(&mut self) -> &mut Field<SequenceSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SEQUENCE])
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(Icmp);
+
+/// Builder for [`Icmp`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct IcmpBuilder {
+    icmp_type: Option<IcmpType>,
+    code: Option<u8>,
+    checksum: Option<u16>,
+    identifier: Option<u16>,
+    sequence: Option<u16>,
+    payload: Vec<u8>,
+}
+
+impl IcmpBuilder {
+    /// Create a new Icmp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the type.
+    pub fn icmp_type(&mut self, icmp_type: impl Into<IcmpType>) -> &mut Self {
+        self.icmp_type = Some(icmp_type.into());
+        self
+    }
+
+    /// Set the code.
+    pub fn code(&mut self, code: impl Into<u8>) -> &mut Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Set the checksum.
+    pub fn checksum(&mut self, checksum: impl Into<u16>) -> &mut Self {
+        self.checksum = Some(checksum.into());
+        self
+    }
+
+    /// Set the identifier.
+    pub fn identifier(&mut self, identifier: impl Into<u16>) -> &mut Self {
+        self.identifier = Some(identifier.into());
+        self
+    }
+
+    /// Set the sequence number.
+    pub fn sequence(&mut self, sequence: impl Into<u16>) -> &mut Self {
+        self.sequence = Some(sequence.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build an Icmp layer.
+    ///
+    /// Unless set explicitly, the type is Echo Request and the checksum is
+    /// computed.
+    pub fn build(&self) -> Icmp<Vec<u8>> {
+        let len = MIN_HEADER_LENGTH + self.payload.len();
+        let mut icmp = unsafe { Icmp::new_unchecked(vec![0; len]) };
+
+        icmp.icmp_type_mut()
+            .set(self.icmp_type.unwrap_or(IcmpType::EchoRequest));
+        icmp.code_mut().set(self.code.unwrap_or_default());
+        icmp.identifier_mut()
+            .set(self.identifier.unwrap_or_default());
+        icmp.sequence_mut().set(self.sequence.unwrap_or_default());
+        icmp.payload_mut().copy_from_slice(&self.payload);
+
+        let checksum = self
+            .checksum
+            .unwrap_or_else(|| internet_checksum(icmp.inner()));
+        icmp.checksum_mut().set(checksum);
+
+        icmp
+    }
+}
+
+/// Create a new Icmp layer with the given fields.
+#[macro_export]
+macro_rules! icmp {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::icmp::IcmpBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn icmp_new_unchecked() {
+        let data: [u8; 10] = [
+            0x08, 0x00, // type = echo request, code = 0
+            0xf7, 0xfc, // checksum
+            0x00, 0x01, // identifier = 1
+            0x00, 0x02, // sequence = 2
+            0x01, 0x02, // payload
+        ];
+
+        let icmp = unsafe { Icmp::new_unchecked(data) };
+
+        assert_eq!(icmp.icmp_type().get(), IcmpType::EchoRequest);
+        assert_eq!(icmp.code().get(), 0);
+        assert_eq!(icmp.identifier().get(), 1);
+        assert_eq!(icmp.sequence().get(), 2);
+        assert_eq!(icmp.payload(), &[0x01, 0x02]);
+        assert!(icmp.original().is_none());
+    }
+
+    #[test]
+    fn icmp_time_exceeded() {
+        let probe = udp!(src_port: 40000u16, dst_port: 33434u16);
+        let probe = ipv4!(ttl: 1u8, protocol: IpProtocol::Udp, payload: probe.inner());
+
+        let icmp = icmp!(
+            icmp_type: IcmpType::TimeExceeded,
+            payload: &probe.inner()[..28],
+        );
+        assert_eq!(internet_checksum(icmp.inner()), 0);
+
+        let original = icmp.original().unwrap();
+        assert_eq!(original.ttl().get(), 1);
+        assert_eq!(original.udp().unwrap().dst_port().get(), 33434);
+        assert_eq!(icmp.next_hop_mtu(), None);
+    }
+
+    #[test]
+    fn icmp_fragmentation_needed() {
+        let icmp = icmp!(
+            icmp_type: IcmpType::DestinationUnreachable,
+            code: super::CODE_FRAGMENTATION_NEEDED,
+            sequence: 1400u16,
+        );
+        assert_eq!(icmp.next_hop_mtu(), Some(1400));
+    }
+}
@@ -0,0 +1,100 @@
+//! ICMP Type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// ICMP Type
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum IcmpType {
+    /// Echo Reply
+    EchoReply = 0,
+
+    /// Destination Unreachable
+    DestinationUnreachable = 3,
+
+    /// Redirect
+    Redirect = 5,
+
+    /// Echo Request
+    EchoRequest = 8,
+
+    /// Time Exceeded
+    TimeExceeded = 11,
+
+    /// Parameter Problem
+    ParameterProblem = 12,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl IcmpType {
+    /// Whether messages of this type report an error and quote the
+    /// offending datagram.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            IcmpType::DestinationUnreachable
+                | IcmpType::Redirect
+                | IcmpType::TimeExceeded
+                | IcmpType::ParameterProblem
+        )
+    }
+}
+
+impl_target!(frominto, IcmpType, u8);
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn icmp_type_str() {
+        test_enum_str!(
+            IcmpType,
+            EchoReply => "EchoReply",
+            DestinationUnreachable => "DestinationUnreachable",
+            Redirect => "Redirect",
+            EchoRequest => "EchoRequest",
+            TimeExceeded => "TimeExceeded",
+            ParameterProblem => "ParameterProblem",
+        );
+    }
+
+    #[test]
+    fn icmp_type_num() {
+        test_enum_num!(
+            IcmpType : u8,
+            EchoReply => 0,
+            DestinationUnreachable => 3,
+            Redirect => 5,
+            EchoRequest => 8,
+            TimeExceeded => 11,
+            ParameterProblem => 12,
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Industrial protocol layers.
+//!
+//! IEC 61850 substation protocols mapped directly onto Ethernet: GOOSE
+//! (Generic Object Oriented Substation Event, Eth type
+//! [`EthType::Goose`](crate::layer::eth::EthType::Goose)) and Sampled Values
+//! (Eth type [`EthType::Sv`](crate::layer::eth::EthType::Sv)). Both share a
+//! short header followed by a BER-encoded APDU, see
+//! [IEC 61850-8-1](https://webstore.iec.ch/publication/6021) and
+//! [IEC 61850-9-2](https://webstore.iec.ch/publication/6023).
+
+pub mod goose;
+pub use goose::{Goose, GooseBuilder, GooseError, GoosePdu};
+
+pub mod sv;
+pub use sv::{Sv, SvAsdu, SvBuilder, SvError};
+
+/// Length of the header shared by GOOSE and SV: APPID, length and two
+/// reserved fields.
+pub const HEADER_LENGTH: usize = 8;
+
+/// Simulation bit of the first reserved field (IEC 61850-8-1 Ed. 2).
+pub const RESERVED1_SIMULATION: u16 = 0x8000;
+
+/// Read a BER tag-length-value.
+///
+/// Returns the tag, the value and the rest of the data. Only single byte
+/// tags and definite lengths of up to 4 bytes are supported, which covers
+/// GOOSE and SV.
+pub(crate) fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+
+    let len = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let n = (first & 0x7F) as usize;
+        if n == 0 || n > 4 || data.len() < n {
+            return None;
+        }
+        let len = data[..n]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        data = &data[n..];
+        len
+    };
+
+    if data.len() < len {
+        return None;
+    }
+    Some((tag, &data[..len], &data[len..]))
+}
+
+/// Iterate the BER tag-length-values of a constructed value.
+///
+/// Iteration stops at the first malformed element.
+pub(crate) fn tlvs(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    core::iter::from_fn(move || {
+        let (tag, value, rest) = read_tlv(data)?;
+        data = rest;
+        Some((tag, value))
+    })
+}
+
+/// Decode a BER unsigned integer of up to 8 significant bytes.
+pub(crate) fn ber_uint(value: &[u8]) -> Option<u64> {
+    let value = match value {
+        [0, rest @ ..] => rest,
+        value => value,
+    };
+    if value.len() > 8 {
+        return None;
+    }
+    Some(value.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64))
+}
+
+/// Encode a BER tag-length-value.
+pub(crate) fn write_tlv(buf: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    buf.push(tag);
+    let len = value.len();
+    if len < 0x80 {
+        buf.push(len as u8);
+    } else {
+        let bytes = (len as u32).to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+        buf.push(0x80 | (4 - skip) as u8);
+        buf.extend_from_slice(&bytes[skip..]);
+    }
+    buf.extend_from_slice(value);
+}
+
+/// Encode an unsigned integer as a BER integer value.
+pub(crate) fn ber_uint_bytes(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take(7).take_while(|byte| **byte == 0).count();
+    let mut out = Vec::with_capacity(9 - skip);
+    if bytes[skip] & 0x80 != 0 {
+        out.push(0);
+    }
+    out.extend_from_slice(&bytes[skip..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ber_round_trip() {
+        for value in [0, 1, 0x7F, 0x80, 0xFFFF, u32::MAX as u64, u64::MAX] {
+            let bytes = ber_uint_bytes(value);
+            assert_eq!(ber_uint(&bytes), Some(value));
+        }
+        assert_eq!(ber_uint_bytes(0x80), vec![0x00, 0x80]);
+
+        let mut buf = Vec::new();
+        write_tlv(&mut buf, 0x80, &[0xAA; 200]);
+        assert_eq!(&buf[..3], &[0x80, 0x81, 200]);
+        let (tag, value, rest) = read_tlv(&buf).unwrap();
+        assert_eq!((tag, value.len(), rest.len()), (0x80, 200, 0));
+        assert!(read_tlv(&buf[..100]).is_none());
+    }
+}
@@ -0,0 +1,497 @@
+//! IEC 61850 GOOSE layer.
+
+use core::time::Duration;
+
+use super::{ber_uint, ber_uint_bytes, read_tlv, tlvs, write_tlv, HEADER_LENGTH};
+use crate::{field_spec, prelude::*};
+
+/// Error type for Goose layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum GooseError {
+    /// Invalid Goose length.
+    #[error("Invalid Goose length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(AppIdSpec, u16, u16);
+field_spec!(LengthSpec, u16, u16);
+field_spec!(ReservedSpec, u16, u16);
+
+/// BER tag of the GOOSE PDU.
+pub const TAG_GOOSE_PDU: u8 = 0x61;
+
+/// Decoded GOOSE PDU.
+///
+/// Fields missing from the PDU are left at their defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GoosePdu<'a> {
+    /// GOOSE control block reference.
+    pub gocb_ref: &'a str,
+    /// Time allowed to live, in milliseconds.
+    pub time_allowed_to_live: u32,
+    /// Data set reference.
+    pub dat_set: &'a str,
+    /// GOOSE identifier.
+    pub go_id: Option<&'a str>,
+    /// Time of the last state change, since the Unix epoch.
+    pub time: Duration,
+    /// Time quality of [`GoosePdu::time`].
+    pub time_quality: u8,
+    /// State number, incremented on every state change.
+    pub st_num: u32,
+    /// Sequence number, incremented on every retransmission.
+    pub sq_num: u32,
+    /// Simulation (test) flag.
+    pub simulation: bool,
+    /// Configuration revision.
+    pub conf_rev: u32,
+    /// Needs commissioning flag.
+    pub nds_com: bool,
+    /// Number of data set entries.
+    pub num_entries: u32,
+    /// BER-encoded data set values.
+    pub all_data: &'a [u8],
+}
+
+impl<'a> GoosePdu<'a> {
+    /// Decode a GOOSE PDU from its BER encoding.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let (tag, value, _) = read_tlv(data)?;
+        if tag != TAG_GOOSE_PDU {
+            return None;
+        }
+
+        let str = |value| core::str::from_utf8(value).ok();
+        let uint = |value| ber_uint(value).map(|value| value as u32);
+        let mut pdu = GoosePdu::default();
+        for (tag, value) in tlvs(value) {
+            match tag {
+                0x80 => pdu.gocb_ref = str(value)?,
+                0x81 => pdu.time_allowed_to_live = uint(value)?,
+                0x82 => pdu.dat_set = str(value)?,
+                0x83 => pdu.go_id = Some(str(value)?),
+                0x84 => {
+                    let time: [u8; 8] = value.try_into().ok()?;
+                    let seconds = u32::from_be_bytes([time[0], time[1], time[2], time[3]]);
+                    let fraction = u32::from_be_bytes([0, time[4], time[5], time[6]]);
+                    let nanos = (fraction as u64 * 1_000_000_000) >> 24;
+                    pdu.time = Duration::new(seconds as u64, nanos as u32);
+                    pdu.time_quality = time[7];
+                }
+                0x85 => pdu.st_num = uint(value)?,
+                0x86 => pdu.sq_num = uint(value)?,
+                0x87 => pdu.simulation = value.first().is_some_and(|b| *b != 0),
+                0x88 => pdu.conf_rev = uint(value)?,
+                0x89 => pdu.nds_com = value.first().is_some_and(|b| *b != 0),
+                0x8A => pdu.num_entries = uint(value)?,
+                0xAB => pdu.all_data = value,
+                _ => {}
+            }
+        }
+        Some(pdu)
+    }
+
+    /// Encode the GOOSE PDU in BER.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let seconds = (self.time.as_secs() as u32).to_be_bytes();
+        let fraction = ((self.time.subsec_nanos() as u64) << 24) / 1_000_000_000;
+        let fraction = (fraction as u32).to_be_bytes();
+        let time = [
+            seconds[0],
+            seconds[1],
+            seconds[2],
+            seconds[3],
+            fraction[1],
+            fraction[2],
+            fraction[3],
+            self.time_quality,
+        ];
+
+        let mut pdu = Vec::new();
+        write_tlv(&mut pdu, 0x80, self.gocb_ref.as_bytes());
+        write_tlv(
+            &mut pdu,
+            0x81,
+            &ber_uint_bytes(self.time_allowed_to_live as u64),
+        );
+        write_tlv(&mut pdu, 0x82, self.dat_set.as_bytes());
+        if let Some(go_id) = self.go_id {
+            write_tlv(&mut pdu, 0x83, go_id.as_bytes());
+        }
+        write_tlv(&mut pdu, 0x84, &time);
+        write_tlv(&mut pdu, 0x85, &ber_uint_bytes(self.st_num as u64));
+        write_tlv(&mut pdu, 0x86, &ber_uint_bytes(self.sq_num as u64));
+        write_tlv(&mut pdu, 0x87, &[self.simulation as u8]);
+        write_tlv(&mut pdu, 0x88, &ber_uint_bytes(self.conf_rev as u64));
+        write_tlv(&mut pdu, 0x89, &[self.nds_com as u8]);
+        write_tlv(&mut pdu, 0x8A, &ber_uint_bytes(self.num_entries as u64));
+        write_tlv(&mut pdu, 0xAB, self.all_data);
+
+        let mut out = Vec::with_capacity(pdu.len() + 4);
+        write_tlv(&mut out, TAG_GOOSE_PDU, &pdu);
+        out
+    }
+}
+
+/// IEC 61850 GOOSE layer.
+///
+/// ```text
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |             APPID             |            Length             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |           Reserved 1          |          Reserved 2           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     APDU (BER) ...
+/// +-+-+-+-+-+-+-+-+-
+/// ```
+pub struct Goose<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Goose<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the APPID: 0..2
+    pub const FIELD_APPID: core::ops::Range<usize> = 0..2;
+    /// Field range of the length: 2..4
+    pub const FIELD_LENGTH: core::ops::Range<usize> = 2..4;
+    /// Field range of the first reserved field: 4..6
+    pub const FIELD_RESERVED1: core::ops::Range<usize> = 4..6;
+    /// Field range of the second reserved field: 6..8
+    pub const FIELD_RESERVED2: core::ops::Range<usize> = 6..8;
+
+    /// Create a new Goose layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Goose frame.
+    ///
+    /// The data must be at least as long as the length field. Otherwise,
+    /// the following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Goose layer.
+    ///
+    /// Trailing bytes after the length, e.g. Ethernet padding, are allowed.
+    pub fn validate(&self) -> Result<(), GooseError> {
+        let len = self.data.as_ref().len();
+        if len < HEADER_LENGTH {
+            return Err(LayerError::min_length("Goose", "header", 0, HEADER_LENGTH, len).into());
+        }
+
+        let length = self.length().get() as usize;
+        if length < HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Goose",
+                "length",
+                Self::FIELD_LENGTH.start,
+                HEADER_LENGTH,
+                length,
+            )
+            .into());
+        }
+        if len < length {
+            return Err(LayerError::min_length("Goose", "apdu", HEADER_LENGTH, length, len).into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Goose layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, GooseError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the APPID.
+    #[inline]
+    pub fn appid(&self) -> &Field<AppIdSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_APPID])
+    }
+
+    /// Get the accessor of the length, header included.
+    #[inline]
+    pub fn length(&self) -> &Field<LengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_LENGTH])
+    }
+
+    /// Get the accessor of the first reserved field.
+    #[inline]
+    pub fn reserved1(&self) -> &Field<ReservedSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_RESERVED1])
+    }
+
+    /// Get the accessor of the second reserved field.
+    #[inline]
+    pub fn reserved2(&self) -> &Field<ReservedSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_RESERVED2])
+    }
+
+    /// Get the BER-encoded APDU, excluding any trailing padding.
+    #[inline]
+    pub fn apdu(&self) -> &[u8] {
+        &self.data.as_ref()[HEADER_LENGTH..self.length().get() as usize]
+    }
+
+    /// Decode the GOOSE PDU.
+    pub fn pdu(&self) -> Option<GoosePdu<'_>> {
+        GoosePdu::parse(self.apdu())
+    }
+}
+
+impl<T> Goose<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the APPID.
+    #[inline]
+    pub fn appid_mut(&mut self) -> &mut Field<AppIdSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_APPID])
+    }
+
+    /// Get the mutable accessor of the length.
+    #[inline]
+    pub fn length_mut(&mut self) -> &mut Field<LengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_LENGTH])
+    }
+
+    /// Get the mutable accessor of the first reserved field.
+    #[inline]
+    pub fn reserved1_mut(&mut self) -> &mut Field<ReservedSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_RESERVED1])
+    }
+
+    /// Get the mutable accessor of the second reserved field.
+    #[inline]
+    pub fn reserved2_mut(&mut self) -> &mut Field<ReservedSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_RESERVED2])
+    }
+
+    /// Get the mutable APDU.
+    #[inline]
+    pub fn apdu_mut(&mut self) -> &mut [u8] {
+        let end = self.length().get() as usize;
+        &mut self.data.as_mut()[HEADER_LENGTH..end]
+    }
+}
+
+layer_impl!(Goose);
+
+/// Builder for [`Goose`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct GooseBuilder {
+    appid: Option<u16>,
+    reserved1: Option<u16>,
+    gocb_ref: String,
+    time_allowed_to_live: Option<u32>,
+    dat_set: String,
+    go_id: Option<String>,
+    time: Duration,
+    st_num: Option<u32>,
+    sq_num: u32,
+    simulation: bool,
+    conf_rev: Option<u32>,
+    num_entries: u32,
+    all_data: Vec<u8>,
+}
+
+impl GooseBuilder {
+    /// Create a new Goose builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the APPID.
+    pub fn appid(&mut self, appid: impl Into<u16>) -> &mut Self {
+        self.appid = Some(appid.into());
+        self
+    }
+
+    /// Set the first reserved field.
+    pub fn reserved1(&mut self, reserved1: impl Into<u16>) -> &mut Self {
+        self.reserved1 = Some(reserved1.into());
+        self
+    }
+
+    /// Set the GOOSE control block reference.
+    pub fn gocb_ref(&mut self, gocb_ref: impl Into<String>) -> &mut Self {
+        self.gocb_ref = gocb_ref.into();
+        self
+    }
+
+    /// Set the time allowed to live, in milliseconds.
+    pub fn time_allowed_to_live(&mut self, time_allowed_to_live: impl Into<u32>) -> &mut Self {
+        self.time_allowed_to_live = Some(time_allowed_to_live.into());
+        self
+    }
+
+    /// Set the data set reference.
+    pub fn dat_set(&mut self, dat_set: impl Into<String>) -> &mut Self {
+        self.dat_set = dat_set.into();
+        self
+    }
+
+    /// Set the GOOSE identifier.
+    pub fn go_id(&mut self, go_id: impl Into<String>) -> &mut Self {
+        self.go_id = Some(go_id.into());
+        self
+    }
+
+    /// Set the time of the last state change, since the Unix epoch.
+    pub fn time(&mut self, time: Duration) -> &mut Self {
+        self.time = time;
+        self
+    }
+
+    /// Set the state number.
+    pub fn st_num(&mut self, st_num: impl Into<u32>) -> &mut Self {
+        self.st_num = Some(st_num.into());
+        self
+    }
+
+    /// Set the sequence number.
+    pub fn sq_num(&mut self, sq_num: impl Into<u32>) -> &mut Self {
+        self.sq_num = sq_num.into();
+        self
+    }
+
+    /// Set the simulation flag.
+    pub fn simulation(&mut self, simulation: bool) -> &mut Self {
+        self.simulation = simulation;
+        self
+    }
+
+    /// Set the configuration revision.
+    pub fn conf_rev(&mut self, conf_rev: impl Into<u32>) -> &mut Self {
+        self.conf_rev = Some(conf_rev.into());
+        self
+    }
+
+    /// Set the number of data set entries.
+    pub fn num_entries(&mut self, num_entries: impl Into<u32>) -> &mut Self {
+        self.num_entries = num_entries.into();
+        self
+    }
+
+    /// Set the BER-encoded data set values.
+    pub fn all_data<T: AsRef<[u8]>>(&mut self, all_data: T) -> &mut Self {
+        self.all_data.extend_from_slice(all_data.as_ref());
+        self
+    }
+
+    /// Build the Goose layer.
+    ///
+    /// Unless set explicitly, the APPID is 0x0001, the time allowed to live
+    /// 2000 ms and the state number and configuration revision 1.
+    pub fn build(&self) -> Goose<Vec<u8>> {
+        let pdu = GoosePdu {
+            gocb_ref: &self.gocb_ref,
+            time_allowed_to_live: self.time_allowed_to_live.unwrap_or(2000),
+            dat_set: &self.dat_set,
+            go_id: self.go_id.as_deref(),
+            time: self.time,
+            time_quality: 0,
+            st_num: self.st_num.unwrap_or(1),
+            sq_num: self.sq_num,
+            simulation: self.simulation,
+            conf_rev: self.conf_rev.unwrap_or(1),
+            nds_com: false,
+            num_entries: self.num_entries,
+            all_data: &self.all_data,
+        };
+
+        let mut data = vec![0; HEADER_LENGTH];
+        data.extend_from_slice(&pdu.to_bytes());
+
+        let mut goose = unsafe { Goose::new_unchecked(data) };
+        let length = goose.inner().len() as u16;
+        goose.appid_mut().set(self.appid.unwrap_or(0x0001));
+        goose.length_mut().set(length);
+        goose
+            .reserved1_mut()
+            .set(self.reserved1.unwrap_or_default());
+        goose
+    }
+}
+
+/// Create a Goose layer with the given fields.
+#[macro_export]
+macro_rules! goose {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::industrial::GooseBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goose_round_trip() {
+        let goose = goose!(
+            appid: 0x3001u16,
+            gocb_ref: "IED1LD0/LLN0$GO$gcb01",
+            dat_set: "IED1LD0/LLN0$DataSet1",
+            go_id: "IED1_GOOSE1",
+            time: Duration::from_millis(1_700_000_000_500),
+            st_num: 7u32,
+            sq_num: 300u32,
+            num_entries: 1u32,
+            all_data: [0x83, 0x01, 0x01],
+        );
+        let goose = Goose::new(goose.inner().as_slice()).unwrap();
+        assert_eq!(goose.appid().get(), 0x3001);
+        assert_eq!(goose.length().get() as usize, goose.inner().len());
+
+        let pdu = goose.pdu().unwrap();
+        assert_eq!(pdu.gocb_ref, "IED1LD0/LLN0$GO$gcb01");
+        assert_eq!(pdu.dat_set, "IED1LD0/LLN0$DataSet1");
+        assert_eq!(pdu.go_id, Some("IED1_GOOSE1"));
+        assert_eq!(pdu.time_allowed_to_live, 2000);
+        assert_eq!(pdu.st_num, 7);
+        assert_eq!(pdu.sq_num, 300);
+        assert_eq!(pdu.num_entries, 1);
+        assert_eq!(pdu.all_data, &[0x83, 0x01, 0x01]);
+        // The UtcTime fraction has a resolution of 2^-24 s.
+        let error = pdu.time.abs_diff(Duration::from_millis(1_700_000_000_500));
+        assert!(error < Duration::from_nanos(60));
+
+        assert_eq!(
+            Goose::new(&goose.inner()[..10]).err(),
+            Some(GooseError::InvalidLength(LayerError::min_length(
+                "Goose",
+                "apdu",
+                HEADER_LENGTH,
+                goose.inner().len(),
+                10
+            )))
+        );
+    }
+}
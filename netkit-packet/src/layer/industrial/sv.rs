@@ -0,0 +1,365 @@
+//! IEC 61850 Sampled Values (SV) layer.
+
+use super::{ber_uint, ber_uint_bytes, read_tlv, tlvs, write_tlv, HEADER_LENGTH};
+use crate::{field_spec, prelude::*};
+
+/// Error type for Sv layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SvError {
+    /// Invalid Sv length.
+    #[error("Invalid Sv length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(AppIdSpec, u16, u16);
+field_spec!(LengthSpec, u16, u16);
+field_spec!(ReservedSpec, u16, u16);
+
+/// BER tag of the SV PDU.
+pub const TAG_SAV_PDU: u8 = 0x60;
+
+/// BER tag of the sequence of ASDUs.
+pub const TAG_SEQ_ASDU: u8 = 0xA2;
+
+/// BER tag of an ASDU.
+pub const TAG_ASDU: u8 = 0x30;
+
+/// Decoded SV Application Service Data Unit.
+///
+/// Fields missing from the ASDU are left at their defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SvAsdu<'a> {
+    /// Sampled values control block identifier.
+    pub sv_id: &'a str,
+    /// Data set reference.
+    pub dat_set: Option<&'a str>,
+    /// Sample counter, wrapping at the configured sample rate.
+    pub smp_cnt: u16,
+    /// Configuration revision.
+    pub conf_rev: u32,
+    /// Synchronization source: 0 none, 1 local, 2 global.
+    pub smp_synch: u8,
+    /// Sample rate.
+    pub smp_rate: Option<u16>,
+    /// Raw sample data.
+    pub samples: &'a [u8],
+}
+
+impl<'a> SvAsdu<'a> {
+    /// Decode an ASDU from its BER encoding, tag included.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let (tag, value, _) = read_tlv(data)?;
+        if tag != TAG_ASDU {
+            return None;
+        }
+
+        let str = |value| core::str::from_utf8(value).ok();
+        let mut asdu = SvAsdu::default();
+        for (tag, value) in tlvs(value) {
+            match tag {
+                0x80 => asdu.sv_id = str(value)?,
+                0x81 => asdu.dat_set = Some(str(value)?),
+                0x82 => asdu.smp_cnt = u16::from_be_bytes(value.try_into().ok()?),
+                0x83 => asdu.conf_rev = u32::from_be_bytes(value.try_into().ok()?),
+                0x85 => asdu.smp_synch = *value.first()?,
+                0x86 => asdu.smp_rate = Some(u16::from_be_bytes(value.try_into().ok()?)),
+                0x87 => asdu.samples = value,
+                _ => {}
+            }
+        }
+        Some(asdu)
+    }
+
+    /// Encode the ASDU in BER, tag included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut asdu = Vec::new();
+        write_tlv(&mut asdu, 0x80, self.sv_id.as_bytes());
+        if let Some(dat_set) = self.dat_set {
+            write_tlv(&mut asdu, 0x81, dat_set.as_bytes());
+        }
+        write_tlv(&mut asdu, 0x82, &self.smp_cnt.to_be_bytes());
+        write_tlv(&mut asdu, 0x83, &self.conf_rev.to_be_bytes());
+        write_tlv(&mut asdu, 0x85, &[self.smp_synch]);
+        if let Some(smp_rate) = self.smp_rate {
+            write_tlv(&mut asdu, 0x86, &smp_rate.to_be_bytes());
+        }
+        write_tlv(&mut asdu, 0x87, self.samples);
+
+        let mut out = Vec::with_capacity(asdu.len() + 4);
+        write_tlv(&mut out, TAG_ASDU, &asdu);
+        out
+    }
+}
+
+/// IEC 61850 Sampled Values (SV) layer.
+///
+/// The header is the same as the one of [`Goose`](super::Goose); the APDU
+/// holds one or more [`SvAsdu`]s.
+pub struct Sv<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Sv<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the APPID: 0..2
+    pub const FIELD_APPID: core::ops::Range<usize> = 0..2;
+    /// Field range of the length: 2..4
+    pub const FIELD_LENGTH: core::ops::Range<usize> = 2..4;
+    /// Field range of the first reserved field: 4..6
+    pub const FIELD_RESERVED1: core::ops::Range<usize> = 4..6;
+    /// Field range of the second reserved field: 6..8
+    pub const FIELD_RESERVED2: core::ops::Range<usize> = 6..8;
+
+    /// Create a new Sv layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Sv frame.
+    ///
+    /// The data must be at least as long as the length field. Otherwise,
+    /// the following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Sv layer.
+    ///
+    /// Trailing bytes after the length, e.g. Ethernet padding, are allowed.
+    pub fn validate(&self) -> Result<(), SvError> {
+        let len = self.data.as_ref().len();
+        if len < HEADER_LENGTH {
+            return Err(LayerError::min_length("Sv", "header", 0, HEADER_LENGTH, len).into());
+        }
+
+        let length = self.length().get() as usize;
+        if length < HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Sv",
+                "length",
+                Self::FIELD_LENGTH.start,
+                HEADER_LENGTH,
+                length,
+            )
+            .into());
+        }
+        if len < length {
+            return Err(LayerError::min_length("Sv", "apdu", HEADER_LENGTH, length, len).into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Sv layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, SvError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the APPID.
+    #[inline]
+    pub fn appid(&self) -> &Field<AppIdSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_APPID])
+    }
+
+    /// Get the accessor of the length, header included.
+    #[inline]
+    pub fn length(&self) -> &Field<LengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_LENGTH])
+    }
+
+    /// Get the accessor of the first reserved field.
+    #[inline]
+    pub fn reserved1(&self) -> &Field<ReservedSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_RESERVED1])
+    }
+
+    /// Get the accessor of the second reserved field.
+    #[inline]
+    pub fn reserved2(&self) -> &Field<ReservedSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_RESERVED2])
+    }
+
+    /// Get the BER-encoded APDU, excluding any trailing padding.
+    #[inline]
+    pub fn apdu(&self) -> &[u8] {
+        &self.data.as_ref()[HEADER_LENGTH..self.length().get() as usize]
+    }
+
+    fn sav_pdu(&self) -> Option<&[u8]> {
+        let (tag, value, _) = read_tlv(self.apdu())?;
+        (tag == TAG_SAV_PDU).then_some(value)
+    }
+
+    /// Get the number of ASDUs announced by the PDU.
+    pub fn no_asdu(&self) -> Option<u32> {
+        tlvs(self.sav_pdu()?)
+            .find(|(tag, _)| *tag == 0x80)
+            .and_then(|(_, value)| ber_uint(value))
+            .map(|value| value as u32)
+    }
+
+    /// Iterate the decoded ASDUs.
+    ///
+    /// Iteration stops at the first malformed ASDU.
+    pub fn asdus(&self) -> impl Iterator<Item = SvAsdu<'_>> {
+        let seq = self
+            .sav_pdu()
+            .and_then(|pdu| tlvs(pdu).find(|(tag, _)| *tag == TAG_SEQ_ASDU))
+            .map_or(&[][..], |(_, value)| value);
+
+        let mut rest = seq;
+        core::iter::from_fn(move || {
+            let (_, _, next) = read_tlv(rest)?;
+            let asdu = SvAsdu::parse(&rest[..rest.len() - next.len()])?;
+            rest = next;
+            Some(asdu)
+        })
+    }
+}
+
+impl<T> Sv<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the APPID.
+    #[inline]
+    pub fn appid_mut(&mut self) -> &mut Field<AppIdSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_APPID])
+    }
+
+    /// Get the mutable accessor of the length.
+    #[inline]
+    pub fn length_mut(&mut self) -> &mut Field<LengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_LENGTH])
+    }
+
+    /// Get the mutable accessor of the first reserved field.
+    #[inline]
+    pub fn reserved1_mut(&mut self) -> &mut Field<ReservedSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_RESERVED1])
+    }
+
+    /// Get the mutable accessor of the second reserved field.
+    #[inline]
+    pub fn reserved2_mut(&mut self) -> &mut Field<ReservedSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_RESERVED2])
+    }
+
+    /// Get the mutable APDU.
+    #[inline]
+    pub fn apdu_mut(&mut self) -> &mut [u8] {
+        let end = self.length().get() as usize;
+        &mut self.data.as_mut()[HEADER_LENGTH..end]
+    }
+}
+
+layer_impl!(Sv);
+
+/// Builder for [`Sv`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct SvBuilder {
+    appid: Option<u16>,
+    reserved1: Option<u16>,
+    asdus: Vec<Vec<u8>>,
+}
+
+impl SvBuilder {
+    /// Create a new Sv builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the APPID.
+    pub fn appid(&mut self, appid: impl Into<u16>) -> &mut Self {
+        self.appid = Some(appid.into());
+        self
+    }
+
+    /// Set the first reserved field.
+    pub fn reserved1(&mut self, reserved1: impl Into<u16>) -> &mut Self {
+        self.reserved1 = Some(reserved1.into());
+        self
+    }
+
+    /// Append an ASDU.
+    pub fn asdu(&mut self, asdu: SvAsdu<'_>) -> &mut Self {
+        self.asdus.push(asdu.to_bytes());
+        self
+    }
+
+    /// Build the Sv layer.
+    ///
+    /// Unless set explicitly, the APPID is 0x4000.
+    pub fn build(&self) -> Sv<Vec<u8>> {
+        let mut pdu = Vec::new();
+        write_tlv(&mut pdu, 0x80, &ber_uint_bytes(self.asdus.len() as u64));
+        write_tlv(&mut pdu, TAG_SEQ_ASDU, &self.asdus.concat());
+
+        let mut data = vec![0; HEADER_LENGTH];
+        write_tlv(&mut data, TAG_SAV_PDU, &pdu);
+
+        let mut sv = unsafe { Sv::new_unchecked(data) };
+        let length = sv.inner().len() as u16;
+        sv.appid_mut().set(self.appid.unwrap_or(0x4000));
+        sv.length_mut().set(length);
+        sv.reserved1_mut().set(self.reserved1.unwrap_or_default());
+        sv
+    }
+}
+
+/// Create a Sv layer with the given fields.
+#[macro_export]
+macro_rules! sv {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::industrial::SvBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sv_asdus() {
+        let asdu = |smp_cnt| SvAsdu {
+            sv_id: "MU01",
+            smp_cnt,
+            conf_rev: 1,
+            smp_synch: 2,
+            samples: &[0; 64],
+            ..Default::default()
+        };
+        let sv = sv!(appid: 0x4001u16, asdu: asdu(3998), asdu: asdu(3999));
+        let sv = Sv::new(sv.inner().as_slice()).unwrap();
+
+        assert_eq!(sv.appid().get(), 0x4001);
+        assert_eq!(sv.no_asdu(), Some(2));
+        let asdus: Vec<_> = sv.asdus().collect();
+        assert_eq!(asdus, vec![asdu(3998), asdu(3999)]);
+        assert_eq!(asdus[1].samples.len(), 64);
+    }
+}
@@ -5,3 +5,6 @@ pub use protocol::IpProtocol;
 
 pub mod v4;
 pub use v4::*;
+
+pub mod v6;
+pub use v6::{Ipv6, Ipv6Builder, Ipv6Error};
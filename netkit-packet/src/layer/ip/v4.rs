@@ -3,14 +3,18 @@
 use core::net::Ipv4Addr;
 
 use super::IpProtocol;
-use crate::{field_spec, impl_target, prelude::*};
+use crate::{field_spec, impl_target, layer::assemble::Assemble, prelude::*};
+
+pub mod options;
+pub use options::*;
 
 /// Error type for Ipv4.
 #[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Ipv4Error {
     /// Invalid Ipv4 length.
-    #[error("Invalid Ipv4 length: Length {0} is less than minimum 20")]
-    InvalidLength(usize),
+    #[error("Invalid Ipv4 length: {0}")]
+    InvalidLength(#[from] LayerError),
 }
 
 impl_target!(frominto, core::net::Ipv4Addr, u32);
@@ -28,6 +32,12 @@ field_spec!(ProtocolSpec, IpProtocol, u8, 0xFF);
 field_spec!(ChecksumSpec, u16, u16);
 field_spec!(Ipv4AddrSpec, core::net::Ipv4Addr, u32);
 
+/// Don't fragment (DF) bit of the flags.
+pub const FLAG_DONT_FRAGMENT: u8 = 0b010;
+
+/// More fragments (MF) bit of the flags.
+pub const FLAG_MORE_FRAGMENTS: u8 = 0b001;
+
 /// Ipv4 layer.
 pub struct Ipv4<T>
 where
@@ -88,10 +98,35 @@ where
     pub fn validate(&self) -> Result<(), Ipv4Error> {
         let data = self.data.as_ref();
         if data.len() < Self::MIN_HEADER_LENGTH {
-            return Err(Ipv4Error::InvalidLength(data.len()));
+            return Err(LayerError::min_length(
+                "Ipv4",
+                "header",
+                0,
+                Self::MIN_HEADER_LENGTH,
+                data.len(),
+            )
+            .into());
+        }
+
+        let offset = Self::FIELD_IHL.start;
+        let header_len = self.ihl().get() as usize * 4;
+        if header_len < Self::MIN_HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Ipv4",
+                "ihl",
+                offset,
+                Self::MIN_HEADER_LENGTH,
+                header_len,
+            )
+            .into());
+        }
+        if header_len > data.len() {
+            return Err(
+                LayerError::max_value("Ipv4", "ihl", offset, data.len(), header_len).into(),
+            );
         }
 
-        // TODO: validate ihl, checksum, etc.
+        // TODO: validate checksum, etc.
 
         Ok(())
     }
@@ -191,7 +226,20 @@ where
     /// Get the options.
     #[inline]
     pub fn options(&self) -> &[u8] {
-        &self.data.as_ref()[Self::MIN_HEADER_LENGTH..(self.ihl().get() - 5) as usize * 4]
+        &self.data.as_ref()[Self::MIN_HEADER_LENGTH..self.ihl().get() as usize * 4]
+    }
+
+    /// Iterate over the parsed options.
+    pub fn option_iter(&self) -> Ipv4Options<'_> {
+        Ipv4Options::new(self.options())
+    }
+
+    /// Get the value of the Router Alert option, if present.
+    pub fn router_alert(&self) -> Option<u16> {
+        self.option_iter().find_map(|option| match option {
+            Ipv4Option::RouterAlert(value) => Some(value),
+            _ => None,
+        })
     }
 
     /// Get the payload.
@@ -217,6 +265,92 @@ where
             None
         }
     }
+
+    /// Get the UDP-Lite layer if the protocol is UDP-Lite.
+    pub fn udplite(&self) -> Option<UdpLite<&[u8]>> {
+        if self.protocol().get() == IpProtocol::UdpLite {
+            UdpLite::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the DCCP layer if the protocol is DCCP.
+    pub fn dccp(&self) -> Option<Dccp<&[u8]>> {
+        if self.protocol().get() == IpProtocol::Dccp {
+            Dccp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the ICMP layer if the protocol is ICMP.
+    pub fn icmp(&self) -> Option<Icmp<&[u8]>> {
+        if self.protocol().get() == IpProtocol::Icmp {
+            Icmp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the GRE layer if the protocol is GRE.
+    pub fn gre(&self) -> Option<Gre<&[u8]>> {
+        if self.protocol().get() == IpProtocol::Gre {
+            Gre::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the ESP layer if the protocol is ESP.
+    pub fn esp(&self) -> Option<Esp<&[u8]>> {
+        if self.protocol().get() == IpProtocol::Esp {
+            Esp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the AH layer if the protocol is AH.
+    pub fn ah(&self) -> Option<Ah<&[u8]>> {
+        if self.protocol().get() == IpProtocol::Ah {
+            Ah::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Compute the checksum of the carried TCP or UDP segment.
+    ///
+    /// The checksum covers the pseudo header and the segment up to the total
+    /// length, ignoring the current value of the segment's checksum field.
+    /// Returns `None` for other protocols or truncated segments.
+    pub fn transport_checksum(&self) -> Option<u16> {
+        let protocol = self.protocol().get();
+        let offset = transport_checksum_offset(protocol)?;
+
+        let data = self.data.as_ref();
+        let segment =
+            data.get(self.ihl().get() as usize * 4..self.total_length().get() as usize)?;
+        if segment.len() < offset + 2 {
+            return None;
+        }
+
+        let checksum = Checksum::new()
+            .add_bytes(&data[Self::FIELD_SRC.start..Self::FIELD_DST.end])
+            .add_u16(u8::from(protocol) as u16)
+            .add_u16(segment.len() as u16)
+            .add_bytes(&segment[..offset])
+            .add_bytes(&segment[offset + 2..])
+            .finish();
+
+        // A computed UDP checksum of zero is transmitted as all ones.
+        if protocol == IpProtocol::Udp && checksum == 0 {
+            Some(0xFFFF)
+        } else {
+            Some(checksum)
+        }
+    }
 }
 
 impl<T> Ipv4<T>
@@ -320,12 +454,44 @@ where
         let range = self.ihl().get() as usize * 4..;
         &mut self.data.as_mut()[range]
     }
+
+    /// Recompute the checksum of the carried TCP or UDP segment.
+    ///
+    /// UDP datagrams without checksum (zero) are left unchanged. Returns
+    /// whether the checksum was updated.
+    pub fn update_transport_checksum(&mut self) -> bool {
+        let Some(checksum) = self.transport_checksum() else {
+            return false;
+        };
+
+        let protocol = self.protocol().get();
+        let Some(offset) = transport_checksum_offset(protocol) else {
+            return false;
+        };
+        let field = &mut self.payload_mut()[offset..offset + 2];
+        if protocol == IpProtocol::Udp && field == [0, 0] {
+            return false;
+        }
+
+        field.copy_from_slice(&checksum.to_be_bytes());
+        true
+    }
 }
 
 layer_impl!(Ipv4);
 
+/// Get the offset of the checksum field in a TCP or UDP header.
+fn transport_checksum_offset(protocol: IpProtocol) -> Option<usize> {
+    match protocol {
+        IpProtocol::Tcp => Some(16),
+        IpProtocol::Udp => Some(6),
+        _ => None,
+    }
+}
+
 /// Builder for [`Ipv4`].
 #[derive(Clone, Debug, Default)]
+#[must_use]
 pub struct Ipv4Builder {
     ihl: Option<u8>,
     dscp: Option<u8>,
@@ -341,6 +507,7 @@ pub struct Ipv4Builder {
     dst: Option<Ipv4Addr>,
     options: Vec<u8>,
     payload: Vec<u8>,
+    profile: Option<Profile>,
 }
 
 impl Ipv4Builder {
@@ -349,6 +516,17 @@ impl Ipv4Builder {
         Self::default()
     }
 
+    /// Create a new Ipv4 builder using the defaults of a profile.
+    ///
+    /// The profile decides the ttl, the flags (e.g. DF) and how the
+    /// identification is chosen unless they are set explicitly.
+    pub fn with_defaults(profile: Profile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Self::default()
+        }
+    }
+
     /// Set the ihl.
     pub fn ihl(&mut self, ihl: impl Into<u8>) -> &mut Self {
         self.ihl = Some(ihl.into());
@@ -435,30 +613,57 @@ impl Ipv4Builder {
 
     /// Build the Ipv4 layer.
     pub fn build(&self) -> Ipv4<Vec<u8>> {
-        // Calculate the ihl
-        // 1. if ihl is set, use it
-        // 2. if ihl is not set, calculate it from the options
-        // 3. if options is not set, use the minimum header length
-        let ihl = self.ihl.unwrap_or(self.options.len() as u8 / 4 + 5);
+        let header_len = self.header_len();
 
         // Calculate the total length
         let length = self
             .total_length
-            .unwrap_or(ihl as u16 * 4 + self.payload.len() as u16);
+            .map_or(header_len + self.payload.len(), usize::from);
+
+        let mut data = vec![0; length];
+        data[header_len..].copy_from_slice(self.payload.as_ref());
+        self.write_header(&mut data);
+
+        unsafe { Ipv4::new_unchecked(data) }
+    }
+
+    /// Get the ihl.
+    ///
+    /// 1. if ihl is set, use it
+    /// 2. if ihl is not set, calculate it from the options
+    /// 3. if options is not set, use the minimum header length
+    fn ihl_or_default(&self) -> u8 {
+        self.ihl.unwrap_or(self.options.len() as u8 / 4 + 5)
+    }
+}
 
-        let mut ipv4 = unsafe { Ipv4::new_unchecked(vec![0; length as usize]) };
+impl Assemble for Ipv4Builder {
+    fn header_len(&self) -> usize {
+        self.ihl_or_default() as usize * 4
+    }
+
+    fn write_header(&self, segment: &mut [u8]) {
+        let ihl = self.ihl_or_default();
+        let length = self.total_length.unwrap_or(segment.len() as u16);
+        let mut ipv4 = unsafe { Ipv4::new_unchecked(segment) };
 
         ipv4.version_mut().set(4);
         ipv4.ihl_mut().set(ihl);
         ipv4.dscp_mut().set(self.dscp.unwrap_or(0));
         ipv4.ecn_mut().set(self.ecn.unwrap_or(0));
         ipv4.total_length_mut().set(length);
-        ipv4.identification_mut()
-            .set(self.identification.unwrap_or(0));
-        ipv4.flags_mut().set(self.flags.unwrap_or(0));
+        ipv4.identification_mut().set(
+            self.identification
+                .unwrap_or_else(|| self.profile.map_or(0, |p| p.id_policy().next())),
+        );
+        ipv4.flags_mut().set(
+            self.flags
+                .unwrap_or(self.profile.map_or(0, |p| p.ipv4_flags())),
+        );
         ipv4.fragment_offset_mut()
             .set(self.fragment_offset.unwrap_or(0));
-        ipv4.ttl_mut().set(self.ttl.unwrap_or(64));
+        ipv4.ttl_mut()
+            .set(self.ttl.unwrap_or(self.profile.map_or(64, |p| p.ttl())));
         ipv4.protocol_mut()
             .set(self.protocol.unwrap_or(IpProtocol::Reserved(255)));
         ipv4.checksum_mut().set(self.checksum.unwrap_or(0));
@@ -467,9 +672,6 @@ impl Ipv4Builder {
         ipv4.dst_mut()
             .set(self.dst.unwrap_or(Ipv4Addr::UNSPECIFIED));
         ipv4.options_mut().copy_from_slice(self.options.as_ref());
-        ipv4.payload_mut().copy_from_slice(self.payload.as_ref());
-
-        ipv4
     }
 }
 
@@ -505,6 +707,7 @@ macro_rules! ipv4 {
 
 #[cfg(test)]
 mod tests {
+    use super::Ipv4Builder;
     use crate::prelude::*;
     use core::net::Ipv4Addr;
 
@@ -548,6 +751,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ipv4_validate_ihl() {
+        let mut data = [0u8; 24];
+
+        data[0] = 0x44; // ihl 4
+        let Err(Ipv4Error::InvalidLength(err)) = Ipv4::new(&data[..]) else {
+            panic!("ihl 4 should be rejected");
+        };
+        assert_eq!((err.field, err.offset), ("ihl", 0));
+        assert_eq!(
+            err.constraint,
+            Constraint::MinValue {
+                min: 20,
+                actual: 16
+            }
+        );
+
+        data[0] = 0x47; // ihl 7, beyond the 24 bytes
+        let Err(Ipv4Error::InvalidLength(err)) = Ipv4::new(&data[..]) else {
+            panic!("ihl 7 should be rejected");
+        };
+        assert_eq!(
+            err.constraint,
+            Constraint::MaxValue {
+                max: 24,
+                actual: 28
+            }
+        );
+
+        data[0] = 0x46; // ihl 6
+        let ipv4 = Ipv4::new(&data[..]).unwrap();
+        assert_eq!(ipv4.options(), &[0; 4]);
+        assert!(ipv4.payload().is_empty());
+    }
+
     #[test]
     fn ipv4_macro() {
         let ipv4 = ipv4!(
@@ -565,4 +803,43 @@ mod tests {
         assert_eq!(ipv4.protocol().get(), IpProtocol::Udp);
         assert_eq!(ipv4.payload(), &[1, 2, 3, 4]);
     }
+
+    #[test]
+    fn ipv4_profile() {
+        let ipv4 = Ipv4Builder::with_defaults(Profile::WindowsHost).build();
+        assert_eq!(ipv4.ttl().get(), 128);
+        assert_eq!(ipv4.flags().get(), 0b010);
+
+        let ipv4 = Ipv4Builder::with_defaults(Profile::CiscoRouter)
+            .ttl(1u8)
+            .identification(7u16)
+            .build();
+        assert_eq!(ipv4.ttl().get(), 1);
+        assert_eq!(ipv4.flags().get(), 0);
+        assert_eq!(ipv4.identification().get(), 7);
+    }
+
+    #[test]
+    fn ipv4_transport_checksum() {
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, checksum: 1u16, payload: [1, 2, 3]);
+        let mut ipv4 = ipv4!(
+            src: Ipv4Addr::new(10, 0, 1, 2),
+            dst: Ipv4Addr::new(10, 0, 1, 3),
+            protocol: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+
+        assert!(ipv4.update_transport_checksum());
+        let mut pseudo = Checksum::new();
+        pseudo
+            .add_bytes(&ipv4.inner()[12..20])
+            .add_u16(17)
+            .add_u16(11)
+            .add_bytes(ipv4.payload());
+        assert_eq!(pseudo.finish(), 0);
+
+        ipv4.payload_mut()[6..8].copy_from_slice(&[0, 0]);
+        assert!(!ipv4.update_transport_checksum());
+        assert_eq!(ipv4.udp().unwrap().checksum().get(), 0);
+    }
 }
@@ -0,0 +1,77 @@
+//! Ipv4 options.
+
+/// Option type End of Option List.
+pub const OPTION_EOL: u8 = 0;
+/// Option type No-Operation.
+pub const OPTION_NOP: u8 = 1;
+/// Option type Router Alert ([RFC 2113]).
+///
+/// [RFC 2113]: https://datatracker.ietf.org/doc/html/rfc2113
+pub const OPTION_ROUTER_ALERT: u8 = 148;
+
+/// A parsed Ipv4 option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ipv4Option<'a> {
+    /// End of option list.
+    Eol,
+    /// No-operation padding.
+    Nop,
+    /// Router alert, with its value (0: examine packet).
+    RouterAlert(u16),
+    /// Any other option.
+    Unknown {
+        /// Option type.
+        kind: u8,
+        /// Option data, without type and length.
+        data: &'a [u8],
+    },
+}
+
+/// Iterator over Ipv4 options.
+///
+/// Stops at the end of option list or at the first malformed option.
+#[derive(Clone, Debug)]
+pub struct Ipv4Options<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Ipv4Options<'a> {
+    /// Iterate over the options in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Ipv4Options<'a> {
+    type Item = Ipv4Option<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = *self.data.first()?;
+        match kind {
+            OPTION_EOL => {
+                self.data = &[];
+                return Some(Ipv4Option::Eol);
+            }
+            OPTION_NOP => {
+                self.data = &self.data[1..];
+                return Some(Ipv4Option::Nop);
+            }
+            _ => {}
+        }
+
+        let len = self.data.get(1).map_or(0, |len| *len as usize);
+        let Some(data) = self.data.get(2..len) else {
+            self.data = &[];
+            return None;
+        };
+        self.data = &self.data[len..];
+
+        let option = match (kind, data.len()) {
+            (OPTION_ROUTER_ALERT, 2) => {
+                Ipv4Option::RouterAlert(u16::from_be_bytes([data[0], data[1]]))
+            }
+            _ => Ipv4Option::Unknown { kind, data },
+        };
+        Some(option)
+    }
+}
@@ -0,0 +1,511 @@
+//! Ipv6 layer.
+
+use core::net::Ipv6Addr;
+
+use super::IpProtocol;
+use crate::{field_spec, impl_target, layer::assemble::Assemble, prelude::*};
+
+/// Error type for Ipv6.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Ipv6Error {
+    /// Invalid Ipv6 length.
+    #[error("Invalid Ipv6 length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+impl_target!(frominto, core::net::Ipv6Addr, u128);
+
+field_spec!(VersionSpec, u8, u8, 0xF0, 4);
+field_spec!(TrafficClassSpec, u8, u16, 0x0FF0, 4);
+field_spec!(FlowLabelSpec, u32, u32, 0x000F_FFFF);
+field_spec!(PayloadLengthSpec, u16, u16);
+field_spec!(NextHeaderSpec, IpProtocol, u8);
+field_spec!(HopLimitSpec, u8, u8);
+field_spec!(Ipv6AddrSpec, core::net::Ipv6Addr, u128);
+
+/// Ipv6 layer.
+///
+/// Only the fixed header is described by fields. The upper-layer protocol
+/// behind the extension headers is reached with
+/// [`upper_layer`](Self::upper_layer).
+pub struct Ipv6<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Ipv6<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the version: 0..1 (4bits)
+    pub const FIELD_VERSION: core::ops::Range<usize> = 0..1;
+    /// Field range of the traffic class: 0..2 (8bits)
+    pub const FIELD_TRAFFIC_CLASS: core::ops::Range<usize> = 0..2;
+    /// Field range of the flow label: 0..4 (20bits)
+    pub const FIELD_FLOW_LABEL: core::ops::Range<usize> = 0..4;
+    /// Field range of the payload length: 4..6
+    pub const FIELD_PAYLOAD_LENGTH: core::ops::Range<usize> = 4..6;
+    /// Field range of the next header: 6..7
+    pub const FIELD_NEXT_HEADER: core::ops::Range<usize> = 6..7;
+    /// Field range of the hop limit: 7..8
+    pub const FIELD_HOP_LIMIT: core::ops::Range<usize> = 7..8;
+    /// Field range of the src: 8..24
+    pub const FIELD_SRC: core::ops::Range<usize> = 8..24;
+    /// Field range of the dst: 24..40
+    pub const FIELD_DST: core::ops::Range<usize> = 24..40;
+    /// Field range of the payload: 40..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 40..;
+
+    /// Header length.
+    pub const HEADER_LENGTH: usize = 40;
+
+    /// Create a new Ipv6 layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is at least 40 bytes long.
+    /// Otherwise, the following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Ipv6 layer.
+    pub fn validate(&self) -> Result<(), Ipv6Error> {
+        let data = self.data.as_ref();
+        if data.len() < Self::HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "Ipv6",
+                "header",
+                0,
+                Self::HEADER_LENGTH,
+                data.len(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Ipv6 layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, Ipv6Error> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the version.
+    #[inline]
+    pub fn version(&self) -> &Field<VersionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION])
+    }
+
+    /// Get the accessor of the traffic class.
+    #[inline]
+    pub fn traffic_class(&self) -> &Field<TrafficClassSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_TRAFFIC_CLASS])
+    }
+
+    /// Get the accessor of the flow label.
+    #[inline]
+    pub fn flow_label(&self) -> &Field<FlowLabelSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLOW_LABEL])
+    }
+
+    /// Get the accessor of the payload length.
+    #[inline]
+    pub fn payload_length(&self) -> &Field<PayloadLengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PAYLOAD_LENGTH])
+    }
+
+    /// Get the accessor of the next header.
+    #[inline]
+    pub fn next_header(&self) -> &Field<NextHeaderSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_NEXT_HEADER])
+    }
+
+    /// Get the accessor of the hop limit.
+    #[inline]
+    pub fn hop_limit(&self) -> &Field<HopLimitSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_HOP_LIMIT])
+    }
+
+    /// Get the accessor of the src.
+    #[inline]
+    pub fn src(&self) -> &Field<Ipv6AddrSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SRC])
+    }
+
+    /// Get the accessor of the dst.
+    #[inline]
+    pub fn dst(&self) -> &Field<Ipv6AddrSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DST])
+    }
+
+    /// Get the payload, including the extension headers.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Get the upper-layer protocol and its data, skipping the extension
+    /// headers.
+    ///
+    /// Hop-by-hop options, routing, destination options and fragment headers
+    /// are skipped. Returns `None` if they are truncated, or for a fragment
+    /// other than the first, whose data does not start with the upper-layer
+    /// header.
+    pub fn upper_layer(&self) -> Option<(IpProtocol, &[u8])> {
+        let mut next = self.next_header().get();
+        let mut data = self.payload();
+        loop {
+            let len = match next {
+                IpProtocol::Hopopt | IpProtocol::Ipv6Route | IpProtocol::Ipv6Opts => {
+                    (*data.get(1)? as usize + 1) * 8
+                }
+                IpProtocol::Ipv6Frag => {
+                    let offset = u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) >> 3;
+                    if offset != 0 {
+                        return None;
+                    }
+                    8
+                }
+                _ => return Some((next, data)),
+            };
+            next = IpProtocol::from(*data.first()?);
+            data = data.get(len..)?;
+        }
+    }
+
+    /// Get the TCP layer if the upper-layer protocol is TCP.
+    pub fn tcp(&self) -> Option<Tcp<&[u8]>> {
+        match self.upper_layer()? {
+            (IpProtocol::Tcp, data) => Tcp::new(data).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the UDP layer if the upper-layer protocol is UDP.
+    pub fn udp(&self) -> Option<Udp<&[u8]>> {
+        match self.upper_layer()? {
+            (IpProtocol::Udp, data) => Udp::new(data).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the UDP-Lite layer if the upper-layer protocol is UDP-Lite.
+    pub fn udplite(&self) -> Option<UdpLite<&[u8]>> {
+        match self.upper_layer()? {
+            (IpProtocol::UdpLite, data) => UdpLite::new(data).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the DCCP layer if the upper-layer protocol is DCCP.
+    pub fn dccp(&self) -> Option<Dccp<&[u8]>> {
+        match self.upper_layer()? {
+            (IpProtocol::Dccp, data) => Dccp::new(data).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Ipv6<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the version.
+    #[inline]
+    pub fn version_mut(&mut self) -> &mut Field<VersionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION])
+    }
+
+    /// Get the mutable accessor of the traffic class.
+    #[inline]
+    pub fn traffic_class_mut(&mut self) -> &mut Field<TrafficClassSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_TRAFFIC_CLASS])
+    }
+
+    /// Get the mutable accessor of the flow label.
+    #[inline]
+    pub fn flow_label_mut(&mut self) -> &mut Field<FlowLabelSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLOW_LABEL])
+    }
+
+    /// Get the mutable accessor of the payload length.
+    #[inline]
+    pub fn payload_length_mut(&mut self) -> &mut Field<PayloadLengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PAYLOAD_LENGTH])
+    }
+
+    /// Get the mutable accessor of the next header.
+    #[inline]
+    pub fn next_header_mut(&mut self) -> &mut Field<NextHeaderSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_NEXT_HEADER])
+    }
+
+    /// Get the mutable accessor of the hop limit.
+    #[inline]
+    pub fn hop_limit_mut(&mut self) -> &mut Field<HopLimitSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_HOP_LIMIT])
+    }
+
+    /// Get the mutable accessor of the src.
+    #[inline]
+    pub fn src_mut(&mut self) -> &mut Field<Ipv6AddrSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SRC])
+    }
+
+    /// Get the mutable accessor of the dst.
+    #[inline]
+    pub fn dst_mut(&mut self) -> &mut Field<Ipv6AddrSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DST])
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(Ipv6);
+
+/// Builder for [`Ipv6`].
+///
+/// UDP over IPv6 must carry a checksum, so a UDP payload with a zero
+/// checksum has it computed over the pseudo header when the header is
+/// written.
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct Ipv6Builder {
+    traffic_class: Option<u8>,
+    flow_label: Option<u32>,
+    payload_length: Option<u16>,
+    next_header: Option<IpProtocol>,
+    hop_limit: Option<u8>,
+    src: Option<Ipv6Addr>,
+    dst: Option<Ipv6Addr>,
+    payload: Vec<u8>,
+}
+
+impl Ipv6Builder {
+    /// Create a new Ipv6 builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the traffic class.
+    pub fn traffic_class(&mut self, traffic_class: impl Into<u8>) -> &mut Self {
+        self.traffic_class = Some(traffic_class.into());
+        self
+    }
+
+    /// Set the flow label.
+    pub fn flow_label(&mut self, flow_label: impl Into<u32>) -> &mut Self {
+        self.flow_label = Some(flow_label.into());
+        self
+    }
+
+    /// Set the payload length.
+    pub fn payload_length(&mut self, payload_length: impl Into<u16>) -> &mut Self {
+        self.payload_length = Some(payload_length.into());
+        self
+    }
+
+    /// Set the next header.
+    pub fn next_header(&mut self, next_header: impl Into<IpProtocol>) -> &mut Self {
+        self.next_header = Some(next_header.into());
+        self
+    }
+
+    /// Set the hop limit.
+    pub fn hop_limit(&mut self, hop_limit: impl Into<u8>) -> &mut Self {
+        self.hop_limit = Some(hop_limit.into());
+        self
+    }
+
+    /// Set the src.
+    pub fn src(&mut self, src: impl Into<Ipv6Addr>) -> &mut Self {
+        self.src = Some(src.into());
+        self
+    }
+
+    /// Set the dst.
+    pub fn dst(&mut self, dst: impl Into<Ipv6Addr>) -> &mut Self {
+        self.dst = Some(dst.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Ipv6 layer.
+    pub fn build(&self) -> Ipv6<Vec<u8>> {
+        let mut data = vec![0; self.header_len()];
+        data.extend_from_slice(&self.payload);
+        self.write_header(&mut data);
+
+        unsafe { Ipv6::new_unchecked(data) }
+    }
+}
+
+impl Assemble for Ipv6Builder {
+    fn header_len(&self) -> usize {
+        Ipv6::<Vec<u8>>::HEADER_LENGTH
+    }
+
+    fn write_header(&self, segment: &mut [u8]) {
+        let payload_length = (segment.len() - self.header_len()) as u16;
+        let mut ipv6 = unsafe { Ipv6::new_unchecked(segment) };
+
+        ipv6.version_mut().set(6);
+        ipv6.traffic_class_mut()
+            .set(self.traffic_class.unwrap_or(0));
+        ipv6.flow_label_mut().set(self.flow_label.unwrap_or(0));
+        ipv6.payload_length_mut()
+            .set(self.payload_length.unwrap_or(payload_length));
+        ipv6.next_header_mut()
+            .set(self.next_header.unwrap_or(IpProtocol::Ipv6NoNxt));
+        ipv6.hop_limit_mut().set(self.hop_limit.unwrap_or(64));
+        ipv6.src_mut()
+            .set(self.src.unwrap_or(Ipv6Addr::UNSPECIFIED));
+        ipv6.dst_mut()
+            .set(self.dst.unwrap_or(Ipv6Addr::UNSPECIFIED));
+
+        let start = match ipv6.upper_layer() {
+            Some((IpProtocol::Udp, data)) if data.len() >= 8 => ipv6.inner().len() - data.len(),
+            _ => return,
+        };
+        let (src, dst) = (ipv6.src().get().into(), ipv6.dst().get().into());
+        let mut udp = unsafe { Udp::new_unchecked(&mut ipv6.inner_mut()[start..]) };
+        if udp.checksum().get() == 0 {
+            let checksum = udp.compute_checksum(src, dst);
+            udp.checksum_mut().set(checksum);
+        }
+    }
+}
+
+/// Create an Ipv6 layer with the given fields.
+///
+/// # Example
+///
+/// ```
+/// # use netkit_packet::prelude::*;
+/// # use std::net::Ipv6Addr;
+/// let ipv6 = ipv6!(
+///     src: Ipv6Addr::LOCALHOST,
+///     dst: "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+///     next_header: IpProtocol::Udp,
+///     payload: [1, 2, 3, 4],
+/// );
+///
+/// assert_eq!(ipv6.version().get(), 6);
+/// assert_eq!(ipv6.payload_length().get(), 4);
+/// assert_eq!(ipv6.src().get(), Ipv6Addr::LOCALHOST);
+/// assert_eq!(ipv6.next_header().get(), IpProtocol::Udp);
+/// assert_eq!(ipv6.payload(), &[1, 2, 3, 4]);
+/// ```
+#[macro_export]
+macro_rules! ipv6 {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::ip::v6::Ipv6Builder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use core::net::Ipv6Addr;
+
+    #[test]
+    fn ipv6_new_unchecked() {
+        let mut data = vec![
+            0x6A, 0xB1, 0x23, 0x45, // version 6, traffic class 0xAB, flow label 0x12345
+            0x00, 0x08, // payload length 8
+            0x11, // next header UDP
+            0x40, // hop limit 64
+        ];
+        data.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        data.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        data.extend_from_slice(&[0x04, 0xD2, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00]);
+
+        let ipv6 = Ipv6::new(data).unwrap();
+        assert_eq!(ipv6.version().get(), 6);
+        assert_eq!(ipv6.traffic_class().get(), 0xAB);
+        assert_eq!(ipv6.flow_label().get(), 0x12345);
+        assert_eq!(ipv6.payload_length().get(), 8);
+        assert_eq!(ipv6.next_header().get(), IpProtocol::Udp);
+        assert_eq!(ipv6.hop_limit().get(), 64);
+        assert_eq!(ipv6.src().get(), Ipv6Addr::LOCALHOST);
+        assert_eq!(ipv6.dst().get(), "2001:db8::1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(ipv6.udp().unwrap().dst_port().get(), 53);
+
+        assert!(Ipv6::new([0; 39]).is_err());
+    }
+
+    #[test]
+    fn ipv6_udp_checksum() {
+        let (src, dst) = (
+            Ipv6Addr::LOCALHOST,
+            "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+        );
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: b"hello");
+        assert_eq!(udp.checksum().get(), 0);
+
+        let ipv6 = ipv6!(src: src, dst: dst, next_header: IpProtocol::Udp, payload: udp.inner());
+        let udp = ipv6.udp().unwrap();
+        assert_ne!(udp.checksum().get(), 0);
+        assert_eq!(udp.verify_checksum(src.into(), dst.into()), Ok(()));
+
+        // Behind extension headers as well.
+        let mut payload = vec![17, 0, 1, 4, 0, 0, 0, 0];
+        payload.extend_from_slice(&udp!(dst_port: 53u16).inner()[..]);
+        let ipv6 = ipv6!(src: src, dst: dst, next_header: IpProtocol::Hopopt, payload: &payload);
+        let udp = ipv6.udp().unwrap();
+        assert_eq!(udp.verify_checksum(src.into(), dst.into()), Ok(()));
+
+        // An explicit checksum is kept.
+        let udp = udp!(dst_port: 53u16, checksum: 0x1234u16);
+        let ipv6 = ipv6!(src: src, dst: dst, next_header: IpProtocol::Udp, payload: udp.inner());
+        assert_eq!(ipv6.udp().unwrap().checksum().get(), 0x1234);
+    }
+
+    #[test]
+    fn ipv6_extension_headers() {
+        let tcp = tcp!(src_port: 40000u16, dst_port: 443u16);
+        // Hop-by-hop options (8 bytes) then a first fragment
+        let mut payload = vec![44, 0, 1, 4, 0, 0, 0, 0];
+        payload.extend_from_slice(&[6, 0, 0x00, 0x01, 0, 0, 0, 1]);
+        payload.extend_from_slice(tcp.inner());
+
+        let ipv6 = ipv6!(next_header: IpProtocol::Hopopt, payload: &payload);
+        assert_eq!(ipv6.tcp().unwrap().dst_port().get(), 443);
+
+        // Later fragments have no upper-layer header
+        payload[10] = 0x05;
+        let ipv6 = ipv6!(next_header: IpProtocol::Hopopt, payload: &payload);
+        assert!(ipv6.upper_layer().is_none());
+    }
+}
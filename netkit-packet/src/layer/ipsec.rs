@@ -0,0 +1,7 @@
+//! IP Security (IPsec) layers.
+
+pub mod ah;
+pub use ah::{Ah, AhBuilder, AhError};
+
+pub mod esp;
+pub use esp::{Esp, EspBuilder, EspDecryptor, EspError, EspPlaintext, EspSaTable};
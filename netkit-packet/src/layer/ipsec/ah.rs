@@ -0,0 +1,410 @@
+//! Authentication Header (AH) layer.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Ah layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AhError {
+    /// Invalid Ah length.
+    #[error("Invalid Ah length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(NextHeaderSpec, IpProtocol, u8);
+field_spec!(PayloadLenSpec, u8, u8);
+field_spec!(ReservedSpec, u16, u16);
+field_spec!(SpiSpec, u32, u32);
+field_spec!(SeqNumSpec, u32, u32);
+
+/// Minimum length of an Ah header.
+pub const MIN_HEADER_LENGTH: usize = 12;
+
+/// Authentication Header (AH) layer.
+///
+/// See [RFC 4302](https://datatracker.ietf.org/doc/html/rfc4302).
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |       Next Header     |     Payload Length    |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                                      Reserved |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                  Security Parameters Index (32) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                            Sequence Number (32) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                   Integrity Check Value (variable) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct Ah<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Ah<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the next header: 0..1
+    pub const FIELD_NEXT_HEADER: core::ops::Range<usize> = 0..1;
+    /// Field range of the payload length: 1..2
+    pub const FIELD_PAYLOAD_LEN: core::ops::Range<usize> = 1..2;
+    /// Field range of the reserved field: 2..4
+    pub const FIELD_RESERVED: core::ops::Range<usize> = 2..4;
+    /// Field range of the SPI: 4..8
+    pub const FIELD_SPI: core::ops::Range<usize> = 4..8;
+    /// Field range of the sequence number: 8..12
+    pub const FIELD_SEQ_NUM: core::ops::Range<usize> = 8..12;
+
+    /// Create a new Ah layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Ah packet.
+    ///
+    /// The data must be at least as long as the header indicated by the
+    /// payload length. Otherwise, the following methods may panic when
+    /// accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Ah layer.
+    pub fn validate(&self) -> Result<(), AhError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("Ah", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+
+        let header_len = self.header_len();
+        if header_len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Ah",
+                "payload_len",
+                Self::FIELD_PAYLOAD_LEN.start,
+                MIN_HEADER_LENGTH,
+                header_len,
+            )
+            .into());
+        }
+
+        if len < header_len {
+            return Err(
+                LayerError::min_length("Ah", "icv", MIN_HEADER_LENGTH, header_len, len).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Ah layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, AhError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the length of the header including the ICV.
+    ///
+    /// The payload length is in 4-octet units, minus 2.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        (self.payload_len().get() as usize + 2) * 4
+    }
+
+    /// Get the accessor of the next header.
+    #[inline]
+    pub fn next_header(&self) -> &Field<NextHeaderSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_NEXT_HEADER])
+    }
+
+    /// Get the accessor of the payload length.
+    #[inline]
+    pub fn payload_len(&self) -> &Field<PayloadLenSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PAYLOAD_LEN])
+    }
+
+    /// Get the accessor of the reserved field.
+    #[inline]
+    pub fn reserved(&self) -> &Field<ReservedSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_RESERVED])
+    }
+
+    /// Get the accessor of the SPI.
+    #[inline]
+    pub fn spi(&self) -> &Field<SpiSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SPI])
+    }
+
+    /// Get the accessor of the sequence number.
+    #[inline]
+    pub fn seq_num(&self) -> &Field<SeqNumSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SEQ_NUM])
+    }
+
+    /// Get the integrity check value.
+    #[inline]
+    pub fn icv(&self) -> &[u8] {
+        &self.data.as_ref()[MIN_HEADER_LENGTH..self.header_len()]
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+
+    /// Get the inner IPv4 layer in tunnel mode.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.next_header().get() == IpProtocol::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the TCP layer in transport mode.
+    pub fn tcp(&self) -> Option<Tcp<&[u8]>> {
+        if self.next_header().get() == IpProtocol::Tcp {
+            Tcp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the UDP layer in transport mode.
+    pub fn udp(&self) -> Option<Udp<&[u8]>> {
+        if self.next_header().get() == IpProtocol::Udp {
+            Udp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the ESP layer when AH and ESP are combined.
+    pub fn esp(&self) -> Option<Esp<&[u8]>> {
+        if self.next_header().get() == IpProtocol::Esp {
+            Esp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Ah<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the next header.
+    #[inline]
+    pub fn next_header_mut(&mut self) -> &mut Field<NextHeaderSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_NEXT_HEADER])
+    }
+
+    /// Get the mutable accessor of the payload length.
+    #[inline]
+    pub fn payload_len_mut(&mut self) -> &mut Field<PayloadLenSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PAYLOAD_LEN])
+    }
+
+    /// Get the mutable accessor of the reserved field.
+    #[inline]
+    pub fn reserved_mut(&mut self) -> &mut Field<ReservedSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_RESERVED])
+    }
+
+    /// Get the mutable accessor of the SPI.
+    #[inline]
+    pub fn spi_mut(&mut self) -> &mut Field<SpiSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SPI])
+    }
+
+    /// Get the mutable accessor of the sequence number.
+    #[inline]
+    pub fn seq_num_mut(&mut self) -> &mut Field<SeqNumSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SEQ_NUM])
+    }
+
+    /// Get the mutable integrity check value.
+    #[inline]
+    pub fn icv_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[MIN_HEADER_LENGTH..header_len]
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[header_len..]
+    }
+}
+
+layer_impl!(Ah);
+
+/// Builder for [`Ah`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct AhBuilder {
+    next_header: Option<IpProtocol>,
+    spi: Option<u32>,
+    seq_num: Option<u32>,
+    icv: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl AhBuilder {
+    /// Create a new Ah builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the next header.
+    pub fn next_header(&mut self, next_header: impl Into<IpProtocol>) -> &mut Self {
+        self.next_header = Some(next_header.into());
+        self
+    }
+
+    /// Set the SPI.
+    pub fn spi(&mut self, spi: impl Into<u32>) -> &mut Self {
+        self.spi = Some(spi.into());
+        self
+    }
+
+    /// Set the sequence number.
+    pub fn seq_num(&mut self, seq_num: impl Into<u32>) -> &mut Self {
+        self.seq_num = Some(seq_num.into());
+        self
+    }
+
+    /// Set the integrity check value.
+    ///
+    /// The ICV is zero padded to a multiple of 4 octets.
+    pub fn icv<T: AsRef<[u8]>>(&mut self, icv: T) -> &mut Self {
+        self.icv.extend_from_slice(icv.as_ref());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Ah layer.
+    pub fn build(&self) -> Ah<Vec<u8>> {
+        let header_len = MIN_HEADER_LENGTH + self.icv.len().next_multiple_of(4);
+
+        let mut ah = unsafe { Ah::new_unchecked(vec![0; header_len + self.payload.len()]) };
+
+        ah.next_header_mut()
+            .set(self.next_header.unwrap_or(IpProtocol::Tcp));
+        ah.payload_len_mut().set((header_len / 4 - 2) as u8);
+        ah.spi_mut().set(self.spi.unwrap_or_default());
+        ah.seq_num_mut().set(self.seq_num.unwrap_or(1));
+        ah.icv_mut()[..self.icv.len()].copy_from_slice(self.icv.as_ref());
+        ah.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        ah
+    }
+}
+
+/// Create an Ah layer with the given fields.
+#[macro_export]
+macro_rules! ah {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::ipsec::ah::AhBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ah_new() {
+        let data: [u8; 28] = [
+            0x06, 0x04, 0x00, 0x00, // next header, payload length, reserved
+            0x00, 0x00, 0x10, 0x01, // spi
+            0x00, 0x00, 0x00, 0x02, // sequence number
+            0x01, 0x02, 0x03, 0x04, // icv
+            0x05, 0x06, 0x07, 0x08, // icv
+            0x09, 0x0a, 0x0b, 0x0c, // icv
+            0xde, 0xad, 0xbe, 0xef, // payload
+        ];
+
+        let ah = Ah::new(data).unwrap();
+
+        assert_eq!(ah.next_header().get(), IpProtocol::Tcp);
+        assert_eq!(ah.header_len(), 24);
+        assert_eq!(ah.spi().get(), 0x1001);
+        assert_eq!(ah.seq_num().get(), 2);
+        assert_eq!(ah.icv().len(), 12);
+        assert_eq!(ah.payload(), &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            Ah::new(&data[..20]).err(),
+            Some(AhError::InvalidLength(LayerError::min_length(
+                "Ah", "icv", 12, 24, 20
+            )))
+        );
+    }
+
+    #[test]
+    fn ah_short_payload_len() {
+        let mut data = [0u8; 16];
+
+        // A payload length of 0 gives an 8-byte header, shorter than the
+        // fixed fields.
+        assert_eq!(
+            Ah::new(data).err(),
+            Some(AhError::InvalidLength(LayerError::min_value(
+                "Ah",
+                "payload_len",
+                1,
+                12,
+                8
+            )))
+        );
+
+        data[1] = 1;
+        let ah = Ah::new(data).unwrap();
+        assert!(ah.icv().is_empty());
+        assert_eq!(ah.payload().len(), 4);
+    }
+
+    #[test]
+    fn ah_builder() {
+        let udp = udp!(src_port: 500u16, dst_port: 500u16);
+        let ah = ah!(
+            next_header: IpProtocol::Udp,
+            spi: 0x1001u32,
+            icv: [0xaa; 12],
+            payload: udp.inner(),
+        );
+
+        assert_eq!(ah.header_len(), 24);
+        assert_eq!(ah.payload_len().get(), 4);
+        assert_eq!(ah.icv(), &[0xaa; 12]);
+        assert_eq!(ah.udp().unwrap().dst_port().get(), 500);
+    }
+}
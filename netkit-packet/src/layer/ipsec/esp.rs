@@ -0,0 +1,383 @@
+//! Encapsulating Security Payload (ESP) layer.
+
+use std::collections::HashMap;
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Esp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EspError {
+    /// Invalid Esp length.
+    #[error("Invalid Esp length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(SpiSpec, u32, u32);
+field_spec!(SeqNumSpec, u32, u32);
+
+/// Minimum length of an Esp header.
+pub const MIN_HEADER_LENGTH: usize = 8;
+
+/// Length of the Esp trailer (pad length and next header).
+pub const TRAILER_LENGTH: usize = 2;
+
+/// Encapsulating Security Payload (ESP) layer.
+///
+/// See [RFC 4303](https://datatracker.ietf.org/doc/html/rfc4303). Only the
+/// SPI and sequence number are in clear text, the rest of the packet can be
+/// recovered with an [`EspDecryptor`].
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                  Security Parameters Index (32) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                            Sequence Number (32) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |          Encrypted payload, padding, trailer, ICV |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct Esp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Esp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the SPI: 0..4
+    pub const FIELD_SPI: core::ops::Range<usize> = 0..4;
+    /// Field range of the sequence number: 4..8
+    pub const FIELD_SEQ_NUM: core::ops::Range<usize> = 4..8;
+    /// Field range of the payload: 8..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 8..;
+
+    /// Create a new Esp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Esp packet.
+    ///
+    /// The data must be at least 8 bytes long. Otherwise, the following
+    /// methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Esp layer.
+    pub fn validate(&self) -> Result<(), EspError> {
+        if self.data.as_ref().len() < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "Esp",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Esp layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, EspError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the SPI.
+    #[inline]
+    pub fn spi(&self) -> &Field<SpiSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SPI])
+    }
+
+    /// Get the accessor of the sequence number.
+    #[inline]
+    pub fn seq_num(&self) -> &Field<SeqNumSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SEQ_NUM])
+    }
+
+    /// Get the (encrypted) payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Decrypt the payload with the given decryptor.
+    ///
+    /// Returns `None` if the decryptor has no matching SA or the decrypted
+    /// data does not end with a valid trailer.
+    pub fn decrypt<D>(&self, decryptor: &D) -> Option<EspPlaintext>
+    where
+        D: EspDecryptor + ?Sized,
+    {
+        let data = decryptor.decrypt(self.spi().get(), self.seq_num().get(), self.payload())?;
+        EspPlaintext::new(data)
+    }
+}
+
+impl<T> Esp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the SPI.
+    #[inline]
+    pub fn spi_mut(&mut self) -> &mut Field<SpiSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SPI])
+    }
+
+    /// Get the mutable accessor of the sequence number.
+    #[inline]
+    pub fn seq_num_mut(&mut self) -> &mut Field<SeqNumSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SEQ_NUM])
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(Esp);
+
+/// Decryption hook for Esp payloads
+///
+/// Implementors hold the security associations (keys and algorithms) and turn
+/// the payload following the sequence number into plaintext. The returned
+/// data must have the IV and ICV removed and still end with the Esp trailer
+/// (padding, pad length and next header).
+pub trait EspDecryptor {
+    /// Decrypt the payload of the SA identified by `spi`.
+    fn decrypt(&self, spi: u32, seq_num: u32, payload: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl<F> EspDecryptor for F
+where
+    F: Fn(u32, u32, &[u8]) -> Option<Vec<u8>>,
+{
+    fn decrypt(&self, spi: u32, seq_num: u32, payload: &[u8]) -> Option<Vec<u8>> {
+        self(spi, seq_num, payload)
+    }
+}
+
+/// Table of per-SA decryptors keyed by SPI.
+#[derive(Default)]
+pub struct EspSaTable {
+    sas: HashMap<u32, Box<dyn EspDecryptor>>,
+}
+
+impl EspSaTable {
+    /// Create a new empty SA table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the decryptor of an SA.
+    pub fn insert(&mut self, spi: u32, decryptor: impl EspDecryptor + 'static) -> &mut Self {
+        self.sas.insert(spi, Box::new(decryptor));
+        self
+    }
+
+    /// Remove the decryptor of an SA.
+    pub fn remove(&mut self, spi: u32) -> bool {
+        self.sas.remove(&spi).is_some()
+    }
+}
+
+impl EspDecryptor for EspSaTable {
+    fn decrypt(&self, spi: u32, seq_num: u32, payload: &[u8]) -> Option<Vec<u8>> {
+        self.sas.get(&spi)?.decrypt(spi, seq_num, payload)
+    }
+}
+
+impl core::fmt::Debug for EspSaTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EspSaTable")
+            .field("spis", &self.sas.keys())
+            .finish()
+    }
+}
+
+/// Decrypted Esp payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EspPlaintext {
+    data: Vec<u8>,
+    payload_len: usize,
+}
+
+impl EspPlaintext {
+    /// Create the plaintext from decrypted data ending with the Esp trailer.
+    ///
+    /// Returns `None` if the pad length exceeds the data.
+    pub fn new(data: Vec<u8>) -> Option<Self> {
+        let len = data.len().checked_sub(TRAILER_LENGTH)?;
+        let pad_len = data[len] as usize;
+        let payload_len = len.checked_sub(pad_len)?;
+
+        Some(Self { data, payload_len })
+    }
+
+    /// Get the protocol of the decrypted payload.
+    pub fn next_header(&self) -> IpProtocol {
+        IpProtocol::from(self.data[self.data.len() - 1])
+    }
+
+    /// Get the decrypted payload without padding and trailer.
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.payload_len]
+    }
+
+    /// Get the inner IPv4 layer in tunnel mode.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.next_header() == IpProtocol::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the TCP layer in transport mode.
+    pub fn tcp(&self) -> Option<Tcp<&[u8]>> {
+        if self.next_header() == IpProtocol::Tcp {
+            Tcp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the UDP layer in transport mode.
+    pub fn udp(&self) -> Option<Udp<&[u8]>> {
+        if self.next_header() == IpProtocol::Udp {
+            Udp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// Builder for [`Esp`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct EspBuilder {
+    spi: Option<u32>,
+    seq_num: Option<u32>,
+    payload: Vec<u8>,
+}
+
+impl EspBuilder {
+    /// Create a new Esp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the SPI.
+    pub fn spi(&mut self, spi: impl Into<u32>) -> &mut Self {
+        self.spi = Some(spi.into());
+        self
+    }
+
+    /// Set the sequence number.
+    pub fn seq_num(&mut self, seq_num: impl Into<u32>) -> &mut Self {
+        self.seq_num = Some(seq_num.into());
+        self
+    }
+
+    /// Set the (already encrypted) payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Esp layer.
+    pub fn build(&self) -> Esp<Vec<u8>> {
+        let mut esp =
+            unsafe { Esp::new_unchecked(vec![0; MIN_HEADER_LENGTH + self.payload.len()]) };
+
+        esp.spi_mut().set(self.spi.unwrap_or_default());
+        esp.seq_num_mut().set(self.seq_num.unwrap_or(1));
+        esp.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        esp
+    }
+}
+
+/// Create an Esp layer with the given fields.
+#[macro_export]
+macro_rules! esp {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::ipsec::esp::EspBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn esp_new() {
+        let data: [u8; 12] = [
+            0x00, 0x00, 0x10, 0x01, // spi
+            0x00, 0x00, 0x00, 0x05, // sequence number
+            0xde, 0xad, 0xbe, 0xef, // encrypted payload
+        ];
+
+        let esp = Esp::new(data).unwrap();
+
+        assert_eq!(esp.spi().get(), 0x1001);
+        assert_eq!(esp.seq_num().get(), 5);
+        assert_eq!(esp.payload(), &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            Esp::new(&data[..7]).err(),
+            Some(EspError::InvalidLength(LayerError::min_length(
+                "Esp", "header", 0, 8, 7
+            )))
+        );
+    }
+
+    #[test]
+    fn esp_decrypt() {
+        // "Decrypt" by xoring with the key of the SA.
+        let udp = udp!(src_port: 500u16, dst_port: 500u16);
+        let mut plaintext = udp.inner().clone();
+        plaintext.extend_from_slice(&[1, 2, 2, IpProtocol::Udp.into()]);
+        let ciphertext: Vec<u8> = plaintext.iter().map(|b| b ^ 0x5a).collect();
+
+        let esp = esp!(spi: 0x1001u32, payload: ciphertext);
+
+        let mut sas = EspSaTable::new();
+        sas.insert(0x1001, |_spi, _seq, payload: &[u8]| {
+            Some(payload.iter().map(|b| b ^ 0x5a).collect())
+        });
+
+        let decrypted = esp.decrypt(&sas).unwrap();
+        assert_eq!(decrypted.next_header(), IpProtocol::Udp);
+        assert_eq!(decrypted.payload(), udp.inner().as_slice());
+        assert_eq!(decrypted.udp().unwrap().src_port().get(), 500);
+
+        sas.remove(0x1001);
+        assert!(esp.decrypt(&sas).is_none());
+    }
+}
@@ -0,0 +1,547 @@
+//! Layer Two Tunneling Protocol (L2TP) layer.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for L2tp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum L2tpError {
+    /// Invalid L2tp length.
+    #[error("Invalid L2tp length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Unsupported L2tp version.
+    #[error("Unsupported L2tp version: {0}")]
+    UnsupportedVersion(u8),
+}
+
+field_spec!(ControlSpec, bool, u8, 0x80, 7);
+field_spec!(LengthPresentSpec, bool, u8, 0x40, 6);
+field_spec!(SeqPresentSpec, bool, u8, 0x08, 3);
+field_spec!(OffsetPresentSpec, bool, u8, 0x02, 1);
+field_spec!(PrioritySpec, bool, u8, 0x01, 0);
+field_spec!(VersionSpec, u8, u8, 0x0F);
+field_spec!(LengthSpec, u16, u16);
+field_spec!(IdSpec, u16, u16);
+field_spec!(SeqSpec, u16, u16);
+field_spec!(OffsetSizeSpec, u16, u16);
+
+/// Minimum length of a L2tp header.
+pub const MIN_HEADER_LENGTH: usize = 6;
+
+/// L2TP version supported by this layer.
+pub const VERSION: u8 = 2;
+
+/// Default UDP port of L2TP.
+pub const DEFAULT_PORT: u16 = 1701;
+
+/// Layer Two Tunneling Protocol (L2TP) layer.
+///
+/// See [RFC 2661](https://datatracker.ietf.org/doc/html/rfc2661). Only
+/// L2TPv2 is supported. Control messages are not dissected further, data
+/// messages carry [`Ppp`] frames.
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// | T| L| x| x| S| x| O| P| x| x| x| x|  Version  |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                             Length (optional) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                                     Tunnel ID |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                                    Session ID |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                                 Ns (optional) |
+/// |                                 Nr (optional) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |   Offset Size (optional) + Offset Pad (optional) |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct L2tp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> L2tp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the flags: 0..1
+    pub const FIELD_FLAGS: core::ops::Range<usize> = 0..1;
+    /// Field range of the version: 1..2 (4bits)
+    pub const FIELD_VERSION: core::ops::Range<usize> = 1..2;
+
+    /// Create a new L2tp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid L2tp packet.
+    ///
+    /// The data must be at least as long as the header indicated by the
+    /// flags. Otherwise, the following methods may panic when accessing the
+    /// fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the L2tp layer.
+    pub fn validate(&self) -> Result<(), L2tpError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("L2tp", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+
+        if self.version().get() != VERSION {
+            return Err(L2tpError::UnsupportedVersion(self.version().get()));
+        }
+
+        // The offset size itself must be readable before the header length.
+        let fixed_len = self.offset_size_offset() + 2 * self.offset_present().get() as usize;
+        if len < fixed_len {
+            return Err(
+                LayerError::min_length("L2tp", "optional fields", 0, fixed_len, len).into(),
+            );
+        }
+
+        if len < self.header_len() {
+            return Err(LayerError::min_length(
+                "L2tp",
+                "offset padding",
+                fixed_len,
+                self.header_len(),
+                len,
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new L2tp layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, L2tpError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    #[inline]
+    fn tunnel_id_offset(&self) -> usize {
+        2 + 2 * self.length_present().get() as usize
+    }
+
+    #[inline]
+    fn offset_size_offset(&self) -> usize {
+        self.tunnel_id_offset() + 4 + 4 * self.seq_present().get() as usize
+    }
+
+    /// Get the length of the header including the optional fields and the
+    /// offset padding.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        match self.offset_size() {
+            Some(offset_size) => self.offset_size_offset() + 2 + offset_size.get() as usize,
+            None => self.offset_size_offset(),
+        }
+    }
+
+    /// Get the accessor of the type bit, set for control messages.
+    #[inline]
+    pub fn control(&self) -> &Field<ControlSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the accessor of the length present bit.
+    #[inline]
+    pub fn length_present(&self) -> &Field<LengthPresentSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the accessor of the sequence present bit.
+    #[inline]
+    pub fn seq_present(&self) -> &Field<SeqPresentSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the accessor of the offset present bit.
+    #[inline]
+    pub fn offset_present(&self) -> &Field<OffsetPresentSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the accessor of the priority bit.
+    #[inline]
+    pub fn priority(&self) -> &Field<PrioritySpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the accessor of the version.
+    #[inline]
+    pub fn version(&self) -> &Field<VersionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION])
+    }
+
+    /// Get the accessor of the length if present.
+    #[inline]
+    pub fn length(&self) -> Option<&Field<LengthSpec>> {
+        self.length_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[2..4]))
+    }
+
+    /// Get the accessor of the tunnel ID.
+    #[inline]
+    pub fn tunnel_id(&self) -> &Field<IdSpec> {
+        let offset = self.tunnel_id_offset();
+        cast_from_bytes(&self.data.as_ref()[offset..offset + 2])
+    }
+
+    /// Get the accessor of the session ID.
+    #[inline]
+    pub fn session_id(&self) -> &Field<IdSpec> {
+        let offset = self.tunnel_id_offset() + 2;
+        cast_from_bytes(&self.data.as_ref()[offset..offset + 2])
+    }
+
+    /// Get the accessor of the sequence number (Ns) if present.
+    #[inline]
+    pub fn ns(&self) -> Option<&Field<SeqSpec>> {
+        let offset = self.tunnel_id_offset() + 4;
+        self.seq_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[offset..offset + 2]))
+    }
+
+    /// Get the accessor of the expected sequence number (Nr) if present.
+    #[inline]
+    pub fn nr(&self) -> Option<&Field<SeqSpec>> {
+        let offset = self.tunnel_id_offset() + 6;
+        self.seq_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[offset..offset + 2]))
+    }
+
+    /// Get the accessor of the offset size if present.
+    #[inline]
+    pub fn offset_size(&self) -> Option<&Field<OffsetSizeSpec>> {
+        let offset = self.offset_size_offset();
+        self.offset_present()
+            .get()
+            .then(|| cast_from_bytes(&self.data.as_ref()[offset..offset + 2]))
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+
+    /// Get the PPP layer of a data message.
+    pub fn ppp(&self) -> Option<Ppp<&[u8]>> {
+        if self.control().get() {
+            None
+        } else {
+            Ppp::new(self.payload()).ok()
+        }
+    }
+}
+
+impl<T> L2tp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the type bit.
+    #[inline]
+    pub fn control_mut(&mut self) -> &mut Field<ControlSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the mutable accessor of the length present bit.
+    #[inline]
+    pub fn length_present_mut(&mut self) -> &mut Field<LengthPresentSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the mutable accessor of the sequence present bit.
+    #[inline]
+    pub fn seq_present_mut(&mut self) -> &mut Field<SeqPresentSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the mutable accessor of the offset present bit.
+    #[inline]
+    pub fn offset_present_mut(&mut self) -> &mut Field<OffsetPresentSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the mutable accessor of the priority bit.
+    #[inline]
+    pub fn priority_mut(&mut self) -> &mut Field<PrioritySpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the mutable accessor of the version.
+    #[inline]
+    pub fn version_mut(&mut self) -> &mut Field<VersionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION])
+    }
+
+    /// Get the mutable accessor of the length if present.
+    #[inline]
+    pub fn length_mut(&mut self) -> Option<&mut Field<LengthSpec>> {
+        if self.length_present().get() {
+            Some(cast_from_bytes_mut(&mut self.data.as_mut()[2..4]))
+        } else {
+            None
+        }
+    }
+
+    /// Get the mutable accessor of the tunnel ID.
+    #[inline]
+    pub fn tunnel_id_mut(&mut self) -> &mut Field<IdSpec> {
+        let offset = self.tunnel_id_offset();
+        cast_from_bytes_mut(&mut self.data.as_mut()[offset..offset + 2])
+    }
+
+    /// Get the mutable accessor of the session ID.
+    #[inline]
+    pub fn session_id_mut(&mut self) -> &mut Field<IdSpec> {
+        let offset = self.tunnel_id_offset() + 2;
+        cast_from_bytes_mut(&mut self.data.as_mut()[offset..offset + 2])
+    }
+
+    /// Get the mutable accessor of the sequence number (Ns) if present.
+    #[inline]
+    pub fn ns_mut(&mut self) -> Option<&mut Field<SeqSpec>> {
+        let offset = self.tunnel_id_offset() + 4;
+        if self.seq_present().get() {
+            Some(cast_from_bytes_mut(
+                &mut self.data.as_mut()[offset..offset + 2],
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Get the mutable accessor of the expected sequence number (Nr) if
+    /// present.
+    #[inline]
+    pub fn nr_mut(&mut self) -> Option<&mut Field<SeqSpec>> {
+        let offset = self.tunnel_id_offset() + 6;
+        if self.seq_present().get() {
+            Some(cast_from_bytes_mut(
+                &mut self.data.as_mut()[offset..offset + 2],
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[header_len..]
+    }
+}
+
+layer_impl!(L2tp);
+
+/// Builder for [`L2tp`].
+///
+/// Control messages always carry the length and sequence numbers.
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct L2tpBuilder {
+    control: bool,
+    length_present: bool,
+    tunnel_id: Option<u16>,
+    session_id: Option<u16>,
+    ns: Option<u16>,
+    nr: Option<u16>,
+    payload: Vec<u8>,
+}
+
+impl L2tpBuilder {
+    /// Create a new L2tp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether this is a control message.
+    pub fn control(&mut self, control: bool) -> &mut Self {
+        self.control = control;
+        self
+    }
+
+    /// Set whether the length field is present in a data message.
+    pub fn length_present(&mut self, length_present: bool) -> &mut Self {
+        self.length_present = length_present;
+        self
+    }
+
+    /// Set the tunnel ID.
+    pub fn tunnel_id(&mut self, tunnel_id: impl Into<u16>) -> &mut Self {
+        self.tunnel_id = Some(tunnel_id.into());
+        self
+    }
+
+    /// Set the session ID.
+    pub fn session_id(&mut self, session_id: impl Into<u16>) -> &mut Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Set the sequence number (Ns), which also sets the sequence present bit.
+    pub fn ns(&mut self, ns: impl Into<u16>) -> &mut Self {
+        self.ns = Some(ns.into());
+        self
+    }
+
+    /// Set the expected sequence number (Nr), which also sets the sequence
+    /// present bit.
+    pub fn nr(&mut self, nr: impl Into<u16>) -> &mut Self {
+        self.nr = Some(nr.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the L2tp layer.
+    pub fn build(&self) -> L2tp<Vec<u8>> {
+        let length_present = self.control || self.length_present;
+        let seq_present = self.control || self.ns.is_some() || self.nr.is_some();
+        let header_len = MIN_HEADER_LENGTH + 2 * length_present as usize + 4 * seq_present as usize;
+        let len = header_len + self.payload.len();
+
+        let mut l2tp = unsafe { L2tp::new_unchecked(vec![0; len]) };
+
+        l2tp.control_mut().set(self.control);
+        l2tp.length_present_mut().set(length_present);
+        l2tp.seq_present_mut().set(seq_present);
+        l2tp.version_mut().set(VERSION);
+        if let Some(length) = l2tp.length_mut() {
+            length.set(len as u16);
+        }
+        l2tp.tunnel_id_mut().set(self.tunnel_id.unwrap_or_default());
+        l2tp.session_id_mut()
+            .set(self.session_id.unwrap_or_default());
+        if let Some(ns) = l2tp.ns_mut() {
+            ns.set(self.ns.unwrap_or_default());
+        }
+        if let Some(nr) = l2tp.nr_mut() {
+            nr.set(self.nr.unwrap_or_default());
+        }
+        l2tp.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        l2tp
+    }
+}
+
+/// Create a L2tp layer with the given fields.
+#[macro_export]
+macro_rules! l2tp {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::l2tp::L2tpBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l2tp_control() {
+        let data: [u8; 12] = [
+            0xc8, 0x02, // flags T L S, version 2
+            0x00, 0x0c, // length
+            0x00, 0x01, // tunnel id
+            0x00, 0x00, // session id
+            0x00, 0x02, // ns
+            0x00, 0x03, // nr
+        ];
+
+        let l2tp = L2tp::new(data).unwrap();
+        assert!(l2tp.control().get());
+        assert_eq!(l2tp.length().unwrap().get(), 12);
+        assert_eq!(l2tp.tunnel_id().get(), 1);
+        assert_eq!(l2tp.session_id().get(), 0);
+        assert_eq!(l2tp.ns().unwrap().get(), 2);
+        assert_eq!(l2tp.nr().unwrap().get(), 3);
+        assert_eq!(l2tp.header_len(), 12);
+        assert!(l2tp.ppp().is_none());
+
+        assert_eq!(
+            L2tp::new(&data[..10]).err(),
+            Some(L2tpError::InvalidLength(LayerError::min_length(
+                "L2tp",
+                "optional fields",
+                0,
+                12,
+                10
+            )))
+        );
+        assert_eq!(
+            L2tp::new([0x00, 0x03, 0, 0, 0, 0]).err(),
+            Some(L2tpError::UnsupportedVersion(3))
+        );
+    }
+
+    #[test]
+    fn l2tp_data_ppp() {
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp);
+        let ppp = ppp!(protocol: PppProtocol::Ipv4, payload: ipv4.inner());
+        let l2tp = l2tp!(tunnel_id: 7u16, session_id: 9u16, payload: ppp.inner());
+
+        let l2tp = L2tp::new(l2tp.inner().as_slice()).unwrap();
+        assert!(!l2tp.control().get());
+        assert!(l2tp.length().is_none());
+        assert!(l2tp.ns().is_none());
+        assert_eq!(l2tp.header_len(), 6);
+        assert_eq!(l2tp.tunnel_id().get(), 7);
+        assert_eq!(l2tp.session_id().get(), 9);
+        assert_eq!(
+            l2tp.ppp().unwrap().ipv4().unwrap().protocol().get(),
+            IpProtocol::Udp
+        );
+    }
+
+    #[test]
+    fn l2tp_offset_padding() {
+        let data: [u8; 11] = [
+            0x02, 0x02, // flags O, version 2
+            0x00, 0x01, // tunnel id
+            0x00, 0x02, // session id
+            0x00, 0x02, // offset size
+            0x00, 0x00, // offset pad
+            0x21, // payload
+        ];
+
+        let l2tp = L2tp::new(data).unwrap();
+        assert_eq!(l2tp.offset_size().unwrap().get(), 2);
+        assert_eq!(l2tp.header_len(), 10);
+        assert_eq!(l2tp.payload(), &[0x21]);
+    }
+}
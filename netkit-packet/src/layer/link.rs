@@ -0,0 +1,121 @@
+//! Link-layer types of captures.
+//!
+//! Captures record a link type (`LINKTYPE_*`) for their packets, which is not
+//! always Ethernet: macOS loopback captures use BSD loopback encapsulation,
+//! tunnel interfaces capture bare IP packets, and Linux "any" captures use
+//! SLL2. The functions here dispatch on the link type to reach the network
+//! layer regardless of the encapsulation.
+
+use crate::prelude::*;
+
+pub mod link_type;
+pub use link_type::*;
+
+/// Get the Eth type and the network layer of a packet.
+///
+/// Returns `None` for unsupported link types and malformed packets.
+pub fn network(link_type: LinkType, data: &[u8]) -> Option<(EthType, &[u8])> {
+    match link_type {
+        LinkType::Ethernet => {
+            let eth = Eth::new(data).ok()?;
+            let (eth_type, payload) = eth.network()?;
+            // Re-slice `data` to return the network layer with its lifetime.
+            let start = payload.as_ptr() as usize - data.as_ptr() as usize;
+            Some((eth_type, &data[start..start + payload.len()]))
+        }
+        LinkType::Null | LinkType::Loop => {
+            let null = Null::new(data).ok()?;
+            Some((null.eth_type(), &data[Null::<&[u8]>::FIELD_PAYLOAD]))
+        }
+        LinkType::Sll2 => {
+            let sll2 = Sll2::new(data).ok()?;
+            Some((sll2.protocol().get(), &data[Sll2::<&[u8]>::FIELD_PAYLOAD]))
+        }
+        LinkType::Raw | LinkType::Ipv4 | LinkType::Ipv6 => {
+            let eth_type = match data.first()? >> 4 {
+                4 => EthType::Ipv4,
+                6 => EthType::Ipv6,
+                _ => return None,
+            };
+            Some((eth_type, data))
+        }
+        _ => None,
+    }
+}
+
+/// Get the IPv4 layer of a packet of any supported link type.
+pub fn ipv4(link_type: LinkType, data: &[u8]) -> Option<Ipv4<&[u8]>> {
+    match network(link_type, data)? {
+        (EthType::Ipv4, payload) => Ipv4::new(payload).ok(),
+        _ => None,
+    }
+}
+
+/// Convert a packet of any supported link type into an Ethernet frame.
+///
+/// Ethernet frames are copied as is. Other encapsulations are replaced by an
+/// Ethernet header with zero addresses (the source address is kept for SLL2
+/// packets carrying a 6-byte link-layer address), so that Ethernet-based
+/// analyses can process them.
+pub fn to_eth(link_type: LinkType, data: &[u8]) -> Option<Vec<u8>> {
+    if link_type == LinkType::Ethernet {
+        return Eth::new(data).ok().map(|_| data.to_vec());
+    }
+
+    let (eth_type, payload) = network(link_type, data)?;
+    let src = match Sll2::new(data) {
+        Ok(sll2) if link_type == LinkType::Sll2 && sll2.addr().len() == 6 => {
+            EthAddr::from_slice(sll2.addr())
+        }
+        _ => EthAddr::default(),
+    };
+
+    Some(
+        eth!(src: src, eth_type: eth_type, payload: payload)
+            .inner()
+            .clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_dispatch() {
+        let ipv4 = ipv4!(ttl: 7u8, protocol: IpProtocol::Udp);
+        let ipv4 = ipv4.inner();
+
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4);
+        let mut null = 2u32.to_le_bytes().to_vec();
+        null.extend_from_slice(ipv4);
+        let sll2 = sll2!(
+            protocol: EthType::Ipv4,
+            addr: [2, 0, 0, 0, 0, 1],
+            payload: ipv4,
+        );
+
+        for (link_type, data) in [
+            (LinkType::Ethernet, eth.inner().as_slice()),
+            (LinkType::Null, null.as_slice()),
+            (LinkType::Raw, ipv4.as_slice()),
+            (LinkType::Sll2, sll2.inner().as_slice()),
+        ] {
+            assert_eq!(
+                super::ipv4(link_type, data).map(|ip| ip.ttl().get()),
+                Some(7),
+                "{link_type}"
+            );
+
+            let eth = to_eth(link_type, data).unwrap();
+            assert!(Eth::new(&eth).unwrap().ipv4().is_some());
+        }
+
+        let eth = to_eth(LinkType::Sll2, sll2.inner()).unwrap();
+        assert_eq!(
+            Eth::new(&eth).unwrap().src().get(),
+            EthAddr::new(2, 0, 0, 0, 0, 1)
+        );
+        assert!(network(LinkType::Unknown(12345), ipv4).is_none());
+    }
+}
@@ -0,0 +1,87 @@
+//! Link type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+/// Link type of a capture (`LINKTYPE_*`)
+///
+/// See the [tcpdump list of link-layer header
+/// types](https://www.tcpdump.org/linktypes.html).
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u32)]
+#[non_exhaustive]
+pub enum LinkType {
+    /// BSD loopback, with a host byte order address family word
+    Null = 0,
+
+    /// Ethernet
+    Ethernet = 1,
+
+    /// Bare IPv4 or IPv6 packets
+    Raw = 101,
+
+    /// OpenBSD loopback, with a network byte order address family word
+    Loop = 108,
+
+    /// Bare IPv4 packets
+    Ipv4 = 228,
+
+    /// Bare IPv6 packets
+    Ipv6 = 229,
+
+    /// Linux cooked capture v2
+    Sll2 = 276,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+
+    #[test]
+    fn link_type_str() {
+        test_enum_str!(
+            LinkType,
+            Null => "Null",
+            Ethernet => "Ethernet",
+            Raw => "Raw",
+            Loop => "Loop",
+            Sll2 => "Sll2",
+        );
+    }
+
+    #[test]
+    fn link_type_num() {
+        test_enum_num!(
+            LinkType: u32,
+            Null => 0,
+            Ethernet => 1,
+            Raw => 101,
+            Loop => 108,
+            Sll2 => 276,
+        );
+    }
+}
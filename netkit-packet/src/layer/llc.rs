@@ -0,0 +1,428 @@
+//! IEEE 802.2 Logical Link Control (LLC) layer, with the SNAP extension.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Llc layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum LlcError {
+    /// Invalid Llc length.
+    #[error("Invalid Llc length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(SapSpec, u8, u8);
+
+/// Minimum length of an Llc header (U-format control field).
+pub const MIN_HEADER_LENGTH: usize = 3;
+
+/// Length of the SNAP extension (OUI and protocol identifier).
+pub const SNAP_LENGTH: usize = 5;
+
+/// SAP of the Spanning Tree Protocol.
+pub const SAP_STP: u8 = 0x42;
+
+/// SAP of the SNAP extension.
+pub const SAP_SNAP: u8 = 0xAA;
+
+/// Control field of unnumbered information frames.
+pub const CONTROL_UI: u8 = 0x03;
+
+/// OUI of SNAP frames whose protocol identifier is an Eth type (RFC 1042).
+pub const OUI_ETHERNET: [u8; 3] = [0x00, 0x00, 0x00];
+
+/// OUI of Cisco.
+pub const OUI_CISCO: [u8; 3] = [0x00, 0x00, 0x0C];
+
+/// OUI of Apple, used by AppleTalk.
+pub const OUI_APPLE: [u8; 3] = [0x08, 0x00, 0x07];
+
+/// SNAP protocol identifier of the Cisco Discovery Protocol.
+pub const PID_CDP: u16 = 0x2000;
+
+/// SNAP protocol identifier of AppleTalk.
+pub const PID_APPLETALK: u16 = 0x809B;
+
+/// SNAP protocol identifier of the AppleTalk ARP.
+pub const PID_AARP: u16 = 0x80F3;
+
+/// Protocol carried by an Llc frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LlcProtocol {
+    /// Spanning Tree Protocol (BPDU).
+    Stp,
+
+    /// Cisco Discovery Protocol.
+    Cdp,
+
+    /// AppleTalk (DDP).
+    AppleTalk,
+
+    /// AppleTalk Address Resolution Protocol.
+    Aarp,
+
+    /// Protocol identified by an Eth type (RFC 1042 encapsulation).
+    Ethernet(EthType),
+
+    /// Any other protocol.
+    Unknown,
+}
+
+/// IEEE 802.2 Logical Link Control (LLC) layer.
+///
+/// LLC follows the Eth header of IEEE 802.3 frames, whose Eth type field
+/// holds the payload length instead (see [`Eth::length`]). When both SAPs are
+/// [`SAP_SNAP`], the SNAP extension follows the control field.
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |         DSAP          |         SSAP          |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |  Control (8 or 16 bits)                       |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                 OUI (SNAP only)               |
+/// +                       +--+--+--+--+--+--+--+--+
+/// |                       |                       |
+/// +--+--+--+--+--+--+--+--+                       +
+/// |   Protocol Identifier (SNAP only)             |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct Llc<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Llc<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the destination SAP: 0..1
+    pub const FIELD_DSAP: core::ops::Range<usize> = 0..1;
+    /// Field range of the source SAP: 1..2
+    pub const FIELD_SSAP: core::ops::Range<usize> = 1..2;
+
+    /// Create a new Llc layer from raw data without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Llc frame.
+    ///
+    /// The data must be at least as long as the header. Otherwise, the
+    /// following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Llc layer.
+    pub fn validate(&self) -> Result<(), LlcError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("Llc", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+
+        if len < self.header_len() {
+            return Err(LayerError::min_length("Llc", "header", 0, self.header_len(), len).into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Llc layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, LlcError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the destination SAP.
+    #[inline]
+    pub fn dsap(&self) -> &Field<SapSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DSAP])
+    }
+
+    /// Get the accessor of the source SAP.
+    ///
+    /// The lowest bit is the command/response bit.
+    #[inline]
+    pub fn ssap(&self) -> &Field<SapSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SSAP])
+    }
+
+    /// Whether the control field is a single byte (unnumbered frame).
+    ///
+    /// Information and supervisory frames have a 2-byte control field.
+    #[inline]
+    pub fn is_unnumbered(&self) -> bool {
+        self.data.as_ref()[2] & 0x03 == 0x03
+    }
+
+    /// Get the control field.
+    #[inline]
+    pub fn control(&self) -> u16 {
+        let data = self.data.as_ref();
+        if self.is_unnumbered() {
+            data[2] as u16
+        } else {
+            u16::from_be_bytes([data[2], data[3]])
+        }
+    }
+
+    #[inline]
+    fn control_end(&self) -> usize {
+        if self.is_unnumbered() {
+            3
+        } else {
+            4
+        }
+    }
+
+    /// Whether the SNAP extension is present.
+    #[inline]
+    pub fn is_snap(&self) -> bool {
+        let data = self.data.as_ref();
+        data[0] == SAP_SNAP && data[1] & 0xFE == SAP_SNAP && data[2] == CONTROL_UI
+    }
+
+    /// Get the length of the header, including the SNAP extension.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        self.control_end() + if self.is_snap() { SNAP_LENGTH } else { 0 }
+    }
+
+    /// Get the SNAP OUI.
+    pub fn oui(&self) -> Option<[u8; 3]> {
+        self.is_snap()
+            .then(|| self.data.as_ref()[3..6].try_into().unwrap())
+    }
+
+    /// Get the SNAP protocol identifier.
+    pub fn pid(&self) -> Option<u16> {
+        let data = self.data.as_ref();
+        self.is_snap()
+            .then(|| u16::from_be_bytes([data[6], data[7]]))
+    }
+
+    /// Get the protocol carried by the frame.
+    pub fn protocol(&self) -> LlcProtocol {
+        let (Some(oui), Some(pid)) = (self.oui(), self.pid()) else {
+            return match self.dsap().get() {
+                SAP_STP => LlcProtocol::Stp,
+                _ => LlcProtocol::Unknown,
+            };
+        };
+
+        match (oui, pid) {
+            (OUI_CISCO, PID_CDP) => LlcProtocol::Cdp,
+            (OUI_APPLE, PID_APPLETALK) => LlcProtocol::AppleTalk,
+            (OUI_ETHERNET, PID_AARP) => LlcProtocol::Aarp,
+            (OUI_ETHERNET, pid) => LlcProtocol::Ethernet(EthType::from(pid)),
+            _ => LlcProtocol::Unknown,
+        }
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+
+    /// Get the IPv4 layer if the frame encapsulates IPv4 (RFC 1042).
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.protocol() == LlcProtocol::Ethernet(EthType::Ipv4) {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Llc<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the destination SAP.
+    #[inline]
+    pub fn dsap_mut(&mut self) -> &mut Field<SapSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DSAP])
+    }
+
+    /// Get the mutable accessor of the source SAP.
+    #[inline]
+    pub fn ssap_mut(&mut self) -> &mut Field<SapSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SSAP])
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[header_len..]
+    }
+}
+
+layer_impl!(Llc);
+
+/// Builder for [`Llc`].
+///
+/// Setting the OUI or the protocol identifier adds the SNAP extension, with
+/// both SAPs defaulting to [`SAP_SNAP`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct LlcBuilder {
+    dsap: Option<u8>,
+    ssap: Option<u8>,
+    control: Option<u8>,
+    oui: Option<[u8; 3]>,
+    pid: Option<u16>,
+    payload: Vec<u8>,
+}
+
+impl LlcBuilder {
+    /// Create a new Llc builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the destination SAP.
+    pub fn dsap(&mut self, dsap: impl Into<u8>) -> &mut Self {
+        self.dsap = Some(dsap.into());
+        self
+    }
+
+    /// Set the source SAP.
+    pub fn ssap(&mut self, ssap: impl Into<u8>) -> &mut Self {
+        self.ssap = Some(ssap.into());
+        self
+    }
+
+    /// Set the control field of an unnumbered frame.
+    pub fn control(&mut self, control: impl Into<u8>) -> &mut Self {
+        self.control = Some(control.into());
+        self
+    }
+
+    /// Set the SNAP OUI.
+    pub fn oui(&mut self, oui: [u8; 3]) -> &mut Self {
+        self.oui = Some(oui);
+        self
+    }
+
+    /// Set the SNAP protocol identifier.
+    pub fn pid(&mut self, pid: impl Into<u16>) -> &mut Self {
+        self.pid = Some(pid.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Llc layer.
+    pub fn build(&self) -> Llc<Vec<u8>> {
+        let snap = self.oui.is_some() || self.pid.is_some();
+        let sap = if snap { SAP_SNAP } else { 0 };
+
+        let mut data = Vec::with_capacity(MIN_HEADER_LENGTH + SNAP_LENGTH + self.payload.len());
+        data.push(self.dsap.unwrap_or(sap));
+        data.push(self.ssap.unwrap_or(sap));
+        data.push(self.control.unwrap_or(CONTROL_UI));
+        if snap {
+            data.extend_from_slice(&self.oui.unwrap_or_default());
+            data.extend_from_slice(&self.pid.unwrap_or_default().to_be_bytes());
+        }
+        data.extend_from_slice(self.payload.as_ref());
+
+        unsafe { Llc::new_unchecked(data) }
+    }
+}
+
+/// Create an Llc layer with the given fields.
+///
+/// # Example
+///
+/// ```
+/// # use netkit_packet::prelude::*;
+/// use netkit_packet::layer::llc::{LlcProtocol, OUI_CISCO, PID_CDP};
+///
+/// let llc = llc!(oui: OUI_CISCO, pid: PID_CDP);
+///
+/// assert!(llc.is_snap());
+/// assert_eq!(llc.protocol(), LlcProtocol::Cdp);
+/// ```
+#[macro_export]
+macro_rules! llc {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::llc::LlcBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn llc_stp() {
+        let data = [SAP_STP, SAP_STP, CONTROL_UI, 0x00, 0x00];
+        let llc = Llc::new(&data[..]).unwrap();
+
+        assert!(llc.is_unnumbered());
+        assert!(!llc.is_snap());
+        assert_eq!(llc.header_len(), 3);
+        assert_eq!(llc.protocol(), LlcProtocol::Stp);
+        assert_eq!(llc.oui(), None);
+        assert_eq!(llc.payload(), &[0x00, 0x00]);
+
+        // Information frame with a 2-byte control field.
+        let llc = Llc::new([0xF0, 0xF0, 0x02, 0x04]).unwrap();
+        assert_eq!(llc.control(), 0x0204);
+        assert_eq!(llc.header_len(), 4);
+        assert_eq!(llc.protocol(), LlcProtocol::Unknown);
+
+        assert_eq!(
+            Llc::new([SAP_SNAP, SAP_SNAP, CONTROL_UI, 0x00]).err(),
+            Some(LlcError::InvalidLength(LayerError::min_length(
+                "Llc", "header", 0, 8, 4
+            )))
+        );
+    }
+
+    #[test]
+    fn llc_snap() {
+        let cdp = llc!(oui: OUI_CISCO, pid: PID_CDP, payload: [0x02]);
+        assert_eq!(&cdp.inner()[..8], &[0xAA, 0xAA, 0x03, 0, 0, 0x0C, 0x20, 0]);
+        assert_eq!(cdp.protocol(), LlcProtocol::Cdp);
+        assert_eq!(cdp.payload(), &[0x02]);
+
+        let atalk = llc!(oui: OUI_APPLE, pid: PID_APPLETALK);
+        assert_eq!(atalk.protocol(), LlcProtocol::AppleTalk);
+        let aarp = llc!(oui: OUI_ETHERNET, pid: PID_AARP);
+        assert_eq!(aarp.protocol(), LlcProtocol::Aarp);
+
+        let ipv4 = ipv4!(ttl: 9u8);
+        let llc = llc!(oui: OUI_ETHERNET, pid: EthType::Ipv4, payload: ipv4.inner());
+        assert_eq!(llc.protocol(), LlcProtocol::Ethernet(EthType::Ipv4));
+        assert_eq!(llc.ipv4().unwrap().ttl().get(), 9);
+    }
+}
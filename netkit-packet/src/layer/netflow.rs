@@ -0,0 +1,548 @@
+//! NetFlow v5 and v9 export decoding.
+//!
+//! Flow exports are UDP datagrams sent by routers to a collector, usually on
+//! port [`DEFAULT_PORT`]. NetFlow v5 records have a fixed layout;
+//! NetFlow v9 ([RFC 3954]) records are described by templates sent in
+//! earlier datagrams of the same exporter, which a [`TemplateCache`] keeps
+//! across calls to [`NetFlowV9::parse`]:
+//!
+//! ```
+//! use netkit_packet::layer::netflow::{NetFlowV9, TemplateCache};
+//!
+//! // A template flowset (ID 0) with template 256: IPv4 source (8) and
+//! // packets (2), then a data flowset of template 256.
+//! let mut datagram = vec![0, 9, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 7];
+//! datagram.extend([0, 0, 0, 16, 1, 0, 0, 2, 0, 8, 0, 4, 0, 2, 0, 4]);
+//! datagram.extend([1, 0, 0, 12, 10, 0, 0, 1, 0, 0, 0, 5]);
+//!
+//! let mut templates = TemplateCache::new();
+//! let export = NetFlowV9::parse(&datagram, &mut templates).unwrap();
+//! assert_eq!(export.records[0].get(8), Some(&[10, 0, 0, 1][..]));
+//! assert_eq!(export.records[0].packets(), Some(5));
+//! ```
+//!
+//! [RFC 3954]: https://datatracker.ietf.org/doc/html/rfc3954
+
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+/// Default UDP port of NetFlow collectors.
+pub const DEFAULT_PORT: u16 = 2055;
+
+/// Length of the NetFlow v5 header.
+pub const V5_HEADER_LENGTH: usize = 24;
+
+/// Length of a NetFlow v5 record.
+pub const V5_RECORD_LENGTH: usize = 48;
+
+/// Length of the NetFlow v9 header.
+pub const V9_HEADER_LENGTH: usize = 20;
+
+/// Flowset ID of NetFlow v9 templates.
+pub const V9_TEMPLATE_FLOWSET: u16 = 0;
+
+/// Flowset ID of NetFlow v9 options templates.
+pub const V9_OPTIONS_TEMPLATE_FLOWSET: u16 = 1;
+
+/// Field types shared by NetFlow v9 and IPFIX (IANA information elements).
+pub mod field {
+    /// Number of bytes.
+    pub const IN_BYTES: u16 = 1;
+    /// Number of packets.
+    pub const IN_PKTS: u16 = 2;
+    /// IP protocol.
+    pub const PROTOCOL: u16 = 4;
+    /// Type of service.
+    pub const TOS: u16 = 5;
+    /// TCP flags.
+    pub const TCP_FLAGS: u16 = 6;
+    /// Source port.
+    pub const L4_SRC_PORT: u16 = 7;
+    /// IPv4 source address.
+    pub const IPV4_SRC_ADDR: u16 = 8;
+    /// Input interface.
+    pub const INPUT_SNMP: u16 = 10;
+    /// Destination port.
+    pub const L4_DST_PORT: u16 = 11;
+    /// IPv4 destination address.
+    pub const IPV4_DST_ADDR: u16 = 12;
+    /// Output interface.
+    pub const OUTPUT_SNMP: u16 = 14;
+    /// Uptime at the last packet, in milliseconds.
+    pub const LAST_SWITCHED: u16 = 21;
+    /// Uptime at the first packet, in milliseconds.
+    pub const FIRST_SWITCHED: u16 = 22;
+    /// IPv6 source address.
+    pub const IPV6_SRC_ADDR: u16 = 27;
+    /// IPv6 destination address.
+    pub const IPV6_DST_ADDR: u16 = 28;
+}
+
+/// Error type for NetFlow exports.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum NetFlowError {
+    /// Invalid NetFlow length.
+    #[error("Invalid NetFlow length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Unsupported NetFlow version.
+    #[error("Unsupported NetFlow version {0}")]
+    UnsupportedVersion(u16),
+
+    /// Invalid flowset or template.
+    #[error("Invalid NetFlow flowset: {0}")]
+    InvalidFlowSet(&'static str),
+}
+
+/// Get the NetFlow version of an export.
+pub fn version(data: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(..2)?.try_into().ok()?))
+}
+
+pub(crate) fn be16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// NetFlow v5 flow record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetFlowV5Record {
+    /// Source address.
+    pub src: Ipv4Addr,
+    /// Destination address.
+    pub dst: Ipv4Addr,
+    /// Next hop router.
+    pub next_hop: Ipv4Addr,
+    /// SNMP index of the input interface.
+    pub input: u16,
+    /// SNMP index of the output interface.
+    pub output: u16,
+    /// Number of packets.
+    pub packets: u32,
+    /// Number of bytes of the IP packets.
+    pub bytes: u32,
+    /// Uptime at the first packet, in milliseconds.
+    pub first: u32,
+    /// Uptime at the last packet, in milliseconds.
+    pub last: u32,
+    /// Source port.
+    pub src_port: u16,
+    /// Destination port.
+    pub dst_port: u16,
+    /// Union of the TCP flags.
+    pub tcp_flags: u8,
+    /// IP protocol.
+    pub protocol: IpProtocol,
+    /// Type of service.
+    pub tos: u8,
+    /// Source AS.
+    pub src_as: u16,
+    /// Destination AS.
+    pub dst_as: u16,
+    /// Source prefix length.
+    pub src_mask: u8,
+    /// Destination prefix length.
+    pub dst_mask: u8,
+}
+
+impl NetFlowV5Record {
+    /// Get the flow key of the record.
+    pub fn flow_key(&self) -> FlowKey {
+        FlowKey::new(
+            self.src,
+            self.dst,
+            self.src_port,
+            self.dst_port,
+            self.protocol,
+        )
+    }
+}
+
+/// NetFlow v5 export.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetFlowV5 {
+    /// Uptime of the exporter, in milliseconds.
+    pub sys_uptime: u32,
+    /// Export time, in seconds since the epoch.
+    pub unix_secs: u32,
+    /// Nanoseconds of the export time.
+    pub unix_nsecs: u32,
+    /// Sequence number of the first record.
+    pub flow_sequence: u32,
+    /// Type of the flow switching engine.
+    pub engine_type: u8,
+    /// Slot number of the flow switching engine.
+    pub engine_id: u8,
+    /// Sampling mode (2 bits) and interval (14 bits).
+    pub sampling_interval: u16,
+    /// Flow records.
+    pub records: Vec<NetFlowV5Record>,
+}
+
+impl NetFlowV5 {
+    /// Decode a NetFlow v5 export.
+    pub fn parse(data: &[u8]) -> Result<Self, NetFlowError> {
+        if data.len() < V5_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "NetFlow",
+                "header",
+                0,
+                V5_HEADER_LENGTH,
+                data.len(),
+            )
+            .into());
+        }
+        match be16(data, 0) {
+            5 => {}
+            version => return Err(NetFlowError::UnsupportedVersion(version)),
+        }
+        let count = be16(data, 2) as usize;
+        let expected = V5_HEADER_LENGTH + count * V5_RECORD_LENGTH;
+        if data.len() < expected {
+            return Err(LayerError::min_length(
+                "NetFlow",
+                "records",
+                V5_HEADER_LENGTH,
+                expected,
+                data.len(),
+            )
+            .into());
+        }
+
+        let addr = |r: &[u8], offset| Ipv4Addr::from(be32(r, offset));
+        let records = data[V5_HEADER_LENGTH..expected]
+            .chunks_exact(V5_RECORD_LENGTH)
+            .map(|r| NetFlowV5Record {
+                src: addr(r, 0),
+                dst: addr(r, 4),
+                next_hop: addr(r, 8),
+                input: be16(r, 12),
+                output: be16(r, 14),
+                packets: be32(r, 16),
+                bytes: be32(r, 20),
+                first: be32(r, 24),
+                last: be32(r, 28),
+                src_port: be16(r, 32),
+                dst_port: be16(r, 34),
+                tcp_flags: r[37],
+                protocol: IpProtocol::from(r[38]),
+                tos: r[39],
+                src_as: be16(r, 40),
+                dst_as: be16(r, 42),
+                src_mask: r[44],
+                dst_mask: r[45],
+            })
+            .collect();
+
+        Ok(Self {
+            sys_uptime: be32(data, 4),
+            unix_secs: be32(data, 8),
+            unix_nsecs: be32(data, 12),
+            flow_sequence: be32(data, 16),
+            engine_type: data[20],
+            engine_id: data[21],
+            sampling_interval: be16(data, 22),
+            records,
+        })
+    }
+
+    /// Get the sampling interval, 1 if unsampled.
+    pub fn sampling_rate(&self) -> u32 {
+        (self.sampling_interval & 0x3FFF).max(1) as u32
+    }
+}
+
+/// Field of a template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TemplateField {
+    /// Field type.
+    pub field_type: u16,
+    /// Length of the field in records.
+    pub length: u16,
+}
+
+/// Templates of the exporters, by source ID and template ID.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateCache {
+    templates: HashMap<(u32, u16), Vec<TemplateField>>,
+}
+
+impl TemplateCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a template.
+    pub fn get(&self, source_id: u32, template_id: u16) -> Option<&[TemplateField]> {
+        self.templates
+            .get(&(source_id, template_id))
+            .map(|t| t.as_slice())
+    }
+
+    /// Insert or replace a template.
+    pub fn insert(&mut self, source_id: u32, template_id: u16, fields: Vec<TemplateField>) {
+        self.templates.insert((source_id, template_id), fields);
+    }
+
+    /// Get the number of templates.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+/// Flow record decoded with a template.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TemplateRecord {
+    /// ID of the template.
+    pub template_id: u16,
+    /// Fields, in template order.
+    pub fields: Vec<(u16, Vec<u8>)>,
+}
+
+impl TemplateRecord {
+    /// Get the value of the first field of a type.
+    pub fn get(&self, field_type: u16) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == field_type)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Get an unsigned field of up to 8 bytes.
+    pub fn get_uint(&self, field_type: u16) -> Option<u64> {
+        let value = self.get(field_type)?;
+        if value.len() > 8 {
+            return None;
+        }
+        Some(value.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    /// Get the number of packets.
+    pub fn packets(&self) -> Option<u64> {
+        self.get_uint(field::IN_PKTS)
+    }
+
+    /// Get the number of bytes.
+    pub fn bytes(&self) -> Option<u64> {
+        self.get_uint(field::IN_BYTES)
+    }
+
+    /// Get the flow key, if the record has IPv4 or IPv6 addresses.
+    ///
+    /// Missing ports and protocol are zero.
+    pub fn flow_key(&self) -> Option<FlowKey> {
+        let addr = |v4, v6| -> Option<IpAddr> {
+            match (self.get(v4), self.get(v6)) {
+                (Some(addr), _) => Some(Ipv4Addr::from(<[u8; 4]>::try_from(addr).ok()?).into()),
+                (_, Some(addr)) => Some(Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?).into()),
+                _ => None,
+            }
+        };
+        let src = addr(field::IPV4_SRC_ADDR, field::IPV6_SRC_ADDR)?;
+        let dst = addr(field::IPV4_DST_ADDR, field::IPV6_DST_ADDR)?;
+        let port = |field_type| self.get_uint(field_type).unwrap_or(0) as u16;
+        Some(FlowKey::new(
+            src,
+            dst,
+            port(field::L4_SRC_PORT),
+            port(field::L4_DST_PORT),
+            IpProtocol::from(self.get_uint(field::PROTOCOL).unwrap_or(0) as u8),
+        ))
+    }
+}
+
+/// NetFlow v9 export.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetFlowV9 {
+    /// Uptime of the exporter, in milliseconds.
+    pub sys_uptime: u32,
+    /// Export time, in seconds since the epoch.
+    pub unix_secs: u32,
+    /// Sequence number of the export.
+    pub sequence: u32,
+    /// ID of the exporter observation domain.
+    pub source_id: u32,
+    /// IDs of the templates defined by the export.
+    pub templates: Vec<u16>,
+    /// Flow records of known templates.
+    pub records: Vec<TemplateRecord>,
+    /// IDs of the data flowsets skipped for lack of a template.
+    pub missing_templates: Vec<u16>,
+}
+
+impl NetFlowV9 {
+    /// Decode a NetFlow v9 export, learning its templates.
+    ///
+    /// Data flowsets whose template was not received yet are skipped and
+    /// listed in [`missing_templates`](Self::missing_templates).
+    pub fn parse(data: &[u8], templates: &mut TemplateCache) -> Result<Self, NetFlowError> {
+        if data.len() < V9_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "NetFlow",
+                "header",
+                0,
+                V9_HEADER_LENGTH,
+                data.len(),
+            )
+            .into());
+        }
+        match be16(data, 0) {
+            9 => {}
+            version => return Err(NetFlowError::UnsupportedVersion(version)),
+        }
+
+        let mut export = Self {
+            sys_uptime: be32(data, 4),
+            unix_secs: be32(data, 8),
+            sequence: be32(data, 12),
+            source_id: be32(data, 16),
+            ..Default::default()
+        };
+
+        let mut sets = &data[V9_HEADER_LENGTH..];
+        while sets.len() >= 4 {
+            let (id, length) = (be16(sets, 0), be16(sets, 2) as usize);
+            if length < 4 || length > sets.len() {
+                return Err(NetFlowError::InvalidFlowSet("invalid flowset length"));
+            }
+            let body = &sets[4..length];
+            sets = &sets[length..];
+
+            match id {
+                V9_TEMPLATE_FLOWSET => {
+                    let mut body = body;
+                    while body.len() >= 4 {
+                        let (template_id, count) = (be16(body, 0), be16(body, 2) as usize);
+                        if body.len() < 4 + count * 4 {
+                            return Err(NetFlowError::InvalidFlowSet("truncated template"));
+                        }
+                        let fields = body[4..4 + count * 4]
+                            .chunks_exact(4)
+                            .map(|f| TemplateField {
+                                field_type: be16(f, 0),
+                                length: be16(f, 2),
+                            })
+                            .collect();
+                        templates.insert(export.source_id, template_id, fields);
+                        export.templates.push(template_id);
+                        body = &body[4 + count * 4..];
+                    }
+                }
+                V9_OPTIONS_TEMPLATE_FLOWSET | 2..=255 => {}
+                template_id => match templates.get(export.source_id, template_id) {
+                    Some(fields) => {
+                        let records = decode_records(template_id, fields, body);
+                        export.records.extend(records);
+                    }
+                    None => export.missing_templates.push(template_id),
+                },
+            }
+        }
+        Ok(export)
+    }
+}
+
+/// Decode the records of a data flowset, ignoring the trailing padding.
+pub(crate) fn decode_records(
+    template_id: u16,
+    fields: &[TemplateField],
+    mut body: &[u8],
+) -> Vec<TemplateRecord> {
+    let record_length: usize = fields.iter().map(|f| f.length as usize).sum();
+    let mut records = Vec::new();
+    if record_length == 0 {
+        return records;
+    }
+    while body.len() >= record_length {
+        let mut offset = 0;
+        let fields = fields
+            .iter()
+            .map(|f| {
+                let value = body[offset..offset + f.length as usize].to_vec();
+                offset += f.length as usize;
+                (f.field_type, value)
+            })
+            .collect();
+        records.push(TemplateRecord {
+            template_id,
+            fields,
+        });
+        body = &body[record_length..];
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netflow_parse() {
+        let mut v5 = vec![
+            0, 5, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0,
+        ];
+        v5.extend(0x4010u16.to_be_bytes());
+        let mut record = [0u8; V5_RECORD_LENGTH];
+        record[..4].copy_from_slice(&[10, 0, 0, 1]);
+        record[4..8].copy_from_slice(&[10, 0, 0, 2]);
+        record[16..20].copy_from_slice(&7u32.to_be_bytes());
+        record[32..34].copy_from_slice(&1234u16.to_be_bytes());
+        record[34..36].copy_from_slice(&80u16.to_be_bytes());
+        record[38] = 6;
+        v5.extend(record);
+
+        let export = NetFlowV5::parse(&v5).unwrap();
+        assert_eq!(export.sampling_rate(), 16);
+        assert_eq!(export.records[0].packets, 7);
+        assert_eq!(
+            export.records[0].flow_key().to_string(),
+            "Tcp 10.0.0.1:1234 -> 10.0.0.2:80"
+        );
+        assert_eq!(
+            NetFlowV5::parse(&v5[..50]),
+            Err(NetFlowError::InvalidLength(LayerError::min_length(
+                "NetFlow", "records", 24, 72, 50
+            )))
+        );
+
+        // Data before its template is skipped, then decoded once the
+        // template is known. Records are padded to 4 bytes.
+        let header = [0, 9, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let data = [1, 0, 0, 12, 0, 80, 17, 1, 187, 6, 0, 0];
+        let template = [0, 0, 0, 16, 1, 0, 0, 2, 0, 11, 0, 2, 0, 4, 0, 1];
+        let mut templates = TemplateCache::new();
+        let export = NetFlowV9::parse(&[&header[..], &data].concat(), &mut templates).unwrap();
+        assert_eq!(export.missing_templates, [256]);
+
+        let export =
+            NetFlowV9::parse(&[&header[..], &template, &data].concat(), &mut templates).unwrap();
+        assert_eq!(export.templates, [256]);
+        assert_eq!(export.records.len(), 2);
+        assert_eq!(export.records[1].get_uint(field::L4_DST_PORT), Some(443));
+        assert_eq!(export.records[1].get_uint(field::PROTOCOL), Some(6));
+        assert_eq!(export.records[1].flow_key(), None);
+
+        // Templates are per source ID.
+        let mut other = header;
+        other[19] = 2;
+        let export = NetFlowV9::parse(&[&other[..], &data].concat(), &mut templates).unwrap();
+        assert_eq!(export.missing_templates, [256]);
+    }
+}
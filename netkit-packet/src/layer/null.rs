@@ -0,0 +1,159 @@
+//! BSD loopback (Null) layer.
+
+use crate::prelude::*;
+
+/// Error type for Null layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum NullError {
+    /// Invalid Null length.
+    #[error("Invalid Null length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+/// Minimum length of a Null header.
+pub const MIN_HEADER_LENGTH: usize = 4;
+
+/// Address family of IPv4.
+pub const AF_INET: u32 = 2;
+
+/// Address families of IPv6 on the BSDs, macOS and Linux.
+pub const AF_INET6: [u32; 4] = [24, 28, 30, 10];
+
+/// BSD loopback (Null) layer.
+///
+/// The header is a single 4-byte address family word. For `LINKTYPE_NULL`
+/// it is in the byte order of the capturing host, for `LINKTYPE_LOOP` in
+/// network byte order; [`family`](Null::family) accepts both.
+pub struct Null<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Null<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the address family: 0..4
+    pub const FIELD_FAMILY: core::ops::Range<usize> = 0..4;
+    /// Field range of the payload: 4..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 4..;
+
+    /// Create a new Null layer from raw data without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Null packet.
+    ///
+    /// The data must be at least 4 bytes long. Otherwise, the following
+    /// methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Null layer.
+    pub fn validate(&self) -> Result<(), NullError> {
+        if self.data.as_ref().len() < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "Null",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Null layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, NullError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the address family, whatever the byte order it is written in.
+    pub fn family(&self) -> u32 {
+        let family = &self.data.as_ref()[Self::FIELD_FAMILY];
+        let family = u32::from_le_bytes([family[0], family[1], family[2], family[3]]);
+        // Families are small, so a value in the upper half is big-endian.
+        if family > 0xFFFF {
+            family.swap_bytes()
+        } else {
+            family
+        }
+    }
+
+    /// Get the Eth type equivalent to the address family.
+    pub fn eth_type(&self) -> EthType {
+        match self.family() {
+            AF_INET => EthType::Ipv4,
+            family if AF_INET6.contains(&family) => EthType::Ipv6,
+            _ => EthType::default(),
+        }
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Get the IPv4 layer if the address family is IPv4.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.eth_type() == EthType::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Null<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(Null);
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn null_family() {
+        let le = Null::new([2u8, 0, 0, 0]).unwrap();
+        assert_eq!(le.family(), 2);
+        assert_eq!(le.eth_type(), EthType::Ipv4);
+
+        let be = Null::new([0u8, 0, 0, 30]).unwrap();
+        assert_eq!(be.family(), 30);
+        assert_eq!(be.eth_type(), EthType::Ipv6);
+
+        assert!(Null::new([0u8; 3]).is_err());
+    }
+}
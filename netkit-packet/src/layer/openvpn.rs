@@ -0,0 +1,355 @@
+//! OpenVPN layer.
+
+use crate::{field_spec, prelude::*};
+
+pub mod opcode;
+pub use opcode::OpenVpnOpcode;
+
+/// Error type for OpenVpn layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum OpenVpnError {
+    /// Invalid OpenVpn length.
+    #[error("Invalid OpenVpn length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Invalid OpenVpn opcode.
+    #[error("Invalid OpenVpn opcode: {0}")]
+    InvalidOpcode(u8),
+}
+
+field_spec!(OpcodeSpec, OpenVpnOpcode, u8, 0xF8, 3);
+field_spec!(KeyIdSpec, u8, u8, 0x07);
+field_spec!(SessionIdSpec, u64, u64);
+field_spec!(PeerIdSpec, u32, u32, 0x00FF_FFFF);
+
+/// Minimum length of an OpenVpn header.
+pub const MIN_HEADER_LENGTH: usize = 1;
+
+/// Default port of OpenVPN, over both UDP and TCP.
+pub const DEFAULT_PORT: u16 = 1194;
+
+/// OpenVPN layer.
+///
+/// This is a shallow parser of the OpenVPN wire protocol: the opcode and key
+/// ID of every packet, the session ID of control channel packets and the
+/// peer ID of `P_DATA_V2` packets. Over TCP, packets are prefixed with a
+/// 16-bit length, which must be stripped first (see [`Tcp::openvpn`]).
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |     Opcode   | Key ID |   Session ID (64, control) ...
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |     Opcode   | Key ID |   Peer ID (24, P_DATA_V2) ...
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct OpenVpn<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> OpenVpn<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the opcode: 0..1 (5bits)
+    pub const FIELD_OPCODE: core::ops::Range<usize> = 0..1;
+    /// Field range of the key ID: 0..1 (3bits)
+    pub const FIELD_KEY_ID: core::ops::Range<usize> = 0..1;
+    /// Field range of the session ID of control packets: 1..9
+    pub const FIELD_SESSION_ID: core::ops::Range<usize> = 1..9;
+    /// Field range of the peer ID of `P_DATA_V2` packets: 0..4 (24bits)
+    pub const FIELD_PEER_ID: core::ops::Range<usize> = 0..4;
+
+    /// Create a new OpenVpn layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid OpenVpn packet.
+    ///
+    /// The data must be at least as long as the header required by the
+    /// opcode. Otherwise, the following methods may panic when accessing the
+    /// fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the OpenVpn layer.
+    pub fn validate(&self) -> Result<(), OpenVpnError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(
+                LayerError::min_length("OpenVpn", "header", 0, MIN_HEADER_LENGTH, len).into(),
+            );
+        }
+
+        if let OpenVpnOpcode::Unknown(opcode) = self.opcode().get() {
+            return Err(OpenVpnError::InvalidOpcode(opcode));
+        }
+
+        if len < self.header_len() {
+            return Err(
+                LayerError::min_length("OpenVpn", "header", 0, self.header_len(), len).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Create a new OpenVpn layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, OpenVpnError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the length of the header parsed by this layer.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        match self.opcode().get() {
+            OpenVpnOpcode::DataV1 => 1,
+            OpenVpnOpcode::DataV2 => 4,
+            opcode if opcode.is_control() => 9,
+            _ => 1,
+        }
+    }
+
+    /// Get the accessor of the opcode.
+    #[inline]
+    pub fn opcode(&self) -> &Field<OpcodeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_OPCODE])
+    }
+
+    /// Get the accessor of the key ID.
+    #[inline]
+    pub fn key_id(&self) -> &Field<KeyIdSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_KEY_ID])
+    }
+
+    /// Get the accessor of the session ID of control packets.
+    #[inline]
+    pub fn session_id(&self) -> Option<&Field<SessionIdSpec>> {
+        if self.opcode().get().is_control() {
+            Some(cast_from_bytes(&self.data.as_ref()[Self::FIELD_SESSION_ID]))
+        } else {
+            None
+        }
+    }
+
+    /// Get the accessor of the peer ID of `P_DATA_V2` packets.
+    #[inline]
+    pub fn peer_id(&self) -> Option<&Field<PeerIdSpec>> {
+        if self.opcode().get() == OpenVpnOpcode::DataV2 {
+            Some(cast_from_bytes(&self.data.as_ref()[Self::FIELD_PEER_ID]))
+        } else {
+            None
+        }
+    }
+
+    /// Get the payload following the parsed header.
+    ///
+    /// For control packets this starts with the (optional) HMAC or the ACK
+    /// array, for data packets it is the encrypted data.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+}
+
+impl<T> OpenVpn<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the opcode.
+    #[inline]
+    pub fn opcode_mut(&mut self) -> &mut Field<OpcodeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_OPCODE])
+    }
+
+    /// Get the mutable accessor of the key ID.
+    #[inline]
+    pub fn key_id_mut(&mut self) -> &mut Field<KeyIdSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_KEY_ID])
+    }
+
+    /// Get the mutable accessor of the session ID of control packets.
+    #[inline]
+    pub fn session_id_mut(&mut self) -> Option<&mut Field<SessionIdSpec>> {
+        if self.opcode().get().is_control() {
+            Some(cast_from_bytes_mut(
+                &mut self.data.as_mut()[Self::FIELD_SESSION_ID],
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Get the mutable accessor of the peer ID of `P_DATA_V2` packets.
+    #[inline]
+    pub fn peer_id_mut(&mut self) -> Option<&mut Field<PeerIdSpec>> {
+        if self.opcode().get() == OpenVpnOpcode::DataV2 {
+            Some(cast_from_bytes_mut(
+                &mut self.data.as_mut()[Self::FIELD_PEER_ID],
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[header_len..]
+    }
+}
+
+layer_impl!(OpenVpn);
+
+/// Builder for [`OpenVpn`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct OpenVpnBuilder {
+    opcode: Option<OpenVpnOpcode>,
+    key_id: Option<u8>,
+    session_id: Option<u64>,
+    peer_id: Option<u32>,
+    payload: Vec<u8>,
+}
+
+impl OpenVpnBuilder {
+    /// Create a new OpenVpn builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the opcode.
+    pub fn opcode(&mut self, opcode: impl Into<OpenVpnOpcode>) -> &mut Self {
+        self.opcode = Some(opcode.into());
+        self
+    }
+
+    /// Set the key ID.
+    pub fn key_id(&mut self, key_id: impl Into<u8>) -> &mut Self {
+        self.key_id = Some(key_id.into());
+        self
+    }
+
+    /// Set the session ID, ignored unless a control packet is built.
+    pub fn session_id(&mut self, session_id: impl Into<u64>) -> &mut Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Set the peer ID, ignored unless a `P_DATA_V2` packet is built.
+    pub fn peer_id(&mut self, peer_id: impl Into<u32>) -> &mut Self {
+        self.peer_id = Some(peer_id.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the OpenVpn layer.
+    pub fn build(&self) -> OpenVpn<Vec<u8>> {
+        let opcode = self.opcode.unwrap_or(OpenVpnOpcode::DataV2);
+        let header_len = match opcode {
+            OpenVpnOpcode::DataV2 => 4,
+            opcode if opcode.is_control() => 9,
+            _ => 1,
+        };
+
+        let mut openvpn =
+            unsafe { OpenVpn::new_unchecked(vec![0; header_len + self.payload.len()]) };
+
+        openvpn.opcode_mut().set(opcode);
+        openvpn.key_id_mut().set(self.key_id.unwrap_or_default());
+        if let Some(session_id) = openvpn.session_id_mut() {
+            session_id.set(self.session_id.unwrap_or_default());
+        }
+        if let Some(peer_id) = openvpn.peer_id_mut() {
+            peer_id.set(self.peer_id.unwrap_or_default());
+        }
+        openvpn.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        openvpn
+    }
+}
+
+/// Create an OpenVpn layer with the given fields.
+#[macro_export]
+macro_rules! openvpn {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::openvpn::OpenVpnBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openvpn_control() {
+        let data: [u8; 14] = [
+            0x38, // P_CONTROL_HARD_RESET_CLIENT_V2, key id 0
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // session id
+            0x00, // ack array length
+            0x00, 0x00, 0x00, 0x00, // packet id
+        ];
+
+        let openvpn = OpenVpn::new(data).unwrap();
+        assert_eq!(
+            openvpn.opcode().get(),
+            OpenVpnOpcode::ControlHardResetClientV2
+        );
+        assert_eq!(openvpn.key_id().get(), 0);
+        assert_eq!(openvpn.session_id().unwrap().get(), 0x0102030405060708);
+        assert!(openvpn.peer_id().is_none());
+        assert_eq!(openvpn.payload().len(), 5);
+
+        assert_eq!(
+            OpenVpn::new(&data[..5]).err(),
+            Some(OpenVpnError::InvalidLength(LayerError::min_length(
+                "OpenVpn", "header", 0, 9, 5
+            )))
+        );
+        assert_eq!(
+            OpenVpn::new([0xf8]).err(),
+            Some(OpenVpnError::InvalidOpcode(31))
+        );
+    }
+
+    #[test]
+    fn openvpn_data_v2() {
+        let openvpn = openvpn!(key_id: 1u8, peer_id: 0x00abcdefu32, payload: [0xaa; 8]);
+
+        assert_eq!(&openvpn.inner()[..4], &[0x49, 0xab, 0xcd, 0xef]);
+        assert_eq!(openvpn.opcode().get(), OpenVpnOpcode::DataV2);
+        assert_eq!(openvpn.key_id().get(), 1);
+        assert_eq!(openvpn.peer_id().unwrap().get(), 0x00abcdef);
+        assert!(openvpn.session_id().is_none());
+        assert_eq!(openvpn.payload(), &[0xaa; 8]);
+    }
+}
@@ -0,0 +1,119 @@
+//! OpenVPN Opcode
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// OpenVPN Opcode
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum OpenVpnOpcode {
+    /// Initial key from client, forget previous state
+    ControlHardResetClientV1 = 1,
+
+    /// Initial key from server, forget previous state
+    ControlHardResetServerV1 = 2,
+
+    /// New key, graceful transition from old to new key
+    ControlSoftResetV1 = 3,
+
+    /// Control channel packet
+    ControlV1 = 4,
+
+    /// Acknowledgement of control channel packets
+    AckV1 = 5,
+
+    /// Data channel packet
+    DataV1 = 6,
+
+    /// Initial key from client, forget previous state
+    ControlHardResetClientV2 = 7,
+
+    /// Initial key from server, forget previous state
+    ControlHardResetServerV2 = 8,
+
+    /// Data channel packet with peer ID
+    DataV2 = 9,
+
+    /// Initial key from client with tls-crypt-v2 wrapped key
+    ControlHardResetClientV3 = 10,
+
+    /// Control channel packet with wrapped client key
+    ControlWkcV1 = 11,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl OpenVpnOpcode {
+    /// Whether the opcode belongs to the control channel.
+    pub fn is_control(&self) -> bool {
+        !matches!(
+            self,
+            OpenVpnOpcode::DataV1 | OpenVpnOpcode::DataV2 | OpenVpnOpcode::Unknown(_)
+        )
+    }
+
+    /// Whether the opcode belongs to the data channel.
+    pub fn is_data(&self) -> bool {
+        matches!(self, OpenVpnOpcode::DataV1 | OpenVpnOpcode::DataV2)
+    }
+}
+
+impl_target!(frominto, OpenVpnOpcode, u8);
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn opcode_str() {
+        test_enum_str!(
+            OpenVpnOpcode,
+            ControlHardResetClientV2 => "ControlHardResetClientV2",
+            ControlV1 => "ControlV1",
+            AckV1 => "AckV1",
+            DataV2 => "DataV2",
+        );
+    }
+
+    #[test]
+    fn opcode_num() {
+        test_enum_num!(
+            OpenVpnOpcode : u8,
+            ControlHardResetClientV1 => 1,
+            ControlHardResetServerV1 => 2,
+            ControlSoftResetV1 => 3,
+            ControlV1 => 4,
+            AckV1 => 5,
+            DataV1 => 6,
+            ControlHardResetClientV2 => 7,
+            ControlHardResetServerV2 => 8,
+            DataV2 => 9,
+            ControlHardResetClientV3 => 10,
+            ControlWkcV1 => 11,
+        );
+    }
+}
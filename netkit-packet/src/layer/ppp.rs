@@ -0,0 +1,275 @@
+//! Point-to-Point Protocol (PPP) layer.
+
+use crate::prelude::*;
+
+pub mod protocol;
+pub use protocol::PppProtocol;
+
+/// Error type for Ppp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PppError {
+    /// Invalid Ppp length.
+    #[error("Invalid Ppp length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+/// Address and control field value of a PPP frame in HDLC-like framing.
+pub const ADDRESS_CONTROL: [u8; 2] = [0xFF, 0x03];
+
+/// Minimum length of a Ppp header (a compressed protocol field).
+pub const MIN_HEADER_LENGTH: usize = 1;
+
+/// Point-to-Point Protocol (PPP) layer.
+///
+/// See [RFC 1661](https://datatracker.ietf.org/doc/html/rfc1661) and
+/// [RFC 1662](https://datatracker.ietf.org/doc/html/rfc1662). Both the
+/// address and control field compression and the protocol field compression
+/// are recognized.
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |  Address (optional)   |  Control (optional)   |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                     Protocol (8 or 16 bits)   |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct Ppp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Ppp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Create a new Ppp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Ppp frame.
+    ///
+    /// The data must be at least as long as the header. Otherwise, the
+    /// following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Ppp layer.
+    pub fn validate(&self) -> Result<(), PppError> {
+        let data = self.data.as_ref();
+        let len = data.len();
+        let offset = if data.starts_with(&ADDRESS_CONTROL) {
+            2
+        } else {
+            0
+        };
+
+        if len <= offset {
+            return Err(LayerError::min_length(
+                "Ppp",
+                "protocol",
+                offset,
+                offset + MIN_HEADER_LENGTH,
+                len,
+            )
+            .into());
+        }
+
+        if len < self.header_len() {
+            return Err(
+                LayerError::min_length("Ppp", "protocol", offset, self.header_len(), len).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Ppp layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, PppError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Whether the address and control fields are present.
+    #[inline]
+    pub fn has_address_control(&self) -> bool {
+        self.data.as_ref().starts_with(&ADDRESS_CONTROL)
+    }
+
+    #[inline]
+    fn protocol_offset(&self) -> usize {
+        if self.has_address_control() {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Whether the protocol field is compressed to a single byte.
+    ///
+    /// Uncompressed protocol numbers always have an even high byte.
+    #[inline]
+    pub fn is_protocol_compressed(&self) -> bool {
+        self.data.as_ref()[self.protocol_offset()] & 0x01 != 0
+    }
+
+    /// Get the length of the header.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        self.protocol_offset() + if self.is_protocol_compressed() { 1 } else { 2 }
+    }
+
+    /// Get the protocol.
+    #[inline]
+    pub fn protocol(&self) -> PppProtocol {
+        let data = &self.data.as_ref()[self.protocol_offset()..];
+        if self.is_protocol_compressed() {
+            PppProtocol::from(data[0] as u16)
+        } else {
+            PppProtocol::from(u16::from_be_bytes([data[0], data[1]]))
+        }
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+
+    /// Get the IPv4 layer if the protocol is IPv4.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.protocol() == PppProtocol::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Ppp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let header_len = self.header_len();
+        &mut self.data.as_mut()[header_len..]
+    }
+}
+
+layer_impl!(Ppp);
+
+/// Builder for [`Ppp`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct PppBuilder {
+    protocol: Option<PppProtocol>,
+    compress_address_control: bool,
+    payload: Vec<u8>,
+}
+
+impl PppBuilder {
+    /// Create a new Ppp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the protocol.
+    pub fn protocol(&mut self, protocol: impl Into<PppProtocol>) -> &mut Self {
+        self.protocol = Some(protocol.into());
+        self
+    }
+
+    /// Set whether the address and control fields are omitted.
+    pub fn compress_address_control(&mut self, compress: bool) -> &mut Self {
+        self.compress_address_control = compress;
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Ppp layer.
+    pub fn build(&self) -> Ppp<Vec<u8>> {
+        let protocol: u16 = self.protocol.unwrap_or(PppProtocol::Ipv4).into();
+
+        let mut data = Vec::with_capacity(4 + self.payload.len());
+        if !self.compress_address_control {
+            data.extend_from_slice(&ADDRESS_CONTROL);
+        }
+        data.extend_from_slice(&protocol.to_be_bytes());
+        data.extend_from_slice(self.payload.as_ref());
+
+        unsafe { Ppp::new_unchecked(data) }
+    }
+}
+
+/// Create a Ppp layer with the given fields.
+#[macro_export]
+macro_rules! ppp {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::ppp::PppBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppp_compression() {
+        let full = Ppp::new([0xFF, 0x03, 0xC0, 0x21, 0x01]).unwrap();
+        assert!(full.has_address_control());
+        assert!(!full.is_protocol_compressed());
+        assert_eq!(full.header_len(), 4);
+        assert_eq!(full.protocol(), PppProtocol::Lcp);
+        assert_eq!(full.payload(), &[0x01]);
+
+        let compressed = Ppp::new([0x21, 0x45]).unwrap();
+        assert!(!compressed.has_address_control());
+        assert!(compressed.is_protocol_compressed());
+        assert_eq!(compressed.protocol(), PppProtocol::Ipv4);
+        assert_eq!(compressed.payload(), &[0x45]);
+
+        assert_eq!(
+            Ppp::new([0xFF, 0x03, 0x00]).err(),
+            Some(PppError::InvalidLength(LayerError::min_length(
+                "Ppp", "protocol", 2, 4, 3
+            )))
+        );
+    }
+
+    #[test]
+    fn ppp_ipv4() {
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp);
+        let ppp = ppp!(protocol: PppProtocol::Ipv4, payload: ipv4.inner());
+
+        assert_eq!(&ppp.inner()[..4], &[0xFF, 0x03, 0x00, 0x21]);
+        assert_eq!(ppp.ipv4().unwrap().protocol().get(), IpProtocol::Udp);
+    }
+}
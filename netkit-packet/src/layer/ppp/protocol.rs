@@ -0,0 +1,92 @@
+//! PPP Protocol
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// PPP Protocol
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u16)]
+#[non_exhaustive]
+pub enum PppProtocol {
+    /// Internet Protocol version 4
+    Ipv4 = 0x0021,
+
+    /// Internet Protocol version 6
+    Ipv6 = 0x0057,
+
+    /// IP Control Protocol
+    Ipcp = 0x8021,
+
+    /// IPv6 Control Protocol
+    Ipv6cp = 0x8057,
+
+    /// Compression Control Protocol
+    Ccp = 0x80FD,
+
+    /// Link Control Protocol
+    Lcp = 0xC021,
+
+    /// Password Authentication Protocol
+    Pap = 0xC023,
+
+    /// Challenge Handshake Authentication Protocol
+    Chap = 0xC223,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+impl_target!(frominto, PppProtocol, u16);
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn protocol_str() {
+        test_enum_str!(
+            PppProtocol,
+            Ipv4 => "Ipv4",
+            Ipv6 => "Ipv6",
+            Lcp => "Lcp",
+            Chap => "Chap",
+        );
+    }
+
+    #[test]
+    fn protocol_num() {
+        test_enum_num!(
+            PppProtocol : u16,
+            Ipv4 => 0x0021,
+            Ipv6 => 0x0057,
+            Ipcp => 0x8021,
+            Ipv6cp => 0x8057,
+            Ccp => 0x80FD,
+            Lcp => 0xC021,
+            Pap => 0xC023,
+            Chap => 0xC223,
+        );
+    }
+}
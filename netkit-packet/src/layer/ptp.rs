@@ -0,0 +1,586 @@
+//! Precision Time Protocol (PTP, IEEE 1588) layer.
+
+use crate::{field_spec, prelude::*};
+
+pub mod message_type;
+pub use message_type::PtpMessageType;
+
+pub mod timestamp;
+pub use timestamp::{ClockIdentity, PtpTimestamp, TIMESTAMP_LENGTH};
+
+/// Error type for Ptp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PtpError {
+    /// Invalid Ptp length.
+    #[error("Invalid Ptp length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(TransportSpecificSpec, u8, u8, 0xF0, 4);
+field_spec!(MessageTypeSpec, PtpMessageType, u8, 0x0F);
+field_spec!(VersionSpec, u8, u8, 0x0F);
+field_spec!(MessageLengthSpec, u16, u16);
+field_spec!(DomainNumberSpec, u8, u8);
+field_spec!(FlagsSpec, u16, u16);
+field_spec!(CorrectionSpec, u64, u64);
+field_spec!(ClockIdentitySpec, ClockIdentity, u64);
+field_spec!(PortNumberSpec, u16, u16);
+field_spec!(SequenceIdSpec, u16, u16);
+field_spec!(ControlFieldSpec, u8, u8);
+field_spec!(LogMessageIntervalSpec, u8, u8);
+
+/// Length of a Ptp header.
+pub const HEADER_LENGTH: usize = 34;
+
+/// UDP port of event messages.
+pub const EVENT_PORT: u16 = 319;
+
+/// UDP port of general messages.
+pub const GENERAL_PORT: u16 = 320;
+
+/// Default protocol version of built messages (PTPv2).
+pub const DEFAULT_VERSION: u8 = 2;
+
+/// Two-step flag: a Follow_Up carries the precise timestamp.
+pub const FLAG_TWO_STEP: u16 = 0x0200;
+
+/// Unicast flag.
+pub const FLAG_UNICAST: u16 = 0x0400;
+
+/// Precision Time Protocol (PTP) layer.
+///
+/// See IEEE 1588-2019, clause 13. PTP messages are carried in UDP on ports
+/// [`EVENT_PORT`] and [`GENERAL_PORT`], or directly in Ethernet with Eth type
+/// [`EthType::Ptp`].
+///
+/// ```text
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// | Trans.| Type  |  Rsv  |  Ver  |        Message Length         |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// | Domain Number |   Reserved    |          Flag Field           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                       Correction Field                        |
+/// |                           (64 bits)                           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                           Reserved                            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                    Source Clock Identity                      |
+/// |                           (64 bits)                           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |       Source Port Number      |          Sequence Id          |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// | Control Field | Log Msg Intvl |  Body ...
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-
+/// ```
+///
+/// Sync, Delay_Req, Follow_Up, Delay_Resp, the peer delay messages and
+/// Announce all start their body with a [`PtpTimestamp`], see
+/// [`Ptp::timestamp`].
+pub struct Ptp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Ptp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the transport specific nibble: 0..1
+    pub const FIELD_TRANSPORT_SPECIFIC: core::ops::Range<usize> = 0..1;
+    /// Field range of the message type: 0..1
+    pub const FIELD_MESSAGE_TYPE: core::ops::Range<usize> = 0..1;
+    /// Field range of the version: 1..2
+    pub const FIELD_VERSION: core::ops::Range<usize> = 1..2;
+    /// Field range of the message length: 2..4
+    pub const FIELD_MESSAGE_LENGTH: core::ops::Range<usize> = 2..4;
+    /// Field range of the domain number: 4..5
+    pub const FIELD_DOMAIN_NUMBER: core::ops::Range<usize> = 4..5;
+    /// Field range of the flag field: 6..8
+    pub const FIELD_FLAGS: core::ops::Range<usize> = 6..8;
+    /// Field range of the correction field: 8..16
+    pub const FIELD_CORRECTION: core::ops::Range<usize> = 8..16;
+    /// Field range of the source clock identity: 20..28
+    pub const FIELD_CLOCK_IDENTITY: core::ops::Range<usize> = 20..28;
+    /// Field range of the source port number: 28..30
+    pub const FIELD_PORT_NUMBER: core::ops::Range<usize> = 28..30;
+    /// Field range of the sequence id: 30..32
+    pub const FIELD_SEQUENCE_ID: core::ops::Range<usize> = 30..32;
+    /// Field range of the control field: 32..33
+    pub const FIELD_CONTROL_FIELD: core::ops::Range<usize> = 32..33;
+    /// Field range of the log message interval: 33..34
+    pub const FIELD_LOG_MESSAGE_INTERVAL: core::ops::Range<usize> = 33..34;
+    /// Field range of the body timestamp: 34..44
+    pub const FIELD_TIMESTAMP: core::ops::Range<usize> = 34..44;
+    /// Field range of the requesting port identity of a Delay_Resp: 44..54
+    pub const FIELD_REQUESTING_PORT: core::ops::Range<usize> = 44..54;
+
+    /// Create a new Ptp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Ptp message.
+    ///
+    /// The data must be at least as long as the message length. Otherwise,
+    /// the following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Ptp layer.
+    ///
+    /// Trailing bytes after the message, e.g. Ethernet padding, are allowed.
+    pub fn validate(&self) -> Result<(), PtpError> {
+        let len = self.data.as_ref().len();
+        if len < HEADER_LENGTH {
+            return Err(LayerError::min_length("Ptp", "header", 0, HEADER_LENGTH, len).into());
+        }
+
+        let length = self.message_length().get() as usize;
+        if length < HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Ptp",
+                "message length",
+                Self::FIELD_MESSAGE_LENGTH.start,
+                HEADER_LENGTH,
+                length,
+            )
+            .into());
+        }
+        if len < length {
+            return Err(LayerError::min_length("Ptp", "body", HEADER_LENGTH, length, len).into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Ptp layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, PtpError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the transport specific nibble.
+    #[inline]
+    pub fn transport_specific(&self) -> &Field<TransportSpecificSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_TRANSPORT_SPECIFIC])
+    }
+
+    /// Get the accessor of the message type.
+    #[inline]
+    pub fn message_type(&self) -> &Field<MessageTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_MESSAGE_TYPE])
+    }
+
+    /// Get the accessor of the version.
+    #[inline]
+    pub fn version(&self) -> &Field<VersionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION])
+    }
+
+    /// Get the accessor of the message length.
+    #[inline]
+    pub fn message_length(&self) -> &Field<MessageLengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_MESSAGE_LENGTH])
+    }
+
+    /// Get the accessor of the domain number.
+    #[inline]
+    pub fn domain_number(&self) -> &Field<DomainNumberSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DOMAIN_NUMBER])
+    }
+
+    /// Get the accessor of the flag field.
+    #[inline]
+    pub fn flags(&self) -> &Field<FlagsSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_FLAGS])
+    }
+
+    /// Whether the two-step flag is set.
+    #[inline]
+    pub fn is_two_step(&self) -> bool {
+        self.flags().get() & FLAG_TWO_STEP != 0
+    }
+
+    /// Get the accessor of the correction field.
+    ///
+    /// The raw value is a signed number of nanoseconds multiplied by 2^16,
+    /// see [`Ptp::correction_nanos`].
+    #[inline]
+    pub fn correction(&self) -> &Field<CorrectionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CORRECTION])
+    }
+
+    /// Get the correction field in nanoseconds.
+    #[inline]
+    pub fn correction_nanos(&self) -> f64 {
+        self.correction().get() as i64 as f64 / 65536.0
+    }
+
+    /// Get the accessor of the source clock identity.
+    #[inline]
+    pub fn clock_identity(&self) -> &Field<ClockIdentitySpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CLOCK_IDENTITY])
+    }
+
+    /// Get the accessor of the source port number.
+    #[inline]
+    pub fn port_number(&self) -> &Field<PortNumberSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PORT_NUMBER])
+    }
+
+    /// Get the accessor of the sequence id.
+    #[inline]
+    pub fn sequence_id(&self) -> &Field<SequenceIdSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SEQUENCE_ID])
+    }
+
+    /// Get the accessor of the control field.
+    #[inline]
+    pub fn control_field(&self) -> &Field<ControlFieldSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CONTROL_FIELD])
+    }
+
+    /// Get the accessor of the log message interval.
+    ///
+    /// The value is a signed base-2 logarithm of seconds stored as a byte.
+    #[inline]
+    pub fn log_message_interval(&self) -> &Field<LogMessageIntervalSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_LOG_MESSAGE_INTERVAL])
+    }
+
+    /// Get the message body, excluding any trailing padding.
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        &self.data.as_ref()[HEADER_LENGTH..self.message_length().get() as usize]
+    }
+
+    /// Get the timestamp starting the body of the message.
+    ///
+    /// This is the origin timestamp of a Sync, Delay_Req or Announce, the
+    /// precise origin timestamp of a Follow_Up and the receive timestamp of
+    /// a Delay_Resp.
+    pub fn timestamp(&self) -> Option<PtpTimestamp> {
+        match self.message_type().get() {
+            PtpMessageType::Signaling | PtpMessageType::Management | PtpMessageType::Unknown(_) => {
+                None
+            }
+            _ => {
+                let bytes = self.body().get(..TIMESTAMP_LENGTH)?;
+                Some(PtpTimestamp::from_bytes(bytes.try_into().ok()?))
+            }
+        }
+    }
+
+    /// Get the requesting port identity of a Delay_Resp, Pdelay_Resp or
+    /// Pdelay_Resp_Follow_Up.
+    pub fn requesting_port(&self) -> Option<(ClockIdentity, u16)> {
+        match self.message_type().get() {
+            PtpMessageType::DelayResp
+            | PtpMessageType::PdelayResp
+            | PtpMessageType::PdelayRespFollowUp => {
+                let bytes = self.body().get(TIMESTAMP_LENGTH..TIMESTAMP_LENGTH + 10)?;
+                let identity: [u8; 8] = bytes[..8].try_into().ok()?;
+                Some((identity.into(), u16::from_be_bytes([bytes[8], bytes[9]])))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T> Ptp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the transport specific nibble.
+    #[inline]
+    pub fn transport_specific_mut(&mut self) -> &mut Field<TransportSpecificSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_TRANSPORT_SPECIFIC])
+    }
+
+    /// Get the mutable accessor of the message type.
+    #[inline]
+    pub fn message_type_mut(&mut self) -> &mut Field<MessageTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_MESSAGE_TYPE])
+    }
+
+    /// Get the mutable accessor of the version.
+    #[inline]
+    pub fn version_mut(&mut self) -> &mut Field<VersionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION])
+    }
+
+    /// Get the mutable accessor of the message length.
+    #[inline]
+    pub fn message_length_mut(&mut self) -> &mut Field<MessageLengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_MESSAGE_LENGTH])
+    }
+
+    /// Get the mutable accessor of the domain number.
+    #[inline]
+    pub fn domain_number_mut(&mut self) -> &mut Field<DomainNumberSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DOMAIN_NUMBER])
+    }
+
+    /// Get the mutable accessor of the flag field.
+    #[inline]
+    pub fn flags_mut(&mut self) -> &mut Field<FlagsSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_FLAGS])
+    }
+
+    /// Get the mutable accessor of the correction field.
+    #[inline]
+    pub fn correction_mut(&mut self) -> &mut Field<CorrectionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CORRECTION])
+    }
+
+    /// Get the mutable accessor of the source clock identity.
+    #[inline]
+    pub fn clock_identity_mut(&mut self) -> &mut Field<ClockIdentitySpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CLOCK_IDENTITY])
+    }
+
+    /// Get the mutable accessor of the source port number.
+    #[inline]
+    pub fn port_number_mut(&mut self) -> &mut Field<PortNumberSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PORT_NUMBER])
+    }
+
+    /// Get the mutable accessor of the sequence id.
+    #[inline]
+    pub fn sequence_id_mut(&mut self) -> &mut Field<SequenceIdSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SEQUENCE_ID])
+    }
+
+    /// Get the mutable accessor of the control field.
+    #[inline]
+    pub fn control_field_mut(&mut self) -> &mut Field<ControlFieldSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CONTROL_FIELD])
+    }
+
+    /// Get the mutable accessor of the log message interval.
+    #[inline]
+    pub fn log_message_interval_mut(&mut self) -> &mut Field<LogMessageIntervalSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_LOG_MESSAGE_INTERVAL])
+    }
+
+    /// Get the mutable message body.
+    #[inline]
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        let end = self.message_length().get() as usize;
+        &mut self.data.as_mut()[HEADER_LENGTH..end]
+    }
+}
+
+layer_impl!(Ptp);
+
+/// Builder for [`Ptp`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct PtpBuilder {
+    message_type: Option<PtpMessageType>,
+    version: Option<u8>,
+    domain_number: Option<u8>,
+    flags: Option<u16>,
+    correction: Option<u64>,
+    clock_identity: Option<ClockIdentity>,
+    port_number: Option<u16>,
+    sequence_id: Option<u16>,
+    log_message_interval: Option<u8>,
+    timestamp: Option<PtpTimestamp>,
+    body: Vec<u8>,
+}
+
+impl PtpBuilder {
+    /// Create a new Ptp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the message type.
+    pub fn message_type(&mut self, message_type: impl Into<PtpMessageType>) -> &mut Self {
+        self.message_type = Some(message_type.into());
+        self
+    }
+
+    /// Set the version.
+    pub fn version(&mut self, version: impl Into<u8>) -> &mut Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the domain number.
+    pub fn domain_number(&mut self, domain_number: impl Into<u8>) -> &mut Self {
+        self.domain_number = Some(domain_number.into());
+        self
+    }
+
+    /// Set the flag field.
+    pub fn flags(&mut self, flags: impl Into<u16>) -> &mut Self {
+        self.flags = Some(flags.into());
+        self
+    }
+
+    /// Set the raw correction field.
+    pub fn correction(&mut self, correction: impl Into<u64>) -> &mut Self {
+        self.correction = Some(correction.into());
+        self
+    }
+
+    /// Set the source clock identity.
+    pub fn clock_identity(&mut self, clock_identity: impl Into<ClockIdentity>) -> &mut Self {
+        self.clock_identity = Some(clock_identity.into());
+        self
+    }
+
+    /// Set the source port number.
+    pub fn port_number(&mut self, port_number: impl Into<u16>) -> &mut Self {
+        self.port_number = Some(port_number.into());
+        self
+    }
+
+    /// Set the sequence id.
+    pub fn sequence_id(&mut self, sequence_id: impl Into<u16>) -> &mut Self {
+        self.sequence_id = Some(sequence_id.into());
+        self
+    }
+
+    /// Set the log message interval.
+    pub fn log_message_interval(&mut self, log_message_interval: impl Into<u8>) -> &mut Self {
+        self.log_message_interval = Some(log_message_interval.into());
+        self
+    }
+
+    /// Set the timestamp starting the body.
+    pub fn timestamp(&mut self, timestamp: impl Into<PtpTimestamp>) -> &mut Self {
+        self.timestamp = Some(timestamp.into());
+        self
+    }
+
+    /// Set the rest of the body, after the timestamp.
+    pub fn body<T: AsRef<[u8]>>(&mut self, body: T) -> &mut Self {
+        self.body.extend_from_slice(body.as_ref());
+        self
+    }
+
+    /// Build the Ptp layer.
+    ///
+    /// Unless set explicitly, the message type is Sync and the version is
+    /// [`DEFAULT_VERSION`]. The control field follows the message type.
+    pub fn build(&self) -> Ptp<Vec<u8>> {
+        let message_type = self.message_type.unwrap_or(PtpMessageType::Sync);
+
+        let mut data = vec![0; HEADER_LENGTH];
+        if let Some(timestamp) = self.timestamp {
+            data.extend_from_slice(&timestamp.to_bytes());
+        }
+        data.extend_from_slice(&self.body);
+
+        let mut ptp = unsafe { Ptp::new_unchecked(data) };
+        let length = ptp.inner().len() as u16;
+        ptp.message_type_mut().set(message_type);
+        ptp.version_mut()
+            .set(self.version.unwrap_or(DEFAULT_VERSION));
+        ptp.message_length_mut().set(length);
+        ptp.domain_number_mut()
+            .set(self.domain_number.unwrap_or_default());
+        ptp.flags_mut().set(self.flags.unwrap_or_default());
+        ptp.correction_mut()
+            .set(self.correction.unwrap_or_default());
+        ptp.clock_identity_mut()
+            .set(self.clock_identity.unwrap_or_default());
+        ptp.port_number_mut().set(self.port_number.unwrap_or(1));
+        ptp.sequence_id_mut()
+            .set(self.sequence_id.unwrap_or_default());
+        ptp.control_field_mut().set(message_type.control_field());
+        ptp.log_message_interval_mut()
+            .set(self.log_message_interval.unwrap_or(0x7F));
+
+        ptp
+    }
+}
+
+/// Create a Ptp layer with the given fields.
+#[macro_export]
+macro_rules! ptp {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::ptp::PtpBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ptp_two_step_sync() {
+        // PTPv2 two-step Sync, domain 0, sequence 0x1234, from port 1 of
+        // 001b21.fffe.123456, log interval 0 and a zero origin timestamp.
+        let mut data = [0u8; 44];
+        data[..8].copy_from_slice(&[0x00, 0x02, 0x00, 0x2C, 0x00, 0x00, 0x02, 0x00]);
+        data[15] = 0x80;
+        data[20..34].copy_from_slice(&[
+            0x00, 0x1B, 0x21, 0xFF, 0xFE, 0x12, 0x34, 0x56, 0x00, 0x01, 0x12, 0x34, 0x00, 0x00,
+        ]);
+
+        let ptp = Ptp::new(&data[..]).unwrap();
+        assert_eq!(ptp.message_type().get(), PtpMessageType::Sync);
+        assert_eq!(ptp.version().get(), 2);
+        assert_eq!(ptp.message_length().get(), 44);
+        assert!(ptp.is_two_step());
+        assert_eq!(ptp.correction_nanos(), 128.0 / 65536.0);
+        assert_eq!(ptp.clock_identity().get().to_string(), "001b21.fffe.123456");
+        assert_eq!(ptp.port_number().get(), 1);
+        assert_eq!(ptp.sequence_id().get(), 0x1234);
+        assert_eq!(ptp.timestamp(), Some(PtpTimestamp::default()));
+        assert_eq!(ptp.requesting_port(), None);
+
+        assert_eq!(
+            Ptp::new(&data[..40]).err(),
+            Some(PtpError::InvalidLength(LayerError::min_length(
+                "Ptp", "body", 34, 44, 40
+            )))
+        );
+    }
+
+    #[test]
+    fn ptp_delay_resp_over_udp() {
+        let requester = ClockIdentity::from(0x0011_22FF_FE33_4455);
+        let ptp = ptp!(
+            message_type: PtpMessageType::DelayResp,
+            sequence_id: 7u16,
+            timestamp: PtpTimestamp::new(1_700_000_000, 500),
+            body: [u64::from(requester).to_be_bytes().as_slice(), &[0, 2]].concat(),
+        );
+        assert_eq!(ptp.message_length().get(), 54);
+        assert_eq!(ptp.control_field().get(), 3);
+
+        let udp = udp!(src_port: GENERAL_PORT, dst_port: GENERAL_PORT, payload: ptp.inner());
+        let udp = Udp::new(udp.inner().as_slice()).unwrap();
+        let ptp = udp.ptp().unwrap();
+        assert_eq!(ptp.sequence_id().get(), 7);
+        assert_eq!(ptp.timestamp(), Some(PtpTimestamp::new(1_700_000_000, 500)));
+        assert_eq!(ptp.requesting_port(), Some((requester, 2)));
+    }
+}
@@ -0,0 +1,133 @@
+//! PTP Message Type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// PTP Message Type
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum PtpMessageType {
+    /// Sync
+    Sync = 0x0,
+
+    /// Delay_Req
+    DelayReq = 0x1,
+
+    /// Pdelay_Req
+    PdelayReq = 0x2,
+
+    /// Pdelay_Resp
+    PdelayResp = 0x3,
+
+    /// Follow_Up
+    FollowUp = 0x8,
+
+    /// Delay_Resp
+    DelayResp = 0x9,
+
+    /// Pdelay_Resp_Follow_Up
+    PdelayRespFollowUp = 0xA,
+
+    /// Announce
+    Announce = 0xB,
+
+    /// Signaling
+    Signaling = 0xC,
+
+    /// Management
+    Management = 0xD,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl PtpMessageType {
+    /// Whether messages of this type are event messages, timestamped on
+    /// transmission and reception and sent to the event port.
+    pub fn is_event(&self) -> bool {
+        matches!(
+            self,
+            PtpMessageType::Sync
+                | PtpMessageType::DelayReq
+                | PtpMessageType::PdelayReq
+                | PtpMessageType::PdelayResp
+        )
+    }
+
+    /// Get the value of the deprecated control field for this type.
+    pub fn control_field(&self) -> u8 {
+        match self {
+            PtpMessageType::Sync => 0,
+            PtpMessageType::DelayReq => 1,
+            PtpMessageType::FollowUp => 2,
+            PtpMessageType::DelayResp => 3,
+            PtpMessageType::Management => 4,
+            _ => 5,
+        }
+    }
+}
+
+impl_target!(frominto, PtpMessageType, u8);
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+
+    #[test]
+    fn ptp_message_type_str() {
+        test_enum_str!(
+            PtpMessageType,
+            Sync => "Sync",
+            DelayReq => "DelayReq",
+            PdelayReq => "PdelayReq",
+            PdelayResp => "PdelayResp",
+            FollowUp => "FollowUp",
+            DelayResp => "DelayResp",
+            PdelayRespFollowUp => "PdelayRespFollowUp",
+            Announce => "Announce",
+            Signaling => "Signaling",
+            Management => "Management",
+        );
+    }
+
+    #[test]
+    fn ptp_message_type_num() {
+        test_enum_num!(
+            PtpMessageType: u8,
+            Sync => 0x0,
+            DelayReq => 0x1,
+            PdelayReq => 0x2,
+            PdelayResp => 0x3,
+            FollowUp => 0x8,
+            DelayResp => 0x9,
+            PdelayRespFollowUp => 0xA,
+            Announce => 0xB,
+            Signaling => 0xC,
+            Management => 0xD,
+        );
+    }
+}
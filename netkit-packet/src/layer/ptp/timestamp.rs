@@ -0,0 +1,132 @@
+//! PTP Timestamp and Clock Identity
+
+use core::{fmt::Display, time::Duration};
+
+use crate::impl_target;
+
+/// Length of a PTP timestamp on the wire.
+pub const TIMESTAMP_LENGTH: usize = 10;
+
+/// PTP timestamp.
+///
+/// A 48-bit number of seconds and a 32-bit number of nanoseconds since the
+/// PTP epoch (1970-01-01 TAI).
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PtpTimestamp {
+    /// Seconds, 48 bits on the wire.
+    pub seconds: u64,
+    /// Nanoseconds, below 10^9 when valid.
+    pub nanoseconds: u32,
+}
+
+impl PtpTimestamp {
+    /// Create a new timestamp.
+    pub const fn new(seconds: u64, nanoseconds: u32) -> Self {
+        Self {
+            seconds,
+            nanoseconds,
+        }
+    }
+
+    /// Read a timestamp from its 10 bytes on the wire.
+    pub fn from_bytes(bytes: &[u8; TIMESTAMP_LENGTH]) -> Self {
+        let mut seconds = [0; 8];
+        seconds[2..].copy_from_slice(&bytes[..6]);
+        Self {
+            seconds: u64::from_be_bytes(seconds),
+            nanoseconds: u32::from_be_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]),
+        }
+    }
+
+    /// Get the 10 bytes of the timestamp on the wire.
+    ///
+    /// Seconds beyond 48 bits are truncated.
+    pub fn to_bytes(&self) -> [u8; TIMESTAMP_LENGTH] {
+        let mut bytes = [0; TIMESTAMP_LENGTH];
+        bytes[..6].copy_from_slice(&self.seconds.to_be_bytes()[2..]);
+        bytes[6..].copy_from_slice(&self.nanoseconds.to_be_bytes());
+        bytes
+    }
+
+    /// Convert into a duration since the PTP epoch.
+    pub fn to_duration(&self) -> Duration {
+        Duration::from_secs(self.seconds) + Duration::from_nanos(self.nanoseconds as u64)
+    }
+
+    /// Get the signed difference `self - other` in nanoseconds.
+    pub fn diff_nanos(&self, other: &PtpTimestamp) -> i128 {
+        let nanos = |ts: &PtpTimestamp| ts.seconds as i128 * 1_000_000_000 + ts.nanoseconds as i128;
+        nanos(self) - nanos(other)
+    }
+}
+
+impl From<Duration> for PtpTimestamp {
+    fn from(duration: Duration) -> Self {
+        Self::new(duration.as_secs(), duration.subsec_nanos())
+    }
+}
+
+impl Display for PtpTimestamp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{:09}", self.seconds, self.nanoseconds)
+    }
+}
+
+/// PTP clock identity.
+///
+/// An EUI-64 identifying a PTP clock, usually derived from a MAC address.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClockIdentity(pub [u8; 8]);
+
+impl From<u64> for ClockIdentity {
+    fn from(value: u64) -> Self {
+        Self(value.to_be_bytes())
+    }
+}
+
+impl From<ClockIdentity> for u64 {
+    fn from(identity: ClockIdentity) -> Self {
+        u64::from_be_bytes(identity.0)
+    }
+}
+
+impl From<[u8; 8]> for ClockIdentity {
+    fn from(octets: [u8; 8]) -> Self {
+        Self(octets)
+    }
+}
+
+impl Display for ClockIdentity {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let [a, b, c, d, e, g, h, i] = self.0;
+        write!(
+            f,
+            "{a:02x}{b:02x}{c:02x}.{d:02x}{e:02x}.{g:02x}{h:02x}{i:02x}"
+        )
+    }
+}
+
+impl_target!(frominto, ClockIdentity, u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ptp_timestamp_bytes() {
+        let ts = PtpTimestamp::new(0x0102_0304_0506, 999_999_999);
+        let bytes = ts.to_bytes();
+        assert_eq!(bytes, [1, 2, 3, 4, 5, 6, 0x3B, 0x9A, 0xC9, 0xFF]);
+        assert_eq!(PtpTimestamp::from_bytes(&bytes), ts);
+        assert_eq!(ts.to_string(), "1108152157446.999999999");
+
+        let later = PtpTimestamp::new(0x0102_0304_0507, 1);
+        assert_eq!(later.diff_nanos(&ts), 2);
+        assert_eq!(ts.diff_nanos(&later), -2);
+
+        let identity = ClockIdentity::from(0x001B_21FF_FE12_3456);
+        assert_eq!(identity.to_string(), "001b21.fffe.123456");
+    }
+}
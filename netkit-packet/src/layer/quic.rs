@@ -0,0 +1,335 @@
+//! QUIC layer.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Quic layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum QuicError {
+    /// Invalid Quic length.
+    #[error("Invalid Quic length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Invalid Quic header.
+    #[error("Invalid Quic header: fixed bit is not set")]
+    InvalidHeader,
+}
+
+field_spec!(VersionSpec, u32, u32);
+
+/// Default UDP port of QUIC.
+pub const DEFAULT_PORT: u16 = 443;
+
+/// Maximum length of a connection ID in QUIC version 1.
+pub const MAX_CID_LENGTH: usize = 20;
+
+/// Header form bit of the first byte: set for long headers.
+pub const HEADER_FORM_LONG: u8 = 0x80;
+
+/// Fixed bit of the first byte, set in all QUIC version 1 and 2 packets.
+pub const FIXED_BIT: u8 = 0x40;
+
+/// Quic layer.
+///
+/// Only the version-independent invariants ([RFC 8999]) are exposed: the
+/// header form, the version and the connection IDs. Everything after them is
+/// protected.
+///
+/// Short headers do not carry the length of the destination connection ID,
+/// which is only known to the endpoints; see
+/// [`short_dcid`](Quic::short_dcid).
+///
+/// ```text
+/// Long header:
+/// +-+-+-+-+-+-+-+-+
+/// |1|  Specific   |
+/// +-+-+-+-+-+-+-+-+--------------------------+
+/// |               Version (32)               |
+/// +-+-+-+-+-+-+-+-+--------------------------+
+/// | DCID Len (8)  | Destination CID (0..2040) ...
+/// +-+-+-+-+-+-+-+-+--------------------------+
+/// | SCID Len (8)  | Source CID (0..2040) ...
+/// +-+-+-+-+-+-+-+-+--------------------------+
+///
+/// Short header:
+/// +-+-+-+-+-+-+-+-+
+/// |0|  Specific   | Destination CID (*) ...
+/// +-+-+-+-+-+-+-+-+
+/// ```
+///
+/// [RFC 8999]: https://datatracker.ietf.org/doc/html/rfc8999
+pub struct Quic<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Quic<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the first byte: 0..1
+    pub const FIELD_FIRST: core::ops::Range<usize> = 0..1;
+    /// Field range of the version of long headers: 1..5
+    pub const FIELD_VERSION: core::ops::Range<usize> = 1..5;
+
+    /// Create a new Quic layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Quic packet.
+    ///
+    /// The data must not be empty, and long headers must hold both
+    /// connection IDs. Otherwise, the following methods may panic when
+    /// accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Quic layer.
+    ///
+    /// The fixed bit of QUIC versions 1 and 2 is required to tell QUIC apart
+    /// from other traffic on its port.
+    pub fn validate(&self) -> Result<(), QuicError> {
+        let data = self.data.as_ref();
+        let Some(&first) = data.first() else {
+            return Err(LayerError::min_length("Quic", "header", 0, 1, 0).into());
+        };
+        if first & FIXED_BIT == 0 {
+            return Err(QuicError::InvalidHeader);
+        }
+
+        if first & HEADER_FORM_LONG != 0 {
+            let len = self.header_len();
+            if data.len() < len {
+                return Err(
+                    LayerError::min_length("Quic", "connection ids", 5, len, data.len()).into(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Quic layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, QuicError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Whether the packet has a long header.
+    #[inline]
+    pub fn is_long_header(&self) -> bool {
+        self.data.as_ref()[0] & HEADER_FORM_LONG != 0
+    }
+
+    /// Get the accessor of the version of a long header.
+    ///
+    /// Version 0 is a version negotiation packet.
+    #[inline]
+    pub fn version(&self) -> Option<&Field<VersionSpec>> {
+        self.is_long_header()
+            .then(|| cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION]))
+    }
+
+    /// Get the destination connection ID of a long header.
+    pub fn dcid(&self) -> Option<&[u8]> {
+        if !self.is_long_header() {
+            return None;
+        }
+        let data = self.data.as_ref();
+        let len = data[5] as usize;
+        Some(&data[6..6 + len])
+    }
+
+    /// Get the source connection ID of a long header.
+    pub fn scid(&self) -> Option<&[u8]> {
+        if !self.is_long_header() {
+            return None;
+        }
+        let data = self.data.as_ref();
+        let at = 6 + data[5] as usize;
+        let len = data[at] as usize;
+        Some(&data[at + 1..at + 1 + len])
+    }
+
+    /// Get the destination connection ID of a short header, given its
+    /// length.
+    pub fn short_dcid(&self, len: usize) -> Option<&[u8]> {
+        if self.is_long_header() {
+            return None;
+        }
+        self.data.as_ref().get(1..1 + len)
+    }
+
+    /// Get the length of the version-independent part of a long header, or 1
+    /// for short headers.
+    pub fn header_len(&self) -> usize {
+        let data = self.data.as_ref();
+        if !self.is_long_header() {
+            return 1;
+        }
+        let Some(&dcid_len) = data.get(5) else {
+            return 6;
+        };
+        let at = 6 + dcid_len as usize;
+        match data.get(at) {
+            Some(&scid_len) => at + 1 + scid_len as usize,
+            None => at + 1,
+        }
+    }
+
+    /// Get the version-specific rest of the packet.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[self.header_len()..]
+    }
+}
+
+impl<T> Quic<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the version of a long header.
+    #[inline]
+    pub fn version_mut(&mut self) -> Option<&mut Field<VersionSpec>> {
+        self.is_long_header()
+            .then(|| cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION]))
+    }
+
+    /// Get the mutable version-specific rest of the packet.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let at = self.header_len();
+        &mut self.data.as_mut()[at..]
+    }
+}
+
+layer_impl!(Quic);
+
+/// Builder for [`Quic`].
+///
+/// A long header (an Initial packet) is built if a version is set, and a
+/// short header otherwise, in which case the source connection ID is
+/// ignored.
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct QuicBuilder {
+    version: Option<u32>,
+    dcid: Vec<u8>,
+    scid: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl QuicBuilder {
+    /// Create a new Quic builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the version, building a long header.
+    pub fn version(&mut self, version: impl Into<u32>) -> &mut Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the destination connection ID.
+    pub fn dcid<T: AsRef<[u8]>>(&mut self, dcid: T) -> &mut Self {
+        self.dcid = dcid.as_ref().to_vec();
+        self
+    }
+
+    /// Set the source connection ID.
+    pub fn scid<T: AsRef<[u8]>>(&mut self, scid: T) -> &mut Self {
+        self.scid = scid.as_ref().to_vec();
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Quic layer.
+    pub fn build(&self) -> Quic<Vec<u8>> {
+        let mut data = Vec::new();
+        match self.version {
+            Some(version) => {
+                data.push(HEADER_FORM_LONG | FIXED_BIT);
+                data.extend_from_slice(&version.to_be_bytes());
+                data.push(self.dcid.len() as u8);
+                data.extend_from_slice(&self.dcid);
+                data.push(self.scid.len() as u8);
+                data.extend_from_slice(&self.scid);
+            }
+            None => {
+                data.push(FIXED_BIT);
+                data.extend_from_slice(&self.dcid);
+            }
+        }
+        data.extend_from_slice(&self.payload);
+
+        unsafe { Quic::new_unchecked(data) }
+    }
+}
+
+/// Create a Quic layer with the given fields.
+#[macro_export]
+macro_rules! quic {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::quic::QuicBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quic_headers() {
+        let initial = quic!(version: 1u32, dcid: [1, 2, 3, 4], scid: [5, 6], payload: [0; 8]);
+        let quic = Quic::new(initial.inner().as_slice()).unwrap();
+        assert!(quic.is_long_header());
+        assert_eq!(quic.version().unwrap().get(), 1);
+        assert_eq!(quic.dcid(), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(quic.scid(), Some(&[5, 6][..]));
+        assert_eq!(quic.payload().len(), 8);
+        assert_eq!(quic.short_dcid(4), None);
+
+        assert_eq!(
+            Quic::new(&initial.inner()[..12]).err(),
+            Some(QuicError::InvalidLength(LayerError::min_length(
+                "Quic",
+                "connection ids",
+                5,
+                13,
+                12
+            )))
+        );
+
+        let short = quic!(dcid: [5, 6], payload: [0; 4]);
+        assert!(!short.is_long_header());
+        assert!(short.version().is_none());
+        assert_eq!(short.short_dcid(2), Some(&[5, 6][..]));
+        assert_eq!(Quic::new(&[0x00][..]).err(), Some(QuicError::InvalidHeader));
+    }
+}
@@ -0,0 +1,546 @@
+//! Real-time Transport Protocol (RTP) layer.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Rtp layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RtpError {
+    /// Invalid Rtp length.
+    #[error("Invalid Rtp length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Invalid Rtp version.
+    #[error("Invalid Rtp version: {0}")]
+    InvalidVersion(u8),
+
+    /// Invalid Rtp padding.
+    #[error("Invalid Rtp padding: {0} bytes of padding exceed the payload")]
+    InvalidPadding(u8),
+}
+
+field_spec!(VersionSpec, u8, u8, 0xC0, 6);
+field_spec!(PaddingSpec, bool, u8, 0x20, 5);
+field_spec!(ExtensionSpec, bool, u8, 0x10, 4);
+field_spec!(CsrcCountSpec, u8, u8, 0x0F);
+field_spec!(MarkerSpec, bool, u8, 0x80, 7);
+field_spec!(PayloadTypeSpec, u8, u8, 0x7F);
+field_spec!(SequenceSpec, u16, u16);
+field_spec!(TimestampSpec, u32, u32);
+field_spec!(SsrcSpec, u32, u32);
+
+/// Minimum length of a Rtp header.
+pub const MIN_HEADER_LENGTH: usize = 12;
+
+/// Version of Rtp defined by RFC 3550.
+pub const VERSION: u8 = 2;
+
+/// Get the RTP clock rate of a static payload type, in Hz.
+///
+/// See [RFC 3551 section 6](https://datatracker.ietf.org/doc/html/rfc3551#section-6).
+/// Dynamic payload types (96-127) are negotiated out of band and return
+/// `None`.
+pub fn clock_rate(payload_type: u8) -> Option<u32> {
+    match payload_type {
+        0 | 3..=5 | 7..=9 | 12 | 13 | 15 | 18 => Some(8_000),
+        6 => Some(16_000),
+        10 | 11 => Some(44_100),
+        16 => Some(11_025),
+        17 => Some(22_050),
+        14 | 25 | 26 | 28 | 31..=34 => Some(90_000),
+        _ => None,
+    }
+}
+
+/// Real-time Transport Protocol (RTP) layer.
+///
+/// See [RFC 3550](https://datatracker.ietf.org/doc/html/rfc3550).
+///
+/// ```text
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |V=2|P|X|  CC   |M|     PT      |       sequence number         |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                           timestamp                           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |           synchronization source (SSRC) identifier            |
+/// +=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+
+/// |            contributing source (CSRC) identifiers             |
+/// |                             ....                              |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+pub struct Rtp<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Rtp<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the version: 0..1
+    pub const FIELD_VERSION: core::ops::Range<usize> = 0..1;
+    /// Field range of the padding flag: 0..1
+    pub const FIELD_PADDING: core::ops::Range<usize> = 0..1;
+    /// Field range of the extension flag: 0..1
+    pub const FIELD_EXTENSION: core::ops::Range<usize> = 0..1;
+    /// Field range of the CSRC count: 0..1
+    pub const FIELD_CSRC_COUNT: core::ops::Range<usize> = 0..1;
+    /// Field range of the marker: 1..2
+    pub const FIELD_MARKER: core::ops::Range<usize> = 1..2;
+    /// Field range of the payload type: 1..2
+    pub const FIELD_PAYLOAD_TYPE: core::ops::Range<usize> = 1..2;
+    /// Field range of the sequence number: 2..4
+    pub const FIELD_SEQUENCE: core::ops::Range<usize> = 2..4;
+    /// Field range of the timestamp: 4..8
+    pub const FIELD_TIMESTAMP: core::ops::Range<usize> = 4..8;
+    /// Field range of the SSRC: 8..12
+    pub const FIELD_SSRC: core::ops::Range<usize> = 8..12;
+
+    /// Create a new Rtp layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Rtp packet.
+    ///
+    /// The length of the data must be at least the header length, including
+    /// the CSRC list and the header extension. Otherwise, the following
+    /// methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Rtp layer.
+    pub fn validate(&self) -> Result<(), RtpError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("Rtp", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+        if self.version().get() != VERSION {
+            return Err(RtpError::InvalidVersion(self.version().get()));
+        }
+
+        let fixed = MIN_HEADER_LENGTH + 4 * self.csrc_count().get() as usize;
+        let header_len = if self.extension().get() {
+            match self.data.as_ref().get(fixed + 2..fixed + 4) {
+                Some(words) => fixed + 4 + 4 * u16::from_be_bytes([words[0], words[1]]) as usize,
+                None => fixed + 4,
+            }
+        } else {
+            fixed
+        };
+        if len < fixed {
+            return Err(
+                LayerError::min_length("Rtp", "csrc list", MIN_HEADER_LENGTH, fixed, len).into(),
+            );
+        }
+        if len < header_len {
+            return Err(LayerError::min_length("Rtp", "extension", fixed, header_len, len).into());
+        }
+
+        if self.padding().get() {
+            let padding = self.data.as_ref()[len - 1];
+            if padding == 0 || padding as usize > len - header_len {
+                return Err(RtpError::InvalidPadding(padding));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Rtp layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, RtpError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the version.
+    #[inline]
+    pub fn version(&self) -> &Field<VersionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VERSION])
+    }
+
+    /// Get the accessor of the padding flag.
+    #[inline]
+    pub fn padding(&self) -> &Field<PaddingSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PADDING])
+    }
+
+    /// Get the accessor of the extension flag.
+    #[inline]
+    pub fn extension(&self) -> &Field<ExtensionSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_EXTENSION])
+    }
+
+    /// Get the accessor of the CSRC count.
+    #[inline]
+    pub fn csrc_count(&self) -> &Field<CsrcCountSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CSRC_COUNT])
+    }
+
+    /// Get the accessor of the marker.
+    #[inline]
+    pub fn marker(&self) -> &Field<MarkerSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_MARKER])
+    }
+
+    /// Get the accessor of the payload type.
+    #[inline]
+    pub fn payload_type(&self) -> &Field<PayloadTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PAYLOAD_TYPE])
+    }
+
+    /// Get the accessor of the sequence number.
+    #[inline]
+    pub fn sequence(&self) -> &Field<SequenceSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SEQUENCE])
+    }
+
+    /// Get the accessor of the timestamp.
+    #[inline]
+    pub fn timestamp(&self) -> &Field<TimestampSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_TIMESTAMP])
+    }
+
+    /// Get the accessor of the SSRC.
+    #[inline]
+    pub fn ssrc(&self) -> &Field<SsrcSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SSRC])
+    }
+
+    /// Get the CSRC identifiers.
+    pub fn csrcs(&self) -> impl Iterator<Item = u32> + '_ {
+        self.data.as_ref()[MIN_HEADER_LENGTH..self.extension_offset()]
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+    }
+
+    /// Get the profile-defined identifier and the data of the header
+    /// extension, if present.
+    pub fn header_extension(&self) -> Option<(u16, &[u8])> {
+        if !self.extension().get() {
+            return None;
+        }
+        let offset = self.extension_offset();
+        let data = self.data.as_ref();
+        let profile = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        Some((profile, &data[offset + 4..self.header_len()]))
+    }
+
+    /// Get the length of the header, including the CSRC list and the header
+    /// extension.
+    pub fn header_len(&self) -> usize {
+        let offset = self.extension_offset();
+        if self.extension().get() {
+            let data = self.data.as_ref();
+            let words = u16::from_be_bytes([data[offset + 2], data[offset + 3]]);
+            offset + 4 + 4 * words as usize
+        } else {
+            offset
+        }
+    }
+
+    /// Get the payload, without padding.
+    pub fn payload(&self) -> &[u8] {
+        let data = self.data.as_ref();
+        let padding = if self.padding().get() {
+            data[data.len() - 1] as usize
+        } else {
+            0
+        };
+        &data[self.header_len()..data.len() - padding]
+    }
+
+    fn extension_offset(&self) -> usize {
+        MIN_HEADER_LENGTH + 4 * self.csrc_count().get() as usize
+    }
+}
+
+impl<T> Rtp<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the version.
+    #[inline]
+    pub fn version_mut(&mut self) -> &mut Field<VersionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VERSION])
+    }
+
+    /// Get the mutable accessor of the padding flag.
+    #[inline]
+    pub fn padding_mut(&mut self) -> &mut Field<PaddingSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PADDING])
+    }
+
+    /// Get the mutable accessor of the extension flag.
+    #[inline]
+    pub fn extension_mut(&mut self) -> &mut Field<ExtensionSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_EXTENSION])
+    }
+
+    /// Get the mutable accessor of the CSRC count.
+    #[inline]
+    pub fn csrc_count_mut(&mut self) -> &mut Field<CsrcCountSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CSRC_COUNT])
+    }
+
+    /// Get the mutable accessor of the marker.
+    #[inline]
+    pub fn marker_mut(&mut self) -> &mut Field<MarkerSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_MARKER])
+    }
+
+    /// Get the mutable accessor of the payload type.
+    #[inline]
+    pub fn payload_type_mut(&mut self) -> &mut Field<PayloadTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PAYLOAD_TYPE])
+    }
+
+    /// Get the mutable accessor of the sequence number.
+    #[inline]
+    pub fn sequence_mut(&mut self) -> &mut Field<SequenceSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SEQUENCE])
+    }
+
+    /// Get the mutable accessor of the timestamp.
+    #[inline]
+    pub fn timestamp_mut(&mut self) -> &mut Field<TimestampSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_TIMESTAMP])
+    }
+
+    /// Get the mutable accessor of the SSRC.
+    #[inline]
+    pub fn ssrc_mut(&mut self) -> &mut Field<SsrcSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SSRC])
+    }
+
+    /// Get the mutable payload, without padding.
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let (start, len) = {
+            let payload = self.payload();
+            (self.header_len(), payload.len())
+        };
+        &mut self.data.as_mut()[start..start + len]
+    }
+}
+
+layer_impl!(Rtp);
+
+/// Builder for [`Rtp`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct RtpBuilder {
+    marker: Option<bool>,
+    payload_type: Option<u8>,
+    sequence: Option<u16>,
+    timestamp: Option<u32>,
+    ssrc: Option<u32>,
+    csrcs: Vec<u32>,
+    payload: Vec<u8>,
+}
+
+impl RtpBuilder {
+    /// Create a new Rtp builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the marker.
+    pub fn marker(&mut self, marker: impl Into<bool>) -> &mut Self {
+        self.marker = Some(marker.into());
+        self
+    }
+
+    /// Set the payload type.
+    pub fn payload_type(&mut self, payload_type: impl Into<u8>) -> &mut Self {
+        self.payload_type = Some(payload_type.into());
+        self
+    }
+
+    /// Set the sequence number.
+    pub fn sequence(&mut self, sequence: impl Into<u16>) -> &mut Self {
+        self.sequence = Some(sequence.into());
+        self
+    }
+
+    /// Set the timestamp.
+    pub fn timestamp(&mut self, timestamp: impl Into<u32>) -> &mut Self {
+        self.timestamp = Some(timestamp.into());
+        self
+    }
+
+    /// Set the SSRC.
+    pub fn ssrc(&mut self, ssrc: impl Into<u32>) -> &mut Self {
+        self.ssrc = Some(ssrc.into());
+        self
+    }
+
+    /// Add a CSRC identifier.
+    pub fn csrcs(&mut self, csrc: impl Into<u32>) -> &mut Self {
+        self.csrcs.push(csrc.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build a Rtp layer.
+    ///
+    /// At most 15 CSRC identifiers are kept.
+    pub fn build(&self) -> Rtp<Vec<u8>> {
+        let csrcs = &self.csrcs[..self.csrcs.len().min(15)];
+
+        let mut data = vec![0; MIN_HEADER_LENGTH];
+        for csrc in csrcs {
+            data.extend_from_slice(&csrc.to_be_bytes());
+        }
+        data.extend_from_slice(&self.payload);
+
+        let mut rtp = unsafe { Rtp::new_unchecked(data) };
+        rtp.version_mut().set(VERSION);
+        rtp.csrc_count_mut().set(csrcs.len() as u8);
+        rtp.marker_mut().set(self.marker.unwrap_or_default());
+        rtp.payload_type_mut()
+            .set(self.payload_type.unwrap_or_default());
+        rtp.sequence_mut().set(self.sequence.unwrap_or_default());
+        rtp.timestamp_mut().set(self.timestamp.unwrap_or_default());
+        rtp.ssrc_mut().set(self.ssrc.unwrap_or_default());
+
+        rtp
+    }
+}
+
+/// Create a new Rtp layer with the given fields.
+///
+/// # Example
+///
+/// ```
+/// use netkit_packet::prelude::*;
+///
+/// let rtp = rtp!(payload_type: 0u8, sequence: 1u16, ssrc: 0x1234u32, payload: [0xFF; 160]);
+/// assert_eq!(rtp.version().get(), 2);
+/// assert_eq!(rtp.sequence().get(), 1);
+/// assert_eq!(rtp.payload().len(), 160);
+/// ```
+#[macro_export]
+macro_rules! rtp {
+    ($($field : ident : $value : expr),* $(,)? ) => {
+        $crate::layer::rtp::RtpBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtp_new() {
+        let data: [u8; 28] = [
+            0xB1,     // version 2, padding, extension, 1 CSRC
+            0x80 | 8, // marker, PCMA
+            0x00,
+            0x2A, // sequence
+            0x00,
+            0x00,
+            0x01,
+            0x40, // timestamp
+            0xDE,
+            0xAD,
+            0xBE,
+            0xEF, // SSRC
+            0x00,
+            0x00,
+            0x00,
+            0x07, // CSRC
+            0xBE,
+            0xDE,
+            0x00,
+            0x01, // extension header
+            0x10,
+            0x20,
+            0x30,
+            0x40, // extension data
+            0x01,
+            0x02, // payload
+            0x00,
+            0x02, // padding
+        ];
+
+        let rtp = Rtp::new(&data[..]).unwrap();
+        assert_eq!(rtp.version().get(), 2);
+        assert!(rtp.marker().get());
+        assert_eq!(rtp.payload_type().get(), 8);
+        assert_eq!(rtp.sequence().get(), 42);
+        assert_eq!(rtp.timestamp().get(), 320);
+        assert_eq!(rtp.ssrc().get(), 0xDEADBEEF);
+        assert_eq!(rtp.csrcs().collect::<Vec<_>>(), [7]);
+        assert_eq!(
+            rtp.header_extension(),
+            Some((0xBEDE, &[0x10, 0x20, 0x30, 0x40][..]))
+        );
+        assert_eq!(rtp.header_len(), 24);
+        assert_eq!(rtp.payload(), [0x01, 0x02]);
+        assert_eq!(clock_rate(rtp.payload_type().get()), Some(8_000));
+
+        assert_eq!(
+            Rtp::new(&data[..20]).err(),
+            Some(RtpError::InvalidLength(LayerError::min_length(
+                "Rtp",
+                "extension",
+                16,
+                24,
+                20
+            )))
+        );
+        let mut bad = data;
+        bad[27] = 5;
+        assert_eq!(Rtp::new(&bad[..]).err(), Some(RtpError::InvalidPadding(5)));
+        bad[0] = 0x40;
+        assert_eq!(Rtp::new(&bad[..]).err(), Some(RtpError::InvalidVersion(1)));
+    }
+
+    #[test]
+    fn rtp_macro() {
+        let rtp = rtp!(
+            marker: true,
+            payload_type: 96u8,
+            sequence: 0xFFFFu16,
+            timestamp: 0x1234_5678u32,
+            ssrc: 1u32,
+            csrcs: 2u32,
+            payload: [0xAA; 4],
+        );
+
+        let rtp = Rtp::new(rtp.inner().as_slice()).unwrap();
+        assert!(rtp.marker().get());
+        assert_eq!(rtp.payload_type().get(), 96);
+        assert_eq!(rtp.sequence().get(), 0xFFFF);
+        assert_eq!(rtp.timestamp().get(), 0x1234_5678);
+        assert_eq!(rtp.csrcs().collect::<Vec<_>>(), [2]);
+        assert_eq!(rtp.header_extension(), None);
+        assert_eq!(rtp.payload(), [0xAA; 4]);
+    }
+}
@@ -0,0 +1,335 @@
+//! sFlow version 5 datagram decoding.
+//!
+//! sFlow agents sample packets and send the first bytes of each sampled
+//! packet, together with interface counters, to a collector, usually on port
+//! [`DEFAULT_PORT`] (see the [sFlow v5 specification]). Flow samples are
+//! decoded with their raw packet header records; other samples and records
+//! are kept undecoded.
+//!
+//! [sFlow v5 specification]: https://sflow.org/sflow_version_5.txt
+
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::netflow::be32;
+use crate::prelude::*;
+
+/// Default UDP port of sFlow collectors.
+pub const DEFAULT_PORT: u16 = 6343;
+
+/// Header protocol of raw packet headers holding Ethernet frames.
+pub const HEADER_PROTOCOL_ETHERNET: u32 = 1;
+
+/// Error type for sFlow datagrams.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SFlowError {
+    /// The datagram ends in the middle of a structure.
+    #[error("Invalid sFlow length: truncated {0}")]
+    Truncated(&'static str),
+
+    /// Unsupported sFlow version.
+    #[error("Unsupported sFlow version {0}")]
+    UnsupportedVersion(u32),
+
+    /// Unsupported agent address type.
+    #[error("Unsupported sFlow agent address type {0}")]
+    UnsupportedAddressType(u32),
+}
+
+/// Flow record of a flow sample.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlowRecord {
+    /// First bytes of the sampled packet (format 1).
+    RawHeader {
+        /// Protocol of the header, [`HEADER_PROTOCOL_ETHERNET`] for
+        /// Ethernet frames.
+        protocol: u32,
+        /// Length of the sampled frame.
+        frame_length: u32,
+        /// Number of bytes removed from the frame before sampling.
+        stripped: u32,
+        /// Header bytes.
+        header: Vec<u8>,
+    },
+
+    /// Undecoded record.
+    Other {
+        /// Enterprise and format of the record.
+        format: u32,
+        /// Data of the record.
+        data: Vec<u8>,
+    },
+}
+
+/// Flow sample (format 1) or expanded flow sample (format 3).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlowSample {
+    /// Sequence number of the sample.
+    pub sequence: u32,
+    /// Type of the data source, e.g. 0 for an interface.
+    pub source_type: u32,
+    /// Index of the data source.
+    pub source_index: u32,
+    /// One packet is sampled every `sampling_rate` packets.
+    pub sampling_rate: u32,
+    /// Number of packets that could have been sampled.
+    pub sample_pool: u32,
+    /// Number of samples dropped for lack of resources.
+    pub drops: u32,
+    /// Input interface.
+    pub input: u32,
+    /// Output interface.
+    pub output: u32,
+    /// Flow records.
+    pub records: Vec<FlowRecord>,
+}
+
+impl FlowSample {
+    /// Get the sampled Ethernet frame header, if any.
+    pub fn ethernet_header(&self) -> Option<&[u8]> {
+        self.records.iter().find_map(|record| match record {
+            FlowRecord::RawHeader {
+                protocol: HEADER_PROTOCOL_ETHERNET,
+                header,
+                ..
+            } => Some(header.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Get the flow key of the sampled packet, if its header was sampled.
+    pub fn flow_key(&self) -> Option<FlowKey> {
+        FlowKey::from_eth(&Eth::new(self.ethernet_header()?).ok()?)
+    }
+
+    /// Get the length of the sampled frame, if its header was sampled.
+    pub fn frame_length(&self) -> Option<u32> {
+        self.records.iter().find_map(|record| match record {
+            FlowRecord::RawHeader { frame_length, .. } => Some(*frame_length),
+            _ => None,
+        })
+    }
+}
+
+/// Sample of an sFlow datagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sample {
+    /// Flow sample.
+    Flow(FlowSample),
+
+    /// Undecoded sample, e.g. counters.
+    Other {
+        /// Enterprise and format of the sample.
+        format: u32,
+        /// Data of the sample.
+        data: Vec<u8>,
+    },
+}
+
+/// sFlow version 5 datagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SFlowDatagram {
+    /// Address of the agent.
+    pub agent: IpAddr,
+    /// ID of the sub-agent.
+    pub sub_agent_id: u32,
+    /// Sequence number of the datagram.
+    pub sequence: u32,
+    /// Uptime of the agent, in milliseconds.
+    pub uptime: u32,
+    /// Samples.
+    pub samples: Vec<Sample>,
+}
+
+/// Reader of XDR-encoded values.
+struct Xdr<'a>(&'a [u8]);
+
+impl<'a> Xdr<'a> {
+    fn u32(&mut self, what: &'static str) -> Result<u32, SFlowError> {
+        if self.0.len() < 4 {
+            return Err(SFlowError::Truncated(what));
+        }
+        let value = be32(self.0, 0);
+        self.0 = &self.0[4..];
+        Ok(value)
+    }
+
+    /// Read opaque data, padded to 4 bytes.
+    fn bytes(&mut self, len: usize, what: &'static str) -> Result<&'a [u8], SFlowError> {
+        let padded = len.checked_add(3).ok_or(SFlowError::Truncated(what))? & !3;
+        if self.0.len() < padded {
+            return Err(SFlowError::Truncated(what));
+        }
+        let value = &self.0[..len];
+        self.0 = &self.0[padded..];
+        Ok(value)
+    }
+
+    /// Read a format and the data following its length.
+    fn structure(&mut self, what: &'static str) -> Result<(u32, &'a [u8]), SFlowError> {
+        let format = self.u32(what)?;
+        let len = self.u32(what)? as usize;
+        Ok((format, self.bytes(len, what)?))
+    }
+}
+
+impl SFlowDatagram {
+    /// Decode an sFlow version 5 datagram.
+    pub fn parse(data: &[u8]) -> Result<Self, SFlowError> {
+        let mut xdr = Xdr(data);
+        match xdr.u32("header")? {
+            5 => {}
+            version => return Err(SFlowError::UnsupportedVersion(version)),
+        }
+        let agent = match xdr.u32("header")? {
+            1 => IpAddr::from(<[u8; 4]>::try_from(xdr.bytes(4, "header")?).unwrap()),
+            2 => IpAddr::from(<[u8; 16]>::try_from(xdr.bytes(16, "header")?).unwrap()),
+            kind => return Err(SFlowError::UnsupportedAddressType(kind)),
+        };
+        let sub_agent_id = xdr.u32("header")?;
+        let sequence = xdr.u32("header")?;
+        let uptime = xdr.u32("header")?;
+        let count = xdr.u32("header")?;
+
+        let mut samples = Vec::new();
+        for _ in 0..count {
+            let (format, data) = xdr.structure("sample")?;
+            let sample = match format {
+                1 => Sample::Flow(Self::flow_sample(data, false)?),
+                3 => Sample::Flow(Self::flow_sample(data, true)?),
+                format => Sample::Other {
+                    format,
+                    data: data.to_vec(),
+                },
+            };
+            samples.push(sample);
+        }
+
+        Ok(Self {
+            agent,
+            sub_agent_id,
+            sequence,
+            uptime,
+            samples,
+        })
+    }
+
+    /// Iterate over the flow samples.
+    pub fn flow_samples(&self) -> impl Iterator<Item = &FlowSample> {
+        self.samples.iter().filter_map(|sample| match sample {
+            Sample::Flow(sample) => Some(sample),
+            _ => None,
+        })
+    }
+
+    fn flow_sample(data: &[u8], expanded: bool) -> Result<FlowSample, SFlowError> {
+        let mut xdr = Xdr(data);
+        let what = "flow sample";
+        let mut sample = FlowSample {
+            sequence: xdr.u32(what)?,
+            ..Default::default()
+        };
+        if expanded {
+            sample.source_type = xdr.u32(what)?;
+            sample.source_index = xdr.u32(what)?;
+        } else {
+            let source = xdr.u32(what)?;
+            (sample.source_type, sample.source_index) = (source >> 24, source & 0xFF_FFFF);
+        }
+        sample.sampling_rate = xdr.u32(what)?;
+        sample.sample_pool = xdr.u32(what)?;
+        sample.drops = xdr.u32(what)?;
+        if expanded {
+            // Format and value of the interfaces.
+            xdr.u32(what)?;
+            sample.input = xdr.u32(what)?;
+            xdr.u32(what)?;
+            sample.output = xdr.u32(what)?;
+        } else {
+            sample.input = xdr.u32(what)?;
+            sample.output = xdr.u32(what)?;
+        }
+
+        let count = xdr.u32(what)?;
+        for _ in 0..count {
+            let (format, data) = xdr.structure("flow record")?;
+            let record = if format == 1 {
+                let mut xdr = Xdr(data);
+                let what = "raw packet header";
+                let protocol = xdr.u32(what)?;
+                let frame_length = xdr.u32(what)?;
+                let stripped = xdr.u32(what)?;
+                let len = xdr.u32(what)? as usize;
+                FlowRecord::RawHeader {
+                    protocol,
+                    frame_length,
+                    stripped,
+                    header: xdr.bytes(len, what)?.to_vec(),
+                }
+            } else {
+                FlowRecord::Other {
+                    format,
+                    data: data.to_vec(),
+                }
+            };
+            sample.records.push(record);
+        }
+        Ok(sample)
+    }
+}
+
+/// Get the agent address of an sFlow datagram without decoding it.
+pub fn agent(data: &[u8]) -> Option<IpAddr> {
+    match be32(data.get(..8)?, 4) {
+        1 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(data.get(8..12)?).ok()?).into()),
+        2 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(data.get(8..24)?).ok()?).into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sflow_parse() {
+        let udp = udp!(src_port: 1234u16, dst_port: 80u16);
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+        let header = frame.inner();
+
+        let words =
+            |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_be_bytes()).collect() };
+        let mut record = words(&[1, 1500, 4, header.len() as u32]);
+        record.extend_from_slice(header);
+        record.resize(record.len().next_multiple_of(4), 0);
+        let mut sample = words(&[7, 3, 512, 1000, 0, 1, 2, 2]);
+        sample.extend(words(&[1, record.len() as u32]));
+        sample.extend(&record);
+        sample.extend(words(&[1001, 4, 0xAABBCCDD]));
+
+        let mut datagram = words(&[5, 1, 0x0A00_0001, 0, 42, 1000, 2]);
+        datagram.extend(words(&[1, sample.len() as u32]));
+        datagram.extend(&sample);
+        datagram.extend(words(&[2, 0]));
+
+        let sflow = SFlowDatagram::parse(&datagram).unwrap();
+        assert_eq!(sflow.agent, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(agent(&datagram), Some(sflow.agent));
+        assert_eq!(sflow.sequence, 42);
+        let flow = sflow.flow_samples().next().unwrap();
+        assert_eq!((flow.source_index, flow.sampling_rate), (3, 512));
+        assert_eq!(flow.frame_length(), Some(1500));
+        assert_eq!(
+            flow.flow_key().unwrap().to_string(),
+            "Udp 0.0.0.0:1234 -> 0.0.0.0:80"
+        );
+        assert_eq!(flow.records.len(), 2);
+        assert!(matches!(sflow.samples[1], Sample::Other { format: 2, .. }));
+
+        assert_eq!(
+            SFlowDatagram::parse(&datagram[..datagram.len() - 1]),
+            Err(SFlowError::Truncated("sample"))
+        );
+    }
+}
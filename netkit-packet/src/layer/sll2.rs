@@ -0,0 +1,318 @@
+//! Linux cooked capture v2 (SLL2) layer.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Sll2 layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Sll2Error {
+    /// Invalid Sll2 length.
+    #[error("Invalid Sll2 length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(ProtocolSpec, EthType, u16);
+field_spec!(InterfaceIndexSpec, u32, u32);
+field_spec!(HardwareTypeSpec, u16, u16);
+field_spec!(PacketTypeSpec, u8, u8);
+field_spec!(AddrLengthSpec, u8, u8);
+
+/// Length of a Sll2 header.
+pub const MIN_HEADER_LENGTH: usize = 20;
+
+/// Linux cooked capture v2 (SLL2) layer.
+///
+/// See [LINKTYPE_LINUX_SLL2](https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL2.html).
+///
+/// ```text
+/// +---------------------------+---------------------------+
+/// |       Protocol type       |         Reserved          |
+/// +---------------------------+---------------------------+
+/// |                    Interface index                    |
+/// +---------------------------+-------------+-------------+
+/// |     ARPHRD_ type          | Packet type | Addr length |
+/// +---------------------------+-------------+-------------+
+/// |                Link-layer address (8 bytes)           |
+/// +-------------------------------------------------------+
+/// ```
+pub struct Sll2<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Sll2<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the protocol type: 0..2
+    pub const FIELD_PROTOCOL: core::ops::Range<usize> = 0..2;
+    /// Field range of the interface index: 4..8
+    pub const FIELD_INTERFACE_INDEX: core::ops::Range<usize> = 4..8;
+    /// Field range of the ARPHRD_ type: 8..10
+    pub const FIELD_HARDWARE_TYPE: core::ops::Range<usize> = 8..10;
+    /// Field range of the packet type: 10..11
+    pub const FIELD_PACKET_TYPE: core::ops::Range<usize> = 10..11;
+    /// Field range of the link-layer address length: 11..12
+    pub const FIELD_ADDR_LENGTH: core::ops::Range<usize> = 11..12;
+    /// Field range of the link-layer address: 12..20
+    pub const FIELD_ADDR: core::ops::Range<usize> = 12..20;
+    /// Field range of the payload: 20..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 20..;
+
+    /// Create a new Sll2 layer from raw data without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Sll2 packet.
+    ///
+    /// The data must be at least 20 bytes long. Otherwise, the following
+    /// methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Sll2 layer.
+    pub fn validate(&self) -> Result<(), Sll2Error> {
+        if self.data.as_ref().len() < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "Sll2",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Sll2 layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, Sll2Error> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the protocol type.
+    #[inline]
+    pub fn protocol(&self) -> &Field<ProtocolSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PROTOCOL])
+    }
+
+    /// Get the accessor of the interface index.
+    #[inline]
+    pub fn interface_index(&self) -> &Field<InterfaceIndexSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_INTERFACE_INDEX])
+    }
+
+    /// Get the accessor of the ARPHRD_ type.
+    #[inline]
+    pub fn hardware_type(&self) -> &Field<HardwareTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_HARDWARE_TYPE])
+    }
+
+    /// Get the accessor of the packet type.
+    ///
+    /// `0` is to us, `1` broadcast, `2` multicast, `3` to someone else and
+    /// `4` sent by us.
+    #[inline]
+    pub fn packet_type(&self) -> &Field<PacketTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PACKET_TYPE])
+    }
+
+    /// Get the accessor of the link-layer address length.
+    #[inline]
+    pub fn addr_length(&self) -> &Field<AddrLengthSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_ADDR_LENGTH])
+    }
+
+    /// Get the link-layer address of the sender.
+    pub fn addr(&self) -> &[u8] {
+        let len = (self.addr_length().get() as usize).min(Self::FIELD_ADDR.len());
+        &self.data.as_ref()[Self::FIELD_ADDR][..len]
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Get the IPv4 layer if the protocol type is IPv4.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.protocol().get() == EthType::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Sll2<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the protocol type.
+    #[inline]
+    pub fn protocol_mut(&mut self) -> &mut Field<ProtocolSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PROTOCOL])
+    }
+
+    /// Get the mutable accessor of the interface index.
+    #[inline]
+    pub fn interface_index_mut(&mut self) -> &mut Field<InterfaceIndexSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_INTERFACE_INDEX])
+    }
+
+    /// Get the mutable accessor of the ARPHRD_ type.
+    #[inline]
+    pub fn hardware_type_mut(&mut self) -> &mut Field<HardwareTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_HARDWARE_TYPE])
+    }
+
+    /// Get the mutable accessor of the packet type.
+    #[inline]
+    pub fn packet_type_mut(&mut self) -> &mut Field<PacketTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PACKET_TYPE])
+    }
+
+    /// Get the mutable accessor of the link-layer address length.
+    #[inline]
+    pub fn addr_length_mut(&mut self) -> &mut Field<AddrLengthSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_ADDR_LENGTH])
+    }
+
+    /// Get the mutable link-layer address field (all 8 bytes).
+    #[inline]
+    pub fn addr_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_ADDR]
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(Sll2);
+
+/// Builder for [`Sll2`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct Sll2Builder {
+    protocol: Option<EthType>,
+    interface_index: Option<u32>,
+    hardware_type: Option<u16>,
+    packet_type: Option<u8>,
+    addr: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl Sll2Builder {
+    /// Create a new Sll2 builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the protocol type.
+    pub fn protocol(&mut self, protocol: impl Into<EthType>) -> &mut Self {
+        self.protocol = Some(protocol.into());
+        self
+    }
+
+    /// Set the interface index.
+    pub fn interface_index(&mut self, interface_index: impl Into<u32>) -> &mut Self {
+        self.interface_index = Some(interface_index.into());
+        self
+    }
+
+    /// Set the ARPHRD_ type.
+    pub fn hardware_type(&mut self, hardware_type: impl Into<u16>) -> &mut Self {
+        self.hardware_type = Some(hardware_type.into());
+        self
+    }
+
+    /// Set the packet type.
+    pub fn packet_type(&mut self, packet_type: impl Into<u8>) -> &mut Self {
+        self.packet_type = Some(packet_type.into());
+        self
+    }
+
+    /// Set the link-layer address, truncated to 8 bytes.
+    pub fn addr<T: AsRef<[u8]>>(&mut self, addr: T) -> &mut Self {
+        let addr = addr.as_ref();
+        self.addr = addr[..addr.len().min(8)].to_vec();
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Sll2 layer.
+    ///
+    /// The ARPHRD_ type defaults to Ethernet (`1`).
+    pub fn build(&self) -> Sll2<Vec<u8>> {
+        let len = MIN_HEADER_LENGTH + self.payload.len();
+
+        let mut sll2 = unsafe { Sll2::new_unchecked(vec![0; len]) };
+
+        sll2.protocol_mut().set(self.protocol.unwrap_or_default());
+        sll2.interface_index_mut()
+            .set(self.interface_index.unwrap_or_default());
+        sll2.hardware_type_mut()
+            .set(self.hardware_type.unwrap_or(1));
+        sll2.packet_type_mut()
+            .set(self.packet_type.unwrap_or_default());
+        sll2.addr_length_mut().set(self.addr.len() as u8);
+        sll2.addr_mut()[..self.addr.len()].copy_from_slice(&self.addr);
+        sll2.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        sll2
+    }
+}
+
+/// Create a Sll2 layer with the given fields.
+///
+/// # Example
+///
+/// ```
+/// # use netkit_packet::prelude::*;
+/// let sll2 = sll2!(
+///     protocol: EthType::Ipv4,
+///     interface_index: 3u32,
+///     addr: [0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+/// );
+///
+/// assert_eq!(sll2.protocol().get(), EthType::Ipv4);
+/// assert_eq!(sll2.interface_index().get(), 3);
+/// assert_eq!(sll2.addr(), [0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+/// ```
+#[macro_export]
+macro_rules! sll2 {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::sll2::Sll2Builder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
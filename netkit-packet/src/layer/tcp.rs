@@ -1,16 +1,22 @@
 //! Transmission Control Protocol (TCP) layer.
 
-use crate::{field_spec, prelude::*};
+use core::net::IpAddr;
+use std::borrow::Cow;
+
+use crate::{field_spec, layer::assemble::Assemble, prelude::*};
 
 pub mod flags;
 pub use flags::*;
+pub mod options;
+pub use options::*;
 
 /// Error type for Tcp layer.
 #[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum TcpError {
     /// Invalid Tcp length.
-    #[error("Invalid Tcp length: Length {0} is less than 8")]
-    InvalidLength(usize),
+    #[error("Invalid Tcp length: {0}")]
+    InvalidLength(#[from] LayerError),
 }
 
 field_spec!(PortSpec, u16, u16);
@@ -72,11 +78,30 @@ where
 
     /// Validate the Tcp layer.
     pub fn validate(&self) -> Result<(), TcpError> {
-        if self.data.as_ref().len() < MIN_HEADER_LENGTH {
-            return Err(TcpError::InvalidLength(self.data.as_ref().len()));
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length("Tcp", "header", 0, MIN_HEADER_LENGTH, len).into());
+        }
+
+        let offset = Self::FIELD_DATA_OFFSET.start;
+        let header_len = self.data_offset().get() as usize * 4;
+        if header_len < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_value(
+                "Tcp",
+                "data_offset",
+                offset,
+                MIN_HEADER_LENGTH,
+                header_len,
+            )
+            .into());
+        }
+        if header_len > len {
+            return Err(
+                LayerError::max_value("Tcp", "data_offset", offset, len, header_len).into(),
+            );
         }
 
-        // TODO: validate data offset, checksum, etc.
+        // TODO: validate checksum, etc.
 
         Ok(())
     }
@@ -149,6 +174,20 @@ where
         cast_from_bytes(&self.data.as_ref()[Self::FIELD_URGENT_POINTER])
     }
 
+    /// Compute the checksum over the pseudo header of the given addresses.
+    ///
+    /// The checksum covers the whole data, ignoring the current value of the
+    /// checksum field. Mixed address families are treated as IPv6 with
+    /// IPv4-mapped addresses.
+    pub fn compute_checksum(&self, src: IpAddr, dst: IpAddr) -> u16 {
+        let data = self.data.as_ref();
+        Checksum::new()
+            .add_pseudo_header(src, dst, IpProtocol::Tcp, data.len())
+            .add_bytes(&data[..Self::FIELD_CHECKSUM.start])
+            .add_bytes(&data[Self::FIELD_CHECKSUM.end..])
+            .finish()
+    }
+
     /// Get the options.
     #[inline]
     pub fn options(&self) -> &[u8] {
@@ -156,12 +195,63 @@ where
         &self.data.as_ref()[range]
     }
 
+    /// Iterate over the parsed options.
+    pub fn option_iter(&self) -> TcpOptions<'_> {
+        TcpOptions::new(self.options())
+    }
+
+    /// Get the maximum segment size option.
+    pub fn mss(&self) -> Option<u16> {
+        self.option_iter().find_map(|option| match option {
+            TcpOption::Mss(mss) => Some(mss),
+            _ => None,
+        })
+    }
+
+    /// Get the window scale shift count, clamped to [`MAX_WINDOW_SCALE`].
+    pub fn window_scale(&self) -> Option<u8> {
+        self.option_iter().find_map(|option| match option {
+            TcpOption::WindowScale(shift) => Some(shift.min(MAX_WINDOW_SCALE)),
+            _ => None,
+        })
+    }
+
+    /// Get the TCP Fast Open cookie, if present; empty in a cookie request.
+    pub fn fast_open_cookie(&self) -> Option<&[u8]> {
+        self.option_iter().find_map(|option| match option {
+            TcpOption::FastOpen(cookie) => Some(cookie),
+            _ => None,
+        })
+    }
+
+    /// Iterate over the Multipath TCP options.
+    pub fn mptcp_options(&self) -> impl Iterator<Item = MptcpOption<'_>> {
+        self.option_iter().filter_map(|option| match option {
+            TcpOption::Mptcp(option) => Some(option),
+            _ => None,
+        })
+    }
+
     /// Get the payload.
     #[inline]
     pub fn payload(&self) -> &[u8] {
         let range = self.data_offset().get() as usize * 4..;
         &self.data.as_ref()[range]
     }
+
+    /// Get the first OpenVPN packet if either port is the OpenVPN port.
+    ///
+    /// The 16-bit length prefix used over TCP is stripped.
+    pub fn openvpn(&self) -> Option<OpenVpn<&[u8]>> {
+        let port = crate::layer::openvpn::DEFAULT_PORT;
+        if self.src_port().get() != port && self.dst_port().get() != port {
+            return None;
+        }
+
+        let payload = self.payload();
+        let len = u16::from_be_bytes([*payload.first()?, *payload.get(1)?]) as usize;
+        OpenVpn::new(payload.get(2..2 + len)?).ok()
+    }
 }
 
 impl<T> Tcp<T>
@@ -247,6 +337,7 @@ layer_impl!(Tcp);
 
 /// Builder for [`Tcp`].
 #[derive(Clone, Debug, Default)]
+#[must_use]
 pub struct TcpBuilder {
     src_port: Option<u16>,
     dst_port: Option<u16>,
@@ -258,7 +349,10 @@ pub struct TcpBuilder {
     checksum: Option<u16>,
     urgent_pointer: Option<u16>,
     options: Vec<u8>,
+    pseudo_header: Option<(IpAddr, IpAddr)>,
+    partial_checksum: bool,
     payload: Vec<u8>,
+    profile: Option<Profile>,
 }
 
 impl TcpBuilder {
@@ -267,6 +361,17 @@ impl TcpBuilder {
         Self::default()
     }
 
+    /// Create a new Tcp builder using the defaults of a profile.
+    ///
+    /// The profile decides the window size and, for SYN segments, the
+    /// options unless they are set explicitly.
+    pub fn with_defaults(profile: Profile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Self::default()
+        }
+    }
+
     /// Set the source port.
     pub fn src_port(&mut self, src_port: impl Into<u16>) -> &mut Self {
         self.src_port = Some(src_port.into());
@@ -327,6 +432,27 @@ impl TcpBuilder {
         self
     }
 
+    /// Set the source and destination addresses of the pseudo header, so
+    /// that the checksum is computed.
+    pub fn pseudo_header<S, D>(&mut self, (src, dst): (S, D)) -> &mut Self
+    where
+        S: Into<IpAddr>,
+        D: Into<IpAddr>,
+    {
+        self.pseudo_header = Some((src.into(), dst.into()));
+        self
+    }
+
+    /// Emit a partial checksum, as with checksum offload.
+    ///
+    /// The checksum field then holds the sum of the pseudo header only (see
+    /// [`partial_checksum`](crate::utils::checksum::partial_checksum)),
+    /// which needs the pseudo header to be set.
+    pub fn partial_checksum(&mut self, partial: bool) -> &mut Self {
+        self.partial_checksum = partial;
+        self
+    }
+
     /// Set the payload.
     pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
         self.payload.extend_from_slice(payload.as_ref());
@@ -334,28 +460,74 @@ impl TcpBuilder {
     }
 
     /// Build the Tcp layer.
+    ///
+    /// Unless set explicitly, the checksum is computed (or only its partial
+    /// sum, see [`partial_checksum`](Self::partial_checksum)) when the pseudo
+    /// header is set, and left zero otherwise.
     pub fn build(&self) -> Tcp<Vec<u8>> {
-        // Calculate the data offset
-        let data_offset = self.data_offset.unwrap_or(self.options.len() as u8 / 4 + 5);
+        let mut data = vec![0; self.header_len()];
+        data.extend_from_slice(&self.payload);
+        self.write_header(&mut data);
+
+        unsafe { Tcp::new_unchecked(data) }
+    }
+
+    /// Get the options, from the profile for SYN segments without options.
+    fn options_or_default(&self) -> Cow<'_, [u8]> {
+        let syn = self.flags.unwrap_or_default().contains(TcpFlags::SYN);
+        match self.profile {
+            Some(profile) if syn && self.options.is_empty() => Cow::Owned(profile.syn_options()),
+            _ => Cow::Borrowed(&self.options),
+        }
+    }
+
+    /// Get the data offset, calculated from the options if not set.
+    fn data_offset_or_default(&self) -> u8 {
+        self.data_offset
+            .unwrap_or(self.options_or_default().len() as u8 / 4 + 5)
+    }
+}
+
+impl Assemble for TcpBuilder {
+    fn header_len(&self) -> usize {
+        self.data_offset_or_default() as usize * 4
+    }
+
+    fn write_header(&self, segment: &mut [u8]) {
+        let flags = self.flags.unwrap_or_default();
+        let syn = flags.contains(TcpFlags::SYN);
+        let options = self.options_or_default();
+
+        let window_size = self.window_size.unwrap_or(match self.profile {
+            Some(profile) if syn => profile.syn_window_size(),
+            Some(profile) => profile.window_size(),
+            None => 64,
+        });
 
-        let mut tcp =
-            unsafe { Tcp::new_unchecked(vec![0; data_offset as usize * 4 + self.payload.len()]) };
+        let data_offset = self.data_offset_or_default();
+        let mut tcp = unsafe { Tcp::new_unchecked(segment) };
 
         tcp.src_port_mut().set(self.src_port.unwrap_or_default());
         tcp.dst_port_mut().set(self.dst_port.unwrap_or_default());
         tcp.seq_num_mut().set(self.seq_num.unwrap_or_default());
         tcp.ack_num_mut().set(self.ack_num.unwrap_or_default());
         tcp.data_offset_mut().set(data_offset);
-        tcp.flags_mut().set(self.flags.unwrap_or_default());
-        tcp.window_size_mut().set(self.window_size.unwrap_or(64));
-        tcp.checksum_mut().set(self.checksum.unwrap_or_default());
+        tcp.flags_mut().set(flags);
+        tcp.window_size_mut().set(window_size);
         tcp.urgent_pointer_mut()
             .set(self.urgent_pointer.unwrap_or_default());
 
-        tcp.options_mut().copy_from_slice(self.options.as_ref());
-        tcp.payload_mut().copy_from_slice(self.payload.as_ref());
+        tcp.options_mut().copy_from_slice(&options);
 
-        tcp
+        let checksum = match (self.checksum, self.pseudo_header) {
+            (Some(checksum), _) => checksum,
+            (None, Some((src, dst))) if self.partial_checksum => {
+                partial_checksum(src, dst, IpProtocol::Tcp, tcp.inner().len())
+            }
+            (None, Some((src, dst))) => tcp.compute_checksum(src, dst),
+            (None, None) => 0,
+        };
+        tcp.checksum_mut().set(checksum);
     }
 }
 
@@ -371,7 +543,10 @@ macro_rules! tcp {
 
 #[cfg(test)]
 mod tests {
-    use crate::{layer::tcp::TcpFlags, prelude::*};
+    use crate::{
+        layer::tcp::{TcpBuilder, TcpFlags},
+        prelude::*,
+    };
 
     #[test]
     fn tcp_new_unchecked() {
@@ -402,6 +577,41 @@ mod tests {
         assert_eq!(tcp.payload(), &[0x01, 0x02, 0x03, 0x04]);
     }
 
+    #[test]
+    fn tcp_validate_data_offset() {
+        let mut data = [0u8; 24];
+
+        data[12] = 0x40; // data_offset = 4
+        let Err(TcpError::InvalidLength(err)) = Tcp::new(&data[..]) else {
+            panic!("data offset 4 should be rejected");
+        };
+        assert_eq!((err.field, err.offset), ("data_offset", 12));
+        assert_eq!(
+            err.constraint,
+            Constraint::MinValue {
+                min: 20,
+                actual: 16
+            }
+        );
+
+        data[12] = 0x70; // data_offset = 7, beyond the 24 bytes
+        let Err(TcpError::InvalidLength(err)) = Tcp::new(&data[..]) else {
+            panic!("data offset 7 should be rejected");
+        };
+        assert_eq!(
+            err.constraint,
+            Constraint::MaxValue {
+                max: 24,
+                actual: 28
+            }
+        );
+
+        data[12] = 0x60; // data_offset = 6
+        let tcp = Tcp::new(&data[..]).unwrap();
+        assert_eq!(tcp.options(), &[0; 4]);
+        assert!(tcp.payload().is_empty());
+    }
+
     #[test]
     fn tcp_macro() {
         let tcp = tcp! {
@@ -419,4 +629,43 @@ mod tests {
         assert_eq!(tcp.urgent_pointer().get(), 0);
         assert_eq!(tcp.payload(), &[0x01, 0x02, 0x03, 0x04]);
     }
+
+    #[test]
+    fn tcp_profile() {
+        let syn = TcpBuilder::with_defaults(Profile::LinuxHost)
+            .flags(TcpFlags::SYN)
+            .build();
+        assert_eq!(syn.window_size().get(), 64240);
+        assert_eq!(syn.data_offset().get(), 10);
+        assert_eq!(syn.options(), Profile::LinuxHost.syn_options());
+        assert_eq!(syn.mss(), Some(1460));
+        assert_eq!(syn.window_scale(), Some(7));
+
+        let ack = TcpBuilder::with_defaults(Profile::LinuxHost)
+            .flags(TcpFlags::ACK)
+            .window_size(1000u16)
+            .build();
+        assert_eq!(ack.window_size().get(), 1000);
+        assert_eq!(ack.data_offset().get(), 5);
+    }
+
+    #[test]
+    fn tcp_checksum() {
+        use core::net::Ipv4Addr;
+
+        let (src, dst) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+        let tcp = tcp!(src_port: 80u16, pseudo_header: (src, dst), payload: b"hello");
+        let ipv4 = ipv4!(src: src, dst: dst, protocol: IpProtocol::Tcp, payload: tcp.inner());
+        assert_eq!(ipv4.transport_checksum(), Some(tcp.checksum().get()));
+
+        let tcp = tcp!(
+            pseudo_header: (src, dst),
+            partial_checksum: true,
+            payload: b"hello",
+        );
+        assert_eq!(
+            tcp.checksum().get(),
+            partial_checksum(src.into(), dst.into(), IpProtocol::Tcp, 20 + 5)
+        );
+    }
 }
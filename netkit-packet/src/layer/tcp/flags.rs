@@ -47,6 +47,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn tcp_flags() {
         let flags = TcpFlags::SYN | TcpFlags::ACK;
         assert_eq!(flags, TcpFlags::SYN | TcpFlags::ACK);
@@ -0,0 +1,290 @@
+//! TCP options.
+
+/// Option kind End of Option List.
+pub const KIND_EOL: u8 = 0;
+/// Option kind No-Operation.
+pub const KIND_NOP: u8 = 1;
+/// Option kind Maximum Segment Size.
+pub const KIND_MSS: u8 = 2;
+/// Option kind Window Scale.
+pub const KIND_WINDOW_SCALE: u8 = 3;
+/// Option kind SACK Permitted.
+pub const KIND_SACK_PERMITTED: u8 = 4;
+/// Option kind SACK.
+pub const KIND_SACK: u8 = 5;
+/// Option kind Timestamps.
+pub const KIND_TIMESTAMPS: u8 = 8;
+/// Option kind Multipath TCP ([RFC 8684]).
+///
+/// [RFC 8684]: https://datatracker.ietf.org/doc/html/rfc8684
+pub const KIND_MPTCP: u8 = 30;
+/// Option kind TCP Fast Open cookie ([RFC 7413]).
+///
+/// [RFC 7413]: https://datatracker.ietf.org/doc/html/rfc7413
+pub const KIND_FAST_OPEN: u8 = 34;
+
+/// MPTCP option subtype MP_CAPABLE.
+pub const MPTCP_MP_CAPABLE: u8 = 0;
+/// MPTCP option subtype MP_JOIN.
+pub const MPTCP_MP_JOIN: u8 = 1;
+/// MPTCP option subtype DSS (data sequence signal).
+pub const MPTCP_DSS: u8 = 2;
+
+/// Maximum window scale shift ([RFC 7323]); larger shifts are clamped.
+///
+/// [RFC 7323]: https://datatracker.ietf.org/doc/html/rfc7323#section-2.3
+pub const MAX_WINDOW_SCALE: u8 = 14;
+
+/// A parsed TCP option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpOption<'a> {
+    /// End of option list.
+    Eol,
+    /// No-operation padding.
+    Nop,
+    /// Maximum segment size.
+    Mss(u16),
+    /// Window scale shift count.
+    WindowScale(u8),
+    /// SACK permitted.
+    SackPermitted,
+    /// SACK blocks, as raw left/right edge pairs.
+    Sack(&'a [u8]),
+    /// Timestamp value and echo reply.
+    Timestamps {
+        /// Timestamp value.
+        value: u32,
+        /// Timestamp echo reply.
+        echo: u32,
+    },
+    /// TCP Fast Open cookie; empty in a cookie request.
+    FastOpen(&'a [u8]),
+    /// Multipath TCP option.
+    Mptcp(MptcpOption<'a>),
+    /// Any other option.
+    Unknown {
+        /// Option kind.
+        kind: u8,
+        /// Option data, without kind and length.
+        data: &'a [u8],
+    },
+}
+
+/// A parsed Multipath TCP option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MptcpOption<'a> {
+    /// Multipath capable, sent in the handshake of the initial subflow.
+    MpCapable {
+        /// Protocol version.
+        version: u8,
+        /// Flags.
+        flags: u8,
+        /// Key of the sender, absent in version 1 SYNs.
+        sender_key: Option<u64>,
+        /// Key of the receiver, echoed in the third ACK.
+        receiver_key: Option<u64>,
+    },
+    /// Join of an additional subflow.
+    MpJoin {
+        /// Whether the subflow is a backup path.
+        backup: bool,
+        /// Address identifier of the sender.
+        address_id: u8,
+        /// Token of the connection at the receiver, only sent in SYNs.
+        token: Option<u32>,
+    },
+    /// Data sequence signal.
+    Dss {
+        /// Data-level acknowledgment.
+        data_ack: Option<u64>,
+        /// Data sequence number of the mapping.
+        data_seq: Option<u64>,
+        /// Whether the data FIN flag is set.
+        data_fin: bool,
+    },
+    /// Any other subtype.
+    Other {
+        /// Option subtype.
+        subtype: u8,
+        /// Option data, without kind and length.
+        data: &'a [u8],
+    },
+}
+
+impl<'a> MptcpOption<'a> {
+    /// Parse the data of an MPTCP option, without kind and length.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let subtype = *data.first()? >> 4;
+        let option = match subtype {
+            MPTCP_MP_CAPABLE => Self::MpCapable {
+                version: data[0] & 0x0F,
+                flags: *data.get(1)?,
+                sender_key: data.get(2..10).map(be_u64),
+                receiver_key: data.get(10..18).map(be_u64),
+            },
+            MPTCP_MP_JOIN => Self::MpJoin {
+                backup: data[0] & 0x01 != 0,
+                address_id: *data.get(1)?,
+                // SYNs are 12 bytes long, including kind and length.
+                token: (data.len() == 10).then(|| be_u32(&data[2..6])),
+            },
+            MPTCP_DSS => {
+                let flags = *data.get(1)?;
+                let mut rest = &data[2..];
+                let mut take = |present: u8, long: u8| {
+                    if flags & present == 0 {
+                        return Some(None);
+                    }
+                    let len = if flags & long != 0 { 8 } else { 4 };
+                    let value = rest.get(..len)?;
+                    rest = &rest[len..];
+                    Some(Some(if len == 8 {
+                        be_u64(value)
+                    } else {
+                        be_u32(value) as u64
+                    }))
+                };
+                let data_ack = take(0x01, 0x02)?;
+                let data_seq = take(0x04, 0x08)?;
+                Self::Dss {
+                    data_ack,
+                    data_seq,
+                    data_fin: flags & 0x10 != 0,
+                }
+            }
+            _ => Self::Other { subtype, data },
+        };
+        Some(option)
+    }
+}
+
+fn be_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+fn be_u64(data: &[u8]) -> u64 {
+    u64::from_be_bytes(data[..8].try_into().unwrap())
+}
+
+/// Iterator over TCP options.
+///
+/// Stops at the end of option list or at the first malformed option.
+#[derive(Clone, Debug)]
+pub struct TcpOptions<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> TcpOptions<'a> {
+    /// Iterate over the options in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for TcpOptions<'a> {
+    type Item = TcpOption<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = *self.data.first()?;
+        match kind {
+            KIND_EOL => {
+                self.data = &[];
+                return Some(TcpOption::Eol);
+            }
+            KIND_NOP => {
+                self.data = &self.data[1..];
+                return Some(TcpOption::Nop);
+            }
+            _ => {}
+        }
+
+        let len = self.data.get(1).map_or(0, |len| *len as usize);
+        let Some(data) = self.data.get(2..len) else {
+            self.data = &[];
+            return None;
+        };
+        self.data = &self.data[len..];
+
+        let option = match (kind, data.len()) {
+            (KIND_MSS, 2) => TcpOption::Mss(u16::from_be_bytes([data[0], data[1]])),
+            (KIND_WINDOW_SCALE, 1) => TcpOption::WindowScale(data[0]),
+            (KIND_SACK_PERMITTED, 0) => TcpOption::SackPermitted,
+            (KIND_SACK, _) => TcpOption::Sack(data),
+            (KIND_TIMESTAMPS, 8) => TcpOption::Timestamps {
+                value: be_u32(&data[..4]),
+                echo: be_u32(&data[4..]),
+            },
+            (KIND_FAST_OPEN, _) => TcpOption::FastOpen(data),
+            (KIND_MPTCP, _) => match MptcpOption::parse(data) {
+                Some(option) => TcpOption::Mptcp(option),
+                None => TcpOption::Unknown { kind, data },
+            },
+            _ => TcpOption::Unknown { kind, data },
+        };
+        Some(option)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+
+    #[test]
+    fn tcp_options_parse() {
+        let options = Profile::LinuxHost.syn_options();
+        let options: Vec<_> = TcpOptions::new(&options).collect();
+        assert_eq!(
+            options,
+            [
+                TcpOption::Mss(1460),
+                TcpOption::SackPermitted,
+                TcpOption::Timestamps { value: 0, echo: 0 },
+                TcpOption::Nop,
+                TcpOption::WindowScale(7),
+            ]
+        );
+
+        // Truncated option.
+        let options: Vec<_> = TcpOptions::new(&[1, 2, 4, 5]).collect();
+        assert_eq!(options, [TcpOption::Nop]);
+    }
+
+    #[test]
+    fn tcp_options_mptcp_fast_open() {
+        let mut options = vec![KIND_FAST_OPEN, 6, 1, 2, 3, 4];
+        // MP_CAPABLE v0 with the sender key
+        options.extend([KIND_MPTCP, 12, 0x00, 0x81]);
+        options.extend(7u64.to_be_bytes());
+        // MP_JOIN SYN, backup, address 2
+        options.extend([KIND_MPTCP, 12, 0x11, 2, 0, 0, 0, 9, 0, 0, 0, 0]);
+        // DSS with a 4-byte data ACK and an 8-byte DSN
+        options.extend([KIND_MPTCP, 20, 0x20, 0x0D, 0, 0, 0, 5]);
+        options.extend(6u64.to_be_bytes());
+        options.extend([0, 0, 0, 0]);
+
+        let options: Vec<_> = TcpOptions::new(&options).collect();
+        assert_eq!(
+            options,
+            [
+                TcpOption::FastOpen(&[1, 2, 3, 4]),
+                TcpOption::Mptcp(MptcpOption::MpCapable {
+                    version: 0,
+                    flags: 0x81,
+                    sender_key: Some(7),
+                    receiver_key: None,
+                }),
+                TcpOption::Mptcp(MptcpOption::MpJoin {
+                    backup: true,
+                    address_id: 2,
+                    token: Some(9),
+                }),
+                TcpOption::Mptcp(MptcpOption::Dss {
+                    data_ack: Some(5),
+                    data_seq: Some(6),
+                    data_fin: false,
+                }),
+            ]
+        );
+    }
+}
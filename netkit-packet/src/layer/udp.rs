@@ -1,17 +1,24 @@
 //! User Datagram Protocol (UDP) layer.
 
-use crate::{field_spec, prelude::*};
+use core::net::IpAddr;
+
+use crate::{field_spec, layer::assemble::Assemble, prelude::*};
 
 /// Error type for Udp layer.
 #[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum UdpError {
     /// Invalid Udp length.
-    #[error("Invalid Udp length: Length {0} is less than 8")]
-    InvalidLength(usize),
+    #[error("Invalid Udp length: {0}")]
+    InvalidLength(#[from] LayerError),
 
     /// Invalid Udp checksum.
     #[error("Invalid Udp checksum")]
     InvalidChecksum,
+
+    /// Zero Udp checksum over IPv6, where the checksum is mandatory.
+    #[error("Zero Udp checksum is illegal over IPv6")]
+    ZeroChecksum,
 }
 
 field_spec!(PortSpec, u16, u16);
@@ -60,7 +67,14 @@ where
     /// Validate the Udp layer.
     pub fn validate(&self) -> Result<(), UdpError> {
         if self.data.as_ref().len() < MIN_HEADER_LENGTH {
-            return Err(UdpError::InvalidLength(self.data.as_ref().len()));
+            return Err(LayerError::min_length(
+                "Udp",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
         }
 
         Ok(())
@@ -109,6 +123,114 @@ where
     pub fn payload(&self) -> &[u8] {
         &self.data.as_ref()[Self::FIELD_PAYLOAD]
     }
+
+    /// Compute the checksum over the pseudo header of the given addresses.
+    ///
+    /// The checksum covers the datagram up to its length field (or the end
+    /// of the data if shorter), ignoring the current value of the checksum
+    /// field. A computed checksum of zero is returned as all ones, since
+    /// zero means "no checksum". Mixed address families are treated as IPv6
+    /// with IPv4-mapped addresses.
+    pub fn compute_checksum(&self, src: IpAddr, dst: IpAddr) -> u16 {
+        let data = self.data.as_ref();
+        let len = (self.length().get() as usize).clamp(MIN_HEADER_LENGTH, data.len());
+        let datagram = &data[..len];
+
+        let checksum = Checksum::new()
+            .add_pseudo_header(src, dst, IpProtocol::Udp, len)
+            .add_bytes(&datagram[..Self::FIELD_CHECKSUM.start])
+            .add_bytes(&datagram[Self::FIELD_CHECKSUM.end..])
+            .finish();
+
+        if checksum == 0 {
+            0xFFFF
+        } else {
+            checksum
+        }
+    }
+
+    /// Verify the checksum against the pseudo header of the given addresses.
+    ///
+    /// A zero checksum means "no checksum" over IPv4 and is accepted, but it
+    /// is illegal over IPv6 ([RFC 8200 section 8.1]) and reported as
+    /// [`UdpError::ZeroChecksum`].
+    ///
+    /// [RFC 8200 section 8.1]: https://datatracker.ietf.org/doc/html/rfc8200#section-8.1
+    pub fn verify_checksum(&self, src: IpAddr, dst: IpAddr) -> Result<(), UdpError> {
+        match self.checksum().get() {
+            0 if src.is_ipv4() && dst.is_ipv4() => Ok(()),
+            0 => Err(UdpError::ZeroChecksum),
+            checksum if checksum == self.compute_checksum(src, dst) => Ok(()),
+            _ => Err(UdpError::InvalidChecksum),
+        }
+    }
+
+    /// Get the WireGuard layer if the payload is recognized as WireGuard.
+    ///
+    /// WireGuard may run on any port, so the payload structure is checked
+    /// instead of the ports.
+    pub fn wireguard(&self) -> Option<WireGuard<&[u8]>> {
+        WireGuard::new(self.payload()).ok()
+    }
+
+    /// Get the DNS layer if either port is the DNS port.
+    pub fn dns(&self) -> Option<Dns<&[u8]>> {
+        let port = crate::layer::dns::DEFAULT_PORT;
+        if self.src_port().get() == port || self.dst_port().get() == port {
+            Dns::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the Quic layer if either port is the QUIC port.
+    pub fn quic(&self) -> Option<Quic<&[u8]>> {
+        let port = crate::layer::quic::DEFAULT_PORT;
+        if self.src_port().get() == port || self.dst_port().get() == port {
+            Quic::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the L2TP layer if either port is the L2TP port.
+    pub fn l2tp(&self) -> Option<L2tp<&[u8]>> {
+        let port = crate::layer::l2tp::DEFAULT_PORT;
+        if self.src_port().get() == port || self.dst_port().get() == port {
+            L2tp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the OpenVPN layer if either port is the OpenVPN port.
+    pub fn openvpn(&self) -> Option<OpenVpn<&[u8]>> {
+        let port = crate::layer::openvpn::DEFAULT_PORT;
+        if self.src_port().get() == port || self.dst_port().get() == port {
+            OpenVpn::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Get the Rtp layer if the payload is recognized as Rtp.
+    ///
+    /// RTP runs on dynamically negotiated ports, so only the header is
+    /// checked, which also accepts some payloads of other protocols.
+    pub fn rtp(&self) -> Option<Rtp<&[u8]>> {
+        Rtp::new(self.payload()).ok()
+    }
+
+    /// Get the Ptp layer if either port is a PTP port.
+    pub fn ptp(&self) -> Option<Ptp<&[u8]>> {
+        use crate::layer::ptp::{EVENT_PORT, GENERAL_PORT};
+        let is_ptp = |port| port == EVENT_PORT || port == GENERAL_PORT;
+        if is_ptp(self.src_port().get()) || is_ptp(self.dst_port().get()) {
+            Ptp::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
 }
 
 impl<T> Udp<T>
@@ -156,11 +278,14 @@ layer_impl!(Udp);
 
 /// Builder for [`Udp`].
 #[derive(Clone, Debug, Default)]
+#[must_use]
 pub struct UdpBuilder {
     src_port: Option<u16>,
     dst_port: Option<u16>,
     length: Option<u16>,
     checksum: Option<u16>,
+    pseudo_header: Option<(IpAddr, IpAddr)>,
+    partial_checksum: bool,
     payload: Vec<u8>,
 }
 
@@ -194,6 +319,27 @@ impl UdpBuilder {
         self
     }
 
+    /// Set the source and destination addresses of the pseudo header, so
+    /// that the checksum is computed.
+    pub fn pseudo_header<S, D>(&mut self, (src, dst): (S, D)) -> &mut Self
+    where
+        S: Into<IpAddr>,
+        D: Into<IpAddr>,
+    {
+        self.pseudo_header = Some((src.into(), dst.into()));
+        self
+    }
+
+    /// Emit a partial checksum, as with checksum offload.
+    ///
+    /// The checksum field then holds the sum of the pseudo header only (see
+    /// [`partial_checksum`](crate::utils::checksum::partial_checksum)),
+    /// which needs the pseudo header to be set.
+    pub fn partial_checksum(&mut self, partial: bool) -> &mut Self {
+        self.partial_checksum = partial;
+        self
+    }
+
     /// Set the payload.
     pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
         self.payload.extend_from_slice(payload.as_ref());
@@ -201,23 +347,47 @@ impl UdpBuilder {
     }
 
     /// Build a Udp layer.
+    ///
+    /// Unless set explicitly, the checksum is computed (or only its partial
+    /// sum, see [`partial_checksum`](Self::partial_checksum)) when the pseudo
+    /// header is set, and left zero ("no checksum", only legal over IPv4)
+    /// otherwise.
     pub fn build(&self) -> Udp<Vec<u8>> {
         // Calculate the length if not provided
         let len = self
             .length
-            .unwrap_or(MIN_HEADER_LENGTH as u16 + self.payload.len() as u16);
+            .map_or(MIN_HEADER_LENGTH + self.payload.len(), usize::from);
+
+        let mut data = vec![0; len];
+        data[MIN_HEADER_LENGTH..].copy_from_slice(self.payload.as_ref());
+        self.write_header(&mut data);
+
+        unsafe { Udp::new_unchecked(data) }
+    }
+}
+
+impl Assemble for UdpBuilder {
+    fn header_len(&self) -> usize {
+        MIN_HEADER_LENGTH
+    }
 
-        let mut udp = unsafe { Udp::new_unchecked(vec![0; len as usize]) };
+    fn write_header(&self, segment: &mut [u8]) {
+        let len = self.length.unwrap_or(segment.len() as u16);
+        let mut udp = unsafe { Udp::new_unchecked(segment) };
 
         udp.src_port_mut().set(self.src_port.unwrap_or_default());
         udp.dst_port_mut().set(self.dst_port.unwrap_or_default());
         udp.length_mut().set(len);
-        // TODO: Calculate checksum
-        udp.checksum_mut().set(self.checksum.unwrap_or_default());
-
-        udp.payload_mut().copy_from_slice(self.payload.as_ref());
 
-        udp
+        let checksum = match (self.checksum, self.pseudo_header) {
+            (Some(checksum), _) => checksum,
+            (None, Some((src, dst))) if self.partial_checksum => {
+                partial_checksum(src, dst, IpProtocol::Udp, len as usize)
+            }
+            (None, Some((src, dst))) => udp.compute_checksum(src, dst),
+            (None, None) => 0,
+        };
+        udp.checksum_mut().set(checksum);
     }
 }
 
@@ -269,4 +439,54 @@ mod tests {
         assert_eq!(udp.length().get(), 10);
         assert_eq!(udp.checksum().get(), 0);
     }
+
+    #[test]
+    fn udp_checksum_zero() {
+        use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+        let v4 = (
+            IpAddr::from(Ipv4Addr::new(192, 168, 0, 1)),
+            IpAddr::from(Ipv4Addr::new(192, 168, 0, 199)),
+        );
+        let v6 = (
+            IpAddr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            IpAddr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2)),
+        );
+
+        // No checksum: legal over IPv4 only.
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: b"hello");
+        assert_eq!(udp.checksum().get(), 0);
+        assert_eq!(udp.verify_checksum(v4.0, v4.1), Ok(()));
+        assert_eq!(udp.verify_checksum(v6.0, v6.1), Err(UdpError::ZeroChecksum));
+
+        // Computed by the builder given the pseudo header.
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, pseudo_header: v6, payload: b"hello");
+        assert_ne!(udp.checksum().get(), 0);
+        assert_eq!(udp.verify_checksum(v6.0, v6.1), Ok(()));
+        assert_eq!(
+            udp.verify_checksum(v6.1, v6.1),
+            Err(UdpError::InvalidChecksum)
+        );
+
+        // Agrees with the Ipv4 layer's computation.
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, pseudo_header: v4, payload: b"hello");
+        let ipv4 = ipv4!(
+            src: Ipv4Addr::new(192, 168, 0, 1),
+            dst: Ipv4Addr::new(192, 168, 0, 199),
+            protocol: IpProtocol::Udp,
+            payload: udp.inner(),
+        );
+        assert_eq!(ipv4.transport_checksum(), Some(udp.checksum().get()));
+
+        // Left for the NIC to complete.
+        let udp = udp!(pseudo_header: v4, partial_checksum: true, payload: b"hello");
+        assert_eq!(
+            udp.checksum().get(),
+            partial_checksum(v4.0, v4.1, IpProtocol::Udp, 13)
+        );
+
+        // An explicit checksum wins.
+        let udp = udp!(pseudo_header: v6, checksum: 0u16);
+        assert_eq!(udp.checksum().get(), 0);
+    }
 }
@@ -0,0 +1,426 @@
+//! Lightweight User Datagram Protocol (UDP-Lite) layer.
+//!
+//! UDP-Lite ([RFC 3828]) is UDP whose checksum may cover only the start of
+//! the datagram, so that applications tolerating errors in the rest of the
+//! payload, like some codecs, receive damaged datagrams instead of losing
+//! them. The length field of UDP becomes the checksum coverage; the length
+//! of the datagram is taken from the IP layer.
+//!
+//! [RFC 3828]: https://datatracker.ietf.org/doc/html/rfc3828
+
+use core::net::IpAddr;
+
+use crate::{
+    field_spec,
+    layer::{assemble::Assemble, udp::PortSpec},
+    prelude::*,
+};
+
+/// Error type for UdpLite layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum UdpLiteError {
+    /// Invalid UdpLite length.
+    #[error("Invalid UdpLite length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Invalid UdpLite checksum.
+    #[error("Invalid UdpLite checksum")]
+    InvalidChecksum,
+
+    /// Zero UdpLite checksum, which is always illegal.
+    #[error("Zero UdpLite checksum is illegal")]
+    ZeroChecksum,
+}
+
+field_spec!(CoverageSpec, u16, u16);
+field_spec!(ChecksumSpec, u16, u16);
+
+/// Minimum length of a UdpLite packet.
+pub const MIN_HEADER_LENGTH: usize = 8;
+
+/// Lightweight User Datagram Protocol (UDP-Lite) layer.
+///
+/// The layer spans the whole datagram: its data must end where the IP
+/// payload ends, as the coverage is checked against it.
+pub struct UdpLite<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> UdpLite<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the source port: 0..2
+    pub const FIELD_SRC_PORT: core::ops::Range<usize> = 0..2;
+    /// Field range of the destination port: 2..4
+    pub const FIELD_DST_PORT: core::ops::Range<usize> = 2..4;
+    /// Field range of the checksum coverage: 4..6
+    pub const FIELD_COVERAGE: core::ops::Range<usize> = 4..6;
+    /// Field range of the checksum: 6..8
+    pub const FIELD_CHECKSUM: core::ops::Range<usize> = 6..8;
+    /// Field range of the payload: 8..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 8..;
+
+    /// Create a new UdpLite layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid UdpLite packet.
+    ///
+    /// The length of the data must be at least 8 bytes. Otherwise, the
+    /// following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the UdpLite layer.
+    ///
+    /// The coverage must be zero (the whole datagram), or cover at least
+    /// the header and at most the datagram.
+    pub fn validate(&self) -> Result<(), UdpLiteError> {
+        let len = self.data.as_ref().len();
+        if len < MIN_HEADER_LENGTH {
+            return Err(
+                LayerError::min_length("UdpLite", "header", 0, MIN_HEADER_LENGTH, len).into(),
+            );
+        }
+
+        let offset = Self::FIELD_COVERAGE.start;
+        match self.coverage().get() as usize {
+            0 => Ok(()),
+            coverage if coverage < MIN_HEADER_LENGTH => Err(LayerError::min_value(
+                "UdpLite",
+                "coverage",
+                offset,
+                MIN_HEADER_LENGTH,
+                coverage,
+            )
+            .into()),
+            coverage if coverage > len => {
+                Err(LayerError::max_value("UdpLite", "coverage", offset, len, coverage).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Create a new UdpLite layer.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, UdpLiteError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the source port.
+    #[inline]
+    pub fn src_port(&self) -> &Field<PortSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_SRC_PORT])
+    }
+
+    /// Get the accessor of the destination port.
+    #[inline]
+    pub fn dst_port(&self) -> &Field<PortSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DST_PORT])
+    }
+
+    /// Get the accessor of the checksum coverage, in bytes from the start
+    /// of the header, or zero for the whole datagram.
+    #[inline]
+    pub fn coverage(&self) -> &Field<CoverageSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_COVERAGE])
+    }
+
+    /// Get the accessor of the checksum.
+    #[inline]
+    pub fn checksum(&self) -> &Field<ChecksumSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_CHECKSUM])
+    }
+
+    /// Get the number of bytes covered by the checksum, header included.
+    #[inline]
+    pub fn covered_len(&self) -> usize {
+        let len = self.data.as_ref().len();
+        match self.coverage().get() as usize {
+            0 => len,
+            coverage => coverage.clamp(MIN_HEADER_LENGTH, len),
+        }
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Get the part of the payload covered by the checksum.
+    #[inline]
+    pub fn covered_payload(&self) -> &[u8] {
+        &self.data.as_ref()[MIN_HEADER_LENGTH..self.covered_len()]
+    }
+
+    /// Compute the checksum over the pseudo header of the given addresses.
+    ///
+    /// The pseudo header holds the length of the whole datagram, while only
+    /// the covered bytes are summed, ignoring the current value of the
+    /// checksum field. A computed checksum of zero is returned as all ones.
+    pub fn compute_checksum(&self, src: IpAddr, dst: IpAddr) -> u16 {
+        let data = self.data.as_ref();
+        let covered = &data[..self.covered_len()];
+
+        let checksum = Checksum::new()
+            .add_pseudo_header(src, dst, IpProtocol::UdpLite, data.len())
+            .add_bytes(&covered[..Self::FIELD_CHECKSUM.start])
+            .add_bytes(&covered[Self::FIELD_CHECKSUM.end..])
+            .finish();
+
+        if checksum == 0 {
+            0xFFFF
+        } else {
+            checksum
+        }
+    }
+
+    /// Verify the checksum against the pseudo header of the given addresses.
+    ///
+    /// Unlike UDP, a zero checksum is illegal over both IPv4 and IPv6, and
+    /// reported as [`UdpLiteError::ZeroChecksum`].
+    pub fn verify_checksum(&self, src: IpAddr, dst: IpAddr) -> Result<(), UdpLiteError> {
+        match self.checksum().get() {
+            0 => Err(UdpLiteError::ZeroChecksum),
+            checksum if checksum == self.compute_checksum(src, dst) => Ok(()),
+            _ => Err(UdpLiteError::InvalidChecksum),
+        }
+    }
+}
+
+impl<T> UdpLite<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the source port.
+    #[inline]
+    pub fn src_port_mut(&mut self) -> &mut Field<PortSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_SRC_PORT])
+    }
+
+    /// Get the mutable accessor of the destination port.
+    #[inline]
+    pub fn dst_port_mut(&mut self) -> &mut Field<PortSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DST_PORT])
+    }
+
+    /// Get the mutable accessor of the checksum coverage.
+    #[inline]
+    pub fn coverage_mut(&mut self) -> &mut Field<CoverageSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_COVERAGE])
+    }
+
+    /// Get the mutable accessor of the checksum.
+    #[inline]
+    pub fn checksum_mut(&mut self) -> &mut Field<ChecksumSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_CHECKSUM])
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(UdpLite);
+
+/// Builder for [`UdpLite`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct UdpLiteBuilder {
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    coverage: Option<u16>,
+    checksum: Option<u16>,
+    pseudo_header: Option<(IpAddr, IpAddr)>,
+    payload: Vec<u8>,
+}
+
+impl UdpLiteBuilder {
+    /// Create a new UdpLite builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the source port.
+    pub fn src_port(&mut self, src_port: impl Into<u16>) -> &mut Self {
+        self.src_port = Some(src_port.into());
+        self
+    }
+
+    /// Set the destination port.
+    pub fn dst_port(&mut self, dst_port: impl Into<u16>) -> &mut Self {
+        self.dst_port = Some(dst_port.into());
+        self
+    }
+
+    /// Set the checksum coverage, zero (the whole datagram) by default.
+    pub fn coverage(&mut self, coverage: impl Into<u16>) -> &mut Self {
+        self.coverage = Some(coverage.into());
+        self
+    }
+
+    /// Set the checksum.
+    pub fn checksum(&mut self, checksum: impl Into<u16>) -> &mut Self {
+        self.checksum = Some(checksum.into());
+        self
+    }
+
+    /// Set the source and destination addresses of the pseudo header, so
+    /// that the checksum is computed.
+    pub fn pseudo_header<S, D>(&mut self, (src, dst): (S, D)) -> &mut Self
+    where
+        S: Into<IpAddr>,
+        D: Into<IpAddr>,
+    {
+        self.pseudo_header = Some((src.into(), dst.into()));
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build a UdpLite layer.
+    ///
+    /// Unless set explicitly, the checksum is computed when the pseudo
+    /// header is set, and left zero (illegal) otherwise.
+    pub fn build(&self) -> UdpLite<Vec<u8>> {
+        let mut data = vec![0; MIN_HEADER_LENGTH + self.payload.len()];
+        data[MIN_HEADER_LENGTH..].copy_from_slice(self.payload.as_ref());
+        self.write_header(&mut data);
+
+        unsafe { UdpLite::new_unchecked(data) }
+    }
+}
+
+impl Assemble for UdpLiteBuilder {
+    fn header_len(&self) -> usize {
+        MIN_HEADER_LENGTH
+    }
+
+    fn write_header(&self, segment: &mut [u8]) {
+        let mut udplite = unsafe { UdpLite::new_unchecked(segment) };
+
+        udplite
+            .src_port_mut()
+            .set(self.src_port.unwrap_or_default());
+        udplite
+            .dst_port_mut()
+            .set(self.dst_port.unwrap_or_default());
+        udplite
+            .coverage_mut()
+            .set(self.coverage.unwrap_or_default());
+
+        let checksum = match (self.checksum, self.pseudo_header) {
+            (Some(checksum), _) => checksum,
+            (None, Some((src, dst))) => udplite.compute_checksum(src, dst),
+            (None, None) => 0,
+        };
+        udplite.checksum_mut().set(checksum);
+    }
+}
+
+/// Create a new UdpLite layer with the given fields.
+#[macro_export]
+macro_rules! udplite {
+    ($($field : ident : $value : expr),* $(,)? ) => {
+        $crate::layer::udplite::UdpLiteBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::{IpAddr, Ipv4Addr};
+
+    use crate::prelude::*;
+
+    #[test]
+    fn udplite_coverage() {
+        let (src, dst) = (
+            IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)),
+            IpAddr::from(Ipv4Addr::new(10, 0, 0, 2)),
+        );
+        let mut udplite = udplite!(
+            src_port: 5004u16,
+            dst_port: 5004u16,
+            coverage: 12u16,
+            pseudo_header: (src, dst),
+            payload: b"head+damaged body",
+        );
+        assert_eq!(udplite.covered_payload(), b"head");
+        assert_eq!(udplite.verify_checksum(src, dst), Ok(()));
+
+        // Damage outside the coverage goes unnoticed
+        udplite.payload_mut()[10] ^= 0xFF;
+        assert_eq!(udplite.verify_checksum(src, dst), Ok(()));
+        udplite.payload_mut()[0] ^= 0xFF;
+        assert_eq!(
+            udplite.verify_checksum(src, dst),
+            Err(UdpLiteError::InvalidChecksum)
+        );
+
+        // Zero coverage is the whole datagram, checksum zero is illegal
+        let udplite = udplite!(payload: b"body", checksum: 0u16);
+        assert_eq!(udplite.covered_len(), 12);
+        assert_eq!(
+            udplite.verify_checksum(src, dst),
+            Err(UdpLiteError::ZeroChecksum)
+        );
+
+        for (coverage, constraint) in [
+            (4, Constraint::MinValue { min: 8, actual: 4 }),
+            (
+                13,
+                Constraint::MaxValue {
+                    max: 12,
+                    actual: 13,
+                },
+            ),
+        ] {
+            let udplite = udplite!(coverage: coverage as u16, payload: b"body");
+            let Err(UdpLiteError::InvalidLength(err)) = UdpLite::new(udplite.inner()) else {
+                panic!("coverage {coverage} accepted");
+            };
+            assert_eq!((err.field, err.offset), ("coverage", 4));
+            assert_eq!(err.constraint, constraint);
+        }
+
+        let ipv4 = ipv4!(
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::UdpLite,
+            payload: udplite!(src_port: 1u16, dst_port: 2u16).inner(),
+        );
+        let key = FlowKey::from_ipv4(&ipv4);
+        assert_eq!((key.src_port, key.dst_port), (1, 2));
+        assert_eq!(ipv4.udplite().unwrap().dst_port().get(), 2);
+    }
+}
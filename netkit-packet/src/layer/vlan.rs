@@ -0,0 +1,373 @@
+//! IEEE 802.1Q VLAN tag.
+
+use crate::{field_spec, prelude::*};
+
+/// Error type for Vlan layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum VlanError {
+    /// Invalid Vlan length.
+    #[error("Invalid Vlan length: {0}")]
+    InvalidLength(#[from] LayerError),
+}
+
+field_spec!(PcpSpec, u8, u8, 0xE0, 5);
+field_spec!(DeiSpec, bool, u8, 0x10, 4);
+field_spec!(VidSpec, u16, u16, 0x0FFF);
+field_spec!(EthTypeSpec, EthType, u16);
+
+/// Minimum length of a Vlan tag.
+pub const MIN_HEADER_LENGTH: usize = 4;
+
+/// IEEE 802.1Q VLAN tag.
+///
+/// The tag follows the Eth header (or an outer tag) whose Eth type is
+/// [`EthType::Vlan`] or [`EthType::ServiceVlan`].
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |   PCP  |DE|             VLAN ID               |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |                      Eth Type                 |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct Vlan<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> Vlan<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the priority code point: 0..1 (3bits)
+    pub const FIELD_PCP: core::ops::Range<usize> = 0..1;
+    /// Field range of the drop eligible indicator: 0..1 (1bit)
+    pub const FIELD_DEI: core::ops::Range<usize> = 0..1;
+    /// Field range of the VLAN identifier: 0..2 (12bits)
+    pub const FIELD_VID: core::ops::Range<usize> = 0..2;
+    /// Field range of the Eth type: 2..4
+    pub const FIELD_ETH_TYPE: core::ops::Range<usize> = 2..4;
+    /// Field range of the payload: 4..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 4..;
+
+    /// Create a new Vlan layer from raw data without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid Vlan tag.
+    ///
+    /// The data must be at least 4 bytes long. Otherwise, the following
+    /// methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the Vlan layer.
+    pub fn validate(&self) -> Result<(), VlanError> {
+        if self.data.as_ref().len() < MIN_HEADER_LENGTH {
+            return Err(LayerError::min_length(
+                "Vlan",
+                "header",
+                0,
+                MIN_HEADER_LENGTH,
+                self.data.as_ref().len(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Create a new Vlan layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, VlanError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the priority code point.
+    #[inline]
+    pub fn pcp(&self) -> &Field<PcpSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_PCP])
+    }
+
+    /// Get the accessor of the drop eligible indicator.
+    #[inline]
+    pub fn dei(&self) -> &Field<DeiSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_DEI])
+    }
+
+    /// Get the accessor of the VLAN identifier.
+    #[inline]
+    pub fn vid(&self) -> &Field<VidSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_VID])
+    }
+
+    /// Get the accessor of the Eth type.
+    #[inline]
+    pub fn eth_type(&self) -> &Field<EthTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_ETH_TYPE])
+    }
+
+    /// Get the payload.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        &self.data.as_ref()[Self::FIELD_PAYLOAD]
+    }
+
+    /// Get the inner Vlan tag if the Eth type is a VLAN tag (QinQ).
+    pub fn vlan(&self) -> Option<Vlan<&[u8]>> {
+        match self.eth_type().get() {
+            EthType::Vlan | EthType::ServiceVlan => Vlan::new(self.payload()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the IPv4 layer if the Eth type is IPv4.
+    pub fn ipv4(&self) -> Option<Ipv4<&[u8]>> {
+        if self.eth_type().get() == EthType::Ipv4 {
+            Ipv4::new(self.payload()).ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Vlan<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the priority code point.
+    #[inline]
+    pub fn pcp_mut(&mut self) -> &mut Field<PcpSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_PCP])
+    }
+
+    /// Get the mutable accessor of the drop eligible indicator.
+    #[inline]
+    pub fn dei_mut(&mut self) -> &mut Field<DeiSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_DEI])
+    }
+
+    /// Get the mutable accessor of the VLAN identifier.
+    #[inline]
+    pub fn vid_mut(&mut self) -> &mut Field<VidSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_VID])
+    }
+
+    /// Get the mutable accessor of the Eth type.
+    #[inline]
+    pub fn eth_type_mut(&mut self) -> &mut Field<EthTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_ETH_TYPE])
+    }
+
+    /// Get the mutable payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data.as_mut()[Self::FIELD_PAYLOAD]
+    }
+}
+
+layer_impl!(Vlan);
+
+/// A VLAN tag to insert into a frame.
+///
+/// The tag protocol identifier is the Eth type announcing the tag:
+/// [`EthType::Vlan`] for 802.1Q tags and [`EthType::ServiceVlan`] for
+/// 802.1ad service tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct VlanTag {
+    /// Tag protocol identifier.
+    pub tpid: EthType,
+    /// Priority code point.
+    pub pcp: u8,
+    /// Drop eligible indicator.
+    pub dei: bool,
+    /// VLAN identifier.
+    pub vid: u16,
+}
+
+impl VlanTag {
+    /// Create an 802.1Q tag.
+    pub fn new(vid: u16) -> Self {
+        Self {
+            tpid: EthType::Vlan,
+            pcp: 0,
+            dei: false,
+            vid,
+        }
+    }
+
+    /// Create an 802.1ad service tag.
+    pub fn service(vid: u16) -> Self {
+        Self {
+            tpid: EthType::ServiceVlan,
+            ..Self::new(vid)
+        }
+    }
+
+    /// Set the priority code point.
+    pub fn with_pcp(mut self, pcp: u8) -> Self {
+        self.pcp = pcp;
+        self
+    }
+
+    /// Get the bytes of the tag, followed by the given Eth type.
+    pub(crate) fn to_bytes(self, eth_type: EthType) -> Vec<u8> {
+        VlanBuilder::new()
+            .pcp(self.pcp)
+            .dei(self.dei)
+            .vid(self.vid)
+            .eth_type(eth_type)
+            .build()
+            .inner()
+            .clone()
+    }
+}
+
+impl From<u16> for VlanTag {
+    fn from(vid: u16) -> Self {
+        Self::new(vid)
+    }
+}
+
+/// Builder for [`Vlan`].
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct VlanBuilder {
+    pcp: Option<u8>,
+    dei: Option<bool>,
+    vid: Option<u16>,
+    eth_type: Option<EthType>,
+    payload: Vec<u8>,
+}
+
+impl VlanBuilder {
+    /// Create a new Vlan builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the priority code point.
+    pub fn pcp(&mut self, pcp: impl Into<u8>) -> &mut Self {
+        self.pcp = Some(pcp.into());
+        self
+    }
+
+    /// Set the drop eligible indicator.
+    pub fn dei(&mut self, dei: bool) -> &mut Self {
+        self.dei = Some(dei);
+        self
+    }
+
+    /// Set the VLAN identifier.
+    pub fn vid(&mut self, vid: impl Into<u16>) -> &mut Self {
+        self.vid = Some(vid.into());
+        self
+    }
+
+    /// Set the Eth type.
+    pub fn eth_type(&mut self, eth_type: impl Into<EthType>) -> &mut Self {
+        self.eth_type = Some(eth_type.into());
+        self
+    }
+
+    /// Set the payload.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the Vlan layer.
+    pub fn build(&self) -> Vlan<Vec<u8>> {
+        let len = MIN_HEADER_LENGTH + self.payload.len();
+
+        let mut vlan = unsafe { Vlan::new_unchecked(vec![0; len]) };
+
+        vlan.vid_mut().set(self.vid.unwrap_or_default());
+        vlan.pcp_mut().set(self.pcp.unwrap_or_default());
+        vlan.dei_mut().set(self.dei.unwrap_or_default());
+        vlan.eth_type_mut().set(self.eth_type.unwrap_or_default());
+        vlan.payload_mut().copy_from_slice(self.payload.as_ref());
+
+        vlan
+    }
+}
+
+/// Create a Vlan layer with the given fields.
+///
+/// # Example
+///
+/// ```
+/// # use netkit_packet::prelude::*;
+/// let vlan = vlan!(pcp: 5u8, vid: 100u16, eth_type: EthType::Ipv4);
+///
+/// assert_eq!(vlan.pcp().get(), 5);
+/// assert!(!vlan.dei().get());
+/// assert_eq!(vlan.vid().get(), 100);
+/// assert_eq!(vlan.eth_type().get(), EthType::Ipv4);
+/// ```
+#[macro_export]
+macro_rules! vlan {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::vlan::VlanBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn vlan_new() {
+        let data: [u8; 4] = [
+            0xA0, 0x64, // pcp 5, dei 0, vid 100
+            0x08, 0x00, // eth type ipv4
+        ];
+
+        let vlan = Vlan::new(&data[..]).unwrap();
+        assert_eq!(vlan.pcp().get(), 5);
+        assert!(!vlan.dei().get());
+        assert_eq!(vlan.vid().get(), 100);
+        assert_eq!(vlan.eth_type().get(), EthType::Ipv4);
+        assert!(Vlan::new(&data[..3]).is_err());
+    }
+
+    #[test]
+    fn vlan_qinq() {
+        let inner = vlan!(vid: 20u16, eth_type: EthType::Ipv4, payload: ipv4!().inner());
+        let outer = vlan!(vid: 10u16, dei: true, eth_type: EthType::Vlan, payload: inner.inner());
+        let eth = eth!(eth_type: EthType::ServiceVlan, payload: outer.inner());
+
+        let outer = eth.vlan().unwrap();
+        assert_eq!(outer.vid().get(), 10);
+        assert!(outer.dei().get());
+        assert_eq!(outer.pcp().get(), 0);
+
+        let inner = outer.vlan().unwrap();
+        assert_eq!(inner.vid().get(), 20);
+        assert!(inner.ipv4().is_some());
+        assert_eq!(eth.vlan_ids(), [10, 20]);
+    }
+}
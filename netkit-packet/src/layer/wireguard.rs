@@ -0,0 +1,454 @@
+//! WireGuard layer.
+
+use crate::{field_spec, prelude::*};
+
+pub mod message_type;
+pub use message_type::WireGuardMessageType;
+
+/// Error type for WireGuard layer.
+#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum WireGuardError {
+    /// Invalid WireGuard length.
+    #[error("Invalid WireGuard length: {0}")]
+    InvalidLength(#[from] LayerError),
+
+    /// Invalid WireGuard message type or reserved field.
+    #[error("Invalid WireGuard message type: {0}")]
+    InvalidMessageType(u8),
+}
+
+field_spec!(MessageTypeSpec, WireGuardMessageType, u8);
+field_spec!(IndexSpec, u32, u32, le);
+field_spec!(CounterSpec, u64, u64, le);
+
+/// Minimum length of a WireGuard message (an empty transport data message).
+pub const MIN_HEADER_LENGTH: usize = 32;
+
+/// Default UDP port of WireGuard.
+pub const DEFAULT_PORT: u16 = 51820;
+
+/// WireGuard layer.
+///
+/// See the [WireGuard whitepaper](https://www.wireguard.com/papers/wireguard.pdf).
+/// Only the clear text fields are exposed, i.e. the message type, the sender
+/// and receiver indices and the transport data counter. Unlike most
+/// protocols, these fields are little-endian.
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |     Message Type      |   Reserved (24) ...   |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |         Sender / Receiver Index (32, LE)      |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |          Message type specific fields ...     |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+pub struct WireGuard<T>
+where
+    T: AsRef<[u8]>,
+{
+    data: T,
+}
+
+impl<T> WireGuard<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Field range of the message type: 0..1
+    pub const FIELD_MESSAGE_TYPE: core::ops::Range<usize> = 0..1;
+    /// Field range of the reserved field: 1..4
+    pub const FIELD_RESERVED: core::ops::Range<usize> = 1..4;
+    /// Field range of the first index: 4..8
+    ///
+    /// This is the sender index of handshake messages and the receiver index
+    /// of cookie replies and transport data.
+    pub const FIELD_INDEX: core::ops::Range<usize> = 4..8;
+    /// Field range of the receiver index of a handshake response: 8..12
+    pub const FIELD_RESPONSE_RECEIVER: core::ops::Range<usize> = 8..12;
+    /// Field range of the counter of transport data: 8..16
+    pub const FIELD_COUNTER: core::ops::Range<usize> = 8..16;
+    /// Field range of the encrypted payload of transport data: 16..
+    pub const FIELD_PAYLOAD: core::ops::RangeFrom<usize> = 16..;
+
+    /// Create a new WireGuard layer without validation.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the data is a valid WireGuard message.
+    ///
+    /// The data must be at least as long as its message type requires.
+    /// Otherwise, the following methods may panic when accessing the fields.
+    #[inline]
+    pub const unsafe fn new_unchecked(data: T) -> Self {
+        Self { data }
+    }
+
+    /// Validate the WireGuard layer.
+    ///
+    /// As WireGuard has no magic number, the message type, the reserved zero
+    /// bytes and the exact message length are used to recognize it.
+    pub fn validate(&self) -> Result<(), WireGuardError> {
+        let data = self.data.as_ref();
+        let Some(&raw_type) = data.first() else {
+            return Err(
+                LayerError::min_length("WireGuard", "header", 0, MIN_HEADER_LENGTH, 0).into(),
+            );
+        };
+
+        let message_type = WireGuardMessageType::from(raw_type);
+        let Some(length) = message_type.length() else {
+            return Err(WireGuardError::InvalidMessageType(raw_type));
+        };
+
+        match message_type {
+            WireGuardMessageType::TransportData if data.len() < length => {
+                return Err(
+                    LayerError::min_length("WireGuard", "header", 0, length, data.len()).into(),
+                );
+            }
+            WireGuardMessageType::TransportData if (data.len() - length) % 16 != 0 => {
+                return Err(LayerError {
+                    layer: "WireGuard",
+                    field: "encrypted packet",
+                    offset: length,
+                    constraint: Constraint::Multiple {
+                        of: 16,
+                        actual: data.len() - length,
+                    },
+                }
+                .into());
+            }
+            WireGuardMessageType::TransportData => {}
+            _ if data.len() != length => {
+                return Err(
+                    LayerError::length("WireGuard", "message", 0, length, data.len()).into(),
+                );
+            }
+            _ => {}
+        }
+
+        if data[Self::FIELD_RESERVED] != [0; 3] {
+            return Err(WireGuardError::InvalidMessageType(raw_type));
+        }
+
+        Ok(())
+    }
+
+    /// Create a new WireGuard layer from raw data.
+    #[inline]
+    pub fn new(data: T) -> Result<Self, WireGuardError> {
+        let res = unsafe { Self::new_unchecked(data) };
+        res.validate()?;
+        Ok(res)
+    }
+
+    /// Get the inner raw data.
+    #[inline]
+    pub const fn inner(&self) -> &T {
+        &self.data
+    }
+
+    /// Get the accessor of the message type.
+    #[inline]
+    pub fn message_type(&self) -> &Field<MessageTypeSpec> {
+        cast_from_bytes(&self.data.as_ref()[Self::FIELD_MESSAGE_TYPE])
+    }
+
+    /// Get the accessor of the sender index of handshake messages.
+    #[inline]
+    pub fn sender_index(&self) -> Option<&Field<IndexSpec>> {
+        match self.message_type().get() {
+            WireGuardMessageType::HandshakeInitiation | WireGuardMessageType::HandshakeResponse => {
+                Some(cast_from_bytes(&self.data.as_ref()[Self::FIELD_INDEX]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the accessor of the receiver index.
+    ///
+    /// Handshake initiations do not carry a receiver index.
+    #[inline]
+    pub fn receiver_index(&self) -> Option<&Field<IndexSpec>> {
+        match self.message_type().get() {
+            WireGuardMessageType::HandshakeResponse => Some(cast_from_bytes(
+                &self.data.as_ref()[Self::FIELD_RESPONSE_RECEIVER],
+            )),
+            WireGuardMessageType::CookieReply | WireGuardMessageType::TransportData => {
+                Some(cast_from_bytes(&self.data.as_ref()[Self::FIELD_INDEX]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the accessor of the counter of transport data.
+    #[inline]
+    pub fn counter(&self) -> Option<&Field<CounterSpec>> {
+        match self.message_type().get() {
+            WireGuardMessageType::TransportData => {
+                Some(cast_from_bytes(&self.data.as_ref()[Self::FIELD_COUNTER]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the encrypted payload of transport data, including the
+    /// authentication tag.
+    ///
+    /// Keepalives carry an empty payload, i.e. only the 16 bytes tag.
+    #[inline]
+    pub fn payload(&self) -> Option<&[u8]> {
+        match self.message_type().get() {
+            WireGuardMessageType::TransportData => Some(&self.data.as_ref()[Self::FIELD_PAYLOAD]),
+            _ => None,
+        }
+    }
+
+    /// Whether the message is a keepalive (transport data without payload).
+    #[inline]
+    pub fn is_keepalive(&self) -> bool {
+        self.message_type().get() == WireGuardMessageType::TransportData
+            && self.data.as_ref().len() == MIN_HEADER_LENGTH
+    }
+}
+
+impl<T> WireGuard<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Get the mutable inner raw data.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Get the mutable accessor of the message type.
+    #[inline]
+    pub fn message_type_mut(&mut self) -> &mut Field<MessageTypeSpec> {
+        cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_MESSAGE_TYPE])
+    }
+
+    /// Get the mutable accessor of the sender index of handshake messages.
+    #[inline]
+    pub fn sender_index_mut(&mut self) -> Option<&mut Field<IndexSpec>> {
+        match self.message_type().get() {
+            WireGuardMessageType::HandshakeInitiation | WireGuardMessageType::HandshakeResponse => {
+                Some(cast_from_bytes_mut(
+                    &mut self.data.as_mut()[Self::FIELD_INDEX],
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the mutable accessor of the receiver index.
+    #[inline]
+    pub fn receiver_index_mut(&mut self) -> Option<&mut Field<IndexSpec>> {
+        match self.message_type().get() {
+            WireGuardMessageType::HandshakeResponse => Some(cast_from_bytes_mut(
+                &mut self.data.as_mut()[Self::FIELD_RESPONSE_RECEIVER],
+            )),
+            WireGuardMessageType::CookieReply | WireGuardMessageType::TransportData => Some(
+                cast_from_bytes_mut(&mut self.data.as_mut()[Self::FIELD_INDEX]),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Get the mutable accessor of the counter of transport data.
+    #[inline]
+    pub fn counter_mut(&mut self) -> Option<&mut Field<CounterSpec>> {
+        match self.message_type().get() {
+            WireGuardMessageType::TransportData => Some(cast_from_bytes_mut(
+                &mut self.data.as_mut()[Self::FIELD_COUNTER],
+            )),
+            _ => None,
+        }
+    }
+
+    /// Get the mutable encrypted payload of transport data.
+    #[inline]
+    pub fn payload_mut(&mut self) -> Option<&mut [u8]> {
+        match self.message_type().get() {
+            WireGuardMessageType::TransportData => {
+                Some(&mut self.data.as_mut()[Self::FIELD_PAYLOAD])
+            }
+            _ => None,
+        }
+    }
+}
+
+layer_impl!(WireGuard);
+
+/// Builder for [`WireGuard`].
+///
+/// The cryptographic fields are left zeroed, only the clear text fields are
+/// set.
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct WireGuardBuilder {
+    message_type: Option<WireGuardMessageType>,
+    sender_index: Option<u32>,
+    receiver_index: Option<u32>,
+    counter: Option<u64>,
+    payload: Vec<u8>,
+}
+
+impl WireGuardBuilder {
+    /// Create a new WireGuard builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the message type.
+    pub fn message_type(&mut self, message_type: impl Into<WireGuardMessageType>) -> &mut Self {
+        self.message_type = Some(message_type.into());
+        self
+    }
+
+    /// Set the sender index, ignored unless a handshake message is built.
+    pub fn sender_index(&mut self, sender_index: impl Into<u32>) -> &mut Self {
+        self.sender_index = Some(sender_index.into());
+        self
+    }
+
+    /// Set the receiver index, ignored for handshake initiations.
+    pub fn receiver_index(&mut self, receiver_index: impl Into<u32>) -> &mut Self {
+        self.receiver_index = Some(receiver_index.into());
+        self
+    }
+
+    /// Set the counter, ignored unless transport data is built.
+    pub fn counter(&mut self, counter: impl Into<u64>) -> &mut Self {
+        self.counter = Some(counter.into());
+        self
+    }
+
+    /// Set the encrypted payload of transport data, without the 16 bytes
+    /// authentication tag.
+    pub fn payload<T: AsRef<[u8]>>(&mut self, payload: T) -> &mut Self {
+        self.payload.extend_from_slice(payload.as_ref());
+        self
+    }
+
+    /// Build the WireGuard layer.
+    pub fn build(&self) -> WireGuard<Vec<u8>> {
+        let message_type = self
+            .message_type
+            .unwrap_or(WireGuardMessageType::TransportData);
+        let length = match message_type {
+            WireGuardMessageType::TransportData => {
+                MIN_HEADER_LENGTH + self.payload.len().next_multiple_of(16)
+            }
+            _ => message_type.length().unwrap_or(MIN_HEADER_LENGTH),
+        };
+
+        let mut wg = unsafe { WireGuard::new_unchecked(vec![0; length]) };
+
+        wg.message_type_mut().set(message_type);
+        if let Some(field) = wg.sender_index_mut() {
+            field.set(self.sender_index.unwrap_or_default());
+        }
+        if let Some(field) = wg.receiver_index_mut() {
+            field.set(self.receiver_index.unwrap_or_default());
+        }
+        if let Some(field) = wg.counter_mut() {
+            field.set(self.counter.unwrap_or_default());
+        }
+        if let Some(payload) = wg.payload_mut() {
+            payload[..self.payload.len()].copy_from_slice(self.payload.as_ref());
+        }
+
+        wg
+    }
+}
+
+/// Create a WireGuard layer with the given fields.
+#[macro_export]
+macro_rules! wireguard {
+    ($($field : ident : $value : expr),* $(,)?) => {
+        $crate::layer::wireguard::WireGuardBuilder::new()
+            $(.$field($value))*
+            .build()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wireguard_transport_data() {
+        let mut data = [0u8; 48];
+        data[0] = 4;
+        data[4..8].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        data[8..16].copy_from_slice(&42u64.to_le_bytes());
+
+        let wg = WireGuard::new(&data[..]).unwrap();
+        assert_eq!(wg.message_type().get(), WireGuardMessageType::TransportData);
+        assert!(wg.sender_index().is_none());
+        assert_eq!(wg.receiver_index().unwrap().get(), 0x1234_5678);
+        assert_eq!(wg.counter().unwrap().get(), 42);
+        assert_eq!(wg.payload().unwrap().len(), 32);
+        assert!(!wg.is_keepalive());
+
+        assert_eq!(
+            WireGuard::new(&data[..40]).err(),
+            Some(WireGuardError::InvalidLength(LayerError {
+                layer: "WireGuard",
+                field: "encrypted packet",
+                offset: 32,
+                constraint: Constraint::Multiple { of: 16, actual: 8 },
+            }))
+        );
+        data[1] = 1;
+        assert_eq!(
+            WireGuard::new(&data[..]).err(),
+            Some(WireGuardError::InvalidMessageType(4))
+        );
+    }
+
+    #[test]
+    fn wireguard_handshake() {
+        let init = wireguard!(
+            message_type: WireGuardMessageType::HandshakeInitiation,
+            sender_index: 7u32,
+        );
+        assert_eq!(init.inner().len(), 148);
+        assert_eq!(&init.inner()[4..8], &[7, 0, 0, 0]);
+        assert_eq!(init.sender_index().unwrap().get(), 7);
+        assert!(init.receiver_index().is_none());
+
+        let resp = wireguard!(
+            message_type: WireGuardMessageType::HandshakeResponse,
+            sender_index: 9u32,
+            receiver_index: 7u32,
+        );
+        let resp = WireGuard::new(resp.inner().as_slice()).unwrap();
+        assert_eq!(resp.sender_index().unwrap().get(), 9);
+        assert_eq!(resp.receiver_index().unwrap().get(), 7);
+        assert!(resp.counter().is_none());
+
+        let keepalive = wireguard!(receiver_index: 9u32, counter: 1u64);
+        assert!(keepalive.is_keepalive());
+    }
+
+    #[test]
+    fn wireguard_little_endian_fields() {
+        let mut data = wireguard!(receiver_index: 1u32, counter: 1u64);
+        data.receiver_index_mut().unwrap().set(0x0102_0304);
+        data.counter_mut().unwrap().set(0x0102_0304_0506_0708);
+        assert_eq!(&data.inner()[4..8], &[4, 3, 2, 1]);
+        assert_eq!(&data.inner()[8..16], &[8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(data.receiver_index().unwrap().get(), 0x0102_0304);
+        assert_eq!(data.counter().unwrap().get(), 0x0102_0304_0506_0708);
+
+        let mut resp = wireguard!(message_type: WireGuardMessageType::HandshakeResponse);
+        resp.sender_index_mut().unwrap().set(0x0A0B_0C0D);
+        resp.receiver_index_mut().unwrap().set(0x0102_0304);
+        assert_eq!(&resp.inner()[4..12], &[0x0D, 0x0C, 0x0B, 0x0A, 4, 3, 2, 1]);
+        assert!(resp.counter_mut().is_none());
+    }
+}
@@ -0,0 +1,90 @@
+//! WireGuard Message Type
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+use strum::{AsRefStr, Display, EnumString};
+
+use crate::impl_target;
+
+/// WireGuard Message Type
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(
+    // core traits
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    // num_enum traits
+    FromPrimitive,
+    IntoPrimitive,
+    // strum traits
+    AsRefStr,
+    Display,
+    EnumString,
+)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum WireGuardMessageType {
+    /// Handshake Initiation
+    HandshakeInitiation = 1,
+
+    /// Handshake Response
+    HandshakeResponse = 2,
+
+    /// Cookie Reply
+    CookieReply = 3,
+
+    /// Transport Data
+    TransportData = 4,
+
+    /// Unknown
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl WireGuardMessageType {
+    /// Get the length of the message, or the minimum length for transport
+    /// data which carries a variable sized payload.
+    pub fn length(&self) -> Option<usize> {
+        match self {
+            WireGuardMessageType::HandshakeInitiation => Some(148),
+            WireGuardMessageType::HandshakeResponse => Some(92),
+            WireGuardMessageType::CookieReply => Some(64),
+            WireGuardMessageType::TransportData => Some(32),
+            WireGuardMessageType::Unknown(_) => None,
+        }
+    }
+}
+
+impl_target!(frominto, WireGuardMessageType, u8);
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_enum_num, test_enum_str};
+
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn message_type_str() {
+        test_enum_str!(
+            WireGuardMessageType,
+            HandshakeInitiation => "HandshakeInitiation",
+            HandshakeResponse => "HandshakeResponse",
+            CookieReply => "CookieReply",
+            TransportData => "TransportData",
+        );
+    }
+
+    #[test]
+    fn message_type_num() {
+        test_enum_num!(
+            WireGuardMessageType : u8,
+            HandshakeInitiation => 1,
+            HandshakeResponse => 2,
+            CookieReply => 3,
+            TransportData => 4,
+        );
+    }
+}
@@ -2,6 +2,18 @@
 
 #![deny(missing_docs)]
 
+pub mod dissect;
+pub mod flow;
+pub mod gen;
+pub mod interop;
 pub mod layer;
 pub mod prelude;
+pub mod profile;
+pub mod transform;
 pub mod utils;
+
+mod sealed {
+    /// Supertrait of the traits only implemented in this crate, so that
+    /// methods can be added to them without breaking downstream crates.
+    pub trait Sealed {}
+}
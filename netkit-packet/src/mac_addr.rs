@@ -4,6 +4,7 @@ use std::{fmt::Display, str::FromStr};
 use serde::Deserialize;
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum MacAddrError {
     #[error("Invalid MAC address, unexpected number: {0}")]
     ParseInt(#[from] core::num::ParseIntError),
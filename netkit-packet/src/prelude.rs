@@ -2,6 +2,11 @@
 
 pub use crate::utils::*;
 
+pub use crate::flow::FlowKey;
 pub use crate::layer::prelude::*;
+pub use crate::profile::Profile;
 
-pub use crate::{eth, eth_addr, ipv4, tcp, udp};
+pub use crate::{
+    ah, eap, eapol, esp, eth, eth_addr, goose, gre, icmp, ipv4, ipv6, l2tp, llc, openvpn, ppp, ptp,
+    quic, rtp, sll2, tcp, udp, vlan, wireguard,
+};
@@ -0,0 +1,199 @@
+//! Default profiles of builders.
+//!
+//! A [`Profile`] mimics the defaults of a common network stack, so that
+//! generated traffic is realistic and fingerprint-consistent without
+//! specifying every field. Fields set explicitly on a builder always take
+//! precedence over the profile.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::{layer::ip::FLAG_DONT_FRAGMENT, utils::rng};
+
+/// Default profile of a network stack.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Profile {
+    /// Linux host.
+    #[default]
+    LinuxHost,
+
+    /// Windows host.
+    WindowsHost,
+
+    /// macOS host.
+    MacOsHost,
+
+    /// Cisco IOS router.
+    CiscoRouter,
+}
+
+/// How the Ipv4 identification is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IdPolicy {
+    /// Always zero.
+    Zero,
+
+    /// A global counter incremented for every packet.
+    Incremental,
+
+    /// A random value for every packet, drawn with [`rng::random`] so that
+    /// it is reproducible inside an [`RngContext`](rng::RngContext).
+    Random,
+}
+
+impl IdPolicy {
+    /// Get the next identification.
+    pub fn next(&self) -> u16 {
+        static COUNTER: AtomicU16 = AtomicU16::new(0);
+
+        match self {
+            IdPolicy::Zero => 0,
+            IdPolicy::Incremental => COUNTER.fetch_add(1, Ordering::Relaxed),
+            IdPolicy::Random => rng::random(),
+        }
+    }
+}
+
+/// A TCP option of the SYN presets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SynOption {
+    Eol,
+    Nop,
+    Mss(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Timestamps,
+}
+
+impl Profile {
+    /// Get the default Ipv4 time to live.
+    pub fn ttl(&self) -> u8 {
+        match self {
+            Profile::LinuxHost | Profile::MacOsHost => 64,
+            Profile::WindowsHost => 128,
+            Profile::CiscoRouter => 255,
+        }
+    }
+
+    /// Get the default Ipv4 flags.
+    pub fn ipv4_flags(&self) -> u8 {
+        match self {
+            Profile::LinuxHost | Profile::WindowsHost | Profile::MacOsHost => FLAG_DONT_FRAGMENT,
+            Profile::CiscoRouter => 0,
+        }
+    }
+
+    /// Get the Ipv4 identification policy.
+    pub fn id_policy(&self) -> IdPolicy {
+        match self {
+            Profile::LinuxHost | Profile::MacOsHost => IdPolicy::Random,
+            Profile::WindowsHost | Profile::CiscoRouter => IdPolicy::Incremental,
+        }
+    }
+
+    /// Get the default TCP window size of SYN segments.
+    pub fn syn_window_size(&self) -> u16 {
+        match self {
+            Profile::LinuxHost | Profile::WindowsHost => 64240,
+            Profile::MacOsHost => 65535,
+            Profile::CiscoRouter => 4128,
+        }
+    }
+
+    /// Get the default (unscaled) TCP window size of other segments.
+    pub fn window_size(&self) -> u16 {
+        match self {
+            Profile::LinuxHost => 502,
+            Profile::WindowsHost => 1026,
+            Profile::MacOsHost => 2058,
+            Profile::CiscoRouter => 4128,
+        }
+    }
+
+    fn syn_option_list(&self) -> &'static [SynOption] {
+        use SynOption::*;
+
+        match self {
+            Profile::LinuxHost => &[Mss(1460), SackPermitted, Timestamps, Nop, WindowScale(7)],
+            Profile::WindowsHost => &[Mss(1460), Nop, WindowScale(8), Nop, Nop, SackPermitted],
+            Profile::MacOsHost => &[
+                Mss(1460),
+                Nop,
+                WindowScale(6),
+                Nop,
+                Nop,
+                Timestamps,
+                SackPermitted,
+                Eol,
+                Eol,
+            ],
+            Profile::CiscoRouter => &[Mss(536)],
+        }
+    }
+
+    /// Get the default TCP options of SYN segments, padded to 4 bytes.
+    ///
+    /// The timestamp values are left zero.
+    pub fn syn_options(&self) -> Vec<u8> {
+        let mut options = Vec::with_capacity(24);
+        for option in self.syn_option_list() {
+            match *option {
+                SynOption::Eol => options.push(0),
+                SynOption::Nop => options.push(1),
+                SynOption::Mss(mss) => {
+                    options.extend_from_slice(&[2, 4]);
+                    options.extend_from_slice(&mss.to_be_bytes());
+                }
+                SynOption::WindowScale(shift) => options.extend_from_slice(&[3, 3, shift]),
+                SynOption::SackPermitted => options.extend_from_slice(&[4, 2]),
+                SynOption::Timestamps => {
+                    options.extend_from_slice(&[8, 10]);
+                    options.extend_from_slice(&[0; 8]);
+                }
+            }
+        }
+        options.resize(options.len().next_multiple_of(4), 0);
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::ip::v4::Ipv4Builder;
+
+    #[test]
+    fn profile_syn_options() {
+        assert_eq!(
+            Profile::LinuxHost.syn_options(),
+            [2, 4, 5, 180, 4, 2, 8, 10, 0, 0, 0, 0, 0, 0, 0, 0, 1, 3, 3, 7]
+        );
+        assert_eq!(Profile::WindowsHost.syn_options().len(), 12);
+        assert_eq!(Profile::MacOsHost.syn_options().len(), 24);
+        assert_eq!(Profile::CiscoRouter.syn_options(), [2, 4, 2, 24]);
+    }
+
+    #[test]
+    fn profile_id_policy() {
+        assert_eq!(IdPolicy::Zero.next(), 0);
+
+        let first = IdPolicy::Incremental.next();
+        let second = IdPolicy::Incremental.next();
+        assert_ne!(first, second);
+
+        // Profiled packets are reproducible from a seed.
+        let ids = |seed| {
+            rng::RngContext::new(seed).enter(|| {
+                (0..4)
+                    .map(|_| {
+                        let ipv4 = Ipv4Builder::with_defaults(Profile::LinuxHost).build();
+                        ipv4.identification().get()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(ids(42), ids(42));
+        assert_ne!(ids(42), ids(43));
+    }
+}
@@ -0,0 +1,61 @@
+//! Packet transforms.
+//!
+//! A [`Transform`] rewrites captured frames in place, e.g. to redact them
+//! before a capture is shared, to remap its endpoints before it is
+//! replayed, or to translate them as a NAT router would. Transforms are chained by collecting them into a
+//! `Vec<Box<dyn Transform>>`, which applies them in order. Applied through
+//! a [`Provenance`] record, the changes of each transform are recorded, so
+//! the processed capture remains auditable.
+
+pub mod mask;
+pub mod nat;
+pub mod provenance;
+pub mod remap;
+pub mod replay;
+pub mod vlan;
+
+pub use mask::PayloadMask;
+pub use nat::NatRouter;
+pub use provenance::Provenance;
+pub use remap::FlowRemap;
+pub use replay::ReplaySanitizer;
+pub use vlan::VlanRewrite;
+
+/// Frame transform
+pub trait Transform {
+    /// Rewrite an Ethernet frame in place.
+    fn transform(&mut self, frame: &mut Vec<u8>);
+
+    /// Get the name of the transform, as recorded in [`Provenance`].
+    ///
+    /// Defaults to the name of the type, without path and generics.
+    fn name(&self) -> &str {
+        let name = core::any::type_name::<Self>();
+        let name = name.split('<').next().unwrap_or(name);
+        name.rsplit("::").next().unwrap_or(name)
+    }
+}
+
+impl<T: Transform + ?Sized> Transform for Box<T> {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        (**self).transform(frame)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+impl<T: Transform> Transform for [T] {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        for transform in self {
+            transform.transform(frame);
+        }
+    }
+}
+
+impl<T: Transform> Transform for Vec<T> {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        self.as_mut_slice().transform(frame)
+    }
+}
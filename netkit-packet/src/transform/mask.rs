@@ -0,0 +1,131 @@
+//! Payload masking.
+
+use super::Transform;
+use crate::prelude::*;
+
+/// Payload mask
+///
+/// Overwrites the application payload of TCP and UDP segments carried over
+/// Ipv4, optionally keeping its first bytes. Lengths are preserved and the
+/// transport checksums are recomputed, so masked frames remain valid.
+/// Frames carrying other protocols are left unchanged.
+///
+/// ```
+/// use netkit_packet::prelude::*;
+/// use netkit_packet::transform::{PayloadMask, Transform};
+///
+/// let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: b"secret");
+/// let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+/// let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone();
+///
+/// PayloadMask::new().keep(2).transform(&mut frame);
+/// let eth = Eth::new(&frame).unwrap();
+/// assert_eq!(eth.ipv4().unwrap().udp().unwrap().payload(), b"se\0\0\0\0");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayloadMask {
+    keep: usize,
+    fill: u8,
+}
+
+impl PayloadMask {
+    /// Create a mask zeroing whole payloads.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of leading payload bytes to keep.
+    pub fn keep(&mut self, keep: usize) -> &mut Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Set the byte written over the payload.
+    pub fn fill(&mut self, fill: u8) -> &mut Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Mask the payload of an Ipv4 packet.
+    ///
+    /// Returns whether the packet carried a TCP or UDP payload.
+    pub fn mask_ipv4<T>(&self, ipv4: &mut Ipv4<T>) -> bool
+    where
+        T: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        let header_len = if let Some(tcp) = ipv4.tcp() {
+            tcp.data_offset().get() as usize * 4
+        } else if ipv4.udp().is_some() {
+            8
+        } else {
+            return false;
+        };
+
+        // Ethernet padding beyond the total length is not payload.
+        let end = (ipv4.total_length().get() as usize)
+            .saturating_sub(ipv4.ihl().get() as usize * 4)
+            .min(ipv4.payload().len());
+        let start = (header_len + self.keep).min(end);
+        ipv4.payload_mut()[start..end].fill(self.fill);
+        ipv4.update_transport_checksum();
+        true
+    }
+}
+
+impl Transform for PayloadMask {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        let Ok(mut eth) = Eth::new(frame.as_mut_slice()) else {
+            return;
+        };
+
+        if let Some(mut ipv4) = eth.ipv4_mut() {
+            self.mask_ipv4(&mut ipv4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(tcp: bool) -> Vec<u8> {
+        let (protocol, payload) = if tcp {
+            let tcp = tcp!(src_port: 40000u16, dst_port: 80u16, payload: b"GET / HTTP/1.1");
+            (IpProtocol::Tcp, tcp.inner().clone())
+        } else {
+            let udp = udp!(src_port: 40000u16, dst_port: 53u16, checksum: 1u16, payload: [7; 10]);
+            (IpProtocol::Udp, udp.inner().clone())
+        };
+        let ipv4 = ipv4!(protocol: protocol, payload: payload);
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn payload_mask() {
+        let mut tcp = frame(true);
+        let len = tcp.len();
+        let mut transforms: Vec<Box<dyn Transform>> = vec![Box::new(*PayloadMask::new().keep(4))];
+        transforms.transform(&mut tcp);
+
+        assert_eq!(tcp.len(), len);
+        let eth = Eth::new(&tcp).unwrap();
+        let ipv4 = eth.ipv4().unwrap();
+        assert_eq!(ipv4.tcp().unwrap().payload(), b"GET \0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(
+            Some(ipv4.tcp().unwrap().checksum().get()),
+            ipv4.transport_checksum()
+        );
+
+        let mut udp = frame(false);
+        PayloadMask::new().fill(b'x').transform(&mut udp);
+        let eth = Eth::new(&udp).unwrap();
+        let ipv4 = eth.ipv4().unwrap();
+        assert_eq!(ipv4.udp().unwrap().payload(), [b'x'; 10]);
+        assert_eq!(
+            Some(ipv4.udp().unwrap().checksum().get()),
+            ipv4.transport_checksum()
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! NAT router simulation.
+
+use core::{
+    net::{Ipv4Addr, SocketAddrV4},
+    ops::RangeInclusive,
+};
+use std::collections::HashMap;
+
+use super::Transform;
+use crate::{layer::tcp::TcpFlags, prelude::*, utils::checksum::internet_checksum};
+
+/// Default range of the ports allocated by the NAT.
+pub const DEFAULT_PORTS: RangeInclusive<u16> = 1024..=65535;
+
+/// Direction of a frame through the NAT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NatDirection {
+    /// From the inside to the outside.
+    Outbound,
+    /// From the outside to the inside.
+    Inbound,
+}
+
+/// NAT router simulation
+///
+/// Turns a capture taken on the inside of a router into the capture of the
+/// same traffic on the outside, e.g. to generate correlated datasets for
+/// stitching flows across a NAT.
+///
+/// Ipv4 packets from the inside prefix are translated to the public address,
+/// with a port allocated per inside TCP or UDP endpoint (endpoint-independent
+/// mapping), and their TTL is decremented; packets to the inside get the
+/// public endpoint back and the TTL they had before the router decremented
+/// it. Frames are re-addressed between the router and the upstream next hop.
+///
+/// Packets to an inside endpoint without mapping, i.e. unsolicited ones, and
+/// frames carrying other protocols are left unchanged.
+///
+/// ```
+/// use std::net::Ipv4Addr;
+///
+/// use netkit_packet::prelude::*;
+/// use netkit_packet::transform::{NatRouter, Transform};
+///
+/// let udp = udp!(src_port: 40000u16, dst_port: 53u16, payload: b"query");
+/// let ipv4 = ipv4!(
+///     protocol: IpProtocol::Udp,
+///     ttl: 64u8,
+///     src: Ipv4Addr::new(192, 168, 1, 10),
+///     dst: Ipv4Addr::new(203, 0, 113, 1),
+///     payload: udp.inner()
+/// );
+/// let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone();
+///
+/// let mut nat = NatRouter::new(Ipv4Addr::new(198, 51, 100, 1));
+/// nat.inside(Ipv4Addr::new(192, 168, 1, 0), 24);
+/// nat.transform(&mut frame);
+///
+/// let eth = Eth::new(&frame).unwrap();
+/// let ipv4 = eth.ipv4().unwrap();
+/// assert_eq!(ipv4.src().get(), Ipv4Addr::new(198, 51, 100, 1));
+/// assert_eq!(ipv4.ttl().get(), 63);
+/// assert_eq!(ipv4.udp().unwrap().src_port().get(), 1024);
+/// ```
+#[derive(Clone, Debug)]
+pub struct NatRouter {
+    public: Ipv4Addr,
+    inside: (Ipv4Addr, u8),
+    ports: RangeInclusive<u16>,
+    router_mac: Option<EthAddr>,
+    upstream_mac: Option<EthAddr>,
+    seq_offset: u32,
+    next_port: u16,
+    mappings: HashMap<(IpProtocol, SocketAddrV4), u16>,
+    reverse: HashMap<(IpProtocol, u16), SocketAddrV4>,
+}
+
+impl NatRouter {
+    /// Create a NAT translating 192.168.0.0/16 to a public address.
+    pub fn new(public: Ipv4Addr) -> Self {
+        Self {
+            public,
+            inside: (Ipv4Addr::new(192, 168, 0, 0), 16),
+            ports: DEFAULT_PORTS,
+            router_mac: None,
+            upstream_mac: None,
+            seq_offset: 0,
+            next_port: *DEFAULT_PORTS.start(),
+            mappings: HashMap::new(),
+            reverse: HashMap::new(),
+        }
+    }
+
+    /// Set the inside prefix.
+    pub fn inside(&mut self, prefix: Ipv4Addr, len: u8) -> &mut Self {
+        self.inside = (prefix, len.min(32));
+        self
+    }
+
+    /// Set the range of allocated ports, clearing the mappings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty.
+    pub fn ports(&mut self, ports: RangeInclusive<u16>) -> &mut Self {
+        assert!(!ports.is_empty(), "empty port range");
+        self.next_port = *ports.start();
+        self.ports = ports;
+        self.mappings.clear();
+        self.reverse.clear();
+        self
+    }
+
+    /// Set the MAC addresses of the outside interface of the router and of
+    /// the upstream next hop.
+    pub fn macs(&mut self, router: EthAddr, upstream: EthAddr) -> &mut Self {
+        self.router_mac = Some(router);
+        self.upstream_mac = Some(upstream);
+        self
+    }
+
+    /// Set the offset added to the TCP sequence numbers of outbound
+    /// segments, as by NATs randomizing them.
+    pub fn seq_offset(&mut self, offset: u32) -> &mut Self {
+        self.seq_offset = offset;
+        self
+    }
+
+    /// Get the public port of an inside endpoint, if mapped.
+    pub fn mapping(&self, protocol: IpProtocol, inside: SocketAddrV4) -> Option<u16> {
+        self.mappings.get(&(protocol, inside)).copied()
+    }
+
+    /// Get the number of mapped endpoints.
+    pub fn len(&self) -> usize {
+        self.mappings.len()
+    }
+
+    /// Whether no endpoint is mapped.
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    fn is_inside(&self, addr: Ipv4Addr) -> bool {
+        let (prefix, len) = self.inside;
+        let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+        u32::from(prefix) & mask == u32::from(addr) & mask
+    }
+
+    /// Allocate the public port of an inside endpoint.
+    ///
+    /// When the range is exhausted, the port of the oldest allocation in
+    /// range order is taken over.
+    fn allocate(&mut self, protocol: IpProtocol, inside: SocketAddrV4) -> u16 {
+        if let Some(port) = self.mapping(protocol, inside) {
+            return port;
+        }
+
+        let size = self.ports.len();
+        let mut port = self.next_port;
+        for _ in 0..size {
+            if !self.reverse.contains_key(&(protocol, port)) {
+                break;
+            }
+            port = self.following(port);
+        }
+        self.next_port = self.following(port);
+
+        if let Some(old) = self.reverse.insert((protocol, port), inside) {
+            self.mappings.remove(&(protocol, old));
+        }
+        self.mappings.insert((protocol, inside), port);
+        port
+    }
+
+    fn following(&self, port: u16) -> u16 {
+        if port == *self.ports.end() {
+            *self.ports.start()
+        } else {
+            port + 1
+        }
+    }
+
+    /// Translate an Ipv4 packet.
+    ///
+    /// Returns the direction of the packet, if it was translated.
+    pub fn translate_ipv4<T>(&mut self, ipv4: &mut Ipv4<T>) -> Option<NatDirection>
+    where
+        T: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        let protocol = ipv4.protocol().get();
+        let ports = if let Some(tcp) = ipv4.tcp() {
+            Some((tcp.src_port().get(), tcp.dst_port().get()))
+        } else {
+            ipv4.udp()
+                .map(|udp| (udp.src_port().get(), udp.dst_port().get()))
+        };
+        let (src, dst) = (ipv4.src().get(), ipv4.dst().get());
+
+        let header_len = ipv4.ihl().get() as usize * 4;
+        let header_valid = ipv4
+            .inner()
+            .as_ref()
+            .get(..header_len)
+            .is_some_and(|header| internet_checksum(header) == 0);
+
+        let direction = if self.is_inside(src) && !self.is_inside(dst) {
+            let port = ports
+                .map(|(src_port, _)| self.allocate(protocol, SocketAddrV4::new(src, src_port)));
+            ipv4.src_mut().set(self.public);
+            if let Some(port) = port {
+                ipv4.payload_mut()[0..2].copy_from_slice(&port.to_be_bytes());
+            }
+            let ttl = ipv4.ttl().get();
+            ipv4.ttl_mut().set(ttl.saturating_sub(1));
+            self.shift_tcp(ipv4, NatDirection::Outbound);
+            NatDirection::Outbound
+        } else if self.is_inside(dst) && !self.is_inside(src) {
+            match ports {
+                Some((_, dst_port)) => {
+                    let port = self.mapping(protocol, SocketAddrV4::new(dst, dst_port))?;
+                    ipv4.payload_mut()[2..4].copy_from_slice(&port.to_be_bytes());
+                }
+                // Without ports, only outbound traffic of the inside
+                // address tells it is mapped.
+                None if !self.mappings.keys().any(|(_, inside)| *inside.ip() == dst) => {
+                    return None
+                }
+                None => {}
+            }
+            ipv4.dst_mut().set(self.public);
+            let ttl = ipv4.ttl().get();
+            ipv4.ttl_mut().set(ttl.saturating_add(1));
+            self.shift_tcp(ipv4, NatDirection::Inbound);
+            NatDirection::Inbound
+        } else {
+            return None;
+        };
+
+        if header_valid {
+            ipv4.checksum_mut().set(0);
+            let checksum = internet_checksum(&ipv4.inner().as_ref()[..header_len]);
+            ipv4.checksum_mut().set(checksum);
+        }
+        ipv4.update_transport_checksum();
+        Some(direction)
+    }
+
+    /// Shift the sequence numbers of outbound segments, and the
+    /// acknowledgments of inbound ones.
+    fn shift_tcp<T>(&self, ipv4: &mut Ipv4<T>, direction: NatDirection)
+    where
+        T: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        if self.seq_offset == 0 || ipv4.protocol().get() != IpProtocol::Tcp {
+            return;
+        }
+        let Ok(mut tcp) = Tcp::new(ipv4.payload_mut()) else {
+            return;
+        };
+        match direction {
+            NatDirection::Outbound => {
+                let seq = tcp.seq_num().get();
+                tcp.seq_num_mut().set(seq.wrapping_add(self.seq_offset));
+            }
+            NatDirection::Inbound if tcp.flags().get().contains(TcpFlags::ACK) => {
+                let ack = tcp.ack_num().get();
+                tcp.ack_num_mut().set(ack.wrapping_add(self.seq_offset));
+            }
+            NatDirection::Inbound => {}
+        }
+    }
+}
+
+impl Transform for NatRouter {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        let Ok(mut eth) = Eth::new(frame.as_mut_slice()) else {
+            return;
+        };
+
+        let Some(direction) = eth
+            .ipv4_mut()
+            .and_then(|mut ipv4| self.translate_ipv4(&mut ipv4))
+        else {
+            return;
+        };
+        let (Some(router), Some(upstream)) = (self.router_mac, self.upstream_mac) else {
+            return;
+        };
+        let (src, dst) = match direction {
+            NatDirection::Outbound => (router, upstream),
+            NatDirection::Inbound => (upstream, router),
+        };
+        eth.src_mut().set(src);
+        eth.dst_mut().set(dst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(src: SocketAddrV4, dst: SocketAddrV4, ttl: u8, seq: u32, ack: u32) -> Vec<u8> {
+        let tcp = tcp!(
+            src_port: src.port(),
+            dst_port: dst.port(),
+            seq_num: seq,
+            ack_num: ack,
+            flags: TcpFlags::ACK,
+            payload: b"data"
+        );
+        let mut ipv4 = ipv4!(
+            protocol: IpProtocol::Tcp,
+            ttl: ttl,
+            src: *src.ip(),
+            dst: *dst.ip(),
+            payload: tcp.inner()
+        );
+        let checksum = internet_checksum(&ipv4.inner()[..20]);
+        ipv4.checksum_mut().set(checksum);
+        ipv4.update_transport_checksum();
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn nat_router() {
+        let public = Ipv4Addr::new(198, 51, 100, 1);
+        let router = EthAddr::new(0x02, 0, 0, 0, 0, 1);
+        let upstream = EthAddr::new(0x02, 0, 0, 0, 0, 2);
+        let mut nat = NatRouter::new(public);
+        nat.inside(Ipv4Addr::new(10, 0, 0, 0), 8)
+            .ports(5000..=5001)
+            .macs(router, upstream)
+            .seq_offset(1000);
+
+        let server: SocketAddrV4 = "203.0.113.1:443".parse().unwrap();
+        let clients: [SocketAddrV4; 3] = [
+            "10.0.0.1:40000".parse().unwrap(),
+            "10.0.0.2:40000".parse().unwrap(),
+            "10.0.0.1:40000".parse().unwrap(),
+        ];
+        let view = |frame: &[u8]| {
+            let eth = Eth::new(frame).unwrap();
+            let ipv4 = eth.ipv4().unwrap();
+            assert_eq!(internet_checksum(&ipv4.inner()[..20]), 0);
+            let tcp = ipv4.tcp().unwrap();
+            assert_eq!(Some(tcp.checksum().get()), ipv4.transport_checksum());
+            (
+                (eth.src().get(), eth.dst().get()),
+                SocketAddrV4::new(ipv4.src().get(), tcp.src_port().get()),
+                SocketAddrV4::new(ipv4.dst().get(), tcp.dst_port().get()),
+                ipv4.ttl().get(),
+                (tcp.seq_num().get(), tcp.ack_num().get()),
+            )
+        };
+
+        let mut outbound = frame(clients[0], server, 64, 1, 0);
+        nat.transform(&mut outbound);
+        let translated = SocketAddrV4::new(public, 5000);
+        assert_eq!(
+            view(&outbound),
+            ((router, upstream), translated, server, 63, (1001, 0))
+        );
+
+        let mut inbound = frame(server, clients[0], 54, 7, 5);
+        nat.transform(&mut inbound);
+        assert_eq!(
+            view(&inbound),
+            ((upstream, router), server, translated, 55, (7, 1005))
+        );
+
+        // Endpoint-independent mapping, and port reuse once exhausted
+        for client in &clients[1..] {
+            let mut outbound = frame(*client, server, 64, 1, 0);
+            nat.transform(&mut outbound);
+        }
+        let tcp = IpProtocol::Tcp;
+        assert_eq!(nat.mapping(tcp, clients[0]), Some(5000));
+        assert_eq!(nat.mapping(tcp, clients[1]), Some(5001));
+        assert_eq!(nat.len(), 2);
+
+        let mut unsolicited = frame(server, "10.0.0.3:22".parse().unwrap(), 54, 7, 5);
+        let original = unsolicited.clone();
+        nat.transform(&mut unsolicited);
+        assert_eq!(unsolicited, original);
+    }
+}
@@ -0,0 +1,262 @@
+//! Provenance of transformed frames.
+
+use core::{fmt, ops::Range, str::FromStr};
+
+use super::Transform;
+use crate::utils::id::{CaptureId, PacketId};
+
+/// Error type for parsing a [`Provenance`].
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[error("Invalid provenance record {0:?}")]
+pub struct ProvenanceError(pub String);
+
+/// Change of a frame by a transform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProvenanceStep {
+    /// Name of the transform.
+    pub transform: String,
+    /// Bytes changed, as offsets in the frame before the transform.
+    pub changed: Range<usize>,
+    /// Length of the frame after the transform.
+    pub len: usize,
+}
+
+/// Provenance record
+///
+/// Records where a frame was captured and which transforms changed which of
+/// its bytes, so a processed capture remains auditable. With the capture
+/// set, it carries the [`PacketId`] of the frame. The record is
+/// written as a single line, e.g. in the comment of a pcapng packet, and
+/// parsed back:
+///
+/// ```
+/// use netkit_packet::prelude::*;
+/// use netkit_packet::transform::{PayloadMask, Provenance, Transform};
+///
+/// let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: b"secret");
+/// let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+/// let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone();
+///
+/// let mut provenance = Provenance::new(7, &frame).offset(4096);
+/// provenance.apply(&mut PayloadMask::new(), &mut frame);
+/// let comment = provenance.to_string();
+/// assert!(comment.starts_with("netkit-provenance packet=7 offset=4096"));
+/// assert_eq!(comment.parse::<Provenance>().unwrap(), provenance);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// Ordinal of the packet in the original capture.
+    pub packet: u64,
+    /// Identifier of the original capture, if known.
+    pub capture: Option<CaptureId>,
+    /// Offset of the packet in the original capture file, if known.
+    pub offset: Option<u64>,
+    /// Length of the original frame.
+    pub original_len: usize,
+    /// Transforms which changed the frame, in order.
+    pub steps: Vec<ProvenanceStep>,
+}
+
+/// Marker starting a provenance record.
+const MARKER: &str = "netkit-provenance";
+
+impl Provenance {
+    /// Start the record of a captured frame.
+    pub fn new(packet: u64, frame: &[u8]) -> Self {
+        Self {
+            packet,
+            capture: None,
+            offset: None,
+            original_len: frame.len(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Set the offset of the packet in the original capture file.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Set the identifier of the original capture.
+    pub fn capture(mut self, capture: CaptureId) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Get the identifier of the packet, if the capture is known.
+    pub fn id(&self) -> Option<PacketId> {
+        self.capture
+            .map(|capture| PacketId::new(capture, self.packet))
+    }
+
+    /// Whether a transform changed the frame.
+    pub fn is_modified(&self) -> bool {
+        !self.steps.is_empty()
+    }
+
+    /// Apply a transform, recording the bytes it changed.
+    ///
+    /// Returns whether the frame changed.
+    pub fn apply<T>(&mut self, transform: &mut T, frame: &mut Vec<u8>) -> bool
+    where
+        T: Transform + ?Sized,
+    {
+        let before = frame.clone();
+        transform.transform(frame);
+        let Some(changed) = changed(&before, frame) else {
+            return false;
+        };
+        self.steps.push(ProvenanceStep {
+            transform: transform.name().to_string(),
+            changed,
+            len: frame.len(),
+        });
+        true
+    }
+
+    /// Apply transforms in order, recording the bytes each changed.
+    pub fn apply_all<T: Transform>(&mut self, transforms: &mut [T], frame: &mut Vec<u8>) {
+        for transform in transforms {
+            self.apply(transform, frame);
+        }
+    }
+}
+
+/// Get the range of `before` differing from `after`.
+fn changed(before: &[u8], after: &[u8]) -> Option<Range<usize>> {
+    if before == after {
+        return None;
+    }
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Some(prefix..before.len() - suffix)
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{MARKER} packet={}", self.packet)?;
+        if let Some(offset) = self.offset {
+            write!(f, " offset={offset}")?;
+        }
+        write!(f, " len={}", self.original_len)?;
+        if let Some(capture) = self.capture {
+            write!(f, " capture={capture}")?;
+        }
+        for step in &self.steps {
+            write!(
+                f,
+                "; {} changed={}..{} len={}",
+                step.transform, step.changed.start, step.changed.end, step.len
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Provenance {
+    type Err = ProvenanceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ProvenanceError(s.to_string());
+        let mut parts = s.trim().split("; ");
+        let mut header = parts.next().ok_or_else(error)?.split(' ');
+        if header.next() != Some(MARKER) {
+            return Err(error());
+        }
+
+        let mut provenance = Provenance::default();
+        for field in header {
+            let (key, value) = field.split_once('=').ok_or_else(error)?;
+            if key == "capture" {
+                provenance.capture = Some(value.parse().map_err(|_| error())?);
+                continue;
+            }
+            let value = value.parse().map_err(|_| error())?;
+            match key {
+                "packet" => provenance.packet = value,
+                "offset" => provenance.offset = Some(value),
+                "len" => provenance.original_len = value as usize,
+                _ => return Err(error()),
+            }
+        }
+
+        for part in parts {
+            let step = (|| {
+                let mut fields = part.split(' ');
+                let transform = fields.next()?.to_string();
+                let (start, end) = fields.next()?.strip_prefix("changed=")?.split_once("..")?;
+                let len = fields.next()?.strip_prefix("len=")?;
+                if fields.next().is_some() {
+                    return None;
+                }
+                Some(ProvenanceStep {
+                    transform,
+                    changed: start.parse().ok()?..end.parse().ok()?,
+                    len: len.parse().ok()?,
+                })
+            })();
+            provenance.steps.push(step.ok_or_else(error)?);
+        }
+        Ok(provenance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::*,
+        transform::{PayloadMask, VlanRewrite},
+    };
+
+    #[test]
+    fn provenance_chain() {
+        let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: b"secret");
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone();
+        let len = frame.len();
+
+        let mut provenance = Provenance::new(3, &frame);
+        let mut transforms: Vec<Box<dyn Transform>> = vec![
+            Box::new(PayloadMask::new()),
+            Box::new(PayloadMask::new()),
+            Box::new(VlanRewrite::Push(VlanTag::new(100))),
+        ];
+        provenance.apply_all(&mut transforms, &mut frame);
+
+        // Masking twice changes the frame once.
+        assert_eq!(provenance.steps.len(), 2);
+        let mask = &provenance.steps[0];
+        assert_eq!(mask.transform, "PayloadMask");
+        assert_eq!(mask.changed.end, len);
+        assert_eq!(mask.len, len);
+        let vlan = &provenance.steps[1];
+        assert_eq!(vlan.transform, "VlanRewrite");
+        assert_eq!((vlan.changed.start, vlan.len), (12, len + 4));
+
+        let comment = provenance.to_string();
+        assert_eq!(comment.parse::<Provenance>(), Ok(provenance.clone()));
+        let capture = CaptureId([0x11; 16]);
+        let provenance = provenance.capture(capture);
+        let comment = provenance.to_string();
+        assert!(comment.contains(" capture=11111111-1111-1111-1111-111111111111; "));
+        assert_eq!(comment.parse::<Provenance>(), Ok(provenance.clone()));
+        assert_eq!(provenance.id(), Some(PacketId::new(capture, 3)));
+        assert!(!Provenance::new(0, &[]).is_modified());
+        for invalid in [
+            "",
+            "netkit-provenance size=1",
+            "netkit-provenance len=1; Mask",
+        ] {
+            assert!(invalid.parse::<Provenance>().is_err());
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Address and port remapping.
+
+use core::net::{Ipv4Addr, SocketAddrV4};
+use std::collections::HashMap;
+
+use super::Transform;
+use crate::{prelude::*, utils::checksum::internet_checksum};
+
+/// Address and port remap
+///
+/// Rewrites the endpoints of Ipv4 packets so a production capture can be
+/// replayed against a test environment. Endpoints are looked up, in order,
+/// in the port mappings, the address mappings, and then re-sourced from the
+/// client pool, if any. Mappings apply to source and destination alike, so
+/// both directions of a flow are rewritten consistently, and every address
+/// drawn from the pool sticks to the original address it replaces.
+///
+/// The transport checksums are recomputed, as is the header checksum when
+/// it was valid. Frames carrying other protocols are left unchanged.
+///
+/// ```
+/// use std::net::Ipv4Addr;
+///
+/// use netkit_packet::prelude::*;
+/// use netkit_packet::transform::{FlowRemap, Transform};
+///
+/// let udp = udp!(src_port: 40000u16, dst_port: 53u16, payload: b"query");
+/// let ipv4 = ipv4!(
+///     protocol: IpProtocol::Udp,
+///     src: Ipv4Addr::new(198, 51, 100, 7),
+///     dst: Ipv4Addr::new(203, 0, 113, 1),
+///     payload: udp.inner()
+/// );
+/// let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone();
+///
+/// let mut remap = FlowRemap::new();
+/// remap
+///     .map_addr(Ipv4Addr::new(203, 0, 113, 1), Ipv4Addr::new(10, 0, 0, 53))
+///     .client_pool([Ipv4Addr::new(10, 0, 1, 1), Ipv4Addr::new(10, 0, 1, 2)]);
+/// remap.transform(&mut frame);
+///
+/// let eth = Eth::new(&frame).unwrap();
+/// let ipv4 = eth.ipv4().unwrap();
+/// assert_eq!(ipv4.src().get(), Ipv4Addr::new(10, 0, 1, 1));
+/// assert_eq!(ipv4.dst().get(), Ipv4Addr::new(10, 0, 0, 53));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FlowRemap {
+    endpoints: HashMap<SocketAddrV4, SocketAddrV4>,
+    addrs: HashMap<Ipv4Addr, Ipv4Addr>,
+    pool: Vec<Ipv4Addr>,
+    assigned: HashMap<Ipv4Addr, Ipv4Addr>,
+}
+
+impl FlowRemap {
+    /// Create a remap leaving every endpoint unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map an address to another one.
+    pub fn map_addr(&mut self, from: Ipv4Addr, to: Ipv4Addr) -> &mut Self {
+        self.addrs.insert(from, to);
+        self
+    }
+
+    /// Map a TCP or UDP endpoint to another one, e.g. a service moved to
+    /// another port in the lab.
+    pub fn map_endpoint(&mut self, from: SocketAddrV4, to: SocketAddrV4) -> &mut Self {
+        self.endpoints.insert(from, to);
+        self
+    }
+
+    /// Set the pool re-sourcing every address without mapping.
+    ///
+    /// Addresses are drawn in order as new ones are seen, and reused from
+    /// the start when the pool is exhausted.
+    pub fn client_pool(&mut self, pool: impl IntoIterator<Item = Ipv4Addr>) -> &mut Self {
+        self.pool = pool.into_iter().collect();
+        self.assigned.clear();
+        self
+    }
+
+    /// Get the addresses drawn from the pool, by original address.
+    pub fn assigned(&self) -> &HashMap<Ipv4Addr, Ipv4Addr> {
+        &self.assigned
+    }
+
+    /// Get the rewritten endpoint of an address and port.
+    pub fn remap(&mut self, addr: Ipv4Addr, port: Option<u16>) -> (Ipv4Addr, Option<u16>) {
+        if let Some(port) = port {
+            if let Some(to) = self.endpoints.get(&SocketAddrV4::new(addr, port)) {
+                return (*to.ip(), Some(to.port()));
+            }
+        }
+        if let Some(to) = self.addrs.get(&addr) {
+            return (*to, port);
+        }
+        if self.pool.is_empty() {
+            return (addr, port);
+        }
+
+        let next = self.pool[self.assigned.len() % self.pool.len()];
+        (*self.assigned.entry(addr).or_insert(next), port)
+    }
+
+    /// Rewrite the endpoints of an Ipv4 packet.
+    ///
+    /// Returns whether the packet was changed.
+    pub fn remap_ipv4<T>(&mut self, ipv4: &mut Ipv4<T>) -> bool
+    where
+        T: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        let ports = if let Some(tcp) = ipv4.tcp() {
+            Some((tcp.src_port().get(), tcp.dst_port().get()))
+        } else {
+            ipv4.udp()
+                .map(|udp| (udp.src_port().get(), udp.dst_port().get()))
+        };
+
+        let (src, dst) = (ipv4.src().get(), ipv4.dst().get());
+        let (new_src, src_port) = self.remap(src, ports.map(|p| p.0));
+        let (new_dst, dst_port) = self.remap(dst, ports.map(|p| p.1));
+        let new_ports = src_port.zip(dst_port);
+        if (new_src, new_dst) == (src, dst) && new_ports == ports {
+            return false;
+        }
+
+        let header_len = ipv4.ihl().get() as usize * 4;
+        let header_valid = ipv4
+            .inner()
+            .as_ref()
+            .get(..header_len)
+            .is_some_and(|header| internet_checksum(header) == 0);
+
+        ipv4.src_mut().set(new_src);
+        ipv4.dst_mut().set(new_dst);
+        if let Some((src_port, dst_port)) = new_ports {
+            // Both TCP and UDP start with the source and destination ports.
+            let payload = ipv4.payload_mut();
+            if payload.len() >= 4 {
+                payload[0..2].copy_from_slice(&src_port.to_be_bytes());
+                payload[2..4].copy_from_slice(&dst_port.to_be_bytes());
+            }
+        }
+
+        if header_valid {
+            ipv4.checksum_mut().set(0);
+            let checksum = internet_checksum(&ipv4.inner().as_ref()[..header_len]);
+            ipv4.checksum_mut().set(checksum);
+        }
+        ipv4.update_transport_checksum();
+        true
+    }
+}
+
+impl Transform for FlowRemap {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        let Ok(mut eth) = Eth::new(frame.as_mut_slice()) else {
+            return;
+        };
+
+        if let Some(mut ipv4) = eth.ipv4_mut() {
+            self.remap_ipv4(&mut ipv4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gen::craft::tcp_segment, layer::tcp::TcpFlags};
+
+    fn frame(src: SocketAddrV4, dst: SocketAddrV4) -> Vec<u8> {
+        tcp_segment(
+            [0; 6],
+            [0; 6],
+            src.into(),
+            dst.into(),
+            TcpFlags::empty(),
+            b"GET /",
+        )
+        .inner()
+        .clone()
+    }
+
+    #[test]
+    fn flow_remap() {
+        let server = "203.0.113.1:80".parse().unwrap();
+        let lab = "10.0.0.80:8080".parse().unwrap();
+        let clients: [SocketAddrV4; 3] = [
+            "198.51.100.1:40000".parse().unwrap(),
+            "198.51.100.2:40000".parse().unwrap(),
+            "198.51.100.1:40001".parse().unwrap(),
+        ];
+
+        let mut remap = FlowRemap::new();
+        remap
+            .map_endpoint(server, lab)
+            .client_pool([Ipv4Addr::new(10, 0, 1, 1), Ipv4Addr::new(10, 0, 1, 2)]);
+
+        let mut rewritten = Vec::new();
+        for client in clients {
+            let mut request = frame(client, server);
+            remap.transform(&mut request);
+            let mut response = frame(server, client);
+            remap.transform(&mut response);
+            rewritten.push((request, response));
+        }
+
+        let endpoints = |frame: &[u8]| {
+            let eth = Eth::new(frame).unwrap();
+            let ipv4 = eth.ipv4().unwrap();
+            assert_eq!(internet_checksum(&ipv4.inner()[..20]), 0);
+            let tcp = ipv4.tcp().unwrap();
+            assert_eq!(Some(tcp.checksum().get()), ipv4.transport_checksum());
+            (
+                SocketAddrV4::new(ipv4.src().get(), tcp.src_port().get()),
+                SocketAddrV4::new(ipv4.dst().get(), tcp.dst_port().get()),
+            )
+        };
+        let expected = ["10.0.1.1:40000", "10.0.1.2:40000", "10.0.1.1:40001"];
+        for ((request, response), client) in rewritten.iter().zip(expected) {
+            let client = client.parse().unwrap();
+            assert_eq!(endpoints(request), (client, lab));
+            assert_eq!(endpoints(response), (lab, client));
+        }
+        assert_eq!(remap.assigned().len(), 2);
+    }
+}
@@ -0,0 +1,348 @@
+//! Per-iteration sanitization of replayed captures.
+
+use core::net::Ipv4Addr;
+
+use super::Transform;
+use crate::{
+    layer::tcp::{
+        options::{KIND_EOL, KIND_NOP, KIND_SACK, KIND_TIMESTAMPS},
+        TcpFlags,
+    },
+    prelude::*,
+    utils::checksum::internet_checksum,
+};
+
+/// Replay sanitizer
+///
+/// Replaying the same capture in a loop against a middlebox sends the same
+/// packets again and again: the device under test may drop the later
+/// iterations as retransmissions or duplicates. The sanitizer shifts, per
+/// iteration, the fields such devices track:
+///
+/// - the TCP sequence numbers of each direction of a flow, along with the
+///   acknowledgments and SACK blocks of the other direction;
+/// - the Ipv4 identification of each source address;
+/// - the TCP timestamp values of each source address, along with the echo
+///   replies to them.
+///
+/// Offsets are derived from a key and the iteration number, so each
+/// iteration is shifted consistently across flows and runs, and iteration
+/// 0 leaves packets unchanged. The transport checksums are recomputed, as
+/// is the header checksum when it was valid. Frames carrying other
+/// protocols are left unchanged.
+///
+/// ```
+/// use netkit_packet::prelude::*;
+/// use netkit_packet::transform::{ReplaySanitizer, Transform};
+///
+/// let tcp = tcp!(src_port: 40000u16, dst_port: 80u16, seq_num: 1000u32, payload: b"GET /");
+/// let ipv4 = ipv4!(protocol: IpProtocol::Tcp, payload: tcp.inner());
+/// let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner()).inner().clone();
+/// let seq = |frame: &[u8]| Eth::new(frame).unwrap().ipv4().unwrap().tcp().unwrap().seq_num().get();
+///
+/// let mut sanitizer = ReplaySanitizer::new(0x5eed);
+/// let mut first = frame.clone();
+/// sanitizer.transform(&mut first);
+/// assert_eq!(seq(&first), 1000);
+///
+/// let mut second = frame.clone();
+/// sanitizer.next_iteration().transform(&mut second);
+/// assert_ne!(seq(&second), 1000);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplaySanitizer {
+    key: u64,
+    iteration: u64,
+}
+
+impl ReplaySanitizer {
+    /// Create a sanitizer deriving its offsets from `key`, at iteration 0.
+    pub fn new(key: u64) -> Self {
+        Self { key, iteration: 0 }
+    }
+
+    /// Set the iteration of the replay.
+    pub fn iteration(&mut self, iteration: u64) -> &mut Self {
+        self.iteration = iteration;
+        self
+    }
+
+    /// Move on to the next iteration of the replay.
+    pub fn next_iteration(&mut self) -> &mut Self {
+        self.iteration += 1;
+        self
+    }
+
+    /// Get the iteration of the replay.
+    pub fn current_iteration(&self) -> u64 {
+        self.iteration
+    }
+
+    /// Get the offset of the sequence numbers sent from `src` to `dst` in
+    /// the current iteration.
+    pub fn seq_offset(&self, src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16)) -> u32 {
+        let mut endpoints = [0; 12];
+        endpoints[..4].copy_from_slice(&src.0.octets());
+        endpoints[4..6].copy_from_slice(&src.1.to_be_bytes());
+        endpoints[6..10].copy_from_slice(&dst.0.octets());
+        endpoints[10..].copy_from_slice(&dst.1.to_be_bytes());
+        self.offset(b"seq", &endpoints) as u32
+    }
+
+    /// Get the offset of the Ipv4 identifications of `src` in the current
+    /// iteration.
+    pub fn id_offset(&self, src: Ipv4Addr) -> u16 {
+        self.offset(b"id", &src.octets()) as u16
+    }
+
+    /// Get the offset of the TCP timestamp values of `src` in the current
+    /// iteration.
+    pub fn timestamp_offset(&self, src: Ipv4Addr) -> u32 {
+        self.offset(b"ts", &src.octets()) as u32
+    }
+
+    /// Derive an offset from the key, the iteration and the shifted field.
+    ///
+    /// Offsets are zero at iteration 0, and odd, hence never zero, after.
+    fn offset(&self, field: &[u8], data: &[u8]) -> u64 {
+        if self.iteration == 0 {
+            return 0;
+        }
+
+        // FNV-1a, stable across runs and platforms, then a final mix so
+        // that consecutive iterations spread over all bits.
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let bytes = [
+            &self.key.to_be_bytes()[..],
+            &self.iteration.to_be_bytes(),
+            field,
+            data,
+        ];
+        for byte in bytes.concat() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        hash | 1
+    }
+
+    /// Shift the fields of an Ipv4 packet.
+    ///
+    /// Returns whether the packet was changed.
+    pub fn sanitize_ipv4<T>(&self, ipv4: &mut Ipv4<T>) -> bool
+    where
+        T: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        if self.iteration == 0 {
+            return false;
+        }
+
+        let header_len = ipv4.ihl().get() as usize * 4;
+        let header_valid = ipv4
+            .inner()
+            .as_ref()
+            .get(..header_len)
+            .is_some_and(|header| internet_checksum(header) == 0);
+
+        let (src, dst) = (ipv4.src().get(), ipv4.dst().get());
+        let id = ipv4.identification().get();
+        ipv4.identification_mut()
+            .set(id.wrapping_add(self.id_offset(src)));
+        if ipv4.protocol().get() == IpProtocol::Tcp {
+            self.shift_tcp(ipv4.payload_mut(), src, dst);
+        }
+
+        if header_valid {
+            ipv4.checksum_mut().set(0);
+            let checksum = internet_checksum(&ipv4.inner().as_ref()[..header_len]);
+            ipv4.checksum_mut().set(checksum);
+        }
+        ipv4.update_transport_checksum();
+        true
+    }
+
+    fn shift_tcp(&self, segment: &mut [u8], src: Ipv4Addr, dst: Ipv4Addr) {
+        let Ok(mut tcp) = Tcp::new(segment) else {
+            return;
+        };
+        let (src_port, dst_port) = (tcp.src_port().get(), tcp.dst_port().get());
+        let seq_offset = self.seq_offset((src, src_port), (dst, dst_port));
+        let ack_offset = self.seq_offset((dst, dst_port), (src, src_port));
+        let acked = tcp.flags().get().contains(TcpFlags::ACK);
+
+        let seq = tcp.seq_num().get();
+        tcp.seq_num_mut().set(seq.wrapping_add(seq_offset));
+        if acked {
+            let ack = tcp.ack_num().get();
+            tcp.ack_num_mut().set(ack.wrapping_add(ack_offset));
+        }
+
+        let value_offset = self.timestamp_offset(src);
+        let echo_offset = if acked {
+            self.timestamp_offset(dst)
+        } else {
+            // The echo reply of a SYN is meaningless.
+            0
+        };
+        let options = tcp.options_mut();
+        let mut i = 0;
+        while let Some(&kind) = options.get(i) {
+            match kind {
+                KIND_EOL => break,
+                KIND_NOP => {
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let len = options.get(i + 1).map_or(0, |len| *len as usize);
+            let Some(data) = options.get_mut(i + 2..i + len) else {
+                break;
+            };
+            match (kind, data.len()) {
+                (KIND_SACK, _) => {
+                    for edge in data.chunks_exact_mut(4) {
+                        add_be_u32(edge, ack_offset);
+                    }
+                }
+                (KIND_TIMESTAMPS, 8) => {
+                    add_be_u32(&mut data[..4], value_offset);
+                    add_be_u32(&mut data[4..], echo_offset);
+                }
+                _ => {}
+            }
+            i += len;
+        }
+    }
+}
+
+/// Add `offset` to the big-endian `u32` in `bytes`.
+fn add_be_u32(bytes: &mut [u8], offset: u32) {
+    let value = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    bytes.copy_from_slice(&value.wrapping_add(offset).to_be_bytes());
+}
+
+impl Transform for ReplaySanitizer {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        let Ok(mut eth) = Eth::new(frame.as_mut_slice()) else {
+            return;
+        };
+
+        if let Some(mut ipv4) = eth.ipv4_mut() {
+            self.sanitize_ipv4(&mut ipv4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::tcp::options::{TcpOption, TcpOptions};
+
+    fn frame(
+        src: (Ipv4Addr, u16),
+        dst: (Ipv4Addr, u16),
+        seq: u32,
+        ack: u32,
+        ts: [u32; 2],
+    ) -> Vec<u8> {
+        let mut options = vec![KIND_NOP, KIND_NOP, KIND_TIMESTAMPS, 10];
+        options.extend(ts[0].to_be_bytes());
+        options.extend(ts[1].to_be_bytes());
+        let flags = if ack == 0 {
+            TcpFlags::SYN
+        } else {
+            TcpFlags::ACK
+        };
+        let tcp = tcp!(
+            src_port: src.1,
+            dst_port: dst.1,
+            seq_num: seq,
+            ack_num: ack,
+            flags: flags,
+            options: options,
+            payload: b"data"
+        );
+        let mut ipv4 = ipv4!(
+            protocol: IpProtocol::Tcp,
+            identification: 7u16,
+            src: src.0,
+            dst: dst.0,
+            payload: tcp.inner()
+        );
+        let checksum = internet_checksum(&ipv4.inner()[..20]);
+        ipv4.checksum_mut().set(checksum);
+        ipv4.update_transport_checksum();
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    /// Get the identification, sequence and acknowledgment numbers, and
+    /// timestamps of a frame, checking its checksums.
+    fn fields(frame: &[u8]) -> (u16, u32, u32, (u32, u32)) {
+        let eth = Eth::new(frame).unwrap();
+        let ipv4 = eth.ipv4().unwrap();
+        assert_eq!(internet_checksum(&ipv4.inner()[..20]), 0);
+        let tcp = ipv4.tcp().unwrap();
+        assert_eq!(Some(tcp.checksum().get()), ipv4.transport_checksum());
+        let ts = TcpOptions::new(tcp.options())
+            .find_map(|option| match option {
+                TcpOption::Timestamps { value, echo } => Some((value, echo)),
+                _ => None,
+            })
+            .unwrap();
+        (
+            ipv4.identification().get(),
+            tcp.seq_num().get(),
+            tcp.ack_num().get(),
+            ts,
+        )
+    }
+
+    #[test]
+    fn replay_sanitizer() {
+        let client = (Ipv4Addr::new(10, 0, 0, 1), 40000);
+        let server = (Ipv4Addr::new(10, 0, 0, 2), 80);
+        let capture = [
+            frame(client, server, 100, 0, [5000, 0]),
+            frame(server, client, 900, 101, [8000, 5000]),
+            frame(client, server, 101, 901, [5001, 8000]),
+        ];
+        let replay = |sanitizer: &mut ReplaySanitizer| {
+            capture
+                .iter()
+                .map(|frame| {
+                    let mut frame = frame.clone();
+                    sanitizer.transform(&mut frame);
+                    frame
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut sanitizer = ReplaySanitizer::new(42);
+        assert_eq!(replay(&mut sanitizer), capture);
+
+        let first = replay(sanitizer.next_iteration());
+        let [syn, syn_ack, ack] = [0, 1, 2].map(|i| fields(&first[i]));
+        assert_ne!(syn.0, 7);
+        assert_eq!(syn.0, ack.0);
+        assert_ne!(syn.1, 100);
+        // The conversation is shifted consistently.
+        assert_eq!(syn_ack.2, syn.1.wrapping_add(1));
+        assert_eq!(ack.1, syn.1.wrapping_add(1));
+        assert_eq!(ack.2, syn_ack.1.wrapping_add(1));
+        assert_eq!(syn.3 .1, 0);
+        assert_eq!(syn_ack.3 .1, syn.3 .0);
+        assert_eq!(ack.3 .1, syn_ack.3 .0);
+
+        // Iterations differ from each other, but not across runs.
+        let second = replay(sanitizer.next_iteration());
+        assert_eq!(sanitizer.current_iteration(), 2);
+        assert_ne!(fields(&second[0]).1, syn.1);
+        assert_eq!(replay(ReplaySanitizer::new(42).iteration(1)), first);
+        assert_ne!(replay(ReplaySanitizer::new(43).iteration(1)), first);
+    }
+}
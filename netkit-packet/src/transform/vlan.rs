@@ -0,0 +1,50 @@
+//! VLAN tag rewriting.
+
+use super::Transform;
+use crate::prelude::*;
+
+/// VLAN tag rewrite
+///
+/// Inserts or strips 802.1Q / 802.1ad tags, keeping the Eth type chain
+/// consistent, e.g. to replay a capture as if taken on a trunk port.
+///
+/// ```
+/// use netkit_packet::prelude::*;
+/// use netkit_packet::transform::{Transform, VlanRewrite};
+///
+/// let mut frame = eth!(eth_type: EthType::Ipv4, payload: ipv4!().inner()).inner().clone();
+///
+/// VlanRewrite::Push(VlanTag::new(10)).transform(&mut frame);
+/// assert_eq!(Eth::new(&frame).unwrap().vlan_ids(), [10]);
+///
+/// VlanRewrite::StripAll.transform(&mut frame);
+/// assert_eq!(Eth::new(&frame).unwrap().eth_type().get(), EthType::Ipv4);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VlanRewrite {
+    /// Insert a tag as the outermost tag.
+    Push(VlanTag),
+
+    /// Remove the outermost tag, if any.
+    Pop,
+
+    /// Remove all tags.
+    StripAll,
+}
+
+impl Transform for VlanRewrite {
+    fn transform(&mut self, frame: &mut Vec<u8>) {
+        let Ok(mut eth) = Eth::new(core::mem::take(frame)) else {
+            return;
+        };
+
+        match *self {
+            VlanRewrite::Push(tag) => eth.push_vlan(tag),
+            VlanRewrite::Pop => {
+                eth.pop_vlan();
+            }
+            VlanRewrite::StripAll => while eth.pop_vlan().is_some() {},
+        }
+        *frame = eth.inner().clone();
+    }
+}
@@ -1,8 +1,14 @@
 //! Utilitie types and functions for netkit-packet.
 
+pub mod checksum;
+pub mod clock;
 pub mod field;
+pub mod id;
+pub mod memory;
+pub mod rng;
 pub mod test_enum;
 
+pub use checksum::{internet_checksum, partial_checksum, Checksum};
 pub use field::*;
 
 pub(crate) fn cast_from_bytes<T>(s: &[u8]) -> &T {
@@ -0,0 +1,161 @@
+//! Internet checksum
+//!
+//! The 16-bit one's complement checksum used by IPv4, ICMP, TCP and UDP. See
+//! [RFC 1071](https://datatracker.ietf.org/doc/html/rfc1071).
+
+use core::net::IpAddr;
+
+use crate::layer::ip::IpProtocol;
+
+/// Internet checksum accumulator
+///
+/// Data can be added in several chunks, e.g. a pseudo header followed by the
+/// segment. Every chunk except the last one should have an even length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Checksum {
+    sum: u32,
+}
+
+impl Checksum {
+    /// Create a new checksum accumulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add bytes to the checksum.
+    pub fn add_bytes(&mut self, data: &[u8]) -> &mut Self {
+        let mut chunks = data.chunks_exact(2);
+        for chunk in &mut chunks {
+            self.add_u16(u16::from_be_bytes([chunk[0], chunk[1]]));
+        }
+        if let [last] = chunks.remainder() {
+            self.add_u16(u16::from_be_bytes([*last, 0]));
+        }
+        self
+    }
+
+    /// Add a 16-bit word to the checksum.
+    pub fn add_u16(&mut self, value: u16) -> &mut Self {
+        self.sum += value as u32;
+        // Fold eagerly so that the sum never overflows.
+        self.sum = (self.sum & 0xFFFF) + (self.sum >> 16);
+        self
+    }
+
+    /// Add the TCP or UDP pseudo header of a segment of `len` bytes.
+    ///
+    /// Mixed address families are treated as IPv6 with IPv4-mapped
+    /// addresses.
+    pub fn add_pseudo_header(
+        &mut self,
+        src: IpAddr,
+        dst: IpAddr,
+        protocol: IpProtocol,
+        len: usize,
+    ) -> &mut Self {
+        let protocol = u8::from(protocol) as u16;
+        match (src, dst) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => self
+                .add_bytes(&src.octets())
+                .add_bytes(&dst.octets())
+                .add_u16(protocol)
+                .add_u16(len as u16),
+            (src, dst) => self
+                .add_bytes(&to_ipv6(src).octets())
+                .add_bytes(&to_ipv6(dst).octets())
+                .add_bytes(&(len as u32).to_be_bytes())
+                .add_u16(protocol),
+        }
+    }
+
+    /// Get the sum, without the one's complement.
+    pub fn sum(&self) -> u16 {
+        self.sum as u16
+    }
+
+    /// Get the checksum, i.e. the one's complement of the sum.
+    pub fn finish(&self) -> u16 {
+        !(self.sum as u16)
+    }
+}
+
+fn to_ipv6(addr: IpAddr) -> core::net::Ipv6Addr {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+        IpAddr::V6(addr) => addr,
+    }
+}
+
+/// Compute the internet checksum of the data.
+///
+/// The checksum field inside the data must be zero, or the result is zero if
+/// the data already carries a correct checksum.
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    Checksum::new().add_bytes(data).finish()
+}
+
+/// Compute the partial checksum of a TCP or UDP segment of `len` bytes.
+///
+/// With checksum offload, the OS leaves the sum of the pseudo header in the
+/// checksum field (not complemented) and the NIC completes it on the wire,
+/// so captures taken on the sending host carry this value instead of the
+/// checksum.
+pub fn partial_checksum(src: IpAddr, dst: IpAddr, protocol: IpProtocol, len: usize) -> u16 {
+    Checksum::new()
+        .add_pseudo_header(src, dst, protocol, len)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_rfc1071() {
+        // Example from RFC 1071 section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(internet_checksum(&data), !0xddf2);
+
+        let mut chunked = Checksum::new();
+        chunked.add_bytes(&data[..4]).add_bytes(&data[4..]);
+        assert_eq!(chunked.finish(), internet_checksum(&data));
+
+        assert_eq!(internet_checksum(&[0xab]), !0xab00);
+    }
+
+    #[test]
+    fn checksum_verify() {
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let checksum = internet_checksum(&header);
+        assert_eq!(checksum, 0xb861);
+
+        header[10..12].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(internet_checksum(&header), 0);
+    }
+
+    #[test]
+    fn checksum_partial() {
+        use core::net::Ipv4Addr;
+
+        let (src, dst) = (
+            Ipv4Addr::new(192, 168, 0, 1),
+            Ipv4Addr::new(192, 168, 0, 199),
+        );
+        let mut segment = [0x04, 0xd2, 0x00, 0x35, 0x00, 0x0a, 0x00, 0x00, 0xab, 0xcd];
+
+        // Completing the partial checksum over the segment, as the NIC
+        // does, gives the full checksum.
+        let partial = partial_checksum(src.into(), dst.into(), IpProtocol::Udp, segment.len());
+        segment[6..8].copy_from_slice(&partial.to_be_bytes());
+        let completed = internet_checksum(&segment);
+        segment[6..8].fill(0);
+        let full = Checksum::new()
+            .add_pseudo_header(src.into(), dst.into(), IpProtocol::Udp, segment.len())
+            .add_bytes(&segment)
+            .finish();
+        assert_eq!(completed, full);
+    }
+}
@@ -0,0 +1,152 @@
+//! Pluggable clock sources.
+//!
+//! Components needing the current time, like the pacing of generators or
+//! the stamping of captured packets, read it from a [`Clock`] instead of the
+//! system directly. Times are [`Duration`]s since the Unix epoch, like
+//! packet timestamps, so latencies can be computed between the two. Tests
+//! and simulations pass a [`ManualClock`] to control time:
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit_packet::utils::clock::{Clock, ManualClock};
+//!
+//! let clock = ManualClock::new(Duration::from_secs(1_000));
+//! clock.sleep(Duration::from_millis(5));
+//! assert_eq!(clock.now(), Duration::from_millis(1_000_005));
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time.
+pub trait Clock {
+    /// Get the current time, since the Unix epoch.
+    fn now(&self) -> Duration;
+
+    /// Wait for `duration` to pass on this clock.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Wall clock of the system.
+///
+/// It follows adjustments of the system time, so it may jump backwards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+/// Monotonic clock, anchored to the wall clock when created.
+///
+/// It never goes backwards, but drifts from the wall clock when the system
+/// time is adjusted.
+#[derive(Clone, Copy, Debug)]
+pub struct MonotonicClock {
+    origin: Instant,
+    epoch: Duration,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MonotonicClock {
+    /// Create a clock anchored to the current wall clock time.
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            epoch: SystemClock.now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.epoch + self.origin.elapsed()
+    }
+}
+
+/// Clock only moving when told to, for tests and simulations.
+///
+/// Clones share the same time, so a test can keep one and hand another to
+/// the code under test. Sleeping advances the clock instead of blocking.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Create a clock at `now`.
+    pub fn new(now: Duration) -> Self {
+        Self {
+            nanos: Arc::new(AtomicU64::new(now.as_nanos() as u64)),
+        }
+    }
+
+    /// Set the current time.
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Advance the current time by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_sources() {
+        let system = SystemClock.now();
+        let monotonic = MonotonicClock::new();
+        let first = monotonic.now();
+        assert!(first.abs_diff(system) < Duration::from_secs(1));
+        assert!(monotonic.now() >= first);
+
+        let manual = ManualClock::default();
+        let shared = manual.clone();
+        shared.sleep(Duration::from_secs(3));
+        assert_eq!(manual.now(), Duration::from_secs(3));
+        manual.set(Duration::from_secs(1));
+        assert_eq!(shared.now(), Duration::from_secs(1));
+    }
+}
@@ -74,8 +74,9 @@ impl Target<u8> for bool {
 /// Underlay trait
 ///
 /// This trait marks the types that can be used as underlay for fields and
-/// provides methods to operate on them.
-pub trait Underlay: Copy {
+/// provides methods to operate on them. It is sealed: the integer and byte
+/// array types are the only underlays.
+pub trait Underlay: Copy + crate::sealed::Sealed {
     /// Convert from big-endian
     fn from_be(x: Self) -> Self;
     /// Convert from little-endian
@@ -103,6 +104,8 @@ pub trait Underlay: Copy {
 macro_rules! impl_underlay {
     ($($t:ty),*) => {
         $(
+            impl crate::sealed::Sealed for $t {}
+
             impl Underlay for $t {
                 #[inline]
                 fn from_be(x: Self) -> Self {
@@ -146,6 +149,8 @@ macro_rules! impl_underlay {
 
     ($($l:literal),*) => {
         $(
+            impl crate::sealed::Sealed for [u8; $l] {}
+
             impl Underlay for [u8; $l] {
                 #[inline]
                 fn from_be(x: Self) -> Self {
@@ -221,7 +226,7 @@ macro_rules! impl_underlay {
     };
 }
 
-impl_underlay!(u8, u16, u32, u64);
+impl_underlay!(u8, u16, u32, u64, u128);
 impl_underlay!(3, 5, 6, 7);
 
 /// Field specification
@@ -231,6 +236,7 @@ impl_underlay!(3, 5, 6, 7);
 /// - The underlay type `U`
 /// - The mask value `MASK`
 /// - The shift value `SHIFT`
+/// - The byte order `LSB`
 pub trait FieldSpec {
     /// The target type
     ///
@@ -247,68 +253,103 @@ pub trait FieldSpec {
 
     /// The shift value
     const SHIFT: u8 = 0;
+
+    /// Whether the field is stored least significant byte first
+    ///
+    /// Little-endian fields are read as such by every [`Field`] accessor,
+    /// whatever its `MSB` parameter.
+    const LSB: bool = false;
 }
 
 /// Field specification macro
 ///
-/// This helper macro is used to define a field specification.
+/// This helper macro is used to define a field specification. A trailing
+/// `le` declares a little-endian field:
+///
+/// ```
+/// use netkit_packet::{field_spec, utils::field::FieldSpec};
+///
+/// field_spec!(LengthSpec, u16, u16, le);
+/// field_spec!(FlagsSpec, u8, u16, 0x0F00, 8, le);
+/// assert!(LengthSpec::LSB && FlagsSpec::LSB);
+/// ```
 #[macro_export]
 macro_rules! field_spec {
-    // FieldSpec with only target and underlay
-    ($name: ident, $t:ty, $u:ty) => {
+    // Common definition, with the extra doc lines
+    (@define $name: ident, $t:ty, $u:ty, $m:expr, $s:expr, $lsb:expr, $(#[$doc:meta])*) => {
         #[doc = concat!("FieldSpec for `", stringify!($name), "` field\n\n")]
         #[doc = concat!("Target type: `", stringify!($t), "`\n")]
         #[doc = concat!("Underlay type: `", stringify!($u), "`\n")]
+        $(#[$doc])*
         #[derive(Debug, Clone, Copy)]
         pub struct $name;
         impl $crate::utils::field::FieldSpec for $name {
             type T = $t;
             type U = $u;
+            const MASK: u64 = $m;
+            const SHIFT: u8 = $s;
+            const LSB: bool = $lsb;
         }
     };
 
+    // Little-endian FieldSpec with only target and underlay
+    ($name: ident, $t:ty, $u:ty, le) => {
+        $crate::field_spec!(@define $name, $t, $u, u64::MAX, 0, true,
+            #[doc = "Byte order: little-endian\n"]);
+    };
+
+    // Little-endian FieldSpec with target, underlay and mask
+    ($name: ident, $t:ty, $u:ty, $m:expr, le) => {
+        $crate::field_spec!(@define $name, $t, $u, $m, 0, true,
+            #[doc = concat!("Mask: `", stringify!($m), "`\n")]
+            #[doc = "Byte order: little-endian\n"]);
+    };
+
+    // Little-endian FieldSpec with target, underlay, mask and shift
+    ($name: ident, $t:ty, $u:ty, $m:expr, $s:expr, le) => {
+        $crate::field_spec!(@define $name, $t, $u, $m, $s, true,
+            #[doc = concat!("Mask: `", stringify!($m), "`\n")]
+            #[doc = concat!("Shift: `", stringify!($s), "`\n")]
+            #[doc = "Byte order: little-endian\n"]);
+    };
+
+    // FieldSpec with only target and underlay
+    ($name: ident, $t:ty, $u:ty) => {
+        $crate::field_spec!(@define $name, $t, $u, u64::MAX, 0, false,);
+    };
+
     // FieldSpec with target, underlay and mask
     ($name: ident, $t:ty, $u:ty, $m:expr) => {
-        #[doc = concat!("FieldSpec for `", stringify!($name), "` field\n\n")]
-        #[doc = concat!("Target type: `", stringify!($t), "`\n")]
-        #[doc = concat!("Underlay type: `", stringify!($u), "`\n")]
-        #[doc = concat!("Mask: `", stringify!($m), "`\n")]
-        #[derive(Debug, Clone, Copy)]
-        pub struct $name;
-        impl $crate::utils::field::FieldSpec for $name {
-            type T = $t;
-            type U = $u;
-            const MASK: u64 = $m;
-        }
+        $crate::field_spec!(@define $name, $t, $u, $m, 0, false,
+            #[doc = concat!("Mask: `", stringify!($m), "`\n")]);
     };
 
     // FieldSpec with target, underlay, mask and shift
     ($name: ident, $t:ty, $u:ty, $m:expr, $s:expr) => {
-        #[doc = concat!("FieldSpec for `", stringify!($name), "` field\n\n")]
-        #[doc = concat!("Target type: `", stringify!($t), "`\n")]
-        #[doc = concat!("Underlay type: `", stringify!($u), "`\n")]
-        #[doc = concat!("Mask: `", stringify!($m), "`\n")]
-        #[doc = concat!("Shift: `", stringify!($s), "`\n")]
-        #[derive(Debug, Clone, Copy)]
-        pub struct $name;
-        impl $crate::utils::field::FieldSpec for $name {
-            type T = $t;
-            type U = $u;
-            const MASK: u64 = $m;
-            const SHIFT: u8 = $s;
-        }
+        $crate::field_spec!(@define $name, $t, $u, $m, $s, false,
+            #[doc = concat!("Mask: `", stringify!($m), "`\n")]
+            #[doc = concat!("Shift: `", stringify!($s), "`\n")]);
     };
 }
 
 /// Field accessor
+///
+/// Fields are cast from arbitrary byte offsets of a packet, so the accessor
+/// is packed to have an alignment of 1 regardless of the underlay type.
+///
+/// Values are stored in network byte order unless `MSB` is `false` or the
+/// spec declares the field [little-endian](FieldSpec::LSB).
 #[derive(Debug, Clone, Copy)]
-#[repr(transparent)]
+#[repr(C, packed)]
 pub struct Field<F: FieldSpec, const MSB: bool = true> {
     value: F::U,
     _marker: std::marker::PhantomData<F::T>,
 }
 
 impl<F: FieldSpec, const MSB: bool> Field<F, MSB> {
+    /// Whether the value is stored most significant byte first.
+    const BIG_ENDIAN: bool = MSB && !F::LSB;
+
     /// Get the inner value without any operations and conversions
     pub fn into_inner(self) -> F::U {
         self.value
@@ -319,7 +360,7 @@ impl<F: FieldSpec, const MSB: bool> Field<F, MSB> {
     /// **Note**: raw here means the mask and shift are applied but the value is
     /// not converted to the target type.
     pub fn raw(&self) -> F::U {
-        let value = if MSB {
+        let value = if Self::BIG_ENDIAN {
             F::U::from_be(self.value)
         } else {
             F::U::from_le(self.value)
@@ -341,7 +382,7 @@ impl<F: FieldSpec, const MSB: bool> Field<F, MSB> {
 
     /// Set the value of the field
     pub fn set(&mut self, value: F::T) {
-        let prev_value = if MSB {
+        let prev_value = if Self::BIG_ENDIAN {
             F::U::from_be(self.value)
         } else {
             F::U::from_le(self.value)
@@ -357,7 +398,7 @@ impl<F: FieldSpec, const MSB: bool> Field<F, MSB> {
                 .bitor(value.into_underlay().shl(F::SHIFT))
         };
 
-        self.value = if MSB {
+        self.value = if Self::BIG_ENDIAN {
             new_value.to_be()
         } else {
             new_value.to_le()
@@ -417,5 +458,21 @@ mod tests {
         assert_eq!(field.raw(), 0x000A);
         assert_eq!(field.get(), 0x0A);
         assert_eq!(field, 0x0A);
+
+        // Little-endian at the spec level, whatever the accessor
+        field_spec!(LeField, u16, u16, 0x0F00, 8, le);
+        let mut field = Field::<LeField> {
+            value: u16::from_le(0x0300),
+            _marker: std::marker::PhantomData,
+        };
+        assert_eq!(field.get(), 0x03);
+        field.set(0x0A);
+        assert_eq!(field.into_inner().to_ne_bytes(), [0x00, 0x0A]);
+        assert_eq!(field, 0x0A);
+        let field = Field::<LeField, false> {
+            value: field.into_inner(),
+            _marker: std::marker::PhantomData,
+        };
+        assert_eq!(field.get(), 0x0A);
     }
 }
@@ -0,0 +1,184 @@
+//! Stable identifiers of captured packets.
+//!
+//! A [`PacketId`] names a packet by the [`CaptureId`] of the capture it
+//! belongs to, a random UUID, and its ordinal in that capture. It is
+//! written as `<uuid>:<ordinal>`, the same in every output, so a finding
+//! in one (an alert, a row of a table) is traced back to the exact packet
+//! in another (a pcapng comment). [`PacketIds`] numbers the packets of a
+//! capture as they are read:
+//!
+//! ```
+//! use netkit_packet::utils::id::{PacketId, PacketIds};
+//!
+//! let mut ids = PacketIds::new();
+//! let first = ids.next_id();
+//! let second = ids.next_id();
+//! assert_eq!((first.ordinal, second.ordinal), (0, 1));
+//! assert_eq!(second.to_string().parse::<PacketId>().unwrap(), second);
+//! ```
+
+use core::{fmt, str::FromStr};
+
+use super::rng;
+
+/// Error type for parsing identifiers.
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[error("Invalid identifier {0:?}")]
+pub struct IdError(pub String);
+
+/// Identifier of a capture, a random (version 4) UUID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CaptureId(pub [u8; 16]);
+
+impl CaptureId {
+    /// Create a random identifier, drawn with [`rng::random`].
+    pub fn new() -> Self {
+        let mut bytes: [u8; 16] = rng::random();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(bytes)
+    }
+}
+
+impl Default for CaptureId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for CaptureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CaptureId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || IdError(s.to_string());
+        let groups: Vec<_> = s.split('-').collect();
+        if groups.iter().map(|g| g.len()).ne([8, 4, 4, 4, 12]) {
+            return Err(error());
+        }
+        let hex = groups.concat();
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let mut bytes = [0; 16];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| error())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// Identifier of a packet: its capture and ordinal in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PacketId {
+    /// Capture of the packet.
+    pub capture: CaptureId,
+    /// Ordinal of the packet in the capture, from 0.
+    pub ordinal: u64,
+}
+
+impl PacketId {
+    /// Create the identifier of a packet.
+    pub fn new(capture: CaptureId, ordinal: u64) -> Self {
+        Self { capture, ordinal }
+    }
+}
+
+impl fmt::Display for PacketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.capture, self.ordinal)
+    }
+}
+
+impl FromStr for PacketId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || IdError(s.to_string());
+        let (capture, ordinal) = s.split_once(':').ok_or_else(error)?;
+        Ok(Self {
+            capture: capture.parse().map_err(|_| error())?,
+            ordinal: ordinal.parse().map_err(|_| error())?,
+        })
+    }
+}
+
+/// Numbering of the packets of a capture
+#[derive(Clone, Debug, Default)]
+pub struct PacketIds {
+    capture: CaptureId,
+    next: u64,
+}
+
+impl PacketIds {
+    /// Number the packets of a new capture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number the packets of a known capture, e.g. when reading it again,
+    /// from `next`.
+    pub fn resume(capture: CaptureId, next: u64) -> Self {
+        Self { capture, next }
+    }
+
+    /// Get the identifier of the capture.
+    pub fn capture(&self) -> CaptureId {
+        self.capture
+    }
+
+    /// Get the identifier of the next packet, and advance.
+    pub fn next_id(&mut self) -> PacketId {
+        let id = PacketId::new(self.capture, self.next);
+        self.next += 1;
+        id
+    }
+}
+
+impl Iterator for PacketIds {
+    type Item = PacketId;
+
+    fn next(&mut self) -> Option<PacketId> {
+        Some(self.next_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rng::RngContext;
+
+    #[test]
+    fn packet_ids() {
+        let capture = RngContext::new(7).enter(CaptureId::new);
+        assert_eq!(capture, RngContext::new(7).enter(CaptureId::new));
+        let text = capture.to_string();
+        assert_eq!(text.len(), 36);
+        assert_eq!(&text[14..15], "4");
+        assert!(matches!(&text[19..20], "8" | "9" | "a" | "b"));
+        assert_eq!(text.parse::<CaptureId>().unwrap(), capture);
+
+        let ids: Vec<_> = PacketIds::resume(capture, 41).take(2).collect();
+        assert_eq!(ids[1], PacketId::new(capture, 42));
+        assert_eq!(ids[1].to_string(), format!("{text}:42"));
+        assert_eq!(format!("{text}:42").parse::<PacketId>().unwrap(), ids[1]);
+
+        for invalid in ["", "42", "0000:1", format!("{text}:x").as_str(), &text[1..]] {
+            assert!(invalid.parse::<PacketId>().is_err(), "{invalid}");
+        }
+        assert!("g0000000-0000-0000-0000-000000000000"
+            .parse::<CaptureId>()
+            .is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Memory budgets shared across subsystems.
+//!
+//! The state of a live probe grows with the traffic it sees: flows in flow
+//! tables, streams being reassembled, packets held for reordering. Left
+//! alone, its worst-case memory is whatever the traffic makes it. A
+//! [`MemoryBudget`] caps the sum: each subsystem charges what it holds to a
+//! [`MemoryAccount`] of the budget, and evicts some of its state when the
+//! budget is exceeded. The budget counts usage, peaks and evictions per
+//! [`Subsystem`] for operators:
+//!
+//! ```
+//! use netkit_packet::utils::memory::{MemoryBudget, Subsystem};
+//!
+//! let budget = MemoryBudget::new(1024);
+//! let mut flows = budget.account(Subsystem::FlowTable);
+//! let mut reorder = budget.account(Subsystem::Reorder);
+//! flows.reserve(800);
+//! reorder.reserve(400);
+//! assert!(budget.is_over());
+//!
+//! reorder.evict(400);
+//! let usage = budget.usage();
+//! assert_eq!(usage.used, 800);
+//! assert_eq!(usage.get(Subsystem::Reorder).evictions, 1);
+//! ```
+//!
+//! Sizes are estimates, from the sizes of the types held; heap data owned
+//! by the state, e.g. packet buffers, is only counted where the subsystem
+//! knows it.
+
+use core::fmt;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Subsystem holding memory of a budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Subsystem {
+    /// Buffers of stream reassembly.
+    Reassembly,
+    /// Fragments held for defragmentation.
+    Defrag,
+    /// Flow tables.
+    FlowTable,
+    /// Reorder buffers.
+    Reorder,
+}
+
+impl Subsystem {
+    /// All subsystems.
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Reassembly,
+        Subsystem::Defrag,
+        Subsystem::FlowTable,
+        Subsystem::Reorder,
+    ];
+
+    /// Get the name of the subsystem, e.g. `flow-table`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Reassembly => "reassembly",
+            Subsystem::Defrag => "defrag",
+            Subsystem::FlowTable => "flow-table",
+            Subsystem::Reorder => "reorder",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    evictions: AtomicU64,
+    evicted_bytes: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    limit: usize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    subsystems: [Counters; 4],
+}
+
+/// Memory budget shared by subsystems
+///
+/// Clones share the same budget. Reservations never fail: subsystems
+/// reserve what they hold, and check [`is_over`](MemoryBudget::is_over) to
+/// evict, so that the usage only exceeds the limit by what a subsystem adds
+/// before evicting.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    shared: Arc<Shared>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl MemoryBudget {
+    /// Create a budget of `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                limit,
+                used: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                subsystems: Default::default(),
+            }),
+        }
+    }
+
+    /// Create a budget without limit, only accounting usage.
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// Get the limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.shared.limit
+    }
+
+    /// Get the number of bytes used.
+    pub fn used(&self) -> usize {
+        self.shared.used.load(Ordering::Relaxed)
+    }
+
+    /// Whether more than the limit is used.
+    pub fn is_over(&self) -> bool {
+        self.used() > self.limit()
+    }
+
+    /// Get the usage to evict down to, 7/8 of the limit.
+    ///
+    /// Subsystems whose eviction is costly evict down to it, so that they
+    /// evict in batches rather than on every addition.
+    pub fn low_watermark(&self) -> usize {
+        self.limit() - self.limit() / 8
+    }
+
+    /// Open an account charging the memory of a subsystem to the budget.
+    pub fn account(&self, subsystem: Subsystem) -> MemoryAccount {
+        MemoryAccount {
+            budget: self.clone(),
+            subsystem,
+            held: 0,
+        }
+    }
+
+    /// Read the usage of the budget.
+    pub fn usage(&self) -> MemoryUsage {
+        let shared = &self.shared;
+        MemoryUsage {
+            limit: shared.limit,
+            used: shared.used.load(Ordering::Relaxed),
+            peak: shared.peak.load(Ordering::Relaxed),
+            subsystems: Subsystem::ALL.map(|subsystem| {
+                let counters = &shared.subsystems[subsystem.index()];
+                SubsystemUsage {
+                    subsystem,
+                    used: counters.used.load(Ordering::Relaxed),
+                    peak: counters.peak.load(Ordering::Relaxed),
+                    evictions: counters.evictions.load(Ordering::Relaxed),
+                    evicted_bytes: counters.evicted_bytes.load(Ordering::Relaxed),
+                }
+            }),
+        }
+    }
+
+    fn counters(&self, subsystem: Subsystem) -> &Counters {
+        &self.shared.subsystems[subsystem.index()]
+    }
+
+    fn add(&self, subsystem: Subsystem, bytes: usize) {
+        let counters = self.counters(subsystem);
+        let used = counters.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        counters.peak.fetch_max(used, Ordering::Relaxed);
+        let used = self.shared.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.shared.peak.fetch_max(used, Ordering::Relaxed);
+    }
+
+    fn sub(&self, subsystem: Subsystem, bytes: usize) {
+        self.counters(subsystem)
+            .used
+            .fetch_sub(bytes, Ordering::Relaxed);
+        self.shared.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Memory of a subsystem charged to a budget
+///
+/// What the account holds is released when it is dropped, and charged
+/// again when it is cloned along with the state it accounts for.
+#[derive(Debug)]
+pub struct MemoryAccount {
+    budget: MemoryBudget,
+    subsystem: Subsystem,
+    held: usize,
+}
+
+impl MemoryAccount {
+    /// Get the budget charged.
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    /// Get the subsystem accounted for.
+    pub fn subsystem(&self) -> Subsystem {
+        self.subsystem
+    }
+
+    /// Get the number of bytes held.
+    pub fn held(&self) -> usize {
+        self.held
+    }
+
+    /// Whether the budget is exceeded, see [`MemoryBudget::is_over`].
+    pub fn is_over(&self) -> bool {
+        self.budget.is_over()
+    }
+
+    /// Charge `bytes` more to the budget.
+    pub fn reserve(&mut self, bytes: usize) {
+        self.held += bytes;
+        self.budget.add(self.subsystem, bytes);
+    }
+
+    /// Give back `bytes`, at most all held.
+    pub fn release(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.held);
+        self.held -= bytes;
+        self.budget.sub(self.subsystem, bytes);
+    }
+
+    /// Reserve or release bytes to hold exactly `bytes`.
+    pub fn set(&mut self, bytes: usize) {
+        if bytes > self.held {
+            self.reserve(bytes - self.held);
+        } else {
+            self.release(self.held - bytes);
+        }
+    }
+
+    /// Give back `bytes` of evicted state, counting an eviction.
+    pub fn evict(&mut self, bytes: usize) {
+        let counters = self.budget.counters(self.subsystem);
+        counters.evictions.fetch_add(1, Ordering::Relaxed);
+        counters
+            .evicted_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.release(bytes);
+    }
+}
+
+impl Clone for MemoryAccount {
+    fn clone(&self) -> Self {
+        let mut account = self.budget.account(self.subsystem);
+        account.reserve(self.held);
+        account
+    }
+}
+
+impl Drop for MemoryAccount {
+    fn drop(&mut self) {
+        self.release(self.held);
+    }
+}
+
+/// Usage of a [`MemoryBudget`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Limit in bytes.
+    pub limit: usize,
+    /// Bytes used.
+    pub used: usize,
+    /// Most bytes used at once.
+    pub peak: usize,
+    /// Usage of each subsystem, in the order of [`Subsystem::ALL`].
+    pub subsystems: [SubsystemUsage; 4],
+}
+
+impl MemoryUsage {
+    /// Get the usage of a subsystem.
+    pub fn get(&self, subsystem: Subsystem) -> &SubsystemUsage {
+        &self.subsystems[subsystem.index()]
+    }
+}
+
+/// Usage of a [`MemoryBudget`] by a subsystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubsystemUsage {
+    /// The subsystem.
+    pub subsystem: Subsystem,
+    /// Bytes used.
+    pub used: usize,
+    /// Most bytes used at once.
+    pub peak: usize,
+    /// Number of evictions.
+    pub evictions: u64,
+    /// Bytes freed by evictions.
+    pub evicted_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_budget() {
+        let budget = MemoryBudget::new(1000);
+        assert_eq!(budget.low_watermark(), 875);
+
+        let mut reassembly = budget.account(Subsystem::Reassembly);
+        reassembly.reserve(600);
+        let mut flows = budget.account(Subsystem::FlowTable);
+        flows.set(300);
+        assert!(!flows.is_over());
+
+        let copy = flows.clone();
+        assert_eq!((budget.used(), copy.held()), (1200, 300));
+        assert!(budget.is_over());
+        drop(copy);
+
+        flows.set(100);
+        reassembly.evict(500);
+        reassembly.release(1000);
+        let usage = budget.usage();
+        assert_eq!((usage.used, usage.peak), (100, 1200));
+        let reassembly = usage.get(Subsystem::Reassembly);
+        assert_eq!((reassembly.used, reassembly.peak), (0, 600));
+        assert_eq!((reassembly.evictions, reassembly.evicted_bytes), (1, 500));
+        assert_eq!(usage.get(Subsystem::FlowTable).peak, 600);
+
+        drop(flows);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(Subsystem::FlowTable.to_string(), "flow-table");
+    }
+}
@@ -0,0 +1,457 @@
+//! Python bindings of netkit.
+//!
+//! The `netkit` Python module exposes the pcap reader and writer, the
+//! dissector, a flow table and the table-producing analyzers. Tables are
+//! returned as dicts of column lists, which `pandas.DataFrame` takes as is:
+//!
+//! ```python
+//! import netkit
+//! import pandas as pd
+//!
+//! tables = netkit.analyze("trace.pcap", ["tcp", "top-talkers"])
+//! tcp = pd.DataFrame(tables["tcp"])
+//!
+//! flows = netkit.FlowTable(bidirectional=True)
+//! for ts, frame in netkit.PcapReader("trace.pcap"):
+//!     flows.add(ts, frame)
+//! flows = pd.DataFrame(flows.to_dict())
+//! ```
+//!
+//! Timestamps are seconds since the epoch, as floats.
+
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::PathBuf,
+    time::Duration,
+};
+
+use netkit::{
+    analysis::{analyze_pcap, Analyzer},
+    capture::file::pcap::{PcapReader, PcapWriter, PcapWriterBuilder},
+    config::{AnalysisConfig, AnalyzerKind, TableAnalyzer},
+    export::{Column, Table},
+    packet::{
+        dissect::DissectorRegistry,
+        flow::{table::FlowTable, FlowKey},
+        layer::eth::Eth,
+    },
+};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyDict, PyList, PyTuple},
+};
+use serde::{de::value::StrDeserializer, Deserialize};
+
+/// Reader of a pcap file, iterating over `(timestamp, data)` pairs.
+#[pyclass(name = "PcapReader", module = "netkit")]
+struct PyPcapReader {
+    reader: PcapReader<BufReader<File>>,
+}
+
+#[pymethods]
+impl PyPcapReader {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let reader = PcapReader::new(BufReader::new(File::open(path)?));
+        Ok(Self { reader })
+    }
+
+    /// Link type of the packets, e.g. 1 for Ethernet.
+    #[getter]
+    fn link_type(&self) -> u32 {
+        self.reader.link_type().into()
+    }
+
+    /// Whether timestamps have nanosecond resolution.
+    #[getter]
+    fn nanosecond(&self) -> bool {
+        self.reader.is_nanosecond()
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> Option<(f64, Bound<'py, PyBytes>)> {
+        let (header, data) = self.reader.next_packet()?;
+        let ts = self.reader.timestamp(&header).as_secs_f64();
+        Some((ts, PyBytes::new(py, &data)))
+    }
+}
+
+/// Writer of a pcap file, also usable as a context manager.
+#[pyclass(name = "PcapWriter", module = "netkit")]
+struct PyPcapWriter {
+    writer: Option<PcapWriter<File>>,
+}
+
+#[pymethods]
+impl PyPcapWriter {
+    #[new]
+    #[pyo3(signature = (path, link_type = 1, nanosecond = false))]
+    fn new(path: PathBuf, link_type: u32, nanosecond: bool) -> PyResult<Self> {
+        let writer = PcapWriterBuilder::new()
+            .network(link_type)
+            .nanosecond(nanosecond)
+            .build(File::create(path)?)?;
+        Ok(Self {
+            writer: Some(writer),
+        })
+    }
+
+    /// Write a packet captured at `ts`.
+    fn write(&mut self, ts: f64, data: &[u8]) -> PyResult<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("write to a closed PcapWriter"))?;
+        Ok(writer.write_packet(duration(ts)?, data)?)
+    }
+
+    /// Flush and close the file; further writes fail.
+    fn close(&mut self) -> PyResult<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.into_inner()?;
+        }
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, _args: &Bound<'_, PyTuple>) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+}
+
+/// Dissector of Ethernet frames, with the built-in dissectors.
+#[pyclass(name = "Dissector", module = "netkit")]
+struct PyDissector {
+    registry: DissectorRegistry,
+}
+
+#[pymethods]
+impl PyDissector {
+    #[new]
+    fn new() -> Self {
+        Self {
+            registry: DissectorRegistry::with_builtins(),
+        }
+    }
+
+    /// Dissect a frame into `(layer, [(field, value)])` pairs, outermost
+    /// layer first.
+    fn dissect(&self, frame: &[u8]) -> Vec<(&'static str, Vec<(&'static str, String)>)> {
+        self.registry
+            .dissect_eth(frame)
+            .layers
+            .iter()
+            .map(|layer| (layer.name(), layer.fields()))
+            .collect()
+    }
+
+    /// Get the values of a field in a frame, named as in Wireshark (e.g.
+    /// `ip.src`) or as `Layer.field` (e.g. `Ipv4.src`).
+    fn field(&self, frame: &[u8], name: &str) -> Vec<String> {
+        self.registry.dissect_eth(frame).field(name)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FlowStats {
+    packets: u64,
+    bytes: u64,
+    first: Duration,
+    last: Duration,
+}
+
+/// Table of the packet and byte counts of flows.
+#[pyclass(name = "FlowTable", module = "netkit")]
+struct PyFlowTable {
+    table: FlowTable<FlowStats>,
+    bidirectional: bool,
+}
+
+#[pymethods]
+impl PyFlowTable {
+    /// Count both directions of a conversation as one flow if
+    /// `bidirectional`.
+    #[new]
+    #[pyo3(signature = (bidirectional = false))]
+    fn new(bidirectional: bool) -> Self {
+        Self {
+            table: FlowTable::new(),
+            bidirectional,
+        }
+    }
+
+    /// Count an Ethernet frame captured at `ts`, returning whether it
+    /// belongs to a flow.
+    fn add(&mut self, ts: f64, frame: &[u8]) -> PyResult<bool> {
+        let ts = duration(ts)?;
+        let Some(mut key) = Eth::new(frame).ok().and_then(|eth| FlowKey::from_eth(&eth)) else {
+            return Ok(false);
+        };
+        if self.bidirectional {
+            key = key.canonical();
+        }
+        let stats = self.table.entry(key).or_insert(FlowStats {
+            packets: 0,
+            bytes: 0,
+            first: ts,
+            last: ts,
+        });
+        stats.packets += 1;
+        stats.bytes += frame.len() as u64;
+        stats.first = stats.first.min(ts);
+        stats.last = stats.last.max(ts);
+        Ok(true)
+    }
+
+    fn __len__(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Get the flows as a dict of columns, ordered by first packet.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let mut flows: Vec<_> = self.table.iter().collect();
+        flows.sort_by_key(|(k, s)| (s.first, k.src, k.dst, k.src_port, k.dst_port));
+
+        let mut table = Table::new();
+        table
+            .push(
+                "src",
+                Column::Str(flows.iter().map(|(k, _)| k.src.to_string()).collect()),
+            )
+            .push(
+                "dst",
+                Column::Str(flows.iter().map(|(k, _)| k.dst.to_string()).collect()),
+            )
+            .push(
+                "src_port",
+                Column::U64(flows.iter().map(|(k, _)| k.src_port.into()).collect()),
+            )
+            .push(
+                "dst_port",
+                Column::U64(flows.iter().map(|(k, _)| k.dst_port.into()).collect()),
+            )
+            .push(
+                "protocol",
+                Column::U64(
+                    flows
+                        .iter()
+                        .map(|(k, _)| u8::from(k.protocol).into())
+                        .collect(),
+                ),
+            )
+            .push(
+                "packets",
+                Column::U64(flows.iter().map(|(_, s)| s.packets).collect()),
+            )
+            .push(
+                "bytes",
+                Column::U64(flows.iter().map(|(_, s)| s.bytes).collect()),
+            )
+            .push(
+                "first",
+                Column::F64(flows.iter().map(|(_, s)| s.first.as_secs_f64()).collect()),
+            )
+            .push(
+                "last",
+                Column::F64(flows.iter().map(|(_, s)| s.last.as_secs_f64()).collect()),
+            );
+        table_to_dict(py, &table)
+    }
+}
+
+/// Feed packets to several analyzers.
+struct Fanout(Vec<Box<dyn TableAnalyzer>>);
+
+impl Analyzer for Fanout {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        for analyzer in &mut self.0 {
+            analyzer.on_packet(ts, frame);
+        }
+    }
+}
+
+/// Run analyzers over a pcap file and get their tables, keyed by analyzer
+/// name (e.g. `tcp`, `top-talkers`).
+///
+/// `config` is an analysis configuration in TOML, for the flow table and
+/// "Decode As" settings; its own list of analyzers is ignored.
+#[pyfunction]
+#[pyo3(signature = (path, analyzers, config = None))]
+fn analyze<'py>(
+    py: Python<'py>,
+    path: PathBuf,
+    analyzers: Vec<String>,
+    config: Option<&str>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let mut config = match config {
+        Some(config) => AnalysisConfig::from_toml(config)
+            .map_err(|error| PyValueError::new_err(error.to_string()))?,
+        None => AnalysisConfig::default(),
+    };
+    config.analyzers = analyzers
+        .iter()
+        .map(|name| {
+            AnalyzerKind::deserialize(StrDeserializer::<serde::de::value::Error>::new(name))
+                .map_err(|_| PyValueError::new_err(format!("unknown analyzer: {name}")))
+        })
+        .collect::<PyResult<_>>()?;
+
+    // Analysis does not touch Python objects, so other threads may run.
+    let tables = py.allow_threads(move || -> io::Result<Vec<Table>> {
+        let mut reader = PcapReader::new(BufReader::new(File::open(path)?));
+        let mut fanout = Fanout(
+            config
+                .build_analyzers()
+                .into_iter()
+                .map(|(_, analyzer)| analyzer)
+                .collect(),
+        );
+        analyze_pcap(&mut reader, &mut fanout);
+        Ok(fanout
+            .0
+            .iter()
+            .map(|analyzer| analyzer.to_table())
+            .collect())
+    })?;
+
+    let dict = PyDict::new(py);
+    for (name, table) in analyzers.iter().zip(&tables) {
+        dict.set_item(name, table_to_dict(py, table)?)?;
+    }
+    Ok(dict)
+}
+
+/// Convert a table into a dict of column lists.
+fn table_to_dict<'py>(py: Python<'py>, table: &Table) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (name, column) in table.columns() {
+        let values = match column {
+            Column::U64(values) => PyList::new(py, values)?,
+            Column::F64(values) => PyList::new(py, values)?,
+            Column::Str(values) => PyList::new(py, values)?,
+        };
+        dict.set_item(name, values)?;
+    }
+    Ok(dict)
+}
+
+fn duration(ts: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(ts).map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+#[pymodule]
+#[pyo3(name = "netkit")]
+fn netkit_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPcapReader>()?;
+    module.add_class::<PyPcapWriter>()?;
+    module.add_class::<PyDissector>()?;
+    module.add_class::<PyFlowTable>()?;
+    module.add_function(wrap_pyfunction!(analyze, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit::packet::{eth, ipv4, prelude::*, udp};
+
+    use super::*;
+
+    fn frame(src: Ipv4Addr, dst_port: u16) -> Vec<u8> {
+        let udp = udp!(src_port: 40000u16, dst_port: dst_port, payload: b"query");
+        let ipv4 = ipv4!(
+            protocol: IpProtocol::Udp,
+            src: src,
+            dst: Ipv4Addr::new(192, 0, 2, 53),
+            payload: udp.inner(),
+        );
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn python_bindings() {
+        pyo3::prepare_freethreaded_python();
+        let path = std::env::temp_dir().join(format!("netkit-py-{}.pcap", std::process::id()));
+        let frames = [
+            frame(Ipv4Addr::new(192, 0, 2, 1), 53),
+            frame(Ipv4Addr::new(192, 0, 2, 2), 53),
+            frame(Ipv4Addr::new(192, 0, 2, 1), 53),
+        ];
+
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "netkit").unwrap();
+            netkit_py(&module).unwrap();
+            for name in [
+                "PcapReader",
+                "PcapWriter",
+                "Dissector",
+                "FlowTable",
+                "analyze",
+            ] {
+                assert!(module.hasattr(name).unwrap(), "{name}");
+            }
+
+            let mut writer = PyPcapWriter::new(path.clone(), 1, true).unwrap();
+            for (i, frame) in frames.iter().enumerate() {
+                writer.write(1.5 + i as f64, frame).unwrap();
+            }
+            assert!(writer.write(-1.0, &frames[0]).is_err());
+            writer.close().unwrap();
+            assert!(writer.write(5.0, &frames[0]).is_err());
+
+            let mut reader = PyPcapReader::new(path.clone()).unwrap();
+            assert_eq!(reader.link_type(), 1);
+            assert!(reader.nanosecond());
+            let mut flows = PyFlowTable::new(false);
+            let mut read = 0;
+            while let Some((ts, data)) = reader.__next__(py) {
+                assert_eq!(ts, 1.5 + read as f64);
+                assert_eq!(data.as_bytes(), frames[read]);
+                assert!(flows.add(ts, data.as_bytes()).unwrap());
+                read += 1;
+            }
+            assert_eq!(read, frames.len());
+            assert!(!flows.add(0.0, &[0; 4]).unwrap());
+            assert_eq!(flows.__len__(), 2);
+            let dict = flows.to_dict(py).unwrap();
+            let packets: Vec<u64> = dict
+                .get_item("packets")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(packets, [2, 1]);
+            let src: Vec<String> = dict.get_item("src").unwrap().unwrap().extract().unwrap();
+            assert_eq!(src, ["192.0.2.1", "192.0.2.2"]);
+
+            let dissector = PyDissector::new();
+            let layers: Vec<_> = dissector
+                .dissect(&frames[0])
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            assert_eq!(layers, ["Eth", "Ipv4", "Udp"]);
+            assert_eq!(dissector.field(&frames[1], "ip.src"), ["192.0.2.2"]);
+
+            let tables = analyze(py, path.clone(), vec!["top-talkers".into()], None).unwrap();
+            assert!(tables.get_item("top-talkers").unwrap().is_some());
+            assert!(analyze(py, path.clone(), vec!["nope".into()], None).is_err());
+            assert!(analyze(py, path.clone(), vec![], Some("analyzers = 1")).is_err());
+        });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
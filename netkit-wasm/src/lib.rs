@@ -0,0 +1,182 @@
+//! WebAssembly bindings of netkit, for inspecting captures in the browser.
+//!
+//! A [`Capture`] parses a pcap or pcapng file uploaded by the user, entirely
+//! client-side, and dissects its packets on demand:
+//!
+//! ```js
+//! import init, { Capture } from "netkit-wasm";
+//!
+//! await init();
+//! const capture = new Capture(new Uint8Array(await file.arrayBuffer()));
+//! for (let i = 0; i < capture.length; i++) {
+//!   const date = new Date(capture.timestamp(i));
+//!   console.log(date, capture.dissect(i));
+//! }
+//! ```
+//!
+//! Build with `wasm-pack build netkit-wasm`.
+
+use std::{io::Cursor, time::Duration};
+
+use js_sys::{Array, Object, Reflect};
+use netkit_capture::file::{pcap::PcapReader, pcapng::PcapngReader};
+use netkit_packet::{
+    dissect::DissectorRegistry,
+    layer::link::{self, LinkType},
+};
+use wasm_bindgen::prelude::*;
+
+/// Block type of a pcapng Section Header Block, starting pcapng files.
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+struct Record {
+    ts: Duration,
+    link_type: LinkType,
+    orig_len: u32,
+    data: Vec<u8>,
+}
+
+/// Packets of a pcap or pcapng file.
+#[wasm_bindgen]
+pub struct Capture {
+    records: Vec<Record>,
+    registry: DissectorRegistry,
+}
+
+#[wasm_bindgen]
+impl Capture {
+    /// Parse a pcap or pcapng file.
+    #[wasm_bindgen(constructor)]
+    pub fn new(file: &[u8]) -> Result<Capture, JsError> {
+        let records = if file.starts_with(&PCAPNG_MAGIC) {
+            read_pcapng(file)?
+        } else {
+            read_pcap(file)?
+        };
+        Ok(Self {
+            records,
+            registry: DissectorRegistry::with_builtins(),
+        })
+    }
+
+    /// Number of packets.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Capture time of a packet, in milliseconds since the epoch as taken by
+    /// `Date`.
+    pub fn timestamp(&self, index: usize) -> Option<f64> {
+        let record = self.records.get(index)?;
+        Some(record.ts.as_secs_f64() * 1000.0)
+    }
+
+    /// Link type of a packet, e.g. 1 for Ethernet.
+    #[wasm_bindgen(js_name = linkType)]
+    pub fn link_type(&self, index: usize) -> Option<u32> {
+        Some(self.records.get(index)?.link_type.into())
+    }
+
+    /// Length of a packet on the wire, which may exceed its captured data.
+    #[wasm_bindgen(js_name = originalLength)]
+    pub fn original_length(&self, index: usize) -> Option<u32> {
+        Some(self.records.get(index)?.orig_len)
+    }
+
+    /// Captured data of a packet, as a `Uint8Array`.
+    pub fn data(&self, index: usize) -> Option<Vec<u8>> {
+        Some(self.records.get(index)?.data.clone())
+    }
+
+    /// Dissect a packet into an array of `{ name, fields }` layers,
+    /// outermost first, where `fields` maps field names to values.
+    ///
+    /// Packets of unsupported link types have no layers.
+    pub fn dissect(&self, index: usize) -> Option<Array> {
+        let record = self.records.get(index)?;
+        let layers = Array::new();
+        let Some(frame) = link::to_eth(record.link_type, &record.data) else {
+            return Some(layers);
+        };
+        for layer in self.registry.dissect_eth(&frame).layers {
+            let fields = Object::new();
+            for (name, value) in layer.fields() {
+                set(&fields, name, value.into());
+            }
+            let object = Object::new();
+            set(&object, "name", layer.name().into());
+            set(&object, "fields", fields.into());
+            layers.push(&object);
+        }
+        Some(layers)
+    }
+}
+
+fn read_pcap(file: &[u8]) -> Result<Vec<Record>, JsError> {
+    let mut reader = PcapReader::try_new(Cursor::new(file))?;
+    let link_type = reader.link_type();
+    let mut records = Vec::new();
+    while let Some((header, data)) = reader.next_packet() {
+        records.push(Record {
+            ts: reader.timestamp(&header),
+            link_type,
+            orig_len: header.orig_len,
+            data,
+        });
+    }
+    Ok(records)
+}
+
+fn read_pcapng(file: &[u8]) -> Result<Vec<Record>, JsError> {
+    let mut reader = PcapngReader::new(Cursor::new(file))?;
+    let mut records = Vec::new();
+    while let Some(packet) = reader.next_packet()? {
+        let link_type = reader
+            .interfaces()
+            .get(packet.interface as usize)
+            .map_or(LinkType::Ethernet, |interface| {
+                LinkType::from(u32::from(interface.link_type))
+            });
+        records.push(Record {
+            ts: packet.ts,
+            link_type,
+            orig_len: packet.orig_len,
+            data: packet.data,
+        });
+    }
+    Ok(records)
+}
+
+/// Set a property of a plain object, which cannot fail.
+fn set(object: &Object, key: &str, value: JsValue) {
+    let _ = Reflect::set(object, &key.into(), &value);
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_capture::file::{pcap::PcapWriter, pcapng::PcapngWriter};
+
+    use super::*;
+
+    #[test]
+    fn capture_formats() {
+        let mut pcap = PcapWriter::new(Vec::new()).unwrap();
+        let mut pcapng = PcapngWriter::new(Vec::new()).unwrap();
+        for i in 0..3u8 {
+            let ts = Duration::from_millis(1500 * i as u64);
+            pcap.write_packet(ts, &[i; 60]).unwrap();
+            pcapng.write_packet(ts, &[i; 60]).unwrap();
+        }
+
+        for file in [pcap.into_inner().unwrap(), pcapng.into_inner().unwrap()] {
+            let capture = Capture::new(&file).unwrap_or_else(|_| panic!("invalid capture"));
+            assert_eq!(capture.length(), 3);
+            assert_eq!(capture.timestamp(2), Some(3000.0));
+            assert_eq!(capture.link_type(1), Some(1));
+            assert_eq!(capture.original_length(1), Some(60));
+            assert_eq!(capture.data(1), Some(vec![1; 60]));
+            assert_eq!(capture.data(3), None);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Analyzers computing reports over captured traffic.
+
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash},
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use netkit_capture::{
+    channel::{ChannelStats, Receiver},
+    file::pcap::PcapReader,
+    shutdown::ShutdownToken,
+};
+use netkit_packet::layer::link::{self, LinkType};
+use profile::{timed, ProfileBatch, Profiler, Stage};
+
+pub mod alert;
+pub mod app;
+pub mod audit;
+pub mod beacon;
+pub mod checkpoint;
+pub mod collector;
+pub mod credentials;
+pub mod ecn;
+pub mod entropy;
+pub mod fit;
+pub mod frame_size;
+pub mod gre;
+pub mod happy_eyeballs;
+pub mod http;
+pub mod iec61850;
+pub mod igmp;
+pub mod l2;
+pub mod mail;
+pub mod multilink;
+pub mod nat;
+pub mod pmtu;
+pub mod profile;
+pub mod quic;
+pub mod replay;
+pub mod rtp;
+pub mod sampling;
+pub mod snapshot;
+pub mod storm;
+pub mod stream;
+pub mod tcp;
+pub mod tls;
+pub mod traceroute;
+pub mod websocket;
+pub mod wireguard;
+
+/// Packet analyzer
+///
+/// Analyzers are fed Ethernet frames in capture order together with their
+/// capture timestamp (since the epoch).
+pub trait Analyzer {
+    /// Process a captured frame.
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]);
+}
+
+impl<A: Analyzer + ?Sized> Analyzer for Box<A> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        (**self).on_packet(ts, frame);
+    }
+}
+
+/// Log the flow table of analyzer `A` each time inserting `key` grows it to
+/// a power of two, to observe flow-table pressure.
+///
+/// Tables using [`FlowTable`](netkit_packet::flow::table::FlowTable) log
+/// their growth themselves.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[inline]
+pub(crate) fn trace_flow_growth<A: ?Sized, K: Eq + Hash>(
+    flows: &HashMap<K, impl Sized, impl BuildHasher>,
+    key: &K,
+) {
+    #[cfg(feature = "tracing")]
+    if (flows.len() + 1).is_power_of_two() && !flows.contains_key(key) {
+        tracing::debug!(
+            analyzer = std::any::type_name::<A>(),
+            flows = flows.len() + 1,
+            "flow table grew"
+        );
+    }
+}
+
+/// Feed every packet of a pcap file to the analyzer.
+///
+/// Packets of other link types than Ethernet are converted to Ethernet
+/// frames (see [`link::to_eth`]); packets of unsupported link types are
+/// skipped.
+pub fn analyze_pcap<R, A>(reader: &mut PcapReader<R>, analyzer: &mut A)
+where
+    R: Read,
+    A: Analyzer + ?Sized,
+{
+    analyze_pcap_with(reader, analyzer, |_, _, _| true);
+}
+
+/// Feed the packets of a pcap file accepted by `keep` to the analyzer.
+///
+/// `keep` is given the timestamp, link type and data of each packet before
+/// its conversion to Ethernet, e.g. to drop the copies of packets captured
+/// twice with a [`Deduplicator`](netkit_capture::dedup::Deduplicator):
+///
+/// ```no_run
+/// # use std::fs::File;
+/// # use netkit::analysis::{analyze_pcap_with, tcp::TcpFlowAnalyzer};
+/// # use netkit::capture::{dedup::Deduplicator, file::pcap::PcapReader};
+/// let mut reader = PcapReader::new(File::open("any.pcap").unwrap());
+/// let mut analyzer = TcpFlowAnalyzer::new();
+/// let mut dedup = Deduplicator::new();
+/// analyze_pcap_with(&mut reader, &mut analyzer, |ts, link_type, data| {
+///     dedup.check(ts, link_type, data)
+/// });
+/// ```
+pub fn analyze_pcap_with<R, A, F>(reader: &mut PcapReader<R>, analyzer: &mut A, keep: F)
+where
+    R: Read,
+    A: Analyzer + ?Sized,
+    F: FnMut(Duration, LinkType, &[u8]) -> bool,
+{
+    analyze_pcap_inner(reader, analyzer, keep, None);
+}
+
+/// Feed the packets of a pcap file accepted by `keep` to the analyzer,
+/// timing the reading, filtering by `keep` and conversion to Ethernet with
+/// `profiler`.
+///
+/// Wrap the analyzers in [`Profiled`](profile::Profiled) to time them as
+/// well.
+pub fn analyze_pcap_profiled<R, A, F>(
+    reader: &mut PcapReader<R>,
+    analyzer: &mut A,
+    keep: F,
+    profiler: &Profiler,
+) where
+    R: Read,
+    A: Analyzer + ?Sized,
+    F: FnMut(Duration, LinkType, &[u8]) -> bool,
+{
+    analyze_pcap_inner(reader, analyzer, keep, Some(profiler.batch()));
+}
+
+fn analyze_pcap_inner<R, A, F>(
+    reader: &mut PcapReader<R>,
+    analyzer: &mut A,
+    mut keep: F,
+    mut batch: Option<ProfileBatch>,
+) where
+    R: Read,
+    A: Analyzer + ?Sized,
+    F: FnMut(Duration, LinkType, &[u8]) -> bool,
+{
+    let link_type = reader.link_type();
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!(
+        "analyze_pcap",
+        analyzer = std::any::type_name::<A>(),
+        ?link_type
+    )
+    .entered();
+    let (start, mut packets, mut skipped) = (Instant::now(), 0u64, 0u64);
+
+    while let Some((header, data)) = timed(&mut batch, Stage::Read, || reader.next_packet()) {
+        let ts = reader.timestamp(&header);
+        packets += 1;
+        if !timed(&mut batch, Stage::Filter, || keep(ts, link_type, &data)) {
+            skipped += 1;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(?ts, "packet filtered out");
+        } else if link_type == LinkType::Ethernet {
+            analyzer.on_packet(ts, &data);
+        } else if let Some(frame) = timed(&mut batch, Stage::Dissect, || {
+            link::to_eth(link_type, &data)
+        }) {
+            analyzer.on_packet(ts, &frame);
+        } else {
+            skipped += 1;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(?ts, "packet not convertible to Ethernet");
+        }
+        if let Some(batch) = &mut batch {
+            batch.end_packet();
+        }
+    }
+
+    let elapsed = start.elapsed();
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        packets,
+        skipped,
+        ?elapsed,
+        pps = packets as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        "analyzed pcap"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (packets, skipped, elapsed);
+}
+
+/// Feed every packet received from a channel to the analyzer, until every
+/// sender is gone.
+///
+/// Packets are pairs of a timestamp and an Ethernet frame, e.g. sent by a
+/// capture thread through a bounded
+/// [`channel`](netkit_capture::channel::channel) whose policy chooses
+/// between stalling capture and dropping packets when analysis falls behind.
+pub fn analyze_channel<T, A>(receiver: &Receiver<(Duration, T)>, analyzer: &mut A)
+where
+    T: AsRef<[u8]>,
+    A: Analyzer + ?Sized,
+{
+    for (ts, frame) in receiver.iter() {
+        analyzer.on_packet(ts, frame.as_ref());
+    }
+
+    #[cfg(feature = "tracing")]
+    {
+        let stats = receiver.stats();
+        tracing::info!(
+            analyzer = std::any::type_name::<A>(),
+            received = stats.received,
+            dropped = stats.dropped,
+            blocked = stats.blocked,
+            high_watermark = stats.high_watermark,
+            "analyzed channel"
+        );
+    }
+}
+
+/// Feed the packets received from a channel to the analyzer until every
+/// sender is gone or the token is cancelled, returning the final counters of
+/// the channel.
+///
+/// Packets queued before the cancellation are still analyzed.
+pub fn analyze_channel_until<T, A>(
+    receiver: &Receiver<(Duration, T)>,
+    analyzer: &mut A,
+    token: &ShutdownToken,
+) -> ChannelStats
+where
+    T: AsRef<[u8]>,
+    A: Analyzer + ?Sized,
+{
+    while let Some((ts, frame)) = receiver.recv_until(token) {
+        analyzer.on_packet(ts, frame.as_ref());
+    }
+
+    let stats = receiver.stats();
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        analyzer = std::any::type_name::<A>(),
+        received = stats.received,
+        dropped = stats.dropped,
+        cancelled = token.is_cancelled(),
+        "analyzed channel until shutdown"
+    );
+    stats
+}
@@ -0,0 +1,103 @@
+//! Scored alerts raised by detectors.
+//!
+//! Detectors, such as the [DNS tunneling
+//! detector](crate::stats::dns::tunnel::DnsTunnelDetector), report
+//! suspicious traffic as [`Alert`]s with a score between 0 and 1, and hand
+//! them out through [`AlertSource`], so that alerts of several detectors can
+//! be collected the same way. [`sink`]s route them out of the process, e.g.
+//! to a file, syslog or a webhook. An alert stamped with the [`PacketId`] of
+//! the packet raising it is traced back to that packet in other outputs.
+
+use std::{fmt::Write as _, net::IpAddr, time::Duration};
+
+use netkit_packet::utils::id::PacketId;
+
+use crate::export::{bundle::json_string, Column, Table};
+
+pub mod sink;
+
+/// A suspicious pattern reported by a detector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    /// Timestamp of the packet raising the alert.
+    pub ts: Duration,
+    /// Name of the detector.
+    pub detector: &'static str,
+    /// Score between 0 (benign) and 1 (certainly suspicious).
+    pub score: f64,
+    /// Source address of the traffic.
+    pub src: IpAddr,
+    /// Destination address of the traffic.
+    pub dst: IpAddr,
+    /// What the alert is about, e.g. a domain.
+    pub subject: String,
+    /// Human-readable description.
+    pub message: String,
+    /// Identifier of the packet raising the alert, if numbered.
+    pub packet: Option<PacketId>,
+}
+
+impl Alert {
+    /// Set the identifier of the packet raising the alert, e.g. of the
+    /// packet just fed to the detector.
+    pub fn with_packet(mut self, packet: PacketId) -> Self {
+        self.packet = Some(packet);
+        self
+    }
+
+    /// Serialize as a single-line JSON object, with a `packet_id` if the
+    /// packet is numbered.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"ts\":{},", self.ts.as_secs_f64());
+        let _ = write!(
+            json,
+            "\"detector\":{},\"score\":{},\"src\":\"{}\",\"dst\":\"{}\",\"subject\":{},\"message\":{}}}",
+            json_string(self.detector),
+            if self.score.is_finite() { self.score } else { 0.0 },
+            self.src,
+            self.dst,
+            json_string(&self.subject),
+            json_string(&self.message),
+        );
+        if let Some(packet) = self.packet {
+            json.pop();
+            let _ = write!(json, ",\"packet_id\":\"{packet}\"}}");
+        }
+        json
+    }
+}
+
+/// Detector reporting [`Alert`]s.
+pub trait AlertSource {
+    /// Get the alerts raised so far.
+    fn alerts(&self) -> &[Alert];
+
+    /// Take the alerts raised so far.
+    fn take_alerts(&mut self) -> Vec<Alert>;
+}
+
+/// Convert alerts into a table with one row per alert.
+pub fn alerts_table(alerts: &[Alert]) -> Table {
+    let strs = |f: fn(&Alert) -> String| Column::Str(alerts.iter().map(f).collect());
+
+    let mut table = Table::new();
+    table
+        .push(
+            "ts",
+            Column::F64(alerts.iter().map(|a| a.ts.as_secs_f64()).collect()),
+        )
+        .push("detector", strs(|a| a.detector.to_string()))
+        .push(
+            "score",
+            Column::F64(alerts.iter().map(|a| a.score).collect()),
+        )
+        .push("src", strs(|a| a.src.to_string()))
+        .push("dst", strs(|a| a.dst.to_string()))
+        .push("subject", strs(|a| a.subject.clone()))
+        .push("message", strs(|a| a.message.clone()))
+        .push(
+            "packet_id",
+            strs(|a| a.packet.map(|p| p.to_string()).unwrap_or_default()),
+        );
+    table
+}
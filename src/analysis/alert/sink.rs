@@ -0,0 +1,448 @@
+//! Destinations of alerts.
+//!
+//! An [`AlertSink`] takes the alerts of any [`AlertSource`] with
+//! [`forward`], so findings are routed without integration code:
+//!
+//! - [`JsonSink`] appends one JSON object per line to a file or writer
+//! - [`SyslogSink`] sends RFC 5424 messages to a syslog server
+//! - [`WebhookSink`] posts JSON objects over HTTP, retrying failed requests
+//!
+//! ```no_run
+//! use netkit::analysis::{
+//!     alert::sink::{forward, AlertSink, JsonSink, SyslogSink},
+//!     beacon::BeaconDetector,
+//! };
+//!
+//! let mut detector = BeaconDetector::new();
+//! // Feed packets, then:
+//! let mut sinks: Vec<Box<dyn AlertSink>> = vec![
+//!     Box::new(JsonSink::create("alerts.jsonl").unwrap()),
+//!     Box::new(SyslogSink::udp("127.0.0.1:514").unwrap()),
+//! ];
+//! forward(&mut detector, &mut sinks).unwrap();
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::{ToSocketAddrs, UdpSocket},
+    path::Path,
+    time::Duration,
+};
+
+use netkit_capture::http::{self, Client};
+
+use super::{Alert, AlertSource};
+use crate::export::bundle::rfc3339;
+
+/// Default number of retries of a failed webhook request.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default delay before the first retry, doubled at every retry.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Default timeout of webhook connections, reads and writes.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default syslog facility (local0).
+pub const DEFAULT_FACILITY: u8 = 16;
+
+/// Destination of alerts.
+pub trait AlertSink {
+    /// Send an alert.
+    fn send(&mut self, alert: &Alert) -> io::Result<()>;
+
+    /// Flush alerts buffered by the sink.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: AlertSink + ?Sized> AlertSink for Box<S> {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        (**self).send(alert)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Collect alerts in memory.
+impl AlertSink for Vec<Alert> {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        self.push(alert.clone());
+        Ok(())
+    }
+}
+
+/// Send every alert to all sinks.
+///
+/// All sinks are tried; the first error is returned.
+impl<S: AlertSink> AlertSink for [S] {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        self.iter_mut()
+            .map(|sink| sink.send(alert))
+            .fold(Ok(()), Result::and)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.iter_mut()
+            .map(|sink| sink.flush())
+            .fold(Ok(()), Result::and)
+    }
+}
+
+impl<S: AlertSink + ?Sized> AlertSink for Vec<Box<S>> {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        self.as_mut_slice().send(alert)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.as_mut_slice().flush()
+    }
+}
+
+/// Take the alerts of a source and send them to a sink, then flush it.
+///
+/// Returns the number of alerts taken. Alerts are not taken back if the
+/// sink fails.
+pub fn forward<A, S>(source: &mut A, sink: &mut S) -> io::Result<usize>
+where
+    A: AlertSource + ?Sized,
+    S: AlertSink + ?Sized,
+{
+    let alerts = source.take_alerts();
+    let result = alerts
+        .iter()
+        .map(|alert| sink.send(alert))
+        .fold(Ok(()), Result::and);
+    result.and(sink.flush())?;
+    Ok(alerts.len())
+}
+
+/// Sink writing alerts as JSON lines.
+#[derive(Debug)]
+pub struct JsonSink<W: Write> {
+    writer: W,
+}
+
+impl JsonSink<BufWriter<File>> {
+    /// Append alerts to a file, creating it if needed.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Write alerts to a writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Get the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> AlertSink for JsonSink<W> {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        writeln!(self.writer, "{}", alert.to_json())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug)]
+enum SyslogTransport {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+/// Sink sending alerts as RFC 5424 syslog messages.
+///
+/// The severity follows the score: critical from 0.9, warning from 0.5,
+/// notice below.
+#[derive(Debug)]
+pub struct SyslogSink {
+    transport: SyslogTransport,
+    facility: u8,
+    app_name: String,
+}
+
+impl SyslogSink {
+    /// Send to a syslog server over UDP.
+    pub fn udp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let bind = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(addr)?;
+        Ok(Self::with_transport(SyslogTransport::Udp(socket)))
+    }
+
+    /// Send to the local syslog daemon, e.g. at `/dev/log`.
+    #[cfg(unix)]
+    pub fn unix(path: impl AsRef<Path>) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self::with_transport(SyslogTransport::Unix(socket)))
+    }
+
+    fn with_transport(transport: SyslogTransport) -> Self {
+        Self {
+            transport,
+            facility: DEFAULT_FACILITY,
+            app_name: "netkit".to_string(),
+        }
+    }
+
+    /// Set the facility, from 0 (kernel) to 23 (local7).
+    pub fn facility(&mut self, facility: u8) -> &mut Self {
+        self.facility = facility.min(23);
+        self
+    }
+
+    /// Set the application name of the messages.
+    pub fn app_name(&mut self, app_name: impl Into<String>) -> &mut Self {
+        self.app_name = app_name.into();
+        self
+    }
+
+    /// Format an alert as a syslog message, ending with the identifier of
+    /// the packet if numbered.
+    pub fn message(&self, alert: &Alert) -> String {
+        let severity = match alert.score {
+            s if s >= 0.9 => 2,
+            s if s >= 0.5 => 4,
+            _ => 5,
+        };
+        let packet = match alert.packet {
+            Some(packet) => format!(", packet {packet}"),
+            None => String::new(),
+        };
+        format!(
+            "<{}>1 {} - {} - {} - {}: {} (score {:.2}, {} -> {}{packet})",
+            self.facility as u16 * 8 + severity,
+            rfc3339(alert.ts),
+            self.app_name,
+            alert.detector,
+            alert.subject,
+            alert.message,
+            alert.score,
+            alert.src,
+            alert.dst,
+        )
+    }
+}
+
+impl AlertSink for SyslogSink {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        let message = self.message(alert);
+        match &self.transport {
+            SyslogTransport::Udp(socket) => socket.send(message.as_bytes())?,
+            #[cfg(unix)]
+            SyslogTransport::Unix(socket) => socket.send(message.as_bytes())?,
+        };
+        Ok(())
+    }
+}
+
+/// Sink posting alerts as JSON objects over HTTP.
+///
+/// Connection failures and server errors are retried with exponential
+/// backoff; rejected requests are not. The client speaks plain HTTP/1.1;
+/// put a TLS proxy in front of remote endpoints.
+#[derive(Debug)]
+pub struct WebhookSink {
+    client: Client,
+    path: String,
+    headers: Vec<(String, String)>,
+    max_retries: u32,
+    backoff: Duration,
+    sent: u64,
+}
+
+impl WebhookSink {
+    /// Post to `path` (e.g. `/hooks/netkit`) of the server at `addr` (e.g.
+    /// `127.0.0.1:8080`).
+    pub fn new(addr: impl Into<String>, path: impl Into<String>) -> Self {
+        let mut client = Client::new(addr);
+        client.timeout(DEFAULT_TIMEOUT);
+        Self {
+            client,
+            path: path.into(),
+            headers: Vec::new(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            sent: 0,
+        }
+    }
+
+    /// Add a header to the requests, e.g. for authorization.
+    pub fn header(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the number of retries of a failed request.
+    pub fn max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the timeout of connections, reads and writes.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.client.timeout(timeout);
+        self
+    }
+
+    /// Get the number of alerts posted.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Post a body in one request.
+    fn post(&self, body: &str) -> io::Result<()> {
+        let mut request = self
+            .client
+            .request("POST", &self.path)
+            .header("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request.body(body.as_bytes()).send()?;
+        match response.status() {
+            200..=299 => Ok(()),
+            _ => Err(response.error("webhook")),
+        }
+    }
+}
+
+impl AlertSink for WebhookSink {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        let body = alert.to_json();
+        http::retry(self.max_retries, self.backoff, || self.post(&body))?;
+        self.sent += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        net::{IpAddr, Ipv4Addr, TcpListener},
+    };
+
+    use netkit_packet::utils::id::{CaptureId, PacketId};
+
+    use super::*;
+
+    struct Alerts(Vec<Alert>);
+
+    impl AlertSource for Alerts {
+        fn alerts(&self) -> &[Alert] {
+            &self.0
+        }
+
+        fn take_alerts(&mut self) -> Vec<Alert> {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    #[test]
+    fn alert_sinks() {
+        let alert = Alert {
+            ts: Duration::from_millis(1_700_000_000_500),
+            detector: "beacon",
+            score: 0.95,
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            subject: "443/tcp".to_string(),
+            message: "regular \"beacons\"".to_string(),
+            packet: None,
+        };
+        let json = "{\"ts\":1700000000.5,\"detector\":\"beacon\",\"score\":0.95,\
+                    \"src\":\"10.0.0.1\",\"dst\":\"192.0.2.1\",\"subject\":\"443/tcp\",\
+                    \"message\":\"regular \\\"beacons\\\"\"}";
+
+        let mut sinks: Vec<Box<dyn AlertSink>> = vec![
+            Box::new(JsonSink::new(Vec::new())),
+            Box::new(Vec::<Alert>::new()),
+        ];
+        let mut source = Alerts(vec![alert.clone(); 2]);
+        assert_eq!(forward(&mut source, &mut sinks).unwrap(), 2);
+        assert!(source.alerts().is_empty());
+        let mut file = JsonSink::new(Vec::new());
+        file.send(&alert).unwrap();
+        assert_eq!(
+            String::from_utf8(file.into_inner()).unwrap(),
+            format!("{json}\n")
+        );
+
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut syslog = SyslogSink::udp(server.local_addr().unwrap()).unwrap();
+        syslog.send(&alert).unwrap();
+        let mut buf = [0; 512];
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..n]).unwrap(),
+            "<130>1 2023-11-14T22:13:20.500000Z - netkit - beacon - 443/tcp: \
+             regular \"beacons\" (score 0.95, 10.0.0.1 -> 192.0.2.1)"
+        );
+        let numbered = alert
+            .clone()
+            .with_packet(PacketId::new(CaptureId([0; 16]), 6));
+        let id = "00000000-0000-0000-0000-000000000000:6";
+        assert!(numbered
+            .to_json()
+            .ends_with(&format!(",\"packet_id\":\"{id}\"}}")));
+        assert!(syslog
+            .message(&numbered)
+            .ends_with(&format!("192.0.2.1, packet {id})")));
+
+        // Fail the first post with a server error, accept the retry.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let webhook = std::thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in ["503 Service Unavailable", "204 No Content"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                while !request.ends_with(b"}") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                write!(stream, "HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").unwrap();
+                let request = String::from_utf8(request).unwrap();
+                bodies.push(request.split("\r\n\r\n").nth(1).unwrap().to_string());
+            }
+            bodies
+        });
+        let mut sink = WebhookSink::new(addr, "/hooks/netkit");
+        sink.header("Authorization", "Bearer token")
+            .backoff(Duration::from_millis(1));
+        sink.send(&alert).unwrap();
+        assert_eq!(sink.sent(), 1);
+        assert_eq!(webhook.join().unwrap(), vec![json; 2]);
+    }
+}
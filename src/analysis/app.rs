@@ -0,0 +1,646 @@
+//! Application protocol identification.
+//!
+//! Flows are labelled with an [`AppProtocol`] by matching [`Signature`]s
+//! against the first payloads of both directions. A signature matches a
+//! byte pattern, a custom predicate, or the server name (SNI) and ALPN
+//! protocols of a TLS ClientHello, optionally restricted to a transport and
+//! ports. Signatures without a pattern are port hints, only used for flows
+//! no pattern identified.
+//!
+//! Signatures registered with [`AppClassifier::register`] take precedence
+//! over earlier ones, so the built-in set is easily refined, e.g. to label
+//! TLS flows to a service by its server name.
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use super::{tls::Reader, trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Default number of payload packets inspected per flow.
+pub const DEFAULT_MAX_PACKETS: usize = 8;
+
+/// Application protocol.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AppProtocol {
+    /// Hypertext Transfer Protocol.
+    Http,
+    /// HTTP/2 negotiated over TLS.
+    Http2,
+    /// Transport Layer Security.
+    Tls,
+    /// QUIC.
+    Quic,
+    /// Secure Shell.
+    Ssh,
+    /// Domain Name System.
+    Dns,
+    /// Network Time Protocol.
+    Ntp,
+    /// Dynamic Host Configuration Protocol.
+    Dhcp,
+    /// Simple Mail Transfer Protocol.
+    Smtp,
+    /// Internet Message Access Protocol.
+    Imap,
+    /// Post Office Protocol.
+    Pop3,
+    /// BitTorrent peer protocol.
+    BitTorrent,
+    /// WireGuard.
+    WireGuard,
+    /// OpenVPN.
+    OpenVpn,
+    /// A protocol or service named by a user signature.
+    Custom(String),
+    /// Not identified.
+    Unknown,
+}
+
+impl fmt::Display for AppProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AppProtocol::Http => "HTTP",
+            AppProtocol::Http2 => "HTTP/2",
+            AppProtocol::Tls => "TLS",
+            AppProtocol::Quic => "QUIC",
+            AppProtocol::Ssh => "SSH",
+            AppProtocol::Dns => "DNS",
+            AppProtocol::Ntp => "NTP",
+            AppProtocol::Dhcp => "DHCP",
+            AppProtocol::Smtp => "SMTP",
+            AppProtocol::Imap => "IMAP",
+            AppProtocol::Pop3 => "POP3",
+            AppProtocol::BitTorrent => "BitTorrent",
+            AppProtocol::WireGuard => "WireGuard",
+            AppProtocol::OpenVpn => "OpenVPN",
+            AppProtocol::Custom(name) => name,
+            AppProtocol::Unknown => "Unknown",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// What a signature matches.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// Payload bytes at an offset.
+    Bytes {
+        /// Offset in the payload.
+        offset: usize,
+        /// Bytes to match.
+        bytes: Vec<u8>,
+    },
+    /// A predicate over the payload.
+    Predicate(fn(&[u8]) -> bool),
+    /// A TLS server name, or any subdomain of it.
+    Sni(String),
+    /// A TLS ALPN protocol offered by the client.
+    Alpn(String),
+}
+
+impl Pattern {
+    fn matches(&self, payload: &[u8], hello: Option<&ClientHello>) -> bool {
+        match self {
+            Pattern::Bytes { offset, bytes } => {
+                payload.get(*offset..).is_some_and(|p| p.starts_with(bytes))
+            }
+            Pattern::Predicate(f) => f(payload),
+            Pattern::Sni(domain) => hello.and_then(|h| h.sni.as_deref()).is_some_and(|sni| {
+                let sni = sni.to_ascii_lowercase();
+                sni == *domain
+                    || sni
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|s| s.ends_with('.'))
+            }),
+            Pattern::Alpn(protocol) => hello.is_some_and(|h| h.alpn.contains(protocol)),
+        }
+    }
+}
+
+/// An application protocol signature.
+#[derive(Clone, Debug)]
+#[must_use]
+pub struct Signature {
+    protocol: AppProtocol,
+    transport: Option<IpProtocol>,
+    ports: Vec<u16>,
+    pattern: Option<Pattern>,
+}
+
+impl Signature {
+    /// Create a signature labelling flows with a protocol.
+    ///
+    /// Without a pattern, the signature is a port hint.
+    pub fn new(protocol: AppProtocol) -> Self {
+        Self {
+            protocol,
+            transport: None,
+            ports: Vec::new(),
+            pattern: None,
+        }
+    }
+
+    /// Restrict the signature to a transport protocol.
+    pub fn transport(mut self, transport: IpProtocol) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Restrict the signature to flows with one of the ports at either end.
+    pub fn ports(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.ports = ports.into_iter().collect();
+        self
+    }
+
+    /// Match payload bytes at an offset.
+    pub fn bytes(mut self, offset: usize, bytes: impl Into<Vec<u8>>) -> Self {
+        self.pattern = Some(Pattern::Bytes {
+            offset,
+            bytes: bytes.into(),
+        });
+        self
+    }
+
+    /// Match payloads satisfying a predicate.
+    pub fn predicate(mut self, predicate: fn(&[u8]) -> bool) -> Self {
+        self.pattern = Some(Pattern::Predicate(predicate));
+        self
+    }
+
+    /// Match TLS server names equal to or under a domain.
+    pub fn sni(mut self, domain: impl Into<String>) -> Self {
+        let domain: String = domain.into();
+        self.pattern = Some(Pattern::Sni(domain.to_ascii_lowercase()));
+        self
+    }
+
+    /// Match TLS ClientHellos offering an ALPN protocol.
+    pub fn alpn(mut self, protocol: impl Into<String>) -> Self {
+        self.pattern = Some(Pattern::Alpn(protocol.into()));
+        self
+    }
+
+    /// Get the protocol of the signature.
+    pub fn protocol(&self) -> &AppProtocol {
+        &self.protocol
+    }
+
+    fn applies(&self, key: &FlowKey) -> bool {
+        self.transport.is_none_or(|t| t == key.protocol)
+            && (self.ports.is_empty()
+                || self.ports.contains(&key.src_port)
+                || self.ports.contains(&key.dst_port))
+    }
+}
+
+/// Get the built-in signatures, in increasing precedence.
+pub fn builtin_signatures() -> Vec<Signature> {
+    use AppProtocol::*;
+    use IpProtocol::{Tcp, Udp};
+
+    let mut signatures = vec![
+        // Port hints
+        Signature::new(Http).transport(Tcp).ports([80, 8080]),
+        Signature::new(Tls).transport(Tcp).ports([443, 993, 995]),
+        Signature::new(Quic).transport(Udp).ports([443]),
+        Signature::new(Ssh).transport(Tcp).ports([22]),
+        Signature::new(Dns).ports([53]),
+        Signature::new(Ntp).transport(Udp).ports([123]),
+        Signature::new(Dhcp).transport(Udp).ports([67, 68]),
+        Signature::new(Smtp).transport(Tcp).ports([25, 587]),
+        Signature::new(Imap).transport(Tcp).ports([143]),
+        Signature::new(Pop3).transport(Tcp).ports([110]),
+        Signature::new(WireGuard).transport(Udp).ports([51820]),
+        Signature::new(OpenVpn).ports([1194]),
+    ];
+
+    for method in [
+        "GET ", "POST ", "PUT ", "HEAD ", "DELETE ", "OPTIONS ", "HTTP/1.",
+    ] {
+        signatures.push(Signature::new(Http).transport(Tcp).bytes(0, method));
+    }
+    signatures.extend([
+        Signature::new(Ssh).transport(Tcp).bytes(0, "SSH-"),
+        Signature::new(Smtp).transport(Tcp).bytes(0, "EHLO "),
+        Signature::new(Smtp).transport(Tcp).bytes(0, "HELO "),
+        Signature::new(Imap).transport(Tcp).bytes(0, "* OK "),
+        Signature::new(Pop3).transport(Tcp).bytes(0, "+OK "),
+        Signature::new(BitTorrent)
+            .transport(Tcp)
+            .bytes(0, b"\x13BitTorrent protocol".to_vec()),
+        Signature::new(Dns)
+            .ports([53])
+            .predicate(|p| netkit_packet::layer::dns::Dns::new(p).is_ok()),
+        Signature::new(Ntp)
+            .transport(Udp)
+            .ports([123])
+            .predicate(|p| p.len() >= 48 && (1..=4).contains(&((p[0] >> 3) & 0x7))),
+        Signature::new(Dhcp)
+            .transport(Udp)
+            .ports([67, 68])
+            .bytes(236, [0x63, 0x82, 0x53, 0x63]),
+        Signature::new(WireGuard).transport(Udp).predicate(|p| {
+            matches!((p.first(), p.len()), (Some(1), 148) | (Some(2), 92)) && p[1..4] == [0, 0, 0]
+        }),
+        // Long header with the fixed bit and a QUIC version 1 or 2
+        Signature::new(Quic).transport(Udp).predicate(|p| {
+            p.len() >= 1200
+                && p[0] & 0xC0 == 0xC0
+                && matches!(p[1..5], [0, 0, 0, 1] | [0x6B, 0x33, 0x43, 0xCF])
+        }),
+        Signature::new(Tls)
+            .transport(Tcp)
+            .predicate(|p| ClientHello::parse(p).is_some()),
+        Signature::new(Http2).alpn("h2"),
+    ]);
+    signatures
+}
+
+/// How a flow was identified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Confidence {
+    /// A payload or TLS signature matched.
+    Signature,
+    /// Only a port hint matched.
+    Port,
+    /// Nothing matched.
+    None,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Confidence::Signature => write!(f, "signature"),
+            Confidence::Port => write!(f, "port"),
+            Confidence::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Label of a flow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowLabel {
+    /// Application protocol.
+    pub protocol: AppProtocol,
+    /// How the protocol was identified.
+    pub confidence: Confidence,
+    /// TLS server name, if a ClientHello was seen.
+    pub sni: Option<String>,
+    /// TLS ALPN protocols offered by the client.
+    pub alpn: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+struct FlowState {
+    key: FlowKey,
+    inspected: usize,
+    matched: Option<AppProtocol>,
+    hello: Option<ClientHello>,
+}
+
+/// Application protocol classifier
+#[derive(Clone, Debug)]
+pub struct AppClassifier {
+    signatures: Vec<Signature>,
+    max_packets: usize,
+    flows: HashMap<FlowKey, FlowState>,
+}
+
+impl Default for AppClassifier {
+    fn default() -> Self {
+        Self {
+            signatures: builtin_signatures(),
+            max_packets: DEFAULT_MAX_PACKETS,
+            flows: HashMap::new(),
+        }
+    }
+}
+
+impl AppClassifier {
+    /// Create a new classifier with the built-in signatures.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new classifier without any signature.
+    pub fn empty() -> Self {
+        Self {
+            signatures: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    /// Register a signature, taking precedence over those registered before.
+    pub fn register(&mut self, signature: Signature) -> &mut Self {
+        self.signatures.push(signature);
+        self
+    }
+
+    /// Set the number of payload packets inspected per flow.
+    pub fn max_packets(&mut self, max_packets: usize) -> &mut Self {
+        self.max_packets = max_packets;
+        self
+    }
+
+    /// Get the label of a flow, in either direction.
+    pub fn label(&self, key: &FlowKey) -> Option<FlowLabel> {
+        self.flows.get(&key.canonical()).map(|s| self.label_of(s))
+    }
+
+    /// Get the labels of all flows, ordered by flow.
+    pub fn labels(&self) -> Vec<(FlowKey, FlowLabel)> {
+        let mut labels: Vec<_> = self
+            .flows
+            .values()
+            .map(|s| (s.key, self.label_of(s)))
+            .collect();
+        labels.sort_by_cached_key(|(k, _)| k.to_string());
+        labels
+    }
+
+    /// Get the number of flows of each protocol, most common first.
+    pub fn protocols(&self) -> Vec<(AppProtocol, u64)> {
+        let mut counts: HashMap<AppProtocol, u64> = HashMap::new();
+        for state in self.flows.values() {
+            *counts.entry(self.label_of(state).protocol).or_default() += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|(pa, a), (pb, b)| b.cmp(a).then_with(|| pa.cmp(pb)));
+        counts
+    }
+
+    fn label_of(&self, state: &FlowState) -> FlowLabel {
+        let (protocol, confidence) = match &state.matched {
+            Some(protocol) => (protocol.clone(), Confidence::Signature),
+            None => self
+                .signatures
+                .iter()
+                .rev()
+                .find(|s| s.pattern.is_none() && !s.ports.is_empty() && s.applies(&state.key))
+                .map_or((AppProtocol::Unknown, Confidence::None), |s| {
+                    (s.protocol.clone(), Confidence::Port)
+                }),
+        };
+        FlowLabel {
+            protocol,
+            confidence,
+            sni: state.hello.as_ref().and_then(|h| h.sni.clone()),
+            alpn: state.hello.as_ref().map_or(Vec::new(), |h| h.alpn.clone()),
+        }
+    }
+
+    fn inspect_transport(
+        &mut self,
+        key: FlowKey,
+        tcp: Option<Tcp<&[u8]>>,
+        udp: Option<Udp<&[u8]>>,
+    ) {
+        if let Some(tcp) = tcp {
+            self.inspect(key, tcp.payload());
+        } else if let Some(udp) = udp {
+            self.inspect(key, udp.payload());
+        }
+    }
+
+    fn inspect(&mut self, key: FlowKey, payload: &[u8]) {
+        trace_flow_growth::<Self, _>(&self.flows, &key.canonical());
+        let state = self
+            .flows
+            .entry(key.canonical())
+            .or_insert_with(|| FlowState {
+                key,
+                inspected: 0,
+                matched: None,
+                hello: None,
+            });
+        if state.matched.is_some() || state.inspected >= self.max_packets || payload.is_empty() {
+            return;
+        }
+        state.inspected += 1;
+
+        if state.hello.is_none() && key.protocol == IpProtocol::Tcp {
+            state.hello = ClientHello::parse(payload);
+        }
+        state.matched = self
+            .signatures
+            .iter()
+            .rev()
+            .find(|s| {
+                s.applies(&key)
+                    && s.pattern
+                        .as_ref()
+                        .is_some_and(|p| p.matches(payload, state.hello.as_ref()))
+            })
+            .map(|s| s.protocol.clone());
+    }
+}
+
+impl Analyzer for AppClassifier {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            let key = FlowKey::from_ipv4(&ipv4);
+            self.inspect_transport(key, ipv4.tcp(), ipv4.udp());
+        } else if let Some(ipv6) = eth.ipv6() {
+            let key = FlowKey::from_ipv6(&ipv6);
+            self.inspect_transport(key, ipv6.tcp(), ipv6.udp());
+        }
+    }
+}
+
+impl ToTable for AppClassifier {
+    /// Convert into a table with one row per flow.
+    fn to_table(&self) -> Table {
+        let labels = self.labels();
+        let strs = |f: &dyn Fn(&FlowKey, &FlowLabel) -> String| {
+            Column::Str(labels.iter().map(|(k, l)| f(k, l)).collect())
+        };
+
+        let mut table = Table::new();
+        table
+            .push("flow", strs(&|k, _| k.to_string()))
+            .push("protocol", strs(&|_, l| l.protocol.to_string()))
+            .push("confidence", strs(&|_, l| l.confidence.to_string()))
+            .push("sni", strs(&|_, l| l.sni.clone().unwrap_or_default()))
+            .push("alpn", strs(&|_, l| l.alpn.join(" ")));
+        table
+    }
+}
+
+/// Fields of a TLS ClientHello used for identification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ClientHello {
+    pub(crate) sni: Option<String>,
+    pub(crate) alpn: Vec<String>,
+}
+
+impl ClientHello {
+    const EXT_SERVER_NAME: u16 = 0;
+    const EXT_ALPN: u16 = 16;
+
+    /// Parse the ClientHello starting a TLS handshake record.
+    ///
+    /// Only the part of the message within the payload is parsed, so a
+    /// ClientHello split over segments may miss its later extensions.
+    pub(crate) fn parse(payload: &[u8]) -> Option<Self> {
+        // Handshake record of TLS 1.0 or later, with a ClientHello
+        if payload.len() < 9 || payload[0] != 22 || payload[1] != 3 || payload[5] != 1 {
+            return None;
+        }
+        let mut r = Reader(&payload[9..]);
+        r.skip(2 + 32)?;
+        let session_id = r.u8()? as usize;
+        r.skip(session_id)?;
+        let suites = r.u16()? as usize;
+        r.skip(suites)?;
+        let compression = r.u8()? as usize;
+        r.skip(compression)?;
+
+        let mut hello = ClientHello {
+            sni: None,
+            alpn: Vec::new(),
+        };
+        let Some(len) = r.u16() else {
+            return Some(hello);
+        };
+        let mut extensions = Reader(r.0.get(..len as usize).unwrap_or(r.0));
+        while let (Some(kind), Some(len)) = (extensions.u16(), extensions.u16()) {
+            let Some(mut data) = extensions.take(len as usize).map(Reader) else {
+                break;
+            };
+            match kind {
+                Self::EXT_SERVER_NAME => {
+                    // Server name list with a host name entry
+                    data.skip(2);
+                    if data.u8() == Some(0) {
+                        hello.sni = data
+                            .u16()
+                            .and_then(|len| data.take(len as usize))
+                            .and_then(|name| std::str::from_utf8(name).ok())
+                            .map(str::to_string);
+                    }
+                }
+                Self::EXT_ALPN => {
+                    data.skip(2);
+                    while let Some(len) = data.u8() {
+                        match data.take(len as usize) {
+                            Some(p) => hello.alpn.push(String::from_utf8_lossy(p).into_owned()),
+                            None => break,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(hello)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::{tcp_frame, udp_frame};
+
+    fn frame(protocol: IpProtocol, ports: (u16, u16), payload: &[u8]) -> Vec<u8> {
+        let src = (Ipv4Addr::new(10, 0, 0, 1), ports.0);
+        let dst = (Ipv4Addr::new(10, 0, 0, 2), ports.1);
+        match protocol {
+            IpProtocol::Tcp => tcp_frame(src, dst, 0, TcpFlags::empty(), payload),
+            _ => udp_frame(src, dst, payload),
+        }
+    }
+
+    fn client_hello(sni: &str, alpn: &[&str]) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        let name = sni.as_bytes();
+        extensions.extend(0u16.to_be_bytes());
+        extensions.extend((name.len() as u16 + 5).to_be_bytes());
+        extensions.extend((name.len() as u16 + 3).to_be_bytes());
+        extensions.push(0);
+        extensions.extend((name.len() as u16).to_be_bytes());
+        extensions.extend(name);
+
+        let list: Vec<u8> = alpn
+            .iter()
+            .flat_map(|p| std::iter::once(p.len() as u8).chain(p.bytes()))
+            .collect();
+        extensions.extend(16u16.to_be_bytes());
+        extensions.extend((list.len() as u16 + 2).to_be_bytes());
+        extensions.extend((list.len() as u16).to_be_bytes());
+        extensions.extend(list);
+
+        let mut body = vec![3, 3];
+        body.extend([0; 32]);
+        body.extend([0, 0, 2, 0x13, 0x01, 1, 0]);
+        body.extend((extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+
+        let mut handshake = vec![1, 0];
+        handshake.extend((body.len() as u16).to_be_bytes());
+        handshake.extend(body);
+        let mut record = vec![22, 3, 1];
+        record.extend((handshake.len() as u16).to_be_bytes());
+        record.extend(handshake);
+        record
+    }
+
+    #[test]
+    fn app_classifier() {
+        let mut classifier = AppClassifier::new();
+        classifier.register(
+            Signature::new(AppProtocol::Custom("Example".into()))
+                .transport(IpProtocol::Tcp)
+                .sni("example.com"),
+        );
+
+        let http = frame(IpProtocol::Tcp, (40000, 8000), b"GET / HTTP/1.1\r\n");
+        let ssh = frame(IpProtocol::Tcp, (40001, 2222), b"SSH-2.0-OpenSSH_9.6\r\n");
+        let h2 = frame(
+            IpProtocol::Tcp,
+            (40002, 443),
+            &client_hello("other.org", &["h2"]),
+        );
+        let custom = frame(
+            IpProtocol::Tcp,
+            (40003, 443),
+            &client_hello("www.Example.com", &["http/1.1"]),
+        );
+        let hint = frame(IpProtocol::Udp, (40004, 123), &[0; 4]);
+        let unknown = frame(IpProtocol::Udp, (40005, 9999), &[0; 4]);
+        for frame in [&http, &ssh, &h2, &custom, &hint, &unknown] {
+            classifier.on_packet(Duration::ZERO, frame);
+        }
+
+        let labels = classifier.labels();
+        let protocols: Vec<_> = labels
+            .iter()
+            .map(|(_, l)| (l.protocol.clone(), l.confidence))
+            .collect();
+        assert_eq!(
+            protocols,
+            [
+                (AppProtocol::Http, Confidence::Signature),
+                (AppProtocol::Ssh, Confidence::Signature),
+                (AppProtocol::Http2, Confidence::Signature),
+                (AppProtocol::Custom("Example".into()), Confidence::Signature),
+                (AppProtocol::Ntp, Confidence::Port),
+                (AppProtocol::Unknown, Confidence::None),
+            ]
+        );
+        assert_eq!(labels[2].1.sni.as_deref(), Some("other.org"));
+        assert_eq!(labels[3].1.alpn, ["http/1.1"]);
+
+        let table = classifier.to_table();
+        assert_eq!(table.len(), 6);
+        assert_eq!(classifier.protocols().len(), 6);
+    }
+}
@@ -0,0 +1,378 @@
+//! Audit of the hosts contacted against allow and deny lists.
+//!
+//! A [`HostAuditor`] reads the server name (SNI) of TLS ClientHellos and the
+//! `Host` header of HTTP requests, and reports a [`HostViolation`] for every
+//! host matching a deny pattern, or, if allow patterns are set, matching
+//! none of them. Patterns are host names in which `*` matches any run of
+//! characters, so `*.example.com` matches the subdomains of `example.com`.
+//!
+//! ```
+//! use netkit::analysis::audit::HostAuditor;
+//!
+//! let mut auditor = HostAuditor::new();
+//! auditor.allow("*.example.com").allow("example.com").deny("ads.example.com");
+//! // Feed packets, then:
+//! for violation in auditor.violations() {
+//!     println!("{} {} ({})", violation.key, violation.host, violation.reason);
+//! }
+//! ```
+//!
+//! Hosts are only read from the payload of single TCP segments, which holds
+//! the ClientHello and request head in practice.
+
+use std::{collections::HashSet, fmt, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use super::{alert::Alert, app::ClientHello, http::HttpHead, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Host name pattern, where `*` matches any run of characters.
+///
+/// Matching ignores case and a trailing dot.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HostPattern(String);
+
+impl HostPattern {
+    /// Create a pattern.
+    pub fn new(pattern: &str) -> Self {
+        Self(normalize(pattern))
+    }
+
+    /// Whether a host matches the pattern.
+    pub fn matches(&self, host: &str) -> bool {
+        glob(self.0.as_bytes(), normalize(host).as_bytes())
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Match `text` against `pattern`, backtracking to the last `*`.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Where a host was read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HostSource {
+    /// Server name of a TLS ClientHello.
+    Sni,
+    /// `Host` header of an HTTP request.
+    HttpHost,
+}
+
+impl fmt::Display for HostSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HostSource::Sni => "sni",
+            HostSource::HttpHost => "http-host",
+        })
+    }
+}
+
+/// Why a host violates the policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ViolationReason {
+    /// The host matches a deny pattern.
+    Denied(HostPattern),
+    /// Allow patterns are set, and the host matches none.
+    NotAllowed,
+}
+
+impl fmt::Display for ViolationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViolationReason::Denied(pattern) => write!(f, "denied by {pattern}"),
+            ViolationReason::NotAllowed => f.write_str("not allowed"),
+        }
+    }
+}
+
+/// Host contacted against the policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostViolation {
+    /// Timestamp of the packet naming the host.
+    pub ts: Duration,
+    /// Key of the flow, from the client.
+    pub key: FlowKey,
+    /// Where the host was read.
+    pub source: HostSource,
+    /// Host, as sent by the client.
+    pub host: String,
+    /// Why the host violates the policy.
+    pub reason: ViolationReason,
+}
+
+/// Violations are certain alerts, so they can be sent to an
+/// [`AlertSink`](super::alert::sink::AlertSink).
+impl From<&HostViolation> for Alert {
+    fn from(violation: &HostViolation) -> Self {
+        Alert {
+            ts: violation.ts,
+            detector: "host-audit",
+            score: 1.0,
+            src: violation.key.src,
+            dst: violation.key.dst,
+            subject: violation.host.clone(),
+            message: format!("{} {}", violation.source, violation.reason),
+            packet: None,
+        }
+    }
+}
+
+/// Analyzer auditing TLS server names and HTTP hosts.
+///
+/// Each host is reported once per flow.
+#[derive(Clone, Debug, Default)]
+pub struct HostAuditor {
+    allow: Vec<HostPattern>,
+    deny: Vec<HostPattern>,
+    reported: HashSet<(FlowKey, String)>,
+    hosts: u64,
+    violations: Vec<HostViolation>,
+}
+
+impl HostAuditor {
+    /// Create an auditor allowing every host.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the hosts matching a pattern, denying all others.
+    pub fn allow(&mut self, pattern: &str) -> &mut Self {
+        self.allow.push(HostPattern::new(pattern));
+        self
+    }
+
+    /// Deny the hosts matching a pattern, even if allowed.
+    pub fn deny(&mut self, pattern: &str) -> &mut Self {
+        self.deny.push(HostPattern::new(pattern));
+        self
+    }
+
+    /// Check a host against the policy.
+    pub fn check(&self, host: &str) -> Option<ViolationReason> {
+        if let Some(pattern) = self.deny.iter().find(|p| p.matches(host)) {
+            return Some(ViolationReason::Denied(pattern.clone()));
+        }
+        (!self.allow.is_empty() && !self.allow.iter().any(|p| p.matches(host)))
+            .then_some(ViolationReason::NotAllowed)
+    }
+
+    /// Get the number of hosts checked.
+    pub fn hosts(&self) -> u64 {
+        self.hosts
+    }
+
+    /// Get the violations, in capture order.
+    pub fn violations(&self) -> &[HostViolation] {
+        &self.violations
+    }
+
+    /// Take the violations, in capture order.
+    pub fn take_violations(&mut self) -> Vec<HostViolation> {
+        std::mem::take(&mut self.violations)
+    }
+
+    fn on_payload(&mut self, ts: Duration, key: FlowKey, payload: &[u8]) {
+        let (source, host) = if let Some(sni) = ClientHello::parse(payload).and_then(|h| h.sni) {
+            (HostSource::Sni, sni)
+        } else if let Some(host) = http_host(payload) {
+            (HostSource::HttpHost, host)
+        } else {
+            return;
+        };
+        if !self.reported.insert((key.canonical(), normalize(&host))) {
+            return;
+        }
+        self.hosts += 1;
+        if let Some(reason) = self.check(&host) {
+            self.violations.push(HostViolation {
+                ts,
+                key,
+                source,
+                host,
+                reason,
+            });
+        }
+    }
+}
+
+/// Get the host of an HTTP request head, without the port.
+fn http_host(payload: &[u8]) -> Option<String> {
+    let end = payload.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = HttpHead::parse(&payload[..end])?;
+    head.method()?;
+    let host = head.header("Host")?;
+    // Keep bracketed IPv6 addresses whole.
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !name.ends_with(':') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    };
+    Some(host.to_string())
+}
+
+impl Analyzer for HostAuditor {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            if let Some(tcp) = ipv4.tcp() {
+                self.on_payload(ts, FlowKey::from_ipv4(&ipv4), tcp.payload());
+            }
+        } else if let Some(ipv6) = eth.ipv6() {
+            if let Some(tcp) = ipv6.tcp() {
+                self.on_payload(ts, FlowKey::from_ipv6(&ipv6), tcp.payload());
+            }
+        }
+    }
+}
+
+impl ToTable for HostAuditor {
+    /// Convert into a table with one row per violation.
+    fn to_table(&self) -> Table {
+        let strs =
+            |f: fn(&HostViolation) -> String| Column::Str(self.violations.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "ts",
+                Column::F64(self.violations.iter().map(|v| v.ts.as_secs_f64()).collect()),
+            )
+            .push("flow", strs(|v| v.key.to_string()))
+            .push("source", strs(|v| v.source.to_string()))
+            .push("host", strs(|v| v.host.clone()))
+            .push("reason", strs(|v| v.reason.to_string()));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    fn frame(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        tcp_frame(
+            (Ipv4Addr::new(10, 0, 0, 1), src_port),
+            (Ipv4Addr::new(192, 0, 2, 1), dst_port),
+            0,
+            TcpFlags::empty(),
+            payload,
+        )
+    }
+
+    /// ClientHello with a server name and no other extension.
+    fn client_hello(sni: &str) -> Vec<u8> {
+        let name = sni.as_bytes();
+        let mut ext = vec![0, 0];
+        ext.extend((name.len() as u16 + 5).to_be_bytes());
+        ext.extend((name.len() as u16 + 3).to_be_bytes());
+        ext.push(0);
+        ext.extend((name.len() as u16).to_be_bytes());
+        ext.extend(name);
+
+        let mut body = vec![3, 3];
+        body.extend([0; 32]);
+        body.extend([0, 0, 2, 0x13, 0x01, 1, 0]);
+        body.extend((ext.len() as u16).to_be_bytes());
+        body.extend(ext);
+        let mut hello = vec![1, 0];
+        hello.extend((body.len() as u16).to_be_bytes());
+        hello.extend(body);
+        let mut record = vec![22, 3, 1];
+        record.extend((hello.len() as u16).to_be_bytes());
+        record.extend(hello);
+        record
+    }
+
+    #[test]
+    fn host_audit() {
+        let pattern = HostPattern::new("*.Example.com.");
+        assert!(pattern.matches("www.example.com"));
+        assert!(pattern.matches("a.b.EXAMPLE.com."));
+        assert!(!pattern.matches("example.com"));
+        assert!(!pattern.matches("badexample.com"));
+        assert!(HostPattern::new("cdn*.net").matches("cdn-1.edge.net"));
+
+        let mut auditor = HostAuditor::new();
+        auditor
+            .allow("example.com")
+            .allow("*.example.com")
+            .deny("ads.example.com");
+        let frames = [
+            frame(40000, 443, &client_hello("www.example.com")),
+            frame(40001, 443, &client_hello("ads.example.com")),
+            frame(40001, 443, &client_hello("ads.example.com")),
+            frame(
+                40002,
+                80,
+                b"GET / HTTP/1.1\r\nHost: tracker.net:8080\r\n\r\n",
+            ),
+            frame(40003, 80, b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+        ];
+        for (i, frame) in frames.iter().enumerate() {
+            auditor.on_packet(Duration::from_secs(i as u64), frame);
+        }
+
+        assert_eq!(auditor.hosts(), 4);
+        let violations: Vec<_> = auditor
+            .violations()
+            .iter()
+            .map(|v| (v.source, v.host.as_str(), v.reason.to_string()))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                (
+                    HostSource::Sni,
+                    "ads.example.com",
+                    "denied by ads.example.com".to_string()
+                ),
+                (
+                    HostSource::HttpHost,
+                    "tracker.net",
+                    "not allowed".to_string()
+                ),
+            ]
+        );
+        assert_eq!(auditor.violations()[0].key.dst_port, 443);
+        assert_eq!(auditor.to_table().len(), 2);
+        let alert = Alert::from(&auditor.violations()[1]);
+        assert_eq!(alert.message, "http-host not allowed");
+    }
+}
@@ -0,0 +1,368 @@
+//! Beaconing detection.
+//!
+//! Implants call back to their command and control server at a fixed
+//! interval, sending little data each time. Seen per channel (source,
+//! destination, destination port and protocol), this is a series of
+//! connections whose start times are regularly spaced, which is rare in
+//! human-driven traffic.
+//!
+//! A beacon is a TCP connection attempt (a SYN), or for UDP a packet after
+//! at least [`min_gap`](BeaconDetector::min_gap) of silence on the channel.
+//! Once a channel has [`min_beacons`](BeaconDetector::min_beacons) beacons,
+//! the coefficient of variation of its intervals (the jitter) is compared to
+//! the [`max_jitter`](BeaconDetector::max_jitter) sensitivity, and the
+//! channel raises an [`Alert`] if the intervals are regular and the beacons
+//! small.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::{flow::table::FlowTable, layer::tcp::TcpFlags, prelude::*};
+
+use super::{
+    alert::{Alert, AlertSource},
+    Analyzer,
+};
+use crate::export::{Column, Table, ToTable};
+
+/// Name of the detector in its alerts.
+pub const DETECTOR: &str = "beacon";
+
+/// Default silence on a UDP channel after which a packet is a new beacon.
+pub const DEFAULT_MIN_GAP: Duration = Duration::from_secs(1);
+
+/// Default number of beacons before a channel is judged.
+pub const DEFAULT_MIN_BEACONS: usize = 6;
+
+/// Default maximum coefficient of variation of beacon intervals.
+pub const DEFAULT_MAX_JITTER: f64 = 0.1;
+
+/// Default maximum mean number of bytes sent per beacon.
+pub const DEFAULT_MAX_BYTES: u64 = 16 * 1024;
+
+/// Default number of beacon start times kept per channel.
+pub const DEFAULT_HISTORY: usize = 32;
+
+/// Traffic from a source to a destination port.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BeaconChannel {
+    /// Key of the channel; its source port is always 0.
+    pub key: FlowKey,
+    /// Start times of the last beacons.
+    pub starts: VecDeque<Duration>,
+    /// Number of beacons.
+    pub beacons: u64,
+    /// Number of bytes sent on the channel.
+    pub bytes: u64,
+    /// Timestamp of the last packet.
+    pub last_seen: Duration,
+    /// Whether the channel raised an alert.
+    pub alerted: bool,
+}
+
+impl BeaconChannel {
+    fn intervals(&self) -> impl Iterator<Item = f64> + '_ {
+        self.starts
+            .iter()
+            .zip(self.starts.iter().skip(1))
+            .map(|(a, b)| b.saturating_sub(*a).as_secs_f64())
+    }
+
+    /// Get the mean interval between the last beacons.
+    pub fn interval(&self) -> Option<Duration> {
+        let n = self.starts.len().checked_sub(1).filter(|n| *n > 0)?;
+        Some(Duration::from_secs_f64(
+            self.intervals().sum::<f64>() / n as f64,
+        ))
+    }
+
+    /// Get the coefficient of variation (standard deviation over mean) of
+    /// the intervals between the last beacons.
+    pub fn jitter(&self) -> Option<f64> {
+        let mean = self.interval()?.as_secs_f64();
+        if mean == 0.0 {
+            return None;
+        }
+        let n = (self.starts.len() - 1) as f64;
+        let variance = self.intervals().map(|i| (i - mean).powi(2)).sum::<f64>() / n;
+        Some(variance.sqrt() / mean)
+    }
+
+    /// Get the mean number of bytes sent per beacon.
+    pub fn bytes_per_beacon(&self) -> u64 {
+        self.bytes / self.beacons.max(1)
+    }
+}
+
+/// Beaconing detector.
+#[derive(Clone, Debug)]
+pub struct BeaconDetector {
+    min_gap: Duration,
+    min_beacons: usize,
+    max_jitter: f64,
+    max_bytes: u64,
+    history: usize,
+    allowed: HashSet<IpAddr>,
+    allowed_ports: HashSet<u16>,
+    channels: FlowTable<BeaconChannel>,
+    alerts: Vec<Alert>,
+}
+
+impl Default for BeaconDetector {
+    fn default() -> Self {
+        Self {
+            min_gap: DEFAULT_MIN_GAP,
+            min_beacons: DEFAULT_MIN_BEACONS,
+            max_jitter: DEFAULT_MAX_JITTER,
+            max_bytes: DEFAULT_MAX_BYTES,
+            history: DEFAULT_HISTORY,
+            allowed: HashSet::new(),
+            allowed_ports: HashSet::new(),
+            channels: FlowTable::new(),
+            alerts: Vec::new(),
+        }
+    }
+}
+
+impl BeaconDetector {
+    /// Create a new beaconing detector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the silence on a UDP channel after which a packet is a new
+    /// beacon.
+    pub fn min_gap(&mut self, min_gap: Duration) -> &mut Self {
+        self.min_gap = min_gap;
+        self
+    }
+
+    /// Set the number of beacons before a channel is judged.
+    pub fn min_beacons(&mut self, min_beacons: usize) -> &mut Self {
+        self.min_beacons = min_beacons.max(3);
+        self
+    }
+
+    /// Set the maximum coefficient of variation of beacon intervals.
+    ///
+    /// Higher values catch implants randomizing their interval, at the cost
+    /// of more false positives.
+    pub fn max_jitter(&mut self, max_jitter: f64) -> &mut Self {
+        self.max_jitter = max_jitter;
+        self
+    }
+
+    /// Set the maximum mean number of bytes sent per beacon.
+    pub fn max_bytes(&mut self, max_bytes: u64) -> &mut Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Set the number of beacon start times kept per channel.
+    pub fn history(&mut self, history: usize) -> &mut Self {
+        self.history = history.max(self.min_beacons);
+        self
+    }
+
+    /// Never report channels to a destination, e.g. a monitoring server.
+    pub fn allow(&mut self, dst: impl Into<IpAddr>) -> &mut Self {
+        self.allowed.insert(dst.into());
+        self
+    }
+
+    /// Never report channels to a destination port, e.g. NTP.
+    pub fn allow_port(&mut self, port: u16) -> &mut Self {
+        self.allowed_ports.insert(port);
+        self
+    }
+
+    /// Get the channels.
+    pub fn channels(&self) -> impl Iterator<Item = &BeaconChannel> {
+        self.channels.iter().map(|(_, channel)| channel)
+    }
+
+    fn on_flow(&mut self, ts: Duration, key: FlowKey, len: usize, flags: Option<TcpFlags>) {
+        if self.allowed.contains(&key.dst) || self.allowed_ports.contains(&key.dst_port) {
+            return;
+        }
+        let beacon = match flags {
+            Some(flags) => flags.contains(TcpFlags::SYN) && !flags.contains(TcpFlags::ACK),
+            // A packet from a lower port to a higher one is taken as a reply.
+            None if key.src_port < key.dst_port => return,
+            None => true,
+        };
+
+        let channel_key = FlowKey::new(key.src, key.dst, 0, key.dst_port, key.protocol);
+        // Only connection attempts open TCP channels.
+        if !beacon && !self.channels.contains_key(&channel_key) {
+            return;
+        }
+        let channel = self
+            .channels
+            .entry(channel_key)
+            .or_insert_with(|| BeaconChannel {
+                key: channel_key,
+                starts: VecDeque::new(),
+                beacons: 0,
+                bytes: 0,
+                last_seen: ts,
+                alerted: false,
+            });
+        let silent = channel.beacons == 0 || ts.saturating_sub(channel.last_seen) >= self.min_gap;
+        let beacon = beacon && (flags.is_some() || silent);
+        channel.bytes += len as u64;
+        channel.last_seen = ts;
+        if !beacon {
+            return;
+        }
+
+        channel.beacons += 1;
+        channel.starts.push_back(ts);
+        if channel.starts.len() > self.history {
+            channel.starts.pop_front();
+        }
+        if channel.alerted || channel.starts.len() < self.min_beacons {
+            return;
+        }
+
+        let Some(jitter) = channel.jitter() else {
+            return;
+        };
+        if jitter > self.max_jitter || channel.bytes_per_beacon() > self.max_bytes {
+            return;
+        }
+        channel.alerted = true;
+        let interval = channel.interval().unwrap_or_default();
+        self.alerts.push(Alert {
+            ts,
+            detector: DETECTOR,
+            score: (1.0 - jitter).clamp(0.0, 1.0),
+            src: key.src,
+            dst: key.dst,
+            subject: format!(
+                "{}/{}",
+                key.dst_port,
+                key.protocol.as_ref().to_ascii_lowercase()
+            ),
+            message: format!(
+                "possible beaconing: {} beacons every {:.1}s (jitter {:.3}), {} bytes each",
+                channel.beacons,
+                interval.as_secs_f64(),
+                jitter,
+                channel.bytes_per_beacon(),
+            ),
+            packet: None,
+        });
+    }
+}
+
+impl AlertSource for BeaconDetector {
+    fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    fn take_alerts(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.alerts)
+    }
+}
+
+impl Analyzer for BeaconDetector {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(key) = FlowKey::from_eth(&eth) else {
+            return;
+        };
+        let (len, flags) = if let Some(ipv4) = eth.ipv4() {
+            (ipv4.inner().len(), ipv4.tcp().map(|tcp| tcp.flags().get()))
+        } else if let Some(ipv6) = eth.ipv6() {
+            (ipv6.inner().len(), ipv6.tcp().map(|tcp| tcp.flags().get()))
+        } else {
+            return;
+        };
+        match key.protocol {
+            IpProtocol::Tcp | IpProtocol::Udp => self.on_flow(ts, key, len, flags),
+            _ => {}
+        }
+    }
+}
+
+impl ToTable for BeaconDetector {
+    /// Convert into a table with one row per channel with at least three
+    /// beacons.
+    ///
+    /// `interval` is in seconds; `jitter` is `NaN` if unknown.
+    fn to_table(&self) -> Table {
+        let channels: Vec<_> = self.channels().filter(|c| c.starts.len() >= 3).collect();
+        let strs =
+            |f: fn(&BeaconChannel) -> String| Column::Str(channels.iter().map(|c| f(c)).collect());
+        let u64s =
+            |f: fn(&BeaconChannel) -> u64| Column::U64(channels.iter().map(|c| f(c)).collect());
+        let f64s =
+            |f: fn(&BeaconChannel) -> f64| Column::F64(channels.iter().map(|c| f(c)).collect());
+
+        let mut table = Table::new();
+        table
+            .push("src", strs(|c| c.key.src.to_string()))
+            .push("dst", strs(|c| c.key.dst.to_string()))
+            .push("dst_port", u64s(|c| c.key.dst_port as u64))
+            .push("protocol", strs(|c| c.key.protocol.to_string()))
+            .push("beacons", u64s(|c| c.beacons))
+            .push(
+                "interval",
+                f64s(|c| c.interval().unwrap_or_default().as_secs_f64()),
+            )
+            .push("jitter", f64s(|c| c.jitter().unwrap_or(f64::NAN)))
+            .push("bytes_per_beacon", u64s(|c| c.bytes_per_beacon()))
+            .push("alerted", u64s(|c| c.alerted as u64));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    fn syn(dst: Ipv4Addr, src_port: u16) -> Vec<u8> {
+        let tcp = tcp!(src_port: src_port, dst_port: 443u16, flags: TcpFlags::SYN);
+        let ipv4 = ipv4!(src: HOST, dst: dst, protocol: IpProtocol::Tcp, payload: tcp.inner());
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn beacon_detection() {
+        let c2 = Ipv4Addr::new(203, 0, 113, 1);
+        let web = Ipv4Addr::new(198, 51, 100, 1);
+        let monitor = Ipv4Addr::new(198, 51, 100, 2);
+
+        let mut detector = BeaconDetector::new();
+        detector.allow(monitor);
+        for i in 0..10u64 {
+            let port = 40000 + i as u16;
+            // Every 60s, with up to 1s of jitter
+            let ts = Duration::from_millis(i * 60_000 + (i * 337) % 1000);
+            detector.on_packet(ts, &syn(c2, port));
+            detector.on_packet(ts, &syn(monitor, port));
+            // Irregular browsing
+            let ts = Duration::from_secs(i * i * 7);
+            detector.on_packet(ts, &syn(web, port));
+        }
+
+        let alerts = detector.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].dst, IpAddr::V4(c2));
+        assert_eq!(alerts[0].subject, "443/tcp");
+        assert!(alerts[0].score > 0.9);
+        assert_eq!(detector.to_table().len(), 2);
+    }
+}
@@ -0,0 +1,407 @@
+//! Checkpointing of long-running analyses.
+//!
+//! Analyzing a multi-hundred-GB archive can take hours; a [`Checkpointer`]
+//! periodically saves the position in the capture together with the state
+//! of the analyzer, so an interrupted job resumes where it stopped instead of
+//! starting over.
+//!
+//! Analyzers opt in by implementing [`Checkpoint`]. Checkpoints are written
+//! to a temporary file first and renamed, so a crash while saving keeps the
+//! previous checkpoint intact.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use netkit_capture::file::pcap::PcapReader;
+use netkit_packet::{
+    flow::FlowScope,
+    layer::link::{self, LinkType},
+    prelude::*,
+};
+
+use super::Analyzer;
+
+/// Magic bytes of checkpoint files.
+pub const CHECKPOINT_MAGIC: [u8; 4] = *b"NKCP";
+
+/// Version of the checkpoint file format.
+pub const CHECKPOINT_VERSION: u32 = 4;
+
+/// Default number of packets between two checkpoints.
+pub const DEFAULT_INTERVAL: u64 = 1_000_000;
+
+/// Analyzer whose state can be saved and restored.
+pub trait Checkpoint {
+    /// Write the state.
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()>;
+
+    /// Replace the state with one written by [`Checkpoint::save`].
+    fn restore(&mut self, reader: &mut dyn Read) -> io::Result<()>;
+}
+
+/// Progress of a checkpointed analysis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CheckpointState {
+    /// Byte offset of the next packet record in the capture.
+    pub offset: u64,
+    /// Number of packets processed.
+    pub packets: u64,
+    /// Timestamp of the last packet processed.
+    pub last_ts: Duration,
+}
+
+/// Runs analyses with periodic checkpoints to a file.
+#[derive(Clone, Debug)]
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: u64,
+}
+
+impl Checkpointer {
+    /// Create a checkpointer saving to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Set the number of packets between two checkpoints.
+    pub fn interval(&mut self, interval: u64) -> &mut Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// Get the checkpoint path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Save a checkpoint.
+    pub fn save<A: Checkpoint + ?Sized>(
+        &self,
+        state: &CheckpointState,
+        analyzer: &A,
+    ) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(&CHECKPOINT_MAGIC)?;
+        write_u32(&mut writer, CHECKPOINT_VERSION)?;
+        write_u64(&mut writer, state.offset)?;
+        write_u64(&mut writer, state.packets)?;
+        write_duration(&mut writer, state.last_ts)?;
+        analyzer.save(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, &self.path)
+    }
+
+    /// Load the checkpoint into `analyzer`, if any.
+    pub fn load<A: Checkpoint + ?Sized>(
+        &self,
+        analyzer: &mut A,
+    ) -> io::Result<Option<CheckpointState>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != CHECKPOINT_MAGIC || read_u32(&mut reader)? != CHECKPOINT_VERSION {
+            return Err(invalid_data("not a checkpoint of this version"));
+        }
+        let state = CheckpointState {
+            offset: read_u64(&mut reader)?,
+            packets: read_u64(&mut reader)?,
+            last_ts: read_duration(&mut reader)?,
+        };
+        analyzer.restore(&mut reader)?;
+        Ok(Some(state))
+    }
+
+    /// Remove the checkpoint, e.g. once the job completed.
+    pub fn clear(&self) -> io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Feed every packet of a pcap file to the analyzer like
+    /// [`analyze_pcap`](super::analyze_pcap), resuming from the checkpoint
+    /// if any and saving one every interval and at the end.
+    pub fn analyze_pcap<R, A>(
+        &self,
+        reader: &mut PcapReader<R>,
+        analyzer: &mut A,
+    ) -> io::Result<CheckpointState>
+    where
+        R: Read + Seek,
+        A: Analyzer + Checkpoint + ?Sized,
+    {
+        let mut state = CheckpointState {
+            offset: reader.offset(),
+            ..Default::default()
+        };
+        if let Some(saved) = self.load(analyzer)? {
+            reader.seek_to(saved.offset)?;
+            state = saved;
+        }
+
+        let link_type = reader.link_type();
+        while let Some((header, data)) = reader.next_packet() {
+            let ts = reader.timestamp(&header);
+            if link_type == LinkType::Ethernet {
+                analyzer.on_packet(ts, &data);
+            } else if let Some(frame) = link::to_eth(link_type, &data) {
+                analyzer.on_packet(ts, &frame);
+            }
+
+            state.offset = reader.offset();
+            state.packets += 1;
+            state.last_ts = ts;
+            if state.packets.is_multiple_of(self.interval) {
+                self.save(&state, analyzer)?;
+            }
+        }
+
+        self.save(&state, analyzer)?;
+        Ok(state)
+    }
+}
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn write_u8(writer: &mut dyn Write, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub(crate) fn read_u8(reader: &mut dyn Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn write_u16(writer: &mut dyn Write, value: u16) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u16(reader: &mut dyn Read) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+pub(crate) fn write_u32(writer: &mut dyn Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u32(reader: &mut dyn Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn write_u64(writer: &mut dyn Write, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u64(reader: &mut dyn Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn write_duration(writer: &mut dyn Write, value: Duration) -> io::Result<()> {
+    write_u64(writer, value.as_secs())?;
+    write_u32(writer, value.subsec_nanos())
+}
+
+pub(crate) fn read_duration(reader: &mut dyn Read) -> io::Result<Duration> {
+    let secs = read_u64(reader)?;
+    let nanos = read_u32(reader)?;
+    if nanos >= 1_000_000_000 {
+        return Err(invalid_data("invalid duration"));
+    }
+    Ok(Duration::new(secs, nanos))
+}
+
+pub(crate) fn write_ip(writer: &mut dyn Write, addr: IpAddr) -> io::Result<()> {
+    match addr {
+        IpAddr::V4(addr) => {
+            write_u8(writer, 4)?;
+            writer.write_all(&addr.octets())
+        }
+        IpAddr::V6(addr) => {
+            write_u8(writer, 6)?;
+            writer.write_all(&addr.octets())
+        }
+    }
+}
+
+pub(crate) fn read_ip(reader: &mut dyn Read) -> io::Result<IpAddr> {
+    match read_u8(reader)? {
+        4 => {
+            let mut octets = [0; 4];
+            reader.read_exact(&mut octets)?;
+            Ok(Ipv4Addr::from(octets).into())
+        }
+        6 => {
+            let mut octets = [0; 16];
+            reader.read_exact(&mut octets)?;
+            Ok(Ipv6Addr::from(octets).into())
+        }
+        _ => Err(invalid_data("invalid address family")),
+    }
+}
+
+pub(crate) fn write_flow_key(writer: &mut dyn Write, key: &FlowKey) -> io::Result<()> {
+    write_ip(writer, key.src)?;
+    write_ip(writer, key.dst)?;
+    writer.write_all(&key.src_port.to_le_bytes())?;
+    writer.write_all(&key.dst_port.to_le_bytes())?;
+    write_u8(writer, key.protocol.into())?;
+    write_option(writer, key.scope.vlan, write_u16)?;
+    write_option(writer, key.scope.flow_label, write_u32)?;
+    write_option(writer, key.scope.dscp, write_u8)?;
+    write_option(writer, key.scope.tunnel_id, write_u32)
+}
+
+pub(crate) fn read_flow_key(reader: &mut dyn Read) -> io::Result<FlowKey> {
+    let src = read_ip(reader)?;
+    let dst = read_ip(reader)?;
+    let mut ports = [0; 4];
+    reader.read_exact(&mut ports)?;
+    let protocol = IpProtocol::from(read_u8(reader)?);
+    let scope = FlowScope {
+        vlan: read_option(reader, read_u16)?,
+        flow_label: read_option(reader, read_u32)?,
+        dscp: read_option(reader, read_u8)?,
+        tunnel_id: read_option(reader, read_u32)?,
+    };
+    Ok(FlowKey::new(
+        src,
+        dst,
+        u16::from_le_bytes([ports[0], ports[1]]),
+        u16::from_le_bytes([ports[2], ports[3]]),
+        protocol,
+    )
+    .with_scope(scope))
+}
+
+/// Write an optional value as a presence byte followed by the value.
+pub(crate) fn write_option<T: Copy>(
+    writer: &mut dyn Write,
+    value: Option<T>,
+    write: fn(&mut dyn Write, T) -> io::Result<()>,
+) -> io::Result<()> {
+    match value {
+        Some(value) => {
+            write_u8(writer, 1)?;
+            write(writer, value)
+        }
+        None => write_u8(writer, 0),
+    }
+}
+
+/// Read an optional value written by [`write_option`].
+pub(crate) fn read_option<T>(
+    reader: &mut dyn Read,
+    read: fn(&mut dyn Read) -> io::Result<T>,
+) -> io::Result<Option<T>> {
+    match read_u8(reader)? {
+        0 => Ok(None),
+        1 => read(reader).map(Some),
+        _ => Err(invalid_data("invalid option tag")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use netkit_capture::file::pcap::PcapWriter;
+
+    use super::*;
+    use crate::analysis::tcp::TcpFlowAnalyzer;
+
+    #[test]
+    fn checkpoint_resume() {
+        let a = Ipv4Addr::new(192, 0, 2, 1);
+        let b = Ipv4Addr::new(198, 51, 100, 1);
+        let frames: Vec<_> = (0..10u32)
+            .map(|i| {
+                let tcp = netkit_packet::tcp!(
+                    src_port: 40000u16,
+                    dst_port: 80u16,
+                    seq_num: 1000 + i * 100,
+                    payload: [0; 100],
+                );
+                let ipv4 = ipv4!(src: a, dst: b, protocol: IpProtocol::Tcp, payload: tcp.inner());
+                eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                    .inner()
+                    .clone()
+            })
+            .collect();
+
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for (i, frame) in frames.iter().enumerate() {
+            writer
+                .write_packet(Duration::from_secs(i as u64), frame)
+                .unwrap();
+        }
+        let pcap = writer.into_inner().unwrap();
+
+        let path = std::env::temp_dir().join(format!("netkit-checkpoint-{}", std::process::id()));
+        let mut checkpointer = Checkpointer::new(&path);
+        checkpointer.interval(4);
+
+        // Interrupted job: the first 6 packets only, so the last checkpoint
+        // is after packet 4.
+        let mut truncated = pcap.clone();
+        truncated.truncate(24 + 6 * (16 + frames[0].len()));
+        let mut reader = PcapReader::new(Cursor::new(truncated));
+        let mut analyzer = TcpFlowAnalyzer::new();
+        for _ in 0..6 {
+            let (header, data) = reader.next_packet().unwrap();
+            analyzer.on_packet(reader.timestamp(&header), &data);
+            if reader.offset() == 24 + 4 * (16 + frames[0].len() as u64) {
+                let state = CheckpointState {
+                    offset: reader.offset(),
+                    packets: 4,
+                    last_ts: Duration::from_secs(3),
+                };
+                checkpointer.save(&state, &analyzer).unwrap();
+            }
+        }
+
+        // Resumed job.
+        let mut reader = PcapReader::new(Cursor::new(pcap.clone()));
+        let mut resumed = TcpFlowAnalyzer::new();
+        let state = checkpointer
+            .analyze_pcap(&mut reader, &mut resumed)
+            .unwrap();
+        assert_eq!(state.packets, 10);
+        assert_eq!(state.last_ts, Duration::from_secs(9));
+
+        let mut reader = PcapReader::new(Cursor::new(pcap));
+        let mut full = TcpFlowAnalyzer::new();
+        crate::analysis::analyze_pcap(&mut reader, &mut full);
+
+        let key = FlowKey::new(a, b, 40000, 80, IpProtocol::Tcp);
+        assert_eq!(resumed.report(&key), full.report(&key));
+        assert_eq!(resumed.report(&key).unwrap().goodput_bytes, 1000);
+
+        checkpointer.clear().unwrap();
+        assert!(!path.exists());
+    }
+}
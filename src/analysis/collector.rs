@@ -0,0 +1,293 @@
+//! Flow export collection.
+//!
+//! A [`FlowCollector`] decodes the NetFlow v5/v9 and sFlow v5 exports sent
+//! to a collector, either captured on the wire ([`Analyzer`]) or received
+//! on a socket ([`collect`](FlowCollector::collect)), and aggregates the
+//! exported flows by canonical flow key. Counts are scaled by the sampling
+//! rate, so they estimate the traffic of the flow and can be compared with
+//! what a capture of the same link saw, e.g. in
+//! [`OverheadStats`](crate::stats::overhead::OverheadStats).
+
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use netkit_packet::{
+    layer::{
+        netflow::{self, NetFlowV5, NetFlowV9, TemplateCache},
+        sflow::{self, SFlowDatagram},
+    },
+    prelude::*,
+};
+
+use super::{trace_flow_growth, Analyzer};
+
+/// Export protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExportProtocol {
+    /// NetFlow version 5.
+    NetFlowV5,
+    /// NetFlow version 9.
+    NetFlowV9,
+    /// sFlow version 5.
+    SFlow,
+}
+
+/// Flow as seen through the exports of one or more exporters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CollectedFlow {
+    /// Number of records or samples of the flow.
+    pub records: u64,
+    /// Estimated number of packets, scaled by the sampling rate.
+    pub packets: u64,
+    /// Estimated number of bytes, scaled by the sampling rate.
+    pub bytes: u64,
+    /// Exporters that reported the flow.
+    pub exporters: Vec<IpAddr>,
+    /// Timestamp of the first export mentioning the flow.
+    pub first_seen: Duration,
+    /// Timestamp of the last export mentioning the flow.
+    pub last_seen: Duration,
+}
+
+impl CollectedFlow {
+    fn record(&mut self, ts: Duration, exporter: IpAddr, packets: u64, bytes: u64) {
+        if self.records == 0 {
+            self.first_seen = ts;
+        }
+        self.records += 1;
+        self.packets += packets;
+        self.bytes += bytes;
+        self.last_seen = self.last_seen.max(ts);
+        if !self.exporters.contains(&exporter) {
+            self.exporters.push(exporter);
+        }
+    }
+}
+
+/// Flow export collector
+///
+/// Exports are recognized by their destination port, [`netflow::DEFAULT_PORT`]
+/// and [`sflow::DEFAULT_PORT`] by default.
+#[derive(Clone, Debug)]
+pub struct FlowCollector {
+    netflow_ports: Vec<u16>,
+    sflow_ports: Vec<u16>,
+    templates: TemplateCache,
+    flows: HashMap<FlowKey, CollectedFlow>,
+    exports: HashMap<ExportProtocol, u64>,
+    missing_templates: u64,
+    errors: u64,
+}
+
+impl Default for FlowCollector {
+    fn default() -> Self {
+        Self {
+            netflow_ports: vec![netflow::DEFAULT_PORT],
+            sflow_ports: vec![sflow::DEFAULT_PORT],
+            templates: TemplateCache::new(),
+            flows: HashMap::new(),
+            exports: HashMap::new(),
+            missing_templates: 0,
+            errors: 0,
+        }
+    }
+}
+
+impl FlowCollector {
+    /// Create a new collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the UDP ports of NetFlow exports.
+    pub fn netflow_ports(&mut self, ports: impl IntoIterator<Item = u16>) -> &mut Self {
+        self.netflow_ports = ports.into_iter().collect();
+        self
+    }
+
+    /// Set the UDP ports of sFlow datagrams.
+    pub fn sflow_ports(&mut self, ports: impl IntoIterator<Item = u16>) -> &mut Self {
+        self.sflow_ports = ports.into_iter().collect();
+        self
+    }
+
+    /// Get the collected flows, by canonical flow key.
+    pub fn flows(&self) -> &HashMap<FlowKey, CollectedFlow> {
+        &self.flows
+    }
+
+    /// Get the collected flow of a key, in either direction.
+    pub fn flow(&self, key: &FlowKey) -> Option<&CollectedFlow> {
+        self.flows.get(&key.canonical())
+    }
+
+    /// Get the number of exports decoded per protocol.
+    pub fn exports(&self, protocol: ExportProtocol) -> u64 {
+        self.exports.get(&protocol).copied().unwrap_or(0)
+    }
+
+    /// Get the number of NetFlow v9 flowsets skipped for lack of a template.
+    pub fn missing_templates(&self) -> u64 {
+        self.missing_templates
+    }
+
+    /// Get the number of exports that failed to decode.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Get the NetFlow v9 templates learnt so far.
+    pub fn templates(&self) -> &TemplateCache {
+        &self.templates
+    }
+
+    /// Decode an export received from `exporter`, e.g. on a UDP socket.
+    ///
+    /// The protocol is told apart by the version field.
+    pub fn collect(&mut self, ts: Duration, exporter: IpAddr, payload: &[u8]) {
+        let protocol = match netflow::version(payload) {
+            Some(5) => ExportProtocol::NetFlowV5,
+            Some(9) => ExportProtocol::NetFlowV9,
+            // sFlow starts with a 32-bit version.
+            Some(0) if payload.get(2..4) == Some(&[0, 5]) => ExportProtocol::SFlow,
+            _ => {
+                self.errors += 1;
+                return;
+            }
+        };
+        if self.decode(ts, exporter, protocol, payload).is_some() {
+            *self.exports.entry(protocol).or_default() += 1;
+        } else {
+            self.errors += 1;
+        }
+    }
+
+    fn decode(
+        &mut self,
+        ts: Duration,
+        exporter: IpAddr,
+        protocol: ExportProtocol,
+        payload: &[u8],
+    ) -> Option<()> {
+        match protocol {
+            ExportProtocol::NetFlowV5 => {
+                let export = NetFlowV5::parse(payload).ok()?;
+                let rate = export.sampling_rate() as u64;
+                for record in &export.records {
+                    self.record(
+                        ts,
+                        exporter,
+                        record.flow_key(),
+                        record.packets as u64 * rate,
+                        record.bytes as u64 * rate,
+                    );
+                }
+            }
+            ExportProtocol::NetFlowV9 => {
+                let export = NetFlowV9::parse(payload, &mut self.templates).ok()?;
+                self.missing_templates += export.missing_templates.len() as u64;
+                for record in &export.records {
+                    if let Some(key) = record.flow_key() {
+                        let packets = record.packets().unwrap_or(0);
+                        let bytes = record.bytes().unwrap_or(0);
+                        self.record(ts, exporter, key, packets, bytes);
+                    }
+                }
+            }
+            ExportProtocol::SFlow => {
+                let datagram = SFlowDatagram::parse(payload).ok()?;
+                for sample in datagram.flow_samples() {
+                    if let Some(key) = sample.flow_key() {
+                        let rate = sample.sampling_rate.max(1) as u64;
+                        let bytes = sample.frame_length().unwrap_or(0) as u64;
+                        self.record(ts, datagram.agent, key, rate, bytes * rate);
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn record(&mut self, ts: Duration, exporter: IpAddr, key: FlowKey, packets: u64, bytes: u64) {
+        trace_flow_growth::<Self, _>(&self.flows, &key.canonical());
+        self.flows
+            .entry(key.canonical())
+            .or_default()
+            .record(ts, exporter, packets, bytes);
+    }
+}
+
+impl Analyzer for FlowCollector {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let (src, payload) = if let Some(ipv4) = eth.ipv4() {
+            let Some(udp) = ipv4.udp() else {
+                return;
+            };
+            let port = udp.dst_port().get();
+            if !self.netflow_ports.contains(&port) && !self.sflow_ports.contains(&port) {
+                return;
+            }
+            (IpAddr::from(ipv4.src().get()), udp.payload().to_vec())
+        } else if let Some(ipv6) = eth.ipv6() {
+            let Some(udp) = ipv6.udp() else {
+                return;
+            };
+            let port = udp.dst_port().get();
+            if !self.netflow_ports.contains(&port) && !self.sflow_ports.contains(&port) {
+                return;
+            }
+            (IpAddr::from(ipv6.src().get()), udp.payload().to_vec())
+        } else {
+            return;
+        };
+        self.collect(ts, src, &payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn flow_collector() {
+        let mut v5 = vec![0, 5, 0, 1];
+        v5.extend([0; 18]);
+        v5.extend(0x4010u16.to_be_bytes());
+        let mut record = [0u8; netflow::V5_RECORD_LENGTH];
+        record[..4].copy_from_slice(&[10, 0, 0, 2]);
+        record[4..8].copy_from_slice(&[10, 0, 0, 1]);
+        record[16..20].copy_from_slice(&3u32.to_be_bytes());
+        record[20..24].copy_from_slice(&300u32.to_be_bytes());
+        record[32..34].copy_from_slice(&80u16.to_be_bytes());
+        record[34..36].copy_from_slice(&1234u16.to_be_bytes());
+        record[38] = 6;
+        v5.extend(record);
+
+        let router = Ipv4Addr::new(192, 0, 2, 254);
+        let udp = udp!(src_port: 50000u16, dst_port: netflow::DEFAULT_PORT, payload: v5);
+        let ipv4 = ipv4!(src: router, protocol: IpProtocol::Udp, payload: udp.inner());
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+
+        let mut collector = FlowCollector::new();
+        collector.on_packet(Duration::from_secs(1), eth.inner());
+        collector.collect(Duration::from_secs(2), router.into(), b"garbage");
+        assert_eq!(collector.exports(ExportProtocol::NetFlowV5), 1);
+        assert_eq!(collector.errors(), 1);
+
+        // Looked up from the direction seen in a capture.
+        let key = FlowKey::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            1234,
+            80,
+            IpProtocol::Tcp,
+        );
+        let flow = collector.flow(&key).unwrap();
+        assert_eq!((flow.packets, flow.bytes), (48, 4800));
+        assert_eq!(flow.exporters, [IpAddr::from(router)]);
+    }
+}
@@ -0,0 +1,367 @@
+//! Cleartext credential scanner.
+//!
+//! Scans reassembled TCP streams and UDP datagrams for credentials sent in
+//! the clear:
+//!
+//! - HTTP Basic authentication (`Authorization` and `Proxy-Authorization`)
+//! - FTP logins (`USER` followed by `PASS`)
+//! - SNMP v1 and v2c community strings
+//!
+//! Findings never hold the secret itself, only a [`redact`]ed form, and refer
+//! to the packet which completed them by its index in the capture.
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+
+use super::{
+    alert::Alert,
+    stream::{LineBuffer, TcpReassembler},
+    Analyzer,
+};
+use crate::export::{Column, Table, ToTable};
+
+/// UDP port of SNMP agents.
+pub const SNMP_PORT: u16 = 161;
+
+/// UDP port of SNMP trap receivers.
+pub const SNMP_TRAP_PORT: u16 = 162;
+
+/// Kind of a credential.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CredentialKind {
+    /// HTTP Basic authentication.
+    HttpBasic,
+    /// FTP login.
+    Ftp,
+    /// SNMP community string.
+    SnmpCommunity,
+}
+
+impl CredentialKind {
+    /// Get the name of the kind.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CredentialKind::HttpBasic => "http-basic",
+            CredentialKind::Ftp => "ftp",
+            CredentialKind::SnmpCommunity => "snmp-community",
+        }
+    }
+}
+
+/// A credential found in the clear.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CredentialFinding {
+    /// Index of the packet completing the credential, from 0.
+    pub packet: u64,
+    /// Timestamp of that packet.
+    pub ts: Duration,
+    /// Key of the direction the credential was sent in.
+    pub key: FlowKey,
+    /// Kind of the credential.
+    pub kind: CredentialKind,
+    /// User name, if the credential has one.
+    pub user: Option<String>,
+    /// Redacted secret.
+    pub secret: String,
+}
+
+/// Findings are alerts of certainty 1, so they can be sent to an
+/// [`AlertSink`](super::alert::sink::AlertSink).
+impl From<&CredentialFinding> for Alert {
+    fn from(finding: &CredentialFinding) -> Self {
+        let user = match &finding.user {
+            Some(user) => format!(" of user {user}"),
+            None => String::new(),
+        };
+        Alert {
+            ts: finding.ts,
+            detector: "credentials",
+            score: 1.0,
+            src: finding.key.src,
+            dst: finding.key.dst,
+            subject: finding.kind.name().to_string(),
+            message: format!("cleartext credential{user}: {}", finding.secret),
+            packet: None,
+        }
+    }
+}
+
+/// Redact a secret, keeping its first character and hiding the rest.
+///
+/// ```
+/// use netkit::analysis::credentials::redact;
+///
+/// assert_eq!(redact(b"hunter2"), "h******");
+/// assert_eq!(redact(b""), "");
+/// ```
+pub fn redact(secret: &[u8]) -> String {
+    let secret = String::from_utf8_lossy(secret);
+    let mut chars = secret.chars();
+    let Some(first) = chars.next() else {
+        return String::new();
+    };
+    std::iter::once(first).chain(chars.map(|_| '*')).collect()
+}
+
+#[derive(Clone, Debug, Default)]
+struct LineState {
+    lines: LineBuffer,
+    ftp_user: Option<String>,
+}
+
+/// Cleartext credential analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct CredentialAnalyzer {
+    packets: u64,
+    reassembler: TcpReassembler,
+    lines: FlowTable<LineState>,
+    findings: Vec<CredentialFinding>,
+}
+
+impl CredentialAnalyzer {
+    /// Create a new credential analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the findings, in capture order.
+    pub fn findings(&self) -> &[CredentialFinding] {
+        &self.findings
+    }
+
+    /// Take the findings, in capture order.
+    pub fn take_findings(&mut self) -> Vec<CredentialFinding> {
+        std::mem::take(&mut self.findings)
+    }
+
+    fn finding(&self, ts: Duration, key: FlowKey, kind: CredentialKind) -> CredentialFinding {
+        CredentialFinding {
+            packet: self.packets,
+            ts,
+            key,
+            kind,
+            user: None,
+            secret: String::new(),
+        }
+    }
+
+    fn on_tcp<T: AsRef<[u8]>>(&mut self, ts: Duration, ipv4: &Ipv4<T>) {
+        let Some(segment) = self.reassembler.push(ipv4) else {
+            return;
+        };
+        let mut state = self.lines.remove(&segment.key).unwrap_or_default();
+        if segment.gap {
+            state = LineState::default();
+        }
+
+        let mut lines = std::mem::take(&mut state.lines);
+        lines.push(&segment.data, |line| {
+            self.on_line(ts, segment.key, &mut state, line)
+        });
+        state.lines = lines;
+
+        if !segment.fin {
+            self.lines.insert(segment.key, state);
+        }
+    }
+
+    fn on_line(&mut self, ts: Duration, key: FlowKey, state: &mut LineState, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("Authorization")
+                || name.eq_ignore_ascii_case("Proxy-Authorization")
+            {
+                let value = value.trim();
+                let Some((scheme, token)) = value.split_once(' ') else {
+                    return;
+                };
+                if !scheme.eq_ignore_ascii_case("Basic") {
+                    return;
+                }
+                let Ok(decoded) = STANDARD.decode(token.trim()) else {
+                    return;
+                };
+                let (user, password) = match decoded.iter().position(|&b| b == b':') {
+                    Some(at) => (&decoded[..at], &decoded[at + 1..]),
+                    None => (&decoded[..], &[][..]),
+                };
+                let mut finding = self.finding(ts, key, CredentialKind::HttpBasic);
+                finding.user = Some(String::from_utf8_lossy(user).into_owned());
+                finding.secret = redact(password);
+                self.findings.push(finding);
+                return;
+            }
+        }
+
+        let (command, argument) = line.split_once(' ').unwrap_or((&line, ""));
+        if command.eq_ignore_ascii_case("USER") {
+            state.ftp_user = Some(argument.to_string());
+        } else if command.eq_ignore_ascii_case("PASS") {
+            let mut finding = self.finding(ts, key, CredentialKind::Ftp);
+            finding.user = state.ftp_user.take();
+            finding.secret = redact(argument.as_bytes());
+            self.findings.push(finding);
+        }
+    }
+
+    fn on_udp(&mut self, ts: Duration, key: FlowKey, udp: Option<Udp<&[u8]>>) {
+        let Some(udp) = udp else {
+            return;
+        };
+        let ports = [udp.src_port().get(), udp.dst_port().get()];
+        if !ports.contains(&SNMP_PORT) && !ports.contains(&SNMP_TRAP_PORT) {
+            return;
+        }
+        if let Some(community) = snmp_community(udp.payload()) {
+            let mut finding = self.finding(ts, key, CredentialKind::SnmpCommunity);
+            finding.secret = redact(community);
+            self.findings.push(finding);
+        }
+    }
+}
+
+/// Read a BER tag and length, returning the value and the rest.
+fn ber(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&t, data) = data.split_first()?;
+    if t != tag {
+        return None;
+    }
+    let (&first, mut data) = data.split_first()?;
+    let len = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || data.len() < n {
+            return None;
+        }
+        let len = data[..n].iter().fold(0, |len, &b| len << 8 | b as usize);
+        data = &data[n..];
+        len
+    };
+    (data.len() >= len).then(|| data.split_at(len))
+}
+
+/// Get the community string of an SNMP v1 or v2c message.
+fn snmp_community(data: &[u8]) -> Option<&[u8]> {
+    let (message, _) = ber(data, 0x30)?;
+    let (version, rest) = ber(message, 0x02)?;
+    // 0 is v1 and 1 is v2c; v3 has no community.
+    if version != [0] && version != [1] {
+        return None;
+    }
+    let (community, _) = ber(rest, 0x04)?;
+    Some(community)
+}
+
+impl Analyzer for CredentialAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        if let Ok(eth) = Eth::new(frame) {
+            if let Some(key) = FlowKey::from_eth(&eth) {
+                if let Some(ipv4) = eth.ipv4() {
+                    self.on_tcp(ts, &ipv4);
+                    self.on_udp(ts, key, ipv4.udp());
+                } else if let Some(ipv6) = eth.ipv6() {
+                    self.on_udp(ts, key, ipv6.udp());
+                }
+            }
+        }
+        self.packets += 1;
+    }
+}
+
+impl ToTable for CredentialAnalyzer {
+    /// Convert into a table with one row per finding.
+    fn to_table(&self) -> Table {
+        let findings = &self.findings;
+        let strs =
+            |f: fn(&CredentialFinding) -> String| Column::Str(findings.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "packet",
+                Column::U64(findings.iter().map(|f| f.packet).collect()),
+            )
+            .push(
+                "ts",
+                Column::F64(findings.iter().map(|f| f.ts.as_secs_f64()).collect()),
+            )
+            .push("src", strs(|f| f.key.src.to_string()))
+            .push("dst", strs(|f| f.key.dst.to_string()))
+            .push(
+                "dst_port",
+                Column::U64(findings.iter().map(|f| f.key.dst_port as u64).collect()),
+            )
+            .push("kind", strs(|f| f.kind.name().to_string()))
+            .push("user", strs(|f| f.user.clone().unwrap_or_default()))
+            .push("secret", strs(|f| f.secret.clone()));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::{tcp_frame, udp_frame};
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(dst_port: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+        tcp_frame(
+            (CLIENT, 40000),
+            (SERVER, dst_port),
+            seq,
+            TcpFlags::ACK,
+            payload,
+        )
+    }
+
+    #[test]
+    fn credentials_scan() {
+        let mut snmp = vec![0x30, 0x0d, 0x02, 0x01, 0x01, 0x04, 0x06];
+        snmp.extend_from_slice(b"public");
+        snmp.extend_from_slice(&[0xa0, 0x00]);
+        let snmp_frame = udp_frame((CLIENT, 50000), (SERVER, SNMP_PORT), snmp);
+
+        // "alice:secret", split across segments
+        let http = b"GET / HTTP/1.1\r\nAuthorization: Basic YWxpY2U6c2VjcmV0\r\n\r\n";
+        let frames = [
+            frame(80, 1, &http[..30]),
+            frame(80, 31, &http[30..]),
+            frame(21, 1, b"USER bob\r\n"),
+            frame(21, 11, b"PASS pw\r\n"),
+            snmp_frame,
+        ];
+
+        let mut analyzer = CredentialAnalyzer::new();
+        for (i, frame) in frames.iter().enumerate() {
+            analyzer.on_packet(Duration::from_secs(i as u64), frame);
+        }
+
+        let findings: Vec<_> = analyzer
+            .findings()
+            .iter()
+            .map(|f| (f.packet, f.kind, f.user.as_deref(), f.secret.as_str()))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                (1, CredentialKind::HttpBasic, Some("alice"), "s*****"),
+                (3, CredentialKind::Ftp, Some("bob"), "p*"),
+                (4, CredentialKind::SnmpCommunity, None, "p*****"),
+            ]
+        );
+        assert_eq!(analyzer.to_table().len(), 3);
+        let alert = Alert::from(&analyzer.findings()[1]);
+        assert_eq!(alert.message, "cleartext credential of user bob: p*");
+    }
+}
@@ -0,0 +1,346 @@
+//! Explicit Congestion Notification (ECN) accounting.
+//!
+//! ECN ([RFC 3168]) lets routers mark packets with Congestion Experienced
+//! (CE) instead of dropping them. The receiver echoes the mark to the sender
+//! with the TCP ECE flag, and the sender acknowledges the echo with CWR once
+//! it reduced its congestion window. This analyzer accounts, per TCP flow
+//! direction, the ECN codepoints at the IP layer against the ECE/CWR flags
+//! at the TCP layer, together with the outcome of the ECN negotiation.
+//!
+//! [RFC 3168]: https://datatracker.ietf.org/doc/html/rfc3168
+
+use std::{collections::HashMap, time::Duration};
+
+use netkit_packet::{layer::tcp::TcpFlags, prelude::*};
+
+use super::{trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// ECN codepoint of packets not using ECN.
+pub const NOT_ECT: u8 = 0b00;
+
+/// ECN codepoint ECT(1).
+pub const ECT1: u8 = 0b01;
+
+/// ECN codepoint ECT(0).
+pub const ECT0: u8 = 0b10;
+
+/// ECN codepoint Congestion Experienced.
+pub const CE: u8 = 0b11;
+
+/// Outcome of the ECN negotiation of a TCP connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EcnNegotiation {
+    /// The handshake was not captured.
+    #[default]
+    Unknown,
+    /// The SYN did not request ECN.
+    NotAttempted,
+    /// The SYN requested ECN (ECE and CWR), without a SYN-ACK yet.
+    Requested,
+    /// The SYN-ACK accepted ECN (ECE without CWR).
+    Accepted,
+    /// The SYN-ACK did not accept ECN.
+    Refused,
+}
+
+impl core::fmt::Display for EcnNegotiation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            EcnNegotiation::Unknown => "unknown",
+            EcnNegotiation::NotAttempted => "not-attempted",
+            EcnNegotiation::Requested => "requested",
+            EcnNegotiation::Accepted => "accepted",
+            EcnNegotiation::Refused => "refused",
+        })
+    }
+}
+
+/// ECN report of a TCP flow direction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EcnReport {
+    /// ECN negotiation of the connection.
+    pub negotiation: EcnNegotiation,
+    /// Number of segments.
+    pub packets: u64,
+    /// Number of Not-ECT segments.
+    pub not_ect: u64,
+    /// Number of ECT(0) segments.
+    pub ect0: u64,
+    /// Number of ECT(1) segments.
+    pub ect1: u64,
+    /// Number of CE-marked segments.
+    pub ce: u64,
+    /// Number of segments with ECE, SYNs excluded.
+    pub ece: u64,
+    /// Number of segments with CWR, SYNs excluded.
+    pub cwr: u64,
+    /// Number of CE episodes in this direction echoed by an ECE from the
+    /// peer.
+    pub ce_echoed: u64,
+    /// Number of ECE episodes from the peer answered by a CWR in this
+    /// direction.
+    pub cwr_responses: u64,
+    pending_ce: bool,
+    pending_ece: bool,
+}
+
+impl EcnReport {
+    /// Get the share of ECN-capable segments marked CE.
+    ///
+    /// Returns `None` if no segment was ECN-capable.
+    pub fn ce_ratio(&self) -> Option<f64> {
+        let capable = self.ect0 + self.ect1 + self.ce;
+        if capable == 0 {
+            return None;
+        }
+
+        Some(self.ce as f64 / capable as f64)
+    }
+
+    fn record_ecn(&mut self, ecn: u8) {
+        self.packets += 1;
+        match ecn {
+            NOT_ECT => self.not_ect += 1,
+            ECT1 => self.ect1 += 1,
+            ECT0 => self.ect0 += 1,
+            _ => {
+                self.ce += 1;
+                self.pending_ce = true;
+            }
+        }
+    }
+}
+
+/// ECN analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct EcnAnalyzer {
+    flows: HashMap<FlowKey, EcnReport>,
+}
+
+impl EcnAnalyzer {
+    /// Create a new ECN analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the report of a flow direction.
+    pub fn report(&self, key: &FlowKey) -> Option<&EcnReport> {
+        self.flows.get(key)
+    }
+
+    /// Get the reports of all flow directions, ordered by endpoints.
+    pub fn reports(&self) -> Vec<(FlowKey, &EcnReport)> {
+        let mut reports: Vec<_> = self.flows.iter().map(|(k, r)| (*k, r)).collect();
+        reports.sort_by_key(|(key, _)| (key.src, key.src_port, key.dst, key.dst_port));
+        reports
+    }
+
+    fn on_ipv4(&mut self, ipv4: &Ipv4<&[u8]>) {
+        let Some(tcp) = ipv4.tcp() else {
+            return;
+        };
+        let key = FlowKey::from_ipv4(ipv4);
+        let flags = tcp.flags().get();
+
+        trace_flow_growth::<Self, _>(&self.flows, &key);
+        let report = self.flows.entry(key).or_default();
+        report.record_ecn(ipv4.ecn().get());
+
+        if flags.contains(TcpFlags::SYN) {
+            let negotiation = if !flags.contains(TcpFlags::ACK) {
+                if flags.contains(TcpFlags::ECE | TcpFlags::CWR) {
+                    EcnNegotiation::Requested
+                } else {
+                    EcnNegotiation::NotAttempted
+                }
+            } else {
+                match report.negotiation {
+                    EcnNegotiation::Requested
+                        if flags.contains(TcpFlags::ECE) && !flags.contains(TcpFlags::CWR) =>
+                    {
+                        EcnNegotiation::Accepted
+                    }
+                    EcnNegotiation::Requested => EcnNegotiation::Refused,
+                    negotiation => negotiation,
+                }
+            };
+            report.negotiation = negotiation;
+            self.flows.entry(key.reversed()).or_default().negotiation = negotiation;
+            return;
+        }
+
+        let echo = flags.contains(TcpFlags::ECE);
+        if echo {
+            report.ece += 1;
+        }
+        if flags.contains(TcpFlags::CWR) {
+            report.cwr += 1;
+            if std::mem::take(&mut report.pending_ece) {
+                report.cwr_responses += 1;
+            }
+        }
+
+        if echo {
+            let peer = self.flows.entry(key.reversed()).or_default();
+            if std::mem::take(&mut peer.pending_ce) {
+                peer.ce_echoed += 1;
+            }
+            peer.pending_ece = true;
+        }
+    }
+}
+
+impl Analyzer for EcnAnalyzer {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_ipv4(&ipv4);
+        }
+    }
+}
+
+impl ToTable for EcnAnalyzer {
+    /// Convert into a table with one row per flow direction.
+    ///
+    /// The CE ratio of flows without ECN-capable segments is `NaN`.
+    fn to_table(&self) -> Table {
+        let reports = self.reports();
+        let u64s =
+            |f: fn(&EcnReport) -> u64| Column::U64(reports.iter().map(|(_, r)| f(r)).collect());
+        let strs =
+            |f: fn(&FlowKey) -> String| Column::Str(reports.iter().map(|(k, _)| f(k)).collect());
+        let ports = |f: fn(&FlowKey) -> u16| {
+            Column::U64(reports.iter().map(|(k, _)| f(k) as u64).collect())
+        };
+
+        let mut table = Table::new();
+        table
+            .push("src", strs(|k| k.src.to_string()))
+            .push("src_port", ports(|k| k.src_port))
+            .push("dst", strs(|k| k.dst.to_string()))
+            .push("dst_port", ports(|k| k.dst_port))
+            .push(
+                "negotiation",
+                Column::Str(
+                    reports
+                        .iter()
+                        .map(|(_, r)| r.negotiation.to_string())
+                        .collect(),
+                ),
+            )
+            .push("packets", u64s(|r| r.packets))
+            .push("not_ect", u64s(|r| r.not_ect))
+            .push("ect0", u64s(|r| r.ect0))
+            .push("ect1", u64s(|r| r.ect1))
+            .push("ce", u64s(|r| r.ce))
+            .push("ece", u64s(|r| r.ece))
+            .push("cwr", u64s(|r| r.cwr))
+            .push("ce_echoed", u64s(|r| r.ce_echoed))
+            .push("cwr_responses", u64s(|r| r.cwr_responses))
+            .push(
+                "ce_ratio",
+                Column::F64(
+                    reports
+                        .iter()
+                        .map(|(_, r)| r.ce_ratio().unwrap_or(f64::NAN))
+                        .collect(),
+                ),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::tcp;
+
+    use super::*;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn segment(from_client: bool, ecn: u8, flags: TcpFlags) -> Vec<u8> {
+        let (src, dst, src_port, dst_port) = if from_client {
+            (CLIENT, SERVER, 40000u16, 80u16)
+        } else {
+            (SERVER, CLIENT, 80, 40000)
+        };
+        let tcp = tcp!(src_port: src_port, dst_port: dst_port, flags: flags);
+        let ipv4 = ipv4!(
+            src: src,
+            dst: dst,
+            ecn: ecn,
+            protocol: IpProtocol::Tcp,
+            payload: tcp.inner(),
+        );
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn ecn_ce_response() {
+        let ack = TcpFlags::ACK;
+        let mut analyzer = EcnAnalyzer::new();
+        for (from_client, ecn, flags) in [
+            (true, NOT_ECT, TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR),
+            (false, NOT_ECT, TcpFlags::SYN | ack | TcpFlags::ECE),
+            (true, ECT0, ack),
+            (true, CE, ack),
+            (true, CE, ack),
+            (false, NOT_ECT, ack | TcpFlags::ECE),
+            (false, NOT_ECT, ack | TcpFlags::ECE),
+            (true, ECT0, ack | TcpFlags::CWR),
+            (false, NOT_ECT, ack),
+        ] {
+            analyzer.on_packet(Duration::ZERO, &segment(from_client, ecn, flags));
+        }
+
+        let key = FlowKey::new(CLIENT, SERVER, 40000, 80, IpProtocol::Tcp);
+        let client = analyzer.report(&key).unwrap();
+        assert_eq!(client.negotiation, EcnNegotiation::Accepted);
+        assert_eq!(
+            (client.packets, client.not_ect, client.ect0, client.ce),
+            (5, 1, 2, 2)
+        );
+        assert_eq!(client.ce_ratio(), Some(0.5));
+        assert_eq!(
+            (client.cwr, client.ce_echoed, client.cwr_responses),
+            (1, 1, 1)
+        );
+
+        let server = analyzer.report(&key.reversed()).unwrap();
+        assert_eq!(server.negotiation, EcnNegotiation::Accepted);
+        assert_eq!((server.ece, server.cwr), (2, 0));
+        assert_eq!(server.ce_ratio(), None);
+
+        let table = analyzer.to_table();
+        assert_eq!(
+            table.column("negotiation"),
+            Some(&Column::Str(vec!["accepted".into(), "accepted".into()]))
+        );
+    }
+
+    #[test]
+    fn ecn_refused() {
+        let mut analyzer = EcnAnalyzer::new();
+        let syn = TcpFlags::SYN | TcpFlags::ECE | TcpFlags::CWR;
+        analyzer.on_packet(Duration::ZERO, &segment(true, NOT_ECT, syn));
+        analyzer.on_packet(
+            Duration::ZERO,
+            &segment(false, NOT_ECT, TcpFlags::SYN | TcpFlags::ACK),
+        );
+
+        let key = FlowKey::new(CLIENT, SERVER, 40000, 80, IpProtocol::Tcp);
+        assert_eq!(
+            analyzer.report(&key).unwrap().negotiation,
+            EcnNegotiation::Refused
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Payload entropy and protocol-agnostic classification.
+//!
+//! When neither ports nor signatures identify a flow, the byte distribution
+//! of its payload still tells a lot: text protocols use few, printable byte
+//! values, compressed data uses all values but unevenly, and encrypted data
+//! is indistinguishable from uniform noise. This analyzer samples the first
+//! payload bytes of every TCP and UDP flow direction and reports the Shannon
+//! entropy, the share of printable bytes and a chi-square statistic against
+//! the uniform distribution, together with a [`PayloadClass`] guess.
+
+use std::{collections::HashMap, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use super::{trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Default number of payload bytes sampled per flow direction.
+pub const DEFAULT_SAMPLE_BYTES: usize = 4096;
+
+/// Minimum number of sampled bytes to classify a flow direction.
+pub const MIN_CLASSIFY_BYTES: u64 = 256;
+
+/// Entropy (bits per byte) above which payload is considered random-looking.
+pub const HIGH_ENTROPY: f64 = 7.0;
+
+/// Entropy (bits per byte) below which payload may be plaintext.
+pub const LOW_ENTROPY: f64 = 6.0;
+
+/// Chi-square statistic (255 degrees of freedom) below which the byte
+/// distribution is considered uniform, at about the 0.1% significance
+/// level.
+pub const UNIFORM_CHI_SQUARE: f64 = 330.0;
+
+/// Guessed nature of a payload.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PayloadClass {
+    /// Not enough payload to decide.
+    #[default]
+    Unknown,
+    /// Mostly printable text.
+    Plaintext,
+    /// Structured binary data.
+    Binary,
+    /// High entropy but not uniform, e.g. compressed data.
+    Compressed,
+    /// Uniformly distributed bytes, e.g. encrypted data.
+    Encrypted,
+}
+
+impl core::fmt::Display for PayloadClass {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            PayloadClass::Unknown => "unknown",
+            PayloadClass::Plaintext => "plaintext",
+            PayloadClass::Binary => "binary",
+            PayloadClass::Compressed => "compressed",
+            PayloadClass::Encrypted => "encrypted",
+        })
+    }
+}
+
+/// Payload statistics of a flow direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntropyReport {
+    /// Number of packets carrying payload.
+    pub packets: u64,
+    /// Number of payload bytes.
+    pub payload_bytes: u64,
+    /// Number of payload bytes sampled.
+    pub sampled_bytes: u64,
+    counts: Box<[u64; 256]>,
+}
+
+impl Default for EntropyReport {
+    fn default() -> Self {
+        Self {
+            packets: 0,
+            payload_bytes: 0,
+            sampled_bytes: 0,
+            counts: Box::new([0; 256]),
+        }
+    }
+}
+
+impl EntropyReport {
+    /// Get the number of occurrences of every byte value in the sample.
+    pub fn counts(&self) -> &[u64; 256] {
+        &self.counts
+    }
+
+    /// Get the Shannon entropy of the sample, in bits per byte.
+    pub fn entropy(&self) -> f64 {
+        if self.sampled_bytes == 0 {
+            return 0.0;
+        }
+
+        let total = self.sampled_bytes as f64;
+        self.counts
+            .iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Get the share of printable ASCII bytes (including whitespace) in the
+    /// sample.
+    pub fn printable_ratio(&self) -> f64 {
+        if self.sampled_bytes == 0 {
+            return 0.0;
+        }
+
+        let printable: u64 = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(byte, _)| matches!(*byte as u8, b'\t' | b'\n' | b'\r' | 0x20..=0x7E))
+            .map(|(_, count)| count)
+            .sum();
+        printable as f64 / self.sampled_bytes as f64
+    }
+
+    /// Get the chi-square statistic of the sample against the uniform
+    /// distribution.
+    pub fn chi_square(&self) -> f64 {
+        if self.sampled_bytes == 0 {
+            return 0.0;
+        }
+
+        let expected = self.sampled_bytes as f64 / 256.0;
+        self.counts
+            .iter()
+            .map(|count| {
+                let diff = *count as f64 - expected;
+                diff * diff / expected
+            })
+            .sum()
+    }
+
+    /// Guess the nature of the payload.
+    pub fn class(&self) -> PayloadClass {
+        if self.sampled_bytes < MIN_CLASSIFY_BYTES {
+            return PayloadClass::Unknown;
+        }
+
+        let entropy = self.entropy();
+        if entropy < LOW_ENTROPY && self.printable_ratio() > 0.9 {
+            PayloadClass::Plaintext
+        } else if entropy < HIGH_ENTROPY {
+            PayloadClass::Binary
+        } else if self.chi_square() < UNIFORM_CHI_SQUARE {
+            PayloadClass::Encrypted
+        } else {
+            PayloadClass::Compressed
+        }
+    }
+
+    fn record(&mut self, payload: &[u8], sample_bytes: usize) {
+        self.packets += 1;
+        self.payload_bytes += payload.len() as u64;
+
+        let room = sample_bytes.saturating_sub(self.sampled_bytes as usize);
+        let sample = &payload[..payload.len().min(room)];
+        for byte in sample {
+            self.counts[*byte as usize] += 1;
+        }
+        self.sampled_bytes += sample.len() as u64;
+    }
+}
+
+/// Payload entropy analyzer.
+#[derive(Clone, Debug)]
+pub struct EntropyAnalyzer {
+    sample_bytes: usize,
+    flows: HashMap<FlowKey, EntropyReport>,
+}
+
+impl Default for EntropyAnalyzer {
+    fn default() -> Self {
+        Self {
+            sample_bytes: DEFAULT_SAMPLE_BYTES,
+            flows: HashMap::new(),
+        }
+    }
+}
+
+impl EntropyAnalyzer {
+    /// Create a new entropy analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of payload bytes sampled per flow direction.
+    pub fn sample_bytes(&mut self, sample_bytes: usize) -> &mut Self {
+        self.sample_bytes = sample_bytes;
+        self
+    }
+
+    /// Get the report of a flow direction.
+    pub fn report(&self, key: &FlowKey) -> Option<&EntropyReport> {
+        self.flows.get(key)
+    }
+
+    /// Get the reports of all flow directions, ordered by endpoints.
+    pub fn reports(&self) -> Vec<(FlowKey, &EntropyReport)> {
+        let mut reports: Vec<_> = self.flows.iter().map(|(k, r)| (*k, r)).collect();
+        reports.sort_by_key(|(key, _)| (key.src, key.src_port, key.dst, key.dst_port));
+        reports
+    }
+
+    fn on_ipv4(&mut self, ipv4: &Ipv4<&[u8]>) {
+        if let Some(tcp) = ipv4.tcp() {
+            self.record(FlowKey::from_ipv4(ipv4), tcp.payload());
+        } else if let Some(udp) = ipv4.udp() {
+            self.record(FlowKey::from_ipv4(ipv4), udp.payload());
+        }
+    }
+
+    fn record(&mut self, key: FlowKey, payload: &[u8]) {
+        if payload.is_empty() {
+            return;
+        }
+
+        trace_flow_growth::<Self, _>(&self.flows, &key);
+        self.flows
+            .entry(key)
+            .or_default()
+            .record(payload, self.sample_bytes);
+    }
+}
+
+impl Analyzer for EntropyAnalyzer {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_ipv4(&ipv4);
+        }
+    }
+}
+
+impl ToTable for EntropyAnalyzer {
+    /// Convert into a table with one row per flow direction.
+    fn to_table(&self) -> Table {
+        let reports = self.reports();
+        let u64s =
+            |f: fn(&EntropyReport) -> u64| Column::U64(reports.iter().map(|(_, r)| f(r)).collect());
+        let f64s =
+            |f: fn(&EntropyReport) -> f64| Column::F64(reports.iter().map(|(_, r)| f(r)).collect());
+        let addrs =
+            |f: fn(&FlowKey) -> String| Column::Str(reports.iter().map(|(k, _)| f(k)).collect());
+        let ports = |f: fn(&FlowKey) -> u16| {
+            Column::U64(reports.iter().map(|(k, _)| f(k) as u64).collect())
+        };
+
+        let mut table = Table::new();
+        table
+            .push("src", addrs(|k| k.src.to_string()))
+            .push("src_port", ports(|k| k.src_port))
+            .push("dst", addrs(|k| k.dst.to_string()))
+            .push("dst_port", ports(|k| k.dst_port))
+            .push("protocol", addrs(|k| k.protocol.to_string()))
+            .push("packets", u64s(|r| r.packets))
+            .push("payload_bytes", u64s(|r| r.payload_bytes))
+            .push("sampled_bytes", u64s(|r| r.sampled_bytes))
+            .push("entropy", f64s(|r| r.entropy()))
+            .push("printable_ratio", f64s(|r| r.printable_ratio()))
+            .push("chi_square", f64s(|r| r.chi_square()))
+            .push(
+                "class",
+                Column::Str(reports.iter().map(|(_, r)| r.class().to_string()).collect()),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    const A: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const B: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        udp_frame((A, 40000), (B, dst_port), payload)
+    }
+
+    #[test]
+    fn entropy_classes() {
+        let text = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n";
+        // xorshift32 as a stand-in for ciphertext.
+        let mut state = 0x1234_5678u32;
+        let noise: Vec<u8> = (0..1200)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect();
+        let binary: Vec<u8> = (0..1200).map(|i| [0, 0, 1, i as u8 % 16][i % 4]).collect();
+
+        let mut analyzer = EntropyAnalyzer::new();
+        analyzer.sample_bytes(4000);
+        for _ in 0..10 {
+            analyzer.on_packet(Duration::ZERO, &frame(80, text));
+        }
+        for chunk in noise.chunks(400) {
+            analyzer.on_packet(Duration::ZERO, &frame(443, chunk));
+            analyzer.on_packet(Duration::ZERO, &frame(9000, &binary));
+        }
+        analyzer.on_packet(Duration::ZERO, &frame(53, &[1, 2, 3]));
+
+        let report = |port| {
+            let key = FlowKey::new(A, B, 40000, port, IpProtocol::Udp);
+            analyzer.report(&key).unwrap()
+        };
+        assert_eq!(report(80).class(), PayloadClass::Plaintext);
+        assert_eq!(report(443).class(), PayloadClass::Encrypted);
+        assert!(report(443).entropy() > 7.5);
+        assert_eq!(report(9000).class(), PayloadClass::Binary);
+        assert_eq!(report(9000).payload_bytes, 3600);
+        assert_eq!(report(9000).sampled_bytes, 3600);
+        assert_eq!(report(53).class(), PayloadClass::Unknown);
+
+        let table = analyzer.to_table();
+        assert_eq!(table.len(), 4);
+    }
+}
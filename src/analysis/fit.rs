@@ -0,0 +1,466 @@
+//! Fitting of payload size and inter-arrival distributions.
+//!
+//! A [`DistributionFitter`] collects the payload sizes and the gaps between
+//! payload-carrying packets of each flow, and fits a lognormal, an
+//! exponential and an empirical distribution to them per application
+//! protocol. The fitted parameters describe a capture compactly, and set up
+//! the traffic generator to produce similar traffic:
+//!
+//! ```
+//! # use netkit::analysis::{app::AppProtocol, fit::{DistributionFitter, Metric}};
+//! # use netkit_packet::gen::shape::Shaper;
+//! let fitter = DistributionFitter::new();
+//! // ... feed packets ...
+//! if let Some(fit) = fitter.fit(&AppProtocol::Dns, Metric::InterArrival) {
+//!     let shaper = Shaper::new(fit.gap_model());
+//! }
+//! if let Some(fit) = fitter.fit(&AppProtocol::Dns, Metric::PayloadSize) {
+//!     let payload = vec![0u8; fit.empirical.sample().round() as usize];
+//! }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    f64::consts::PI,
+    fmt,
+    time::Duration,
+};
+
+use netkit_packet::{gen::shape::GapModel, prelude::*, utils::rng};
+
+use super::{
+    app::{AppClassifier, AppProtocol},
+    trace_flow_growth, Analyzer,
+};
+use crate::export::{Column, Table, ToTable};
+
+/// Default number of samples of each metric kept per flow.
+pub const DEFAULT_MAX_SAMPLES: usize = 1024;
+
+/// Default number of intervals of the empirical CDF.
+pub const DEFAULT_QUANTILES: usize = 20;
+
+/// Quantity whose distribution is fitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Metric {
+    /// Transport payload length, in bytes.
+    PayloadSize,
+    /// Time between two payload-carrying packets of a flow, in seconds.
+    InterArrival,
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Metric::PayloadSize => "payload-size",
+            Metric::InterArrival => "inter-arrival",
+        })
+    }
+}
+
+/// Draw a uniform value in `(0, 1]`.
+fn uniform() -> f64 {
+    1.0 - rng::random::<f64>()
+}
+
+/// Lognormal distribution, whose logarithm is normal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogNormal {
+    /// Mean of the logarithm.
+    pub mu: f64,
+    /// Standard deviation of the logarithm.
+    pub sigma: f64,
+}
+
+impl LogNormal {
+    /// Fit to the positive samples by maximum likelihood.
+    pub fn fit(samples: &[f64]) -> Option<Self> {
+        let logs: Vec<f64> = samples
+            .iter()
+            .filter(|&&x| x > 0.0)
+            .map(|x| x.ln())
+            .collect();
+        if logs.is_empty() {
+            return None;
+        }
+        let n = logs.len() as f64;
+        let mu = logs.iter().sum::<f64>() / n;
+        let sigma = (logs.iter().map(|l| (l - mu).powi(2)).sum::<f64>() / n).sqrt();
+        Some(Self { mu, sigma })
+    }
+
+    /// Get the mean.
+    pub fn mean(&self) -> f64 {
+        (self.mu + self.sigma * self.sigma / 2.0).exp()
+    }
+
+    /// Draw a value.
+    pub fn sample(&self) -> f64 {
+        // Box-Muller transform
+        let normal = (-2.0 * uniform().ln()).sqrt() * (2.0 * PI * uniform()).cos();
+        (self.mu + self.sigma * normal).exp()
+    }
+}
+
+/// Exponential distribution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exponential {
+    /// Rate, the inverse of the mean.
+    pub rate: f64,
+}
+
+impl Exponential {
+    /// Fit to the samples by maximum likelihood.
+    ///
+    /// Returns `None` unless the mean of the samples is positive.
+    pub fn fit(samples: &[f64]) -> Option<Self> {
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        (mean > 0.0).then(|| Self { rate: mean.recip() })
+    }
+
+    /// Get the mean.
+    pub fn mean(&self) -> f64 {
+        self.rate.recip()
+    }
+
+    /// Draw a value.
+    pub fn sample(&self) -> f64 {
+        -uniform().ln() / self.rate
+    }
+}
+
+/// Empirical distribution, given by evenly spaced quantiles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Empirical {
+    quantiles: Vec<f64>,
+}
+
+impl Empirical {
+    /// Fit to the samples with `intervals` intervals between quantiles.
+    ///
+    /// Returns `None` if there is no sample.
+    pub fn fit(samples: &[f64], intervals: usize) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let intervals = intervals.max(1);
+        let quantiles = (0..=intervals)
+            .map(|i| {
+                let rank = i as f64 / intervals as f64 * (sorted.len() - 1) as f64;
+                let (low, high) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+                low + (high - low) * rank.fract()
+            })
+            .collect();
+        Some(Self { quantiles })
+    }
+
+    /// Get the quantiles, from the minimum to the maximum.
+    pub fn quantiles(&self) -> &[f64] {
+        &self.quantiles
+    }
+
+    /// Get the value below which a fraction `p` of the samples lies,
+    /// interpolating between the quantiles.
+    pub fn quantile(&self, p: f64) -> f64 {
+        let position = p.clamp(0.0, 1.0) * (self.quantiles.len() - 1) as f64;
+        let low = position.floor() as usize;
+        let high = position.ceil() as usize;
+        let (low_value, high_value) = (self.quantiles[low], self.quantiles[high]);
+        low_value + (high_value - low_value) * position.fract()
+    }
+
+    /// Get the fraction of the samples at most `x`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        let intervals = (self.quantiles.len() - 1) as f64;
+        let Some(high) = self.quantiles.iter().position(|&q| q > x) else {
+            return 1.0;
+        };
+        if high == 0 {
+            return 0.0;
+        }
+        let (low_value, high_value) = (self.quantiles[high - 1], self.quantiles[high]);
+        (high - 1) as f64 / intervals + (x - low_value) / (high_value - low_value) / intervals
+    }
+
+    /// Draw a value by inverting the CDF.
+    pub fn sample(&self) -> f64 {
+        self.quantile(rng::random())
+    }
+}
+
+/// Distributions fitted to the samples of a metric.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fit {
+    /// Number of samples.
+    pub samples: usize,
+    /// Mean of the samples.
+    pub mean: f64,
+    /// Lognormal fit, if some samples are positive.
+    pub lognormal: Option<LogNormal>,
+    /// Exponential fit, if the mean is positive.
+    pub exponential: Option<Exponential>,
+    /// Empirical distribution.
+    pub empirical: Empirical,
+}
+
+impl Fit {
+    /// Fit the distributions with [`DEFAULT_QUANTILES`] intervals of the
+    /// empirical CDF.
+    ///
+    /// Returns `None` if there is no sample.
+    pub fn new(samples: &[f64]) -> Option<Self> {
+        Self::with_quantiles(samples, DEFAULT_QUANTILES)
+    }
+
+    /// Fit the distributions with `intervals` intervals of the empirical CDF.
+    pub fn with_quantiles(samples: &[f64], intervals: usize) -> Option<Self> {
+        Some(Self {
+            empirical: Empirical::fit(samples, intervals)?,
+            samples: samples.len(),
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            lognormal: LogNormal::fit(samples),
+            exponential: Exponential::fit(samples),
+        })
+    }
+
+    /// Get the gap model of the generator for inter-arrival times in
+    /// seconds, i.e. a Poisson process of the exponential rate.
+    ///
+    /// Without a positive mean gap, packets are sent back to back.
+    pub fn gap_model(&self) -> GapModel {
+        match self.exponential {
+            Some(exponential) => GapModel::Poisson {
+                rate: exponential.rate,
+            },
+            None => GapModel::Constant {
+                rate: f64::INFINITY,
+            },
+        }
+    }
+}
+
+/// Samples of a flow.
+#[derive(Clone, Debug, Default)]
+struct FlowSamples {
+    last: Option<Duration>,
+    sizes: Vec<f64>,
+    gaps: Vec<f64>,
+}
+
+/// Analyzer fitting payload size and inter-arrival distributions per
+/// application protocol.
+#[derive(Clone, Debug)]
+pub struct DistributionFitter {
+    classifier: AppClassifier,
+    max_samples: usize,
+    quantiles: usize,
+    flows: HashMap<FlowKey, FlowSamples>,
+}
+
+impl Default for DistributionFitter {
+    fn default() -> Self {
+        Self {
+            classifier: AppClassifier::new(),
+            max_samples: DEFAULT_MAX_SAMPLES,
+            quantiles: DEFAULT_QUANTILES,
+            flows: HashMap::new(),
+        }
+    }
+}
+
+impl DistributionFitter {
+    /// Create a new fitter labelling flows with the built-in signatures.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the classifier labelling the flows.
+    pub fn classifier(&mut self, classifier: AppClassifier) -> &mut Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Set the number of samples of each metric kept per flow.
+    pub fn max_samples(&mut self, max_samples: usize) -> &mut Self {
+        self.max_samples = max_samples;
+        self
+    }
+
+    /// Set the number of intervals of the empirical CDFs.
+    pub fn quantiles(&mut self, intervals: usize) -> &mut Self {
+        self.quantiles = intervals;
+        self
+    }
+
+    /// Get the samples of a metric per protocol.
+    fn samples(&self, metric: Metric) -> BTreeMap<AppProtocol, Vec<f64>> {
+        let mut samples: BTreeMap<AppProtocol, Vec<f64>> = BTreeMap::new();
+        for (key, flow) in &self.flows {
+            let protocol = self
+                .classifier
+                .label(key)
+                .map_or(AppProtocol::Unknown, |l| l.protocol);
+            samples.entry(protocol).or_default().extend(match metric {
+                Metric::PayloadSize => &flow.sizes,
+                Metric::InterArrival => &flow.gaps,
+            });
+        }
+        samples
+    }
+
+    /// Fit the distribution of a metric of a protocol.
+    pub fn fit(&self, protocol: &AppProtocol, metric: Metric) -> Option<Fit> {
+        Fit::with_quantiles(self.samples(metric).get(protocol)?, self.quantiles)
+    }
+
+    /// Fit the distributions of all metrics and protocols, ordered by
+    /// protocol.
+    pub fn fits(&self) -> Vec<(AppProtocol, Metric, Fit)> {
+        let mut fits = Vec::new();
+        for metric in [Metric::PayloadSize, Metric::InterArrival] {
+            for (protocol, samples) in self.samples(metric) {
+                if let Some(fit) = Fit::with_quantiles(&samples, self.quantiles) {
+                    fits.push((protocol, metric, fit));
+                }
+            }
+        }
+        fits.sort_by(|(pa, ma, _), (pb, mb, _)| pa.cmp(pb).then(ma.cmp(mb)));
+        fits
+    }
+
+    fn on_payload(&mut self, ts: Duration, key: FlowKey, len: usize) {
+        if len == 0 {
+            return;
+        }
+        trace_flow_growth::<Self, _>(&self.flows, &key.canonical());
+        let flow = self.flows.entry(key.canonical()).or_default();
+        if flow.sizes.len() < self.max_samples {
+            flow.sizes.push(len as f64);
+        }
+        if let Some(last) = flow.last.replace(ts) {
+            if flow.gaps.len() < self.max_samples {
+                flow.gaps.push(ts.saturating_sub(last).as_secs_f64());
+            }
+        }
+    }
+}
+
+impl Analyzer for DistributionFitter {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        self.classifier.on_packet(ts, frame);
+
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        if let Some(ipv4) = eth.ipv4() {
+            let len = payload_len(ipv4.tcp(), ipv4.udp());
+            self.on_payload(ts, FlowKey::from_ipv4(&ipv4), len);
+        } else if let Some(ipv6) = eth.ipv6() {
+            let len = payload_len(ipv6.tcp(), ipv6.udp());
+            self.on_payload(ts, FlowKey::from_ipv6(&ipv6), len);
+        }
+    }
+}
+
+/// Get the length of the transport payload, zero for other transports.
+fn payload_len(tcp: Option<Tcp<&[u8]>>, udp: Option<Udp<&[u8]>>) -> usize {
+    match (tcp, udp) {
+        (Some(tcp), _) => tcp.payload().len(),
+        (None, Some(udp)) => udp.payload().len(),
+        (None, None) => 0,
+    }
+}
+
+impl ToTable for DistributionFitter {
+    /// Convert into a table with one row per protocol and metric.
+    ///
+    /// Parameters of a distribution which could not be fitted are NaN.
+    fn to_table(&self) -> Table {
+        let fits = self.fits();
+        let floats =
+            |f: &dyn Fn(&Fit) -> f64| Column::F64(fits.iter().map(|(_, _, fit)| f(fit)).collect());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "protocol",
+                Column::Str(fits.iter().map(|(p, _, _)| p.to_string()).collect()),
+            )
+            .push(
+                "metric",
+                Column::Str(fits.iter().map(|(_, m, _)| m.to_string()).collect()),
+            )
+            .push(
+                "samples",
+                Column::U64(fits.iter().map(|(_, _, f)| f.samples as u64).collect()),
+            )
+            .push("mean", floats(&|f| f.mean))
+            .push(
+                "lognormal_mu",
+                floats(&|f| f.lognormal.map_or(f64::NAN, |l| l.mu)),
+            )
+            .push(
+                "lognormal_sigma",
+                floats(&|f| f.lognormal.map_or(f64::NAN, |l| l.sigma)),
+            )
+            .push(
+                "exponential_rate",
+                floats(&|f| f.exponential.map_or(f64::NAN, |e| e.rate)),
+            )
+            .push("p50", floats(&|f| f.empirical.quantile(0.5)))
+            .push("p90", floats(&|f| f.empirical.quantile(0.9)))
+            .push("p99", floats(&|f| f.empirical.quantile(0.99)));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::utils::rng::RngContext;
+
+    use super::*;
+
+    #[test]
+    fn fit_dns_distributions() {
+        let mut fitter = DistributionFitter::new();
+        for i in 0..100u64 {
+            let payload = vec![0u8; 20 + (i % 5) as usize * 10];
+            let udp = udp!(src_port: 5353u16, dst_port: 53u16, payload: &payload);
+            let ipv4 = ipv4!(
+                protocol: IpProtocol::Udp,
+                src: Ipv4Addr::new(192, 0, 2, 1),
+                dst: Ipv4Addr::new(192, 0, 2, 2),
+                payload: udp.inner()
+            );
+            let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+            fitter.on_packet(Duration::from_millis(i * 500), eth.inner());
+        }
+
+        let size = fitter.fit(&AppProtocol::Dns, Metric::PayloadSize).unwrap();
+        assert_eq!(size.samples, 100);
+        assert_eq!(size.mean, 40.0);
+        assert_eq!(size.empirical.quantile(0.0), 20.0);
+        assert_eq!(size.empirical.quantile(1.0), 60.0);
+        assert_eq!(size.empirical.cdf(10.0), 0.0);
+        assert_eq!(size.empirical.cdf(60.0), 1.0);
+
+        let gap = fitter.fit(&AppProtocol::Dns, Metric::InterArrival).unwrap();
+        assert_eq!(gap.samples, 99);
+        assert_eq!(gap.gap_model(), GapModel::Poisson { rate: 2.0 });
+        let lognormal = gap.lognormal.unwrap();
+        assert!((lognormal.mu - 0.5f64.ln()).abs() < 1e-9 && lognormal.sigma < 1e-9);
+
+        let sampled = RngContext::new(1).enter(|| size.empirical.sample());
+        assert!((20.0..=60.0).contains(&sampled));
+        assert!(fitter
+            .fit(&AppProtocol::Http, Metric::PayloadSize)
+            .is_none());
+
+        let table = fitter.to_table();
+        assert_eq!(table.len(), 2);
+    }
+}
@@ -0,0 +1,252 @@
+//! Frame size accounting and IPv4 length sanity checks.
+//!
+//! Jumbo frames are common in data centers and storage networks, and
+//! segmentation offloads make captures contain frames far larger than the
+//! MTU. This analyzer counts them, and reports packets whose IPv4 total
+//! length disagrees with the number of bytes captured, which points at
+//! truncated captures, offloads or broken encapsulation.
+
+use std::{
+    io::{self, Read, Write},
+    net::Ipv4Addr,
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::{checkpoint::*, Analyzer};
+
+/// Largest standard Ethernet frame (1500-byte MTU, VLAN tagged, with FCS).
+pub const MAX_STANDARD_FRAME: usize = 1522;
+
+/// Largest jumbo Ethernet frame (9000-byte MTU, VLAN tagged, with FCS).
+///
+/// Larger frames are counted as super-jumbo.
+pub const MAX_JUMBO_FRAME: usize = 9022;
+
+/// Minimum Ethernet frame length without FCS, below which frames are
+/// padded.
+pub const MIN_FRAME: usize = 60;
+
+/// IPv4 packet whose total length disagrees with its captured length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LengthMismatch {
+    /// Timestamp of the packet.
+    pub ts: Duration,
+    /// Source address.
+    pub src: Ipv4Addr,
+    /// Destination address.
+    pub dst: Ipv4Addr,
+    /// Total length in the IPv4 header.
+    pub total_length: u16,
+    /// Number of IPv4 bytes captured.
+    pub captured: usize,
+}
+
+impl LengthMismatch {
+    /// Whether fewer bytes were captured than the total length, e.g. with a
+    /// snapshot length.
+    pub fn is_truncated(&self) -> bool {
+        self.captured < self.total_length as usize
+    }
+}
+
+/// Frame size analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct FrameSizeAnalyzer {
+    frames: u64,
+    jumbo: u64,
+    super_jumbo: u64,
+    largest: usize,
+    mismatches: Vec<LengthMismatch>,
+}
+
+impl FrameSizeAnalyzer {
+    /// Create a new frame size analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of frames seen.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Get the number of jumbo frames, larger than [`MAX_STANDARD_FRAME`]
+    /// and at most [`MAX_JUMBO_FRAME`].
+    pub fn jumbo(&self) -> u64 {
+        self.jumbo
+    }
+
+    /// Get the number of super-jumbo frames, larger than
+    /// [`MAX_JUMBO_FRAME`].
+    pub fn super_jumbo(&self) -> u64 {
+        self.super_jumbo
+    }
+
+    /// Get the length of the largest frame seen.
+    pub fn largest(&self) -> usize {
+        self.largest
+    }
+
+    /// Get the length mismatches detected so far.
+    pub fn mismatches(&self) -> &[LengthMismatch] {
+        &self.mismatches
+    }
+
+    /// Take the length mismatches detected so far.
+    pub fn take_mismatches(&mut self) -> Vec<LengthMismatch> {
+        std::mem::take(&mut self.mismatches)
+    }
+}
+
+impl Analyzer for FrameSizeAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        self.frames += 1;
+        self.largest = self.largest.max(frame.len());
+        if frame.len() > MAX_JUMBO_FRAME {
+            self.super_jumbo += 1;
+        } else if frame.len() > MAX_STANDARD_FRAME {
+            self.jumbo += 1;
+        }
+
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+
+        let total_length = ipv4.total_length().get();
+        let captured = ipv4.inner().len();
+        // Short frames are padded up to the Ethernet minimum.
+        let padded = frame.len() <= MIN_FRAME && captured > total_length as usize;
+        if captured != total_length as usize && !padded {
+            self.mismatches.push(LengthMismatch {
+                ts,
+                src: ipv4.src().get(),
+                dst: ipv4.dst().get(),
+                total_length,
+                captured,
+            });
+        }
+    }
+}
+
+impl Checkpoint for FrameSizeAnalyzer {
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u64(writer, self.frames)?;
+        write_u64(writer, self.jumbo)?;
+        write_u64(writer, self.super_jumbo)?;
+        write_u64(writer, self.largest as u64)?;
+        write_u64(writer, self.mismatches.len() as u64)?;
+        for mismatch in &self.mismatches {
+            write_duration(writer, mismatch.ts)?;
+            writer.write_all(&mismatch.src.octets())?;
+            writer.write_all(&mismatch.dst.octets())?;
+            write_u32(writer, mismatch.total_length as u32)?;
+            write_u64(writer, mismatch.captured as u64)?;
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        self.frames = read_u64(reader)?;
+        self.jumbo = read_u64(reader)?;
+        self.super_jumbo = read_u64(reader)?;
+        self.largest = read_u64(reader)? as usize;
+        let len = read_u64(reader)?;
+        self.mismatches.clear();
+        for _ in 0..len {
+            let ts = read_duration(reader)?;
+            let mut addrs = [0; 8];
+            reader.read_exact(&mut addrs)?;
+            self.mismatches.push(LengthMismatch {
+                ts,
+                src: Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]),
+                dst: Ipv4Addr::new(addrs[4], addrs[5], addrs[6], addrs[7]),
+                total_length: read_u32(reader)? as u16,
+                captured: read_u64(reader)? as usize,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: usize) -> Vec<u8> {
+        let udp = udp!(payload: vec![0xAB; payload]);
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn frame_size_jumbo() {
+        let mut analyzer = FrameSizeAnalyzer::new();
+        for payload in [0, 1472, 4000, 8972, 9000, 60000] {
+            analyzer.on_packet(Duration::ZERO, &frame(payload));
+        }
+
+        assert_eq!(analyzer.frames(), 6);
+        assert_eq!(analyzer.jumbo(), 2);
+        assert_eq!(analyzer.super_jumbo(), 2);
+        assert_eq!(analyzer.largest(), 14 + 20 + 8 + 60000);
+        assert!(analyzer.mismatches().is_empty());
+    }
+
+    #[test]
+    fn frame_size_vlan_tagged() {
+        let tagged = |len: usize| {
+            let udp = udp!(payload: vec![0xAB; len - 14 - 4 - 20 - 8]);
+            let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+            let tag = vlan!(vid: 10u16, eth_type: EthType::Ipv4, payload: ipv4.inner());
+            eth!(eth_type: EthType::Vlan, payload: tag.inner())
+                .inner()
+                .clone()
+        };
+
+        let mut analyzer = FrameSizeAnalyzer::new();
+        for len in [MAX_STANDARD_FRAME, MAX_STANDARD_FRAME + 1] {
+            let frame = tagged(len);
+            assert_eq!(frame.len(), len);
+            analyzer.on_packet(Duration::ZERO, &frame);
+        }
+        assert_eq!(analyzer.jumbo(), 1);
+        assert_eq!(analyzer.largest(), 1523);
+
+        analyzer.on_packet(Duration::ZERO, &tagged(MAX_JUMBO_FRAME));
+        analyzer.on_packet(Duration::ZERO, &tagged(MAX_JUMBO_FRAME + 1));
+        assert_eq!((analyzer.jumbo(), analyzer.super_jumbo()), (2, 1));
+    }
+
+    #[test]
+    fn frame_size_mismatch() {
+        let mut analyzer = FrameSizeAnalyzer::new();
+
+        // Truncated by a snapshot length.
+        let jumbo = frame(8972);
+        analyzer.on_packet(Duration::from_secs(1), &jumbo[..1514]);
+
+        // Padded, both legitimately and beyond the Ethernet minimum.
+        let mut short = frame(4);
+        short.resize(MIN_FRAME, 0);
+        analyzer.on_packet(Duration::from_secs(2), &short);
+        let mut padded = frame(100);
+        padded.extend_from_slice(&[0; 8]);
+        analyzer.on_packet(Duration::from_secs(3), &padded);
+
+        let mismatches = analyzer.take_mismatches();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].total_length, 9000);
+        assert_eq!(mismatches[0].captured, 1500);
+        assert!(mismatches[0].is_truncated());
+        assert_eq!(mismatches[1].ts, Duration::from_secs(3));
+        assert_eq!(mismatches[1].captured, 136);
+        assert!(!mismatches[1].is_truncated());
+    }
+}
@@ -0,0 +1,560 @@
+//! Dual-stack Happy Eyeballs connection races.
+//!
+//! A Happy Eyeballs client (RFC 8305) resolves a name with parallel A and
+//! AAAA queries, then races connection attempts over both address families,
+//! preferring IPv6 and falling back to IPv4 after a short delay. When IPv6
+//! is broken the race still succeeds, only slower, so the brokenness goes
+//! unnoticed unless the races are looked at.
+//!
+//! This analyzer follows the DNS lookups and the TCP SYNs of the clients of
+//! a capture. Attempts to the addresses of one name and port started within
+//! the race window form a race, won by the first attempt answered with a
+//! SYN-ACK. Clients are told apart by MAC address, which a dual-stack host
+//! shares between its IPv4 and IPv6 addresses, so the capture should be
+//! taken on the client's link. Attempts to addresses not seen in a DNS
+//! answer are attributed to the address itself.
+
+use std::{collections::HashMap, fmt, net::IpAddr, time::Duration};
+
+use netkit_packet::{
+    layer::{
+        dns::{DnsRData, DnsRrType},
+        tcp::TcpFlags,
+    },
+    prelude::*,
+};
+
+use super::Analyzer;
+use crate::export::{Column, Table, ToTable};
+
+/// Default window in which connection attempts join a race.
+pub const DEFAULT_RACE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Address family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IpFamily {
+    /// IPv4.
+    V4,
+    /// IPv6.
+    V6,
+}
+
+impl IpFamily {
+    fn of(addr: &IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(_) => IpFamily::V4,
+            IpAddr::V6(_) => IpFamily::V6,
+        }
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpFamily::V4 => write!(f, "IPv4"),
+            IpFamily::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// A and AAAA lookup of a name by a client.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DnsLookup {
+    /// Timestamp of the A query.
+    pub a_query: Option<Duration>,
+    /// Timestamp of the AAAA query.
+    pub aaaa_query: Option<Duration>,
+    /// Timestamp of the A answer.
+    pub a_answer: Option<Duration>,
+    /// Timestamp of the AAAA answer.
+    pub aaaa_answer: Option<Duration>,
+}
+
+impl DnsLookup {
+    /// Get the delay between the A and AAAA queries, if both were sent.
+    pub fn query_gap(&self) -> Option<Duration> {
+        let (a, aaaa) = (self.a_query?, self.aaaa_query?);
+        Some(a.max(aaaa) - a.min(aaaa))
+    }
+}
+
+/// A connection attempt of a race.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attempt {
+    /// Source address.
+    pub src: IpAddr,
+    /// Destination address.
+    pub dst: IpAddr,
+    /// Source port of the client.
+    pub src_port: u16,
+    /// Timestamp of the first SYN.
+    pub syn: Duration,
+    /// Number of SYNs sent, including retransmissions.
+    pub syns: u64,
+    /// Timestamp of the SYN-ACK, if answered.
+    pub answered: Option<Duration>,
+    /// Whether the destination reset the attempt.
+    pub reset: bool,
+}
+
+impl Attempt {
+    /// Get the family of the attempt.
+    pub fn family(&self) -> IpFamily {
+        IpFamily::of(&self.dst)
+    }
+}
+
+/// Connection attempts of a client to a destination started together.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Race {
+    /// Client MAC address, as a dual-stack host has distinct IPv4 and IPv6
+    /// addresses.
+    pub client: EthAddr,
+    /// Destination name, or address when not resolved in the capture.
+    pub destination: String,
+    /// Destination port.
+    pub port: u16,
+    /// Lookup of the destination name, if seen.
+    pub lookup: Option<DnsLookup>,
+    /// Attempts in the order they were started.
+    pub attempts: Vec<Attempt>,
+}
+
+impl Race {
+    /// Get the timestamp of the first attempt.
+    pub fn start(&self) -> Duration {
+        self.attempts.first().map_or(Duration::ZERO, |a| a.syn)
+    }
+
+    /// Get the first attempt answered.
+    pub fn winner(&self) -> Option<&Attempt> {
+        self.attempts
+            .iter()
+            .filter(|a| a.answered.is_some())
+            .min_by_key(|a| a.answered)
+    }
+
+    /// Get the delay from the first attempt to the winning SYN-ACK.
+    pub fn connect_time(&self) -> Option<Duration> {
+        Some(self.winner()?.answered? - self.start())
+    }
+
+    /// Get the delay between the first attempt of each family, if both were
+    /// attempted.
+    pub fn fallback_delay(&self) -> Option<Duration> {
+        let first = |family| {
+            self.attempts
+                .iter()
+                .find(|a| a.family() == family)
+                .map(|a| a.syn)
+        };
+        let (v4, v6) = (first(IpFamily::V4)?, first(IpFamily::V6)?);
+        Some(v4.max(v6) - v4.min(v6))
+    }
+
+    /// Whether IPv6 was attempted and lost to IPv4 without any IPv6 answer.
+    pub fn ipv6_broken(&self) -> bool {
+        let ipv6 = || self.attempts.iter().filter(|a| a.family() == IpFamily::V6);
+        self.winner().map(Attempt::family) == Some(IpFamily::V4)
+            && ipv6().next().is_some()
+            && ipv6().all(|a| a.answered.is_none())
+    }
+}
+
+/// Summary of the races to a destination.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DestinationReport {
+    /// Number of races.
+    pub races: u64,
+    /// Number of races won over IPv6.
+    pub ipv6_wins: u64,
+    /// Number of races won over IPv4.
+    pub ipv4_wins: u64,
+    /// Number of races without any answer.
+    pub failures: u64,
+    /// Number of races where IPv6 was attempted, unanswered, and IPv4 won.
+    pub ipv6_broken: u64,
+    /// Mean connect time of the races won.
+    pub mean_connect_time: Option<Duration>,
+    /// Mean delay between the families, in races attempting both.
+    pub mean_fallback_delay: Option<Duration>,
+}
+
+fn mean(values: &[Duration]) -> Option<Duration> {
+    (!values.is_empty()).then(|| values.iter().sum::<Duration>() / values.len() as u32)
+}
+
+type AttemptKey = (IpAddr, u16, IpAddr, u16);
+
+/// Happy Eyeballs analyzer.
+#[derive(Clone, Debug)]
+pub struct HappyEyeballsAnalyzer {
+    race_window: Duration,
+    names: HashMap<IpAddr, String>,
+    lookups: HashMap<(EthAddr, String), DnsLookup>,
+    races: Vec<Race>,
+    open: HashMap<(EthAddr, String, u16), usize>,
+    attempts: HashMap<AttemptKey, (usize, usize)>,
+}
+
+impl Default for HappyEyeballsAnalyzer {
+    fn default() -> Self {
+        Self {
+            race_window: DEFAULT_RACE_WINDOW,
+            names: HashMap::new(),
+            lookups: HashMap::new(),
+            races: Vec::new(),
+            open: HashMap::new(),
+            attempts: HashMap::new(),
+        }
+    }
+}
+
+impl HappyEyeballsAnalyzer {
+    /// Create a new Happy Eyeballs analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the window in which connection attempts join a race.
+    pub fn race_window(&mut self, race_window: Duration) -> &mut Self {
+        self.race_window = race_window;
+        self
+    }
+
+    /// Get the races, in the order they started.
+    pub fn races(&self) -> &[Race] {
+        &self.races
+    }
+
+    /// Get the summary of the races to each destination and port, ordered
+    /// by destination.
+    pub fn reports(&self) -> Vec<((String, u16), DestinationReport)> {
+        let mut grouped: HashMap<(String, u16), Vec<&Race>> = HashMap::new();
+        for race in &self.races {
+            grouped
+                .entry((race.destination.clone(), race.port))
+                .or_default()
+                .push(race);
+        }
+
+        let mut reports: Vec<_> = grouped
+            .into_iter()
+            .map(|(key, races)| {
+                let mut report = DestinationReport {
+                    races: races.len() as u64,
+                    ..Default::default()
+                };
+                for race in &races {
+                    match race.winner().map(Attempt::family) {
+                        Some(IpFamily::V6) => report.ipv6_wins += 1,
+                        Some(IpFamily::V4) => report.ipv4_wins += 1,
+                        None => report.failures += 1,
+                    }
+                    report.ipv6_broken += race.ipv6_broken() as u64;
+                }
+                let connect: Vec<_> = races.iter().filter_map(|r| r.connect_time()).collect();
+                let fallback: Vec<_> = races.iter().filter_map(|r| r.fallback_delay()).collect();
+                report.mean_connect_time = mean(&connect);
+                report.mean_fallback_delay = mean(&fallback);
+                (key, report)
+            })
+            .collect();
+        reports.sort_by(|(a, _), (b, _)| a.cmp(b));
+        reports
+    }
+
+    fn on_dns<T: AsRef<[u8]>>(&mut self, ts: Duration, client: EthAddr, dns: &Dns<T>) {
+        let Some(question) = dns.questions().next() else {
+            return;
+        };
+        let mut name = question.qname().to_string();
+        name.make_ascii_lowercase();
+        let name = name.trim_end_matches('.').to_string();
+        let qtype = question.qtype().get();
+        if qtype != DnsRrType::A && qtype != DnsRrType::AAAA {
+            return;
+        }
+
+        let answer = dns.qr().get();
+        if answer {
+            for record in dns.records() {
+                let addr = match record.rdata {
+                    DnsRData::A(addr) => IpAddr::V4(addr),
+                    DnsRData::Aaaa(addr) => IpAddr::V6(addr),
+                    _ => continue,
+                };
+                self.names.insert(addr, name.clone());
+            }
+        }
+
+        let lookup = self.lookups.entry((client, name)).or_default();
+        // A repeated query starts a new lookup
+        let sent = match qtype {
+            DnsRrType::A => lookup.a_query,
+            _ => lookup.aaaa_query,
+        };
+        if !answer && sent.is_some() {
+            *lookup = DnsLookup::default();
+        }
+        let slot = match (qtype, answer) {
+            (DnsRrType::A, false) => &mut lookup.a_query,
+            (DnsRrType::A, true) => &mut lookup.a_answer,
+            (_, false) => &mut lookup.aaaa_query,
+            (_, true) => &mut lookup.aaaa_answer,
+        };
+        *slot = Some(ts);
+    }
+
+    fn on_transport(
+        &mut self,
+        ts: Duration,
+        macs: (EthAddr, EthAddr),
+        src: IpAddr,
+        dst: IpAddr,
+        tcp: Option<Tcp<&[u8]>>,
+        udp: Option<Udp<&[u8]>>,
+    ) {
+        if let Some(tcp) = tcp {
+            self.on_tcp(ts, macs.0, src, dst, &tcp);
+        } else if let Some(dns) = udp.as_ref().and_then(|udp| udp.dns()) {
+            let client = if dns.qr().get() { macs.1 } else { macs.0 };
+            self.on_dns(ts, client, &dns);
+        }
+    }
+
+    fn on_tcp(&mut self, ts: Duration, mac: EthAddr, src: IpAddr, dst: IpAddr, tcp: &Tcp<&[u8]>) {
+        let flags = tcp.flags().get();
+        let (src_port, dst_port) = (tcp.src_port().get(), tcp.dst_port().get());
+
+        if flags.contains(TcpFlags::SYN) && !flags.contains(TcpFlags::ACK) {
+            let key = (src, src_port, dst, dst_port);
+            if let Some(&(race, attempt)) = self.attempts.get(&key) {
+                self.races[race].attempts[attempt].syns += 1;
+                return;
+            }
+
+            let destination = self
+                .names
+                .get(&dst)
+                .cloned()
+                .unwrap_or_else(|| dst.to_string());
+            let open = (mac, destination.clone(), dst_port);
+            let race = match self.open.get(&open) {
+                Some(&race) if ts.saturating_sub(self.races[race].start()) <= self.race_window => {
+                    race
+                }
+                _ => {
+                    self.races.push(Race {
+                        client: mac,
+                        lookup: self.lookups.get(&(mac, destination.clone())).cloned(),
+                        destination,
+                        port: dst_port,
+                        attempts: Vec::new(),
+                    });
+                    self.open.insert(open, self.races.len() - 1);
+                    self.races.len() - 1
+                }
+            };
+
+            let attempts = &mut self.races[race].attempts;
+            attempts.push(Attempt {
+                src,
+                dst,
+                src_port,
+                syn: ts,
+                syns: 1,
+                answered: None,
+                reset: false,
+            });
+            self.attempts.insert(key, (race, attempts.len() - 1));
+            return;
+        }
+
+        // Answers travel from the destination back to the client
+        let Some(&(race, attempt)) = self.attempts.get(&(dst, dst_port, src, src_port)) else {
+            return;
+        };
+        let attempt = &mut self.races[race].attempts[attempt];
+        if flags.contains(TcpFlags::SYN | TcpFlags::ACK) {
+            attempt.answered.get_or_insert(ts);
+        } else if flags.contains(TcpFlags::RST) && attempt.answered.is_none() {
+            attempt.reset = true;
+        }
+    }
+}
+
+impl Analyzer for HappyEyeballsAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        let macs = (eth.src().get(), eth.dst().get());
+        if let Some(ipv4) = eth.ipv4() {
+            let (src, dst) = (ipv4.src().get().into(), ipv4.dst().get().into());
+            self.on_transport(ts, macs, src, dst, ipv4.tcp(), ipv4.udp());
+        } else if let Some(ipv6) = eth.ipv6() {
+            let (src, dst) = (ipv6.src().get().into(), ipv6.dst().get().into());
+            self.on_transport(ts, macs, src, dst, ipv6.tcp(), ipv6.udp());
+        }
+    }
+}
+
+impl ToTable for HappyEyeballsAnalyzer {
+    /// Convert into a table with one row per destination and port.
+    fn to_table(&self) -> Table {
+        let reports = self.reports();
+        let u64s = |f: fn(&DestinationReport) -> u64| {
+            Column::U64(reports.iter().map(|(_, r)| f(r)).collect())
+        };
+        let millis = |f: fn(&DestinationReport) -> Option<Duration>| {
+            Column::F64(
+                reports
+                    .iter()
+                    .map(|(_, r)| f(r).map_or(f64::NAN, |d| d.as_secs_f64() * 1e3))
+                    .collect(),
+            )
+        };
+
+        let mut table = Table::new();
+        table
+            .push(
+                "destination",
+                Column::Str(reports.iter().map(|((d, _), _)| d.clone()).collect()),
+            )
+            .push(
+                "port",
+                Column::U64(reports.iter().map(|((_, p), _)| *p as u64).collect()),
+            )
+            .push("races", u64s(|r| r.races))
+            .push("ipv6_wins", u64s(|r| r.ipv6_wins))
+            .push("ipv4_wins", u64s(|r| r.ipv4_wins))
+            .push("failures", u64s(|r| r.failures))
+            .push("ipv6_broken", u64s(|r| r.ipv6_broken))
+            .push("connect_ms", millis(|r| r.mean_connect_time))
+            .push("fallback_delay_ms", millis(|r| r.mean_fallback_delay));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use netkit_packet::{
+        dns_question,
+        layer::dns::{DnsBuilder, DnsRecord},
+    };
+
+    use super::*;
+
+    const CLIENT4: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const SERVER4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 80);
+    const RESOLVER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+
+    fn client6() -> Ipv6Addr {
+        "2001:db8:1::10".parse().unwrap()
+    }
+
+    fn server6() -> Ipv6Addr {
+        "2001:db8:2::80".parse().unwrap()
+    }
+
+    fn ip_frame(src: IpAddr, dst: IpAddr, protocol: IpProtocol, payload: &[u8]) -> Vec<u8> {
+        match (src, dst) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                let ipv4 = ipv4!(src: src, dst: dst, protocol: protocol, payload: payload);
+                eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                let ipv6 = ipv6!(src: src, dst: dst, next_header: protocol, payload: payload);
+                eth!(eth_type: EthType::Ipv6, payload: ipv6.inner())
+            }
+            _ => unreachable!(),
+        }
+        .inner()
+        .clone()
+    }
+
+    fn dns(qtype: DnsRrType, answer: Option<DnsRData>) -> Vec<u8> {
+        let mut builder = DnsBuilder::new();
+        builder.questions(dns_question!(qname: "www.example.com", qtype: qtype));
+        let (src, dst, ports) = match answer {
+            Some(rdata) => {
+                builder
+                    .qr(true)
+                    .answers(DnsRecord::new("www.example.com", 60, rdata));
+                (RESOLVER, CLIENT4, (53u16, 50000u16))
+            }
+            None => (CLIENT4, RESOLVER, (50000, 53)),
+        };
+        let dns = builder.build();
+        let udp = udp!(src_port: ports.0, dst_port: ports.1, payload: dns.inner());
+        ip_frame(src.into(), dst.into(), IpProtocol::Udp, udp.inner())
+    }
+
+    fn tcp(src: IpAddr, dst: IpAddr, ports: (u16, u16), flags: TcpFlags) -> Vec<u8> {
+        let tcp = tcp!(src_port: ports.0, dst_port: ports.1, flags: flags);
+        ip_frame(src, dst, IpProtocol::Tcp, tcp.inner())
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn happy_eyeballs_fallback() {
+        let (c4, s4) = (IpAddr::from(CLIENT4), IpAddr::from(SERVER4));
+        let (c6, s6) = (IpAddr::from(client6()), IpAddr::from(server6()));
+        let syn = TcpFlags::SYN;
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+
+        let mut analyzer = HappyEyeballsAnalyzer::new();
+        let packets = [
+            (0, dns(DnsRrType::AAAA, None)),
+            (1, dns(DnsRrType::A, None)),
+            (20, dns(DnsRrType::AAAA, Some(DnsRData::Aaaa(server6())))),
+            (21, dns(DnsRrType::A, Some(DnsRData::A(SERVER4)))),
+            // IPv6 is unanswered, IPv4 wins after the fallback delay
+            (25, tcp(c6, s6, (40000, 443), syn)),
+            (275, tcp(c4, s4, (40001, 443), syn)),
+            (300, tcp(s4, c4, (443, 40001), syn_ack)),
+            (1025, tcp(c6, s6, (40000, 443), syn)),
+            // A later race won over IPv6
+            (10_000, tcp(c6, s6, (40002, 443), syn)),
+            (10_020, tcp(s6, c6, (443, 40002), syn_ack)),
+        ];
+        for (ts, packet) in &packets {
+            analyzer.on_packet(ms(*ts), packet);
+        }
+
+        let races = analyzer.races();
+        assert_eq!(races.len(), 2);
+        let race = &races[0];
+        assert_eq!(race.destination, "www.example.com");
+        assert_eq!(race.attempts.len(), 2);
+        assert_eq!(race.attempts[0].syns, 2);
+        assert_eq!(race.winner().unwrap().family(), IpFamily::V4);
+        assert_eq!(race.connect_time(), Some(ms(275)));
+        assert_eq!(race.fallback_delay(), Some(ms(250)));
+        assert!(race.ipv6_broken());
+        let lookup = race.lookup.as_ref().unwrap();
+        assert_eq!(lookup.query_gap(), Some(ms(1)));
+        assert_eq!(lookup.a_answer, Some(ms(21)));
+
+        let reports = analyzer.reports();
+        assert_eq!(reports.len(), 1);
+        let ((destination, port), report) = &reports[0];
+        assert_eq!((destination.as_str(), *port), ("www.example.com", 443));
+        assert_eq!(report.races, 2);
+        assert_eq!(report.ipv6_wins, 1);
+        assert_eq!(report.ipv4_wins, 1);
+        assert_eq!(report.ipv6_broken, 1);
+        assert_eq!(report.mean_connect_time, Some(ms(285) / 2 + ms(5)));
+        assert_eq!(report.mean_fallback_delay, Some(ms(250)));
+
+        let table = analyzer.to_table();
+        assert_eq!(table.column("ipv6_broken"), Some(&Column::U64(vec![1])));
+    }
+}
@@ -0,0 +1,900 @@
+//! HTTP/1.x transaction extraction.
+//!
+//! An [`HttpStream`] parses the messages of one direction of a reassembled
+//! TCP stream: heads, and bodies framed by `Content-Length`, chunked
+//! transfer coding, or the end of the connection. [`HttpAnalyzer`] pairs the
+//! requests of a connection with its responses in order, which handles
+//! pipelined requests, and records each transaction with its sizes and
+//! timing. Connections upgraded to WebSocket go on with
+//! [`websocket`](super::websocket) frames.
+//!
+//! With the `http-decode` feature, kept bodies can be decoded from their
+//! `Content-Encoding` (gzip, deflate or brotli) with `decode_content`.
+//!
+//! ```
+//! use netkit::analysis::http::{HttpEvent, HttpStream};
+//!
+//! let mut stream = HttpStream::new();
+//! stream.extend(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n");
+//!
+//! let Some(HttpEvent::Head(head)) = stream.next_event() else { unreachable!() };
+//! assert_eq!(head.status(), Some(200));
+//! assert_eq!(stream.next_event(), Some(HttpEvent::Body(b"abc".to_vec())));
+//! assert_eq!(stream.next_event(), Some(HttpEvent::End));
+//! ```
+
+use std::{collections::VecDeque, time::Duration};
+
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+
+use super::{
+    stream::{StreamSegment, TcpReassembler},
+    websocket::{WebSocketRecord, WebSocketStream},
+    Analyzer,
+};
+use crate::export::{Column, Table, ToTable};
+
+/// Maximum length of a message head; longer heads are not HTTP.
+pub const MAX_HEAD_LENGTH: usize = 64 * 1024;
+
+/// Start line of an HTTP message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HttpStart {
+    /// Request line.
+    Request {
+        /// Method.
+        method: String,
+        /// Request target.
+        uri: String,
+        /// Protocol version, e.g. `HTTP/1.1`.
+        version: String,
+    },
+    /// Status line.
+    Response {
+        /// Protocol version, e.g. `HTTP/1.1`.
+        version: String,
+        /// Status code.
+        status: u16,
+        /// Reason phrase.
+        reason: String,
+    },
+}
+
+/// Head of an HTTP message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpHead {
+    /// Start line.
+    pub start: HttpStart,
+    /// Header fields, in order.
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpHead {
+    /// Parse a head, without the final empty line.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data).ok()?;
+        let mut lines = text.split("\r\n");
+        let mut parts = lines.next()?.splitn(3, ' ');
+        let (first, second, third) = (parts.next()?, parts.next()?, parts.next().unwrap_or(""));
+
+        let start = if first.starts_with("HTTP/") {
+            HttpStart::Response {
+                version: first.to_string(),
+                status: second.parse().ok()?,
+                reason: third.to_string(),
+            }
+        } else if third.starts_with("HTTP/") && first.bytes().all(|b| b.is_ascii_uppercase()) {
+            HttpStart::Request {
+                method: first.to_string(),
+                uri: second.to_string(),
+                version: third.to_string(),
+            }
+        } else {
+            return None;
+        };
+
+        let headers = lines
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect();
+        Some(Self { start, headers })
+    }
+
+    /// Get the value of the first header field with the given name, ignoring
+    /// case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Get the method of a request.
+    pub fn method(&self) -> Option<&str> {
+        match &self.start {
+            HttpStart::Request { method, .. } => Some(method),
+            HttpStart::Response { .. } => None,
+        }
+    }
+
+    /// Get the status code of a response.
+    pub fn status(&self) -> Option<u16> {
+        match self.start {
+            HttpStart::Response { status, .. } => Some(status),
+            HttpStart::Request { .. } => None,
+        }
+    }
+
+    /// Get the body length given by `Content-Length`.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("Content-Length")?.parse().ok()
+    }
+
+    /// Whether the body uses the chunked transfer coding.
+    pub fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding")
+            .is_some_and(|te| te.to_ascii_lowercase().ends_with("chunked"))
+    }
+}
+
+/// Event of an [`HttpStream`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HttpEvent {
+    /// A message head.
+    Head(HttpHead),
+    /// Body bytes, without the chunked framing.
+    Body(Vec<u8>),
+    /// End of a message.
+    End,
+    /// The stream is not HTTP (any more); following bytes are ignored.
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Head,
+    Length(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailer,
+    UntilClose,
+    Ended,
+    Failed,
+}
+
+/// Parser of the HTTP messages of one stream direction
+///
+/// Bytes are [`extend`](Self::extend)ed in and events pulled with
+/// [`next_event`](Self::next_event). The body length of a message is taken
+/// from its head; responses to `HEAD` requests and responses with a 1xx,
+/// 204 or 304 status have no body, which only the caller can tell: call
+/// [`skip_body`](Self::skip_body) after their head.
+#[derive(Clone, Debug)]
+pub struct HttpStream {
+    buf: Vec<u8>,
+    state: State,
+    upgraded: bool,
+}
+
+impl Default for HttpStream {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            state: State::Head,
+            upgraded: false,
+        }
+    }
+}
+
+impl HttpStream {
+    /// Create a new stream parser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append stream bytes.
+    pub fn extend(&mut self, data: &[u8]) {
+        if !matches!(self.state, State::Failed) && !self.upgraded {
+            self.buf.extend_from_slice(data);
+        }
+    }
+
+    /// Mark the end of the stream, ending a body delimited by it.
+    ///
+    /// Returns whether a message ended.
+    pub fn finish(&mut self) -> bool {
+        let ended = self.state == State::UntilClose;
+        if ended {
+            self.state = State::Ended;
+        }
+        ended
+    }
+
+    /// Reset the parser, e.g. after a gap in the stream.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Declare that the message whose head was just returned has no body.
+    pub fn skip_body(&mut self) {
+        self.state = State::Ended;
+    }
+
+    /// Stop parsing, as the connection switched to another protocol.
+    ///
+    /// Returns the bytes received after the last message, which belong to
+    /// the new protocol.
+    pub fn upgrade(&mut self) -> Vec<u8> {
+        self.upgraded = true;
+        std::mem::take(&mut self.buf)
+    }
+
+    /// Whether the connection switched to another protocol.
+    pub fn is_upgraded(&self) -> bool {
+        self.upgraded
+    }
+
+    /// Get the next event from the bytes received so far.
+    pub fn next_event(&mut self) -> Option<HttpEvent> {
+        if self.upgraded {
+            return None;
+        }
+        match self.state {
+            State::Failed => None,
+            State::Ended => {
+                self.state = State::Head;
+                Some(HttpEvent::End)
+            }
+            State::Head => self.head(),
+            State::Length(0) => {
+                self.state = State::Ended;
+                self.next_event()
+            }
+            State::Length(remaining) => {
+                let body = self.take(remaining)?;
+                self.state = State::Length(remaining - body.len() as u64);
+                Some(HttpEvent::Body(body))
+            }
+            State::UntilClose => {
+                let body = self.take(u64::MAX)?;
+                Some(HttpEvent::Body(body))
+            }
+            State::ChunkSize => {
+                let line = self.line()?;
+                let size = line.split(';').next().unwrap_or_default().trim();
+                match u64::from_str_radix(size, 16) {
+                    Ok(0) => self.state = State::Trailer,
+                    Ok(size) => self.state = State::ChunkData(size),
+                    Err(_) => return self.fail(),
+                }
+                self.next_event()
+            }
+            State::ChunkData(remaining) => {
+                let body = self.take(remaining)?;
+                let remaining = remaining - body.len() as u64;
+                self.state = if remaining == 0 {
+                    State::ChunkEnd
+                } else {
+                    State::ChunkData(remaining)
+                };
+                Some(HttpEvent::Body(body))
+            }
+            State::ChunkEnd => {
+                self.line()?;
+                self.state = State::ChunkSize;
+                self.next_event()
+            }
+            State::Trailer => {
+                let line = self.line()?;
+                if line.is_empty() {
+                    self.state = State::Ended;
+                }
+                self.next_event()
+            }
+        }
+    }
+
+    fn head(&mut self) -> Option<HttpEvent> {
+        let Some(end) = self.buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.buf.len() > MAX_HEAD_LENGTH {
+                return self.fail();
+            }
+            return None;
+        };
+        let Some(head) = HttpHead::parse(&self.buf[..end]) else {
+            return self.fail();
+        };
+        self.buf.drain(..end + 4);
+
+        self.state = if head.is_chunked() {
+            State::ChunkSize
+        } else if let Some(len) = head.content_length() {
+            State::Length(len)
+        } else if head.status().is_some() {
+            State::UntilClose
+        } else {
+            State::Length(0)
+        };
+        Some(HttpEvent::Head(head))
+    }
+
+    fn take(&mut self, max: u64) -> Option<Vec<u8>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let len = self.buf.len().min(max.try_into().unwrap_or(usize::MAX));
+        Some(self.buf.drain(..len).collect())
+    }
+
+    fn line(&mut self) -> Option<String> {
+        let end = self.buf.windows(2).position(|w| w == b"\r\n")?;
+        let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+        self.buf.drain(..end + 2);
+        Some(line)
+    }
+
+    fn fail(&mut self) -> Option<HttpEvent> {
+        self.state = State::Failed;
+        self.buf.clear();
+        Some(HttpEvent::Error)
+    }
+}
+
+/// An HTTP request and its response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpTransaction {
+    /// Key of the client to server direction.
+    pub key: FlowKey,
+    /// Request method.
+    pub method: String,
+    /// Request target.
+    pub uri: String,
+    /// `Host` header of the request.
+    pub host: Option<String>,
+    /// Response status code, if a response was seen.
+    pub status: Option<u16>,
+    /// Number of request body bytes.
+    pub request_bytes: u64,
+    /// Number of response body bytes.
+    pub response_bytes: u64,
+    /// Request body, without chunked framing, if kept.
+    pub request_body: Vec<u8>,
+    /// Response body, without chunked framing, if kept.
+    pub response_body: Vec<u8>,
+    /// `Content-Encoding` header of the response.
+    pub response_encoding: Option<String>,
+    /// Timestamp of the request head.
+    pub request_ts: Duration,
+    /// Timestamp of the response head.
+    pub response_ts: Option<Duration>,
+    /// Timestamp of the end of the response.
+    pub end_ts: Option<Duration>,
+}
+
+impl HttpTransaction {
+    /// Get the time from the request head to the response head.
+    pub fn latency(&self) -> Option<Duration> {
+        Some(self.response_ts?.saturating_sub(self.request_ts))
+    }
+
+    /// Get the response body with its content coding removed.
+    ///
+    /// Fails for truncated bodies; see [`HttpAnalyzer::max_body_size`].
+    #[cfg(feature = "http-decode")]
+    pub fn decoded_response_body(&self) -> std::io::Result<Vec<u8>> {
+        match &self.response_encoding {
+            Some(encoding) => decode_content(encoding, &self.response_body),
+            None => Ok(self.response_body.clone()),
+        }
+    }
+}
+
+/// Remove the content codings of a body.
+///
+/// `encoding` is a `Content-Encoding` header: a list of `gzip`, `deflate`,
+/// `br` or `identity` in the order they were applied.
+///
+/// ```
+/// use netkit::analysis::http::decode_content;
+///
+/// // "hi", compressed with deflate
+/// let body = [0x78, 0x9c, 0xcb, 0xc8, 0x04, 0x00, 0x01, 0x3b, 0x00, 0xd2];
+/// assert_eq!(decode_content("deflate", &body).unwrap(), b"hi");
+/// assert!(decode_content("compress", &body).is_err());
+/// ```
+#[cfg(feature = "http-decode")]
+pub fn decode_content(encoding: &str, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::{Error, ErrorKind, Read};
+
+    let mut data = body.to_vec();
+    for coding in encoding.rsplit(',').map(str::trim) {
+        let mut decoded = Vec::new();
+        match coding.to_ascii_lowercase().as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => {
+                flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut decoded)?;
+            }
+            // Some servers send raw deflate data instead of zlib.
+            "deflate" => {
+                if flate2::read::ZlibDecoder::new(&data[..])
+                    .read_to_end(&mut decoded)
+                    .is_err()
+                {
+                    decoded.clear();
+                    flate2::read::DeflateDecoder::new(&data[..]).read_to_end(&mut decoded)?;
+                }
+            }
+            "br" => {
+                brotli::Decompressor::new(&data[..], 4096).read_to_end(&mut decoded)?;
+            }
+            coding => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unsupported content coding: {coding}"),
+                ))
+            }
+        }
+        data = decoded;
+    }
+    Ok(data)
+}
+
+#[derive(Clone, Debug, Default)]
+struct Connection {
+    client: Option<FlowKey>,
+    requests: HttpStream,
+    responses: HttpStream,
+    pending: VecDeque<HttpTransaction>,
+    responding: bool,
+    /// Client and server streams after an upgrade to WebSocket.
+    websocket: Option<(WebSocketStream, WebSocketStream)>,
+}
+
+/// HTTP transaction analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct HttpAnalyzer {
+    reassembler: TcpReassembler,
+    connections: FlowTable<Connection>,
+    transactions: Vec<HttpTransaction>,
+    websocket: Vec<WebSocketRecord>,
+    max_body_size: usize,
+}
+
+impl HttpAnalyzer {
+    /// Create a new HTTP analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of body bytes kept per message, 0 (the default)
+    /// keeping none.
+    ///
+    /// Longer bodies are truncated, but still counted in full.
+    pub fn max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Get the transactions completed so far, in order of completion.
+    ///
+    /// Requests still waiting for their response are added when the
+    /// connection closes, or by [`flush`](Self::flush).
+    pub fn transactions(&self) -> &[HttpTransaction] {
+        &self.transactions
+    }
+
+    /// Get the WebSocket frames of upgraded connections, in order.
+    pub fn websocket_frames(&self) -> &[WebSocketRecord] {
+        &self.websocket
+    }
+
+    /// Add the requests still waiting for their response to the
+    /// transactions.
+    pub fn flush(&mut self) {
+        for (_, connection) in self.connections.iter_mut() {
+            self.transactions.extend(connection.pending.drain(..));
+        }
+    }
+
+    /// Feed reassembled stream bytes, e.g. decrypted TLS application data.
+    pub fn on_segment(&mut self, ts: Duration, segment: StreamSegment) {
+        let canonical = segment.key.canonical();
+        let connection = self.connections.entry(canonical).or_default();
+
+        let client = *connection.client.get_or_insert_with(|| {
+            if HttpHead::parse(head_line(&segment.data)).is_some_and(|h| h.status().is_some()) {
+                segment.key.reversed()
+            } else {
+                segment.key
+            }
+        });
+
+        if connection.requests.is_upgraded() {
+            if let Some((client_ws, server_ws)) = &mut connection.websocket {
+                let ws = if segment.key == client {
+                    client_ws
+                } else {
+                    server_ws
+                };
+                if segment.gap {
+                    ws.fail();
+                }
+                ws.extend(&segment.data);
+            }
+        } else {
+            if segment.gap {
+                // Resynchronize at the next message head; a response cut
+                // short is recorded as is.
+                if segment.key == client {
+                    connection.requests.reset();
+                } else {
+                    connection.responses.reset();
+                    if std::mem::take(&mut connection.responding) {
+                        self.transactions.extend(connection.pending.pop_front());
+                    }
+                }
+            }
+
+            if segment.key == client {
+                connection.requests.extend(&segment.data);
+                on_requests(ts, client, connection, self.max_body_size);
+            } else {
+                connection.responses.extend(&segment.data);
+                on_responses(ts, connection, &mut self.transactions, self.max_body_size);
+                if segment.fin && connection.responses.finish() {
+                    end_response(ts, connection, &mut self.transactions);
+                }
+            }
+        }
+
+        if let Some((client_ws, server_ws)) = &mut connection.websocket {
+            for (ws, key, from_client) in [
+                (client_ws, client, true),
+                (server_ws, client.reversed(), false),
+            ] {
+                while let Some(frame) = ws.next_frame() {
+                    self.websocket.push(WebSocketRecord {
+                        ts,
+                        key,
+                        from_client,
+                        frame,
+                    });
+                }
+            }
+        }
+
+        if segment.fin {
+            if let Some(connection) = self.connections.remove(&canonical) {
+                self.transactions.extend(connection.pending);
+            }
+        }
+    }
+}
+
+/// Get the first line of a segment, to tell requests from responses.
+fn head_line(data: &[u8]) -> &[u8] {
+    let end = data
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .unwrap_or(data.len());
+    &data[..end]
+}
+
+fn on_requests(ts: Duration, client: FlowKey, connection: &mut Connection, max_body: usize) {
+    while let Some(event) = connection.requests.next_event() {
+        match event {
+            HttpEvent::Head(head) => {
+                let HttpStart::Request { method, uri, .. } = &head.start else {
+                    continue;
+                };
+                connection.pending.push_back(HttpTransaction {
+                    key: client,
+                    method: method.clone(),
+                    uri: uri.clone(),
+                    host: head.header("Host").map(str::to_string),
+                    status: None,
+                    request_bytes: 0,
+                    response_bytes: 0,
+                    request_body: Vec::new(),
+                    response_body: Vec::new(),
+                    response_encoding: None,
+                    request_ts: ts,
+                    response_ts: None,
+                    end_ts: None,
+                });
+            }
+            HttpEvent::Body(body) => {
+                if let Some(transaction) = connection.pending.back_mut() {
+                    transaction.request_bytes += body.len() as u64;
+                    keep_body(&mut transaction.request_body, &body, max_body);
+                }
+            }
+            HttpEvent::End | HttpEvent::Error => {}
+        }
+    }
+}
+
+fn on_responses(
+    ts: Duration,
+    connection: &mut Connection,
+    done: &mut Vec<HttpTransaction>,
+    max_body: usize,
+) {
+    while let Some(event) = connection.responses.next_event() {
+        match event {
+            HttpEvent::Head(head) => {
+                let status = head.status().unwrap_or_default();
+                let Some(transaction) = connection.pending.front_mut() else {
+                    continue;
+                };
+                // Interim responses precede the final one.
+                if (100..200).contains(&status) && status != 101 {
+                    connection.responses.skip_body();
+                    continue;
+                }
+                if transaction.method == "HEAD" || status == 204 || status == 304 {
+                    connection.responses.skip_body();
+                }
+                transaction.status = Some(status);
+                transaction.response_encoding = head.header("Content-Encoding").map(str::to_string);
+                transaction.response_ts = Some(ts);
+                connection.responding = true;
+
+                if status == 101 {
+                    let client = connection.requests.upgrade();
+                    let server = connection.responses.upgrade();
+                    end_response(ts, connection, done);
+                    if head
+                        .header("Upgrade")
+                        .is_some_and(|p| p.eq_ignore_ascii_case("websocket"))
+                    {
+                        let mut client_ws = WebSocketStream::new();
+                        client_ws.extend(&client);
+                        let mut server_ws = WebSocketStream::new();
+                        server_ws.extend(&server);
+                        connection.websocket = Some((client_ws, server_ws));
+                    }
+                }
+            }
+            HttpEvent::Body(body) => {
+                if let Some(transaction) = connection.pending.front_mut() {
+                    transaction.response_bytes += body.len() as u64;
+                    keep_body(&mut transaction.response_body, &body, max_body);
+                }
+            }
+            HttpEvent::End => end_response(ts, connection, done),
+            HttpEvent::Error => {}
+        }
+    }
+}
+
+fn keep_body(kept: &mut Vec<u8>, body: &[u8], max_body: usize) {
+    let len = body.len().min(max_body.saturating_sub(kept.len()));
+    kept.extend_from_slice(&body[..len]);
+}
+
+fn end_response(ts: Duration, connection: &mut Connection, done: &mut Vec<HttpTransaction>) {
+    if !std::mem::take(&mut connection.responding) {
+        return;
+    }
+    if let Some(mut transaction) = connection.pending.pop_front() {
+        transaction.end_ts = Some(ts);
+        done.push(transaction);
+    }
+}
+
+impl Analyzer for HttpAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        if let Some(segment) = self.reassembler.push(&ipv4) {
+            self.on_segment(ts, segment);
+        }
+    }
+}
+
+impl ToTable for HttpAnalyzer {
+    /// Convert into a table with one row per transaction.
+    ///
+    /// Timestamps and latencies are in seconds; missing values are `NaN`, and
+    /// a missing status is 0.
+    fn to_table(&self) -> Table {
+        let transactions = &self.transactions;
+        let strs =
+            |f: fn(&HttpTransaction) -> String| Column::Str(transactions.iter().map(f).collect());
+        let u64s =
+            |f: fn(&HttpTransaction) -> u64| Column::U64(transactions.iter().map(f).collect());
+        let f64s =
+            |f: fn(&HttpTransaction) -> f64| Column::F64(transactions.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push("client", strs(|t| t.key.src.to_string()))
+            .push("server", strs(|t| t.key.dst.to_string()))
+            .push("server_port", u64s(|t| t.key.dst_port as u64))
+            .push("method", strs(|t| t.method.clone()))
+            .push("host", strs(|t| t.host.clone().unwrap_or_default()))
+            .push("uri", strs(|t| t.uri.clone()))
+            .push("status", u64s(|t| t.status.unwrap_or(0) as u64))
+            .push("request_bytes", u64s(|t| t.request_bytes))
+            .push("response_bytes", u64s(|t| t.response_bytes))
+            .push("request_ts", f64s(|t| t.request_ts.as_secs_f64()))
+            .push(
+                "latency",
+                f64s(|t| t.latency().map_or(f64::NAN, |l| l.as_secs_f64())),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(from_client: bool, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let (src, dst) = if from_client {
+            ((CLIENT, 40000), (SERVER, 80))
+        } else {
+            ((SERVER, 80), (CLIENT, 40000))
+        };
+        tcp_frame(src, dst, seq, TcpFlags::ACK, payload)
+    }
+
+    #[test]
+    fn http_pipelined_transactions() {
+        let requests: &[u8] = b"GET /a HTTP/1.1\r\nHost: example.com\r\n\r\n\
+            POST /b HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello\
+            HEAD /c HTTP/1.1\r\n\r\n";
+        let responses: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc\
+            HTTP/1.1 100 Continue\r\n\r\n\
+            HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nxy\r\n1\r\nz\r\n0\r\n\r\n\
+            HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n";
+
+        let mut analyzer = HttpAnalyzer::new();
+        analyzer.max_body_size(4);
+        let ms = Duration::from_millis;
+        analyzer.on_packet(ms(0), &frame(true, 1, &requests[..50]));
+        analyzer.on_packet(ms(1), &frame(true, 51, &requests[50..]));
+        analyzer.on_packet(ms(10), &frame(false, 1, &responses[..40]));
+        analyzer.on_packet(ms(20), &frame(false, 41, &responses[40..]));
+
+        let transactions = analyzer.transactions();
+        assert_eq!(transactions.len(), 3);
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|t| {
+                (
+                    t.method.as_str(),
+                    t.uri.as_str(),
+                    t.status,
+                    t.request_bytes,
+                    t.response_bytes,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("GET", "/a", Some(200), 0, 3),
+                ("POST", "/b", Some(201), 5, 3),
+                ("HEAD", "/c", Some(200), 0, 0),
+            ]
+        );
+        assert_eq!(transactions[0].host.as_deref(), Some("example.com"));
+        assert_eq!(transactions[0].key.src, CLIENT);
+        assert_eq!(transactions[0].latency(), Some(ms(10)));
+        assert_eq!(transactions[1].latency(), Some(ms(19)));
+        assert_eq!(transactions[1].request_body, b"hell");
+        assert_eq!(transactions[1].response_body, b"xyz");
+        assert_eq!(analyzer.to_table().len(), 3);
+    }
+
+    #[test]
+    fn http_body_limit() {
+        let request: &[u8] = b"POST /a HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc";
+        let response: &[u8] =
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 3\r\n\r\nxyz";
+
+        let mut analyzer = HttpAnalyzer::new();
+        let ms = Duration::from_millis;
+        analyzer.on_packet(ms(0), &frame(true, 1, request));
+        analyzer.on_packet(ms(1), &frame(false, 1, response));
+
+        let transaction = &analyzer.transactions()[0];
+        assert_eq!(transaction.request_bytes, 3);
+        assert_eq!(transaction.response_bytes, 3);
+        assert!(transaction.request_body.is_empty());
+        assert!(transaction.response_body.is_empty());
+        assert_eq!(transaction.response_encoding.as_deref(), Some("gzip"));
+    }
+
+    #[cfg(feature = "http-decode")]
+    #[test]
+    fn http_decode_content() {
+        use std::io::Write;
+
+        let text = b"hello, hello, hello".repeat(10);
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&text).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22)
+            .write_all(&gzip)
+            .unwrap();
+
+        assert_eq!(decode_content("gzip", &gzip).unwrap(), text);
+        assert_eq!(decode_content("GZIP, identity", &gzip).unwrap(), text);
+        assert_eq!(decode_content("gzip, br", &br).unwrap(), text);
+        assert_eq!(decode_content("", &text).unwrap(), text);
+        let error = decode_content("compress", &gzip).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(decode_content("gzip", &gzip[..gzip.len() / 2]).is_err());
+
+        let request: &[u8] = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let mut responses = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzip.len()
+        )
+        .into_bytes();
+        responses.extend_from_slice(&gzip);
+        responses.extend_from_slice(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+
+        let mut analyzer = HttpAnalyzer::new();
+        analyzer.max_body_size(gzip.len());
+        let ms = Duration::from_millis;
+        analyzer.on_packet(ms(0), &frame(true, 1, request));
+        analyzer.on_packet(ms(1), &frame(false, 1, &responses));
+
+        let transactions = analyzer.transactions();
+        assert_eq!(transactions[0].response_body, gzip);
+        assert_eq!(transactions[0].decoded_response_body().unwrap(), text);
+        assert_eq!(transactions[1].decoded_response_body().unwrap(), b"ok");
+
+        let mut analyzer = HttpAnalyzer::new();
+        analyzer.max_body_size(gzip.len() / 2);
+        analyzer.on_packet(ms(0), &frame(true, 1, request));
+        analyzer.on_packet(ms(1), &frame(false, 1, &responses));
+        assert!(analyzer.transactions()[0].decoded_response_body().is_err());
+    }
+
+    #[test]
+    fn http_websocket_upgrade() {
+        let request: &[u8] =
+            b"GET /chat HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+        let mut response =
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n".to_vec();
+        // Server frame right after the head
+        response.extend_from_slice(&[0x81, 0x02, b'h', b'i']);
+        let client_frame = [0x81, 0x82, 1, 2, 3, 4, b'y' ^ 1, b'o' ^ 2];
+
+        let mut analyzer = HttpAnalyzer::new();
+        let ms = Duration::from_millis;
+        analyzer.on_packet(ms(0), &frame(true, 1, request));
+        analyzer.on_packet(ms(1), &frame(false, 1, &response));
+        let seq = 1 + request.len() as u32;
+        analyzer.on_packet(ms(2), &frame(true, seq, &client_frame));
+
+        assert_eq!(analyzer.transactions()[0].status, Some(101));
+        let frames: Vec<_> = analyzer
+            .websocket_frames()
+            .iter()
+            .map(|r| (r.from_client, r.frame.payload.as_slice()))
+            .collect();
+        assert_eq!(frames, [(false, &b"hi"[..]), (true, &b"yo"[..])]);
+    }
+}
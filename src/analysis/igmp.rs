@@ -0,0 +1,207 @@
+//! IGMP Router Alert validation.
+//!
+//! IGMPv2 ([RFC 2236]) and IGMPv3 ([RFC 3376]) messages must be sent with
+//! the IPv4 Router Alert option, so that routers examine membership reports
+//! for groups they are not yet forwarding. Hosts omitting it work on simple
+//! networks and break subtly behind routers and snooping switches that
+//! enforce it: IGMPv3 routers may drop such messages.
+//!
+//! [RFC 2236]: https://datatracker.ietf.org/doc/html/rfc2236
+//! [RFC 3376]: https://datatracker.ietf.org/doc/html/rfc3376
+
+use std::{net::Ipv4Addr, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use super::Analyzer;
+
+/// IGMP message type of membership queries.
+const TYPE_QUERY: u8 = 0x11;
+/// IGMP message type of IGMPv1 membership reports.
+const TYPE_REPORT_V1: u8 = 0x12;
+/// IGMP message type of IGMPv2 membership reports.
+const TYPE_REPORT_V2: u8 = 0x16;
+/// IGMP message type of IGMPv2 leave group messages.
+const TYPE_LEAVE_V2: u8 = 0x17;
+/// IGMP message type of IGMPv3 membership reports.
+const TYPE_REPORT_V3: u8 = 0x22;
+
+/// IGMP message, with the protocol version it belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IgmpMessage {
+    /// Membership query of the given version.
+    Query(u8),
+    /// Membership report of the given version.
+    Report(u8),
+    /// Leave group (IGMPv2).
+    Leave,
+    /// Any other message type.
+    Other(u8),
+}
+
+impl IgmpMessage {
+    /// Parse the message type of an IGMP message.
+    ///
+    /// Query versions are told apart by length and maximum response time
+    /// (RFC 3376, section 7.1).
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let kind = *data.first()?;
+        let message = match kind {
+            TYPE_QUERY if data.len() >= 12 => Self::Query(3),
+            TYPE_QUERY if data.get(1).copied()? == 0 => Self::Query(1),
+            TYPE_QUERY => Self::Query(2),
+            TYPE_REPORT_V1 => Self::Report(1),
+            TYPE_REPORT_V2 => Self::Report(2),
+            TYPE_LEAVE_V2 => Self::Leave,
+            TYPE_REPORT_V3 => Self::Report(3),
+            _ => Self::Other(kind),
+        };
+        Some(message)
+    }
+
+    /// Get the protocol version of the message, if known.
+    pub fn version(self) -> Option<u8> {
+        match self {
+            Self::Query(version) | Self::Report(version) => Some(version),
+            Self::Leave => Some(2),
+            Self::Other(_) => None,
+        }
+    }
+
+    /// Whether the message must carry the Router Alert option.
+    pub fn requires_router_alert(self) -> bool {
+        matches!(self.version(), Some(2 | 3))
+    }
+}
+
+/// Event detected by [`IgmpAnalyzer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IgmpEvent {
+    /// An IGMPv2 or IGMPv3 message was sent without Router Alert.
+    MissingRouterAlert {
+        /// Timestamp of the packet.
+        ts: Duration,
+        /// Sender of the message.
+        src: Ipv4Addr,
+        /// Destination of the message.
+        dst: Ipv4Addr,
+        /// The message.
+        message: IgmpMessage,
+    },
+}
+
+/// IGMP Router Alert analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct IgmpAnalyzer {
+    messages: u64,
+    missing: u64,
+    events: Vec<IgmpEvent>,
+}
+
+impl IgmpAnalyzer {
+    /// Create a new IGMP analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of IGMP messages seen.
+    pub fn messages(&self) -> u64 {
+        self.messages
+    }
+
+    /// Get the number of messages missing the Router Alert option.
+    pub fn missing_router_alert(&self) -> u64 {
+        self.missing
+    }
+
+    /// Get the events detected so far.
+    pub fn events(&self) -> &[IgmpEvent] {
+        &self.events
+    }
+
+    /// Take the events detected so far.
+    pub fn take_events(&mut self) -> Vec<IgmpEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn on_ipv4(&mut self, ts: Duration, ipv4: &Ipv4<&[u8]>) {
+        if ipv4.protocol().get() != IpProtocol::Igmp {
+            return;
+        }
+        let Some(message) = IgmpMessage::parse(ipv4.payload()) else {
+            return;
+        };
+        self.messages += 1;
+
+        if message.requires_router_alert() && ipv4.router_alert().is_none() {
+            self.missing += 1;
+            self.events.push(IgmpEvent::MissingRouterAlert {
+                ts,
+                src: ipv4.src().get(),
+                dst: ipv4.dst().get(),
+                message,
+            });
+        }
+    }
+}
+
+impl Analyzer for IgmpAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_ipv4(ts, &ipv4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::layer::ip::{Ipv4Builder, OPTION_ROUTER_ALERT};
+
+    use super::*;
+
+    fn frame(igmp: &[u8], router_alert: bool) -> Vec<u8> {
+        let mut ipv4 = Ipv4Builder::new();
+        ipv4.ttl(1u8)
+            .protocol(IpProtocol::Igmp)
+            .src(Ipv4Addr::new(10, 0, 0, 1))
+            .dst(Ipv4Addr::new(224, 0, 0, 22))
+            .payload(igmp);
+        if router_alert {
+            ipv4.options([OPTION_ROUTER_ALERT, 4, 0, 0]);
+        }
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.build().inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn igmp_router_alert() {
+        let report_v3 = [TYPE_REPORT_V3, 0, 0, 0, 0, 0, 0, 0];
+        let report_v1 = [TYPE_REPORT_V1, 0, 0, 0, 224, 0, 0, 1];
+
+        let mut analyzer = IgmpAnalyzer::new();
+        analyzer.on_packet(Duration::ZERO, &frame(&report_v3, true));
+        analyzer.on_packet(Duration::from_secs(1), &frame(&report_v3, false));
+        // IGMPv1 predates Router Alert
+        analyzer.on_packet(Duration::from_secs(2), &frame(&report_v1, false));
+
+        assert_eq!(analyzer.messages(), 3);
+        assert_eq!(analyzer.missing_router_alert(), 1);
+        assert_eq!(
+            analyzer.events(),
+            [IgmpEvent::MissingRouterAlert {
+                ts: Duration::from_secs(1),
+                src: Ipv4Addr::new(10, 0, 0, 1),
+                dst: Ipv4Addr::new(224, 0, 0, 22),
+                message: IgmpMessage::Report(3),
+            }]
+        );
+        assert_eq!(
+            IgmpMessage::parse(&[TYPE_QUERY, 100, 0, 0, 0, 0, 0, 0]),
+            Some(IgmpMessage::Query(2))
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Layer-2 loop and MAC flapping detection.
+//!
+//! Bridging loops show up in captures as the same frame circulating many
+//! times, broadcast rates far above normal, and source MAC addresses
+//! bouncing between VLANs (or ports mapped to VLANs) as the loop carries
+//! frames back into the wrong segment.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::Analyzer;
+
+/// Default window in which VLAN moves of a MAC address are counted.
+pub const DEFAULT_FLAP_WINDOW: Duration = Duration::from_secs(10);
+
+/// Default number of VLAN moves within the window reported as flapping.
+pub const DEFAULT_FLAP_THRESHOLD: usize = 3;
+
+/// Default number of copies of a frame within a second reported as a loop.
+pub const DEFAULT_DUPLICATE_THRESHOLD: u64 = 10;
+
+/// Default number of broadcast frames per second and VLAN reported as a
+/// storm.
+pub const DEFAULT_BROADCAST_THRESHOLD: u64 = 1000;
+
+/// Event detected by [`L2LoopAnalyzer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum L2Event {
+    /// A source MAC address moved between VLANs repeatedly.
+    MacFlap {
+        /// Timestamp of the last move.
+        ts: Duration,
+        /// Flapping MAC address.
+        mac: EthAddr,
+        /// VLAN the address moved from (`None` if untagged).
+        from: Option<u16>,
+        /// VLAN the address moved to (`None` if untagged).
+        to: Option<u16>,
+        /// Number of moves within the flap window.
+        moves: usize,
+    },
+
+    /// The same frame was seen many times within a second.
+    Loop {
+        /// Timestamp at which the threshold was reached.
+        ts: Duration,
+        /// Source MAC address of the frame.
+        mac: EthAddr,
+        /// Number of copies seen.
+        copies: u64,
+    },
+
+    /// The broadcast rate of a VLAN exceeded the threshold.
+    BroadcastStorm {
+        /// Start of the second in which the threshold was reached.
+        ts: Duration,
+        /// VLAN of the broadcasts (`None` if untagged).
+        vlan: Option<u16>,
+        /// Number of broadcast frames in that second so far.
+        frames: u64,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+struct MacState {
+    vlan: Option<u16>,
+    moves: Vec<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Counter {
+    second: u64,
+    count: u64,
+}
+
+impl Counter {
+    /// Count an occurrence, returning the count within the current second.
+    fn count(&mut self, ts: Duration) -> u64 {
+        if self.second != ts.as_secs() {
+            self.second = ts.as_secs();
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count
+    }
+}
+
+/// Layer-2 loop analyzer.
+///
+/// Each condition is reported once when its threshold is reached; a
+/// persisting condition is reported again in every later second (loops and
+/// storms) or flap window (flapping).
+#[derive(Clone, Debug)]
+pub struct L2LoopAnalyzer {
+    flap_window: Duration,
+    flap_threshold: usize,
+    duplicate_threshold: u64,
+    broadcast_threshold: u64,
+    macs: HashMap<EthAddr, MacState>,
+    frames: HashMap<u64, Counter>,
+    broadcasts: HashMap<Option<u16>, Counter>,
+    events: Vec<L2Event>,
+}
+
+impl Default for L2LoopAnalyzer {
+    fn default() -> Self {
+        Self {
+            flap_window: DEFAULT_FLAP_WINDOW,
+            flap_threshold: DEFAULT_FLAP_THRESHOLD,
+            duplicate_threshold: DEFAULT_DUPLICATE_THRESHOLD,
+            broadcast_threshold: DEFAULT_BROADCAST_THRESHOLD,
+            macs: HashMap::new(),
+            frames: HashMap::new(),
+            broadcasts: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl L2LoopAnalyzer {
+    /// Create a new layer-2 loop analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the window in which VLAN moves of a MAC address are counted.
+    pub fn flap_window(&mut self, flap_window: Duration) -> &mut Self {
+        self.flap_window = flap_window;
+        self
+    }
+
+    /// Set the number of VLAN moves within the window reported as flapping.
+    pub fn flap_threshold(&mut self, flap_threshold: usize) -> &mut Self {
+        self.flap_threshold = flap_threshold;
+        self
+    }
+
+    /// Set the number of copies of a frame within a second reported as a
+    /// loop.
+    pub fn duplicate_threshold(&mut self, duplicate_threshold: u64) -> &mut Self {
+        self.duplicate_threshold = duplicate_threshold;
+        self
+    }
+
+    /// Set the number of broadcast frames per second and VLAN reported as a
+    /// storm.
+    pub fn broadcast_threshold(&mut self, broadcast_threshold: u64) -> &mut Self {
+        self.broadcast_threshold = broadcast_threshold;
+        self
+    }
+
+    /// Get the events detected so far.
+    pub fn events(&self) -> &[L2Event] {
+        &self.events
+    }
+
+    /// Take the events detected so far.
+    pub fn take_events(&mut self) -> Vec<L2Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn on_eth(&mut self, ts: Duration, eth: &Eth<&[u8]>) {
+        let src = eth.src().get();
+        let vlan = eth.vlan_ids().first().copied();
+
+        self.check_flap(ts, src, vlan);
+
+        // VLAN tags may be rewritten along the loop, so hash the frame from
+        // the source address on, without tags.
+        let mut hasher = DefaultHasher::new();
+        eth.inner()[Eth::<&[u8]>::FIELD_SRC].hash(&mut hasher);
+        untagged_payload(eth).hash(&mut hasher);
+        let copies = self.frames.entry(hasher.finish()).or_default().count(ts);
+        if copies == self.duplicate_threshold {
+            self.events.push(L2Event::Loop {
+                ts,
+                mac: src,
+                copies,
+            });
+        }
+
+        if eth.dst().get().is_broadcast() {
+            let frames = self.broadcasts.entry(vlan).or_default().count(ts);
+            if frames == self.broadcast_threshold {
+                self.events.push(L2Event::BroadcastStorm {
+                    ts: Duration::from_secs(ts.as_secs()),
+                    vlan,
+                    frames,
+                });
+            }
+        }
+
+        // Forget frames of past seconds to bound memory.
+        if self.frames.len() > 1 << 16 {
+            self.frames.retain(|_, c| c.second == ts.as_secs());
+        }
+    }
+
+    fn check_flap(&mut self, ts: Duration, mac: EthAddr, vlan: Option<u16>) {
+        let Some(state) = self.macs.get_mut(&mac) else {
+            self.macs.insert(
+                mac,
+                MacState {
+                    vlan,
+                    moves: Vec::new(),
+                },
+            );
+            return;
+        };
+        if state.vlan == vlan {
+            return;
+        }
+
+        let from = std::mem::replace(&mut state.vlan, vlan);
+        state
+            .moves
+            .retain(|t| ts.saturating_sub(*t) < self.flap_window);
+        state.moves.push(ts);
+
+        if state.moves.len() >= self.flap_threshold {
+            self.events.push(L2Event::MacFlap {
+                ts,
+                mac,
+                from,
+                to: vlan,
+                moves: state.moves.len(),
+            });
+            state.moves.clear();
+        }
+    }
+}
+
+impl Analyzer for L2LoopAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        if let Ok(eth) = Eth::new(frame) {
+            self.on_eth(ts, &eth);
+        }
+    }
+}
+
+/// Get the payload of the innermost VLAN tag, or of the Eth header.
+fn untagged_payload<'a>(eth: &Eth<&'a [u8]>) -> &'a [u8] {
+    let tags = eth.vlan_ids().len();
+    &eth.inner()[Eth::<&[u8]>::FIELD_ETH_TYPE.start + tags * 4..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+
+    fn frame(dst: EthAddr, vlan: u16, payload: u8) -> Vec<u8> {
+        let tag = vlan!(vid: vlan, eth_type: EthType::Arp, payload: [payload; 28]);
+        eth!(src: MAC, dst: dst, eth_type: EthType::Vlan, payload: tag.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn l2_mac_flap() {
+        let mut analyzer = L2LoopAnalyzer::new();
+        for (i, vlan) in [10, 20, 10, 20].into_iter().enumerate() {
+            analyzer.on_packet(
+                Duration::from_secs(i as u64),
+                &frame(EthAddr::new(2, 0, 0, 0, 0, 2), vlan, i as u8),
+            );
+        }
+
+        assert_eq!(
+            analyzer.events(),
+            [L2Event::MacFlap {
+                ts: Duration::from_secs(3),
+                mac: MAC.into(),
+                from: Some(10),
+                to: Some(20),
+                moves: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn l2_loop_and_storm() {
+        let mut analyzer = L2LoopAnalyzer::new();
+        analyzer.broadcast_threshold(20);
+        for i in 0..20 {
+            let ts = Duration::from_millis(i * 10);
+            analyzer.on_packet(ts, &frame(EthAddr::BROADCAST, 10, 0));
+        }
+
+        let events = analyzer.take_events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], L2Event::Loop { copies: 10, .. }));
+        assert!(matches!(
+            events[1],
+            L2Event::BroadcastStorm {
+                vlan: Some(10),
+                frames: 20,
+                ..
+            }
+        ));
+        assert!(analyzer.events().is_empty());
+    }
+}
@@ -0,0 +1,557 @@
+//! SMTP, IMAP and POP3 command-level analysis.
+//!
+//! Mail protocols are line-based: clients send commands and servers answer
+//! with replies carrying a status (`250` in SMTP, `OK` in IMAP, `+OK` in
+//! POP3). [`MailAnalyzer`] follows the cleartext ports of the three
+//! protocols over reassembled TCP, records commands and replies, and detects
+//! sessions upgrading to TLS with `STARTTLS` (`STLS` in POP3), after which
+//! the rest of the session is encrypted and no longer parsed.
+//!
+//! Message contents (SMTP `DATA`, and multi-line POP3 replies) are skipped.
+//! IMAP literals are not, so lines inside them may show up as commands.
+
+use std::time::Duration;
+
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+
+use super::{
+    stream::{LineBuffer, TcpReassembler},
+    Analyzer,
+};
+use crate::export::{Column, Table, ToTable};
+
+/// Mail protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MailProtocol {
+    /// SMTP, on port 25 (relay) or 587 (submission).
+    Smtp,
+    /// IMAP, on port 143.
+    Imap,
+    /// POP3, on port 110.
+    Pop3,
+}
+
+impl MailProtocol {
+    /// Get the protocol of a cleartext server port.
+    pub fn from_port(port: u16) -> Option<Self> {
+        match port {
+            25 | 587 => Some(MailProtocol::Smtp),
+            143 => Some(MailProtocol::Imap),
+            110 => Some(MailProtocol::Pop3),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the protocol.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MailProtocol::Smtp => "smtp",
+            MailProtocol::Imap => "imap",
+            MailProtocol::Pop3 => "pop3",
+        }
+    }
+
+    /// Get the command upgrading a session to TLS.
+    pub fn starttls_command(&self) -> &'static str {
+        match self {
+            MailProtocol::Pop3 => "STLS",
+            _ => "STARTTLS",
+        }
+    }
+}
+
+/// A client command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MailCommand {
+    /// IMAP tag.
+    pub tag: Option<String>,
+    /// Command verb, in upper case.
+    pub verb: String,
+    /// Rest of the line.
+    pub argument: String,
+}
+
+impl MailCommand {
+    /// Parse a command line.
+    pub fn parse(protocol: MailProtocol, line: &str) -> Option<Self> {
+        let (tag, line) = match protocol {
+            MailProtocol::Imap => {
+                let (tag, rest) = line.split_once(' ')?;
+                (Some(tag.to_string()), rest)
+            }
+            _ => (None, line),
+        };
+        let (verb, argument) = line.split_once(' ').unwrap_or((line, ""));
+        if verb.is_empty() || !verb.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(Self {
+            tag,
+            verb: verb.to_ascii_uppercase(),
+            argument: argument.to_string(),
+        })
+    }
+}
+
+/// A server reply, or its last line for multi-line SMTP replies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MailReply {
+    /// IMAP tag, `*` for untagged and `+` for continuation replies.
+    pub tag: Option<String>,
+    /// Status: the SMTP reply code, the IMAP status (`OK`, `NO`, `BAD`,
+    /// `PREAUTH` or `BYE`) or the POP3 status (`+OK` or `-ERR`).
+    pub status: String,
+    /// Rest of the line.
+    pub text: String,
+}
+
+impl MailReply {
+    /// Parse a reply line.
+    ///
+    /// Returns `None` for lines which are not the end of a reply, such as
+    /// the continued lines of SMTP replies and IMAP untagged data.
+    pub fn parse(protocol: MailProtocol, line: &str) -> Option<Self> {
+        match protocol {
+            MailProtocol::Smtp => {
+                let code = line.get(..3)?;
+                if !code.bytes().all(|b| b.is_ascii_digit())
+                    || line.as_bytes().get(3) == Some(&b'-')
+                {
+                    return None;
+                }
+                Some(Self {
+                    tag: None,
+                    status: code.to_string(),
+                    text: line.get(4..).unwrap_or_default().to_string(),
+                })
+            }
+            MailProtocol::Imap => {
+                let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+                let (status, text) = rest.split_once(' ').unwrap_or((rest, ""));
+                let status = status.to_ascii_uppercase();
+                if tag == "+" {
+                    return Some(Self {
+                        tag: Some(tag.to_string()),
+                        status: String::new(),
+                        text: rest.to_string(),
+                    });
+                }
+                matches!(status.as_str(), "OK" | "NO" | "BAD" | "PREAUTH" | "BYE").then(|| Self {
+                    tag: Some(tag.to_string()),
+                    status,
+                    text: text.to_string(),
+                })
+            }
+            MailProtocol::Pop3 => {
+                let (status, text) = line.split_once(' ').unwrap_or((line, ""));
+                matches!(status, "+OK" | "-ERR").then(|| Self {
+                    tag: None,
+                    status: status.to_string(),
+                    text: text.to_string(),
+                })
+            }
+        }
+    }
+
+    /// Whether the reply reports success.
+    pub fn is_positive(&self) -> bool {
+        matches!(self.status.as_str(), "OK" | "PREAUTH" | "+OK" | "")
+            || self.status.starts_with('2')
+            || self.status.starts_with('3')
+    }
+
+    /// Whether the reply reports an error.
+    pub fn is_error(&self) -> bool {
+        matches!(self.status.as_str(), "NO" | "BAD" | "-ERR")
+            || self.status.starts_with('4')
+            || self.status.starts_with('5')
+    }
+}
+
+/// Event of a mail session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MailEvent {
+    /// The client sent a command.
+    Command {
+        /// Timestamp.
+        ts: Duration,
+        /// Key of the client to server direction.
+        key: FlowKey,
+        /// Command.
+        command: MailCommand,
+    },
+    /// The server sent a reply.
+    Reply {
+        /// Timestamp.
+        ts: Duration,
+        /// Key of the client to server direction.
+        key: FlowKey,
+        /// Reply.
+        reply: MailReply,
+    },
+    /// The server accepted to upgrade the session to TLS.
+    StartTls {
+        /// Timestamp.
+        ts: Duration,
+        /// Key of the client to server direction.
+        key: FlowKey,
+    },
+}
+
+/// A mail session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MailSession {
+    /// Key of the client to server direction.
+    pub key: FlowKey,
+    /// Protocol.
+    pub protocol: MailProtocol,
+    /// Timestamp of the first segment.
+    pub first_seen: Duration,
+    /// Number of commands.
+    pub commands: u64,
+    /// Number of replies.
+    pub replies: u64,
+    /// Number of error replies.
+    pub errors: u64,
+    /// Timestamp of the upgrade to TLS, if any.
+    pub starttls: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SessionState {
+    client: LineBuffer,
+    server: LineBuffer,
+    /// The client is sending an SMTP message.
+    client_data: bool,
+    /// The server is sending a multi-line POP3 reply.
+    server_data: bool,
+    /// Last command sent.
+    last_command: Option<MailCommand>,
+}
+
+/// Mail protocol analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct MailAnalyzer {
+    reassembler: TcpReassembler,
+    sessions: Vec<MailSession>,
+    index: FlowTable<(usize, SessionState)>,
+    events: Vec<MailEvent>,
+}
+
+impl MailAnalyzer {
+    /// Create a new mail analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the sessions in the order they were first seen.
+    pub fn sessions(&self) -> &[MailSession] {
+        &self.sessions
+    }
+
+    /// Get the events so far.
+    pub fn events(&self) -> &[MailEvent] {
+        &self.events
+    }
+
+    /// Take the events so far.
+    pub fn take_events(&mut self) -> Vec<MailEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn on_tcp<T: AsRef<[u8]>>(&mut self, ts: Duration, ipv4: &Ipv4<T>) {
+        let Some(segment) = self.reassembler.push(ipv4) else {
+            return;
+        };
+        let (key, from_client, protocol) =
+            if let Some(p) = MailProtocol::from_port(segment.key.dst_port) {
+                (segment.key, true, p)
+            } else if let Some(p) = MailProtocol::from_port(segment.key.src_port) {
+                (segment.key.reversed(), false, p)
+            } else {
+                return;
+            };
+
+        let canonical = key.canonical();
+        let (id, mut state) = match self.index.remove(&canonical) {
+            Some(entry) => entry,
+            None => {
+                self.sessions.push(MailSession {
+                    key,
+                    protocol,
+                    first_seen: ts,
+                    commands: 0,
+                    replies: 0,
+                    errors: 0,
+                    starttls: None,
+                });
+                (self.sessions.len() - 1, SessionState::default())
+            }
+        };
+        let session = &mut self.sessions[id];
+        if session.starttls.is_some() {
+            // Encrypted from here on.
+            if !segment.fin {
+                self.index.insert(canonical, (id, state));
+            }
+            return;
+        }
+
+        let events = &mut self.events;
+        if from_client {
+            if segment.gap {
+                state.client.clear();
+                state.client_data = false;
+            }
+            let mut lines = std::mem::take(&mut state.client);
+            lines.push(&segment.data, |line| {
+                on_client_line(ts, session, &mut state, events, line)
+            });
+            state.client = lines;
+        } else {
+            if segment.gap {
+                state.server.clear();
+                state.server_data = false;
+            }
+            let mut lines = std::mem::take(&mut state.server);
+            lines.push(&segment.data, |line| {
+                on_server_line(ts, session, &mut state, events, line)
+            });
+            state.server = lines;
+        }
+
+        if !segment.fin {
+            self.index.insert(canonical, (id, state));
+        }
+    }
+}
+
+fn on_client_line(
+    ts: Duration,
+    session: &mut MailSession,
+    state: &mut SessionState,
+    events: &mut Vec<MailEvent>,
+    line: &[u8],
+) {
+    if state.client_data {
+        state.client_data = line != b".";
+        return;
+    }
+    let line = String::from_utf8_lossy(line);
+    let Some(command) = MailCommand::parse(session.protocol, &line) else {
+        return;
+    };
+    session.commands += 1;
+    state.last_command = Some(command.clone());
+    events.push(MailEvent::Command {
+        ts,
+        key: session.key,
+        command,
+    });
+}
+
+fn on_server_line(
+    ts: Duration,
+    session: &mut MailSession,
+    state: &mut SessionState,
+    events: &mut Vec<MailEvent>,
+    line: &[u8],
+) {
+    if state.server_data {
+        state.server_data = line != b".";
+        return;
+    }
+    let line = String::from_utf8_lossy(line);
+    let Some(reply) = MailReply::parse(session.protocol, &line) else {
+        return;
+    };
+    session.replies += 1;
+    if reply.is_error() {
+        session.errors += 1;
+    }
+
+    let positive = reply.is_positive();
+    let last = state.last_command.as_ref();
+    let last_verb = last.map(|c| c.verb.as_str());
+    // The tagged reply of IMAP ends the command; SMTP and POP3 reply once.
+    let completes = session.protocol != MailProtocol::Imap
+        || last.and_then(|c| c.tag.as_ref()) == reply.tag.as_ref();
+    match session.protocol {
+        MailProtocol::Smtp if reply.status == "354" => state.client_data = true,
+        MailProtocol::Pop3 if positive => {
+            let argument = last.map_or("", |c| c.argument.as_str());
+            state.server_data = match last_verb {
+                Some("RETR" | "TOP" | "CAPA") => true,
+                Some("LIST" | "UIDL") => argument.is_empty(),
+                _ => false,
+            };
+        }
+        _ => {}
+    }
+
+    let starttls = positive
+        && completes
+        && last_verb == Some(session.protocol.starttls_command())
+        && reply.tag.as_deref() != Some("+");
+    events.push(MailEvent::Reply {
+        ts,
+        key: session.key,
+        reply,
+    });
+    if completes {
+        state.last_command = None;
+    }
+    if starttls {
+        session.starttls = Some(ts);
+        events.push(MailEvent::StartTls {
+            ts,
+            key: session.key,
+        });
+    }
+}
+
+impl Analyzer for MailAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_tcp(ts, &ipv4);
+        }
+    }
+}
+
+impl ToTable for MailAnalyzer {
+    /// Convert into a table with one row per session.
+    ///
+    /// `starttls` is 1 for sessions upgraded to TLS.
+    fn to_table(&self) -> Table {
+        let sessions = &self.sessions;
+        let strs = |f: fn(&MailSession) -> String| Column::Str(sessions.iter().map(f).collect());
+        let u64s = |f: fn(&MailSession) -> u64| Column::U64(sessions.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push("client", strs(|s| s.key.src.to_string()))
+            .push("server", strs(|s| s.key.dst.to_string()))
+            .push("server_port", u64s(|s| s.key.dst_port as u64))
+            .push("protocol", strs(|s| s.protocol.name().to_string()))
+            .push("commands", u64s(|s| s.commands))
+            .push("replies", u64s(|s| s.replies))
+            .push("errors", u64s(|s| s.errors))
+            .push("starttls", u64s(|s| s.starttls.is_some() as u64));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(from_client: bool, port: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let (src, dst) = if from_client {
+            ((CLIENT, 40000), (SERVER, port))
+        } else {
+            ((SERVER, port), (CLIENT, 40000))
+        };
+        tcp_frame(src, dst, seq, TcpFlags::ACK, payload)
+    }
+
+    /// Feed a dialogue of alternating server and client chunks.
+    fn run(analyzer: &mut MailAnalyzer, port: u16, dialogue: &[&[u8]]) {
+        let (mut client_seq, mut server_seq) = (1, 1);
+        for (i, chunk) in dialogue.iter().enumerate() {
+            let from_client = i % 2 == 1;
+            let seq = if from_client {
+                &mut client_seq
+            } else {
+                &mut server_seq
+            };
+            analyzer.on_packet(
+                Duration::from_secs(i as u64),
+                &frame(from_client, port, *seq, chunk),
+            );
+            *seq += chunk.len() as u32;
+        }
+    }
+
+    #[test]
+    fn mail_sessions() {
+        let mut analyzer = MailAnalyzer::new();
+        run(
+            &mut analyzer,
+            25,
+            &[
+                b"220 mx.example.com ESMTP\r\n",
+                b"EHLO client\r\n",
+                b"250-mx.example.com\r\n250 STARTTLS\r\n",
+                b"MAIL FROM:<a@example.com>\r\n",
+                b"250 OK\r\n",
+                b"DATA\r\n",
+                b"354 Go ahead\r\n",
+                b"HELO not a command\r\n.\r\n",
+                b"550 Rejected\r\n",
+                b"STARTTLS\r\n",
+                b"220 Ready\r\n",
+                b"\x16\x03\x01 encrypted\r\n",
+            ],
+        );
+        run(
+            &mut analyzer,
+            143,
+            &[
+                b"* OK IMAP ready\r\n",
+                b"a1 STARTTLS\r\n",
+                b"a1 BAD not now\r\n",
+            ],
+        );
+        run(
+            &mut analyzer,
+            110,
+            &[
+                b"+OK ready\r\n",
+                b"RETR 1\r\n",
+                b"+OK message\r\n-ERR inside the message\r\n.\r\n",
+                b"STLS\r\n",
+                b"+OK begin TLS\r\n",
+            ],
+        );
+
+        let sessions: Vec<_> = analyzer
+            .sessions()
+            .iter()
+            .map(|s| {
+                (
+                    s.protocol,
+                    s.commands,
+                    s.replies,
+                    s.errors,
+                    s.starttls.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sessions,
+            [
+                (MailProtocol::Smtp, 4, 6, 1, true),
+                (MailProtocol::Imap, 1, 2, 1, false),
+                (MailProtocol::Pop3, 2, 3, 0, true),
+            ]
+        );
+        let starttls = analyzer
+            .events()
+            .iter()
+            .filter(|e| matches!(e, MailEvent::StartTls { .. }))
+            .count();
+        assert_eq!(starttls, 2);
+        assert_eq!(analyzer.to_table().len(), 3);
+    }
+}
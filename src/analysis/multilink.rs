@@ -0,0 +1,347 @@
+//! Flows split across the links of a multi-link capture.
+//!
+//! When traffic is captured on several links at once, e.g. the members of a
+//! LAG or the paths of an ECMP group, the two directions of a flow may be
+//! hashed onto different links. Each link then only sees one half of the
+//! flow, and analyzing the links separately reports bogus half-open flows.
+//! [`MultiLinkAnalyzer`] keeps the interface each packet was captured on,
+//! merges both directions of a flow whatever their links, and tells which
+//! flows were split and which links carry halves of the same flows:
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use netkit::analysis::multilink::MultiLinkAnalyzer;
+//! # use netkit::capture::file::pcapng::PcapngReader;
+//! let mut analyzer = MultiLinkAnalyzer::new();
+//! analyzer.read_pcapng(&mut PcapngReader::new(File::open("lag.pcapng")?)?)?;
+//!
+//! for flow in analyzer.flows().iter().filter(|flow| flow.is_split()) {
+//!     println!("{}: {:?} / {:?}", flow.key, flow.forward.interfaces, flow.reverse.interfaces);
+//! }
+//! for ((a, b), flows) in analyzer.split_links() {
+//!     println!("interfaces {a} and {b} share {flows} flows");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Read},
+    time::Duration,
+};
+
+use netkit_capture::{
+    dedup::Origin,
+    file::{pcap::PcapReader, pcapng::PcapngReader},
+};
+use netkit_packet::{layer::link, prelude::*};
+
+use super::{trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// One direction of a [`LinkFlow`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkDirection {
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of bytes of the frames.
+    pub bytes: u64,
+    /// Interfaces the packets were captured on, sorted.
+    pub interfaces: Vec<u32>,
+}
+
+impl LinkDirection {
+    fn record(&mut self, interface: u32, len: usize) {
+        self.packets += 1;
+        self.bytes += len as u64;
+        if let Err(i) = self.interfaces.binary_search(&interface) {
+            self.interfaces.insert(i, interface);
+        }
+    }
+}
+
+/// Flow merged across the interfaces of a capture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkFlow {
+    /// Key of the flow, from the sender of its first packet.
+    pub key: FlowKey,
+    /// Timestamp of the first packet.
+    pub first: Duration,
+    /// Timestamp of the last packet.
+    pub last: Duration,
+    /// Packets from the sender of the first packet.
+    pub forward: LinkDirection,
+    /// Packets to the sender of the first packet.
+    pub reverse: LinkDirection,
+}
+
+impl LinkFlow {
+    /// Whether only one direction of the flow was seen, on any interface.
+    pub fn is_half_open(&self) -> bool {
+        self.reverse.packets == 0
+    }
+
+    /// Whether both directions were seen, but not on the same interfaces.
+    ///
+    /// Analyzed link by link, some links of a split flow only see one of
+    /// its directions.
+    pub fn is_split(&self) -> bool {
+        !self.is_half_open() && self.forward.interfaces != self.reverse.interfaces
+    }
+
+    /// Get the interfaces the flow was captured on, sorted.
+    pub fn interfaces(&self) -> Vec<u32> {
+        let mut interfaces = self.forward.interfaces.clone();
+        interfaces.extend(&self.reverse.interfaces);
+        interfaces.sort_unstable();
+        interfaces.dedup();
+        interfaces
+    }
+
+    fn state(&self) -> &'static str {
+        if self.is_half_open() {
+            "half-open"
+        } else if self.is_split() {
+            "split"
+        } else {
+            "joint"
+        }
+    }
+}
+
+/// Analyzer merging the directions of flows captured on several interfaces.
+#[derive(Clone, Debug, Default)]
+pub struct MultiLinkAnalyzer {
+    flows: HashMap<FlowKey, LinkFlow>,
+}
+
+impl MultiLinkAnalyzer {
+    /// Create a new multi-link analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process an Ethernet frame captured on `interface`.
+    pub fn on_interface_packet(&mut self, ts: Duration, interface: u32, frame: &[u8]) {
+        let Some(key) = Eth::new(frame).ok().and_then(|eth| FlowKey::from_eth(&eth)) else {
+            return;
+        };
+
+        trace_flow_growth::<Self, _>(&self.flows, &key.canonical());
+        let flow = self
+            .flows
+            .entry(key.canonical())
+            .or_insert_with(|| LinkFlow {
+                key,
+                first: ts,
+                last: ts,
+                forward: LinkDirection::default(),
+                reverse: LinkDirection::default(),
+            });
+        flow.last = flow.last.max(ts);
+        let direction = if key == flow.key {
+            &mut flow.forward
+        } else {
+            &mut flow.reverse
+        };
+        direction.record(interface, frame.len());
+    }
+
+    /// Process a packet of any supported link type captured on `interface`.
+    pub fn on_record(&mut self, ts: Duration, interface: u32, link_type: LinkType, data: &[u8]) {
+        if link_type == LinkType::Ethernet {
+            self.on_interface_packet(ts, interface, data);
+        } else if let Some(frame) = link::to_eth(link_type, data) {
+            self.on_interface_packet(ts, interface, &frame);
+        }
+    }
+
+    /// Get the analyzer of the packets captured on `interface`, e.g. to feed
+    /// it one pcap file per link.
+    pub fn interface(&mut self, interface: u32) -> LinkSide<'_> {
+        LinkSide {
+            analyzer: self,
+            interface,
+        }
+    }
+
+    /// Process every packet of a pcapng file, on the interface of its block.
+    pub fn read_pcapng<R: Read>(&mut self, reader: &mut PcapngReader<R>) -> io::Result<()> {
+        while let Some(packet) = reader.next_packet()? {
+            let Some(description) = reader.interfaces().get(packet.interface as usize) else {
+                continue;
+            };
+            let link_type = LinkType::from(u32::from(description.link_type));
+            self.on_record(packet.ts, packet.interface, link_type, &packet.data);
+        }
+        Ok(())
+    }
+
+    /// Process every packet of a pcap file.
+    ///
+    /// Packets of Linux "any" captures (SLL2) are put on the interface of
+    /// their header, other packets on interface 0.
+    pub fn read_pcap<R: Read>(&mut self, reader: &mut PcapReader<R>) {
+        let link_type = reader.link_type();
+        while let Some((header, data)) = reader.next_packet() {
+            let interface = Origin::of(link_type, &data).map_or(0, |origin| origin.interface);
+            self.on_record(reader.timestamp(&header), interface, link_type, &data);
+        }
+    }
+
+    /// Get the flows, sorted by first packet.
+    pub fn flows(&self) -> Vec<&LinkFlow> {
+        let mut flows: Vec<_> = self.flows.values().collect();
+        flows.sort_by_key(|flow| {
+            let key = &flow.key;
+            (flow.first, key.src, key.src_port, key.dst, key.dst_port)
+        });
+        flows
+    }
+
+    /// Get the flow of a key, in either direction.
+    pub fn flow(&self, key: &FlowKey) -> Option<&LinkFlow> {
+        self.flows.get(&key.canonical())
+    }
+
+    /// Get the number of split flows per pair of interfaces.
+    ///
+    /// Pairs of interfaces carrying halves of the same flows are likely
+    /// members of the same LAG or ECMP group.
+    pub fn split_links(&self) -> BTreeMap<(u32, u32), u64> {
+        let mut links = BTreeMap::new();
+        for flow in self.flows.values().filter(|flow| flow.is_split()) {
+            let interfaces = flow.interfaces();
+            for (i, &a) in interfaces.iter().enumerate() {
+                for &b in &interfaces[i + 1..] {
+                    *links.entry((a, b)).or_default() += 1;
+                }
+            }
+        }
+        links
+    }
+}
+
+impl ToTable for MultiLinkAnalyzer {
+    /// Convert the flows into a table, one row per flow.
+    fn to_table(&self) -> Table {
+        let flows = self.flows();
+        let col =
+            |f: fn(&LinkFlow) -> String| Column::Str(flows.iter().map(|flow| f(flow)).collect());
+        let num = |f: fn(&LinkFlow) -> u64| Column::U64(flows.iter().map(|flow| f(flow)).collect());
+        let list = |interfaces: &[u32]| {
+            let interfaces: Vec<_> = interfaces.iter().map(u32::to_string).collect();
+            interfaces.join(",")
+        };
+
+        let mut table = Table::new();
+        table
+            .push("protocol", col(|flow| flow.key.protocol.to_string()))
+            .push("src", col(|flow| flow.key.src.to_string()))
+            .push("src_port", num(|flow| flow.key.src_port as u64))
+            .push("dst", col(|flow| flow.key.dst.to_string()))
+            .push("dst_port", num(|flow| flow.key.dst_port as u64))
+            .push("state", col(|flow| flow.state().to_string()))
+            .push(
+                "forward_interfaces",
+                Column::Str(
+                    flows
+                        .iter()
+                        .map(|flow| list(&flow.forward.interfaces))
+                        .collect(),
+                ),
+            )
+            .push(
+                "reverse_interfaces",
+                Column::Str(
+                    flows
+                        .iter()
+                        .map(|flow| list(&flow.reverse.interfaces))
+                        .collect(),
+                ),
+            )
+            .push("forward_packets", num(|flow| flow.forward.packets))
+            .push("reverse_packets", num(|flow| flow.reverse.packets));
+        table
+    }
+}
+
+/// Analyzer of one interface of a [`MultiLinkAnalyzer`].
+#[derive(Debug)]
+pub struct LinkSide<'a> {
+    analyzer: &'a mut MultiLinkAnalyzer,
+    interface: u32,
+}
+
+impl Analyzer for LinkSide<'_> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        self.analyzer.on_interface_packet(ts, self.interface, frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    fn segment(src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16), flags: TcpFlags) -> Vec<u8> {
+        tcp_frame(src, dst, 0, flags, &[])
+    }
+
+    #[test]
+    fn multilink_split() {
+        let server = (Ipv4Addr::new(198, 51, 100, 7), 443);
+        let client = |port| (Ipv4Addr::new(10, 0, 0, 1), port);
+        let ms = Duration::from_millis;
+
+        let mut analyzer = MultiLinkAnalyzer::new();
+        // Hashed apart: requests on link 1, responses on link 2.
+        analyzer.on_interface_packet(ms(1), 1, &segment(client(40000), server, TcpFlags::SYN));
+        analyzer.interface(2).on_packet(
+            ms(2),
+            &segment(server, client(40000), TcpFlags::SYN | TcpFlags::ACK),
+        );
+        analyzer.on_interface_packet(ms(3), 1, &segment(client(40000), server, TcpFlags::ACK));
+        // Both directions on link 3.
+        analyzer.on_interface_packet(ms(4), 3, &segment(client(40001), server, TcpFlags::SYN));
+        analyzer.on_interface_packet(
+            ms(5),
+            3,
+            &segment(server, client(40001), TcpFlags::SYN | TcpFlags::ACK),
+        );
+        // Really half-open.
+        analyzer.on_interface_packet(ms(6), 2, &segment(client(40002), server, TcpFlags::SYN));
+
+        let flows = analyzer.flows();
+        assert_eq!(flows.len(), 3);
+        let split = flows[0];
+        assert!(split.is_split() && !split.is_half_open());
+        assert_eq!(split.key.src_port, 40000);
+        assert_eq!((split.forward.packets, split.reverse.packets), (2, 1));
+        assert_eq!(split.forward.interfaces, [1]);
+        assert_eq!(split.reverse.interfaces, [2]);
+        assert_eq!(split.interfaces(), [1, 2]);
+        assert_eq!((split.first, split.last), (ms(1), ms(3)));
+
+        assert!(!flows[1].is_split() && !flows[1].is_half_open());
+        assert!(flows[2].is_half_open() && !flows[2].is_split());
+        let key = FlowKey::new(server.0, client(40002).0, 443, 40002, IpProtocol::Tcp);
+        assert_eq!(analyzer.flow(&key), Some(flows[2]));
+
+        assert_eq!(analyzer.split_links(), BTreeMap::from([((1, 2), 1)]));
+
+        let table = analyzer.to_table();
+        assert_eq!(
+            table.column("state"),
+            Some(&Column::Str(vec![
+                "split".into(),
+                "joint".into(),
+                "half-open".into()
+            ]))
+        );
+    }
+}
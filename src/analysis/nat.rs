@@ -0,0 +1,433 @@
+//! Flow stitching across a NAT.
+//!
+//! Given captures taken on both sides of a NAT, [`NatStitcher`] matches each
+//! inside flow to the outside flow it was translated to. Addresses and ports
+//! are rewritten by the translation, so flows are matched on what survives
+//! it: the start time (within the clock skew of the two captures), the
+//! hashes of the first payloads, and for TCP the sequence numbers, whose
+//! offset stays constant even when the NAT randomizes them. A
+//! [`ClockCorrection`] of the outside capture, e.g. estimated by
+//! [`ClockSync`](netkit_capture::clock_sync::ClockSync), lets a small skew
+//! tell flows apart.
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use netkit::analysis::{analyze_pcap, nat::NatStitcher};
+//! # use netkit::capture::file::pcap::PcapReader;
+//! let mut stitcher = NatStitcher::new();
+//! analyze_pcap(&mut PcapReader::new(File::open("lan.pcap")?), &mut stitcher.inside());
+//! analyze_pcap(&mut PcapReader::new(File::open("wan.pcap")?), &mut stitcher.outside());
+//!
+//! for mapping in stitcher.stitch() {
+//!     println!("{} => {}", mapping.inside, mapping.outside);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_capture::clock_sync::ClockCorrection;
+use netkit_packet::prelude::*;
+
+use super::{trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Default maximum difference between the start times of matched flows.
+pub const DEFAULT_MAX_SKEW: Duration = Duration::from_secs(1);
+
+/// Default number of payload-carrying packets fingerprinted per flow.
+pub const DEFAULT_FINGERPRINT_PACKETS: usize = 8;
+
+/// Inside flow matched to its translated outside flow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NatMapping {
+    /// Key of the flow on the inside, from its initiator.
+    pub inside: FlowKey,
+    /// Key of the flow on the outside, from its initiator.
+    pub outside: FlowKey,
+    /// Start time of the inside flow.
+    pub inside_start: Duration,
+    /// Start time of the outside flow, on the clock of the inside capture.
+    pub outside_start: Duration,
+    /// Number of fingerprinted payloads found on both sides.
+    pub payload_matches: usize,
+    /// Offset added by the NAT to the initiator's TCP sequence numbers, if
+    /// it was consistent across the fingerprinted packets.
+    pub seq_delta: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Sample {
+    forward: bool,
+    hash: u64,
+    seq: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+struct Fingerprint {
+    key: FlowKey,
+    start: Duration,
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Capture {
+    flows: HashMap<FlowKey, Fingerprint>,
+}
+
+impl Capture {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8], max_samples: usize) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+
+        let key = FlowKey::from_ipv4(&ipv4);
+        trace_flow_growth::<Self, _>(&self.flows, &key.canonical());
+        let flow = self
+            .flows
+            .entry(key.canonical())
+            .or_insert_with(|| Fingerprint {
+                key,
+                start: ts,
+                samples: Vec::new(),
+            });
+        if flow.samples.len() >= max_samples {
+            return;
+        }
+
+        let Some((hash, seq)) = payload_hash(&ipv4) else {
+            return;
+        };
+        flow.samples.push(Sample {
+            forward: key == flow.key,
+            hash,
+            seq,
+        });
+    }
+}
+
+/// Analyzer matching flows across a NAT.
+#[derive(Clone, Debug)]
+pub struct NatStitcher {
+    max_skew: Duration,
+    fingerprint_packets: usize,
+    clock: ClockCorrection,
+    inside: Capture,
+    outside: Capture,
+}
+
+impl Default for NatStitcher {
+    fn default() -> Self {
+        Self {
+            max_skew: DEFAULT_MAX_SKEW,
+            fingerprint_packets: DEFAULT_FINGERPRINT_PACKETS,
+            clock: ClockCorrection::default(),
+            inside: Capture::default(),
+            outside: Capture::default(),
+        }
+    }
+}
+
+impl NatStitcher {
+    /// Create a new NAT stitcher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum difference between the start times of matched flows.
+    ///
+    /// This must cover the clock skew between the two captures.
+    pub fn max_skew(&mut self, max_skew: Duration) -> &mut Self {
+        self.max_skew = max_skew;
+        self
+    }
+
+    /// Set the number of payload-carrying packets fingerprinted per flow.
+    pub fn fingerprint_packets(&mut self, fingerprint_packets: usize) -> &mut Self {
+        self.fingerprint_packets = fingerprint_packets;
+        self
+    }
+
+    /// Set the correction of the clock of the outside capture to the clock
+    /// of the inside one.
+    ///
+    /// The maximum skew then only needs to cover the error of the
+    /// correction.
+    pub fn clock(&mut self, correction: ClockCorrection) -> &mut Self {
+        self.clock = correction;
+        self
+    }
+
+    /// Get the analyzer of the inside capture.
+    pub fn inside(&mut self) -> NatSide<'_> {
+        NatSide {
+            stitcher: self,
+            outside: false,
+        }
+    }
+
+    /// Get the analyzer of the outside capture.
+    pub fn outside(&mut self) -> NatSide<'_> {
+        NatSide {
+            stitcher: self,
+            outside: true,
+        }
+    }
+
+    /// Match the inside flows to the outside flows.
+    ///
+    /// Candidate pairs share the protocol and the server port and start
+    /// within the maximum skew. They are matched greedily, best first:
+    /// most payload matches, then consistent sequence numbers, then closest
+    /// start times. Mappings are sorted by inside start time.
+    pub fn stitch(&self) -> Vec<NatMapping> {
+        let mut candidates = Vec::new();
+        for inside in self.inside.flows.values() {
+            for outside in self.outside.flows.values() {
+                if let Some(mapping) = self.candidate(inside, outside) {
+                    candidates.push(mapping);
+                }
+            }
+        }
+
+        candidates.sort_by_key(|m| {
+            (
+                std::cmp::Reverse(m.payload_matches),
+                m.seq_delta.is_none(),
+                m.inside_start.abs_diff(m.outside_start),
+                m.inside_start,
+            )
+        });
+
+        let mut used = HashSet::new();
+        let mut mappings = Vec::new();
+        for mapping in candidates {
+            if used.contains(&(false, mapping.inside)) || used.contains(&(true, mapping.outside)) {
+                continue;
+            }
+            used.insert((false, mapping.inside));
+            used.insert((true, mapping.outside));
+            mappings.push(mapping);
+        }
+
+        mappings.sort_by_key(|m| (m.inside_start, key_order(&m.inside)));
+        mappings
+    }
+
+    fn candidate(&self, inside: &Fingerprint, outside: &Fingerprint) -> Option<NatMapping> {
+        let outside_start = self.clock.to_a(outside.start);
+        if inside.key.protocol != outside.key.protocol
+            || inside.key.dst_port != outside.key.dst_port
+            || inside.start.abs_diff(outside_start) > self.max_skew
+        {
+            return None;
+        }
+
+        let pairs: Vec<_> = inside
+            .samples
+            .iter()
+            .zip(&outside.samples)
+            .take_while(|(i, o)| i.forward == o.forward && i.hash == o.hash)
+            .collect();
+        let both_have_payload = !inside.samples.is_empty() && !outside.samples.is_empty();
+        if both_have_payload && pairs.is_empty() {
+            return None;
+        }
+
+        let mut deltas = pairs
+            .iter()
+            .filter(|(i, _)| i.forward)
+            .filter_map(|(i, o)| Some(o.seq?.wrapping_sub(i.seq?)));
+        let seq_delta = deltas
+            .next()
+            .filter(|first| deltas.all(|delta| delta == *first));
+
+        Some(NatMapping {
+            inside: inside.key,
+            outside: outside.key,
+            inside_start: inside.start,
+            outside_start,
+            payload_matches: pairs.len(),
+            seq_delta,
+        })
+    }
+}
+
+impl ToTable for NatStitcher {
+    /// Convert the NAT mappings into a table, one row per mapping.
+    fn to_table(&self) -> Table {
+        let mappings = self.stitch();
+        let col = |f: fn(&NatMapping) -> String| Column::Str(mappings.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push("protocol", col(|m| m.inside.protocol.to_string()))
+            .push("inside_src", col(|m| m.inside.src.to_string()))
+            .push(
+                "inside_src_port",
+                Column::U64(mappings.iter().map(|m| m.inside.src_port as u64).collect()),
+            )
+            .push("outside_src", col(|m| m.outside.src.to_string()))
+            .push(
+                "outside_src_port",
+                Column::U64(mappings.iter().map(|m| m.outside.src_port as u64).collect()),
+            )
+            .push("outside_dst", col(|m| m.outside.dst.to_string()))
+            .push(
+                "outside_dst_port",
+                Column::U64(mappings.iter().map(|m| m.outside.dst_port as u64).collect()),
+            )
+            .push(
+                "payload_matches",
+                Column::U64(mappings.iter().map(|m| m.payload_matches as u64).collect()),
+            );
+        table
+    }
+}
+
+/// Analyzer of one side of a [`NatStitcher`].
+#[derive(Debug)]
+pub struct NatSide<'a> {
+    stitcher: &'a mut NatStitcher,
+    outside: bool,
+}
+
+impl Analyzer for NatSide<'_> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let max_samples = self.stitcher.fingerprint_packets;
+        let capture = if self.outside {
+            &mut self.stitcher.outside
+        } else {
+            &mut self.stitcher.inside
+        };
+        capture.on_packet(ts, frame, max_samples);
+    }
+}
+
+/// Hash the transport payload of a packet, along with its TCP sequence
+/// number. Returns `None` for packets without payload.
+fn payload_hash(ipv4: &Ipv4<&[u8]>) -> Option<(u64, Option<u32>)> {
+    let mut hasher = DefaultHasher::new();
+    let seq = if let Some(tcp) = ipv4.tcp() {
+        if tcp.payload().is_empty() {
+            return None;
+        }
+        tcp.payload().hash(&mut hasher);
+        Some(tcp.seq_num().get())
+    } else if let Some(udp) = ipv4.udp() {
+        if udp.payload().is_empty() {
+            return None;
+        }
+        udp.payload().hash(&mut hasher);
+        None
+    } else {
+        if ipv4.payload().is_empty() {
+            return None;
+        }
+        ipv4.payload().hash(&mut hasher);
+        None
+    };
+
+    Some((hasher.finish(), seq))
+}
+
+/// Get a total order on flow keys, to sort mappings deterministically.
+fn key_order(key: &FlowKey) -> (IpAddr, u16, IpAddr, u16) {
+    (key.src, key.src_port, key.dst, key.dst_port)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 7);
+
+    fn segment(src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16), seq: u32, payload: &[u8]) -> Vec<u8> {
+        tcp_frame(src, dst, seq, TcpFlags::ACK, payload)
+    }
+
+    /// Feed a request/response exchange of a client.
+    fn exchange(side: &mut NatSide, start: Duration, client: (Ipv4Addr, u16), isn: u32, id: u8) {
+        let server = (SERVER, 443);
+        let ms = Duration::from_millis;
+        side.on_packet(start, &segment(client, server, isn, &[]));
+        side.on_packet(start + ms(1), &segment(client, server, isn + 1, &[id; 100]));
+        side.on_packet(start + ms(2), &segment(server, client, 7, &[id + 1; 50]));
+        side.on_packet(
+            start + ms(3),
+            &segment(client, server, isn + 101, &[id; 20]),
+        );
+    }
+
+    #[test]
+    fn nat_stitch() {
+        let public = Ipv4Addr::new(203, 0, 113, 1);
+        let host_a = (Ipv4Addr::new(192, 168, 1, 10), 40000);
+        let host_b = (Ipv4Addr::new(192, 168, 1, 11), 40000);
+        let s = Duration::from_secs;
+        let skew = Duration::from_millis(200);
+
+        let mut stitcher = NatStitcher::new();
+        // Two clients starting at the same time: timing alone is ambiguous.
+        exchange(&mut stitcher.inside(), s(10), host_a, 1000, 1);
+        exchange(&mut stitcher.inside(), s(10), host_b, 1000, 3);
+        // The NAT translates the ports and randomizes the sequence numbers.
+        exchange(
+            &mut stitcher.outside(),
+            s(10) + skew,
+            (public, 61001),
+            9000,
+            3,
+        );
+        exchange(
+            &mut stitcher.outside(),
+            s(10) + skew,
+            (public, 61000),
+            5000,
+            1,
+        );
+        // An unrelated flow, long after.
+        exchange(&mut stitcher.outside(), s(60), (public, 61002), 1, 1);
+
+        let mappings = stitcher.stitch();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[0].inside.src_port, 40000);
+        assert_eq!(mappings[0].inside.src, IpAddr::from(host_a.0));
+        assert_eq!(mappings[0].outside.src_port, 61000);
+        assert_eq!(mappings[0].payload_matches, 3);
+        assert_eq!(mappings[0].seq_delta, Some(4000));
+        assert_eq!(mappings[1].inside.src, IpAddr::from(host_b.0));
+        assert_eq!(mappings[1].outside.src_port, 61001);
+        assert_eq!(mappings[1].seq_delta, Some(8000));
+
+        let table = stitcher.to_table();
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.column("outside_src_port"),
+            Some(&Column::U64(vec![61000, 61001]))
+        );
+
+        // The clock of the outside capture is five seconds ahead
+        let mut stitcher = NatStitcher::new();
+        exchange(&mut stitcher.inside(), s(10), host_a, 1000, 1);
+        exchange(&mut stitcher.outside(), s(15), (public, 61000), 5000, 1);
+        assert!(stitcher.stitch().is_empty());
+        stitcher.clock(ClockCorrection::new(5_000_000_000));
+        let mappings = stitcher.stitch();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].outside_start, s(10));
+    }
+}
@@ -0,0 +1,543 @@
+//! Path MTU discovery troubleshooting.
+//!
+//! Path MTU discovery (RFC 1191) relies on routers answering oversized
+//! packets carrying the DF flag with an ICMP Fragmentation Needed message.
+//! When that message is filtered, large packets silently disappear while
+//! small ones get through: connections establish but stall on the first full
+//! sized segment, a PMTUD black hole.
+//!
+//! This analyzer follows every IPv4 path (source to destination) and
+//! correlates the DF packets sent, the TCP segments acknowledged by the
+//! peer, the retransmissions of large segments and the Fragmentation Needed
+//! messages quoting the path, to classify it with [`PmtuFinding`]s.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::{
+    layer::{ip::FLAG_DONT_FRAGMENT, tcp::TcpFlags},
+    prelude::*,
+};
+
+use super::{trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Default number of large retransmissions without ICMP feedback taken as a
+/// black hole.
+pub const DEFAULT_BLACK_HOLE_RETRANSMISSIONS: u64 = 3;
+
+/// Default delay after a Fragmentation Needed message during which
+/// oversized packets already in flight are tolerated.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(1);
+
+/// Default maximum number of unacknowledged segments followed per flow.
+pub const DEFAULT_MAX_OUTSTANDING: usize = 4096;
+
+/// Size up to which packets are never considered large (RFC 791 minimum
+/// datagram size every host accepts).
+const MIN_LARGE_SIZE: u16 = 576;
+
+/// IPv4 and TCP header bytes without options, added to the MSS.
+const TCP_IPV4_HEADERS: u16 = 40;
+
+/// Diagnosis of a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PmtuFinding {
+    /// Routers report a smaller MTU and the sender adapts.
+    Working {
+        /// Smallest reported next-hop MTU.
+        mtu: u16,
+    },
+    /// Routers report a smaller MTU but the sender keeps sending larger DF
+    /// packets, e.g. because the ICMP messages are dropped before the host.
+    IcmpIgnored {
+        /// Smallest reported next-hop MTU.
+        mtu: u16,
+        /// Largest DF packet sent after the grace period.
+        size: u16,
+    },
+    /// Large DF segments are retransmitted without any ICMP feedback.
+    BlackHole {
+        /// Largest retransmitted packet.
+        size: u16,
+        /// Largest packet acknowledged by the peer, if any.
+        delivered: Option<u16>,
+    },
+    /// The MSS advertised by the destination does not fit the reported MTU,
+    /// e.g. because MSS clamping is missing on a tunnel.
+    MssExceedsMtu {
+        /// Advertised MSS.
+        mss: u16,
+        /// Smallest reported next-hop MTU.
+        mtu: u16,
+    },
+}
+
+impl fmt::Display for PmtuFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PmtuFinding::Working { mtu } => write!(f, "pmtud working (mtu {mtu})"),
+            PmtuFinding::IcmpIgnored { mtu, size } => {
+                write!(f, "icmp ignored (mtu {mtu}, sent {size})")
+            }
+            PmtuFinding::BlackHole {
+                size,
+                delivered: Some(delivered),
+            } => write!(f, "black hole (lost {size}, delivered {delivered})"),
+            PmtuFinding::BlackHole {
+                size,
+                delivered: None,
+            } => write!(f, "black hole (lost {size})"),
+            PmtuFinding::MssExceedsMtu { mss, mtu } => {
+                write!(f, "mss exceeds mtu (mss {mss}, mtu {mtu})")
+            }
+        }
+    }
+}
+
+/// Report of a path from a source to a destination.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathReport {
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of packets with the DF flag.
+    pub df_packets: u64,
+    /// Largest packet with the DF flag (IP total length).
+    pub max_df_size: u16,
+    /// Largest TCP segment acknowledged by the destination (IP total length).
+    pub max_delivered_size: u16,
+    /// Number of retransmitted DF segments larger than any delivered one.
+    pub large_retransmissions: u64,
+    /// Largest of these retransmitted segments.
+    pub max_retransmitted_size: u16,
+    /// Number of Fragmentation Needed messages quoting the path.
+    pub frag_needed: u64,
+    /// Smallest non-zero next-hop MTU reported.
+    pub reported_mtu: Option<u16>,
+    /// Routers sending Fragmentation Needed messages.
+    pub reporters: BTreeSet<IpAddr>,
+    /// Timestamp of the first Fragmentation Needed message.
+    pub first_frag_needed: Option<Duration>,
+    /// Number of DF packets larger than the reported MTU sent after the
+    /// grace period.
+    pub oversized_after_icmp: u64,
+    /// Largest of these packets.
+    pub max_oversized_size: u16,
+    /// MSS advertised by the destination in its SYN.
+    pub mss: Option<u16>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FlowState {
+    /// Unacknowledged segments: sequence number to end and packet size.
+    outstanding: HashMap<u32, (u32, u16)>,
+}
+
+/// Path MTU discovery analyzer.
+#[derive(Clone, Debug)]
+pub struct PmtuAnalyzer {
+    black_hole_retransmissions: u64,
+    grace: Duration,
+    max_outstanding: usize,
+    paths: HashMap<(IpAddr, IpAddr), PathReport>,
+    flows: HashMap<FlowKey, FlowState>,
+}
+
+impl Default for PmtuAnalyzer {
+    fn default() -> Self {
+        Self {
+            black_hole_retransmissions: DEFAULT_BLACK_HOLE_RETRANSMISSIONS,
+            grace: DEFAULT_GRACE,
+            max_outstanding: DEFAULT_MAX_OUTSTANDING,
+            paths: HashMap::new(),
+            flows: HashMap::new(),
+        }
+    }
+}
+
+impl PmtuAnalyzer {
+    /// Create a new path MTU analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of large retransmissions without ICMP feedback taken
+    /// as a black hole.
+    pub fn black_hole_retransmissions(&mut self, retransmissions: u64) -> &mut Self {
+        self.black_hole_retransmissions = retransmissions;
+        self
+    }
+
+    /// Set the delay after a Fragmentation Needed message during which
+    /// oversized packets are tolerated.
+    pub fn grace(&mut self, grace: Duration) -> &mut Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Set the maximum number of unacknowledged segments followed per flow.
+    pub fn max_outstanding(&mut self, max_outstanding: usize) -> &mut Self {
+        self.max_outstanding = max_outstanding;
+        self
+    }
+
+    /// Get the report of a path.
+    pub fn report(&self, src: IpAddr, dst: IpAddr) -> Option<&PathReport> {
+        self.paths.get(&(src, dst))
+    }
+
+    /// Get the reports of all paths, ordered by source and destination.
+    pub fn reports(&self) -> Vec<((IpAddr, IpAddr), &PathReport)> {
+        let mut reports: Vec<_> = self.paths.iter().map(|(k, v)| (*k, v)).collect();
+        reports.sort_by_key(|(k, _)| *k);
+        reports
+    }
+
+    /// Get the findings of a path report.
+    pub fn findings(&self, report: &PathReport) -> Vec<PmtuFinding> {
+        let mut findings = Vec::new();
+
+        if let Some(mtu) = report.reported_mtu {
+            if report.oversized_after_icmp > 0 {
+                findings.push(PmtuFinding::IcmpIgnored {
+                    mtu,
+                    size: report.max_oversized_size,
+                });
+            } else {
+                findings.push(PmtuFinding::Working { mtu });
+            }
+            if let Some(mss) = report.mss {
+                if mss.saturating_add(TCP_IPV4_HEADERS) > mtu {
+                    findings.push(PmtuFinding::MssExceedsMtu { mss, mtu });
+                }
+            }
+        } else if report.large_retransmissions >= self.black_hole_retransmissions {
+            findings.push(PmtuFinding::BlackHole {
+                size: report.max_retransmitted_size,
+                delivered: (report.max_delivered_size > 0).then_some(report.max_delivered_size),
+            });
+        }
+
+        findings
+    }
+
+    /// Get the paths with findings, ordered by source and destination.
+    pub fn problems(&self) -> Vec<((IpAddr, IpAddr), Vec<PmtuFinding>)> {
+        self.reports()
+            .into_iter()
+            .map(|(path, report)| (path, self.findings(report)))
+            .filter(|(_, findings)| {
+                findings
+                    .iter()
+                    .any(|f| !matches!(f, PmtuFinding::Working { .. }))
+            })
+            .collect()
+    }
+
+    fn on_frag_needed(&mut self, ts: Duration, router: IpAddr, icmp: &Icmp<&[u8]>) {
+        let (Some(mtu), Some(original)) = (icmp.next_hop_mtu(), icmp.original()) else {
+            return;
+        };
+        let path = (original.src().get().into(), original.dst().get().into());
+        let report = self.paths.entry(path).or_default();
+        report.frag_needed += 1;
+        report.reporters.insert(router);
+        report.first_frag_needed.get_or_insert(ts);
+        if mtu != 0 {
+            report.reported_mtu = Some(report.reported_mtu.map_or(mtu, |m| m.min(mtu)));
+        }
+    }
+
+    fn on_tcp(&mut self, key: FlowKey, size: u16, df: bool, tcp: &Tcp<&[u8]>) {
+        let path = (key.src, key.dst);
+
+        // Acknowledgments deliver the segments of the reverse direction
+        if tcp.flags().get().contains(TcpFlags::ACK) {
+            let ack = tcp.ack_num().get();
+            if let Some(flow) = self.flows.get_mut(&key.reversed()) {
+                let mut delivered = 0;
+                flow.outstanding.retain(|_, (end, size)| {
+                    let acked = (ack.wrapping_sub(*end) as i32) >= 0;
+                    if acked {
+                        delivered = delivered.max(*size);
+                    }
+                    !acked
+                });
+                if delivered > 0 {
+                    let report = self.paths.entry((key.dst, key.src)).or_default();
+                    report.max_delivered_size = report.max_delivered_size.max(delivered);
+                }
+            }
+        }
+
+        if tcp.flags().get().contains(TcpFlags::SYN) {
+            if let Some(mss) = tcp.mss() {
+                // The MSS of the SYN limits the segments of the other direction
+                self.paths.entry((key.dst, key.src)).or_default().mss = Some(mss);
+            }
+        }
+
+        let len = tcp.payload().len() as u32;
+        if len == 0 {
+            return;
+        }
+        let seq = tcp.seq_num().get();
+        trace_flow_growth::<Self, _>(&self.flows, &key);
+        let flow = self.flows.entry(key).or_default();
+        let retransmitted = flow.outstanding.contains_key(&seq);
+        if !retransmitted {
+            if flow.outstanding.len() >= self.max_outstanding {
+                flow.outstanding.clear();
+            }
+            flow.outstanding.insert(seq, (seq.wrapping_add(len), size));
+        }
+
+        let report = self.paths.entry(path).or_default();
+        if retransmitted && df && size > report.max_delivered_size.max(MIN_LARGE_SIZE) {
+            report.large_retransmissions += 1;
+            report.max_retransmitted_size = report.max_retransmitted_size.max(size);
+        }
+    }
+}
+
+impl Analyzer for PmtuAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+
+        if let Some(icmp) = ipv4.icmp() {
+            self.on_frag_needed(ts, ipv4.src().get().into(), &icmp);
+        }
+
+        let key = FlowKey::from_ipv4(&ipv4);
+        let size = ipv4.total_length().get();
+        let df = ipv4.flags().get() & FLAG_DONT_FRAGMENT != 0;
+        let grace = self.grace;
+
+        let report = self.paths.entry((key.src, key.dst)).or_default();
+        report.packets += 1;
+        if df {
+            report.df_packets += 1;
+            report.max_df_size = report.max_df_size.max(size);
+            if let (Some(mtu), Some(first)) = (report.reported_mtu, report.first_frag_needed) {
+                if size > mtu && ts >= first + grace {
+                    report.oversized_after_icmp += 1;
+                    report.max_oversized_size = report.max_oversized_size.max(size);
+                }
+            }
+        }
+
+        if let Some(tcp) = ipv4.tcp() {
+            self.on_tcp(key, size, df, &tcp);
+        }
+    }
+}
+
+impl ToTable for PmtuAnalyzer {
+    /// Convert into a table with one row per path.
+    fn to_table(&self) -> Table {
+        let reports = self.reports();
+        let u64s =
+            |f: fn(&PathReport) -> u64| Column::U64(reports.iter().map(|(_, r)| f(r)).collect());
+        let addrs = |f: fn(&(IpAddr, IpAddr)) -> IpAddr| {
+            Column::Str(reports.iter().map(|(k, _)| f(k).to_string()).collect())
+        };
+
+        let mut table = Table::new();
+        table
+            .push("src", addrs(|k| k.0))
+            .push("dst", addrs(|k| k.1))
+            .push("packets", u64s(|r| r.packets))
+            .push("df_packets", u64s(|r| r.df_packets))
+            .push("max_df_size", u64s(|r| r.max_df_size as u64))
+            .push("max_delivered_size", u64s(|r| r.max_delivered_size as u64))
+            .push("large_retransmissions", u64s(|r| r.large_retransmissions))
+            .push("frag_needed", u64s(|r| r.frag_needed))
+            .push("reported_mtu", u64s(|r| r.reported_mtu.unwrap_or(0) as u64))
+            .push("mss", u64s(|r| r.mss.unwrap_or(0) as u64))
+            .push(
+                "findings",
+                Column::Str(
+                    reports
+                        .iter()
+                        .map(|(_, r)| {
+                            let findings: Vec<_> =
+                                self.findings(r).iter().map(|f| f.to_string()).collect();
+                            findings.join("; ")
+                        })
+                        .collect(),
+                ),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{
+        icmp,
+        layer::{
+            icmp::{IcmpType, CODE_FRAGMENTATION_NEEDED},
+            tcp::options::KIND_MSS,
+        },
+    };
+
+    use super::*;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const ROUTER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 254);
+
+    fn segment(
+        src: Ipv4Addr,
+        dst: Ipv4Addr,
+        seq: u32,
+        ack: u32,
+        flags: TcpFlags,
+        len: usize,
+    ) -> Ipv4<Vec<u8>> {
+        let (src_port, dst_port) = if src == SERVER {
+            (443u16, 40000u16)
+        } else {
+            (40000, 443)
+        };
+        let mut tcp = netkit_packet::layer::tcp::TcpBuilder::new();
+        tcp.src_port(src_port)
+            .dst_port(dst_port)
+            .seq_num(seq)
+            .ack_num(ack)
+            .flags(flags)
+            .payload(vec![0; len]);
+        if flags.contains(TcpFlags::SYN) {
+            tcp.options([KIND_MSS, 4, 0x05, 0xB4]);
+        }
+        ipv4!(
+            src: src,
+            dst: dst,
+            flags: FLAG_DONT_FRAGMENT,
+            protocol: IpProtocol::Tcp,
+            payload: tcp.build().inner(),
+        )
+    }
+
+    fn frame(ipv4: &Ipv4<Vec<u8>>) -> Vec<u8> {
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    fn feed(analyzer: &mut PmtuAnalyzer, ts: u64, ipv4: &Ipv4<Vec<u8>>) {
+        analyzer.on_packet(Duration::from_millis(ts), &frame(ipv4));
+    }
+
+    #[test]
+    fn pmtu_black_hole() {
+        let mut analyzer = PmtuAnalyzer::new();
+        let ack = TcpFlags::ACK;
+
+        feed(
+            &mut analyzer,
+            0,
+            &segment(CLIENT, SERVER, 0, 0, TcpFlags::SYN, 0),
+        );
+        feed(
+            &mut analyzer,
+            1,
+            &segment(SERVER, CLIENT, 0, 1, TcpFlags::SYN | ack, 0),
+        );
+        // A small response is delivered
+        feed(&mut analyzer, 2, &segment(SERVER, CLIENT, 1, 1, ack, 500));
+        feed(&mut analyzer, 3, &segment(CLIENT, SERVER, 1, 501, ack, 0));
+        // A full sized segment is retransmitted again and again
+        for i in 0..4 {
+            feed(
+                &mut analyzer,
+                10 + i * 200,
+                &segment(SERVER, CLIENT, 501, 1, ack, 1460),
+            );
+        }
+
+        let report = analyzer.report(SERVER.into(), CLIENT.into()).unwrap();
+        assert_eq!(report.max_delivered_size, 540);
+        assert_eq!(report.large_retransmissions, 3);
+        assert_eq!(report.max_df_size, 1500);
+        assert_eq!(
+            analyzer.findings(report),
+            [PmtuFinding::BlackHole {
+                size: 1500,
+                delivered: Some(540)
+            }]
+        );
+        assert_eq!(analyzer.problems().len(), 1);
+        assert!(analyzer
+            .findings(analyzer.report(CLIENT.into(), SERVER.into()).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn pmtu_frag_needed() {
+        let mut analyzer = PmtuAnalyzer::new();
+        let ack = TcpFlags::ACK;
+
+        feed(
+            &mut analyzer,
+            0,
+            &segment(CLIENT, SERVER, 0, 0, TcpFlags::SYN, 0),
+        );
+        let large = segment(SERVER, CLIENT, 1, 1, ack, 1460);
+        feed(&mut analyzer, 1, &large);
+
+        let icmp = icmp!(
+            icmp_type: IcmpType::DestinationUnreachable,
+            code: CODE_FRAGMENTATION_NEEDED,
+            sequence: 1400u16,
+            payload: &large.inner()[..28],
+        );
+        let icmp =
+            ipv4!(src: ROUTER, dst: SERVER, protocol: IpProtocol::Icmp, payload: icmp.inner());
+        feed(&mut analyzer, 2, &icmp);
+
+        let report = analyzer.report(SERVER.into(), CLIENT.into()).unwrap();
+        assert_eq!(report.frag_needed, 1);
+        assert_eq!(report.reported_mtu, Some(1400));
+        assert!(report.reporters.contains(&IpAddr::from(ROUTER)));
+        assert_eq!(
+            analyzer.findings(report),
+            [
+                PmtuFinding::Working { mtu: 1400 },
+                PmtuFinding::MssExceedsMtu {
+                    mss: 1460,
+                    mtu: 1400
+                }
+            ]
+        );
+
+        // Still sending full sized packets after the grace period
+        feed(
+            &mut analyzer,
+            2000,
+            &segment(SERVER, CLIENT, 1461, 1, ack, 1460),
+        );
+        let report = analyzer.report(SERVER.into(), CLIENT.into()).unwrap();
+        assert_eq!(
+            analyzer.findings(report)[0],
+            PmtuFinding::IcmpIgnored {
+                mtu: 1400,
+                size: 1500
+            }
+        );
+
+        let table = analyzer.to_table();
+        assert_eq!(table.len(), 3);
+    }
+}
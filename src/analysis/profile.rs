@@ -0,0 +1,395 @@
+//! Self-profiling of the packet pipeline.
+//!
+//! Which stage limits throughput depends on the workload: a capture of
+//! small packets is bound by reading them, one of TLS handshakes by the
+//! certificate analyzer. A [`Profiler`] measures the time spent in each
+//! [`Stage`] of a pipeline (reading, dissecting, filtering, every analyzer
+//! and exporting) and reports the breakdown as a [`Profile`], without an
+//! external profiler. Stages time themselves into a [`ProfileBatch`], added
+//! to the profiler once per batch of packets, so profiling costs a clock
+//! read per stage and packet.
+//!
+//! [`Profiled`] times an analyzer, [`analyze_pcap_profiled`] the reading,
+//! filtering and dissecting of a pcap file, and the probe service its
+//! stages when given a profiler.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit::analysis::{
+//!     profile::{Profiled, Profiler, Stage},
+//!     storm::StormAnalyzer,
+//!     Analyzer,
+//! };
+//!
+//! let profiler = Profiler::new();
+//! let mut analyzer = Profiled::new(&profiler, "storm", StormAnalyzer::new());
+//! analyzer.on_packet(Duration::ZERO, &[0; 60]);
+//! analyzer.flush();
+//!
+//! let profile = profiler.report();
+//! assert_eq!(profile.stage(Stage::Analyzer("storm")).unwrap().calls, 1);
+//! println!("{profile}");
+//! ```
+//!
+//! [`analyze_pcap_profiled`]: super::analyze_pcap_profiled
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::Analyzer;
+use crate::export::{Column, Table, ToTable};
+
+/// Default number of packets of a batch.
+pub const DEFAULT_BATCH_PACKETS: u64 = 1024;
+
+/// Stage of a packet pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// Reading packets from a file or interface.
+    Read,
+    /// Dissecting packets, e.g. converting them to Ethernet.
+    Dissect,
+    /// Filtering packets.
+    Filter,
+    /// An analyzer, by name.
+    Analyzer(&'static str),
+    /// Exporting reports.
+    Export,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Read => f.write_str("read"),
+            Stage::Dissect => f.write_str("dissect"),
+            Stage::Filter => f.write_str("filter"),
+            Stage::Analyzer(name) => write!(f, "analyzer {name}"),
+            Stage::Export => f.write_str("export"),
+        }
+    }
+}
+
+/// Time spent in a stage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageStats {
+    /// Number of times the stage ran.
+    pub calls: u64,
+    /// Total time spent in the stage.
+    pub time: Duration,
+    /// Longest time spent in the stage in a batch.
+    pub max_batch: Duration,
+}
+
+impl StageStats {
+    /// Get the average time of a call.
+    pub fn per_call(&self) -> Duration {
+        Duration::from_nanos((self.time.as_nanos() / self.calls.max(1) as u128) as u64)
+    }
+}
+
+/// Breakdown of the time spent per stage.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Number of batches added.
+    pub batches: u64,
+    /// Stages, in the order they were first seen.
+    pub stages: Vec<(Stage, StageStats)>,
+}
+
+impl Profile {
+    /// Get the time spent in a stage.
+    pub fn stage(&self, stage: Stage) -> Option<&StageStats> {
+        self.stages
+            .iter()
+            .find(|(s, _)| *s == stage)
+            .map(|(_, stats)| stats)
+    }
+
+    /// Get the time spent in all stages.
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, stats)| stats.time).sum()
+    }
+
+    /// Get the share of the total time spent in a stage, between 0 and 1.
+    pub fn share(&self, stage: Stage) -> f64 {
+        let total = self.total().as_secs_f64();
+        match self.stage(stage) {
+            Some(stats) if total > 0.0 => stats.time.as_secs_f64() / total,
+            _ => 0.0,
+        }
+    }
+
+    /// Get the stage the most time was spent in, the one limiting
+    /// throughput.
+    pub fn bottleneck(&self) -> Option<Stage> {
+        self.stages
+            .iter()
+            .max_by_key(|(_, stats)| stats.time)
+            .map(|(stage, _)| *stage)
+    }
+
+    /// Get the stages, most time first.
+    fn sorted(&self) -> Vec<(Stage, StageStats)> {
+        let mut stages = self.stages.clone();
+        stages.sort_by(|(a, sa), (b, sb)| sb.time.cmp(&sa.time).then(a.cmp(b)));
+        stages
+    }
+}
+
+impl fmt::Display for Profile {
+    /// Format as a text table, most time first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:>12} {:>12} {:>7} {:>12}",
+            "stage", "calls", "time", "share", "per call"
+        )?;
+        for (stage, stats) in self.sorted() {
+            writeln!(
+                f,
+                "{:<24} {:>12} {:>12} {:>6.1}% {:>12}",
+                stage.to_string(),
+                stats.calls,
+                format!("{:.3?}", stats.time),
+                self.share(stage) * 100.0,
+                format!("{:.1?}", stats.per_call()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ToTable for Profile {
+    /// Convert into a table with one row per stage, most time first.
+    ///
+    /// Times are in seconds.
+    fn to_table(&self) -> Table {
+        let stages = self.sorted();
+        let f64s =
+            |f: &dyn Fn(&(Stage, StageStats)) -> f64| Column::F64(stages.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "stage",
+                Column::Str(stages.iter().map(|(s, _)| s.to_string()).collect()),
+            )
+            .push(
+                "calls",
+                Column::U64(stages.iter().map(|(_, s)| s.calls).collect()),
+            )
+            .push("time", f64s(&|(_, s)| s.time.as_secs_f64()))
+            .push("share", f64s(&|(stage, _)| self.share(*stage)))
+            .push("per_call", f64s(&|(_, s)| s.per_call().as_secs_f64()))
+            .push("max_batch", f64s(&|(_, s)| s.max_batch.as_secs_f64()));
+        table
+    }
+}
+
+/// Profiler of a pipeline
+///
+/// A handle shared by the stages: clones add to the same profile.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    profile: Arc<Mutex<Profile>>,
+}
+
+impl Profiler {
+    /// Create a profiler with an empty profile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a batch adding to this profiler.
+    pub fn batch(&self) -> ProfileBatch {
+        ProfileBatch {
+            profiler: self.clone(),
+            stages: Vec::new(),
+            packets: 0,
+        }
+    }
+
+    /// Get the profile of the batches added so far.
+    pub fn report(&self) -> Profile {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Profile> {
+        self.profile.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Times of the stages of a batch of packets, added to its [`Profiler`]
+/// every [`DEFAULT_BATCH_PACKETS`] packets and when dropped.
+#[derive(Debug)]
+pub struct ProfileBatch {
+    profiler: Profiler,
+    stages: Vec<(Stage, StageStats)>,
+    packets: u64,
+}
+
+impl ProfileBatch {
+    /// Run a stage and record its time.
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(stage, start.elapsed());
+        result
+    }
+
+    /// Record the time of a run of a stage.
+    pub fn record(&mut self, stage: Stage, time: Duration) {
+        let index = match self.stages.iter().position(|(s, _)| *s == stage) {
+            Some(index) => index,
+            None => {
+                self.stages.push((stage, StageStats::default()));
+                self.stages.len() - 1
+            }
+        };
+        let stats = &mut self.stages[index].1;
+        stats.calls += 1;
+        stats.time += time;
+    }
+
+    /// Count a packet through the pipeline, adding the batch to the
+    /// profiler when full.
+    pub fn end_packet(&mut self) {
+        self.packets += 1;
+        if self.packets >= DEFAULT_BATCH_PACKETS {
+            self.flush();
+        }
+    }
+
+    /// Add the batch to the profiler, and start a new one.
+    pub fn flush(&mut self) {
+        self.packets = 0;
+        if self.stages.is_empty() {
+            return;
+        }
+        let mut profile = self.profiler.lock();
+        profile.batches += 1;
+        for (stage, batch) in self.stages.drain(..) {
+            let index = match profile.stages.iter().position(|(s, _)| *s == stage) {
+                Some(index) => index,
+                None => {
+                    profile.stages.push((stage, StageStats::default()));
+                    profile.stages.len() - 1
+                }
+            };
+            let stats = &mut profile.stages[index].1;
+            stats.calls += batch.calls;
+            stats.time += batch.time;
+            stats.max_batch = stats.max_batch.max(batch.time);
+        }
+    }
+}
+
+impl Drop for ProfileBatch {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Run a stage, timing it if profiling.
+pub(crate) fn timed<T>(batch: &mut Option<ProfileBatch>, stage: Stage, f: impl FnOnce() -> T) -> T {
+    match batch {
+        Some(batch) => batch.time(stage, f),
+        None => f(),
+    }
+}
+
+/// Analyzer timing the analyzer it wraps
+#[derive(Debug)]
+pub struct Profiled<A> {
+    stage: Stage,
+    batch: ProfileBatch,
+    inner: A,
+}
+
+impl<A: Analyzer> Profiled<A> {
+    /// Wrap an analyzer, timing it as [`Stage::Analyzer`] with the given
+    /// name.
+    pub fn new(profiler: &Profiler, name: &'static str, inner: A) -> Self {
+        Self {
+            stage: Stage::Analyzer(name),
+            batch: profiler.batch(),
+            inner,
+        }
+    }
+
+    /// Get the wrapped analyzer.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the wrapped analyzer, consuming self and adding the last batch
+    /// to the profiler.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Add the current batch to the profiler, e.g. before reporting.
+    pub fn flush(&mut self) {
+        self.batch.flush();
+    }
+}
+
+impl<A: Analyzer> Analyzer for Profiled<A> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let inner = &mut self.inner;
+        self.batch.time(self.stage, || inner.on_packet(ts, frame));
+        self.batch.end_packet();
+    }
+}
+
+impl<A: ToTable> ToTable for Profiled<A> {
+    fn to_table(&self) -> Table {
+        self.inner.to_table()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiler_batches() {
+        let profiler = Profiler::new();
+        let ms = Duration::from_millis;
+        {
+            let mut batch = profiler.batch();
+            for _ in 0..DEFAULT_BATCH_PACKETS + 1 {
+                batch.record(Stage::Read, ms(1));
+                batch.record(Stage::Analyzer("tcp"), ms(3));
+                batch.end_packet();
+            }
+            assert_eq!(profiler.report().batches, 1);
+        }
+        let mut batch = profiler.clone().batch();
+        batch.record(Stage::Export, ms(10));
+        assert_eq!(batch.time(Stage::Filter, || 42), 42);
+        drop(batch);
+
+        let profile = profiler.report();
+        assert_eq!(profile.batches, 3);
+        assert_eq!(profile.bottleneck(), Some(Stage::Analyzer("tcp")));
+        let read = profile.stage(Stage::Read).unwrap();
+        assert_eq!(read.calls, DEFAULT_BATCH_PACKETS + 1);
+        assert_eq!(read.per_call(), ms(1));
+        assert_eq!(read.max_batch, ms(DEFAULT_BATCH_PACKETS));
+        assert!((profile.share(Stage::Read) - 0.25).abs() < 0.01);
+
+        let text = profile.to_string();
+        assert!(text.lines().nth(1).unwrap().starts_with("analyzer tcp"));
+        let table = profile.to_table();
+        assert_eq!(table.len(), 4);
+        assert_eq!(
+            table.column("calls"),
+            Some(&Column::U64(vec![1025, 1025, 1, 1]))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! QUIC connection tracking.
+//!
+//! QUIC connections survive changes of address and port (connection
+//! migration, or a NAT rebinding), so keying them by 5-tuple splits one
+//! connection into several flows. The connection IDs in the clear part of
+//! QUIC headers identify connections instead: long headers carry both IDs
+//! with their lengths, and short headers carry the destination ID, whose
+//! length is learned from the long headers of the handshake.
+//!
+//! Connections whose handshake was not captured, or which switch to
+//! connection IDs exchanged in encrypted frames, fall back to their 5-tuple.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+
+use super::Analyzer;
+use crate::export::{Column, Table, ToTable};
+
+/// A QUIC connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuicConnection {
+    /// Connection IDs seen, in order.
+    pub cids: Vec<Vec<u8>>,
+    /// Canonical 5-tuples the connection used, in order.
+    pub paths: Vec<FlowKey>,
+    /// Version of the last long header.
+    pub version: Option<u32>,
+    /// Timestamp of the first packet.
+    pub first_seen: Duration,
+    /// Timestamp of the last packet.
+    pub last_seen: Duration,
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of UDP payload bytes.
+    pub bytes: u64,
+}
+
+impl QuicConnection {
+    fn new(ts: Duration) -> Self {
+        Self {
+            cids: Vec::new(),
+            paths: Vec::new(),
+            version: None,
+            first_seen: ts,
+            last_seen: ts,
+            packets: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Get the number of times the connection moved to a new path.
+    pub fn migrations(&self) -> usize {
+        self.paths.len().saturating_sub(1)
+    }
+}
+
+/// QUIC connection analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct QuicAnalyzer {
+    connections: Vec<QuicConnection>,
+    cids: HashMap<Vec<u8>, usize>,
+    cid_lens: BTreeSet<usize>,
+    paths: FlowTable<usize>,
+}
+
+impl QuicAnalyzer {
+    /// Create a new QUIC analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the connections in the order they were first seen.
+    pub fn connections(&self) -> &[QuicConnection] {
+        &self.connections
+    }
+
+    /// Get the connection last seen on a 5-tuple, in either direction.
+    pub fn connection(&self, key: &FlowKey) -> Option<&QuicConnection> {
+        let id = self.paths.get(&key.canonical())?;
+        Some(&self.connections[*id])
+    }
+
+    fn on_udp(&mut self, ts: Duration, key: FlowKey, udp: Option<Udp<&[u8]>>) {
+        if let Some(quic) = udp.as_ref().and_then(|udp| udp.quic()) {
+            self.on_quic(ts, key, &quic);
+        }
+    }
+
+    fn on_quic(&mut self, ts: Duration, key: FlowKey, quic: &Quic<&[u8]>) {
+        let path = key.canonical();
+        let cids: Vec<&[u8]> = if quic.is_long_header() {
+            [quic.dcid(), quic.scid()]
+                .into_iter()
+                .flatten()
+                .filter(|cid| !cid.is_empty())
+                .collect()
+        } else {
+            // Prefer the longest known length matching a known ID.
+            self.cid_lens
+                .iter()
+                .rev()
+                .filter_map(|len| quic.short_dcid(*len))
+                .find(|cid| self.cids.contains_key(*cid))
+                .into_iter()
+                .collect()
+        };
+
+        let id = cids
+            .iter()
+            .find_map(|cid| self.cids.get(*cid).copied())
+            .or_else(|| self.paths.get(&path).copied())
+            .unwrap_or_else(|| {
+                self.connections.push(QuicConnection::new(ts));
+                self.connections.len() - 1
+            });
+
+        let connection = &mut self.connections[id];
+        for cid in cids {
+            if !self.cids.contains_key(cid) {
+                self.cids.insert(cid.to_vec(), id);
+                self.cid_lens.insert(cid.len());
+                connection.cids.push(cid.to_vec());
+            }
+        }
+        if !connection.paths.contains(&path) {
+            connection.paths.push(path);
+        }
+        self.paths.insert(path, id);
+
+        if let Some(version) = quic.version() {
+            connection.version = Some(version.get());
+        }
+        connection.last_seen = ts;
+        connection.packets += 1;
+        connection.bytes += quic.inner().len() as u64;
+    }
+}
+
+impl Analyzer for QuicAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(key) = FlowKey::from_eth(&eth) else {
+            return;
+        };
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_udp(ts, key, ipv4.udp());
+        } else if let Some(ipv6) = eth.ipv6() {
+            self.on_udp(ts, key, ipv6.udp());
+        }
+    }
+}
+
+impl ToTable for QuicAnalyzer {
+    /// Convert into a table with one row per connection, keyed by its first
+    /// path.
+    fn to_table(&self) -> Table {
+        let connections = &self.connections;
+        let path = |f: fn(&FlowKey) -> String| {
+            Column::Str(connections.iter().map(|c| f(&c.paths[0])).collect())
+        };
+        let u64s = |f: fn(&QuicConnection) -> u64| Column::U64(connections.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push("src", path(|k| k.src.to_string()))
+            .push("src_port", path(|k| k.src_port.to_string()))
+            .push("dst", path(|k| k.dst.to_string()))
+            .push("dst_port", path(|k| k.dst_port.to_string()))
+            .push("cids", u64s(|c| c.cids.len() as u64))
+            .push("migrations", u64s(|c| c.migrations() as u64))
+            .push("packets", u64s(|c| c.packets))
+            .push("bytes", u64s(|c| c.bytes));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16), quic: Quic<Vec<u8>>) -> Vec<u8> {
+        udp_frame(src, dst, quic.inner())
+    }
+
+    #[test]
+    fn quic_connection_migration() {
+        let client = (CLIENT, 50000);
+        let server = (SERVER, 443);
+        // The client moves to another port, e.g. after a NAT rebinding.
+        let rebound = (CLIENT, 50001);
+        let packets = [
+            frame(
+                client,
+                server,
+                quic!(version: 1u32, dcid: [9; 8], scid: [1; 4]),
+            ),
+            frame(
+                server,
+                client,
+                quic!(version: 1u32, dcid: [1; 4], scid: [2; 8]),
+            ),
+            frame(client, server, quic!(dcid: [2; 8], payload: [0; 20])),
+            frame(rebound, server, quic!(dcid: [2; 8], payload: [0; 20])),
+            frame(server, rebound, quic!(dcid: [1; 4], payload: [0; 20])),
+            // Unrelated connection without handshake
+            frame(
+                (CLIENT, 50002),
+                server,
+                quic!(dcid: [3; 8], payload: [0; 20]),
+            ),
+        ];
+
+        let mut analyzer = QuicAnalyzer::new();
+        for (i, packet) in packets.iter().enumerate() {
+            analyzer.on_packet(Duration::from_secs(i as u64), packet);
+        }
+
+        let connections = analyzer.connections();
+        assert_eq!(connections.len(), 2);
+        let connection = &connections[0];
+        assert_eq!(connection.cids, [vec![9; 8], vec![1; 4], vec![2; 8]]);
+        assert_eq!(connection.version, Some(1));
+        assert_eq!(connection.packets, 5);
+        assert_eq!(connection.migrations(), 1);
+
+        let rebound = FlowKey::new(SERVER, CLIENT, 443, 50001, IpProtocol::Udp);
+        assert_eq!(analyzer.connection(&rebound), Some(connection));
+        assert_eq!(analyzer.to_table().len(), 2);
+    }
+}
@@ -0,0 +1,502 @@
+//! RTP stream quality.
+//!
+//! RTP streams are told apart by flow and SSRC. Each stream reports the
+//! interarrival jitter of [RFC 3550 section 6.4.1], the packets lost from
+//! gaps in the extended sequence numbers, and a rough MOS estimate from a
+//! simplified E-model, overall and per interval.
+//!
+//! RTP runs on negotiated ports, so UDP datagrams between unprivileged ports
+//! with a valid RTP header are taken as RTP; RTCP payload types are skipped.
+//! Short-lived false positives are filtered by a minimum packet count.
+//!
+//! [RFC 3550 section 6.4.1]: https://datatracker.ietf.org/doc/html/rfc3550#section-6.4.1
+
+use std::{collections::HashMap, time::Duration};
+
+use netkit_packet::{layer::rtp::clock_rate, prelude::*};
+
+use super::{trace_flow_growth, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Default clock rate of dynamic payload types, in Hz.
+pub const DEFAULT_CLOCK_RATE: u32 = 8_000;
+
+/// Default length of the intervals reported.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Default minimum number of packets of a stream reported.
+pub const DEFAULT_MIN_PACKETS: u64 = 5;
+
+/// Estimate the MOS of a voice call with the simplified E-model.
+///
+/// The R factor starts from 93.2 and is reduced by the effective latency,
+/// i.e. the one-way latency plus twice the jitter plus 10 ms of codec delay,
+/// and by 2.5 per percent of packets lost. It is then mapped to a MOS
+/// between 1 and 4.5 as in ITU-T G.107.
+pub fn estimate_mos(latency: Duration, jitter: Duration, loss_percent: f64) -> f64 {
+    let effective = (latency + 2 * jitter).as_secs_f64() * 1e3 + 10.0;
+    let r = if effective < 160.0 {
+        93.2 - effective / 40.0
+    } else {
+        93.2 - (effective - 120.0) / 10.0
+    };
+    let r = (r - 2.5 * loss_percent).clamp(0.0, 100.0);
+    1.0 + 0.035 * r + 7e-6 * r * (r - 60.0) * (100.0 - r)
+}
+
+/// Quality of a stream over an interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtpInterval {
+    /// Start of the interval, relative to the first packet of the stream.
+    pub start: Duration,
+    /// Number of packets received.
+    pub packets: u64,
+    /// Number of packets lost.
+    pub lost: u64,
+    /// Jitter at the end of the interval.
+    pub jitter: Duration,
+    /// MOS estimate.
+    pub mos: f64,
+}
+
+/// Quality of a RTP stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtpStreamReport {
+    /// Flow of the stream.
+    pub key: FlowKey,
+    /// Synchronization source.
+    pub ssrc: u32,
+    /// Payload type of the first packet.
+    pub payload_type: u8,
+    /// Clock rate of the payload type, in Hz.
+    pub clock_rate: u32,
+    /// Number of packets received, without duplicates.
+    pub packets: u64,
+    /// Number of packets expected from the sequence numbers.
+    pub expected: u64,
+    /// Number of packets lost.
+    pub lost: u64,
+    /// Number of duplicate packets.
+    pub duplicates: u64,
+    /// Number of packets received out of order.
+    pub reordered: u64,
+    /// Interarrival jitter at the end of the stream.
+    pub jitter: Duration,
+    /// Maximum interarrival jitter.
+    pub max_jitter: Duration,
+    /// MOS estimate over the whole stream.
+    pub mos: f64,
+    /// Timestamp of the first packet.
+    pub first_seen: Duration,
+    /// Timestamp of the last packet.
+    pub last_seen: Duration,
+    /// Quality per interval.
+    pub intervals: Vec<RtpInterval>,
+}
+
+impl RtpStreamReport {
+    /// Get the share of packets lost, in percent.
+    pub fn loss_percent(&self) -> f64 {
+        loss_percent(self.lost, self.expected)
+    }
+}
+
+fn loss_percent(lost: u64, expected: u64) -> f64 {
+    if expected == 0 {
+        0.0
+    } else {
+        lost as f64 * 100.0 / expected as f64
+    }
+}
+
+#[derive(Clone, Debug)]
+struct IntervalState {
+    index: u64,
+    packets: u64,
+    max_seq: u64,
+    jitter: f64,
+}
+
+#[derive(Clone, Debug)]
+struct StreamState {
+    payload_type: u8,
+    clock_rate: u32,
+    base_seq: u64,
+    max_seq: u64,
+    packets: u64,
+    duplicates: u64,
+    reordered: u64,
+    transit: Option<i64>,
+    jitter: f64,
+    max_jitter: f64,
+    first_seen: Duration,
+    last_seen: Duration,
+    intervals: Vec<IntervalState>,
+}
+
+impl StreamState {
+    fn new(ts: Duration, rtp: &Rtp<&[u8]>, clock_rate: u32) -> Self {
+        let seq = rtp.sequence().get() as u64;
+        Self {
+            payload_type: rtp.payload_type().get(),
+            clock_rate,
+            base_seq: seq,
+            max_seq: seq.wrapping_sub(1),
+            packets: 0,
+            duplicates: 0,
+            reordered: 0,
+            transit: None,
+            jitter: 0.0,
+            max_jitter: 0.0,
+            first_seen: ts,
+            last_seen: ts,
+            intervals: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, ts: Duration, rtp: &Rtp<&[u8]>, interval: Duration) {
+        // Extend the sequence number to the cycle closest to the maximum
+        let seq = rtp.sequence().get();
+        let delta = seq.wrapping_sub(self.max_seq as u16);
+        if self.packets > 0 && delta == 0 {
+            self.duplicates += 1;
+            return;
+        }
+        if self.packets == 0 || delta < 0x8000 {
+            self.max_seq = self.max_seq.wrapping_add(delta as u64);
+        } else {
+            self.reordered += 1;
+        }
+        self.packets += 1;
+        self.last_seen = ts;
+
+        // Interarrival jitter in timestamp units (RFC 3550 A.8)
+        let arrival = (ts.as_secs_f64() * self.clock_rate as f64) as i64;
+        let transit = arrival.wrapping_sub(rtp.timestamp().get() as i64);
+        if let Some(prev) = self.transit {
+            let d = (transit - prev) as i32 as f64;
+            self.jitter += (d.abs() - self.jitter) / 16.0;
+            self.max_jitter = self.max_jitter.max(self.jitter);
+        }
+        self.transit = Some(transit);
+
+        let index =
+            ((ts.saturating_sub(self.first_seen)).as_nanos() / interval.as_nanos().max(1)) as u64;
+        if self.intervals.last().is_none_or(|i| i.index != index) {
+            self.intervals.push(IntervalState {
+                index,
+                packets: 0,
+                max_seq: self.max_seq,
+                jitter: 0.0,
+            });
+        }
+        let current = self.intervals.last_mut().unwrap();
+        current.packets += 1;
+        current.max_seq = current.max_seq.max(self.max_seq);
+        current.jitter = self.jitter;
+    }
+
+    fn jitter(&self, units: f64) -> Duration {
+        Duration::from_secs_f64(units / self.clock_rate as f64)
+    }
+}
+
+/// RTP stream quality analyzer.
+#[derive(Clone, Debug)]
+pub struct RtpAnalyzer {
+    interval: Duration,
+    min_packets: u64,
+    latency: Duration,
+    clock_rates: HashMap<u8, u32>,
+    streams: HashMap<(FlowKey, u32), StreamState>,
+}
+
+impl Default for RtpAnalyzer {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            min_packets: DEFAULT_MIN_PACKETS,
+            latency: Duration::ZERO,
+            clock_rates: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl RtpAnalyzer {
+    /// Create a new RTP analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the length of the intervals reported.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the minimum number of packets of a stream reported.
+    pub fn min_packets(&mut self, min_packets: u64) -> &mut Self {
+        self.min_packets = min_packets;
+        self
+    }
+
+    /// Set the one-way latency assumed by the MOS estimate, as a capture
+    /// point only sees the jitter.
+    pub fn latency(&mut self, latency: Duration) -> &mut Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Set the clock rate of a payload type, e.g. negotiated in SDP.
+    pub fn clock_rate(&mut self, payload_type: u8, rate: u32) -> &mut Self {
+        self.clock_rates.insert(payload_type, rate);
+        self
+    }
+
+    /// Get the reports of the streams, ordered by first packet.
+    pub fn reports(&self) -> Vec<RtpStreamReport> {
+        let mut reports: Vec<_> = self
+            .streams
+            .iter()
+            .filter(|(_, s)| s.packets >= self.min_packets)
+            .map(|(&(key, ssrc), s)| self.report(key, ssrc, s))
+            .collect();
+        reports.sort_by(|a, b| {
+            a.first_seen
+                .cmp(&b.first_seen)
+                .then_with(|| a.ssrc.cmp(&b.ssrc))
+        });
+        reports
+    }
+
+    fn report(&self, key: FlowKey, ssrc: u32, s: &StreamState) -> RtpStreamReport {
+        let expected = s.max_seq.wrapping_sub(s.base_seq).wrapping_add(1);
+        let lost = expected.saturating_sub(s.packets);
+
+        let mut prev_max = s.base_seq.wrapping_sub(1);
+        let intervals = s
+            .intervals
+            .iter()
+            .map(|i| {
+                let expected = i.max_seq.wrapping_sub(prev_max);
+                let lost = expected.saturating_sub(i.packets);
+                prev_max = i.max_seq;
+                let jitter = s.jitter(i.jitter);
+                RtpInterval {
+                    start: self.interval * i.index as u32,
+                    packets: i.packets,
+                    lost,
+                    jitter,
+                    mos: estimate_mos(self.latency, jitter, loss_percent(lost, expected)),
+                }
+            })
+            .collect();
+
+        let jitter = s.jitter(s.jitter);
+        RtpStreamReport {
+            key,
+            ssrc,
+            payload_type: s.payload_type,
+            clock_rate: s.clock_rate,
+            packets: s.packets,
+            expected,
+            lost,
+            duplicates: s.duplicates,
+            reordered: s.reordered,
+            jitter,
+            max_jitter: s.jitter(s.max_jitter),
+            mos: estimate_mos(self.latency, jitter, loss_percent(lost, expected)),
+            first_seen: s.first_seen,
+            last_seen: s.last_seen,
+            intervals,
+        }
+    }
+
+    /// Convert the intervals of the streams into a table, one row per
+    /// stream and interval.
+    pub fn interval_table(&self) -> Table {
+        let rows: Vec<_> = self
+            .reports()
+            .into_iter()
+            .flat_map(|r| {
+                let stream = format!("{} {:08x}", r.key, r.ssrc);
+                r.intervals.into_iter().map(move |i| (stream.clone(), i))
+            })
+            .collect();
+
+        let mut table = Table::new();
+        table
+            .push(
+                "stream",
+                Column::Str(rows.iter().map(|(s, _)| s.clone()).collect()),
+            )
+            .push(
+                "start",
+                Column::F64(rows.iter().map(|(_, i)| i.start.as_secs_f64()).collect()),
+            )
+            .push(
+                "packets",
+                Column::U64(rows.iter().map(|(_, i)| i.packets).collect()),
+            )
+            .push(
+                "lost",
+                Column::U64(rows.iter().map(|(_, i)| i.lost).collect()),
+            )
+            .push(
+                "jitter_ms",
+                Column::F64(
+                    rows.iter()
+                        .map(|(_, i)| i.jitter.as_secs_f64() * 1e3)
+                        .collect(),
+                ),
+            )
+            .push(
+                "mos",
+                Column::F64(rows.iter().map(|(_, i)| i.mos).collect()),
+            );
+        table
+    }
+
+    fn on_udp(&mut self, ts: Duration, key: FlowKey, udp: Option<Udp<&[u8]>>) {
+        let Some(udp) = udp else {
+            return;
+        };
+        if key.src_port < 1024 || key.dst_port < 1024 {
+            return;
+        }
+        let Some(rtp) = udp.rtp() else {
+            return;
+        };
+        // RTCP shares the version bits and uses packet types 200-204
+        if (72..=76).contains(&rtp.payload_type().get()) {
+            return;
+        }
+
+        let payload_type = rtp.payload_type().get();
+        let rate = self
+            .clock_rates
+            .get(&payload_type)
+            .copied()
+            .or_else(|| clock_rate(payload_type))
+            .unwrap_or(DEFAULT_CLOCK_RATE);
+        let key = (key, rtp.ssrc().get());
+        trace_flow_growth::<Self, _>(&self.streams, &key);
+        self.streams
+            .entry(key)
+            .or_insert_with(|| StreamState::new(ts, &rtp, rate))
+            .update(ts, &rtp, self.interval);
+    }
+}
+
+impl Analyzer for RtpAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_udp(ts, FlowKey::from_ipv4(&ipv4), ipv4.udp());
+        } else if let Some(ipv6) = eth.ipv6() {
+            self.on_udp(ts, FlowKey::from_ipv6(&ipv6), ipv6.udp());
+        }
+    }
+}
+
+impl ToTable for RtpAnalyzer {
+    /// Convert into a table with one row per stream.
+    fn to_table(&self) -> Table {
+        let reports = self.reports();
+        let u64s = |f: fn(&RtpStreamReport) -> u64| Column::U64(reports.iter().map(f).collect());
+        let f64s = |f: fn(&RtpStreamReport) -> f64| Column::F64(reports.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "flow",
+                Column::Str(reports.iter().map(|r| r.key.to_string()).collect()),
+            )
+            .push("ssrc", u64s(|r| r.ssrc as u64))
+            .push("payload_type", u64s(|r| r.payload_type as u64))
+            .push("packets", u64s(|r| r.packets))
+            .push("lost", u64s(|r| r.lost))
+            .push("loss_pct", f64s(|r| r.loss_percent()))
+            .push("duplicates", u64s(|r| r.duplicates))
+            .push("reordered", u64s(|r| r.reordered))
+            .push("jitter_ms", f64s(|r| r.jitter.as_secs_f64() * 1e3))
+            .push("max_jitter_ms", f64s(|r| r.max_jitter.as_secs_f64() * 1e3))
+            .push("mos", f64s(|r| r.mos));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    fn frame(seq: u16, timestamp: u32) -> Vec<u8> {
+        let rtp = rtp!(
+            payload_type: 0u8,
+            sequence: seq,
+            timestamp: timestamp,
+            ssrc: 0xCAFEu32,
+            payload: [0xFF; 160],
+        );
+        udp_frame(
+            (Ipv4Addr::new(10, 0, 0, 1), 16384),
+            (Ipv4Addr::new(10, 0, 0, 2), 16386),
+            rtp.inner(),
+        )
+    }
+
+    #[test]
+    fn rtp_stream_quality() {
+        let mut analyzer = RtpAnalyzer::new();
+        analyzer.interval(Duration::from_secs(1));
+
+        // 100 packets of 20 ms across a sequence wrap, one lost, one late
+        for i in 0..100u32 {
+            let seq = 65500u16.wrapping_add(i as u16);
+            if seq == 65530 || seq == 30 {
+                continue;
+            }
+            let delay = if i == 60 { 10 } else { 0 };
+            let ts = Duration::from_millis(20 * i as u64 + delay);
+            analyzer.on_packet(ts, &frame(seq, 160 * i));
+        }
+        analyzer.on_packet(Duration::from_millis(2000), &frame(65530, 160 * 30));
+
+        let reports = analyzer.reports();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.ssrc, 0xCAFE);
+        assert_eq!(report.clock_rate, 8_000);
+        assert_eq!(report.expected, 100);
+        assert_eq!(report.packets, 99);
+        assert_eq!(report.lost, 1);
+        assert_eq!(report.reordered, 1);
+        assert!((report.loss_percent() - 1.0).abs() < 1e-9);
+        assert!(report.max_jitter > Duration::ZERO);
+        assert!(report.mos < estimate_mos(Duration::ZERO, Duration::ZERO, 0.0));
+
+        assert_eq!(report.intervals.len(), 3);
+        assert_eq!(report.intervals[0].packets, 49);
+        assert_eq!(report.intervals[0].lost, 1);
+        assert_eq!(report.intervals[1].lost, 1);
+        assert_eq!(report.intervals[2].packets, 1);
+
+        assert_eq!(analyzer.to_table().len(), 1);
+        assert_eq!(analyzer.interval_table().len(), 3);
+    }
+
+    #[test]
+    fn rtp_estimate_mos() {
+        let best = estimate_mos(Duration::ZERO, Duration::ZERO, 0.0);
+        assert!((best - 4.4).abs() < 0.05);
+        let lossy = estimate_mos(Duration::from_millis(100), Duration::from_millis(20), 5.0);
+        assert!(lossy < 4.0 && lossy > 1.0);
+    }
+}
@@ -0,0 +1,270 @@
+//! Deterministic packet and flow sampling.
+//!
+//! On very high-rate links analyzing every packet is too expensive. A
+//! [`Sampled`] analyzer forwards only a deterministic subset of the packets
+//! to the wrapped analyzer, and keeps the sampling metadata needed to scale
+//! its statistics back up.
+//!
+//! Packet sampling (1-in-N) bounds the cost but breaks per-flow analyses,
+//! since every flow loses packets. Flow sampling keeps or drops whole flows
+//! (both directions) based on a hash of their key, so sampled flows remain
+//! complete.
+
+use std::{net::IpAddr, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use super::Analyzer;
+use crate::export::{Column, Table, ToTable};
+
+/// Sampling strategy.
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Sampling {
+    /// Keep every packet.
+    #[default]
+    All,
+
+    /// Keep every N-th packet, starting with the first one.
+    OneInN(u32),
+
+    /// Keep the flows whose key hashes to 0 modulo N, i.e. about 1 in N
+    /// flows. Packets without a flow key are kept 1-in-N.
+    FlowHash {
+        /// Sampling ratio.
+        n: u32,
+        /// Seed of the hash, to choose another subset of flows.
+        seed: u64,
+    },
+}
+
+impl Sampling {
+    /// Get the nominal sampling ratio, i.e. one kept packet or flow out of
+    /// the ratio.
+    pub fn ratio(&self) -> u32 {
+        match *self {
+            Sampling::All => 1,
+            Sampling::OneInN(n) | Sampling::FlowHash { n, .. } => n.max(1),
+        }
+    }
+}
+
+impl core::fmt::Display for Sampling {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Sampling::All => write!(f, "all"),
+            Sampling::OneInN(n) => write!(f, "1-in-{n}"),
+            Sampling::FlowHash { n, seed } => write!(f, "flow-hash 1-in-{n} seed {seed}"),
+        }
+    }
+}
+
+/// Analyzer forwarding a sample of the packets to an inner analyzer.
+#[derive(Clone, Debug)]
+pub struct Sampled<A> {
+    sampling: Sampling,
+    inner: A,
+    seen: u64,
+    sampled: u64,
+}
+
+impl<A: Analyzer> Sampled<A> {
+    /// Wrap an analyzer with a sampling strategy.
+    pub fn new(sampling: Sampling, inner: A) -> Self {
+        Self {
+            sampling,
+            inner,
+            seen: 0,
+            sampled: 0,
+        }
+    }
+
+    /// Get the sampling strategy.
+    pub fn sampling(&self) -> Sampling {
+        self.sampling
+    }
+
+    /// Get the wrapped analyzer.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the wrapped analyzer, consuming self.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Get the number of packets seen.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Get the number of packets forwarded to the wrapped analyzer.
+    pub fn sampled(&self) -> u64 {
+        self.sampled
+    }
+
+    /// Get the factor scaling sampled packet counts to estimated totals.
+    ///
+    /// This is the observed ratio of seen to sampled packets, which differs
+    /// from the nominal ratio for flow sampling.
+    pub fn scale(&self) -> f64 {
+        if self.sampled == 0 {
+            return self.sampling.ratio() as f64;
+        }
+
+        self.seen as f64 / self.sampled as f64
+    }
+
+    fn keep(&self, frame: &[u8]) -> bool {
+        let n = self.sampling.ratio() as u64;
+        match self.sampling {
+            Sampling::All => true,
+            Sampling::OneInN(_) => self.seen.is_multiple_of(n),
+            Sampling::FlowHash { seed, .. } => {
+                let key = Eth::new(frame).ok().and_then(|eth| FlowKey::from_eth(&eth));
+                match key {
+                    Some(key) => flow_hash(&key.canonical(), seed).is_multiple_of(n),
+                    None => self.seen.is_multiple_of(n),
+                }
+            }
+        }
+    }
+}
+
+impl<A: Analyzer> Analyzer for Sampled<A> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let keep = self.keep(frame);
+        self.seen += 1;
+
+        if keep {
+            self.sampled += 1;
+            self.inner.on_packet(ts, frame);
+        }
+    }
+}
+
+impl<A: Analyzer + ToTable> ToTable for Sampled<A> {
+    /// Convert the wrapped analyzer into a table, appending the sampling
+    /// strategy and the observed scale factor to every row.
+    fn to_table(&self) -> Table {
+        let mut table = self.inner.to_table();
+        let rows = table.len();
+        table
+            .push(
+                "sampling",
+                Column::Str(vec![self.sampling.to_string(); rows]),
+            )
+            .push("sampling_scale", Column::F64(vec![self.scale(); rows]));
+        table
+    }
+}
+
+/// Hash a flow key with FNV-1a, stable across runs and platforms.
+fn flow_hash(key: &FlowKey, seed: u64) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+    let mut hash = 0xCBF2_9CE4_8422_2325 ^ seed;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    };
+
+    for addr in [key.src, key.dst] {
+        match addr {
+            IpAddr::V4(addr) => write(&addr.octets()),
+            IpAddr::V6(addr) => write(&addr.octets()),
+        }
+    }
+    write(&key.src_port.to_be_bytes());
+    write(&key.dst_port.to_be_bytes());
+    write(&[u8::from(key.protocol)]);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    #[derive(Default)]
+    struct Count(Vec<u16>);
+
+    impl Analyzer for Count {
+        fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+            let eth = Eth::new(frame).unwrap();
+            self.0.push(FlowKey::from_eth(&eth).unwrap().src_port);
+        }
+    }
+
+    impl ToTable for Count {
+        fn to_table(&self) -> Table {
+            let mut table = Table::new();
+            table.push("packets", Column::U64(vec![self.0.len() as u64]));
+            table
+        }
+    }
+
+    fn frame(src_port: u16, reply: bool) -> Vec<u8> {
+        let client = (Ipv4Addr::new(10, 0, 0, 1), src_port);
+        let server = (Ipv4Addr::new(10, 0, 0, 2), 53);
+        if reply {
+            udp_frame(server, client, [])
+        } else {
+            udp_frame(client, server, [])
+        }
+    }
+
+    #[test]
+    fn sampling_one_in_n() {
+        let mut sampled = Sampled::new(Sampling::OneInN(4), Count::default());
+        for port in 0..10 {
+            sampled.on_packet(Duration::ZERO, &frame(port, false));
+        }
+
+        assert_eq!(sampled.inner().0, [0, 4, 8]);
+        assert_eq!(sampled.seen(), 10);
+        assert_eq!(sampled.sampled(), 3);
+
+        let table = sampled.to_table();
+        assert_eq!(
+            table.column("sampling"),
+            Some(&Column::Str(vec!["1-in-4".into()]))
+        );
+        assert_eq!(
+            table.column("sampling_scale"),
+            Some(&Column::F64(vec![10.0 / 3.0]))
+        );
+    }
+
+    #[test]
+    fn sampling_flow_hash() {
+        let sampling = Sampling::FlowHash { n: 4, seed: 1 };
+        let mut sampled = Sampled::new(sampling, Count::default());
+        for port in 1000..1100 {
+            sampled.on_packet(Duration::ZERO, &frame(port, false));
+            sampled.on_packet(Duration::ZERO, &frame(port, true));
+        }
+
+        // Both directions of a sampled flow are kept.
+        let ports = &sampled.inner().0;
+        assert_eq!(ports.len() % 2, 0);
+        assert!(ports.chunks(2).all(|p| p[1] == 53));
+        assert!(ports.len() > 20 && ports.len() < 80);
+
+        let mut again = Sampled::new(sampling, Count::default());
+        for port in 1000..1100 {
+            again.on_packet(Duration::ZERO, &frame(port, false));
+            again.on_packet(Duration::ZERO, &frame(port, true));
+        }
+        assert_eq!(&again.inner().0, ports);
+    }
+}
@@ -0,0 +1,185 @@
+//! Snapshots of analyzer state for rolling restarts.
+//!
+//! A live probe tracking long-lived flows loses their context when it is
+//! restarted, e.g. to upgrade it. [`save_snapshot`] writes the state of an
+//! analyzer, including its flow table, to a compact binary snapshot which
+//! [`load_snapshot`] reloads into the new process.
+//!
+//! Unlike a [`Checkpointer`](super::checkpoint::Checkpointer), a snapshot
+//! carries no position in a capture. The state is serialized by the
+//! [`Checkpoint`] implementation of the analyzer and framed with a version
+//! and a SHA-256 digest, so snapshots of other versions and corrupted or
+//! truncated snapshots are rejected before touching the analyzer.
+//!
+//! ```
+//! use netkit::analysis::{snapshot::*, tcp::TcpFlowAnalyzer};
+//!
+//! let analyzer = TcpFlowAnalyzer::new();
+//! let mut snapshot = Vec::new();
+//! save_snapshot(&mut snapshot, &analyzer).unwrap();
+//!
+//! let mut restarted = TcpFlowAnalyzer::new();
+//! load_snapshot(&snapshot[..], &mut restarted).unwrap();
+//!
+//! snapshot[20] ^= 1;
+//! assert!(load_snapshot(&snapshot[..], &mut restarted).is_err());
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::checkpoint::*;
+
+/// Magic bytes of snapshots.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"NKSS";
+
+/// Version of the snapshot framing.
+///
+/// The state inside is versioned by [`CHECKPOINT_VERSION`].
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Write a snapshot of the state of an analyzer.
+pub fn save_snapshot<A: Checkpoint + ?Sized>(
+    mut writer: impl Write,
+    analyzer: &A,
+) -> io::Result<()> {
+    let mut state = Vec::new();
+    analyzer.save(&mut state)?;
+
+    writer.write_all(&SNAPSHOT_MAGIC)?;
+    write_u32(&mut writer, SNAPSHOT_VERSION)?;
+    write_u32(&mut writer, CHECKPOINT_VERSION)?;
+    write_u64(&mut writer, state.len() as u64)?;
+    writer.write_all(&state)?;
+    writer.write_all(&Sha256::digest(&state))?;
+    writer.flush()
+}
+
+/// Replace the state of an analyzer with a snapshot.
+///
+/// The analyzer is left untouched if the snapshot is of another version,
+/// truncated or corrupted.
+pub fn load_snapshot<A: Checkpoint + ?Sized>(
+    mut reader: impl Read,
+    analyzer: &mut A,
+) -> io::Result<()> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != SNAPSHOT_MAGIC {
+        return Err(invalid_data("not a snapshot"));
+    }
+    let version = read_u32(&mut reader)?;
+    let state_version = read_u32(&mut reader)?;
+    if version != SNAPSHOT_VERSION || state_version != CHECKPOINT_VERSION {
+        return Err(invalid_data("snapshot of another version"));
+    }
+
+    let len = read_u64(&mut reader)?;
+    let mut state = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut state)?;
+    let mut digest = [0; 32];
+    if state.len() as u64 != len || reader.read_exact(&mut digest).is_err() {
+        return Err(invalid_data("truncated snapshot"));
+    }
+    if Sha256::digest(&state)[..] != digest {
+        return Err(invalid_data("corrupted snapshot"));
+    }
+
+    let mut state = &state[..];
+    analyzer.restore(&mut state)?;
+    if !state.is_empty() {
+        return Err(invalid_data("trailing data in snapshot"));
+    }
+    Ok(())
+}
+
+/// Write a snapshot to a file.
+///
+/// The snapshot is written to a temporary file first and renamed, so a
+/// crash while saving keeps the previous snapshot intact.
+pub fn save_snapshot_file<A: Checkpoint + ?Sized>(
+    path: impl AsRef<Path>,
+    analyzer: &A,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    save_snapshot(&mut writer, analyzer)?;
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(tmp, path)
+}
+
+/// Load a snapshot from a file, if any.
+///
+/// Returns whether a snapshot was loaded.
+pub fn load_snapshot_file<A: Checkpoint + ?Sized>(
+    path: impl AsRef<Path>,
+    analyzer: &mut A,
+) -> io::Result<bool> {
+    match File::open(path) {
+        Ok(file) => load_snapshot(BufReader::new(file), analyzer).map(|_| true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use netkit_packet::prelude::*;
+
+    use super::*;
+    use crate::analysis::{tcp::TcpFlowAnalyzer, Analyzer};
+
+    #[test]
+    fn snapshot_reload() {
+        let (a, b) = (Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(198, 51, 100, 1));
+        let mut analyzer = TcpFlowAnalyzer::new();
+        for i in 0..4u32 {
+            let tcp = netkit_packet::tcp!(
+                src_port: 40000u16,
+                dst_port: 80u16,
+                seq_num: 1000 + i * 100,
+                payload: [0; 100],
+            );
+            let ipv4 = ipv4!(src: a, dst: b, protocol: IpProtocol::Tcp, payload: tcp.inner());
+            let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+            analyzer.on_packet(Duration::from_secs(i as u64), eth.inner());
+        }
+        let key = FlowKey::new(a, b, 40000, 80, IpProtocol::Tcp);
+
+        let path = std::env::temp_dir().join(format!("netkit-snapshot-{}", std::process::id()));
+        save_snapshot_file(&path, &analyzer).unwrap();
+        let mut restarted = TcpFlowAnalyzer::new();
+        assert!(load_snapshot_file(&path, &mut restarted).unwrap());
+        assert_eq!(restarted.report(&key), analyzer.report(&key));
+        std::fs::remove_file(&path).unwrap();
+        assert!(!load_snapshot_file(&path, &mut restarted).unwrap());
+
+        let mut snapshot = Vec::new();
+        save_snapshot(&mut snapshot, &analyzer).unwrap();
+        let mut fresh = TcpFlowAnalyzer::new();
+        for corrupt in [
+            snapshot[..snapshot.len() - 1].to_vec(),
+            {
+                let mut s = snapshot.clone();
+                s[40] ^= 0x80;
+                s
+            },
+            {
+                let mut s = snapshot.clone();
+                s[4] = 2;
+                s
+            },
+        ] {
+            assert!(load_snapshot(&corrupt[..], &mut fresh).is_err());
+            assert!(fresh.report(&key).is_none());
+        }
+    }
+}
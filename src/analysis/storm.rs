@@ -0,0 +1,357 @@
+//! Broadcast, multicast and unknown-unicast storm detection.
+//!
+//! Flooded traffic reaches every port of a VLAN, so a storm of it degrades
+//! the whole segment. [`StormAnalyzer`] counts the [`FloodClass`] of every
+//! frame per VLAN and source MAC address, with the peak rate in a second,
+//! and reports a [`StormEvent`] when the rate of a class in a VLAN exceeds
+//! its threshold.
+//!
+//! Unicast frames are unknown unicast when their destination was never seen
+//! as the source of a frame in the VLAN: a switch has not learned it either,
+//! and floods them.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::Analyzer;
+use crate::export::{Column, Table, ToTable};
+
+/// Default number of broadcast frames per second and VLAN reported as a
+/// storm.
+pub const DEFAULT_BROADCAST_THRESHOLD: u64 = 1000;
+
+/// Default number of multicast frames per second and VLAN reported as a
+/// storm.
+pub const DEFAULT_MULTICAST_THRESHOLD: u64 = 5000;
+
+/// Default number of unknown-unicast frames per second and VLAN reported as
+/// a storm.
+pub const DEFAULT_UNKNOWN_UNICAST_THRESHOLD: u64 = 1000;
+
+/// Class of flooded frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FloodClass {
+    /// Frames to the broadcast address.
+    Broadcast,
+    /// Frames to a multicast address.
+    Multicast,
+    /// Unicast frames to an address not seen as a source in the VLAN.
+    UnknownUnicast,
+}
+
+impl FloodClass {
+    /// Get the name of the class, as in tables.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FloodClass::Broadcast => "broadcast",
+            FloodClass::Multicast => "multicast",
+            FloodClass::UnknownUnicast => "unknown-unicast",
+        }
+    }
+}
+
+/// Counters of flooded frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FloodStats {
+    /// Number of frames.
+    pub frames: u64,
+    /// Number of bytes.
+    pub bytes: u64,
+    /// Largest number of frames within a second.
+    pub peak: u64,
+    /// Timestamp of the first frame.
+    pub first: Duration,
+    /// Timestamp of the last frame.
+    pub last: Duration,
+    second: u64,
+    count: u64,
+}
+
+impl FloodStats {
+    /// Get the average number of frames per second, between the first and
+    /// the last frame.
+    pub fn rate(&self) -> f64 {
+        let duration = self.last.saturating_sub(self.first).as_secs_f64();
+        if duration > 0.0 {
+            self.frames as f64 / duration
+        } else {
+            self.frames as f64
+        }
+    }
+
+    /// Count a frame, returning the count within the current second.
+    fn add(&mut self, ts: Duration, len: usize) -> u64 {
+        if self.frames == 0 {
+            self.first = ts;
+        }
+        self.frames += 1;
+        self.bytes += len as u64;
+        self.last = ts;
+        if self.second != ts.as_secs() {
+            self.second = ts.as_secs();
+            self.count = 0;
+        }
+        self.count += 1;
+        self.peak = self.peak.max(self.count);
+        self.count
+    }
+}
+
+/// VLAN (`None` if untagged), source address and class of flooded frames.
+pub type SourceKey = (Option<u16>, EthAddr, FloodClass);
+
+/// Storm of flooded frames in a VLAN.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StormEvent {
+    /// Start of the second in which the threshold was reached.
+    pub ts: Duration,
+    /// VLAN of the frames (`None` if untagged).
+    pub vlan: Option<u16>,
+    /// Class of the frames.
+    pub class: FloodClass,
+    /// Number of frames in that second so far.
+    pub frames: u64,
+    /// Source sending the most of these frames in that second.
+    pub top_source: EthAddr,
+}
+
+/// Storm analyzer
+///
+/// A storm is reported once when its threshold is reached, and again in
+/// every later second it persists.
+#[derive(Clone, Debug)]
+pub struct StormAnalyzer {
+    thresholds: [u64; 3],
+    learned: HashSet<(Option<u16>, EthAddr)>,
+    vlans: BTreeMap<(Option<u16>, FloodClass), FloodStats>,
+    sources: HashMap<SourceKey, FloodStats>,
+    events: Vec<StormEvent>,
+}
+
+impl Default for StormAnalyzer {
+    fn default() -> Self {
+        Self {
+            thresholds: [
+                DEFAULT_BROADCAST_THRESHOLD,
+                DEFAULT_MULTICAST_THRESHOLD,
+                DEFAULT_UNKNOWN_UNICAST_THRESHOLD,
+            ],
+            learned: HashSet::new(),
+            vlans: BTreeMap::new(),
+            sources: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl StormAnalyzer {
+    /// Create a new storm analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of frames of a class per second and VLAN reported as
+    /// a storm.
+    pub fn threshold(&mut self, class: FloodClass, frames: u64) -> &mut Self {
+        self.thresholds[class as usize] = frames;
+        self
+    }
+
+    /// Get the counters of a class in a VLAN.
+    pub fn vlan(&self, vlan: Option<u16>, class: FloodClass) -> FloodStats {
+        self.vlans.get(&(vlan, class)).copied().unwrap_or_default()
+    }
+
+    /// Get the counters of a class sent by a source in a VLAN.
+    pub fn source(&self, vlan: Option<u16>, src: EthAddr, class: FloodClass) -> FloodStats {
+        self.sources
+            .get(&(vlan, src, class))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the counters of the sources, most frames first.
+    pub fn sources(&self) -> Vec<(SourceKey, FloodStats)> {
+        let mut sources: Vec<_> = self.sources.iter().map(|(k, v)| (*k, *v)).collect();
+        sources.sort_by(|(ka, a), (kb, b)| {
+            b.frames
+                .cmp(&a.frames)
+                .then_with(|| (ka.0, ka.1.to_string(), ka.2).cmp(&(kb.0, kb.1.to_string(), kb.2)))
+        });
+        sources
+    }
+
+    /// Get the events detected so far.
+    pub fn events(&self) -> &[StormEvent] {
+        &self.events
+    }
+
+    /// Take the events detected so far.
+    pub fn take_events(&mut self) -> Vec<StormEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn on_eth(&mut self, ts: Duration, eth: &Eth<&[u8]>, len: usize) {
+        let src = eth.src().get();
+        let dst = eth.dst().get();
+        let vlan = eth.vlan_ids().first().copied();
+        self.learned.insert((vlan, src));
+
+        let class = if dst.is_broadcast() {
+            FloodClass::Broadcast
+        } else if dst.is_multicast() {
+            FloodClass::Multicast
+        } else if !self.learned.contains(&(vlan, dst)) {
+            FloodClass::UnknownUnicast
+        } else {
+            return;
+        };
+
+        self.sources
+            .entry((vlan, src, class))
+            .or_default()
+            .add(ts, len);
+        let frames = self.vlans.entry((vlan, class)).or_default().add(ts, len);
+        if frames == self.thresholds[class as usize] {
+            let second = ts.as_secs();
+            let top_source = self
+                .sources
+                .iter()
+                .filter(|((v, _, c), s)| *v == vlan && *c == class && s.second == second)
+                .max_by_key(|(_, s)| s.count)
+                .map_or(src, |((_, mac, _), _)| *mac);
+            self.events.push(StormEvent {
+                ts: Duration::from_secs(second),
+                vlan,
+                class,
+                frames,
+                top_source,
+            });
+        }
+    }
+}
+
+impl Analyzer for StormAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        if let Ok(eth) = Eth::new(frame) {
+            self.on_eth(ts, &eth, frame.len());
+        }
+    }
+}
+
+impl ToTable for StormAnalyzer {
+    /// Convert into a table with one row per VLAN and class, with an empty
+    /// `src`, followed by one row per source, most frames first.
+    ///
+    /// Untagged frames are in VLAN 0.
+    fn to_table(&self) -> Table {
+        let rows: Vec<_> = self
+            .vlans
+            .iter()
+            .map(|((vlan, class), stats)| (*vlan, String::new(), *class, *stats))
+            .chain(
+                self.sources()
+                    .into_iter()
+                    .map(|((vlan, src, class), stats)| (vlan, src.to_string(), class, stats)),
+            )
+            .collect();
+        let u64s = |f: fn(&FloodStats) -> u64| Column::U64(rows.iter().map(|r| f(&r.3)).collect());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "vlan",
+                Column::U64(rows.iter().map(|r| r.0.unwrap_or(0) as u64).collect()),
+            )
+            .push(
+                "src",
+                Column::Str(rows.iter().map(|r| r.1.clone()).collect()),
+            )
+            .push(
+                "class",
+                Column::Str(rows.iter().map(|r| r.2.as_str().to_string()).collect()),
+            )
+            .push("frames", u64s(|s| s.frames))
+            .push("bytes", u64s(|s| s.bytes))
+            .push("peak_fps", u64s(|s| s.peak))
+            .push(
+                "avg_fps",
+                Column::F64(rows.iter().map(|r| r.3.rate()).collect()),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: EthAddr = EthAddr::new(0x02, 0, 0, 0, 0, 1);
+    const PEER: EthAddr = EthAddr::new(0x02, 0, 0, 0, 0, 2);
+
+    fn frame(src: EthAddr, dst: EthAddr, vlan: u16) -> Vec<u8> {
+        let tag = vlan!(vid: vlan, eth_type: EthType::Arp, payload: [0u8; 28]);
+        eth!(src: src, dst: dst, eth_type: EthType::Vlan, payload: tag.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn storm_analyzer() {
+        let mut analyzer = StormAnalyzer::new();
+        analyzer.threshold(FloodClass::Broadcast, 50);
+
+        // PEER is unknown until it sends a frame itself
+        analyzer.on_packet(Duration::ZERO, &frame(HOST, PEER, 10));
+        analyzer.on_packet(Duration::ZERO, &frame(PEER, HOST, 10));
+        analyzer.on_packet(Duration::ZERO, &frame(HOST, PEER, 10));
+        analyzer.on_packet(Duration::ZERO, &frame(HOST, PEER, 20));
+        let mdns = EthAddr::new(0x01, 0, 0x5e, 0, 0, 0xfb);
+        analyzer.on_packet(Duration::ZERO, &frame(HOST, mdns, 10));
+
+        for i in 0..60 {
+            let src = if i % 3 == 0 { HOST } else { PEER };
+            let ts = Duration::from_millis(1000 + i * 10);
+            analyzer.on_packet(ts, &frame(src, EthAddr::BROADCAST, 10));
+        }
+
+        let unknown = analyzer.vlan(Some(10), FloodClass::UnknownUnicast);
+        assert_eq!(unknown.frames, 1);
+        assert_eq!(
+            analyzer.vlan(Some(20), FloodClass::UnknownUnicast).frames,
+            1
+        );
+        assert_eq!(analyzer.vlan(Some(10), FloodClass::Multicast).frames, 1);
+
+        let broadcast = analyzer.vlan(Some(10), FloodClass::Broadcast);
+        assert_eq!((broadcast.frames, broadcast.peak), (60, 60));
+        assert!((broadcast.rate() - 60.0 / 0.59).abs() < 1e-6);
+        assert_eq!(
+            analyzer
+                .source(Some(10), PEER, FloodClass::Broadcast)
+                .frames,
+            40
+        );
+        assert_eq!(
+            analyzer.events(),
+            [StormEvent {
+                ts: Duration::from_secs(1),
+                vlan: Some(10),
+                class: FloodClass::Broadcast,
+                frames: 50,
+                top_source: PEER,
+            }]
+        );
+
+        let table = analyzer.to_table();
+        assert_eq!(table.len(), 4 + analyzer.sources().len());
+        assert_eq!(
+            table.column("peak_fps"),
+            Some(&Column::U64(vec![60, 1, 1, 1, 40, 20, 1, 1, 1]))
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! TCP stream reassembly.
+//!
+//! Application protocols see TCP as two byte streams, while captures hold
+//! segments: retransmitted, sometimes lost, and split anywhere. A
+//! [`TcpReassembler`] follows each direction in sequence order and hands out
+//! the bytes each segment adds to its stream.
+
+use netkit_packet::{
+    flow::table::FlowTable, layer::tcp::TcpFlags, prelude::*, utils::memory::MemoryAccount,
+};
+
+/// New bytes of a TCP stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamSegment {
+    /// Key of the direction.
+    pub key: FlowKey,
+    /// Bytes added to the stream, retransmitted bytes excluded.
+    pub data: Vec<u8>,
+    /// Whether bytes are missing before `data`, e.g. a segment was lost or
+    /// the capture started mid-stream.
+    pub gap: bool,
+    /// Whether the direction ended with a FIN or RST.
+    pub fin: bool,
+}
+
+/// Reassembler of TCP streams
+///
+/// Segments are followed per direction in sequence order: retransmitted
+/// bytes are skipped, and a gap (a lost or out of order segment) is reported
+/// and skipped over, so that consumers can resynchronize. A direction is
+/// forgotten on FIN or RST.
+///
+/// A reassembler given a [`MemoryAccount`] forgets the least recently used
+/// directions while the budget is exceeded; their next segments are
+/// reported as gaps.
+#[derive(Clone, Debug, Default)]
+pub struct TcpReassembler {
+    streams: FlowTable<Stream>,
+    clock: u64,
+}
+
+/// State of a direction.
+#[derive(Clone, Copy, Debug, Default)]
+struct Stream {
+    /// Next sequence number expected, if known.
+    next_seq: Option<u32>,
+    /// Value of the clock when last used.
+    used: u64,
+}
+
+impl TcpReassembler {
+    /// Create a new reassembler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of directions followed.
+    pub fn streams(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Charge the memory of the followed directions to an account.
+    pub fn memory(&mut self, account: MemoryAccount) -> &mut Self {
+        self.streams.memory(account);
+        self
+    }
+
+    /// Feed a packet, returning the bytes its TCP segment adds.
+    ///
+    /// Returns `None` for packets which are not TCP, and for segments adding
+    /// nothing to an open stream.
+    pub fn push<T: AsRef<[u8]>>(&mut self, ipv4: &Ipv4<T>) -> Option<StreamSegment> {
+        let tcp = ipv4.tcp()?;
+        let key = FlowKey::from_ipv4(ipv4);
+        let flags = tcp.flags().get();
+        let payload = tcp.payload();
+
+        // SYN occupies one sequence number.
+        let seq = if flags.contains(TcpFlags::SYN) {
+            tcp.seq_num().get().wrapping_add(1)
+        } else {
+            tcp.seq_num().get()
+        };
+        self.clock += 1;
+        let stream = self.streams.entry(key).or_default();
+        stream.used = self.clock;
+        let next_seq = &mut stream.next_seq;
+        if flags.contains(TcpFlags::SYN) {
+            *next_seq = Some(seq);
+        }
+        let gap = next_seq.is_none();
+        let next = *next_seq.get_or_insert(seq);
+
+        let delivered = next.wrapping_sub(seq) as i32;
+        let (data, gap) = if delivered < 0 {
+            (payload, true)
+        } else {
+            (payload.get(delivered as usize..).unwrap_or_default(), gap)
+        };
+        let end = seq.wrapping_add(payload.len() as u32);
+        if (end.wrapping_sub(next) as i32) > 0 {
+            *next_seq = Some(end);
+        }
+
+        let fin = flags.intersects(TcpFlags::FIN | TcpFlags::RST);
+        if fin {
+            self.streams.remove(&key);
+        } else {
+            self.streams.evict_by(|_, stream| stream.used);
+        }
+        // The first segment of a stream seen mid-way is a gap only if it
+        // carries data.
+        let gap = gap && !data.is_empty() && !flags.contains(TcpFlags::SYN);
+        if data.is_empty() && !fin && !gap {
+            return None;
+        }
+        Some(StreamSegment {
+            key,
+            data: data.to_vec(),
+            gap,
+            fin,
+        })
+    }
+}
+
+/// Default maximum length of a line in a [`LineBuffer`].
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Splitter of a byte stream into lines
+///
+/// Lines end with `\n`, and are handed out without their `\r\n` or `\n`.
+/// Lines longer than the maximum are skipped whole.
+#[derive(Clone, Debug)]
+pub struct LineBuffer {
+    buf: Vec<u8>,
+    overlong: bool,
+    max_len: usize,
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            overlong: false,
+            max_len: DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+}
+
+impl LineBuffer {
+    /// Create a new line buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum length of a line.
+    pub fn max_len(&mut self, max_len: usize) -> &mut Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Drop the partial line, e.g. after a gap in the stream.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.overlong = false;
+    }
+
+    /// Append stream bytes, calling `f` with each line they complete.
+    pub fn push(&mut self, mut data: &[u8], mut f: impl FnMut(&[u8])) {
+        while let Some(end) = data.iter().position(|&b| b == b'\n') {
+            if !self.overlong {
+                self.buf.extend_from_slice(&data[..end]);
+                let line = self.buf.strip_suffix(b"\r").unwrap_or(&self.buf);
+                if line.len() <= self.max_len {
+                    f(line);
+                }
+            }
+            self.clear();
+            data = &data[end + 1..];
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() > self.max_len {
+            self.buf.clear();
+            self.overlong = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::utils::memory::{MemoryBudget, Subsystem};
+
+    use super::*;
+
+    fn segment(seq: u32, flags: TcpFlags, payload: &[u8]) -> Ipv4<Vec<u8>> {
+        let tcp = tcp!(
+            src_port: 40000u16,
+            dst_port: 80u16,
+            seq_num: seq,
+            flags: flags,
+            payload: payload,
+        );
+        ipv4!(
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::Tcp,
+            payload: tcp.inner(),
+        )
+    }
+
+    #[test]
+    fn tcp_reassembly() {
+        let mut reassembler = TcpReassembler::new();
+        let data = |s: Option<StreamSegment>| s.map(|s| (s.data, s.gap, s.fin));
+
+        assert_eq!(
+            data(reassembler.push(&segment(99, TcpFlags::SYN, &[]))),
+            None
+        );
+        assert_eq!(
+            data(reassembler.push(&segment(100, TcpFlags::ACK, b"abc"))),
+            Some((b"abc".to_vec(), false, false))
+        );
+        // Retransmission overlapping the delivered bytes
+        assert_eq!(
+            data(reassembler.push(&segment(100, TcpFlags::ACK, b"abcde"))),
+            Some((b"de".to_vec(), false, false))
+        );
+        assert_eq!(
+            data(reassembler.push(&segment(101, TcpFlags::ACK, b"bc"))),
+            None
+        );
+        // Lost segment
+        assert_eq!(
+            data(reassembler.push(&segment(110, TcpFlags::ACK, b"xy"))),
+            Some((b"xy".to_vec(), true, false))
+        );
+        assert_eq!(
+            data(reassembler.push(&segment(112, TcpFlags::FIN, &[]))),
+            Some((Vec::new(), false, true))
+        );
+        assert_eq!(reassembler.streams(), 0);
+
+        // Stream joined mid-way
+        assert!(
+            reassembler
+                .push(&segment(500, TcpFlags::ACK, b"z"))
+                .unwrap()
+                .gap
+        );
+
+        // Least recently used directions are forgotten over budget
+        let entry = std::mem::size_of::<(FlowKey, Stream)>();
+        let budget = MemoryBudget::new(8 * entry);
+        let mut reassembler = TcpReassembler::new();
+        reassembler.memory(budget.account(Subsystem::Reassembly));
+        let from = |src_port: u16| {
+            let tcp =
+                tcp!(src_port: src_port, dst_port: 80u16, flags: TcpFlags::ACK, payload: b"z");
+            ipv4!(protocol: IpProtocol::Tcp, payload: tcp.inner())
+        };
+        for port in [0, 1, 2, 3, 4, 5, 6, 7, 0, 8] {
+            reassembler.push(&from(port));
+        }
+        assert_eq!(reassembler.streams(), 7);
+        // Port 0 was used again, so ports 1 and 2 were forgotten
+        assert!(reassembler.push(&from(0)).is_none());
+        assert!(reassembler.push(&from(1)).unwrap().gap);
+        assert_eq!(budget.usage().get(Subsystem::Reassembly).evictions, 2);
+    }
+}
@@ -0,0 +1,845 @@
+//! TCP flow quality analysis.
+//!
+//! Tracks every TCP flow direction seen in a capture and reports throughput
+//! (all bytes on the wire) against goodput (payload bytes delivered for the
+//! first time), retransmissions and idle gaps.
+//!
+//! Keep-alive probes (an empty or one-byte segment one below the next
+//! expected sequence number) are counted apart from retransmissions, and
+//! [`TcpFlowAnalyzer::idle_connections`] lists the connections held open
+//! without data, e.g. to check middlebox timeouts or connection pooling.
+//!
+//! Multipath TCP subflows are flagged from their MP_CAPABLE and MP_JOIN
+//! options, and [`TcpFlowAnalyzer::mptcp_connections`] groups the subflows
+//! of each connection.
+//!
+//! Advertised windows are scaled by the window scale negotiated in the
+//! handshake, so the window reports give true receive window sizes. Flows
+//! whose handshake was not captured report unscaled windows.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use netkit_packet::{
+    flow::table::{FlowTable, FlowTableConfig},
+    layer::tcp::{MptcpOption, TcpFlags},
+    prelude::*,
+};
+use sha2::{Digest, Sha256};
+
+use super::{checkpoint::*, Analyzer};
+use crate::export::{Column, Table, ToTable};
+
+/// Default gap after which a flow is considered idle.
+pub const DEFAULT_IDLE_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Quality report of a TCP flow direction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TcpFlowReport {
+    /// Number of segments.
+    pub packets: u64,
+    /// Number of bytes on the wire (Ipv4 total length).
+    pub bytes: u64,
+    /// Number of payload bytes, retransmissions included.
+    pub payload_bytes: u64,
+    /// Number of payload bytes sent for the first time.
+    pub goodput_bytes: u64,
+    /// Number of payload bytes sent again.
+    pub retransmitted_bytes: u64,
+    /// Number of segments carrying retransmitted payload.
+    pub retransmitted_packets: u64,
+    /// Timestamp of the first segment.
+    pub first_seen: Duration,
+    /// Timestamp of the last segment.
+    pub last_seen: Duration,
+    /// Number of gaps longer than the idle threshold.
+    pub idle_gaps: u64,
+    /// Total time spent in such gaps.
+    pub idle_time: Duration,
+    /// Longest gap between two consecutive segments.
+    pub max_idle: Duration,
+    /// Window scale shift count applied to the windows advertised by this
+    /// direction; `None` until the handshake is seen, and 0 if window
+    /// scaling was not negotiated.
+    pub window_scale: Option<u8>,
+    /// Last advertised receive window, in bytes.
+    pub last_window: u32,
+    /// Smallest advertised receive window, in bytes.
+    pub min_window: Option<u32>,
+    /// Largest advertised receive window, in bytes.
+    pub max_window: u32,
+    /// Number of segments advertising a zero window, resets excluded.
+    pub zero_windows: u64,
+    /// Number of keep-alive probes.
+    pub keepalives: u64,
+    /// Timestamp of the last segment carrying new payload.
+    pub last_data: Option<Duration>,
+    /// Whether a FIN or RST was seen.
+    pub closed: bool,
+    /// Role of the direction in a Multipath TCP connection, if any.
+    pub mptcp: Option<MptcpSubflow>,
+    next_seq: Option<u32>,
+    syn_scale: Option<Option<u8>>,
+}
+
+impl TcpFlowReport {
+    /// Get the time between the first and the last segment.
+    pub fn duration(&self) -> Duration {
+        self.last_seen.saturating_sub(self.first_seen)
+    }
+
+    /// Get the throughput in bytes per second.
+    ///
+    /// Returns `None` if the flow lasted no time.
+    pub fn throughput(&self) -> Option<f64> {
+        rate(self.bytes, self.duration())
+    }
+
+    /// Get the goodput in bytes per second.
+    ///
+    /// Returns `None` if the flow lasted no time.
+    pub fn goodput(&self) -> Option<f64> {
+        rate(self.goodput_bytes, self.duration())
+    }
+
+    /// Get the share of payload bytes that were retransmitted.
+    ///
+    /// Returns `None` if the flow carried no payload.
+    pub fn retransmission_rate(&self) -> Option<f64> {
+        if self.payload_bytes == 0 {
+            return None;
+        }
+
+        Some(self.retransmitted_bytes as f64 / self.payload_bytes as f64)
+    }
+
+    /// Get the time since new payload was last sent, or since the first
+    /// segment if none was.
+    pub fn idle_since_data(&self) -> Duration {
+        self.last_seen
+            .saturating_sub(self.last_data.unwrap_or(self.first_seen))
+    }
+
+    fn record(&mut self, ts: Duration, bytes: u64, idle_threshold: Duration) {
+        if self.packets == 0 {
+            self.first_seen = ts;
+        } else {
+            let gap = ts.saturating_sub(self.last_seen);
+            self.max_idle = self.max_idle.max(gap);
+            if gap >= idle_threshold {
+                self.idle_gaps += 1;
+                self.idle_time += gap;
+            }
+        }
+        self.last_seen = ts;
+        self.packets += 1;
+        self.bytes += bytes;
+    }
+
+    fn record_window(&mut self, window: u16, flags: TcpFlags) {
+        // Windows of SYN segments are never scaled.
+        let shift = if flags.contains(TcpFlags::SYN) {
+            0
+        } else {
+            self.window_scale.unwrap_or(0)
+        };
+        let window = (window as u32) << shift;
+
+        self.last_window = window;
+        self.min_window = Some(self.min_window.map_or(window, |min| min.min(window)));
+        self.max_window = self.max_window.max(window);
+        if window == 0 && !flags.contains(TcpFlags::RST) {
+            self.zero_windows += 1;
+        }
+    }
+
+    fn record_seq(&mut self, ts: Duration, seq: u32, len: u32, flags: TcpFlags) {
+        if flags.intersects(TcpFlags::FIN | TcpFlags::RST) {
+            self.closed = true;
+        }
+        // Keep-alives reuse the last sent sequence number, with at most one
+        // garbage byte, so that the peer answers with a duplicate ACK.
+        let control = TcpFlags::SYN | TcpFlags::FIN | TcpFlags::RST;
+        if len <= 1 && !flags.intersects(control) && self.next_seq == Some(seq.wrapping_add(1)) {
+            self.keepalives += 1;
+            return;
+        }
+
+        // SYN and FIN each occupy one sequence number.
+        let seq = if flags.contains(TcpFlags::SYN) {
+            seq.wrapping_add(1)
+        } else {
+            seq
+        };
+        let end = seq.wrapping_add(len);
+        let next = match self.next_seq {
+            Some(next) if !flags.contains(TcpFlags::SYN) => next,
+            _ => seq,
+        };
+
+        self.payload_bytes += len as u64;
+        // Bytes below the highest sequence number seen were already sent.
+        let old = next.wrapping_sub(seq);
+        let old = if old < 1 << 31 { old.min(len) } else { 0 };
+        if old > 0 {
+            self.retransmitted_bytes += old as u64;
+            self.retransmitted_packets += 1;
+        }
+        self.goodput_bytes += (len - old) as u64;
+        if len > old {
+            self.last_data = Some(ts);
+        }
+
+        let end = if flags.contains(TcpFlags::FIN) {
+            end.wrapping_add(1)
+        } else {
+            end
+        };
+        if end.wrapping_sub(next) < 1 << 31 {
+            self.next_seq = Some(end);
+        } else {
+            self.next_seq = Some(next);
+        }
+    }
+}
+
+/// Role of a flow direction in a Multipath TCP connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MptcpSubflow {
+    /// Initial subflow, whose MP_CAPABLE option carried the key of the
+    /// sender.
+    Initial {
+        /// Token of the connection at the sender, derived from its key.
+        token: u32,
+    },
+    /// Additional subflow, joining an existing connection.
+    Join {
+        /// Token of the connection at the receiver.
+        token: u32,
+        /// Address identifier of the sender.
+        address_id: u8,
+        /// Whether the subflow is a backup path.
+        backup: bool,
+    },
+}
+
+impl MptcpSubflow {
+    /// Get the connection token.
+    pub fn token(&self) -> u32 {
+        match *self {
+            Self::Initial { token } | Self::Join { token, .. } => token,
+        }
+    }
+}
+
+/// Get the MPTCP token of a key: the most significant 32 bits of its
+/// SHA-256 hash (RFC 8684, section 3.2).
+pub fn mptcp_token(key: u64) -> u32 {
+    let hash = Sha256::digest(key.to_be_bytes());
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+/// A Multipath TCP connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MptcpConnection {
+    /// Canonical key of the initial subflow.
+    pub initial: FlowKey,
+    /// Canonical keys of the additional subflows.
+    pub joins: Vec<FlowKey>,
+}
+
+fn rate(bytes: u64, duration: Duration) -> Option<f64> {
+    if duration.is_zero() {
+        return None;
+    }
+
+    Some(bytes as f64 / duration.as_secs_f64())
+}
+
+/// A connection held open without new payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdleConnection {
+    /// Key of the direction seen first.
+    pub key: FlowKey,
+    /// Time since new payload was last sent in either direction.
+    pub idle_for: Duration,
+    /// Number of keep-alive probes in both directions.
+    pub keepalives: u64,
+    /// Timestamp of the last segment in either direction.
+    pub last_seen: Duration,
+}
+
+/// TCP flow quality analyzer.
+#[derive(Clone, Debug)]
+pub struct TcpFlowAnalyzer {
+    idle_threshold: Duration,
+    flows: FlowTable<TcpFlowReport>,
+}
+
+impl Default for TcpFlowAnalyzer {
+    fn default() -> Self {
+        Self {
+            idle_threshold: DEFAULT_IDLE_THRESHOLD,
+            flows: FlowTable::new(),
+        }
+    }
+}
+
+impl TcpFlowAnalyzer {
+    /// Create a new TCP flow analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the gap after which a flow is considered idle.
+    pub fn idle_threshold(&mut self, idle_threshold: Duration) -> &mut Self {
+        self.idle_threshold = idle_threshold;
+        self
+    }
+
+    /// Set the configuration of the flow table, e.g. its hash key.
+    ///
+    /// Flows already tracked are dropped.
+    pub fn flow_table(&mut self, config: FlowTableConfig) -> &mut Self {
+        self.flows = FlowTable::with_config(config);
+        self
+    }
+
+    /// Get the report of a flow direction.
+    pub fn report(&self, key: &FlowKey) -> Option<&TcpFlowReport> {
+        self.flows.get(key)
+    }
+
+    /// Get the reports of all flow directions, ordered by endpoints.
+    pub fn reports(&self) -> Vec<(FlowKey, &TcpFlowReport)> {
+        let mut reports: Vec<_> = self.flows.iter().map(|(k, r)| (*k, r)).collect();
+        reports.sort_by_key(|(key, _)| (key.src, key.src_port, key.dst, key.dst_port));
+        reports
+    }
+
+    /// Get the connections open for at least `min_idle` without new payload
+    /// in either direction, ordered by endpoints.
+    ///
+    /// Each connection is reported once, keyed by the direction seen first.
+    /// Closed connections are excluded.
+    pub fn idle_connections(&self, min_idle: Duration) -> Vec<IdleConnection> {
+        let mut idle = Vec::new();
+        let mut seen = HashSet::new();
+        for (key, report) in self.reports() {
+            seen.insert(key);
+            let peer = self.flows.get(&key.reversed());
+            if peer.is_some_and(|peer| {
+                peer.first_seen < report.first_seen
+                    || (peer.first_seen == report.first_seen && seen.contains(&key.reversed()))
+            }) {
+                continue;
+            }
+
+            let last_seen = peer.map_or(report.last_seen, |p| p.last_seen.max(report.last_seen));
+            let last_data = [Some(report), peer]
+                .into_iter()
+                .flatten()
+                .filter_map(|r| r.last_data)
+                .max();
+            let idle_for = last_seen.saturating_sub(last_data.unwrap_or(report.first_seen));
+            if report.closed || peer.is_some_and(|p| p.closed) || idle_for < min_idle {
+                continue;
+            }
+
+            idle.push(IdleConnection {
+                key,
+                idle_for,
+                keepalives: report.keepalives + peer.map_or(0, |p| p.keepalives),
+                last_seen,
+            });
+        }
+        idle
+    }
+
+    /// Get the Multipath TCP connections, ordered by endpoints of their
+    /// initial subflow.
+    ///
+    /// Joins whose initial subflow was not captured are left out.
+    pub fn mptcp_connections(&self) -> Vec<MptcpConnection> {
+        let reports = self.reports();
+        let mut connections: Vec<MptcpConnection> = Vec::new();
+        let mut tokens = HashMap::new();
+        for (key, report) in &reports {
+            let Some(MptcpSubflow::Initial { token }) = report.mptcp else {
+                continue;
+            };
+            let initial = key.canonical();
+            let index = match connections.iter().position(|c| c.initial == initial) {
+                Some(index) => index,
+                None => {
+                    connections.push(MptcpConnection {
+                        initial,
+                        joins: Vec::new(),
+                    });
+                    connections.len() - 1
+                }
+            };
+            tokens.insert(token, index);
+        }
+
+        for (key, report) in &reports {
+            let Some(MptcpSubflow::Join { token, .. }) = report.mptcp else {
+                continue;
+            };
+            if let Some(index) = tokens.get(&token) {
+                let joins = &mut connections[*index].joins;
+                if !joins.contains(&key.canonical()) {
+                    joins.push(key.canonical());
+                }
+            }
+        }
+        connections
+    }
+
+    fn on_ipv4(&mut self, ts: Duration, ipv4: &Ipv4<&[u8]>) {
+        let Some(tcp) = ipv4.tcp() else {
+            return;
+        };
+
+        let key = self.flows.keying().key_ipv4(ipv4);
+        let flags = tcp.flags().get();
+        if flags.contains(TcpFlags::SYN) {
+            self.on_syn(key, tcp.window_scale(), flags.contains(TcpFlags::ACK));
+        }
+
+        let report = self.flows.entry(key).or_default();
+        report.record(ts, ipv4.total_length().get() as u64, self.idle_threshold);
+        report.record_window(tcp.window_size().get(), flags);
+        report.record_seq(ts, tcp.seq_num().get(), tcp.payload().len() as u32, flags);
+        if report.mptcp.is_none() {
+            report.mptcp = tcp.mptcp_options().find_map(|option| match option {
+                MptcpOption::MpCapable {
+                    sender_key: Some(key),
+                    ..
+                } => Some(MptcpSubflow::Initial {
+                    token: mptcp_token(key),
+                }),
+                MptcpOption::MpJoin {
+                    backup,
+                    address_id,
+                    token: Some(token),
+                } => Some(MptcpSubflow::Join {
+                    token,
+                    address_id,
+                    backup,
+                }),
+                _ => None,
+            });
+        }
+    }
+
+    /// Record the window scale option of a SYN, and once both SYNs are seen
+    /// settle the scale of both directions: scaling applies only if both
+    /// sides sent the option.
+    fn on_syn(&mut self, key: FlowKey, scale: Option<u8>, ack: bool) {
+        self.flows.entry(key).or_default().syn_scale = Some(scale);
+        if !ack {
+            return;
+        }
+
+        let peer_scale = self.flows.get(&key.reversed()).and_then(|r| r.syn_scale);
+        let Some(peer_scale) = peer_scale else {
+            return;
+        };
+        let (scale, peer_scale) = match (scale, peer_scale) {
+            (Some(scale), Some(peer_scale)) => (scale, peer_scale),
+            _ => (0, 0),
+        };
+        self.flows.entry(key).or_default().window_scale = Some(scale);
+        if let Some(peer) = self.flows.get_mut(&key.reversed()) {
+            peer.window_scale = Some(peer_scale);
+        }
+    }
+}
+
+impl Analyzer for TcpFlowAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_ipv4(ts, &ipv4);
+        }
+    }
+}
+
+impl Checkpoint for TcpFlowAnalyzer {
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        write_u64(writer, self.flows.len() as u64)?;
+        for (key, r) in &self.flows {
+            write_flow_key(writer, key)?;
+            for value in [
+                r.packets,
+                r.bytes,
+                r.payload_bytes,
+                r.goodput_bytes,
+                r.retransmitted_bytes,
+                r.retransmitted_packets,
+                r.idle_gaps,
+                r.zero_windows,
+                r.keepalives,
+            ] {
+                write_u64(writer, value)?;
+            }
+            for value in [r.first_seen, r.last_seen, r.idle_time, r.max_idle] {
+                write_duration(writer, value)?;
+            }
+            write_option(writer, r.window_scale, write_u8)?;
+            write_u32(writer, r.last_window)?;
+            write_option(writer, r.min_window, write_u32)?;
+            write_u32(writer, r.max_window)?;
+            write_option(writer, r.next_seq, write_u32)?;
+            write_option(writer, r.last_data, write_duration)?;
+            write_u8(writer, r.closed as u8)?;
+            match r.mptcp {
+                None => write_u8(writer, 0)?,
+                Some(MptcpSubflow::Initial { token }) => {
+                    write_u8(writer, 1)?;
+                    write_u32(writer, token)?;
+                }
+                Some(MptcpSubflow::Join {
+                    token,
+                    address_id,
+                    backup,
+                }) => {
+                    write_u8(writer, 2)?;
+                    write_u32(writer, token)?;
+                    write_u8(writer, address_id)?;
+                    write_u8(writer, backup as u8)?;
+                }
+            }
+            write_option(
+                writer,
+                r.syn_scale.map(|s| s.map_or(0, |s| s + 1)),
+                write_u8,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+        let len = read_u64(reader)?;
+        self.flows.clear();
+        for _ in 0..len {
+            let key = read_flow_key(reader)?;
+            let mut counters = [0; 9];
+            for value in &mut counters {
+                *value = read_u64(reader)?;
+            }
+            let mut durations = [Duration::ZERO; 4];
+            for value in &mut durations {
+                *value = read_duration(reader)?;
+            }
+            let [packets, bytes, payload_bytes, goodput_bytes] = [0, 1, 2, 3].map(|i| counters[i]);
+            let [retransmitted_bytes, retransmitted_packets, idle_gaps, zero_windows] =
+                [4, 5, 6, 7].map(|i| counters[i]);
+            let keepalives = counters[8];
+            let [first_seen, last_seen, idle_time, max_idle] = durations;
+
+            let report = TcpFlowReport {
+                packets,
+                bytes,
+                payload_bytes,
+                goodput_bytes,
+                retransmitted_bytes,
+                retransmitted_packets,
+                first_seen,
+                last_seen,
+                idle_gaps,
+                idle_time,
+                max_idle,
+                window_scale: read_option(reader, read_u8)?,
+                last_window: read_u32(reader)?,
+                min_window: read_option(reader, read_u32)?,
+                max_window: read_u32(reader)?,
+                zero_windows,
+                next_seq: read_option(reader, read_u32)?,
+                keepalives,
+                last_data: read_option(reader, read_duration)?,
+                closed: read_u8(reader)? != 0,
+                mptcp: match read_u8(reader)? {
+                    0 => None,
+                    1 => Some(MptcpSubflow::Initial {
+                        token: read_u32(reader)?,
+                    }),
+                    2 => Some(MptcpSubflow::Join {
+                        token: read_u32(reader)?,
+                        address_id: read_u8(reader)?,
+                        backup: read_u8(reader)? != 0,
+                    }),
+                    _ => return Err(invalid_data("invalid MPTCP subflow")),
+                },
+                syn_scale: read_option(reader, read_u8)?.map(|s| s.checked_sub(1)),
+            };
+            self.flows.insert(key, report);
+        }
+        Ok(())
+    }
+}
+
+impl ToTable for TcpFlowAnalyzer {
+    /// Convert into a table with one row per flow direction.
+    ///
+    /// Durations are in seconds and rates in bytes per second; rates of flows
+    /// lasting no time are `NaN`.
+    fn to_table(&self) -> Table {
+        let reports = self.reports();
+        let u64s =
+            |f: fn(&TcpFlowReport) -> u64| Column::U64(reports.iter().map(|(_, r)| f(r)).collect());
+        let f64s =
+            |f: fn(&TcpFlowReport) -> f64| Column::F64(reports.iter().map(|(_, r)| f(r)).collect());
+        let addrs =
+            |f: fn(&FlowKey) -> String| Column::Str(reports.iter().map(|(k, _)| f(k)).collect());
+        let ports = |f: fn(&FlowKey) -> u16| {
+            Column::U64(reports.iter().map(|(k, _)| f(k) as u64).collect())
+        };
+
+        let mut table = Table::new();
+        table
+            .push("src", addrs(|k| k.src.to_string()))
+            .push("src_port", ports(|k| k.src_port))
+            .push("dst", addrs(|k| k.dst.to_string()))
+            .push("dst_port", ports(|k| k.dst_port))
+            .push("packets", u64s(|r| r.packets))
+            .push("bytes", u64s(|r| r.bytes))
+            .push("payload_bytes", u64s(|r| r.payload_bytes))
+            .push("goodput_bytes", u64s(|r| r.goodput_bytes))
+            .push("retransmitted_bytes", u64s(|r| r.retransmitted_bytes))
+            .push("retransmitted_packets", u64s(|r| r.retransmitted_packets))
+            .push("first_seen", f64s(|r| r.first_seen.as_secs_f64()))
+            .push("duration", f64s(|r| r.duration().as_secs_f64()))
+            .push("throughput", f64s(|r| r.throughput().unwrap_or(f64::NAN)))
+            .push("goodput", f64s(|r| r.goodput().unwrap_or(f64::NAN)))
+            .push("idle_gaps", u64s(|r| r.idle_gaps))
+            .push("idle_time", f64s(|r| r.idle_time.as_secs_f64()))
+            .push("max_idle", f64s(|r| r.max_idle.as_secs_f64()))
+            .push(
+                "window_scale",
+                f64s(|r| r.window_scale.map_or(f64::NAN, |s| s as f64)),
+            )
+            .push("min_window", u64s(|r| r.min_window.unwrap_or(0) as u64))
+            .push("max_window", u64s(|r| r.max_window as u64))
+            .push("zero_windows", u64s(|r| r.zero_windows))
+            .push("keepalives", u64s(|r| r.keepalives))
+            .push(
+                "idle_since_data",
+                f64s(|r| r.idle_since_data().as_secs_f64()),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::tcp;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    const A: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const B: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(seq: u32, flags: TcpFlags, payload: &[u8]) -> Vec<u8> {
+        tcp_frame((A, 40000), (B, 80), seq, flags, payload)
+    }
+
+    #[test]
+    fn tcp_flow_report() {
+        let mut analyzer = TcpFlowAnalyzer::new();
+        let packets = [
+            (0, frame(1000, TcpFlags::SYN, &[])),
+            (100, frame(1001, TcpFlags::ACK, &[0; 100])),
+            (200, frame(1101, TcpFlags::ACK, &[0; 100])),
+            // Full and partial retransmissions.
+            (300, frame(1101, TcpFlags::ACK, &[0; 100])),
+            (2300, frame(1151, TcpFlags::ACK, &[0; 100])),
+            (2400, frame(1251, TcpFlags::FIN, &[])),
+        ];
+        for (ms, packet) in &packets {
+            analyzer.on_packet(Duration::from_millis(*ms), packet);
+        }
+
+        let key = FlowKey::new(A, B, 40000, 80, IpProtocol::Tcp);
+        let report = analyzer.report(&key).unwrap();
+        assert_eq!(report.packets, 6);
+        assert_eq!(report.payload_bytes, 400);
+        assert_eq!(report.goodput_bytes, 250);
+        assert_eq!(report.retransmitted_bytes, 150);
+        assert_eq!(report.retransmitted_packets, 2);
+        assert_eq!(report.idle_gaps, 1);
+        assert_eq!(report.max_idle, Duration::from_secs(2));
+        assert_eq!(report.goodput(), Some(250.0 / 2.4));
+        assert_eq!(report.retransmission_rate(), Some(150.0 / 400.0));
+
+        let table = analyzer.to_table();
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.column("goodput_bytes"), Some(&Column::U64(vec![250])));
+    }
+
+    #[test]
+    fn tcp_keepalive_and_idle() {
+        let ack = |seq: u32, payload: &[u8]| frame(seq, TcpFlags::ACK, payload);
+        let mut analyzer = TcpFlowAnalyzer::new();
+        let packets = [
+            (0, ack(1000, &[0; 100])),
+            // Keep-alives with and without a garbage byte.
+            (45, ack(1099, &[])),
+            (90, ack(1099, &[0])),
+            (135, ack(1099, &[])),
+        ];
+        for (secs, packet) in &packets {
+            analyzer.on_packet(Duration::from_secs(*secs), packet);
+        }
+
+        let key = FlowKey::new(A, B, 40000, 80, IpProtocol::Tcp);
+        let report = analyzer.report(&key).unwrap();
+        assert_eq!(report.keepalives, 3);
+        assert_eq!(report.retransmitted_packets, 0);
+        assert_eq!(report.idle_since_data(), Duration::from_secs(135));
+
+        let idle = analyzer.idle_connections(Duration::from_secs(120));
+        assert_eq!(idle.len(), 1);
+        assert_eq!(idle[0].key, key);
+        assert_eq!(idle[0].keepalives, 3);
+        assert!(analyzer
+            .idle_connections(Duration::from_secs(200))
+            .is_empty());
+
+        // Closed connections are not idle.
+        analyzer.on_packet(Duration::from_secs(140), &frame(1100, TcpFlags::FIN, &[]));
+        assert!(analyzer.idle_connections(Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn tcp_window_scaling() {
+        let segment = |from_a: bool, flags: TcpFlags, window: u16, options: &[u8]| {
+            let (src, dst, src_port, dst_port) = if from_a {
+                (A, B, 40000u16, 80u16)
+            } else {
+                (B, A, 80, 40000)
+            };
+            let tcp = tcp!(
+                src_port: src_port,
+                dst_port: dst_port,
+                flags: flags,
+                window_size: window,
+                options: options,
+            );
+            let ipv4 = ipv4!(src: src, dst: dst, protocol: IpProtocol::Tcp, payload: tcp.inner());
+            eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                .inner()
+                .clone()
+        };
+        let wscale = |shift: u8| [1, 3, 3, shift];
+
+        let mut analyzer = TcpFlowAnalyzer::new();
+        for packet in [
+            segment(true, TcpFlags::SYN, 64240, &wscale(7)),
+            segment(false, TcpFlags::SYN | TcpFlags::ACK, 65535, &wscale(2)),
+            segment(true, TcpFlags::ACK, 502, &[]),
+            segment(false, TcpFlags::ACK, 0, &[]),
+            segment(false, TcpFlags::ACK, 1000, &[]),
+        ] {
+            analyzer.on_packet(Duration::ZERO, &packet);
+        }
+
+        let key = FlowKey::new(A, B, 40000, 80, IpProtocol::Tcp);
+        let a = analyzer.report(&key).unwrap();
+        assert_eq!(a.window_scale, Some(7));
+        assert_eq!(a.last_window, 502 << 7);
+        assert_eq!(a.max_window, 502 << 7);
+
+        let b = analyzer.report(&key.reversed()).unwrap();
+        assert_eq!(b.window_scale, Some(2));
+        assert_eq!(b.last_window, 4000);
+        assert_eq!((b.min_window, b.max_window), (Some(0), 65535));
+        assert_eq!(b.zero_windows, 1);
+
+        // Without the option on both SYNs, windows are not scaled.
+        let mut analyzer = TcpFlowAnalyzer::new();
+        for packet in [
+            segment(true, TcpFlags::SYN, 64240, &wscale(7)),
+            segment(false, TcpFlags::SYN | TcpFlags::ACK, 65535, &[]),
+            segment(true, TcpFlags::ACK, 502, &[]),
+        ] {
+            analyzer.on_packet(Duration::ZERO, &packet);
+        }
+        let a = analyzer.report(&key).unwrap();
+        assert_eq!(a.window_scale, Some(0));
+        assert_eq!(a.last_window, 502);
+    }
+
+    #[test]
+    fn tcp_bad_data_offset() {
+        let key = FlowKey::new(A, B, 40000, 80, IpProtocol::Tcp);
+        let mut analyzer = TcpFlowAnalyzer::new();
+        // Data offsets below the fixed header and beyond the segment, on a
+        // SYN whose options would otherwise be parsed for the window scale.
+        for data_offset in [0x30, 0xf0] {
+            let mut packet = frame(1000, TcpFlags::SYN, &[]);
+            packet[14 + 20 + 12] = data_offset;
+            analyzer.on_packet(Duration::ZERO, &packet);
+        }
+        assert!(analyzer.report(&key).is_none());
+
+        analyzer.on_packet(Duration::ZERO, &frame(1000, TcpFlags::SYN, &[]));
+        assert_eq!(analyzer.report(&key).unwrap().packets, 1);
+    }
+
+    #[test]
+    fn tcp_mptcp_subflows() {
+        use netkit_packet::layer::tcp::KIND_MPTCP;
+
+        let segment = |src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16), options: &[u8]| {
+            let tcp = tcp!(
+                src_port: src.1,
+                dst_port: dst.1,
+                flags: TcpFlags::SYN,
+                options: options,
+            );
+            let ipv4 =
+                ipv4!(src: src.0, dst: dst.0, protocol: IpProtocol::Tcp, payload: tcp.inner());
+            eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                .inner()
+                .clone()
+        };
+        let key = 0x0123_4567_89AB_CDEFu64;
+        let token = mptcp_token(key);
+
+        let mut capable = vec![KIND_MPTCP, 12, 0x00, 0x81];
+        capable.extend(key.to_be_bytes());
+        let mut join = vec![KIND_MPTCP, 12, 0x10, 1];
+        join.extend(token.to_be_bytes());
+        join.extend([0; 4]);
+
+        let a2 = Ipv4Addr::new(192, 0, 2, 2);
+        let mut analyzer = TcpFlowAnalyzer::new();
+        analyzer.on_packet(Duration::ZERO, &segment((B, 80), (A, 40000), &capable));
+        analyzer.on_packet(Duration::ZERO, &segment((a2, 40001), (B, 80), &join));
+        analyzer.on_packet(Duration::ZERO, &segment((A, 40002), (B, 80), &[]));
+
+        let initial = FlowKey::new(B, A, 80, 40000, IpProtocol::Tcp);
+        assert_eq!(
+            analyzer.report(&initial).unwrap().mptcp,
+            Some(MptcpSubflow::Initial { token })
+        );
+        assert_eq!(
+            analyzer.mptcp_connections(),
+            [MptcpConnection {
+                initial: initial.canonical(),
+                joins: vec![FlowKey::new(a2, B, 40001, 80, IpProtocol::Tcp).canonical()],
+            }]
+        );
+    }
+}
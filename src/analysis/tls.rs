@@ -0,0 +1,467 @@
+//! TLS record and handshake parsing.
+//!
+//! A TLS connection carries records ([RFC 8446 section 5]) over each TCP
+//! stream direction, and the handshake messages are carried, possibly split
+//! or coalesced, in handshake records. [`TlsRecordStream`] splits a stream
+//! into records and [`HandshakeBuffer`] reassembles the handshake messages
+//! of one direction; [`HandshakeFollower`] does both for the plaintext part
+//! of the handshakes of many connections.
+//!
+//! [`CertificateAnalyzer`] extracts the server certificates of handshakes.
+//! With the `tls-decrypt` feature, `decrypt::TlsDecryptor` decrypts the
+//! application data of connections whose secrets are in a [`KeyLog`].
+//!
+//! [RFC 8446 section 5]: https://datatracker.ietf.org/doc/html/rfc8446#section-5
+
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+
+use super::stream::StreamSegment;
+
+pub mod cert;
+#[cfg(feature = "tls-decrypt")]
+pub mod decrypt;
+pub mod keylog;
+
+pub use cert::{Certificate, CertificateAnalyzer};
+pub use keylog::KeyLog;
+
+/// Content type of ChangeCipherSpec records.
+pub const CONTENT_CHANGE_CIPHER_SPEC: u8 = 20;
+/// Content type of alert records.
+pub const CONTENT_ALERT: u8 = 21;
+/// Content type of handshake records.
+pub const CONTENT_HANDSHAKE: u8 = 22;
+/// Content type of application data records.
+pub const CONTENT_APPLICATION_DATA: u8 = 23;
+
+/// Handshake type of ClientHello messages.
+pub const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+/// Handshake type of ServerHello messages.
+pub const HANDSHAKE_SERVER_HELLO: u8 = 2;
+/// Handshake type of Certificate messages.
+pub const HANDSHAKE_CERTIFICATE: u8 = 11;
+/// Handshake type of Finished messages.
+pub const HANDSHAKE_FINISHED: u8 = 20;
+
+/// TLS 1.2 protocol version.
+pub const TLS_1_2: u16 = 0x0303;
+/// TLS 1.3 protocol version.
+pub const TLS_1_3: u16 = 0x0304;
+
+/// Maximum length of a record fragment: 2^14 bytes of plaintext plus the
+/// allowed expansion of TLS 1.2 ciphertexts.
+pub const MAX_RECORD_LENGTH: usize = (1 << 14) + 2048;
+
+/// Maximum length of a handshake message; longer messages stop the parsing.
+pub const MAX_HANDSHAKE_LENGTH: usize = 256 * 1024;
+
+/// Server name indication extension.
+const EXT_SERVER_NAME: u16 = 0;
+/// Extension carrying the negotiated version in TLS 1.3 ServerHellos.
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+/// Extension carrying the selected pre-shared key in TLS 1.3 ServerHellos.
+const EXT_PRE_SHARED_KEY: u16 = 41;
+/// Random of ServerHellos which are HelloRetryRequests.
+const HELLO_RETRY_REQUEST: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// A TLS record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsRecord {
+    /// Content type.
+    pub content_type: u8,
+    /// Legacy record version.
+    pub version: u16,
+    /// Fragment, encrypted or not.
+    pub fragment: Vec<u8>,
+}
+
+impl TlsRecord {
+    /// Get the 5-byte header of the record.
+    pub fn header(&self) -> [u8; 5] {
+        let [v0, v1] = self.version.to_be_bytes();
+        let [l0, l1] = (self.fragment.len() as u16).to_be_bytes();
+        [self.content_type, v0, v1, l0, l1]
+    }
+}
+
+/// Splitter of one stream direction into TLS records.
+///
+/// Bytes which cannot be a record, such as a stream of another protocol,
+/// stop the parsing.
+#[derive(Clone, Debug, Default)]
+pub struct TlsRecordStream {
+    buf: Vec<u8>,
+    failed: bool,
+}
+
+impl TlsRecordStream {
+    /// Create a new record stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append stream bytes.
+    pub fn extend(&mut self, data: &[u8]) {
+        if !self.failed {
+            self.buf.extend_from_slice(data);
+        }
+    }
+
+    /// Stop parsing, e.g. after a gap in the stream, from which record
+    /// boundaries cannot be recovered.
+    pub fn fail(&mut self) {
+        self.failed = true;
+        self.buf = Vec::new();
+    }
+
+    /// Whether parsing stopped.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Get the next record from the bytes received so far.
+    pub fn next_record(&mut self) -> Option<TlsRecord> {
+        if self.failed {
+            return None;
+        }
+        let &[content_type, major, minor, ..] = &self.buf[..] else {
+            return None;
+        };
+        if !(CONTENT_CHANGE_CIPHER_SPEC..=CONTENT_APPLICATION_DATA).contains(&content_type)
+            || major != 3
+        {
+            self.fail();
+            return None;
+        }
+        let len = u16::from_be_bytes([*self.buf.get(3)?, *self.buf.get(4)?]) as usize;
+        if len > MAX_RECORD_LENGTH {
+            self.fail();
+            return None;
+        }
+        let fragment = self.buf.get(5..5 + len)?.to_vec();
+        self.buf.drain(..5 + len);
+        Some(TlsRecord {
+            content_type,
+            version: u16::from_be_bytes([major, minor]),
+            fragment,
+        })
+    }
+}
+
+/// A handshake message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandshakeMessage {
+    /// Handshake type.
+    pub msg_type: u8,
+    /// Body, without the type and length.
+    pub body: Vec<u8>,
+}
+
+/// Reassembler of the handshake messages of one stream direction.
+///
+/// Fed with the (decrypted) fragments of handshake records.
+#[derive(Clone, Debug, Default)]
+pub struct HandshakeBuffer {
+    buf: Vec<u8>,
+    failed: bool,
+}
+
+impl HandshakeBuffer {
+    /// Create a new handshake buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the fragment of a handshake record.
+    pub fn extend(&mut self, fragment: &[u8]) {
+        if !self.failed {
+            self.buf.extend_from_slice(fragment);
+        }
+    }
+
+    /// Get the next complete message.
+    ///
+    /// Messages longer than [`MAX_HANDSHAKE_LENGTH`] stop the parsing.
+    pub fn next_message(&mut self) -> Option<HandshakeMessage> {
+        if self.failed {
+            return None;
+        }
+        let &[msg_type, l0, l1, l2, ..] = &self.buf[..] else {
+            return None;
+        };
+        let len = u32::from_be_bytes([0, l0, l1, l2]) as usize;
+        if len > MAX_HANDSHAKE_LENGTH {
+            self.failed = true;
+            self.buf = Vec::new();
+            return None;
+        }
+        let body = self.buf.get(4..4 + len)?.to_vec();
+        self.buf.drain(..4 + len);
+        Some(HandshakeMessage { msg_type, body })
+    }
+}
+
+/// A plaintext handshake message of a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandshakeEvent {
+    /// Key of the direction the message was sent in.
+    pub key: FlowKey,
+    /// Whether the client sent the message.
+    pub from_client: bool,
+    /// Message.
+    pub message: HandshakeMessage,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FollowedDirection {
+    records: TlsRecordStream,
+    handshake: HandshakeBuffer,
+    started: bool,
+    encrypted: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Followed {
+    /// Directions, indexed by whether they go from the higher endpoint.
+    directions: [FollowedDirection; 2],
+    client: Option<usize>,
+}
+
+/// Follower of the plaintext handshakes of TLS connections
+///
+/// Each direction is followed until it starts encrypting: at its
+/// ChangeCipherSpec up to TLS 1.2, and right after the ServerHello in TLS
+/// 1.3.
+#[derive(Clone, Debug, Default)]
+pub struct HandshakeFollower {
+    connections: FlowTable<Followed>,
+}
+
+impl HandshakeFollower {
+    /// Create a new handshake follower.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed reassembled stream bytes, returning the handshake messages they
+    /// complete.
+    pub fn push(&mut self, segment: &StreamSegment) -> Vec<HandshakeEvent> {
+        let canonical = segment.key.canonical();
+        let index = usize::from(segment.key != canonical);
+        let connection = self.connections.entry(canonical).or_default();
+
+        let mut events = Vec::new();
+        let direction = &mut connection.directions[index];
+        // A stream joined mid-way may still start at a record.
+        if segment.gap && direction.started {
+            direction.records.fail();
+        }
+        direction.started = true;
+        direction.records.extend(&segment.data);
+        while let Some(record) = connection.directions[index].records.next_record() {
+            let direction = &mut connection.directions[index];
+            if record.content_type == CONTENT_CHANGE_CIPHER_SPEC {
+                direction.encrypted = true;
+            }
+            if record.content_type != CONTENT_HANDSHAKE || direction.encrypted {
+                continue;
+            }
+            direction.handshake.extend(&record.fragment);
+            while let Some(message) = connection.directions[index].handshake.next_message() {
+                match message.msg_type {
+                    HANDSHAKE_CLIENT_HELLO => connection.client = Some(index),
+                    HANDSHAKE_SERVER_HELLO => {
+                        let hello = ServerHello::parse(&message.body);
+                        if hello.is_some_and(|h| h.version == TLS_1_3 && !h.is_retry_request()) {
+                            for direction in &mut connection.directions {
+                                direction.encrypted = true;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                events.push(HandshakeEvent {
+                    key: segment.key,
+                    from_client: connection.client == Some(index),
+                    message,
+                });
+                if connection.directions[index].encrypted {
+                    break;
+                }
+            }
+        }
+
+        if segment.fin {
+            self.connections.remove(&canonical);
+        } else if connection.directions.iter().all(|d| d.encrypted) {
+            // Nothing left to follow
+            for direction in &mut connection.directions {
+                direction.records.fail();
+            }
+        }
+        events
+    }
+}
+
+/// Fields of a ClientHello.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientHello {
+    /// Client random, identifying the connection in key logs.
+    pub random: [u8; 32],
+    /// Session ID, of a session to resume up to TLS 1.2.
+    pub session_id: Vec<u8>,
+    /// Offered cipher suites.
+    pub cipher_suites: Vec<u16>,
+    /// Server name indication.
+    pub server_name: Option<String>,
+}
+
+impl ClientHello {
+    /// Parse the body of a ClientHello message.
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        let mut r = Reader(body);
+        r.skip(2)?;
+        let random = r.take(32)?.try_into().unwrap();
+        let session_id = r.u8()? as usize;
+        let session_id = r.take(session_id)?.to_vec();
+        let suites = r.u16()? as usize;
+        let cipher_suites = r
+            .take(suites)?
+            .chunks_exact(2)
+            .map(|s| u16::from_be_bytes([s[0], s[1]]))
+            .collect();
+        let compression = r.u8()? as usize;
+        r.skip(compression)?;
+
+        let mut server_name = None;
+        for (kind, mut data) in extensions(&mut r) {
+            if kind == EXT_SERVER_NAME {
+                // Server name list with a host name entry
+                data.skip(2);
+                if data.u8() == Some(0) {
+                    server_name = data
+                        .u16()
+                        .and_then(|len| data.take(len as usize))
+                        .and_then(|name| std::str::from_utf8(name).ok())
+                        .map(str::to_string);
+                }
+            }
+        }
+        Some(Self {
+            random,
+            session_id,
+            cipher_suites,
+            server_name,
+        })
+    }
+}
+
+/// Fields of a ServerHello.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerHello {
+    /// Server random.
+    pub random: [u8; 32],
+    /// Session ID, echoing the client's when resuming up to TLS 1.2.
+    pub session_id: Vec<u8>,
+    /// Selected cipher suite.
+    pub cipher_suite: u16,
+    /// Negotiated version, from the supported versions extension if present.
+    pub version: u16,
+    /// Whether the server accepted a pre-shared key, resuming in TLS 1.3.
+    pub pre_shared_key: bool,
+}
+
+impl ServerHello {
+    /// Parse the body of a ServerHello message.
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        let mut r = Reader(body);
+        let mut version = r.u16()?;
+        let random = r.take(32)?.try_into().unwrap();
+        let session_id = r.u8()? as usize;
+        let session_id = r.take(session_id)?.to_vec();
+        let cipher_suite = r.u16()?;
+        r.skip(1)?;
+
+        let mut pre_shared_key = false;
+        for (kind, mut data) in extensions(&mut r) {
+            match kind {
+                EXT_SUPPORTED_VERSIONS => version = data.u16().unwrap_or(version),
+                EXT_PRE_SHARED_KEY => pre_shared_key = true,
+                _ => {}
+            }
+        }
+        Some(Self {
+            random,
+            session_id,
+            cipher_suite,
+            version,
+            pre_shared_key,
+        })
+    }
+
+    /// Whether the server resumes a session of a ClientHello: by pre-shared
+    /// key in TLS 1.3, and by echoing the session ID before.
+    ///
+    /// Session tickets are recognized as long as the client sends a session
+    /// ID with them, as most do.
+    pub fn resumes(&self, client: &ClientHello) -> bool {
+        if self.version == TLS_1_3 {
+            self.pre_shared_key
+        } else {
+            !self.session_id.is_empty() && self.session_id == client.session_id
+        }
+    }
+
+    /// Whether this is a HelloRetryRequest, asking the client for another
+    /// ClientHello.
+    pub fn is_retry_request(&self) -> bool {
+        self.random == HELLO_RETRY_REQUEST
+    }
+}
+
+/// Get the type and data of the extensions at the end of a hello, if any.
+fn extensions<'a>(r: &mut Reader<'a>) -> Vec<(u16, Reader<'a>)> {
+    let Some(len) = r.u16() else {
+        return Vec::new();
+    };
+    let mut data = Reader(r.0.get(..len as usize).unwrap_or(r.0));
+    let mut extensions = Vec::new();
+    while let (Some(kind), Some(len)) = (data.u16(), data.u16()) {
+        match data.take(len as usize) {
+            Some(ext) => extensions.push((kind, Reader(ext))),
+            None => break,
+        }
+    }
+    extensions
+}
+
+/// Cursor over big-endian encoded fields.
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (head, tail) = (self.0.get(..len)?, &self.0[len..]);
+        self.0 = tail;
+        Some(head)
+    }
+
+    pub(crate) fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u24(&mut self) -> Option<u32> {
+        self.take(3)
+            .map(|b| u32::from_be_bytes([0, b[0], b[1], b[2]]))
+    }
+}
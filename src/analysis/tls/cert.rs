@@ -0,0 +1,617 @@
+//! X.509 certificates of TLS handshakes.
+//!
+//! Servers send their certificate chain in the Certificate handshake
+//! message, in the clear up to TLS 1.2 and encrypted since TLS 1.3.
+//! [`CertificateAnalyzer`] extracts the chains of plaintext handshakes (and
+//! the decryptor of the `tls-decrypt` feature those of decrypted ones), and
+//! [`Certificate`] parses the basics of each certificate ([RFC 5280]) for
+//! inventory and expiry audits.
+//!
+//! [RFC 5280]: https://datatracker.ietf.org/doc/html/rfc5280
+
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+use sha2::{Digest, Sha256};
+
+use super::{
+    ClientHello, HandshakeFollower, Reader, HANDSHAKE_CERTIFICATE, HANDSHAKE_CLIENT_HELLO,
+};
+use crate::{
+    analysis::{
+        stream::{StreamSegment, TcpReassembler},
+        Analyzer,
+    },
+    export::{Column, Table, ToTable},
+};
+
+/// Distinguished name, as attribute type and value pairs.
+///
+/// Well-known attribute types are named by their short name, e.g. `CN`, and
+/// others by their dotted OID.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DistinguishedName(pub Vec<(String, String)>);
+
+impl DistinguishedName {
+    /// Get the (last) common name.
+    pub fn common_name(&self) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(kind, _)| kind == "CN")
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl fmt::Display for DistinguishedName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (kind, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{kind}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Subject alternative name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SubjectAltName {
+    /// DNS name, possibly a wildcard.
+    Dns(String),
+    /// Email address.
+    Email(String),
+    /// URI.
+    Uri(String),
+    /// IP address.
+    Ip(IpAddr),
+}
+
+impl fmt::Display for SubjectAltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectAltName::Dns(name) => write!(f, "DNS:{name}"),
+            SubjectAltName::Email(email) => write!(f, "email:{email}"),
+            SubjectAltName::Uri(uri) => write!(f, "URI:{uri}"),
+            SubjectAltName::Ip(ip) => write!(f, "IP:{ip}"),
+        }
+    }
+}
+
+/// Basic fields of an X.509 certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Certificate {
+    /// Serial number, big-endian.
+    pub serial: Vec<u8>,
+    /// Subject.
+    pub subject: DistinguishedName,
+    /// Issuer.
+    pub issuer: DistinguishedName,
+    /// Start of the validity period, since the epoch.
+    pub not_before: Duration,
+    /// End of the validity period, since the epoch.
+    pub not_after: Duration,
+    /// Subject alternative names.
+    pub subject_alt_names: Vec<SubjectAltName>,
+    /// SHA-256 fingerprint of the DER encoding.
+    pub fingerprint: [u8; 32],
+}
+
+impl Certificate {
+    /// Parse a DER-encoded certificate.
+    ///
+    /// The signature is neither parsed nor verified.
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        let (cert, _) = expect(der, 0x30)?;
+        let (mut tbs, _) = expect(cert, 0x30)?;
+        if tbs.first() == Some(&0xa0) {
+            // Explicit version
+            tbs = expect(tbs, 0xa0)?.1;
+        }
+        let (serial, rest) = expect(tbs, 0x02)?;
+        let (_signature, rest) = expect(rest, 0x30)?;
+        let (issuer, rest) = expect(rest, 0x30)?;
+        let (validity, rest) = expect(rest, 0x30)?;
+        let (subject, rest) = expect(rest, 0x30)?;
+        let (_public_key, mut rest) = expect(rest, 0x30)?;
+
+        let (not_before_tag, not_before, validity) = tlv(validity)?;
+        let (not_after_tag, not_after, _) = tlv(validity)?;
+
+        let mut subject_alt_names = Vec::new();
+        while let Some((tag, value, next)) = tlv(rest) {
+            rest = next;
+            if tag == 0xa3 {
+                subject_alt_names = extensions(value).unwrap_or_default();
+            }
+        }
+
+        Some(Self {
+            serial: serial.to_vec(),
+            subject: name(subject)?,
+            issuer: name(issuer)?,
+            not_before: time(not_before_tag, not_before)?,
+            not_after: time(not_after_tag, not_after)?,
+            subject_alt_names,
+            fingerprint: Sha256::digest(der).into(),
+        })
+    }
+
+    /// Whether the certificate is valid at a time since the epoch.
+    pub fn is_valid_at(&self, ts: Duration) -> bool {
+        self.not_before <= ts && ts <= self.not_after
+    }
+
+    /// Get the time left until the certificate expires, `None` if it
+    /// already has.
+    pub fn expires_in(&self, ts: Duration) -> Option<Duration> {
+        self.not_after.checked_sub(ts)
+    }
+
+    /// Whether the subject and issuer are the same.
+    pub fn is_self_issued(&self) -> bool {
+        self.subject == self.issuer
+    }
+}
+
+/// Split the body of a Certificate handshake message into DER-encoded
+/// certificates, the server's first.
+pub fn certificate_chain(body: &[u8], tls13: bool) -> Option<Vec<Vec<u8>>> {
+    let mut r = Reader(body);
+    if tls13 {
+        let context = r.u8()? as usize;
+        r.skip(context)?;
+    }
+    let len = r.u24()? as usize;
+    let mut list = Reader(r.take(len)?);
+    let mut chain = Vec::new();
+    while !list.0.is_empty() {
+        let len = list.u24()? as usize;
+        chain.push(list.take(len)?.to_vec());
+        if tls13 {
+            // Per-certificate extensions
+            let len = list.u16()? as usize;
+            list.skip(len)?;
+        }
+    }
+    Some(chain)
+}
+
+/// Read a DER tag and length, returning the tag, value and the rest.
+fn tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+    let len = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || data.len() < n {
+            return None;
+        }
+        let len = data[..n].iter().fold(0, |len, &b| len << 8 | b as usize);
+        data = &data[n..];
+        len
+    };
+    (data.len() >= len).then(|| (tag, &data[..len], &data[len..]))
+}
+
+/// Read a DER value with an expected tag, returning the value and the rest.
+fn expect(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (t, value, rest) = tlv(data)?;
+    (t == tag).then_some((value, rest))
+}
+
+/// Parse the content of a Name.
+fn name(mut rdns: &[u8]) -> Option<DistinguishedName> {
+    let mut name = DistinguishedName::default();
+    while !rdns.is_empty() {
+        let (mut set, rest) = expect(rdns, 0x31)?;
+        rdns = rest;
+        while !set.is_empty() {
+            let (attribute, rest) = expect(set, 0x30)?;
+            set = rest;
+            let (oid, value) = expect(attribute, 0x06)?;
+            let (tag, value, _) = tlv(value)?;
+            name.0.push((attribute_type(oid), string(tag, value)));
+        }
+    }
+    Some(name)
+}
+
+/// Get the short name of an attribute type, or its dotted OID.
+fn attribute_type(oid: &[u8]) -> String {
+    let name = match oid {
+        [0x55, 0x04, 0x03] => "CN",
+        [0x55, 0x04, 0x05] => "serialNumber",
+        [0x55, 0x04, 0x06] => "C",
+        [0x55, 0x04, 0x07] => "L",
+        [0x55, 0x04, 0x08] => "ST",
+        [0x55, 0x04, 0x0a] => "O",
+        [0x55, 0x04, 0x0b] => "OU",
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01] => "emailAddress",
+        [0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19] => "DC",
+        _ => return dotted(oid),
+    };
+    name.to_string()
+}
+
+/// Format an OID in dotted notation.
+fn dotted(oid: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut arc = 0u64;
+    for &b in oid {
+        arc = arc << 7 | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - 40 * first);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+    }
+    arcs.iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Decode a directory string.
+fn string(tag: u8, value: &[u8]) -> String {
+    match tag {
+        // BMPString
+        0x1e => char::decode_utf16(
+            value
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]])),
+        )
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect(),
+        _ => String::from_utf8_lossy(value).into_owned(),
+    }
+}
+
+/// Parse a UTCTime or GeneralizedTime in UTC.
+fn time(tag: u8, value: &[u8]) -> Option<Duration> {
+    let value = std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        0x17 => {
+            let year: i64 = value.get(..2)?.parse().ok()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &value[2..],
+            )
+        }
+        0x18 => (value.get(..4)?.parse().ok()?, &value[4..]),
+        _ => return None,
+    };
+    if rest.len() != 10 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize| rest[i..i + 2].parse::<i64>().unwrap();
+    let (month, day) = (field(0), field(2));
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch in the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + field(4) * 3600 + field(6) * 60 + field(8);
+    u64::try_from(secs).ok().map(Duration::from_secs)
+}
+
+/// Get the subject alternative names from the extensions.
+fn extensions(value: &[u8]) -> Option<Vec<SubjectAltName>> {
+    let (mut extensions, _) = expect(value, 0x30)?;
+    while !extensions.is_empty() {
+        let (extension, rest) = expect(extensions, 0x30)?;
+        extensions = rest;
+        let (oid, mut rest) = expect(extension, 0x06)?;
+        if let Some((_critical, next)) = expect(rest, 0x01) {
+            rest = next;
+        }
+        let (octets, _) = expect(rest, 0x04)?;
+        if oid == [0x55, 0x1d, 0x11] {
+            return subject_alt_names(octets);
+        }
+    }
+    Some(Vec::new())
+}
+
+fn subject_alt_names(octets: &[u8]) -> Option<Vec<SubjectAltName>> {
+    let (mut names, _) = expect(octets, 0x30)?;
+    let mut sans = Vec::new();
+    while let Some((tag, value, rest)) = tlv(names) {
+        names = rest;
+        let text = || String::from_utf8_lossy(value).into_owned();
+        let san = match tag {
+            0x81 => SubjectAltName::Email(text()),
+            0x82 => SubjectAltName::Dns(text()),
+            0x86 => SubjectAltName::Uri(text()),
+            0x87 => match value.len() {
+                4 => SubjectAltName::Ip(Ipv4Addr::from(<[u8; 4]>::try_from(value).ok()?).into()),
+                16 => SubjectAltName::Ip(Ipv6Addr::from(<[u8; 16]>::try_from(value).ok()?).into()),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        sans.push(san);
+    }
+    Some(sans)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Certificate chain sent by the server of a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertificateRecord {
+    /// Timestamp of the segment completing the Certificate message.
+    pub ts: Duration,
+    /// Key of the client to server direction.
+    pub key: FlowKey,
+    /// Server name the client asked for.
+    pub server_name: Option<String>,
+    /// Parsed certificates, the server's first.
+    pub chain: Vec<Certificate>,
+}
+
+impl CertificateRecord {
+    /// Get the server's certificate.
+    pub fn leaf(&self) -> Option<&Certificate> {
+        self.chain.first()
+    }
+}
+
+/// Extractor of the server certificates of plaintext TLS handshakes.
+///
+/// Handshakes of TLS 1.3, whose certificates are encrypted, are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct CertificateAnalyzer {
+    reassembler: TcpReassembler,
+    follower: HandshakeFollower,
+    /// Server names asked for by the clients, by canonical key.
+    server_names: FlowTable<Option<String>>,
+    records: Vec<CertificateRecord>,
+}
+
+impl CertificateAnalyzer {
+    /// Create a new certificate analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the certificate chains seen so far.
+    pub fn records(&self) -> &[CertificateRecord] {
+        &self.records
+    }
+
+    /// Take the certificate chains seen so far.
+    pub fn take_records(&mut self) -> Vec<CertificateRecord> {
+        std::mem::take(&mut self.records)
+    }
+
+    /// Feed reassembled stream bytes.
+    pub fn on_segment(&mut self, ts: Duration, segment: StreamSegment) {
+        let canonical = segment.key.canonical();
+        for event in self.follower.push(&segment) {
+            match event.message.msg_type {
+                HANDSHAKE_CLIENT_HELLO => {
+                    let hello = ClientHello::parse(&event.message.body);
+                    self.server_names
+                        .insert(canonical, hello.and_then(|h| h.server_name));
+                }
+                HANDSHAKE_CERTIFICATE if !event.from_client => {
+                    let chain = certificate_chain(&event.message.body, false)
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(|der| Certificate::parse(der))
+                        .collect();
+                    self.records.push(CertificateRecord {
+                        ts,
+                        key: event.key.reversed(),
+                        server_name: self.server_names.remove(&canonical).flatten(),
+                        chain,
+                    });
+                }
+                _ => {}
+            }
+        }
+        if segment.fin {
+            self.server_names.remove(&canonical);
+        }
+    }
+}
+
+impl Analyzer for CertificateAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        if let Some(segment) = self.reassembler.push(&ipv4) {
+            self.on_segment(ts, segment);
+        }
+    }
+}
+
+impl ToTable for CertificateAnalyzer {
+    /// Convert into a table with one row per chain, describing the server's
+    /// certificate.
+    ///
+    /// Validity times are in seconds since the epoch, and alternative names
+    /// are comma-separated; fields of a missing certificate are empty or 0.
+    fn to_table(&self) -> Table {
+        let records = &self.records;
+        let strs =
+            |f: fn(&CertificateRecord) -> String| Column::Str(records.iter().map(f).collect());
+        let u64s = |f: fn(&CertificateRecord) -> u64| Column::U64(records.iter().map(f).collect());
+        let leaf = |f: fn(&Certificate) -> String| {
+            Column::Str(
+                records
+                    .iter()
+                    .map(|r| r.leaf().map(f).unwrap_or_default())
+                    .collect(),
+            )
+        };
+        let secs = |f: fn(&Certificate) -> Duration| {
+            Column::U64(
+                records
+                    .iter()
+                    .map(|r| r.leaf().map_or(0, |c| f(c).as_secs()))
+                    .collect(),
+            )
+        };
+
+        let mut table = Table::new();
+        table
+            .push("client", strs(|r| r.key.src.to_string()))
+            .push("server", strs(|r| r.key.dst.to_string()))
+            .push("server_port", u64s(|r| r.key.dst_port as u64))
+            .push(
+                "server_name",
+                strs(|r| r.server_name.clone().unwrap_or_default()),
+            )
+            .push("subject", leaf(|c| c.subject.to_string()))
+            .push("issuer", leaf(|c| c.issuer.to_string()))
+            .push(
+                "subject_alt_names",
+                leaf(|c| {
+                    c.subject_alt_names
+                        .iter()
+                        .map(SubjectAltName::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }),
+            )
+            .push("not_before", secs(|c| c.not_before))
+            .push("not_after", secs(|c| c.not_after))
+            .push("serial", leaf(|c| hex(&c.serial)))
+            .push("fingerprint", leaf(|c| hex(&c.fingerprint)))
+            .push("chain_length", u64s(|r| r.chain.len() as u64));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::analysis::tls::{CONTENT_HANDSHAKE, HANDSHAKE_SERVER_HELLO};
+    use crate::fixture::tcp_frame;
+
+    /// Self-signed certificate for example.com, valid during 2024.
+    const CERT: &str = concat!(
+        "MIIB2zCCAYGgAwIBAgICEjQwCgYIKoZIzj0EAwIwNTELMAkGA1UEBhMCVVMxEDAOBgNVBAoMB0V4",
+        "YW1wbGUxFDASBgNVBAMMC2V4YW1wbGUuY29tMB4XDTI0MDEwMTAwMDAwMFoXDTI1MDEwMTAwMDAw",
+        "MFowNTELMAkGA1UEBhMCVVMxEDAOBgNVBAoMB0V4YW1wbGUxFDASBgNVBAMMC2V4YW1wbGUuY29t",
+        "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAES1e75LJIMp4HkGhCF7GtS0ELurF8mbyHfXMKXXDY",
+        "p9vvfrr8AGqPUQtoQvaTYFo2bOaB/wIElrB2pxyTlK0Xo6OBgDB+MB0GA1UdDgQWBBRAZ/WivsxV",
+        "0stl4unJteETkOTy9zAfBgNVHSMEGDAWgBRAZ/WivsxV0stl4unJteETkOTy9zAPBgNVHRMBAf8E",
+        "BTADAQH/MCsGA1UdEQQkMCKCC2V4YW1wbGUuY29tgg0qLmV4YW1wbGUuY29thwTAAAIBMAoGCCqG",
+        "SM49BAMCA0gAMEUCIFXcKooNARD0xijRcpL16Jn5SbAQ1VDvF4deh8899PvWAiEAoDRKZw1EQco5",
+        "fq8zptmi3t5qq1/XRf4WdjZR5G4ZtjA=",
+    );
+
+    fn frame(from_client: bool, payload: &[u8]) -> Vec<u8> {
+        let client = (Ipv4Addr::new(192, 0, 2, 1), 40000);
+        let server = (Ipv4Addr::new(192, 0, 2, 2), 443);
+        let (src, dst) = if from_client {
+            (client, server)
+        } else {
+            (server, client)
+        };
+        tcp_frame(src, dst, 1, TcpFlags::ACK, payload)
+    }
+
+    /// Wrap a handshake message into a record.
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() as u32).to_be_bytes();
+        let record_len = (body.len() as u16 + 4).to_be_bytes();
+        [
+            &[CONTENT_HANDSHAKE, 3, 3, record_len[0], record_len[1]],
+            &[msg_type, len[1], len[2], len[3]][..],
+            body,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn tls_certificate_extraction() {
+        let der = STANDARD.decode(CERT).unwrap();
+
+        let mut client_hello = vec![3, 3];
+        client_hello.extend_from_slice(&[1; 32]);
+        client_hello.extend_from_slice(&[0, 0, 2, 0xc0, 0x2f, 1, 0]);
+        // Server name extension
+        client_hello.extend_from_slice(&[0, 20, 0, 0, 0, 16, 0, 14, 0, 0, 11]);
+        client_hello.extend_from_slice(b"example.com");
+
+        let mut server_hello = vec![3, 3];
+        server_hello.extend_from_slice(&[2; 32]);
+        server_hello.extend_from_slice(&[0, 0xc0, 0x2f, 0]);
+
+        let entry = (der.len() as u32).to_be_bytes();
+        let list = (der.len() as u32 + 3).to_be_bytes();
+        let certificate = [&list[1..], &entry[1..], &der].concat();
+
+        let mut analyzer = CertificateAnalyzer::new();
+        let ts = Duration::from_secs;
+        let client = handshake(HANDSHAKE_CLIENT_HELLO, &client_hello);
+        analyzer.on_packet(ts(0), &frame(true, &client));
+        let server = [
+            handshake(HANDSHAKE_SERVER_HELLO, &server_hello),
+            handshake(HANDSHAKE_CERTIFICATE, &certificate),
+        ]
+        .concat();
+        analyzer.on_packet(ts(1), &frame(false, &server));
+
+        let records = analyzer.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].key.dst_port, 443);
+        assert_eq!(records[0].server_name.as_deref(), Some("example.com"));
+
+        let cert = records[0].leaf().unwrap();
+        assert_eq!(cert.serial, [0x12, 0x34]);
+        assert_eq!(cert.subject.to_string(), "C=US, O=Example, CN=example.com");
+        assert_eq!(cert.subject.common_name(), Some("example.com"));
+        assert!(cert.is_self_issued());
+        assert_eq!(cert.not_before, ts(1704067200));
+        assert_eq!(cert.not_after, ts(1735689600));
+        assert!(cert.is_valid_at(ts(1720000000)));
+        assert_eq!(cert.expires_in(ts(1735689600 - 86400)), Some(ts(86400)));
+        assert_eq!(
+            cert.subject_alt_names,
+            [
+                SubjectAltName::Dns("example.com".into()),
+                SubjectAltName::Dns("*.example.com".into()),
+                SubjectAltName::Ip(Ipv4Addr::new(192, 0, 2, 1).into()),
+            ]
+        );
+        assert_eq!(
+            hex(&cert.fingerprint),
+            "55f6d0b2e4af7b449b0dedfa4178cf0b6a74b882aa2abd98d7d59aed8e119bee"
+        );
+        assert_eq!(analyzer.to_table().len(), 1);
+    }
+}
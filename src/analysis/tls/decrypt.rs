@@ -0,0 +1,807 @@
+//! Decryption of TLS application data.
+//!
+//! [`TlsDecryptor`] follows the TLS connections of reassembled TCP streams,
+//! looks their secrets up in a [`KeyLog`] by client random, and hands out
+//! the decrypted application data as [`StreamSegment`]s, so that the
+//! protocol inside can be analyzed like a plaintext one:
+//!
+//! ```
+//! use netkit::analysis::{
+//!     http::HttpAnalyzer,
+//!     tls::{decrypt::TlsDecryptor, KeyLog},
+//!     Analyzer,
+//! };
+//!
+//! let mut tls = TlsDecryptor::new(KeyLog::new());
+//! let mut http = HttpAnalyzer::new();
+//! # let packets: Vec<(std::time::Duration, Vec<u8>)> = Vec::new();
+//! for (ts, frame) in packets {
+//!     tls.on_packet(ts, &frame);
+//!     for (ts, segment) in tls.take_segments() {
+//!         http.on_segment(ts, segment);
+//!     }
+//! }
+//! ```
+//!
+//! TLS 1.2 connections with AES-GCM or ChaCha20-Poly1305 cipher suites are
+//! decrypted with their master secret (`CLIENT_RANDOM`), and TLS 1.3
+//! connections with their handshake and first application traffic secrets.
+//! Key updates, 0-RTT data and CBC cipher suites are not supported.
+
+use std::time::Duration;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes128Gcm, Aes256Gcm,
+};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::SimpleHkdf;
+use hmac::{Mac, SimpleHmac};
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+use sha2::{
+    digest::{core_api::BlockSizeUser, Digest},
+    Sha256, Sha384,
+};
+
+use super::{
+    cert::{certificate_chain, Certificate, CertificateRecord},
+    keylog::{
+        CLIENT_HANDSHAKE_TRAFFIC_SECRET, CLIENT_RANDOM, CLIENT_TRAFFIC_SECRET_0,
+        SERVER_HANDSHAKE_TRAFFIC_SECRET, SERVER_TRAFFIC_SECRET_0,
+    },
+    ClientHello, HandshakeBuffer, KeyLog, ServerHello, TlsRecord, TlsRecordStream,
+    CONTENT_APPLICATION_DATA, CONTENT_CHANGE_CIPHER_SPEC, CONTENT_HANDSHAKE, HANDSHAKE_CERTIFICATE,
+    HANDSHAKE_CLIENT_HELLO, HANDSHAKE_FINISHED, HANDSHAKE_SERVER_HELLO, TLS_1_3,
+};
+use crate::analysis::{
+    stream::{StreamSegment, TcpReassembler},
+    Analyzer,
+};
+
+/// Length of AEAD authentication tags.
+const TAG_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AeadKind {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+/// A supported cipher suite.
+#[derive(Clone, Copy, Debug)]
+struct Suite {
+    id: u16,
+    aead: AeadKind,
+    sha384: bool,
+}
+
+const SUITES: &[Suite] = &[
+    // TLS 1.3
+    suite(0x1301, AeadKind::Aes128Gcm, false),
+    suite(0x1302, AeadKind::Aes256Gcm, true),
+    suite(0x1303, AeadKind::ChaCha20Poly1305, false),
+    // TLS 1.2 (EC)DHE and RSA key exchanges
+    suite(0x009c, AeadKind::Aes128Gcm, false),
+    suite(0x009d, AeadKind::Aes256Gcm, true),
+    suite(0x009e, AeadKind::Aes128Gcm, false),
+    suite(0x009f, AeadKind::Aes256Gcm, true),
+    suite(0xc02b, AeadKind::Aes128Gcm, false),
+    suite(0xc02c, AeadKind::Aes256Gcm, true),
+    suite(0xc02f, AeadKind::Aes128Gcm, false),
+    suite(0xc030, AeadKind::Aes256Gcm, true),
+    suite(0xcca8, AeadKind::ChaCha20Poly1305, false),
+    suite(0xcca9, AeadKind::ChaCha20Poly1305, false),
+    suite(0xccaa, AeadKind::ChaCha20Poly1305, false),
+];
+
+const fn suite(id: u16, aead: AeadKind, sha384: bool) -> Suite {
+    Suite { id, aead, sha384 }
+}
+
+impl Suite {
+    fn find(id: u16) -> Option<Self> {
+        SUITES.iter().find(|s| s.id == id).copied()
+    }
+
+    fn key_len(&self) -> usize {
+        match self.aead {
+            AeadKind::Aes128Gcm => 16,
+            AeadKind::Aes256Gcm | AeadKind::ChaCha20Poly1305 => 32,
+        }
+    }
+
+    /// Length of the TLS 1.2 implicit IV: a 4-byte salt for AES-GCM, whose
+    /// records carry the rest of the nonce.
+    fn fixed_iv_len(&self) -> usize {
+        match self.aead {
+            AeadKind::ChaCha20Poly1305 => 12,
+            _ => 4,
+        }
+    }
+
+    /// Derive the keys of a direction from a TLS 1.3 traffic secret.
+    fn traffic_cipher(&self, secret: &[u8]) -> Option<RecordCipher> {
+        let (key, iv) = if self.sha384 {
+            (
+                expand_label::<Sha384>(secret, "key", self.key_len())?,
+                expand_label::<Sha384>(secret, "iv", 12)?,
+            )
+        } else {
+            (
+                expand_label::<Sha256>(secret, "key", self.key_len())?,
+                expand_label::<Sha256>(secret, "iv", 12)?,
+            )
+        };
+        RecordCipher::new(self.aead, &key, &iv, true)
+    }
+
+    /// Derive the client and server keys from a TLS 1.2 master secret.
+    fn master_ciphers(
+        &self,
+        master: &[u8],
+        client_random: &[u8; 32],
+        server_random: &[u8; 32],
+    ) -> Option<(RecordCipher, RecordCipher)> {
+        let seed = [&server_random[..], &client_random[..]].concat();
+        let (key_len, iv_len) = (self.key_len(), self.fixed_iv_len());
+        let len = 2 * (key_len + iv_len);
+        let block = if self.sha384 {
+            prf::<Sha384>(master, b"key expansion", &seed, len)
+        } else {
+            prf::<Sha256>(master, b"key expansion", &seed, len)
+        };
+        let (client_key, rest) = block.split_at(key_len);
+        let (server_key, rest) = rest.split_at(key_len);
+        let (client_iv, server_iv) = rest.split_at(iv_len);
+        Some((
+            RecordCipher::new(self.aead, client_key, client_iv, false)?,
+            RecordCipher::new(self.aead, server_key, server_iv, false)?,
+        ))
+    }
+}
+
+/// HKDF-Expand-Label of TLS 1.3 with an empty context.
+fn expand_label<D>(secret: &[u8], label: &str, len: usize) -> Option<Vec<u8>>
+where
+    D: Digest + BlockSizeUser + Clone,
+{
+    let label = [b"tls13 ", label.as_bytes()].concat();
+    let mut info = (len as u16).to_be_bytes().to_vec();
+    info.push(label.len() as u8);
+    info.extend_from_slice(&label);
+    info.push(0);
+
+    let mut out = vec![0; len];
+    SimpleHkdf::<D>::from_prk(secret)
+        .ok()?
+        .expand(&info, &mut out)
+        .ok()?;
+    Some(out)
+}
+
+/// PRF of TLS 1.2.
+fn prf<D>(secret: &[u8], label: &[u8], seed: &[u8], len: usize) -> Vec<u8>
+where
+    D: Digest + BlockSizeUser + Clone,
+{
+    let seed = [label, seed].concat();
+    let hmac = |parts: &[&[u8]]| {
+        let mut mac = <SimpleHmac<D> as KeyInit>::new_from_slice(secret).unwrap();
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().to_vec()
+    };
+
+    let mut out = Vec::with_capacity(len);
+    let mut a = hmac(&[&seed]);
+    while out.len() < len {
+        out.extend(hmac(&[&a, &seed]));
+        a = hmac(&[&a]);
+    }
+    out.truncate(len);
+    out
+}
+
+#[derive(Clone)]
+enum AeadCipher {
+    Aes128Gcm(Box<Aes128Gcm>),
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(Box<ChaCha20Poly1305>),
+}
+
+/// Record protection keys of one direction.
+#[derive(Clone)]
+struct RecordCipher {
+    aead: AeadCipher,
+    iv: Vec<u8>,
+    tls13: bool,
+    seq: u64,
+}
+
+impl std::fmt::Debug for RecordCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordCipher")
+            .field("tls13", &self.tls13)
+            .field("seq", &self.seq)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RecordCipher {
+    fn new(kind: AeadKind, key: &[u8], iv: &[u8], tls13: bool) -> Option<Self> {
+        let aead = match kind {
+            AeadKind::Aes128Gcm => {
+                AeadCipher::Aes128Gcm(Box::new(Aes128Gcm::new_from_slice(key).ok()?))
+            }
+            AeadKind::Aes256Gcm => {
+                AeadCipher::Aes256Gcm(Box::new(Aes256Gcm::new_from_slice(key).ok()?))
+            }
+            AeadKind::ChaCha20Poly1305 => {
+                AeadCipher::ChaCha20Poly1305(Box::new(ChaCha20Poly1305::new_from_slice(key).ok()?))
+            }
+        };
+        Some(Self {
+            aead,
+            iv: iv.to_vec(),
+            tls13,
+            seq: 0,
+        })
+    }
+
+    /// Nonce of the next record, given the explicit part of TLS 1.2
+    /// AES-GCM records.
+    fn nonce(&self, explicit: Option<&[u8]>) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        match explicit {
+            Some(explicit) => {
+                nonce[..4].copy_from_slice(&self.iv);
+                nonce[4..].copy_from_slice(explicit);
+            }
+            None => {
+                nonce.copy_from_slice(&self.iv);
+                for (n, s) in nonce[4..].iter_mut().zip(self.seq.to_be_bytes()) {
+                    *n ^= s;
+                }
+            }
+        }
+        nonce
+    }
+
+    fn has_explicit_nonce(&self) -> bool {
+        self.iv.len() == 4
+    }
+
+    /// Additional data of TLS 1.2 records.
+    fn tls12_aad(&self, record: &TlsRecord, len: usize) -> Vec<u8> {
+        let mut aad = self.seq.to_be_bytes().to_vec();
+        aad.push(record.content_type);
+        aad.extend_from_slice(&record.version.to_be_bytes());
+        aad.extend_from_slice(&(len as u16).to_be_bytes());
+        aad
+    }
+
+    /// Decrypt a record, returning its content type and plaintext.
+    fn open(&mut self, record: &TlsRecord) -> Option<(u8, Vec<u8>)> {
+        let (nonce, ciphertext) = if self.has_explicit_nonce() {
+            let explicit = record.fragment.get(..8)?;
+            (self.nonce(Some(explicit)), &record.fragment[8..])
+        } else {
+            (self.nonce(None), &record.fragment[..])
+        };
+        let aad = if self.tls13 {
+            record.header().to_vec()
+        } else {
+            self.tls12_aad(record, ciphertext.len().checked_sub(TAG_LENGTH)?)
+        };
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &aad,
+        };
+        let plaintext = match &self.aead {
+            AeadCipher::Aes128Gcm(c) => c.decrypt((&nonce).into(), payload),
+            AeadCipher::Aes256Gcm(c) => c.decrypt((&nonce).into(), payload),
+            AeadCipher::ChaCha20Poly1305(c) => c.decrypt((&nonce).into(), payload),
+        }
+        .ok()?;
+        self.seq += 1;
+
+        if !self.tls13 {
+            return Some((record.content_type, plaintext));
+        }
+        // TLS 1.3 inner plaintext: content, content type, zero padding
+        let mut plaintext = plaintext;
+        let end = plaintext.iter().rposition(|&b| b != 0)?;
+        let content_type = plaintext[end];
+        plaintext.truncate(end);
+        Some((content_type, plaintext))
+    }
+
+    /// Encrypt a record, the inverse of [`open`](Self::open).
+    #[cfg(test)]
+    fn seal(&mut self, content_type: u8, data: &[u8]) -> TlsRecord {
+        let explicit = self.seq.to_be_bytes();
+        let (nonce, msg, outer_type) = if self.tls13 {
+            let msg = [data, &[content_type]].concat();
+            (self.nonce(None), msg, CONTENT_APPLICATION_DATA)
+        } else if self.has_explicit_nonce() {
+            (self.nonce(Some(&explicit)), data.to_vec(), content_type)
+        } else {
+            (self.nonce(None), data.to_vec(), content_type)
+        };
+        let mut record = TlsRecord {
+            content_type: outer_type,
+            version: 0x0303,
+            fragment: vec![0; msg.len() + TAG_LENGTH],
+        };
+        let aad = if self.tls13 {
+            record.header().to_vec()
+        } else {
+            self.tls12_aad(&record, msg.len())
+        };
+        let payload = Payload {
+            msg: &msg,
+            aad: &aad,
+        };
+        let ciphertext = match &self.aead {
+            AeadCipher::Aes128Gcm(c) => c.encrypt((&nonce).into(), payload),
+            AeadCipher::Aes256Gcm(c) => c.encrypt((&nonce).into(), payload),
+            AeadCipher::ChaCha20Poly1305(c) => c.encrypt((&nonce).into(), payload),
+        }
+        .unwrap();
+        record.fragment = if self.has_explicit_nonce() {
+            [&explicit[..], &ciphertext].concat()
+        } else {
+            ciphertext
+        };
+        self.seq += 1;
+        record
+    }
+}
+
+/// State of one direction of a connection.
+#[derive(Clone, Debug, Default)]
+struct Direction {
+    records: TlsRecordStream,
+    handshake: HandshakeBuffer,
+    /// Keys protecting the records.
+    cipher: Option<RecordCipher>,
+    /// Keys taking over at the ChangeCipherSpec (TLS 1.2) or after the
+    /// Finished message (TLS 1.3).
+    next: Option<RecordCipher>,
+    started: bool,
+    ended: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Connection {
+    /// Directions, indexed by whether they go from the higher endpoint.
+    directions: [Direction; 2],
+    client_random: Option<[u8; 32]>,
+    server_name: Option<String>,
+    /// Index of the client direction.
+    client: Option<usize>,
+    tls13: bool,
+    /// Whether keys were found for the connection.
+    decrypting: bool,
+}
+
+impl Connection {
+    /// Process the (decrypted) handshake messages of a direction, returning
+    /// the certificate chain of the server, if any.
+    fn on_handshake(
+        &mut self,
+        index: usize,
+        data: &[u8],
+        keylog: &KeyLog,
+    ) -> Option<Vec<Certificate>> {
+        let mut chain = None;
+        self.directions[index].handshake.extend(data);
+        while let Some(message) = self.directions[index].handshake.next_message() {
+            match message.msg_type {
+                HANDSHAKE_CLIENT_HELLO => {
+                    if let Some(hello) = ClientHello::parse(&message.body) {
+                        self.client = Some(index);
+                        self.client_random = Some(hello.random);
+                        self.server_name = hello.server_name;
+                    }
+                }
+                HANDSHAKE_CERTIFICATE if self.client != Some(index) => {
+                    chain = certificate_chain(&message.body, self.tls13).map(|chain| {
+                        chain
+                            .iter()
+                            .filter_map(|der| Certificate::parse(der))
+                            .collect()
+                    });
+                }
+                HANDSHAKE_SERVER_HELLO => {
+                    if let Some(hello) = ServerHello::parse(&message.body) {
+                        self.on_server_hello(index, hello, keylog);
+                    }
+                }
+                HANDSHAKE_FINISHED if self.tls13 => {
+                    let direction = &mut self.directions[index];
+                    if direction.next.is_some() {
+                        direction.cipher = direction.next.take();
+                    }
+                }
+                _ => {}
+            }
+        }
+        chain
+    }
+
+    /// Derive the keys of both directions once the cipher suite is known.
+    fn on_server_hello(&mut self, server: usize, hello: ServerHello, keylog: &KeyLog) {
+        let (Some(client_random), Some(suite)) =
+            (self.client_random, Suite::find(hello.cipher_suite))
+        else {
+            return;
+        };
+        if hello.is_retry_request() {
+            return;
+        }
+        let client = 1 - server;
+        self.tls13 = hello.version == TLS_1_3;
+
+        if self.tls13 {
+            let cipher = |label| {
+                keylog
+                    .get(label, &client_random)
+                    .and_then(|secret| suite.traffic_cipher(secret))
+            };
+            let directions = &mut self.directions;
+            directions[client].cipher = cipher(CLIENT_HANDSHAKE_TRAFFIC_SECRET);
+            directions[client].next = cipher(CLIENT_TRAFFIC_SECRET_0);
+            directions[server].cipher = cipher(SERVER_HANDSHAKE_TRAFFIC_SECRET);
+            directions[server].next = cipher(SERVER_TRAFFIC_SECRET_0);
+            self.decrypting = directions.iter().any(|d| d.next.is_some());
+        } else if let Some((client_cipher, server_cipher)) = keylog
+            .get(CLIENT_RANDOM, &client_random)
+            .and_then(|master| suite.master_ciphers(master, &client_random, &hello.random))
+        {
+            self.directions[client].next = Some(client_cipher);
+            self.directions[server].next = Some(server_cipher);
+            self.decrypting = true;
+        }
+    }
+}
+
+/// Decryptor of TLS connections.
+#[derive(Clone, Debug)]
+pub struct TlsDecryptor {
+    keylog: KeyLog,
+    reassembler: TcpReassembler,
+    connections: FlowTable<Connection>,
+    segments: Vec<(Duration, StreamSegment)>,
+    certificates: Vec<CertificateRecord>,
+    decrypted_records: u64,
+    failed_records: u64,
+}
+
+impl TlsDecryptor {
+    /// Create a new decryptor with the secrets of a key log.
+    pub fn new(keylog: KeyLog) -> Self {
+        Self {
+            keylog,
+            reassembler: TcpReassembler::new(),
+            connections: FlowTable::new(),
+            segments: Vec::new(),
+            certificates: Vec::new(),
+            decrypted_records: 0,
+            failed_records: 0,
+        }
+    }
+
+    /// Get the key log, e.g. to add secrets found later in a capture.
+    ///
+    /// Secrets only apply to connections whose ServerHello comes after them.
+    pub fn keylog_mut(&mut self) -> &mut KeyLog {
+        &mut self.keylog
+    }
+
+    /// Get the number of records decrypted so far.
+    pub fn decrypted_records(&self) -> u64 {
+        self.decrypted_records
+    }
+
+    /// Get the number of records which failed to decrypt, e.g. because of a
+    /// wrong secret; the direction is not decrypted any further.
+    pub fn failed_records(&self) -> u64 {
+        self.failed_records
+    }
+
+    /// Get the certificate chains of the handshakes seen so far, decrypted
+    /// or not.
+    pub fn certificates(&self) -> &[CertificateRecord] {
+        &self.certificates
+    }
+
+    /// Take the decrypted application data, in order.
+    ///
+    /// The segments carry the keys of their TCP direction, and report the
+    /// end of decrypted directions as well.
+    pub fn take_segments(&mut self) -> Vec<(Duration, StreamSegment)> {
+        std::mem::take(&mut self.segments)
+    }
+
+    /// Feed reassembled stream bytes.
+    pub fn on_segment(&mut self, ts: Duration, segment: StreamSegment) {
+        let canonical = segment.key.canonical();
+        let index = usize::from(segment.key != canonical);
+        let direction = &mut self.connections.entry(canonical).or_default().directions[index];
+        // A stream joined mid-way may still start at a record.
+        if segment.gap && direction.started {
+            direction.records.fail();
+        }
+        direction.started = true;
+        direction.records.extend(&segment.data);
+        while let Some(record) = connection_of(&mut self.connections, segment.key).directions[index]
+            .records
+            .next_record()
+        {
+            self.on_record(ts, segment.key, index, record);
+        }
+
+        if segment.fin {
+            let connection = connection_of(&mut self.connections, segment.key);
+            connection.directions[index].ended = true;
+            if connection.decrypting {
+                self.segments.push((
+                    ts,
+                    StreamSegment {
+                        key: segment.key,
+                        data: Vec::new(),
+                        gap: false,
+                        fin: true,
+                    },
+                ));
+            }
+            if connection.directions.iter().all(|d| d.ended) {
+                self.connections.remove(&canonical);
+            }
+        }
+    }
+
+    fn on_record(&mut self, ts: Duration, key: FlowKey, index: usize, record: TlsRecord) {
+        let connection = connection_of(&mut self.connections, key);
+        let direction = &mut connection.directions[index];
+
+        if record.content_type == CONTENT_CHANGE_CIPHER_SPEC {
+            // TLS 1.3 keeps it for compatibility only.
+            if !connection.tls13 && direction.next.is_some() {
+                direction.cipher = direction.next.take();
+            }
+            return;
+        }
+
+        let (content_type, data, decrypted) = match &mut direction.cipher {
+            Some(cipher) => match cipher.open(&record) {
+                Some((content_type, data)) => {
+                    self.decrypted_records += 1;
+                    (content_type, data, true)
+                }
+                None => {
+                    self.failed_records += 1;
+                    direction.records.fail();
+                    return;
+                }
+            },
+            None => (record.content_type, record.fragment, false),
+        };
+
+        match content_type {
+            CONTENT_HANDSHAKE => {
+                if let Some(chain) = connection.on_handshake(index, &data, &self.keylog) {
+                    self.certificates.push(CertificateRecord {
+                        ts,
+                        key: key.reversed(),
+                        server_name: connection.server_name.clone(),
+                        chain,
+                    });
+                }
+            }
+            CONTENT_APPLICATION_DATA if decrypted && !data.is_empty() => {
+                self.segments.push((
+                    ts,
+                    StreamSegment {
+                        key,
+                        data,
+                        gap: false,
+                        fin: false,
+                    },
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn connection_of(connections: &mut FlowTable<Connection>, key: FlowKey) -> &mut Connection {
+    connections.get_mut(&key.canonical()).unwrap()
+}
+
+impl Analyzer for TlsDecryptor {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        if let Some(segment) = self.reassembler.push(&ipv4) {
+            self.on_segment(ts, segment);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::analysis::http::HttpAnalyzer;
+    use crate::fixture::tcp_frame;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(from_client: bool, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let (src, dst) = if from_client {
+            ((CLIENT, 40000), (SERVER, 443))
+        } else {
+            ((SERVER, 443), (CLIENT, 40000))
+        };
+        tcp_frame(src, dst, seq, TcpFlags::ACK, payload)
+    }
+
+    fn record(content_type: u8, fragment: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, 3, 3];
+        record.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        record.extend_from_slice(fragment);
+        record
+    }
+
+    fn sealed(cipher: &mut RecordCipher, content_type: u8, data: &[u8]) -> Vec<u8> {
+        let record = cipher.seal(content_type, data);
+        [&record.header()[..], &record.fragment].concat()
+    }
+
+    fn hello(msg_type: u8, random: [u8; 32], suite: u16, tls13: bool) -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&random);
+        body.push(0);
+        if msg_type == HANDSHAKE_CLIENT_HELLO {
+            body.extend_from_slice(&[0, 2]);
+        }
+        body.extend_from_slice(&suite.to_be_bytes());
+        body.extend_from_slice(if msg_type == HANDSHAKE_CLIENT_HELLO {
+            &[1, 0]
+        } else {
+            &[0]
+        });
+        if tls13 && msg_type == HANDSHAKE_SERVER_HELLO {
+            body.extend_from_slice(&[0, 6, 0, 43, 0, 2, 3, 4]);
+        }
+        record(CONTENT_HANDSHAKE, &handshake(msg_type, &body))
+    }
+
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() as u32).to_be_bytes();
+        [&[msg_type, len[1], len[2], len[3]], body].concat()
+    }
+
+    /// Run a TLS connection carrying one HTTP transaction through the
+    /// decryptor and an HTTP analyzer.
+    fn decrypt(tls13: bool) -> (TlsDecryptor, HttpAnalyzer) {
+        let (client_random, server_random) = ([1; 32], [2; 32]);
+        let mut keylog = KeyLog::new();
+        let (suite, mut client, mut server, mut client_app, mut server_app);
+        if tls13 {
+            suite = Suite::find(0x1301).unwrap();
+            for (label, secret) in [
+                (CLIENT_HANDSHAKE_TRAFFIC_SECRET, 3),
+                (SERVER_HANDSHAKE_TRAFFIC_SECRET, 4),
+                (CLIENT_TRAFFIC_SECRET_0, 5),
+                (SERVER_TRAFFIC_SECRET_0, 6),
+            ] {
+                keylog.insert(label, client_random, vec![secret; 32]);
+            }
+            let cipher = |secret| suite.traffic_cipher(&[secret; 32]).unwrap();
+            (client, server) = (cipher(3), cipher(4));
+            (client_app, server_app) = (cipher(5), cipher(6));
+        } else {
+            suite = Suite::find(0xc02f).unwrap();
+            keylog.insert(CLIENT_RANDOM, client_random, vec![7; 48]);
+            (client, server) = suite
+                .master_ciphers(&[7; 48], &client_random, &server_random)
+                .unwrap();
+            (client_app, server_app) = (client.clone(), server.clone());
+        }
+
+        let finished = handshake(HANDSHAKE_FINISHED, &[0; 32]);
+        let ccs = record(CONTENT_CHANGE_CIPHER_SPEC, &[1]);
+        let client_hello = hello(HANDSHAKE_CLIENT_HELLO, client_random, suite.id, tls13);
+        let mut server_flight = hello(HANDSHAKE_SERVER_HELLO, server_random, suite.id, tls13);
+        server_flight.extend(&ccs);
+        // TLS 1.3 certificates are encrypted, here with an empty chain.
+        let certificate = handshake(HANDSHAKE_CERTIFICATE, &[0, 0, 0, 0]);
+        let server_handshake = if tls13 {
+            [certificate, finished.clone()].concat()
+        } else {
+            finished.clone()
+        };
+        server_flight.extend(sealed(&mut server, CONTENT_HANDSHAKE, &server_handshake));
+        let mut client_flight = ccs.clone();
+        client_flight.extend(sealed(&mut client, CONTENT_HANDSHAKE, &finished));
+        if !tls13 {
+            (client_app, server_app) = (client.clone(), server.clone());
+        }
+        client_flight.extend(sealed(
+            &mut client_app,
+            CONTENT_APPLICATION_DATA,
+            b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        ));
+        let response = sealed(
+            &mut server_app,
+            CONTENT_APPLICATION_DATA,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi",
+        );
+
+        let mut decryptor = TlsDecryptor::new(keylog);
+        let mut http = HttpAnalyzer::new();
+        let ms = Duration::from_millis;
+        let client_seq = 1 + client_hello.len() as u32;
+        let server_seq = 1 + server_flight.len() as u32;
+        for (ts, frame) in [
+            (ms(0), frame(true, 1, &client_hello)),
+            (ms(1), frame(false, 1, &server_flight)),
+            (ms(2), frame(true, client_seq, &client_flight)),
+            (ms(3), frame(false, server_seq, &response)),
+        ] {
+            decryptor.on_packet(ts, &frame);
+            for (ts, segment) in decryptor.take_segments() {
+                http.on_segment(ts, segment);
+            }
+        }
+        (decryptor, http)
+    }
+
+    #[test]
+    fn tls_decryption() {
+        // Server handshake keys of the simple 1-RTT handshake of RFC 8448
+        let secret = [
+            0xb6, 0x7b, 0x7d, 0x69, 0x0c, 0xc1, 0x6c, 0x4e, 0x75, 0xe5, 0x42, 0x13, 0xcb, 0x2d,
+            0x37, 0xb4, 0xe9, 0xc9, 0x12, 0xbc, 0xde, 0xd9, 0x10, 0x5d, 0x42, 0xbe, 0xfd, 0x59,
+            0xd3, 0x91, 0xad, 0x38,
+        ];
+        assert_eq!(
+            expand_label::<Sha256>(&secret, "key", 16).unwrap(),
+            [
+                0x3f, 0xce, 0x51, 0x60, 0x09, 0xc2, 0x17, 0x27, 0xd0, 0xf2, 0xe4, 0xe8, 0x6e, 0xe4,
+                0x03, 0xbc
+            ]
+        );
+        assert_eq!(
+            expand_label::<Sha256>(&secret, "iv", 12).unwrap(),
+            [0x5d, 0x31, 0x3e, 0xb2, 0x67, 0x12, 0x76, 0xee, 0x13, 0x00, 0x0b, 0x30]
+        );
+
+        for tls13 in [true, false] {
+            let (decryptor, http) = decrypt(tls13);
+            assert_eq!(decryptor.decrypted_records(), 4);
+            assert_eq!(decryptor.failed_records(), 0);
+            assert_eq!(decryptor.certificates().len(), usize::from(tls13));
+
+            let transactions = http.transactions();
+            assert_eq!(transactions.len(), 1);
+            assert_eq!(transactions[0].host.as_deref(), Some("example.com"));
+            assert_eq!(transactions[0].status, Some(200));
+            assert_eq!(transactions[0].response_bytes, 2);
+            assert_eq!(transactions[0].latency(), Some(Duration::from_millis(1)));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! TLS key log files.
+//!
+//! Clients such as browsers write the secrets of their TLS connections to
+//! the file named by `SSLKEYLOGFILE`, one `<label> <client random> <secret>`
+//! line per secret, in hex ([NSS key log format]). Captures may carry the
+//! same text in pcapng decryption secrets blocks, see
+//! [`PcapngReader::key_log`](netkit_capture::file::pcapng::PcapngReader::key_log).
+//!
+//! ```
+//! use netkit::analysis::tls::keylog::{KeyLog, CLIENT_RANDOM};
+//!
+//! let random = "01".repeat(32);
+//! let line = format!("# comment\nCLIENT_RANDOM {random} {}\n", "ab".repeat(48));
+//! let keylog = KeyLog::parse(line.as_bytes());
+//!
+//! assert_eq!(keylog.len(), 1);
+//! assert_eq!(keylog.get(CLIENT_RANDOM, &[1; 32]), Some(&[0xab; 48][..]));
+//! ```
+//!
+//! [NSS key log format]: https://datatracker.ietf.org/doc/draft-ietf-tls-keylogfile/
+
+use std::{collections::HashMap, io, path::Path};
+
+/// Label of TLS 1.2 master secrets.
+pub const CLIENT_RANDOM: &str = "CLIENT_RANDOM";
+/// Label of TLS 1.3 client handshake traffic secrets.
+pub const CLIENT_HANDSHAKE_TRAFFIC_SECRET: &str = "CLIENT_HANDSHAKE_TRAFFIC_SECRET";
+/// Label of TLS 1.3 server handshake traffic secrets.
+pub const SERVER_HANDSHAKE_TRAFFIC_SECRET: &str = "SERVER_HANDSHAKE_TRAFFIC_SECRET";
+/// Label of TLS 1.3 first client application traffic secrets.
+pub const CLIENT_TRAFFIC_SECRET_0: &str = "CLIENT_TRAFFIC_SECRET_0";
+/// Label of TLS 1.3 first server application traffic secrets.
+pub const SERVER_TRAFFIC_SECRET_0: &str = "SERVER_TRAFFIC_SECRET_0";
+
+/// Secrets of a key log, by label and client random.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyLog {
+    secrets: HashMap<(String, [u8; 32]), Vec<u8>>,
+}
+
+impl KeyLog {
+    /// Create an empty key log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse key log text, skipping comments and malformed lines.
+    pub fn parse(text: &[u8]) -> Self {
+        let mut keylog = Self::new();
+        keylog.extend(text);
+        keylog
+    }
+
+    /// Read a key log file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&std::fs::read(path)?))
+    }
+
+    /// Add the secrets of key log text.
+    pub fn extend(&mut self, text: &[u8]) {
+        for line in text.split(|&b| b == b'\n') {
+            let Ok(line) = std::str::from_utf8(line) else {
+                continue;
+            };
+            let mut fields = line.split_ascii_whitespace();
+            let (Some(label), Some(random), Some(secret), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if label.starts_with('#') {
+                continue;
+            }
+            let (Some(random), Some(secret)) = (from_hex(random), from_hex(secret)) else {
+                continue;
+            };
+            if let Ok(random) = random.try_into() {
+                self.insert(label, random, secret);
+            }
+        }
+    }
+
+    /// Add a secret.
+    pub fn insert(&mut self, label: &str, client_random: [u8; 32], secret: Vec<u8>) {
+        self.secrets
+            .insert((label.to_string(), client_random), secret);
+    }
+
+    /// Get the secret with a label of the connection with a client random.
+    pub fn get(&self, label: &str, client_random: &[u8; 32]) -> Option<&[u8]> {
+        self.secrets
+            .get(&(label.to_string(), *client_random))
+            .map(Vec::as_slice)
+    }
+
+    /// Get the number of secrets.
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    /// Whether there are no secrets.
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
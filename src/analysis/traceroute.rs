@@ -0,0 +1,427 @@
+//! Passive traceroute path reconstruction.
+//!
+//! A traceroute sends probes with increasing TTLs; every router where a
+//! probe expires answers with an ICMP Time Exceeded message quoting the
+//! probe's headers, and the destination finally answers with a Port
+//! Unreachable or an Echo Reply. When both the probes and the answers are in
+//! a capture, this analyzer matches them through the quoted headers and
+//! rebuilds the hop list of every traced destination, without sending
+//! anything.
+//!
+//! Packets with a TTL up to [`DEFAULT_MAX_PROBE_TTL`] are taken as probes.
+//! A probe is identified by its addresses, its IP identification and either
+//! its ports (UDP, TCP) or its echo identifier and sequence number (ICMP).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::Analyzer;
+use crate::export::{Column, Table, ToTable};
+
+/// Default maximum TTL of a probe.
+pub const DEFAULT_MAX_PROBE_TTL: u8 = 30;
+
+/// Default number of outstanding probes kept for matching.
+pub const DEFAULT_MAX_PROBES: usize = 65536;
+
+/// A router or destination that answered probes of a given TTL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hop {
+    /// TTL of the probes.
+    pub ttl: u8,
+    /// Address of the responder.
+    pub addr: IpAddr,
+    /// Number of answers.
+    pub responses: u64,
+    /// Minimum round-trip time from probe to answer.
+    pub rtt: Option<Duration>,
+    /// Whether the responder is the destination itself.
+    pub destination: bool,
+}
+
+/// Reconstructed path from a source to a traced destination.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TracePath {
+    /// Number of probes sent.
+    pub probes: u64,
+    /// Number of probes answered.
+    pub answered: u64,
+    hops: BTreeMap<(u8, IpAddr), Hop>,
+}
+
+impl TracePath {
+    /// Get the hops, ordered by TTL.
+    ///
+    /// Several responders for one TTL indicate load balancing.
+    pub fn hops(&self) -> impl Iterator<Item = &Hop> {
+        self.hops.values()
+    }
+
+    /// Whether the destination answered.
+    pub fn reached(&self) -> bool {
+        self.hops.values().any(|hop| hop.destination)
+    }
+
+    /// Get the TTLs up to the last answered one that got no answer.
+    pub fn gaps(&self) -> Vec<u8> {
+        let Some(last) = self.hops.keys().map(|(ttl, _)| *ttl).max() else {
+            return Vec::new();
+        };
+
+        (1..=last)
+            .filter(|ttl| !self.hops.keys().any(|(t, _)| t == ttl))
+            .collect()
+    }
+
+    fn record(&mut self, ttl: u8, addr: IpAddr, rtt: Duration, destination: bool) {
+        self.answered += 1;
+        let hop = self.hops.entry((ttl, addr)).or_insert(Hop {
+            ttl,
+            addr,
+            responses: 0,
+            rtt: None,
+            destination,
+        });
+        hop.responses += 1;
+        hop.rtt = Some(hop.rtt.map_or(rtt, |min| min.min(rtt)));
+    }
+}
+
+/// Identity of a probe, as found again in quoted headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct ProbeKey {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: IpProtocol,
+    identification: u16,
+    ids: (u16, u16),
+}
+
+impl ProbeKey {
+    /// Build the key of a probe from its IPv4 header and at least the first
+    /// 8 bytes of its payload.
+    fn new(ipv4: &Ipv4<&[u8]>) -> Option<Self> {
+        let payload = ipv4.payload();
+        let protocol = ipv4.protocol().get();
+        let offset = match protocol {
+            IpProtocol::Tcp | IpProtocol::Udp => 0,
+            IpProtocol::Icmp => 4,
+            _ => return None,
+        };
+        let ids = payload.get(offset..offset + 4)?;
+
+        Some(Self {
+            src: ipv4.src().get(),
+            dst: ipv4.dst().get(),
+            protocol,
+            identification: ipv4.identification().get(),
+            ids: (
+                u16::from_be_bytes([ids[0], ids[1]]),
+                u16::from_be_bytes([ids[2], ids[3]]),
+            ),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Probe {
+    ts: Duration,
+    ttl: u8,
+}
+
+/// Passive traceroute analyzer.
+#[derive(Clone, Debug)]
+pub struct TracerouteAnalyzer {
+    max_probe_ttl: u8,
+    max_probes: usize,
+    probes: HashMap<ProbeKey, Probe>,
+    /// Echo requests by (src, dst, identifier, sequence), to match replies.
+    echoes: HashMap<(Ipv4Addr, Ipv4Addr, u16, u16), ProbeKey>,
+    paths: HashMap<(IpAddr, IpAddr), TracePath>,
+    unmatched: u64,
+}
+
+impl Default for TracerouteAnalyzer {
+    fn default() -> Self {
+        Self {
+            max_probe_ttl: DEFAULT_MAX_PROBE_TTL,
+            max_probes: DEFAULT_MAX_PROBES,
+            probes: HashMap::new(),
+            echoes: HashMap::new(),
+            paths: HashMap::new(),
+            unmatched: 0,
+        }
+    }
+}
+
+impl TracerouteAnalyzer {
+    /// Create a new traceroute analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum TTL of a packet to be taken as a probe.
+    pub fn max_probe_ttl(&mut self, max_probe_ttl: u8) -> &mut Self {
+        self.max_probe_ttl = max_probe_ttl;
+        self
+    }
+
+    /// Set the number of outstanding probes kept for matching.
+    ///
+    /// Further probes are ignored until answers free room.
+    pub fn max_probes(&mut self, max_probes: usize) -> &mut Self {
+        self.max_probes = max_probes;
+        self
+    }
+
+    /// Get the path from `src` to `dst`.
+    pub fn path(&self, src: IpAddr, dst: IpAddr) -> Option<&TracePath> {
+        self.paths.get(&(src, dst))
+    }
+
+    /// Get all paths, ordered by source and destination.
+    pub fn paths(&self) -> Vec<((IpAddr, IpAddr), &TracePath)> {
+        let mut paths: Vec<_> = self.paths.iter().map(|(k, p)| (*k, p)).collect();
+        paths.sort_by_key(|(key, _)| *key);
+        paths
+    }
+
+    /// Get the number of ICMP errors quoting no known probe.
+    pub fn unmatched(&self) -> u64 {
+        self.unmatched
+    }
+
+    fn on_ipv4(&mut self, ts: Duration, ipv4: &Ipv4<&[u8]>) {
+        if let Some(icmp) = ipv4.icmp() {
+            match icmp.icmp_type().get() {
+                IcmpType::TimeExceeded | IcmpType::DestinationUnreachable => {
+                    if let Some(original) = icmp.original() {
+                        self.on_error(ts, ipv4, &original);
+                    }
+                    return;
+                }
+                IcmpType::EchoReply => {
+                    let echo = (
+                        ipv4.dst().get(),
+                        ipv4.src().get(),
+                        icmp.identifier().get(),
+                        icmp.sequence().get(),
+                    );
+                    if let Some(key) = self.echoes.remove(&echo) {
+                        self.answer(ts, key, ipv4.src().get());
+                    }
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        let ttl = ipv4.ttl().get();
+        if ttl == 0 || ttl > self.max_probe_ttl || self.probes.len() >= self.max_probes {
+            return;
+        }
+        let Some(key) = ProbeKey::new(ipv4) else {
+            return;
+        };
+
+        if let Some(icmp) = ipv4.icmp() {
+            if icmp.icmp_type().get() != IcmpType::EchoRequest {
+                return;
+            }
+            let (id, seq) = key.ids;
+            self.echoes.insert((key.src, key.dst, id, seq), key);
+        }
+
+        self.probes.insert(key, Probe { ts, ttl });
+        self.paths
+            .entry((key.src.into(), key.dst.into()))
+            .or_default()
+            .probes += 1;
+    }
+
+    fn on_error(&mut self, ts: Duration, ipv4: &Ipv4<&[u8]>, original: &Ipv4<&[u8]>) {
+        match ProbeKey::new(original) {
+            Some(key) if self.probes.contains_key(&key) => {
+                if key.protocol == IpProtocol::Icmp {
+                    let (id, seq) = key.ids;
+                    self.echoes.remove(&(key.src, key.dst, id, seq));
+                }
+                self.answer(ts, key, ipv4.src().get());
+            }
+            _ => self.unmatched += 1,
+        }
+    }
+
+    fn answer(&mut self, ts: Duration, key: ProbeKey, responder: Ipv4Addr) {
+        let Some(probe) = self.probes.remove(&key) else {
+            return;
+        };
+
+        let rtt = ts.saturating_sub(probe.ts);
+        self.paths
+            .entry((key.src.into(), key.dst.into()))
+            .or_default()
+            .record(probe.ttl, responder.into(), rtt, responder == key.dst);
+    }
+}
+
+impl Analyzer for TracerouteAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_ipv4(ts, &ipv4);
+        }
+    }
+}
+
+impl ToTable for TracerouteAnalyzer {
+    /// Convert into a table with one row per hop.
+    fn to_table(&self) -> Table {
+        let hops: Vec<_> = self
+            .paths()
+            .into_iter()
+            .flat_map(|(key, path)| path.hops().map(move |hop| (key, hop)))
+            .collect();
+        let addrs =
+            |f: fn(&((IpAddr, IpAddr), &Hop)) -> String| Column::Str(hops.iter().map(f).collect());
+        let u64s = |f: fn(&Hop) -> u64| Column::U64(hops.iter().map(|(_, h)| f(h)).collect());
+
+        let mut table = Table::new();
+        table
+            .push("src", addrs(|((src, _), _)| src.to_string()))
+            .push("dst", addrs(|((_, dst), _)| dst.to_string()))
+            .push("ttl", u64s(|h| h.ttl as u64))
+            .push("hop", addrs(|(_, h)| h.addr.to_string()))
+            .push("responses", u64s(|h| h.responses))
+            .push(
+                "rtt_ms",
+                Column::F64(
+                    hops.iter()
+                        .map(|(_, h)| h.rtt.map_or(f64::NAN, |rtt| rtt.as_secs_f64() * 1e3))
+                        .collect(),
+                ),
+            )
+            .push("destination", u64s(|h| h.destination as u64));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::{icmp, udp};
+
+    use super::*;
+
+    const SRC: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const DST: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(ipv4: &Ipv4<Vec<u8>>) -> Vec<u8> {
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    fn probe(ttl: u8) -> Ipv4<Vec<u8>> {
+        let udp = udp!(src_port: 40000u16, dst_port: 33433u16 + ttl as u16);
+        ipv4!(
+            src: SRC,
+            dst: DST,
+            ttl: ttl,
+            identification: 100u16 + ttl as u16,
+            protocol: IpProtocol::Udp,
+            payload: udp.inner(),
+        )
+    }
+
+    fn error(from: Ipv4Addr, icmp_type: IcmpType, probe: &Ipv4<Vec<u8>>) -> Vec<u8> {
+        let icmp = icmp!(icmp_type: icmp_type, payload: &probe.inner()[..28]);
+        frame(&ipv4!(src: from, dst: SRC, protocol: IpProtocol::Icmp, payload: icmp.inner()))
+    }
+
+    #[test]
+    fn traceroute_udp_path() {
+        let routers = [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 1, 1)];
+        let ms = Duration::from_millis;
+
+        let mut analyzer = TracerouteAnalyzer::new();
+        for ttl in 1..=4u8 {
+            let probe = probe(ttl);
+            let at = ms(ttl as u64 * 100);
+            analyzer.on_packet(at, &frame(&probe));
+
+            let answer = match ttl {
+                1 | 2 => Some(error(
+                    routers[ttl as usize - 1],
+                    IcmpType::TimeExceeded,
+                    &probe,
+                )),
+                // No answer from the third hop.
+                3 => None,
+                _ => Some(error(DST, IcmpType::DestinationUnreachable, &probe)),
+            };
+            if let Some(answer) = answer {
+                analyzer.on_packet(at + ms(ttl as u64 * 5), &answer);
+            }
+        }
+        // Ordinary traffic is not a probe, nor is an unknown quoted packet.
+        let traffic = ipv4!(src: SRC, dst: DST, ttl: 64u8, protocol: IpProtocol::Udp);
+        analyzer.on_packet(ms(500), &frame(&traffic));
+        analyzer.on_packet(
+            ms(500),
+            &error(routers[0], IcmpType::TimeExceeded, &probe(9)),
+        );
+
+        let path = analyzer.path(SRC.into(), DST.into()).unwrap();
+        assert_eq!(path.probes, 4);
+        assert_eq!(path.answered, 3);
+        assert!(path.reached());
+        assert_eq!(path.gaps(), vec![3]);
+
+        let hops: Vec<_> = path.hops().map(|hop| (hop.ttl, hop.addr)).collect();
+        assert_eq!(
+            hops,
+            vec![
+                (1, routers[0].into()),
+                (2, routers[1].into()),
+                (4, DST.into())
+            ]
+        );
+        assert_eq!(path.hops().next().unwrap().rtt, Some(ms(5)));
+        assert_eq!(analyzer.unmatched(), 1);
+
+        let table = analyzer.to_table();
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn traceroute_icmp_echo() {
+        let echo = |ttl: u8| {
+            let icmp = icmp!(identifier: 7u16, sequence: ttl as u16);
+            ipv4!(src: SRC, dst: DST, ttl: ttl, protocol: IpProtocol::Icmp, payload: icmp.inner())
+        };
+        let reply = icmp!(icmp_type: IcmpType::EchoReply, identifier: 7u16, sequence: 2u16);
+        let reply = ipv4!(src: DST, dst: SRC, protocol: IpProtocol::Icmp, payload: reply.inner());
+
+        let mut analyzer = TracerouteAnalyzer::new();
+        analyzer.on_packet(Duration::ZERO, &frame(&echo(1)));
+        analyzer.on_packet(
+            Duration::ZERO,
+            &error(Ipv4Addr::new(10, 0, 0, 1), IcmpType::TimeExceeded, &echo(1)),
+        );
+        analyzer.on_packet(Duration::ZERO, &frame(&echo(2)));
+        analyzer.on_packet(Duration::ZERO, &frame(&reply));
+
+        let path = analyzer.path(SRC.into(), DST.into()).unwrap();
+        assert!(path.reached());
+        assert_eq!(path.hops().count(), 2);
+        assert!(path.gaps().is_empty());
+    }
+}
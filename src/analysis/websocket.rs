@@ -0,0 +1,243 @@
+//! WebSocket frame parsing.
+//!
+//! After an HTTP `101 Switching Protocols` response with `Upgrade:
+//! websocket`, both directions of the connection carry WebSocket frames
+//! ([RFC 6455]). [`WebSocketStream`] parses the frames of one direction and
+//! unmasks their payloads; [`HttpAnalyzer`](super::http::HttpAnalyzer)
+//! switches its connections to it at the upgrade.
+//!
+//! ```
+//! use netkit::analysis::websocket::{WebSocketOpcode, WebSocketStream};
+//!
+//! let mut stream = WebSocketStream::new();
+//! // Masked "Hi" text frame, as sent by a client
+//! stream.extend(&[0x81, 0x82, 1, 2, 3, 4, b'H' ^ 1, b'i' ^ 2]);
+//!
+//! let frame = stream.next_frame().unwrap();
+//! assert!(frame.fin);
+//! assert_eq!(frame.opcode, WebSocketOpcode::Text);
+//! assert_eq!(frame.mask, Some([1, 2, 3, 4]));
+//! assert_eq!(frame.payload, b"Hi");
+//! ```
+//!
+//! [RFC 6455]: https://datatracker.ietf.org/doc/html/rfc6455
+
+use std::time::Duration;
+
+use netkit_packet::prelude::*;
+
+/// Maximum payload length of a frame; longer frames stop the parsing.
+pub const MAX_FRAME_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Opcode of a WebSocket frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WebSocketOpcode {
+    /// Continuation of a fragmented message.
+    Continuation,
+    /// Text message.
+    Text,
+    /// Binary message.
+    Binary,
+    /// Connection close.
+    Close,
+    /// Ping.
+    Ping,
+    /// Pong.
+    Pong,
+    /// Reserved opcode.
+    Reserved(u8),
+}
+
+impl From<u8> for WebSocketOpcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x0 => WebSocketOpcode::Continuation,
+            0x1 => WebSocketOpcode::Text,
+            0x2 => WebSocketOpcode::Binary,
+            0x8 => WebSocketOpcode::Close,
+            0x9 => WebSocketOpcode::Ping,
+            0xa => WebSocketOpcode::Pong,
+            value => WebSocketOpcode::Reserved(value),
+        }
+    }
+}
+
+impl WebSocketOpcode {
+    /// Whether the opcode is a control frame opcode.
+    pub fn is_control(&self) -> bool {
+        match self {
+            WebSocketOpcode::Close | WebSocketOpcode::Ping | WebSocketOpcode::Pong => true,
+            WebSocketOpcode::Reserved(value) => value & 0x8 != 0,
+            _ => false,
+        }
+    }
+}
+
+/// A WebSocket frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSocketFrame {
+    /// Whether this is the last frame of a message.
+    pub fin: bool,
+    /// Reserved bits, used by extensions such as compression.
+    pub rsv: u8,
+    /// Opcode.
+    pub opcode: WebSocketOpcode,
+    /// Masking key, set on frames sent by clients.
+    pub mask: Option<[u8; 4]>,
+    /// Unmasked payload.
+    pub payload: Vec<u8>,
+}
+
+impl WebSocketFrame {
+    /// Parse a frame at the start of `data`, returning it and its length.
+    ///
+    /// Returns `Ok(None)` if `data` does not hold the whole frame yet, and
+    /// `Err` with the payload length if the frame is longer than
+    /// [`MAX_FRAME_LENGTH`].
+    pub fn parse(data: &[u8]) -> Result<Option<(Self, usize)>, u64> {
+        let [first, second, ..] = *data else {
+            return Ok(None);
+        };
+        let (len, mut at) = match second & 0x7f {
+            126 => match data.get(2..4) {
+                Some(len) => (u16::from_be_bytes([len[0], len[1]]) as u64, 4),
+                None => return Ok(None),
+            },
+            127 => match data.get(2..10) {
+                Some(len) => (u64::from_be_bytes(len.try_into().unwrap()), 10),
+                None => return Ok(None),
+            },
+            len => (len as u64, 2),
+        };
+        if len > MAX_FRAME_LENGTH {
+            return Err(len);
+        }
+
+        let mask = if second & 0x80 != 0 {
+            let Some(mask) = data.get(at..at + 4) else {
+                return Ok(None);
+            };
+            at += 4;
+            Some([mask[0], mask[1], mask[2], mask[3]])
+        } else {
+            None
+        };
+
+        let end = at + len as usize;
+        let Some(payload) = data.get(at..end) else {
+            return Ok(None);
+        };
+        let payload = match mask {
+            Some(mask) => payload
+                .iter()
+                .zip(mask.iter().cycle())
+                .map(|(b, m)| b ^ m)
+                .collect(),
+            None => payload.to_vec(),
+        };
+
+        let frame = Self {
+            fin: first & 0x80 != 0,
+            rsv: (first >> 4) & 0x7,
+            opcode: WebSocketOpcode::from(first & 0xf),
+            mask,
+            payload,
+        };
+        Ok(Some((frame, end)))
+    }
+}
+
+/// Parser of the WebSocket frames of one stream direction.
+#[derive(Clone, Debug, Default)]
+pub struct WebSocketStream {
+    buf: Vec<u8>,
+    failed: bool,
+}
+
+impl WebSocketStream {
+    /// Create a new stream parser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append stream bytes.
+    pub fn extend(&mut self, data: &[u8]) {
+        if !self.failed {
+            self.buf.extend_from_slice(data);
+        }
+    }
+
+    /// Stop parsing, e.g. after a gap in the stream, from which frame
+    /// boundaries cannot be recovered.
+    pub fn fail(&mut self) {
+        self.failed = true;
+        self.buf = Vec::new();
+    }
+
+    /// Whether parsing stopped.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Get the next frame from the bytes received so far.
+    pub fn next_frame(&mut self) -> Option<WebSocketFrame> {
+        if self.failed {
+            return None;
+        }
+        match WebSocketFrame::parse(&self.buf) {
+            Ok(Some((frame, len))) => {
+                self.buf.drain(..len);
+                Some(frame)
+            }
+            Ok(None) => None,
+            Err(_) => {
+                self.fail();
+                None
+            }
+        }
+    }
+}
+
+/// A WebSocket frame seen on a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSocketRecord {
+    /// Timestamp of the segment completing the frame.
+    pub ts: Duration,
+    /// Key of the direction the frame was sent in.
+    pub key: FlowKey,
+    /// Whether the client sent the frame.
+    pub from_client: bool,
+    /// Frame.
+    pub frame: WebSocketFrame,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn websocket_frames() {
+        let mut data = vec![0x02, 126, 0x01, 0x00];
+        data.extend_from_slice(&[0xab; 256]);
+        // Ping split across two segments
+        data.extend_from_slice(&[0x89, 0x01]);
+
+        let mut stream = WebSocketStream::new();
+        stream.extend(&data);
+        let frame = stream.next_frame().unwrap();
+        assert!(!frame.fin);
+        assert_eq!(frame.opcode, WebSocketOpcode::Binary);
+        assert_eq!(frame.mask, None);
+        assert_eq!(frame.payload.len(), 256);
+        assert_eq!(stream.next_frame(), None);
+
+        stream.extend(b"x");
+        let frame = stream.next_frame().unwrap();
+        assert!(frame.opcode.is_control());
+        assert_eq!(frame.payload, b"x");
+
+        stream.extend(&[0x82, 127, 0xff, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stream.next_frame(), None);
+        assert!(stream.is_failed());
+    }
+}
@@ -0,0 +1,294 @@
+//! WireGuard session accounting.
+//!
+//! WireGuard payloads are encrypted, but the clear text indices are enough to
+//! group packets into sessions: a handshake initiation announces the
+//! initiator's index, the response pairs it with the responder's index, and
+//! every transport data message carries the index chosen by its receiver.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::Analyzer;
+
+/// Traffic volume of one direction of a WireGuard session.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WireGuardVolume {
+    /// Number of transport data messages, keepalives included.
+    pub packets: u64,
+    /// Number of encrypted payload bytes.
+    pub bytes: u64,
+    /// Number of keepalives.
+    pub keepalives: u64,
+    /// Highest counter seen.
+    pub max_counter: Option<u64>,
+}
+
+impl WireGuardVolume {
+    fn record(&mut self, wg: &WireGuard<&[u8]>) {
+        self.packets += 1;
+        self.bytes += wg.payload().map_or(0, |p| p.len()) as u64;
+        if wg.is_keepalive() {
+            self.keepalives += 1;
+        }
+        if let Some(counter) = wg.counter() {
+            self.max_counter = self.max_counter.max(Some(counter.get()));
+        }
+    }
+}
+
+/// A WireGuard session.
+///
+/// Sessions seen without their handshake (e.g. the capture started after it)
+/// are reported per direction, with the sender as initiator and the unknown
+/// index left empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WireGuardSession {
+    /// Endpoint sending the handshake initiation.
+    pub initiator: SocketAddr,
+    /// Endpoint answering the handshake.
+    pub responder: SocketAddr,
+    /// Index chosen by the initiator.
+    pub initiator_index: Option<u32>,
+    /// Index chosen by the responder.
+    pub responder_index: Option<u32>,
+    /// Whether a handshake response was seen.
+    pub established: bool,
+    /// Number of cookie replies (the responder was under load).
+    pub cookie_replies: u64,
+    /// Timestamp of the first message.
+    pub first_seen: Duration,
+    /// Timestamp of the last message.
+    pub last_seen: Duration,
+    /// Transport data from the initiator to the responder.
+    pub to_responder: WireGuardVolume,
+    /// Transport data from the responder to the initiator.
+    pub to_initiator: WireGuardVolume,
+}
+
+impl WireGuardSession {
+    fn new(ts: Duration, initiator: SocketAddr, responder: SocketAddr) -> Self {
+        Self {
+            initiator,
+            responder,
+            initiator_index: None,
+            responder_index: None,
+            established: false,
+            cookie_replies: 0,
+            first_seen: ts,
+            last_seen: ts,
+            to_responder: WireGuardVolume::default(),
+            to_initiator: WireGuardVolume::default(),
+        }
+    }
+
+    /// Get the total number of encrypted payload bytes in both directions.
+    pub fn bytes(&self) -> u64 {
+        self.to_responder.bytes + self.to_initiator.bytes
+    }
+}
+
+/// WireGuard session analyzer.
+#[derive(Clone, Debug, Default)]
+pub struct WireGuardAnalyzer {
+    sessions: Vec<WireGuardSession>,
+    // (owner of the index, index) -> session
+    indices: HashMap<(IpAddr, u32), usize>,
+}
+
+impl WireGuardAnalyzer {
+    /// Create a new WireGuard analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the sessions in the order they were first seen.
+    pub fn sessions(&self) -> &[WireGuardSession] {
+        &self.sessions
+    }
+
+    fn on_message(
+        &mut self,
+        ts: Duration,
+        src: SocketAddr,
+        dst: SocketAddr,
+        wg: &WireGuard<&[u8]>,
+    ) {
+        let sender = wg.sender_index().map(|i| i.get());
+        let receiver = wg.receiver_index().map(|i| i.get());
+
+        match wg.message_type().get() {
+            WireGuardMessageType::HandshakeInitiation => {
+                let mut session = WireGuardSession::new(ts, src, dst);
+                session.initiator_index = sender;
+                self.insert(session, src.ip(), sender);
+            }
+            WireGuardMessageType::HandshakeResponse => {
+                let id = match receiver.and_then(|r| self.lookup(dst.ip(), r)) {
+                    Some(id) => id,
+                    None => {
+                        let mut session = WireGuardSession::new(ts, dst, src);
+                        session.initiator_index = receiver;
+                        self.insert(session, dst.ip(), receiver)
+                    }
+                };
+                if let Some(sender) = sender {
+                    self.indices.insert((src.ip(), sender), id);
+                }
+                let session = &mut self.sessions[id];
+                session.responder_index = sender;
+                session.established = true;
+                session.last_seen = ts;
+            }
+            WireGuardMessageType::CookieReply => {
+                if let Some(id) = receiver.and_then(|r| self.lookup(dst.ip(), r)) {
+                    let session = &mut self.sessions[id];
+                    session.cookie_replies += 1;
+                    session.last_seen = ts;
+                }
+            }
+            WireGuardMessageType::TransportData => {
+                let id = match receiver.and_then(|r| self.lookup(dst.ip(), r)) {
+                    Some(id) => id,
+                    None => {
+                        let mut session = WireGuardSession::new(ts, src, dst);
+                        session.responder_index = receiver;
+                        session.established = true;
+                        self.insert(session, dst.ip(), receiver)
+                    }
+                };
+                let session = &mut self.sessions[id];
+                session.last_seen = ts;
+                if session.initiator.ip() == src.ip() {
+                    session.to_responder.record(wg);
+                } else {
+                    session.to_initiator.record(wg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lookup(&self, owner: IpAddr, index: u32) -> Option<usize> {
+        self.indices.get(&(owner, index)).copied()
+    }
+
+    fn insert(&mut self, session: WireGuardSession, owner: IpAddr, index: Option<u32>) -> usize {
+        let id = self.sessions.len();
+        self.sessions.push(session);
+        if let Some(index) = index {
+            self.indices.insert((owner, index), id);
+        }
+        id
+    }
+}
+
+impl Analyzer for WireGuardAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        let Some(udp) = ipv4.udp() else {
+            return;
+        };
+        let Some(wg) = udp.wireguard() else {
+            return;
+        };
+
+        let src = SocketAddr::new(ipv4.src().get().into(), udp.src_port().get());
+        let dst = SocketAddr::new(ipv4.dst().get().into(), udp.dst_port().get());
+        self.on_message(ts, src, dst, &wg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    const A: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const B: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn frame(src: Ipv4Addr, dst: Ipv4Addr, wg: WireGuard<Vec<u8>>) -> Vec<u8> {
+        udp_frame((src, 51820), (dst, 51820), wg.inner())
+    }
+
+    #[test]
+    fn wireguard_session() {
+        let packets = [
+            frame(
+                A,
+                B,
+                wireguard!(message_type: WireGuardMessageType::HandshakeInitiation, sender_index: 1u32),
+            ),
+            frame(
+                B,
+                A,
+                wireguard!(
+                    message_type: WireGuardMessageType::HandshakeResponse,
+                    sender_index: 2u32,
+                    receiver_index: 1u32,
+                ),
+            ),
+            frame(
+                A,
+                B,
+                wireguard!(receiver_index: 2u32, counter: 0u64, payload: [0; 64]),
+            ),
+            frame(
+                B,
+                A,
+                wireguard!(receiver_index: 1u32, counter: 0u64, payload: [0; 20]),
+            ),
+            frame(A, B, wireguard!(receiver_index: 2u32, counter: 1u64)),
+        ];
+
+        let mut analyzer = WireGuardAnalyzer::new();
+        for (i, packet) in packets.iter().enumerate() {
+            analyzer.on_packet(Duration::from_secs(i as u64), packet);
+        }
+
+        let sessions = analyzer.sessions();
+        assert_eq!(sessions.len(), 1);
+
+        let session = &sessions[0];
+        assert_eq!(session.initiator.ip(), A);
+        assert_eq!(session.initiator_index, Some(1));
+        assert_eq!(session.responder_index, Some(2));
+        assert!(session.established);
+        assert_eq!(session.to_responder.packets, 2);
+        assert_eq!(session.to_responder.keepalives, 1);
+        assert_eq!(session.to_responder.max_counter, Some(1));
+        assert_eq!(session.to_responder.bytes, 64 + 16 + 16);
+        assert_eq!(session.to_initiator.bytes, 32 + 16);
+        assert_eq!(session.last_seen, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn wireguard_session_without_handshake() {
+        let mut analyzer = WireGuardAnalyzer::new();
+        analyzer.on_packet(
+            Duration::ZERO,
+            &frame(A, B, wireguard!(receiver_index: 9u32, payload: [0; 16])),
+        );
+        analyzer.on_packet(
+            Duration::from_secs(1),
+            &frame(A, B, wireguard!(receiver_index: 9u32, counter: 1u64)),
+        );
+
+        let sessions = analyzer.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].initiator_index, None);
+        assert_eq!(sessions[0].responder_index, Some(9));
+        assert_eq!(sessions[0].to_responder.packets, 2);
+    }
+}
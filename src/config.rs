@@ -0,0 +1,503 @@
+//! Declarative configuration of analysis runs.
+//!
+//! An [`AnalysisConfig`] bundles everything a run needs: which packets to
+//! analyze, which analyzers to run over them, how flow tables and sampling
+//! are set up, and where reports are exported. Loaded from a TOML or YAML
+//! file, it makes a run reproducible from the file alone:
+//!
+//! ```
+//! use netkit::config::{AnalysisConfig, AnalyzerKind};
+//!
+//! let config = AnalysisConfig::from_toml(
+//!     r#"
+//!     filter = "tcp port 443"
+//!     analyzers = ["tcp", "certificates"]
+//!     sampling = { one-in-n = 10 }
+//!
+//!     [flow-table]
+//!     capacity = 100000
+//!
+//!     [[exports]]
+//!     analyzer = "certificates"
+//!     path = "certificates.csv"
+//!     "#,
+//! )
+//! .unwrap();
+//!
+//! assert_eq!(config.analyzers, [AnalyzerKind::Tcp, AnalyzerKind::Certificates]);
+//! assert_eq!(config.build_analyzers().len(), 2);
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use netkit_packet::{
+    dissect::{decode_as::DecodeKey, DissectorRegistry},
+    flow::table::FlowTableConfig,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{
+        app::AppClassifier, beacon::BeaconDetector, credentials::CredentialAnalyzer,
+        ecn::EcnAnalyzer, entropy::EntropyAnalyzer, happy_eyeballs::HappyEyeballsAnalyzer,
+        http::HttpAnalyzer, mail::MailAnalyzer, pmtu::PmtuAnalyzer, quic::QuicAnalyzer,
+        rtp::RtpAnalyzer, sampling::Sampling, storm::StormAnalyzer, tcp::TcpFlowAnalyzer,
+        tls::CertificateAnalyzer, traceroute::TracerouteAnalyzer, Analyzer,
+    },
+    export::{privacy::DpNoise, ToTable},
+    stats::{
+        coverage::ProtocolCoverage,
+        dns::ZoneTransferAnalyzer,
+        overhead::OverheadStats,
+        tenant::{PerTenant, TenantClassifier, TenantRule},
+        top::TopTalkers,
+    },
+};
+
+/// Error loading or validating a configuration.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// Reading the file failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// The TOML is invalid.
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+
+    /// The YAML is invalid.
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+
+    /// The file extension tells neither TOML nor YAML.
+    #[error("Unknown configuration format: {0}")]
+    UnknownFormat(PathBuf),
+
+    /// The configuration is inconsistent.
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+}
+
+/// Analyzer which can be enabled in a configuration.
+///
+/// These are the analyzers producing a table; they are named in kebab-case,
+/// e.g. `happy-eyeballs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnalyzerKind {
+    /// [`AppClassifier`]
+    App,
+    /// [`BeaconDetector`]
+    Beacon,
+    /// [`CertificateAnalyzer`]
+    Certificates,
+    /// [`ProtocolCoverage`]
+    Coverage,
+    /// [`CredentialAnalyzer`]
+    Credentials,
+    /// [`EcnAnalyzer`]
+    Ecn,
+    /// [`EntropyAnalyzer`]
+    Entropy,
+    /// [`HappyEyeballsAnalyzer`]
+    HappyEyeballs,
+    /// [`HttpAnalyzer`]
+    Http,
+    /// [`MailAnalyzer`]
+    Mail,
+    /// [`OverheadStats`]
+    Overhead,
+    /// [`PmtuAnalyzer`]
+    Pmtu,
+    /// [`QuicAnalyzer`]
+    Quic,
+    /// [`RtpAnalyzer`]
+    Rtp,
+    /// [`StormAnalyzer`]
+    Storm,
+    /// [`TcpFlowAnalyzer`]
+    Tcp,
+    /// [`TopTalkers`]
+    TopTalkers,
+    /// [`TracerouteAnalyzer`]
+    Traceroute,
+    /// [`ZoneTransferAnalyzer`]
+    ZoneTransfer,
+}
+
+/// Analyzer producing a table, as built from a configuration.
+pub trait TableAnalyzer: Analyzer + ToTable {}
+
+impl<T: Analyzer + ToTable> TableAnalyzer for T {}
+
+impl AnalyzerKind {
+    /// Get the name of the kind, as in configuration files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnalyzerKind::App => "app",
+            AnalyzerKind::Beacon => "beacon",
+            AnalyzerKind::Certificates => "certificates",
+            AnalyzerKind::Coverage => "coverage",
+            AnalyzerKind::Credentials => "credentials",
+            AnalyzerKind::Ecn => "ecn",
+            AnalyzerKind::Entropy => "entropy",
+            AnalyzerKind::HappyEyeballs => "happy-eyeballs",
+            AnalyzerKind::Http => "http",
+            AnalyzerKind::Mail => "mail",
+            AnalyzerKind::Overhead => "overhead",
+            AnalyzerKind::Pmtu => "pmtu",
+            AnalyzerKind::Quic => "quic",
+            AnalyzerKind::Rtp => "rtp",
+            AnalyzerKind::Storm => "storm",
+            AnalyzerKind::Tcp => "tcp",
+            AnalyzerKind::TopTalkers => "top-talkers",
+            AnalyzerKind::Traceroute => "traceroute",
+            AnalyzerKind::ZoneTransfer => "zone-transfer",
+        }
+    }
+
+    /// Build the analyzer with its defaults and the flow table and "Decode
+    /// As" settings of a configuration.
+    pub fn build(&self, config: &AnalysisConfig) -> Box<dyn TableAnalyzer> {
+        match self {
+            AnalyzerKind::App => Box::new(AppClassifier::new()),
+            AnalyzerKind::Beacon => Box::new(BeaconDetector::new()),
+            AnalyzerKind::Certificates => Box::new(CertificateAnalyzer::new()),
+            AnalyzerKind::Coverage => {
+                let mut analyzer = ProtocolCoverage::new();
+                analyzer.registry(config.registry());
+                Box::new(analyzer)
+            }
+            AnalyzerKind::Credentials => Box::new(CredentialAnalyzer::new()),
+            AnalyzerKind::Ecn => Box::new(EcnAnalyzer::new()),
+            AnalyzerKind::Entropy => Box::new(EntropyAnalyzer::new()),
+            AnalyzerKind::HappyEyeballs => Box::new(HappyEyeballsAnalyzer::new()),
+            AnalyzerKind::Http => Box::new(HttpAnalyzer::new()),
+            AnalyzerKind::Mail => Box::new(MailAnalyzer::new()),
+            AnalyzerKind::Overhead => {
+                let mut analyzer = OverheadStats::new();
+                analyzer.registry(config.registry());
+                Box::new(analyzer)
+            }
+            AnalyzerKind::Pmtu => Box::new(PmtuAnalyzer::new()),
+            AnalyzerKind::Quic => Box::new(QuicAnalyzer::new()),
+            AnalyzerKind::Rtp => Box::new(RtpAnalyzer::new()),
+            AnalyzerKind::Storm => Box::new(StormAnalyzer::new()),
+            AnalyzerKind::Tcp => {
+                let mut analyzer = TcpFlowAnalyzer::new();
+                analyzer.flow_table(config.flow_table);
+                Box::new(analyzer)
+            }
+            AnalyzerKind::TopTalkers => Box::new(TopTalkers::new()),
+            AnalyzerKind::Traceroute => Box::new(TracerouteAnalyzer::new()),
+            AnalyzerKind::ZoneTransfer => Box::new(ZoneTransferAnalyzer::new()),
+        }
+    }
+}
+
+/// Format of an exported table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    /// CSV with a header line.
+    #[default]
+    Csv,
+}
+
+/// Where the table of an analyzer is exported.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportTarget {
+    /// Analyzer whose table is exported.
+    pub analyzer: AnalyzerKind,
+    /// Output file.
+    pub path: PathBuf,
+    /// Output format.
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Privacy budget of the [Laplace noise](DpNoise) added to the counts of
+    /// the table, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epsilon: Option<f64>,
+}
+
+impl ExportTarget {
+    /// Write a table to the target.
+    pub fn write(&self, table: &impl ToTable) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        let mut table = table.to_table();
+        if let Some(epsilon) = self.epsilon {
+            table = DpNoise::new(epsilon).apply(&table);
+        }
+        match self.format {
+            ExportFormat::Csv => table.write_csv(&mut writer),
+        }
+    }
+}
+
+/// Changes from one configuration to another, e.g. on a reload.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// Whether the filter changed.
+    pub filter: bool,
+    /// Analyzers enabled by the new configuration.
+    pub added: Vec<AnalyzerKind>,
+    /// Analyzers disabled by the new configuration.
+    pub removed: Vec<AnalyzerKind>,
+    /// Whether settings shared by the analyzers changed (flow table,
+    /// sampling, "Decode As" or tenants), so that every analyzer is rebuilt.
+    pub rebuild: bool,
+    /// Whether the exports changed.
+    pub exports: bool,
+}
+
+impl ConfigDiff {
+    /// Whether the configurations are the same.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Configuration of an analysis run.
+///
+/// All fields are optional in configuration files; keys are in kebab-case.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AnalysisConfig {
+    /// Filter expression selecting the packets to analyze, in pcap filter
+    /// syntax; applied by the packet source.
+    pub filter: Option<String>,
+    /// Analyzers to run.
+    pub analyzers: Vec<AnalyzerKind>,
+    /// Settings of the flow tables of the analyzers.
+    pub flow_table: FlowTableConfig,
+    /// Sampling of the packets fed to the analyzers.
+    pub sampling: Sampling,
+    /// "Decode As" overrides, e.g. `"udp.port:8053" = "Dns"`.
+    pub decode_as: BTreeMap<DecodeKey, String>,
+    /// Tables to export at the end of the run.
+    pub exports: Vec<ExportTarget>,
+    /// Tenants by name, e.g. `blue = ["10.1.0.0/16", "vlan:100"]`; when
+    /// set, analyzers keep statistics per tenant (see [`PerTenant`]).
+    pub tenants: BTreeMap<String, Vec<TenantRule>>,
+}
+
+impl AnalysisConfig {
+    /// Create the default configuration, running no analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse and validate a TOML configuration.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse and validate a YAML configuration.
+    pub fn from_yaml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = serde_yaml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Load a configuration file, in TOML or YAML depending on its
+    /// extension (`.toml`, `.yaml` or `.yml`).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => Err(ConfigError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Serialize the configuration as TOML, e.g. to record the settings of
+    /// a run next to its results.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("configuration is serializable")
+    }
+
+    /// Build a registry of the built-in dissectors with the "Decode As"
+    /// overrides.
+    pub fn registry(&self) -> DissectorRegistry {
+        let mut registry = DissectorRegistry::with_builtins();
+        for (key, plugin) in &self.decode_as {
+            registry.decode_as(*key, plugin.clone());
+        }
+        registry
+    }
+
+    /// Check that the configuration is consistent.
+    ///
+    /// A sampling ratio must not be 0, exported analyzers must be enabled,
+    /// and "Decode As" overrides must name a built-in dissector.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self.sampling {
+            Sampling::OneInN(0) | Sampling::FlowHash { n: 0, .. } => {
+                return Err(ConfigError::Invalid("sampling ratio is 0".into()));
+            }
+            _ => {}
+        }
+        let builtins = DissectorRegistry::with_builtins().names();
+        for (key, plugin) in &self.decode_as {
+            if !builtins.contains(&plugin.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "unknown dissector {plugin:?} for {key}"
+                )));
+            }
+        }
+        for export in &self.exports {
+            if !self.analyzers.contains(&export.analyzer) {
+                return Err(ConfigError::Invalid(format!(
+                    "exported analyzer {:?} is not enabled",
+                    export.analyzer
+                )));
+            }
+            if export.epsilon.is_some_and(|e| !(e > 0.0 && e.is_finite())) {
+                return Err(ConfigError::Invalid(format!(
+                    "epsilon of the export to {} is not positive",
+                    export.path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the changes from this configuration to `new`.
+    pub fn diff(&self, new: &Self) -> ConfigDiff {
+        let missing = |from: &Self, to: &Self| {
+            from.analyzers
+                .iter()
+                .filter(|kind| !to.analyzers.contains(kind))
+                .copied()
+                .collect()
+        };
+        ConfigDiff {
+            filter: self.filter != new.filter,
+            added: missing(new, self),
+            removed: missing(self, new),
+            rebuild: self.flow_table != new.flow_table
+                || self.sampling != new.sampling
+                || self.decode_as != new.decode_as
+                || self.tenants != new.tenants,
+            exports: self.exports != new.exports,
+        }
+    }
+
+    /// Build the classifier of the tenants, if any.
+    pub fn tenant_classifier(&self) -> Option<TenantClassifier> {
+        if self.tenants.is_empty() {
+            return None;
+        }
+        let mut classifier = TenantClassifier::new();
+        for (name, rules) in &self.tenants {
+            classifier.tenant(name, rules.iter().copied());
+        }
+        Some(classifier)
+    }
+
+    /// Build an analyzer, per tenant if tenants are set.
+    pub fn build_analyzer(&self, kind: AnalyzerKind) -> Box<dyn TableAnalyzer> {
+        match self.tenant_classifier() {
+            Some(classifier) => {
+                let config = self.clone();
+                Box::new(PerTenant::new(classifier, move || kind.build(&config)))
+            }
+            None => kind.build(self),
+        }
+    }
+
+    /// Build the enabled analyzers, in order.
+    pub fn build_analyzers(&self) -> Vec<(AnalyzerKind, Box<dyn TableAnalyzer>)> {
+        self.analyzers
+            .iter()
+            .map(|kind| (*kind, self.build_analyzer(*kind)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::flow::table::HashKey;
+
+    use super::*;
+
+    #[test]
+    fn analysis_config_formats() {
+        let yaml = "
+filter: udp
+analyzers: [http, top-talkers]
+flow-table:
+  hash-key: !fixed [1, 2]
+sampling: !flow-hash { n: 4, seed: 7 }
+decode-as:
+  udp.port:8053: Dns
+exports:
+  - analyzer: http
+    path: http.csv
+    format: csv
+    epsilon: 1.0
+";
+        let config = AnalysisConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.filter.as_deref(), Some("udp"));
+        assert_eq!(config.flow_table.hash_key, HashKey::Fixed(1, 2));
+        assert_eq!(config.sampling, Sampling::FlowHash { n: 4, seed: 7 });
+        assert_eq!(config.exports[0].path, PathBuf::from("http.csv"));
+        assert_eq!(config.exports[0].epsilon, Some(1.0));
+        assert_eq!(config.decode_as[&DecodeKey::UdpPort(8053)], "Dns");
+
+        // The same run, recorded as TOML
+        assert_eq!(
+            AnalysisConfig::from_toml(&config.to_toml()).unwrap(),
+            config
+        );
+
+        assert!(AnalysisConfig::from_toml("analyzers = [\"nope\"]").is_err());
+        assert!(AnalysisConfig::from_toml("unknown = 1").is_err());
+        assert!(matches!(
+            AnalysisConfig::from_toml("[[exports]]\nanalyzer = \"tcp\"\npath = \"tcp.csv\""),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            AnalysisConfig::from_toml(
+                "analyzers = [\"tcp\"]\n[[exports]]\nanalyzer = \"tcp\"\npath = \"tcp.csv\"\nepsilon = 0"
+            ),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            AnalysisConfig::from_toml("sampling = { one-in-n = 0 }"),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            AnalysisConfig::from_toml("decode-as = { \"udp.port:1\" = \"Nope\" }"),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(AnalysisConfig::from_toml("decode-as = { \"udp:1\" = \"Dns\" }").is_err());
+
+        let new = AnalysisConfig::from_yaml("filter: udp\nanalyzers: [tcp, http]").unwrap();
+        let diff = config.diff(&new);
+        assert_eq!(
+            (diff.added, diff.removed),
+            (vec![AnalyzerKind::Tcp], vec![AnalyzerKind::TopTalkers])
+        );
+        assert!(!diff.filter && diff.rebuild && diff.exports);
+        assert!(config.diff(&config).is_empty());
+
+        let tenants = AnalysisConfig::from_toml(
+            "analyzers = [\"top-talkers\"]\n[tenants]\nblue = [\"10.1.0.0/16\", \"vlan:100\"]",
+        )
+        .unwrap();
+        assert_eq!(tenants.tenants["blue"][1], TenantRule::Vlan(100));
+        assert!(tenants.tenant_classifier().is_some());
+        assert!(AnalysisConfig::from_toml("[tenants]\nblue = [\"10.1.0.0\"]").is_err());
+    }
+}
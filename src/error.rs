@@ -0,0 +1,305 @@
+//! Unified error type.
+//!
+//! The crates of netkit each report their own errors: layers fail to parse
+//! with e.g. [`Ipv4Error`], indexes with [`IndexError`], files with
+//! [`io::Error`]. An application processing a capture mostly wants to tell
+//! the user which packet failed and why. [`Error`] wraps all of them and
+//! carries where in the capture the failure happened, so it reads like
+//! `packet #4412 at offset 0x1a2b: Ipv4: Invalid Ipv4 length: ...`. Parse
+//! errors keep the [`LayerError`] locating the failure in the layer, see
+//! [`Error::layer_error`].
+//!
+//! The [`Context`] trait attaches the position to any result:
+//!
+//! ```
+//! use netkit::{error::Context, packet::prelude::*};
+//!
+//! let result = Ipv4::new(&[0x45u8; 8][..]).packet(4412).offset(0x1a2b);
+//! let Err(err) = result else { unreachable!() };
+//! assert_eq!(err.packet(), Some(4412));
+//! assert!(err.to_string().starts_with("packet #4412 at offset 0x1a2b: Ipv4: "));
+//! ```
+
+use std::{fmt, io};
+
+use netkit_capture::file::index::IndexError;
+use netkit_packet::{dissect::DissectError, prelude::*};
+
+/// Result type using [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What went wrong.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Reading or writing failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// An index file is invalid.
+    #[error(transparent)]
+    Index(#[from] IndexError),
+
+    /// A layer failed to parse.
+    #[error("{layer}: {source}")]
+    Parse {
+        /// Name of the layer.
+        layer: &'static str,
+        /// Error of the layer.
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A packet could not be dissected.
+    #[error(transparent)]
+    Dissect(#[from] DissectError),
+
+    /// A packet could not be built.
+    #[error("{layer}: {reason}")]
+    Build {
+        /// Name of the layer.
+        layer: &'static str,
+        /// Reason of the failure.
+        reason: String,
+    },
+
+    /// Capturing failed.
+    #[error("{0}")]
+    Capture(String),
+}
+
+/// Error of netkit, with the position in the capture where it happened.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    packet: Option<u64>,
+    offset: Option<u64>,
+}
+
+impl Error {
+    /// Create a new error without position.
+    pub fn new(kind: impl Into<ErrorKind>) -> Self {
+        Self {
+            kind: kind.into(),
+            packet: None,
+            offset: None,
+        }
+    }
+
+    /// Create a parse error of a layer.
+    pub fn parse(
+        layer: &'static str,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Self::new(ErrorKind::Parse {
+            layer,
+            source: source.into(),
+        })
+    }
+
+    /// Create a build error of a layer.
+    pub fn build(layer: &'static str, reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Build {
+            layer,
+            reason: reason.into(),
+        })
+    }
+
+    /// Create a capture error.
+    pub fn capture(reason: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Capture(reason.into()))
+    }
+
+    /// Set the ordinal of the packet, if not already set.
+    pub fn with_packet(mut self, packet: u64) -> Self {
+        self.packet.get_or_insert(packet);
+        self
+    }
+
+    /// Set the offset in the capture, if not already set.
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset.get_or_insert(offset);
+        self
+    }
+
+    /// Get what went wrong.
+    #[inline]
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Get the ordinal of the packet.
+    #[inline]
+    pub fn packet(&self) -> Option<u64> {
+        self.packet
+    }
+
+    /// Get the offset in the capture.
+    #[inline]
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Get where in its layer a parse error happened, if known.
+    pub fn layer_error(&self) -> Option<&LayerError> {
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            if let Some(err) = err.downcast_ref::<LayerError>() {
+                return Some(err);
+            }
+            source = err.source();
+        }
+        None
+    }
+
+    /// Get the name of the layer that failed, if any.
+    pub fn layer(&self) -> Option<&'static str> {
+        match &self.kind {
+            ErrorKind::Parse { layer, .. } | ErrorKind::Build { layer, .. } => Some(layer),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.packet, self.offset) {
+            (Some(packet), Some(offset)) => write!(f, "packet #{packet} at offset {offset:#x}: ")?,
+            (Some(packet), None) => write!(f, "packet #{packet}: ")?,
+            (None, Some(offset)) => write!(f, "offset {offset:#x}: ")?,
+            (None, None) => {}
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.kind)
+    }
+}
+
+impl<E: Into<ErrorKind>> From<E> for Error {
+    fn from(err: E) -> Self {
+        Self::new(err)
+    }
+}
+
+macro_rules! impl_from_layer_error {
+    ($($layer:literal => $err:ty),* $(,)?) => {
+        $(
+            impl From<$err> for ErrorKind {
+                fn from(err: $err) -> Self {
+                    ErrorKind::Parse {
+                        layer: $layer,
+                        source: Box::new(err),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_layer_error!(
+    "Eth" => EthError,
+    "Null" => NullError,
+    "Sll2" => Sll2Error,
+    "Vlan" => VlanError,
+    "Llc" => LlcError,
+    "Ppp" => PppError,
+    "Eapol" => EapolError,
+    "Eap" => EapError,
+    "Goose" => GooseError,
+    "Sv" => SvError,
+    "Ptp" => PtpError,
+    "Ipv4" => Ipv4Error,
+    "Ipv6" => Ipv6Error,
+    "Icmp" => IcmpError,
+    "Ah" => AhError,
+    "Esp" => EspError,
+    "Gre" => GreError,
+    "Tcp" => TcpError,
+    "Udp" => UdpError,
+    "UdpLite" => UdpLiteError,
+    "Dccp" => DccpError,
+    "L2tp" => L2tpError,
+    "Dns" => DnsError,
+    "Rtp" => RtpError,
+    "Quic" => QuicError,
+    "OpenVpn" => OpenVpnError,
+    "WireGuard" => WireGuardError,
+);
+
+/// A bare [`LayerError`] converts like the error of its layer.
+impl From<LayerError> for ErrorKind {
+    fn from(err: LayerError) -> Self {
+        ErrorKind::Parse {
+            layer: err.layer,
+            source: Box::new(err),
+        }
+    }
+}
+
+/// Attach the position in the capture to an error.
+///
+/// This trait is sealed; it is implemented for results.
+pub trait Context<T>: sealed::Sealed {
+    /// Set the ordinal of the packet that failed.
+    fn packet(self, packet: u64) -> Result<T>;
+
+    /// Set the offset in the capture where the failure happened.
+    fn offset(self, offset: u64) -> Result<T>;
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl<T, E> Sealed for std::result::Result<T, E> {}
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn packet(self, packet: u64) -> Result<T> {
+        self.map_err(|err| err.into().with_packet(packet))
+    }
+
+    fn offset(self, offset: u64) -> Result<T> {
+        self.map_err(|err| err.into().with_offset(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn error_context() {
+        let layer_err = LayerError::min_length("Ipv4", "header", 0, 20, 8);
+        let err = Error::from(Ipv4Error::InvalidLength(layer_err))
+            .with_packet(4412)
+            .with_offset(0x1a2b);
+        assert_eq!(
+            err.to_string(),
+            "packet #4412 at offset 0x1a2b: Ipv4: Invalid Ipv4 length: header at offset 0: \
+             length 8 is less than 20"
+        );
+        assert_eq!(err.layer(), Some("Ipv4"));
+        assert!(err.source().is_some());
+        assert_eq!(err.layer_error(), Some(&layer_err));
+
+        let bare = Error::from(LayerError::min_value("Ptp", "message length", 2, 34, 10));
+        assert_eq!(bare.layer(), Some("Ptp"));
+        assert_eq!(bare.layer_error().map(|e| e.offset), Some(2));
+
+        // The innermost context wins.
+        let err = Err::<(), _>(err).packet(1).unwrap_err();
+        assert_eq!(err.packet(), Some(4412));
+
+        let err = Err::<(), _>(io::Error::from(io::ErrorKind::UnexpectedEof))
+            .offset(24)
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Io(_)));
+        assert!(err.to_string().starts_with("offset 0x18: "));
+        assert_eq!(err.layer(), None);
+    }
+}
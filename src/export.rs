@@ -0,0 +1,191 @@
+//! Columnar export of reports.
+//!
+//! Reports implementing [`ToTable`] are converted into a [`Table`], a list of
+//! named and typed columns of equal length, which can be written out as CSV
+//! or handed to a dataframe library column by column.
+
+pub mod bundle;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+pub mod dataset;
+#[cfg(feature = "encryption")]
+pub mod encrypt;
+pub mod eve;
+pub mod events;
+pub mod ipfix;
+pub mod partition;
+pub mod privacy;
+pub mod rdns;
+pub mod retention;
+pub mod zeek;
+
+use std::io::{self, Write};
+
+/// A typed column of values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    /// Unsigned integers.
+    U64(Vec<u64>),
+    /// Floating point numbers.
+    F64(Vec<f64>),
+    /// Strings.
+    Str(Vec<String>),
+}
+
+impl Column {
+    /// Get the number of values in the column.
+    pub fn len(&self) -> usize {
+        match self {
+            Column::U64(values) => values.len(),
+            Column::F64(values) => values.len(),
+            Column::Str(values) => values.len(),
+        }
+    }
+
+    /// Whether the column is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn extend(&mut self, other: Column) {
+        match (self, other) {
+            (Column::U64(values), Column::U64(other)) => values.extend(other),
+            (Column::F64(values), Column::F64(other)) => values.extend(other),
+            (Column::Str(values), Column::Str(other)) => values.extend(other),
+            _ => panic!("column type mismatch"),
+        }
+    }
+
+    fn write_value<W: Write>(&self, writer: &mut W, row: usize) -> io::Result<()> {
+        match self {
+            Column::U64(values) => write!(writer, "{}", values[row]),
+            Column::F64(values) => write!(writer, "{}", values[row]),
+            Column::Str(values) => {
+                let value = &values[row];
+                if value.contains([',', '"', '\n', '\r']) {
+                    write!(writer, "\"{}\"", value.replace('"', "\"\""))
+                } else {
+                    write!(writer, "{value}")
+                }
+            }
+        }
+    }
+}
+
+/// A table of named columns of equal length.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    columns: Vec<(String, Column)>,
+}
+
+impl Table {
+    /// Create a new empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column length differs from the existing columns.
+    pub fn push(&mut self, name: impl Into<String>, column: Column) -> &mut Self {
+        if let Some((_, first)) = self.columns.first() {
+            assert_eq!(first.len(), column.len(), "column length mismatch");
+        }
+        self.columns.push((name.into(), column));
+        self
+    }
+
+    /// Get the columns with their names.
+    pub fn columns(&self) -> &[(String, Column)] {
+        &self.columns
+    }
+
+    /// Get a column by name.
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|(n, _)| n == name).map(|(_, c)| c)
+    }
+
+    /// Get the number of rows.
+    pub fn len(&self) -> usize {
+        self.columns.first().map_or(0, |(_, c)| c.len())
+    }
+
+    /// Whether the table has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append the rows of a table with the same columns.
+    ///
+    /// An empty table without columns takes the columns of the other one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column names or types differ.
+    pub fn append(&mut self, other: Table) -> &mut Self {
+        if self.columns.is_empty() {
+            self.columns = other.columns;
+            return self;
+        }
+        assert_eq!(self.columns.len(), other.columns.len(), "column mismatch");
+        for ((name, column), (other_name, other)) in self.columns.iter_mut().zip(other.columns) {
+            assert_eq!(*name, other_name, "column name mismatch");
+            column.extend(other);
+        }
+        self
+    }
+
+    /// Write the table as CSV with a header line.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let header: Vec<_> = self.columns.iter().map(|(n, _)| n.as_str()).collect();
+        writeln!(writer, "{}", header.join(","))?;
+
+        for row in 0..self.len() {
+            for (i, (_, column)) in self.columns.iter().enumerate() {
+                if i > 0 {
+                    writer.write_all(b",")?;
+                }
+                column.write_value(writer, row)?;
+            }
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Conversion of a report into a [`Table`].
+pub trait ToTable {
+    /// Convert into a table, one row per report entry.
+    fn to_table(&self) -> Table;
+}
+
+impl<T: ToTable + ?Sized> ToTable for Box<T> {
+    fn to_table(&self) -> Table {
+        (**self).to_table()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_csv() {
+        let mut table = Table::new();
+        table
+            .push("name", Column::Str(vec!["a".into(), "b,c".into()]))
+            .push("count", Column::U64(vec![1, 2]))
+            .push("rate", Column::F64(vec![0.5, 1.0]));
+
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "name,count,rate\na,1,0.5\n\"b,c\",2,1\n"
+        );
+        assert_eq!(table.column("count"), Some(&Column::U64(vec![1, 2])));
+    }
+}
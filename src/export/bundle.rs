@@ -0,0 +1,306 @@
+//! Self-describing report bundles.
+//!
+//! A [`ReportBundle`] runs the usual first-look analyses over a capture and
+//! writes them to a directory that can be zipped and shared as is:
+//!
+//! | File                | Content                                         |
+//! |---------------------|-------------------------------------------------|
+//! | `summary.json`      | Capture span, totals and the list of files      |
+//! | `protocols.csv`     | [Protocol hierarchy](ProtocolHierarchy)         |
+//! | `top_talkers.csv`   | [Top endpoints](TopTalkers) by bytes sent       |
+//! | `flows.csv`         | [Per-flow bytes and overhead](OverheadStats)    |
+//! | `tcp_flows.csv`     | [TCP flow quality](TcpFlowAnalyzer)             |
+//! | `anomalies.csv`     | Alerts of the beaconing and DNS tunneling detectors |
+//!
+//! ```no_run
+//! use netkit::export::bundle::ReportBundle;
+//!
+//! let summary = ReportBundle::from_pcap("incident.pcap", "incident-report").unwrap();
+//! println!("{} packets, {} alerts", summary.packets, summary.alerts);
+//! ```
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use netkit_capture::file::pcap::PcapReader;
+
+use super::ToTable;
+use crate::{
+    analysis::{
+        alert::{alerts_table, AlertSource},
+        analyze_pcap,
+        beacon::BeaconDetector,
+        tcp::TcpFlowAnalyzer,
+        Analyzer,
+    },
+    stats::{
+        dns::DnsTunnelDetector, hierarchy::ProtocolHierarchy, overhead::OverheadStats,
+        top::TopTalkers,
+    },
+};
+
+/// Files of a bundle besides the summary, in the order they are written.
+pub const BUNDLE_FILES: [&str; 5] = [
+    "protocols.csv",
+    "top_talkers.csv",
+    "flows.csv",
+    "tcp_flows.csv",
+    "anomalies.csv",
+];
+
+/// Totals written to `summary.json`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BundleSummary {
+    /// Name of the capture, e.g. its file name.
+    pub capture: Option<String>,
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of bytes.
+    pub bytes: u64,
+    /// Timestamp of the first packet.
+    pub first_seen: Option<Duration>,
+    /// Timestamp of the last packet.
+    pub last_seen: Option<Duration>,
+    /// Number of flows.
+    pub flows: u64,
+    /// Number of TCP flow directions.
+    pub tcp_flows: u64,
+    /// Number of alerts.
+    pub alerts: u64,
+}
+
+impl BundleSummary {
+    /// Serialize as a JSON object.
+    pub fn to_json(&self) -> String {
+        let secs =
+            |ts: Option<Duration>| ts.map_or("null".to_string(), |ts| ts.as_secs_f64().to_string());
+        let duration = match (self.first_seen, self.last_seen) {
+            (Some(first), Some(last)) => last.saturating_sub(first).as_secs_f64(),
+            _ => 0.0,
+        };
+        let files: Vec<_> = BUNDLE_FILES.iter().map(|f| json_string(f)).collect();
+
+        let mut json = String::from("{\n");
+        let mut field = |name: &str, value: String| {
+            let _ = writeln!(json, "  \"{name}\": {value},");
+        };
+        field(
+            "generator",
+            json_string(concat!("netkit ", env!("CARGO_PKG_VERSION"))),
+        );
+        field(
+            "capture",
+            self.capture
+                .as_deref()
+                .map_or("null".to_string(), json_string),
+        );
+        field("packets", self.packets.to_string());
+        field("bytes", self.bytes.to_string());
+        field("first_seen", secs(self.first_seen));
+        field("last_seen", secs(self.last_seen));
+        field("duration", duration.to_string());
+        field("flows", self.flows.to_string());
+        field("tcp_flows", self.tcp_flows.to_string());
+        field("alerts", self.alerts.to_string());
+        let _ = writeln!(json, "  \"files\": [{}]\n}}", files.join(", "));
+        json
+    }
+}
+
+/// Report bundle builder
+///
+/// Feeds every packet to the analyses of the bundle; [`write`](Self::write)
+/// then writes their reports.
+pub struct ReportBundle {
+    summary: BundleSummary,
+    hierarchy: ProtocolHierarchy,
+    top: TopTalkers,
+    overhead: OverheadStats,
+    tcp: TcpFlowAnalyzer,
+    beacons: BeaconDetector,
+    tunnels: DnsTunnelDetector,
+}
+
+impl Default for ReportBundle {
+    fn default() -> Self {
+        Self {
+            summary: BundleSummary::default(),
+            hierarchy: ProtocolHierarchy::new(),
+            top: TopTalkers::new(),
+            overhead: OverheadStats::new(),
+            tcp: TcpFlowAnalyzer::new(),
+            beacons: BeaconDetector::new(),
+            tunnels: DnsTunnelDetector::new(),
+        }
+    }
+}
+
+impl ReportBundle {
+    /// Create a new bundle builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the capture written to the summary.
+    pub fn capture(&mut self, name: impl Into<String>) -> &mut Self {
+        self.summary.capture = Some(name.into());
+        self
+    }
+
+    /// Analyze a pcap file and write its bundle to `dir`.
+    pub fn from_pcap(pcap: impl AsRef<Path>, dir: impl AsRef<Path>) -> io::Result<BundleSummary> {
+        let pcap = pcap.as_ref();
+        let mut reader = PcapReader::new(io::BufReader::new(File::open(pcap)?));
+        let mut bundle = Self::new();
+        if let Some(name) = pcap.file_name() {
+            bundle.capture(name.to_string_lossy());
+        }
+        analyze_pcap(&mut reader, &mut bundle);
+        bundle.write(dir)
+    }
+
+    /// Get the totals, as written to the summary.
+    pub fn summary(&self) -> BundleSummary {
+        let alerts = self.beacons.alerts().len() + self.tunnels.alerts().len();
+        BundleSummary {
+            flows: self.overhead.flows().len() as u64,
+            tcp_flows: self.tcp.reports().len() as u64,
+            alerts: alerts as u64,
+            ..self.summary.clone()
+        }
+    }
+
+    /// Write the bundle to a directory, created if missing, returning the
+    /// summary.
+    pub fn write(&self, dir: impl AsRef<Path>) -> io::Result<BundleSummary> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut alerts = [self.beacons.alerts(), self.tunnels.alerts()].concat();
+        alerts.sort_by_key(|a| a.ts);
+        let tables = [
+            self.hierarchy.to_table(),
+            self.top.to_table(),
+            self.overhead.to_table(),
+            self.tcp.to_table(),
+            alerts_table(&alerts),
+        ];
+        for (name, table) in BUNDLE_FILES.iter().zip(tables) {
+            let mut writer = BufWriter::new(File::create(dir.join(name))?);
+            table.write_csv(&mut writer)?;
+            writer.flush()?;
+        }
+
+        let summary = self.summary();
+        fs::write(dir.join("summary.json"), summary.to_json())?;
+        Ok(summary)
+    }
+}
+
+impl Analyzer for ReportBundle {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let summary = &mut self.summary;
+        summary.packets += 1;
+        summary.bytes += frame.len() as u64;
+        summary.first_seen.get_or_insert(ts);
+        summary.last_seen = Some(summary.last_seen.map_or(ts, |last| last.max(ts)));
+
+        self.hierarchy.on_packet(ts, frame);
+        self.top.on_packet(ts, frame);
+        self.overhead.on_packet(ts, frame);
+        self.tcp.on_packet(ts, frame);
+        self.beacons.on_packet(ts, frame);
+        self.tunnels.on_packet(ts, frame);
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Format a timestamp since the epoch as an RFC 3339 UTC date and time,
+/// with microseconds.
+pub(crate) fn rfc3339(ts: Duration) -> String {
+    let secs = ts.as_secs();
+    // Civil date from days since the epoch, after Howard Hinnant.
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        ts.subsec_micros(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{layer::tcp::TcpFlags, prelude::*};
+
+    use super::*;
+
+    #[test]
+    fn report_bundle() {
+        let tcp = tcp!(src_port: 40000u16, dst_port: 80u16, flags: TcpFlags::SYN);
+        let ipv4 = ipv4!(
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::Tcp,
+            payload: tcp.inner(),
+        );
+        let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+
+        let mut bundle = ReportBundle::new();
+        bundle.capture("a \"quoted\" name.pcap");
+        bundle.on_packet(Duration::from_secs(2), frame.inner());
+        bundle.on_packet(Duration::from_secs(5), frame.inner());
+
+        let dir = std::env::temp_dir().join(format!("netkit-bundle-{}", std::process::id()));
+        let summary = bundle.write(&dir).unwrap();
+        assert_eq!(
+            (summary.packets, summary.flows, summary.tcp_flows),
+            (2, 1, 1)
+        );
+
+        let json = fs::read_to_string(dir.join("summary.json")).unwrap();
+        assert!(json.contains(r#""capture": "a \"quoted\" name.pcap","#));
+        assert!(json.contains("\"duration\": 3,"));
+        let protocols = fs::read_to_string(dir.join("protocols.csv")).unwrap();
+        assert!(protocols.contains("Eth/Ipv4/Tcp,3,2,100,"));
+        for file in BUNDLE_FILES {
+            assert!(dir.join(file).exists());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+//! Inserts into ClickHouse over HTTP.
+//!
+//! A [`ClickHouseExporter`] batches rows and inserts them with the HTTP
+//! interface of ClickHouse in the `JSONEachRow` format, retrying failed
+//! inserts with exponential backoff. Rows come from any report implementing
+//! [`ToTable`](super::ToTable) via [`insert_table`](ClickHouseExporter::insert_table),
+//! or from packets: as an [`Analyzer`], the exporter records one row per
+//! IPv4 or IPv6 packet with the columns of the
+//! [partitioned export](super::partition), `ts` being a float.
+//!
+//! The client speaks plain HTTP/1.1; put a TLS proxy in front of remote
+//! servers.
+//!
+//! ```no_run
+//! use netkit::export::clickhouse::ClickHouseExporter;
+//!
+//! let mut exporter = ClickHouseExporter::new("127.0.0.1:8123", "netkit.packets");
+//! exporter.user("netkit", "secret").batch_size(50_000);
+//! // Feed packets, then:
+//! exporter.flush().unwrap();
+//! ```
+
+use std::{fmt::Write as _, io, time::Duration};
+
+use netkit_capture::http::{self, Client};
+use netkit_packet::prelude::*;
+
+use super::{bundle::json_string, Column, Table};
+use crate::analysis::Analyzer;
+
+/// Default number of rows per insert.
+pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// Default number of retries of a failed insert.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default delay before the first retry, doubled at every retry.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Default timeout of connections, reads and writes.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ClickHouse exporter
+///
+/// Rows are buffered until [`batch_size`](Self::batch_size) of them are
+/// pending, then inserted in one request. Errors of inserts triggered by
+/// packets are kept and returned by the next [`flush`](Self::flush); the
+/// rows of a failed insert are kept for it.
+#[derive(Debug)]
+pub struct ClickHouseExporter {
+    client: Client,
+    table: String,
+    credentials: Option<(String, String)>,
+    batch_size: usize,
+    max_retries: u32,
+    backoff: Duration,
+    pending: String,
+    rows: usize,
+    inserted: u64,
+    error: Option<io::Error>,
+}
+
+impl ClickHouseExporter {
+    /// Create an exporter inserting into `table` (e.g. `db.packets`) of the
+    /// server at `addr` (e.g. `127.0.0.1:8123`).
+    pub fn new(addr: impl Into<String>, table: impl Into<String>) -> Self {
+        let mut client = Client::new(addr);
+        client.timeout(DEFAULT_TIMEOUT);
+        Self {
+            client,
+            table: table.into(),
+            credentials: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            pending: String::new(),
+            rows: 0,
+            inserted: 0,
+            error: None,
+        }
+    }
+
+    /// Set the user and password.
+    pub fn user(&mut self, user: impl Into<String>, password: impl Into<String>) -> &mut Self {
+        self.credentials = Some((user.into(), password.into()));
+        self
+    }
+
+    /// Set the number of rows per insert.
+    pub fn batch_size(&mut self, batch_size: usize) -> &mut Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set the number of retries of a failed insert.
+    pub fn max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the timeout of connections, reads and writes.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.client.timeout(timeout);
+        self
+    }
+
+    /// Get the number of rows inserted.
+    pub fn inserted(&self) -> u64 {
+        self.inserted
+    }
+
+    /// Get the number of rows not inserted yet.
+    pub fn pending(&self) -> usize {
+        self.rows
+    }
+
+    /// Queue the rows of a table, inserting full batches.
+    pub fn insert_table(&mut self, table: &Table) -> io::Result<()> {
+        for row in 0..table.len() {
+            self.pending.push('{');
+            for (i, (name, column)) in table.columns().iter().enumerate() {
+                if i > 0 {
+                    self.pending.push(',');
+                }
+                let _ = write!(self.pending, "{}:", json_string(name));
+                match column {
+                    Column::U64(values) => {
+                        let _ = write!(self.pending, "{}", values[row]);
+                    }
+                    Column::F64(values) if values[row].is_finite() => {
+                        let _ = write!(self.pending, "{}", values[row]);
+                    }
+                    Column::F64(_) => self.pending.push_str("null"),
+                    Column::Str(values) => self.pending.push_str(&json_string(&values[row])),
+                }
+            }
+            self.pending.push_str("}\n");
+            self.rows += 1;
+            if self.rows >= self.batch_size {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert the pending rows.
+    ///
+    /// Returns the error of a previous insert triggered by a packet, if any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        http::retry(self.max_retries, self.backoff, || self.post())?;
+        self.inserted += self.rows as u64;
+        self.pending.clear();
+        self.rows = 0;
+        Ok(())
+    }
+
+    /// Send the pending rows in one request.
+    fn post(&self) -> io::Result<()> {
+        let query = format!("INSERT INTO {} FORMAT JSONEachRow", self.table);
+        let target = format!("/?query={}", percent_encode(&query));
+        let mut request = self
+            .client
+            .request("POST", &target)
+            .header("Content-Type", "application/x-ndjson");
+        if let Some((user, password)) = &self.credentials {
+            request = request
+                .header("X-ClickHouse-User", user)
+                .header("X-ClickHouse-Key", password);
+        }
+        let response = request.body(self.pending.as_bytes()).send()?;
+        match response.status() {
+            200 => Ok(()),
+            // The body holds the ClickHouse exception.
+            _ => Err(response.error("ClickHouse")),
+        }
+    }
+}
+
+impl Analyzer for ClickHouseExporter {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Some(key) = Eth::new(frame).ok().and_then(|eth| FlowKey::from_eth(&eth)) else {
+            return;
+        };
+        let _ = writeln!(
+            self.pending,
+            "{{\"ts\":{},\"protocol\":\"{}\",\"src\":\"{}\",\"src_port\":{},\"dst\":\"{}\",\
+             \"dst_port\":{},\"length\":{}}}",
+            ts.as_secs_f64(),
+            key.protocol,
+            key.src,
+            key.src_port,
+            key.dst,
+            key.dst_port,
+            frame.len(),
+        );
+        self.rows += 1;
+        if self.rows >= self.batch_size && self.error.is_none() {
+            if let Err(e) = self.flush() {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{b:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Answer one request per response, returning the requests.
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                while !request.ends_with(b"}\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn clickhouse_insert() {
+        // Fail the first insert with a server error, accept the retry.
+        let (addr, server) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+        ]);
+        let mut exporter = ClickHouseExporter::new(addr, "netkit.packets");
+        exporter
+            .user("netkit", "secret")
+            .batch_size(2)
+            .backoff(Duration::from_millis(1));
+        let udp = udp!(src_port: 1u16, dst_port: 2u16);
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+        exporter.on_packet(Duration::from_millis(1500), eth.inner());
+        assert_eq!(exporter.pending(), 1);
+        exporter.on_packet(Duration::from_secs(2), eth.inner());
+        exporter.flush().unwrap();
+        assert_eq!((exporter.inserted(), exporter.pending()), (2, 0));
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0], requests[1]);
+        let request = &requests[1];
+        assert!(request.starts_with(
+            "POST /?query=INSERT%20INTO%20netkit.packets%20FORMAT%20JSONEachRow HTTP/1.1\r\n"
+        ));
+        assert!(request.contains("X-ClickHouse-User: netkit\r\n"));
+        assert!(request.ends_with(
+            "\r\n\r\n{\"ts\":1.5,\"protocol\":\"Udp\",\"src\":\"0.0.0.0\",\"src_port\":1,\
+             \"dst\":\"0.0.0.0\",\"dst_port\":2,\"length\":42}\n\
+             {\"ts\":2,\"protocol\":\"Udp\",\"src\":\"0.0.0.0\",\"src_port\":1,\
+             \"dst\":\"0.0.0.0\",\"dst_port\":2,\"length\":42}\n"
+        ));
+
+        // Rejected queries are not retried.
+        let (addr, server) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 16\r\n\r\nUnknown table xx",
+        ]);
+        let mut exporter = ClickHouseExporter::new(addr, "xx");
+        let mut table = Table::new();
+        table.push("n", Column::U64(vec![1]));
+        exporter.insert_table(&table).unwrap();
+        let err = exporter.flush().unwrap_err();
+        assert!(err.to_string().ends_with("404: Unknown table xx"));
+        assert_eq!(exporter.pending(), 1);
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+}
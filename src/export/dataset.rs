@@ -0,0 +1,294 @@
+//! Fixed-length per-flow feature vectors for machine learning datasets.
+//!
+//! Each flow becomes one row holding the features of its first packets:
+//! IP packet size, inter-arrival time, direction and TCP flag bits. Shorter
+//! flows are zero-padded so every row has the same width. Addresses and
+//! absolute timestamps are dropped: flows are numbered in order of
+//! appearance and times are relative to the previous packet of the flow, so
+//! the dataset can be shared without exposing the hosts of the capture.
+
+use std::{collections::HashMap, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use crate::{
+    analysis::Analyzer,
+    export::{Column, Table, ToTable},
+};
+
+/// Default number of packets kept per flow.
+pub const DEFAULT_PACKETS_PER_FLOW: usize = 20;
+
+/// Direction of a packet within its flow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the host that sent the first packet of the flow.
+    Forward,
+    /// Towards the host that sent the first packet of the flow.
+    Backward,
+}
+
+/// Features of a single packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketFeatures {
+    /// Size of the IP packet in bytes.
+    pub size: u16,
+    /// Time since the previous packet of the flow, zero for the first.
+    pub iat: Duration,
+    /// Direction of the packet.
+    pub direction: Direction,
+    /// TCP flag bits, zero for other protocols.
+    pub flags: u8,
+}
+
+/// Features of a flow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowSample {
+    /// Flow number, in order of appearance.
+    pub id: u64,
+    /// Transport protocol.
+    pub protocol: IpProtocol,
+    /// Destination port of the first packet, usually the service port.
+    pub port: u16,
+    /// Number of packets of the flow, including those not kept.
+    pub packets: u64,
+    /// Features of the first packets.
+    pub features: Vec<PacketFeatures>,
+}
+
+/// Dataset exporter
+///
+/// Collects the [`FlowSample`] of every IPv4 and IPv6 flow. The table has
+/// one row per flow with `size_<i>`, `iat_<i>`, `dir_<i>` and `flags_<i>`
+/// columns for each of the first packets, padded with zeros.
+#[derive(Clone, Debug)]
+pub struct DatasetExporter {
+    packets_per_flow: usize,
+    min_packets: u64,
+    ports: bool,
+    samples: Vec<FlowSample>,
+    flows: HashMap<FlowKey, (usize, FlowKey, Duration)>,
+}
+
+impl Default for DatasetExporter {
+    fn default() -> Self {
+        Self {
+            packets_per_flow: DEFAULT_PACKETS_PER_FLOW,
+            min_packets: 1,
+            ports: false,
+            samples: Vec::new(),
+            flows: HashMap::new(),
+        }
+    }
+}
+
+impl DatasetExporter {
+    /// Create a new dataset exporter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of packets kept per flow, the width of the vectors.
+    pub fn packets_per_flow(&mut self, packets: usize) -> &mut Self {
+        self.packets_per_flow = packets;
+        self
+    }
+
+    /// Set the minimum number of packets of the flows exported.
+    pub fn min_packets(&mut self, packets: u64) -> &mut Self {
+        self.min_packets = packets;
+        self
+    }
+
+    /// Set whether the service port is exported, e.g. as a label.
+    pub fn ports(&mut self, ports: bool) -> &mut Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Get the samples of the flows exported, in order of appearance.
+    pub fn samples(&self) -> Vec<&FlowSample> {
+        self.samples
+            .iter()
+            .filter(|s| s.packets >= self.min_packets)
+            .collect()
+    }
+
+    fn add(&mut self, ts: Duration, key: FlowKey, size: u16, flags: u8) {
+        let canonical = key.canonical();
+        if !self.flows.contains_key(&canonical) {
+            self.samples.push(FlowSample {
+                id: self.samples.len() as u64,
+                protocol: key.protocol,
+                port: key.dst_port,
+                packets: 0,
+                features: Vec::new(),
+            });
+            self.flows
+                .insert(canonical, (self.samples.len() - 1, key, ts));
+        }
+        let (index, initiator, last) = self.flows.get_mut(&canonical).unwrap();
+
+        let sample = &mut self.samples[*index];
+        sample.packets += 1;
+        if sample.features.len() < self.packets_per_flow {
+            sample.features.push(PacketFeatures {
+                size,
+                iat: ts.saturating_sub(*last),
+                direction: if key == *initiator {
+                    Direction::Forward
+                } else {
+                    Direction::Backward
+                },
+                flags,
+            });
+        }
+        *last = ts;
+    }
+}
+
+fn tcp_flags(tcp: Option<Tcp<&[u8]>>) -> u8 {
+    tcp.map_or(0, |tcp| tcp.flags().get().bits())
+}
+
+impl Analyzer for DatasetExporter {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            let size = ipv4.total_length().get();
+            self.add(ts, FlowKey::from_ipv4(&ipv4), size, tcp_flags(ipv4.tcp()));
+        } else if let Some(ipv6) = eth.ipv6() {
+            let size = ipv6.payload_length().get().saturating_add(40);
+            self.add(ts, FlowKey::from_ipv6(&ipv6), size, tcp_flags(ipv6.tcp()));
+        }
+    }
+}
+
+impl ToTable for DatasetExporter {
+    /// Convert into a table with one row per flow.
+    ///
+    /// Directions are `1` for forward, `2` for backward and `0` for padding;
+    /// inter-arrival times are in seconds.
+    fn to_table(&self) -> Table {
+        let samples = self.samples();
+        let features = |i: usize, f: &dyn Fn(&PacketFeatures) -> u64| {
+            Column::U64(
+                samples
+                    .iter()
+                    .map(|s| s.features.get(i).map_or(0, f))
+                    .collect(),
+            )
+        };
+
+        let mut table = Table::new();
+        table
+            .push("flow", Column::U64(samples.iter().map(|s| s.id).collect()))
+            .push(
+                "protocol",
+                Column::U64(
+                    samples
+                        .iter()
+                        .map(|s| u8::from(s.protocol) as u64)
+                        .collect(),
+                ),
+            );
+        if self.ports {
+            table.push(
+                "port",
+                Column::U64(samples.iter().map(|s| s.port as u64).collect()),
+            );
+        }
+        table.push(
+            "packets",
+            Column::U64(samples.iter().map(|s| s.packets).collect()),
+        );
+
+        for i in 0..self.packets_per_flow {
+            table
+                .push(format!("size_{i}"), features(i, &|p| p.size as u64))
+                .push(
+                    format!("iat_{i}"),
+                    Column::F64(
+                        samples
+                            .iter()
+                            .map(|s| s.features.get(i).map_or(0.0, |p| p.iat.as_secs_f64()))
+                            .collect(),
+                    ),
+                )
+                .push(
+                    format!("dir_{i}"),
+                    features(i, &|p| match p.direction {
+                        Direction::Forward => 1,
+                        Direction::Backward => 2,
+                    }),
+                )
+                .push(format!("flags_{i}"), features(i, &|p| p.flags as u64));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    fn frame(src: Ipv4Addr, dst: Ipv4Addr, ports: (u16, u16), flags: TcpFlags) -> Vec<u8> {
+        tcp_frame((src, ports.0), (dst, ports.1), 0, flags, &[])
+    }
+
+    #[test]
+    fn dataset_exporter() {
+        let (client, server) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+        let mut exporter = DatasetExporter::new();
+        exporter.packets_per_flow(2).ports(true);
+
+        let syn = frame(client, server, (40000, 443), TcpFlags::SYN);
+        let syn_ack = frame(server, client, (443, 40000), TcpFlags::SYN | TcpFlags::ACK);
+        let ack = frame(client, server, (40000, 443), TcpFlags::ACK);
+        let other = frame(client, server, (40001, 80), TcpFlags::SYN);
+        exporter.on_packet(Duration::from_millis(100), &syn);
+        exporter.on_packet(Duration::from_millis(130), &syn_ack);
+        exporter.on_packet(Duration::from_millis(200), &other);
+        exporter.on_packet(Duration::from_millis(131), &ack);
+
+        let samples = exporter.samples();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].packets, 3);
+        assert_eq!(samples[0].port, 443);
+        assert_eq!(
+            samples[0].features,
+            [
+                PacketFeatures {
+                    size: 40,
+                    iat: Duration::ZERO,
+                    direction: Direction::Forward,
+                    flags: TcpFlags::SYN.bits(),
+                },
+                PacketFeatures {
+                    size: 40,
+                    iat: Duration::from_millis(30),
+                    direction: Direction::Backward,
+                    flags: (TcpFlags::SYN | TcpFlags::ACK).bits(),
+                },
+            ]
+        );
+
+        let table = exporter.to_table();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.columns().len(), 4 + 2 * 4);
+        assert_eq!(table.column("port"), Some(&Column::U64(vec![443, 80])));
+        assert_eq!(table.column("dir_1"), Some(&Column::U64(vec![2, 0])));
+        assert_eq!(table.column("size_1"), Some(&Column::U64(vec![40, 0])));
+
+        exporter.min_packets(2);
+        assert_eq!(exporter.to_table().len(), 1);
+    }
+}
@@ -0,0 +1,384 @@
+//! Encryption of captures at rest.
+//!
+//! Raw traffic often holds personal data that must not be stored in the
+//! clear. An [`EncryptingWriter`] wraps the output of a capture writer and
+//! produces an authenticated, encrypted container, which a
+//! [`DecryptingReader`] turns back into the original bytes for a capture
+//! reader:
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit::export::encrypt::{DecryptingReader, EncryptingWriter, EncryptionKey};
+//! use netkit_capture::file::pcap::{PcapReader, PcapWriter};
+//!
+//! let key = EncryptionKey::generate();
+//! let mut writer = PcapWriter::new(EncryptingWriter::new(Vec::new(), &key).unwrap()).unwrap();
+//! writer.write_packet(Duration::ZERO, &[0; 60]).unwrap();
+//! let container = writer.into_inner().unwrap().finish().unwrap();
+//!
+//! let reader = PcapReader::new(DecryptingReader::new(container.as_slice(), &key).unwrap());
+//! assert_eq!(reader.count(), 1);
+//! ```
+//!
+//! # Envelope format
+//!
+//! | Field       | Size | Content                                          |
+//! |-------------|------|--------------------------------------------------|
+//! | magic       | 4    | [`MAGIC`]                                        |
+//! | version     | 1    | [`VERSION`]                                      |
+//! | chunk size  | 4    | Big-endian plaintext length of full chunks       |
+//! | salt        | 16   | Random salt of the file key                      |
+//! | chunks      |      | Encrypted chunks, the last one flagged           |
+//!
+//! The file key is derived from the 256-bit [`EncryptionKey`] and the salt
+//! with HKDF-SHA256 (info `netkit capture v1`). The plaintext is split into
+//! chunks of the chunk size, the last one possibly shorter or empty. Each
+//! chunk is a big-endian `u32` holding its ciphertext length, with the most
+//! significant bit set on the last chunk, then its AES-256-GCM ciphertext
+//! and tag. The nonce of chunk `i` is `i` as a big-endian `u64`, three zero
+//! bytes and 1 for the last chunk or 0 otherwise, and the header is the
+//! associated data of every chunk. Reordered, truncated or extended
+//! containers thus fail to decrypt.
+
+use std::io::{self, Read, Write};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Magic number of encrypted containers.
+pub const MAGIC: [u8; 4] = *b"NKEC";
+
+/// Version of the envelope format.
+pub const VERSION: u8 = 1;
+
+/// Default plaintext length of chunks.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Largest plaintext length of chunks accepted when decrypting.
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+const HEADER_LENGTH: usize = 25;
+const TAG_LENGTH: usize = 16;
+const LAST_CHUNK: u32 = 1 << 31;
+const KDF_INFO: &[u8] = b"netkit capture v1";
+
+/// 256-bit key of encrypted containers.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Create a key from its bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Generate a random key.
+    pub fn generate() -> Self {
+        let mut key = [0; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Get the bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn cipher(&self, salt: &[u8]) -> Aes256Gcm {
+        let mut key = [0; 32];
+        Hkdf::<Sha256>::new(Some(salt), &self.0)
+            .expand(KDF_INFO, &mut key)
+            .expect("valid HKDF output length");
+        Aes256Gcm::new(&key.into())
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+fn nonce(counter: u64, last: bool) -> Nonce<aes_gcm::aead::consts::U12> {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce.into()
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Writer of encrypted containers
+///
+/// Must be [`finish`](Self::finish)ed, or the container is rejected as
+/// truncated.
+pub struct EncryptingWriter<W: Write> {
+    writer: W,
+    cipher: Aes256Gcm,
+    header: [u8; HEADER_LENGTH],
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    counter: u64,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Create a writer with [`DEFAULT_CHUNK_SIZE`] and write the header.
+    pub fn new(writer: W, key: &EncryptionKey) -> io::Result<Self> {
+        Self::with_chunk_size(writer, key, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Create a writer with the given chunk size and write the header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chunk size is zero or above [`MAX_CHUNK_SIZE`].
+    pub fn with_chunk_size(
+        mut writer: W,
+        key: &EncryptionKey,
+        chunk_size: u32,
+    ) -> io::Result<Self> {
+        assert!(
+            (1..=MAX_CHUNK_SIZE).contains(&chunk_size),
+            "invalid chunk size"
+        );
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+
+        let mut header = [0; HEADER_LENGTH];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = VERSION;
+        header[5..9].copy_from_slice(&chunk_size.to_be_bytes());
+        header[9..].copy_from_slice(&salt);
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            writer,
+            cipher: key.cipher(&salt),
+            header,
+            chunk_size: chunk_size as usize,
+            buffer: Vec::with_capacity(chunk_size as usize),
+            counter: 0,
+        })
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce(self.counter, last),
+                Payload {
+                    msg: &self.buffer,
+                    aad: &self.header,
+                },
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        let len = ciphertext.len() as u32 | if last { LAST_CHUNK } else { 0 };
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(&ciphertext)?;
+        self.buffer.clear();
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// Write the last chunk, flush and get the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == self.chunk_size {
+            self.write_chunk(false)?;
+        }
+        Ok(len)
+    }
+
+    /// Flush the chunks written so far.
+    ///
+    /// The partial chunk stays buffered, since only the last chunk may be
+    /// shorter than the chunk size.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> std::fmt::Debug for EncryptingWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptingWriter")
+            .field("chunk_size", &self.chunk_size)
+            .field("counter", &self.counter)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reader of encrypted containers
+///
+/// Fails with [`io::ErrorKind::InvalidData`] on a wrong key or a
+/// tampered, truncated or extended container. Data is only returned once
+/// its chunk is authenticated.
+pub struct DecryptingReader<R: Read> {
+    reader: R,
+    cipher: Aes256Gcm,
+    header: [u8; HEADER_LENGTH],
+    chunk_size: usize,
+    chunk: Vec<u8>,
+    pos: usize,
+    counter: u64,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Create a reader and check the header.
+    pub fn new(mut reader: R, key: &EncryptionKey) -> io::Result<Self> {
+        let mut header = [0; HEADER_LENGTH];
+        reader.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(invalid_data("not an encrypted capture"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported encrypted capture version"));
+        }
+        let chunk_size = u32::from_be_bytes(header[5..9].try_into().unwrap());
+        if !(1..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+            return Err(invalid_data("invalid chunk size"));
+        }
+
+        Ok(Self {
+            reader,
+            cipher: key.cipher(&header[9..]),
+            header,
+            chunk_size: chunk_size as usize,
+            chunk: Vec::new(),
+            pos: 0,
+            counter: 0,
+            done: false,
+        })
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let mut len = [0; 4];
+        self.reader
+            .read_exact(&mut len)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => invalid_data("truncated encrypted capture"),
+                _ => e,
+            })?;
+        let len = u32::from_be_bytes(len);
+        let last = len & LAST_CHUNK != 0;
+        let len = (len & !LAST_CHUNK) as usize;
+        if len < TAG_LENGTH
+            || len > self.chunk_size + TAG_LENGTH
+            || (!last && len != self.chunk_size + TAG_LENGTH)
+        {
+            return Err(invalid_data("invalid chunk length"));
+        }
+
+        let mut ciphertext = vec![0; len];
+        self.reader
+            .read_exact(&mut ciphertext)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => invalid_data("truncated encrypted capture"),
+                _ => e,
+            })?;
+        self.chunk = self
+            .cipher
+            .decrypt(
+                &nonce(self.counter, last),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &self.header,
+                },
+            )
+            .map_err(|_| invalid_data("encrypted capture failed to authenticate"))?;
+        self.pos = 0;
+        self.counter += 1;
+
+        if last {
+            self.done = true;
+            if self.reader.read(&mut [0])? != 0 {
+                return Err(invalid_data("data after the last chunk"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl<R: Read> std::fmt::Debug for DecryptingReader<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecryptingReader")
+            .field("chunk_size", &self.chunk_size)
+            .field("counter", &self.counter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_roundtrip() {
+        let key = EncryptionKey::from_bytes([7; 32]);
+        let plaintext: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+
+        let seal = |data: &[u8]| {
+            let mut writer = EncryptingWriter::with_chunk_size(Vec::new(), &key, 256).unwrap();
+            writer.write_all(data).unwrap();
+            writer.finish().unwrap()
+        };
+        let open = |container: &[u8], key: &EncryptionKey| {
+            let mut data = Vec::new();
+            DecryptingReader::new(container, key)?.read_to_end(&mut data)?;
+            io::Result::Ok(data)
+        };
+
+        let container = seal(&plaintext);
+        // Header, 3 full chunks and a last one of 232 bytes
+        assert_eq!(container.len(), 25 + 3 * (4 + 256 + 16) + 4 + 232 + 16);
+        assert_eq!(open(&container, &key).unwrap(), plaintext);
+        assert_eq!(open(&seal(&[]), &key).unwrap(), []);
+
+        let wrong = EncryptionKey::from_bytes([8; 32]);
+        let truncated = &container[..25 + 4 + 256 + 16];
+        let mut extended = container.clone();
+        extended.push(0);
+        let mut tampered = container.clone();
+        tampered[30] ^= 1;
+        for (container, key) in [
+            (&container[..], &wrong),
+            (truncated, &key),
+            (&extended[..], &key),
+            (&tampered[..], &key),
+        ] {
+            let err = open(container, key).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Suricata EVE JSON output.
+//!
+//! An [`EveWriter`] writes flow records and alerts as EVE events, one JSON
+//! object per line with the fields of Suricata's `flow` and `alert` event
+//! types, so netkit probes feed SIEM pipelines built for Suricata unchanged.
+//! As an [`AlertSink`], it takes the alerts of any detector:
+//!
+//! ```
+//! use netkit::analysis::alert::sink::forward;
+//! use netkit::analysis::beacon::BeaconDetector;
+//! use netkit::export::{eve::EveWriter, ipfix::BiflowMeter};
+//!
+//! let (meter, mut detector) = (BiflowMeter::new(), BeaconDetector::new());
+//! // Feed packets, then:
+//! let mut eve = EveWriter::new(Vec::new());
+//! eve.host("probe-1");
+//! for record in meter.records() {
+//!     eve.write_flow(&record).unwrap();
+//! }
+//! forward(&mut detector, &mut eve).unwrap();
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::{
+    bundle::{json_string, rfc3339},
+    ipfix::BiflowRecord,
+};
+use crate::analysis::alert::{sink::AlertSink, Alert};
+
+/// First signature ID of alerts, in the range of local rules.
+///
+/// Each detector gets a stable ID above it, derived from its name.
+pub const SIGNATURE_ID_BASE: u32 = 1_000_000;
+
+/// Get the flow ID of a record, as used in EVE events and Zeek UIDs.
+///
+/// The ID is a hash of the key and start time, stable across runs, and
+/// below 2^51 like Suricata's, so JSON parsers read it exactly.
+pub fn flow_id(record: &BiflowRecord) -> u64 {
+    let key = record.key.canonical();
+    let mut bytes = Vec::with_capacity(48);
+    for addr in [key.src, key.dst] {
+        match addr {
+            IpAddr::V4(addr) => bytes.extend_from_slice(&addr.octets()),
+            IpAddr::V6(addr) => bytes.extend_from_slice(&addr.octets()),
+        }
+    }
+    bytes.extend_from_slice(&key.src_port.to_be_bytes());
+    bytes.extend_from_slice(&key.dst_port.to_be_bytes());
+    bytes.push(key.protocol.into());
+    bytes.extend_from_slice(&record.start.as_nanos().to_be_bytes());
+    fnv1a(&bytes) & ((1 << 51) - 1)
+}
+
+/// 64-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Format a timestamp as Suricata does, with a numeric UTC offset.
+fn timestamp(ts: Duration) -> String {
+    let mut timestamp = rfc3339(ts);
+    timestamp.pop();
+    timestamp.push_str("+0000");
+    timestamp
+}
+
+/// Get the name of a protocol in EVE events.
+fn proto(protocol: IpProtocol) -> String {
+    match protocol {
+        IpProtocol::Tcp => "TCP".to_string(),
+        IpProtocol::Udp => "UDP".to_string(),
+        IpProtocol::Icmp => "ICMP".to_string(),
+        IpProtocol::Ipv6Icmp => "IPv6-ICMP".to_string(),
+        IpProtocol::Sctp => "SCTP".to_string(),
+        protocol => u8::from(protocol).to_string(),
+    }
+}
+
+/// Writer of Suricata EVE JSON events.
+#[derive(Debug)]
+pub struct EveWriter<W: Write> {
+    writer: W,
+    host: Option<String>,
+    events: u64,
+}
+
+impl<W: Write> EveWriter<W> {
+    /// Write events to a writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            host: None,
+            events: 0,
+        }
+    }
+
+    /// Set the sensor name, written as the `host` of events.
+    pub fn host(&mut self, host: impl Into<String>) -> &mut Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Get the number of events written.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Get the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write a `flow` event, timestamped at the end of the flow.
+    pub fn write_flow(&mut self, record: &BiflowRecord) -> io::Result<()> {
+        let key = &record.key;
+        let mut event = self.header(record.end, Some(flow_id(record)), "flow");
+        let _ = write!(event, ",\"src_ip\":\"{}\"", key.src);
+        if has_ports(key.protocol) {
+            let _ = write!(event, ",\"src_port\":{}", key.src_port);
+        }
+        let _ = write!(event, ",\"dest_ip\":\"{}\"", key.dst);
+        if has_ports(key.protocol) {
+            let _ = write!(event, ",\"dest_port\":{}", key.dst_port);
+        }
+        let _ = write!(
+            event,
+            ",\"proto\":\"{}\",\"flow\":{{\"pkts_toserver\":{},\"pkts_toclient\":{},\
+             \"bytes_toserver\":{},\"bytes_toclient\":{},\"start\":\"{}\",\"end\":\"{}\",\
+             \"age\":{},\"state\":\"closed\",\"reason\":\"timeout\",\"alerted\":false}}}}",
+            proto(key.protocol),
+            record.packets,
+            record.reverse_packets,
+            record.bytes,
+            record.reverse_bytes,
+            timestamp(record.start),
+            timestamp(record.end),
+            record.end.saturating_sub(record.start).as_secs(),
+        );
+        self.write_event(&event)
+    }
+
+    /// Write an `alert` event.
+    ///
+    /// The detector is the category of the alert, and the severity follows
+    /// the score: 1 (high) from 0.9, 2 from 0.5, 3 below. The packet of a
+    /// numbered alert is in `pcap_cnt`, counted from 1 like Suricata's, and
+    /// `packet_id`.
+    pub fn write_alert(&mut self, alert: &Alert) -> io::Result<()> {
+        let severity = match alert.score {
+            s if s >= 0.9 => 1,
+            s if s >= 0.5 => 2,
+            _ => 3,
+        };
+        let signature_id =
+            SIGNATURE_ID_BASE + (fnv1a(alert.detector.as_bytes()) % 1_000_000) as u32;
+        let mut event = self.header(alert.ts, None, "alert");
+        if let Some(packet) = alert.packet {
+            let _ = write!(
+                event,
+                ",\"pcap_cnt\":{},\"packet_id\":\"{packet}\"",
+                packet.ordinal + 1
+            );
+        }
+        let _ = write!(
+            event,
+            ",\"src_ip\":\"{}\",\"dest_ip\":\"{}\",\"alert\":{{\"action\":\"allowed\",\"gid\":1,\
+             \"signature_id\":{signature_id},\"rev\":1,\"signature\":{},\"category\":{},\
+             \"severity\":{severity},\"metadata\":{{\"subject\":[{}],\"score\":[\"{:.2}\"]}}}}}}",
+            alert.src,
+            alert.dst,
+            json_string(&alert.message),
+            json_string(alert.detector),
+            json_string(&alert.subject),
+            alert.score,
+        );
+        self.write_event(&event)
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Start an event with the fields common to all types.
+    fn header(&self, ts: Duration, flow_id: Option<u64>, event_type: &str) -> String {
+        let mut event = format!("{{\"timestamp\":\"{}\"", timestamp(ts));
+        if let Some(flow_id) = flow_id {
+            let _ = write!(event, ",\"flow_id\":{flow_id}");
+        }
+        let _ = write!(event, ",\"event_type\":\"{event_type}\"");
+        if let Some(host) = &self.host {
+            let _ = write!(event, ",\"host\":{}", json_string(host));
+        }
+        event
+    }
+
+    fn write_event(&mut self, event: &str) -> io::Result<()> {
+        writeln!(self.writer, "{event}")?;
+        self.events += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> AlertSink for EveWriter<W> {
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        self.write_alert(alert)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Whether flows of a protocol are keyed by ports.
+pub(crate) fn has_ports(protocol: IpProtocol) -> bool {
+    matches!(
+        protocol,
+        IpProtocol::Tcp
+            | IpProtocol::Udp
+            | IpProtocol::Sctp
+            | IpProtocol::UdpLite
+            | IpProtocol::Dccp
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::utils::id::{CaptureId, PacketId};
+
+    use super::*;
+
+    #[test]
+    fn eve_events() {
+        let record = BiflowRecord {
+            key: FlowKey::new(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                40000,
+                443,
+                IpProtocol::Tcp,
+            ),
+            start: Duration::from_secs(1_700_000_000),
+            end: Duration::from_millis(1_700_000_002_500),
+            packets: 3,
+            bytes: 180,
+            reverse_packets: 2,
+            reverse_bytes: 1200,
+        };
+        let id = flow_id(&record);
+        let reversed = BiflowRecord {
+            key: record.key.reversed(),
+            ..record
+        };
+        assert_eq!(flow_id(&reversed), id);
+        assert!(id < 1 << 51);
+
+        let mut eve = EveWriter::new(Vec::new());
+        eve.host("probe-1");
+        eve.write_flow(&record).unwrap();
+        eve.send(&Alert {
+            ts: Duration::from_secs(1_700_000_001),
+            detector: "beacon",
+            score: 0.95,
+            src: record.key.src,
+            dst: record.key.dst,
+            subject: "443/tcp".to_string(),
+            message: "regular beacons".to_string(),
+            packet: Some(PacketId::new(CaptureId([0; 16]), 6)),
+        })
+        .unwrap();
+        assert_eq!(eve.events(), 2);
+
+        let output = String::from_utf8(eve.into_inner()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!(
+                "{{\"timestamp\":\"2023-11-14T22:13:22.500000+0000\",\"flow_id\":{id},\
+                 \"event_type\":\"flow\",\"host\":\"probe-1\",\"src_ip\":\"10.0.0.1\",\
+                 \"src_port\":40000,\"dest_ip\":\"192.0.2.1\",\"dest_port\":443,\
+                 \"proto\":\"TCP\",\"flow\":{{\"pkts_toserver\":3,\"pkts_toclient\":2,\
+                 \"bytes_toserver\":180,\"bytes_toclient\":1200,\
+                 \"start\":\"2023-11-14T22:13:20.000000+0000\",\
+                 \"end\":\"2023-11-14T22:13:22.500000+0000\",\"age\":2,\"state\":\"closed\",\
+                 \"reason\":\"timeout\",\"alerted\":false}}}}"
+            )
+        );
+        assert!(lines[1].starts_with(
+            "{\"timestamp\":\"2023-11-14T22:13:21.000000+0000\",\"event_type\":\"alert\""
+        ));
+        assert!(lines[1]
+            .contains("\"pcap_cnt\":7,\"packet_id\":\"00000000-0000-0000-0000-000000000000:6\""));
+        assert!(lines[1]
+            .contains("\"signature\":\"regular beacons\",\"category\":\"beacon\",\"severity\":1"));
+    }
+}
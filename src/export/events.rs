@@ -0,0 +1,350 @@
+//! Join of reports with external event logs.
+//!
+//! Most reports have to answer what the network was doing when something
+//! else happened: a deploy, a failover, a line in syslog. An [`EventLog`]
+//! holds such timestamped events and joins them with the time columns of a
+//! table, e.g. listing the events during each flow, or counts the traffic
+//! around each event with [`EventWindows`].
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufReader, time::Duration};
+//!
+//! use netkit::analysis::tcp::TcpFlowAnalyzer;
+//! use netkit::export::{events::EventLog, ToTable};
+//!
+//! let flows = TcpFlowAnalyzer::new();
+//! let mut log = EventLog::read(BufReader::new(File::open("deploys.log").unwrap())).unwrap();
+//! log.window(Duration::from_secs(5), Duration::from_secs(60));
+//!
+//! let mut table = flows.to_table();
+//! log.annotate(&mut table, "first_seen", Some("duration"), "events");
+//! table.write_csv(&mut std::io::stdout()).unwrap();
+//! ```
+
+use std::{
+    io::{self, BufRead},
+    ops::Range,
+    time::Duration,
+};
+
+use crate::analysis::Analyzer;
+
+use super::{Column, Table, ToTable};
+
+/// Separator of the labels of several events in a column.
+pub const LABEL_SEPARATOR: &str = "; ";
+
+/// Timestamped external event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Time of the event, since the epoch.
+    pub ts: Duration,
+    /// Label of the event, e.g. a syslog line.
+    pub label: String,
+}
+
+impl Event {
+    /// Create an event.
+    pub fn new(ts: Duration, label: impl Into<String>) -> Self {
+        Self {
+            ts,
+            label: label.into(),
+        }
+    }
+}
+
+/// Timeline of external events.
+///
+/// An event spans a window from `before` it to `after` it, both zero by
+/// default, and is joined with the times and intervals its window overlaps.
+#[derive(Clone, Debug, Default)]
+pub struct EventLog {
+    events: Vec<Event>,
+    before: Duration,
+    after: Duration,
+}
+
+impl EventLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a log with one `<timestamp> <label>` event per line.
+    ///
+    /// Timestamps are either seconds since the epoch, e.g.
+    /// `1700000000.25`, or RFC 3339 dates and times, e.g.
+    /// `2023-11-14T22:13:20.25Z`. Empty lines and lines starting with `#`
+    /// are skipped.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut log = Self::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (ts, label) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let ts = parse_timestamp(ts).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid timestamp on line {}: {ts}", number + 1),
+                )
+            })?;
+            log.push(Event::new(ts, label.trim_start()));
+        }
+        Ok(log)
+    }
+
+    /// Add an event.
+    pub fn push(&mut self, event: Event) -> &mut Self {
+        let index = self.events.partition_point(|e| e.ts <= event.ts);
+        self.events.insert(index, event);
+        self
+    }
+
+    /// Set how long before and after an event it is joined with.
+    pub fn window(&mut self, before: Duration, after: Duration) -> &mut Self {
+        self.before = before;
+        self.after = after;
+        self
+    }
+
+    /// Get the events, in time order.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Get the events whose window overlaps an interval, in time order.
+    pub fn overlapping(&self, start: Duration, end: Duration) -> &[Event] {
+        &self.events[self.range(start, end)]
+    }
+
+    fn range(&self, start: Duration, end: Duration) -> Range<usize> {
+        let from = self.events.partition_point(|e| e.ts + self.after < start);
+        let to = self.events.partition_point(|e| e.ts <= end + self.before);
+        from..to.max(from)
+    }
+
+    /// Add a column of the labels of the events during each row of a table.
+    ///
+    /// Rows span from the time of `start_column` for the time of
+    /// `duration_column`, or are instants without it; both hold seconds.
+    /// Labels are separated by [`LABEL_SEPARATOR`]. Rows with a missing or
+    /// invalid time get an empty value; nothing is added if `start_column`
+    /// is not a column of times.
+    pub fn annotate(
+        &self,
+        table: &mut Table,
+        start_column: &str,
+        duration_column: Option<&str>,
+        column: &str,
+    ) {
+        let Some(starts) = table.column(start_column).and_then(seconds) else {
+            return;
+        };
+        let durations = duration_column
+            .and_then(|name| table.column(name))
+            .and_then(seconds)
+            .unwrap_or_else(|| vec![Some(Duration::ZERO); starts.len()]);
+
+        let labels = starts
+            .iter()
+            .zip(durations)
+            .map(|(start, duration)| match (start, duration) {
+                (Some(start), Some(duration)) => self
+                    .overlapping(*start, *start + duration)
+                    .iter()
+                    .map(|e| e.label.as_str())
+                    .collect::<Vec<_>>()
+                    .join(LABEL_SEPARATOR),
+                _ => String::new(),
+            })
+            .collect();
+        table.push(column, Column::Str(labels));
+    }
+
+    /// Create an analyzer counting the traffic in the window of each event.
+    pub fn windows(&self) -> EventWindows {
+        EventWindows {
+            counts: vec![(0, 0); self.events.len()],
+            log: self.clone(),
+        }
+    }
+}
+
+/// Times of a column of seconds, `None` where invalid.
+fn seconds(column: &Column) -> Option<Vec<Option<Duration>>> {
+    match column {
+        Column::U64(values) => Some(
+            values
+                .iter()
+                .map(|s| Some(Duration::from_secs(*s)))
+                .collect(),
+        ),
+        Column::F64(values) => Some(
+            values
+                .iter()
+                .map(|s| Duration::try_from_secs_f64(*s).ok())
+                .collect(),
+        ),
+        Column::Str(_) => None,
+    }
+}
+
+/// Parse seconds since the epoch or an RFC 3339 date and time.
+fn parse_timestamp(ts: &str) -> Option<Duration> {
+    if !ts.contains('-') {
+        return ts
+            .parse()
+            .ok()
+            .and_then(|s| Duration::try_from_secs_f64(s).ok());
+    }
+
+    let (date, time) = ts.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let at = time.rfind(['+', '-'])?;
+        let (hours, minutes) = time[at + 1..].split_once(':')?;
+        let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        let sign = if time[at..].starts_with('-') { -1 } else { 1 };
+        (&time[..at], sign * offset)
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    // Days since the epoch from a civil date, after Howard Hinnant.
+    let y = year - (month <= 2) as i64;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let nanos = match fraction {
+        "" => 0,
+        _ => format!("{fraction:0<9}").get(..9)?.parse().ok()?,
+    };
+    Some(Duration::new(u64::try_from(secs).ok()?, nanos))
+}
+
+/// Traffic counter in the windows of events
+///
+/// Counts the packets and bytes captured in the window of each event of an
+/// [`EventLog`], created with [`EventLog::windows`].
+#[derive(Clone, Debug)]
+pub struct EventWindows {
+    log: EventLog,
+    counts: Vec<(u64, u64)>,
+}
+
+impl EventWindows {
+    /// Get the events with the packets and bytes in their window.
+    pub fn counts(&self) -> impl Iterator<Item = (&Event, u64, u64)> {
+        self.log
+            .events
+            .iter()
+            .zip(&self.counts)
+            .map(|(event, (packets, bytes))| (event, *packets, *bytes))
+    }
+}
+
+impl Analyzer for EventWindows {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        for count in &mut self.counts[self.log.range(ts, ts)] {
+            count.0 += 1;
+            count.1 += frame.len() as u64;
+        }
+    }
+}
+
+impl ToTable for EventWindows {
+    /// Convert into a table with one row per event, in time order.
+    fn to_table(&self) -> Table {
+        let events = &self.log.events;
+        let mut table = Table::new();
+        table
+            .push(
+                "ts",
+                Column::F64(events.iter().map(|e| e.ts.as_secs_f64()).collect()),
+            )
+            .push(
+                "event",
+                Column::Str(events.iter().map(|e| e.label.clone()).collect()),
+            )
+            .push(
+                "packets",
+                Column::U64(self.counts.iter().map(|c| c.0).collect()),
+            )
+            .push(
+                "bytes",
+                Column::U64(self.counts.iter().map(|c| c.1).collect()),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_log_join() {
+        let log = "\
+# deploys
+1700000010 deploy api v2
+2023-11-14T22:13:50.5Z failover db
+2023-11-15T00:13:40+02:00 rollback api
+";
+        let mut log = EventLog::read(log.as_bytes()).unwrap();
+        let secs = |s: f64| Duration::from_secs_f64(1_700_000_000.0 + s);
+        let ts: Vec<_> = log.events().iter().map(|e| e.ts).collect();
+        assert_eq!(ts, [secs(10.0), secs(20.0), secs(30.5)]);
+        assert_eq!(log.events()[2].label, "failover db");
+        assert!(EventLog::read("yesterday reboot".as_bytes()).is_err());
+
+        let mut table = Table::new();
+        table
+            .push(
+                "first_seen",
+                Column::F64(vec![1_700_000_005.0, 1_700_000_025.0, f64::NAN]),
+            )
+            .push("duration", Column::F64(vec![20.0, 1.0, 0.0]));
+        log.annotate(&mut table, "first_seen", Some("duration"), "events");
+        log.window(Duration::ZERO, Duration::from_secs(5));
+        log.annotate(&mut table, "first_seen", None, "after");
+        assert_eq!(
+            table.column("events"),
+            Some(&Column::Str(vec![
+                "deploy api v2; rollback api".into(),
+                String::new(),
+                String::new()
+            ]))
+        );
+        assert_eq!(
+            table.column("after"),
+            Some(&Column::Str(vec![
+                String::new(),
+                "rollback api".into(),
+                String::new()
+            ]))
+        );
+
+        let mut windows = log.windows();
+        for s in [9.0, 12.0, 16.0, 31.0] {
+            windows.on_packet(secs(s), &[0; 100]);
+        }
+        let counts: Vec<_> = windows.counts().map(|(_, p, b)| (p, b)).collect();
+        assert_eq!(counts, [(1, 100), (0, 0), (1, 100)]);
+        assert_eq!(windows.to_table().len(), 3);
+    }
+}
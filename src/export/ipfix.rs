@@ -0,0 +1,519 @@
+//! IPFIX export of bidirectional flows.
+//!
+//! A [`BiflowMeter`] aggregates packets into bidirectional flow records, and
+//! an [`IpfixExporter`] encodes them as IPFIX messages ([RFC 7011]) ready to
+//! be sent to a collector, one message per UDP datagram.
+//!
+//! Records are biflows ([RFC 5103]): the forward counters are those of the
+//! initiator, i.e. the direction of the first packet, and the reverse
+//! counters use the reverse information elements (enterprise number
+//! [`REVERSE_PEN`]), so collectors see one record per conversation rather
+//! than two unidirectional halves.
+//!
+//! Over UDP, a collector which restarted or joined late only learns the
+//! templates when they are sent again. The exporter resends its templates,
+//! and an options template describing the exporting process, every
+//! [`template_refresh`](IpfixExporter::template_refresh) messages.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit::export::ipfix::{BiflowMeter, IpfixExporter};
+//!
+//! let meter = BiflowMeter::new();
+//! // Feed packets, then:
+//! let mut exporter = IpfixExporter::new(1);
+//! for message in exporter.export(Duration::from_secs(1_700_000_000), &meter.records()) {
+//!     assert_eq!(&message[..2], &[0, 10]);
+//! }
+//! ```
+//!
+//! [RFC 7011]: https://datatracker.ietf.org/doc/html/rfc7011
+//! [RFC 5103]: https://datatracker.ietf.org/doc/html/rfc5103
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use crate::analysis::Analyzer;
+
+/// Version of IPFIX messages.
+pub const IPFIX_VERSION: u16 = 10;
+
+/// Private enterprise number of the reverse information elements.
+pub const REVERSE_PEN: u32 = 29305;
+
+/// Set ID of templates.
+pub const TEMPLATE_SET: u16 = 2;
+
+/// Set ID of options templates.
+pub const OPTIONS_TEMPLATE_SET: u16 = 3;
+
+/// Template ID of IPv4 biflow records.
+pub const TEMPLATE_IPV4: u16 = 256;
+
+/// Template ID of IPv6 biflow records.
+pub const TEMPLATE_IPV6: u16 = 257;
+
+/// Template ID of the exporting process options.
+pub const TEMPLATE_OPTIONS: u16 = 258;
+
+/// Default number of messages between two template refreshes.
+pub const DEFAULT_TEMPLATE_REFRESH: u32 = 20;
+
+/// Default maximum length of a message, fitting an Ethernet MTU.
+pub const DEFAULT_MAX_MESSAGE: usize = 1400;
+
+/// Length of the message header.
+const HEADER_LENGTH: usize = 16;
+
+/// Value of `biflowDirection` for records in initiator order.
+const BIFLOW_INITIATOR: u8 = 1;
+
+/// Field specifier: information element, length and enterprise number.
+type Field = (u16, u16, Option<u32>);
+
+/// Fields of the biflow templates after the addresses.
+const BIFLOW_FIELDS: [Field; 10] = [
+    (7, 2, None),              // sourceTransportPort
+    (11, 2, None),             // destinationTransportPort
+    (4, 1, None),              // protocolIdentifier
+    (152, 8, None),            // flowStartMilliseconds
+    (153, 8, None),            // flowEndMilliseconds
+    (1, 8, None),              // octetDeltaCount
+    (2, 8, None),              // packetDeltaCount
+    (1, 8, Some(REVERSE_PEN)), // reverseOctetDeltaCount
+    (2, 8, Some(REVERSE_PEN)), // reversePacketDeltaCount
+    (239, 1, None),            // biflowDirection
+];
+
+/// Bidirectional flow record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BiflowRecord {
+    /// Key in the direction of the initiator.
+    pub key: FlowKey,
+    /// Timestamp of the first packet.
+    pub start: Duration,
+    /// Timestamp of the last packet.
+    pub end: Duration,
+    /// Number of packets from the initiator.
+    pub packets: u64,
+    /// Number of bytes from the initiator.
+    pub bytes: u64,
+    /// Number of packets towards the initiator.
+    pub reverse_packets: u64,
+    /// Number of bytes towards the initiator.
+    pub reverse_bytes: u64,
+}
+
+/// Biflow meter
+///
+/// Aggregates IPv4 and IPv6 packets into [`BiflowRecord`]s, counting the
+/// bytes of the IP packets.
+#[derive(Clone, Debug, Default)]
+pub struct BiflowMeter {
+    flows: HashMap<FlowKey, BiflowRecord>,
+}
+
+impl BiflowMeter {
+    /// Create a new meter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the records, ordered by start time.
+    pub fn records(&self) -> Vec<BiflowRecord> {
+        let mut records: Vec<_> = self.flows.values().copied().collect();
+        records.sort_by_key(|r| r.start);
+        records
+    }
+
+    /// Remove and return the records of the flows idle since `before`, e.g.
+    /// to export them periodically.
+    pub fn drain_idle(&mut self, before: Duration) -> Vec<BiflowRecord> {
+        let mut records = Vec::new();
+        self.flows.retain(|_, r| {
+            let idle = r.end < before;
+            if idle {
+                records.push(*r);
+            }
+            !idle
+        });
+        records.sort_by_key(|r| r.start);
+        records
+    }
+}
+
+impl Analyzer for BiflowMeter {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let (key, len) = if let Some(ipv4) = eth.ipv4() {
+            (FlowKey::from_ipv4(&ipv4), ipv4.total_length().get() as u64)
+        } else if let Some(ipv6) = eth.ipv6() {
+            (
+                FlowKey::from_ipv6(&ipv6),
+                40 + ipv6.payload_length().get() as u64,
+            )
+        } else {
+            return;
+        };
+
+        let record = self.flows.entry(key.canonical()).or_insert(BiflowRecord {
+            key,
+            start: ts,
+            end: ts,
+            packets: 0,
+            bytes: 0,
+            reverse_packets: 0,
+            reverse_bytes: 0,
+        });
+        record.end = record.end.max(ts);
+        if record.key == key {
+            record.packets += 1;
+            record.bytes += len;
+        } else {
+            record.reverse_packets += 1;
+            record.reverse_bytes += len;
+        }
+    }
+}
+
+/// IPFIX exporter
+///
+/// Encodes biflow records into messages, keeping the sequence number and
+/// the template refresh schedule across calls.
+#[derive(Clone, Debug)]
+pub struct IpfixExporter {
+    observation_domain: u32,
+    process_id: u32,
+    exporter: Option<IpAddr>,
+    template_refresh: u32,
+    max_message: usize,
+    sequence: u32,
+    since_templates: Option<u32>,
+}
+
+impl IpfixExporter {
+    /// Create an exporter for an observation domain.
+    pub fn new(observation_domain: u32) -> Self {
+        Self {
+            observation_domain,
+            process_id: std::process::id(),
+            exporter: None,
+            template_refresh: DEFAULT_TEMPLATE_REFRESH,
+            max_message: DEFAULT_MAX_MESSAGE,
+            sequence: 0,
+            since_templates: None,
+        }
+    }
+
+    /// Set the ID of the exporting process, the process ID by default.
+    pub fn process_id(&mut self, process_id: u32) -> &mut Self {
+        self.process_id = process_id;
+        self
+    }
+
+    /// Set the address of the exporter reported in the options.
+    pub fn exporter(&mut self, exporter: IpAddr) -> &mut Self {
+        self.exporter = Some(exporter);
+        self.since_templates = None;
+        self
+    }
+
+    /// Set the number of messages between two template refreshes.
+    pub fn template_refresh(&mut self, messages: u32) -> &mut Self {
+        self.template_refresh = messages.max(1);
+        self
+    }
+
+    /// Set the maximum length of a message, at least 512 bytes so the
+    /// templates and a record fit.
+    pub fn max_message(&mut self, len: usize) -> &mut Self {
+        self.max_message = len.clamp(512, u16::MAX as usize);
+        self
+    }
+
+    /// Send the templates with the next message, e.g. after the collector
+    /// restarted.
+    pub fn reset_templates(&mut self) {
+        self.since_templates = None;
+    }
+
+    /// Get the sequence number, i.e. the number of data records exported.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Encode records into messages exported at `export_time` (since the
+    /// epoch).
+    ///
+    /// Templates and exporter options come first when due. At least one
+    /// message is returned, so calling this with no records refreshes the
+    /// templates on schedule.
+    pub fn export(&mut self, export_time: Duration, records: &[BiflowRecord]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        let mut records = records.iter().peekable();
+        loop {
+            let mut message = self.header();
+            if self
+                .since_templates
+                .is_none_or(|since| since >= self.template_refresh)
+            {
+                self.write_templates(&mut message);
+                self.since_templates = Some(0);
+            }
+
+            let mut count = 0;
+            while let Some(record) = records.peek() {
+                let template = template_of(record);
+                if count > 0 && message.len() + 4 + record_length(template) > self.max_message {
+                    break;
+                }
+                let mut set = set_header(template);
+                while let Some(record) = records.next_if(|r| template_of(r) == template) {
+                    write_record(&mut set, record);
+                    count += 1;
+                    if message.len() + set.len() + record_length(template) > self.max_message {
+                        break;
+                    }
+                }
+                close_set(&mut message, set);
+            }
+
+            self.finish(&mut message, export_time);
+            self.sequence = self.sequence.wrapping_add(count);
+            *self.since_templates.get_or_insert(0) += 1;
+            messages.push(message);
+            if records.peek().is_none() {
+                return messages;
+            }
+        }
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(self.max_message);
+        message.extend(IPFIX_VERSION.to_be_bytes());
+        message.resize(HEADER_LENGTH, 0);
+        message
+    }
+
+    fn finish(&self, message: &mut [u8], export_time: Duration) {
+        let len = message.len() as u16;
+        message[2..4].copy_from_slice(&len.to_be_bytes());
+        message[4..8].copy_from_slice(&(export_time.as_secs() as u32).to_be_bytes());
+        message[8..12].copy_from_slice(&self.sequence.to_be_bytes());
+        message[12..16].copy_from_slice(&self.observation_domain.to_be_bytes());
+    }
+
+    fn write_templates(&self, message: &mut Vec<u8>) {
+        let mut set = set_header(TEMPLATE_SET);
+        for (template, addr_field, addr_len) in [(TEMPLATE_IPV4, 8, 4), (TEMPLATE_IPV6, 27, 16)] {
+            let fields: Vec<Field> = [
+                (addr_field, addr_len, None),
+                (addr_field + 4, addr_len, None),
+            ]
+            .into_iter()
+            .chain(BIFLOW_FIELDS)
+            .collect();
+            set.extend(template.to_be_bytes());
+            set.extend((fields.len() as u16).to_be_bytes());
+            write_fields(&mut set, &fields);
+        }
+        close_set(message, set);
+
+        let fields = self.options_fields();
+        let mut set = set_header(OPTIONS_TEMPLATE_SET);
+        set.extend(TEMPLATE_OPTIONS.to_be_bytes());
+        set.extend((fields.len() as u16).to_be_bytes());
+        // The observation domain is the scope.
+        set.extend(1u16.to_be_bytes());
+        write_fields(&mut set, &fields);
+        close_set(message, set);
+
+        let mut set = set_header(TEMPLATE_OPTIONS);
+        set.extend(self.observation_domain.to_be_bytes());
+        set.extend(self.process_id.to_be_bytes());
+        match self.exporter {
+            Some(IpAddr::V4(addr)) => set.extend(addr.octets()),
+            Some(IpAddr::V6(addr)) => set.extend(addr.octets()),
+            None => {}
+        }
+        close_set(message, set);
+    }
+
+    fn options_fields(&self) -> Vec<Field> {
+        let mut fields = vec![
+            (149, 4, None), // observationDomainId
+            (144, 4, None), // exportingProcessId
+        ];
+        match self.exporter {
+            Some(IpAddr::V4(_)) => fields.push((130, 4, None)), // exporterIPv4Address
+            Some(IpAddr::V6(_)) => fields.push((131, 16, None)), // exporterIPv6Address
+            None => {}
+        }
+        fields
+    }
+}
+
+fn template_of(record: &BiflowRecord) -> u16 {
+    match record.key.src {
+        IpAddr::V4(_) => TEMPLATE_IPV4,
+        IpAddr::V6(_) => TEMPLATE_IPV6,
+    }
+}
+
+fn record_length(template: u16) -> usize {
+    let addrs = if template == TEMPLATE_IPV4 { 8 } else { 32 };
+    addrs + BIFLOW_FIELDS.iter().map(|f| f.1 as usize).sum::<usize>()
+}
+
+fn set_header(id: u16) -> Vec<u8> {
+    let mut set = id.to_be_bytes().to_vec();
+    set.extend([0, 0]);
+    set
+}
+
+fn close_set(message: &mut Vec<u8>, mut set: Vec<u8>) {
+    let len = set.len() as u16;
+    set[2..4].copy_from_slice(&len.to_be_bytes());
+    message.extend(set);
+}
+
+fn write_fields(set: &mut Vec<u8>, fields: &[Field]) {
+    for (id, len, pen) in fields {
+        match pen {
+            Some(pen) => {
+                set.extend((id | 0x8000).to_be_bytes());
+                set.extend(len.to_be_bytes());
+                set.extend(pen.to_be_bytes());
+            }
+            None => {
+                set.extend(id.to_be_bytes());
+                set.extend(len.to_be_bytes());
+            }
+        }
+    }
+}
+
+fn write_record(set: &mut Vec<u8>, record: &BiflowRecord) {
+    let key = &record.key;
+    match (key.src, key.dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            set.extend(src.octets());
+            set.extend(dst.octets());
+        }
+        (src, dst) => {
+            let v6 = |addr| match addr {
+                IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+                IpAddr::V6(addr) => addr,
+            };
+            let (src, dst): (Ipv6Addr, Ipv6Addr) = (v6(src), v6(dst));
+            set.extend(src.octets());
+            set.extend(dst.octets());
+        }
+    }
+    set.extend(key.src_port.to_be_bytes());
+    set.extend(key.dst_port.to_be_bytes());
+    set.push(key.protocol.into());
+    set.extend((record.start.as_millis() as u64).to_be_bytes());
+    set.extend((record.end.as_millis() as u64).to_be_bytes());
+    set.extend(record.bytes.to_be_bytes());
+    set.extend(record.packets.to_be_bytes());
+    set.extend(record.reverse_bytes.to_be_bytes());
+    set.extend(record.reverse_packets.to_be_bytes());
+    set.push(BIFLOW_INITIATOR);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    /// Get the IDs and lengths of the sets of a message.
+    fn sets(message: &[u8]) -> Vec<(u16, usize)> {
+        let mut sets = Vec::new();
+        let mut rest = &message[HEADER_LENGTH..];
+        while !rest.is_empty() {
+            let id = u16::from_be_bytes([rest[0], rest[1]]);
+            let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            sets.push((id, len));
+            rest = &rest[len..];
+        }
+        sets
+    }
+
+    #[test]
+    fn ipfix_biflow_export() {
+        let (client, server) = (Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1));
+        let mut meter = BiflowMeter::new();
+        for (i, (src, dst, sport, dport)) in [
+            (client, server, 40000u16, 80u16),
+            (server, client, 80, 40000),
+            (server, client, 80, 40000),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let udp = udp!(src_port: sport, dst_port: dport, payload: [0; 12]);
+            let ipv4 = ipv4!(src: src, dst: dst, protocol: IpProtocol::Udp, payload: udp.inner());
+            let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+            meter.on_packet(Duration::from_secs(10 + i as u64), eth.inner());
+        }
+        let records = meter.records();
+        assert_eq!(records.len(), 1);
+        // The client initiated the flow, even though its address is higher.
+        assert_eq!(records[0].key.src, client);
+        assert_eq!((records[0].packets, records[0].bytes), (1, 40));
+        assert_eq!(
+            (records[0].reverse_packets, records[0].reverse_bytes),
+            (2, 80)
+        );
+
+        let mut exporter = IpfixExporter::new(7);
+        exporter
+            .exporter(Ipv4Addr::new(192, 0, 2, 1).into())
+            .template_refresh(2);
+        let ts = Duration::from_secs(1_700_000_000);
+        let message = &exporter.export(ts, &records)[0];
+        assert_eq!(&message[..2], &[0, 10]);
+        assert_eq!(
+            u16::from_be_bytes([message[2], message[3]]) as usize,
+            message.len()
+        );
+        assert_eq!(&message[12..16], &7u32.to_be_bytes());
+        let ids: Vec<_> = sets(message).iter().map(|s| s.0).collect();
+        assert_eq!(
+            ids,
+            [
+                TEMPLATE_SET,
+                OPTIONS_TEMPLATE_SET,
+                TEMPLATE_OPTIONS,
+                TEMPLATE_IPV4
+            ]
+        );
+        assert_eq!(sets(message)[3].1, 4 + record_length(TEMPLATE_IPV4));
+        // Reverse counters use the enterprise bit and the RFC 5103 number.
+        let reverse = [0x80, 0x01, 0, 8, 0, 0, 0x72, 0x79];
+        assert!(message.windows(8).any(|w| w == reverse));
+
+        // Templates are refreshed every two messages.
+        let message = &exporter.export(ts, &records)[0];
+        assert_eq!(sets(message).len(), 1);
+        assert_eq!(&message[8..12], &1u32.to_be_bytes());
+        assert_eq!(sets(&exporter.export(ts, &[])[0]).len(), 3);
+
+        // Large exports are split into messages.
+        let many = vec![records[0]; 100];
+        let messages = exporter.export(ts, &many);
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|m| m.len() <= DEFAULT_MAX_MESSAGE));
+        assert_eq!(exporter.sequence(), 102);
+    }
+}
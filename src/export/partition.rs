@@ -0,0 +1,389 @@
+//! Flow-partitioned packet records for distributed processing.
+//!
+//! Downstream jobs (Spark, ClickHouse, ...) scale by processing shards in
+//! parallel, which only works if every packet of a flow lands in the same
+//! shard. A [`PartitionExporter`] writes one CSV record per packet and
+//! shards them by the hash of the canonical flow key, so both directions of
+//! a conversation stay together:
+//!
+//! | Column      | Content                                   |
+//! |-------------|-------------------------------------------|
+//! | `ts`        | Capture timestamp, seconds since the epoch |
+//! | `protocol`  | Transport protocol                        |
+//! | `src`       | Source address                            |
+//! | `src_port`  | Source port                               |
+//! | `dst`       | Destination address                       |
+//! | `dst_port`  | Destination port                          |
+//! | `length`    | Length of the frame                       |
+//!
+//! Flows are mapped to shards with a fixed SipHash key and jump consistent
+//! hashing, so the same flow lands in the same shard across probes and runs,
+//! and growing from N to N + 1 shards only moves 1 / (N + 1) of the flows.
+//! [`PartitionExporter::create_dir`] writes the shards as `part-NNNNN.csv`
+//! with a `manifest.json` describing the partitioning.
+//!
+//! ```
+//! use netkit::export::partition::{jump_hash, Partitioner};
+//! # use netkit::packet::prelude::*;
+//! # use std::net::Ipv4Addr;
+//!
+//! let key = FlowKey::new(Ipv4Addr::LOCALHOST, Ipv4Addr::BROADCAST, 1234, 53, IpProtocol::Udp);
+//! let partitioner = Partitioner::new(8);
+//! assert_eq!(partitioner.shard(&key), partitioner.shard(&key.reversed()));
+//! assert!(jump_hash(42, 8) < 8);
+//! ```
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use netkit_packet::{
+    flow::table::{FlowHashBuilder, HashKey},
+    prelude::*,
+};
+
+use super::bundle::json_string;
+use crate::analysis::Analyzer;
+
+/// Default SipHash key of partitioners, shared by all netkit probes.
+pub const DEFAULT_HASH_KEY: (u64, u64) = (0x6e65_746b_6974_0001, 0x7061_7274_6974_696f);
+
+/// Name of the manifest written next to the shards.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Header line of the shards.
+pub const RECORD_HEADER: &str = "ts,protocol,src,src_port,dst,dst_port,length";
+
+/// Map a hash to one of `buckets` buckets with jump consistent hashing
+/// (Lamping and Veach, 2014).
+///
+/// # Panics
+///
+/// Panics if `buckets` is zero.
+pub fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    assert!(buckets > 0, "no buckets");
+    let (mut b, mut j) = (-1i64, 0i64);
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as u32
+}
+
+/// Mapping of flows to shards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Partitioner {
+    shards: u32,
+    hash_key: (u64, u64),
+    hasher: FlowHashBuilder,
+}
+
+impl Partitioner {
+    /// Create a partitioner into `shards` shards with the default hash key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: u32) -> Self {
+        Self::with_hash_key(shards, DEFAULT_HASH_KEY)
+    }
+
+    /// Create a partitioner with a given hash key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_hash_key(shards: u32, hash_key: (u64, u64)) -> Self {
+        assert!(shards > 0, "no shards");
+        Self {
+            shards,
+            hash_key,
+            hasher: FlowHashBuilder::new(HashKey::Fixed(hash_key.0, hash_key.1)),
+        }
+    }
+
+    /// Get the number of shards.
+    pub fn shards(&self) -> u32 {
+        self.shards
+    }
+
+    /// Get the hash key.
+    pub fn hash_key(&self) -> (u64, u64) {
+        self.hash_key
+    }
+
+    /// Get the shard of a flow, the same for both directions.
+    pub fn shard(&self, key: &FlowKey) -> usize {
+        use core::hash::BuildHasher;
+
+        jump_hash(self.hasher.hash_one(key.canonical()), self.shards) as usize
+    }
+}
+
+/// Description of a partitioned export, written to `manifest.json`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PartitionManifest {
+    /// SipHash key of the partitioner.
+    pub hash_key: (u64, u64),
+    /// File names of the shards, in shard order.
+    pub files: Vec<String>,
+    /// Number of records of each shard.
+    pub records: Vec<u64>,
+    /// Number of packets not written, e.g. non-IP frames.
+    pub skipped: u64,
+    /// Timestamp of the first record.
+    pub first_seen: Option<Duration>,
+    /// Timestamp of the last record.
+    pub last_seen: Option<Duration>,
+}
+
+impl PartitionManifest {
+    /// Serialize as a JSON object.
+    pub fn to_json(&self) -> String {
+        let secs =
+            |ts: Option<Duration>| ts.map_or("null".to_string(), |ts| ts.as_secs_f64().to_string());
+        let shards: Vec<_> = self
+            .files
+            .iter()
+            .zip(&self.records)
+            .enumerate()
+            .map(|(i, (file, records))| {
+                format!(
+                    "{{\"shard\": {i}, \"file\": {}, \"records\": {records}}}",
+                    json_string(file)
+                )
+            })
+            .collect();
+
+        let mut json = String::from("{\n");
+        let mut field = |name: &str, value: String| {
+            let _ = writeln!(json, "  \"{name}\": {value},");
+        };
+        field(
+            "generator",
+            json_string(concat!("netkit ", env!("CARGO_PKG_VERSION"))),
+        );
+        field("partitioning", json_string("siphash13-jump"));
+        field("key", json_string("canonical-5-tuple"));
+        field(
+            "hash_key",
+            format!("[{}, {}]", self.hash_key.0, self.hash_key.1),
+        );
+        field("format", json_string("csv"));
+        field("columns", json_string(RECORD_HEADER));
+        field("records", self.records.iter().sum::<u64>().to_string());
+        field("skipped", self.skipped.to_string());
+        field("first_seen", secs(self.first_seen));
+        field("last_seen", secs(self.last_seen));
+        let _ = writeln!(
+            json,
+            "  \"shards\": [\n    {}\n  ]\n}}",
+            shards.join(",\n    ")
+        );
+        json
+    }
+}
+
+/// Partitioned exporter
+///
+/// Writes one record per IPv4 or IPv6 packet to the writer of its shard.
+/// Write errors stop the export and are returned by
+/// [`finish`](Self::finish).
+#[derive(Debug)]
+pub struct PartitionExporter<W: Write> {
+    partitioner: Partitioner,
+    writers: Vec<W>,
+    manifest: PartitionManifest,
+    dir: Option<PathBuf>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> PartitionExporter<W> {
+    /// Create an exporter with one writer per shard, e.g. streams to the
+    /// workers of a cluster.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no writers.
+    pub fn new(writers: Vec<W>) -> Self {
+        let files = (0..writers.len()).map(shard_file).collect();
+        Self::with_partitioner(Partitioner::new(writers.len() as u32), writers, files)
+    }
+
+    fn with_partitioner(partitioner: Partitioner, mut writers: Vec<W>, files: Vec<String>) -> Self {
+        let mut error = None;
+        for writer in &mut writers {
+            if let Err(e) = writeln!(writer, "{RECORD_HEADER}") {
+                error.get_or_insert(e);
+            }
+        }
+        Self {
+            manifest: PartitionManifest {
+                hash_key: partitioner.hash_key(),
+                records: vec![0; files.len()],
+                files,
+                ..Default::default()
+            },
+            partitioner,
+            writers,
+            dir: None,
+            error,
+        }
+    }
+
+    /// Set the hash key, e.g. to match another partitioned dataset.
+    ///
+    /// Must be set before the first packet.
+    pub fn hash_key(&mut self, hash_key: (u64, u64)) -> &mut Self {
+        self.partitioner = Partitioner::with_hash_key(self.partitioner.shards(), hash_key);
+        self.manifest.hash_key = hash_key;
+        self
+    }
+
+    /// Get the partitioner.
+    pub fn partitioner(&self) -> &Partitioner {
+        &self.partitioner
+    }
+
+    /// Get the manifest so far.
+    pub fn manifest(&self) -> &PartitionManifest {
+        &self.manifest
+    }
+
+    /// Flush the shards, writing the manifest if exporting to a directory,
+    /// and return the writers with the manifest.
+    pub fn finish(mut self) -> io::Result<(Vec<W>, PartitionManifest)> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        for writer in &mut self.writers {
+            writer.flush()?;
+        }
+        if let Some(dir) = &self.dir {
+            fs::write(dir.join(MANIFEST_FILE), self.manifest.to_json())?;
+        }
+        Ok((self.writers, self.manifest))
+    }
+
+    fn record(&mut self, ts: Duration, key: &FlowKey, length: usize) -> io::Result<()> {
+        let shard = self.partitioner.shard(key);
+        writeln!(
+            self.writers[shard],
+            "{}.{:09},{},{},{},{},{},{length}",
+            ts.as_secs(),
+            ts.subsec_nanos(),
+            key.protocol,
+            key.src,
+            key.src_port,
+            key.dst,
+            key.dst_port,
+        )?;
+        self.manifest.records[shard] += 1;
+        Ok(())
+    }
+}
+
+impl PartitionExporter<BufWriter<File>> {
+    /// Create an exporter writing `shards` shards to a directory, created if
+    /// missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn create_dir(dir: impl AsRef<Path>, shards: u32) -> io::Result<Self> {
+        assert!(shards > 0, "no shards");
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let files: Vec<_> = (0..shards as usize).map(shard_file).collect();
+        let writers = files
+            .iter()
+            .map(|file| Ok(BufWriter::new(File::create(dir.join(file))?)))
+            .collect::<io::Result<_>>()?;
+        let mut exporter = Self::with_partitioner(Partitioner::new(shards), writers, files);
+        exporter.dir = Some(dir.to_path_buf());
+        Ok(exporter)
+    }
+}
+
+impl<W: Write> Analyzer for PartitionExporter<W> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let Some(key) = Eth::new(frame).ok().and_then(|eth| FlowKey::from_eth(&eth)) else {
+            self.manifest.skipped += 1;
+            return;
+        };
+        if let Err(e) = self.record(ts, &key, frame.len()) {
+            self.error = Some(e);
+            return;
+        }
+        self.manifest.first_seen.get_or_insert(ts);
+        self.manifest.last_seen = Some(ts);
+    }
+}
+
+fn shard_file(shard: usize) -> String {
+    format!("part-{shard:05}.csv")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn partition_export() {
+        // Growing the shards only moves flows to the new shard.
+        for key in 0..1000u64 {
+            let (a, b) = (jump_hash(key, 10), jump_hash(key, 11));
+            assert!(a == b || b == 10);
+        }
+
+        let dir = std::env::temp_dir().join(format!("netkit-partition-{}", std::process::id()));
+        let mut exporter = PartitionExporter::create_dir(&dir, 4).unwrap();
+        for i in 0..16u8 {
+            let (src, dst) = (Ipv4Addr::new(10, 0, 0, i), Ipv4Addr::new(10, 0, 1, 1));
+            let (src, dst) = if i % 2 == 0 { (src, dst) } else { (dst, src) };
+            let udp = udp!(src_port: 1000u16 + u16::from(i / 2), dst_port: 9999u16);
+            let ipv4 = ipv4!(src: src, dst: dst, protocol: IpProtocol::Udp, payload: udp.inner());
+            let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+            exporter.on_packet(Duration::from_secs(u64::from(i)), eth.inner());
+        }
+        exporter.on_packet(Duration::from_secs(20), &[0; 10]);
+        let (_, manifest) = exporter.finish().unwrap();
+        assert_eq!(manifest.records.iter().sum::<u64>(), 16);
+        assert_eq!(manifest.skipped, 1);
+
+        let mut lines = 0;
+        for file in &manifest.files {
+            let shard = fs::read_to_string(dir.join(file)).unwrap();
+            assert!(shard.starts_with(RECORD_HEADER));
+            lines += shard.lines().count() - 1;
+        }
+        assert_eq!(lines, 16);
+        let json = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        assert!(json.contains("\"file\": \"part-00003.csv\""));
+        assert!(json.contains("\"records\": 16,"));
+        fs::remove_dir_all(dir).unwrap();
+
+        // Streams get the same records.
+        let mut exporter = PartitionExporter::new(vec![Vec::new(), Vec::new()]);
+        let udp = udp!(src_port: 1u16, dst_port: 2u16);
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let eth = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+        exporter.on_packet(Duration::from_millis(1500), eth.inner());
+        let (writers, _) = exporter.finish().unwrap();
+        let records: String = writers
+            .iter()
+            .map(|w| String::from_utf8_lossy(&w[RECORD_HEADER.len() + 1..]).into_owned())
+            .collect();
+        assert_eq!(records, "1.500000000,Udp,0.0.0.0,1,0.0.0.0,2,42\n");
+    }
+}
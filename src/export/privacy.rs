@@ -0,0 +1,204 @@
+//! Differential privacy noise for exported aggregates.
+//!
+//! [`DpNoise`] adds Laplace noise to the count columns of a [`Table`] before
+//! it leaves the probe, so that published reports (per-port packet counts,
+//! top talkers, ...) are ε-differentially private with respect to a single
+//! contribution to each count:
+//!
+//! ```
+//! use netkit::export::{privacy::DpNoise, Column, Table};
+//!
+//! let mut table = Table::new();
+//! table
+//!     .push("port", Column::Str(vec!["53".into(), "443".into()]))
+//!     .push("packets", Column::U64(vec![1200, 48000]));
+//! let noised = DpNoise::new(0.5).columns(["packets"]).apply(&table);
+//! assert_eq!(noised.column("port"), table.column("port"));
+//! ```
+//!
+//! # Threat model
+//!
+//! The adversary sees the exported tables, and may know every other
+//! contribution; the noise hides whether one contribution, changing each
+//! noised cell by at most the *sensitivity*, was made. In particular:
+//!
+//! - The unit protected is what the sensitivity bounds. With the default of
+//!   1, a packet count protects a single packet, not a host or a user, who
+//!   typically send many. To protect a host, set the sensitivity to the most
+//!   it can add to a cell, and cap its contribution upstream to that bound.
+//! - A contribution to several noised cells (packets and bytes of the same
+//!   group, or rows of several windows) spends ε per cell, and repeated
+//!   releases of the same data compose: the budget of a report is the sum.
+//! - Only the noised columns are protected. Key columns, such as addresses
+//!   or ports, are published as is, and the presence of a row may reveal a
+//!   contribution on its own; drop or coarsen such keys, or publish a fixed
+//!   set of rows.
+//! - Noise is drawn with floating point arithmetic, which leaks through its
+//!   low-order bits in theory; rounding counts to integers mitigates but
+//!   does not remove it. The draws come from the cryptographically secure
+//!   thread RNG, but are reproducible under a seeded
+//!   [`RngContext`](netkit_packet::utils::rng::RngContext), which must only
+//!   be used in tests.
+
+use netkit_packet::utils::rng;
+
+use super::{Column, Table, ToTable};
+
+/// Default sensitivity: one contribution changes a count by at most 1.
+pub const DEFAULT_SENSITIVITY: f64 = 1.0;
+
+/// Laplace mechanism applied to the columns of tables.
+#[derive(Clone, Debug, PartialEq)]
+#[must_use]
+pub struct DpNoise {
+    epsilon: f64,
+    sensitivity: f64,
+    columns: Option<Vec<String>>,
+}
+
+impl DpNoise {
+    /// Noise with a privacy budget of `epsilon` per cell; smaller is more
+    /// private and noisier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `epsilon` is not positive and finite.
+    pub fn new(epsilon: f64) -> Self {
+        assert!(
+            epsilon > 0.0 && epsilon.is_finite(),
+            "epsilon must be positive"
+        );
+        Self {
+            epsilon,
+            sensitivity: DEFAULT_SENSITIVITY,
+            columns: None,
+        }
+    }
+
+    /// Set the most a single contribution changes a cell by.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sensitivity` is not positive and finite.
+    pub fn sensitivity(mut self, sensitivity: f64) -> Self {
+        assert!(
+            sensitivity > 0.0 && sensitivity.is_finite(),
+            "sensitivity must be positive"
+        );
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    /// Noise these columns only, instead of every integer column.
+    pub fn columns<S: Into<String>>(mut self, columns: impl IntoIterator<Item = S>) -> Self {
+        self.columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Get the privacy budget per cell.
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Get the scale of the Laplace noise, the sensitivity over epsilon.
+    pub fn scale(&self) -> f64 {
+        self.sensitivity / self.epsilon
+    }
+
+    /// Draw a Laplace noise value.
+    pub fn sample(&self) -> f64 {
+        // Inverse transform of a uniform value in (-0.5, 0.5)
+        let u = loop {
+            let u = rng::random::<f64>() - 0.5;
+            if u != -0.5 {
+                break u;
+            }
+        };
+        -self.scale() * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+    }
+
+    /// Get a copy of a table with noise added to the selected columns.
+    ///
+    /// Integer columns are rounded and clamped at zero, which is
+    /// post-processing and keeps the guarantee. String columns are never
+    /// noised.
+    pub fn apply(&self, table: &Table) -> Table {
+        let mut noised = Table::new();
+        for (name, column) in table.columns() {
+            let selected = self
+                .columns
+                .as_ref()
+                .map_or(matches!(column, Column::U64(_)), |c| c.contains(name));
+            let column = match column {
+                Column::U64(values) if selected => Column::U64(
+                    values
+                        .iter()
+                        .map(|&v| (v as f64 + self.sample()).round().max(0.0) as u64)
+                        .collect(),
+                ),
+                Column::F64(values) if selected => {
+                    Column::F64(values.iter().map(|v| v + self.sample()).collect())
+                }
+                column => column.clone(),
+            };
+            noised.push(name.clone(), column);
+        }
+        noised
+    }
+}
+
+/// Report whose table is noised on export.
+#[derive(Clone, Debug)]
+pub struct Noised<T> {
+    /// The report.
+    pub inner: T,
+    /// Noise added to its table.
+    pub noise: DpNoise,
+}
+
+impl<T: ToTable> ToTable for Noised<T> {
+    fn to_table(&self) -> Table {
+        self.noise.apply(&self.inner.to_table())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::utils::rng::RngContext;
+
+    use super::*;
+
+    #[test]
+    fn laplace_noise() {
+        let noise = DpNoise::new(0.5).sensitivity(2.0);
+        assert_eq!(noise.scale(), 4.0);
+
+        let mut context = RngContext::new(7);
+        let samples: Vec<f64> = context.enter(|| (0..20000).map(|_| noise.sample()).collect());
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let mean_abs = samples.iter().map(|s| s.abs()).sum::<f64>() / samples.len() as f64;
+        // Laplace(0, b) has mean 0 and mean absolute deviation b
+        assert!(mean.abs() < 0.2, "{mean}");
+        assert!((mean_abs - 4.0).abs() < 0.2, "{mean_abs}");
+
+        let mut table = Table::new();
+        table
+            .push("key", Column::Str(vec!["a".into(), "b".into()]))
+            .push("packets", Column::U64(vec![0, 1_000_000]))
+            .push("ratio", Column::F64(vec![0.5, 0.25]));
+        let noised = context.enter(|| noise.apply(&table));
+        assert_eq!(noised.column("key"), table.column("key"));
+        assert_eq!(noised.column("ratio"), table.column("ratio"));
+        let Some(Column::U64(packets)) = noised.column("packets") else {
+            panic!("packets not a count column");
+        };
+        assert!(packets[1].abs_diff(1_000_000) < 100);
+        assert_ne!(noised, table);
+    }
+
+    #[test]
+    #[should_panic(expected = "sensitivity must be positive")]
+    fn laplace_noise_invalid_sensitivity() {
+        let _ = DpNoise::new(1.0).sensitivity(f64::NAN);
+    }
+}
@@ -0,0 +1,413 @@
+//! Reverse DNS enrichment of reports.
+//!
+//! Top-talker reports listing bare addresses are hard to act on. A
+//! [`ReverseDns`] enricher looks up the PTR names of the addresses of a
+//! report, a bounded number at a time, and caches them for their TTL so
+//! reports exported periodically do not query the same addresses again.
+//! Lookups go through a [`Resolver`], by default a [`DnsResolver`] querying
+//! a DNS server over UDP.
+//!
+//! ```no_run
+//! use netkit::export::{rdns::{DnsResolver, ReverseDns}, ToTable};
+//! use netkit::stats::top::TopTalkers;
+//!
+//! let talkers = TopTalkers::new();
+//! let rdns = ReverseDns::new(DnsResolver::new("192.0.2.53:53".parse().unwrap()));
+//!
+//! let mut table = talkers.to_table();
+//! rdns.annotate(&mut table, "endpoint", "name");
+//! table.write_csv(&mut std::io::stdout()).unwrap();
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use netkit_packet::{
+    layer::dns::{question::DnsQuestionBuilder, DnsBuilder, DnsRCode, DnsRData, DnsRrType},
+    prelude::*,
+};
+
+use super::{Column, Table};
+
+/// Default number of concurrent lookups.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Default number of cached addresses.
+pub const DEFAULT_CACHE_CAPACITY: usize = 65536;
+
+/// Default time addresses without a name are cached.
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(300);
+
+/// Default longest time names are cached, whatever their TTL.
+pub const DEFAULT_MAX_TTL: Duration = Duration::from_secs(3600);
+
+/// Default time to wait for a DNS answer.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Source of reverse lookups.
+pub trait Resolver: Send + Sync {
+    /// Look up the name of an address and its TTL.
+    ///
+    /// Returns `None` if the address has no name.
+    fn reverse(&self, addr: IpAddr) -> io::Result<Option<(String, Duration)>>;
+}
+
+/// Get the name of the PTR record of an address, in `in-addr.arpa` or
+/// `ip6.arpa`.
+pub fn ptr_name(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, c, d] = addr.octets();
+            format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+        }
+        IpAddr::V6(addr) => {
+            let mut name = String::with_capacity(72);
+            for byte in addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0xf, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// Resolver querying a DNS server over UDP.
+#[derive(Clone, Debug)]
+pub struct DnsResolver {
+    server: SocketAddr,
+    timeout: Duration,
+}
+
+impl DnsResolver {
+    /// Create a resolver querying a server.
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the time to wait for an answer.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Resolver for DnsResolver {
+    fn reverse(&self, addr: IpAddr) -> io::Result<Option<(String, Duration)>> {
+        let bind: SocketAddr = match self.server {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(self.server)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+
+        let id = netkit_packet::utils::rng::random::<u16>();
+        let query = DnsBuilder::new()
+            .id(id)
+            .rd(true)
+            .questions(
+                DnsQuestionBuilder::new()
+                    .qname(ptr_name(addr))
+                    .qtype(DnsRrType::PTR)
+                    .build(),
+            )
+            .build();
+        socket.send(query.inner())?;
+
+        let mut buf = [0; 4096];
+        loop {
+            let len = socket.recv(&mut buf)?;
+            let Ok(dns) = Dns::new(&buf[..len]) else {
+                continue;
+            };
+            if dns.id().get() != id || !dns.qr().get() {
+                continue;
+            }
+            if dns.rcode().get() != DnsRCode::NoError {
+                return Ok(None);
+            }
+            return Ok(dns.records().find_map(|record| match record.rdata {
+                DnsRData::Ptr(name) => Some((name, Duration::from_secs(record.ttl as u64))),
+                _ => None,
+            }));
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    name: Option<String>,
+    expires: Instant,
+}
+
+/// Reverse DNS enricher with a TTL cache.
+#[derive(Debug)]
+pub struct ReverseDns<R> {
+    resolver: Arc<R>,
+    cache: Mutex<HashMap<IpAddr, Entry>>,
+    concurrency: usize,
+    capacity: usize,
+    negative_ttl: Duration,
+    max_ttl: Duration,
+}
+
+impl<R: Resolver> ReverseDns<R> {
+    /// Create an enricher looking names up with a resolver.
+    pub fn new(resolver: R) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            cache: Mutex::new(HashMap::new()),
+            concurrency: DEFAULT_CONCURRENCY,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            max_ttl: DEFAULT_MAX_TTL,
+        }
+    }
+
+    /// Set the number of concurrent lookups.
+    pub fn concurrency(&mut self, concurrency: usize) -> &mut Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set the number of cached addresses.
+    pub fn capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the time addresses without a name, or failing to resolve, are
+    /// cached.
+    pub fn negative_ttl(&mut self, negative_ttl: Duration) -> &mut Self {
+        self.negative_ttl = negative_ttl;
+        self
+    }
+
+    /// Set the longest time names are cached, whatever their TTL.
+    pub fn max_ttl(&mut self, max_ttl: Duration) -> &mut Self {
+        self.max_ttl = max_ttl;
+        self
+    }
+
+    /// Get the number of cached addresses.
+    pub fn cached(&self) -> usize {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Get the name of an address.
+    pub fn name(&self, addr: IpAddr) -> Option<String> {
+        self.names([addr]).remove(&addr).flatten()
+    }
+
+    /// Get the names of addresses, looking up those not cached.
+    pub fn names(
+        &self,
+        addrs: impl IntoIterator<Item = IpAddr>,
+    ) -> HashMap<IpAddr, Option<String>> {
+        let now = Instant::now();
+        let mut names = HashMap::new();
+        let mut missing = Vec::new();
+        let mut seen = HashSet::new();
+        {
+            let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            for addr in addrs {
+                match cache.get(&addr) {
+                    Some(entry) if entry.expires > now => {
+                        names.insert(addr, entry.name.clone());
+                    }
+                    _ if seen.insert(addr) => missing.push(addr),
+                    _ => {}
+                }
+            }
+        }
+
+        for (addr, entry) in self.lookup(missing) {
+            names.insert(addr, entry.name.clone());
+            self.insert(addr, entry);
+        }
+        names
+    }
+
+    /// Add a name column to a table, from the addresses of another column.
+    ///
+    /// Values of the address column are addresses or socket addresses;
+    /// others, and addresses without a name, get an empty name.
+    pub fn annotate(&self, table: &mut Table, column: &str, name_column: &str) {
+        let Some(Column::Str(values)) = table.column(column) else {
+            return;
+        };
+        let addrs: Vec<_> = values
+            .iter()
+            .map(|value| {
+                value
+                    .parse::<IpAddr>()
+                    .ok()
+                    .or_else(|| value.parse::<SocketAddr>().ok().map(|s| s.ip()))
+            })
+            .collect();
+        let names = self.names(addrs.iter().flatten().copied());
+        let column = addrs
+            .iter()
+            .map(|addr| {
+                addr.and_then(|addr| names.get(&addr).cloned().flatten())
+                    .unwrap_or_default()
+            })
+            .collect();
+        table.push(name_column, Column::Str(column));
+    }
+
+    /// Look addresses up, `concurrency` at a time.
+    fn lookup(&self, addrs: Vec<IpAddr>) -> Vec<(IpAddr, Entry)> {
+        let queue = Mutex::new(addrs.into_iter());
+        let results = Mutex::new(Vec::new());
+        let workers = self.concurrency.min(queue.lock().unwrap().len());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let Some(addr) = queue.lock().unwrap_or_else(|e| e.into_inner()).next() else {
+                        break;
+                    };
+                    let entry = match self.resolver.reverse(addr) {
+                        Ok(Some((name, ttl))) => Entry {
+                            name: Some(name),
+                            expires: Instant::now() + ttl.min(self.max_ttl),
+                        },
+                        Ok(None) | Err(_) => Entry {
+                            name: None,
+                            expires: Instant::now() + self.negative_ttl,
+                        },
+                    };
+                    results
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((addr, entry));
+                });
+            }
+        });
+        results.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn insert(&self, addr: IpAddr, entry: Entry) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if cache.len() >= self.capacity && !cache.contains_key(&addr) {
+            let now = Instant::now();
+            cache.retain(|_, entry| entry.expires > now);
+            if cache.len() >= self.capacity {
+                return;
+            }
+        }
+        cache.insert(addr, entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use netkit_packet::layer::dns::DnsRecord;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counting {
+        lookups: AtomicUsize,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl Resolver for Counting {
+        fn reverse(&self, addr: IpAddr) -> io::Result<Option<(String, Duration)>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            match addr {
+                IpAddr::V4(v4) if v4.octets()[3] % 2 == 0 => Ok(Some((
+                    format!("host{}.example", v4.octets()[3]),
+                    Duration::from_secs(60),
+                ))),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn reverse_dns_enrich() {
+        assert_eq!(
+            ptr_name(Ipv4Addr::new(192, 0, 2, 1).into()),
+            "1.2.0.192.in-addr.arpa"
+        );
+        assert!(ptr_name(Ipv6Addr::LOCALHOST.into()).starts_with("1.0.0.0.0.0"));
+
+        let mut rdns = ReverseDns::new(Counting::default());
+        rdns.concurrency(3);
+        let mut table = Table::new();
+        let endpoints = (1..=10)
+            .map(|i| format!("192.0.2.{i}"))
+            .chain(["192.0.2.2:443".into(), "n/a".into()])
+            .collect();
+        table.push("endpoint", Column::Str(endpoints));
+        rdns.annotate(&mut table, "endpoint", "name");
+
+        let Some(Column::Str(names)) = table.column("name") else {
+            panic!("no name column");
+        };
+        assert_eq!(names[1], "host2.example");
+        assert_eq!(names[0], "");
+        assert_eq!(names[10], "host2.example");
+        assert_eq!(names[11], "");
+        assert_eq!(rdns.resolver.lookups.load(Ordering::SeqCst), 10);
+        assert!(rdns.resolver.max_running.load(Ordering::SeqCst) <= 3);
+
+        // Cached, including negative answers.
+        assert_eq!(
+            rdns.name("192.0.2.4".parse().unwrap()).unwrap(),
+            "host4.example"
+        );
+        assert_eq!(rdns.name("192.0.2.5".parse().unwrap()), None);
+        assert_eq!(rdns.resolver.lookups.load(Ordering::SeqCst), 10);
+        assert_eq!(rdns.cached(), 10);
+
+        // A local server answering PTR queries.
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (len, peer) = server.recv_from(&mut buf).unwrap();
+            let query = Dns::new(&buf[..len]).unwrap();
+            let qname = query.questions().next().unwrap().qname().to_string();
+            assert_eq!(qname.trim_end_matches('.'), "1.2.0.192.in-addr.arpa");
+            let answer = DnsBuilder::new()
+                .id(query.id().get())
+                .qr(true)
+                .answers(DnsRecord::new(
+                    qname,
+                    120,
+                    DnsRData::Ptr("gw.example".into()),
+                ))
+                .build();
+            server.send_to(answer.inner(), peer).unwrap();
+        });
+        let resolver = DnsResolver::new(addr);
+        let answer = resolver
+            .reverse(Ipv4Addr::new(192, 0, 2, 1).into())
+            .unwrap();
+        handle.join().unwrap();
+        let (name, ttl) = answer.unwrap();
+        assert_eq!(name.trim_end_matches('.'), "gw.example");
+        assert_eq!(ttl, Duration::from_secs(120));
+    }
+}
@@ -0,0 +1,334 @@
+//! Payload retention driven by analyzer feedback.
+//!
+//! Always-on capture cannot afford to keep every payload, but the payload of
+//! the traffic an analyzer flags is what an investigation needs. A
+//! [`RetentionWriter`] writes packets with their whole payload when their
+//! flow or hosts are flagged, e.g. by an [`Alert`] or a parse error, and
+//! truncated to their headers ([`Trim::Headers`]) otherwise. Packets are held
+//! for a while before they are written, so that an alert raised on a later
+//! packet of a flow still retains the earlier ones.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit::export::retention::RetentionWriter;
+//! use netkit_capture::file::pcap::PcapWriter;
+//!
+//! let mut writer = RetentionWriter::new(PcapWriter::new(Vec::new()).unwrap());
+//! writer.hold(Duration::from_secs(2));
+//! # let frame = [0u8; 60];
+//! writer.write_packet(Duration::from_secs(1), &frame).unwrap();
+//! // ...feed the analyzers, then flag what they report
+//! writer.flag_packet(Duration::from_secs(1), &frame);
+//! let file = writer.into_inner().unwrap();
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_capture::file::pcap::{PcapWriter, Trim};
+use netkit_packet::{
+    layer::link::{self, LinkType},
+    prelude::*,
+};
+
+use crate::analysis::alert::Alert;
+
+/// Default time packets are held before they are written.
+pub const DEFAULT_HOLD: Duration = Duration::from_secs(5);
+
+/// Default time the payload of a flagged flow is retained after the flag.
+pub const DEFAULT_RETAIN: Duration = Duration::from_secs(300);
+
+/// Counters of a [`RetentionWriter`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetentionStats {
+    /// Number of packets written.
+    pub packets: u64,
+    /// Number of packets written whole because they were flagged.
+    pub retained: u64,
+    /// Number of packets truncated to their headers.
+    pub truncated: u64,
+    /// Number of payload bytes not written.
+    pub bytes_saved: u64,
+}
+
+/// Time range of the packets retained by a flag.
+#[derive(Clone, Copy, Debug)]
+struct Window {
+    since: Duration,
+    until: Duration,
+}
+
+impl Window {
+    fn contains(&self, ts: Duration) -> bool {
+        self.since <= ts && ts <= self.until
+    }
+}
+
+/// Pcap writer keeping the payload of flagged flows only
+///
+/// A flag at `ts` retains the packets of its flow (or hosts) from `ts` minus
+/// the hold time to `ts` plus the retention time; flags of the same flow
+/// extend the range. Packets are expected in timestamp order, and flags at
+/// most the hold time after the packets they are about.
+///
+/// The underlying writer should not trim packets itself.
+#[derive(Debug)]
+pub struct RetentionWriter<W: Write> {
+    writer: PcapWriter<W>,
+    hold: Duration,
+    retain: Duration,
+    held: VecDeque<(Duration, Vec<u8>, Option<FlowKey>)>,
+    flows: HashMap<FlowKey, Window>,
+    hosts: HashMap<(IpAddr, IpAddr), Window>,
+    swept: Duration,
+    stats: RetentionStats,
+}
+
+impl<W: Write> RetentionWriter<W> {
+    /// Create a retention writer over a pcap writer.
+    pub fn new(writer: PcapWriter<W>) -> Self {
+        Self {
+            writer,
+            hold: DEFAULT_HOLD,
+            retain: DEFAULT_RETAIN,
+            held: VecDeque::new(),
+            flows: HashMap::new(),
+            hosts: HashMap::new(),
+            swept: Duration::ZERO,
+            stats: RetentionStats::default(),
+        }
+    }
+
+    /// Set the time packets are held before they are written.
+    pub fn hold(&mut self, hold: Duration) -> &mut Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Set the time the payload of a flagged flow is retained after the
+    /// flag.
+    pub fn retain(&mut self, retain: Duration) -> &mut Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Get the counters.
+    pub fn stats(&self) -> RetentionStats {
+        self.stats
+    }
+
+    /// Flag a flow, in both directions, at `ts`.
+    pub fn flag_flow(&mut self, key: &FlowKey, ts: Duration) -> &mut Self {
+        let window = self.window(ts);
+        extend(self.flows.entry(key.canonical()).or_insert(window), window);
+        self
+    }
+
+    /// Flag all traffic between two hosts at `ts`.
+    pub fn flag_hosts(&mut self, a: IpAddr, b: IpAddr, ts: Duration) -> &mut Self {
+        let window = self.window(ts);
+        extend(self.hosts.entry(hosts(a, b)).or_insert(window), window);
+        self
+    }
+
+    /// Flag the hosts of alerts, which do not name the ports of the flow.
+    pub fn flag_alerts(&mut self, alerts: &[Alert]) -> &mut Self {
+        for alert in alerts {
+            self.flag_hosts(alert.src, alert.dst, alert.ts);
+        }
+        self
+    }
+
+    /// Flag the flow of a packet, e.g. one failing to parse.
+    ///
+    /// Packets without a flow are only kept whole if not IP, see
+    /// [`Trim::Headers`].
+    pub fn flag_packet(&mut self, ts: Duration, data: &[u8]) -> &mut Self {
+        if let Some(key) = self.flow_key(data) {
+            self.flag_flow(&key, ts);
+        }
+        self
+    }
+
+    /// Write a packet captured at `ts`, once the hold time has passed.
+    pub fn write_packet(&mut self, ts: Duration, data: &[u8]) -> io::Result<()> {
+        let key = self.flow_key(data);
+        self.held.push_back((ts, data.to_vec(), key));
+        while let Some(&(oldest, ..)) = self.held.front() {
+            if oldest + self.hold > ts {
+                break;
+            }
+            self.write_held()?;
+        }
+        if ts >= self.swept + self.hold {
+            self.swept = ts;
+            let expired = ts.saturating_sub(self.hold);
+            self.flows.retain(|_, window| window.until >= expired);
+            self.hosts.retain(|_, window| window.until >= expired);
+        }
+        Ok(())
+    }
+
+    /// Write the held packets and flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.held.is_empty() {
+            self.write_held()?;
+        }
+        self.writer.flush()
+    }
+
+    /// Write the held packets and get the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        self.writer.into_inner()
+    }
+
+    fn window(&self, ts: Duration) -> Window {
+        Window {
+            since: ts.saturating_sub(self.hold),
+            until: ts.saturating_add(self.retain),
+        }
+    }
+
+    fn flow_key(&self, data: &[u8]) -> Option<FlowKey> {
+        let key = match link::network(LinkType::from(self.writer.header().network), data)? {
+            (EthType::Ipv4, payload) => FlowKey::from_ipv4(&Ipv4::new(payload).ok()?),
+            (EthType::Ipv6, payload) => FlowKey::from_ipv6(&Ipv6::new(payload).ok()?),
+            _ => return None,
+        };
+        Some(key.canonical())
+    }
+
+    fn write_held(&mut self) -> io::Result<()> {
+        let Some((ts, data, key)) = self.held.pop_front() else {
+            return Ok(());
+        };
+        let flagged = key.is_some_and(|key| {
+            self.flows.get(&key).is_some_and(|w| w.contains(ts))
+                || self
+                    .hosts
+                    .get(&hosts(key.src, key.dst))
+                    .is_some_and(|w| w.contains(ts))
+        });
+        let len = if flagged {
+            data.len()
+        } else {
+            Trim::Headers.len(self.writer.header().network, &data)
+        };
+
+        self.stats.packets += 1;
+        if flagged {
+            self.stats.retained += 1;
+        } else if len < data.len() {
+            self.stats.truncated += 1;
+            self.stats.bytes_saved += (data.len() - len) as u64;
+        }
+        self.writer
+            .write_captured(ts, &data[..len], data.len() as u32)
+    }
+}
+
+/// Extend a window to cover another one.
+fn extend(window: &mut Window, other: Window) {
+    window.since = window.since.min(other.since);
+    window.until = window.until.max(other.until);
+}
+
+/// Get a pair of hosts in either order.
+fn hosts(a: IpAddr, b: IpAddr) -> (IpAddr, IpAddr) {
+    (a.min(b), a.max(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, net::Ipv4Addr};
+
+    use netkit_capture::file::pcap::PcapReader;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    fn frame(src: u8, dst: u8, src_port: u16, dst_port: u16) -> Vec<u8> {
+        udp_frame(
+            (Ipv4Addr::new(10, 0, 0, src), src_port),
+            (Ipv4Addr::new(10, 0, 0, dst), dst_port),
+            [0x5a; 100],
+        )
+    }
+
+    #[test]
+    fn retention_writer() {
+        let secs = Duration::from_secs;
+        let mut writer = RetentionWriter::new(PcapWriter::new(Vec::new()).unwrap());
+        writer.hold(secs(2)).retain(secs(10));
+
+        // Flagged one second after its first packet, both directions kept
+        writer
+            .write_packet(secs(1), &frame(1, 2, 1000, 53))
+            .unwrap();
+        writer
+            .write_packet(secs(1), &frame(1, 2, 2000, 53))
+            .unwrap();
+        writer
+            .write_packet(secs(2), &frame(2, 1, 53, 1000))
+            .unwrap();
+        writer.flag_packet(secs(2), &frame(2, 1, 53, 1000));
+        writer
+            .write_packet(secs(3), &frame(1, 2, 1000, 53))
+            .unwrap();
+        // Past the retention
+        writer
+            .write_packet(secs(20), &frame(1, 2, 1000, 53))
+            .unwrap();
+
+        let alert = Alert {
+            ts: secs(21),
+            detector: "test",
+            score: 1.0,
+            src: Ipv4Addr::new(10, 0, 0, 3).into(),
+            dst: Ipv4Addr::new(10, 0, 0, 1).into(),
+            subject: String::new(),
+            message: String::new(),
+            packet: None,
+        };
+        writer
+            .write_packet(secs(21), &frame(1, 3, 4000, 53))
+            .unwrap();
+        writer.flag_alerts(&[alert]);
+        writer
+            .write_packet(secs(21), &frame(1, 4, 4000, 53))
+            .unwrap();
+
+        assert_eq!(
+            writer.stats(),
+            RetentionStats {
+                packets: 4,
+                retained: 3,
+                truncated: 1,
+                bytes_saved: 100,
+            }
+        );
+        let data = writer.into_inner().unwrap();
+        let lens: Vec<_> = PcapReader::new(Cursor::new(data))
+            .map(|(header, _)| (header.incl_len, header.orig_len))
+            .collect();
+        assert_eq!(
+            lens,
+            [
+                (142, 142),
+                (42, 142),
+                (142, 142),
+                (142, 142),
+                (42, 142),
+                (142, 142),
+                (42, 142)
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Zeek `conn.log` output.
+//!
+//! A [`ZeekConnWriter`] writes flow records in the TSV format of Zeek's
+//! connection log, header included, so tools and pipelines reading Zeek
+//! logs take netkit flows unchanged. The UID of a connection is derived
+//! from its [EVE flow ID](super::eve::flow_id), so both outputs of a probe
+//! can be joined.
+//!
+//! Only what a [`BiflowRecord`] knows is filled in; the other fields, such
+//! as the connection state, are unset (`-`).
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::{
+    bundle::rfc3339,
+    eve::{flow_id, has_ports},
+    ipfix::BiflowRecord,
+};
+
+/// Fields of the log, in order.
+pub const FIELDS: [(&str, &str); 21] = [
+    ("ts", "time"),
+    ("uid", "string"),
+    ("id.orig_h", "addr"),
+    ("id.orig_p", "port"),
+    ("id.resp_h", "addr"),
+    ("id.resp_p", "port"),
+    ("proto", "enum"),
+    ("service", "string"),
+    ("duration", "interval"),
+    ("orig_bytes", "count"),
+    ("resp_bytes", "count"),
+    ("conn_state", "string"),
+    ("local_orig", "bool"),
+    ("local_resp", "bool"),
+    ("missed_bytes", "count"),
+    ("history", "string"),
+    ("orig_pkts", "count"),
+    ("orig_ip_bytes", "count"),
+    ("resp_pkts", "count"),
+    ("resp_ip_bytes", "count"),
+    ("tunnel_parents", "set[string]"),
+];
+
+/// Get the Zeek UID of a flow record: `C` followed by its flow ID in base 62.
+pub fn uid(record: &BiflowRecord) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let mut id = flow_id(record);
+    let mut digits = Vec::new();
+    while id > 0 || digits.is_empty() {
+        digits.push(DIGITS[(id % 62) as usize]);
+        id /= 62;
+    }
+    digits.push(b'C');
+    digits.iter().rev().map(|&d| d as char).collect()
+}
+
+/// Format a time as Zeek does in the header, e.g. `2023-11-14-22-13-20`.
+fn header_time(ts: Duration) -> String {
+    rfc3339(ts)[..19].replace(['T', ':'], "-")
+}
+
+/// Writer of Zeek `conn.log` files.
+///
+/// The header is written with the first record, `#open` being its start;
+/// [`finish`](Self::finish) writes the `#close` line.
+#[derive(Debug)]
+pub struct ZeekConnWriter<W: Write> {
+    writer: W,
+    last: Option<Duration>,
+    records: u64,
+}
+
+impl<W: Write> ZeekConnWriter<W> {
+    /// Write the log to a writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            last: None,
+            records: 0,
+        }
+    }
+
+    /// Get the number of records written.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Write a flow record.
+    pub fn write_record(&mut self, record: &BiflowRecord) -> io::Result<()> {
+        if self.last.is_none() {
+            self.write_header(record.start)?;
+        }
+        self.last = self.last.max(Some(record.end));
+
+        let key = &record.key;
+        let proto = match key.protocol {
+            IpProtocol::Tcp => "tcp",
+            IpProtocol::Udp => "udp",
+            IpProtocol::Icmp | IpProtocol::Ipv6Icmp => "icmp",
+            _ => "unknown_transport",
+        };
+        let port = |port: u16| {
+            if has_ports(key.protocol) {
+                port.to_string()
+            } else {
+                "0".to_string()
+            }
+        };
+        writeln!(
+            self.writer,
+            "{:.6}\t{}\t{}\t{}\t{}\t{}\t{proto}\t-\t{:.6}\t-\t-\t-\t-\t-\t0\t-\t{}\t{}\t{}\t{}\t-",
+            record.start.as_secs_f64(),
+            uid(record),
+            key.src,
+            port(key.src_port),
+            key.dst,
+            port(key.dst_port),
+            record.end.saturating_sub(record.start).as_secs_f64(),
+            record.packets,
+            record.bytes,
+            record.reverse_packets,
+            record.reverse_bytes,
+        )?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Write the `#close` line, if a record was written, and get the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(last) = self.last {
+            writeln!(self.writer, "#close\t{}", header_time(last))?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self, open: Duration) -> io::Result<()> {
+        let names: Vec<_> = FIELDS.iter().map(|(name, _)| *name).collect();
+        let types: Vec<_> = FIELDS.iter().map(|(_, ty)| *ty).collect();
+        write!(
+            self.writer,
+            "#separator \\x09\n#set_separator\t,\n#empty_field\t(empty)\n#unset_field\t-\n\
+             #path\tconn\n#open\t{}\n#fields\t{}\n#types\t{}\n",
+            header_time(open),
+            names.join("\t"),
+            types.join("\t"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    #[test]
+    fn zeek_conn_log() {
+        let record = BiflowRecord {
+            key: FlowKey::new(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53)),
+                40000,
+                53,
+                IpProtocol::Udp,
+            ),
+            start: Duration::from_secs(1_700_000_000),
+            end: Duration::from_millis(1_700_000_000_250),
+            packets: 1,
+            bytes: 60,
+            reverse_packets: 1,
+            reverse_bytes: 120,
+        };
+        let mut zeek = ZeekConnWriter::new(Vec::new());
+        zeek.write_record(&record).unwrap();
+        assert_eq!(zeek.records(), 1);
+        let log = String::from_utf8(zeek.finish().unwrap()).unwrap();
+
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "#separator \\x09");
+        assert_eq!(lines[5], "#open\t2023-11-14-22-13-20");
+        assert!(lines[6].starts_with("#fields\tts\tuid\tid.orig_h"));
+        let row: Vec<_> = lines[8].split('\t').collect();
+        assert_eq!(row.len(), FIELDS.len());
+        assert_eq!(row[0], "1700000000.000000");
+        assert_eq!(row[1], uid(&record));
+        assert!(row[1].starts_with('C'));
+        assert_eq!(
+            &row[2..9],
+            [
+                "10.0.0.1",
+                "40000",
+                "192.0.2.53",
+                "53",
+                "udp",
+                "-",
+                "0.250000"
+            ]
+        );
+        assert_eq!(&row[16..], ["1", "60", "1", "120", "-"]);
+        assert_eq!(lines[9], "#close\t2023-11-14-22-13-20");
+    }
+}
@@ -0,0 +1,48 @@
+//! Frames shared by the unit tests, crafted with
+//! [`netkit_packet::gen::craft`].
+
+use std::net::SocketAddr;
+
+use netkit_packet::{
+    gen::craft,
+    layer::tcp::{TcpBuilder, TcpFlags},
+};
+
+/// MAC address of both ends of crafted frames.
+const MAC: [u8; 6] = [0; 6];
+
+/// Build an Ethernet frame carrying a TCP segment from `src` to `dst`.
+pub fn tcp_frame(
+    src: impl Into<SocketAddr>,
+    dst: impl Into<SocketAddr>,
+    seq: u32,
+    flags: TcpFlags,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut tcp = TcpBuilder::new();
+    tcp.seq_num(seq).flags(flags).payload(payload);
+    tcp_frame_with(src, dst, &tcp)
+}
+
+/// Build an Ethernet frame carrying a TCP segment from `src` to `dst`,
+/// with the other fields taken from a builder.
+pub fn tcp_frame_with(
+    src: impl Into<SocketAddr>,
+    dst: impl Into<SocketAddr>,
+    tcp: &TcpBuilder,
+) -> Vec<u8> {
+    craft::tcp_segment_with(MAC, MAC, src.into(), dst.into(), tcp)
+        .inner()
+        .clone()
+}
+
+/// Build an Ethernet frame carrying a UDP datagram from `src` to `dst`.
+pub fn udp_frame(
+    src: impl Into<SocketAddr>,
+    dst: impl Into<SocketAddr>,
+    payload: impl AsRef<[u8]>,
+) -> Vec<u8> {
+    craft::udp_datagram(MAC, MAC, src.into(), dst.into(), payload)
+        .inner()
+        .clone()
+}
@@ -0,0 +1,5 @@
+//! Textual packet output formats.
+
+pub mod sequence;
+pub mod tcpdump;
+pub mod theme;
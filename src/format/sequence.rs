@@ -0,0 +1,290 @@
+//! Sequence diagrams of conversations.
+//!
+//! [`SequenceDiagram`] collects the packets exchanged between endpoints and
+//! renders them as a [Mermaid](https://mermaid.js.org) sequence diagram or as
+//! plain text, with TCP flags, sequence numbers, payload sizes and times
+//! relative to the first packet.
+//!
+//! ```text
+//!                       10.0.0.1:40000                       10.0.0.2:80
+//! 0.000000                      |--------[S] seq 1000 len 0-------->|
+//! 0.010000                      |<--[S.] seq 5000 ack 1001 len 0----|
+//! ```
+
+use std::{fmt::Write as _, net::SocketAddr, time::Duration};
+
+use netkit_packet::{layer::tcp::TcpFlags, prelude::*};
+
+use super::tcpdump::tcp_flags;
+use crate::analysis::Analyzer;
+
+/// Default maximum number of messages kept.
+pub const DEFAULT_MAX_MESSAGES: usize = 1000;
+
+/// A packet of the diagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// Time since the first packet of the diagram.
+    pub time: Duration,
+    /// Sending endpoint.
+    pub from: SocketAddr,
+    /// Receiving endpoint.
+    pub to: SocketAddr,
+    /// Summary of the packet.
+    pub label: String,
+}
+
+/// Sequence diagram builder.
+#[derive(Clone, Debug)]
+pub struct SequenceDiagram {
+    conversation: Option<FlowKey>,
+    max_messages: usize,
+    first: Option<Duration>,
+    participants: Vec<SocketAddr>,
+    messages: Vec<Message>,
+    dropped: u64,
+}
+
+impl Default for SequenceDiagram {
+    fn default() -> Self {
+        Self {
+            conversation: None,
+            max_messages: DEFAULT_MAX_MESSAGES,
+            first: None,
+            participants: Vec::new(),
+            messages: Vec::new(),
+            dropped: 0,
+        }
+    }
+}
+
+impl SequenceDiagram {
+    /// Create a new sequence diagram of all packets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep the packets of the conversation of `key`, both directions.
+    pub fn conversation(&mut self, key: FlowKey) -> &mut Self {
+        self.conversation = Some(key);
+        self
+    }
+
+    /// Set the maximum number of messages; later packets are dropped.
+    pub fn max_messages(&mut self, max_messages: usize) -> &mut Self {
+        self.max_messages = max_messages;
+        self
+    }
+
+    /// Get the endpoints, in order of appearance.
+    pub fn participants(&self) -> &[SocketAddr] {
+        &self.participants
+    }
+
+    /// Get the messages.
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Get the number of packets dropped beyond the maximum.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Render as a Mermaid sequence diagram.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("sequenceDiagram\n");
+        for (i, participant) in self.participants.iter().enumerate() {
+            let _ = writeln!(out, "    participant P{i} as {participant}");
+        }
+        for message in &self.messages {
+            let _ = writeln!(
+                out,
+                "    P{}->>P{}: +{:.6}s {}",
+                self.index(message.from),
+                self.index(message.to),
+                message.time.as_secs_f64(),
+                message.label
+            );
+        }
+        if self.dropped > 0 {
+            let _ = writeln!(out, "    Note over P0: {} more packets", self.dropped);
+        }
+        out
+    }
+
+    /// Render as a plain text diagram, one lane per endpoint.
+    pub fn to_text(&self) -> String {
+        const TIME_WIDTH: usize = 12;
+
+        let widest_name = self.participants.iter().map(|p| p.to_string().len());
+        let widest_label = self.messages.iter().map(|m| m.label.len() + 6);
+        let width = widest_name.chain(widest_label).max().unwrap_or(0) + 2;
+        let lane = |i: usize| TIME_WIDTH + i * width + width / 2;
+
+        let mut out = " ".repeat(TIME_WIDTH);
+        for participant in &self.participants {
+            let _ = write!(out, "{:^width$}", participant.to_string());
+        }
+        out = out.trim_end().to_string();
+        out.push('\n');
+
+        for message in &self.messages {
+            let (from, to) = (self.index(message.from), self.index(message.to));
+            let mut line = vec![' '; lane(self.participants.len() - 1) + 1];
+            for i in 0..self.participants.len() {
+                line[lane(i)] = '|';
+            }
+
+            let (left, right) = (lane(from.min(to)), lane(from.max(to)));
+            line[left + 1..right].fill('-');
+            if from < to {
+                line[right - 1] = '>';
+            } else if from > to {
+                line[left + 1] = '<';
+            }
+            let span = right.saturating_sub(left + 3);
+            let label: Vec<char> = message.label.chars().take(span).collect();
+            let start = left + 2 + (span - label.len()) / 2;
+            line[start..start + label.len()].copy_from_slice(&label);
+
+            let time = format!("{:.6}", message.time.as_secs_f64());
+            let time: Vec<char> = time.chars().take(TIME_WIDTH - 1).collect();
+            line[..time.len()].copy_from_slice(&time);
+
+            out.extend(line);
+            out.push('\n');
+        }
+        if self.dropped > 0 {
+            let _ = writeln!(out, "... {} more packets", self.dropped);
+        }
+        out
+    }
+
+    fn index(&self, endpoint: SocketAddr) -> usize {
+        self.participants
+            .iter()
+            .position(|p| *p == endpoint)
+            .unwrap_or_default()
+    }
+
+    fn participant(&mut self, endpoint: SocketAddr) {
+        if !self.participants.contains(&endpoint) {
+            self.participants.push(endpoint);
+        }
+    }
+
+    fn on_ipv4(&mut self, ts: Duration, ipv4: &Ipv4<&[u8]>) {
+        let key = FlowKey::from_ipv4(ipv4);
+        if let Some(conversation) = self.conversation {
+            if key != conversation && key != conversation.reversed() {
+                return;
+            }
+        }
+        if self.messages.len() >= self.max_messages {
+            self.dropped += 1;
+            return;
+        }
+
+        let label = if let Some(tcp) = ipv4.tcp() {
+            let flags = tcp.flags().get();
+            let mut label = format!("[{}] seq {}", tcp_flags(flags), tcp.seq_num().get());
+            if flags.contains(TcpFlags::ACK) {
+                let _ = write!(label, " ack {}", tcp.ack_num().get());
+            }
+            let _ = write!(label, " len {}", tcp.payload().len());
+            label
+        } else if let Some(udp) = ipv4.udp() {
+            format!("UDP len {}", udp.payload().len())
+        } else {
+            format!(
+                "ip-proto-{} len {}",
+                u8::from(ipv4.protocol().get()),
+                ipv4.payload().len()
+            )
+        };
+
+        let from = SocketAddr::new(key.src, key.src_port);
+        let to = SocketAddr::new(key.dst, key.dst_port);
+        self.participant(from);
+        self.participant(to);
+
+        let first = *self.first.get_or_insert(ts);
+        self.messages.push(Message {
+            time: ts.saturating_sub(first),
+            from,
+            to,
+            label,
+        });
+    }
+}
+
+impl Analyzer for SequenceDiagram {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.on_ipv4(ts, &ipv4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpBuilder;
+
+    use super::*;
+    use crate::fixture::tcp_frame_with;
+
+    const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn frame(from_a: bool, seq: u32, ack: u32, flags: TcpFlags) -> Vec<u8> {
+        let (src, dst) = if from_a {
+            ((A, 40000), (B, 80))
+        } else {
+            ((B, 80), (A, 40000))
+        };
+        let mut tcp = TcpBuilder::new();
+        tcp.seq_num(seq).ack_num(ack).flags(flags);
+        tcp_frame_with(src, dst, &tcp)
+    }
+
+    #[test]
+    fn sequence_diagram_render() {
+        let mut diagram = SequenceDiagram::new();
+        diagram.max_messages(2);
+        diagram.on_packet(Duration::from_secs(5), &frame(true, 1000, 0, TcpFlags::SYN));
+        diagram.on_packet(
+            Duration::from_millis(5010),
+            &frame(false, 5000, 1001, TcpFlags::SYN | TcpFlags::ACK),
+        );
+        diagram.on_packet(
+            Duration::from_millis(5020),
+            &frame(true, 1001, 5001, TcpFlags::ACK),
+        );
+
+        assert_eq!(diagram.dropped(), 1);
+        assert_eq!(
+            diagram.to_mermaid(),
+            "sequenceDiagram\n\
+             \x20   participant P0 as 10.0.0.1:40000\n\
+             \x20   participant P1 as 10.0.0.2:80\n\
+             \x20   P0->>P1: +0.000000s [S] seq 1000 len 0\n\
+             \x20   P1->>P0: +0.010000s [S.] seq 5000 ack 1001 len 0\n\
+             \x20   Note over P0: 1 more packets\n"
+        );
+
+        let text = diagram.to_text();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("10.0.0.1:40000") && lines[0].contains("10.0.0.2:80"));
+        assert!(lines[1].starts_with("0.000000"));
+        assert!(lines[1].contains("--[S] seq 1000 len 0--"));
+        assert!(lines[2].starts_with("0.010000") && lines[2].contains("|<--[S.]"));
+    }
+}
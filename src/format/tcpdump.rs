@@ -0,0 +1,522 @@
+//! tcpdump-style one-line packet summaries.
+//!
+//! The output follows `tcpdump -n -S`: addresses are not resolved and TCP
+//! sequence numbers are absolute, so lines can be diffed against tcpdump
+//! output directly.
+//!
+//! ```text
+//! 12:00:00.000000 IP 10.0.0.1.40000 > 10.0.0.2.80: Flags [S], seq 1000, win 64240, length 0
+//! 12:00:00.000100 IP 10.0.0.2.53 > 10.0.0.1.40000: UDP, length 12
+//! ```
+//!
+//! With a colored [`Theme`], the parts of a line are colored by layer, and
+//! packets with bad checksums or retransmitted TCP payload are highlighted;
+//! the text itself is unchanged.
+
+use std::{collections::HashMap, fmt::Write as _, io, time::Duration};
+
+use netkit_packet::{
+    layer::tcp::{MptcpOption, TcpFlags, TcpOption},
+    prelude::*,
+    utils::internet_checksum,
+};
+
+use super::theme::Theme;
+
+/// Timestamp format, matching the tcpdump `-t` options.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// Time of day (UTC), the tcpdump default.
+    #[default]
+    TimeOfDay,
+    /// Seconds since the epoch (`-tt`).
+    Epoch,
+    /// Time since the previous packet (`-ttt`).
+    Delta,
+    /// Time since the first packet (`-ttttt`).
+    SinceFirst,
+    /// No timestamp (`-t`).
+    None,
+}
+
+/// tcpdump-style formatter.
+#[derive(Clone, Debug, Default)]
+pub struct TcpdumpFormatter {
+    timestamp: TimestampFormat,
+    link_header: bool,
+    theme: Theme,
+    first: Option<Duration>,
+    prev: Option<Duration>,
+    next_seq: HashMap<FlowKey, u32>,
+}
+
+impl TcpdumpFormatter {
+    /// Create a new formatter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timestamp format.
+    pub fn timestamp(&mut self, timestamp: TimestampFormat) -> &mut Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Set whether to print the link-level header (`-e`).
+    pub fn link_header(&mut self, link_header: bool) -> &mut Self {
+        self.link_header = link_header;
+        self
+    }
+
+    /// Set the color theme.
+    pub fn theme(&mut self, theme: Theme) -> &mut Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Format an Ethernet frame captured at `ts` (since the epoch).
+    pub fn format(&mut self, ts: Duration, frame: &[u8]) -> String {
+        let mut line = String::new();
+        self.write_timestamp(&mut line, ts);
+
+        let Ok(eth) = Eth::new(frame) else {
+            let _ = write!(line, "[|ether] length {}", frame.len());
+            return line;
+        };
+
+        let mut eth_type = eth.eth_type().get();
+        let mut payload = eth.payload();
+        let mut link = String::new();
+        if self.link_header {
+            let _ = write!(
+                link,
+                "{} > {}, ethertype {}, length {}: ",
+                mac(&eth.src().get()),
+                mac(&eth.dst().get()),
+                eth_type_name(eth_type),
+                frame.len()
+            );
+        }
+
+        while matches!(eth_type, EthType::Vlan | EthType::ServiceVlan) {
+            let Ok(vlan) = Vlan::new(payload) else {
+                line.push_str(&self.theme.paint(self.theme.palette.link, &link));
+                line.push_str("[|vlan]");
+                return line;
+            };
+            eth_type = vlan.eth_type().get();
+            payload = &payload[payload.len() - vlan.payload().len()..];
+            if self.link_header {
+                let _ = write!(
+                    link,
+                    "vlan {}, p {}, ethertype {}, ",
+                    vlan.vid().get(),
+                    vlan.pcp().get(),
+                    eth_type_name(eth_type)
+                );
+            }
+        }
+
+        match eth_type {
+            EthType::Ipv4 => {
+                line.push_str(&self.theme.paint(self.theme.palette.link, &link));
+                self.write_ipv4(&mut line, payload);
+            }
+            _ if self.link_header => {
+                let _ = write!(link, "length {}", payload.len());
+                line.push_str(&self.theme.paint(self.theme.palette.link, &link));
+            }
+            _ => {
+                let link = format!(
+                    "ethertype {}, length {}",
+                    eth_type_name(eth_type),
+                    frame.len()
+                );
+                line.push_str(&self.theme.paint(self.theme.palette.link, &link));
+            }
+        }
+        line
+    }
+
+    /// Format an Ethernet frame and write it as a line.
+    pub fn write<W: io::Write>(
+        &mut self,
+        writer: &mut W,
+        ts: Duration,
+        frame: &[u8],
+    ) -> io::Result<()> {
+        writeln!(writer, "{}", self.format(ts, frame))
+    }
+
+    fn write_timestamp(&mut self, line: &mut String, ts: Duration) {
+        let first = *self.first.get_or_insert(ts);
+        let prev = self.prev.replace(ts).unwrap_or(ts);
+
+        let mut stamp = String::new();
+        let _ = match self.timestamp {
+            TimestampFormat::TimeOfDay => {
+                let secs = ts.as_secs() % 86400;
+                write!(
+                    stamp,
+                    "{:02}:{:02}:{:02}.{:06} ",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60,
+                    ts.subsec_micros()
+                )
+            }
+            TimestampFormat::Epoch => {
+                write!(stamp, "{}.{:06} ", ts.as_secs(), ts.subsec_micros())
+            }
+            TimestampFormat::Delta => write_elapsed(&mut stamp, ts.saturating_sub(prev)),
+            TimestampFormat::SinceFirst => write_elapsed(&mut stamp, ts.saturating_sub(first)),
+            TimestampFormat::None => Ok(()),
+        };
+        line.push_str(&self.theme.paint(self.theme.palette.timestamp, &stamp));
+    }
+
+    fn write_ipv4(&mut self, line: &mut String, data: &[u8]) {
+        let network = self.theme.palette.network;
+        let Ok(ipv4) = Ipv4::new(data) else {
+            line.push_str(&self.theme.paint(network, "IP [|ip]"));
+            return;
+        };
+        let (src, dst) = (ipv4.src().get(), ipv4.dst().get());
+
+        let header_len = ipv4.ihl().get() as usize * 4;
+        let bad_header = data
+            .get(..header_len)
+            .is_none_or(|header| internet_checksum(header) != 0);
+        let network = if bad_header {
+            self.theme.palette.anomaly
+        } else {
+            network
+        };
+
+        let (header, transport, anomaly) = if let Some(tcp) = ipv4.tcp() {
+            let header = format!(
+                "IP {src}.{} > {dst}.{}: ",
+                tcp.src_port().get(),
+                tcp.dst_port().get()
+            );
+            let mut transport = String::new();
+            write_tcp(&mut transport, &tcp);
+            let bad_checksum = ipv4.transport_checksum() != Some(tcp.checksum().get());
+            let anomaly = bad_checksum || self.is_retransmission(&ipv4, &tcp);
+            (header, transport, anomaly)
+        } else if let Some(udp) = ipv4.udp() {
+            let header = format!(
+                "IP {src}.{} > {dst}.{}: ",
+                udp.src_port().get(),
+                udp.dst_port().get()
+            );
+            let transport = format!("UDP, length {}", udp.payload().len());
+            let checksum = udp.checksum().get();
+            let anomaly = checksum != 0 && ipv4.transport_checksum() != Some(checksum);
+            (header, transport, anomaly)
+        } else {
+            let header = format!("IP {src} > {dst}: ");
+            let transport = format!(
+                "ip-proto-{} {}",
+                u8::from(ipv4.protocol().get()),
+                ipv4.payload().len()
+            );
+            (header, transport, false)
+        };
+
+        let transport_style = if anomaly {
+            self.theme.palette.anomaly
+        } else {
+            self.theme.palette.transport
+        };
+        line.push_str(&self.theme.paint(network, &header));
+        line.push_str(&self.theme.paint(transport_style, &transport));
+    }
+
+    /// Check whether a segment only carries payload sent before.
+    ///
+    /// Only tracked with colors enabled, as the plain output has no use for
+    /// it.
+    fn is_retransmission(&mut self, ipv4: &Ipv4<&[u8]>, tcp: &Tcp<&[u8]>) -> bool {
+        if !self.theme.enabled {
+            return false;
+        }
+
+        let flags = tcp.flags().get();
+        let len = tcp.payload().len() as u32;
+        let syn_fin = flags.intersects(TcpFlags::SYN | TcpFlags::FIN) as u32;
+        let end = tcp.seq_num().get().wrapping_add(len + syn_fin);
+
+        let key = FlowKey::from_ipv4(ipv4);
+        let next = match self.next_seq.get_mut(&key) {
+            Some(next) if !flags.contains(TcpFlags::SYN) => next,
+            _ => {
+                self.next_seq.insert(key, end);
+                return false;
+            }
+        };
+        let old = len > 0 && next.wrapping_sub(end) < 1 << 31;
+        if end.wrapping_sub(*next) < 1 << 31 {
+            *next = end;
+        }
+        old
+    }
+}
+
+fn write_elapsed(line: &mut String, elapsed: Duration) -> core::fmt::Result {
+    let secs = elapsed.as_secs();
+    write!(
+        line,
+        "{:02}:{:02}:{:02}.{:06} ",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        elapsed.subsec_micros()
+    )
+}
+
+fn mac(addr: &EthAddr) -> String {
+    addr.to_string().to_lowercase()
+}
+
+fn eth_type_name(eth_type: EthType) -> String {
+    let name = match eth_type {
+        EthType::Ipv4 => "IPv4",
+        EthType::Arp => "ARP",
+        EthType::Ipv6 => "IPv6",
+        EthType::Eapol => "EAPOL",
+        EthType::Vlan => "802.1Q",
+        EthType::ServiceVlan => "802.1Q-QinQ",
+        _ => "Unknown",
+    };
+    format!("{name} (0x{:04x})", u16::from(eth_type))
+}
+
+fn write_tcp(line: &mut String, tcp: &Tcp<&[u8]>) {
+    let flags = tcp.flags().get();
+    let seq = tcp.seq_num().get();
+    let len = tcp.payload().len() as u32;
+
+    let _ = write!(line, "Flags [{}]", tcp_flags(flags));
+    if len > 0 {
+        let _ = write!(line, ", seq {seq}:{}", seq.wrapping_add(len));
+    } else if flags.intersects(TcpFlags::SYN | TcpFlags::FIN | TcpFlags::RST) {
+        let _ = write!(line, ", seq {seq}");
+    }
+    if flags.contains(TcpFlags::ACK) {
+        let _ = write!(line, ", ack {}", tcp.ack_num().get());
+    }
+    let _ = write!(line, ", win {}", tcp.window_size().get());
+    if flags.contains(TcpFlags::URG) {
+        let _ = write!(line, ", urg {}", tcp.urgent_pointer().get());
+    }
+
+    let options: Vec<_> = tcp.option_iter().map(tcp_option).collect();
+    if !options.is_empty() {
+        let _ = write!(line, ", options [{}]", options.join(","));
+    }
+    let _ = write!(line, ", length {len}");
+}
+
+/// Format TCP flags in tcpdump order, `.` standing for ACK.
+pub(super) fn tcp_flags(flags: TcpFlags) -> String {
+    let chars: String = [
+        (TcpFlags::FIN, 'F'),
+        (TcpFlags::SYN, 'S'),
+        (TcpFlags::RST, 'R'),
+        (TcpFlags::PSH, 'P'),
+        (TcpFlags::ACK, '.'),
+        (TcpFlags::URG, 'U'),
+        (TcpFlags::ECE, 'E'),
+        (TcpFlags::CWR, 'W'),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| flags.contains(*flag))
+    .map(|(_, c)| c)
+    .collect();
+
+    if chars.is_empty() {
+        "none".to_string()
+    } else {
+        chars
+    }
+}
+
+fn tcp_option(option: TcpOption) -> String {
+    match option {
+        TcpOption::Eol => "eol".to_string(),
+        TcpOption::Nop => "nop".to_string(),
+        TcpOption::Mss(mss) => format!("mss {mss}"),
+        TcpOption::WindowScale(shift) => format!("wscale {shift}"),
+        TcpOption::SackPermitted => "sackOK".to_string(),
+        TcpOption::Sack(blocks) => {
+            let mut sack = format!("sack {}", blocks.len() / 8);
+            for block in blocks.chunks_exact(8) {
+                let left = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+                let right = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+                let _ = write!(sack, " {{{left}:{right}}}");
+            }
+            sack
+        }
+        TcpOption::Timestamps { value, echo } => format!("TS val {value} ecr {echo}"),
+        TcpOption::FastOpen([]) => "tfo cookiereq".to_string(),
+        TcpOption::FastOpen(cookie) => {
+            let mut tfo = "tfo cookie ".to_string();
+            for byte in cookie {
+                let _ = write!(tfo, "{byte:02x}");
+            }
+            tfo
+        }
+        TcpOption::Mptcp(option) => mptcp_option(option),
+        TcpOption::Unknown { kind, data } => {
+            let mut unknown = format!("unknown-{kind}");
+            if !data.is_empty() {
+                unknown.push(' ');
+                for byte in data {
+                    let _ = write!(unknown, "{byte:02x}");
+                }
+            }
+            unknown
+        }
+    }
+}
+
+fn mptcp_option(option: MptcpOption) -> String {
+    match option {
+        MptcpOption::MpCapable { version, .. } => format!("mptcp capable v{version}"),
+        MptcpOption::MpJoin {
+            backup,
+            address_id,
+            token,
+        } => {
+            let mut join = "mptcp join".to_string();
+            if backup {
+                join.push_str(" backup");
+            }
+            let _ = write!(join, " id {address_id}");
+            if let Some(token) = token {
+                let _ = write!(join, " token 0x{token:x}");
+            }
+            join
+        }
+        MptcpOption::Dss {
+            data_ack,
+            data_seq,
+            data_fin,
+        } => {
+            let mut dss = "mptcp dss".to_string();
+            if data_fin {
+                dss.push_str(" fin");
+            }
+            if let Some(ack) = data_ack {
+                let _ = write!(dss, " ack {ack}");
+            }
+            if let Some(seq) = data_seq {
+                let _ = write!(dss, " seq {seq}");
+            }
+            dss
+        }
+        MptcpOption::Other { subtype, .. } => format!("mptcp subtype {subtype}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{tcp, udp};
+
+    use super::*;
+    use crate::format::theme::Palette;
+
+    const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn frame(protocol: IpProtocol, payload: &[u8]) -> Vec<u8> {
+        let ipv4 = ipv4!(src: A, dst: B, protocol: protocol, payload: payload);
+        eth!(
+            src: EthAddr::new(0, 0x11, 0x22, 0x33, 0x44, 0x55),
+            dst: EthAddr::new(0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb),
+            eth_type: EthType::Ipv4,
+            payload: ipv4.inner(),
+        )
+        .inner()
+        .clone()
+    }
+
+    #[test]
+    fn tcpdump_lines() {
+        let mut formatter = TcpdumpFormatter::new();
+        let ts = Duration::new(1_700_000_000, 123_456_000);
+
+        let syn = tcp!(
+            src_port: 40000u16,
+            dst_port: 80u16,
+            seq_num: 1000u32,
+            flags: TcpFlags::SYN,
+            window_size: 64240u16,
+            options: [2, 4, 0x05, 0xb4, 1, 3, 3, 7],
+        );
+        assert_eq!(
+            formatter.format(ts, &frame(IpProtocol::Tcp, syn.inner())),
+            "22:13:20.123456 IP 10.0.0.1.40000 > 10.0.0.2.80: Flags [S], seq 1000, \
+             win 64240, options [mss 1460,nop,wscale 7], length 0"
+        );
+
+        let data = tcp!(
+            src_port: 40000u16,
+            dst_port: 80u16,
+            seq_num: 1001u32,
+            ack_num: 5001u32,
+            flags: TcpFlags::PSH | TcpFlags::ACK,
+            window_size: 502u16,
+            payload: [0; 100],
+        );
+        formatter.timestamp(TimestampFormat::SinceFirst);
+        assert_eq!(
+            formatter.format(
+                ts + Duration::from_millis(1500),
+                &frame(IpProtocol::Tcp, data.inner())
+            ),
+            "00:00:01.500000 IP 10.0.0.1.40000 > 10.0.0.2.80: Flags [P.], seq 1001:1101, \
+             ack 5001, win 502, length 100"
+        );
+
+        let udp = udp!(src_port: 53u16, dst_port: 40000u16, payload: [0; 12]);
+        formatter.timestamp(TimestampFormat::None).link_header(true);
+        assert_eq!(
+            formatter.format(ts, &frame(IpProtocol::Udp, udp.inner())),
+            "00:11:22:33:44:55 > 66:77:88:99:aa:bb, ethertype IPv4 (0x0800), length 54: \
+             IP 10.0.0.1.53 > 10.0.0.2.40000: UDP, length 12"
+        );
+    }
+
+    #[test]
+    fn tcpdump_theme() {
+        let palette = Palette::default();
+        let theme = Theme::colored(palette);
+        let mut formatter = TcpdumpFormatter::new();
+        formatter.timestamp(TimestampFormat::None).theme(theme);
+
+        let segment = tcp!(src_port: 40000u16, dst_port: 80u16, seq_num: 1u32, payload: [0; 10]);
+        let mut ipv4 = ipv4!(src: A, dst: B, protocol: IpProtocol::Tcp, payload: segment.inner());
+        ipv4.update_transport_checksum();
+        let checksum = internet_checksum(&ipv4.inner()[..20]);
+        ipv4.checksum_mut().set(checksum);
+        let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone();
+
+        let header = theme.paint(palette.network, "IP 10.0.0.1.40000 > 10.0.0.2.80: ");
+        let transport = "Flags [none], seq 1:11, win 64, length 10";
+        assert_eq!(
+            formatter.format(Duration::ZERO, &frame),
+            header.clone() + &theme.paint(palette.transport, transport)
+        );
+        // Retransmission.
+        assert_eq!(
+            formatter.format(Duration::ZERO, &frame),
+            header + &theme.paint(palette.anomaly, transport)
+        );
+    }
+}
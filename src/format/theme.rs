@@ -0,0 +1,193 @@
+//! ANSI terminal color themes.
+//!
+//! A [`Theme`] colors the parts of textual output by layer and highlights
+//! anomalies. Colors follow the [NO_COLOR](https://no-color.org) convention:
+//! [`Theme::from_env`] disables them if `NO_COLOR` is set to a non-empty
+//! value, if `TERM` is `dumb`, or if stdout is not a terminal.
+
+use std::io::IsTerminal;
+
+/// Terminal color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// Black.
+    Black,
+    /// Red.
+    Red,
+    /// Green.
+    Green,
+    /// Yellow.
+    Yellow,
+    /// Blue.
+    Blue,
+    /// Magenta.
+    Magenta,
+    /// Cyan.
+    Cyan,
+    /// White.
+    White,
+    /// Color of the 256-color palette.
+    Fixed(u8),
+}
+
+impl Color {
+    fn code(&self) -> String {
+        match self {
+            Color::Black => "30".to_string(),
+            Color::Red => "31".to_string(),
+            Color::Green => "32".to_string(),
+            Color::Yellow => "33".to_string(),
+            Color::Blue => "34".to_string(),
+            Color::Magenta => "35".to_string(),
+            Color::Cyan => "36".to_string(),
+            Color::White => "37".to_string(),
+            Color::Fixed(n) => format!("38;5;{n}"),
+        }
+    }
+}
+
+/// Text style.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[must_use]
+pub struct Style {
+    /// Foreground color.
+    pub fg: Option<Color>,
+    /// Bold text.
+    pub bold: bool,
+    /// Dimmed text.
+    pub dim: bool,
+}
+
+impl Style {
+    /// Create a style with the given foreground color.
+    pub const fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            bold: false,
+            dim: false,
+        }
+    }
+
+    /// Make the style bold.
+    pub const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    /// Make the style dimmed.
+    pub const fn dim(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    /// Get the SGR escape sequence of the style, empty for the plain style.
+    pub fn prefix(&self) -> String {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.dim {
+            codes.push("2".to_string());
+        }
+        if let Some(fg) = self.fg {
+            codes.push(fg.code());
+        }
+
+        if codes.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", codes.join(";"))
+        }
+    }
+}
+
+/// Styles of the parts of an output line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// Timestamps.
+    pub timestamp: Style,
+    /// Link layer (Ethernet, VLAN).
+    pub link: Style,
+    /// Network layer (IP).
+    pub network: Style,
+    /// Transport layer (TCP, UDP).
+    pub transport: Style,
+    /// Application layer.
+    pub application: Style,
+    /// Anomalies, e.g. bad checksums or retransmissions.
+    pub anomaly: Style,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            timestamp: Style::default().dim(),
+            link: Style::fg(Color::Blue),
+            network: Style::fg(Color::Cyan),
+            transport: Style::fg(Color::Green),
+            application: Style::fg(Color::Magenta),
+            anomaly: Style::fg(Color::Red).bold(),
+        }
+    }
+}
+
+/// Color theme of textual output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Theme {
+    /// Styles of the output parts.
+    pub palette: Palette,
+    /// Whether colors are emitted.
+    pub enabled: bool,
+}
+
+impl Theme {
+    /// Create a theme without colors.
+    pub fn plain() -> Self {
+        Self::default()
+    }
+
+    /// Create a theme with colors of the given palette.
+    pub fn colored(palette: Palette) -> Self {
+        Self {
+            palette,
+            enabled: true,
+        }
+    }
+
+    /// Create a theme with the default palette, enabled if the environment
+    /// allows colors on stdout.
+    pub fn from_env() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let dumb = std::env::var_os("TERM").is_some_and(|v| v == "dumb");
+        Self {
+            palette: Palette::default(),
+            enabled: !no_color && !dumb && std::io::stdout().is_terminal(),
+        }
+    }
+
+    /// Wrap `text` in the escape sequences of `style`.
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        let prefix = style.prefix();
+        if !self.enabled || prefix.is_empty() || text.is_empty() {
+            return text.to_string();
+        }
+
+        format!("{prefix}{text}\x1b[0m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_paint() {
+        let style = Style::fg(Color::Red).bold();
+        assert_eq!(Theme::plain().paint(style, "bad"), "bad");
+
+        let theme = Theme::colored(Palette::default());
+        assert_eq!(theme.paint(style, "bad"), "\x1b[1;31mbad\x1b[0m");
+        assert_eq!(theme.paint(Style::default(), "ok"), "ok");
+        assert_eq!(Style::fg(Color::Fixed(208)).prefix(), "\x1b[38;5;208m");
+    }
+}
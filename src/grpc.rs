@@ -0,0 +1,551 @@
+//! Remote capture access over gRPC.
+//!
+//! A [`CaptureHub`] is fed packets like any [`Analyzer`], e.g. on the
+//! analysis thread of a probe, and a [`CaptureService`] serves what it sees
+//! to remote clients, as the `netkit.capture.Capture` service of
+//! `proto/capture.proto`:
+//!
+//! - `Subscribe` streams the records of the packets matching a filter, from
+//!   the subscription on;
+//! - `Flows` streams the summaries of the flows seen so far matching a
+//!   filter.
+//!
+//! Filters are in the pcap filter syntax of [`IndexFilter`]. Requests are
+//! checked by an [`Authenticator`], e.g. a [`BearerToken`], before anything
+//! is streamed.
+//!
+//! ```no_run
+//! use netkit::grpc::{BearerToken, CaptureHub, CaptureService};
+//!
+//! # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+//! let hub = CaptureHub::new();
+//! // Feed `hub.clone()` on the capture thread, e.g. with `analyze_channel`.
+//! let mut service = CaptureService::new(hub);
+//! service.authenticator(BearerToken::new("secret"));
+//! tonic::transport::Server::builder()
+//!     .add_service(service.into_server())
+//!     .serve("0.0.0.0:50051".parse()?)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Slow subscribers miss the records they fall behind on rather than
+//! stalling capture; see [`CaptureHub::lagged`].
+
+// `Status` is the error of every gRPC handler.
+#![allow(clippy::result_large_err)]
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use netkit_capture::file::{
+    index::{filter::IndexFilter, IndexEntry},
+    pcap::PacketHeader,
+};
+use netkit_packet::{
+    flow::{table::FlowTable, FlowKey},
+    layer::eth::Eth,
+};
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+use tonic::{metadata::MetadataMap, Request, Response, Status};
+
+use crate::analysis::Analyzer;
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/netkit.capture.Capture.rs"));
+}
+
+pub use generated::{
+    capture_client::CaptureClient,
+    capture_server::{Capture, CaptureServer},
+};
+
+/// Default number of records buffered per subscriber.
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// Request of `Subscribe`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribeRequest {
+    /// Filter in pcap filter syntax; empty matches every packet.
+    #[prost(string, tag = "1")]
+    pub filter: String,
+    /// Whether records carry the packet data.
+    #[prost(bool, tag = "2")]
+    pub include_data: bool,
+}
+
+/// Request of `Flows`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlowsRequest {
+    /// Filter in pcap filter syntax; empty matches every flow.
+    ///
+    /// Length primitives see a zero length.
+    #[prost(string, tag = "1")]
+    pub filter: String,
+}
+
+/// 5-tuple of a flow.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Flow {
+    /// Source address.
+    #[prost(string, tag = "1")]
+    pub src: String,
+    /// Destination address.
+    #[prost(string, tag = "2")]
+    pub dst: String,
+    /// Source port.
+    #[prost(uint32, tag = "3")]
+    pub src_port: u32,
+    /// Destination port.
+    #[prost(uint32, tag = "4")]
+    pub dst_port: u32,
+    /// IP protocol.
+    #[prost(uint32, tag = "5")]
+    pub protocol: u32,
+}
+
+impl From<&FlowKey> for Flow {
+    fn from(key: &FlowKey) -> Self {
+        Self {
+            src: key.src.to_string(),
+            dst: key.dst.to_string(),
+            src_port: key.src_port.into(),
+            dst_port: key.dst_port.into(),
+            protocol: u8::from(key.protocol).into(),
+        }
+    }
+}
+
+/// Packet streamed by `Subscribe`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CaptureRecord {
+    /// Capture time, in nanoseconds since the epoch.
+    #[prost(uint64, tag = "1")]
+    pub ts_ns: u64,
+    /// Length of the frame.
+    #[prost(uint32, tag = "2")]
+    pub orig_len: u32,
+    /// Flow of the packet, unset outside IP flows.
+    #[prost(message, optional, tag = "3")]
+    pub flow: Option<Flow>,
+    /// Frame, empty unless requested.
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: Vec<u8>,
+}
+
+/// Flow streamed by `Flows`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlowSummary {
+    /// The flow.
+    #[prost(message, optional, tag = "1")]
+    pub flow: Option<Flow>,
+    /// Number of packets.
+    #[prost(uint64, tag = "2")]
+    pub packets: u64,
+    /// Number of bytes.
+    #[prost(uint64, tag = "3")]
+    pub bytes: u64,
+    /// Capture time of the first packet, in nanoseconds since the epoch.
+    #[prost(uint64, tag = "4")]
+    pub first_ns: u64,
+    /// Capture time of the last packet, in nanoseconds since the epoch.
+    #[prost(uint64, tag = "5")]
+    pub last_ns: u64,
+}
+
+#[derive(Debug)]
+struct Packet {
+    ts: Duration,
+    frame: Vec<u8>,
+    flow: Option<FlowKey>,
+}
+
+impl Packet {
+    fn entry(&self) -> IndexEntry {
+        entry(self.ts, self.frame.len() as u32, self.flow)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FlowStats {
+    packets: u64,
+    bytes: u64,
+    first: Duration,
+    last: Duration,
+}
+
+#[derive(Debug)]
+struct Shared {
+    packets: broadcast::Sender<Arc<Packet>>,
+    flows: Mutex<FlowTable<FlowStats>>,
+    lagged: AtomicU64,
+}
+
+/// Source of the packets and flows streamed by a [`CaptureService`].
+///
+/// Cloning a hub gives another handle to the same hub.
+#[derive(Clone, Debug)]
+pub struct CaptureHub {
+    shared: Arc<Shared>,
+}
+
+impl Default for CaptureHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CaptureHub {
+    /// Create a hub buffering [`DEFAULT_CAPACITY`] records per subscriber.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create a hub buffering `capacity` records per subscriber.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                packets: broadcast::channel(capacity.max(1)).0,
+                flows: Mutex::new(FlowTable::new()),
+                lagged: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Get the number of subscribers.
+    pub fn subscribers(&self) -> usize {
+        self.shared.packets.receiver_count()
+    }
+
+    /// Get the number of records missed by subscribers falling behind.
+    pub fn lagged(&self) -> u64 {
+        self.shared.lagged.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of flows seen.
+    pub fn flow_count(&self) -> usize {
+        self.flows().len()
+    }
+
+    /// Forget the flows idle since before `before`, returning their number.
+    pub fn expire_flows(&self, before: Duration) -> usize {
+        let mut flows = self.flows();
+        let len = flows.len();
+        flows.retain(|_, stats| stats.last >= before);
+        len - flows.len()
+    }
+
+    fn flows(&self) -> std::sync::MutexGuard<'_, FlowTable<FlowStats>> {
+        self.shared
+            .flows
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Analyzer for CaptureHub {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let flow = Eth::new(frame).ok().and_then(|eth| FlowKey::from_eth(&eth));
+        if let Some(flow) = flow {
+            let mut flows = self.flows();
+            let stats = flows.entry(flow).or_insert(FlowStats {
+                packets: 0,
+                bytes: 0,
+                first: ts,
+                last: ts,
+            });
+            stats.packets += 1;
+            stats.bytes += frame.len() as u64;
+            stats.last = stats.last.max(ts);
+        }
+
+        if self.subscribers() > 0 {
+            let packet = Packet {
+                ts,
+                frame: frame.to_vec(),
+                flow,
+            };
+            // Only fails without subscribers.
+            let _ = self.shared.packets.send(Arc::new(packet));
+        }
+    }
+}
+
+/// Check of the requests of a [`CaptureService`].
+pub trait Authenticator: Send + Sync + 'static {
+    /// Check the metadata of a request, e.g. its `authorization` header.
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<(), Status>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&MetadataMap) -> Result<(), Status> + Send + Sync + 'static,
+{
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        self(metadata)
+    }
+}
+
+/// Authenticator accepting requests with an `authorization: Bearer <token>`
+/// header.
+#[derive(Clone, Debug)]
+pub struct BearerToken {
+    expected: String,
+}
+
+impl BearerToken {
+    /// Accept requests bearing the token.
+    pub fn new(token: impl AsRef<str>) -> Self {
+        Self {
+            expected: format!("Bearer {}", token.as_ref()),
+        }
+    }
+}
+
+impl Authenticator for BearerToken {
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        let given = metadata
+            .get("authorization")
+            .map(|value| value.as_bytes())
+            .unwrap_or_default();
+        // Compare in constant time for tokens of the expected length.
+        let expected = self.expected.as_bytes();
+        let diff = given.len() ^ expected.len()
+            | given
+                .iter()
+                .zip(expected)
+                .fold(0, |diff, (a, b)| diff | usize::from(a ^ b));
+        if diff == 0 {
+            Ok(())
+        } else {
+            Err(Status::unauthenticated("invalid bearer token"))
+        }
+    }
+}
+
+type ServiceStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// gRPC service streaming the packets and flows of a [`CaptureHub`].
+pub struct CaptureService {
+    hub: CaptureHub,
+    authenticator: Option<Arc<dyn Authenticator>>,
+}
+
+impl CaptureService {
+    /// Serve a hub to every client.
+    pub fn new(hub: CaptureHub) -> Self {
+        Self {
+            hub,
+            authenticator: None,
+        }
+    }
+
+    /// Set the check of the requests.
+    pub fn authenticator(&mut self, authenticator: impl Authenticator) -> &mut Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Wrap into a server to add to a `tonic` router.
+    pub fn into_server(self) -> CaptureServer<Self> {
+        CaptureServer::new(self)
+    }
+
+    fn check<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        match &self.authenticator {
+            Some(authenticator) => authenticator.authenticate(request.metadata()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_filter(filter: &str) -> Result<Option<IndexFilter>, Status> {
+    if filter.trim().is_empty() {
+        return Ok(None);
+    }
+    filter
+        .parse()
+        .map(Some)
+        .map_err(|error| Status::invalid_argument(format!("{error}")))
+}
+
+fn entry(ts: Duration, len: u32, flow: Option<FlowKey>) -> IndexEntry {
+    IndexEntry {
+        offset: 0,
+        header: PacketHeader {
+            ts_sec: ts.as_secs() as u32,
+            ts_usec: ts.subsec_micros(),
+            incl_len: len,
+            orig_len: len,
+        },
+        flow,
+    }
+}
+
+fn nanos(ts: Duration) -> u64 {
+    ts.as_nanos() as u64
+}
+
+#[tonic::async_trait]
+impl Capture for CaptureService {
+    type SubscribeStream = ServiceStream<CaptureRecord>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        self.check(&request)?;
+        let SubscribeRequest {
+            filter,
+            include_data,
+        } = request.into_inner();
+        let filter = parse_filter(&filter)?;
+
+        let hub = self.hub.clone();
+        let packets = BroadcastStream::new(hub.shared.packets.subscribe());
+        let records = packets.filter_map(move |packet| match packet {
+            Ok(packet) if filter.as_ref().is_none_or(|f| f.matches(&packet.entry())) => {
+                Some(Ok(CaptureRecord {
+                    ts_ns: nanos(packet.ts),
+                    orig_len: packet.frame.len() as u32,
+                    flow: packet.flow.as_ref().map(Flow::from),
+                    data: if include_data {
+                        packet.frame.clone()
+                    } else {
+                        Vec::new()
+                    },
+                }))
+            }
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                hub.shared.lagged.fetch_add(missed, Ordering::Relaxed);
+                None
+            }
+        });
+        Ok(Response::new(Box::pin(records)))
+    }
+
+    type FlowsStream = ServiceStream<FlowSummary>;
+
+    async fn flows(
+        &self,
+        request: Request<FlowsRequest>,
+    ) -> Result<Response<Self::FlowsStream>, Status> {
+        self.check(&request)?;
+        let filter = parse_filter(&request.into_inner().filter)?;
+
+        let mut flows: Vec<_> = self
+            .hub
+            .flows()
+            .iter()
+            .filter(|(key, stats)| {
+                filter
+                    .as_ref()
+                    .is_none_or(|f| f.matches(&entry(stats.first, 0, Some(**key))))
+            })
+            .map(|(key, stats)| (*key, *stats))
+            .collect();
+        flows.sort_by_key(|(_, stats)| stats.first);
+
+        let summaries = flows.into_iter().map(|(key, stats)| {
+            Ok(FlowSummary {
+                flow: Some(Flow::from(&key)),
+                packets: stats.packets,
+                bytes: stats.bytes,
+                first_ns: nanos(stats.first),
+                last_ns: nanos(stats.last),
+            })
+        });
+        Ok(Response::new(Box::pin(tokio_stream::iter(summaries))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    fn frame(dst_port: u16) -> Vec<u8> {
+        udp_frame(
+            (Ipv4Addr::new(10, 0, 0, 1), 1000),
+            (Ipv4Addr::new(10, 0, 0, 2), dst_port),
+            [0; 8],
+        )
+    }
+
+    #[test]
+    fn grpc_capture_service() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut hub = CaptureHub::with_capacity(8);
+        let mut service = CaptureService::new(hub.clone());
+        service.authenticator(BearerToken::new("secret"));
+
+        let request = |filter: &str| {
+            let mut request = Request::new(SubscribeRequest {
+                filter: filter.to_string(),
+                include_data: true,
+            });
+            let token = "Bearer secret".parse().unwrap();
+            request.metadata_mut().insert("authorization", token);
+            request
+        };
+        runtime.block_on(async {
+            let denied = service
+                .subscribe(Request::new(SubscribeRequest::default()))
+                .await;
+            assert_eq!(denied.err().unwrap().code(), tonic::Code::Unauthenticated);
+            let invalid = service.subscribe(request("port")).await;
+            assert_eq!(invalid.err().unwrap().code(), tonic::Code::InvalidArgument);
+
+            let mut records = service
+                .subscribe(request("udp and dst port 53"))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(hub.subscribers(), 1);
+            hub.on_packet(Duration::from_secs(1), &frame(53));
+            hub.on_packet(Duration::from_secs(2), &frame(123));
+            hub.on_packet(Duration::from_secs(3), &frame(53));
+
+            let record = records.next().await.unwrap().unwrap();
+            assert_eq!(record.ts_ns, 1_000_000_000);
+            assert_eq!(record.data, frame(53));
+            assert_eq!(record.flow.unwrap().dst_port, 53);
+            let record = records.next().await.unwrap().unwrap();
+            assert_eq!(record.ts_ns, 3_000_000_000);
+
+            let mut request = Request::new(FlowsRequest {
+                filter: "dst port 123".to_string(),
+            });
+            let token = "Bearer secret".parse().unwrap();
+            request.metadata_mut().insert("authorization", token);
+            let flows: Vec<_> = service
+                .flows(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .collect()
+                .await;
+            assert_eq!(flows.len(), 1);
+            let flow = flows[0].as_ref().unwrap();
+            assert_eq!((flow.packets, flow.first_ns), (1, 2_000_000_000));
+        });
+        assert_eq!(hub.flow_count(), 2);
+        assert_eq!(hub.expire_flows(Duration::from_secs(3)), 1);
+    }
+}
@@ -1,2 +1,20 @@
 pub use netkit_capture as capture;
 pub use netkit_packet as packet;
+
+pub mod analysis;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod error;
+pub mod export;
+#[cfg(test)]
+mod fixture;
+pub mod format;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod probe;
+#[cfg(feature = "config")]
+pub mod service;
+pub mod stats;
+pub mod testing;
+
+pub use error::Error;
@@ -0,0 +1,37 @@
+//! Active measurements.
+//!
+//! Probers send crafted packets through a [`ProbeSocket`] and infer path
+//! properties from the answers. The socket is left to the caller, e.g. a raw
+//! IPv4 socket or an injection handle paired with a capture, so probers are
+//! independent of the platform and can be driven against simulated paths.
+//! Link-level tools, like the [`selftest`] or the [`responder`], use a
+//! [`LinkSocket`] sending and receiving whole frames instead.
+
+use std::{io, time::Duration};
+
+pub mod pmtu;
+pub mod responder;
+pub mod selftest;
+
+/// A socket sending and receiving IPv4 packets.
+pub trait ProbeSocket {
+    /// Send an IPv4 packet.
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+
+    /// Receive an IPv4 packet, waiting at most the timeout.
+    ///
+    /// Returns `None` if no packet arrived in time.
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A socket sending and receiving Ethernet frames, e.g. a packet socket
+/// bound to an interface.
+pub trait LinkSocket {
+    /// Send an Ethernet frame.
+    fn send(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    /// Receive an Ethernet frame, waiting at most the timeout.
+    ///
+    /// Returns `None` if no frame arrived in time.
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
@@ -0,0 +1,437 @@
+//! Path MTU prober.
+//!
+//! The path MTU to a target is binary-searched with ICMP Echo Requests of
+//! varying size sent with the Don't Fragment flag. A reply means the size
+//! fits; a Fragmentation Needed message narrows the search to the next-hop
+//! MTU it reports; silence after the retries means the probe was dropped
+//! without feedback, the signature of a PMTU black hole.
+//!
+//! Once the MTU is known, the hop where it drops is located by walking the
+//! TTL with a probe one byte too large: routers before the bottleneck answer
+//! with Time Exceeded, the bottleneck answers with Fragmentation Needed or,
+//! if it is a black hole, not at all.
+
+use std::{
+    io,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use netkit_packet::{
+    layer::{icmp::CODE_FRAGMENTATION_NEEDED, ip::v4::FLAG_DONT_FRAGMENT},
+    prelude::*,
+};
+
+use super::ProbeSocket;
+
+/// Default largest MTU probed.
+pub const DEFAULT_MAX_MTU: u16 = 1500;
+
+/// Default smallest MTU probed, the minimum every IPv4 link must carry
+/// unfragmented in practice.
+pub const DEFAULT_MIN_MTU: u16 = 576;
+
+/// Default time to wait for an answer to a probe.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Default number of probes sent per size before giving up.
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Default largest TTL tried when locating the drop.
+pub const DEFAULT_MAX_HOPS: u8 = 30;
+
+/// Default ICMP identifier of the probes.
+pub const DEFAULT_IDENTIFIER: u16 = 0x6E6B;
+
+/// Size of the IPv4 and ICMP headers of a probe.
+const HEADER_LENGTH: u16 = 28;
+
+/// Where the MTU of a path drops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MtuDrop {
+    /// Hop count of the router forwarding onto the smaller link.
+    pub hop: u8,
+    /// Address of that router, if it answered any probe.
+    pub router: Option<Ipv4Addr>,
+    /// Next-hop MTU reported by the router, if any.
+    pub reported_mtu: Option<u16>,
+    /// Whether the router dropped the probe without feedback.
+    pub silent: bool,
+}
+
+/// Result of a path MTU measurement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PmtuResult {
+    /// Largest packet size reaching the target unfragmented.
+    pub mtu: u16,
+    /// Number of probes sent.
+    pub probes: u32,
+    /// Whether some larger size was dropped without any ICMP feedback.
+    pub black_hole: bool,
+    /// Where the MTU drops, if below the largest MTU probed and located.
+    pub drop: Option<MtuDrop>,
+}
+
+/// Answer to a probe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Answer {
+    Reply,
+    TooBig(Ipv4Addr, Option<u16>),
+    TimeExceeded(Ipv4Addr),
+    Timeout,
+}
+
+/// Path MTU prober.
+#[derive(Clone, Debug)]
+pub struct PmtuProber {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    min_mtu: u16,
+    max_mtu: u16,
+    timeout: Duration,
+    retries: u32,
+    max_hops: u8,
+    locate: bool,
+    identifier: u16,
+    sequence: u16,
+    probes: u32,
+}
+
+impl PmtuProber {
+    /// Create a new prober from a source to a target address.
+    pub fn new(src: Ipv4Addr, dst: Ipv4Addr) -> Self {
+        Self {
+            src,
+            dst,
+            min_mtu: DEFAULT_MIN_MTU,
+            max_mtu: DEFAULT_MAX_MTU,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            max_hops: DEFAULT_MAX_HOPS,
+            locate: true,
+            identifier: DEFAULT_IDENTIFIER,
+            sequence: 0,
+            probes: 0,
+        }
+    }
+
+    /// Set the smallest MTU probed.
+    pub fn min_mtu(&mut self, min_mtu: u16) -> &mut Self {
+        self.min_mtu = min_mtu.max(HEADER_LENGTH);
+        self
+    }
+
+    /// Set the largest MTU probed, usually the MTU of the local link.
+    pub fn max_mtu(&mut self, max_mtu: u16) -> &mut Self {
+        self.max_mtu = max_mtu;
+        self
+    }
+
+    /// Set the time to wait for an answer to a probe.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the number of probes sent per size before giving up.
+    pub fn retries(&mut self, retries: u32) -> &mut Self {
+        self.retries = retries.max(1);
+        self
+    }
+
+    /// Set the largest TTL tried when locating the drop.
+    pub fn max_hops(&mut self, max_hops: u8) -> &mut Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Set whether the hop where the MTU drops is located.
+    pub fn locate(&mut self, locate: bool) -> &mut Self {
+        self.locate = locate;
+        self
+    }
+
+    /// Set the ICMP identifier of the probes, telling concurrent probers
+    /// apart.
+    pub fn identifier(&mut self, identifier: u16) -> &mut Self {
+        self.identifier = identifier;
+        self
+    }
+
+    /// Measure the path MTU to the target.
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if even the smallest probe does
+    /// not reach the target.
+    pub fn probe<S: ProbeSocket>(&mut self, socket: &mut S) -> io::Result<PmtuResult> {
+        self.probes = 0;
+        if self.probe_size(socket, self.min_mtu, 64)? != Answer::Reply {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no reply from {} to the smallest probe", self.dst),
+            ));
+        }
+
+        let (mut lo, mut hi) = (self.min_mtu, self.max_mtu.max(self.min_mtu));
+        let mut next = Some(hi);
+        let mut black_hole = false;
+        while lo < hi {
+            let size = next.take().unwrap_or(lo + (hi - lo).div_ceil(2));
+            match self.probe_size(socket, size, 64)? {
+                Answer::Reply => lo = size,
+                Answer::TooBig(_, mtu) => {
+                    hi = size - 1;
+                    // Try the reported MTU next, usually the answer
+                    if let Some(mtu) = mtu.filter(|mtu| (lo + 1..=hi).contains(mtu)) {
+                        hi = mtu;
+                        next = Some(mtu);
+                    }
+                }
+                Answer::TimeExceeded(_) | Answer::Timeout => {
+                    hi = size - 1;
+                    black_hole = true;
+                }
+            }
+        }
+
+        let drop = if self.locate && lo < self.max_mtu {
+            self.locate_drop(socket, lo + 1)?
+        } else {
+            None
+        };
+
+        Ok(PmtuResult {
+            mtu: lo,
+            probes: self.probes,
+            black_hole,
+            drop,
+        })
+    }
+
+    fn locate_drop<S: ProbeSocket>(
+        &mut self,
+        socket: &mut S,
+        size: u16,
+    ) -> io::Result<Option<MtuDrop>> {
+        let mut last = None;
+        for ttl in 1..=self.max_hops {
+            match self.probe_size(socket, size, ttl)? {
+                Answer::TimeExceeded(router) => last = Some(router),
+                // The router answered Time Exceeded to the previous TTL
+                Answer::TooBig(router, mtu) => {
+                    return Ok(Some(MtuDrop {
+                        hop: ttl - 1,
+                        router: Some(router),
+                        reported_mtu: mtu,
+                        silent: false,
+                    }))
+                }
+                // The drop is behind the last hop answering
+                Answer::Timeout => {
+                    return Ok(Some(MtuDrop {
+                        hop: ttl - 1,
+                        router: last,
+                        reported_mtu: None,
+                        silent: true,
+                    }))
+                }
+                Answer::Reply => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    fn probe_size<S: ProbeSocket>(
+        &mut self,
+        socket: &mut S,
+        size: u16,
+        ttl: u8,
+    ) -> io::Result<Answer> {
+        for _ in 0..self.retries {
+            self.sequence = self.sequence.wrapping_add(1);
+            self.probes += 1;
+            let icmp = icmp!(
+                identifier: self.identifier,
+                sequence: self.sequence,
+                payload: vec![0; (size - HEADER_LENGTH) as usize],
+            );
+            let ipv4 = ipv4!(
+                src: self.src,
+                dst: self.dst,
+                ttl: ttl,
+                flags: FLAG_DONT_FRAGMENT,
+                protocol: IpProtocol::Icmp,
+                payload: icmp.inner(),
+            );
+            socket.send(ipv4.inner())?;
+
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let Some(packet) = socket.recv(remaining)? else {
+                    break;
+                };
+                if let Some(answer) = self.classify(&packet) {
+                    return Ok(answer);
+                }
+                if remaining.is_zero() {
+                    break;
+                }
+            }
+        }
+        Ok(Answer::Timeout)
+    }
+
+    fn classify(&self, packet: &[u8]) -> Option<Answer> {
+        let ipv4 = Ipv4::new(packet).ok()?;
+        let icmp = ipv4.icmp()?;
+        let ours = |icmp: &Icmp<&[u8]>| {
+            icmp.identifier().get() == self.identifier && icmp.sequence().get() == self.sequence
+        };
+
+        let from = ipv4.src().get();
+        match icmp.icmp_type().get() {
+            IcmpType::EchoReply if from == self.dst && ours(&icmp) => Some(Answer::Reply),
+            IcmpType::DestinationUnreachable | IcmpType::TimeExceeded => {
+                let original = icmp.original()?;
+                let quoted =
+                    Icmp::new(&original.inner()[original.ihl().get() as usize * 4..]).ok()?;
+                if original.dst().get() != self.dst || !ours(&quoted) {
+                    return None;
+                }
+                match icmp.icmp_type().get() {
+                    IcmpType::TimeExceeded => Some(Answer::TimeExceeded(from)),
+                    _ if icmp.code().get() == CODE_FRAGMENTATION_NEEDED => Some(Answer::TooBig(
+                        from,
+                        icmp.next_hop_mtu().filter(|mtu| *mtu != 0),
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    const SRC: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DST: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 7);
+
+    /// A path of routers, each with the MTU of its outgoing link and whether
+    /// it sends Fragmentation Needed.
+    struct Path {
+        routers: Vec<(Ipv4Addr, u16, bool)>,
+        answers: VecDeque<Vec<u8>>,
+    }
+
+    impl Path {
+        fn error(from: Ipv4Addr, icmp_type: IcmpType, mtu: u16, quote: &[u8]) -> Vec<u8> {
+            let code = if icmp_type == IcmpType::TimeExceeded {
+                0
+            } else {
+                CODE_FRAGMENTATION_NEEDED
+            };
+            let icmp = icmp!(
+                icmp_type: icmp_type,
+                code: code,
+                sequence: mtu,
+                payload: &quote[..28],
+            );
+            ipv4!(src: from, dst: SRC, protocol: IpProtocol::Icmp, payload: icmp.inner())
+                .inner()
+                .clone()
+        }
+    }
+
+    impl ProbeSocket for Path {
+        fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+            let ipv4 = Ipv4::new(packet).unwrap();
+            let ttl = ipv4.ttl().get() as usize;
+            for (hop, &(router, mtu, icmp)) in self.routers.iter().enumerate() {
+                if ttl == hop + 1 {
+                    let answer = Self::error(router, IcmpType::TimeExceeded, 0, packet);
+                    self.answers.push_back(answer);
+                    return Ok(());
+                }
+                if packet.len() > mtu as usize {
+                    if icmp {
+                        let answer =
+                            Self::error(router, IcmpType::DestinationUnreachable, mtu, packet);
+                        self.answers.push_back(answer);
+                    }
+                    return Ok(());
+                }
+            }
+
+            let request = ipv4.icmp().unwrap();
+            let reply = icmp!(
+                icmp_type: IcmpType::EchoReply,
+                identifier: request.identifier().get(),
+                sequence: request.sequence().get(),
+                payload: request.payload(),
+            );
+            let reply =
+                ipv4!(src: DST, dst: SRC, protocol: IpProtocol::Icmp, payload: reply.inner());
+            self.answers.push_back(reply.inner().clone());
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.answers.pop_front())
+        }
+    }
+
+    fn simulated(routers: &[(u16, bool)]) -> Path {
+        Path {
+            routers: routers
+                .iter()
+                .enumerate()
+                .map(|(i, &(mtu, icmp))| (Ipv4Addr::new(192, 0, 2, i as u8 + 1), mtu, icmp))
+                .collect(),
+            answers: VecDeque::new(),
+        }
+    }
+
+    #[test]
+    fn pmtu_prober_black_hole() {
+        let mut path = simulated(&[(1500, true), (1400, false), (1500, true)]);
+        let mut prober = PmtuProber::new(SRC, DST);
+        let result = prober.probe(&mut path).unwrap();
+
+        assert_eq!(result.mtu, 1400);
+        assert!(result.black_hole);
+        assert_eq!(
+            result.drop,
+            Some(MtuDrop {
+                hop: 2,
+                router: Some(Ipv4Addr::new(192, 0, 2, 2)),
+                reported_mtu: None,
+                silent: true,
+            })
+        );
+    }
+
+    #[test]
+    fn pmtu_prober_fragmentation_needed() {
+        let mut path = simulated(&[(1500, true), (1500, true), (1280, true)]);
+        let mut prober = PmtuProber::new(SRC, DST);
+        let result = prober.probe(&mut path).unwrap();
+
+        assert_eq!(result.mtu, 1280);
+        assert!(!result.black_hole);
+        // The minimum, the maximum and the reported MTU, then 4 TTLs
+        assert_eq!(result.probes, 3 + 4);
+        let drop = result.drop.unwrap();
+        assert_eq!((drop.hop, drop.reported_mtu), (3, Some(1280)));
+
+        let mut unreachable = simulated(&[(500, true)]);
+        assert_eq!(
+            prober.probe(&mut unreachable).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! ARP and NDP responder.
+//!
+//! Replaying a capture in a lab often sends traffic to a gateway or host
+//! that does not exist there, so the devices under test never resolve its
+//! address. A [`NeighborResponder`] stands in for such hosts: it answers ARP
+//! requests and IPv6 Neighbor Solicitations for the addresses it is given,
+//! on a [`LinkSocket`] capturing and injecting on the lab interface.
+//!
+//! ```
+//! use std::net::Ipv4Addr;
+//!
+//! use netkit::probe::responder::NeighborResponder;
+//! use netkit::packet::prelude::*;
+//!
+//! let gateway = EthAddr::new(0x02, 0, 0, 0, 0, 1);
+//! let mut responder = NeighborResponder::new();
+//! responder.answer(Ipv4Addr::new(192, 0, 2, 1), gateway);
+//! assert_eq!(responder.addrs().count(), 1);
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+
+use netkit_packet::{
+    layer::{eth::EthBuilder, ip::Ipv6Builder},
+    prelude::*,
+};
+
+use super::LinkSocket;
+
+/// ICMPv6 type of Neighbor Solicitations.
+const NEIGHBOR_SOLICITATION: u8 = 135;
+
+/// ICMPv6 type of Neighbor Advertisements.
+const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// Hop limit of Neighbor Discovery messages, proving they are on-link.
+const ND_HOP_LIMIT: u8 = 255;
+
+/// Responder to ARP requests and Neighbor Solicitations.
+#[derive(Clone, Debug, Default)]
+pub struct NeighborResponder {
+    addrs: HashMap<IpAddr, EthAddr>,
+    answered: u64,
+}
+
+impl NeighborResponder {
+    /// Create a responder answering for no address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer for an IPv4 or IPv6 address with a MAC address.
+    pub fn answer(&mut self, addr: impl Into<IpAddr>, mac: EthAddr) -> &mut Self {
+        self.addrs.insert(addr.into(), mac);
+        self
+    }
+
+    /// Stop answering for an address.
+    pub fn remove(&mut self, addr: impl Into<IpAddr>) -> &mut Self {
+        self.addrs.remove(&addr.into());
+        self
+    }
+
+    /// Get the addresses answered for.
+    pub fn addrs(&self) -> impl Iterator<Item = (&IpAddr, &EthAddr)> {
+        self.addrs.iter()
+    }
+
+    /// Get the number of requests answered.
+    pub fn answered(&self) -> u64 {
+        self.answered
+    }
+
+    /// Get the answer to a captured frame, if it is a request for one of the
+    /// addresses.
+    pub fn respond(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let eth = Eth::new(frame).ok()?;
+        let answer = match eth.eth_type().get() {
+            EthType::Arp => self.respond_arp(eth.payload()),
+            EthType::Ipv6 => self.respond_ns(eth.src().get(), &eth.ipv6()?),
+            _ => None,
+        };
+        if answer.is_some() {
+            self.answered += 1;
+        }
+        answer
+    }
+
+    /// Answer requests captured on a socket for `duration`.
+    ///
+    /// Returns the number of requests answered.
+    pub fn serve<S: LinkSocket>(&mut self, socket: &mut S, duration: Duration) -> io::Result<u64> {
+        let deadline = Instant::now() + duration;
+        let answered = self.answered;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            if let Some(frame) = socket.recv(remaining)? {
+                if let Some(answer) = self.respond(&frame) {
+                    socket.send(&answer)?;
+                }
+            }
+        }
+        Ok(self.answered - answered)
+    }
+
+    /// Answer an Ethernet/IPv4 ARP request.
+    fn respond_arp(&self, arp: &[u8]) -> Option<Vec<u8>> {
+        // Hardware type 1 (Ethernet), protocol IPv4, address lengths 6 and 4,
+        // operation 1 (request).
+        if arp.get(..8)? != [0, 1, 8, 0, 6, 4, 0, 1] || arp.len() < 28 {
+            return None;
+        }
+        let target = Ipv4Addr::from(<[u8; 4]>::try_from(&arp[24..28]).ok()?);
+        let mac = *self.addrs.get(&IpAddr::V4(target))?;
+        let (sender_mac, sender_ip) = (&arp[8..14], &arp[14..18]);
+
+        let mut reply = Vec::with_capacity(28);
+        reply.extend_from_slice(&[0, 1, 8, 0, 6, 4, 0, 2]);
+        reply.extend_from_slice(mac.as_ref());
+        reply.extend_from_slice(&target.octets());
+        reply.extend_from_slice(sender_mac);
+        reply.extend_from_slice(sender_ip);
+
+        let eth = EthBuilder::new()
+            .src(mac)
+            .dst(EthAddr::from_slice(sender_mac))
+            .eth_type(EthType::Arp)
+            .payload(reply)
+            .build();
+        Some(eth.inner().clone())
+    }
+
+    /// Answer an IPv6 Neighbor Solicitation.
+    fn respond_ns(&self, solicitor_mac: EthAddr, ipv6: &Ipv6<&[u8]>) -> Option<Vec<u8>> {
+        let (IpProtocol::Ipv6Icmp, icmp) = ipv6.upper_layer()? else {
+            return None;
+        };
+        if icmp.first() != Some(&NEIGHBOR_SOLICITATION)
+            || icmp.len() < 24
+            || ipv6.hop_limit().get() != ND_HOP_LIMIT
+        {
+            return None;
+        }
+        let target = Ipv6Addr::from(<[u8; 16]>::try_from(&icmp[8..24]).ok()?);
+        let mac = *self.addrs.get(&IpAddr::V6(target))?;
+
+        // Solicitations of duplicate address detection come from the
+        // unspecified address; their answer goes to all nodes, unsolicited.
+        let solicitor = ipv6.src().get();
+        let (dst, dst_mac, flags) = if solicitor.is_unspecified() {
+            let all_nodes = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+            (all_nodes, EthAddr::new(0x33, 0x33, 0, 0, 0, 1), 0x20)
+        } else {
+            // Solicited and Override flags.
+            (solicitor, solicitor_mac, 0x60)
+        };
+
+        let mut na = vec![NEIGHBOR_ADVERTISEMENT, 0, 0, 0, flags, 0, 0, 0];
+        na.extend_from_slice(&target.octets());
+        // Target link-layer address option.
+        na.extend_from_slice(&[2, 1]);
+        na.extend_from_slice(mac.as_ref());
+        let checksum = Checksum::new()
+            .add_pseudo_header(
+                IpAddr::V6(target),
+                IpAddr::V6(dst),
+                IpProtocol::Ipv6Icmp,
+                na.len(),
+            )
+            .add_bytes(&na)
+            .finish();
+        na[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        let packet = Ipv6Builder::new()
+            .next_header(IpProtocol::Ipv6Icmp)
+            .hop_limit(ND_HOP_LIMIT)
+            .src(target)
+            .dst(dst)
+            .payload(na)
+            .build();
+        let eth = EthBuilder::new()
+            .src(mac)
+            .dst(dst_mac)
+            .eth_type(EthType::Ipv6)
+            .payload(packet.inner())
+            .build();
+        Some(eth.inner().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbor_responder() {
+        let host = EthAddr::new(0x02, 0, 0, 0, 0, 0x10);
+        let gateway = EthAddr::new(0x02, 0, 0, 0, 0, 1);
+        let gateway_v6: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let mut responder = NeighborResponder::new();
+        responder
+            .answer(Ipv4Addr::new(192, 0, 2, 1), gateway)
+            .answer(gateway_v6, gateway);
+
+        let mut request = vec![0, 1, 8, 0, 6, 4, 0, 1];
+        request.extend_from_slice(host.as_ref());
+        request.extend_from_slice(&[192, 0, 2, 10]);
+        request.extend_from_slice(&[0; 6]);
+        request.extend_from_slice(&[192, 0, 2, 1]);
+        let frame = |dst, eth_type, payload: &[u8]| {
+            EthBuilder::new()
+                .src(host)
+                .dst(dst)
+                .eth_type(eth_type)
+                .payload(payload)
+                .build()
+                .inner()
+                .clone()
+        };
+
+        let reply = responder
+            .respond(&frame(EthAddr::BROADCAST, EthType::Arp, &request))
+            .unwrap();
+        let eth = Eth::new(&reply).unwrap();
+        assert_eq!((eth.src().get(), eth.dst().get()), (gateway, host));
+        assert_eq!(eth.payload()[6..8], [0, 2]);
+        assert_eq!(eth.payload()[8..14], <[u8; 6]>::from(gateway));
+        assert_eq!(eth.payload()[24..28], [192, 0, 2, 10]);
+
+        request[27] = 2;
+        let other = frame(EthAddr::BROADCAST, EthType::Arp, &request);
+        assert_eq!(responder.respond(&other), None);
+
+        let mut ns = vec![NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+        ns.extend_from_slice(&gateway_v6.octets());
+        let solicitor: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        let ipv6 = Ipv6Builder::new()
+            .next_header(IpProtocol::Ipv6Icmp)
+            .hop_limit(ND_HOP_LIMIT)
+            .src(solicitor)
+            .dst("ff02::1:ff00:1".parse::<Ipv6Addr>().unwrap())
+            .payload(ns)
+            .build();
+        let solicitation = frame(
+            EthAddr::new(0x33, 0x33, 0xff, 0, 0, 1),
+            EthType::Ipv6,
+            ipv6.inner(),
+        );
+        let advertisement = responder.respond(&solicitation).unwrap();
+        let eth = Eth::new(&advertisement).unwrap();
+        assert_eq!(eth.dst().get(), host);
+        let ipv6 = eth.ipv6().unwrap();
+        assert_eq!(
+            (ipv6.src().get(), ipv6.dst().get()),
+            (gateway_v6, solicitor)
+        );
+        let (_, na) = ipv6.upper_layer().unwrap();
+        assert_eq!((na[0], na[4]), (NEIGHBOR_ADVERTISEMENT, 0x60));
+        let checksum = Checksum::new()
+            .add_pseudo_header(
+                IpAddr::V6(gateway_v6),
+                IpAddr::V6(solicitor),
+                IpProtocol::Ipv6Icmp,
+                na.len(),
+            )
+            .add_bytes(na)
+            .finish();
+        assert_eq!(checksum, 0);
+        assert_eq!(responder.answered(), 2);
+    }
+}
@@ -0,0 +1,631 @@
+//! Long-running probe service.
+//!
+//! A [`ProbeService`] wires a packet source to the analyzers and exports of
+//! an [`AnalysisConfig`]: a capture thread reads packets into a bounded
+//! [`channel`], the service feeds them to the analyzers, exports their
+//! tables periodically and once more on shutdown. A monitoring agent then
+//! only needs its configuration file and a `main`:
+//!
+//! A configuration loaded from a file is reloaded on SIGHUP or when the file
+//! changes. Only what changed is applied (see [`AnalysisConfig::diff`]): a
+//! new filter is set on the running source, added analyzers start empty,
+//! and removed ones are exported a last time, while the others keep their
+//! state. A configuration failing to load is ignored, keeping the old one.
+//!
+//! Given a [`Profiler`], the service times reading, each analyzer and
+//! exporting, to tell which of them limits throughput.
+//!
+//! ```no_run
+//! use netkit::service::{PacketSource, ProbeService};
+//! use netkit::capture::shutdown::{HangupSignal, ShutdownToken};
+//! # use std::{io, time::Duration};
+//! # use netkit::service::SourceRead;
+//! # struct Interface;
+//! # impl PacketSource for Interface {
+//! #     fn read(&mut self, _: Duration) -> io::Result<SourceRead> { Ok(SourceRead::End) }
+//! # }
+//! # fn open_interface() -> Interface { Interface }
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let mut service = ProbeService::load(open_interface(), "probe.toml")?;
+//!     service
+//!         .shutdown_token(ShutdownToken::with_signals()?)
+//!         .reload_signal(HangupSignal::install()?)
+//!         .watch_config(true);
+//!     let report = service.run()?;
+//!     eprintln!("{} packets analyzed", report.packets);
+//!     Ok(())
+//! }
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use netkit_capture::{
+    channel::{channel, ChannelConfig, ChannelStats},
+    shutdown::{HangupSignal, ShutdownToken},
+};
+
+use crate::{
+    analysis::{
+        profile::{timed, ProfileBatch, Profiler, Stage},
+        sampling::Sampled,
+        Analyzer,
+    },
+    config::{AnalysisConfig, AnalyzerKind, ConfigError, TableAnalyzer},
+};
+
+pub mod session;
+
+/// Default interval between two exports.
+pub const DEFAULT_EXPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default time a read waits before checking for shutdown.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Outcome of reading a packet source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceRead {
+    /// A packet, with its timestamp and Ethernet frame.
+    Packet(Duration, Vec<u8>),
+    /// No packet arrived in time.
+    Timeout,
+    /// The source is exhausted, e.g. at the end of a file.
+    End,
+}
+
+/// Source of captured packets, e.g. a live interface.
+pub trait PacketSource: Send {
+    /// Read the next packet, waiting at most `timeout`.
+    fn read(&mut self, timeout: Duration) -> io::Result<SourceRead>;
+
+    /// Apply the filter of the configuration, in pcap filter syntax.
+    ///
+    /// Sources without filtering support refuse any filter.
+    fn set_filter(&mut self, filter: Option<&str>) -> io::Result<()> {
+        match filter {
+            None => Ok(()),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "packet source does not support filters",
+            )),
+        }
+    }
+}
+
+/// Error running a probe service.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ServiceError {
+    /// The configuration is invalid.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// The packet source failed.
+    #[error("packet source: {0}")]
+    Source(#[source] io::Error),
+    /// Writing an export failed.
+    #[error("exporting {path}: {source}", path = path.display())]
+    Export {
+        /// Output file.
+        path: PathBuf,
+        /// Underlying error.
+        source: io::Error,
+    },
+}
+
+/// Summary of a service run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ServiceReport {
+    /// Packets fed to the analyzers, before sampling.
+    pub packets: u64,
+    /// Counters of the channel between capture and analysis.
+    pub channel: ChannelStats,
+    /// Tables written, over all exports.
+    pub exports: u64,
+    /// Configurations reloaded.
+    pub reloads: u64,
+    /// Configurations which failed to load or apply.
+    pub reload_errors: u64,
+}
+
+/// Analyzers of a configuration, in order.
+type Analyzers = Vec<(AnalyzerKind, Sampled<Box<dyn TableAnalyzer>>)>;
+
+/// Filter to set on the source, taken by the capture thread.
+#[derive(Debug, Default)]
+struct FilterUpdate {
+    pending: Mutex<Option<Option<String>>>,
+    rejected: AtomicU64,
+}
+
+/// Probe daemon running the analyzers of a configuration over a packet
+/// source until shut down.
+pub struct ProbeService<S> {
+    source: S,
+    config: AnalysisConfig,
+    channel: ChannelConfig,
+    export_interval: Duration,
+    poll_interval: Duration,
+    token: ShutdownToken,
+    path: Option<PathBuf>,
+    watch: bool,
+    hangup: Option<HangupSignal>,
+    profiler: Option<Profiler>,
+}
+
+impl<S: PacketSource + 'static> ProbeService<S> {
+    /// Create a service with a configuration.
+    pub fn new(source: S, config: AnalysisConfig) -> Self {
+        Self {
+            source,
+            config,
+            channel: ChannelConfig::default(),
+            export_interval: DEFAULT_EXPORT_INTERVAL,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            token: ShutdownToken::new(),
+            path: None,
+            watch: false,
+            hangup: None,
+            profiler: None,
+        }
+    }
+
+    /// Create a service with a configuration file, which can be reloaded.
+    pub fn load(source: S, path: impl AsRef<Path>) -> Result<Self, ServiceError> {
+        let path = path.as_ref();
+        let mut service = Self::new(source, AnalysisConfig::load(path)?);
+        service.path = Some(path.to_path_buf());
+        Ok(service)
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &AnalysisConfig {
+        &self.config
+    }
+
+    /// Set the channel between capture and analysis.
+    pub fn channel(&mut self, channel: ChannelConfig) -> &mut Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Set the interval between two exports.
+    pub fn export_interval(&mut self, interval: Duration) -> &mut Self {
+        self.export_interval = interval;
+        self
+    }
+
+    /// Set the time a read waits before checking for shutdown.
+    pub fn poll_interval(&mut self, interval: Duration) -> &mut Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set the token shutting the service down, e.g. one watching signals.
+    pub fn shutdown_token(&mut self, token: ShutdownToken) -> &mut Self {
+        self.token = token;
+        self
+    }
+
+    /// Reload the configuration file when its modification time changes.
+    pub fn watch_config(&mut self, watch: bool) -> &mut Self {
+        self.watch = watch;
+        self
+    }
+
+    /// Reload the configuration file on SIGHUP.
+    pub fn reload_signal(&mut self, hangup: HangupSignal) -> &mut Self {
+        self.hangup = Some(hangup);
+        self
+    }
+
+    /// Profile the stages of the service: reading packets (including the
+    /// time waiting for them), each analyzer, and exporting.
+    ///
+    /// The breakdown is in [`Profiler::report`] once the service has run,
+    /// and logged at shutdown with the `tracing` feature.
+    pub fn profiler(&mut self, profiler: Profiler) -> &mut Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    /// Get a handle to the token shutting the service down.
+    pub fn token(&self) -> ShutdownToken {
+        self.token.clone()
+    }
+
+    /// Run until the source ends or the token is cancelled.
+    ///
+    /// Packets queued at shutdown are still analyzed before the final
+    /// export. A failing source shuts the service down, without the final
+    /// export.
+    pub fn run(self) -> Result<ServiceReport, ServiceError> {
+        let Self {
+            mut source,
+            mut config,
+            channel: channel_config,
+            export_interval,
+            poll_interval,
+            token,
+            path,
+            watch,
+            hangup,
+            profiler,
+        } = self;
+        config.validate()?;
+        source
+            .set_filter(config.filter.as_deref())
+            .map_err(ServiceError::Source)?;
+
+        let mut analyzers = build_analyzers(&config, Analyzers::new());
+        let filter = Arc::new(FilterUpdate::default());
+        let (tx, rx) = channel(channel_config);
+        let capture = {
+            let token = token.clone();
+            let filter = filter.clone();
+            let mut batch = profiler.as_ref().map(Profiler::batch);
+            thread::spawn(move || -> io::Result<()> {
+                while !token.is_cancelled() {
+                    let update = filter
+                        .pending
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .take();
+                    if let Some(update) = update {
+                        // The source keeps its old filter.
+                        if source.set_filter(update.as_deref()).is_err() {
+                            filter.rejected.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    let start = batch.is_some().then(Instant::now);
+                    match source.read(poll_interval)? {
+                        SourceRead::Packet(ts, frame) => {
+                            if let (Some(batch), Some(start)) = (&mut batch, start) {
+                                batch.record(Stage::Read, start.elapsed());
+                                batch.end_packet();
+                            }
+                            if tx.send((ts, frame)).is_err() {
+                                break;
+                            }
+                        }
+                        SourceRead::Timeout => {}
+                        SourceRead::End => break,
+                    }
+                }
+                Ok(())
+            })
+        };
+
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut last_modified: Option<SystemTime> = path.as_deref().and_then(modified);
+        let mut report = ServiceReport::default();
+        let mut batch = profiler.as_ref().map(Profiler::batch);
+        let mut next_export = Instant::now() + export_interval;
+        let mut next_check = Instant::now() + poll_interval;
+        loop {
+            match rx.recv_timeout(poll_interval) {
+                Some((ts, frame)) => {
+                    report.packets += 1;
+                    feed(&mut analyzers, &mut batch, ts, &frame);
+                }
+                None if capture.is_finished() => break,
+                None => {}
+            }
+
+            let now = Instant::now();
+            if let Some(path) = path.as_deref().filter(|_| now >= next_check) {
+                next_check = now + poll_interval;
+                let hung_up = hangup.as_ref().is_some_and(HangupSignal::take);
+                let changed = watch && {
+                    let current = modified(path);
+                    current != std::mem::replace(&mut last_modified, current)
+                };
+                if hung_up || changed {
+                    match AnalysisConfig::load(path) {
+                        Ok(new) => {
+                            let diff = config.diff(&new);
+                            // Last tables of the analyzers going away
+                            let (gone, kept): (Analyzers, Analyzers) = analyzers
+                                .into_iter()
+                                .partition(|(kind, _)| diff.rebuild || diff.removed.contains(kind));
+                            report.exports +=
+                                timed(&mut batch, Stage::Export, || export(&config, &gone))?;
+                            analyzers = build_analyzers(&new, kept);
+                            if diff.filter {
+                                *filter.pending.lock().unwrap_or_else(|e| e.into_inner()) =
+                                    Some(new.filter.clone());
+                            }
+                            config = new;
+                            report.reloads += 1;
+                        }
+                        Err(_) => report.reload_errors += 1,
+                    }
+                }
+            }
+            if now >= next_export {
+                report.exports += timed(&mut batch, Stage::Export, || export(&config, &analyzers))?;
+                next_export = Instant::now() + export_interval;
+            }
+        }
+
+        // The capture thread is done; drain what it queued last.
+        while let Some((ts, frame)) = rx.recv() {
+            report.packets += 1;
+            feed(&mut analyzers, &mut batch, ts, &frame);
+        }
+        match capture.join() {
+            Ok(result) => result.map_err(ServiceError::Source)?,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+        report.exports += timed(&mut batch, Stage::Export, || export(&config, &analyzers))?;
+        report.channel = rx.stats();
+        report.reload_errors += filter.rejected.load(Ordering::Relaxed);
+        drop(batch);
+        #[cfg(feature = "tracing")]
+        if let Some(profiler) = &profiler {
+            let profile = profiler.report();
+            tracing::info!(bottleneck = ?profile.bottleneck(), "pipeline profile\n{profile}");
+        }
+        Ok(report)
+    }
+}
+
+/// Feed a packet to the analyzers, timing each of them if profiling.
+fn feed(analyzers: &mut Analyzers, batch: &mut Option<ProfileBatch>, ts: Duration, frame: &[u8]) {
+    for (kind, analyzer) in analyzers.iter_mut() {
+        timed(batch, Stage::Analyzer(kind.name()), || {
+            analyzer.on_packet(ts, frame)
+        });
+    }
+    if let Some(batch) = batch {
+        batch.end_packet();
+    }
+}
+
+/// Build the analyzers of a configuration, reusing the ones in `kept`.
+fn build_analyzers(config: &AnalysisConfig, mut kept: Analyzers) -> Analyzers {
+    config
+        .analyzers
+        .iter()
+        .map(|kind| match kept.iter().position(|(k, _)| k == kind) {
+            Some(index) => kept.swap_remove(index),
+            None => (
+                *kind,
+                Sampled::new(config.sampling, config.build_analyzer(*kind)),
+            ),
+        })
+        .collect()
+}
+
+/// Write the tables of the configured exports.
+fn export(
+    config: &AnalysisConfig,
+    analyzers: &[(AnalyzerKind, Sampled<Box<dyn TableAnalyzer>>)],
+) -> Result<u64, ServiceError> {
+    let mut written = 0;
+    for target in &config.exports {
+        let Some((_, analyzer)) = analyzers.iter().find(|(kind, _)| *kind == target.analyzer)
+        else {
+            continue;
+        };
+        target
+            .write(analyzer)
+            .map_err(|source| ServiceError::Export {
+                path: target.path.clone(),
+                source,
+            })?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, net::Ipv4Addr};
+
+    use netkit_packet::prelude::*;
+
+    use super::*;
+
+    /// Source replaying frames, then idling or ending.
+    struct Frames {
+        frames: VecDeque<Vec<u8>>,
+        idle: bool,
+    }
+
+    impl PacketSource for Frames {
+        fn read(&mut self, timeout: Duration) -> io::Result<SourceRead> {
+            match self.frames.pop_front() {
+                Some(frame) => Ok(SourceRead::Packet(Duration::ZERO, frame)),
+                None if self.idle => {
+                    thread::sleep(timeout);
+                    Ok(SourceRead::Timeout)
+                }
+                None => Ok(SourceRead::End),
+            }
+        }
+    }
+
+    #[test]
+    fn probe_service_runs() {
+        let dir = std::env::temp_dir().join(format!("netkit-service-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("top.csv");
+        let config = AnalysisConfig::from_toml(&format!(
+            "analyzers = [\"top-talkers\"]\n[[exports]]\nanalyzer = \"top-talkers\"\npath = {:?}",
+            path
+        ))
+        .unwrap();
+
+        let udp = udp!(src_port: 5353u16, dst_port: 53u16, payload: b"query");
+        let ipv4 = ipv4!(
+            protocol: IpProtocol::Udp,
+            src: Ipv4Addr::new(192, 0, 2, 1),
+            dst: Ipv4Addr::new(192, 0, 2, 2),
+            payload: udp.inner()
+        );
+        let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone();
+        let frames = Frames {
+            frames: vec![frame; 5].into(),
+            idle: false,
+        };
+
+        let profiler = Profiler::new();
+        let mut service = ProbeService::new(frames, config.clone());
+        service.profiler(profiler.clone());
+        let report = service.run().unwrap();
+        assert_eq!(report.packets, 5);
+        assert_eq!(report.exports, 1);
+        assert_eq!(report.channel.received, 5);
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert!(csv.contains("192.0.2.1"));
+        let profile = profiler.report();
+        assert_eq!(profile.stage(Stage::Read).unwrap().calls, 5);
+        assert_eq!(
+            profile.stage(Stage::Analyzer("top-talkers")).unwrap().calls,
+            5
+        );
+        assert_eq!(profile.stage(Stage::Export).unwrap().calls, 1);
+
+        // An idle live source runs until shut down.
+        let idle = Frames {
+            frames: VecDeque::new(),
+            idle: true,
+        };
+        let mut service = ProbeService::new(idle, config.clone());
+        service
+            .poll_interval(Duration::from_millis(5))
+            .export_interval(Duration::ZERO);
+        let token = service.token();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(30));
+            token.cancel();
+        });
+        let report = service.run().unwrap();
+        canceller.join().unwrap();
+        assert_eq!(report.packets, 0);
+        assert!(report.exports > 1);
+
+        let mut filtered = ProbeService::new(
+            Frames {
+                frames: VecDeque::new(),
+                idle: false,
+            },
+            AnalysisConfig {
+                filter: Some("udp".into()),
+                ..AnalysisConfig::new()
+            },
+        );
+        filtered.poll_interval(Duration::from_millis(1));
+        assert!(matches!(filtered.run(), Err(ServiceError::Source(_))));
+
+        // Reloads apply the new configuration, and ignore broken ones.
+        let config_path = dir.join("probe.toml");
+        std::fs::write(&config_path, config.to_toml()).unwrap();
+        let idle = Frames {
+            frames: VecDeque::new(),
+            idle: true,
+        };
+        let mut service = ProbeService::load(idle, &config_path).unwrap();
+        service
+            .poll_interval(Duration::from_millis(5))
+            .watch_config(true);
+        let token = service.token();
+        let editor = thread::spawn(move || {
+            for (i, text) in ["analyzers = [\"tcp\"]", "analyzers = 1"]
+                .iter()
+                .enumerate()
+            {
+                thread::sleep(Duration::from_millis(30));
+                std::fs::write(&config_path, text).unwrap();
+                let file = std::fs::File::options()
+                    .write(true)
+                    .open(&config_path)
+                    .unwrap();
+                let mtime = SystemTime::now() + Duration::from_secs(i as u64 + 1);
+                file.set_modified(mtime).unwrap();
+            }
+            thread::sleep(Duration::from_millis(30));
+            token.cancel();
+        });
+        let report = service.run().unwrap();
+        editor.join().unwrap();
+        assert_eq!((report.reloads, report.reload_errors), (1, 1));
+        // Top talkers, exported as it was removed
+        assert_eq!(report.exports, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Idle source recording the filters set, refusing `bad`.
+    struct Filtered(Arc<Mutex<Vec<Option<String>>>>);
+
+    impl PacketSource for Filtered {
+        fn read(&mut self, timeout: Duration) -> io::Result<SourceRead> {
+            thread::sleep(timeout);
+            Ok(SourceRead::Timeout)
+        }
+
+        fn set_filter(&mut self, filter: Option<&str>) -> io::Result<()> {
+            if filter == Some("bad") {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "bad filter"));
+            }
+            self.0.lock().unwrap().push(filter.map(str::to_string));
+            Ok(())
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_service_reloads_on_hangup() {
+        let dir = std::env::temp_dir().join(format!("netkit-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("probe.toml");
+        let csv = dir.join("top.csv");
+        let config = move |filter: &str| {
+            format!(
+                "{filter}\nanalyzers = [\"top-talkers\"]\n[[exports]]\n\
+                 analyzer = \"top-talkers\"\npath = {csv:?}"
+            )
+        };
+        std::fs::write(&config_path, config("")).unwrap();
+
+        let filters = Arc::new(Mutex::new(Vec::new()));
+        let mut service = ProbeService::load(Filtered(filters.clone()), &config_path).unwrap();
+        service
+            .poll_interval(Duration::from_millis(5))
+            .reload_signal(HangupSignal::install().unwrap());
+        let token = service.token();
+        let editor = thread::spawn(move || {
+            for filter in ["udp", "bad"] {
+                thread::sleep(Duration::from_millis(30));
+                let text = config(&format!("filter = {filter:?}"));
+                std::fs::write(&config_path, text).unwrap();
+                // SAFETY: SIGHUP is handled.
+                unsafe { libc::raise(libc::SIGHUP) };
+            }
+            thread::sleep(Duration::from_millis(30));
+            token.cancel();
+        });
+        let report = service.run().unwrap();
+        editor.join().unwrap();
+
+        assert_eq!(*filters.lock().unwrap(), [None, Some("udp".to_string())]);
+        // Both reloads applied; the source refused the second filter.
+        assert_eq!((report.reloads, report.reload_errors), (2, 1));
+        // The analyzer is kept across the reloads, so only exported at the end.
+        assert_eq!(report.exports, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+//! Recording and replay of live capture sessions.
+//!
+//! A [`SessionRecorder`] wraps the [`PacketSource`] of a live session and
+//! writes every outcome of its reads to a session file, with the time each
+//! read took, as well as the filters set on it. A [`SessionReplay`] then
+//! plays the file back through the same trait, with the same timing, so a
+//! bug observed live is reproduced deterministically, e.g. in a test:
+//!
+//! ```no_run
+//! use netkit::service::{session::SessionReplay, ProbeService};
+//! # use netkit::config::AnalysisConfig;
+//!
+//! let source = SessionReplay::open("incident.nksession").unwrap();
+//! let report = ProbeService::new(source, AnalysisConfig::default())
+//!     .run()
+//!     .unwrap();
+//! ```
+//!
+//! Replay fails with [`io::ErrorKind::InvalidInput`] where the calls on the
+//! source diverge from the recording, e.g. when another filter is set.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{PacketSource, SourceRead};
+
+/// Magic bytes at the start of a session file.
+pub const SESSION_MAGIC: [u8; 4] = *b"NKSR";
+
+/// Current version of the session file format.
+pub const SESSION_VERSION: u16 = 1;
+
+const TAG_PACKET: u8 = 0;
+const TAG_TIMEOUT: u8 = 1;
+const TAG_END: u8 = 2;
+const TAG_ERROR: u8 = 3;
+const TAG_FILTER: u8 = 4;
+
+/// Error kinds kept by recordings; others are replayed as `Other`.
+const ERROR_KINDS: [io::ErrorKind; 12] = [
+    io::ErrorKind::Other,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::Unsupported,
+];
+
+/// Packet source recording the reads of another source.
+#[derive(Debug)]
+pub struct SessionRecorder<S, W: Write> {
+    source: S,
+    writer: W,
+}
+
+impl<S: PacketSource> SessionRecorder<S, BufWriter<File>> {
+    /// Record the session of a source to a file.
+    pub fn create(source: S, path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(source, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<S: PacketSource, W: Write> SessionRecorder<S, W> {
+    /// Record the session of a source to a writer.
+    pub fn new(source: S, mut writer: W) -> io::Result<Self> {
+        writer.write_all(&SESSION_MAGIC)?;
+        writer.write_all(&SESSION_VERSION.to_le_bytes())?;
+        writer.flush()?;
+        Ok(Self { source, writer })
+    }
+
+    /// Get the recorded source and the writer back.
+    pub fn into_inner(self) -> (S, W) {
+        (self.source, self.writer)
+    }
+
+    fn write_read(&mut self, elapsed: Duration, read: &io::Result<SourceRead>) -> io::Result<()> {
+        match read {
+            Ok(SourceRead::Packet(ts, frame)) => {
+                self.writer.write_all(&[TAG_PACKET])?;
+                write_duration(&mut self.writer, elapsed)?;
+                write_duration(&mut self.writer, *ts)?;
+                write_bytes(&mut self.writer, frame)?;
+            }
+            Ok(SourceRead::Timeout) => {
+                self.writer.write_all(&[TAG_TIMEOUT])?;
+                write_duration(&mut self.writer, elapsed)?;
+            }
+            Ok(SourceRead::End) => {
+                self.writer.write_all(&[TAG_END])?;
+                write_duration(&mut self.writer, elapsed)?;
+            }
+            Err(error) => {
+                self.writer.write_all(&[TAG_ERROR])?;
+                write_duration(&mut self.writer, elapsed)?;
+                write_error(&mut self.writer, error)?;
+            }
+        }
+        // Keep what was observed before a crash.
+        self.writer.flush()
+    }
+}
+
+impl<S: PacketSource, W: Write + Send> PacketSource for SessionRecorder<S, W> {
+    fn read(&mut self, timeout: Duration) -> io::Result<SourceRead> {
+        let start = Instant::now();
+        let read = self.source.read(timeout);
+        self.write_read(start.elapsed(), &read)?;
+        read
+    }
+
+    fn set_filter(&mut self, filter: Option<&str>) -> io::Result<()> {
+        let result = self.source.set_filter(filter);
+        self.writer.write_all(&[TAG_FILTER])?;
+        write_bytes(&mut self.writer, filter.unwrap_or_default().as_bytes())?;
+        self.writer.write_all(&[u8::from(filter.is_some())])?;
+        match &result {
+            Ok(()) => self.writer.write_all(&[0])?,
+            Err(error) => {
+                self.writer.write_all(&[1])?;
+                write_error(&mut self.writer, error)?;
+            }
+        }
+        self.writer.flush()?;
+        result
+    }
+}
+
+/// Packet source replaying a recorded session.
+#[derive(Debug)]
+pub struct SessionReplay<R> {
+    reader: R,
+    realtime: bool,
+}
+
+impl SessionReplay<BufReader<File>> {
+    /// Replay the session recorded in a file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> SessionReplay<R> {
+    /// Replay the session recorded in a reader.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let magic: [u8; 4] = read_array(&mut reader)?;
+        if magic != SESSION_MAGIC {
+            return Err(invalid_data("not a session file"));
+        }
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != SESSION_VERSION {
+            return Err(invalid_data("unsupported session file version"));
+        }
+        Ok(Self {
+            reader,
+            realtime: true,
+        })
+    }
+
+    /// Set whether reads take as long as they did live, true by default.
+    ///
+    /// Without it, the session is replayed as fast as it is read, in the
+    /// same order.
+    pub fn realtime(&mut self, realtime: bool) -> &mut Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Read the tag of the next event, `None` at the end of the recording.
+    fn next_tag(&mut self) -> io::Result<Option<u8>> {
+        let mut tag = [0];
+        match self.reader.read(&mut tag)? {
+            0 => Ok(None),
+            _ => Ok(Some(tag[0])),
+        }
+    }
+}
+
+impl<R: Read + Send> PacketSource for SessionReplay<R> {
+    fn read(&mut self, _timeout: Duration) -> io::Result<SourceRead> {
+        let Some(tag) = self.next_tag()? else {
+            return Ok(SourceRead::End);
+        };
+        if tag == TAG_FILTER {
+            return Err(diverged("read where the recording sets a filter"));
+        }
+        let elapsed = read_duration(&mut self.reader)?;
+        let read = match tag {
+            TAG_PACKET => {
+                let ts = read_duration(&mut self.reader)?;
+                Ok(SourceRead::Packet(ts, read_bytes(&mut self.reader)?))
+            }
+            TAG_TIMEOUT => Ok(SourceRead::Timeout),
+            TAG_END => Ok(SourceRead::End),
+            TAG_ERROR => Err(read_error(&mut self.reader)?),
+            _ => return Err(invalid_data("unknown session event")),
+        };
+        if self.realtime {
+            thread::sleep(elapsed);
+        }
+        read
+    }
+
+    fn set_filter(&mut self, filter: Option<&str>) -> io::Result<()> {
+        if self.next_tag()? != Some(TAG_FILTER) {
+            return Err(diverged("filter set where the recording reads"));
+        }
+        let expression = read_bytes(&mut self.reader)?;
+        let [some, failed] = read_array(&mut self.reader)?;
+        let recorded = (some != 0).then_some(expression.as_slice());
+        let result = match failed {
+            0 => Ok(()),
+            _ => Err(read_error(&mut self.reader)?),
+        };
+        if recorded != filter.map(str::as_bytes) {
+            return Err(diverged("filter differs from the recording"));
+        }
+        result
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn diverged(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("replay diverged: {message}"),
+    )
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_duration<W: Write>(writer: &mut W, duration: Duration) -> io::Result<()> {
+    writer.write_all(&(duration.as_nanos() as u64).to_le_bytes())
+}
+
+fn read_duration<R: Read>(reader: &mut R) -> io::Result<Duration> {
+    Ok(Duration::from_nanos(u64::from_le_bytes(read_array(
+        reader,
+    )?)))
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = u32::from_le_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn write_error<W: Write>(writer: &mut W, error: &io::Error) -> io::Result<()> {
+    let kind = ERROR_KINDS
+        .iter()
+        .position(|kind| *kind == error.kind())
+        .unwrap_or(0);
+    writer.write_all(&[kind as u8])?;
+    write_bytes(writer, error.to_string().as_bytes())
+}
+
+fn read_error<R: Read>(reader: &mut R) -> io::Result<io::Error> {
+    let [kind] = read_array(reader)?;
+    let kind = ERROR_KINDS
+        .get(kind as usize)
+        .copied()
+        .unwrap_or(io::ErrorKind::Other);
+    let message = String::from_utf8_lossy(&read_bytes(reader)?).into_owned();
+    Ok(io::Error::new(kind, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, io::Cursor};
+
+    use super::*;
+
+    /// Source scripted with the outcomes of its reads.
+    struct Scripted {
+        reads: VecDeque<(Duration, io::Result<SourceRead>)>,
+    }
+
+    impl PacketSource for Scripted {
+        fn read(&mut self, _timeout: Duration) -> io::Result<SourceRead> {
+            let (delay, read) = self
+                .reads
+                .pop_front()
+                .unwrap_or((Duration::ZERO, Ok(SourceRead::End)));
+            thread::sleep(delay);
+            read
+        }
+
+        fn set_filter(&mut self, filter: Option<&str>) -> io::Result<()> {
+            match filter {
+                Some("bad") => Err(io::Error::new(io::ErrorKind::InvalidInput, "bad filter")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn session_record_replay() {
+        let packet = |i: u8| SourceRead::Packet(Duration::from_secs(i.into()), vec![i; 60]);
+        let error = io::Error::new(io::ErrorKind::ConnectionReset, "interface down");
+        let source = Scripted {
+            reads: VecDeque::from([
+                (Duration::ZERO, Ok(packet(1))),
+                (Duration::from_millis(30), Ok(SourceRead::Timeout)),
+                (Duration::ZERO, Ok(packet(2))),
+                (Duration::ZERO, Err(error)),
+            ]),
+        };
+
+        let mut recorder = SessionRecorder::new(source, Vec::new()).unwrap();
+        recorder.set_filter(Some("udp")).unwrap();
+        let mut live = Vec::new();
+        for _ in 0..4 {
+            live.push(
+                recorder
+                    .read(Duration::from_millis(100))
+                    .map_err(|e| e.kind()),
+            );
+        }
+        assert!(recorder.set_filter(Some("bad")).is_err());
+        let file = recorder.into_inner().1;
+
+        let mut replay = SessionReplay::new(Cursor::new(file.clone())).unwrap();
+        replay.set_filter(Some("udp")).unwrap();
+        let start = Instant::now();
+        for expected in &live {
+            let read = replay.read(Duration::ZERO).map_err(|e| e.kind());
+            assert_eq!(&read, expected);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        let error = replay.set_filter(Some("bad")).unwrap_err();
+        assert_eq!(error.to_string(), "bad filter");
+        assert_eq!(replay.read(Duration::ZERO).unwrap(), SourceRead::End);
+
+        // Diverging calls are reported.
+        let mut replay = SessionReplay::new(Cursor::new(file)).unwrap();
+        replay.realtime(false);
+        let error = replay.set_filter(Some("tcp")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(replay.read(Duration::ZERO).unwrap(), packet(1));
+        assert!(replay.read(Duration::ZERO).is_ok());
+        assert!(replay.set_filter(None).is_err());
+        assert!(SessionReplay::new(Cursor::new(b"NKIX\x01\x00")).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+//! Protocol statistics collected over captured traffic.
+//!
+//! Collectors implement [`Analyzer`](crate::analysis::Analyzer), so they can
+//! be fed from a capture file or a live stream alike.
+
+pub mod coverage;
+pub mod dns;
+pub mod hierarchy;
+pub mod histogram;
+pub mod overhead;
+pub mod streaming;
+pub mod tenant;
+pub mod tls;
+pub mod top;
+pub mod ttl;
@@ -0,0 +1,265 @@
+//! Protocol coverage of the dissectors.
+//!
+//! Tells which protocols of a capture a [`DissectorRegistry`] parses, fails
+//! to parse, or does not recognize at all. A frame is fully parsed when its
+//! layers are dissected up to the end of the protocol stack, partially
+//! parsed when a dissector fails or none handles the rest of the frame, and
+//! unrecognized when not even its first layer is. This tells users what is
+//! in a file and maintainers which dissectors are missing.
+//!
+//! Protocols without a dissector are named after the hint of the layer
+//! below, e.g. `ethtype 0x88cc`, `ip proto 132` or `udp port 5060`, the
+//! lower of both ports.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use netkit_packet::dissect::{DissectError, DissectorRegistry, NextHint};
+
+use crate::{
+    analysis::Analyzer,
+    export::{Column, Table, ToTable},
+};
+
+/// How much of a frame or protocol the dissectors cover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Coverage {
+    /// Parsed.
+    Full,
+    /// Parsed up to a failing or missing dissector.
+    Partial,
+    /// Not recognized.
+    Unrecognized,
+}
+
+impl Coverage {
+    /// Get the name of the coverage, as in tables.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Coverage::Full => "full",
+            Coverage::Partial => "partial",
+            Coverage::Unrecognized => "unrecognized",
+        }
+    }
+}
+
+/// Frames and bytes counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CoverageCount {
+    /// Number of frames.
+    pub frames: u64,
+    /// Number of bytes.
+    pub bytes: u64,
+}
+
+impl CoverageCount {
+    fn add(&mut self, bytes: usize) {
+        self.frames += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+/// Protocol coverage collector
+///
+/// Frames are counted by [`Coverage`] with their full length. Protocols are
+/// counted by the bytes attributed to them: the header of a parsed layer,
+/// or the rest of the frame from a layer which failed to parse or which no
+/// dissector recognizes.
+pub struct ProtocolCoverage {
+    registry: DissectorRegistry,
+    frames: BTreeMap<Coverage, CoverageCount>,
+    protocols: BTreeMap<(String, Coverage), CoverageCount>,
+}
+
+impl Default for ProtocolCoverage {
+    fn default() -> Self {
+        Self {
+            registry: DissectorRegistry::with_builtins(),
+            frames: BTreeMap::new(),
+            protocols: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProtocolCoverage {
+    /// Create a new collector with the built-in dissectors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the registry whose coverage is measured.
+    pub fn registry(&mut self, registry: DissectorRegistry) -> &mut Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Get the frames of a coverage.
+    pub fn frames(&self, coverage: Coverage) -> CoverageCount {
+        self.frames.get(&coverage).copied().unwrap_or_default()
+    }
+
+    /// Get a protocol's counts of a coverage.
+    pub fn protocol(&self, name: &str, coverage: Coverage) -> CoverageCount {
+        self.protocols
+            .get(&(name.to_string(), coverage))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the protocols with their coverage, most bytes first.
+    pub fn protocols(&self) -> Vec<(&str, Coverage, CoverageCount)> {
+        let mut protocols: Vec<_> = self
+            .protocols
+            .iter()
+            .map(|((name, coverage), count)| (name.as_str(), *coverage, *count))
+            .collect();
+        protocols.sort_by_key(|p| std::cmp::Reverse(p.2.bytes));
+        protocols
+    }
+
+    fn count(&mut self, name: String, coverage: Coverage, bytes: usize) {
+        self.protocols
+            .entry((name, coverage))
+            .or_default()
+            .add(bytes);
+    }
+}
+
+/// Name a protocol without a dissector after the hint of the layer below.
+fn unrecognized_name(hint: &NextHint) -> String {
+    match *hint {
+        NextHint::EthType(eth_type) => format!("ethtype {:#06x}", u16::from(eth_type)),
+        NextHint::IpProtocol(protocol) => format!("ip proto {}", u8::from(protocol)),
+        NextHint::Udp { src_port, dst_port } => format!("udp port {}", src_port.min(dst_port)),
+        NextHint::Tcp { src_port, dst_port } => format!("tcp port {}", src_port.min(dst_port)),
+        NextHint::Named(name) => name.to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+impl Analyzer for ProtocolCoverage {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        let dissected = self.registry.dissect_eth(frame);
+
+        let mut offset = 0;
+        for layer in &dissected.layers {
+            let len = layer.header_len().min(frame.len() - offset);
+            self.count(layer.name().to_string(), Coverage::Full, len);
+            offset += len;
+        }
+
+        let rest = frame.len() - dissected.offset;
+        let partial = if let Some(DissectError::Malformed { plugin, .. }) = &dissected.error {
+            self.count(plugin.to_string(), Coverage::Partial, rest);
+            true
+        } else if let Some(hint) = &dissected.unrecognized {
+            self.count(unrecognized_name(hint), Coverage::Unrecognized, rest);
+            true
+        } else {
+            false
+        };
+
+        let coverage = match (dissected.layers.is_empty(), partial) {
+            (true, _) => Coverage::Unrecognized,
+            (false, true) => Coverage::Partial,
+            (false, false) => Coverage::Full,
+        };
+        self.frames.entry(coverage).or_default().add(frame.len());
+    }
+}
+
+impl ToTable for ProtocolCoverage {
+    /// Convert into a table with one row per protocol and coverage, most
+    /// bytes first.
+    ///
+    /// Frames totals are not included; see [`ProtocolCoverage::frames`].
+    fn to_table(&self) -> Table {
+        let protocols = self.protocols();
+        let mut table = Table::new();
+        table
+            .push(
+                "protocol",
+                Column::Str(protocols.iter().map(|p| p.0.to_string()).collect()),
+            )
+            .push(
+                "coverage",
+                Column::Str(protocols.iter().map(|p| p.1.as_str().to_string()).collect()),
+            )
+            .push(
+                "frames",
+                Column::U64(protocols.iter().map(|p| p.2.frames).collect()),
+            )
+            .push(
+                "bytes",
+                Column::U64(protocols.iter().map(|p| p.2.bytes).collect()),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{dns, dns_question, prelude::*};
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    const CLIENT: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 0, 0, 1), 40000);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    #[test]
+    fn protocol_coverage() {
+        let dns = dns!(questions: dns_question!(qname: "example.com"));
+        let lldp = eth!(eth_type: EthType::from(0x88cc), payload: &[0u8; 10][..]);
+
+        let mut coverage = ProtocolCoverage::new();
+        coverage.on_packet(
+            Duration::ZERO,
+            &udp_frame(CLIENT, (SERVER, 53), dns.inner()),
+        );
+        coverage.on_packet(Duration::ZERO, &udp_frame(CLIENT, (SERVER, 5060), [0; 100]));
+        coverage.on_packet(Duration::ZERO, &udp_frame(CLIENT, (SERVER, 53), [0xff; 4]));
+        coverage.on_packet(Duration::ZERO, lldp.inner());
+        coverage.on_packet(Duration::ZERO, &[0; 6]);
+
+        assert_eq!(coverage.frames(Coverage::Full).frames, 1);
+        assert_eq!(coverage.frames(Coverage::Partial).frames, 3);
+        assert_eq!(
+            coverage.frames(Coverage::Unrecognized),
+            CoverageCount {
+                frames: 1,
+                bytes: 6
+            }
+        );
+
+        assert_eq!(coverage.protocol("Udp", Coverage::Full).bytes, 3 * 8);
+        assert_eq!(
+            coverage.protocol("Dns", Coverage::Full).bytes,
+            dns.inner().len() as u64
+        );
+        assert_eq!(coverage.protocol("Dns", Coverage::Partial).bytes, 4);
+        assert_eq!(
+            coverage.protocol("udp port 5060", Coverage::Unrecognized),
+            CoverageCount {
+                frames: 1,
+                bytes: 100
+            }
+        );
+        assert_eq!(
+            coverage.protocol("ethtype 0x88cc", Coverage::Unrecognized),
+            CoverageCount {
+                frames: 1,
+                bytes: 10
+            }
+        );
+        assert_eq!(coverage.protocol("Eth", Coverage::Partial).frames, 1);
+
+        let table = coverage.to_table();
+        assert_eq!(table.len(), coverage.protocols().len());
+        let Some(Column::Str(protocols)) = table.column("protocol") else {
+            panic!("no protocol column");
+        };
+        assert_eq!(protocols[0], "udp port 5060");
+    }
+}
@@ -0,0 +1,293 @@
+//! DNS statistics.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use netkit_packet::{
+    layer::dns::{DnsRCode, DnsRrType, DEFAULT_PORT},
+    prelude::*,
+};
+
+use super::histogram::Histogram;
+use crate::analysis::Analyzer;
+
+pub mod tcp;
+pub use tcp::DnsTcpReassembler;
+
+pub mod tunnel;
+pub use tunnel::DnsTunnelDetector;
+
+pub mod zone;
+pub use zone::{ZoneDiff, ZoneTransfer, ZoneTransferAnalyzer};
+
+/// Time after which an unanswered query is forgotten.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// DNS statistics collector
+///
+/// Counts queries and responses carried over UDP or TCP port 53, and
+/// collects:
+///
+/// - the distribution of query types (of queries),
+/// - the distribution of response codes (of responses),
+/// - the queried domains, optionally aggregated to their second-level domain,
+/// - a histogram of response sizes in power-of-two buckets,
+/// - a [`Histogram`] of response latencies, matching responses to queries by
+///   flow and transaction ID.
+#[derive(Clone, Debug, Default)]
+pub struct DnsStats {
+    aggregate_sld: bool,
+    queries: u64,
+    responses: u64,
+    qtypes: HashMap<DnsRrType, u64>,
+    rcodes: HashMap<DnsRCode, u64>,
+    domains: HashMap<String, u64>,
+    response_sizes: BTreeMap<usize, u64>,
+    latency: Histogram,
+    pending: HashMap<(FlowKey, u16), Duration>,
+    reassembler: DnsTcpReassembler,
+}
+
+impl DnsStats {
+    /// Create a new DNS statistics collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether domains are aggregated to their second-level domain.
+    ///
+    /// The aggregation keeps the last two labels (`www.example.com` becomes
+    /// `example.com`) and does not consult the public suffix list.
+    pub fn aggregate_sld(&mut self, aggregate_sld: bool) -> &mut Self {
+        self.aggregate_sld = aggregate_sld;
+        self
+    }
+
+    /// Get the number of queries.
+    pub fn queries(&self) -> u64 {
+        self.queries
+    }
+
+    /// Get the number of responses.
+    pub fn responses(&self) -> u64 {
+        self.responses
+    }
+
+    /// Get the query type distribution, most frequent first.
+    pub fn qtypes(&self) -> Vec<(DnsRrType, u64)> {
+        sorted_counts(self.qtypes.iter().map(|(k, v)| (*k, *v)), |k| k.to_string())
+    }
+
+    /// Get the response code distribution, most frequent first.
+    pub fn rcodes(&self) -> Vec<(DnsRCode, u64)> {
+        sorted_counts(self.rcodes.iter().map(|(k, v)| (*k, *v)), |k| k.to_string())
+    }
+
+    /// Get the `n` most queried domains, most frequent first.
+    pub fn top_domains(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut domains = sorted_counts(self.domains.iter().map(|(k, v)| (k.as_str(), *v)), |k| {
+            k.to_string()
+        });
+        domains.truncate(n);
+        domains
+    }
+
+    /// Get the response size histogram.
+    ///
+    /// Each entry is the inclusive lower bound of a power-of-two bucket (`0`,
+    /// `1`, `2`, `4`, ..., `512`, `1024`, ...) and the number of responses
+    /// whose DNS message size falls into it.
+    pub fn response_sizes(&self) -> Vec<(usize, u64)> {
+        self.response_sizes.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    /// Get the histogram of response latencies, in nanoseconds.
+    pub fn latency(&self) -> &Histogram {
+        &self.latency
+    }
+
+    /// Record a DNS message.
+    pub fn record<T: AsRef<[u8]>>(&mut self, dns: &Dns<T>) {
+        if dns.qr().get() {
+            self.responses += 1;
+            *self.rcodes.entry(dns.rcode().get()).or_default() += 1;
+
+            let size = dns.inner().as_ref().len();
+            let bucket = if size == 0 { 0 } else { 1 << size.ilog2() };
+            *self.response_sizes.entry(bucket).or_default() += 1;
+            return;
+        }
+
+        self.queries += 1;
+        for question in dns.questions() {
+            *self.qtypes.entry(question.qtype().get()).or_default() += 1;
+
+            let mut name = question.qname().to_string();
+            name.make_ascii_lowercase();
+            let name = name.trim_end_matches('.');
+            let domain = if self.aggregate_sld { sld(name) } else { name };
+            *self.domains.entry(domain.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Record a DNS message of a flow, matching responses to queries.
+    fn record_timed<T: AsRef<[u8]>>(&mut self, ts: Duration, key: FlowKey, dns: &Dns<T>) {
+        self.record(dns);
+
+        let id = dns.id().get();
+        if !dns.qr().get() {
+            if self.pending.len() >= 1 << 16 {
+                self.pending
+                    .retain(|_, sent| ts.saturating_sub(*sent) < QUERY_TIMEOUT);
+            }
+            self.pending.insert((key, id), ts);
+        } else if let Some(sent) = self.pending.remove(&(key.reversed(), id)) {
+            self.latency.record_duration(ts.saturating_sub(sent));
+        }
+    }
+}
+
+impl Analyzer for DnsStats {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        let key = FlowKey::from_ipv4(&ipv4);
+
+        if let Some(udp) = ipv4.udp() {
+            if let Some(dns) = udp.dns() {
+                self.record_timed(ts, key, &dns);
+            }
+            return;
+        }
+
+        // DNS over TCP frames messages across segments
+        let Some(tcp) = ipv4.tcp() else {
+            return;
+        };
+        if tcp.src_port().get() != DEFAULT_PORT && tcp.dst_port().get() != DEFAULT_PORT {
+            return;
+        }
+        for message in self.reassembler.push(&ipv4) {
+            if let Ok(dns) = Dns::new(message.as_slice()) {
+                self.record_timed(ts, key, &dns);
+            }
+        }
+    }
+}
+
+fn sld(name: &str) -> &str {
+    match name.rmatch_indices('.').nth(1) {
+        Some((i, _)) => &name[i + 1..],
+        None => name,
+    }
+}
+
+fn sorted_counts<K, F>(counts: impl Iterator<Item = (K, u64)>, key: F) -> Vec<(K, u64)>
+where
+    F: Fn(&K) -> String,
+{
+    let mut counts: Vec<_> = counts.collect();
+    counts.sort_by(|(ka, a), (kb, b)| b.cmp(a).then_with(|| key(ka).cmp(&key(kb))));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{dns, dns_question};
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    fn frame(dns: Dns<Vec<u8>>) -> Vec<u8> {
+        let client = (Ipv4Addr::new(10, 0, 0, 1), 40000);
+        let server = (Ipv4Addr::new(10, 0, 0, 2), 53);
+        if dns.qr().get() {
+            udp_frame(server, client, dns.inner())
+        } else {
+            udp_frame(client, server, dns.inner())
+        }
+    }
+
+    fn query(name: &str, qtype: DnsRrType) -> Vec<u8> {
+        frame(dns!(questions: dns_question!(qname: name, qtype: qtype)))
+    }
+
+    #[test]
+    fn dns_stats() {
+        let packets = [
+            query("www.example.com", DnsRrType::A),
+            query("WWW.example.com", DnsRrType::AAAA),
+            query("mail.example.com", DnsRrType::A),
+            query("example.org", DnsRrType::MX),
+            frame(dns!(qr: true, rcode: DnsRCode::NXDomain)),
+            frame(dns!(qr: true)),
+        ];
+
+        let mut stats = DnsStats::new();
+        let mut sld = DnsStats::new();
+        sld.aggregate_sld(true);
+        for packet in &packets {
+            stats.on_packet(Duration::ZERO, packet);
+            sld.on_packet(Duration::ZERO, packet);
+        }
+
+        assert_eq!(stats.queries(), 4);
+        assert_eq!(stats.responses(), 2);
+        assert_eq!(stats.qtypes()[0], (DnsRrType::A, 2));
+        assert_eq!(stats.rcodes().len(), 2);
+        assert_eq!(stats.top_domains(1), [("www.example.com", 2)]);
+        assert_eq!(stats.response_sizes(), [(8, 2)]);
+
+        assert_eq!(
+            sld.top_domains(10),
+            [("example.com", 3), ("example.org", 1)]
+        );
+
+        // Responses matched to their query by flow and ID.
+        let mut stats = DnsStats::new();
+        for (ms, id) in [(0, 1u16), (5, 2)] {
+            stats.on_packet(Duration::from_millis(ms), &frame(dns!(id: id)));
+        }
+        for (ms, id) in [(20, 2u16), (30, 1), (40, 3)] {
+            stats.on_packet(Duration::from_millis(ms), &frame(dns!(id: id, qr: true)));
+        }
+        assert_eq!(stats.latency().count(), 2);
+        assert_eq!(stats.latency().max(), Some(30_000_000));
+        assert_eq!(stats.latency().min(), Some(15_000_000));
+    }
+
+    #[test]
+    fn dns_stats_malformed_names() {
+        let raw = |question: &[u8]| {
+            let mut data = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+            data.extend_from_slice(question);
+            frame(Dns::new(data).unwrap())
+        };
+
+        let mut stats = DnsStats::new();
+        for question in [
+            // Truncated pointer, label past the end, reserved label type.
+            &b"\xc0"[..],
+            b"\x3fwww\x00\x00\x01\x00\x01",
+            b"\x80\x0c\x00\x01\x00\x01",
+            // Pointer without its qtype and qclass.
+            b"\xc0\x0c\x00",
+            // Non-UTF-8 label.
+            b"\x02\xff\xfe\x00\x00\x01\x00\x01",
+        ] {
+            stats.on_packet(Duration::ZERO, &raw(question));
+        }
+
+        assert_eq!(stats.queries(), 5);
+        assert_eq!(stats.qtypes(), [(DnsRrType::A, 1)]);
+        assert_eq!(stats.top_domains(10), [("\u{fffd}\u{fffd}", 1)]);
+    }
+}
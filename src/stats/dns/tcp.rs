@@ -0,0 +1,152 @@
+//! DNS over TCP message framing.
+
+use std::collections::HashMap;
+
+use netkit_packet::{layer::tcp::TcpFlags, prelude::*};
+
+use crate::analysis::trace_flow_growth;
+
+/// Reassembler of DNS messages carried over TCP
+///
+/// DNS over TCP prefixes every message with its 2-byte length (RFC 1035
+/// section 4.2.2), and a message may span several segments or share one with
+/// other messages. Segments are followed per direction in sequence order:
+/// retransmitted bytes are skipped, while a gap (a lost or out of order
+/// segment) drops the pending bytes and restarts framing at the next segment.
+#[derive(Clone, Debug, Default)]
+pub struct DnsTcpReassembler {
+    streams: HashMap<FlowKey, Stream>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Stream {
+    next_seq: Option<u32>,
+    pending: Vec<u8>,
+}
+
+impl DnsTcpReassembler {
+    /// Create a new reassembler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of directions followed.
+    pub fn streams(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Feed a TCP segment, returning the DNS messages it completes.
+    ///
+    /// Returned messages do not include the length prefix. Segments which are
+    /// not TCP are ignored. The direction is forgotten on FIN or RST.
+    pub fn push<T: AsRef<[u8]>>(&mut self, ipv4: &Ipv4<T>) -> Vec<Vec<u8>> {
+        let Some(tcp) = ipv4.tcp() else {
+            return Vec::new();
+        };
+        let key = FlowKey::from_ipv4(ipv4);
+        let flags = tcp.flags().get();
+        let seq = tcp.seq_num().get();
+        let payload = tcp.payload();
+
+        trace_flow_growth::<Self, _>(&self.streams, &key);
+        let stream = self.streams.entry(key).or_default();
+        if flags.contains(TcpFlags::SYN) {
+            stream.next_seq = Some(seq.wrapping_add(1));
+            stream.pending.clear();
+        }
+
+        let seq = if flags.contains(TcpFlags::SYN) {
+            seq.wrapping_add(1)
+        } else {
+            seq
+        };
+        let next_seq = *stream.next_seq.get_or_insert(seq);
+        let delivered = next_seq.wrapping_sub(seq) as i32;
+        if delivered < 0 {
+            // Gap: restart framing at this segment
+            stream.pending.clear();
+            stream.pending.extend_from_slice(payload);
+        } else if (delivered as usize) < payload.len() {
+            stream
+                .pending
+                .extend_from_slice(&payload[delivered as usize..]);
+        }
+        let end = seq.wrapping_add(payload.len() as u32);
+        if (end.wrapping_sub(next_seq) as i32) > 0 {
+            stream.next_seq = Some(end);
+        }
+
+        let mut messages = Vec::new();
+        while let [hi, lo, rest @ ..] = stream.pending.as_slice() {
+            let len = u16::from_be_bytes([*hi, *lo]) as usize;
+            if rest.len() < len {
+                break;
+            }
+            messages.push(rest[..len].to_vec());
+            stream.pending.drain(..2 + len);
+        }
+
+        if flags.intersects(TcpFlags::FIN | TcpFlags::RST) {
+            self.streams.remove(&key);
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn segment(seq: u32, flags: TcpFlags, payload: &[u8]) -> Ipv4<Vec<u8>> {
+        let tcp = tcp!(
+            src_port: 53u16,
+            dst_port: 40000u16,
+            seq_num: seq,
+            flags: flags,
+            payload: payload,
+        );
+        ipv4!(
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            protocol: IpProtocol::Tcp,
+            payload: tcp.inner(),
+        )
+    }
+
+    #[test]
+    fn dns_tcp_reassembly() {
+        let mut reassembler = DnsTcpReassembler::new();
+        let stream = b"\x00\x03abc\x00\x02de\x00\x04fghi";
+
+        assert!(reassembler
+            .push(&segment(99, TcpFlags::SYN, &[]))
+            .is_empty());
+        assert_eq!(
+            reassembler.push(&segment(100, TcpFlags::ACK, &stream[..6])),
+            [b"abc".to_vec()]
+        );
+        // Retransmission overlapping the delivered bytes
+        assert_eq!(
+            reassembler.push(&segment(100, TcpFlags::ACK, &stream[..9])),
+            [b"de".to_vec()]
+        );
+        assert_eq!(
+            reassembler.push(&segment(109, TcpFlags::ACK, &stream[9..])),
+            [b"fghi".to_vec()]
+        );
+
+        // A gap restarts framing at the next segment
+        assert!(reassembler
+            .push(&segment(115, TcpFlags::ACK, b"\x00\x05ab"))
+            .is_empty());
+        assert_eq!(
+            reassembler.push(&segment(200, TcpFlags::ACK, b"\x00\x01z")),
+            [b"z".to_vec()]
+        );
+
+        reassembler.push(&segment(203, TcpFlags::FIN, &[]));
+        assert_eq!(reassembler.streams(), 0);
+    }
+}
@@ -0,0 +1,413 @@
+//! DNS tunneling and exfiltration detection.
+//!
+//! Tunnels carry data in the names they query under a domain they control
+//! (`<encoded data>.t.example.com`) and in the answers, which favors record
+//! types holding arbitrary data like TXT and NULL. This gives them away:
+//!
+//! - the subdomains are long and look random (high character entropy),
+//! - each name is queried once, so resolvers never answer from cache and
+//!   the query rate to the domain is high,
+//! - names which do not exist are answered with NXDOMAIN, but since no name
+//!   repeats, negative caching does not slow the tunnel down either,
+//! - TXT and NULL queries are far more frequent than in normal traffic.
+//!
+//! [`DnsTunnelDetector`] scores each query on these features, per
+//! second-level domain and time window, and raises an [`Alert`] the first
+//! time a domain scores above the threshold within a window.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::{
+    layer::dns::{DnsRCode, DnsRrType, DEFAULT_PORT},
+    prelude::*,
+};
+
+use super::{sld, tcp::DnsTcpReassembler};
+use crate::analysis::{
+    alert::{Alert, AlertSource},
+    Analyzer,
+};
+
+/// Name of the detector in its alerts.
+pub const DETECTOR: &str = "dns-tunnel";
+
+/// Default window over which per-domain features are computed.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default number of queries to a domain per window scoring as a full rate.
+pub const DEFAULT_RATE_THRESHOLD: u64 = 100;
+
+/// Default score above which an alert is raised.
+pub const DEFAULT_SCORE_THRESHOLD: f64 = 0.6;
+
+/// Subdomain length from which the length feature starts to score.
+const SHORT_SUBDOMAIN: usize = 20;
+
+/// Subdomain length at which the length feature scores fully.
+const LONG_SUBDOMAIN: usize = 60;
+
+/// Character entropy (bits) from which the entropy feature starts to score.
+const LOW_NAME_ENTROPY: f64 = 3.0;
+
+/// Character entropy (bits) at which the entropy feature scores fully, about
+/// that of base32 encoded data.
+const HIGH_NAME_ENTROPY: f64 = 4.5;
+
+/// Features of the queries to a domain within the current window.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DomainFeatures {
+    /// Number of queries.
+    pub queries: u64,
+    /// Number of distinct subdomains queried.
+    pub unique_subdomains: u64,
+    /// Number of TXT and NULL queries.
+    pub data_queries: u64,
+    /// Number of responses.
+    pub responses: u64,
+    /// Number of NXDOMAIN responses.
+    pub nxdomains: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct DomainState {
+    window_start: Duration,
+    features: DomainFeatures,
+    subdomains: HashSet<String>,
+    alerted: bool,
+}
+
+/// DNS tunneling detector.
+#[derive(Clone, Debug)]
+pub struct DnsTunnelDetector {
+    window: Duration,
+    rate_threshold: u64,
+    score_threshold: f64,
+    allowlist: HashSet<String>,
+    domains: HashMap<String, DomainState>,
+    alerts: Vec<Alert>,
+    reassembler: DnsTcpReassembler,
+}
+
+impl Default for DnsTunnelDetector {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            rate_threshold: DEFAULT_RATE_THRESHOLD,
+            score_threshold: DEFAULT_SCORE_THRESHOLD,
+            allowlist: HashSet::new(),
+            domains: HashMap::new(),
+            alerts: Vec::new(),
+            reassembler: DnsTcpReassembler::default(),
+        }
+    }
+}
+
+/// Get the Shannon entropy of the characters of a string, in bits.
+pub fn name_entropy(name: &str) -> f64 {
+    if name.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0u32; 256];
+    for b in name.bytes() {
+        counts[b as usize] += 1;
+    }
+    let len = name.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn ramp(value: f64, low: f64, high: f64) -> f64 {
+    ((value - low) / (high - low)).clamp(0.0, 1.0)
+}
+
+impl DnsTunnelDetector {
+    /// Create a new DNS tunneling detector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the window over which per-domain features are computed.
+    pub fn window(&mut self, window: Duration) -> &mut Self {
+        self.window = window;
+        self
+    }
+
+    /// Set the number of queries to a domain per window scoring as a full
+    /// rate.
+    pub fn rate_threshold(&mut self, rate_threshold: u64) -> &mut Self {
+        self.rate_threshold = rate_threshold.max(1);
+        self
+    }
+
+    /// Set the score above which an alert is raised.
+    pub fn score_threshold(&mut self, score_threshold: f64) -> &mut Self {
+        self.score_threshold = score_threshold;
+        self
+    }
+
+    /// Never score queries to a second-level domain, e.g. of a CDN or an
+    /// anti-virus service using DNS lookups legitimately.
+    pub fn allow(&mut self, domain: impl Into<String>) -> &mut Self {
+        let mut domain = domain.into();
+        domain.make_ascii_lowercase();
+        self.allowlist
+            .insert(domain.trim_end_matches('.').to_string());
+        self
+    }
+
+    /// Get the features of a domain within its current window.
+    pub fn features(&self, domain: &str) -> Option<&DomainFeatures> {
+        self.domains.get(domain).map(|state| &state.features)
+    }
+
+    /// Score a query for `subdomain` given the features of its domain.
+    ///
+    /// The score is a weighted sum of the subdomain entropy (0.3) and length
+    /// (0.2), the query rate (0.2), the share of TXT and NULL queries (0.15)
+    /// and the share of NXDOMAIN responses (0.15).
+    pub fn score(&self, subdomain: &str, features: &DomainFeatures) -> f64 {
+        let share = |n: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                n as f64 / total as f64
+            }
+        };
+
+        let entropy = ramp(name_entropy(subdomain), LOW_NAME_ENTROPY, HIGH_NAME_ENTROPY);
+        let length = ramp(
+            subdomain.len() as f64,
+            SHORT_SUBDOMAIN as f64,
+            LONG_SUBDOMAIN as f64,
+        );
+        // Repeated names are served from cache, so only unique ones count.
+        let rate = ramp(
+            features.unique_subdomains as f64,
+            0.0,
+            self.rate_threshold as f64,
+        );
+        let data = share(features.data_queries, features.queries);
+        let nxdomain = share(features.nxdomains, features.responses);
+
+        0.3 * entropy + 0.2 * length + 0.2 * rate + 0.15 * data + 0.15 * nxdomain
+    }
+
+    fn state(&mut self, ts: Duration, domain: &str) -> Option<&mut DomainState> {
+        if self.allowlist.contains(domain) {
+            return None;
+        }
+        let state = self.domains.entry(domain.to_string()).or_default();
+        if ts.saturating_sub(state.window_start) >= self.window {
+            *state = DomainState {
+                window_start: ts,
+                ..Default::default()
+            };
+        }
+        Some(state)
+    }
+
+    /// Record a DNS message sent from `src` to `dst`.
+    pub fn record<T: AsRef<[u8]>>(&mut self, ts: Duration, src: IpAddr, dst: IpAddr, dns: &Dns<T>) {
+        let response = dns.qr().get();
+        let nxdomain = dns.rcode().get() == DnsRCode::NXDomain;
+
+        for question in dns.questions() {
+            let mut name = question.qname().to_string();
+            name.make_ascii_lowercase();
+            let name = name.trim_end_matches('.');
+            let domain = sld(name);
+            let subdomain = name
+                .strip_suffix(domain)
+                .unwrap_or_default()
+                .trim_end_matches('.');
+
+            if response {
+                if let Some(state) = self.state(ts, domain) {
+                    state.features.responses += 1;
+                    state.features.nxdomains += nxdomain as u64;
+                }
+                continue;
+            }
+
+            let qtype = question.qtype().get();
+            let Some(state) = self.state(ts, domain) else {
+                continue;
+            };
+            state.features.queries += 1;
+            if qtype == DnsRrType::TXT || qtype == DnsRrType::NULL {
+                state.features.data_queries += 1;
+            }
+            if state.subdomains.insert(subdomain.to_string()) {
+                state.features.unique_subdomains += 1;
+            }
+            if state.alerted {
+                continue;
+            }
+
+            let features = state.features.clone();
+            let score = self.score(subdomain, &features);
+            if score >= self.score_threshold {
+                if let Some(state) = self.domains.get_mut(domain) {
+                    state.alerted = true;
+                }
+                self.alerts.push(Alert {
+                    ts,
+                    detector: DETECTOR,
+                    score,
+                    src,
+                    dst,
+                    subject: domain.to_string(),
+                    message: format!(
+                        "possible DNS tunnel: {} queries to {} distinct names, {} TXT/NULL, {} NXDOMAIN",
+                        features.queries,
+                        features.unique_subdomains,
+                        features.data_queries,
+                        features.nxdomains,
+                    ),
+                    packet: None,
+                });
+            }
+        }
+    }
+}
+
+impl AlertSource for DnsTunnelDetector {
+    fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    fn take_alerts(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.alerts)
+    }
+}
+
+impl Analyzer for DnsTunnelDetector {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        let src = IpAddr::V4(ipv4.src().get());
+        let dst = IpAddr::V4(ipv4.dst().get());
+
+        if let Some(udp) = ipv4.udp() {
+            if let Some(dns) = udp.dns() {
+                self.record(ts, src, dst, &dns);
+            }
+            return;
+        }
+
+        let Some(tcp) = ipv4.tcp() else {
+            return;
+        };
+        if tcp.src_port().get() != DEFAULT_PORT && tcp.dst_port().get() != DEFAULT_PORT {
+            return;
+        }
+        for message in self.reassembler.push(&ipv4) {
+            if let Ok(dns) = Dns::new(message.as_slice()) {
+                self.record(ts, src, dst, &dns);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{dns, dns_question};
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    fn frame(dns: Dns<Vec<u8>>) -> Vec<u8> {
+        let (src_port, dst_port) = if dns.qr().get() {
+            (53, 40000)
+        } else {
+            (40000, 53)
+        };
+        udp_frame(
+            (Ipv4Addr::new(10, 0, 0, 1), src_port),
+            (Ipv4Addr::new(10, 0, 0, 2), dst_port),
+            dns.inner(),
+        )
+    }
+
+    #[test]
+    fn dns_tunnel_detection() {
+        let mut detector = DnsTunnelDetector::new();
+        detector.rate_threshold(20).allow("cdn.net");
+
+        let mut seed = 0x1234_5678u32;
+        for i in 0..40u64 {
+            let ts = Duration::from_millis(i * 100);
+            // Benign lookups, repeated
+            detector.on_packet(
+                ts,
+                &frame(
+                    dns!(questions: dns_question!(qname: "www.example.com", qtype: DnsRrType::A)),
+                ),
+            );
+
+            // Random-looking labels, as base32 encoded data
+            let label: String = (0..50)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    b"abcdefghijklmnopqrstuvwxyz234567"[(seed % 32) as usize] as char
+                })
+                .collect();
+            for domain in ["t.evil.com", "cdn.net"] {
+                let name = format!("{label}.{domain}");
+                detector.on_packet(
+                    ts,
+                    &frame(
+                        dns!(questions: dns_question!(qname: name.as_str(), qtype: DnsRrType::TXT)),
+                    ),
+                );
+                detector.on_packet(
+                    ts,
+                    &frame(dns!(
+                        qr: true,
+                        rcode: DnsRCode::NXDomain,
+                        questions: dns_question!(qname: name.as_str(), qtype: DnsRrType::TXT),
+                    )),
+                );
+            }
+        }
+
+        let alerts = detector.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].subject, "evil.com");
+        assert!(alerts[0].score >= DEFAULT_SCORE_THRESHOLD);
+        assert_eq!(
+            detector.features("example.com").unwrap().unique_subdomains,
+            1
+        );
+        assert!(detector.features("cdn.net").is_none());
+    }
+
+    #[test]
+    fn dns_tunnel_malformed_names() {
+        let mut detector = DnsTunnelDetector::new();
+        for question in [&b"\xc0"[..], b"\x3fabc\x00\x00\x10\x00\x01"] {
+            let mut data = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+            data.extend_from_slice(question);
+            detector.on_packet(Duration::ZERO, &frame(Dns::new(data).unwrap()));
+        }
+        assert!(detector.alerts().is_empty());
+    }
+}
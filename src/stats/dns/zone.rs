@@ -0,0 +1,520 @@
+//! DNS zone transfers (AXFR and IXFR).
+//!
+//! A zone transfer is answered over TCP by a series of messages whose
+//! records form one stream, opened and closed by the SOA record of the zone
+//! (RFC 5936). Only the first message repeats the question, so the messages
+//! are tied together by their connection. An incremental transfer (RFC 1995)
+//! is a sequence of differences, each a deletion list opened by the SOA of
+//! the old serial and an addition list opened by the SOA of the new one.
+
+use std::{collections::HashMap, time::Duration};
+
+use netkit_packet::{
+    layer::{
+        dns::{DnsRCode, DnsRData, DnsRecord, DnsRrType, DEFAULT_PORT},
+        tcp::TcpFlags,
+    },
+    prelude::*,
+};
+
+use super::tcp::DnsTcpReassembler;
+use crate::{
+    analysis::{trace_flow_growth, Analyzer},
+    export::{Column, Table, ToTable},
+};
+
+/// Default maximum number of records kept per transfer.
+pub const DEFAULT_MAX_RECORDS: usize = 1 << 20;
+
+/// Difference between two versions of a zone, from an incremental transfer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneDiff {
+    /// Serial of the old version.
+    pub from: u32,
+    /// Serial of the new version.
+    pub to: u32,
+    /// Records deleted from the old version.
+    pub deleted: Vec<DnsRecord>,
+    /// Records added in the new version.
+    pub added: Vec<DnsRecord>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Opening,
+    Started,
+    Full,
+    Deletions,
+    Additions,
+    Done,
+}
+
+/// A zone transfer seen in the capture.
+#[derive(Clone, Debug)]
+pub struct ZoneTransfer {
+    /// Transferred zone, lowercase and without the trailing dot.
+    pub zone: String,
+    /// Transfer type, [`DnsRrType::AXFR`] or [`DnsRrType::IXFR`].
+    pub kind: DnsRrType,
+    /// Connection in the server to client direction.
+    pub key: FlowKey,
+    /// Response code of the first message.
+    pub rcode: DnsRCode,
+    /// Serial of the transferred version, from the opening SOA.
+    pub serial: Option<u32>,
+    /// Records in transfer order, including the opening and closing SOA.
+    pub records: Vec<DnsRecord>,
+    /// Records not kept once the maximum was reached.
+    pub dropped: u64,
+    /// Differences of an incremental transfer, empty otherwise.
+    pub diffs: Vec<ZoneDiff>,
+    /// Number of messages.
+    pub messages: u64,
+    /// Timestamp of the first message.
+    pub first_seen: Duration,
+    /// Timestamp of the last message.
+    pub last_seen: Duration,
+    phase: Phase,
+}
+
+impl ZoneTransfer {
+    fn new(zone: String, kind: DnsRrType, key: FlowKey, rcode: DnsRCode, ts: Duration) -> Self {
+        Self {
+            zone,
+            kind,
+            key,
+            rcode,
+            serial: None,
+            records: Vec::new(),
+            dropped: 0,
+            diffs: Vec::new(),
+            messages: 0,
+            first_seen: ts,
+            last_seen: ts,
+            phase: Phase::Opening,
+        }
+    }
+
+    /// Whether the closing SOA was seen, or an IXFR was answered with the
+    /// single SOA of an up to date zone.
+    pub fn is_complete(&self) -> bool {
+        self.phase == Phase::Done && self.serial.is_some()
+    }
+
+    /// Whether the transfer is a sequence of differences.
+    pub fn is_incremental(&self) -> bool {
+        !self.diffs.is_empty()
+    }
+
+    /// Get the records of the zone of a complete full transfer.
+    ///
+    /// This excludes the closing SOA. Returns `None` for incremental or
+    /// incomplete transfers, and if records were dropped.
+    pub fn zone_records(&self) -> Option<&[DnsRecord]> {
+        if !self.is_complete() || self.is_incremental() || self.dropped > 0 {
+            return None;
+        }
+        Some(&self.records[..self.records.len().saturating_sub(1)])
+    }
+
+    fn push(&mut self, record: DnsRecord, max_records: usize) {
+        let soa = match record.rdata {
+            DnsRData::Soa { serial, .. } => Some(serial),
+            _ => None,
+        };
+
+        self.phase = match (self.phase, soa) {
+            (Phase::Opening, Some(serial)) => {
+                self.serial = Some(serial);
+                Phase::Started
+            }
+            (Phase::Opening, None) | (Phase::Done, _) => Phase::Done,
+            (Phase::Started | Phase::Full | Phase::Additions, Some(serial))
+                if Some(serial) == self.serial =>
+            {
+                Phase::Done
+            }
+            (Phase::Started | Phase::Additions, Some(from)) if self.kind == DnsRrType::IXFR => {
+                self.diffs.push(ZoneDiff {
+                    from,
+                    to: from,
+                    deleted: Vec::new(),
+                    added: Vec::new(),
+                });
+                Phase::Deletions
+            }
+            (Phase::Deletions, Some(to)) => {
+                if let Some(diff) = self.diffs.last_mut() {
+                    diff.to = to;
+                }
+                Phase::Additions
+            }
+            (Phase::Deletions, None) => {
+                if let Some(diff) = self.diffs.last_mut() {
+                    diff.deleted.push(record.clone());
+                }
+                Phase::Deletions
+            }
+            (Phase::Additions, None) => {
+                if let Some(diff) = self.diffs.last_mut() {
+                    diff.added.push(record.clone());
+                }
+                Phase::Additions
+            }
+            (Phase::Started | Phase::Full | Phase::Additions, _) => Phase::Full,
+        };
+
+        if self.records.len() < max_records {
+            self.records.push(record);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Zone transfer analyzer
+///
+/// Follows the DNS responses sent from port 53 over TCP and collects the
+/// records of every AXFR and IXFR across message and segment boundaries,
+/// until the closing SOA. Transfers cut short by the end of the connection
+/// or an error response are kept as incomplete.
+#[derive(Clone, Debug)]
+pub struct ZoneTransferAnalyzer {
+    max_records: usize,
+    reassembler: DnsTcpReassembler,
+    active: HashMap<FlowKey, ZoneTransfer>,
+    transfers: Vec<ZoneTransfer>,
+}
+
+impl Default for ZoneTransferAnalyzer {
+    fn default() -> Self {
+        Self {
+            max_records: DEFAULT_MAX_RECORDS,
+            reassembler: DnsTcpReassembler::new(),
+            active: HashMap::new(),
+            transfers: Vec::new(),
+        }
+    }
+}
+
+impl ZoneTransferAnalyzer {
+    /// Create a new zone transfer analyzer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of records kept per transfer.
+    pub fn max_records(&mut self, max_records: usize) -> &mut Self {
+        self.max_records = max_records;
+        self
+    }
+
+    /// Get the finished transfers, complete or not, in the order they ended.
+    pub fn transfers(&self) -> &[ZoneTransfer] {
+        &self.transfers
+    }
+
+    /// Get the transfers still in progress, oldest first.
+    pub fn in_progress(&self) -> Vec<&ZoneTransfer> {
+        let mut active: Vec<_> = self.active.values().collect();
+        active.sort_by_key(|transfer| (transfer.first_seen, transfer.key.to_string()));
+        active
+    }
+
+    fn on_message(&mut self, ts: Duration, key: FlowKey, message: &[u8]) {
+        let Ok(dns) = Dns::new(message) else {
+            return;
+        };
+        if !dns.qr().get() {
+            return;
+        }
+
+        let mut transfer = match self.active.remove(&key) {
+            Some(transfer) => transfer,
+            None => {
+                let Some(question) = dns.questions().next() else {
+                    return;
+                };
+                let kind = question.qtype().get();
+                if kind != DnsRrType::AXFR && kind != DnsRrType::IXFR {
+                    return;
+                }
+                let mut zone = question.qname().to_string();
+                zone.make_ascii_lowercase();
+                let zone = zone.trim_end_matches('.').to_string();
+                ZoneTransfer::new(zone, kind, key, dns.rcode().get(), ts)
+            }
+        };
+        transfer.messages += 1;
+        transfer.last_seen = ts;
+
+        if dns.rcode().get() != DnsRCode::NoError {
+            transfer.phase = Phase::Done;
+        }
+        for record in dns.records() {
+            if transfer.phase == Phase::Done {
+                break;
+            }
+            transfer.push(record, self.max_records);
+        }
+        // A single SOA answers an IXFR for an up to date zone
+        if transfer.kind == DnsRrType::IXFR && transfer.phase == Phase::Started {
+            transfer.phase = Phase::Done;
+        }
+
+        if transfer.phase == Phase::Done {
+            self.transfers.push(transfer);
+        } else {
+            trace_flow_growth::<Self, _>(&self.active, &key);
+            self.active.insert(key, transfer);
+        }
+    }
+}
+
+impl Analyzer for ZoneTransferAnalyzer {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        let Some(tcp) = ipv4.tcp() else {
+            return;
+        };
+        let key = FlowKey::from_ipv4(&ipv4);
+
+        if tcp.src_port().get() == DEFAULT_PORT {
+            for message in self.reassembler.push(&ipv4) {
+                self.on_message(ts, key, &message);
+            }
+        }
+
+        if tcp.flags().get().intersects(TcpFlags::FIN | TcpFlags::RST) {
+            for key in [key, key.reversed()] {
+                if let Some(transfer) = self.active.remove(&key) {
+                    self.transfers.push(transfer);
+                }
+            }
+        }
+    }
+}
+
+impl ToTable for ZoneTransferAnalyzer {
+    /// Convert into a table with one row per finished transfer.
+    fn to_table(&self) -> Table {
+        let transfers = &self.transfers;
+        let u64s = |f: fn(&ZoneTransfer) -> u64| Column::U64(transfers.iter().map(f).collect());
+        let strs = |f: fn(&ZoneTransfer) -> String| Column::Str(transfers.iter().map(f).collect());
+
+        let mut table = Table::new();
+        table
+            .push("zone", strs(|t| t.zone.clone()))
+            .push("kind", strs(|t| t.kind.to_string()))
+            .push("server", strs(|t| t.key.src.to_string()))
+            .push("client", strs(|t| t.key.dst.to_string()))
+            .push("rcode", strs(|t| t.rcode.to_string()))
+            .push(
+                "serial",
+                strs(|t| t.serial.map(|s| s.to_string()).unwrap_or_default()),
+            )
+            .push("messages", u64s(|t| t.messages))
+            .push("records", u64s(|t| t.records.len() as u64 + t.dropped))
+            .push("diffs", u64s(|t| t.diffs.len() as u64))
+            .push("complete", u64s(|t| t.is_complete() as u64))
+            .push(
+                "duration_ms",
+                Column::F64(
+                    transfers
+                        .iter()
+                        .map(|t| (t.last_seen - t.first_seen).as_secs_f64() * 1e3)
+                        .collect(),
+                ),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{
+        dns, dns_question,
+        layer::dns::{DnsBuilder, DnsQuestion},
+    };
+
+    use super::*;
+    use crate::fixture::tcp_frame;
+
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 53);
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn soa(serial: u32) -> DnsRecord {
+        DnsRecord::new(
+            "example.com",
+            3600,
+            DnsRData::Soa {
+                mname: "ns1.example.com".into(),
+                rname: "hostmaster.example.com".into(),
+                serial,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            },
+        )
+    }
+
+    fn a(name: &str, last: u8) -> DnsRecord {
+        DnsRecord::new(name, 300, DnsRData::A([192, 0, 2, last].into()))
+    }
+
+    fn question(qtype: DnsRrType) -> DnsQuestion<Vec<u8>> {
+        dns_question!(qname: "example.com", qtype: qtype)
+    }
+
+    fn message(question: Option<DnsQuestion<Vec<u8>>>, records: &[DnsRecord]) -> Vec<u8> {
+        let mut builder = DnsBuilder::new();
+        builder.id(7u16).qr(true).aa(true);
+        if let Some(question) = question {
+            builder.questions(question);
+        }
+        for record in records {
+            builder.answers(record.clone());
+        }
+        let dns = builder.build();
+
+        let mut data = (dns.inner().len() as u16).to_be_bytes().to_vec();
+        data.extend_from_slice(dns.inner());
+        data
+    }
+
+    fn segment(seq: u32, flags: TcpFlags, payload: &[u8]) -> Vec<u8> {
+        tcp_frame((SERVER, 53), (CLIENT, 40000), seq, flags, payload)
+    }
+
+    fn feed(analyzer: &mut ZoneTransferAnalyzer, stream: &[u8], chunk: usize, fin: bool) {
+        analyzer.on_packet(Duration::ZERO, &segment(999, TcpFlags::SYN, &[]));
+        for (i, data) in stream.chunks(chunk).enumerate() {
+            let seq = 1000 + (i * chunk) as u32;
+            analyzer.on_packet(
+                Duration::from_millis(i as u64),
+                &segment(seq, TcpFlags::ACK, data),
+            );
+        }
+        if fin {
+            let seq = 1000 + stream.len() as u32;
+            analyzer.on_packet(Duration::from_secs(1), &segment(seq, TcpFlags::FIN, &[]));
+        }
+    }
+
+    #[test]
+    fn zone_transfer_axfr() {
+        let mut stream = message(
+            Some(question(DnsRrType::AXFR)),
+            &[soa(5), a("example.com", 1), a("www.example.com", 2)],
+        );
+        stream.extend(message(None, &[a("mail.example.com", 3), soa(5)]));
+
+        let mut analyzer = ZoneTransferAnalyzer::new();
+        feed(&mut analyzer, &stream, 37, false);
+
+        assert!(analyzer.in_progress().is_empty());
+        let transfer = &analyzer.transfers()[0];
+        assert_eq!(transfer.zone, "example.com");
+        assert_eq!(transfer.kind, DnsRrType::AXFR);
+        assert_eq!(transfer.key.src, SERVER);
+        assert_eq!(transfer.serial, Some(5));
+        assert_eq!(transfer.messages, 2);
+        assert!(transfer.is_complete());
+        assert!(!transfer.is_incremental());
+
+        let records = transfer.zone_records().unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[3], a("mail.example.com", 3));
+
+        let table = analyzer.to_table();
+        assert_eq!(table.column("records"), Some(&Column::U64(vec![5])));
+    }
+
+    #[test]
+    fn zone_transfer_ixfr() {
+        let stream = message(
+            Some(question(DnsRrType::IXFR)),
+            &[
+                soa(3),
+                soa(1),
+                a("old.example.com", 1),
+                soa(2),
+                a("new.example.com", 2),
+                soa(2),
+                soa(3),
+                a("newer.example.com", 3),
+                soa(3),
+            ],
+        );
+        let mut analyzer = ZoneTransferAnalyzer::new();
+        feed(&mut analyzer, &stream, 1000, false);
+
+        let transfer = &analyzer.transfers()[0];
+        assert!(transfer.is_complete());
+        assert_eq!(transfer.zone_records(), None);
+        assert_eq!(
+            transfer.diffs,
+            [
+                ZoneDiff {
+                    from: 1,
+                    to: 2,
+                    deleted: vec![a("old.example.com", 1)],
+                    added: vec![a("new.example.com", 2)],
+                },
+                ZoneDiff {
+                    from: 2,
+                    to: 3,
+                    deleted: vec![],
+                    added: vec![a("newer.example.com", 3)],
+                },
+            ]
+        );
+
+        // Up to date zone
+        let mut analyzer = ZoneTransferAnalyzer::new();
+        feed(
+            &mut analyzer,
+            &message(Some(question(DnsRrType::IXFR)), &[soa(3)]),
+            1000,
+            false,
+        );
+        assert!(analyzer.transfers()[0].is_complete());
+    }
+
+    #[test]
+    fn zone_transfer_incomplete() {
+        let stream = message(
+            Some(question(DnsRrType::AXFR)),
+            &[soa(5), a("example.com", 1)],
+        );
+        let mut analyzer = ZoneTransferAnalyzer::new();
+        analyzer.max_records(1);
+        feed(&mut analyzer, &stream, 1000, false);
+        assert_eq!(analyzer.in_progress().len(), 1);
+
+        analyzer.on_packet(Duration::ZERO, &segment(5000, TcpFlags::RST, &[]));
+        let transfer = &analyzer.transfers()[0];
+        assert!(!transfer.is_complete());
+        assert_eq!(transfer.dropped, 1);
+
+        let refused = dns!(
+            qr: true,
+            rcode: DnsRCode::Refused,
+            questions: question(DnsRrType::AXFR)
+        );
+        let mut stream = (refused.inner().len() as u16).to_be_bytes().to_vec();
+        stream.extend_from_slice(refused.inner());
+        let mut analyzer = ZoneTransferAnalyzer::new();
+        feed(&mut analyzer, &stream, 1000, true);
+        assert_eq!(analyzer.transfers()[0].rcode, DnsRCode::Refused);
+        assert!(!analyzer.transfers()[0].is_complete());
+    }
+}
@@ -0,0 +1,193 @@
+//! Protocol hierarchy statistics.
+//!
+//! Like Wireshark's "Protocol Hierarchy", counts the frames and bytes of
+//! every protocol stack seen, e.g. `Eth/Ipv4/Udp`. Every prefix of a stack
+//! is counted too, so the `Eth/Ipv4` node covers all the IPv4 traffic.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use netkit_packet::dissect::DissectorRegistry;
+
+use crate::{
+    analysis::Analyzer,
+    export::{Column, Table, ToTable},
+};
+
+/// Frames and bytes of a node of the hierarchy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HierarchyNode {
+    /// Number of frames containing the stack.
+    pub frames: u64,
+    /// Number of bytes of these frames.
+    pub bytes: u64,
+}
+
+/// Protocol hierarchy collector
+pub struct ProtocolHierarchy {
+    registry: DissectorRegistry,
+    frames: u64,
+    bytes: u64,
+    nodes: BTreeMap<String, HierarchyNode>,
+}
+
+impl Default for ProtocolHierarchy {
+    fn default() -> Self {
+        Self {
+            registry: DissectorRegistry::with_builtins(),
+            frames: 0,
+            bytes: 0,
+            nodes: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProtocolHierarchy {
+    /// Create a new collector with the built-in dissectors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the registry used to walk the layers of a frame.
+    pub fn registry(&mut self, registry: DissectorRegistry) -> &mut Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Get the number of frames.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Get the number of bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Get a node by its stack, e.g. `Eth/Ipv4`.
+    pub fn node(&self, stack: &str) -> Option<&HierarchyNode> {
+        self.nodes.get(stack)
+    }
+
+    /// Get the nodes in depth-first order, parents before their children.
+    pub fn nodes(&self) -> impl Iterator<Item = (&str, &HierarchyNode)> {
+        self.nodes
+            .iter()
+            .map(|(stack, node)| (stack.as_str(), node))
+    }
+}
+
+impl Analyzer for ProtocolHierarchy {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        self.frames += 1;
+        self.bytes += frame.len() as u64;
+
+        let mut stack = String::new();
+        for layer in &self.registry.dissect_eth(frame).layers {
+            if !stack.is_empty() {
+                stack.push('/');
+            }
+            stack.push_str(layer.name());
+            let node = self.nodes.entry(stack.clone()).or_default();
+            node.frames += 1;
+            node.bytes += frame.len() as u64;
+        }
+    }
+}
+
+impl ToTable for ProtocolHierarchy {
+    /// Convert into a table with one row per node, parents before their
+    /// children.
+    ///
+    /// Shares are in percent of all frames and bytes.
+    fn to_table(&self) -> Table {
+        let nodes: Vec<_> = self.nodes().collect();
+        let percent = |count: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                100.0 * count as f64 / total as f64
+            }
+        };
+
+        let mut table = Table::new();
+        table
+            .push(
+                "protocol",
+                Column::Str(nodes.iter().map(|(s, _)| s.to_string()).collect()),
+            )
+            .push(
+                "depth",
+                Column::U64(
+                    nodes
+                        .iter()
+                        .map(|(s, _)| s.split('/').count() as u64)
+                        .collect(),
+                ),
+            )
+            .push(
+                "frames",
+                Column::U64(nodes.iter().map(|(_, n)| n.frames).collect()),
+            )
+            .push(
+                "frames_pct",
+                Column::F64(
+                    nodes
+                        .iter()
+                        .map(|(_, n)| percent(n.frames, self.frames))
+                        .collect(),
+                ),
+            )
+            .push(
+                "bytes",
+                Column::U64(nodes.iter().map(|(_, n)| n.bytes).collect()),
+            )
+            .push(
+                "bytes_pct",
+                Column::F64(
+                    nodes
+                        .iter()
+                        .map(|(_, n)| percent(n.bytes, self.bytes))
+                        .collect(),
+                ),
+            );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn protocol_hierarchy() {
+        let udp = udp!(src_port: 1234u16, dst_port: 53u16);
+        let ipv4 = ipv4!(protocol: IpProtocol::Udp, payload: udp.inner());
+        let udp = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+        let tcp = tcp!(src_port: 1234u16, dst_port: 80u16);
+        let ipv4 = ipv4!(protocol: IpProtocol::Tcp, payload: tcp.inner());
+        let tcp = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+
+        let mut hierarchy = ProtocolHierarchy::new();
+        for frame in [&udp, &udp, &tcp] {
+            hierarchy.on_packet(Duration::ZERO, frame.inner());
+        }
+
+        let stacks: Vec<_> = hierarchy.nodes().map(|(s, n)| (s, n.frames)).collect();
+        assert_eq!(
+            stacks,
+            [
+                ("Eth", 3),
+                ("Eth/Ipv4", 3),
+                ("Eth/Ipv4/Tcp", 1),
+                ("Eth/Ipv4/Udp", 2)
+            ]
+        );
+        let table = hierarchy.to_table();
+        assert_eq!(
+            table.column("frames_pct"),
+            Some(&Column::F64(vec![100.0, 100.0, 100.0 / 3.0, 200.0 / 3.0]))
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Log-bucketed histograms.
+//!
+//! Latencies span orders of magnitude, and their high percentiles matter
+//! most. Keeping every sample is costly, and linear buckets are either too
+//! coarse for the fast path or too many for the tail. A [`Histogram`] uses
+//! HDR-style buckets: each power of two is split into `2^precision` linear
+//! sub-buckets, so every value is recorded with a relative error of at most
+//! `2^-precision` in constant memory per order of magnitude. Histograms of
+//! the same precision merge exactly, e.g. across threads or captures.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit::stats::histogram::Histogram;
+//!
+//! let mut histogram = Histogram::new();
+//! for ms in 1..=1000 {
+//!     histogram.record_duration(Duration::from_millis(ms));
+//! }
+//! let p99 = histogram.quantile_duration(0.99).as_secs_f64();
+//! assert!((p99 - 0.990).abs() < 0.990 / 128.0);
+//! ```
+
+use std::time::Duration;
+
+/// Default number of bits of precision, for a relative error below 1%.
+pub const DEFAULT_PRECISION: u32 = 7;
+
+/// Maximum number of bits of precision.
+pub const MAX_PRECISION: u32 = 16;
+
+/// Log-bucketed histogram of unsigned values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    precision: u32,
+    counts: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::with_precision(DEFAULT_PRECISION)
+    }
+}
+
+impl Histogram {
+    /// Create an empty histogram with the default precision.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty histogram splitting each power of two into
+    /// `2^precision` buckets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision` is above [`MAX_PRECISION`].
+    pub fn with_precision(precision: u32) -> Self {
+        assert!(precision <= MAX_PRECISION, "precision too high");
+        Self {
+            precision,
+            counts: Vec::new(),
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Get the number of bits of precision.
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// Record a value.
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    /// Record a value `n` times.
+    pub fn record_n(&mut self, value: u64, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let index = self.index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += n;
+        self.count += n;
+        self.sum += value as u128 * n as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Record a duration, in nanoseconds.
+    pub fn record_duration(&mut self, value: Duration) {
+        self.record(value.as_nanos().min(u64::MAX as u128) as u64);
+    }
+
+    /// Add the values of another histogram.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the precisions differ.
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!(self.precision, other.precision, "precision mismatch");
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Get the number of values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether no value was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get the smallest value, if any.
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Get the largest value, if any.
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Get the exact mean of the values, if any.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// Get the value at a quantile in `0.0..=1.0`, e.g. `0.99` for the 99th
+    /// percentile.
+    ///
+    /// The value is the upper bound of the bucket holding the quantile,
+    /// within the smallest and largest values recorded. Returns 0 if the
+    /// histogram is empty.
+    pub fn quantile(&self, q: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return self.bounds(index).1.clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Get the duration at a quantile, for durations recorded with
+    /// [`record_duration`](Self::record_duration).
+    pub fn quantile_duration(&self, q: f64) -> Duration {
+        Duration::from_nanos(self.quantile(q))
+    }
+
+    /// Iterate over the non-empty buckets as their inclusive bounds and
+    /// count.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| {
+                let (low, high) = self.bounds(index);
+                (low, high, *count)
+            })
+    }
+
+    fn index(&self, value: u64) -> usize {
+        let p = self.precision;
+        if value < 1 << p {
+            return value as usize;
+        }
+        let shift = value.ilog2() - p;
+        let mantissa = (value >> shift) - (1 << p);
+        (((shift + 1) as u64) << p | mantissa) as usize
+    }
+
+    fn bounds(&self, index: usize) -> (u64, u64) {
+        let p = self.precision;
+        let index = index as u64;
+        if index < 1 << p {
+            return (index, index);
+        }
+        let shift = (index >> p) - 1;
+        let low = ((index & ((1 << p) - 1)) | 1 << p) << shift;
+        (low, low + ((1 << shift) - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_quantiles() {
+        let histogram = Histogram::with_precision(4);
+        for value in [0, 1, 15, 16, 17, 1000, u64::MAX] {
+            let index = histogram.index(value);
+            let (low, high) = histogram.bounds(index);
+            assert!(low <= value && value <= high, "{value} in {low}..={high}");
+            assert!(high - low <= low / 16);
+        }
+
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        for value in 1..=10_000u64 {
+            if value % 2 == 0 { &mut a } else { &mut b }.record(value);
+        }
+        a.merge(&b);
+        assert_eq!(a.count(), 10_000);
+        assert_eq!((a.min(), a.max()), (Some(1), Some(10_000)));
+        assert_eq!(a.mean(), Some(5000.5));
+        for q in [0.5, 0.9, 0.99, 0.999] {
+            let expected = q * 10_000.0;
+            let error = (a.quantile(q) as f64 - expected).abs() / expected;
+            assert!(error < 1.0 / 128.0, "{q}: {}", a.quantile(q));
+        }
+        assert_eq!(a.quantile(1.0), 10_000);
+        assert_eq!(a.quantile(0.0), 1);
+        assert_eq!(a.buckets().map(|b| b.2).sum::<u64>(), 10_000);
+        assert_eq!(Histogram::new().quantile(0.5), 0);
+    }
+}
@@ -0,0 +1,299 @@
+//! Header overhead accounting.
+//!
+//! Every captured byte is attributed either to the header of a protocol
+//! layer, to the application payload, or to link padding after the end of
+//! the IP packet. The share of header bytes is the encapsulation overhead,
+//! which drives MTU and tunnelling decisions.
+//!
+//! Frames are walked with a [`DissectorRegistry`], so custom plugins extend
+//! the layers accounted for. The payload starts after the last header the
+//! registry recognizes, or at the first layer named as a payload layer (e.g.
+//! `Dns`), whose bytes are application data from the network's point of view.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use netkit_packet::{dissect::DissectorRegistry, prelude::*};
+
+use crate::{
+    analysis::{trace_flow_growth, Analyzer},
+    export::{Column, Table, ToTable},
+};
+
+/// Layers counted as application payload by default.
+pub const DEFAULT_PAYLOAD_LAYERS: &[&str] = &["Dns"];
+
+/// Byte accounting of a set of packets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OverheadReport {
+    /// Number of packets.
+    pub packets: u64,
+    /// Number of captured bytes.
+    pub bytes: u64,
+    /// Header bytes by layer name.
+    pub headers: BTreeMap<&'static str, u64>,
+    /// Application payload bytes.
+    pub payload: u64,
+    /// Bytes after the end of the IP packet, e.g. Ethernet padding.
+    pub padding: u64,
+}
+
+impl OverheadReport {
+    /// Get the number of header bytes of all layers.
+    pub fn header_bytes(&self) -> u64 {
+        self.headers.values().sum()
+    }
+
+    /// Get the share of header and padding bytes, in percent.
+    pub fn overhead_percent(&self) -> f64 {
+        percent(self.header_bytes() + self.padding, self.bytes)
+    }
+
+    /// Get the share of header bytes of a layer, in percent.
+    pub fn layer_percent(&self, layer: &str) -> f64 {
+        percent(self.headers.get(layer).copied().unwrap_or(0), self.bytes)
+    }
+
+    fn add(&mut self, frame: &FrameBytes) {
+        self.packets += 1;
+        self.bytes += frame.bytes;
+        for (layer, len) in &frame.headers {
+            *self.headers.entry(layer).or_default() += len;
+        }
+        self.payload += frame.payload;
+        self.padding += frame.padding;
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+/// Byte accounting of a single frame.
+struct FrameBytes {
+    bytes: u64,
+    headers: Vec<(&'static str, u64)>,
+    payload: u64,
+    padding: u64,
+}
+
+/// Header overhead collector
+///
+/// Accounts header, payload and padding bytes overall and per flow. Flows
+/// are keyed by the canonical [`FlowKey`] of their outermost IPv4 layer;
+/// frames without one only count in the total.
+pub struct OverheadStats {
+    registry: DissectorRegistry,
+    payload_layers: Vec<String>,
+    total: OverheadReport,
+    flows: HashMap<FlowKey, OverheadReport>,
+}
+
+impl Default for OverheadStats {
+    fn default() -> Self {
+        Self {
+            registry: DissectorRegistry::with_builtins(),
+            payload_layers: DEFAULT_PAYLOAD_LAYERS
+                .iter()
+                .map(|l| l.to_string())
+                .collect(),
+            total: OverheadReport::default(),
+            flows: HashMap::new(),
+        }
+    }
+}
+
+impl OverheadStats {
+    /// Create a new collector with the built-in dissectors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the registry used to walk the layers of a frame.
+    pub fn registry(&mut self, registry: DissectorRegistry) -> &mut Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Set the names of the layers counted as application payload.
+    pub fn payload_layers<I, S>(&mut self, layers: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.payload_layers = layers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Get the report over all packets.
+    pub fn total(&self) -> &OverheadReport {
+        &self.total
+    }
+
+    /// Get the report of a flow, in either direction.
+    pub fn flow(&self, key: &FlowKey) -> Option<&OverheadReport> {
+        self.flows.get(&key.canonical())
+    }
+
+    /// Get the flow reports, largest first.
+    pub fn flows(&self) -> Vec<(FlowKey, &OverheadReport)> {
+        let mut flows: Vec<_> = self.flows.iter().map(|(k, v)| (*k, v)).collect();
+        flows.sort_by(|(ka, a), (kb, b)| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| ka.to_string().cmp(&kb.to_string()))
+        });
+        flows
+    }
+
+    fn account(&self, frame: &[u8]) -> (FrameBytes, Option<FlowKey>) {
+        let dissected = self.registry.dissect_eth(frame);
+
+        let mut headers = Vec::new();
+        let mut offset = 0;
+        let mut end = frame.len();
+        let mut key = None;
+        for layer in &dissected.layers {
+            if self.payload_layers.iter().any(|l| l == layer.name()) {
+                break;
+            }
+            if layer.name() == "Ipv4" && key.is_none() {
+                if let Ok(ipv4) = Ipv4::new(&frame[offset..]) {
+                    key = Some(FlowKey::from_ipv4(&ipv4).canonical());
+                    let total_length = ipv4.total_length().get() as usize;
+                    end = end.min(offset + total_length.max(layer.header_len()));
+                }
+            }
+            let len = layer.header_len().min(frame.len() - offset);
+            headers.push((layer.name(), len as u64));
+            offset += len;
+        }
+
+        let bytes = FrameBytes {
+            bytes: frame.len() as u64,
+            headers,
+            payload: end.saturating_sub(offset) as u64,
+            padding: (frame.len() - end.max(offset)) as u64,
+        };
+        (bytes, key)
+    }
+}
+
+impl Analyzer for OverheadStats {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        let (bytes, key) = self.account(frame);
+        self.total.add(&bytes);
+        if let Some(key) = key {
+            trace_flow_growth::<Self, _>(&self.flows, &key);
+            self.flows.entry(key).or_default().add(&bytes);
+        }
+    }
+}
+
+impl ToTable for OverheadStats {
+    /// Convert into a table with a `total` row followed by one row per flow.
+    ///
+    /// Header bytes get one `<layer>_bytes` column per layer seen.
+    fn to_table(&self) -> Table {
+        let flows = self.flows();
+        let rows: Vec<_> = std::iter::once(&self.total)
+            .chain(flows.iter().map(|(_, r)| *r))
+            .collect();
+        let u64s =
+            |f: &dyn Fn(&OverheadReport) -> u64| Column::U64(rows.iter().map(|r| f(r)).collect());
+
+        let mut names = vec!["total".to_string()];
+        names.extend(flows.iter().map(|(k, _)| k.to_string()));
+
+        let mut table = Table::new();
+        table
+            .push("flow", Column::Str(names))
+            .push("packets", u64s(&|r| r.packets))
+            .push("bytes", u64s(&|r| r.bytes))
+            .push("header_bytes", u64s(&|r| r.header_bytes()))
+            .push("payload_bytes", u64s(&|r| r.payload))
+            .push("padding_bytes", u64s(&|r| r.padding))
+            .push(
+                "overhead_pct",
+                Column::F64(rows.iter().map(|r| r.overhead_percent()).collect()),
+            );
+
+        for layer in self.total.headers.keys() {
+            table.push(
+                format!("{}_bytes", layer.to_lowercase()),
+                u64s(&|r| r.headers.get(layer).copied().unwrap_or(0)),
+            );
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::{dns, dns_question};
+
+    use super::*;
+    use crate::fixture::udp_frame;
+
+    const CLIENT: (Ipv4Addr, u16) = (Ipv4Addr::new(10, 0, 0, 1), 40000);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    #[test]
+    fn overhead_stats() {
+        let mut stats = OverheadStats::new();
+
+        // 14 + 20 + 8 bytes of headers and 100 of payload
+        stats.on_packet(Duration::ZERO, &udp_frame(CLIENT, (SERVER, 9999), [0; 100]));
+        // Padded to the Ethernet minimum of 60 bytes
+        let mut padded = udp_frame(CLIENT, (SERVER, 9999), [0; 2]);
+        padded.resize(60, 0);
+        stats.on_packet(Duration::ZERO, &padded);
+
+        let total = stats.total();
+        assert_eq!(total.packets, 2);
+        assert_eq!(total.bytes, 142 + 60);
+        assert_eq!(total.headers.get("Eth"), Some(&28));
+        assert_eq!(total.headers.get("Ipv4"), Some(&40));
+        assert_eq!(total.headers.get("Udp"), Some(&16));
+        assert_eq!(total.payload, 102);
+        assert_eq!(total.padding, 16);
+        assert_eq!(
+            total.header_bytes() + total.payload + total.padding,
+            total.bytes
+        );
+        assert!((total.overhead_percent() - 100.0 * 100.0 / 202.0).abs() < 1e-9);
+
+        let flows = stats.flows();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].1, total);
+        assert_eq!(stats.flow(&flows[0].0.reversed()), Some(total));
+    }
+
+    #[test]
+    fn overhead_payload_layers() {
+        let dns = dns!(questions: dns_question!(qname: "example.com"));
+        let frame = udp_frame(CLIENT, (SERVER, 53), dns.inner());
+
+        let mut stats = OverheadStats::new();
+        stats.on_packet(Duration::ZERO, &frame);
+        assert_eq!(stats.total().headers.get("Dns"), None);
+        assert_eq!(stats.total().payload, dns.inner().len() as u64);
+
+        let mut stats = OverheadStats::new();
+        stats.payload_layers(Vec::<String>::new());
+        stats.on_packet(Duration::ZERO, &frame);
+        assert!(stats.total().headers.contains_key("Dns"));
+
+        let table = stats.to_table();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.column("udp_bytes"), Some(&Column::U64(vec![8, 8])));
+    }
+}
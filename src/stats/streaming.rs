@@ -0,0 +1,282 @@
+//! Constant-memory streaming statistics.
+//!
+//! Dashboards poll live analyzers for smoothed metrics, and the analyzers
+//! should not keep every sample to answer them. [`P2Quantile`] estimates a
+//! quantile with the P² algorithm in five markers, [`Ewma`] smooths a series
+//! of values, and [`EwmaRate`] smooths the rate of timestamped events, e.g.
+//! packets or bytes per second.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use netkit::stats::streaming::{EwmaRate, P2Quantile};
+//!
+//! let mut median = P2Quantile::new(0.5);
+//! let mut rate = EwmaRate::new(Duration::from_secs(1));
+//! for ms in 0..10_000u64 {
+//!     median.record((ms % 100) as f64);
+//!     rate.record(Duration::from_millis(ms), 1.0);
+//! }
+//! assert!((median.quantile().unwrap() - 50.0).abs() < 2.0);
+//! assert!((rate.rate() - 1000.0).abs() < 10.0);
+//! ```
+
+use std::time::Duration;
+
+/// Streaming quantile estimator (Jain and Chlamtac's P² algorithm)
+///
+/// Tracks five markers whose heights follow the minimum, the quantile and
+/// its neighbours, and the maximum, adjusted with piecewise-parabolic
+/// interpolation as values arrive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct P2Quantile {
+    q: f64,
+    count: u64,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Create an estimator of the quantile `q`, e.g. `0.99` for the 99th
+    /// percentile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not within `[0, 1]`.
+    pub fn new(q: f64) -> Self {
+        assert!((0.0..=1.0).contains(&q), "quantile must be within [0, 1]");
+        Self {
+            q,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * q, 1.0 + 4.0 * q, 3.0 + 2.0 * q, 5.0],
+            increments: [0.0, q / 2.0, q, (1.0 + q) / 2.0, 1.0],
+        }
+    }
+
+    /// Get the estimated quantile.
+    #[inline]
+    pub fn q(&self) -> f64 {
+        self.q
+    }
+
+    /// Get the number of values.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Record a value.
+    pub fn record(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count as usize] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let h = &mut self.heights;
+        let k = if value < h[0] {
+            h[0] = value;
+            0
+        } else if value >= h[4] {
+            h[4] = value;
+            3
+        } else {
+            (1..5).find(|&i| value < h[i]).unwrap() - 1
+        };
+        for position in &mut self.positions[k + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            let n = &self.positions;
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let height = self.parabolic(i, d);
+                self.heights[i] = if self.heights[i - 1] < height && height < self.heights[i + 1] {
+                    height
+                } else {
+                    self.linear(i, d)
+                };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    /// Get the estimated quantile, if any value was recorded.
+    ///
+    /// Until five values are recorded, the quantile is taken from the
+    /// values themselves.
+    pub fn quantile(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..=4 => {
+                let mut values = self.heights[..self.count as usize].to_vec();
+                values.sort_by(f64::total_cmp);
+                let rank = (self.q * (values.len() - 1) as f64).round() as usize;
+                Some(values[rank])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (n, h) = (&self.positions, &self.heights);
+        h[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (h[i + 1] - h[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (h[i] - h[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let (n, h) = (&self.positions, &self.heights);
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        h[i] + d * (h[j] - h[i]) / (n[j] - n[i])
+    }
+}
+
+/// Exponentially weighted moving average of a series of values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ewma {
+    /// Create an average giving the weight `alpha` to each new value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not within `(0, 1]`.
+    pub fn new(alpha: f64) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha must be within (0, 1]");
+        Self { alpha, value: None }
+    }
+
+    /// Record a value.
+    pub fn record(&mut self, value: f64) {
+        self.value = Some(match self.value {
+            Some(average) => average + self.alpha * (value - average),
+            None => value,
+        });
+    }
+
+    /// Get the average, if any value was recorded.
+    #[inline]
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Exponentially weighted rate of timestamped events.
+///
+/// Each event's amount decays by half every half-life, and the rate is the
+/// decayed sum over the mean lifetime, so it settles on the rate of a steady
+/// stream and decays to zero when the stream stops. The rate is
+/// underestimated during the first few half-lives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EwmaRate {
+    half_life: Duration,
+    sum: f64,
+    last: Duration,
+}
+
+impl EwmaRate {
+    /// Create a rate decaying with the given half-life.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the half-life is zero.
+    pub fn new(half_life: Duration) -> Self {
+        assert!(!half_life.is_zero(), "half-life must not be zero");
+        Self {
+            half_life,
+            sum: 0.0,
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Get the half-life.
+    #[inline]
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    /// Record an event of `amount`, e.g. one packet or its bytes.
+    ///
+    /// Events older than the last one count as if they happened with it.
+    pub fn record(&mut self, ts: Duration, amount: f64) {
+        self.sum = self.decayed(ts) + amount;
+        self.last = self.last.max(ts);
+    }
+
+    /// Get the rate per second as of the last event.
+    pub fn rate(&self) -> f64 {
+        self.sum / self.lifetime()
+    }
+
+    /// Get the rate per second as of `ts`, decayed since the last event.
+    pub fn rate_at(&self, ts: Duration) -> f64 {
+        self.decayed(ts) / self.lifetime()
+    }
+
+    fn decayed(&self, ts: Duration) -> f64 {
+        let elapsed = ts.saturating_sub(self.last).as_secs_f64();
+        self.sum * (-elapsed / self.half_life.as_secs_f64()).exp2()
+    }
+
+    fn lifetime(&self) -> f64 {
+        self.half_life.as_secs_f64() / std::f64::consts::LN_2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streaming_estimates() {
+        // Deterministic shuffle of 1..=10000
+        let values = (1..=10_000u64).map(|i| (i * 7919 % 10_000 + 1) as f64);
+        let mut p50 = P2Quantile::new(0.5);
+        let mut p99 = P2Quantile::new(0.99);
+        for value in values {
+            p50.record(value);
+            p99.record(value);
+        }
+        assert_eq!(p50.count(), 10_000);
+        assert!((p50.quantile().unwrap() - 5000.0).abs() < 100.0);
+        assert!((p99.quantile().unwrap() - 9900.0).abs() < 50.0);
+
+        let mut few = P2Quantile::new(0.5);
+        assert_eq!(few.quantile(), None);
+        for value in [3.0, 1.0, 2.0] {
+            few.record(value);
+        }
+        assert_eq!(few.quantile(), Some(2.0));
+
+        let mut ewma = Ewma::new(0.5);
+        ewma.record(10.0);
+        ewma.record(20.0);
+        assert_eq!(ewma.value(), Some(15.0));
+
+        let half_life = Duration::from_secs(1);
+        let mut rate = EwmaRate::new(half_life);
+        for ms in 0..20_000 {
+            rate.record(Duration::from_millis(ms), 1.0);
+        }
+        assert!((rate.rate() - 1000.0).abs() < 1.0);
+        let later = Duration::from_millis(19_999) + half_life;
+        assert!((rate.rate_at(later) - rate.rate() / 2.0).abs() < 1e-6);
+    }
+}
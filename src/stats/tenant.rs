@@ -0,0 +1,375 @@
+//! Per-tenant statistics.
+//!
+//! A probe on shared infrastructure sees the traffic of several tenants or
+//! network segments, told apart by their address ranges or VLANs. A
+//! [`TenantClassifier`] names the tenant of each packet, and [`PerTenant`]
+//! keeps one collector per tenant, so every tenant gets its own statistics
+//! and its own rows in exported tables.
+//!
+//! ```
+//! use netkit::stats::{tenant::{PerTenant, TenantClassifier}, top::TopTalkers};
+//!
+//! let mut classifier = TenantClassifier::new();
+//! classifier
+//!     .tenant("blue", ["10.1.0.0/16".parse().unwrap()])
+//!     .tenant("red", ["10.2.0.0/16".parse().unwrap(), "vlan:200".parse().unwrap()])
+//!     .fallback("shared");
+//! let top = PerTenant::new(classifier, TopTalkers::new);
+//! assert_eq!(top.tenants().count(), 0);
+//! ```
+
+use std::{collections::BTreeMap, fmt, net::IpAddr, str::FromStr, time::Duration};
+
+use netkit_packet::prelude::*;
+
+use crate::{
+    analysis::Analyzer,
+    export::{Column, Table, ToTable},
+};
+
+/// Error type for parsing a [`TenantRule`].
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Eq)]
+#[error("Invalid tenant rule {0:?}: expected e.g. 10.0.0.0/8, 2001:db8::/32 or vlan:100")]
+pub struct TenantRuleError(pub String);
+
+/// Rule assigning packets to a tenant.
+///
+/// Written as a CIDR prefix, e.g. `10.0.0.0/8` or `2001:db8::/32`, or as a
+/// VLAN, e.g. `vlan:100`.
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TenantRule {
+    /// Addresses in a prefix, as source or destination.
+    Prefix(IpAddr, u8),
+    /// Frames tagged with a VLAN ID, at any level of stacking.
+    Vlan(u16),
+}
+
+impl TenantRule {
+    /// Whether an address is in the prefix of the rule.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let TenantRule::Prefix(prefix, len) = *self else {
+            return false;
+        };
+        let len = len as u32;
+        match (prefix, addr) {
+            (IpAddr::V4(prefix), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+                u32::from(prefix) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(prefix), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+                u128::from(prefix) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TenantRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TenantRule::Prefix(addr, len) => write!(f, "{addr}/{len}"),
+            TenantRule::Vlan(id) => write!(f, "vlan:{id}"),
+        }
+    }
+}
+
+impl FromStr for TenantRule {
+    type Err = TenantRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || TenantRuleError(s.to_string());
+        if let Some(id) = s.trim().strip_prefix("vlan:") {
+            return match id.trim().parse() {
+                Ok(id) if id < 4096 => Ok(TenantRule::Vlan(id)),
+                _ => Err(error()),
+            };
+        }
+
+        let (addr, len) = s.trim().split_once('/').ok_or_else(error)?;
+        let addr: IpAddr = addr.parse().map_err(|_| error())?;
+        let len: u8 = len.parse().map_err(|_| error())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if len > max {
+            return Err(error());
+        }
+        Ok(TenantRule::Prefix(addr, len))
+    }
+}
+
+impl TryFrom<String> for TenantRule {
+    type Error = TenantRuleError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TenantRule> for String {
+    fn from(rule: TenantRule) -> Self {
+        rule.to_string()
+    }
+}
+
+/// Classifier of packets into named tenants.
+///
+/// VLAN rules are checked first, then the source address and then the
+/// destination address, against the longest matching prefix of all
+/// tenants. Packets matching no rule go to the fallback tenant, if any.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TenantClassifier {
+    rules: Vec<(TenantRule, String)>,
+    fallback: Option<String>,
+}
+
+impl TenantClassifier {
+    /// Create a classifier without tenants.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tenant with its rules.
+    pub fn tenant(
+        &mut self,
+        name: impl Into<String>,
+        rules: impl IntoIterator<Item = TenantRule>,
+    ) -> &mut Self {
+        let name = name.into();
+        self.rules
+            .extend(rules.into_iter().map(|rule| (rule, name.clone())));
+        self
+    }
+
+    /// Set the tenant of packets matching no rule.
+    pub fn fallback(&mut self, name: impl Into<String>) -> &mut Self {
+        self.fallback = Some(name.into());
+        self
+    }
+
+    /// Get the tenant of an Ethernet frame.
+    pub fn classify(&self, frame: &[u8]) -> Option<&str> {
+        let Ok(eth) = Eth::new(frame) else {
+            return self.fallback.as_deref();
+        };
+
+        let vlans = eth.vlan_ids();
+        let by_vlan = self.rules.iter().find(|(rule, _)| match rule {
+            TenantRule::Vlan(id) => vlans.contains(id),
+            TenantRule::Prefix(..) => false,
+        });
+        if let Some((_, name)) = by_vlan {
+            return Some(name);
+        }
+
+        let addrs = if let Some(ipv4) = eth.ipv4() {
+            Some((ipv4.src().get().into(), ipv4.dst().get().into()))
+        } else {
+            eth.ipv6()
+                .map(|ipv6| (ipv6.src().get().into(), ipv6.dst().get().into()))
+        };
+        if let Some((src, dst)) = addrs {
+            for addr in [src, dst] {
+                if let Some(name) = self.longest_prefix(addr) {
+                    return Some(name);
+                }
+            }
+        }
+        self.fallback.as_deref()
+    }
+
+    fn longest_prefix(&self, addr: IpAddr) -> Option<&str> {
+        self.rules
+            .iter()
+            .filter(|(rule, _)| rule.contains(addr))
+            .max_by_key(|(rule, _)| match rule {
+                TenantRule::Prefix(_, len) => *len,
+                TenantRule::Vlan(_) => 0,
+            })
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+/// Analyzer keeping one collector per tenant.
+///
+/// Collectors are created on the first packet of their tenant.
+pub struct PerTenant<A> {
+    classifier: TenantClassifier,
+    new: Box<dyn Fn() -> A>,
+    tenants: BTreeMap<String, A>,
+    unassigned: u64,
+}
+
+impl<A: Analyzer> PerTenant<A> {
+    /// Create per-tenant collectors, built by `new`.
+    pub fn new(classifier: TenantClassifier, new: impl Fn() -> A + 'static) -> Self {
+        Self {
+            classifier,
+            new: Box::new(new),
+            tenants: BTreeMap::new(),
+            unassigned: 0,
+        }
+    }
+
+    /// Get the classifier.
+    pub fn classifier(&self) -> &TenantClassifier {
+        &self.classifier
+    }
+
+    /// Get the collector of a tenant.
+    pub fn get(&self, tenant: &str) -> Option<&A> {
+        self.tenants.get(tenant)
+    }
+
+    /// Get the collectors by tenant name.
+    pub fn tenants(&self) -> impl Iterator<Item = (&str, &A)> {
+        self.tenants.iter().map(|(name, a)| (name.as_str(), a))
+    }
+
+    /// Get the number of packets of no tenant, without fallback.
+    pub fn unassigned(&self) -> u64 {
+        self.unassigned
+    }
+}
+
+impl<A> fmt::Debug for PerTenant<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PerTenant")
+            .field("classifier", &self.classifier)
+            .field("tenants", &self.tenants.keys().collect::<Vec<_>>())
+            .field("unassigned", &self.unassigned)
+            .finish()
+    }
+}
+
+impl<A: Analyzer> Analyzer for PerTenant<A> {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Some(tenant) = self.classifier.classify(frame) else {
+            self.unassigned += 1;
+            return;
+        };
+        if !self.tenants.contains_key(tenant) {
+            self.tenants.insert(tenant.to_string(), (self.new)());
+        }
+        if let Some(analyzer) = self.tenants.get_mut(tenant) {
+            analyzer.on_packet(ts, frame);
+        }
+    }
+}
+
+impl<A: Analyzer + ToTable> PerTenant<A> {
+    /// Convert the collectors into a table per tenant, e.g. to export every
+    /// tenant to its own file.
+    pub fn tables(&self) -> BTreeMap<&str, Table> {
+        self.tenants
+            .iter()
+            .map(|(name, a)| (name.as_str(), a.to_table()))
+            .collect()
+    }
+}
+
+impl<A: Analyzer + ToTable> ToTable for PerTenant<A> {
+    /// Concatenate the tables of the collectors, prepending the tenant to
+    /// every row.
+    fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        for (name, a) in &self.tenants {
+            let inner = a.to_table();
+            let mut rows = Table::new();
+            rows.push("tenant", Column::Str(vec![name.clone(); inner.len()]));
+            for (column, values) in inner.columns() {
+                rows.push(column.clone(), values.clone());
+            }
+            table.append(rows);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use netkit_packet::layer::eth::EthBuilder;
+
+    use super::*;
+    use crate::stats::top::TopTalkers;
+
+    #[test]
+    fn per_tenant_stats() {
+        for (s, rule) in [
+            (
+                "10.0.0.0/8",
+                TenantRule::Prefix(Ipv4Addr::new(10, 0, 0, 0).into(), 8),
+            ),
+            (
+                "2001:db8::/32",
+                TenantRule::Prefix("2001:db8::".parse::<Ipv6Addr>().unwrap().into(), 32),
+            ),
+            ("vlan:100", TenantRule::Vlan(100)),
+        ] {
+            assert_eq!(s.parse(), Ok(rule));
+            assert_eq!(rule.to_string(), s);
+        }
+        for s in ["10.0.0.0/33", "10.0.0.0", "vlan:4096", "vlan"] {
+            assert!(s.parse::<TenantRule>().is_err());
+        }
+        assert!(TenantRule::Prefix(Ipv4Addr::UNSPECIFIED.into(), 0)
+            .contains(Ipv4Addr::BROADCAST.into()));
+
+        let mut classifier = TenantClassifier::new();
+        classifier
+            .tenant("blue", ["10.0.0.0/8".parse().unwrap()])
+            .tenant(
+                "red",
+                ["10.2.0.0/16".parse().unwrap(), "vlan:200".parse().unwrap()],
+            );
+
+        let frame = |src: Ipv4Addr, dst: Ipv4Addr, vlan: Option<u16>| {
+            let udp = udp!(src_port: 1000u16, dst_port: 53u16, payload: b"query");
+            let ipv4 = ipv4!(protocol: IpProtocol::Udp, src: src, dst: dst, payload: udp.inner());
+            let mut eth = EthBuilder::new()
+                .eth_type(EthType::Ipv4)
+                .payload(ipv4.inner())
+                .build()
+                .inner()
+                .clone();
+            if let Some(id) = vlan {
+                // 802.1Q tag after the addresses
+                eth.splice(12..12, [0x81, 0x00, (id >> 8) as u8, id as u8]);
+            }
+            eth
+        };
+        let outside = Ipv4Addr::new(192, 0, 2, 1);
+        let frames = [
+            frame(Ipv4Addr::new(10, 1, 0, 1), outside, None),
+            frame(outside, Ipv4Addr::new(10, 2, 0, 1), None),
+            frame(outside, Ipv4Addr::new(10, 1, 0, 1), Some(200)),
+            frame(outside, outside, None),
+        ];
+        let tenants: Vec<_> = frames.iter().map(|f| classifier.classify(f)).collect();
+        assert_eq!(tenants, [Some("blue"), Some("red"), Some("red"), None]);
+
+        let mut top = PerTenant::new(classifier, TopTalkers::new);
+        for frame in &frames {
+            top.on_packet(Duration::ZERO, frame);
+        }
+        assert_eq!(top.unassigned(), 1);
+        assert_eq!(top.tables().len(), 2);
+        let table = top.to_table();
+        let blue = top.get("blue").unwrap().to_table().len();
+        let red = top.get("red").unwrap().to_table().len();
+        assert_eq!(table.len(), blue + red);
+        assert_eq!(table.columns()[0].0, "tenant");
+        assert_eq!(
+            table.column("tenant"),
+            Some(&Column::Str(
+                [vec!["blue".to_string(); blue], vec!["red".to_string(); red]].concat()
+            ))
+        );
+    }
+}
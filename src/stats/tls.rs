@@ -0,0 +1,289 @@
+//! TLS statistics.
+
+use std::{collections::HashMap, time::Duration};
+
+use netkit_packet::{flow::table::FlowTable, prelude::*};
+
+use crate::analysis::{
+    stream::{StreamSegment, TcpReassembler},
+    tls::{
+        ClientHello, HandshakeFollower, ServerHello, HANDSHAKE_CLIENT_HELLO, HANDSHAKE_SERVER_HELLO,
+    },
+    Analyzer,
+};
+
+/// TLS statistics collector
+///
+/// Follows the plaintext handshakes of TCP connections, and collects:
+///
+/// - the distribution of negotiated protocol versions,
+/// - the distribution of selected cipher suites,
+/// - the share of handshakes resuming a session (see
+///   [`ServerHello::resumes`]),
+/// - the server names (SNI) asked for, and how many clients sent none.
+///
+/// A handshake is counted at its ServerHello; HelloRetryRequests are not
+/// handshakes of their own.
+#[derive(Clone, Debug, Default)]
+pub struct TlsStats {
+    reassembler: TcpReassembler,
+    follower: HandshakeFollower,
+    /// ClientHellos waiting for their ServerHello, by canonical key.
+    hellos: FlowTable<ClientHello>,
+    client_hellos: u64,
+    handshakes: u64,
+    resumed: u64,
+    without_server_name: u64,
+    versions: HashMap<u16, u64>,
+    cipher_suites: HashMap<u16, u64>,
+    server_names: HashMap<String, u64>,
+}
+
+impl TlsStats {
+    /// Create a new TLS statistics collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of ClientHellos.
+    pub fn client_hellos(&self) -> u64 {
+        self.client_hellos
+    }
+
+    /// Get the number of handshakes, i.e. of ServerHellos.
+    pub fn handshakes(&self) -> u64 {
+        self.handshakes
+    }
+
+    /// Get the number of handshakes resuming a session.
+    pub fn resumed(&self) -> u64 {
+        self.resumed
+    }
+
+    /// Get the share of handshakes resuming a session, 0 without handshakes.
+    pub fn resumption_rate(&self) -> f64 {
+        if self.handshakes == 0 {
+            0.0
+        } else {
+            self.resumed as f64 / self.handshakes as f64
+        }
+    }
+
+    /// Get the negotiated version distribution, most frequent first.
+    pub fn versions(&self) -> Vec<(u16, u64)> {
+        sorted_counts(&self.versions)
+    }
+
+    /// Get the selected cipher suite distribution, most frequent first.
+    pub fn cipher_suites(&self) -> Vec<(u16, u64)> {
+        sorted_counts(&self.cipher_suites)
+    }
+
+    /// Get the number of distinct server names asked for.
+    pub fn server_names(&self) -> usize {
+        self.server_names.len()
+    }
+
+    /// Get the number of ClientHellos without a server name.
+    pub fn without_server_name(&self) -> u64 {
+        self.without_server_name
+    }
+
+    /// Get the `n` most asked for server names, most frequent first.
+    pub fn top_server_names(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut names: Vec<_> = self
+            .server_names
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count))
+            .collect();
+        names.sort_by(|(ka, a), (kb, b)| b.cmp(a).then_with(|| ka.cmp(kb)));
+        names.truncate(n);
+        names
+    }
+
+    /// Record a ClientHello.
+    pub fn record_client_hello(&mut self, hello: &ClientHello) {
+        self.client_hellos += 1;
+        match &hello.server_name {
+            Some(name) => {
+                *self
+                    .server_names
+                    .entry(name.to_ascii_lowercase())
+                    .or_default() += 1
+            }
+            None => self.without_server_name += 1,
+        }
+    }
+
+    /// Record a ServerHello, answering a ClientHello if it was seen.
+    pub fn record_server_hello(&mut self, hello: &ServerHello, client: Option<&ClientHello>) {
+        if hello.is_retry_request() {
+            return;
+        }
+        self.handshakes += 1;
+        *self.versions.entry(hello.version).or_default() += 1;
+        *self.cipher_suites.entry(hello.cipher_suite).or_default() += 1;
+        if client.is_some_and(|client| hello.resumes(client)) {
+            self.resumed += 1;
+        }
+    }
+
+    /// Feed reassembled stream bytes.
+    pub fn on_segment(&mut self, segment: StreamSegment) {
+        let canonical = segment.key.canonical();
+        for event in self.follower.push(&segment) {
+            let body = &event.message.body;
+            match event.message.msg_type {
+                HANDSHAKE_CLIENT_HELLO => {
+                    if let Some(hello) = ClientHello::parse(body) {
+                        self.record_client_hello(&hello);
+                        self.hellos.insert(canonical, hello);
+                    }
+                }
+                HANDSHAKE_SERVER_HELLO => {
+                    if let Some(hello) = ServerHello::parse(body) {
+                        let client = self.hellos.remove(&canonical);
+                        self.record_server_hello(&hello, client.as_ref());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if segment.fin {
+            self.hellos.remove(&canonical);
+        }
+    }
+}
+
+impl Analyzer for TlsStats {
+    fn on_packet(&mut self, _ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            return;
+        };
+        if let Some(segment) = self.reassembler.push(&ipv4) {
+            self.on_segment(segment);
+        }
+    }
+}
+
+fn sorted_counts(counts: &HashMap<u16, u64>) -> Vec<(u16, u64)> {
+    let mut counts: Vec<_> = counts.iter().map(|(k, v)| (*k, *v)).collect();
+    counts.sort_by(|(ka, a), (kb, b)| b.cmp(a).then_with(|| ka.cmp(kb)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use netkit_packet::layer::tcp::TcpFlags;
+
+    use super::*;
+    use crate::analysis::tls::{CONTENT_HANDSHAKE, TLS_1_2, TLS_1_3};
+    use crate::fixture::tcp_frame;
+
+    fn frame(from_client: bool, client_port: u16, payload: &[u8]) -> Vec<u8> {
+        let client = (Ipv4Addr::new(10, 0, 0, 1), client_port);
+        let server = (Ipv4Addr::new(10, 0, 0, 2), 443);
+        let (src, dst) = if from_client {
+            (client, server)
+        } else {
+            (server, client)
+        };
+        tcp_frame(src, dst, 1, TcpFlags::ACK, payload)
+    }
+
+    fn record(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() as u32).to_be_bytes();
+        let record_len = (body.len() as u16 + 4).to_be_bytes();
+        [
+            &[CONTENT_HANDSHAKE, 3, 3, record_len[0], record_len[1]],
+            &[msg_type, len[1], len[2], len[3]][..],
+            body,
+        ]
+        .concat()
+    }
+
+    fn client_hello(session_id: &[u8], server_name: Option<&str>) -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[1; 32]);
+        body.push(session_id.len() as u8);
+        body.extend_from_slice(session_id);
+        body.extend_from_slice(&[0, 2, 0x13, 0x01, 1, 0]);
+        if let Some(name) = server_name {
+            let len = name.len() as u16;
+            body.extend_from_slice(&(len + 9).to_be_bytes());
+            body.extend_from_slice(&[0, 0]);
+            body.extend_from_slice(&(len + 5).to_be_bytes());
+            body.extend_from_slice(&(len + 3).to_be_bytes());
+            body.push(0);
+            body.extend_from_slice(&len.to_be_bytes());
+            body.extend_from_slice(name.as_bytes());
+        }
+        record(HANDSHAKE_CLIENT_HELLO, &body)
+    }
+
+    fn server_hello(session_id: &[u8], suite: u16, version: u16, psk: bool) -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[2; 32]);
+        body.push(session_id.len() as u8);
+        body.extend_from_slice(session_id);
+        body.extend_from_slice(&suite.to_be_bytes());
+        body.push(0);
+        let mut extensions = Vec::new();
+        if version == TLS_1_3 {
+            extensions.extend_from_slice(&[0, 43, 0, 2, 3, 4]);
+        }
+        if psk {
+            extensions.extend_from_slice(&[0, 41, 0, 2, 0, 0]);
+        }
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+        record(HANDSHAKE_SERVER_HELLO, &body)
+    }
+
+    #[test]
+    fn tls_stats() {
+        let mut stats = TlsStats::new();
+        let ts = Duration::ZERO;
+        let handshakes = [
+            // Full TLS 1.3 handshake
+            (
+                client_hello(&[7; 32], Some("Example.com")),
+                server_hello(&[7; 32], 0x1301, TLS_1_3, false),
+            ),
+            // TLS 1.3 resumption
+            (
+                client_hello(&[8; 32], Some("example.com")),
+                server_hello(&[8; 32], 0x1301, TLS_1_3, true),
+            ),
+            // TLS 1.2 resumption by session ID
+            (
+                client_hello(&[9; 32], Some("example.org")),
+                server_hello(&[9; 32], 0xc02f, TLS_1_2, false),
+            ),
+            // Full TLS 1.2 handshake without SNI
+            (
+                client_hello(&[], None),
+                server_hello(&[6; 32], 0xc02f, TLS_1_2, false),
+            ),
+        ];
+        for (port, (client, server)) in (40000..).zip(handshakes) {
+            stats.on_packet(ts, &frame(true, port, &client));
+            stats.on_packet(ts, &frame(false, port, &server));
+        }
+
+        assert_eq!(stats.client_hellos(), 4);
+        assert_eq!(stats.handshakes(), 4);
+        assert_eq!(stats.resumed(), 2);
+        assert_eq!(stats.resumption_rate(), 0.5);
+        assert_eq!(stats.versions(), [(TLS_1_2, 2), (TLS_1_3, 2)]);
+        assert_eq!(stats.cipher_suites(), [(0x1301, 2), (0xc02f, 2)]);
+        assert_eq!(stats.server_names(), 2);
+        assert_eq!(stats.without_server_name(), 1);
+        assert_eq!(stats.top_server_names(1), [("example.com", 2)]);
+    }
+}
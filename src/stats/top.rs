@@ -0,0 +1,357 @@
+//! Bounded-memory heavy hitters.
+//!
+//! Counting the traffic of every endpoint or conversation of a live stream
+//! with a hash map grows without limit, and scanning or spoofed traffic
+//! makes it grow fastest. [`SpaceSaving`] tracks a fixed number of counters
+//! and still finds every key whose share of the stream exceeds the error
+//! bound, with counts overestimated by at most that bound.
+//!
+//! [`TopTalkers`] applies it to the bytes sent by endpoints and exchanged by
+//! conversations.
+//!
+//! ```
+//! use netkit::stats::top::SpaceSaving;
+//!
+//! let mut top = SpaceSaving::with_error(0.1);
+//! for key in ["a", "b", "a", "c", "a", "d", "e", "a"] {
+//!     top.insert(key, 1);
+//! }
+//! assert_eq!(top.top(1)[0].key, "a");
+//! assert!(top.top(1)[0].count >= 4);
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use super::streaming::EwmaRate;
+use crate::{
+    analysis::Analyzer,
+    export::{Column, Table, ToTable},
+};
+
+/// Default error bound, as a share of the stream.
+pub const DEFAULT_ERROR: f64 = 0.001;
+
+/// Default half-life of the smoothed packet and byte rates.
+pub const DEFAULT_RATE_HALF_LIFE: Duration = Duration::from_secs(5);
+
+/// An estimated heavy hitter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeavyHitter<K> {
+    /// Key.
+    pub key: K,
+    /// Estimated count, never below the true count.
+    pub count: u64,
+    /// Maximum overestimation of the count.
+    pub error: u64,
+}
+
+impl<K> HeavyHitter<K> {
+    /// Get the guaranteed lower bound of the count.
+    #[inline]
+    pub fn min_count(&self) -> u64 {
+        self.count - self.error
+    }
+}
+
+/// Space-Saving heavy hitter sketch (Metwally et al.)
+///
+/// Keeps at most `capacity` counters. A new key replaces the smallest
+/// counter and inherits its count as error, so every count is overestimated
+/// by at most `total / capacity`.
+#[derive(Clone, Debug)]
+pub struct SpaceSaving<K> {
+    capacity: usize,
+    counters: HashMap<K, (u64, u64, u64)>,
+    order: BTreeMap<(u64, u64), K>,
+    next_id: u64,
+    total: u64,
+}
+
+impl<K: Clone + Eq + Hash> SpaceSaving<K> {
+    /// Create a sketch with the given number of counters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must not be zero");
+        Self {
+            capacity,
+            counters: HashMap::with_capacity(capacity),
+            order: BTreeMap::new(),
+            next_id: 0,
+            total: 0,
+        }
+    }
+
+    /// Create a sketch whose counts are overestimated by at most `error`
+    /// times the total.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the error is not within `(0, 1]`.
+    pub fn with_error(error: f64) -> Self {
+        assert!(error > 0.0 && error <= 1.0, "error must be within (0, 1]");
+        Self::new((1.0 / error).ceil() as usize)
+    }
+
+    /// Get the number of counters.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the total weight inserted.
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Get the number of tracked keys.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Whether no key is tracked.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// Get the maximum overestimation of any count.
+    pub fn error_bound(&self) -> u64 {
+        self.total / self.capacity as u64
+    }
+
+    /// Add `weight` to the count of a key.
+    pub fn insert(&mut self, key: K, weight: u64) {
+        self.total += weight;
+
+        if let Some((count, _, id)) = self.counters.get_mut(&key) {
+            let key = self
+                .order
+                .remove(&(*count, *id))
+                .expect("counter is ordered");
+            *count += weight;
+            self.order.insert((*count, *id), key);
+            return;
+        }
+
+        let (count, error) = if self.counters.len() < self.capacity {
+            (weight, 0)
+        } else {
+            let ((min, _), evicted) = self.order.pop_first().expect("sketch is full");
+            self.counters.remove(&evicted);
+            (min + weight, min)
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.counters.insert(key.clone(), (count, error, id));
+        self.order.insert((count, id), key);
+    }
+
+    /// Get the estimate of a key, if tracked.
+    pub fn get(&self, key: &K) -> Option<HeavyHitter<K>> {
+        self.counters.get(key).map(|(count, error, _)| HeavyHitter {
+            key: key.clone(),
+            count: *count,
+            error: *error,
+        })
+    }
+
+    /// Get the `n` keys with the largest estimated counts, largest first.
+    pub fn top(&self, n: usize) -> Vec<HeavyHitter<K>> {
+        self.order
+            .iter()
+            .rev()
+            .take(n)
+            .map(|((count, _), key)| HeavyHitter {
+                key: key.clone(),
+                count: *count,
+                error: self.counters[key].1,
+            })
+            .collect()
+    }
+
+    /// Get the keys guaranteed to hold more than `share` of the total,
+    /// largest first.
+    pub fn heavy_hitters(&self, share: f64) -> Vec<HeavyHitter<K>> {
+        let threshold = share * self.total as f64;
+        let mut hitters = self.top(self.capacity);
+        hitters.retain(|h| h.min_count() as f64 > threshold);
+        hitters
+    }
+}
+
+/// Bounded-memory endpoint and conversation statistics.
+///
+/// Endpoints are counted by the bytes they send, conversations by the bytes
+/// of both directions. The overall packet and byte rates are smoothed with
+/// an [`EwmaRate`] for dashboards to poll.
+#[derive(Clone, Debug)]
+pub struct TopTalkers {
+    endpoints: SpaceSaving<IpAddr>,
+    conversations: SpaceSaving<FlowKey>,
+    packet_rate: EwmaRate,
+    byte_rate: EwmaRate,
+}
+
+impl Default for TopTalkers {
+    fn default() -> Self {
+        Self::with_error(DEFAULT_ERROR)
+    }
+}
+
+impl TopTalkers {
+    /// Create a new collector with the default error bound.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new collector whose byte counts are overestimated by at most
+    /// `error` times the total.
+    pub fn with_error(error: f64) -> Self {
+        Self {
+            endpoints: SpaceSaving::with_error(error),
+            conversations: SpaceSaving::with_error(error),
+            packet_rate: EwmaRate::new(DEFAULT_RATE_HALF_LIFE),
+            byte_rate: EwmaRate::new(DEFAULT_RATE_HALF_LIFE),
+        }
+    }
+
+    /// Set the half-life of the smoothed rates, resetting them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the half-life is zero.
+    pub fn rate_half_life(&mut self, half_life: Duration) -> &mut Self {
+        self.packet_rate = EwmaRate::new(half_life);
+        self.byte_rate = EwmaRate::new(half_life);
+        self
+    }
+
+    /// Get the endpoint sketch, counting bytes sent.
+    pub fn endpoints(&self) -> &SpaceSaving<IpAddr> {
+        &self.endpoints
+    }
+
+    /// Get the conversation sketch, counting bytes of both directions under
+    /// the canonical key.
+    pub fn conversations(&self) -> &SpaceSaving<FlowKey> {
+        &self.conversations
+    }
+
+    /// Get the smoothed rate of packets per second.
+    pub fn packet_rate(&self) -> &EwmaRate {
+        &self.packet_rate
+    }
+
+    /// Get the smoothed rate of bytes per second.
+    pub fn byte_rate(&self) -> &EwmaRate {
+        &self.byte_rate
+    }
+}
+
+impl Analyzer for TopTalkers {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        self.packet_rate.record(ts, 1.0);
+        self.byte_rate.record(ts, frame.len() as f64);
+
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+        let Some(key) = FlowKey::from_eth(&eth) else {
+            return;
+        };
+
+        let bytes = frame.len() as u64;
+        self.endpoints.insert(key.src, bytes);
+        self.conversations.insert(key.canonical(), bytes);
+    }
+}
+
+impl ToTable for TopTalkers {
+    /// Tabulate the tracked endpoints, largest first.
+    fn to_table(&self) -> Table {
+        let top = self.endpoints.top(self.endpoints.capacity());
+        let mut table = Table::new();
+        table
+            .push(
+                "endpoint",
+                Column::Str(top.iter().map(|h| h.key.to_string()).collect()),
+            )
+            .push("bytes", Column::U64(top.iter().map(|h| h.count).collect()))
+            .push("error", Column::U64(top.iter().map(|h| h.error).collect()));
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn space_saving_error_bound() {
+        let mut top = SpaceSaving::new(10);
+        let mut exact = HashMap::new();
+        // Two heavy keys among a long tail of distinct keys
+        for i in 0..1000u32 {
+            let key = match i % 4 {
+                0 => 1_000_000,
+                1 => 2_000_000,
+                _ => i,
+            };
+            top.insert(key, 1);
+            *exact.entry(key).or_insert(0) += 1;
+        }
+
+        assert_eq!(top.len(), 10);
+        assert_eq!(top.total(), 1000);
+        assert_eq!(top.error_bound(), 100);
+        for hitter in top.top(10) {
+            let count = exact[&hitter.key];
+            assert!(hitter.min_count() <= count && count <= hitter.count);
+            assert!(hitter.error <= top.error_bound());
+        }
+
+        let heavy: Vec<_> = top.heavy_hitters(0.1).into_iter().map(|h| h.key).collect();
+        assert_eq!(heavy.len(), 2);
+        assert!(heavy.contains(&1_000_000) && heavy.contains(&2_000_000));
+    }
+
+    #[test]
+    fn top_talkers() {
+        let frame = |(src, src_port): (Ipv4Addr, u16), (dst, dst_port): (Ipv4Addr, u16), len| {
+            let udp = udp!(src_port: src_port, dst_port: dst_port, payload: vec![0; len]);
+            let ipv4 = ipv4!(src: src, dst: dst, protocol: IpProtocol::Udp, payload: udp.inner());
+            eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                .inner()
+                .clone()
+        };
+        let a = (Ipv4Addr::new(10, 0, 0, 1), 5000);
+        let b = (Ipv4Addr::new(10, 0, 0, 2), 53);
+
+        let mut talkers = TopTalkers::with_error(0.5);
+        talkers.on_packet(Duration::ZERO, &frame(a, b, 1000));
+        talkers.on_packet(Duration::ZERO, &frame(b, a, 10));
+        talkers.on_packet(Duration::ZERO, &frame(a, b, 1000));
+
+        let top = talkers.endpoints().top(1);
+        assert_eq!(top[0].key, IpAddr::from(a.0));
+        let conversations = talkers.conversations().top(1);
+        assert_eq!(conversations[0].key.src, IpAddr::from(a.0));
+        assert_eq!(conversations[0].count, talkers.endpoints().total());
+        assert_eq!(talkers.to_table().len(), 2);
+        assert!(talkers.byte_rate().rate() > talkers.packet_rate().rate());
+    }
+}
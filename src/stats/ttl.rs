@@ -0,0 +1,289 @@
+//! TTL distribution and path change detection.
+//!
+//! The TTL of packets from a given source only changes when the path (or the
+//! sender) changes, so a sudden shift of the dominant TTL hints at a routing
+//! change or at spoofed traffic.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    time::Duration,
+};
+
+use netkit_packet::prelude::*;
+
+use crate::analysis::Analyzer;
+
+/// Default width of a time bucket.
+pub const DEFAULT_BUCKET: Duration = Duration::from_secs(60);
+
+/// Default TTL difference considered a shift.
+pub const DEFAULT_SHIFT_THRESHOLD: u8 = 2;
+
+/// TTL summary of a source over a time bucket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TtlBucket {
+    /// Start of the bucket.
+    pub start: Duration,
+    /// Number of packets.
+    pub packets: u64,
+    /// Minimum TTL.
+    pub min: u8,
+    /// Maximum TTL.
+    pub max: u8,
+    /// Most frequent TTL (the lowest one on ties).
+    pub mode: u8,
+    histogram: BTreeMap<u8, u64>,
+}
+
+impl TtlBucket {
+    fn new(start: Duration) -> Self {
+        Self {
+            start,
+            packets: 0,
+            min: u8::MAX,
+            max: 0,
+            mode: 0,
+            histogram: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, ttl: u8) {
+        self.packets += 1;
+        self.min = self.min.min(ttl);
+        self.max = self.max.max(ttl);
+
+        let count = self.histogram.entry(ttl).or_default();
+        *count += 1;
+        let count = *count;
+        let mode_count = self.histogram.get(&self.mode).copied().unwrap_or(0);
+        if count > mode_count || (count == mode_count && ttl < self.mode) {
+            self.mode = ttl;
+        }
+    }
+
+    /// Get the TTL distribution of the bucket, ordered by TTL.
+    pub fn histogram(&self) -> Vec<(u8, u64)> {
+        self.histogram.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    /// Get the estimated hop count of the dominant TTL.
+    pub fn hops(&self) -> u8 {
+        initial_ttl(self.mode) - self.mode
+    }
+}
+
+/// A sudden shift of the dominant TTL of a source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TtlShift {
+    /// Source address.
+    pub src: IpAddr,
+    /// Start of the bucket in which the shift is observed.
+    pub at: Duration,
+    /// Dominant TTL before the shift.
+    pub from: u8,
+    /// Dominant TTL after the shift.
+    pub to: u8,
+}
+
+/// Get the most likely initial TTL of a packet received with `ttl`.
+///
+/// Common stacks start with 32, 64, 128 or 255.
+pub fn initial_ttl(ttl: u8) -> u8 {
+    match ttl {
+        0..=32 => 32,
+        33..=64 => 64,
+        65..=128 => 128,
+        _ => 255,
+    }
+}
+
+/// Per-source TTL statistics collector.
+///
+/// Summaries are kept per source and per time bucket. When a bucket is
+/// closed, its dominant TTL is compared with the previous bucket of the same
+/// source, and a [`TtlShift`] is recorded if they differ by at least the
+/// shift threshold.
+#[derive(Clone, Debug)]
+pub struct TtlStats {
+    bucket: Duration,
+    shift_threshold: u8,
+    sources: HashMap<IpAddr, Source>,
+    shifts: Vec<TtlShift>,
+}
+
+impl Default for TtlStats {
+    fn default() -> Self {
+        Self {
+            bucket: DEFAULT_BUCKET,
+            shift_threshold: DEFAULT_SHIFT_THRESHOLD,
+            sources: HashMap::new(),
+            shifts: Vec::new(),
+        }
+    }
+}
+
+impl TtlStats {
+    /// Create a new TTL statistics collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the width of a time bucket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is zero.
+    pub fn bucket(&mut self, bucket: Duration) -> &mut Self {
+        assert!(!bucket.is_zero(), "bucket width must not be zero");
+        self.bucket = bucket;
+        self
+    }
+
+    /// Set the TTL difference considered a shift.
+    pub fn shift_threshold(&mut self, shift_threshold: u8) -> &mut Self {
+        self.shift_threshold = shift_threshold;
+        self
+    }
+
+    /// Get the buckets of a source, oldest first.
+    ///
+    /// Buckets without packets are omitted.
+    pub fn buckets(&self, src: &IpAddr) -> &[TtlBucket] {
+        self.sources.get(src).map_or(&[], |s| s.buckets.as_slice())
+    }
+
+    /// Get the sources seen, ordered by address.
+    pub fn sources(&self) -> Vec<IpAddr> {
+        let mut sources: Vec<_> = self.sources.keys().copied().collect();
+        sources.sort();
+        sources
+    }
+
+    /// Get the shifts detected so far, in detection order.
+    pub fn shifts(&self) -> &[TtlShift] {
+        &self.shifts
+    }
+
+    /// Record the TTL of a packet.
+    pub fn record(&mut self, ts: Duration, src: IpAddr, ttl: u8) {
+        let bucket = self.bucket.as_nanos();
+        let start = Duration::from_nanos((ts.as_nanos() / bucket * bucket) as u64);
+
+        let source = self.sources.entry(src).or_default();
+        if source.buckets.last().is_none_or(|b| b.start < start) {
+            source.close(src, self.shift_threshold, &mut self.shifts);
+            source.buckets.push(TtlBucket::new(start));
+        }
+
+        // Late packets are accounted to the latest bucket.
+        if let Some(last) = source.buckets.last_mut() {
+            last.record(ttl);
+        }
+    }
+
+    /// Close the current buckets, detecting shifts in them.
+    ///
+    /// Call this once the capture is over.
+    pub fn flush(&mut self) {
+        let mut sources: Vec<_> = self.sources.iter_mut().collect();
+        sources.sort_by_key(|(src, _)| **src);
+        for (src, source) in sources {
+            source.close(*src, self.shift_threshold, &mut self.shifts);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Source {
+    buckets: Vec<TtlBucket>,
+    /// Number of buckets already compared with their predecessor.
+    closed: usize,
+}
+
+impl Source {
+    fn close(&mut self, src: IpAddr, threshold: u8, shifts: &mut Vec<TtlShift>) {
+        if self.closed == self.buckets.len() {
+            return;
+        }
+        self.closed = self.buckets.len();
+
+        if let [.., prev, last] = self.buckets.as_slice() {
+            if prev.mode.abs_diff(last.mode) >= threshold {
+                shifts.push(TtlShift {
+                    src,
+                    at: last.start,
+                    from: prev.mode,
+                    to: last.mode,
+                });
+            }
+        }
+    }
+}
+
+impl Analyzer for TtlStats {
+    fn on_packet(&mut self, ts: Duration, frame: &[u8]) {
+        let Ok(eth) = Eth::new(frame) else {
+            return;
+        };
+
+        if let Some(ipv4) = eth.ipv4() {
+            self.record(ts, ipv4.src().get().into(), ipv4.ttl().get());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const SRC: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    fn frame(ttl: u8) -> Vec<u8> {
+        let ipv4 = ipv4!(src: SRC, ttl: ttl, protocol: IpProtocol::Udp);
+        eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+            .inner()
+            .clone()
+    }
+
+    #[test]
+    fn ttl_buckets() {
+        let mut stats = TtlStats::new();
+        stats.bucket(Duration::from_secs(10));
+        for (secs, ttl) in [(0, 57), (1, 57), (2, 56), (12, 57)] {
+            stats.on_packet(Duration::from_secs(secs), &frame(ttl));
+        }
+
+        let buckets = stats.buckets(&SRC.into());
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].packets, 3);
+        assert_eq!((buckets[0].min, buckets[0].max), (56, 57));
+        assert_eq!(buckets[0].mode, 57);
+        assert_eq!(buckets[0].hops(), 7);
+        assert_eq!(buckets[0].histogram(), [(56, 1), (57, 2)]);
+        assert_eq!(buckets[1].start, Duration::from_secs(10));
+        assert!(stats.shifts().is_empty());
+    }
+
+    #[test]
+    fn ttl_shift() {
+        let mut stats = TtlStats::new();
+        stats.bucket(Duration::from_secs(10));
+        for (secs, ttl) in [(0, 57), (10, 57), (20, 120), (21, 120), (30, 120)] {
+            stats.on_packet(Duration::from_secs(secs), &frame(ttl));
+        }
+        stats.flush();
+
+        assert_eq!(
+            stats.shifts(),
+            [TtlShift {
+                src: SRC.into(),
+                at: Duration::from_secs(20),
+                from: 57,
+                to: 120,
+            }]
+        );
+    }
+}
@@ -0,0 +1,523 @@
+//! Assertion DSL for tests over captures.
+//!
+//! Regression tests over capture corpora mostly check that a given packet
+//! has given fields. [`expect!`](crate::expect) makes them one-liners:
+//!
+//! ```
+//! use netkit::{expect, testing::Capture};
+//! use netkit::packet::{layer::tcp::TcpFlags, prelude::*, tcp};
+//!
+//! let tcp = tcp!(src_port: 443u16, dst_port: 40000u16, flags: TcpFlags::SYN | TcpFlags::ACK);
+//! let ipv4 = ipv4!(protocol: IpProtocol::Tcp, payload: tcp.inner());
+//! let frame = eth!(eth_type: EthType::Ipv4, payload: ipv4.inner());
+//! let capture = Capture::from_frames([frame.inner().clone()]);
+//!
+//! expect!(capture).len(1).packet(0).tcp().flag(TcpFlags::SYN).src_port(443);
+//! ```
+//!
+//! Failed expectations panic with the packet index and the mismatching
+//! field, pointing at the caller's line. Packets are indexed from 0.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    net::Ipv4Addr,
+    path::Path,
+    time::Duration,
+};
+
+use netkit_capture::file::pcap::PcapReader;
+use netkit_packet::{
+    layer::{
+        link::{self, LinkType},
+        tcp::TcpFlags,
+    },
+    prelude::*,
+};
+
+/// A packet of a [`Capture`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Capture timestamp, since the epoch.
+    pub ts: Duration,
+    /// Ethernet frame.
+    pub data: Vec<u8>,
+}
+
+/// Packets loaded in memory for assertions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capture {
+    frames: Vec<Frame>,
+}
+
+impl Capture {
+    /// Create a capture of Ethernet frames with zero timestamps.
+    pub fn from_frames<I: IntoIterator<Item = Vec<u8>>>(frames: I) -> Self {
+        Self {
+            frames: frames
+                .into_iter()
+                .map(|data| Frame {
+                    ts: Duration::ZERO,
+                    data,
+                })
+                .collect(),
+        }
+    }
+
+    /// Read every packet of a pcap file, converted to Ethernet frames.
+    ///
+    /// Packets of unsupported link types are kept as they are.
+    pub fn from_pcap<R: Read>(reader: &mut PcapReader<R>) -> Self {
+        let link_type = reader.link_type();
+        let mut frames = Vec::new();
+        while let Some((header, data)) = reader.next_packet() {
+            let ts = reader.timestamp(&header);
+            let data = if link_type == LinkType::Ethernet {
+                data
+            } else {
+                link::to_eth(link_type, &data).unwrap_or(data)
+            };
+            frames.push(Frame { ts, data });
+        }
+        Self { frames }
+    }
+
+    /// Read a pcap file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(Self::from_pcap(&mut PcapReader::new(file)))
+    }
+
+    /// Get the packets.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+}
+
+/// Create an [`Expect`] over a [`Capture`].
+#[macro_export]
+macro_rules! expect {
+    ($capture : expr) => {
+        $crate::testing::Expect::new(&$capture)
+    };
+}
+
+/// Expectations over a whole capture.
+#[derive(Clone, Copy, Debug)]
+pub struct Expect<'a> {
+    capture: &'a Capture,
+}
+
+impl<'a> Expect<'a> {
+    /// Start expectations over `capture`.
+    pub fn new(capture: &'a Capture) -> Self {
+        Self { capture }
+    }
+
+    /// Expect the number of packets.
+    #[track_caller]
+    pub fn len(self, len: usize) -> Self {
+        let actual = self.capture.frames.len();
+        assert_eq!(actual, len, "expected {len} packets, found {actual}");
+        self
+    }
+
+    /// Expect the number of packets matching a predicate.
+    #[track_caller]
+    pub fn count(self, count: usize, predicate: impl Fn(PacketExpect<'a>) -> bool) -> Self {
+        let actual = (0..self.capture.frames.len())
+            .filter(|i| predicate(self.at(*i)))
+            .count();
+        assert_eq!(
+            actual, count,
+            "expected {count} matching packets, found {actual}"
+        );
+        self
+    }
+
+    /// Focus on the packet at `index`.
+    #[track_caller]
+    pub fn packet(self, index: usize) -> PacketExpect<'a> {
+        let len = self.capture.frames.len();
+        assert!(index < len, "expected packet {index}, capture has {len}");
+        self.at(index)
+    }
+
+    fn at(self, index: usize) -> PacketExpect<'a> {
+        PacketExpect {
+            index,
+            frame: &self.capture.frames[index],
+        }
+    }
+}
+
+/// Expectations over a packet.
+#[derive(Clone, Copy, Debug)]
+pub struct PacketExpect<'a> {
+    index: usize,
+    frame: &'a Frame,
+}
+
+impl<'a> PacketExpect<'a> {
+    /// Get the frame.
+    pub fn frame(&self) -> &'a Frame {
+        self.frame
+    }
+
+    /// Expect the timestamp.
+    #[track_caller]
+    pub fn ts(self, ts: Duration) -> Self {
+        self.check("timestamp", ts, self.frame.ts);
+        self
+    }
+
+    /// Expect the frame length.
+    #[track_caller]
+    pub fn frame_len(self, len: usize) -> Self {
+        self.check("frame length", len, self.frame.data.len());
+        self
+    }
+
+    /// Expect an IPv4 packet and focus on it.
+    #[track_caller]
+    pub fn ipv4(self) -> Ipv4Expect<'a> {
+        let ipv4 = self.parse_ipv4();
+        Ipv4Expect {
+            packet: self,
+            src: ipv4.src().get(),
+            dst: ipv4.dst().get(),
+            ttl: ipv4.ttl().get(),
+            protocol: ipv4.protocol().get(),
+        }
+    }
+
+    /// Expect a TCP segment and focus on it.
+    #[track_caller]
+    pub fn tcp(self) -> TcpExpect<'a> {
+        let ipv4 = self.parse_ipv4();
+        let Some(tcp) = ipv4.tcp() else {
+            self.fail("expected a TCP segment");
+        };
+        let payload = reborrow(&self.frame.data, tcp.payload());
+        TcpExpect {
+            packet: self,
+            src_port: tcp.src_port().get(),
+            dst_port: tcp.dst_port().get(),
+            seq: tcp.seq_num().get(),
+            ack: tcp.ack_num().get(),
+            flags: tcp.flags().get(),
+            window: tcp.window_size().get(),
+            payload,
+        }
+    }
+
+    /// Expect a UDP datagram and focus on it.
+    #[track_caller]
+    pub fn udp(self) -> UdpExpect<'a> {
+        let ipv4 = self.parse_ipv4();
+        let Some(udp) = ipv4.udp() else {
+            self.fail("expected a UDP datagram");
+        };
+        let payload = reborrow(&self.frame.data, udp.payload());
+        UdpExpect {
+            packet: self,
+            src_port: udp.src_port().get(),
+            dst_port: udp.dst_port().get(),
+            payload,
+        }
+    }
+
+    /// Whether the packet is a TCP segment, for use in predicates.
+    pub fn is_tcp(&self) -> bool {
+        Eth::new(self.frame.data.as_slice())
+            .ok()
+            .and_then(|eth| eth.ipv4().and_then(|ipv4| ipv4.tcp().map(|_| ())))
+            .is_some()
+    }
+
+    /// Whether the packet is a UDP datagram, for use in predicates.
+    pub fn is_udp(&self) -> bool {
+        Eth::new(self.frame.data.as_slice())
+            .ok()
+            .and_then(|eth| eth.ipv4().and_then(|ipv4| ipv4.udp().map(|_| ())))
+            .is_some()
+    }
+
+    #[track_caller]
+    fn parse_ipv4(&self) -> Ipv4<&'a [u8]> {
+        let data: &'a [u8] = &self.frame.data;
+        let Ok(eth) = Eth::new(data) else {
+            self.fail("expected an Ethernet frame");
+        };
+        let Some(ipv4) = eth.ipv4() else {
+            self.fail("expected an IPv4 packet");
+        };
+        Ipv4::new(reborrow(data, ipv4.inner())).unwrap()
+    }
+
+    #[track_caller]
+    fn check<T: PartialEq + core::fmt::Debug>(&self, field: &str, expected: T, actual: T) {
+        if expected != actual {
+            self.fail(&format!("expected {field} {expected:?}, found {actual:?}"));
+        }
+    }
+
+    #[track_caller]
+    fn fail(&self, msg: &str) -> ! {
+        panic!("packet {}: {msg}", self.index)
+    }
+}
+
+/// Re-borrow `part`, a sub-slice of `data`, with the lifetime of `data`, so
+/// it outlives the layers it was parsed with.
+fn reborrow<'a>(data: &'a [u8], part: &[u8]) -> &'a [u8] {
+    let start = part.as_ptr() as usize - data.as_ptr() as usize;
+    &data[start..start + part.len()]
+}
+
+/// Expectations over an IPv4 packet.
+#[derive(Clone, Copy, Debug)]
+pub struct Ipv4Expect<'a> {
+    packet: PacketExpect<'a>,
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    ttl: u8,
+    protocol: IpProtocol,
+}
+
+impl<'a> Ipv4Expect<'a> {
+    /// Expect the source address.
+    #[track_caller]
+    pub fn src(self, src: Ipv4Addr) -> Self {
+        self.packet.check("source address", src, self.src);
+        self
+    }
+
+    /// Expect the destination address.
+    #[track_caller]
+    pub fn dst(self, dst: Ipv4Addr) -> Self {
+        self.packet.check("destination address", dst, self.dst);
+        self
+    }
+
+    /// Expect the time to live.
+    #[track_caller]
+    pub fn ttl(self, ttl: u8) -> Self {
+        self.packet.check("TTL", ttl, self.ttl);
+        self
+    }
+
+    /// Expect the protocol.
+    #[track_caller]
+    pub fn protocol(self, protocol: IpProtocol) -> Self {
+        self.packet.check("protocol", protocol, self.protocol);
+        self
+    }
+
+    /// Go back to the packet.
+    pub fn packet(self) -> PacketExpect<'a> {
+        self.packet
+    }
+}
+
+/// Expectations over a TCP segment.
+#[derive(Clone, Copy, Debug)]
+pub struct TcpExpect<'a> {
+    packet: PacketExpect<'a>,
+    src_port: u16,
+    dst_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: TcpFlags,
+    window: u16,
+    payload: &'a [u8],
+}
+
+impl<'a> TcpExpect<'a> {
+    /// Expect the source port.
+    #[track_caller]
+    pub fn src_port(self, port: u16) -> Self {
+        self.packet.check("source port", port, self.src_port);
+        self
+    }
+
+    /// Expect the destination port.
+    #[track_caller]
+    pub fn dst_port(self, port: u16) -> Self {
+        self.packet.check("destination port", port, self.dst_port);
+        self
+    }
+
+    /// Expect the flags to contain `flag`.
+    #[track_caller]
+    pub fn flag(self, flag: TcpFlags) -> Self {
+        if !self.flags.contains(flag) {
+            self.packet
+                .fail(&format!("expected flag {flag:?}, found {:?}", self.flags));
+        }
+        self
+    }
+
+    /// Expect the flags to not contain `flag`.
+    #[track_caller]
+    pub fn no_flag(self, flag: TcpFlags) -> Self {
+        if self.flags.intersects(flag) {
+            self.packet.fail(&format!(
+                "expected no flag {flag:?}, found {:?}",
+                self.flags
+            ));
+        }
+        self
+    }
+
+    /// Expect exactly the given flags.
+    #[track_caller]
+    pub fn flags(self, flags: TcpFlags) -> Self {
+        self.packet.check("flags", flags, self.flags);
+        self
+    }
+
+    /// Expect the sequence number.
+    #[track_caller]
+    pub fn seq(self, seq: u32) -> Self {
+        self.packet.check("sequence number", seq, self.seq);
+        self
+    }
+
+    /// Expect the acknowledgment number.
+    #[track_caller]
+    pub fn ack(self, ack: u32) -> Self {
+        self.packet.check("acknowledgment number", ack, self.ack);
+        self
+    }
+
+    /// Expect the window size.
+    #[track_caller]
+    pub fn window(self, window: u16) -> Self {
+        self.packet.check("window size", window, self.window);
+        self
+    }
+
+    /// Expect the payload.
+    #[track_caller]
+    pub fn payload(self, payload: impl AsRef<[u8]>) -> Self {
+        self.packet.check("payload", payload.as_ref(), self.payload);
+        self
+    }
+
+    /// Expect the payload length.
+    #[track_caller]
+    pub fn payload_len(self, len: usize) -> Self {
+        self.packet.check("payload length", len, self.payload.len());
+        self
+    }
+
+    /// Go back to the packet.
+    pub fn packet(self) -> PacketExpect<'a> {
+        self.packet
+    }
+}
+
+/// Expectations over a UDP datagram.
+#[derive(Clone, Copy, Debug)]
+pub struct UdpExpect<'a> {
+    packet: PacketExpect<'a>,
+    src_port: u16,
+    dst_port: u16,
+    payload: &'a [u8],
+}
+
+impl<'a> UdpExpect<'a> {
+    /// Expect the source port.
+    #[track_caller]
+    pub fn src_port(self, port: u16) -> Self {
+        self.packet.check("source port", port, self.src_port);
+        self
+    }
+
+    /// Expect the destination port.
+    #[track_caller]
+    pub fn dst_port(self, port: u16) -> Self {
+        self.packet.check("destination port", port, self.dst_port);
+        self
+    }
+
+    /// Expect the payload.
+    #[track_caller]
+    pub fn payload(self, payload: impl AsRef<[u8]>) -> Self {
+        self.packet.check("payload", payload.as_ref(), self.payload);
+        self
+    }
+
+    /// Expect the payload length.
+    #[track_caller]
+    pub fn payload_len(self, len: usize) -> Self {
+        self.packet.check("payload length", len, self.payload.len());
+        self
+    }
+
+    /// Go back to the packet.
+    pub fn packet(self) -> PacketExpect<'a> {
+        self.packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netkit_packet::{tcp, udp};
+
+    use super::*;
+
+    fn capture() -> Capture {
+        let a = Ipv4Addr::new(10, 0, 0, 1);
+        let b = Ipv4Addr::new(10, 0, 0, 2);
+        let syn = tcp!(src_port: 40000u16, dst_port: 443u16, seq_num: 7u32, flags: TcpFlags::SYN);
+        let dns = udp!(src_port: 5353u16, dst_port: 53u16, payload: b"query");
+        let frames = [
+            ipv4!(src: a, dst: b, ttl: 64u8, protocol: IpProtocol::Tcp, payload: syn.inner()),
+            ipv4!(src: a, dst: b, protocol: IpProtocol::Udp, payload: dns.inner()),
+        ]
+        .map(|ipv4| {
+            eth!(eth_type: EthType::Ipv4, payload: ipv4.inner())
+                .inner()
+                .clone()
+        });
+        Capture::from_frames(frames)
+    }
+
+    #[test]
+    fn expect_chain() {
+        let capture = capture();
+        expect!(capture)
+            .len(2)
+            .count(1, |p| p.is_tcp())
+            .packet(0)
+            .ipv4()
+            .src(Ipv4Addr::new(10, 0, 0, 1))
+            .ttl(64)
+            .packet()
+            .tcp()
+            .flag(TcpFlags::SYN)
+            .no_flag(TcpFlags::ACK)
+            .dst_port(443)
+            .seq(7)
+            .payload_len(0);
+        expect!(capture)
+            .packet(1)
+            .udp()
+            .dst_port(53)
+            .payload(b"query");
+    }
+
+    #[test]
+    #[should_panic(expected = "packet 1: expected a TCP segment")]
+    fn expect_wrong_layer() {
+        expect!(capture()).packet(1).tcp();
+    }
+
+    #[test]
+    #[should_panic(expected = "packet 0: expected destination port 80, found 443")]
+    fn expect_wrong_field() {
+        expect!(capture()).packet(0).tcp().dst_port(80);
+    }
+}